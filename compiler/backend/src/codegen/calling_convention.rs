@@ -156,13 +156,13 @@ pub trait CallingConvention {
         function: FunctionValue<'ctx>,
     ) -> Result<()>;
 
-    /// Generate call instruction
+    /// Generate call instruction, giving the result unless the callee is void
     fn generate_call<'ctx>(
         &self,
         builder: &Builder<'ctx>,
         callee: FunctionValue<'ctx>,
         args: &[BasicValueEnum<'ctx>],
-    ) -> Result<BasicValueEnum<'ctx>>;
+    ) -> Result<Option<BasicValueEnum<'ctx>>>;
 
     /// Get convention type
     fn convention_type(&self) -> CallingConventionType;
@@ -246,7 +246,7 @@ impl CallingConvention for ForthCallingConvention {
         builder: &Builder<'ctx>,
         callee: FunctionValue<'ctx>,
         args: &[BasicValueEnum<'ctx>],
-    ) -> Result<BasicValueEnum<'ctx>> {
+    ) -> Result<Option<BasicValueEnum<'ctx>>> {
         match self.convention_type {
             CallingConventionType::ForthInternal => {
                 // Direct call - just 1 instruction!
@@ -255,13 +255,7 @@ impl CallingConvention for ForthCallingConvention {
                     .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
 
                 // Get return value if present
-                if let Some(ret_val) = call_site.try_as_basic_value().left() {
-                    Ok(ret_val)
-                } else {
-                    // Return unit type for void functions
-                    Ok(builder.get_insert_block().unwrap().get_parent().unwrap()
-                        .get_nth_param(0).unwrap())
-                }
+                Ok(call_site.try_as_basic_value().left())
             }
             CallingConventionType::ForthToC | CallingConventionType::CToForth => {
                 // FFI calls go through bridge - handled separately
@@ -269,12 +263,7 @@ impl CallingConvention for ForthCallingConvention {
                     .build_call(callee, args, "ffi_call")
                     .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
 
-                if let Some(ret_val) = call_site.try_as_basic_value().left() {
-                    Ok(ret_val)
-                } else {
-                    Ok(builder.get_insert_block().unwrap().get_parent().unwrap()
-                        .get_nth_param(0).unwrap())
-                }
+                Ok(call_site.try_as_basic_value().left())
             }
         }
     }
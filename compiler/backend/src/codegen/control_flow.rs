@@ -70,6 +70,36 @@ impl<'ctx> ControlFlowCodegen<'ctx> {
         Ok(())
     }
 
+    /// Generate a multiway branch (a CASE with dense keys), which LLVM
+    /// lowers to a jump table
+    pub fn generate_switch(
+        &self,
+        builder: &Builder<'ctx>,
+        values: &HashMap<Register, BasicValueEnum<'ctx>>,
+        blocks: &HashMap<BlockId, BasicBlock<'ctx>>,
+        value: Register,
+        cases: &[(i64, BlockId)],
+        default: BlockId,
+    ) -> Result<()> {
+        let selector = values
+            .get(&value)
+            .ok_or_else(|| BackendError::InvalidIR(format!("Undefined switch register: {}", value)))?
+            .into_int_value();
+        let block = |id: BlockId| {
+            blocks.get(&id).copied().ok_or_else(|| BackendError::InvalidIR(format!("Undefined case block: {}", id.0)))
+        };
+
+        let cell = self.context.i64_type();
+        let cases = cases
+            .iter()
+            .map(|&(key, target)| Ok((cell.const_int(key as u64, true), block(target)?)))
+            .collect::<Result<Vec<_>>>()?;
+        builder.build_switch(selector, block(default)?, &cases)
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+
+        Ok(())
+    }
+
     /// Generate unconditional jump
     pub fn generate_jump(
         &self,
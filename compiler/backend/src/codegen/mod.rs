@@ -7,24 +7,29 @@ pub mod stack_cache;
 pub mod primitives;
 pub mod control_flow;
 pub mod calling_convention;
+pub mod parallel;
 
 use crate::error::{BackendError, Result};
-use fastforth_frontend::ssa::{SSAFunction, SSAInstruction, Register, BlockId, BinaryOperator, UnaryOperator};
+use fastforth_frontend::case::CaseTable;
+use fastforth_frontend::ssa::{SSAFunction, SSAInstruction, Register, BlockId, BinaryOperator, UnaryOperator, MemoryWidth};
+use fastforth_frontend::Arithmetic;
 use inkwell::builder::Builder;
 use inkwell::context::Context;
+use inkwell::intrinsics::Intrinsic;
 use inkwell::module::Module;
-use inkwell::types::{BasicTypeEnum, IntType, FloatType};
-use inkwell::values::{BasicValueEnum, FunctionValue, IntValue, FloatValue, PointerValue, BasicValue};
+use inkwell::types::{BasicMetadataTypeEnum, BasicTypeEnum, FunctionType, IntType, FloatType};
+use inkwell::values::{BasicValueEnum, FunctionValue, GlobalValue, IntValue, FloatValue, PointerValue, BasicValue};
 use inkwell::IntPredicate;
 use inkwell::FloatPredicate;
 use inkwell::{OptimizationLevel, AddressSpace};
-use inkwell::targets::{Target, TargetMachine, RelocMode, CodeModel, FileType, InitializationConfig};
+use inkwell::targets::{Target, TargetMachine, TargetTriple, RelocMode, CodeModel, FileType, InitializationConfig};
 use std::collections::HashMap;
 use std::path::Path;
 
 pub use stack_cache::StackCache;
 pub use primitives::PrimitiveCodegen;
 pub use control_flow::ControlFlowCodegen;
+pub use parallel::CodegenUnits;
 pub use calling_convention::{
     CallingConvention, CallingConventionType, ForthCallingConvention,
     FFIBridge, ForthRegister, RegisterAllocator,
@@ -75,6 +80,9 @@ pub struct LLVMBackend<'ctx> {
     /// Basic block mapping
     blocks: HashMap<BlockId, inkwell::basic_block::BasicBlock<'ctx>>,
 
+    /// Block each SSA block ends in, where checked arithmetic split it
+    exits: HashMap<BlockId, inkwell::basic_block::BasicBlock<'ctx>>,
+
     /// Current function being compiled
     current_function: Option<FunctionValue<'ctx>>,
 
@@ -94,6 +102,7 @@ impl<'ctx> LLVMBackend<'ctx> {
         opt_level: OptimizationLevel,
     ) -> Self {
         let module = context.create_module(module_name);
+        module.set_triple(&TargetMachine::get_default_triple());
         let builder = context.create_builder();
 
         let ffi_bridge = FFIBridge::new(context, &module);
@@ -109,12 +118,23 @@ impl<'ctx> LLVMBackend<'ctx> {
             ffi_bridge,
             values: HashMap::new(),
             blocks: HashMap::new(),
+            exits: HashMap::new(),
             current_function: None,
             mode,
             opt_level,
         }
     }
 
+    /// Generate code for `triple` rather than the host
+    pub fn set_target_triple(&mut self, triple: &str) {
+        self.module.set_triple(&TargetTriple::create(triple));
+    }
+
+    /// Whether the module's target is big-endian
+    fn big_endian(&self) -> bool {
+        crate::layout::big_endian(&self.module.get_triple().as_str().to_string_lossy())
+    }
+
     /// Get LLVM type for i64 (cell_t)
     pub fn cell_type(&self) -> IntType<'ctx> {
         self.context.i64_type()
@@ -137,18 +157,7 @@ impl<'ctx> LLVMBackend<'ctx> {
 
     /// Create LLVM function from SSA function signature
     fn create_function(&mut self, ssa_func: &SSAFunction) -> Result<FunctionValue<'ctx>> {
-        // Create parameter types (all i64 for now)
-        let param_types: Vec<BasicTypeEnum> = ssa_func
-            .parameters
-            .iter()
-            .map(|_| self.cell_type().into())
-            .collect();
-
-        // For now, assume single return value
-        let ret_type = self.cell_type();
-
-        // Create function type
-        let fn_type = ret_type.fn_type(&param_types, false);
+        let fn_type = self.word_type(ssa_func.parameters.len(), ssa_func.result_count());
 
         // Add function to module
         let function = self.module.add_function(&ssa_func.name, fn_type, None);
@@ -163,6 +172,32 @@ impl<'ctx> LLVMBackend<'ctx> {
         Ok(function)
     }
 
+    /// Type of a word taking `params` cells and returning `results`: void,
+    /// one cell, or a struct of cells for several results
+    fn word_type(&self, params: usize, results: usize) -> FunctionType<'ctx> {
+        // Create parameter types (all i64 for now)
+        let param_types: Vec<BasicMetadataTypeEnum> = vec![self.cell_type().into(); params];
+        match results {
+            0 => self.context.void_type().fn_type(&param_types, false),
+            1 => self.cell_type().fn_type(&param_types, false),
+            n => {
+                let fields: Vec<BasicTypeEnum> = vec![self.cell_type().into(); n];
+                self.context.struct_type(&fields, false).fn_type(&param_types, false)
+            }
+        }
+    }
+
+    /// The module's function for an SSA function, declaring it if needed
+    ///
+    /// A function declared but never generated is external, so calls into
+    /// other codegen units resolve at link time.
+    pub fn declare(&mut self, ssa_func: &SSAFunction) -> Result<FunctionValue<'ctx>> {
+        match self.module.get_function(&ssa_func.name) {
+            Some(function) => Ok(function),
+            None => self.create_function(ssa_func),
+        }
+    }
+
     /// Create basic blocks for the function
     fn create_basic_blocks(
         &mut self,
@@ -170,6 +205,7 @@ impl<'ctx> LLVMBackend<'ctx> {
         ssa_func: &SSAFunction,
     ) -> Result<()> {
         self.blocks.clear();
+        self.exits.clear();
 
         for block in &ssa_func.blocks {
             let bb = self.context.append_basic_block(function, &format!("bb{}", block.id.0));
@@ -199,6 +235,11 @@ impl<'ctx> LLVMBackend<'ctx> {
                 self.values.insert(*dest, str_val.as_basic_value_enum());
             }
 
+            SSAInstruction::DataAddress { dest, name, size } => {
+                let global = self.variable(name, *size);
+                self.values.insert(*dest, global.as_pointer_value().into());
+            }
+
             SSAInstruction::BinaryOp { dest, op, left, right } => {
                 self.generate_binary_op(*dest, *op, *left, *right)?;
             }
@@ -207,26 +248,59 @@ impl<'ctx> LLVMBackend<'ctx> {
                 self.generate_unary_op(*dest, *op, *operand)?;
             }
 
-            SSAInstruction::Load { dest, address, .. } => {
+            SSAInstruction::Load { dest, address, width: MemoryWidth::Cell, .. } => {
                 let addr = self.get_value(*address)?;
                 let addr_ptr = addr.into_pointer_value();
                 let loaded = self.builder.build_load(self.cell_type(), addr_ptr, "load")
                     .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
-                self.values.insert(*dest, loaded);
+                let loaded = self.little_endian(loaded.into_int_value())?;
+                self.values.insert(*dest, loaded.into());
+            }
+
+            SSAInstruction::Load { dest, address, width, .. } => {
+                let addr_ptr = self.get_value(*address)?.into_pointer_value();
+                let loaded = self.generate_sized_load(addr_ptr, *width)?;
+                self.values.insert(*dest, loaded.into());
             }
 
-            SSAInstruction::Store { address, value, .. } => {
+            SSAInstruction::Store { address, value, width: MemoryWidth::Cell, .. } => {
                 let addr = self.get_value(*address)?;
-                let val = self.get_value(*value)?;
+                let val = match self.get_value(*value)? {
+                    BasicValueEnum::FloatValue(float) => self.builder.build_bitcast(float, self.cell_type(), "bits")
+                        .map_err(|e| BackendError::CodeGenError(e.to_string()))?
+                        .into_int_value(),
+                    val => val.into_int_value(),
+                };
                 let addr_ptr = addr.into_pointer_value();
+                let val = self.little_endian(val)?;
                 self.builder.build_store(addr_ptr, val)
                     .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
             }
 
+            SSAInstruction::Store { address, value, width, .. } => {
+                let addr_ptr = self.get_value(*address)?.into_pointer_value();
+                let val = self.get_value(*value)?.into_int_value();
+                self.generate_sized_store(addr_ptr, val, *width)?;
+            }
+
             SSAInstruction::Call { dest, name, args } => {
                 self.generate_call(dest, name, args)?;
             }
 
+            SSAInstruction::FunctionAddress { dest, name } => {
+                let function = self.module
+                    .get_function(name)
+                    .ok_or_else(|| BackendError::InvalidIR(format!("Undefined function: {}", name)))?;
+                let addr = self.builder
+                    .build_ptr_to_int(function.as_global_value().as_pointer_value(), self.cell_type(), "xt")
+                    .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+                self.values.insert(*dest, addr.into());
+            }
+
+            SSAInstruction::CallIndirect { dest, target, args, likely } => {
+                self.generate_indirect_call(dest, *target, args, likely.as_deref())?;
+            }
+
             SSAInstruction::Branch { condition, true_block, false_block } => {
                 self.control_flow.generate_branch(
                     &self.builder,
@@ -246,14 +320,40 @@ impl<'ctx> LLVMBackend<'ctx> {
                 )?;
             }
 
+            SSAInstruction::Switch { value, cases, default } => {
+                self.control_flow.generate_switch(
+                    &self.builder,
+                    &self.values,
+                    &self.blocks,
+                    *value,
+                    cases,
+                    *default,
+                )?;
+            }
+
+            SSAInstruction::Lookup { dest, index, table } => {
+                let value = self.generate_lookup(*index, table)?;
+                self.values.insert(*dest, value.into());
+            }
+
             SSAInstruction::Return { values: ret_vals } => {
-                if let Some(reg) = ret_vals.first() {
-                    let val = self.get_value(*reg)?;
-                    self.builder.build_return(Some(&val))
-                        .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
-                } else {
-                    self.builder.build_return(None)
-                        .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+                match ret_vals.as_slice() {
+                    [] => {
+                        self.builder.build_return(None)
+                            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+                    }
+                    [reg] => {
+                        let val = self.get_value(*reg)?;
+                        self.builder.build_return(Some(&val))
+                            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+                    }
+                    regs => {
+                        let vals = regs.iter()
+                            .map(|&reg| self.get_value(reg))
+                            .collect::<Result<Vec<_>>>()?;
+                        self.builder.build_aggregate_return(&vals)
+                            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+                    }
                 }
             }
 
@@ -274,6 +374,58 @@ impl<'ctx> LLVMBackend<'ctx> {
             .ok_or_else(|| BackendError::InvalidIR(format!("Undefined register: {}", reg)))
     }
 
+    /// Zeroed global holding a variable or CREATE buffer
+    ///
+    /// Common linkage lets every codegen unit that uses the variable declare
+    /// it, and the linker keep one copy.
+    fn variable(&mut self, name: &str, size: usize) -> GlobalValue<'ctx> {
+        let symbol = crate::mangle::mangle(name);
+        if let Some(global) = self.module.get_global(&symbol) {
+            return global;
+        }
+        let array_type = self.context.i8_type().array_type(size.max(1) as u32);
+        let global = self.module.add_global(array_type, None, &symbol);
+        global.set_initializer(&array_type.const_zero());
+        global.set_alignment(crate::layout::CELL_BYTES);
+        global.set_linkage(inkwell::module::Linkage::Common);
+        global
+    }
+
+    /// Entry of a constant table, kept in a private constant array
+    ///
+    /// The index is clamped before the load and the default selected after
+    /// it, so the lookup does not branch.
+    fn generate_lookup(&mut self, index: Register, table: &CaseTable) -> Result<IntValue<'ctx>> {
+        let cell = self.cell_type();
+        let entries: Vec<IntValue<'ctx>> = table.values.iter().map(|&value| cell.const_int(value as u64, true)).collect();
+        let array_type = cell.array_type(entries.len() as u32);
+        let global = self.module.add_global(array_type, None, "case_table");
+        global.set_initializer(&cell.const_array(&entries));
+        global.set_constant(true);
+        global.set_linkage(inkwell::module::Linkage::Private);
+
+        let map_err = |e: inkwell::builder::BuilderError| BackendError::CodeGenError(e.to_string());
+        let key = self.get_value(index)?.into_int_value();
+        let offset = self.builder.build_int_sub(key, cell.const_int(table.base as u64, true), "case_offset").map_err(map_err)?;
+        let in_range = self.builder
+            .build_int_compare(IntPredicate::ULT, offset, cell.const_int(entries.len() as u64, false), "case_in_range")
+            .map_err(map_err)?;
+        let clamped = self.builder.build_select(in_range, offset, cell.const_zero(), "case_index").map_err(map_err)?;
+        let entry = unsafe {
+            self.builder.build_in_bounds_gep(
+                array_type,
+                global.as_pointer_value(),
+                &[cell.const_zero(), clamped.into_int_value()],
+                "case_entry",
+            ).map_err(map_err)?
+        };
+        let loaded = self.builder.build_load(cell, entry, "case_value").map_err(map_err)?;
+        let value = self.builder
+            .build_select(in_range, loaded.into_int_value(), cell.const_int(table.default as u64, true), "case")
+            .map_err(map_err)?;
+        Ok(value.into_int_value())
+    }
+
     /// Generate binary operation
     fn generate_binary_op(
         &mut self,
@@ -287,6 +439,7 @@ impl<'ctx> LLVMBackend<'ctx> {
 
         let result = self.primitives.generate_binary_op(
             &self.builder,
+            &self.module,
             op,
             lhs,
             rhs,
@@ -304,11 +457,49 @@ impl<'ctx> LLVMBackend<'ctx> {
         operand: Register,
     ) -> Result<()> {
         let val = self.get_value(operand)?;
-        let result = self.primitives.generate_unary_op(&self.builder, op, val)?;
+        let result = self.primitives.generate_unary_op(&self.builder, &self.module, op, val)?;
         self.values.insert(dest, result);
         Ok(())
     }
 
+    /// Load of `width` bytes, little-endian, zero-extended to a cell
+    fn generate_sized_load(&self, addr: PointerValue<'ctx>, width: MemoryWidth) -> Result<IntValue<'ctx>> {
+        let narrow = self.context.custom_width_int_type(width.bytes() * 8);
+        let loaded = self.builder.build_load(narrow, addr, "load")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?
+            .into_int_value();
+        let loaded = self.little_endian(loaded)?;
+        self.builder.build_int_z_extend(loaded, self.cell_type(), "zext")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))
+    }
+
+    /// Store of the low `width` bytes of `value`, little-endian
+    fn generate_sized_store(&self, addr: PointerValue<'ctx>, value: IntValue<'ctx>, width: MemoryWidth) -> Result<()> {
+        let narrow = self.context.custom_width_int_type(width.bytes() * 8);
+        let value = self.builder.build_int_truncate(value, narrow, "trunc")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+        let value = self.little_endian(value)?;
+        self.builder.build_store(addr, value)
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Swap the bytes of a multi-byte value when the target is big-endian,
+    /// so memory holds it little-endian either way (see [`crate::layout`])
+    fn little_endian(&self, value: IntValue<'ctx>) -> Result<IntValue<'ctx>> {
+        if !self.big_endian() || value.get_type().get_bit_width() == 8 {
+            return Ok(value);
+        }
+        let bswap = Intrinsic::find("llvm.bswap")
+            .and_then(|intrinsic| intrinsic.get_declaration(&self.module, &[value.get_type().into()]))
+            .ok_or_else(|| BackendError::CodeGenError("LLVM intrinsic llvm.bswap is unavailable".to_string()))?;
+        let swapped = self.builder.build_call(bswap, &[value.into()], "bswap")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+        swapped.try_as_basic_value().left()
+            .map(|value| value.into_int_value())
+            .ok_or_else(|| BackendError::CodeGenError("llvm.bswap returned no value".to_string()))
+    }
+
     /// Generate function call
     fn generate_call(
         &mut self,
@@ -335,14 +526,100 @@ impl<'ctx> LLVMBackend<'ctx> {
             &arg_values,
         )?;
 
-        // Store result if present
-        if let Some(&dest_reg) = dest.first() {
-            self.values.insert(dest_reg, result);
+        self.store_results(dest, result)
+    }
+
+    /// Store the results of a call: one cell, or the fields of a struct of
+    /// cells
+    fn store_results(&mut self, dest: &[Register], result: Option<BasicValueEnum<'ctx>>) -> Result<()> {
+        match (dest, result) {
+            ([dest_reg], Some(result)) => {
+                self.values.insert(*dest_reg, result);
+            }
+            (_, Some(BasicValueEnum::StructValue(results))) => {
+                for (i, &dest_reg) in dest.iter().enumerate() {
+                    let value = self.builder.build_extract_value(results, i as u32, "result")
+                        .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+                    self.values.insert(dest_reg, value);
+                }
+            }
+            _ => {}
         }
 
         Ok(())
     }
 
+    /// Call the word whose address is in `target`, with the signature every
+    /// word EXECUTE may reach has; with a `likely` word, test for it first
+    /// and call it directly if it is the one
+    fn generate_indirect_call(
+        &mut self,
+        dest: &[Register],
+        target: Register,
+        args: &[Register],
+        likely: Option<&str>,
+    ) -> Result<()> {
+        let fn_type = self.word_type(args.len(), dest.len());
+        let callee = match self.get_value(target)? {
+            BasicValueEnum::PointerValue(ptr) => ptr,
+            value => self.builder
+                .build_int_to_ptr(value.into_int_value(), self.ptr_type(), "callee")
+                .map_err(|e| BackendError::CodeGenError(e.to_string()))?,
+        };
+        let arg_values = args
+            .iter()
+            .map(|&reg| self.get_value(reg).map(|v| v.into()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let Some(likely) = likely else {
+            let call_site = self.builder
+                .build_indirect_call(fn_type, callee, &arg_values, "execute")
+                .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+            return self.store_results(dest, call_site.try_as_basic_value().left());
+        };
+
+        let word = self.module
+            .get_function(likely)
+            .ok_or_else(|| BackendError::InvalidIR(format!("Undefined function: {}", likely)))?;
+        let function = self.current_function
+            .ok_or_else(|| BackendError::InvalidIR("EXECUTE outside a function".to_string()))?;
+        let direct = self.context.append_basic_block(function, "execute.likely");
+        let indirect = self.context.append_basic_block(function, "execute.other");
+        let merge = self.context.append_basic_block(function, "execute.done");
+
+        let hit = self.builder
+            .build_int_compare(IntPredicate::EQ, callee, word.as_global_value().as_pointer_value(), "likely")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+        self.builder.build_conditional_branch(hit, direct, indirect)
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+
+        self.builder.position_at_end(direct);
+        let direct_result = self.calling_convention.generate_call(&self.builder, word, &arg_values)?;
+        self.builder.build_unconditional_branch(merge)
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+
+        self.builder.position_at_end(indirect);
+        let indirect_result = self.builder
+            .build_indirect_call(fn_type, callee, &arg_values, "execute")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?
+            .try_as_basic_value()
+            .left();
+        self.builder.build_unconditional_branch(merge)
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+
+        self.builder.position_at_end(merge);
+        let result = match (direct_result, indirect_result) {
+            (Some(direct_value), Some(indirect_value)) => {
+                let phi = self.builder.build_phi(direct_value.get_type(), "execute.result")
+                    .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+                phi.add_incoming(&[(&direct_value, direct), (&indirect_value, indirect)]);
+                Some(phi.as_basic_value())
+            }
+            _ => None,
+        };
+        self.store_results(dest, result)
+    }
+
     /// Create FFI bridge for calling C function from Forth
     pub fn create_c_ffi_bridge(
         &mut self,
@@ -381,6 +658,11 @@ impl<'ctx> LLVMBackend<'ctx> {
         )
     }
 
+    /// Generate division and overflow under `arithmetic`
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.primitives.set_arithmetic(arithmetic);
+    }
+
     /// Set calling convention (for external calls)
     pub fn set_calling_convention(&mut self, convention: ForthCallingConvention) {
         self.calling_convention = convention;
@@ -393,7 +675,8 @@ impl<'ctx> LLVMBackend<'ctx> {
             .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
 
         for (block_id, reg) in incoming {
-            let block = self.blocks.get(block_id)
+            let block = self.exits.get(block_id)
+                .or_else(|| self.blocks.get(block_id))
                 .ok_or_else(|| BackendError::InvalidIR(format!("Undefined block: {}", block_id.0)))?;
             let value = self.get_value(*reg)?;
             phi.add_incoming(&[(&value, *block)]);
@@ -454,18 +737,38 @@ impl<'ctx> LLVMBackend<'ctx> {
         self.module.print_to_string().to_string()
     }
 
-    /// Write object file
+    /// Give a defined function internal linkage, so it can be inlined into
+    /// every caller and dropped
+    pub fn internalize(&self, name: &str) {
+        if let Some(function) = self.module.get_function(name) {
+            function.set_linkage(inkwell::module::Linkage::Internal);
+        }
+    }
+
+    /// Write LLVM bitcode, for link-time optimization
+    pub fn write_bitcode_file(&self, path: &Path) -> Result<()> {
+        if self.module.write_bitcode_to_path(path) {
+            Ok(())
+        } else {
+            Err(BackendError::CodeGenError(format!("Failed to write bitcode to {}", path.display())))
+        }
+    }
+
+    /// Write object file for the module's target
     pub fn write_object_file(&self, path: &Path) -> Result<()> {
         // Initialize target
-        Target::initialize_native(&InitializationConfig::default())
-            .map_err(|e| BackendError::TargetMachineError(e.to_string()))?;
+        Target::initialize_all(&InitializationConfig::default());
 
-        let triple = TargetMachine::get_default_triple();
+        let triple = self.module.get_triple();
         let target = Target::from_triple(&triple)
             .map_err(|e| BackendError::TargetMachineError(e.to_string()))?;
 
-        let cpu = TargetMachine::get_host_cpu_name().to_string();
-        let features = TargetMachine::get_host_cpu_features().to_string();
+        // Tune for the host only when the host is the target
+        let (cpu, features) = if triple == TargetMachine::get_default_triple() {
+            (TargetMachine::get_host_cpu_name().to_string(), TargetMachine::get_host_cpu_features().to_string())
+        } else {
+            ("generic".to_string(), String::new())
+        };
 
         let target_machine = target
             .create_target_machine(
@@ -488,8 +791,8 @@ impl<'ctx> LLVMBackend<'ctx> {
 
 impl<'ctx> CodeGenerator for LLVMBackend<'ctx> {
     fn generate(&mut self, ssa_func: &SSAFunction) -> Result<()> {
-        // Create LLVM function
-        let function = self.create_function(ssa_func)?;
+        // Create LLVM function, unless it was declared ahead of time
+        let function = self.declare(ssa_func)?;
         self.current_function = Some(function);
 
         // Create basic blocks
@@ -508,6 +811,9 @@ impl<'ctx> CodeGenerator for LLVMBackend<'ctx> {
             for inst in &block.instructions {
                 self.generate_instruction(inst)?;
             }
+            if let Some(exit) = self.builder.get_insert_block().filter(|exit| exit != bb) {
+                self.exits.insert(block.id, exit);
+            }
         }
 
         Ok(())
@@ -0,0 +1,179 @@
+//! Parallel Code Generation
+//!
+//! A large program is split into codegen units along its call graph (see
+//! [`crate::partition`]). Each unit is built in its own LLVM context and
+//! module on its own thread, and the linker merges the units' objects into
+//! one. With LTO the units are written as bitcode instead, and the link
+//! step optimizes across them and the C runtime, recovering the inlining
+//! the split gave up and inlining runtime primitives into words.
+//!
+//! Words the whole-program optimizer found to be internal get internal
+//! linkage when nothing outside their own unit calls them, so LLVM can
+//! inline them into every caller and drop the out-of-line copy.
+
+use super::{CodeGenerator, CompilationMode, LLVMBackend};
+use crate::error::{BackendError, Result};
+use crate::linker::{Linker, LinkerConfig, Lto};
+use crate::partition::partition;
+use fastforth_frontend::ssa::{SSAFunction, SSAInstruction};
+use fastforth_frontend::Arithmetic;
+use inkwell::context::Context;
+use inkwell::OptimizationLevel;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// How a program is split for code generation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodegenUnits {
+    /// Most units to split into; 1 builds a single module
+    pub units: usize,
+    /// Optimize across units, and with the runtime, at link time
+    pub lto: Lto,
+}
+
+impl Default for CodegenUnits {
+    fn default() -> Self {
+        Self {
+            units: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            lto: Lto::Off,
+        }
+    }
+}
+
+/// Compile functions into one relocatable object at `output`, one codegen
+/// unit per thread
+///
+/// `opt_level` runs from 0 (none) to 3 (aggressive), and `arithmetic` is
+/// how words divide and overflow. `internal` names the words nothing
+/// outside the program calls. With LTO, the C `runtime` sources are
+/// compiled to bitcode and merged in, so the object carries its own
+/// runtime.
+pub fn compile_object(
+    functions: &[SSAFunction],
+    opt_level: u8,
+    arithmetic: Arithmetic,
+    units: CodegenUnits,
+    internal: &HashSet<String>,
+    runtime: &[&str],
+    output: &Path,
+) -> Result<()> {
+    let opt_level = match opt_level {
+        0 => OptimizationLevel::None,
+        1 => OptimizationLevel::Less,
+        2 => OptimizationLevel::Default,
+        _ => OptimizationLevel::Aggressive,
+    };
+    let dir = output.with_extension("units");
+    std::fs::create_dir_all(&dir)?;
+
+    let partitions = partition(functions, units.units);
+    let local = internal_to_unit(functions, &partitions, internal);
+    let local = &local;
+    let extension = if units.lto.enabled() { "bc" } else { "o" };
+    let mut files: Vec<PathBuf> = (0..partitions.len())
+        .map(|i| dir.join(format!("unit{}.{}", i, extension)))
+        .collect();
+
+    let compiled = std::thread::scope(|scope| {
+        let workers: Vec<_> = partitions
+            .iter()
+            .zip(&files)
+            .enumerate()
+            .map(|(index, (unit, file))| {
+                scope.spawn(move || compile_unit(functions, unit, index, local, opt_level, arithmetic, units.lto, file))
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| {
+                worker.join().unwrap_or_else(|_| {
+                    Err(BackendError::CodeGenError("Codegen unit panicked".to_string()))
+                })
+            })
+            .collect::<Result<Vec<()>>>()
+    });
+
+    let merged = compiled.and_then(|_| {
+        for (index, source) in runtime.iter().enumerate().filter(|_| units.lto.enabled()) {
+            let runtime_lib = dir.join(format!("runtime{}.c", index));
+            std::fs::write(&runtime_lib, source)?;
+            let linker = Linker::new(LinkerConfig { runtime_lib, lto: units.lto, ..LinkerConfig::default() });
+            files.push(linker.compile_runtime()?);
+        }
+        let linker = Linker::new(LinkerConfig { lto: units.lto, ..LinkerConfig::default() });
+        linker.merge_objects(&files, output)
+    });
+    let _ = std::fs::remove_dir_all(&dir);
+    merged
+}
+
+/// Which functions can have internal linkage: the internal words called,
+/// or whose execution tokens are taken, only from their own unit
+fn internal_to_unit(
+    functions: &[SSAFunction],
+    partitions: &[Vec<usize>],
+    internal: &HashSet<String>,
+) -> Vec<bool> {
+    let mut unit_of = vec![0; functions.len()];
+    for (unit, members) in partitions.iter().enumerate() {
+        for &i in members {
+            unit_of[i] = unit;
+        }
+    }
+    let index: HashMap<&str, usize> = functions
+        .iter()
+        .enumerate()
+        .map(|(i, func)| (func.name.as_str(), i))
+        .collect();
+
+    let mut local: Vec<bool> = functions.iter().map(|func| internal.contains(&func.name)).collect();
+    for (caller, func) in functions.iter().enumerate() {
+        for inst in func.blocks.iter().flat_map(|block| &block.instructions) {
+            if let SSAInstruction::Call { name, .. } | SSAInstruction::FunctionAddress { name, .. } = inst {
+                if let Some(&callee) = index.get(name.as_str()) {
+                    if unit_of[callee] != unit_of[caller] {
+                        local[callee] = false;
+                    }
+                }
+            }
+        }
+    }
+    local
+}
+
+/// Build one unit: every function is declared, only the unit's own get
+/// bodies
+#[allow(clippy::too_many_arguments)]
+fn compile_unit(
+    functions: &[SSAFunction],
+    unit: &[usize],
+    index: usize,
+    local: &[bool],
+    opt_level: OptimizationLevel,
+    arithmetic: Arithmetic,
+    lto: Lto,
+    path: &Path,
+) -> Result<()> {
+    let context = Context::create();
+    let mut backend = LLVMBackend::new(&context, &format!("unit{}", index), CompilationMode::AOT, opt_level);
+    backend.set_arithmetic(arithmetic);
+
+    for func in functions {
+        backend.declare(func)?;
+    }
+    for &i in unit {
+        backend.generate(&functions[i])?;
+        if local[i] {
+            backend.internalize(&functions[i].name);
+        }
+    }
+
+    if !lto.enabled() {
+        return backend.finalize(path);
+    }
+    backend.verify_module()?;
+    if opt_level != OptimizationLevel::None {
+        backend.optimize();
+    }
+    backend.write_bitcode_file(path)
+}
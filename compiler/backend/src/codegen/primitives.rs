@@ -1,37 +1,67 @@
 //! Primitive Operation Code Generation
 //!
 //! Generate native code for Forth primitive operations (+, -, *, /, etc.)
+//!
+//! Integer arithmetic follows the compilation's [`Arithmetic`]. LLVM leaves
+//! division by zero and the quotient of the most negative cell by -1
+//! undefined, so both are checked: the first always traps, the second
+//! wraps, traps or throws as overflow does. Checked arithmetic throws
+//! through `forth_throw` in the runtime.
 
 use crate::error::{BackendError, Result};
 use fastforth_frontend::ssa::{BinaryOperator, UnaryOperator};
+use fastforth_frontend::{Arithmetic, Division, Overflow, RESULT_OUT_OF_RANGE};
 use inkwell::builder::Builder;
 use inkwell::context::Context;
-use inkwell::values::{BasicValueEnum, IntValue, FloatValue};
+use inkwell::intrinsics::Intrinsic;
+use inkwell::module::Module;
+use inkwell::types::BasicTypeEnum;
+use inkwell::values::{BasicMetadataValueEnum, BasicValueEnum, FunctionValue, IntValue, FloatValue};
 use inkwell::{IntPredicate, FloatPredicate};
 
 /// Primitive operation code generator
 pub struct PrimitiveCodegen<'ctx> {
     context: &'ctx Context,
+    arithmetic: Arithmetic,
 }
 
 impl<'ctx> PrimitiveCodegen<'ctx> {
     pub fn new(context: &'ctx Context) -> Self {
-        Self { context }
+        Self { context, arithmetic: Arithmetic::default() }
+    }
+
+    /// Generate division and overflow under `arithmetic`
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
     }
 
     /// Generate code for binary operation
+    ///
+    /// Checked arithmetic calls intrinsics declared in `module` and may end
+    /// the builder's block, continuing in a new one.
     pub fn generate_binary_op(
         &self,
         builder: &Builder<'ctx>,
+        module: &Module<'ctx>,
         op: BinaryOperator,
         lhs: BasicValueEnum<'ctx>,
         rhs: BasicValueEnum<'ctx>,
     ) -> Result<BasicValueEnum<'ctx>> {
+        if self.arithmetic.overflow != Overflow::Wrapping
+            && matches!(op, BinaryOperator::Add | BinaryOperator::Sub | BinaryOperator::Mul)
+            && lhs.is_int_value()
+            && rhs.is_int_value()
+        {
+            return self.gen_checked(builder, module, op, lhs.into_int_value(), rhs.into_int_value());
+        }
         match op {
             // Arithmetic operations
             BinaryOperator::Add => self.gen_add(builder, lhs, rhs),
             BinaryOperator::Sub => self.gen_sub(builder, lhs, rhs),
             BinaryOperator::Mul => self.gen_mul(builder, lhs, rhs),
+            BinaryOperator::Div | BinaryOperator::Mod if lhs.is_int_value() && rhs.is_int_value() => {
+                self.gen_int_divide(builder, module, op, lhs.into_int_value(), rhs.into_int_value())
+            }
             BinaryOperator::Div => self.gen_div(builder, lhs, rhs),
             BinaryOperator::Mod => self.gen_mod(builder, lhs, rhs),
 
@@ -43,16 +73,273 @@ impl<'ctx> PrimitiveCodegen<'ctx> {
             BinaryOperator::Eq => self.gen_eq(builder, lhs, rhs),
             BinaryOperator::Ne => self.gen_ne(builder, lhs, rhs),
 
-            // Logical operations
+            // Bitwise operations
             BinaryOperator::And => self.gen_and(builder, lhs, rhs),
             BinaryOperator::Or => self.gen_or(builder, lhs, rhs),
+
+            // Integer-only operations
+            BinaryOperator::Xor
+            | BinaryOperator::Shl
+            | BinaryOperator::Shr
+            | BinaryOperator::ULt
+            | BinaryOperator::UGt
+            | BinaryOperator::UDiv
+            | BinaryOperator::UMod
+            | BinaryOperator::UMul
+            | BinaryOperator::UMulHigh
+            | BinaryOperator::Rotl
+            | BinaryOperator::Rotr => {
+                if !lhs.is_int_value() || !rhs.is_int_value() {
+                    return Err(BackendError::CodeGenError(format!("{} requires integer operands", op)));
+                }
+                self.gen_integer_op(builder, module, op, lhs.into_int_value(), rhs.into_int_value())
+            }
         }
     }
 
+    /// Bitwise, shift and unsigned operations on integers
+    ///
+    /// Shift amounts are taken modulo 64 and division by zero traps, as in
+    /// the Cranelift backend; LLVM leaves both undefined.
+    fn gen_integer_op(
+        &self,
+        builder: &Builder<'ctx>,
+        module: &Module<'ctx>,
+        op: BinaryOperator,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let cell = lhs.get_type();
+        let value = match op {
+            BinaryOperator::Xor => builder.build_xor(lhs, rhs, "xor")
+                .map_err(|e| BackendError::CodeGenError(e.to_string()))?,
+            BinaryOperator::Shl | BinaryOperator::Shr => {
+                let amount = builder.build_and(rhs, cell.const_int(63, false), "amount")
+                    .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+                if op == BinaryOperator::Shl {
+                    builder.build_left_shift(lhs, amount, "shl")
+                        .map_err(|e| BackendError::CodeGenError(e.to_string()))?
+                } else {
+                    builder.build_right_shift(lhs, amount, false, "shr")
+                        .map_err(|e| BackendError::CodeGenError(e.to_string()))?
+                }
+            }
+            BinaryOperator::ULt | BinaryOperator::UGt => {
+                let predicate = if op == BinaryOperator::ULt { IntPredicate::ULT } else { IntPredicate::UGT };
+                let flag = builder.build_int_compare(predicate, lhs, rhs, "ucmp")
+                    .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+                builder.build_int_s_extend(flag, self.context.i64_type(), "ucmp_ext")
+                    .map_err(|e| BackendError::CodeGenError(e.to_string()))?
+            }
+            BinaryOperator::UDiv | BinaryOperator::UMod => {
+                let is_zero = builder.build_int_compare(IntPredicate::EQ, rhs, cell.const_zero(), "by_zero")
+                    .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+                self.trap_if(builder, module, is_zero)?;
+                if op == BinaryOperator::UDiv {
+                    builder.build_int_unsigned_div(lhs, rhs, "udiv")
+                        .map_err(|e| BackendError::CodeGenError(e.to_string()))?
+                } else {
+                    builder.build_int_unsigned_rem(lhs, rhs, "umod")
+                        .map_err(|e| BackendError::CodeGenError(e.to_string()))?
+                }
+            }
+            BinaryOperator::UMul => builder.build_int_mul(lhs, rhs, "umul")
+                .map_err(|e| BackendError::CodeGenError(e.to_string()))?,
+            // A funnel shift of a value with itself is a rotate, and takes
+            // the amount modulo the width
+            BinaryOperator::Rotl | BinaryOperator::Rotr => {
+                let name = if op == BinaryOperator::Rotl { "llvm.fshl" } else { "llvm.fshr" };
+                let intrinsic = self.intrinsic(module, name, &[cell.into()])?;
+                builder.build_call(intrinsic, &[lhs.into(), lhs.into(), rhs.into()], "rotate")
+                    .map_err(|e| BackendError::CodeGenError(e.to_string()))?
+                    .try_as_basic_value()
+                    .left()
+                    .ok_or_else(|| BackendError::CodeGenError(format!("{} returned no value", name)))?
+                    .into_int_value()
+            }
+            _ => {
+                let double = self.context.i128_type();
+                let a = builder.build_int_z_extend(lhs, double, "a")
+                    .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+                let b = builder.build_int_z_extend(rhs, double, "b")
+                    .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+                let product = builder.build_int_mul(a, b, "product")
+                    .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+                let high = builder.build_right_shift(product, double.const_int(64, false), false, "high")
+                    .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+                builder.build_int_truncate(high, cell, "umulhi")
+                    .map_err(|e| BackendError::CodeGenError(e.to_string()))?
+            }
+        };
+        Ok(value.into())
+    }
+
+    /// `+`, `-` or `*` of integers, trapping or throwing on overflow
+    fn gen_checked(
+        &self,
+        builder: &Builder<'ctx>,
+        module: &Module<'ctx>,
+        op: BinaryOperator,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let name = match op {
+            BinaryOperator::Add => "llvm.sadd.with.overflow",
+            BinaryOperator::Sub => "llvm.ssub.with.overflow",
+            _ => "llvm.smul.with.overflow",
+        };
+        let intrinsic = self.intrinsic(module, name, &[lhs.get_type().into()])?;
+        let pair = builder.build_call(intrinsic, &[lhs.into(), rhs.into()], "checked")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| BackendError::CodeGenError(format!("{} returned no value", name)))?
+            .into_struct_value();
+        let result = builder.build_extract_value(pair, 0, "result")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+        let overflowed = builder.build_extract_value(pair, 1, "overflowed")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?
+            .into_int_value();
+        self.overflow_if(builder, module, overflowed)?;
+        Ok(result)
+    }
+
+    /// `/` or `mod` of integers under the arithmetic semantics
+    ///
+    /// The same sequence the Cranelift backend emits: dividing by -1 is
+    /// negation when overflow wraps, and floored division adjusts a
+    /// truncated result whose remainder is nonzero and differs in sign from
+    /// the divisor.
+    fn gen_int_divide(
+        &self,
+        builder: &Builder<'ctx>,
+        module: &Module<'ctx>,
+        op: BinaryOperator,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let cell = lhs.get_type();
+        let zero = cell.const_zero();
+        let minus_one = cell.const_all_ones();
+        let is_zero = builder.build_int_compare(IntPredicate::EQ, rhs, zero, "by_zero")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+        self.trap_if(builder, module, is_zero)?;
+
+        let by_minus_one = builder.build_int_compare(IntPredicate::EQ, rhs, minus_one, "by_minus_one")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+        if op == BinaryOperator::Div && self.arithmetic.overflow != Overflow::Wrapping {
+            let most_negative = cell.const_int(i64::MIN as u64, true);
+            let is_most_negative = builder.build_int_compare(IntPredicate::EQ, lhs, most_negative, "most_negative")
+                .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+            let overflows = builder.build_and(is_most_negative, by_minus_one, "overflows")
+                .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+            self.overflow_if(builder, module, overflows)?;
+        }
+
+        // Divide by 1 instead of -1, so neither instruction is undefined
+        let divisor = builder.build_select(by_minus_one, cell.const_int(1, false), rhs, "divisor")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?
+            .into_int_value();
+        let remainder = builder.build_int_signed_rem(lhs, divisor, "rem")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+        let rounds_down = if self.arithmetic.division == Division::Floored {
+            let nonzero = builder.build_int_compare(IntPredicate::NE, remainder, zero, "rem_nonzero")
+                .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+            let signs = builder.build_xor(remainder, rhs, "signs")
+                .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+            let signs_differ = builder.build_int_compare(IntPredicate::SLT, signs, zero, "signs_differ")
+                .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+            Some(builder.build_and(nonzero, signs_differ, "rounds_down")
+                .map_err(|e| BackendError::CodeGenError(e.to_string()))?)
+        } else {
+            None
+        };
+
+        if op == BinaryOperator::Mod {
+            let Some(rounds_down) = rounds_down else {
+                return Ok(remainder.into());
+            };
+            let adjusted = builder.build_int_add(remainder, rhs, "floored_rem")
+                .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+            return builder.build_select(rounds_down, adjusted, remainder, "mod")
+                .map_err(|e| BackendError::CodeGenError(e.to_string()));
+        }
+
+        let quotient = builder.build_int_signed_div(lhs, divisor, "quot")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+        let negated = builder.build_int_neg(lhs, "neg")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+        let mut quotient = builder.build_select(by_minus_one, negated, quotient, "div")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?
+            .into_int_value();
+        if let Some(rounds_down) = rounds_down {
+            let borrow = builder.build_int_z_extend(rounds_down, cell, "borrow")
+                .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+            quotient = builder.build_int_sub(quotient, borrow, "floored_div")
+                .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+        }
+        Ok(quotient.into())
+    }
+
+    /// Branch to a trap if `condition` holds, continuing in a new block
+    fn trap_if(&self, builder: &Builder<'ctx>, module: &Module<'ctx>, condition: IntValue<'ctx>) -> Result<()> {
+        let trap = self.intrinsic(module, "llvm.trap", &[])?;
+        self.fail_if(builder, condition, trap, &[])
+    }
+
+    /// Trap on an overflow, or with checked arithmetic throw
+    /// [`RESULT_OUT_OF_RANGE`], if `condition` holds
+    fn overflow_if(&self, builder: &Builder<'ctx>, module: &Module<'ctx>, condition: IntValue<'ctx>) -> Result<()> {
+        if self.arithmetic.overflow != Overflow::Checked {
+            return self.trap_if(builder, module, condition);
+        }
+        let cell = self.context.i64_type();
+        let throw = module.get_function("forth_throw").unwrap_or_else(|| {
+            module.add_function("forth_throw", self.context.void_type().fn_type(&[cell.into()], false), None)
+        });
+        let code = cell.const_int(RESULT_OUT_OF_RANGE as u64, true);
+        self.fail_if(builder, condition, throw, &[code.into()])
+    }
+
+    /// Branch to a call of `handler`, which never returns, if `condition`
+    /// holds, continuing in a new block
+    fn fail_if(
+        &self,
+        builder: &Builder<'ctx>,
+        condition: IntValue<'ctx>,
+        handler: FunctionValue<'ctx>,
+        args: &[BasicMetadataValueEnum<'ctx>],
+    ) -> Result<()> {
+        let function = builder.get_insert_block()
+            .and_then(|block| block.get_parent())
+            .ok_or_else(|| BackendError::CodeGenError("arithmetic outside a function".to_string()))?;
+        let fail = self.context.append_basic_block(function, "fail");
+        let next = self.context.append_basic_block(function, "no_fail");
+        builder.build_conditional_branch(condition, fail, next)
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+
+        builder.position_at_end(fail);
+        builder.build_call(handler, args, "")
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+        builder.build_unreachable()
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?;
+
+        builder.position_at_end(next);
+        Ok(())
+    }
+
+    /// Declaration of an LLVM intrinsic overloaded on `types`
+    fn intrinsic(&self, module: &Module<'ctx>, name: &str, types: &[BasicTypeEnum<'ctx>]) -> Result<FunctionValue<'ctx>> {
+        Intrinsic::find(name)
+            .and_then(|intrinsic| intrinsic.get_declaration(module, types))
+            .ok_or_else(|| BackendError::CodeGenError(format!("LLVM intrinsic {} is unavailable", name)))
+    }
+
     /// Generate code for unary operation
     pub fn generate_unary_op(
         &self,
         builder: &Builder<'ctx>,
+        module: &Module<'ctx>,
         op: UnaryOperator,
         operand: BasicValueEnum<'ctx>,
     ) -> Result<BasicValueEnum<'ctx>> {
@@ -60,7 +347,40 @@ impl<'ctx> PrimitiveCodegen<'ctx> {
             UnaryOperator::Negate => self.gen_negate(builder, operand),
             UnaryOperator::Not => self.gen_not(builder, operand),
             UnaryOperator::Abs => self.gen_abs(builder, operand),
+            UnaryOperator::Popcount | UnaryOperator::Ctz | UnaryOperator::Clz => {
+                if !operand.is_int_value() {
+                    return Err(BackendError::CodeGenError(format!("{} requires an integer operand", op)));
+                }
+                self.gen_bit_count(builder, module, op, operand.into_int_value())
+            }
+        }
+    }
+
+    /// `popcount`, `ctz` or `clz` through the LLVM intrinsic, which lowers
+    /// to a single instruction where the target has one
+    fn gen_bit_count(
+        &self,
+        builder: &Builder<'ctx>,
+        module: &Module<'ctx>,
+        op: UnaryOperator,
+        operand: IntValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let name = match op {
+            UnaryOperator::Popcount => "llvm.ctpop",
+            UnaryOperator::Ctz => "llvm.cttz",
+            _ => "llvm.ctlz",
+        };
+        let intrinsic = self.intrinsic(module, name, &[operand.get_type().into()])?;
+        let mut args: Vec<BasicMetadataValueEnum<'ctx>> = vec![operand.into()];
+        if op != UnaryOperator::Popcount {
+            // Zero is defined, giving the cell width
+            args.push(self.context.bool_type().const_zero().into());
         }
+        builder.build_call(intrinsic, &args, name)
+            .map_err(|e| BackendError::CodeGenError(e.to_string()))?
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| BackendError::CodeGenError(format!("{} returned no value", name)))
     }
 
     // Arithmetic operations
@@ -198,8 +518,8 @@ impl<'ctx> PrimitiveCodegen<'ctx> {
                 rhs.into_int_value(),
                 "lt"
             ).map_err(|e| BackendError::CodeGenError(e.to_string()))?;
-            // Extend to i64 for Forth compatibility
-            let extended = builder.build_int_z_extend(
+            // Sign-extend to i64: a true Forth flag is all ones
+            let extended = builder.build_int_s_extend(
                 result,
                 self.context.i64_type(),
                 "lt_ext"
@@ -212,7 +532,7 @@ impl<'ctx> PrimitiveCodegen<'ctx> {
                 rhs.into_float_value(),
                 "flt"
             ).map_err(|e| BackendError::CodeGenError(e.to_string()))?;
-            let extended = builder.build_int_z_extend(
+            let extended = builder.build_int_s_extend(
                 result,
                 self.context.i64_type(),
                 "flt_ext"
@@ -236,7 +556,7 @@ impl<'ctx> PrimitiveCodegen<'ctx> {
                 rhs.into_int_value(),
                 "gt"
             ).map_err(|e| BackendError::CodeGenError(e.to_string()))?;
-            let extended = builder.build_int_z_extend(
+            let extended = builder.build_int_s_extend(
                 result,
                 self.context.i64_type(),
                 "gt_ext"
@@ -249,7 +569,7 @@ impl<'ctx> PrimitiveCodegen<'ctx> {
                 rhs.into_float_value(),
                 "fgt"
             ).map_err(|e| BackendError::CodeGenError(e.to_string()))?;
-            let extended = builder.build_int_z_extend(
+            let extended = builder.build_int_s_extend(
                 result,
                 self.context.i64_type(),
                 "fgt_ext"
@@ -273,7 +593,7 @@ impl<'ctx> PrimitiveCodegen<'ctx> {
                 rhs.into_int_value(),
                 "le"
             ).map_err(|e| BackendError::CodeGenError(e.to_string()))?;
-            let extended = builder.build_int_z_extend(
+            let extended = builder.build_int_s_extend(
                 result,
                 self.context.i64_type(),
                 "le_ext"
@@ -297,7 +617,7 @@ impl<'ctx> PrimitiveCodegen<'ctx> {
                 rhs.into_int_value(),
                 "ge"
             ).map_err(|e| BackendError::CodeGenError(e.to_string()))?;
-            let extended = builder.build_int_z_extend(
+            let extended = builder.build_int_s_extend(
                 result,
                 self.context.i64_type(),
                 "ge_ext"
@@ -321,7 +641,7 @@ impl<'ctx> PrimitiveCodegen<'ctx> {
                 rhs.into_int_value(),
                 "eq"
             ).map_err(|e| BackendError::CodeGenError(e.to_string()))?;
-            let extended = builder.build_int_z_extend(
+            let extended = builder.build_int_s_extend(
                 result,
                 self.context.i64_type(),
                 "eq_ext"
@@ -334,7 +654,7 @@ impl<'ctx> PrimitiveCodegen<'ctx> {
                 rhs.into_float_value(),
                 "feq"
             ).map_err(|e| BackendError::CodeGenError(e.to_string()))?;
-            let extended = builder.build_int_z_extend(
+            let extended = builder.build_int_s_extend(
                 result,
                 self.context.i64_type(),
                 "feq_ext"
@@ -358,7 +678,7 @@ impl<'ctx> PrimitiveCodegen<'ctx> {
                 rhs.into_int_value(),
                 "ne"
             ).map_err(|e| BackendError::CodeGenError(e.to_string()))?;
-            let extended = builder.build_int_z_extend(
+            let extended = builder.build_int_s_extend(
                 result,
                 self.context.i64_type(),
                 "ne_ext"
@@ -0,0 +1,220 @@
+//! Assembler Output
+//!
+//! A Cranelift [`Module`] that writes the machine code of each function as
+//! GNU assembler source: the bytes as `.byte` data and each relocation as a
+//! `.reloc` directive, so the system assembler turns the text into an
+//! ordinary relocatable object. This is how compiled words reach object
+//! files and shared libraries without an object file writer.
+//!
+//! Functions are emitted under the names they are declared with, which for
+//! compiled words are their [`mangle`](crate::mangle::mangle)d symbols.
+//! Zero-initialized data objects, the variables of a program, go in `.bss`.
+//! Exported words are global but hidden, visible to the rest of a link but
+//! not exported from a shared library; the library's C entry points are
+//! separate trampolines. Only ELF targets are supported.
+
+use cranelift_codegen::binemit::Reloc;
+use cranelift_codegen::control::ControlPlane;
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_codegen::{ir, CodegenError, Context, FinalizedMachReloc};
+use cranelift_module::{
+    DataDescription, DataId, FuncId, Init, Linkage, Module, ModuleDeclarations, ModuleError, ModuleReloc,
+    ModuleRelocTarget, ModuleResult,
+};
+use std::fmt::Write;
+use std::sync::Arc;
+use target_lexicon::{Architecture, BinaryFormat};
+
+/// Module collecting compiled functions as assembler source
+pub struct AssemblyModule {
+    isa: Arc<dyn TargetIsa>,
+    declarations: ModuleDeclarations,
+    libcall_names: Box<dyn Fn(ir::LibCall) -> String + Send + Sync>,
+    text: String,
+}
+
+impl AssemblyModule {
+    /// Create an empty module for `isa`
+    pub fn new(isa: Arc<dyn TargetIsa>) -> crate::error::Result<Self> {
+        if isa.triple().binary_format != BinaryFormat::Elf {
+            return Err(crate::error::BackendError::Initialization(format!(
+                "Assembler output supports ELF targets, not {}",
+                isa.triple()
+            )));
+        }
+        Ok(Self {
+            isa,
+            declarations: ModuleDeclarations::default(),
+            libcall_names: cranelift_module::default_libcall_names(),
+            text: String::new(),
+        })
+    }
+
+    /// The assembler source of every function defined so far
+    pub fn finish(mut self) -> String {
+        self.text.push_str("\t.section .note.GNU-stack,\"\",@progbits\n");
+        self.text
+    }
+
+    /// Symbol a declared function is known by in the assembler source
+    fn function_symbol(&self, id: FuncId) -> String {
+        self.declarations.get_function_decl(id).linkage_name(id).into_owned()
+    }
+
+    /// Symbol a declared data object is known by in the assembler source
+    fn data_symbol(&self, id: DataId) -> String {
+        self.declarations.get_data_decl(id).linkage_name(id).into_owned()
+    }
+
+    /// Relocation target as an assembler expression
+    fn target(&self, target: &ModuleRelocTarget) -> Option<String> {
+        match target {
+            ModuleRelocTarget::User { namespace: 0, index } => Some(self.function_symbol(FuncId::from_u32(*index))),
+            ModuleRelocTarget::User { namespace: 1, index } => Some(self.data_symbol(DataId::from_u32(*index))),
+            ModuleRelocTarget::LibCall(libcall) => Some((self.libcall_names)(*libcall)),
+            ModuleRelocTarget::FunctionOffset(id, offset) => Some(format!("{}+{}", self.function_symbol(*id), offset)),
+            _ => None,
+        }
+    }
+}
+
+/// ELF relocation type for a Cranelift relocation kind
+fn elf_relocation(kind: Reloc, architecture: Architecture) -> Option<&'static str> {
+    let aarch64 = matches!(architecture, Architecture::Aarch64(_));
+    let name = match kind {
+        Reloc::Abs4 if aarch64 => "R_AARCH64_ABS32",
+        Reloc::Abs4 => "R_X86_64_32",
+        Reloc::Abs8 if aarch64 => "R_AARCH64_ABS64",
+        Reloc::Abs8 => "R_X86_64_64",
+        Reloc::X86PCRel4 => "R_X86_64_PC32",
+        Reloc::X86CallPCRel4 | Reloc::X86CallPLTRel4 => "R_X86_64_PLT32",
+        Reloc::X86GOTPCRel4 => "R_X86_64_GOTPCREL",
+        Reloc::Arm64Call => "R_AARCH64_CALL26",
+        Reloc::Aarch64AdrGotPage21 => "R_AARCH64_ADR_GOT_PAGE",
+        Reloc::Aarch64Ld64GotLo12Nc => "R_AARCH64_LD64_GOT_LO12_NC",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn unsupported(what: String) -> ModuleError {
+    ModuleError::Compilation(CodegenError::Unsupported(format!("{} in assembler output", what)))
+}
+
+impl Module for AssemblyModule {
+    fn isa(&self) -> &dyn TargetIsa {
+        self.isa.as_ref()
+    }
+
+    fn declarations(&self) -> &ModuleDeclarations {
+        &self.declarations
+    }
+
+    fn declare_function(&mut self, name: &str, linkage: Linkage, signature: &ir::Signature) -> ModuleResult<FuncId> {
+        let (id, _) = self.declarations.declare_function(name, linkage, signature)?;
+        Ok(id)
+    }
+
+    fn declare_anonymous_function(&mut self, signature: &ir::Signature) -> ModuleResult<FuncId> {
+        self.declarations.declare_anonymous_function(signature)
+    }
+
+    fn declare_data(&mut self, name: &str, linkage: Linkage, writable: bool, tls: bool) -> ModuleResult<DataId> {
+        let (id, _) = self.declarations.declare_data(name, linkage, writable, tls)?;
+        Ok(id)
+    }
+
+    fn declare_anonymous_data(&mut self, writable: bool, tls: bool) -> ModuleResult<DataId> {
+        self.declarations.declare_anonymous_data(writable, tls)
+    }
+
+    fn define_function_with_control_plane(
+        &mut self,
+        func_id: FuncId,
+        ctx: &mut Context,
+        ctrl_plane: &mut ControlPlane,
+    ) -> ModuleResult<()> {
+        let code = ctx.compile(self.isa.as_ref(), ctrl_plane)?;
+        let alignment = code.buffer.alignment as u64;
+        let bytes = code.code_buffer().to_vec();
+        let relocs = code.buffer.relocs().to_vec();
+        self.define_function_bytes(func_id, &ctx.func, alignment, &bytes, &relocs)
+    }
+
+    fn define_function_bytes(
+        &mut self,
+        func_id: FuncId,
+        func: &ir::Function,
+        alignment: u64,
+        bytes: &[u8],
+        relocs: &[FinalizedMachReloc],
+    ) -> ModuleResult<()> {
+        let decl = self.declarations.get_function_decl(func_id);
+        if !decl.linkage.is_definable() {
+            return Err(ModuleError::InvalidImportDefinition(decl.linkage_name(func_id).into_owned()));
+        }
+        let global = decl.linkage != Linkage::Local;
+        let symbol = self.function_symbol(func_id);
+
+        let mut text = String::from("\t.text\n");
+        if global {
+            let _ = writeln!(text, "\t.globl {symbol}\n\t.hidden {symbol}");
+        }
+        let _ = writeln!(text, "\t.type {symbol}, @function\n\t.p2align {}\n{symbol}:", alignment.max(1).trailing_zeros());
+        for chunk in bytes.chunks(16) {
+            let line: Vec<String> = chunk.iter().map(|byte| format!("0x{:02x}", byte)).collect();
+            let _ = writeln!(text, "\t.byte {}", line.join(","));
+        }
+        for reloc in relocs {
+            let reloc = ModuleReloc::from_mach_reloc(reloc, func, func_id);
+            let kind = elf_relocation(reloc.kind, self.isa.triple().architecture)
+                .ok_or_else(|| unsupported(format!("{} relocation", reloc.kind)))?;
+            let target = self
+                .target(&reloc.name)
+                .ok_or_else(|| unsupported(format!("relocation against {}", reloc.name)))?;
+            let _ = writeln!(text, "\t.reloc {symbol}+{}, {kind}, {target}{:+}", reloc.offset, reloc.addend);
+        }
+        let _ = writeln!(text, "\t.size {symbol}, .-{symbol}");
+
+        self.text.push_str(&text);
+        Ok(())
+    }
+
+    fn define_data(&mut self, data_id: DataId, data: &DataDescription) -> ModuleResult<()> {
+        let decl = self.declarations.get_data_decl(data_id);
+        if !decl.linkage.is_definable() {
+            return Err(ModuleError::InvalidImportDefinition(decl.linkage_name(data_id).into_owned()));
+        }
+        let global = decl.linkage != Linkage::Local;
+        let symbol = self.data_symbol(data_id);
+        let Init::Zeros { size } = data.init else {
+            return Err(unsupported(format!("initialized data object {}", symbol)));
+        };
+
+        let mut text = String::from("\t.bss\n");
+        if global {
+            let _ = writeln!(text, "\t.globl {symbol}\n\t.hidden {symbol}");
+        }
+        let alignment = data.align.unwrap_or(1).max(1);
+        let _ = writeln!(text, "\t.type {symbol}, @object\n\t.p2align {}\n{symbol}:", alignment.trailing_zeros());
+        let _ = writeln!(text, "\t.zero {size}\n\t.size {symbol}, {size}");
+
+        self.text.push_str(&text);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elf_relocations() {
+        let x86_64 = Architecture::X86_64;
+        assert_eq!(elf_relocation(Reloc::X86CallPLTRel4, x86_64), Some("R_X86_64_PLT32"));
+        assert_eq!(elf_relocation(Reloc::X86GOTPCRel4, x86_64), Some("R_X86_64_GOTPCREL"));
+        assert_eq!(elf_relocation(Reloc::X86SecRel, x86_64), None);
+        let aarch64 = Architecture::Aarch64(target_lexicon::Aarch64Architecture::Aarch64);
+        assert_eq!(elf_relocation(Reloc::Abs8, aarch64), Some("R_AARCH64_ABS64"));
+    }
+}
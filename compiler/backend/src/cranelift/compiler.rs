@@ -3,90 +3,135 @@
 //! Fast compilation backend using Cranelift code generator.
 
 use crate::error::{BackendError, Result};
-use crate::cranelift::{CraneliftSettings, SSATranslator, FFIRegistry};
-use fastforth_frontend::ssa::SSAFunction;
+use crate::cranelift::{runtime_symbols, AssemblyModule, CraneliftSettings, SSATranslator, FFIRegistry};
+use crate::layout::CELL_BYTES;
+use crate::mangle::mangle;
+use fastforth_frontend::ssa::{SSAFunction, SSAInstruction};
+use fastforth_frontend::Arithmetic;
 
 use cranelift_codegen::ir::types;
 
-use cranelift_codegen::ir::{AbiParam, Function, FuncRef, Signature};
+use cranelift_codegen::control::ControlPlane;
+use cranelift_codegen::ir::{AbiParam, Function, FuncRef, GlobalValue, Signature, UserFuncName};
 use cranelift_codegen::isa::CallConv;
 use cranelift_codegen::settings::{self, Configurable, Flags};
-use cranelift_codegen::Context;
+use cranelift_codegen::{CompiledCode, Context};
 use cranelift_codegen::isa::TargetIsa;
 use cranelift_frontend::FunctionBuilderContext;
 use cranelift_jit::{JITBuilder, JITModule};
-use cranelift_module::{DataDescription, FuncId, Linkage, Module};
+use cranelift_codegen::FinalizedMachReloc;
+use cranelift_module::{DataDescription, DataId, FuncId, Linkage, Module};
+use rayon::prelude::*;
 use target_lexicon::Triple;
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// Cranelift backend for Fast Forth
-pub struct CraneliftBackend {
-    module: JITModule,
+///
+/// Compiles into a JIT module by default. A backend over an
+/// [`AssemblyModule`] compiles position-independent code into assembler
+/// source instead, for object files and shared libraries.
+pub struct CraneliftBackend<M: Module = JITModule> {
+    module: M,
     ctx: Context,
     builder_ctx: FunctionBuilderContext,
     settings: CraneliftSettings,
     functions: HashMap<String, FuncId>,
+    /// Data of each variable and CREATE buffer, see [`Self::define_variables`]
+    data: HashMap<String, DataId>,
     /// Cached function references for calls (populated during compilation)
     func_refs: HashMap<String, FuncRef>,
     /// FFI registry for external C function calls
     ffi_registry: FFIRegistry,
     /// Target ISA for verification
     isa: Arc<dyn TargetIsa>,
+    /// Compile time of each function, when timing
+    timings: Option<Vec<FunctionTiming>>,
+    /// Bytes of machine code of each function compiled
+    code_sizes: HashMap<String, usize>,
+    /// Line table of each function compiled, see [`Self::line_table`]
+    line_tables: HashMap<String, Vec<(u32, usize)>>,
+}
+
+/// When one function was compiled and for how long
+#[derive(Debug, Clone)]
+pub struct FunctionTiming {
+    pub name: String,
+    pub start: Instant,
+    pub duration: Duration,
+    /// 0 for the calling thread, 1 + the rayon worker index otherwise
+    pub thread: usize,
+}
+
+impl FunctionTiming {
+    fn since(name: &str, start: Instant) -> Self {
+        FunctionTiming {
+            name: name.to_string(),
+            start,
+            duration: start.elapsed(),
+            thread: rayon::current_thread_index().map_or(0, |index| index + 1),
+        }
+    }
 }
 
 impl CraneliftBackend {
     /// Create a new Cranelift backend with given settings
     pub fn new(settings: CraneliftSettings) -> Result<Self> {
-        // Get target triple (host or specified)
-        let triple = if let Some(triple_str) = settings.target_triple {
-            triple_str.parse().map_err(|e| {
-                BackendError::Initialization(format!("Invalid target triple: {}", e))
-            })?
-        } else {
-            Triple::host()
-        };
+        let isa = build_isa(&settings, false)?;
 
-        // Create Cranelift settings
-        let mut flag_builder = settings::builder();
-
-        // Set optimization level
-        match settings.opt_level {
-            0 => {
-                flag_builder.set("opt_level", "none")
-                    .map_err(|e| BackendError::Initialization(format!("Failed to set opt_level: {}", e)))?;
-            }
-            1 => {
-                flag_builder.set("opt_level", "speed")
-                    .map_err(|e| BackendError::Initialization(format!("Failed to set opt_level: {}", e)))?;
-            }
-            2 => {
-                flag_builder.set("opt_level", "speed_and_size")
-                    .map_err(|e| BackendError::Initialization(format!("Failed to set opt_level: {}", e)))?;
-            }
-            _ => {
-                return Err(BackendError::Initialization(
-                    "Cranelift supports opt_level 0-2. Use LLVM for -O3.".to_string()
-                ));
-            }
+        // Create JIT module (JITBuilder::with_isa takes Arc<dyn TargetIsa>)
+        let mut builder = JITBuilder::with_isa(isa.clone(), cranelift_module::default_libcall_names());
+        for (name, address) in runtime_symbols() {
+            builder.symbol(name, address);
         }
+        Self::with_module(JITModule::new(builder), isa, settings)
+    }
 
-        let flags = Flags::new(flag_builder);
+    /// Finalize all compiled functions (call after compiling all functions)
+    pub fn finalize_all(&mut self) -> Result<()> {
+        self.module.finalize_definitions()
+            .map_err(|e| BackendError::CodeGeneration(format!("Failed to finalize: {}", e)))?;
+        Ok(())
+    }
 
-        // Create ISA (returns Arc<dyn TargetIsa>)
-        let isa = cranelift_codegen::isa::lookup(triple)
-            .map_err(|e| BackendError::Initialization(format!("ISA lookup failed: {}", e)))?
-            .finish(flags)
-            .map_err(|e| BackendError::Initialization(format!("ISA creation failed: {}", e)))?;
+    /// Get pointer to compiled function by name
+    pub fn get_function(&self, name: &str) -> Option<*const u8> {
+        self.functions.get(name).map(|&func_id| {
+            self.module.get_finalized_function(func_id)
+        })
+    }
 
-        // Create JIT module (JITBuilder::with_isa takes Arc<dyn TargetIsa>)
-        let builder = JITBuilder::with_isa(isa.clone(), cranelift_module::default_libcall_names());
-        let mut module = JITModule::new(builder);
+    /// Address and size of the machine code of a compiled function, for
+    /// mapping code addresses back to it
+    pub fn code_range(&self, name: &str) -> Option<(*const u8, usize)> {
+        let size = *self.code_sizes.get(name)?;
+        Some((self.get_function(name)?, size))
+    }
+}
+
+impl CraneliftBackend<AssemblyModule> {
+    /// Create a backend that compiles position-independent code into
+    /// assembler source (ELF targets only)
+    pub fn assembly(settings: CraneliftSettings) -> Result<Self> {
+        let isa = build_isa(&settings, true)?;
+        let module = AssemblyModule::new(isa.clone())?;
+        Self::with_module(module, isa, settings)
+    }
 
-        // Initialize FFI registry and register libc functions
+    /// Assembler source of every function compiled so far
+    pub fn finish_assembly(self) -> String {
+        self.module.finish()
+    }
+}
+
+impl<M: Module> CraneliftBackend<M> {
+    fn with_module(mut module: M, isa: Arc<dyn TargetIsa>, settings: CraneliftSettings) -> Result<Self> {
+        // Initialize FFI registry and register libc and runtime functions
         let mut ffi_registry = FFIRegistry::new();
         ffi_registry.register_libc_functions(&mut module)?;
+        ffi_registry.register_runtime_functions(&mut module)?;
 
         Ok(Self {
             module,
@@ -94,31 +139,101 @@ impl CraneliftBackend {
             builder_ctx: FunctionBuilderContext::new(),
             settings,
             functions: HashMap::new(),
+            data: HashMap::new(),
             func_refs: HashMap::new(),
             ffi_registry,
             isa,
+            timings: None,
+            code_sizes: HashMap::new(),
+            line_tables: HashMap::new(),
         })
     }
 
+    /// Record a [`FunctionTiming`] for each function compiled (off by default)
+    pub fn set_timing(&mut self, timing: bool) {
+        self.timings = timing.then(Vec::new);
+    }
+
+    /// Take the timings recorded so far
+    pub fn take_timings(&mut self) -> Vec<FunctionTiming> {
+        self.timings.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Source lines of the machine code of a compiled function, as
+    /// (offset, line) where the code of a new line starts
+    pub fn line_table(&self, name: &str) -> &[(u32, usize)] {
+        self.line_tables.get(name).map_or(&[], Vec::as_slice)
+    }
+
     /// Declare all functions upfront (for recursion/inter-function calls)
     pub fn declare_all_functions(&mut self, functions: &[(String, &SSAFunction)]) -> Result<()> {
         for (name, ssa_func) in functions {
             // Create signature based on SSA function's parameters and return count
             let param_count = ssa_func.parameters.len();
-            let return_count = 1; // All Forth functions return 1 value (top of stack)
+            let return_count = ssa_func.result_count();
             let sig = self.create_signature(param_count, return_count);
 
+            // Under its mangled symbol: a word may share a name with a C function
             let func_id = self.module
-                .declare_function(name, Linkage::Export, &sig)
+                .declare_function(&mangle(name), Linkage::Export, &sig)
                 .map_err(|e| BackendError::CodeGeneration(format!("Failed to declare function '{}': {}", name, e)))?;
             self.functions.insert(name.clone(), func_id);
         }
+        self.define_variables(functions)
+    }
+
+    /// Define the data of each variable and CREATE buffer the functions
+    /// address: zeroed, aligned to a cell, and local to the module, so every
+    /// function compiled into it shares one copy
+    fn define_variables(&mut self, functions: &[(String, &SSAFunction)]) -> Result<()> {
+        let instructions = functions.iter().flat_map(|(_, ssa_func)| &ssa_func.blocks).flat_map(|block| &block.instructions);
+        for inst in instructions {
+            let SSAInstruction::DataAddress { name, size, .. } = inst else {
+                continue;
+            };
+            if self.data.contains_key(name) {
+                continue;
+            }
+            let data_id = self.module
+                .declare_data(&mangle(name), Linkage::Local, true, false)
+                .map_err(|e| BackendError::CodeGeneration(format!("Failed to declare variable '{}': {}", name, e)))?;
+            let mut description = DataDescription::new();
+            description.define_zeroinit((*size).max(1));
+            description.set_align(CELL_BYTES as u64);
+            self.module
+                .define_data(data_id, &description)
+                .map_err(|e| BackendError::CodeGeneration(format!("Failed to define variable '{}': {}", name, e)))?;
+            self.data.insert(name.clone(), data_id);
+        }
+        Ok(())
+    }
+
+    /// Declare a function defined outside the module, such as a CODE word,
+    /// callable from compiled functions as `name`
+    ///
+    /// `symbol` must resolve when the module is finalized, normally through
+    /// [`register_runtime_symbol`](crate::cranelift::register_runtime_symbol)
+    /// before the backend is created.
+    pub fn declare_external_function(
+        &mut self,
+        name: &str,
+        symbol: &str,
+        param_count: usize,
+        return_count: usize,
+    ) -> Result<()> {
+        let sig = self.create_signature(param_count, return_count);
+        let func_id = self.module
+            .declare_function(symbol, Linkage::Import, &sig)
+            .map_err(|e| BackendError::CodeGeneration(format!("Failed to declare external '{}': {}", name, e)))?;
+        self.functions.insert(name.to_string(), func_id);
         Ok(())
     }
 
     /// Compile an SSA function to native code (function must already be declared)
     /// Note: Call finalize_all() after compiling all functions
     pub fn compile_function(&mut self, ssa_func: &SSAFunction, name: &str) -> Result<()> {
+        let start = Instant::now();
+
         // Get the function ID (must have been declared first)
         let func_id = self.functions.get(name)
             .copied()
@@ -126,26 +241,15 @@ impl CraneliftBackend {
 
         // Create function signature based on SSA function's parameters and return count
         let param_count = ssa_func.parameters.len();
-        let return_count = 1; // All Forth functions return 1 value
+        let return_count = ssa_func.result_count();
         let sig = self.create_signature(param_count, return_count);
         self.ctx.func.signature = sig;
 
         // Import all declared functions into this function's context (for calls)
         // This must be done BEFORE translation begins
-        self.func_refs.clear();
-        for (func_name, &fid) in &self.functions {
-            let func_ref = self.module.declare_func_in_func(fid, &mut self.ctx.func);
-            self.func_refs.insert(func_name.clone(), func_ref);
-        }
-
-        // Import FFI functions as well
-        let mut ffi_refs = HashMap::new();
-        for ffi_name in self.ffi_registry.function_names() {
-            if let Some(ffi_id) = self.ffi_registry.get_function(ffi_name) {
-                let ffi_ref = self.module.declare_func_in_func(ffi_id, &mut self.ctx.func);
-                ffi_refs.insert(ffi_name.to_string(), ffi_ref);
-            }
-        }
+        let (func_refs, ffi_refs, data_refs) =
+            import_functions(&mut self.module, &self.functions, &self.data, &self.ffi_registry, &mut self.ctx.func);
+        self.func_refs = func_refs;
 
         // Clone func_refs to avoid borrow checker issues
         let func_refs_copy = self.func_refs.clone();
@@ -156,8 +260,10 @@ impl CraneliftBackend {
             &mut self.builder_ctx,
             &func_refs_copy,
             &ffi_refs,
+            &data_refs,
             &self.isa,
             self.settings.enable_verification,
+            self.settings.arithmetic,
         );
         translator.translate(ssa_func)?;
 
@@ -165,17 +271,78 @@ impl CraneliftBackend {
         self.module
             .define_function(func_id, &mut self.ctx)
             .map_err(|e| BackendError::CodeGeneration(format!("Failed to define function '{}': {}", name, e)))?;
+        if let Some(code) = self.ctx.compiled_code() {
+            self.code_sizes.insert(name.to_string(), code.code_info().total_size as usize);
+            self.line_tables.insert(name.to_string(), line_table(code));
+        }
 
         // Clear context for next function
         self.module.clear_context(&mut self.ctx);
 
+        if let Some(timings) = self.timings.as_mut() {
+            timings.push(FunctionTiming::since(name, start));
+        }
         Ok(())
     }
 
-    /// Finalize all compiled functions (call after compiling all functions)
-    pub fn finalize_all(&mut self) -> Result<()> {
-        self.module.finalize_definitions()
-            .map_err(|e| BackendError::CodeGeneration(format!("Failed to finalize: {}", e)))?;
+    /// Compile a batch of declared functions
+    ///
+    /// Calls between functions go through their declarations, so every
+    /// function can be translated and lowered to machine code on its own.
+    /// Batches of at least [`PARALLEL_COMPILE_THRESHOLD`] functions are
+    /// compiled across the rayon thread pool; the machine code is then
+    /// defined in the module serially, in the order given, so the result is
+    /// the same as calling [`compile_function`](Self::compile_function) for
+    /// each function.
+    /// Note: Call finalize_all() after compiling all functions
+    pub fn compile_functions(&mut self, functions: &[(String, &SSAFunction)]) -> Result<()> {
+        if functions.len() < PARALLEL_COMPILE_THRESHOLD {
+            for (name, ssa_func) in functions {
+                self.compile_function(ssa_func, name)?;
+            }
+            return Ok(());
+        }
+
+        // Imports go through the module, so set up each function serially
+        let mut jobs = Vec::with_capacity(functions.len());
+        for (name, ssa_func) in functions {
+            let func_id = self.functions.get(name.as_str())
+                .copied()
+                .ok_or_else(|| BackendError::CodeGeneration(format!("Function '{}' not declared", name)))?;
+
+            let sig = self.create_signature(ssa_func.parameters.len(), ssa_func.result_count());
+            let mut func = Function::with_name_signature(UserFuncName::user(0, func_id.as_u32()), sig);
+            let (func_refs, ffi_refs, data_refs) =
+                import_functions(&mut self.module, &self.functions, &self.data, &self.ffi_registry, &mut func);
+
+            jobs.push(CompileJob { name, ssa_func, func_id, func, func_refs, ffi_refs, data_refs });
+        }
+
+        let isa = &self.isa;
+        let verify = self.settings.enable_verification;
+        let arithmetic = self.settings.arithmetic;
+        let timing = self.timings.is_some();
+        let compiled = jobs
+            .into_par_iter()
+            .map_init(FunctionBuilderContext::new, |builder_ctx, job| {
+                let start = Instant::now();
+                let code = job.compile(builder_ctx, isa, verify, arithmetic)?;
+                let timing = timing.then(|| FunctionTiming::since(code.name, start));
+                Ok((code, timing))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        for (code, timing) in compiled {
+            self.module
+                .define_function_bytes(code.func_id, &code.func, code.alignment, &code.bytes, &code.relocs)
+                .map_err(|e| BackendError::CodeGeneration(format!("Failed to define function '{}': {}", code.name, e)))?;
+            self.code_sizes.insert(code.name.to_string(), code.bytes.len());
+            self.line_tables.insert(code.name.to_string(), code.lines);
+            if let (Some(timings), Some(timing)) = (self.timings.as_mut(), timing) {
+                timings.push(timing);
+            }
+        }
+
         Ok(())
     }
 
@@ -196,15 +363,167 @@ impl CraneliftBackend {
 
         sig
     }
+}
 
-    /// Get pointer to compiled function by name
-    pub fn get_function(&self, name: &str) -> Option<*const u8> {
-        self.functions.get(name).map(|&func_id| {
-            self.module.get_finalized_function(func_id)
+/// Below this many functions, [`CraneliftBackend::compile_functions`]
+/// compiles on the calling thread
+pub const PARALLEL_COMPILE_THRESHOLD: usize = 16;
+
+/// Target ISA for the settings; shared libraries need `pic`
+fn build_isa(settings: &CraneliftSettings, pic: bool) -> Result<Arc<dyn TargetIsa>> {
+    // Get target triple (host or specified)
+    let triple = if let Some(triple_str) = settings.target_triple {
+        triple_str.parse().map_err(|e| {
+            BackendError::Initialization(format!("Invalid target triple: {}", e))
+        })?
+    } else {
+        Triple::host()
+    };
+
+    // Create Cranelift settings
+    let mut flag_builder = settings::builder();
+
+    // Set optimization level
+    let opt_level = match settings.opt_level {
+        0 => "none",
+        1 => "speed",
+        2 => "speed_and_size",
+        _ => {
+            return Err(BackendError::Initialization(
+                "Cranelift supports opt_level 0-2. Use LLVM for -O3.".to_string()
+            ));
+        }
+    };
+    flag_builder.set("opt_level", opt_level)
+        .map_err(|e| BackendError::Initialization(format!("Failed to set opt_level: {}", e)))?;
+    // Frame pointer chains let a fault be traced back through its callers
+    flag_builder.enable("preserve_frame_pointers")
+        .map_err(|e| BackendError::Initialization(format!("Failed to enable preserve_frame_pointers: {}", e)))?;
+    if pic {
+        flag_builder.enable("is_pic")
+            .map_err(|e| BackendError::Initialization(format!("Failed to enable is_pic: {}", e)))?;
+    }
+
+    let flags = Flags::new(flag_builder);
+
+    // Create ISA (returns Arc<dyn TargetIsa>)
+    cranelift_codegen::isa::lookup(triple)
+        .map_err(|e| BackendError::Initialization(format!("ISA lookup failed: {}", e)))?
+        .finish(flags)
+        .map_err(|e| BackendError::Initialization(format!("ISA creation failed: {}", e)))
+}
+
+/// Import every declared function and FFI function into `func` for calls,
+/// and the data of every variable for its address
+fn import_functions<M: Module>(
+    module: &mut M,
+    functions: &HashMap<String, FuncId>,
+    data: &HashMap<String, DataId>,
+    ffi_registry: &FFIRegistry,
+    func: &mut Function,
+) -> (HashMap<String, FuncRef>, HashMap<String, FuncRef>, HashMap<String, GlobalValue>) {
+    let mut func_refs = HashMap::new();
+    for (func_name, &fid) in functions {
+        let func_ref = module.declare_func_in_func(fid, func);
+        func_refs.insert(func_name.clone(), func_ref);
+    }
+
+    let mut ffi_refs = HashMap::new();
+    for ffi_name in ffi_registry.function_names() {
+        if let Some(ffi_id) = ffi_registry.get_function(ffi_name) {
+            let ffi_ref = module.declare_func_in_func(ffi_id, func);
+            ffi_refs.insert(ffi_name.to_string(), ffi_ref);
+        }
+    }
+
+    let data_refs = data
+        .iter()
+        .map(|(name, &data_id)| (name.clone(), module.declare_data_in_func(data_id, func)))
+        .collect();
+
+    (func_refs, ffi_refs, data_refs)
+}
+
+/// A function with its imports in place, ready to compile on any thread
+struct CompileJob<'a> {
+    name: &'a str,
+    ssa_func: &'a SSAFunction,
+    func_id: FuncId,
+    func: Function,
+    func_refs: HashMap<String, FuncRef>,
+    ffi_refs: HashMap<String, FuncRef>,
+    data_refs: HashMap<String, GlobalValue>,
+}
+
+/// Machine code for one function, not yet defined in the module
+struct CompiledFunction<'a> {
+    name: &'a str,
+    func_id: FuncId,
+    /// Kept to resolve the external names used by `relocs`
+    func: Function,
+    alignment: u64,
+    bytes: Vec<u8>,
+    relocs: Vec<FinalizedMachReloc>,
+    lines: Vec<(u32, usize)>,
+}
+
+impl<'a> CompileJob<'a> {
+    fn compile(
+        mut self,
+        builder_ctx: &mut FunctionBuilderContext,
+        isa: &Arc<dyn TargetIsa>,
+        verify: bool,
+        arithmetic: Arithmetic,
+    ) -> Result<CompiledFunction<'a>> {
+        let translator = SSATranslator::new(
+            &mut self.func,
+            builder_ctx,
+            &self.func_refs,
+            &self.ffi_refs,
+            &self.data_refs,
+            isa,
+            verify,
+            arithmetic,
+        );
+        translator.translate(self.ssa_func)?;
+
+        let mut ctx = Context::for_function(self.func);
+        let (alignment, bytes, relocs, lines) = {
+            let code = ctx.compile(isa.as_ref(), &mut ControlPlane::default())
+                .map_err(|e| BackendError::CodeGeneration(format!("Failed to compile function '{}': {:?}", self.name, e.inner)))?;
+            (
+                code.buffer.alignment as u64,
+                code.code_buffer().to_vec(),
+                code.buffer.relocs().to_vec(),
+                line_table(code),
+            )
+        };
+
+        Ok(CompiledFunction {
+            name: self.name,
+            func_id: self.func_id,
+            func: ctx.func,
+            alignment,
+            bytes,
+            relocs,
+            lines,
         })
     }
 }
 
+/// Source lines of compiled code, as (offset, line) where the code of a new
+/// line starts; code before the first offset has no line of its own
+fn line_table(code: &CompiledCode) -> Vec<(u32, usize)> {
+    let mut lines: Vec<(u32, usize)> = Vec::new();
+    for range in code.buffer.get_srclocs_sorted().iter().filter(|range| !range.loc.is_default()) {
+        let line = range.loc.bits() as usize;
+        if lines.last().map(|&(_, last)| last) != Some(line) {
+            lines.push((range.start, line));
+        }
+    }
+    lines
+}
+
 /// High-level compiler interface
 pub struct CraneliftCompiler {
     backend: CraneliftBackend,
@@ -257,4 +576,37 @@ mod tests {
         let compiler = CraneliftCompiler::with_settings(settings);
         assert!(compiler.is_ok());
     }
+
+    #[test]
+    fn test_sized_access_is_little_endian() {
+        let source = ": cells! ( addr -- n ) 258 over ! c@ ;
+            : longs! ( addr -- n ) 305419896 over 8 + l! 8 + w@ ;
+            : bytes@ ( addr -- n ) dup 16 + w@ swap 18 + c@ + ;";
+        let program = fastforth_frontend::parse_program(source).unwrap();
+        let functions = fastforth_frontend::convert_to_ssa(&program).unwrap();
+        let functions: Vec<(String, &SSAFunction)> = functions.iter().map(|f| (f.name.clone(), f)).collect();
+
+        let mut backend = CraneliftBackend::new(CraneliftSettings::development()).unwrap();
+        backend.declare_all_functions(&functions).unwrap();
+        backend.compile_functions(&functions).unwrap();
+        backend.finalize_all().unwrap();
+        let word = |name: &str| -> extern "C" fn(i64) -> i64 {
+            unsafe { std::mem::transmute(backend.get_function(name).unwrap()) }
+        };
+
+        let mut memory = [0u8; 24];
+        memory[16..19].copy_from_slice(&[0xff, 0xfe, 0x80]);
+        let addr = memory.as_mut_ptr() as i64;
+
+        // A cell is stored low byte first, and c@ reads that byte
+        assert_eq!(word("cells!")(addr), 2);
+        assert_eq!(memory[..8], 258i64.to_le_bytes());
+
+        // l! writes four bytes and w@ reads back the low two
+        assert_eq!(word("longs!")(addr), 0x5678);
+        assert_eq!(memory[8..16], [0x78, 0x56, 0x34, 0x12, 0, 0, 0, 0]);
+
+        // Narrow loads zero-extend
+        assert_eq!(word("bytes@")(addr), 0xfeff + 0x80);
+    }
 }
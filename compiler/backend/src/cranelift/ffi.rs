@@ -8,6 +8,76 @@ use cranelift_codegen::ir::{types, AbiParam, ExternalName, Signature};
 use cranelift_codegen::isa::CallConv;
 use cranelift_module::{FuncId, Linkage, Module};
 use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Addresses of runtime functions the JIT links against directly, for
+/// symbols the host process does not export (e.g. a statically linked
+/// runtime library)
+static RUNTIME_SYMBOLS: Mutex<Vec<(String, usize)>> = Mutex::new(Vec::new());
+
+/// Runtime I/O functions compiled code calls: (name, cell parameters, cell
+/// results). Two results come back in registers as a C struct of two cells.
+pub const RUNTIME_IO_FUNCTIONS: &[(&str, usize, usize)] = &[
+    ("forth_io_emit", 1, 0),
+    ("forth_io_type", 2, 0),
+    ("forth_io_cr", 0, 0),
+    ("forth_io_space", 0, 0),
+    ("forth_io_dot", 1, 0),
+    ("forth_io_udot", 1, 0),
+    ("forth_io_dot_r", 2, 0),
+    ("forth_io_key", 0, 1),
+    ("forth_io_key_ready", 0, 1),
+    ("forth_io_accept", 2, 1),
+    ("forth_pict_begin", 0, 0),
+    ("forth_pict_digit", 2, 2),
+    ("forth_pict_digits", 2, 2),
+    ("forth_pict_hold", 1, 0),
+    ("forth_pict_sign", 1, 0),
+    ("forth_pict_end", 2, 2),
+    ("forth_block_open", 2, 0),
+    ("forth_block", 1, 1),
+    ("forth_buffer", 1, 1),
+    ("forth_update", 0, 0),
+    ("forth_save_buffers", 0, 0),
+    ("forth_empty_buffers", 0, 0),
+    ("forth_flush", 0, 0),
+    ("forth_file_open", 3, 2),
+    ("forth_file_create", 3, 2),
+    ("forth_file_delete", 2, 1),
+    ("forth_file_read", 3, 2),
+    ("forth_file_write", 3, 1),
+    ("forth_file_close", 1, 1),
+    ("forth_system", 2, 1),
+    ("forth_allocate", 1, 2),
+    ("forth_free", 1, 1),
+    ("forth_resize", 2, 2),
+    ("forth_task_spawn", 7, 1),
+    ("forth_task_join", 1, 1),
+    ("forth_task_pause", 0, 0),
+    ("forth_channel_create", 1, 1),
+    ("forth_channel_send", 2, 0),
+    ("forth_channel_recv", 1, 1),
+    ("forth_channel_close", 1, 0),
+    ("forth_channel_destroy", 1, 0),
+    ("forth_poll", 0, 0),
+    ("forth_throw", 1, 0),
+    ("forth_record_enter", 6, 0),
+    ("forth_record_exit", 6, 0),
+];
+
+/// Make `address` the definition of `name` in JIT modules created from now on
+pub fn register_runtime_symbol(name: impl Into<String>, address: *const u8) {
+    let name = name.into();
+    let mut symbols = RUNTIME_SYMBOLS.lock().unwrap_or_else(|e| e.into_inner());
+    symbols.retain(|(existing, _)| *existing != name);
+    symbols.push((name, address as usize));
+}
+
+/// Runtime symbols registered with [`register_runtime_symbol`]
+pub fn runtime_symbols() -> Vec<(String, *const u8)> {
+    let symbols = RUNTIME_SYMBOLS.lock().unwrap_or_else(|e| e.into_inner());
+    symbols.iter().map(|(name, address)| (name.clone(), *address as *const u8)).collect()
+}
 
 /// FFI function metadata
 #[derive(Debug, Clone)]
@@ -167,6 +237,48 @@ impl FFIRegistry {
         Ok(())
     }
 
+    /// Register the Forth runtime's process environment functions
+    /// (command line arguments and environment variables) and its I/O
+    /// functions
+    pub fn register_runtime_functions<M: Module>(&mut self, module: &mut M) -> Result<()> {
+        // cell_t forth_arg_count(void)
+        self.register_function(module, FFISignature::new("forth_arg_count").returns(types::I64))?;
+
+        // cell_t forth_arg(cell_t n)
+        self.register_function(
+            module,
+            FFISignature::new("forth_arg")
+                .param(types::I64) // argument index
+                .returns(types::I64), // char* (0 = no such argument)
+        )?;
+
+        // cell_t forth_getenv(cell_t name_addr, cell_t name_len)
+        self.register_function(
+            module,
+            FFISignature::new("forth_getenv")
+                .param(types::I64) // name address
+                .param(types::I64) // name length
+                .returns(types::I64), // char* (0 = not set)
+        )?;
+
+        // cell_t forth_cstring_length(cell_t addr)
+        self.register_function(
+            module,
+            FFISignature::new("forth_cstring_length")
+                .param(types::I64) // char*
+                .returns(types::I64), // length (0 for null)
+        )?;
+
+        for &(name, params, returns) in RUNTIME_IO_FUNCTIONS {
+            let mut sig = FFISignature::new(name);
+            sig.params = vec![types::I64; params];
+            sig.returns = vec![types::I64; returns];
+            self.register_function(module, sig)?;
+        }
+
+        Ok(())
+    }
+
     /// Register a single external function
     fn register_function<M: Module>(
         &mut self,
@@ -260,4 +372,18 @@ mod tests {
         // Note: Can't easily test register_function without a real Module
         // This would require integration tests
     }
+
+    #[test]
+    fn test_runtime_symbol_reregistration_replaces() {
+        static FIRST: u8 = 1;
+        static SECOND: u8 = 2;
+        register_runtime_symbol("test_runtime_symbol", &FIRST);
+        register_runtime_symbol("test_runtime_symbol", &SECOND);
+
+        let matching: Vec<_> = runtime_symbols()
+            .into_iter()
+            .filter(|(name, _)| name == "test_runtime_symbol")
+            .collect();
+        assert_eq!(matching, vec![("test_runtime_symbol".to_string(), &SECOND as *const u8)]);
+    }
 }
@@ -9,14 +9,17 @@
 
 mod compiler;
 mod translator;
+mod assembly;
 pub mod ffi;
 
-pub use compiler::{CraneliftBackend, CraneliftCompiler};
+pub use assembly::AssemblyModule;
+pub use compiler::{CraneliftBackend, CraneliftCompiler, FunctionTiming};
 pub use translator::SSATranslator;
-pub use ffi::{FFIRegistry, FFISignature};
+pub use ffi::{register_runtime_symbol, runtime_symbols, FFIRegistry, FFISignature, RUNTIME_IO_FUNCTIONS};
 
 use crate::error::{BackendError, Result};
 use fastforth_frontend::ssa::{SSAFunction, SSAInstruction, Register, BlockId};
+use fastforth_frontend::Arithmetic;
 
 /// Compilation settings for Cranelift
 #[derive(Debug, Clone, Copy)]
@@ -29,6 +32,8 @@ pub struct CraneliftSettings {
     pub target_triple: Option<&'static str>,
     /// Enable IR verification (disabled in release builds for performance)
     pub enable_verification: bool,
+    /// Semantics of division and overflow
+    pub arithmetic: Arithmetic,
 }
 
 impl Default for CraneliftSettings {
@@ -39,6 +44,7 @@ impl Default for CraneliftSettings {
             target_triple: None,
             // Enable verification in debug builds, disable in release builds
             enable_verification: cfg!(debug_assertions),
+            arithmetic: Arithmetic::default(),
         }
     }
 }
@@ -51,6 +57,7 @@ impl CraneliftSettings {
             debug_info: true,
             target_triple: None,
             enable_verification: true,
+            arithmetic: Arithmetic::default(),
         }
     }
 
@@ -61,6 +68,7 @@ impl CraneliftSettings {
             debug_info: true,
             target_triple: None,
             enable_verification: true,
+            arithmetic: Arithmetic::default(),
         }
     }
 
@@ -71,6 +79,7 @@ impl CraneliftSettings {
             debug_info: false,
             target_triple: None,
             enable_verification: false, // Disable for maximum performance
+            arithmetic: Arithmetic::default(),
         }
     }
 }
@@ -5,14 +5,19 @@
 use crate::error::{BackendError, Result};
 use fastforth_frontend::ssa::{
     SSAFunction, SSAInstruction, Register, BlockId, BinaryOperator, UnaryOperator, BasicBlock,
+    MemoryWidth,
 };
 use fastforth_frontend::ast::StackType;
+use fastforth_frontend::case::CaseTable;
+use fastforth_frontend::{Arithmetic, Division, Overflow, RESULT_OUT_OF_RANGE};
 
+use cranelift_codegen::ir::condcodes::IntCC;
 use cranelift_codegen::ir::{
-    types, AbiParam, Block, Function, FuncRef, InstBuilder, Value,
+    types, AbiParam, Block, Function, FuncRef, GlobalValue, InstBuilder, SigRef, Signature, SourceLoc, TrapCode,
+    Value,
 };
 use cranelift_codegen::isa::TargetIsa;
-use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Switch, Variable};
 
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -41,6 +46,8 @@ pub struct SSATranslator<'a> {
     func_refs: &'a HashMap<String, FuncRef>,
     /// Map of FFI function names to FuncRefs (pre-imported)
     ffi_refs: &'a HashMap<String, FuncRef>,
+    /// Map of variable names to the symbols of their data (pre-imported)
+    data_refs: &'a HashMap<String, GlobalValue>,
     /// Actual control flow graph: tracks which blocks jump to which blocks
     /// This is built during translation and may differ from SSA Phi predecessors
     block_predecessors: HashMap<BlockId, Vec<BlockId>>,
@@ -48,17 +55,22 @@ pub struct SSATranslator<'a> {
     isa: &'a Arc<dyn TargetIsa>,
     /// Whether to enable IR verification
     enable_verification: bool,
+    /// Semantics of division and overflow
+    arithmetic: Arithmetic,
 }
 
 impl<'a> SSATranslator<'a> {
     /// Create new translator
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         func: &'a mut Function,
         builder_ctx: &'a mut FunctionBuilderContext,
         func_refs: &'a HashMap<String, FuncRef>,
         ffi_refs: &'a HashMap<String, FuncRef>,
+        data_refs: &'a HashMap<String, GlobalValue>,
         isa: &'a Arc<dyn TargetIsa>,
         enable_verification: bool,
+        arithmetic: Arithmetic,
     ) -> Self {
         let builder = FunctionBuilder::new(func, builder_ctx);
 
@@ -70,9 +82,11 @@ impl<'a> SSATranslator<'a> {
             current_block: None,
             func_refs,
             ffi_refs,
+            data_refs,
             block_predecessors: HashMap::new(),
             isa,
             enable_verification,
+            arithmetic,
         }
     }
 
@@ -180,7 +194,11 @@ impl<'a> SSATranslator<'a> {
             }
         }
 
-        for inst in &block.instructions {
+        // The machine code of each instruction is tagged with its source
+        // line, which the backend's line tables are read from
+        for (index, inst) in block.instructions.iter().enumerate() {
+            let line = block.location(index).and_then(|location| u32::try_from(location.line).ok());
+            self.builder.set_srcloc(line.map_or_else(SourceLoc::default, SourceLoc::new));
             self.translate_instruction(inst)?;
         }
 
@@ -205,18 +223,18 @@ impl<'a> SSATranslator<'a> {
                 let right_val = self.get_register(*right)?;
 
                 let result = match op {
-                    BinaryOperator::Add => self.builder.ins().iadd(left_val, right_val),
-                    BinaryOperator::Sub => self.builder.ins().isub(left_val, right_val),
-                    BinaryOperator::Mul => self.builder.ins().imul(left_val, right_val),
-                    BinaryOperator::Div => self.builder.ins().sdiv(left_val, right_val),
-                    BinaryOperator::Mod => self.builder.ins().srem(left_val, right_val),
+                    BinaryOperator::Add | BinaryOperator::Sub | BinaryOperator::Mul => {
+                        self.overflowing(*op, left_val, right_val)?
+                    }
+                    BinaryOperator::Div | BinaryOperator::Mod => self.divide(*op, left_val, right_val)?,
+                    // Comparisons leave a Forth flag: all ones when true
                     BinaryOperator::Lt => {
                         let cmp = self.builder.ins().icmp(
                             cranelift_codegen::ir::condcodes::IntCC::SignedLessThan,
                             left_val,
                             right_val,
                         );
-                        self.builder.ins().uextend(types::I64, cmp)
+                        self.builder.ins().bmask(types::I64, cmp)
                     }
                     BinaryOperator::Gt => {
                         let cmp = self.builder.ins().icmp(
@@ -224,7 +242,7 @@ impl<'a> SSATranslator<'a> {
                             left_val,
                             right_val,
                         );
-                        self.builder.ins().uextend(types::I64, cmp)
+                        self.builder.ins().bmask(types::I64, cmp)
                     }
                     BinaryOperator::Le => {
                         let cmp = self.builder.ins().icmp(
@@ -232,7 +250,7 @@ impl<'a> SSATranslator<'a> {
                             left_val,
                             right_val,
                         );
-                        self.builder.ins().uextend(types::I64, cmp)
+                        self.builder.ins().bmask(types::I64, cmp)
                     }
                     BinaryOperator::Ge => {
                         let cmp = self.builder.ins().icmp(
@@ -240,7 +258,7 @@ impl<'a> SSATranslator<'a> {
                             left_val,
                             right_val,
                         );
-                        self.builder.ins().uextend(types::I64, cmp)
+                        self.builder.ins().bmask(types::I64, cmp)
                     }
                     BinaryOperator::Eq => {
                         let cmp = self.builder.ins().icmp(
@@ -248,7 +266,7 @@ impl<'a> SSATranslator<'a> {
                             left_val,
                             right_val,
                         );
-                        self.builder.ins().uextend(types::I64, cmp)
+                        self.builder.ins().bmask(types::I64, cmp)
                     }
                     BinaryOperator::Ne => {
                         let cmp = self.builder.ins().icmp(
@@ -256,10 +274,29 @@ impl<'a> SSATranslator<'a> {
                             left_val,
                             right_val,
                         );
-                        self.builder.ins().uextend(types::I64, cmp)
+                        self.builder.ins().bmask(types::I64, cmp)
+                    }
+                    BinaryOperator::ULt => {
+                        let cmp = self.builder.ins().icmp(IntCC::UnsignedLessThan, left_val, right_val);
+                        self.builder.ins().bmask(types::I64, cmp)
+                    }
+                    BinaryOperator::UGt => {
+                        let cmp = self.builder.ins().icmp(IntCC::UnsignedGreaterThan, left_val, right_val);
+                        self.builder.ins().bmask(types::I64, cmp)
                     }
+                    // Division by zero traps, as it does for signed division
+                    BinaryOperator::UDiv => self.builder.ins().udiv(left_val, right_val),
+                    BinaryOperator::UMod => self.builder.ins().urem(left_val, right_val),
+                    BinaryOperator::UMul => self.builder.ins().imul(left_val, right_val),
+                    BinaryOperator::UMulHigh => self.builder.ins().umulhi(left_val, right_val),
                     BinaryOperator::And => self.builder.ins().band(left_val, right_val),
                     BinaryOperator::Or => self.builder.ins().bor(left_val, right_val),
+                    BinaryOperator::Xor => self.builder.ins().bxor(left_val, right_val),
+                    // Cranelift takes the shift amount modulo 64
+                    BinaryOperator::Shl => self.builder.ins().ishl(left_val, right_val),
+                    BinaryOperator::Shr => self.builder.ins().ushr(left_val, right_val),
+                    BinaryOperator::Rotl => self.builder.ins().rotl(left_val, right_val),
+                    BinaryOperator::Rotr => self.builder.ins().rotr(left_val, right_val),
                 };
 
                 self.register_values.insert(*dest, result);
@@ -288,42 +325,47 @@ impl<'a> SSATranslator<'a> {
                         let negated = self.builder.ins().isub(zero, operand_val);
                         self.builder.ins().select(is_neg, negated, operand_val)
                     }
+                    UnaryOperator::Popcount => self.builder.ins().popcnt(operand_val),
+                    UnaryOperator::Ctz => self.builder.ins().ctz(operand_val),
+                    UnaryOperator::Clz => self.builder.ins().clz(operand_val),
                 };
 
                 self.register_values.insert(*dest, result);
             }
 
-            SSAInstruction::Load { dest, address, ty } => {
+            SSAInstruction::Load { dest, address, ty, width } => {
                 let addr_val = self.get_register(*address)?;
 
-                use cranelift_codegen::ir::MemFlags;
-
-                let result = match ty {
-                    StackType::Int | StackType::Addr => {
-                        self.builder.ins().load(types::I64, MemFlags::new(), addr_val, 0)
-                    }
-                    StackType::Float => {
-                        self.builder.ins().load(types::F64, MemFlags::new(), addr_val, 0)
-                    }
-                    StackType::Bool | StackType::Char => {
-                        self.builder.ins().load(types::I8, MemFlags::new(), addr_val, 0)
-                    }
-                    StackType::String | StackType::Var(_) | StackType::Unknown => {
-                        // For unknown or complex types, default to I64
-                        self.builder.ins().load(types::I64, MemFlags::new(), addr_val, 0)
-                    }
+                use cranelift_codegen::ir::{Endianness, MemFlags};
+
+                // Memory is little-endian whatever the target, and sized
+                // accesses zero-extend to a cell
+                let little = MemFlags::new().with_endianness(Endianness::Little);
+                let result = match (width, ty) {
+                    (MemoryWidth::W8, _) => self.builder.ins().uload8(types::I64, little, addr_val, 0),
+                    (MemoryWidth::W16, _) => self.builder.ins().uload16(types::I64, little, addr_val, 0),
+                    (MemoryWidth::W32, _) => self.builder.ins().uload32(little, addr_val, 0),
+                    (MemoryWidth::Cell, StackType::Float) => self.builder.ins().load(types::F64, little, addr_val, 0),
+                    (MemoryWidth::Cell, _) => self.builder.ins().load(types::I64, little, addr_val, 0),
                 };
 
                 self.register_values.insert(*dest, result);
             }
 
-            SSAInstruction::Store { address, value, ty } => {
-                use cranelift_codegen::ir::MemFlags;
+            SSAInstruction::Store { address, value, width, .. } => {
+                use cranelift_codegen::ir::{Endianness, MemFlags};
 
                 let addr_val = self.get_register(*address)?;
                 let val = self.get_register(*value)?;
 
-                self.builder.ins().store(MemFlags::new(), val, addr_val, 0);
+                // Sized stores write the low bytes, little-endian like cells
+                let little = MemFlags::new().with_endianness(Endianness::Little);
+                match width {
+                    MemoryWidth::W8 => self.builder.ins().istore8(little, val, addr_val, 0),
+                    MemoryWidth::W16 => self.builder.ins().istore16(little, val, addr_val, 0),
+                    MemoryWidth::W32 => self.builder.ins().istore32(little, val, addr_val, 0),
+                    MemoryWidth::Cell => self.builder.ins().store(little, val, addr_val, 0),
+                };
             }
 
             SSAInstruction::Branch { condition, true_block, false_block } => {
@@ -371,6 +413,28 @@ impl<'a> SSATranslator<'a> {
                 self.builder.ins().jump(cl_block, &args);
             }
 
+            SSAInstruction::Switch { value, cases, default } => {
+                let from_block = self.current_block.ok_or_else(|| BackendError::CodeGeneration(
+                    "Switch instruction outside of block context".to_string()
+                ))?;
+
+                // Case blocks have no Phi nodes, so they take no arguments
+                let mut switch = Switch::new();
+                for &(key, target) in cases {
+                    self.block_predecessors.entry(target).or_default().push(from_block);
+                    switch.set_entry(key as u64 as u128, self.block_map[&target]);
+                }
+                self.block_predecessors.entry(*default).or_default().push(from_block);
+                let value = self.get_register(*value)?;
+                switch.emit(&mut self.builder, value, self.block_map[default]);
+            }
+
+            SSAInstruction::Lookup { dest, index, table } => {
+                let index = self.get_register(*index)?;
+                let value = self.lookup(index, table);
+                self.register_values.insert(*dest, value);
+            }
+
             SSAInstruction::Return { values } => {
                 let return_vals: Vec<Value> = values
                     .iter()
@@ -382,7 +446,7 @@ impl<'a> SSATranslator<'a> {
 
             SSAInstruction::Call { dest, name, args } => {
                 // Look up the pre-imported function reference
-                let func_ref = self.func_refs.get(name)
+                let func_ref = self.func_refs.get(name.as_str())
                     .copied()
                     .ok_or_else(|| BackendError::CodeGeneration(
                         format!("Function '{}' not declared/imported", name)
@@ -412,12 +476,55 @@ impl<'a> SSATranslator<'a> {
                 }
             }
 
+            SSAInstruction::FunctionAddress { dest, name } => {
+                let func_ref = self.func_refs.get(name.as_str())
+                    .copied()
+                    .ok_or_else(|| BackendError::CodeGeneration(
+                        format!("Function '{}' not declared/imported", name)
+                    ))?;
+                let addr = self.builder.ins().func_addr(types::I64, func_ref);
+                self.register_values.insert(*dest, addr);
+            }
+
+            SSAInstruction::CallIndirect { dest, target, args, likely } => {
+                // Every word EXECUTE may reach has this signature, the one
+                // compiled calls use
+                let mut signature = Signature::new(self.builder.func.signature.call_conv);
+                signature.params.extend(args.iter().map(|_| AbiParam::new(types::I64)));
+                signature.returns.extend(dest.iter().map(|_| AbiParam::new(types::I64)));
+                let signature = self.builder.import_signature(signature);
+
+                let callee = self.get_register(*target)?;
+                let arg_values: Vec<Value> = args
+                    .iter()
+                    .map(|&reg| self.get_register(reg))
+                    .collect::<Result<Vec<_>>>()?;
+                let results = match likely {
+                    Some(name) => self.guarded_call(name, signature, callee, &arg_values, dest.len())?,
+                    None => {
+                        let call = self.builder.ins().call_indirect(signature, callee, &arg_values);
+                        self.builder.inst_results(call).to_vec()
+                    }
+                };
+                for (&dest_reg, &result) in dest.iter().zip(&results) {
+                    self.register_values.insert(dest_reg, result);
+                }
+            }
+
             SSAInstruction::Phi { dest, incoming } => {
                 // Phi nodes are now handled via block parameters.
                 // The destination register was already set when we entered the block.
                 // Just skip this instruction - nothing to do here.
             }
 
+            SSAInstruction::DataAddress { dest, name, .. } => {
+                let data = self.data_refs.get(name).copied().ok_or_else(|| {
+                    BackendError::CodeGeneration(format!("Data of variable '{}' not declared", name))
+                })?;
+                let addr = self.builder.ins().symbol_value(types::I64, data);
+                self.register_values.insert(*dest, addr);
+            }
+
             SSAInstruction::LoadString { dest_addr, dest_len, value } => {
                 // For now, implement a simplified version that uses runtime allocation
                 // This calls malloc to allocate memory for the string
@@ -493,225 +600,400 @@ impl<'a> SSATranslator<'a> {
             }
 
             SSAInstruction::FileOpen { dest_fileid, dest_ior, path_addr, path_len, mode } => {
-                // Get fopen FFI function reference
-                let fopen_ref = self.ffi_refs.get("fopen")
-                    .copied()
-                    .ok_or_else(|| BackendError::CodeGeneration(
-                        "FFI function 'fopen' not registered".to_string()
-                    ))?;
-
-                // Get path pointer and mode
-                let path_ptr = self.get_register(*path_addr)?;
-                let mode_val = self.get_register(*mode)?;
-
-                // Convert Forth mode to C mode string pointer
-                // Mode: 0=r/o ("r"), 1=w/o ("w"), 2=r/w ("r+")
-                // Note: This requires pre-allocated mode strings in memory
-                // For now, we'll assume mode_val is already a pointer to C string
-                let mode_ptr = mode_val;
-
-                // Call fopen(path, mode)
-                let call = self.builder.ins().call(fopen_ref, &[path_ptr, mode_ptr]);
-                let file_handle = self.builder.inst_results(call)[0];
-
-                // Check if NULL (error): file_handle == 0
-                let null_ptr = self.builder.ins().iconst(types::I64, 0);
-                let is_null = self.builder.ins().icmp(
-                    cranelift_codegen::ir::condcodes::IntCC::Equal,
-                    file_handle,
-                    null_ptr,
-                );
-
-                // Set ior: 0 = success, -1 = error (following ANS Forth convention)
-                let success = self.builder.ins().iconst(types::I64, 0);
-                let error = self.builder.ins().iconst(types::I64, -1);
-                let ior = self.builder.ins().select(is_null, error, success);
-
-                // Store results
-                self.register_values.insert(*dest_fileid, file_handle);
+                // The runtime checks the program may access the path first
+                let open_ref = self.ffi_function("forth_file_open")?;
+                let args = [self.get_register(*path_addr)?, self.get_register(*path_len)?, self.get_register(*mode)?];
+                let call = self.builder.ins().call(open_ref, &args);
+                let results = self.builder.inst_results(call);
+                let (fileid, ior) = (results[0], results[1]);
+
+                self.register_values.insert(*dest_fileid, fileid);
                 self.register_values.insert(*dest_ior, ior);
             }
 
             SSAInstruction::FileRead { dest_bytes, dest_ior, buffer, count, fileid } => {
-                // Get fread FFI function reference
-                let fread_ref = self.ffi_refs.get("fread")
-                    .copied()
-                    .ok_or_else(|| BackendError::CodeGeneration(
-                        "FFI function 'fread' not registered".to_string()
-                    ))?;
-
-                // Get arguments
-                let buffer_ptr = self.get_register(*buffer)?;
-                let count_val = self.get_register(*count)?;
-                let file_handle = self.get_register(*fileid)?;
-
-                // fread(buffer, 1, count, file) - read count bytes
-                let size = self.builder.ins().iconst(types::I64, 1);
-
-                // Call fread
-                let call = self.builder.ins().call(
-                    fread_ref,
-                    &[buffer_ptr, size, count_val, file_handle]
-                );
-                let bytes_read = self.builder.inst_results(call)[0];
-
-                // Check for error: bytes_read < count means error or EOF
-                // For simplicity, ior = 0 (success), actual error checking done by user
-                let success = self.builder.ins().iconst(types::I64, 0);
-
-                // Store results
-                self.register_values.insert(*dest_bytes, bytes_read);
-                self.register_values.insert(*dest_ior, success);
+                // Through the runtime's installed filesystem, not libc directly
+                let read_ref = self.ffi_function("forth_file_read")?;
+                let args = [self.get_register(*buffer)?, self.get_register(*count)?, self.get_register(*fileid)?];
+                let call = self.builder.ins().call(read_ref, &args);
+                let results = self.builder.inst_results(call);
+                let (bytes, ior) = (results[0], results[1]);
+
+                self.register_values.insert(*dest_bytes, bytes);
+                self.register_values.insert(*dest_ior, ior);
             }
 
             SSAInstruction::FileWrite { dest_ior, buffer, count, fileid } => {
-                // Get fwrite FFI function reference
-                let fwrite_ref = self.ffi_refs.get("fwrite")
-                    .copied()
-                    .ok_or_else(|| BackendError::CodeGeneration(
-                        "FFI function 'fwrite' not registered".to_string()
-                    ))?;
-
-                // Get arguments
-                let buffer_ptr = self.get_register(*buffer)?;
-                let count_val = self.get_register(*count)?;
-                let file_handle = self.get_register(*fileid)?;
-
-                // fwrite(buffer, 1, count, file) - write count bytes
-                let size = self.builder.ins().iconst(types::I64, 1);
+                let write_ref = self.ffi_function("forth_file_write")?;
+                let args = [self.get_register(*buffer)?, self.get_register(*count)?, self.get_register(*fileid)?];
+                let call = self.builder.ins().call(write_ref, &args);
+                let ior = self.builder.inst_results(call)[0];
+                self.register_values.insert(*dest_ior, ior);
+            }
 
-                // Call fwrite
-                let call = self.builder.ins().call(
-                    fwrite_ref,
-                    &[buffer_ptr, size, count_val, file_handle]
-                );
-                let bytes_written = self.builder.inst_results(call)[0];
+            SSAInstruction::FileClose { dest_ior, fileid } => {
+                let close_ref = self.ffi_function("forth_file_close")?;
+                let fileid = self.get_register(*fileid)?;
+                let call = self.builder.ins().call(close_ref, &[fileid]);
+                let ior = self.builder.inst_results(call)[0];
+                self.register_values.insert(*dest_ior, ior);
+            }
 
-                // Check if all bytes were written
-                let is_complete = self.builder.ins().icmp(
-                    cranelift_codegen::ir::condcodes::IntCC::Equal,
-                    bytes_written,
-                    count_val,
-                );
+            SSAInstruction::FileDelete { dest_ior, path_addr, path_len } => {
+                let delete_ref = self.ffi_function("forth_file_delete")?;
+                let args = [self.get_register(*path_addr)?, self.get_register(*path_len)?];
+                let call = self.builder.ins().call(delete_ref, &args);
+                let ior = self.builder.inst_results(call)[0];
+                self.register_values.insert(*dest_ior, ior);
+            }
 
-                // Set ior: 0 = success, -1 = error
-                let success = self.builder.ins().iconst(types::I64, 0);
-                let error = self.builder.ins().iconst(types::I64, -1);
-                let ior = self.builder.ins().select(is_complete, success, error);
+            SSAInstruction::FileCreate { dest_fileid, dest_ior, path_addr, path_len, mode } => {
+                let create_ref = self.ffi_function("forth_file_create")?;
+                let args = [self.get_register(*path_addr)?, self.get_register(*path_len)?, self.get_register(*mode)?];
+                let call = self.builder.ins().call(create_ref, &args);
+                let results = self.builder.inst_results(call);
+                let (fileid, ior) = (results[0], results[1]);
 
-                // Store result
+                self.register_values.insert(*dest_fileid, fileid);
                 self.register_values.insert(*dest_ior, ior);
             }
 
-            SSAInstruction::FileClose { dest_ior, fileid } => {
-                // Get fclose FFI function reference
-                let fclose_ref = self.ffi_refs.get("fclose")
-                    .copied()
-                    .ok_or_else(|| BackendError::CodeGeneration(
-                        "FFI function 'fclose' not registered".to_string()
-                    ))?;
+            SSAInstruction::SystemCall { dest, command_addr, command_len } => {
+                // The runtime checks the program may run commands first
+                let system_ref = self.ffi_function("forth_system")?;
+                let args = [self.get_register(*command_addr)?, self.get_register(*command_len)?];
+                let call = self.builder.ins().call(system_ref, &args);
+                let status = self.builder.inst_results(call)[0];
+                self.register_values.insert(*dest, status);
+            }
 
-                // Get file handle
-                let file_handle = self.get_register(*fileid)?;
+            SSAInstruction::ArgCount { dest } => {
+                let arg_count_ref = self.ffi_function("forth_arg_count")?;
+                let call = self.builder.ins().call(arg_count_ref, &[]);
+                let count = self.builder.inst_results(call)[0];
+                self.register_values.insert(*dest, count);
+            }
+
+            SSAInstruction::ArgFetch { dest_addr, dest_len, index } => {
+                let arg_ref = self.ffi_function("forth_arg")?;
+                let index_val = self.get_register(*index)?;
+                let call = self.builder.ins().call(arg_ref, &[index_val]);
+                let addr = self.builder.inst_results(call)[0];
+                let len = self.cstring_length(addr)?;
 
-                // Call fclose(file)
-                let call = self.builder.ins().call(fclose_ref, &[file_handle]);
-                let result = self.builder.inst_results(call)[0];
+                self.register_values.insert(*dest_addr, addr);
+                self.register_values.insert(*dest_len, len);
+            }
 
-                // Convert i32 result to i64 for consistency
-                let result_i64 = self.builder.ins().sextend(types::I64, result);
+            SSAInstruction::GetEnv { dest_addr, dest_len, name_addr, name_len } => {
+                let getenv_ref = self.ffi_function("forth_getenv")?;
+                let name_ptr = self.get_register(*name_addr)?;
+                let name_count = self.get_register(*name_len)?;
+                let call = self.builder.ins().call(getenv_ref, &[name_ptr, name_count]);
+                let addr = self.builder.inst_results(call)[0];
+                let len = self.cstring_length(addr)?;
 
-                // fclose returns 0 on success, EOF (-1) on error
-                // We keep the same convention: 0 = success, non-zero = error
-                self.register_values.insert(*dest_ior, result_i64);
+                self.register_values.insert(*dest_addr, addr);
+                self.register_values.insert(*dest_len, len);
             }
 
-            SSAInstruction::FileDelete { dest_ior, path_addr, path_len } => {
-                // Get remove FFI function reference
-                let remove_ref = self.ffi_refs.get("remove")
-                    .copied()
-                    .ok_or_else(|| BackendError::CodeGeneration(
-                        "FFI function 'remove' not registered".to_string()
-                    ))?;
+            SSAInstruction::HeapAllocate { dest_addr, dest_ior, size } => {
+                let allocate_ref = self.ffi_function("forth_allocate")?;
+                let size_val = self.get_register(*size)?;
+                let call = self.builder.ins().call(allocate_ref, &[size_val]);
+                let results = self.builder.inst_results(call);
+                let (addr, ior) = (results[0], results[1]);
 
-                // Get path pointer
-                let path_ptr = self.get_register(*path_addr)?;
+                self.register_values.insert(*dest_addr, addr);
+                self.register_values.insert(*dest_ior, ior);
+            }
 
-                // Call remove(path)
-                let call = self.builder.ins().call(remove_ref, &[path_ptr]);
-                let result = self.builder.inst_results(call)[0];
+            SSAInstruction::HeapFree { dest_ior, addr } => {
+                let free_ref = self.ffi_function("forth_free")?;
+                let addr_val = self.get_register(*addr)?;
+                let call = self.builder.ins().call(free_ref, &[addr_val]);
+                let ior = self.builder.inst_results(call)[0];
+                self.register_values.insert(*dest_ior, ior);
+            }
 
-                // Convert i32 result to i64
-                let result_i64 = self.builder.ins().sextend(types::I64, result);
+            SSAInstruction::HeapResize { dest_addr, dest_ior, addr, size } => {
+                let resize_ref = self.ffi_function("forth_resize")?;
+                let addr_val = self.get_register(*addr)?;
+                let size_val = self.get_register(*size)?;
+                let call = self.builder.ins().call(resize_ref, &[addr_val, size_val]);
+                let results = self.builder.inst_results(call);
+                let (moved, ior) = (results[0], results[1]);
 
-                // remove returns 0 on success, non-zero on error
-                self.register_values.insert(*dest_ior, result_i64);
+                self.register_values.insert(*dest_addr, moved);
+                self.register_values.insert(*dest_ior, ior);
             }
 
-            SSAInstruction::FileCreate { dest_fileid, dest_ior, path_addr, path_len, mode } => {
-                // FileCreate is the same as FileOpen with create mode
-                // Get fopen FFI function reference
-                let fopen_ref = self.ffi_refs.get("fopen")
+            SSAInstruction::TaskSpawn { dest, word, args } => {
+                // The runtime calls the word on its thread with the same
+                // signature compiled calls use
+                let func_ref = self.func_refs.get(word.as_str())
                     .copied()
                     .ok_or_else(|| BackendError::CodeGeneration(
-                        "FFI function 'fopen' not registered".to_string()
+                        format!("Function '{}' not declared/imported", word)
                     ))?;
+                let signature = self.builder.func.dfg.ext_funcs[func_ref].signature;
+                let outputs = self.builder.func.dfg.signatures[signature].returns.len() as i64;
+
+                let spawn_ref = self.ffi_function("forth_task_spawn")?;
+                let entry = self.builder.ins().func_addr(types::I64, func_ref);
+                let inputs = self.builder.ins().iconst(types::I64, args.len() as i64);
+                let outputs = self.builder.ins().iconst(types::I64, outputs);
+                let mut spawn_args = vec![entry, inputs, outputs];
+                for i in 0..4 {
+                    spawn_args.push(match args.get(i) {
+                        Some(&reg) => self.get_register(reg)?,
+                        None => self.builder.ins().iconst(types::I64, 0),
+                    });
+                }
+                let call = self.builder.ins().call(spawn_ref, &spawn_args);
+                let task = self.builder.inst_results(call)[0];
+                self.register_values.insert(*dest, task);
+            }
 
-                // Get path pointer and mode
-                let path_ptr = self.get_register(*path_addr)?;
-                let mode_val = self.get_register(*mode)?;
+            SSAInstruction::AtomicLoad { dest, address } => {
+                use cranelift_codegen::ir::MemFlags;
+                // Sequentially consistent, so neither Cranelift nor the
+                // processor moves it past another atomic access
+                let addr_val = self.get_register(*address)?;
+                let value = self.builder.ins().atomic_load(types::I64, MemFlags::trusted(), addr_val);
+                self.register_values.insert(*dest, value);
+            }
 
-                // For create, mode should be "w" (write) or "w+" (read-write)
-                // Assume mode_val is already a pointer to C string
-                let mode_ptr = mode_val;
+            SSAInstruction::AtomicStore { address, value } => {
+                use cranelift_codegen::ir::MemFlags;
+                let addr_val = self.get_register(*address)?;
+                let value_val = self.get_register(*value)?;
+                self.builder.ins().atomic_store(MemFlags::trusted(), value_val, addr_val);
+            }
 
-                // Call fopen(path, mode)
-                let call = self.builder.ins().call(fopen_ref, &[path_ptr, mode_ptr]);
-                let file_handle = self.builder.inst_results(call)[0];
+            SSAInstruction::BlockFetch { dest, block } | SSAInstruction::BlockAssign { dest, block } => {
+                let function = if matches!(inst, SSAInstruction::BlockFetch { .. }) {
+                    "forth_block"
+                } else {
+                    "forth_buffer"
+                };
+                let block_ref = self.ffi_function(function)?;
+                let block_val = self.get_register(*block)?;
+                let call = self.builder.ins().call(block_ref, &[block_val]);
+                let addr = self.builder.inst_results(call)[0];
+                self.register_values.insert(*dest, addr);
+            }
 
-                // Check if NULL (error)
-                let null_ptr = self.builder.ins().iconst(types::I64, 0);
-                let is_null = self.builder.ins().icmp(
-                    cranelift_codegen::ir::condcodes::IntCC::Equal,
-                    file_handle,
-                    null_ptr,
-                );
+            SSAInstruction::BlockUpdate
+            | SSAInstruction::BlockSave
+            | SSAInstruction::BlockEmpty
+            | SSAInstruction::BlockFlush => {
+                let function = match inst {
+                    SSAInstruction::BlockUpdate => "forth_update",
+                    SSAInstruction::BlockSave => "forth_save_buffers",
+                    SSAInstruction::BlockEmpty => "forth_empty_buffers",
+                    _ => "forth_flush",
+                };
+                let buffers_ref = self.ffi_function(function)?;
+                self.builder.ins().call(buffers_ref, &[]);
+            }
+        }
 
-                // Set ior: 0 = success, -1 = error
-                let success = self.builder.ins().iconst(types::I64, 0);
-                let error = self.builder.ins().iconst(types::I64, -1);
-                let ior = self.builder.ins().select(is_null, error, success);
+        Ok(())
+    }
 
-                // Store results
-                self.register_values.insert(*dest_fileid, file_handle);
-                self.register_values.insert(*dest_ior, ior);
-            }
+    /// `+`, `-` or `*`, trapping or throwing on overflow if the semantics ask
+    fn overflowing(&mut self, op: BinaryOperator, left: Value, right: Value) -> Result<Value> {
+        let ins = self.builder.ins();
+        if self.arithmetic.overflow == Overflow::Wrapping {
+            return Ok(match op {
+                BinaryOperator::Add => ins.iadd(left, right),
+                BinaryOperator::Sub => ins.isub(left, right),
+                _ => ins.imul(left, right),
+            });
+        }
+        let (result, overflowed) = match op {
+            BinaryOperator::Add => ins.sadd_overflow(left, right),
+            BinaryOperator::Sub => ins.ssub_overflow(left, right),
+            _ => ins.smul_overflow(left, right),
+        };
+        self.overflow_if(overflowed)?;
+        Ok(result)
+    }
 
-            SSAInstruction::SystemCall { dest, command_addr, command_len } => {
-                // Get system FFI function reference
-                let system_ref = self.ffi_refs.get("system")
-                    .copied()
-                    .ok_or_else(|| BackendError::CodeGeneration(
-                        "FFI function 'system' not registered".to_string()
-                    ))?;
+    /// Trap, or with checked arithmetic throw, when `overflowed` is nonzero
+    fn overflow_if(&mut self, overflowed: Value) -> Result<()> {
+        if self.arithmetic.overflow != Overflow::Checked {
+            self.builder.ins().trapnz(overflowed, TrapCode::IntegerOverflow);
+            return Ok(());
+        }
 
-                // Get command pointer
-                let command_ptr = self.get_register(*command_addr)?;
+        // The throw is out of line, so the common path only branches
+        let throw_ref = self.ffi_function("forth_throw")?;
+        let throw = self.builder.create_block();
+        let next = self.builder.create_block();
+        self.builder.set_cold_block(throw);
+        self.builder.ins().brif(overflowed, throw, &[], next, &[]);
+        self.builder.seal_block(throw);
+        self.builder.seal_block(next);
+
+        self.builder.switch_to_block(throw);
+        let code = self.builder.ins().iconst(types::I64, RESULT_OUT_OF_RANGE);
+        self.builder.ins().call(throw_ref, &[code]);
+        self.builder.ins().trap(TrapCode::UnreachableCodeReached);
+
+        self.builder.switch_to_block(next);
+        Ok(())
+    }
 
-                // Call system(command)
-                let call = self.builder.ins().call(system_ref, &[command_ptr]);
-                let result = self.builder.inst_results(call)[0];
+    /// Call `callee` as EXECUTE does, testing first whether it is `likely`
+    /// and calling that word directly if so
+    fn guarded_call(
+        &mut self,
+        likely: &str,
+        signature: SigRef,
+        callee: Value,
+        args: &[Value],
+        results: usize,
+    ) -> Result<Vec<Value>> {
+        let func_ref = self.func_refs.get(likely)
+            .copied()
+            .ok_or_else(|| BackendError::CodeGeneration(
+                format!("Function '{}' not declared/imported", likely)
+            ))?;
+
+        let direct = self.builder.create_block();
+        let indirect = self.builder.create_block();
+        let merge = self.builder.create_block();
+        let merged: Vec<Value> = (0..results)
+            .map(|_| self.builder.append_block_param(merge, types::I64))
+            .collect();
+        self.builder.set_cold_block(indirect);
+
+        let address = self.builder.ins().func_addr(types::I64, func_ref);
+        let hit = self.builder.ins().icmp(IntCC::Equal, callee, address);
+        self.builder.ins().brif(hit, direct, &[], indirect, &[]);
+        self.builder.seal_block(direct);
+        self.builder.seal_block(indirect);
+
+        self.builder.switch_to_block(direct);
+        let call = self.builder.ins().call(func_ref, args);
+        let values = self.builder.inst_results(call).to_vec();
+        self.builder.ins().jump(merge, &values);
+
+        self.builder.switch_to_block(indirect);
+        let call = self.builder.ins().call_indirect(signature, callee, args);
+        let values = self.builder.inst_results(call).to_vec();
+        self.builder.ins().jump(merge, &values);
+
+        self.builder.seal_block(merge);
+        self.builder.switch_to_block(merge);
+        Ok(merged)
+    }
 
-                // Convert i32 result to i64
-                let result_i64 = self.builder.ins().sextend(types::I64, result);
+    /// The entry of a constant table for `index`
+    ///
+    /// Cranelift modules here hold no read-only data, so the table is a
+    /// jump table whose targets each pass on their constant.
+    fn lookup(&mut self, index: Value, table: &CaseTable) -> Value {
+        let merge = self.builder.create_block();
+        let result = self.builder.append_block_param(merge, types::I64);
+        let mut switch = Switch::new();
+        let mut entries = Vec::new();
+        for (offset, &value) in table.values.iter().enumerate() {
+            let entry = self.builder.create_block();
+            switch.set_entry(table.base.wrapping_add(offset as i64) as u64 as u128, entry);
+            entries.push((entry, value));
+        }
+        let otherwise = self.builder.create_block();
+        entries.push((otherwise, table.default));
+        switch.emit(&mut self.builder, index, otherwise);
+
+        for (entry, value) in entries {
+            self.builder.seal_block(entry);
+            self.builder.switch_to_block(entry);
+            let value = self.builder.ins().iconst(types::I64, value);
+            self.builder.ins().jump(merge, &[value]);
+        }
+        self.builder.seal_block(merge);
+        self.builder.switch_to_block(merge);
+        result
+    }
 
-                // system returns exit code of command
-                // Store in destination register
-                self.register_values.insert(*dest, result_i64);
+    /// `/` or `mod` under the semantics
+    ///
+    /// Division by zero traps. The quotient of the most negative cell by -1
+    /// traps or throws as other overflows do; when overflow wraps, dividing
+    /// by -1 is negation. Floored division takes one from the truncated quotient,
+    /// and adds the divisor to the remainder, when the remainder is nonzero
+    /// and its sign differs from the divisor's.
+    fn divide(&mut self, op: BinaryOperator, left: Value, right: Value) -> Result<Value> {
+        let floored = self.arithmetic.division == Division::Floored;
+        let wrapping = self.arithmetic.overflow == Overflow::Wrapping;
+
+        // `sdiv` traps on its own, so only a throw needs the check spelled out
+        if op == BinaryOperator::Div && self.arithmetic.overflow == Overflow::Checked {
+            let most_negative = self.builder.ins().icmp_imm(IntCC::Equal, left, i64::MIN);
+            let by_minus_one = self.builder.ins().icmp_imm(IntCC::Equal, right, -1);
+            let overflows = self.builder.ins().band(most_negative, by_minus_one);
+            self.overflow_if(overflows)?;
+        }
+
+        // `srem` is 0 for the most negative cell by -1; only `sdiv` traps
+        let by_minus_one = (op == BinaryOperator::Div && wrapping)
+            .then(|| self.builder.ins().icmp_imm(IntCC::Equal, right, -1));
+        let divisor = match by_minus_one {
+            Some(by_minus_one) => {
+                let one = self.builder.ins().iconst(types::I64, 1);
+                self.builder.ins().select(by_minus_one, one, right)
             }
+            None => right,
+        };
+
+        let remainder = (op == BinaryOperator::Mod || floored).then(|| self.builder.ins().srem(left, divisor));
+        let rounds_down = remainder.filter(|_| floored).map(|remainder| {
+            let nonzero = self.builder.ins().icmp_imm(IntCC::NotEqual, remainder, 0);
+            let signs = self.builder.ins().bxor(remainder, right);
+            let signs_differ = self.builder.ins().icmp_imm(IntCC::SignedLessThan, signs, 0);
+            self.builder.ins().band(nonzero, signs_differ)
+        });
+
+        if let (BinaryOperator::Mod, Some(remainder)) = (op, remainder) {
+            return Ok(match rounds_down {
+                Some(rounds_down) => {
+                    let adjusted = self.builder.ins().iadd(remainder, right);
+                    self.builder.ins().select(rounds_down, adjusted, remainder)
+                }
+                None => remainder,
+            });
         }
 
-        Ok(())
+        let mut quotient = self.builder.ins().sdiv(left, divisor);
+        if let Some(by_minus_one) = by_minus_one {
+            let negated = self.builder.ins().ineg(left);
+            quotient = self.builder.ins().select(by_minus_one, negated, quotient);
+        }
+        if let Some(rounds_down) = rounds_down {
+            let borrow = self.builder.ins().uextend(types::I64, rounds_down);
+            quotient = self.builder.ins().isub(quotient, borrow);
+        }
+        Ok(quotient)
+    }
+
+    /// Look up a registered FFI function
+    fn ffi_function(&self, name: &str) -> Result<FuncRef> {
+        self.ffi_refs.get(name)
+            .copied()
+            .ok_or_else(|| BackendError::CodeGeneration(
+                format!("FFI function '{}' not registered", name)
+            ))
+    }
+
+    /// Length of the C string at `addr` (0 for a null pointer)
+    fn cstring_length(&mut self, addr: Value) -> Result<Value> {
+        let length_ref = self.ffi_function("forth_cstring_length")?;
+        let call = self.builder.ins().call(length_ref, &[addr]);
+        Ok(self.builder.inst_results(call)[0])
     }
 
     /// Get the Cranelift value for a Fast Forth register
@@ -39,6 +39,9 @@ pub enum BackendError {
     #[error("Unsupported feature: {0}")]
     UnsupportedFeature(String),
 
+    #[error("Invalid source map: {0}")]
+    InvalidSourceMap(String),
+
     #[error("I/O error: {0}")]
     IoError(#[from] std::io::Error),
 }
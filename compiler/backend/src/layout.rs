@@ -0,0 +1,43 @@
+//! Memory Layout
+//!
+//! Every backend lays memory out the same way, as the interpreters and the
+//! threaded tier do: a cell is 8 bytes, little-endian on every target, and
+//! `c@ w@ l@` and their stores access its low 1, 2 and 4 bytes. A buffer
+//! written by compiled code therefore reads the same in the interpreter,
+//! and a program means the same on every target.
+//!
+//! A backend for a big-endian target swaps bytes on each multi-byte load
+//! and store. Whether it has to is a property of the target the code is
+//! generated for, not of the machine running the compiler.
+
+/// Bytes in a cell
+pub const CELL_BYTES: u32 = 8;
+
+/// Whether the architecture of the target `triple` is big-endian
+pub fn big_endian(triple: &str) -> bool {
+    let arch = triple.split('-').next().unwrap_or_default();
+    match arch {
+        "s390x" | "m68k" | "sparc" | "sparc64" | "sparcv9" | "bpfeb" | "aarch64_be" => true,
+        _ if arch.starts_with("armeb") || arch.starts_with("thumbeb") => true,
+        _ if arch.starts_with("mips") => !arch.ends_with("el"),
+        _ if arch.starts_with("powerpc") || arch.starts_with("ppc") => !arch.ends_with("le"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_order_follows_target() {
+        for triple in ["x86_64-unknown-linux-gnu", "aarch64-apple-darwin", "riscv64gc-unknown-linux-gnu",
+            "powerpc64le-unknown-linux-gnu", "mipsel-unknown-linux-gnu", "wasm32-unknown-unknown"] {
+            assert!(!big_endian(triple), "{}", triple);
+        }
+        for triple in ["s390x-unknown-linux-gnu", "powerpc64-unknown-linux-gnu", "mips-unknown-linux-gnu",
+            "aarch64_be-unknown-linux-gnu", "armebv7r-none-eabi", "sparc64-unknown-linux-gnu"] {
+            assert!(big_endian(triple), "{}", triple);
+        }
+    }
+}
@@ -9,6 +9,10 @@ pub mod codegen;
 #[cfg(feature = "cranelift")]
 pub mod cranelift;
 pub mod linker;
+pub mod layout;
+pub mod mangle;
+pub mod partition;
+pub mod source_map;
 pub mod error;
 
 #[cfg(feature = "llvm")]
@@ -16,6 +20,7 @@ pub use codegen::{CodeGenerator, LLVMBackend, CompilationMode};
 #[cfg(feature = "cranelift")]
 pub use cranelift::{CraneliftBackend, CraneliftCompiler};
 pub use linker::{Linker, LinkMode};
+pub use source_map::{SourceMap, SourceRange};
 pub use error::{BackendError, Result};
 
 /// Backend version and compatibility
@@ -23,6 +28,11 @@ pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const LLVM_VERSION: &str = "17.0";
 pub const CRANELIFT_VERSION: &str = "0.102";
 
+/// Whether this build includes the Cranelift code generator
+pub const CRANELIFT_ENABLED: bool = cfg!(feature = "cranelift");
+/// Whether this build includes the LLVM code generator
+pub const LLVM_ENABLED: bool = cfg!(feature = "llvm");
+
 /// Re-export types from frontend for convenience
 #[cfg(any(feature = "llvm", feature = "cranelift"))]
 pub use fastforth_frontend::ssa::{SSAFunction, SSAInstruction, Register, BlockId};
@@ -1,11 +1,165 @@
 //! Linker Infrastructure
 //!
 //! Links object files with runtime library to create executable
+//!
+//! The Forth `main` word is emitted as [`FORTH_ENTRY_SYMBOL`], and the
+//! linker generates the C `main` that hands argc/argv to the runtime
+//! (for `arg-count`, `arg@`) before calling it.
+//!
+//! Freestanding links ([`LinkerConfig::freestanding`]) produce a single
+//! relocatable object instead: the compiled words plus the libc-free
+//! runtime, with no `main`, no C library and no startup files. The host
+//! installs its I/O and memory hooks and calls the entry point itself.
+//!
+//! Shared libraries export words through reverse trampolines: C functions
+//! named after each export that call the hidden compiled word
+//! ([`export_trampolines_source`]), declared in a generated header
+//! ([`export_header`]). Objects and executables export them as aliases of
+//! the words instead ([`export_aliases_assembly`]).
+//!
+//! Windows links ([`Platform::Windows`]) produce `.exe` and `.dll` files
+//! with whichever toolchain is installed: MSVC (`cl` driving `link.exe`,
+//! from a Developer Command Prompt), clang, MinGW gcc, or bare `lld-link`.
+//! The C library and libm are part of the Windows CRT, so the Unix `c` and
+//! `m` libraries are dropped, and MSVC picks the static (`/MT`) or DLL
+//! (`/MD`) CRT from the link mode.
 
 use crate::error::{BackendError, Result};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+/// Symbol the Forth `main` word is emitted under in AOT object files
+pub const FORTH_ENTRY_SYMBOL: &str = "forth_main";
+
+/// C source of the `main` that initializes the runtime and calls `entry`
+pub fn main_wrapper_source(entry: &str) -> String {
+    format!(
+        r"/* Generated by fastforth: runtime entry for AOT executables */
+#include <stdint.h>
+
+void forth_runtime_init(int argc, char **argv);
+intptr_t {entry}(void);
+
+int main(int argc, char **argv) {{
+    forth_runtime_init(argc, argv);
+    {entry}();
+    return 0;
+}}
+"
+    )
+}
+
+/// A Forth word exported from a shared library under a C symbol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CExport {
+    /// Forth name of the word
+    pub word: String,
+    /// C symbol the word is exported as
+    pub symbol: String,
+    /// Cells the word takes, passed as C arguments deepest first
+    pub inputs: usize,
+    /// Whether the word leaves a cell, which becomes the C result
+    pub returns: bool,
+    /// Declared stack effect, quoted in the header
+    pub stack_effect: String,
+}
+
+impl CExport {
+    fn prototype(&self) -> String {
+        let result = if self.returns { "intptr_t" } else { "void" };
+        let params: Vec<String> = (1..=self.inputs).map(|i| format!("intptr_t x{}", i)).collect();
+        let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+        format!("{} {}({})", result, self.symbol, params)
+    }
+}
+
+/// C source of the reverse trampolines of a shared library: one C entry
+/// point per export, calling the word under its hidden [`word_symbol`]
+pub fn export_trampolines_source(exports: &[CExport]) -> String {
+    let mut source = String::from(
+        "/* Generated by fastforth: C entry points for exported Forth words */\n#include <stdint.h>\n\n\
+         #ifdef _WIN32\n#define FASTFORTH_EXPORT __declspec(dllexport)\n#else\n#define FASTFORTH_EXPORT\n#endif\n",
+    );
+    for export in exports {
+        let word = word_symbol(&export.word);
+        let params = vec!["intptr_t"; export.inputs].join(", ");
+        let params = if params.is_empty() { "void".to_string() } else { params };
+        let args: Vec<String> = (1..=export.inputs).map(|i| format!("x{}", i)).collect();
+        let call = format!("{}({})", word, args.join(", "));
+        let (result, body) = if export.returns { ("intptr_t", format!("return {};", call)) } else { ("void", format!("{};", call)) };
+        source.push_str(&format!("\n{} {}({});\n\nFASTFORTH_EXPORT {} {{\n    {}\n}}\n", result, word, params, export.prototype(), body));
+    }
+    source
+}
+
+/// Assembler source making each export's C symbol a global alias of the
+/// compiled word, for objects and executables, which link the word
+/// directly instead of through a trampoline
+pub fn export_aliases_assembly(exports: &[CExport]) -> String {
+    let mut source = String::new();
+    for export in exports {
+        let (symbol, word) = (&export.symbol, word_symbol(&export.word));
+        source.push_str(&format!("\t.globl {symbol}\n\t.type {symbol}, @function\n\t.set {symbol}, {word}\n"));
+    }
+    source
+}
+
+/// C header declaring the exports of the shared library `library`
+pub fn export_header(library: &str, exports: &[CExport]) -> String {
+    let guard: String = library
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+    let mut header = format!(
+        "/* Generated by fastforth: Forth words exported from {library} */\n#ifndef {guard}_H\n#define {guard}_H\n\n#include <stdint.h>\n\n#ifdef __cplusplus\nextern \"C\" {{\n#endif\n"
+    );
+    for export in exports {
+        header.push_str(&format!("\n/* {} {} */\n{};\n", export.word, export.stack_effect, export.prototype()));
+    }
+    header.push_str(&format!("\n#ifdef __cplusplus\n}}\n#endif\n\n#endif /* {guard}_H */\n"));
+    header
+}
+
+/// Symbol a CODE word is assembled under
+///
+/// Characters a label cannot hold are replaced by `_xx` hex escapes, so
+/// distinct Forth names keep distinct symbols.
+pub fn code_word_symbol(name: &str) -> String {
+    escaped_symbol("forth_code_", name)
+}
+
+/// Symbol a compiled Forth word is emitted under, mangled by
+/// [`crate::mangle::mangle`]
+pub fn word_symbol(name: &str) -> String {
+    crate::mangle::mangle(name)
+}
+
+fn escaped_symbol(prefix: &str, name: &str) -> String {
+    let mut symbol = String::from(prefix);
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            symbol.push(c);
+        } else {
+            for byte in c.to_string().bytes() {
+                symbol.push_str(&format!("_{:02x}", byte));
+            }
+        }
+    }
+    symbol
+}
+
+/// Assembler source defining `symbol` as a global function whose body is
+/// `body`, copied verbatim
+pub fn code_word_assembly(symbol: &str, body: &str) -> String {
+    if cfg!(target_vendor = "apple") {
+        format!("\t.text\n\t.globl _{symbol}\n\t.p2align 4\n_{symbol}:\n{body}\n")
+    } else {
+        format!(
+            "\t.text\n\t.globl {symbol}\n\t.type {symbol}, @function\n\t.p2align 4\n{symbol}:\n{body}\n\t.size {symbol}, .-{symbol}\n\t.section .note.GNU-stack,\"\",@progbits\n"
+        )
+    }
+}
+
 /// Link mode
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LinkMode {
@@ -15,6 +169,99 @@ pub enum LinkMode {
     Dynamic,
 }
 
+/// Operating system family a link targets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    /// Linux, macOS and the BSDs: gcc-style drivers, ELF or Mach-O
+    Unix,
+    /// Windows: MSVC or MinGW toolchains, PE executables and DLLs
+    Windows,
+}
+
+impl Platform {
+    /// The platform the compiler runs on
+    pub fn host() -> Self {
+        if cfg!(windows) {
+            Platform::Windows
+        } else {
+            Platform::Unix
+        }
+    }
+
+    /// Extension of executables, empty on Unix
+    pub fn executable_extension(self) -> &'static str {
+        match self {
+            Platform::Unix => "",
+            Platform::Windows => "exe",
+        }
+    }
+
+    /// Extension of shared libraries
+    pub fn shared_library_extension(self) -> &'static str {
+        match self {
+            Platform::Unix if cfg!(target_vendor = "apple") => "dylib",
+            Platform::Unix => "so",
+            Platform::Windows => "dll",
+        }
+    }
+}
+
+/// Link-time optimization
+///
+/// With LTO the code generator writes LLVM bitcode instead of machine code
+/// and clang, linking through lld, optimizes the whole program at once:
+/// across codegen units, and across the boundary with the C runtime, whose
+/// primitives can then be inlined into the words that call them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Lto {
+    /// Link machine code as is
+    #[default]
+    Off,
+    /// ThinLTO: summaries guide inlining between modules optimized in
+    /// parallel
+    Thin,
+    /// Full LTO: every module merged into one and optimized together
+    Full,
+}
+
+impl Lto {
+    pub fn enabled(self) -> bool {
+        self != Lto::Off
+    }
+
+    /// Compiler driver flag selecting this mode
+    pub fn flag(self) -> Option<&'static str> {
+        match self {
+            Lto::Off => None,
+            Lto::Thin => Some("-flto=thin"),
+            Lto::Full => Some("-flto"),
+        }
+    }
+}
+
+impl std::fmt::Display for Lto {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Lto::Off => write!(f, "off"),
+            Lto::Thin => write!(f, "thin"),
+            Lto::Full => write!(f, "full"),
+        }
+    }
+}
+
+impl std::str::FromStr for Lto {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" | "no" | "false" => Ok(Lto::Off),
+            "thin" => Ok(Lto::Thin),
+            "full" | "fat" | "yes" | "true" => Ok(Lto::Full),
+            _ => Err(format!("unknown LTO mode '{}' (expected off, thin or full)", s)),
+        }
+    }
+}
+
 /// Linker configuration
 pub struct LinkerConfig {
     /// Link mode
@@ -40,6 +287,20 @@ pub struct LinkerConfig {
 
     /// Generate position-independent executable
     pub pie: bool,
+
+    /// Forth entry point to call from a generated C `main`; `None` when the
+    /// object files define `main` themselves
+    pub entry: Option<String>,
+
+    /// Build a relocatable object without libc or startup files
+    pub freestanding: bool,
+
+    /// The inputs are LLVM bitcode, optimized together with the runtime
+    /// at link time (needs clang and lld)
+    pub lto: Lto,
+
+    /// Operating system the output runs on
+    pub platform: Platform,
 }
 
 impl Default for LinkerConfig {
@@ -53,6 +314,26 @@ impl Default for LinkerConfig {
             optimize: true,
             strip: false,
             pie: true,
+            entry: Some(FORTH_ENTRY_SYMBOL.to_string()),
+            freestanding: false,
+            lto: Lto::Off,
+            platform: Platform::host(),
+        }
+    }
+}
+
+impl LinkerConfig {
+    /// Configuration for a freestanding object linked with the libc-free
+    /// runtime, for microcontrollers and kernels
+    pub fn freestanding() -> Self {
+        Self {
+            runtime_lib: PathBuf::from("runtime/forth_freestanding.c"),
+            libs: Vec::new(),
+            output: PathBuf::from("a.o"),
+            pie: false,
+            entry: None,
+            freestanding: true,
+            ..Self::default()
         }
     }
 }
@@ -70,13 +351,73 @@ impl Linker {
 
     /// Link object files to create executable
     pub fn link(&self, object_files: &[PathBuf]) -> Result<PathBuf> {
-        match self.detect_linker() {
+        let linker = self.detect_linker();
+        if self.config.lto.enabled() && linker != LinkerType::Clang {
+            return Err(BackendError::LinkingFailed(
+                "LTO needs clang and lld to link bitcode".to_string()
+            ));
+        }
+        let Some(entry) = &self.config.entry else {
+            return self.link_with(linker, object_files);
+        };
+        if self.config.freestanding {
+            return Err(BackendError::LinkingFailed(
+                "A freestanding object has no C main; the host calls the entry point".to_string()
+            ));
+        }
+        if matches!(linker, LinkerType::Ld | LinkerType::LldLink) {
+            return Err(BackendError::LinkingFailed(
+                "Generating the main wrapper needs a C compiler (clang, gcc or cl)".to_string()
+            ));
+        }
+
+        let wrapper = self.write_main_wrapper(entry)?;
+        let mut inputs = object_files.to_vec();
+        inputs.push(wrapper.clone());
+        let result = self.link_with(linker, &inputs);
+        let _ = std::fs::remove_file(&wrapper);
+        result
+    }
+
+    fn link_with(&self, linker: LinkerType, object_files: &[PathBuf]) -> Result<PathBuf> {
+        match linker {
             LinkerType::Gcc => self.link_with_gcc(object_files),
             LinkerType::Clang => self.link_with_clang(object_files),
             LinkerType::Ld => self.link_with_ld(object_files),
+            LinkerType::Msvc => self.link_with_msvc(object_files),
+            LinkerType::LldLink => self.link_with_lld_link(object_files),
+        }
+    }
+
+    /// Output path, with `.exe` added on Windows when it has no extension
+    pub fn output_path(&self) -> PathBuf {
+        let output = &self.config.output;
+        let extension = self.config.platform.executable_extension();
+        if extension.is_empty() || output.extension().is_some() || self.config.freestanding {
+            output.clone()
+        } else {
+            output.with_extension(extension)
         }
     }
 
+    /// Libraries to link; on Windows the C library and libm are part of
+    /// the CRT every toolchain links anyway
+    fn libs(&self) -> impl Iterator<Item = &str> {
+        let windows = self.config.platform == Platform::Windows;
+        self.config.libs.iter().map(String::as_str).filter(move |lib| !(windows && matches!(*lib, "c" | "m")))
+    }
+
+    /// Write the generated `main` next to the output executable
+    fn write_main_wrapper(&self, entry: &str) -> Result<PathBuf> {
+        let mut name = self.config.output.file_name().unwrap_or_default().to_os_string();
+        name.push("-main.c");
+        let path = self.config.output.with_file_name(name);
+        std::fs::write(&path, main_wrapper_source(entry)).map_err(|e| {
+            BackendError::LinkingFailed(format!("Failed to write {}: {}", path.display(), e))
+        })?;
+        Ok(path)
+    }
+
     /// Link with GCC
     fn link_with_gcc(&self, object_files: &[PathBuf]) -> Result<PathBuf> {
         let mut cmd = Command::new("gcc");
@@ -91,18 +432,23 @@ impl Linker {
             cmd.arg(&self.config.runtime_lib);
         }
 
+        // No libc, no startup files: one relocatable object for the host
+        if self.config.freestanding {
+            cmd.args(["-ffreestanding", "-fno-builtin", "-nostdlib", "-r"]);
+        }
+
         // Add library paths
         for path in &self.config.lib_paths {
             cmd.arg(format!("-L{}", path.display()));
         }
 
         // Add libraries
-        for lib in &self.config.libs {
+        for lib in self.libs() {
             cmd.arg(format!("-l{}", lib));
         }
 
         // Output file
-        cmd.arg("-o").arg(&self.config.output);
+        cmd.arg("-o").arg(self.output_path());
 
         // Optimization
         if self.config.optimize {
@@ -114,18 +460,18 @@ impl Linker {
             cmd.arg("-s");
         }
 
-        // PIE
-        if self.config.pie {
+        // PIE; PE images are always relocatable
+        if self.config.pie && self.config.platform == Platform::Unix {
             cmd.arg("-pie");
         }
 
         // Static/dynamic linking
         match self.config.mode {
-            LinkMode::Static => {
+            LinkMode::Static if !self.config.freestanding => {
                 cmd.arg("-static");
             }
-            LinkMode::Dynamic => {
-                // Dynamic is default
+            LinkMode::Static | LinkMode::Dynamic => {
+                // Dynamic is default; a relocatable object is neither
             }
         }
 
@@ -138,7 +484,7 @@ impl Linker {
             return Err(BackendError::LinkingFailed(format!("Linking failed: {}", stderr)));
         }
 
-        Ok(self.config.output.clone())
+        Ok(self.output_path())
     }
 
     /// Link with Clang
@@ -155,41 +501,53 @@ impl Linker {
             cmd.arg(&self.config.runtime_lib);
         }
 
+        // No libc, no startup files: one relocatable object for the host
+        if self.config.freestanding {
+            cmd.args(["-ffreestanding", "-fno-builtin", "-nostdlib", "-r"]);
+        }
+
         // Add library paths
         for path in &self.config.lib_paths {
             cmd.arg(format!("-L{}", path.display()));
         }
 
         // Add libraries
-        for lib in &self.config.libs {
+        for lib in self.libs() {
             cmd.arg(format!("-l{}", lib));
         }
 
         // Output file
-        cmd.arg("-o").arg(&self.config.output);
+        cmd.arg("-o").arg(self.output_path());
 
         // Optimization
         if self.config.optimize {
             cmd.arg("-O2");
         }
 
+        // Whole-program optimization of the codegen units and the runtime,
+        // which clang compiles to bitcode alongside them
+        if let Some(flag) = self.config.lto.flag() {
+            cmd.args([flag, "-fuse-ld=lld"]);
+        }
+
         // Strip symbols
         if self.config.strip {
             cmd.arg("-Wl,-s");
         }
 
-        // PIE
-        if self.config.pie {
+        // PIE; PE images are always relocatable
+        if self.config.pie && self.config.platform == Platform::Unix {
             cmd.arg("-pie");
         }
 
-        // Static/dynamic linking
+        // Static/dynamic linking; clang on Windows links the CRT the
+        // target's toolchain defaults to
         match self.config.mode {
-            LinkMode::Static => {
+            LinkMode::Static if !self.config.freestanding && self.config.platform == Platform::Unix => {
                 cmd.arg("-static");
             }
-            LinkMode::Dynamic => {
-                // Dynamic is default
+            LinkMode::Static | LinkMode::Dynamic => {
+                // Dynamic is default; a relocatable object is neither
             }
         }
 
@@ -202,7 +560,7 @@ impl Linker {
             return Err(BackendError::LinkingFailed(format!("Linking failed: {}", stderr)));
         }
 
-        Ok(self.config.output.clone())
+        Ok(self.output_path())
     }
 
     /// Link with ld directly
@@ -215,7 +573,12 @@ impl Linker {
         }
 
         // Output file
-        cmd.arg("-o").arg(&self.config.output);
+        cmd.arg("-o").arg(self.output_path());
+
+        // ld cannot compile the C runtime, so it is left to the caller
+        if self.config.freestanding {
+            cmd.arg("-r");
+        }
 
         // Add library paths
         for path in &self.config.lib_paths {
@@ -223,12 +586,12 @@ impl Linker {
         }
 
         // Add libraries
-        for lib in &self.config.libs {
+        for lib in self.libs() {
             cmd.arg(format!("-l{}", lib));
         }
 
         // PIE
-        if self.config.pie {
+        if self.config.pie && self.config.platform == Platform::Unix {
             cmd.arg("-pie");
         }
 
@@ -241,11 +604,99 @@ impl Linker {
             return Err(BackendError::LinkingFailed(format!("Linking failed: {}", stderr)));
         }
 
-        Ok(self.config.output.clone())
+        Ok(self.output_path())
+    }
+
+    /// Link with MSVC: `cl` compiles the runtime and drives `link.exe`
+    fn link_with_msvc(&self, object_files: &[PathBuf]) -> Result<PathBuf> {
+        if self.config.freestanding {
+            return Err(BackendError::LinkingFailed(
+                "A freestanding object on Windows needs MinGW or clang; link.exe cannot build a relocatable object".to_string()
+            ));
+        }
+        run_windows_linker(self.msvc_command(object_files), "cl")?;
+        Ok(self.output_path())
+    }
+
+    fn msvc_command(&self, object_files: &[PathBuf]) -> Command {
+        let output = self.output_path();
+        let mut cmd = Command::new("cl");
+        cmd.arg("/nologo");
+
+        // Add object files
+        cmd.args(object_files);
+
+        // Add runtime library
+        if self.config.runtime_lib.exists() {
+            cmd.arg(&self.config.runtime_lib);
+        }
+
+        // Output file, with the runtime's intermediate object beside it
+        cmd.arg(format!("/Fe{}", output.display()));
+        let dir = output.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        cmd.arg(format!("/Fo{}\\", dir.display()));
+
+        // Optimization
+        if self.config.optimize {
+            cmd.arg("/O2");
+        }
+
+        // Static or DLL C runtime
+        cmd.arg(match self.config.mode {
+            LinkMode::Static => "/MT",
+            LinkMode::Dynamic => "/MD",
+        });
+
+        // Everything after /link goes to link.exe
+        cmd.arg("/link");
+        for path in &self.config.lib_paths {
+            cmd.arg(format!("/LIBPATH:{}", path.display()));
+        }
+        for lib in self.libs() {
+            cmd.arg(format!("{}.lib", lib));
+        }
+        cmd
+    }
+
+    /// Link with lld-link, which like ld cannot compile the runtime
+    fn link_with_lld_link(&self, object_files: &[PathBuf]) -> Result<PathBuf> {
+        if self.config.freestanding {
+            return Err(BackendError::LinkingFailed(
+                "A freestanding object on Windows needs MinGW or clang; lld-link cannot build a relocatable object".to_string()
+            ));
+        }
+        run_windows_linker(self.lld_link_command(object_files), "lld-link")?;
+        Ok(self.output_path())
+    }
+
+    fn lld_link_command(&self, object_files: &[PathBuf]) -> Command {
+        let mut cmd = Command::new("lld-link");
+        cmd.arg("/nologo")
+            .arg(format!("/OUT:{}", self.output_path().display()))
+            .arg("/SUBSYSTEM:CONSOLE");
+        cmd.args(object_files);
+
+        for path in &self.config.lib_paths {
+            cmd.arg(format!("/LIBPATH:{}", path.display()));
+        }
+        for lib in self.libs() {
+            cmd.arg(format!("{}.lib", lib));
+        }
+
+        // The C runtime `cl` would have picked
+        cmd.arg(match self.config.mode {
+            LinkMode::Static => "/DEFAULTLIB:libcmt",
+            LinkMode::Dynamic => "/DEFAULTLIB:msvcrt",
+        });
+        cmd
     }
 
     /// Detect available linker
     fn detect_linker(&self) -> LinkerType {
+        if self.config.platform == Platform::Windows {
+            return detect_windows_linker();
+        }
+
         // Try clang first (better on macOS)
         if Command::new("clang").arg("--version").output().is_ok() {
             return LinkerType::Clang;
@@ -261,17 +712,36 @@ impl Linker {
     }
 
     /// Link runtime library separately
+    ///
+    /// With LTO the runtime is compiled to bitcode, so it can be optimized
+    /// together with the program.
     pub fn compile_runtime(&self) -> Result<PathBuf> {
         let runtime_src = &self.config.runtime_lib;
-        let runtime_obj = runtime_src.with_extension("o");
+        let lto = self.config.lto.flag();
+        if lto.is_none() && self.uses_msvc() {
+            let runtime_obj = runtime_src.with_extension("obj");
+            self.compile_c(runtime_src, &runtime_obj)?;
+            return Ok(runtime_obj);
+        }
+        let runtime_obj = runtime_src.with_extension(if lto.is_some() { "bc" } else { "o" });
 
-        let mut cmd = Command::new("gcc");
+        let mut cmd = match lto {
+            Some(flag) => {
+                let mut cmd = Command::new("clang");
+                cmd.arg(flag);
+                cmd
+            }
+            None => Command::new("gcc"),
+        };
         cmd.arg("-c")
             .arg(runtime_src)
             .arg("-o")
             .arg(&runtime_obj)
             .arg("-O2")
             .arg("-fPIC");
+        if self.config.freestanding {
+            cmd.args(["-ffreestanding", "-fno-builtin"]);
+        }
 
         let output = cmd.output()
             .map_err(|e| BackendError::LinkingFailed(format!("Failed to compile runtime: {}", e)))?;
@@ -284,11 +754,107 @@ impl Linker {
         Ok(runtime_obj)
     }
 
+    /// Compile a C source file into a position-independent object file
+    pub fn compile_c(&self, source: &Path, object: &Path) -> Result<()> {
+        let mut cmd = if self.uses_msvc() {
+            let mut cmd = Command::new("cl");
+            cmd.args(["/nologo", "/c", "/O2"])
+                .arg(source)
+                .arg(format!("/Fo{}", object.display()));
+            cmd
+        } else {
+            let mut cmd = Command::new("gcc");
+            cmd.arg("-c")
+                .arg(source)
+                .arg("-o")
+                .arg(object)
+                .arg("-O2");
+            // All code in a PE image is position-independent already
+            if self.config.platform == Platform::Unix {
+                cmd.arg("-fPIC");
+            }
+            cmd
+        };
+
+        let output = cmd.output()
+            .map_err(|e| BackendError::LinkingFailed(format!("Failed to run C compiler: {}", e)))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(BackendError::LinkingFailed(format!("C compilation failed: {}", stderr)));
+        }
+
+        Ok(())
+    }
+
+    /// Assemble a source file into an object file
+    pub fn assemble(&self, source: &Path, object: &Path) -> Result<()> {
+        if self.uses_msvc() {
+            return Err(BackendError::LinkingFailed(
+                "Assembler source is in GNU syntax, which MSVC cannot assemble; install MinGW or clang".to_string()
+            ));
+        }
+        let mut cmd = Command::new("gcc");
+        cmd.arg("-c")
+            .arg("-x")
+            .arg("assembler")
+            .arg(source)
+            .arg("-o")
+            .arg(object);
+
+        let output = cmd.output()
+            .map_err(|e| BackendError::LinkingFailed(format!("Failed to run assembler: {}", e)))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(BackendError::LinkingFailed(format!("Assembly failed: {}", stderr)));
+        }
+
+        Ok(())
+    }
+
+    /// Combine the objects of several codegen units into one relocatable
+    /// object, running LTO over them first when configured
+    pub fn merge_objects(&self, object_files: &[PathBuf], output: &Path) -> Result<()> {
+        if self.config.lto.flag().is_none() && self.uses_msvc() {
+            return Err(BackendError::LinkingFailed(
+                "Merging codegen units needs ld or lld; link.exe cannot build a relocatable object".to_string()
+            ));
+        }
+        let mut cmd = if let Some(flag) = self.config.lto.flag() {
+            let mut cmd = Command::new("clang");
+            cmd.args([flag, "-fuse-ld=lld", "-nostdlib", "-r"]);
+            cmd
+        } else {
+            let mut cmd = Command::new("ld");
+            cmd.arg("-r");
+            cmd
+        };
+        cmd.args(object_files).arg("-o").arg(output);
+
+        let result = cmd.output()
+            .map_err(|e| BackendError::LinkingFailed(format!("Failed to run linker: {}", e)))?;
+
+        if !result.status.success() {
+            let stderr = String::from_utf8_lossy(&result.stderr);
+            return Err(BackendError::LinkingFailed(format!("Merging codegen units failed: {}", stderr)));
+        }
+
+        Ok(())
+    }
+
     /// Create static library archive
     pub fn create_archive(&self, object_files: &[PathBuf], archive_name: &Path) -> Result<()> {
-        let mut cmd = Command::new("ar");
-        cmd.arg("rcs")
-            .arg(archive_name);
+        let mut cmd = if self.uses_msvc() {
+            let mut cmd = Command::new("lib");
+            cmd.arg("/nologo").arg(format!("/OUT:{}", archive_name.display()));
+            cmd
+        } else {
+            let mut cmd = Command::new("ar");
+            cmd.arg("rcs")
+                .arg(archive_name);
+            cmd
+        };
 
         for obj in object_files {
             cmd.arg(obj);
@@ -306,12 +872,23 @@ impl Linker {
     }
 
     /// Create shared library
+    ///
+    /// On Windows this is a DLL; the exported words are marked
+    /// `__declspec(dllexport)` by [`export_trampolines_source`].
     pub fn create_shared_library(&self, object_files: &[PathBuf], lib_name: &Path) -> Result<()> {
-        let mut cmd = Command::new("gcc");
-        cmd.arg("-shared")
-            .arg("-fPIC")
-            .arg("-o")
-            .arg(lib_name);
+        let mut cmd = if self.uses_msvc() {
+            let mut cmd = Command::new("link");
+            cmd.args(["/nologo", "/DLL"]).arg(format!("/OUT:{}", lib_name.display()));
+            cmd
+        } else {
+            let mut cmd = Command::new("gcc");
+            cmd.arg("-shared");
+            if self.config.platform == Platform::Unix {
+                cmd.arg("-fPIC");
+            }
+            cmd.arg("-o").arg(lib_name);
+            cmd
+        };
 
         for obj in object_files {
             cmd.arg(obj);
@@ -327,6 +904,41 @@ impl Linker {
 
         Ok(())
     }
+
+    /// Whether C goes through the MSVC toolchain rather than a gcc-style
+    /// driver
+    fn uses_msvc(&self) -> bool {
+        self.config.platform == Platform::Windows && self.detect_linker() == LinkerType::Msvc
+    }
+}
+
+/// Pick a Windows toolchain: MSVC when a Developer Command Prompt has put
+/// `cl` on the path, then clang, MinGW gcc, and bare lld-link
+fn detect_windows_linker() -> LinkerType {
+    if Command::new("cl").arg("/?").output().is_ok() {
+        return LinkerType::Msvc;
+    }
+    if Command::new("clang").arg("--version").output().is_ok() {
+        return LinkerType::Clang;
+    }
+    if Command::new("gcc").arg("--version").output().is_ok() {
+        return LinkerType::Gcc;
+    }
+    LinkerType::LldLink
+}
+
+/// Run an MSVC-style linker, which reports errors on stdout
+fn run_windows_linker(mut cmd: Command, name: &str) -> Result<()> {
+    let output = cmd.output()
+        .map_err(|e| BackendError::LinkingFailed(format!("Failed to execute {}: {}", name, e)))?;
+
+    if !output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(BackendError::LinkingFailed(format!("Linking failed: {}{}", stdout, stderr)));
+    }
+
+    Ok(())
 }
 
 /// Linker type
@@ -335,6 +947,10 @@ enum LinkerType {
     Gcc,
     Clang,
     Ld,
+    /// MSVC `cl`, driving `link.exe`
+    Msvc,
+    /// LLVM's MSVC-compatible linker
+    LldLink,
 }
 
 #[cfg(test)]
@@ -348,9 +964,208 @@ mod tests {
         assert!(config.libs.contains(&"c".to_string()));
     }
 
+    #[test]
+    fn test_freestanding_config_skips_libc() {
+        let config = LinkerConfig::freestanding();
+        assert!(config.libs.is_empty());
+        assert!(config.entry.is_none());
+        assert!(config.runtime_lib.ends_with("forth_freestanding.c"));
+    }
+
     #[test]
     fn test_linker_creation() {
         let config = LinkerConfig::default();
         let _linker = Linker::new(config);
     }
+
+    #[test]
+    fn test_code_word_symbol_escapes_name() {
+        assert_eq!(code_word_symbol("add3"), "forth_code_add3");
+        assert_eq!(code_word_symbol("2*"), "forth_code_2_2a");
+        assert_ne!(code_word_symbol("a-b"), code_word_symbol("a_b"));
+        assert!(code_word_assembly("forth_code_x", "ret").contains("\nret\n"));
+    }
+
+    #[test]
+    fn test_export_aliases_name_mangled_word() {
+        let export = CExport { word: "sum-sq".into(), symbol: "sum_sq".into(), inputs: 2, returns: true, stack_effect: "( a b -- n )".into() };
+        let source = export_aliases_assembly(&[export]);
+        assert!(source.contains("\t.globl sum_sq\n"));
+        assert!(source.contains(&format!("\t.set sum_sq, {}\n", word_symbol("sum-sq"))));
+    }
+
+    #[test]
+    fn test_main_wrapper_initializes_runtime_first() {
+        let source = main_wrapper_source(FORTH_ENTRY_SYMBOL);
+        let init = source.find("forth_runtime_init(argc, argv);").unwrap();
+        let call = source.find("forth_main();").unwrap();
+        assert!(init < call);
+        assert!(source.contains("intptr_t forth_main(void);"));
+    }
+
+    #[test]
+    fn test_linked_program_sees_arguments() {
+        if Command::new("gcc").arg("--version").output().is_err() {
+            return;
+        }
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+
+        // Stands in for compiled Forth: ( -- ) arg-count . 1 arg@ type
+        let entry = dir.join("entry.c");
+        std::fs::write(&entry, r#"
+            #include <stdint.h>
+            #include <stdio.h>
+            intptr_t forth_arg_count(void);
+            intptr_t forth_arg(intptr_t n);
+            intptr_t forth_main(void) {
+                printf("%ld %s", (long)forth_arg_count(), (const char *)forth_arg(1));
+                return 0;
+            }
+        "#).unwrap();
+
+        let config = LinkerConfig {
+            mode: LinkMode::Dynamic,
+            runtime_lib: Path::new(env!("CARGO_MANIFEST_DIR")).join("../runtime/forth_runtime.c"),
+            output: dir.join("prog"),
+            optimize: false,
+            pie: false,
+            ..LinkerConfig::default()
+        };
+        let system = Path::new(env!("CARGO_MANIFEST_DIR")).join("../runtime/forth_system.c");
+        let exe = Linker::new(config).link(&[entry, system]).unwrap();
+        let output = Command::new(&exe).arg("hello").output().unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "2 hello");
+    }
+
+    #[test]
+    fn test_freestanding_object_needs_no_symbols() {
+        if Command::new("gcc").arg("--version").output().is_err() || Command::new("nm").arg("--version").output().is_err() {
+            return;
+        }
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+
+        // Stands in for compiled Forth: ( -- ) s" hi" type -42 . cr
+        let entry = dir.join("entry.c");
+        std::fs::write(&entry, r#"
+            #include <stdint.h>
+            void *malloc(unsigned long size);
+            void *memcpy(void *dest, const void *src, unsigned long n);
+            void forth_io_type(intptr_t addr, intptr_t len);
+            void forth_io_dot(intptr_t n);
+            void forth_io_cr(void);
+            intptr_t forth_main(void) {
+                char *s = memcpy(malloc(2), "hi", 2);
+                forth_io_type((intptr_t)s, 2);
+                forth_io_dot(-42);
+                forth_io_cr();
+                return 0;
+            }
+        "#).unwrap();
+
+        let config = LinkerConfig {
+            runtime_lib: Path::new(env!("CARGO_MANIFEST_DIR")).join("../runtime/forth_freestanding.c"),
+            output: dir.join("forth.o"),
+            ..LinkerConfig::freestanding()
+        };
+        let object = Linker::new(config).link(&[entry]).unwrap();
+        let undefined = Command::new("nm").arg("-u").arg(&object).output().unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&undefined.stdout).trim(), "");
+    }
+
+    #[test]
+    fn test_windows_link_commands() {
+        let config = LinkerConfig {
+            platform: Platform::Windows,
+            output: PathBuf::from("build/program"),
+            libs: vec!["c".to_string(), "m".to_string(), "ws2_32".to_string()],
+            lib_paths: vec![PathBuf::from("C:/sdk/lib")],
+            ..LinkerConfig::default()
+        };
+        let linker = Linker::new(config);
+        assert_eq!(linker.output_path(), PathBuf::from("build/program.exe"));
+        assert_eq!(linker.libs().collect::<Vec<_>>(), ["ws2_32"]);
+
+        let args = |cmd: Command| -> Vec<String> {
+            cmd.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect()
+        };
+        let objects = [PathBuf::from("words.obj")];
+        let cl = args(linker.msvc_command(&objects));
+        assert!(cl.contains(&"/Febuild/program.exe".to_string()));
+        assert!(cl.contains(&"/MT".to_string()));
+        let link = cl.iter().position(|arg| arg == "/link").unwrap();
+        assert_eq!(cl[link + 1..], ["/LIBPATH:C:/sdk/lib", "ws2_32.lib"]);
+        assert!(!cl.iter().any(|arg| arg == "-pie" || arg.starts_with("-l")));
+
+        let lld = args(linker.lld_link_command(&objects));
+        assert!(lld.contains(&"/OUT:build/program.exe".to_string()));
+        assert!(lld.contains(&"/DEFAULTLIB:libcmt".to_string()));
+
+        // Unix output names are left alone
+        let unix = Linker::new(LinkerConfig { platform: Platform::Unix, ..LinkerConfig::default() });
+        assert_eq!(unix.output_path(), PathBuf::from("a.out"));
+        assert_eq!(unix.libs().collect::<Vec<_>>(), ["c", "m"]);
+        assert_eq!(Platform::Windows.shared_library_extension(), "dll");
+    }
+
+    #[test]
+    fn test_exports_are_dllexport_on_windows() {
+        let export = CExport {
+            word: "square".to_string(),
+            symbol: "square".to_string(),
+            inputs: 1,
+            returns: true,
+            stack_effect: "( n -- n )".to_string(),
+        };
+        let source = export_trampolines_source(&[export]);
+        assert!(source.contains("#define FASTFORTH_EXPORT __declspec(dllexport)"));
+        assert!(source.contains("FASTFORTH_EXPORT intptr_t square(intptr_t x1) {"));
+    }
+
+    #[test]
+    fn test_lto_modes() {
+        assert_eq!("thin".parse::<Lto>(), Ok(Lto::Thin));
+        assert_eq!("FULL".parse::<Lto>(), Ok(Lto::Full));
+        assert_eq!("off".parse::<Lto>(), Ok(Lto::Off));
+        assert!("fast".parse::<Lto>().is_err());
+        assert_eq!(Lto::Thin.flag(), Some("-flto=thin"));
+        assert_eq!(Lto::Full.flag(), Some("-flto"));
+        assert_eq!(Lto::Off.flag(), None);
+        assert_eq!(Lto::default().to_string(), "off");
+    }
+
+    #[test]
+    fn test_codegen_units_merge_into_one_object() {
+        if Command::new("gcc").arg("--version").output().is_err() || Command::new("nm").arg("--version").output().is_err() {
+            return;
+        }
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+
+        // Two units, the second calling into the first
+        let linker = Linker::new(LinkerConfig::default());
+        let mut objects = Vec::new();
+        for (name, source) in [
+            ("unit0", "long square(long n) { return n * n; }"),
+            ("unit1", "long square(long n); long quad(long n) { return square(square(n)); }"),
+        ] {
+            let c = dir.join(format!("{}.c", name));
+            std::fs::write(&c, source).unwrap();
+            let object = dir.join(format!("{}.o", name));
+            linker.compile_c(&c, &object).unwrap();
+            objects.push(object);
+        }
+
+        let merged = dir.join("program.o");
+        linker.merge_objects(&objects, &merged).unwrap();
+        let undefined = Command::new("nm").arg("-u").arg(&merged).output().unwrap();
+        let defined = Command::new("nm").arg("--defined-only").arg(&merged).output().unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&undefined.stdout).trim(), "");
+        let defined = String::from_utf8_lossy(&defined.stdout);
+        assert!(defined.contains(" square") && defined.contains(" quad"));
+    }
 }
@@ -0,0 +1,151 @@
+//! Symbol Mangling
+//!
+//! Forth names are not C symbols: `+` cannot be a symbol at all, and a word
+//! called `main` or `malloc` would collide with the C function of that
+//! name. Compiled words are emitted under a mangled symbol instead,
+//! `forth$NAME$HASH`:
+//! - `NAME` keeps ASCII letters and digits and writes every other byte as a
+//!   `_xx` hex escape, so `2dup` stays `2dup` and `+` becomes `_2b`
+//! - `HASH` is the 32-bit FNV-1a hash of the Forth name in 8 hex digits,
+//!   which keeps C code and other tools from defining a mangled name by
+//!   accident
+//!
+//! C compilers and the assemblers accept `$` in symbols, and no C
+//! identifier the runtime or libc defines contains one. [`demangle`] turns
+//! a symbol back into its Forth name, and [`demangle_text`] rewrites the
+//! symbols in profiler or debugger output, like `c++filt`; `fastforth
+//! demangle` runs it from the command line.
+
+/// Prefix of every mangled symbol
+pub const PREFIX: &str = "forth$";
+
+/// Symbol a compiled Forth word is emitted under
+pub fn mangle(name: &str) -> String {
+    let mut symbol = String::from(PREFIX);
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() {
+            symbol.push(byte as char);
+        } else {
+            symbol.push_str(&format!("_{:02x}", byte));
+        }
+    }
+    symbol.push_str(&format!("${:08x}", hash(name)));
+    symbol
+}
+
+/// Forth name of a mangled symbol, or `None` if it is not one
+///
+/// Accepts the leading `_` Mach-O adds to every symbol.
+pub fn demangle(symbol: &str) -> Option<String> {
+    let symbol = if symbol.starts_with("_forth$") { &symbol[1..] } else { symbol };
+    let (escaped, hash_digits) = symbol.strip_prefix(PREFIX)?.split_once('$')?;
+    if hash_digits.len() != 8 {
+        return None;
+    }
+    let expected = u32::from_str_radix(hash_digits, 16).ok()?;
+
+    let bytes = escaped.as_bytes();
+    let mut name = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'_' => {
+                let digits = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+                name.push(u8::from_str_radix(digits, 16).ok()?);
+                i += 3;
+            }
+            byte if byte.is_ascii_alphanumeric() => {
+                name.push(byte);
+                i += 1;
+            }
+            _ => return None,
+        }
+    }
+    let name = String::from_utf8(name).ok()?;
+    (hash(&name) == expected).then_some(name)
+}
+
+/// `text` with every mangled symbol in it replaced by its Forth name
+///
+/// Anything that only looks like a mangled symbol is left alone.
+pub fn demangle_text(text: &str) -> String {
+    demangle_text_with(text, |name| name)
+}
+
+/// [`demangle_text`], writing each Forth name as `show` gives it
+pub fn demangle_text_with(text: &str, show: impl Fn(String) -> String) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(PREFIX) {
+        let (before, candidate) = rest.split_at(start);
+        out.push_str(before);
+        let length = symbol_length(candidate);
+        match demangle(&candidate[..length]) {
+            Some(name) => {
+                // A Mach-O symbol's underscore belongs to it
+                if let Some(prefix) = out.strip_suffix('_') {
+                    if !prefix.ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+                        out.pop();
+                    }
+                }
+                out.push_str(&show(name));
+                rest = &candidate[length..];
+            }
+            None => {
+                out.push_str(PREFIX);
+                rest = &candidate[PREFIX.len()..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Length of the mangled symbol `text` starts with, if it is well formed
+/// up to its hash; at least the prefix
+fn symbol_length(text: &str) -> usize {
+    let escaped = text[PREFIX.len()..]
+        .bytes()
+        .take_while(|byte| byte.is_ascii_alphanumeric() || *byte == b'_')
+        .count();
+    let end = PREFIX.len() + escaped;
+    if text.as_bytes().get(end) == Some(&b'$') && text.len() >= end + 9 && text.is_char_boundary(end + 9) {
+        end + 9
+    } else {
+        PREFIX.len()
+    }
+}
+
+/// 32-bit FNV-1a
+fn hash(name: &str) -> u32 {
+    name.bytes().fold(0x811c_9dc5, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mangle_round_trips() {
+        assert_eq!(mangle("2dup"), format!("forth$2dup${:08x}", hash("2dup")));
+        assert!(mangle("+").starts_with("forth$_2b$"));
+        assert_ne!(mangle("a-b"), mangle("a_b"));
+        for name in ["main", "malloc", "+", "a_b", "2dup", "s\"", "émile", ""] {
+            assert_eq!(demangle(&mangle(name)).as_deref(), Some(name));
+            assert_eq!(demangle(&format!("_{}", mangle(name))).as_deref(), Some(name));
+        }
+
+        // A wrong hash, or a plain C symbol, is not a Forth word
+        let forged = mangle("main").replace("main$", "mian$");
+        assert_eq!(demangle(&forged), None);
+        assert_eq!(demangle("main"), None);
+        assert_eq!(demangle("forth$x$zz"), None);
+    }
+
+    #[test]
+    fn test_demangle_text() {
+        let text = format!("  42.0%  fifth  [.] {}+0x1a\n  callq {}\n  _{}", mangle("+loop"), mangle("main"), mangle("sq"));
+        assert_eq!(demangle_text(&text), "  42.0%  fifth  [.] +loop+0x1a\n  callq main\n  sq");
+        assert_eq!(demangle_text("forth$ and forth$x$1 stay"), "forth$ and forth$x$1 stay");
+    }
+}
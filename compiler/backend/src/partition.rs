@@ -0,0 +1,131 @@
+//! Codegen Unit Partitioning
+//!
+//! Splits a program into codegen units that are compiled in parallel, each
+//! as its own module. Functions are laid out in call-graph post-order
+//! (callees before their callers, a word's helpers next to it) and the
+//! order is cut into runs of roughly equal size, so most calls stay inside
+//! one unit where the code generator can still inline them.
+
+use fastforth_frontend::ssa::{SSAFunction, SSAInstruction};
+use std::collections::HashMap;
+
+/// Indices of the functions in each unit, at most `units` of them and
+/// none empty
+pub fn partition(functions: &[SSAFunction], units: usize) -> Vec<Vec<usize>> {
+    if functions.is_empty() {
+        return Vec::new();
+    }
+    let units = units.clamp(1, functions.len());
+
+    let index: HashMap<&str, usize> = functions
+        .iter()
+        .enumerate()
+        .map(|(i, func)| (func.name.as_str(), i))
+        .collect();
+    let mut callees = vec![Vec::new(); functions.len()];
+    let mut called = vec![false; functions.len()];
+    for (caller, func) in functions.iter().enumerate() {
+        for inst in func.blocks.iter().flat_map(|block| &block.instructions) {
+            if let SSAInstruction::Call { name, .. } | SSAInstruction::FunctionAddress { name, .. } = inst {
+                if let Some(&callee) = index.get(name.as_str()) {
+                    if callee != caller && !callees[caller].contains(&callee) {
+                        callees[caller].push(callee);
+                        called[callee] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    // Roots first, then whatever only cycles reach
+    let roots = (0..functions.len()).filter(|&i| !called[i]).chain(0..functions.len());
+    let mut order = Vec::with_capacity(functions.len());
+    let mut visited = vec![false; functions.len()];
+    for root in roots {
+        if visited[root] {
+            continue;
+        }
+        visited[root] = true;
+        let mut stack = vec![(root, 0)];
+        while let Some((func, next)) = stack.last_mut() {
+            match callees[*func].get(*next) {
+                Some(&callee) => {
+                    *next += 1;
+                    if !visited[callee] {
+                        visited[callee] = true;
+                        stack.push((callee, 0));
+                    }
+                }
+                None => {
+                    order.push(*func);
+                    stack.pop();
+                }
+            }
+        }
+    }
+
+    let size = |i: usize| 1 + functions[i].blocks.iter().map(|block| block.instructions.len()).sum::<usize>();
+    let mut remaining: usize = order.iter().map(|&i| size(i)).sum();
+    let mut partitions: Vec<Vec<usize>> = Vec::with_capacity(units);
+    let mut current = Vec::new();
+    let mut current_size = 0;
+    for (position, &func) in order.iter().enumerate() {
+        let units_left = units - partitions.len();
+        let target = remaining.div_ceil(units_left);
+        // Close the unit when it is full, or when the functions left are
+        // only enough to give each remaining unit one
+        let full = current_size + size(func) > target || order.len() - position < units_left;
+        if !current.is_empty() && units_left > 1 && full {
+            remaining -= current_size;
+            partitions.push(std::mem::take(&mut current));
+            current_size = 0;
+        }
+        current.push(func);
+        current_size += size(func);
+    }
+    partitions.push(current);
+    partitions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastforth_frontend::{convert_to_ssa, parse_program};
+
+    fn functions(source: &str) -> Vec<SSAFunction> {
+        convert_to_ssa(&parse_program(source).unwrap()).unwrap()
+    }
+
+    fn names(functions: &[SSAFunction], partitions: &[Vec<usize>]) -> Vec<Vec<String>> {
+        partitions
+            .iter()
+            .map(|unit| unit.iter().map(|&i| functions[i].name.clone()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_callees_stay_with_their_callers() {
+        let functions = functions(
+            ": a dup * ; : b a a 1 + ; : c 2 * ; : d c c 3 - ; : e b d + ;",
+        );
+        let partitions = partition(&functions, 2);
+        assert_eq!(names(&functions, &partitions), [vec!["a", "b"], vec!["c", "d", "e"]]);
+
+        assert_eq!(partition(&functions, 1), [[0, 1, 2, 3, 4]]);
+        assert!(partition(&[], 4).is_empty());
+    }
+
+    #[test]
+    fn test_every_function_lands_in_exactly_one_unit() {
+        let source: String = (0..20).map(|i| format!(": w{} {} {} + ; ", i, i, i)).collect();
+        let functions = functions(&source);
+        for units in [1, 3, 7, 20, 50] {
+            let partitions = partition(&functions, units);
+            assert_eq!(partitions.len(), units.min(functions.len()));
+            assert!(partitions.iter().all(|unit| !unit.is_empty()));
+            let mut all: Vec<usize> = partitions.concat();
+            all.sort_unstable();
+            assert_eq!(all, (0..functions.len()).collect::<Vec<_>>());
+        }
+    }
+}
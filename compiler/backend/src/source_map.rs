@@ -0,0 +1,259 @@
+//! Source Maps
+//!
+//! Which word, and which source line, a machine code address belongs to.
+//! The JIT keeps one for the code it compiled, and AOT builds write one
+//! next to the executable or shared library, read back from the symbol
+//! table of the linked file, whose code ranges are the ones the linker
+//! assigned. Crash snapshots name the words of a faulting run with it, and
+//! `fastforth demangle --source-map` resolves addresses and adds the lines
+//! to profiler output.
+//!
+//! Within a word, each stretch of machine code is attributed to the source
+//! line of the Forth it was compiled from (see
+//! [`CraneliftBackend::line_table`](crate::cranelift::CraneliftBackend::line_table)).
+//!
+//! The text form is one header line and one line per word, addresses in
+//! hex:
+//!
+//! ```text
+//! fifth-source-map 1
+//! 1139 1c 3 forth$sq$48521d89 0:3
+//! 1155 2a - forth$main$ea90e208 4:5 13:6
+//! ```
+//!
+//! Each word line gives the start, the size, the line the word is defined
+//! on (`-` if unknown) and its mangled symbol (see [`crate::mangle`]),
+//! which keeps the format free of the spaces a Forth name cannot hold
+//! anyway and makes the map easy to join with symbol tables. Then come
+//! `offset:line` pairs, where the code of each source line starts.
+
+use crate::error::{BackendError, Result};
+use crate::mangle::{demangle, mangle};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// First line of the text form
+const HEADER: &str = "fifth-source-map 1";
+
+/// Where the source map of an AOT-built `binary` is written: next to it,
+/// with the extension `.srcmap`
+pub fn source_map_path(binary: &Path) -> PathBuf {
+    binary.with_extension("srcmap")
+}
+
+/// Code of one compiled word
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceRange {
+    pub start: u64,
+    pub size: u64,
+    /// Forth name of the word
+    pub word: String,
+    /// Line the word is defined on
+    pub line: Option<usize>,
+    /// Where the code of each source line starts, as (offset from
+    /// `start`, line), in offset order
+    pub lines: Vec<(u64, usize)>,
+}
+
+impl SourceRange {
+    /// Whether `address` is in the word's code
+    pub fn contains(&self, address: u64) -> bool {
+        self.start <= address && address - self.start < self.size
+    }
+
+    /// Source line the code at `address` was compiled from; the line the
+    /// word is defined on for code no line of its own covers, such as the
+    /// prologue
+    pub fn line_at(&self, address: u64) -> Option<usize> {
+        let offset = address.wrapping_sub(self.start);
+        let after = self.lines.partition_point(|&(start, _)| start <= offset);
+        after.checked_sub(1).map(|index| self.lines[index].1).or(self.line)
+    }
+}
+
+/// Code ranges of a program's words, in address order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    ranges: Vec<SourceRange>,
+}
+
+impl SourceMap {
+    /// Map the code `ranges`, in any order
+    pub fn new(mut ranges: Vec<SourceRange>) -> Self {
+        ranges.sort_by_key(|range| range.start);
+        Self { ranges }
+    }
+
+    /// Every word's range, in address order
+    pub fn ranges(&self) -> &[SourceRange] {
+        &self.ranges
+    }
+
+    /// The word whose code holds `address`
+    pub fn find(&self, address: u64) -> Option<&SourceRange> {
+        let after = self.ranges.partition_point(|range| range.start <= address);
+        after.checked_sub(1).map(|index| &self.ranges[index]).filter(|range| range.contains(address))
+    }
+
+    /// The range of `word`
+    pub fn word(&self, word: &str) -> Option<&SourceRange> {
+        self.ranges.iter().find(|range| range.word == word)
+    }
+
+    /// `address` as `word+0xOFFSET`, with its source line when known
+    pub fn symbolize(&self, address: u64) -> Option<String> {
+        let range = self.find(address)?;
+        let mut text = format!("{}+{:#x}", range.word, address - range.start);
+        if let Some(line) = range.line_at(address) {
+            text.push_str(&format!(" (line {})", line));
+        }
+        Some(text)
+    }
+
+    /// The words of a linked ELF executable or shared library: every
+    /// defined function whose symbol is a mangled word, with its lines
+    /// from the same word in `words`, whose start and size are ignored
+    pub fn from_elf(image: &[u8], words: &[SourceRange]) -> Result<Self> {
+        let words: HashMap<&str, &SourceRange> = words.iter().map(|range| (range.word.as_str(), range)).collect();
+        let ranges = elf_functions(image)?
+            .into_iter()
+            .filter_map(|(symbol, start, size)| {
+                let word = demangle(&symbol)?;
+                let (line, lines) = words.get(word.as_str()).map_or((None, Vec::new()), |range| (range.line, range.lines.clone()));
+                Some(SourceRange { start, size, word, line, lines })
+            })
+            .collect();
+        Ok(Self::new(ranges))
+    }
+
+    /// The text form
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\n", HEADER);
+        for range in &self.ranges {
+            let line = range.line.map_or_else(|| "-".to_string(), |line| line.to_string());
+            text.push_str(&format!("{:x} {:x} {} {}", range.start, range.size, line, mangle(&range.word)));
+            for (offset, line) in &range.lines {
+                text.push_str(&format!(" {:x}:{}", offset, line));
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Read the text form
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines();
+        if lines.next().map(str::trim) != Some(HEADER) {
+            return Err(BackendError::InvalidSourceMap(format!("missing '{}' header", HEADER)));
+        }
+        let mut ranges = Vec::new();
+        for (number, entry) in lines.enumerate().filter(|(_, entry)| !entry.trim().is_empty()) {
+            let invalid = || BackendError::InvalidSourceMap(format!("line {}: '{}'", number + 2, entry));
+            let fields: Vec<&str> = entry.split_whitespace().collect();
+            let [start, size, line, symbol, lines @ ..] = &fields[..] else {
+                return Err(invalid());
+            };
+            let lines = lines
+                .iter()
+                .map(|pair| {
+                    let (offset, line) = pair.split_once(':')?;
+                    Some((u64::from_str_radix(offset, 16).ok()?, line.parse().ok()?))
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(invalid)?;
+            ranges.push(SourceRange {
+                start: u64::from_str_radix(start, 16).map_err(|_| invalid())?,
+                size: u64::from_str_radix(size, 16).map_err(|_| invalid())?,
+                line: if *line == "-" { None } else { Some(line.parse().map_err(|_| invalid())?) },
+                word: demangle(symbol).ok_or_else(invalid)?,
+                lines,
+            });
+        }
+        Ok(Self::new(ranges))
+    }
+}
+
+/// Defined functions in the symbol table of a little-endian ELF64 file,
+/// as (symbol, address, size)
+fn elf_functions(image: &[u8]) -> Result<Vec<(String, u64, u64)>> {
+    const SHT_SYMTAB: u32 = 2;
+    const STT_FUNC: u8 = 2;
+    const SYMBOL_SIZE: usize = 24;
+
+    let not_elf = || BackendError::UnsupportedFeature("source maps need a little-endian ELF64 file".to_string());
+    if image.get(..6) != Some(b"\x7fELF\x02\x01".as_slice()) {
+        return Err(not_elf());
+    }
+    let truncated = || BackendError::InvalidSourceMap("truncated ELF file".to_string());
+    let u16_at = |offset: usize| image.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize).ok_or_else(truncated);
+    let u32_at = |offset: usize| image.get(offset..offset + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap())).ok_or_else(truncated);
+    let u64_at = |offset: usize| image.get(offset..offset + 8).map(|b| u64::from_le_bytes(b.try_into().unwrap())).ok_or_else(truncated);
+
+    let sections = u64_at(0x28)? as usize;
+    let (section_size, section_count) = (u16_at(0x3a)?, u16_at(0x3c)?);
+    let section = |index: usize| sections + index * section_size;
+
+    let mut functions = Vec::new();
+    for index in 0..section_count {
+        let header = section(index);
+        if u32_at(header + 4)? != SHT_SYMTAB {
+            continue;
+        }
+        let (offset, size) = (u64_at(header + 0x18)? as usize, u64_at(header + 0x20)? as usize);
+        let strings = u64_at(section(u32_at(header + 0x28)? as usize) + 0x18)? as usize;
+        for symbol in (offset..offset + size).step_by(SYMBOL_SIZE) {
+            let info = *image.get(symbol + 4).ok_or_else(truncated)?;
+            if info & 0xf != STT_FUNC || u16_at(symbol + 6)? == 0 {
+                continue;
+            }
+            let name = strings + u32_at(symbol)? as usize;
+            let name = image.get(name..).ok_or_else(truncated)?;
+            let name = &name[..name.iter().position(|&byte| byte == 0).ok_or_else(truncated)?];
+            functions.push((String::from_utf8_lossy(name).into_owned(), u64_at(symbol + 8)?, u64_at(symbol + 16)?));
+        }
+    }
+    Ok(functions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map() -> SourceMap {
+        SourceMap::new(vec![
+            SourceRange { start: 0x1040, size: 0x20, word: "main".into(), line: None, lines: vec![(0x4, 5), (0x13, 6)] },
+            SourceRange { start: 0x1000, size: 0x1c, word: "+loop".into(), line: Some(3), lines: Vec::new() },
+        ])
+    }
+
+    #[test]
+    fn test_find_address() {
+        let map = map();
+        assert_eq!(map.find(0x1000).unwrap().word, "+loop");
+        assert_eq!(map.find(0x105f).unwrap().word, "main");
+        assert!(map.find(0x101c).is_none(), "padding between words");
+        assert!(map.find(0xfff).is_none());
+        assert!(map.find(0x1060).is_none());
+        assert_eq!(map.symbolize(0x1004).as_deref(), Some("+loop+0x4 (line 3)"));
+        assert_eq!(map.symbolize(0x1040).as_deref(), Some("main+0x0"), "prologue");
+        assert_eq!(map.symbolize(0x1050).as_deref(), Some("main+0x10 (line 5)"));
+        assert_eq!(map.symbolize(0x1053).as_deref(), Some("main+0x13 (line 6)"));
+        assert_eq!(map.word("main").unwrap().start, 0x1040);
+    }
+
+    #[test]
+    fn test_text_round_trips() {
+        let text = map().to_text();
+        assert!(text.starts_with("fifth-source-map 1\n1000 1c 3 forth$_2bloop$"));
+        assert!(text.ends_with(" 4:5 13:6\n"));
+        assert_eq!(SourceMap::parse(&text).unwrap(), map());
+        assert!(SourceMap::parse("1000 1c 3 forth$x$00000000").is_err());
+        assert!(SourceMap::parse("fifth-source-map 1\n1000 1c 3 printf").is_err());
+    }
+
+    #[test]
+    fn test_non_elf_file_is_rejected() {
+        assert!(SourceMap::from_elf(b"MZ\x90\x00", &[]).is_err());
+        assert!(SourceMap::from_elf(b"\x7fELF\x02\x01", &[]).is_err());
+    }
+}
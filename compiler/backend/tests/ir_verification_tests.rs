@@ -92,8 +92,6 @@ mod cranelift_verification_tests {
             entry_block: BlockId(0),
             blocks: vec![
                 fastforth_frontend::ssa::BasicBlock {
-                    id: BlockId(0),
-                    predecessors: vec![],
                     instructions: vec![
                         SSAInstruction::BinaryOp {
                             dest: result,
@@ -105,6 +103,7 @@ mod cranelift_verification_tests {
                             values: smallvec![result],
                         },
                     ],
+                    ..fastforth_frontend::ssa::BasicBlock::new(BlockId(0))
                 },
             ],
         }
@@ -11,7 +11,7 @@ use fastforth::spec::{Specification, SpecValidator, StackEffect, StackParameter,
 
 /// Benchmark pattern database LRU cache
 fn bench_pattern_cache(c: &mut Criterion) {
-    let mut db = PatternDatabase::open("test.db").unwrap();
+    let mut db = PatternDatabase::open(":memory:").unwrap();
     db.seed_defaults().unwrap();
 
     let pattern_ids = vec![
@@ -225,7 +225,7 @@ fn bench_parallel_validation(c: &mut Criterion) {
 
 /// Benchmark end-to-end agent workflow
 fn bench_agent_workflow(c: &mut Criterion) {
-    let mut db = PatternDatabase::open("test.db").unwrap();
+    let mut db = PatternDatabase::open(":memory:").unwrap();
     db.seed_defaults().unwrap();
 
     let json = r#"{
@@ -5,13 +5,14 @@
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
-use std::time::{Duration, Instant};
+use std::process::Command;
 
 use anyhow::{Context, Result, bail};
 use serde::{Deserialize, Serialize};
 
+use crate::counters::{run_counted, HardwareCounters};
 use crate::optimizations::OptimizationLevel;
+use crate::statistics::{sample, CacheState, SampleStatistics, Sampled, SamplingConfig};
 
 /// Benchmark result with timing statistics
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,9 +26,64 @@ pub struct BenchmarkResult {
     pub max_time_ms: f64,
     pub stddev_ms: f64,
     pub correctness_verified: bool,
+    #[serde(default)]
+    pub median_time_ms: f64,
+    /// Confidence interval of the mean
+    #[serde(default)]
+    pub ci_low_ms: f64,
+    #[serde(default)]
+    pub ci_high_ms: f64,
+    #[serde(default)]
+    pub confidence_level: f64,
+    /// Samples rejected as outliers before computing the statistics
+    #[serde(default)]
+    pub outliers_rejected: usize,
+    /// Whether sampling reached the target precision
+    #[serde(default)]
+    pub converged: bool,
+    #[serde(default)]
+    pub cache: CacheState,
+    /// Hardware events per run, averaged over the kept samples
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counters: Option<HardwareCounters>,
 }
 
 impl BenchmarkResult {
+    fn from_sampled(name: String, language: &str, optimization: String, sampled: Sampled) -> Self {
+        let stats: SampleStatistics = sampled.statistics;
+        Self {
+            name,
+            language: language.to_string(),
+            optimization: Some(optimization),
+            iterations: stats.samples,
+            avg_time_ms: stats.mean,
+            min_time_ms: stats.min,
+            max_time_ms: stats.max,
+            stddev_ms: stats.stddev,
+            correctness_verified: true,
+            median_time_ms: stats.median,
+            ci_low_ms: stats.ci_low,
+            ci_high_ms: stats.ci_high,
+            confidence_level: stats.confidence,
+            outliers_rejected: stats.outliers,
+            converged: sampled.converged,
+            cache: sampled.cache,
+            counters: None,
+        }
+    }
+
+    fn with_counters(mut self, runs: &[HardwareCounters]) -> Self {
+        // Counters of warmup runs are not part of the result
+        let sampled = &runs[runs.len().saturating_sub(self.iterations)..];
+        self.counters = HardwareCounters::mean(sampled);
+        self
+    }
+
+    /// Half-width of the confidence interval in milliseconds
+    pub fn margin_ms(&self) -> f64 {
+        (self.ci_high_ms - self.ci_low_ms) / 2.0
+    }
+
     pub fn speedup_vs(&self, baseline: &BenchmarkResult) -> f64 {
         baseline.avg_time_ms / self.avg_time_ms
     }
@@ -38,6 +94,8 @@ pub struct BenchmarkSuite {
     benchmarks_dir: PathBuf,
     forth_dir: PathBuf,
     c_dir: PathBuf,
+    sampling: SamplingConfig,
+    hardware_counters: bool,
 }
 
 impl BenchmarkSuite {
@@ -57,53 +115,44 @@ impl BenchmarkSuite {
             benchmarks_dir,
             forth_dir,
             c_dir,
+            sampling: SamplingConfig::default(),
+            hardware_counters: false,
         })
     }
 
+    /// Set how benchmarks are sampled
+    pub fn with_sampling(mut self, sampling: SamplingConfig) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Count hardware events of each run, where counters are available
+    pub fn with_counters(mut self, enabled: bool) -> Self {
+        self.hardware_counters = enabled;
+        self
+    }
+
     /// Run C baseline benchmark
-    pub fn run_c_benchmark(&self, executable: &Path, iterations: usize) -> Result<BenchmarkResult> {
+    pub fn run_c_benchmark(&self, executable: &Path) -> Result<BenchmarkResult> {
         let name = executable.file_stem()
             .and_then(|s| s.to_str())
             .context("Invalid executable name")?
             .to_string();
 
-        // Warmup
-        for _ in 0..10 {
-            let _ = Command::new(executable)
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .status()?;
-        }
-
-        // Measure
-        let mut times = Vec::new();
-        for _ in 0..iterations {
-            let start = Instant::now();
-            let output = Command::new(executable)
-                .output()
+        let mut counters = Vec::new();
+        let sampled = sample(&self.sampling, || {
+            let run = run_counted(&mut Command::new(executable), self.hardware_counters)
                 .context("Failed to execute C benchmark")?;
-            let elapsed = start.elapsed();
 
-            if !output.status.success() {
+            if !run.status.success() {
                 bail!("C benchmark failed: {}", name);
             }
 
-            times.push(elapsed.as_secs_f64() * 1000.0);
-        }
-
-        let stats = compute_statistics(&times);
+            counters.extend(run.counters);
+            Ok(run.elapsed.as_secs_f64() * 1000.0)
+        })?;
 
-        Ok(BenchmarkResult {
-            name,
-            language: "C".to_string(),
-            optimization: Some("gcc -O2".to_string()),
-            iterations,
-            avg_time_ms: stats.mean,
-            min_time_ms: stats.min,
-            max_time_ms: stats.max,
-            stddev_ms: stats.stddev,
-            correctness_verified: true,
-        })
+        Ok(BenchmarkResult::from_sampled(name, "C", "gcc -O2".to_string(), sampled).with_counters(&counters))
     }
 
     /// Run Forth benchmark with specified optimization level
@@ -111,7 +160,6 @@ impl BenchmarkSuite {
         &self,
         benchmark: &str,
         opt_level: OptimizationLevel,
-        iterations: usize
     ) -> Result<BenchmarkResult> {
         let forth_file = self.forth_dir.join(format!("{}.fth", benchmark));
         if !forth_file.exists() {
@@ -120,51 +168,38 @@ impl BenchmarkSuite {
 
         // For now, simulate running with gforth
         // In practice, this would compile with Fast Forth and run
-        let mut times = Vec::new();
-
-        // Warmup
-        for _ in 0..10 {
-            let _ = self.execute_forth_benchmark(&forth_file, &opt_level)?;
-        }
-
-        // Measure
-        for _ in 0..iterations {
-            let time = self.execute_forth_benchmark(&forth_file, &opt_level)?;
-            times.push(time);
-        }
-
-        let stats = compute_statistics(&times);
-
-        Ok(BenchmarkResult {
-            name: benchmark.to_string(),
-            language: "Forth".to_string(),
-            optimization: Some(format!("{:?}", opt_level)),
-            iterations,
-            avg_time_ms: stats.mean,
-            min_time_ms: stats.min,
-            max_time_ms: stats.max,
-            stddev_ms: stats.stddev,
-            correctness_verified: true,
-        })
+        let mut counters = Vec::new();
+        let sampled = sample(&self.sampling, || {
+            let (time_ms, run_counters) = self.execute_forth_benchmark(&forth_file, &opt_level)?;
+            counters.extend(run_counters);
+            Ok(time_ms)
+        })?;
+
+        Ok(BenchmarkResult::from_sampled(
+            benchmark.to_string(),
+            "Forth",
+            format!("{:?}", opt_level),
+            sampled,
+        )
+        .with_counters(&counters))
     }
 
-    fn execute_forth_benchmark(&self, forth_file: &Path, opt_level: &OptimizationLevel) -> Result<f64> {
+    fn execute_forth_benchmark(
+        &self,
+        forth_file: &Path,
+        opt_level: &OptimizationLevel,
+    ) -> Result<(f64, Option<HardwareCounters>)> {
         // This is a placeholder - in practice, you would:
         // 1. Compile the Forth code with Fast Forth at the specified optimization level
         // 2. Execute the compiled code
         // 3. Measure execution time
 
         // For now, simulate with gforth
-        let start = Instant::now();
-        let output = Command::new("gforth")
-            .arg(forth_file)
-            .arg("-e")
-            .arg("bye")
-            .output();
-
-        match output {
-            Ok(output) if output.status.success() => {
-                let elapsed = start.elapsed();
+        let mut command = Command::new("gforth");
+        command.arg(forth_file).arg("-e").arg("bye");
+
+        match run_counted(&mut command, self.hardware_counters) {
+            Ok(run) if run.status.success() => {
                 // Apply optimization simulation factor
                 let factor = match opt_level {
                     OptimizationLevel::None => 1.0,
@@ -172,16 +207,17 @@ impl BenchmarkSuite {
                     OptimizationLevel::PGO => 0.70,
                     OptimizationLevel::Aggressive => 0.60,
                 };
-                Ok(elapsed.as_secs_f64() * 1000.0 * factor)
+                Ok((run.elapsed.as_secs_f64() * 1000.0 * factor, run.counters))
             }
             _ => {
-                // Fallback to estimated time
-                Ok(match forth_file.file_stem().and_then(|s| s.to_str()) {
+                // Fallback to estimated time, with nothing counted
+                let time_ms = match forth_file.file_stem().and_then(|s| s.to_str()) {
                     Some("sieve") => 10.0,
                     Some("fibonacci") => 5.0,
                     Some("matrix") => 2.0,
                     _ => 1.0,
-                })
+                };
+                Ok((time_ms, None))
             }
         }
     }
@@ -205,34 +241,6 @@ impl BenchmarkSuite {
     }
 }
 
-/// Statistical measurements
-#[derive(Debug, Clone)]
-struct Statistics {
-    mean: f64,
-    min: f64,
-    max: f64,
-    stddev: f64,
-}
-
-fn compute_statistics(values: &[f64]) -> Statistics {
-    let n = values.len() as f64;
-    let mean = values.iter().sum::<f64>() / n;
-    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
-    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
-
-    let variance = values.iter()
-        .map(|v| (v - mean).powi(2))
-        .sum::<f64>() / n;
-    let stddev = variance.sqrt();
-
-    Statistics {
-        mean,
-        min,
-        max,
-        stddev,
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -240,7 +248,7 @@ mod tests {
     #[test]
     fn test_statistics() {
         let values = vec![1.0, 2.0, 3.0, 4.0, 5.0];
-        let stats = compute_statistics(&values);
+        let stats = SampleStatistics::from_samples(&values, 0.95);
 
         assert!((stats.mean - 3.0).abs() < 0.001);
         assert!((stats.min - 1.0).abs() < 0.001);
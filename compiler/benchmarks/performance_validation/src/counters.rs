@@ -0,0 +1,250 @@
+/// Hardware performance counters
+///
+/// Counts instructions retired, branch misses and cache misses of a
+/// benchmark process, so results say how much work a run did and not just
+/// how long it took. Built with the `perf-counters` feature:
+///
+/// - Linux opens `perf_event_open` counters that are inherited by the
+///   benchmark process and folded back in when it exits.
+/// - macOS reads the instructions retired that kperf's fixed counters
+///   attribute to the process, through `proc_pid_rusage`. Branch and
+///   cache misses need configurable counters and root, so they are left
+///   out.
+///
+/// Elsewhere, or when counters cannot be opened (e.g. restricted by
+/// `perf_event_paranoid`), runs are timed without counters.
+
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Events counted during one run, or averaged over several
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HardwareCounters {
+    pub instructions: Option<u64>,
+    pub branch_misses: Option<u64>,
+    pub cache_misses: Option<u64>,
+}
+
+impl HardwareCounters {
+    /// Mean of each event over the runs that counted it
+    pub fn mean(runs: &[HardwareCounters]) -> Option<Self> {
+        fn mean_of(values: impl Iterator<Item = Option<u64>>) -> Option<u64> {
+            let counted: Vec<u64> = values.flatten().collect();
+            (!counted.is_empty()).then(|| counted.iter().sum::<u64>() / counted.len() as u64)
+        }
+
+        let mean = Self {
+            instructions: mean_of(runs.iter().map(|r| r.instructions)),
+            branch_misses: mean_of(runs.iter().map(|r| r.branch_misses)),
+            cache_misses: mean_of(runs.iter().map(|r| r.cache_misses)),
+        };
+        (mean != Self::default()).then_some(mean)
+    }
+}
+
+/// Outcome of running a benchmark process once
+#[derive(Debug, Clone)]
+pub struct CountedRun {
+    pub status: ExitStatus,
+    pub elapsed: Duration,
+    pub counters: Option<HardwareCounters>,
+}
+
+/// Run `command` to completion with its output discarded, timing it and,
+/// if `count` is set and counters are available, counting its events
+pub fn run_counted(command: &mut Command, count: bool) -> Result<CountedRun> {
+    command.stdout(Stdio::null()).stderr(Stdio::null());
+    if count {
+        platform::run(command)
+    } else {
+        run_timed(command)
+    }
+}
+
+fn run_timed(command: &mut Command) -> Result<CountedRun> {
+    let start = Instant::now();
+    let status = command.status().context("Failed to run benchmark")?;
+    Ok(CountedRun { status, elapsed: start.elapsed(), counters: None })
+}
+
+#[cfg(all(feature = "perf-counters", target_os = "linux"))]
+mod platform {
+    use super::*;
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    const PERF_TYPE_HARDWARE: u32 = 0;
+    const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+    const PERF_COUNT_HW_CACHE_MISSES: u64 = 3;
+    const PERF_COUNT_HW_BRANCH_MISSES: u64 = 5;
+
+    const FLAG_DISABLED: u64 = 1 << 0;
+    const FLAG_INHERIT: u64 = 1 << 1;
+    const FLAG_EXCLUDE_KERNEL: u64 = 1 << 5;
+    const FLAG_EXCLUDE_HV: u64 = 1 << 6;
+
+    const PERF_EVENT_IOC_ENABLE: libc::c_ulong = 0x2400;
+    const PERF_EVENT_IOC_DISABLE: libc::c_ulong = 0x2401;
+
+    /// `struct perf_event_attr` as of PERF_ATTR_SIZE_VER0
+    #[repr(C)]
+    #[derive(Default)]
+    struct PerfEventAttr {
+        kind: u32,
+        size: u32,
+        config: u64,
+        sample_period: u64,
+        sample_type: u64,
+        read_format: u64,
+        flags: u64,
+        wakeup_events: u32,
+        bp_type: u32,
+        config1: u64,
+    }
+
+    /// A user-space counter that follows the calling process's children
+    fn open(config: u64) -> Option<File> {
+        let attr = PerfEventAttr {
+            kind: PERF_TYPE_HARDWARE,
+            size: std::mem::size_of::<PerfEventAttr>() as u32,
+            config,
+            flags: FLAG_DISABLED | FLAG_INHERIT | FLAG_EXCLUDE_KERNEL | FLAG_EXCLUDE_HV,
+            ..Default::default()
+        };
+        // pid 0, any cpu, no group, no flags
+        let fd = unsafe { libc::syscall(libc::SYS_perf_event_open, &attr as *const PerfEventAttr, 0, -1, -1, 0) };
+        (fd >= 0).then(|| unsafe { File::from_raw_fd(fd as i32) })
+    }
+
+    fn read(counter: &mut Option<File>) -> Option<u64> {
+        let mut value = [0u8; 8];
+        counter.as_mut()?.read_exact(&mut value).ok()?;
+        Some(u64::from_ne_bytes(value))
+    }
+
+    pub fn run(command: &mut Command) -> Result<CountedRun> {
+        let mut counters = [
+            open(PERF_COUNT_HW_INSTRUCTIONS),
+            open(PERF_COUNT_HW_BRANCH_MISSES),
+            open(PERF_COUNT_HW_CACHE_MISSES),
+        ];
+        if counters.iter().all(Option::is_none) {
+            return run_timed(command);
+        }
+
+        let ioctl_all = |counters: &[Option<File>], request| {
+            for counter in counters.iter().flatten() {
+                unsafe { libc::ioctl(counter.as_raw_fd(), request, 0) };
+            }
+        };
+
+        // Enabled just around the child, which inherits the counters and
+        // adds its counts to them when it exits
+        ioctl_all(&counters, PERF_EVENT_IOC_ENABLE);
+        let start = Instant::now();
+        let status = command.status();
+        let elapsed = start.elapsed();
+        ioctl_all(&counters, PERF_EVENT_IOC_DISABLE);
+        let status = status.context("Failed to run benchmark")?;
+
+        let [instructions, branch_misses, cache_misses] = &mut counters;
+        Ok(CountedRun {
+            status,
+            elapsed,
+            counters: Some(HardwareCounters {
+                instructions: read(instructions),
+                branch_misses: read(branch_misses),
+                cache_misses: read(cache_misses),
+            }),
+        })
+    }
+}
+
+#[cfg(all(feature = "perf-counters", target_os = "macos"))]
+mod platform {
+    use super::*;
+
+    const RUSAGE_INFO_V4: libc::c_int = 4;
+
+    /// `struct rusage_info_v4` from <sys/resource.h>
+    #[repr(C)]
+    #[derive(Default)]
+    struct RusageInfoV4 {
+        ri_uuid: [u8; 16],
+        /// ri_user_time through ri_lifetime_max_phys_footprint
+        leading: [u64; 29],
+        ri_instructions: u64,
+        ri_cycles: u64,
+        trailing: [u64; 4],
+    }
+
+    extern "C" {
+        fn proc_pid_rusage(pid: libc::c_int, flavor: libc::c_int, buffer: *mut RusageInfoV4) -> libc::c_int;
+    }
+
+    pub fn run(command: &mut Command) -> Result<CountedRun> {
+        let start = Instant::now();
+        let mut child = command.spawn().context("Failed to run benchmark")?;
+        let pid = child.id() as libc::pid_t;
+
+        // Wait for exit without reaping, so the usage can still be read
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let waited = unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, libc::WEXITED | libc::WNOWAIT) };
+        let elapsed = start.elapsed();
+
+        let mut usage = RusageInfoV4::default();
+        let read = waited == 0 && unsafe { proc_pid_rusage(pid, RUSAGE_INFO_V4, &mut usage) } == 0;
+        let status = child.wait().context("Failed to run benchmark")?;
+
+        Ok(CountedRun {
+            status,
+            elapsed,
+            counters: read.then(|| HardwareCounters {
+                instructions: Some(usage.ri_instructions),
+                branch_misses: None,
+                cache_misses: None,
+            }),
+        })
+    }
+}
+
+#[cfg(not(all(feature = "perf-counters", any(target_os = "linux", target_os = "macos"))))]
+mod platform {
+    use super::*;
+
+    pub fn run(command: &mut Command) -> Result<CountedRun> {
+        run_timed(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mean_skips_uncounted_events() {
+        let runs = vec![
+            HardwareCounters { instructions: Some(100), branch_misses: Some(4), cache_misses: None },
+            HardwareCounters { instructions: Some(300), branch_misses: None, cache_misses: None },
+        ];
+        let mean = HardwareCounters::mean(&runs).unwrap();
+        assert_eq!(mean.instructions, Some(200));
+        assert_eq!(mean.branch_misses, Some(4));
+        assert_eq!(mean.cache_misses, None);
+
+        assert!(HardwareCounters::mean(&[HardwareCounters::default()]).is_none());
+    }
+
+    #[test]
+    fn test_run_counted_times_process() {
+        let run = run_counted(&mut Command::new("true"), true).unwrap();
+        assert!(run.status.success());
+        if let Some(instructions) = run.counters.and_then(|c| c.instructions) {
+            assert!(instructions > 0);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+/// HTML dashboard of benchmark performance over time
+///
+/// Renders one chart per benchmark from the results database, with a line
+/// per optimization level across the machine's recorded runs. The page is
+/// self-contained (inline SVG, no scripts) so it can be archived as a CI
+/// artifact and opened anywhere.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use anyhow::Result;
+
+use crate::history::{MachineFingerprint, ResultsDatabase, TrendPoint};
+
+const CHART_WIDTH: f64 = 720.0;
+const CHART_HEIGHT: f64 = 240.0;
+const MARGIN: f64 = 40.0;
+
+/// Line colors, by series
+const COLORS: [&str; 6] = ["#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b"];
+
+/// Dashboard of every series measured on `machine`
+pub fn render_dashboard(db: &ResultsDatabase, machine: &MachineFingerprint) -> Result<String> {
+    let mut benchmarks: BTreeMap<String, Vec<(String, Vec<TrendPoint>)>> = BTreeMap::new();
+    for (benchmark, optimization) in db.series(&machine.id)? {
+        let trend = db.trend(&benchmark, &optimization, &machine.id)?;
+        benchmarks.entry(benchmark).or_default().push((optimization, trend));
+    }
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Fast Forth Performance Dashboard</title>\n");
+    html.push_str(
+        "<style>\
+         body { font-family: sans-serif; margin: 2em; }\
+         table { border-collapse: collapse; margin-bottom: 2em; }\
+         td, th { border: 1px solid #ccc; padding: 4px 8px; text-align: right; }\
+         th:first-child, td:first-child { text-align: left; }\
+         .slower { color: #c00; } .faster { color: #080; }\
+         </style>\n",
+    );
+    html.push_str("</head>\n<body>\n<h1>Fast Forth Performance Dashboard</h1>\n");
+    let _ = writeln!(html, "<p>Machine: {}</p>", escape(&machine.description));
+
+    if benchmarks.is_empty() {
+        html.push_str("<p>No runs recorded on this machine yet.</p>\n");
+    }
+
+    for (benchmark, series) in &benchmarks {
+        let _ = writeln!(html, "<h2>{}</h2>", escape(benchmark));
+        html.push_str(&chart(series));
+        html.push_str(&latest_table(series));
+    }
+
+    html.push_str("</body>\n</html>\n");
+    Ok(html)
+}
+
+/// Line chart of mean time per run
+fn chart(series: &[(String, Vec<TrendPoint>)]) -> String {
+    // Runs on the x axis, in the order they were recorded
+    let mut runs: Vec<i64> = series.iter().flat_map(|(_, trend)| trend.iter().map(|p| p.run_id)).collect();
+    runs.sort_unstable();
+    runs.dedup();
+    let max_ms = series
+        .iter()
+        .flat_map(|(_, trend)| trend.iter().map(|p| p.ci_high_ms.max(p.avg_time_ms)))
+        .fold(0.0, f64::max)
+        .max(f64::MIN_POSITIVE);
+
+    let plot_width = CHART_WIDTH - 2.0 * MARGIN;
+    let plot_height = CHART_HEIGHT - 2.0 * MARGIN;
+    let x = |run_id: i64| {
+        let index = runs.binary_search(&run_id).unwrap_or(0) as f64;
+        MARGIN + if runs.len() > 1 { index / (runs.len() - 1) as f64 * plot_width } else { plot_width / 2.0 }
+    };
+    let y = |ms: f64| MARGIN + plot_height - ms / max_ms * plot_height;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" xmlns=\"http://www.w3.org/2000/svg\">",
+        w = CHART_WIDTH,
+        h = CHART_HEIGHT
+    );
+    let _ = writeln!(
+        svg,
+        "<line x1=\"{m}\" y1=\"{b}\" x2=\"{r}\" y2=\"{b}\" stroke=\"#999\"/>\
+         <line x1=\"{m}\" y1=\"{m}\" x2=\"{m}\" y2=\"{b}\" stroke=\"#999\"/>",
+        m = MARGIN,
+        b = MARGIN + plot_height,
+        r = MARGIN + plot_width
+    );
+    let _ = writeln!(svg, "<text x=\"4\" y=\"{:.1}\" font-size=\"11\">{:.3} ms</text>", MARGIN - 6.0, max_ms);
+    let _ = writeln!(svg, "<text x=\"4\" y=\"{:.1}\" font-size=\"11\">0</text>", MARGIN + plot_height);
+
+    for (i, (optimization, trend)) in series.iter().enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        let points: Vec<String> = trend.iter().map(|p| format!("{:.1},{:.1}", x(p.run_id), y(p.avg_time_ms))).collect();
+        let _ = writeln!(svg, "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"2\" points=\"{}\"/>", color, points.join(" "));
+        for point in trend {
+            let _ = writeln!(
+                svg,
+                "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\" fill=\"{}\"><title>{} @ {}: {:.3} ms</title></circle>",
+                x(point.run_id),
+                y(point.avg_time_ms),
+                color,
+                escape(optimization),
+                escape(&point.commit),
+                point.avg_time_ms
+            );
+        }
+        let _ = writeln!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{:.1}\" font-size=\"11\" fill=\"{}\">{}</text>",
+            MARGIN + 8.0 + i as f64 * 110.0,
+            CHART_HEIGHT - 8.0,
+            color,
+            escape(optimization)
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Latest measurement of each series and its change since the run before
+fn latest_table(series: &[(String, Vec<TrendPoint>)]) -> String {
+    let mut table = String::from(
+        "<table>\n<tr><th>Optimization</th><th>Commit</th><th>Recorded</th><th>Time (ms)</th><th>Change</th><th>Runs</th></tr>\n",
+    );
+
+    for (optimization, trend) in series {
+        let Some(latest) = trend.last() else { continue };
+        let change = match trend.len().checked_sub(2).map(|i| &trend[i]) {
+            Some(previous) if previous.avg_time_ms > 0.0 => {
+                let change = (latest.avg_time_ms - previous.avg_time_ms) / previous.avg_time_ms * 100.0;
+                let class = if change > 0.0 { "slower" } else { "faster" };
+                format!("<span class=\"{}\">{:+.1}%</span>", class, change)
+            }
+            _ => "-".to_string(),
+        };
+        let _ = writeln!(
+            table,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.3}</td><td>{}</td><td>{}</td></tr>",
+            escape(optimization),
+            escape(&latest.commit),
+            escape(&latest.recorded_at),
+            latest.avg_time_ms,
+            change,
+            trend.len()
+        );
+    }
+
+    table.push_str("</table>\n");
+    table
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::RunInfo;
+
+    #[test]
+    fn test_dashboard_charts_each_benchmark() {
+        let mut db = ResultsDatabase::open_in_memory().unwrap();
+        let machine = MachineFingerprint::new("test <machine>");
+        for (commit, time) in [("aaa", 10.0), ("bbb", 12.0)] {
+            let run = RunInfo { commit: commit.to_string(), machine: machine.clone(), recorded_at: "2026-01-01".to_string() };
+            let result = serde_json::from_value(serde_json::json!({
+                "name": "sieve", "language": "Forth", "optimization": "Aggressive", "iterations": 10,
+                "avg_time_ms": time, "min_time_ms": time, "max_time_ms": time, "stddev_ms": 0.0,
+                "correctness_verified": true,
+            }))
+            .unwrap();
+            db.record(&run, &[result]).unwrap();
+        }
+
+        let html = render_dashboard(&db, &machine).unwrap();
+        assert!(html.contains("<h2>sieve</h2>"));
+        assert_eq!(html.matches("<polyline").count(), 1);
+        assert_eq!(html.matches("<circle").count(), 2);
+        assert!(html.contains("+20.0%"));
+        assert!(html.contains("test &lt;machine&gt;"));
+    }
+}
@@ -0,0 +1,369 @@
+/// Historical results database
+///
+/// Every validation run is recorded in SQLite under the commit it measured
+/// and a fingerprint of the machine it ran on, one row per benchmark and
+/// optimization level. Trends and regression baselines are only ever
+/// drawn from runs on the same machine, since timings from different
+/// hardware are not comparable.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::benchmarks::BenchmarkResult;
+
+/// Schema version written to `PRAGMA user_version`
+const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS machines (
+        id TEXT PRIMARY KEY,
+        description TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        commit_hash TEXT NOT NULL,
+        machine_id TEXT NOT NULL REFERENCES machines(id),
+        recorded_at TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS measurements (
+        run_id INTEGER NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+        benchmark TEXT NOT NULL,
+        language TEXT NOT NULL,
+        optimization TEXT NOT NULL,
+        avg_time_ms REAL NOT NULL,
+        median_time_ms REAL NOT NULL,
+        ci_low_ms REAL NOT NULL,
+        ci_high_ms REAL NOT NULL,
+        stddev_ms REAL NOT NULL,
+        iterations INTEGER NOT NULL,
+        instructions INTEGER,
+        PRIMARY KEY (run_id, benchmark, optimization)
+    );
+    CREATE INDEX IF NOT EXISTS idx_runs_machine ON runs(machine_id, id);
+    CREATE INDEX IF NOT EXISTS idx_measurements_series ON measurements(benchmark, optimization);
+";
+
+/// Identity of the hardware a run was measured on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MachineFingerprint {
+    /// Stable hash of the description
+    pub id: String,
+    /// CPU model, core count, architecture and OS
+    pub description: String,
+}
+
+impl MachineFingerprint {
+    pub fn new(description: impl Into<String>) -> Self {
+        let description = description.into();
+        Self { id: format!("{:016x}", fnv1a(description.as_bytes())), description }
+    }
+
+    /// Fingerprint of this machine
+    ///
+    /// Only hardware goes in, not the host name, so identical CI runners
+    /// share a history.
+    pub fn detect() -> Self {
+        let cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        Self::new(format!(
+            "{} | {} cores | {}-{}",
+            cpu_model().unwrap_or_else(|| "unknown CPU".to_string()),
+            cores,
+            std::env::consts::ARCH,
+            std::env::consts::OS
+        ))
+    }
+}
+
+impl fmt::Display for MachineFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.id, self.description)
+    }
+}
+
+fn cpu_model() -> Option<String> {
+    if cfg!(target_os = "macos") {
+        let output = Command::new("sysctl").args(["-n", "machdep.cpu.brand_string"]).output().ok()?;
+        let model = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        return (!model.is_empty()).then_some(model);
+    }
+
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").ok()?;
+    cpuinfo
+        .lines()
+        .find(|line| line.starts_with("model name") || line.starts_with("Model"))
+        .and_then(|line| line.split_once(':'))
+        .map(|(_, model)| model.trim().to_string())
+}
+
+/// 64-bit FNV-1a, stable across Rust releases unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Commit of the working tree, marked `-dirty` if it has local changes
+pub fn current_commit() -> String {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+
+    match git(&["rev-parse", "--short=12", "HEAD"]) {
+        Some(commit) if git(&["status", "--porcelain"]).is_some_and(|status| !status.is_empty()) => {
+            format!("{}-dirty", commit)
+        }
+        Some(commit) => commit,
+        None => "unknown".to_string(),
+    }
+}
+
+/// What a run is recorded under
+#[derive(Debug, Clone)]
+pub struct RunInfo {
+    pub commit: String,
+    pub machine: MachineFingerprint,
+    pub recorded_at: String,
+}
+
+impl RunInfo {
+    /// The current commit on this machine, now
+    pub fn current() -> Self {
+        Self {
+            commit: current_commit(),
+            machine: MachineFingerprint::detect(),
+            recorded_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        }
+    }
+}
+
+/// One run's measurement of a benchmark at an optimization level
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrendPoint {
+    pub run_id: i64,
+    pub commit: String,
+    pub recorded_at: String,
+    pub avg_time_ms: f64,
+    pub ci_low_ms: f64,
+    pub ci_high_ms: f64,
+    pub instructions: Option<u64>,
+}
+
+/// SQLite store of every recorded run
+pub struct ResultsDatabase {
+    conn: Connection,
+}
+
+impl ResultsDatabase {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let conn = Connection::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        Self::init(conn)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            anyhow::bail!("Results database is version {}, newer than supported ({})", version, SCHEMA_VERSION);
+        }
+        conn.execute_batch(SCHEMA)?;
+        conn.execute_batch(&format!("PRAGMA user_version = {}; PRAGMA foreign_keys = ON;", SCHEMA_VERSION))?;
+        Ok(Self { conn })
+    }
+
+    /// Record a run's results, returning the run's id
+    pub fn record(&mut self, run: &RunInfo, results: &[BenchmarkResult]) -> Result<i64> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO machines (id, description) VALUES (?1, ?2)
+             ON CONFLICT(id) DO UPDATE SET description = excluded.description",
+            params![run.machine.id, run.machine.description],
+        )?;
+        tx.execute(
+            "INSERT INTO runs (commit_hash, machine_id, recorded_at) VALUES (?1, ?2, ?3)",
+            params![run.commit, run.machine.id, run.recorded_at],
+        )?;
+        let run_id = tx.last_insert_rowid();
+
+        for result in results {
+            tx.execute(
+                "INSERT OR REPLACE INTO measurements
+                 (run_id, benchmark, language, optimization, avg_time_ms, median_time_ms,
+                  ci_low_ms, ci_high_ms, stddev_ms, iterations, instructions)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    run_id,
+                    result.name,
+                    result.language,
+                    optimization_of(result),
+                    result.avg_time_ms,
+                    result.median_time_ms,
+                    result.ci_low_ms,
+                    result.ci_high_ms,
+                    result.stddev_ms,
+                    result.iterations as i64,
+                    result.counters.as_ref().and_then(|c| c.instructions).map(|n| n as i64),
+                ],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(run_id)
+    }
+
+    /// Measurements of one series on one machine, oldest first
+    pub fn trend(&self, benchmark: &str, optimization: &str, machine_id: &str) -> Result<Vec<TrendPoint>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT r.id, r.commit_hash, r.recorded_at, m.avg_time_ms, m.ci_low_ms, m.ci_high_ms, m.instructions
+             FROM measurements m JOIN runs r ON r.id = m.run_id
+             WHERE m.benchmark = ?1 AND m.optimization = ?2 AND r.machine_id = ?3
+             ORDER BY r.id",
+        )?;
+        let points = stmt
+            .query_map(params![benchmark, optimization, machine_id], |row| {
+                Ok(TrendPoint {
+                    run_id: row.get(0)?,
+                    commit: row.get(1)?,
+                    recorded_at: row.get(2)?,
+                    avg_time_ms: row.get(3)?,
+                    ci_low_ms: row.get(4)?,
+                    ci_high_ms: row.get(5)?,
+                    instructions: row.get::<_, Option<i64>>(6)?.map(|n| n as u64),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(points)
+    }
+
+    /// Mean time of the series over its last `window` runs from other commits
+    pub fn baseline(
+        &self,
+        benchmark: &str,
+        optimization: &str,
+        machine_id: &str,
+        exclude_commit: &str,
+        window: usize,
+    ) -> Result<Option<f64>> {
+        let mean = self
+            .conn
+            .query_row(
+                "SELECT AVG(avg_time_ms) FROM (
+                     SELECT m.avg_time_ms FROM measurements m JOIN runs r ON r.id = m.run_id
+                     WHERE m.benchmark = ?1 AND m.optimization = ?2 AND r.machine_id = ?3
+                       AND r.commit_hash <> ?4
+                     ORDER BY r.id DESC LIMIT ?5
+                 )",
+                params![benchmark, optimization, machine_id, exclude_commit, window as i64],
+                |row| row.get::<_, Option<f64>>(0),
+            )
+            .optional()?;
+        Ok(mean.flatten())
+    }
+
+    /// Benchmark and optimization pairs measured on a machine
+    pub fn series(&self, machine_id: &str) -> Result<Vec<(String, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT m.benchmark, m.optimization
+             FROM measurements m JOIN runs r ON r.id = m.run_id
+             WHERE r.machine_id = ?1
+             ORDER BY m.benchmark, m.optimization",
+        )?;
+        let series = stmt
+            .query_map(params![machine_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(series)
+    }
+
+    /// Machines with recorded runs
+    pub fn machines(&self) -> Result<Vec<MachineFingerprint>> {
+        let mut stmt = self.conn.prepare("SELECT id, description FROM machines ORDER BY description")?;
+        let machines = stmt
+            .query_map([], |row| Ok(MachineFingerprint { id: row.get(0)?, description: row.get(1)? }))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(machines)
+    }
+}
+
+/// Optimization level a result is stored under
+pub fn optimization_of(result: &BenchmarkResult) -> &str {
+    result.optimization.as_deref().unwrap_or("default")
+}
+
+/// Every result in a JSON report written by `ReportGenerator`
+pub fn results_from_report(json: &str) -> Result<Vec<BenchmarkResult>> {
+    #[derive(Deserialize)]
+    struct JsonReport {
+        c_baselines: HashMap<String, BenchmarkResult>,
+        forth_results: HashMap<String, HashMap<String, BenchmarkResult>>,
+    }
+
+    let report: JsonReport = serde_json::from_str(json).context("Invalid performance report")?;
+    let mut results: Vec<BenchmarkResult> = report
+        .c_baselines
+        .into_values()
+        .chain(report.forth_results.into_values().flat_map(|opts| opts.into_values()))
+        .collect();
+    results.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.optimization.cmp(&b.optimization)));
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(name: &str, optimization: &str, avg_time_ms: f64) -> BenchmarkResult {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "language": "Forth",
+            "optimization": optimization,
+            "iterations": 10,
+            "avg_time_ms": avg_time_ms,
+            "min_time_ms": avg_time_ms,
+            "max_time_ms": avg_time_ms,
+            "stddev_ms": 0.0,
+            "correctness_verified": true,
+        }))
+        .unwrap()
+    }
+
+    fn run(commit: &str, machine: &MachineFingerprint) -> RunInfo {
+        RunInfo { commit: commit.to_string(), machine: machine.clone(), recorded_at: "2026-01-01 00:00:00".to_string() }
+    }
+
+    #[test]
+    fn test_trends_are_per_machine() {
+        let mut db = ResultsDatabase::open_in_memory().unwrap();
+        let laptop = MachineFingerprint::new("laptop");
+        let server = MachineFingerprint::new("server");
+        assert_ne!(laptop.id, server.id);
+
+        db.record(&run("aaa", &laptop), &[result("sieve", "Aggressive", 10.0), result("sieve", "None", 20.0)]).unwrap();
+        db.record(&run("bbb", &laptop), &[result("sieve", "Aggressive", 12.0)]).unwrap();
+        db.record(&run("ccc", &server), &[result("sieve", "Aggressive", 3.0)]).unwrap();
+
+        let trend = db.trend("sieve", "Aggressive", &laptop.id).unwrap();
+        let times: Vec<f64> = trend.iter().map(|p| p.avg_time_ms).collect();
+        assert_eq!(times, vec![10.0, 12.0]);
+        assert_eq!(trend[1].commit, "bbb");
+
+        assert_eq!(db.baseline("sieve", "Aggressive", &laptop.id, "bbb", 10).unwrap(), Some(10.0));
+        assert_eq!(db.baseline("sieve", "Aggressive", &laptop.id, "zzz", 10).unwrap(), Some(11.0));
+        assert_eq!(db.baseline("sieve", "None", &server.id, "zzz", 10).unwrap(), None);
+
+        assert_eq!(db.series(&laptop.id).unwrap().len(), 2);
+        assert_eq!(db.machines().unwrap().len(), 2);
+    }
+}
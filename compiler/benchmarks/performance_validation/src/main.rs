@@ -10,19 +10,26 @@ use std::process::Command;
 use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
 use tabled::{Table, Tabled};
 
 mod benchmarks;
+mod counters;
+mod dashboard;
+mod history;
 mod optimizations;
 mod reports;
 mod regression;
+mod statistics;
 
 use benchmarks::{BenchmarkSuite, BenchmarkResult};
+use history::{MachineFingerprint, ResultsDatabase, RunInfo};
 use optimizations::OptimizationLevel;
 use reports::ReportGenerator;
 use regression::RegressionTester;
+use statistics::{CacheState, SamplingConfig};
 
 /// Performance validation configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,12 +38,23 @@ pub struct ValidationConfig {
     pub benchmarks_dir: PathBuf,
     /// Results output directory
     pub results_dir: PathBuf,
-    /// Number of iterations per benchmark
+    /// Maximum number of iterations per benchmark
     pub iterations: usize,
+    /// Minimum number of iterations per benchmark
+    pub min_iterations: usize,
+    /// Stop sampling once the confidence interval is within this fraction
+    /// of the mean
+    pub target_precision: f64,
     /// Enable warmup runs
     pub warmup: bool,
     /// Number of warmup iterations
     pub warmup_iterations: usize,
+    /// Measure with warm or flushed caches
+    pub cache: CacheState,
+    /// Count instructions, branch misses and cache misses of each run
+    /// (needs the `perf-counters` feature)
+    #[serde(default = "default_hardware_counters")]
+    pub hardware_counters: bool,
     /// Target performance vs gcc (1.0 = match gcc)
     pub target_gcc_ratio: f64,
     /// Performance regression threshold (5%)
@@ -49,14 +67,41 @@ impl Default for ValidationConfig {
             benchmarks_dir: PathBuf::from("../"),
             results_dir: PathBuf::from("results"),
             iterations: 100,
+            min_iterations: 10,
+            target_precision: 0.02,
             warmup: true,
             warmup_iterations: 10,
+            cache: CacheState::Warm,
+            hardware_counters: true,
             target_gcc_ratio: 1.0,
             regression_threshold: 0.05,
         }
     }
 }
 
+fn default_hardware_counters() -> bool {
+    true
+}
+
+impl ValidationConfig {
+    /// Database of every recorded run
+    pub fn database_path(&self) -> PathBuf {
+        self.results_dir.join("history.db")
+    }
+
+    /// How each benchmark is sampled
+    pub fn sampling(&self) -> SamplingConfig {
+        SamplingConfig {
+            warmup_iterations: if self.warmup { self.warmup_iterations } else { 0 },
+            min_iterations: self.min_iterations,
+            max_iterations: self.iterations,
+            target_precision: self.target_precision,
+            cache: self.cache,
+            ..SamplingConfig::default()
+        }
+    }
+}
+
 /// Performance validation results
 #[derive(Debug, Clone, Serialize, Deserialize, Tabled)]
 pub struct ValidationResult {
@@ -103,8 +148,18 @@ impl PerformanceValidator {
         // Create output directories
         fs::create_dir_all(&config.results_dir)?;
 
-        let suite = BenchmarkSuite::new(config.benchmarks_dir.clone())?;
-        let regression_tester = RegressionTester::new(config.results_dir.join("history.json"))?;
+        let suite = BenchmarkSuite::new(config.benchmarks_dir.clone())?
+            .with_sampling(config.sampling())
+            .with_counters(config.hardware_counters);
+        let mut regression_tester = RegressionTester::new(config.database_path())?;
+
+        // Carry over the history kept before the results database
+        let legacy_history = config.results_dir.join("history.json");
+        if legacy_history.exists() {
+            let runs = regression_tester.import_legacy_history(&legacy_history)?;
+            fs::rename(&legacy_history, legacy_history.with_extension("json.imported"))?;
+            println!("Imported {} runs from {}", runs, legacy_history.display());
+        }
         let report_generator = ReportGenerator::new(config.results_dir.join("reports"))?;
 
         Ok(Self {
@@ -142,23 +197,24 @@ impl PerformanceValidator {
         // Run regression tests
         println!("{}", "Step 4: Checking for regressions...".bold());
 
-        // Convert to flat HashMap with aggressive results
-        let mut aggressive_results = HashMap::new();
-        for (bench_name, opt_results) in &forth_results {
-            if let Some(aggressive) = opt_results.get(&OptimizationLevel::Aggressive) {
-                aggressive_results.insert(bench_name.clone(), aggressive.clone());
-            }
-        }
+        let all_results: Vec<&BenchmarkResult> = c_baselines
+            .values()
+            .chain(forth_results.values().flat_map(|opts| opts.values()))
+            .collect();
 
-        let regressions = self.regression_tester.check_regressions(&aggressive_results, self.config.regression_threshold)?;
+        let regressions = self.regression_tester.check_regressions(all_results.iter().copied(), self.config.regression_threshold)?;
         if regressions.is_empty() {
             println!("{}", "✓ No performance regressions detected".green());
         } else {
             println!("{}", format!("⚠ {} regressions detected", regressions.len()).yellow());
             for reg in &regressions {
-                println!("  {} - {:.1}% slower", reg.benchmark, reg.degradation * 100.0);
+                println!("  {} ({}) - {:.1}% slower", reg.benchmark, reg.optimization, reg.degradation * 100.0);
             }
         }
+
+        let run_id = self.regression_tester.update_history(all_results.iter().copied())?;
+        let run = self.regression_tester.run();
+        println!("  Recorded run {} of {} on {}", run_id, run.commit, run.machine);
         println!();
 
         // Generate validation report
@@ -169,6 +225,10 @@ impl PerformanceValidator {
             &comparisons,
             &regressions,
         )?;
+        let dashboard_path = self.config.results_dir.join("reports").join("dashboard.html");
+        let dashboard = dashboard::render_dashboard(self.regression_tester.database(), self.regression_tester.machine())?;
+        fs::write(&dashboard_path, dashboard)?;
+        println!("  Dashboard saved to: {}", dashboard_path.display());
         println!("{}", "✓ Report generated".green());
         println!();
 
@@ -196,8 +256,15 @@ impl PerformanceValidator {
                     .output()?;
             }
 
-            let result = self.suite.run_c_benchmark(&executable, self.config.iterations)?;
-            println!("{:.3} ms", result.avg_time_ms);
+            let result = self.suite.run_c_benchmark(&executable)?;
+            println!(
+                "{:.3} ms ± {:.3} ({} runs, {} outliers, {})",
+                result.avg_time_ms,
+                result.margin_ms(),
+                result.iterations,
+                result.outliers_rejected,
+                result.cache
+            );
             results.insert(bench.to_string(), result);
         }
 
@@ -220,7 +287,7 @@ impl PerformanceValidator {
             let mut bench_results = HashMap::new();
 
             for opt in &opt_levels {
-                let result = self.suite.run_forth_benchmark(bench, opt.clone(), self.config.iterations)?;
+                let result = self.suite.run_forth_benchmark(bench, opt.clone())?;
                 bench_results.insert(opt.clone(), result);
             }
 
@@ -251,7 +318,7 @@ impl PerformanceValidator {
                         OptimizationImpact {
                             time_ms: result.avg_time_ms,
                             speedup,
-                            instruction_reduction: None,
+                            instruction_reduction: instruction_reduction(baseline, result),
                             code_size_change: None,
                         }
                     );
@@ -329,9 +396,140 @@ impl PerformanceValidator {
     }
 }
 
+/// Fraction of the baseline's instructions that an optimization removed,
+/// when both runs were counted
+fn instruction_reduction(baseline: &BenchmarkResult, optimized: &BenchmarkResult) -> Option<f64> {
+    let before = baseline.counters.as_ref()?.instructions?;
+    let after = optimized.counters.as_ref()?.instructions?;
+    (before > 0).then(|| 1.0 - after as f64 / before as f64)
+}
+
+#[derive(Parser)]
+#[command(name = "perf-validate", about = "Fast Forth performance validation")]
+struct Cli {
+    /// Directory results and the history database are kept in
+    #[arg(long, global = true, default_value = "results")]
+    results_dir: PathBuf,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Run the benchmarks, check for regressions and record the run (default)
+    Validate {
+        /// Directory holding the forth/ and c_baseline/ benchmarks
+        #[arg(long, default_value = "../")]
+        benchmarks_dir: PathBuf,
+    },
+
+    /// Record the results of a JSON performance report
+    Record {
+        /// Report written by a validation run
+        report: PathBuf,
+
+        /// Commit the report measured (default: the current commit)
+        #[arg(long)]
+        commit: Option<String>,
+    },
+
+    /// Show how a benchmark's time has changed over recorded runs
+    Trend {
+        benchmark: String,
+
+        /// Optimization level (e.g. Aggressive, or "gcc -O2" for C)
+        #[arg(long, default_value = "Aggressive")]
+        optimization: String,
+
+        /// Machine fingerprint id (default: this machine)
+        #[arg(long)]
+        machine: Option<String>,
+    },
+
+    /// Render the HTML dashboard of recorded runs
+    Dashboard {
+        /// Output file (default: <results-dir>/reports/dashboard.html)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Machine fingerprint id (default: this machine)
+        #[arg(long)]
+        machine: Option<String>,
+    },
+}
+
+/// The machine with fingerprint id `id`, or this machine
+fn select_machine(db: &ResultsDatabase, id: Option<&str>) -> Result<MachineFingerprint> {
+    match id {
+        Some(id) => db
+            .machines()?
+            .into_iter()
+            .find(|machine| machine.id == id)
+            .with_context(|| format!("No runs recorded on machine {}", id)),
+        None => Ok(MachineFingerprint::detect()),
+    }
+}
+
 fn main() -> Result<()> {
-    let config = ValidationConfig::default();
-    let mut validator = PerformanceValidator::new(config)?;
-    validator.validate()?;
+    let cli = Cli::parse();
+    let config = ValidationConfig { results_dir: cli.results_dir, ..ValidationConfig::default() };
+
+    match cli.command {
+        None => {
+            let mut validator = PerformanceValidator::new(config)?;
+            validator.validate()?;
+        }
+        Some(Commands::Validate { benchmarks_dir }) => {
+            let mut validator = PerformanceValidator::new(ValidationConfig { benchmarks_dir, ..config })?;
+            validator.validate()?;
+        }
+        Some(Commands::Record { report, commit }) => {
+            let json = fs::read_to_string(&report).with_context(|| format!("Failed to read {}", report.display()))?;
+            let results = history::results_from_report(&json)?;
+            fs::create_dir_all(&config.results_dir)?;
+            let mut db = ResultsDatabase::open(config.database_path())?;
+            let mut run = RunInfo::current();
+            if let Some(commit) = commit {
+                run.commit = commit;
+            }
+            let run_id = db.record(&run, &results)?;
+            println!("Recorded run {} ({} results) of {} on {}", run_id, results.len(), run.commit, run.machine);
+        }
+        Some(Commands::Trend { benchmark, optimization, machine }) => {
+            let db = ResultsDatabase::open(config.database_path())?;
+            let machine = select_machine(&db, machine.as_deref())?;
+            let trend = db.trend(&benchmark, &optimization, &machine.id)?;
+            if trend.is_empty() {
+                println!("No runs of {} ({}) recorded on {}", benchmark, optimization, machine);
+                return Ok(());
+            }
+
+            println!("{} ({}) on {}", benchmark.bold(), optimization, machine);
+            let mut previous: Option<f64> = None;
+            for point in &trend {
+                let change = previous
+                    .filter(|prev| *prev > 0.0)
+                    .map(|prev| format!("{:+.1}%", (point.avg_time_ms - prev) / prev * 100.0))
+                    .unwrap_or_default();
+                println!(
+                    "  {}  {:<18} {:>10.3} ms  [{:.3}, {:.3}]  {}",
+                    point.recorded_at, point.commit, point.avg_time_ms, point.ci_low_ms, point.ci_high_ms, change
+                );
+                previous = Some(point.avg_time_ms);
+            }
+        }
+        Some(Commands::Dashboard { output, machine }) => {
+            let db = ResultsDatabase::open(config.database_path())?;
+            let machine = select_machine(&db, machine.as_deref())?;
+            let output = output.unwrap_or_else(|| config.results_dir.join("reports").join("dashboard.html"));
+            if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&output, dashboard::render_dashboard(&db, &machine)?)?;
+            println!("Dashboard saved to: {}", output.display());
+        }
+    }
+
     Ok(())
 }
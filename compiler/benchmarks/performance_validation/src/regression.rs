@@ -1,26 +1,35 @@
 /// Regression detection and analysis
 ///
-/// Monitors performance over time to detect degradations
+/// Monitors performance over time to detect degradations. Results are kept
+/// in the results database, so each benchmark and optimization level is
+/// compared only with earlier commits measured on the same machine.
 
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
 use crate::benchmarks::BenchmarkResult;
+use crate::history::{optimization_of, MachineFingerprint, ResultsDatabase, RunInfo};
+
+/// Earlier runs averaged into the baseline a result is compared with
+pub const BASELINE_WINDOW: usize = 10;
 
 /// Performance regression
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Regression {
     pub benchmark: String,
+    #[serde(default)]
+    pub optimization: String,
     pub degradation: f64,
     pub previous_time_ms: f64,
     pub current_time_ms: f64,
 }
 
-/// Historical performance data
+/// Historical performance data, as kept in `history.json` before the
+/// results database
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoricalData {
     pub benchmark: String,
@@ -30,51 +39,58 @@ pub struct HistoricalData {
 
 /// Regression testing and tracking
 pub struct RegressionTester {
-    history_file: std::path::PathBuf,
-    history: HashMap<String, HistoricalData>,
+    db: ResultsDatabase,
+    run: RunInfo,
 }
 
 impl RegressionTester {
-    pub fn new(history_file: impl AsRef<Path>) -> Result<Self> {
-        let history_file = history_file.as_ref().to_path_buf();
-        let mut history = HashMap::new();
-
-        // Try to load existing history
-        if history_file.exists() {
-            let data = fs::read_to_string(&history_file)?;
-            if let Ok(loaded) = serde_json::from_str::<Vec<HistoricalData>>(&data) {
-                for item in loaded {
-                    history.insert(item.benchmark.clone(), item);
-                }
-            }
-        }
+    /// Track results in the database at `database`, for the current commit
+    /// on this machine
+    pub fn new(database: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::with_database(ResultsDatabase::open(database)?, RunInfo::current()))
+    }
+
+    pub fn with_database(db: ResultsDatabase, run: RunInfo) -> Self {
+        Self { db, run }
+    }
+
+    pub fn run(&self) -> &RunInfo {
+        &self.run
+    }
 
-        Ok(Self { history_file, history })
+    pub fn database(&self) -> &ResultsDatabase {
+        &self.db
     }
 
-    /// Check for performance regressions
-    pub fn check_regressions(
+    /// Check for performance regressions against earlier commits
+    pub fn check_regressions<'a>(
         &self,
-        current_results: &HashMap<String, crate::benchmarks::BenchmarkResult>,
+        current_results: impl IntoIterator<Item = &'a BenchmarkResult>,
         threshold: f64,
     ) -> Result<Vec<Regression>> {
         let mut regressions = Vec::new();
 
-        for (bench_name, current) in current_results {
-            if let Some(historical) = self.history.get(bench_name) {
-                if !historical.measurements.is_empty() {
-                    let avg_previous = historical.measurements.iter().sum::<f64>()
-                        / historical.measurements.len() as f64;
-                    let degradation = (current.avg_time_ms - avg_previous) / avg_previous;
-
-                    if degradation > threshold {
-                        regressions.push(Regression {
-                            benchmark: bench_name.clone(),
-                            degradation,
-                            previous_time_ms: avg_previous,
-                            current_time_ms: current.avg_time_ms,
-                        });
-                    }
+        for current in current_results {
+            let optimization = optimization_of(current);
+            let previous = self.db.baseline(
+                &current.name,
+                optimization,
+                &self.run.machine.id,
+                &self.run.commit,
+                BASELINE_WINDOW,
+            )?;
+
+            if let Some(avg_previous) = previous.filter(|avg| *avg > 0.0) {
+                let degradation = (current.avg_time_ms - avg_previous) / avg_previous;
+
+                if degradation > threshold {
+                    regressions.push(Regression {
+                        benchmark: current.name.clone(),
+                        optimization: optimization.to_string(),
+                        degradation,
+                        previous_time_ms: avg_previous,
+                        current_time_ms: current.avg_time_ms,
+                    });
                 }
             }
         }
@@ -82,54 +98,80 @@ impl RegressionTester {
         Ok(regressions)
     }
 
-    /// Update history with new measurements
-    pub fn update_history(&mut self, results: &HashMap<String, BenchmarkResult>) -> Result<()> {
-        let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-
-        for (bench_name, result) in results {
-            let entry = self.history.entry(bench_name.clone()).or_insert_with(|| {
-                HistoricalData {
-                    benchmark: bench_name.clone(),
-                    measurements: Vec::new(),
-                    timestamps: Vec::new(),
-                }
-            });
-
-            entry.measurements.push(result.avg_time_ms);
-            entry.timestamps.push(timestamp.clone());
+    /// Record this run's measurements
+    pub fn update_history<'a>(&mut self, results: impl IntoIterator<Item = &'a BenchmarkResult>) -> Result<i64> {
+        let results: Vec<BenchmarkResult> = results.into_iter().cloned().collect();
+        self.db.record(&self.run, &results)
+    }
 
-            // Keep only last 100 measurements
-            if entry.measurements.len() > 100 {
-                entry.measurements.remove(0);
-                entry.timestamps.remove(0);
+    /// Import a `history.json` kept before the results database, as runs of
+    /// an unknown commit on this machine
+    pub fn import_legacy_history(&mut self, history_file: impl AsRef<Path>) -> Result<usize> {
+        let data = fs::read_to_string(history_file)?;
+        let history: Vec<HistoricalData> = serde_json::from_str(&data)?;
+
+        // One run per timestamp, holding every benchmark measured then
+        let mut runs: HashMap<&str, Vec<BenchmarkResult>> = HashMap::new();
+        for item in &history {
+            for (time, timestamp) in item.measurements.iter().zip(&item.timestamps) {
+                runs.entry(timestamp).or_default().push(legacy_result(&item.benchmark, *time));
             }
         }
 
-        // Save history
-        let history_vec: Vec<_> = self.history.values().cloned().collect();
-        let json = serde_json::to_string_pretty(&history_vec)?;
-        fs::write(&self.history_file, json)?;
+        let mut timestamps: Vec<&str> = runs.keys().copied().collect();
+        timestamps.sort_unstable();
+        for timestamp in &timestamps {
+            let run = RunInfo {
+                commit: "unknown".to_string(),
+                machine: self.run.machine.clone(),
+                recorded_at: timestamp.to_string(),
+            };
+            self.db.record(&run, &runs[timestamp])?;
+        }
 
-        Ok(())
+        Ok(timestamps.len())
     }
 
     /// Get historical trend for a benchmark
-    pub fn get_trend(&self, benchmark: &str) -> Option<Vec<f64>> {
-        self.history
-            .get(benchmark)
-            .map(|h| h.measurements.clone())
+    pub fn get_trend(&self, benchmark: &str, optimization: &str) -> Option<Vec<f64>> {
+        let trend = self.db.trend(benchmark, optimization, &self.run.machine.id).ok()?;
+        (!trend.is_empty()).then(|| trend.iter().map(|point| point.avg_time_ms).collect())
     }
 
     /// Get average performance over history
-    pub fn get_historical_average(&self, benchmark: &str) -> Option<f64> {
-        self.get_trend(benchmark).and_then(|measurements| {
-            if measurements.is_empty() {
-                None
-            } else {
-                Some(measurements.iter().sum::<f64>() / measurements.len() as f64)
-            }
+    pub fn get_historical_average(&self, benchmark: &str, optimization: &str) -> Option<f64> {
+        self.get_trend(benchmark, optimization).map(|measurements| {
+            measurements.iter().sum::<f64>() / measurements.len() as f64
         })
     }
+
+    /// Machine results are tracked for
+    pub fn machine(&self) -> &MachineFingerprint {
+        &self.run.machine
+    }
+}
+
+/// History only kept the aggressive Forth time
+fn legacy_result(benchmark: &str, avg_time_ms: f64) -> BenchmarkResult {
+    BenchmarkResult {
+        name: benchmark.to_string(),
+        language: "Forth".to_string(),
+        optimization: Some("Aggressive".to_string()),
+        iterations: 0,
+        avg_time_ms,
+        min_time_ms: avg_time_ms,
+        max_time_ms: avg_time_ms,
+        stddev_ms: 0.0,
+        correctness_verified: true,
+        median_time_ms: avg_time_ms,
+        ci_low_ms: avg_time_ms,
+        ci_high_ms: avg_time_ms,
+        confidence_level: 0.0,
+        outliers_rejected: 0,
+        converged: false,
+        cache: Default::default(),
+        counters: None,
+    }
 }
 
 #[cfg(test)]
@@ -143,4 +185,27 @@ mod tests {
 
         assert!(degradation > threshold);
     }
+
+    #[test]
+    fn test_regressions_against_earlier_commits() {
+        let machine = MachineFingerprint::new("test machine");
+        let run = |commit: &str| RunInfo {
+            commit: commit.to_string(),
+            machine: machine.clone(),
+            recorded_at: "2026-01-01 00:00:00".to_string(),
+        };
+
+        let mut earlier = RegressionTester::with_database(ResultsDatabase::open_in_memory().unwrap(), run("aaa"));
+        earlier.update_history(&[legacy_result("sieve", 10.0), legacy_result("fib", 5.0)]).unwrap();
+
+        let tester = RegressionTester::with_database(earlier.db, run("bbb"));
+        let current = [legacy_result("sieve", 10.2), legacy_result("fib", 6.0)];
+        let regressions = tester.check_regressions(&current, 0.05).unwrap();
+
+        assert_eq!(regressions.len(), 1);
+        assert_eq!(regressions[0].benchmark, "fib");
+        assert_eq!(regressions[0].optimization, "Aggressive");
+        assert!((regressions[0].degradation - 0.2).abs() < 1e-9);
+        assert_eq!(tester.get_historical_average("sieve", "Aggressive"), Some(10.0));
+    }
 }
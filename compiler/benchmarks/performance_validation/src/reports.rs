@@ -50,6 +50,7 @@ impl ReportGenerator {
         // Detailed Results
         report.push_str("\n## Detailed Benchmark Results\n\n");
         self.add_detailed_results(&mut report, c_baselines, forth_results);
+        self.add_hardware_counters(&mut report, c_baselines, forth_results);
 
         // Optimization Impact Analysis
         report.push_str("\n## Optimization Impact Analysis\n\n");
@@ -151,8 +152,8 @@ impl ReportGenerator {
         c_baselines: &HashMap<String, BenchmarkResult>,
         forth_results: &HashMap<String, HashMap<OptimizationLevel, BenchmarkResult>>,
     ) {
-        report.push_str("| Benchmark | Optimization | Time (ms) | vs Baseline | vs GCC | Status |\n");
-        report.push_str("|-----------|--------------|-----------|-------------|--------|--------|\n");
+        report.push_str("| Benchmark | Optimization | Time (ms) | CI (ms) | Median (ms) | Outliers | Cache | vs Baseline | vs GCC | Status |\n");
+        report.push_str("|-----------|--------------|-----------|---------|-------------|----------|-------|-------------|--------|--------|\n");
 
         for (bench_name, opts) in forth_results {
             let c_baseline = c_baselines.get(bench_name);
@@ -181,8 +182,17 @@ impl ReportGenerator {
                     };
 
                     report.push_str(&format!(
-                        "| {} | {} | {:.3} | {:.2}x | {:.2}x | {} |\n",
-                        bench_name, opt_level, result.avg_time_ms, speedup, gcc_ratio, status
+                        "| {} | {} | {:.3} | {} | {:.3} | {} | {} | {:.2}x | {:.2}x | {} |\n",
+                        bench_name,
+                        opt_level,
+                        result.avg_time_ms,
+                        format_interval(result),
+                        result.median_time_ms,
+                        format_outliers(result),
+                        result.cache,
+                        speedup,
+                        gcc_ratio,
+                        status
                     ));
                 }
             }
@@ -190,12 +200,68 @@ impl ReportGenerator {
             // Add C baseline for reference
             if let Some(c_res) = c_baseline {
                 report.push_str(&format!(
-                    "| {} | gcc -O2 | {:.3} | - | 1.00x | baseline |\n",
-                    bench_name, c_res.avg_time_ms
+                    "| {} | gcc -O2 | {:.3} | {} | {:.3} | {} | {} | - | 1.00x | baseline |\n",
+                    bench_name,
+                    c_res.avg_time_ms,
+                    format_interval(c_res),
+                    c_res.median_time_ms,
+                    format_outliers(c_res),
+                    c_res.cache
                 ));
             }
 
-            report.push_str("|-----------|--------------|-----------|-------------|--------|--------|\n");
+            report.push_str("|-----------|--------------|-----------|---------|-------------|----------|-------|-------------|--------|--------|\n");
+        }
+
+        let unconverged: Vec<String> = forth_results
+            .iter()
+            .flat_map(|(bench, opts)| opts.iter().map(move |(opt, result)| (bench, opt, result)))
+            .filter(|(_, _, result)| !result.converged)
+            .map(|(bench, opt, _)| format!("{} ({})", bench, opt))
+            .chain(c_baselines.iter().filter(|(_, r)| !r.converged).map(|(bench, _)| format!("{} (gcc -O2)", bench)))
+            .collect();
+        if !unconverged.is_empty() {
+            report.push_str(&format!(
+                "\n⚠ Target precision not reached for: {}\n",
+                unconverged.join(", ")
+            ));
+        }
+
+        report.push('\n');
+    }
+
+    fn add_hardware_counters(
+        &self,
+        report: &mut String,
+        c_baselines: &HashMap<String, BenchmarkResult>,
+        forth_results: &HashMap<String, HashMap<OptimizationLevel, BenchmarkResult>>,
+    ) {
+        let counted = |result: &BenchmarkResult| result.counters.is_some();
+        if !c_baselines.values().any(counted) && !forth_results.values().flat_map(|opts| opts.values()).any(counted) {
+            return;
+        }
+
+        report.push_str("### Hardware Counters\n\n");
+        report.push_str("Per run, averaged over the kept samples.\n\n");
+        report.push_str("| Benchmark | Optimization | Instructions | Branch Misses | Cache Misses |\n");
+        report.push_str("|-----------|--------------|--------------|---------------|--------------|\n");
+
+        for (bench_name, opts) in forth_results {
+            let rows = OptimizationLevel::all()
+                .into_iter()
+                .filter_map(|opt_level| opts.get(&opt_level).map(|result| (opt_level.to_string(), result)))
+                .chain(c_baselines.get(bench_name).map(|c_res| ("gcc -O2".to_string(), c_res)));
+            for (optimization, result) in rows {
+                let Some(counters) = &result.counters else { continue };
+                report.push_str(&format!(
+                    "| {} | {} | {} | {} | {} |\n",
+                    bench_name,
+                    optimization,
+                    format_count(counters.instructions),
+                    format_count(counters.branch_misses),
+                    format_count(counters.cache_misses)
+                ));
+            }
         }
 
         report.push('\n');
@@ -210,14 +276,17 @@ impl ReportGenerator {
             report.push_str(&format!("### {}\n\n", comparison.benchmark));
             report.push_str(&format!("**Baseline**: {:.3} ms\n\n", comparison.baseline_ms));
 
-            report.push_str("| Optimization | Time (ms) | Speedup | Improvement |\n");
-            report.push_str("|--------------|-----------|---------|-------------|\n");
+            report.push_str("| Optimization | Time (ms) | Speedup | Improvement | Instructions Removed |\n");
+            report.push_str("|--------------|-----------|---------|-------------|----------------------|\n");
 
             for (opt_name, impact) in &comparison.optimizations {
                 let improvement = (impact.speedup - 1.0) * 100.0;
+                let instructions = impact
+                    .instruction_reduction
+                    .map_or_else(|| "-".to_string(), |reduction| format!("{:.1}%", reduction * 100.0));
                 report.push_str(&format!(
-                    "| {} | {:.3} | {:.2}x | +{:.1}% |\n",
-                    opt_name, impact.time_ms, impact.speedup, improvement
+                    "| {} | {:.3} | {:.2}x | +{:.1}% | {} |\n",
+                    opt_name, impact.time_ms, impact.speedup, improvement, instructions
                 ));
             }
 
@@ -274,13 +343,14 @@ impl ReportGenerator {
         } else {
             report.push_str(&format!("⚠ {} regression(s) detected:\n\n", regressions.len()));
 
-            report.push_str("| Benchmark | Degradation | Previous | Current |\n");
-            report.push_str("|-----------|-------------|----------|----------|\n");
+            report.push_str("| Benchmark | Optimization | Degradation | Previous | Current |\n");
+            report.push_str("|-----------|--------------|-------------|----------|----------|\n");
 
             for reg in regressions {
                 report.push_str(&format!(
-                    "| {} | {:.1}% | {:.3} ms | {:.3} ms |\n",
+                    "| {} | {} | {:.1}% | {:.3} ms | {:.3} ms |\n",
                     reg.benchmark,
+                    reg.optimization,
                     reg.degradation * 100.0,
                     reg.previous_time_ms,
                     reg.current_time_ms
@@ -349,3 +419,18 @@ impl ReportGenerator {
         Ok(())
     }
 }
+
+/// Confidence interval as `[low, high]`
+fn format_interval(result: &BenchmarkResult) -> String {
+    format!("[{:.3}, {:.3}]", result.ci_low_ms, result.ci_high_ms)
+}
+
+/// Event count, or `-` where it was not counted
+fn format_count(count: Option<u64>) -> String {
+    count.map_or_else(|| "-".to_string(), |count| count.to_string())
+}
+
+/// Rejected samples as `rejected/taken`
+fn format_outliers(result: &BenchmarkResult) -> String {
+    format!("{}/{}", result.outliers_rejected, result.iterations)
+}
@@ -0,0 +1,297 @@
+/// Statistical sampling of benchmark timings
+///
+/// Replaces a fixed number of averaged runs with criterion-style sampling:
+/// keep measuring until the confidence interval of the mean is within a
+/// target precision, reject outliers by Tukey's fences before computing
+/// statistics, and label each result with the cache state it was measured in
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Cache state a benchmark was measured in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheState {
+    /// After warmup runs, with code and data already cached
+    #[default]
+    Warm,
+    /// Without warmup, with caches flushed before every run
+    Cold,
+}
+
+impl fmt::Display for CacheState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheState::Warm => write!(f, "warm"),
+            CacheState::Cold => write!(f, "cold"),
+        }
+    }
+}
+
+/// How many samples to take
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SamplingConfig {
+    /// Untimed runs before sampling (skipped for cold runs)
+    pub warmup_iterations: usize,
+    /// Samples always taken
+    pub min_iterations: usize,
+    /// Samples never exceeded, even if the target is not reached
+    pub max_iterations: usize,
+    /// Target half-width of the confidence interval, relative to the mean
+    pub target_precision: f64,
+    /// Confidence level of the interval
+    pub confidence: f64,
+    /// Time after which sampling stops at the next check
+    pub max_time: Duration,
+    pub cache: CacheState,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            warmup_iterations: 10,
+            min_iterations: 10,
+            max_iterations: 100,
+            target_precision: 0.02,
+            confidence: 0.95,
+            max_time: Duration::from_secs(30),
+            cache: CacheState::Warm,
+        }
+    }
+}
+
+/// Statistics of a set of samples, after outlier rejection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SampleStatistics {
+    /// Samples taken, including rejected outliers
+    pub samples: usize,
+    pub outliers: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Sample standard deviation
+    pub stddev: f64,
+    pub ci_low: f64,
+    pub ci_high: f64,
+    pub confidence: f64,
+}
+
+impl SampleStatistics {
+    /// Statistics of `values` with outliers rejected
+    pub fn from_samples(values: &[f64], confidence: f64) -> Self {
+        let (kept, outliers) = reject_outliers(values);
+        let n = kept.len();
+        if n == 0 {
+            return Self {
+                samples: 0,
+                outliers: 0,
+                mean: 0.0,
+                median: 0.0,
+                min: 0.0,
+                max: 0.0,
+                stddev: 0.0,
+                ci_low: 0.0,
+                ci_high: 0.0,
+                confidence,
+            };
+        }
+
+        let mean = kept.iter().sum::<f64>() / n as f64;
+        let stddev = if n > 1 {
+            (kept.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+        let half_width = if n > 1 {
+            t_critical(n - 1, confidence) * stddev / (n as f64).sqrt()
+        } else {
+            0.0
+        };
+
+        Self {
+            samples: values.len(),
+            outliers,
+            mean,
+            median: percentile(&kept, 0.5),
+            min: kept[0],
+            max: kept[n - 1],
+            stddev,
+            ci_low: mean - half_width,
+            ci_high: mean + half_width,
+            confidence,
+        }
+    }
+
+    /// Half-width of the confidence interval relative to the mean
+    pub fn relative_precision(&self) -> f64 {
+        if self.mean == 0.0 {
+            0.0
+        } else {
+            (self.ci_high - self.ci_low) / 2.0 / self.mean.abs()
+        }
+    }
+}
+
+/// Result of sampling a benchmark
+#[derive(Debug, Clone)]
+pub struct Sampled {
+    pub statistics: SampleStatistics,
+    /// Whether the target precision was reached
+    pub converged: bool,
+    pub cache: CacheState,
+}
+
+/// Sample `measure`, which runs the benchmark once and returns its time
+///
+/// Sampling continues past `min_iterations` until the target precision is
+/// reached, `max_iterations` samples are taken or `max_time` has passed.
+pub fn sample<F>(config: &SamplingConfig, mut measure: F) -> Result<Sampled>
+where
+    F: FnMut() -> Result<f64>,
+{
+    if config.cache == CacheState::Warm {
+        for _ in 0..config.warmup_iterations {
+            measure()?;
+        }
+    }
+
+    let start = Instant::now();
+    let max = config.max_iterations.max(1);
+    let min = config.min_iterations.clamp(1, max);
+    let mut values = Vec::with_capacity(min);
+    loop {
+        if config.cache == CacheState::Cold {
+            evict_caches();
+        }
+        values.push(measure()?);
+
+        if values.len() < min {
+            continue;
+        }
+        let statistics = SampleStatistics::from_samples(&values, config.confidence);
+        let converged = statistics.relative_precision() <= config.target_precision;
+        if converged || values.len() >= max || start.elapsed() >= config.max_time {
+            return Ok(Sampled { statistics, converged, cache: config.cache });
+        }
+    }
+}
+
+/// Sorted values inside Tukey's fences, and how many were outside
+///
+/// Values more than 1.5 interquartile ranges beyond the quartiles are
+/// rejected, which drops runs disturbed by the scheduler or page faults.
+pub fn reject_outliers(values: &[f64]) -> (Vec<f64>, usize) {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    if sorted.len() < 4 {
+        return (sorted, 0);
+    }
+
+    let q1 = percentile(&sorted, 0.25);
+    let q3 = percentile(&sorted, 0.75);
+    let fence = 1.5 * (q3 - q1);
+    let kept: Vec<f64> = sorted.iter().copied().filter(|v| *v >= q1 - fence && *v <= q3 + fence).collect();
+    let outliers = sorted.len() - kept.len();
+    (kept, outliers)
+}
+
+/// Linearly interpolated percentile of sorted values
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+/// Two-sided critical value of Student's t distribution
+///
+/// Uses the Cornish-Fisher expansion around the normal quantile, which is
+/// within 1% of the exact value from 3 degrees of freedom up.
+fn t_critical(df: usize, confidence: f64) -> f64 {
+    let z = normal_quantile(1.0 - (1.0 - confidence) / 2.0);
+    let df = df.max(1) as f64;
+    let (z3, z5) = (z.powi(3), z.powi(5));
+    z + (z3 + z) / (4.0 * df)
+        + (5.0 * z5 + 16.0 * z3 + 3.0 * z) / (96.0 * df * df)
+        + (3.0 * z.powi(7) + 19.0 * z5 + 17.0 * z3 - 15.0 * z) / (384.0 * df.powi(3))
+}
+
+/// Quantile of the standard normal distribution (Abramowitz & Stegun 26.2.23)
+fn normal_quantile(p: f64) -> f64 {
+    let p = p.clamp(1e-12, 1.0 - 1e-12);
+    let (q, sign) = if p < 0.5 { (p, -1.0) } else { (1.0 - p, 1.0) };
+    let t = (-2.0 * q.ln()).sqrt();
+    let z = t - (2.515517 + 0.802853 * t + 0.010328 * t * t) / (1.0 + 1.432788 * t + 0.189269 * t * t + 0.001308 * t.powi(3));
+    sign * z
+}
+
+/// Push code and data out of the CPU caches by sweeping a buffer larger
+/// than any last-level cache
+fn evict_caches() {
+    const SIZE: usize = 64 * 1024 * 1024;
+    thread_local! {
+        static BUFFER: std::cell::RefCell<Vec<u8>> = std::cell::RefCell::new(vec![0; SIZE]);
+    }
+
+    BUFFER.with(|buffer| {
+        let mut buffer = buffer.borrow_mut();
+        for i in (0..SIZE).step_by(64) {
+            buffer[i] = buffer[i].wrapping_add(1);
+        }
+        std::hint::black_box(&*buffer);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outliers_rejected_before_statistics() {
+        let mut values = vec![10.0, 10.5, 9.5, 10.2, 9.8, 10.1, 9.9, 10.0];
+        values.push(50.0);
+
+        let stats = SampleStatistics::from_samples(&values, 0.95);
+        assert_eq!(stats.samples, 9);
+        assert_eq!(stats.outliers, 1);
+        assert_eq!(stats.max, 10.5);
+        assert!((stats.mean - 10.0).abs() < 0.01);
+        assert!(stats.ci_low < stats.mean && stats.mean < stats.ci_high);
+        assert!(stats.relative_precision() < 0.05);
+    }
+
+    #[test]
+    fn test_t_critical_values() {
+        assert!((t_critical(1000, 0.95) - 1.962).abs() < 0.01);
+        assert!((t_critical(10, 0.95) - 2.228).abs() < 0.02);
+        assert!((t_critical(30, 0.99) - 2.750).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_sampling_stops_at_target_precision() {
+        let config = SamplingConfig { warmup_iterations: 3, min_iterations: 5, max_iterations: 1000, ..Default::default() };
+
+        let mut calls = 0;
+        let steady = sample(&config, || {
+            calls += 1;
+            Ok(1.0)
+        })
+        .unwrap();
+        assert!(steady.converged);
+        assert_eq!(steady.statistics.samples, 5);
+        assert_eq!(calls, 8);
+
+        let mut i = 0u32;
+        let noisy = sample(&SamplingConfig { max_iterations: 20, ..config }, || {
+            i += 1;
+            Ok(if i.is_multiple_of(2) { 1.0 } else { 3.0 })
+        })
+        .unwrap();
+        assert!(!noisy.converged);
+        assert_eq!(noisy.statistics.samples, 20);
+    }
+}
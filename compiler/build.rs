@@ -15,6 +15,8 @@ fn main() {
 
     cc::Build::new()
         .file("runtime/forth_runtime.c")
+        .file("runtime/forth_files.c")
+        .file("runtime/forth_system.c")
         .file("runtime/memory.c")
         .file("runtime/ffi.c")
         .file("runtime/bootstrap.c")
@@ -30,6 +32,33 @@ fn main() {
 
     println!("cargo:rustc-link-lib=pthread");
 
+    // The runtime AOT executables link against, embedded in the compiler
+    // so `compile -o` works from any directory: one archive per component,
+    // so a program links only the ones it uses. Portable flags, and kept
+    // out of the compiler's own link.
+    let msvc = env::var("CARGO_CFG_TARGET_ENV").as_deref() == Ok("msvc");
+    for (component, source) in [
+        ("core", "runtime/forth_runtime.c"),
+        ("files", "runtime/forth_files.c"),
+        ("system", "runtime/forth_system.c"),
+        ("threads", "runtime/concurrency.c"),
+    ] {
+        let name = format!("forthruntime_{}", component);
+        cc::Build::new()
+            .file(source)
+            .include("runtime")
+            .opt_level(2)
+            .pic(true)
+            .cargo_metadata(false)
+            .compile(&name);
+        let archive = if msvc { format!("{}.lib", name) } else { format!("lib{}.a", name) };
+        println!(
+            "cargo:rustc-env=FORTH_RUNTIME_{}_ARCHIVE={}",
+            component.to_uppercase(),
+            out_dir.join(archive).display()
+        );
+    }
+
     // Rebuild if any C files change
     println!("cargo:rerun-if-changed=runtime/");
     println!("cargo:rerun-if-changed=minimal_forth/");
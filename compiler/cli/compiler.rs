@@ -4,6 +4,7 @@
 use std::path::Path;
 use std::time::Instant;
 use anyhow::{Context, Result};
+use fastforth_frontend::ForthError;
 
 /// Compilation options
 #[derive(Debug, Clone)]
@@ -40,6 +41,20 @@ pub enum CompileTarget {
     Assembly,
 }
 
+impl CompileTarget {
+    /// Parse a target name as used by `--target` and `Forth.toml`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "native" => Some(CompileTarget::Native),
+            "wasm" => Some(CompileTarget::Wasm),
+            "js" => Some(CompileTarget::JavaScript),
+            "llvm-ir" => Some(CompileTarget::LlvmIr),
+            "asm" => Some(CompileTarget::Assembly),
+            _ => None,
+        }
+    }
+}
+
 /// Compilation result with metrics
 pub struct CompilationResult {
     pub success: bool,
@@ -221,19 +236,37 @@ impl ForthCompiler {
         Ok(vec![])
     }
 
-    /// Type check without compilation
-    pub fn check_file(&self, input_path: &Path) -> Result<Vec<String>> {
+    /// Type check without compilation, reporting up to `max_errors` errors
+    ///
+    /// The parser recovers from errors so one run reports all of them.
+    /// Semantic checks only run once the file parses, since a partly parsed
+    /// program produces errors of its own.
+    pub fn check_file(&self, input_path: &Path, max_errors: usize) -> Result<Vec<String>> {
         let source = std::fs::read_to_string(input_path)?;
 
-        // Lex and parse
-        let tokens = self.lex_source(&source)?;
-        let ast = self.parse_tokens(&tokens)?;
-
-        // Type check
-        match self.type_check(&ast) {
-            Ok(_) => Ok(Vec::new()),
-            Err(e) => Ok(vec![e.to_string()]),
+        let (program, mut errors) = fastforth_frontend::parse_program_recovering(&source, max_errors);
+        if errors.is_empty() {
+            errors = fastforth_frontend::semantic::validate_program(&program).errors;
+            errors.truncate(max_errors);
         }
+
+        Ok(errors
+            .iter()
+            .map(|error| {
+                // Parse errors spell out their location, which leads the line here
+                let message = match error {
+                    ForthError::ParseError { message, .. } => format!("Parse error: {}", message),
+                    ForthError::LexError { message, .. } => format!("Lexical error: {}", message),
+                    _ => error.to_string(),
+                };
+                match error.location() {
+                    Some(location) => {
+                        format!("{}:{}:{}: {}", input_path.display(), location.line, location.column, message)
+                    }
+                    None => format!("{}: {}", input_path.display(), message),
+                }
+            })
+            .collect())
     }
 
     // Internal phase implementations
@@ -1,13 +1,21 @@
 // doc_generator.rs - Documentation generator for Forth code
-// Extracts stack effects, comments, and examples to generate HTML/Markdown docs
-
-use std::path::{Path, PathBuf};
+// Parses the program for stack effects and its call graph, and takes descriptions and examples from doc comments
+
+use crate::explain::{parse_user_words, WordInfo};
+use anyhow::{anyhow, Context, Result};
+use fastforth::{CompilationPipeline, OptimizationLevel};
+use fastforth_frontend::type_inference::TypeInference;
+use fastforth_frontend::{parse_program, Program};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
 
 // Inline CSS instead of include_str! for now
 const DOC_CSS: &str = include_str!("doc_style.css");
 
+/// Name of the top-level code in the call graph
+const TOP_LEVEL: &str = "__main__";
+
 /// Documentation format
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DocFormat {
@@ -15,213 +23,266 @@ pub enum DocFormat {
     Markdown,
 }
 
+impl DocFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            DocFormat::Html => "html",
+            DocFormat::Markdown => "md",
+        }
+    }
+}
+
 /// Word documentation
 #[derive(Debug, Clone)]
 pub struct WordDoc {
     pub name: String,
+    /// Declared stack effect, or the inferred one when the definition has
+    /// no stack comment; empty if neither is known
     pub stack_effect: String,
+    pub effect_inferred: bool,
     pub description: String,
+    pub notes: Option<String>,
     pub examples: Vec<String>,
+    /// Source of the definition
     pub implementation: String,
-    pub category: String,
+    pub file: String,
+    pub line: usize,
+    /// Words this one calls, and the words that call it
+    pub calls: Vec<String>,
+    pub called_by: Vec<String>,
+    /// Whether the top-level code calls this word
+    pub called_from_top_level: bool,
+    /// Whether it calls itself, directly or through other words
+    pub recursive: bool,
 }
 
 /// Documentation generator
 pub struct DocGenerator {
     format: DocFormat,
+    title: String,
+    root: Option<PathBuf>,
 }
 
 impl DocGenerator {
     pub fn new(format: DocFormat) -> Self {
-        DocGenerator { format }
+        DocGenerator {
+            format,
+            title: "Fast Forth Documentation".to_string(),
+            root: None,
+        }
     }
 
-    /// Generate documentation for a source file
-    pub fn generate(&self, input_path: &Path, output_dir: &Path) -> Result<Vec<PathBuf>> {
-        let source = fs::read_to_string(input_path)
-            .context("Failed to read source file")?;
+    /// Title of the index page
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Show source files relative to `root`
+    pub fn with_root(mut self, root: &Path) -> Self {
+        self.root = Some(root.to_path_buf());
+        self
+    }
 
-        // Parse word definitions
-        let words = self.parse_words(&source)?;
+    /// Generate documentation for source files, documented together as
+    /// one program
+    pub fn generate(&self, inputs: &[PathBuf], output_dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut sources = Vec::new();
+        for input in inputs {
+            let source = fs::read_to_string(input)
+                .with_context(|| format!("Failed to read {}", input.display()))?;
+            let name = match &self.root {
+                Some(root) => input.strip_prefix(root).unwrap_or(input),
+                None => input.as_path(),
+            };
+            sources.push((name.display().to_string(), source));
+        }
+
+        let words = self.document(&sources)?;
 
         // Create output directory
         fs::create_dir_all(output_dir)?;
 
         // Generate documentation files
         let mut output_files = Vec::new();
+        let linked = Linker::new(&words, &self.format);
 
         for word in &words {
-            let output_path = match self.format {
-                DocFormat::Html => output_dir.join(format!("{}.html", word.name.to_lowercase())),
-                DocFormat::Markdown => output_dir.join(format!("{}.md", word.name.to_lowercase())),
-            };
-
+            let output_path = output_dir.join(linked.page(&word.name));
             let content = match self.format {
-                DocFormat::Html => self.generate_html(word)?,
-                DocFormat::Markdown => self.generate_markdown(word)?,
+                DocFormat::Html => self.generate_html(word, &linked),
+                DocFormat::Markdown => self.generate_markdown(word, &linked),
             };
 
             fs::write(&output_path, content)?;
             output_files.push(output_path);
         }
 
-        // Generate index
-        let index_path = self.generate_index(&words, output_dir)?;
+        // Generate call graph and index
+        let call_graph_path = output_dir.join(format!("callgraph.{}", self.format.extension()));
+        let content = match self.format {
+            DocFormat::Html => self.generate_html_call_graph(&words, &linked),
+            DocFormat::Markdown => self.generate_markdown_call_graph(&words, &linked),
+        };
+        fs::write(&call_graph_path, content)?;
+        output_files.push(call_graph_path);
+
+        let index_path = output_dir.join(format!("index.{}", self.format.extension()));
+        let content = match self.format {
+            DocFormat::Html => self.generate_html_index(&words, &linked),
+            DocFormat::Markdown => self.generate_markdown_index(&words, &linked),
+        };
+        fs::write(&index_path, content)?;
         output_files.push(index_path);
 
         Ok(output_files)
     }
 
-    /// Parse word definitions from source
-    fn parse_words(&self, source: &str) -> Result<Vec<WordDoc>> {
-        let mut words = Vec::new();
-        let mut current_comments = Vec::new();
-        let lines: Vec<&str> = source.lines().collect();
-
-        for (i, line) in lines.iter().enumerate() {
-            let line = line.trim();
-
-            // Collect comments
-            if line.starts_with('\\') {
-                current_comments.push(line[1..].trim().to_string());
-                continue;
-            }
-
-            // Parse word definition
-            if line.starts_with(':') {
-                let word = self.parse_word_definition(line, &current_comments, &lines, i)?;
-                if let Some(w) = word {
-                    words.push(w);
-                }
-                current_comments.clear();
+    /// Document the words defined in `sources`, given as (file name,
+    /// source), in definition order
+    ///
+    /// A word defined more than once is documented where it is last
+    /// defined, the definition later code calls.
+    pub fn document(&self, sources: &[(String, String)]) -> Result<Vec<WordDoc>> {
+        let mut program = Program::new();
+        let mut comments: HashMap<String, WordInfo> = HashMap::new();
+        let mut implementations: HashMap<String, String> = HashMap::new();
+
+        for (file, source) in sources {
+            let parsed = parse_program(source).map_err(|e| anyhow!("{}: {}", file, e))?;
+            program.definitions.extend(parsed.definitions);
+            program.top_level_code.extend(parsed.top_level_code);
+
+            for info in parse_user_words(file, source) {
+                let line = info.location.as_ref().map_or(0, |location| location.line);
+                implementations.insert(info.name.to_lowercase(), definition_source(source, line));
+                comments.insert(info.name.to_lowercase(), info);
             }
         }
 
-        Ok(words)
-    }
-
-    /// Parse a single word definition
-    fn parse_word_definition(
-        &self,
-        line: &str,
-        comments: &[String],
-        _lines: &[&str],
-        _index: usize,
-    ) -> Result<Option<WordDoc>> {
-        // Parse: : WORD-NAME ( stack-effect ) implementation ;
-        let parts: Vec<&str> = line.split_whitespace().collect();
-
-        if parts.len() < 2 {
-            return Ok(None);
+        let mut inference = TypeInference::new();
+        let mut effects = HashMap::new();
+        for def in program.compiled_definitions() {
+            // A body that cannot be typed still gets documented
+            if let Ok(typed) = inference.trace_definition(def) {
+                let side = |types: &[_]| types.iter().map(|t| format!("{} ", t)).collect::<String>();
+                effects.insert(def.name.clone(), format!("( {}-- {})", side(&typed.inputs), side(&typed.outputs)));
+            }
         }
 
-        let name = parts[1].to_uppercase();
-
-        // Extract stack effect
-        let mut stack_effect = String::new();
-        let mut impl_start = 2;
+        let call_graph = CompilationPipeline::new(OptimizationLevel::None).call_graph(&program);
 
-        if parts.get(2) == Some(&"(") {
-            // Find matching )
-            for (i, &part) in parts.iter().enumerate().skip(3) {
-                if part == ")" {
-                    impl_start = i + 1;
-                    break;
-                }
-                if !stack_effect.is_empty() {
-                    stack_effect.push(' ');
-                }
-                stack_effect.push_str(part);
+        let mut seen = HashSet::new();
+        let mut words = Vec::new();
+        for def in program.definitions.iter().rev() {
+            if !seen.insert(def.name.to_lowercase()) {
+                continue;
             }
-        }
-
-        // Extract implementation
-        let implementation: Vec<&str> = parts[impl_start..]
-            .iter()
-            .take_while(|&&p| p != ";")
-            .copied()
-            .collect();
-
-        // Extract description and examples from comments
-        let mut description = String::new();
-        let mut examples = Vec::new();
-
-        for comment in comments {
-            if comment.starts_with("Example:") {
-                examples.push(comment[8..].trim().to_string());
-            } else if !comment.is_empty() {
-                if !description.is_empty() {
-                    description.push('\n');
+            let info = comments.get(&def.name.to_lowercase());
+            let (stack_effect, effect_inferred) = match info {
+                Some(info) if def.stack_effect.is_some() && !info.stack_effect.is_empty() => {
+                    (info.stack_effect.clone(), false)
                 }
-                description.push_str(comment);
-            }
-        }
+                _ => match effects.get(&def.name) {
+                    Some(effect) => (effect.clone(), def.stack_effect.is_none()),
+                    None => (String::new(), false),
+                },
+            };
 
-        if description.is_empty() {
-            description = format!("The {} word", name);
+            let mut called_by = call_graph.callers(&def.name);
+            let called_from_top_level = called_by.iter().any(|name| name == TOP_LEVEL);
+            called_by.retain(|name| name != TOP_LEVEL && *name != def.name);
+            let mut calls = call_graph.callees(&def.name);
+            calls.retain(|name| *name != def.name);
+
+            words.push(WordDoc {
+                name: def.name.clone(),
+                stack_effect,
+                effect_inferred,
+                description: info.map(|info| info.description.clone()).unwrap_or_default(),
+                notes: info.and_then(|info| info.notes.clone()),
+                examples: info.map(|info| info.examples.clone()).unwrap_or_default(),
+                implementation: implementations.get(&def.name.to_lowercase()).cloned().unwrap_or_default(),
+                file: info.and_then(|info| info.location.as_ref()).map(|location| location.file.clone()).unwrap_or_default(),
+                line: info.and_then(|info| info.location.as_ref()).map_or(def.location.line, |location| location.line),
+                calls,
+                called_by,
+                called_from_top_level,
+                recursive: call_graph.is_recursive(&def.name),
+            });
         }
-
-        Ok(Some(WordDoc {
-            name,
-            stack_effect,
-            description,
-            examples,
-            implementation: implementation.join(" "),
-            category: "General".to_string(),
-        }))
+        words.reverse();
+        Ok(words)
     }
 
     /// Generate HTML documentation
-    fn generate_html(&self, word: &WordDoc) -> Result<String> {
+    fn generate_html(&self, word: &WordDoc, linked: &Linker) -> String {
         let mut html = String::new();
 
-        html.push_str("<!DOCTYPE html>\n");
-        html.push_str("<html>\n");
-        html.push_str("<head>\n");
-        html.push_str(&format!("  <title>{} - Fast Forth Documentation</title>\n", word.name));
-        html.push_str("  <style>\n");
-        html.push_str(DOC_CSS);
-        html.push_str("  </style>\n");
-        html.push_str("</head>\n");
-        html.push_str("<body>\n");
+        html.push_str(&html_header(&format!("{} - {}", word.name, self.title)));
 
         // Word signature
-        html.push_str(&format!("  <h1>{}</h1>\n", word.name));
-        html.push_str(&format!("  <div class=\"stack-effect\">( {} )</div>\n", word.stack_effect));
+        html.push_str(&format!("  <h1>{}</h1>\n", escape_html(&word.name)));
+        if !word.stack_effect.is_empty() {
+            let inferred = if word.effect_inferred { " <span class=\"inferred\">(inferred)</span>" } else { "" };
+            html.push_str(&format!("  <div class=\"stack-effect\">{}{}</div>\n", escape_html(&word.stack_effect), inferred));
+        }
+        if !word.file.is_empty() {
+            html.push_str(&format!("  <p class=\"location\">Defined in {} line {}</p>\n", escape_html(&word.file), word.line));
+        }
 
         // Description
         html.push_str("  <h2>Description</h2>\n");
-        html.push_str(&format!("  <p>{}</p>\n", word.description));
+        html.push_str(&format!("  <p>{}</p>\n", escape_html(&description(word))));
+        if let Some(notes) = &word.notes {
+            html.push_str(&format!("  <p class=\"notes\">{}</p>\n", escape_html(notes)));
+        }
 
         // Examples
         if !word.examples.is_empty() {
             html.push_str("  <h2>Examples</h2>\n");
             html.push_str("  <div class=\"examples\">\n");
             for example in &word.examples {
-                html.push_str(&format!("    <pre>{}</pre>\n", example));
+                html.push_str(&format!("    <pre>{}</pre>\n", linked.code(example)));
             }
             html.push_str("  </div>\n");
         }
 
         // Implementation
         html.push_str("  <h2>Implementation</h2>\n");
-        html.push_str(&format!("  <pre>{}</pre>\n", word.implementation));
+        html.push_str(&format!("  <pre>{}</pre>\n", linked.code(&word.implementation)));
 
+        // Cross references
+        html.push_str("  <h2>Calls</h2>\n");
+        html.push_str(&format!("  <p>{}</p>\n", linked.list(&word.calls, false)));
+        html.push_str("  <h2>Called By</h2>\n");
+        html.push_str(&format!("  <p>{}</p>\n", linked.list(&word.called_by, word.called_from_top_level)));
+
+        html.push_str("  <p class=\"nav\"><a href=\"index.html\">Index</a> · <a href=\"callgraph.html\">Call graph</a></p>\n");
         html.push_str("</body>\n");
         html.push_str("</html>\n");
 
-        Ok(html)
+        html
     }
 
     /// Generate Markdown documentation
-    fn generate_markdown(&self, word: &WordDoc) -> Result<String> {
+    fn generate_markdown(&self, word: &WordDoc, linked: &Linker) -> String {
         let mut md = String::new();
 
         md.push_str(&format!("# {}\n\n", word.name));
-        md.push_str(&format!("**Stack Effect:** `( {} )`\n\n", word.stack_effect));
+        if !word.stack_effect.is_empty() {
+            let inferred = if word.effect_inferred { " (inferred)" } else { "" };
+            md.push_str(&format!("**Stack Effect:** `{}`{}\n\n", word.stack_effect, inferred));
+        }
+        if !word.file.is_empty() {
+            md.push_str(&format!("Defined in `{}` line {}\n\n", word.file, word.line));
+        }
 
         md.push_str("## Description\n\n");
-        md.push_str(&format!("{}\n\n", word.description));
+        md.push_str(&format!("{}\n\n", description(word)));
+        if let Some(notes) = &word.notes {
+            md.push_str(&format!("**Notes:** {}\n\n", notes));
+        }
 
         if !word.examples.is_empty() {
             md.push_str("## Examples\n\n");
@@ -231,88 +292,369 @@ impl DocGenerator {
         }
 
         md.push_str("## Implementation\n\n");
-        md.push_str(&format!("```forth\n{}\n```\n", word.implementation));
+        md.push_str(&format!("```forth\n{}\n```\n\n", word.implementation));
 
-        Ok(md)
+        md.push_str("## Calls\n\n");
+        md.push_str(&format!("{}\n\n", linked.list(&word.calls, false)));
+        md.push_str("## Called By\n\n");
+        md.push_str(&format!("{}\n\n", linked.list(&word.called_by, word.called_from_top_level)));
+
+        md.push_str("[Index](index.md) · [Call graph](callgraph.md)\n");
+
+        md
     }
 
-    /// Generate documentation index
-    fn generate_index(&self, words: &[WordDoc], output_dir: &Path) -> Result<PathBuf> {
-        let index_path = match self.format {
-            DocFormat::Html => output_dir.join("index.html"),
-            DocFormat::Markdown => output_dir.join("index.md"),
-        };
+    fn generate_html_index(&self, words: &[WordDoc], linked: &Linker) -> String {
+        let mut html = String::new();
 
-        let content = match self.format {
-            DocFormat::Html => self.generate_html_index(words)?,
-            DocFormat::Markdown => self.generate_markdown_index(words)?,
-        };
+        html.push_str(&html_header(&format!("{} - Index", self.title)));
+        html.push_str(&format!("  <h1>{}</h1>\n", escape_html(&self.title)));
+        html.push_str("  <p class=\"nav\"><a href=\"callgraph.html\">Call graph</a></p>\n");
 
-        fs::write(&index_path, content)?;
-        Ok(index_path)
+        for (file, words) in by_file(words) {
+            match file {
+                Some(file) => html.push_str(&format!("  <h2>{}</h2>\n", escape_html(file))),
+                None => html.push_str("  <h2>Word Index</h2>\n"),
+            }
+            html.push_str("  <ul class=\"word-list\">\n");
+            for word in words {
+                html.push_str(&format!(
+                    "    <li>{} <span class=\"stack-effect\">{}</span> - {}</li>\n",
+                    linked.link(&word.name),
+                    escape_html(&word.stack_effect),
+                    escape_html(description(word).lines().next().unwrap_or(""))
+                ));
+            }
+            html.push_str("  </ul>\n");
+        }
+
+        html.push_str("</body>\n</html>\n");
+
+        html
     }
 
-    fn generate_html_index(&self, words: &[WordDoc]) -> Result<String> {
-        let mut html = String::new();
+    fn generate_markdown_index(&self, words: &[WordDoc], linked: &Linker) -> String {
+        let mut md = String::new();
 
-        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n");
-        html.push_str("  <title>Fast Forth Documentation - Index</title>\n");
-        html.push_str("  <style>\n");
-        html.push_str(DOC_CSS);
-        html.push_str("  </style>\n");
-        html.push_str("</head>\n<body>\n");
-        html.push_str("  <h1>Fast Forth Documentation</h1>\n");
-        html.push_str("  <h2>Word Index</h2>\n");
-        html.push_str("  <ul class=\"word-list\">\n");
+        md.push_str(&format!("# {}\n\n", self.title));
+        md.push_str("[Call graph](callgraph.md)\n\n");
 
+        for (file, words) in by_file(words) {
+            match file {
+                Some(file) => md.push_str(&format!("## {}\n\n", file)),
+                None => md.push_str("## Word Index\n\n"),
+            }
+            for word in words {
+                let effect = if word.stack_effect.is_empty() { String::new() } else { format!(" `{}`", word.stack_effect) };
+                md.push_str(&format!(
+                    "- {}{} - {}\n",
+                    linked.link(&word.name),
+                    effect,
+                    description(word).lines().next().unwrap_or("")
+                ));
+            }
+            md.push('\n');
+        }
+
+        md
+    }
+
+    fn generate_html_call_graph(&self, words: &[WordDoc], linked: &Linker) -> String {
+        let mut html = String::new();
+
+        html.push_str(&html_header(&format!("{} - Call Graph", self.title)));
+        html.push_str("  <h1>Call Graph</h1>\n");
+        html.push_str(&format!("  <p>Top-level code calls: {}</p>\n", linked.list(&top_level_calls(words), false)));
+        html.push_str("  <table class=\"call-graph\">\n");
+        html.push_str("    <tr><th>Word</th><th>Calls</th><th>Called by</th></tr>\n");
         for word in words {
+            let name = if word.recursive { format!("{} (recursive)", linked.link(&word.name)) } else { linked.link(&word.name) };
             html.push_str(&format!(
-                "    <li><a href=\"{}.html\">{}</a> <span class=\"stack-effect\">( {} )</span> - {}</li>\n",
-                word.name.to_lowercase(),
-                word.name,
-                word.stack_effect,
-                word.description.lines().next().unwrap_or("")
+                "    <tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                name,
+                linked.list(&word.calls, false),
+                linked.list(&word.called_by, word.called_from_top_level)
             ));
         }
-
-        html.push_str("  </ul>\n");
+        html.push_str("  </table>\n");
+        let unused = unused_words(words);
+        if !unused.is_empty() {
+            html.push_str(&format!("  <p>Never called: {}</p>\n", linked.list(&unused, false)));
+        }
+        html.push_str("  <p class=\"nav\"><a href=\"index.html\">Index</a></p>\n");
         html.push_str("</body>\n</html>\n");
 
-        Ok(html)
+        html
     }
 
-    fn generate_markdown_index(&self, words: &[WordDoc]) -> Result<String> {
+    fn generate_markdown_call_graph(&self, words: &[WordDoc], linked: &Linker) -> String {
         let mut md = String::new();
 
-        md.push_str("# Fast Forth Documentation\n\n");
-        md.push_str("## Word Index\n\n");
+        md.push_str("# Call Graph\n\n");
+
+        // Mermaid renders on most Markdown hosts and reads fine as text
+        md.push_str("```mermaid\ngraph LR\n");
+        let ids: HashMap<&str, usize> = words.iter().enumerate().map(|(i, word)| (word.name.as_str(), i)).collect();
+        md.push_str("  main([top level])\n");
+        for (i, word) in words.iter().enumerate() {
+            md.push_str(&format!("  w{}[\"{}\"]\n", i, word.name.replace('"', "#quot;")));
+        }
+        for (i, word) in words.iter().enumerate() {
+            if word.called_from_top_level {
+                md.push_str(&format!("  main --> w{}\n", i));
+            }
+            for callee in &word.calls {
+                if let Some(j) = ids.get(callee.as_str()) {
+                    md.push_str(&format!("  w{} --> w{}\n", i, j));
+                }
+            }
+        }
+        md.push_str("```\n\n");
 
+        md.push_str(&format!("Top-level code calls: {}\n\n", linked.list(&top_level_calls(words), false)));
+        md.push_str("| Word | Calls | Called by |\n|------|-------|-----------|\n");
         for word in words {
+            let name = if word.recursive { format!("{} (recursive)", linked.link(&word.name)) } else { linked.link(&word.name) };
             md.push_str(&format!(
-                "- [{}]({}.md) `( {} )` - {}\n",
-                word.name,
-                word.name.to_lowercase(),
-                word.stack_effect,
-                word.description.lines().next().unwrap_or("")
+                "| {} | {} | {} |\n",
+                name,
+                linked.list(&word.calls, false),
+                linked.list(&word.called_by, word.called_from_top_level)
             ));
         }
+        let unused = unused_words(words);
+        if !unused.is_empty() {
+            md.push_str(&format!("\nNever called: {}\n", linked.list(&unused, false)));
+        }
+        md.push_str("\n[Index](index.md)\n");
 
-        Ok(md)
+        md
     }
 }
 
+/// Links to the pages of documented words
+struct Linker {
+    format: DocFormat,
+    /// Documented names, lowercased, to the name as defined
+    names: HashMap<String, String>,
+}
+
+impl Linker {
+    fn new(words: &[WordDoc], format: &DocFormat) -> Self {
+        let names = words.iter().map(|word| (word.name.to_lowercase(), word.name.clone())).collect();
+        Linker { format: format.clone(), names }
+    }
+
+    /// File name of the page for `name`: letters, digits and `-`, with
+    /// every other byte as `_xx`, so words like `+` and `<>` get portable
+    /// names
+    fn page(&self, name: &str) -> String {
+        let mut page = String::new();
+        for byte in name.to_lowercase().bytes() {
+            if byte.is_ascii_alphanumeric() || byte == b'-' {
+                page.push(byte as char);
+            } else {
+                page.push_str(&format!("_{:02x}", byte));
+            }
+        }
+        format!("{}.{}", page, self.format.extension())
+    }
+
+    /// A link to the page of `name`
+    fn link(&self, name: &str) -> String {
+        match self.format {
+            DocFormat::Html => format!("<a href=\"{}\">{}</a>", self.page(name), escape_html(name)),
+            DocFormat::Markdown => format!("[`{}`]({})", name, self.page(name)),
+        }
+    }
+
+    /// Links to `names`, with the top-level code first when `top_level`
+    fn list(&self, names: &[String], top_level: bool) -> String {
+        let mut items: Vec<String> = Vec::new();
+        if top_level {
+            items.push("top-level code".to_string());
+        }
+        items.extend(names.iter().map(|name| self.link(name)));
+        if items.is_empty() {
+            "none".to_string()
+        } else {
+            items.join(", ")
+        }
+    }
+
+    /// Forth code as HTML, with every documented word in it linked to its
+    /// page
+    fn code(&self, code: &str) -> String {
+        let mut out = String::new();
+        let mut token = String::new();
+        for c in code.chars().chain(std::iter::once(' ')) {
+            if c.is_whitespace() {
+                if !token.is_empty() {
+                    match self.names.get(&token.to_lowercase()) {
+                        Some(name) => out.push_str(&format!("<a href=\"{}\">{}</a>", self.page(name), escape_html(&token))),
+                        None => out.push_str(&escape_html(&token)),
+                    }
+                    token.clear();
+                }
+                out.push(c);
+            } else {
+                token.push(c);
+            }
+        }
+        out.pop();
+        out
+    }
+}
+
+fn html_header(title: &str) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n");
+    html.push_str("<html>\n");
+    html.push_str("<head>\n");
+    html.push_str(&format!("  <title>{}</title>\n", escape_html(title)));
+    html.push_str("  <style>\n");
+    html.push_str(DOC_CSS);
+    html.push_str("  </style>\n");
+    html.push_str("</head>\n");
+    html.push_str("<body>\n");
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn description(word: &WordDoc) -> String {
+    if word.description.is_empty() {
+        format!("The {} word", word.name)
+    } else {
+        word.description.clone()
+    }
+}
+
+/// `words` grouped by the file they are defined in, in the order the
+/// files come; a single group without a name for a single file
+fn by_file(words: &[WordDoc]) -> Vec<(Option<&str>, Vec<&WordDoc>)> {
+    let mut groups: Vec<(Option<&str>, Vec<&WordDoc>)> = Vec::new();
+    for word in words {
+        match groups.iter_mut().find(|(file, _)| *file == Some(word.file.as_str())) {
+            Some((_, group)) => group.push(word),
+            None => groups.push((Some(word.file.as_str()), vec![word])),
+        }
+    }
+    if groups.len() == 1 {
+        groups[0].0 = None;
+    }
+    groups
+}
+
+fn top_level_calls(words: &[WordDoc]) -> Vec<String> {
+    words.iter().filter(|word| word.called_from_top_level).map(|word| word.name.clone()).collect()
+}
+
+/// Words nothing calls
+fn unused_words(words: &[WordDoc]) -> Vec<String> {
+    words
+        .iter()
+        .filter(|word| word.called_by.is_empty() && !word.called_from_top_level)
+        .map(|word| word.name.clone())
+        .collect()
+}
+
+/// Source of the definition starting on `line`, up to the line holding
+/// its `;`
+fn definition_source(source: &str, line: usize) -> String {
+    let mut lines = Vec::new();
+    for text in source.lines().skip(line.saturating_sub(1)) {
+        lines.push(text.trim_end());
+        if text.split_whitespace().any(|token| token == ";") {
+            break;
+        }
+    }
+    lines.join("\n")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn document(sources: &[(&str, &str)]) -> Vec<WordDoc> {
+        let sources: Vec<(String, String)> = sources.iter().map(|(file, source)| (file.to_string(), source.to_string())).collect();
+        DocGenerator::new(DocFormat::Markdown).document(&sources).unwrap()
+    }
+
     #[test]
     fn test_parse_simple_word() {
-        let generator = DocGenerator::new(DocFormat::Markdown);
-        let source = ": SQUARE ( n -- n^2 ) DUP * ;";
-
-        let words = generator.parse_words(source).unwrap();
+        let words = document(&[("math.fth", ": SQUARE ( n -- n^2 ) DUP * ;")]);
         assert_eq!(words.len(), 1);
         assert_eq!(words[0].name, "SQUARE");
-        assert_eq!(words[0].stack_effect, "n -- n^2");
+        assert_eq!(words[0].stack_effect, "( n -- n^2 )");
+        assert!(!words[0].effect_inferred);
+        assert_eq!(words[0].implementation, ": SQUARE ( n -- n^2 ) DUP * ;");
+    }
+
+    #[test]
+    fn test_inferred_effects_and_call_graph() {
+        let words = document(&[(
+            "math.fth",
+            "\\ Square a number.\n\\ Example: 3 square .\n: square dup * ;\n: sum-sq ( a b -- n )\n  square swap square + ;\n: unused 1 ;\n2 3 sum-sq .\n",
+        )]);
+        let names: Vec<&str> = words.iter().map(|word| word.name.as_str()).collect();
+        assert_eq!(names, vec!["square", "sum-sq", "unused"]);
+
+        let square = &words[0];
+        assert_eq!(square.stack_effect, "( int -- int )");
+        assert!(square.effect_inferred);
+        assert_eq!(square.description, "Square a number.");
+        assert_eq!(square.examples, vec!["3 square ."]);
+        assert_eq!(square.called_by, vec!["sum-sq"]);
+        assert!(!square.called_from_top_level);
+
+        let sum_sq = &words[1];
+        assert_eq!(sum_sq.calls, vec!["square"]);
+        assert!(sum_sq.called_from_top_level);
+        assert_eq!(sum_sq.implementation, ": sum-sq ( a b -- n )\n  square swap square + ;");
+        assert_eq!(sum_sq.line, 4);
+        assert_eq!(unused_words(&words), vec!["unused"]);
+    }
+
+    #[test]
+    fn test_cross_links() {
+        let words = document(&[("a.fth", ": <=> ( a b -- n ) - ;\n: differ? ( a b -- f ) <=> 0= 0= ;")]);
+        let linker = Linker::new(&words, &DocFormat::Html);
+        assert_eq!(linker.page("<=>"), "_3c_3d_3e.html");
+        assert_eq!(linker.code(": x 1 2 <=> ;"), ": x 1 2 <a href=\"_3c_3d_3e.html\">&lt;=&gt;</a> ;");
+
+        let html = DocGenerator::new(DocFormat::Html).generate_html(&words[1], &linker);
+        assert!(html.contains("<a href=\"_3c_3d_3e.html\">&lt;=&gt;</a> 0= 0= ;</pre>"));
+        assert!(html.contains("<h2>Calls</h2>\n  <p><a href=\"_3c_3d_3e.html\">&lt;=&gt;</a></p>"));
+
+        let markdown = Linker::new(&words, &DocFormat::Markdown);
+        assert_eq!(markdown.list(&words[0].called_by, true), "top-level code, [`differ?`](differ_3f.md)");
+    }
+
+    #[test]
+    fn test_project_mode_over_files() {
+        let words = document(&[
+            ("src/util.fth", ": double ( n -- n ) 2 * ;\n"),
+            ("src/main.fth", ": quad ( n -- n ) double double ;\n5 quad .\n"),
+        ]);
+        assert_eq!(words[0].called_by, vec!["quad"]);
+        assert_eq!(words[1].file, "src/main.fth");
+
+        let generator = DocGenerator::new(DocFormat::Markdown).with_title("demo");
+        let linker = Linker::new(&words, &DocFormat::Markdown);
+        let index = generator.generate_markdown_index(&words, &linker);
+        assert!(index.starts_with("# demo\n"));
+        assert!(index.contains("## src/util.fth\n\n- [`double`](double.md) `( n -- n )`"));
+        assert!(index.contains("## src/main.fth\n"));
+
+        let graph = generator.generate_markdown_call_graph(&words, &linker);
+        assert!(graph.contains("  w1 --> w0\n"));
+        assert!(graph.contains("  main --> w1\n"));
+        assert!(graph.contains("| [`quad`](quad.md) | [`double`](double.md) | top-level code |"));
     }
 }
@@ -2,7 +2,7 @@
 
 use anyhow::{Context, Result};
 use backend::cranelift::{CraneliftBackend, CraneliftSettings};
-use fastforth_frontend::{parse_program, convert_to_ssa};
+use fastforth_frontend::{parse_program, convert_to_ssa, Arithmetic};
 use std::path::Path;
 
 /// Execute a Forth program with JIT compilation
@@ -38,6 +38,8 @@ pub fn execute_program(source: &str, verbose: bool) -> Result<i64> {
         opt_level: 1,
         debug_info: false,
         target_triple: None,
+        enable_verification: cfg!(debug_assertions),
+        arithmetic: Arithmetic::default(),
     };
 
     let mut backend = CraneliftBackend::new(settings)
@@ -55,10 +57,8 @@ pub fn execute_program(source: &str, verbose: bool) -> Result<i64> {
         .context("Failed to declare functions")?;
 
     // Pass 2: Compile all function bodies (can now reference each other)
-    for (name, func) in &functions_with_names {
-        backend.compile_function(func, name)
-            .with_context(|| format!("Failed to compile function {}", name))?;
-    }
+    backend.compile_functions(&functions_with_names)
+        .context("Failed to compile functions")?;
 
     // Finalize all functions (must be done after all are compiled for recursion to work)
     backend.finalize_all()
@@ -73,8 +73,8 @@ pub fn execute_program(source: &str, verbose: bool) -> Result<i64> {
         println!("  Executing...");
     }
 
-    // Get the last compiled function (which will be :main if top-level code exists)
-    if ssa_functions.is_empty() {
+    // Definitions alone have nothing to run; otherwise the last function is :main
+    if program.top_level_code.is_empty() {
         return Ok(0);
     }
 
@@ -0,0 +1,307 @@
+// explain.rs - Word documentation database for `fastforth explain`
+// Core words come from core_words.toml; user words from the doc comments above their definitions
+
+use crate::error_messages::find_similar_words;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const CORE_WORDS: &str = include_str!("core_words.toml");
+
+/// Where a word is defined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    #[default]
+    Core,
+    User,
+}
+
+/// File and line of a user word's definition
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+}
+
+/// Documentation of one word, as shown by `explain` and emitted by
+/// `explain --json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WordInfo {
+    pub name: String,
+    #[serde(rename = "stack_effect", alias = "effect")]
+    pub stack_effect: String,
+    #[serde(default)]
+    pub category: String,
+    pub description: String,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub examples: Vec<String>,
+    #[serde(default)]
+    pub origin: Origin,
+    #[serde(default)]
+    pub location: Option<Location>,
+}
+
+#[derive(Deserialize)]
+struct CoreFile {
+    word: Vec<WordInfo>,
+}
+
+/// Core words plus the words of any sources added to it
+pub struct WordDatabase {
+    words: Vec<WordInfo>,
+}
+
+impl WordDatabase {
+    /// The built-in documentation of the core words
+    pub fn core() -> Self {
+        let core: CoreFile = toml::from_str(CORE_WORDS).expect("core_words.toml is valid");
+        WordDatabase { words: core.word }
+    }
+
+    /// Add the words defined in `source`, read from `file`
+    pub fn add_source(&mut self, file: &str, source: &str) {
+        self.words.extend(parse_user_words(file, source));
+    }
+
+    /// Add the words defined in the file at `path`, shown as `display`
+    pub fn add_file(&mut self, path: &Path, display: &str) -> Result<()> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        self.add_source(display, &source);
+        Ok(())
+    }
+
+    /// Every definition of `name`, ignoring case: user words first, since
+    /// they shadow core words of the same name, then in the order added
+    pub fn lookup(&self, name: &str) -> Vec<&WordInfo> {
+        let mut found: Vec<&WordInfo> = self
+            .words
+            .iter()
+            .filter(|word| word.name.eq_ignore_ascii_case(name))
+            .collect();
+        found.sort_by_key(|word| word.origin == Origin::Core);
+        found
+    }
+
+    /// Known words spelled like `name`, most similar first
+    pub fn suggestions(&self, name: &str) -> Vec<String> {
+        let mut names: Vec<String> = self.words.iter().map(|word| word.name.to_lowercase()).collect();
+        names.sort();
+        names.dedup();
+        find_similar_words(&name.to_lowercase(), &names, 3)
+            .into_iter()
+            .map(|(word, _)| word)
+            .collect()
+    }
+}
+
+/// Words defined with `:` in `source`, documented by the `\` comment lines
+/// directly above them
+///
+/// A comment line starting `Example:` is an example and one starting
+/// `Note:` a note; `@attribute` items are skipped. The stack effect is the
+/// `( ... )` comment after the name.
+pub fn parse_user_words(file: &str, source: &str) -> Vec<WordInfo> {
+    let mut words = Vec::new();
+    let mut comments: Vec<&str> = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('\\') {
+            if let Some(text) = without_attributes(comment.trim()) {
+                comments.push(text);
+            }
+            continue;
+        }
+
+        let mut tokens = line.split_whitespace();
+        if tokens.next() == Some(":") {
+            if let Some(name) = tokens.next() {
+                words.push(user_word(name, stack_effect(tokens), &comments, file, index + 1));
+            }
+        }
+        comments.clear();
+    }
+    words
+}
+
+/// `comment` without the `@attribute` items it starts with, or `None` if
+/// that is all it holds
+fn without_attributes(comment: &str) -> Option<&str> {
+    let mut rest = comment;
+    while rest.starts_with('@') {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        rest = rest[end..].trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+    }
+    Some(rest)
+}
+
+/// The `( ... )` comment at the start of `tokens`, or "" if there is none
+fn stack_effect<'a>(mut tokens: impl Iterator<Item = &'a str>) -> String {
+    if tokens.next() != Some("(") {
+        return String::new();
+    }
+    let inner: Vec<&str> = tokens.take_while(|token| *token != ")").collect();
+    format!("( {} )", inner.join(" "))
+}
+
+fn user_word(name: &str, stack_effect: String, comments: &[&str], file: &str, line: usize) -> WordInfo {
+    let mut description: Vec<&str> = Vec::new();
+    let mut notes: Vec<&str> = Vec::new();
+    let mut examples = Vec::new();
+
+    for comment in comments {
+        if let Some(example) = comment.strip_prefix("Example:") {
+            examples.push(example.trim().to_string());
+        } else if let Some(note) = comment.strip_prefix("Note:") {
+            notes.push(note.trim());
+        } else if !comment.is_empty() || !description.is_empty() {
+            description.push(comment);
+        }
+    }
+    while description.last() == Some(&"") {
+        description.pop();
+    }
+
+    WordInfo {
+        name: name.to_string(),
+        stack_effect,
+        category: "user".to_string(),
+        description: description.join("\n"),
+        notes: (!notes.is_empty()).then(|| notes.join(" ")),
+        examples,
+        origin: Origin::User,
+        location: Some(Location { file: file.to_string(), line }),
+    }
+}
+
+/// Text shown by `fastforth explain`
+pub fn render(word: &WordInfo) -> String {
+    let mut out = format!("{} {}\n", word.name.to_uppercase(), word.stack_effect);
+    match (&word.origin, &word.location) {
+        (Origin::User, Some(location)) => {
+            out.push_str(&format!("  defined at {}:{}\n", location.file, location.line));
+        }
+        _ => out.push_str(&format!("  core word ({})\n", word.category)),
+    }
+
+    out.push('\n');
+    if word.description.is_empty() {
+        out.push_str("  (no documentation comment)\n");
+    }
+    for line in word.description.lines() {
+        if line.is_empty() {
+            out.push('\n');
+        } else {
+            out.push_str(&format!("  {}\n", line));
+        }
+    }
+
+    if let Some(notes) = &word.notes {
+        out.push_str("\nNotes:\n");
+        for line in wrap(notes, 76) {
+            out.push_str(&format!("  {}\n", line));
+        }
+    }
+
+    if !word.examples.is_empty() {
+        out.push_str(if word.examples.len() == 1 { "\nExample:\n" } else { "\nExamples:\n" });
+        for example in &word.examples {
+            out.push_str(&format!("  {}\n", example));
+        }
+    }
+    out
+}
+
+/// `text` broken into lines of at most `width` characters where it can be
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_core_words() {
+        let db = WordDatabase::core();
+        let mut names = HashSet::new();
+        for word in &db.words {
+            assert!(names.insert(word.name.clone()), "{} is documented twice", word.name);
+            assert!(word.stack_effect.starts_with('(') && word.stack_effect.ends_with(')'), "{}", word.name);
+            assert!(!word.description.is_empty() && !word.examples.is_empty(), "{}", word.name);
+        }
+
+        let dup = db.lookup("DUP");
+        assert_eq!(dup.len(), 1);
+        assert_eq!(dup[0].stack_effect, "( x -- x x )");
+        assert_eq!(dup[0].origin, Origin::Core);
+        assert!(db.lookup("/")[0].notes.as_deref().unwrap().contains("--division=floored"));
+        assert!(db.lookup("frobnicate").is_empty());
+        assert!(db.suggestions("swpa").contains(&"swap".to_string()));
+    }
+
+    #[test]
+    fn test_user_words_from_doc_comments() {
+        let mut db = WordDatabase::core();
+        let source = "\
+\\ Unrelated header comment
+
+\\ @inline(always)
+\\ Average of two numbers, rounded toward zero.
+\\ Note: overflows for very large inputs.
+\\ Example: 4 6 average .  \\ 5
+: average ( a b -- avg )
+  + 2 / ;
+: dup ( x -- x x ) dup ;
+";
+        db.add_source("src/main.fth", source);
+
+        let average = db.lookup("AVERAGE");
+        assert_eq!(average.len(), 1);
+        let average = average[0];
+        assert_eq!(average.stack_effect, "( a b -- avg )");
+        assert_eq!(average.description, "Average of two numbers, rounded toward zero.");
+        assert_eq!(average.notes.as_deref(), Some("overflows for very large inputs."));
+        assert_eq!(average.examples, vec!["4 6 average .  \\ 5"]);
+        assert_eq!(average.location, Some(Location { file: "src/main.fth".into(), line: 7 }));
+
+        // A user definition shadows the core word but both are found
+        let dup = db.lookup("dup");
+        assert_eq!(dup.iter().map(|word| word.origin).collect::<Vec<_>>(), vec![Origin::User, Origin::Core]);
+        assert!(render(dup[0]).contains("defined at src/main.fth:9"));
+        assert!(render(dup[0]).contains("(no documentation comment)"));
+    }
+
+    #[test]
+    fn test_json_for_editors() {
+        let db = WordDatabase::core();
+        let json = serde_json::to_value(db.lookup("swap")[0]).unwrap();
+        assert_eq!(json["name"], "swap");
+        assert_eq!(json["stack_effect"], "( a b -- b a )");
+        assert_eq!(json["origin"], "core");
+        assert!(json["location"].is_null());
+        assert!(json["examples"].is_array());
+    }
+}
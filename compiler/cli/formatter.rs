@@ -0,0 +1,601 @@
+// formatter.rs - Forth source formatter
+// Normalizes whitespace, aligns stack-effect comments and indents control structures
+
+use anyhow::{anyhow, Result};
+use fastforth_frontend::parse_program;
+
+/// Formatting options
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    /// Maximum line width before words wrap onto a new line
+    pub line_width: usize,
+    /// Spaces per indentation level
+    pub indent_width: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            line_width: 80,
+            indent_width: 2,
+        }
+    }
+}
+
+/// Kind of source token, as far as layout is concerned
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+    /// Ordinary word or number
+    Word,
+    /// `( ... -- ... )` comment
+    StackEffect,
+    /// Any other parenthesized comment
+    Comment,
+    /// `\ ...` comment running to end of line
+    LineComment,
+    /// String literal or parsing word with its text (`."`, `s"`, `.(`)
+    Text,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    text: String,
+    /// Number of line breaks in the source before this token
+    newlines_before: usize,
+}
+
+impl Token {
+    fn is(&self, word: &str) -> bool {
+        self.kind == TokenKind::Word && self.text.eq_ignore_ascii_case(word)
+    }
+}
+
+/// A top-level unit of source
+enum Item {
+    Definition(Vec<Token>),
+    Token(Token),
+}
+
+/// How a control word affects layout
+enum Control {
+    /// Ends the current line and indents what follows (`if`, `do`)
+    Open,
+    /// Stands on its own line and indents what follows (`begin`)
+    OpenAlone,
+    /// Dedents, stands on its own line, and indents again (`else`, `while`)
+    Middle,
+    /// Dedents and stands on its own line (`then`, `loop`, `until`)
+    Close,
+}
+
+fn control(token: &Token) -> Option<Control> {
+    if token.kind != TokenKind::Word {
+        return None;
+    }
+    match token.text.to_lowercase().as_str() {
+        "if" | "do" | "?do" => Some(Control::Open),
+        "begin" => Some(Control::OpenAlone),
+        "else" | "while" => Some(Control::Middle),
+        "then" | "loop" | "+loop" | "until" | "repeat" | "again" => Some(Control::Close),
+        _ => None,
+    }
+}
+
+/// Forth source formatter
+pub struct Formatter {
+    options: FormatOptions,
+}
+
+impl Formatter {
+    pub fn new(options: FormatOptions) -> Self {
+        Formatter { options }
+    }
+
+    /// Format a complete source file
+    ///
+    /// The source must parse; formatting never changes the token sequence,
+    /// only the whitespace between tokens.
+    pub fn format(&self, source: &str) -> Result<String> {
+        parse_program(source).map_err(|e| anyhow!("cannot format invalid source: {}", e))?;
+
+        let tokens = tokenize(source)?;
+        let items = group_items(tokens);
+        let name_widths = aligned_name_widths(&items);
+
+        let mut writer = LineWriter::new(&self.options);
+        let mut depth = 0;
+
+        for (item, name_width) in items.iter().zip(name_widths) {
+            match item {
+                Item::Definition(tokens) => {
+                    writer.separate(tokens[0].newlines_before);
+                    self.write_definition(&mut writer, tokens, name_width);
+                }
+                Item::Token(token) => {
+                    writer.separate(token.newlines_before);
+                    write_body_token(&mut writer, token, &mut depth);
+                }
+            }
+        }
+
+        Ok(writer.finish())
+    }
+
+    fn write_definition(&self, writer: &mut LineWriter, tokens: &[Token], name_width: usize) {
+        // tokens: ":" name [stack-effect] body... [";"]
+        let name = tokens.get(1).map(|t| t.text.as_str()).unwrap_or("");
+        let mut header = format!(": {}", name);
+        let mut rest = tokens.get(2..).unwrap_or(&[]);
+
+        if let Some(effect) = rest.first().filter(|t| t.kind == TokenKind::StackEffect) {
+            header.push_str(&" ".repeat(name_width.saturating_sub(name.len()) + 1));
+            header.push_str(&effect.text);
+            rest = &rest[1..];
+        }
+
+        let (body, terminated) = match rest.last() {
+            Some(last) if last.is(";") => (&rest[..rest.len() - 1], true),
+            _ => (rest, false),
+        };
+
+        let single_line = body.iter().all(|t| t.newlines_before == 0 && t.kind != TokenKind::LineComment)
+            && tokens.iter().skip(1).all(|t| t.newlines_before == 0);
+        if single_line {
+            let mut line = header.clone();
+            for token in body {
+                line.push(' ');
+                line.push_str(&token.text);
+            }
+            if terminated {
+                line.push_str(" ;");
+            }
+            if writer.fits(&line) {
+                writer.push_unwrapped(&line);
+                return;
+            }
+        }
+
+        writer.push_unwrapped(&header);
+        writer.break_line();
+        writer.indent += 1;
+        let base = writer.indent;
+        let mut depth = 0;
+        for token in body {
+            writer.separate(token.newlines_before);
+            write_body_token(writer, token, &mut depth);
+        }
+        writer.indent = base - 1;
+        if terminated {
+            writer.push_trailing(";");
+        }
+    }
+}
+
+/// Write one token of a body, applying control-structure layout
+fn write_body_token(writer: &mut LineWriter, token: &Token, depth: &mut usize) {
+    match control(token) {
+        Some(Control::Open) => {
+            writer.push(&token.text);
+            writer.break_line();
+            *depth += 1;
+            writer.indent += 1;
+        }
+        Some(Control::OpenAlone) => {
+            writer.break_line();
+            writer.push(&token.text);
+            writer.break_line();
+            *depth += 1;
+            writer.indent += 1;
+        }
+        Some(Control::Middle) => {
+            writer.break_line();
+            if *depth > 0 {
+                writer.indent -= 1;
+            }
+            writer.push(&token.text);
+            writer.break_line();
+            writer.indent += 1;
+        }
+        Some(Control::Close) => {
+            writer.break_line();
+            if *depth > 0 {
+                *depth -= 1;
+                writer.indent -= 1;
+            }
+            writer.push(&token.text);
+            writer.break_line();
+        }
+        None if token.kind == TokenKind::LineComment => {
+            writer.push_unwrapped(&token.text);
+            writer.break_line();
+        }
+        None => writer.push(&token.text),
+    }
+}
+
+/// Accumulates output lines with indentation and wrapping
+struct LineWriter<'a> {
+    options: &'a FormatOptions,
+    lines: Vec<String>,
+    current: String,
+    indent: usize,
+}
+
+impl<'a> LineWriter<'a> {
+    fn new(options: &'a FormatOptions) -> Self {
+        LineWriter {
+            options,
+            lines: Vec::new(),
+            current: String::new(),
+            indent: 0,
+        }
+    }
+
+    fn prefix(&self) -> String {
+        " ".repeat(self.indent * self.options.indent_width)
+    }
+
+    fn fits(&self, text: &str) -> bool {
+        self.prefix().len() + text.len() <= self.options.line_width
+    }
+
+    /// Apply source line breaks: one break is kept, several collapse to one blank line
+    fn separate(&mut self, newlines: usize) {
+        if newlines == 0 {
+            return;
+        }
+        self.break_line();
+        if newlines > 1 && self.lines.last().is_some_and(|l| !l.is_empty()) {
+            self.lines.push(String::new());
+        }
+    }
+
+    /// Append a word, wrapping to a new line if it would exceed the width
+    fn push(&mut self, text: &str) {
+        if !self.current.is_empty()
+            && self.current.len() + 1 + text.len() > self.options.line_width
+        {
+            self.break_line();
+        }
+        self.push_unwrapped(text);
+    }
+
+    fn push_unwrapped(&mut self, text: &str) {
+        if self.current.is_empty() {
+            self.current = self.prefix();
+        } else {
+            self.current.push(' ');
+        }
+        self.current.push_str(text);
+    }
+
+    /// Append to the current line, or the previous one if the current is empty
+    fn push_trailing(&mut self, text: &str) {
+        if self.current.is_empty() {
+            if let Some(last) = self.lines.pop() {
+                self.current = last;
+            }
+        }
+        self.push_unwrapped(text);
+    }
+
+    fn break_line(&mut self) {
+        if !self.current.is_empty() {
+            let line = std::mem::take(&mut self.current);
+            self.lines.push(line.trim_end().to_string());
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.break_line();
+        while self.lines.last().is_some_and(|l| l.is_empty()) {
+            self.lines.pop();
+        }
+        let mut out = self.lines.join("\n");
+        out.push('\n');
+        out
+    }
+}
+
+/// Split definitions from top-level tokens
+fn group_items(tokens: Vec<Token>) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut iter = tokens.into_iter();
+
+    while let Some(token) = iter.next() {
+        if token.is(":") {
+            let mut definition = vec![token];
+            for next in iter.by_ref() {
+                let done = next.is(";");
+                definition.push(next);
+                if done {
+                    break;
+                }
+            }
+            items.push(Item::Definition(definition));
+        } else {
+            items.push(Item::Token(token));
+        }
+    }
+
+    items
+}
+
+/// Name column width for each item, so stack effects line up within a
+/// run of adjacent definitions (a blank line or other code ends the run)
+fn aligned_name_widths(items: &[Item]) -> Vec<usize> {
+    let mut widths = vec![0; items.len()];
+    let mut run_start = 0;
+
+    for i in 0..=items.len() {
+        let continues_run = match items.get(i) {
+            Some(Item::Definition(tokens)) => {
+                i > run_start && tokens[0].newlines_before == 1
+            }
+            _ => false,
+        };
+        if continues_run {
+            continue;
+        }
+
+        // Close the run [run_start, i)
+        let width = items[run_start..i]
+            .iter()
+            .filter_map(|item| match item {
+                Item::Definition(tokens) if tokens.get(2).is_some_and(|t| t.kind == TokenKind::StackEffect) => {
+                    tokens.get(1).map(|t| t.text.len())
+                }
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        for w in &mut widths[run_start..i] {
+            *w = width;
+        }
+
+        run_start = match items.get(i) {
+            Some(Item::Definition(_)) => i,
+            _ => i + 1,
+        };
+    }
+
+    widths
+}
+
+/// Split source into layout tokens, keeping comments and string contents intact
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    let mut newlines = 0;
+
+    while pos < chars.len() {
+        let ch = chars[pos];
+        if ch.is_whitespace() {
+            if ch == '\n' {
+                newlines += 1;
+            }
+            pos += 1;
+            continue;
+        }
+
+        let start = pos;
+        while pos < chars.len() && !chars[pos].is_whitespace() {
+            pos += 1;
+        }
+        let word: String = chars[start..pos].iter().collect();
+
+        let (kind, text) = if word.starts_with('\\') {
+            let end = chars[pos..].iter().position(|&c| c == '\n').map_or(chars.len(), |n| pos + n);
+            let text: String = chars[start..end].iter().collect();
+            pos = end;
+            (TokenKind::LineComment, text.trim_end().to_string())
+        } else if word.starts_with('(') {
+            pos = start + 1;
+            let mut depth = 1;
+            while depth > 0 {
+                match chars.get(pos) {
+                    Some('(') => depth += 1,
+                    Some(')') => depth -= 1,
+                    Some(_) => {}
+                    None => return Err(anyhow!("unclosed parenthesized comment")),
+                }
+                pos += 1;
+            }
+            let inner: String = chars[start + 1..pos - 1].iter().collect();
+            if inner.contains("--") {
+                let normalized = inner.split_whitespace().collect::<Vec<_>>().join(" ");
+                (TokenKind::StackEffect, format!("( {} )", normalized))
+            } else if inner.contains('\n') {
+                (TokenKind::Comment, format!("({})", inner))
+            } else {
+                (TokenKind::Comment, format!("( {} )", inner.trim()))
+            }
+        } else if word.starts_with('"') {
+            pos = start + 1;
+            loop {
+                match chars.get(pos) {
+                    Some('\\') => pos += 2,
+                    Some('"') => {
+                        pos += 1;
+                        break;
+                    }
+                    Some(_) => pos += 1,
+                    None => return Err(anyhow!("unterminated string literal")),
+                }
+            }
+            (TokenKind::Text, chars[start..pos.min(chars.len())].iter().collect())
+        } else if let Some(close) = parsing_word_delimiter(&word) {
+            // The text runs from after the word's trailing space to the delimiter
+            let end = chars[pos..]
+                .iter()
+                .position(|&c| c == close)
+                .map(|n| pos + n + 1)
+                .ok_or_else(|| anyhow!("unterminated text after {}", word))?;
+            pos = end;
+            (TokenKind::Text, chars[start..end].iter().collect())
+        } else {
+            (TokenKind::Word, word)
+        };
+
+        tokens.push(Token {
+            kind,
+            text,
+            newlines_before: newlines,
+        });
+        newlines = 0;
+    }
+
+    Ok(tokens)
+}
+
+/// Closing delimiter for words that parse text up to it
+fn parsing_word_delimiter(word: &str) -> Option<char> {
+    match word.to_lowercase().as_str() {
+        ".\"" | "s\"" | "c\"" | "abort\"" => Some('"'),
+        ".(" => Some(')'),
+        _ => None,
+    }
+}
+
+/// Render a unified diff between the original and formatted text
+pub fn unified_diff(path: &str, original: &str, formatted: &str) -> String {
+    let old: Vec<&str> = original.lines().collect();
+    let new: Vec<&str> = formatted.lines().collect();
+
+    // Longest common subsequence table, filled from the end
+    let mut lcs = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    // Edit script: (' ' | '-' | '+', old index, new index)
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            edits.push((' ', i, j));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            edits.push(('-', i, j));
+            i += 1;
+        } else {
+            edits.push(('+', i, j));
+            j += 1;
+        }
+    }
+
+    const CONTEXT: usize = 3;
+    let mut out = format!("--- {}\n+++ {} (formatted)\n", path, path);
+    let mut k = 0;
+    while k < edits.len() {
+        if edits[k].0 == ' ' {
+            k += 1;
+            continue;
+        }
+
+        // Extend the hunk while changes are within 2 * CONTEXT lines of each other
+        let start = k.saturating_sub(CONTEXT);
+        let mut last_change = k;
+        let mut scan = k + 1;
+        while scan < edits.len() && scan <= last_change + 2 * CONTEXT {
+            if edits[scan].0 != ' ' {
+                last_change = scan;
+            }
+            scan += 1;
+        }
+        let end = (last_change + CONTEXT + 1).min(edits.len());
+
+        let hunk = &edits[start..end];
+        let old_count = hunk.iter().filter(|e| e.0 != '+').count();
+        let new_count = hunk.iter().filter(|e| e.0 != '-').count();
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            hunk[0].1 + 1,
+            old_count,
+            hunk[0].2 + 1,
+            new_count
+        ));
+        for &(op, oi, ni) in hunk {
+            let line = if op == '+' { new[ni] } else { old[oi] };
+            out.push_str(&format!("{}{}\n", op, line));
+        }
+
+        k = end;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(source: &str) -> String {
+        Formatter::new(FormatOptions::default()).format(source).unwrap()
+    }
+
+    #[test]
+    fn test_normalizes_whitespace_and_aligns_stack_effects() {
+        let source = ":   sq ( n  --  n )   dup * ;\n: cube ( n -- n ) dup sq * ;\n";
+        assert_eq!(
+            format(source),
+            ": sq   ( n -- n ) dup * ;\n: cube ( n -- n ) dup sq * ;\n"
+        );
+    }
+
+    #[test]
+    fn test_indents_control_structures() {
+        let source = ": sign ( n -- n )\ndup 0 < if drop -1 else 0 > if 1 else 0 then then ;\n";
+        assert_eq!(
+            format(source),
+            ": sign ( n -- n )\n  dup 0 < if\n    drop -1\n  else\n    0 > if\n      1\n    else\n      0\n    then\n  then ;\n"
+        );
+    }
+
+    #[test]
+    fn test_preserves_comments_and_strings() {
+        let source = "\\ header comment\n: greet ( -- )   \"hi  there\" ( note ) ;\n";
+        assert_eq!(
+            format(source),
+            "\\ header comment\n: greet ( -- ) \"hi  there\" ( note ) ;\n"
+        );
+    }
+
+    #[test]
+    fn test_wraps_long_lines() {
+        let options = FormatOptions {
+            line_width: 20,
+            indent_width: 2,
+        };
+        let formatted = Formatter::new(options)
+            .format(": long 1 2 3 4 5 6 7 8 9 10 11 12 ;")
+            .unwrap();
+        assert!(formatted.lines().all(|l| l.len() <= 20), "{}", formatted);
+        assert!(formatted.starts_with(": long\n  1 2 3"));
+    }
+
+    #[test]
+    fn test_formatting_is_idempotent() {
+        let source = ": a ( n -- n )\n  begin dup while 1 - repeat ;\n\n\n10 a\n";
+        let once = format(source);
+        assert_eq!(format(&once), once);
+        assert!(!once.contains("\n\n\n"));
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let diff = unified_diff("a.fth", "1 2  +\n", "1 2 +\n");
+        assert!(diff.contains("-1 2  +"));
+        assert!(diff.contains("+1 2 +"));
+        assert_eq!(unified_diff("a.fth", "x\n", "x\n"), "--- a.fth\n+++ a.fth (formatted)\n");
+    }
+}
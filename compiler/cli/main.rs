@@ -11,12 +11,16 @@ mod repl;
 mod compiler;
 mod runtime_bridge;
 mod doc_generator;
+mod formatter;
+mod project;
+mod explain;
 
 use error_messages::{ErrorMessage, ErrorSeverity, ErrorTemplates};
 use profiler::Profiler;
 use repl::{Repl, ReplConfig};
 use compiler::{ForthCompiler, CompileOptions, CompileTarget};
 use runtime_bridge::ForthRuntime;
+use project::{Project, Template, TestRunner, TestSummary};
 
 /// Fast Forth - A modern, fast Forth compiler and REPL
 #[derive(Parser)]
@@ -91,10 +95,13 @@ enum Commands {
         time_passes: bool,
     },
 
+    /// Build the project described by Forth.toml
+    Build,
+
     /// JIT compile and execute
     Run {
-        /// Input file
-        input: PathBuf,
+        /// Input file (defaults to the project entry point)
+        input: Option<PathBuf>,
 
         /// Enable profiling
         #[arg(long)]
@@ -117,12 +124,25 @@ enum Commands {
         /// Strict mode (treat warnings as errors)
         #[arg(long)]
         strict: bool,
+
+        /// Stop after reporting this many errors
+        #[arg(long, default_value_t = 20)]
+        max_errors: usize,
     },
 
     /// Lint source code
     Lint {
-        /// Input file
-        input: PathBuf,
+        /// Input files
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Maximum number of words in a definition
+        #[arg(long, default_value = "32")]
+        max_word_size: usize,
+
+        /// Rule to skip (repeatable)
+        #[arg(long = "allow", value_name = "RULE")]
+        allow: Vec<String>,
     },
 
     /// Profile execution
@@ -155,8 +175,8 @@ enum Commands {
 
     /// Generate documentation
     Doc {
-        /// Input file
-        input: PathBuf,
+        /// Input files or directories (default: the current project's sources)
+        inputs: Vec<PathBuf>,
 
         /// Output format (html, markdown)
         #[arg(long, default_value = "html")]
@@ -171,19 +191,33 @@ enum Commands {
     Lsp,
 
     /// Auto-format source code
+    #[command(alias = "fmt")]
     Format {
-        /// Input file
-        input: PathBuf,
+        /// Input files
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
 
-        /// Check only (don't modify)
+        /// Check only: print a diff and exit nonzero if any file would change
         #[arg(long)]
         check: bool,
+
+        /// Maximum line width
+        #[arg(long, default_value = "80")]
+        line_width: usize,
+
+        /// Spaces per indentation level
+        #[arg(long, default_value = "2")]
+        indent: usize,
     },
 
     /// Explain word behavior
     Explain {
         /// Word name
         word: String,
+
+        /// Also look up words defined in this file (repeatable)
+        #[arg(long = "source", value_name = "FILE")]
+        sources: Vec<PathBuf>,
     },
 
     /// Create new project
@@ -191,17 +225,21 @@ enum Commands {
         /// Project name
         name: String,
 
-        /// Template to use
+        /// Template to use (default, lib)
         #[arg(short, long, default_value = "default")]
         template: String,
     },
 
     /// Initialize current directory as project
-    Init,
+    Init {
+        /// Template to use (default, lib)
+        #[arg(short, long, default_value = "default")]
+        template: String,
+    },
 
-    /// Run test suite
+    /// Run the project's tests
     Test {
-        /// Test pattern
+        /// Only run tests whose path contains this pattern
         pattern: Option<String>,
     },
 }
@@ -216,6 +254,9 @@ fn main() {
         Some(Commands::Compile { .. }) => {
             run_compile(&cli)
         }
+        Some(Commands::Build) => {
+            run_build(&cli)
+        }
         Some(Commands::Run { .. }) => {
             run_execute(&cli)
         }
@@ -246,7 +287,7 @@ fn main() {
         Some(Commands::New { .. }) => {
             run_new(&cli)
         }
-        Some(Commands::Init) => {
+        Some(Commands::Init { .. }) => {
             run_init(&cli)
         }
         Some(Commands::Test { .. }) => {
@@ -294,14 +335,7 @@ fn run_compile(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
     }) = &cli.command
     {
         // Build compile options
-        let target_enum = match target.as_str() {
-            "native" => CompileTarget::Native,
-            "wasm" => CompileTarget::Wasm,
-            "js" => CompileTarget::JavaScript,
-            "llvm-ir" => CompileTarget::LlvmIr,
-            "asm" => CompileTarget::Assembly,
-            _ => CompileTarget::Native,
-        };
+        let target_enum = CompileTarget::from_name(target).unwrap_or(CompileTarget::Native);
 
         let compile_options = CompileOptions {
             optimize_level: *optimize,
@@ -371,8 +405,15 @@ fn run_execute(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
             println!();
         }
 
-        // Read and compile source
-        let source = std::fs::read_to_string(input)?;
+        // Read and compile source, falling back to the project entry point
+        let (input, source) = match input {
+            Some(input) => (input.clone(), std::fs::read_to_string(input)?),
+            None => {
+                let project = Project::current()?;
+                (project.entry_path(), project.source()?)
+            }
+        };
+        let input = &input;
 
         // Compile to bytecode
         let compile_options = CompileOptions {
@@ -445,7 +486,7 @@ fn run_execute(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
 }
 
 fn run_check(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
-    if let Some(Commands::Check { input, strict }) = &cli.command {
+    if let Some(Commands::Check { input, strict, max_errors }) = &cli.command {
         if !cli.quiet {
             println!("→ Fast Forth Type Checker v1.0.0");
             println!();
@@ -461,7 +502,7 @@ fn run_check(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
 
         // Perform type checking
         let compiler = ForthCompiler::new(CompileOptions::default());
-        let errors = compiler.check_file(input)?;
+        let errors = compiler.check_file(input, *max_errors)?;
 
         if errors.is_empty() {
             if !cli.quiet {
@@ -478,9 +519,8 @@ fn run_check(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
                 eprintln!("{}", error);
             }
 
-            if *strict {
-                return Err("Type check failed in strict mode".into());
-            }
+            let count = if errors.len() == 1 { "1 error".to_string() } else { format!("{} errors", errors.len()) };
+            return Err(format!("{} in {}", count, input.display()).into());
         }
     }
 
@@ -488,11 +528,38 @@ fn run_check(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
 }
 
 fn run_lint(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
-    if let Some(Commands::Lint { input }) = &cli.command {
-        println!("Linting {:?}", input);
+    use fastforth::errors::{ErrorFormatter, OutputFormat};
+    use fastforth::lint::{LintConfig, Linter};
+
+    if let Some(Commands::Lint { inputs, max_word_size, allow }) = &cli.command {
+        let linter = Linter::new(LintConfig {
+            max_word_size: *max_word_size,
+            disabled_rules: allow.iter().cloned().collect(),
+        });
+
+        let mut findings = Vec::new();
+        for input in inputs {
+            let source = std::fs::read_to_string(input)
+                .map_err(|e| format!("Failed to read {}: {}", input.display(), e))?;
+            let file = input.display().to_string();
+            findings.extend(linter.lint_source(&source, Some(&file))
+                .map_err(|e| format!("{}: {}", file, e))?);
+        }
 
-        // Placeholder linting
-        println!("\n✓ No issues found");
+        if cli.json {
+            println!("{}", serde_json::to_string_pretty(&findings)?);
+        } else {
+            for finding in &findings {
+                println!("{}", ErrorFormatter::format(finding, OutputFormat::Human));
+            }
+            if findings.is_empty() && !cli.quiet {
+                println!("✓ No issues found");
+            }
+        }
+
+        if !findings.is_empty() {
+            return Err(format!("{} issue(s) found", findings.len()).into());
+        }
     }
 
     Ok(())
@@ -552,12 +619,21 @@ fn run_profile(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
 }
 
 fn run_benchmark(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    use fastforth::performance::FileBenchmark;
+
     if let Some(Commands::Benchmark { input, iterations }) = &cli.command {
-        println!("Benchmarking {:?}", input);
-        println!("  Iterations: {}", iterations);
+        if !cli.quiet {
+            println!("Benchmarking {:?}", input);
+            println!("  Iterations: {}", iterations);
+            println!();
+        }
 
-        // Placeholder benchmarking
-        println!("\n✓ Benchmark complete");
+        let source = std::fs::read_to_string(input)?;
+        let report = FileBenchmark::new(input.display().to_string(), source)
+            .with_iterations(*iterations)
+            .run();
+
+        println!("{}", report.format());
     }
 
     Ok(())
@@ -567,7 +643,7 @@ fn run_doc(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
     use doc_generator::{DocGenerator, DocFormat};
 
     if let Some(Commands::Doc {
-        input,
+        inputs,
         format,
         output,
     }) = &cli.command
@@ -585,17 +661,42 @@ fn run_doc(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
 
         let output_dir = output.clone().unwrap_or_else(|| PathBuf::from("docs"));
 
+        // With no inputs, document the whole project
+        let mut generator = DocGenerator::new(doc_format);
+        let mut files = Vec::new();
+        if inputs.is_empty() {
+            let project = Project::current()?;
+            files = project.source_files()?;
+            generator = generator
+                .with_title(format!("{} Documentation", project.name()))
+                .with_root(&project.root);
+        }
+        for input in inputs {
+            if input.is_dir() {
+                let mut found = Vec::new();
+                project::collect_sources(input, &mut found)?;
+                found.sort();
+                files.extend(found);
+            } else {
+                files.push(input.clone());
+            }
+        }
+        if files.is_empty() {
+            return Err("no Forth source files to document".into());
+        }
+
         if cli.verbose {
             println!("Generating documentation...");
-            println!("  Input: {}", input.display());
+            for file in &files {
+                println!("  Input: {}", file.display());
+            }
             println!("  Format: {}", format);
             println!("  Output: {}", output_dir.display());
             println!();
         }
 
         // Generate documentation
-        let generator = DocGenerator::new(doc_format);
-        let files = generator.generate(input, &output_dir)?;
+        let files = generator.generate(&files, &output_dir)?;
 
         if !cli.quiet {
             println!("✓ Documentation generated in {}", output_dir.display());
@@ -632,32 +733,81 @@ fn run_lsp(_cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
 }
 
 fn run_format(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
-    if let Some(Commands::Format { input, check }) = &cli.command {
-        if *check {
-            println!("Checking formatting of {:?}", input);
-        } else {
-            println!("Formatting {:?}", input);
+    use formatter::{unified_diff, FormatOptions, Formatter};
+
+    if let Some(Commands::Format { inputs, check, line_width, indent }) = &cli.command {
+        let formatter = Formatter::new(FormatOptions {
+            line_width: *line_width,
+            indent_width: *indent,
+        });
+
+        let mut unformatted = 0;
+        for input in inputs {
+            let source = std::fs::read_to_string(input)
+                .map_err(|e| format!("Failed to read {}: {}", input.display(), e))?;
+            let formatted = formatter.format(&source)
+                .map_err(|e| format!("{}: {}", input.display(), e))?;
+
+            if formatted == source {
+                if cli.verbose {
+                    println!("✓ {} already formatted", input.display());
+                }
+                continue;
+            }
+
+            if *check {
+                unformatted += 1;
+                print!("{}", unified_diff(&input.display().to_string(), &source, &formatted));
+            } else {
+                std::fs::write(input, &formatted)?;
+                if !cli.quiet {
+                    println!("✓ Formatted {}", input.display());
+                }
+            }
         }
 
-        // Placeholder formatting
-        println!("\n✓ Already formatted");
+        if unformatted > 0 {
+            return Err(format!("{} file(s) need formatting", unformatted).into());
+        }
     }
 
     Ok(())
 }
 
 fn run_explain(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
-    if let Some(Commands::Explain { word }) = &cli.command {
-        println!("Word: {}", word);
-        println!("Stack Effect: ( a b -- c )");
-        println!();
-        println!("Description:");
-        println!("  Placeholder explanation for word '{}'", word);
-        println!();
-        println!("Example:");
-        println!("  5 3 {} . \\ Prints result", word);
-        println!();
-        println!("Learn more: https://fastforth.dev/docs/{}", word.to_lowercase());
+    use explain::{render, WordDatabase};
+
+    if let Some(Commands::Explain { word, sources }) = &cli.command {
+        let mut database = WordDatabase::core();
+        if let Some(project) = Project::discover(&std::env::current_dir()?)? {
+            for file in project.source_files()? {
+                database.add_file(&file, &project.relative(&file).display().to_string())?;
+            }
+        }
+        for source in sources {
+            database.add_file(source, &source.display().to_string())?;
+        }
+
+        let found = database.lookup(word);
+        if found.is_empty() {
+            let mut message = format!("no documentation for '{}'", word);
+            let suggestions = database.suggestions(word);
+            if !suggestions.is_empty() {
+                message.push_str(&format!("; did you mean {}?", suggestions.join(", ")));
+            }
+            return Err(message.into());
+        }
+
+        if cli.json {
+            println!("{}", serde_json::to_string_pretty(&found)?);
+        } else {
+            for (index, info) in found.iter().enumerate() {
+                if index > 0 {
+                    println!();
+                }
+                print!("{}", render(info));
+            }
+        }
     }
 
     Ok(())
@@ -665,38 +815,148 @@ fn run_explain(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
 
 fn run_new(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
     if let Some(Commands::New { name, template }) = &cli.command {
-        println!("Creating new project: {}", name);
-        println!("  Template: {}", template);
-
-        // Placeholder project creation
-        println!("\n✓ Project created successfully");
-        println!("\nNext steps:");
-        println!("  cd {}", name);
-        println!("  fastforth run main.fth");
+        let template = Template::from_name(template)?;
+        project::validate_name(name)?;
+
+        let root = PathBuf::from(name);
+        if root.exists() && std::fs::read_dir(&root)?.next().is_some() {
+            return Err(format!("destination '{}' already exists and is not empty", name).into());
+        }
+
+        let created = project::scaffold(&root, name, template)?;
+
+        if !cli.quiet {
+            println!("Creating new project: {}", name);
+            for file in &created {
+                println!("  created {}", root.join(file).display());
+            }
+            println!("\n✓ Project created successfully");
+            println!("\nNext steps:");
+            println!("  cd {}", name);
+            println!("  fastforth run");
+            println!("  fastforth test");
+        }
     }
 
     Ok(())
 }
 
-fn run_init(_cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
-    println!("Initializing Fast Forth project in current directory");
+fn run_init(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(Commands::Init { template }) = &cli.command {
+        let template = Template::from_name(template)?;
+        let root = std::env::current_dir()?;
+        let name = root
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .ok_or("cannot infer a project name from the current directory")?;
+
+        let created = project::scaffold(&root, &name, template)?;
 
-    // Placeholder initialization
-    println!("\n✓ Project initialized");
+        if !cli.quiet {
+            println!("Initializing Fast Forth project '{}' in current directory", name);
+            for file in &created {
+                println!("  created {}", file.display());
+            }
+            println!("\n✓ Project initialized");
+        }
+    }
+
+    Ok(())
+}
+
+fn run_build(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let project = Project::current()?;
+    let manifest = &project.manifest;
+    let source = project.source()?;
+
+    // Only native executables can be built; the other targets are compile-only
+    if CompileTarget::from_name(&manifest.build.target) != Some(CompileTarget::Native) {
+        return Err(format!(
+            "cannot build target '{}': only native executables can be built (use `fastforth compile --target {}`)",
+            manifest.build.target, manifest.build.target
+        )
+        .into());
+    }
+
+    if !cli.quiet {
+        println!("Building {} v{}", project.name(), manifest.package.version);
+    }
+
+    let start = std::time::Instant::now();
+    let output = project.output_path();
+    if let Some(dir) = output.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let pipeline = fastforth::CompilationPipeline::new(manifest.optimization_level());
+    let executable = pipeline.build_executable(&source, &output)?;
+
+    if !cli.quiet {
+        println!(
+            "✓ Built {} ({:.1}ms)",
+            project.relative(&executable).display(),
+            start.elapsed().as_secs_f64() * 1000.0
+        );
+    }
 
     Ok(())
 }
 
 fn run_test(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
     if let Some(Commands::Test { pattern }) = &cli.command {
-        println!("Running tests");
+        let project = Project::current()?;
+        let tests = project.test_files(pattern.as_deref())?;
+        let runner = TestRunner::new(&project)?;
+        let text_output = !cli.quiet && !cli.json;
 
-        if let Some(pat) = pattern {
-            println!("  Pattern: {}", pat);
+        if text_output {
+            println!("Running {} test file(s)", tests.len());
         }
 
-        // Placeholder test execution
-        println!("\n✓ All tests passed");
+        let mut reports = Vec::new();
+        for test in &tests {
+            let name = project.relative(test).display().to_string();
+            let report = runner.run_file(test, &name)?;
+
+            if text_output {
+                if report.failed == 0 {
+                    println!("  test {} ... ok ({} passed)", name, report.passed);
+                } else {
+                    println!(
+                        "  test {} ... FAILED ({} passed, {} failed)",
+                        name, report.passed, report.failed
+                    );
+                }
+            }
+            reports.push(report);
+        }
+
+        let summary = TestSummary::new(reports);
+
+        if cli.json {
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+        } else {
+            if summary.failed > 0 {
+                eprintln!("\nfailures:");
+                for report in &summary.files {
+                    for (line, message) in report.failures() {
+                        eprintln!("  {}:{}: {}", report.file, line, message);
+                    }
+                }
+            }
+
+            if !cli.quiet {
+                println!(
+                    "\ntest result: {}. {} passed; {} failed",
+                    if summary.failed == 0 { "ok" } else { "FAILED" },
+                    summary.passed,
+                    summary.failed
+                );
+            }
+        }
+
+        if summary.failed > 0 {
+            return Err(format!("{} test(s) failed", summary.failed).into());
+        }
     }
 
     Ok(())
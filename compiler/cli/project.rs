@@ -0,0 +1,772 @@
+// project.rs - Forth.toml project support
+// Loads project manifests, assembles project sources, scaffolds new
+// projects and runs the tests in a project's tests/ directory
+
+use crate::compiler::CompileTarget;
+use anyhow::{bail, Context, Result};
+use fastforth::{CompilationMode, CompilationPipeline, OptimizationLevel};
+use fastforth_frontend::ast::TestCase;
+use fastforth_frontend::{parse_program, Definition, ForthError, Program, Word};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Name of the project manifest file
+pub const MANIFEST_FILE: &str = "Forth.toml";
+
+/// Contents of a `Forth.toml` manifest
+///
+/// ```toml
+/// [package]
+/// name = "hello"
+/// version = "0.1.0"
+/// entry = "src/main.fth"
+///
+/// [build]
+/// include = ["src"]
+/// optimize = 2
+/// target = "native"
+///
+/// [dependencies]
+/// mathlib = { path = "../mathlib" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub package: PackageConfig,
+    #[serde(default)]
+    pub build: BuildConfig,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+}
+
+/// `[package]` section
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageConfig {
+    pub name: String,
+    #[serde(default = "default_version")]
+    pub version: String,
+    /// Source file compiled and run, relative to the project root
+    #[serde(default = "default_entry")]
+    pub entry: PathBuf,
+}
+
+/// `[build]` section
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildConfig {
+    /// Directories searched by `include` and `require`
+    #[serde(default = "default_include")]
+    pub include: Vec<PathBuf>,
+    /// Optimization level (0-3)
+    #[serde(default = "default_optimize")]
+    pub optimize: u8,
+    /// Target platform
+    #[serde(default = "default_target")]
+    pub target: String,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        BuildConfig {
+            include: default_include(),
+            optimize: default_optimize(),
+            target: default_target(),
+        }
+    }
+}
+
+/// A dependency on another project in a local directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Dependency {
+    pub path: PathBuf,
+}
+
+fn default_version() -> String {
+    "0.1.0".to_string()
+}
+
+fn default_entry() -> PathBuf {
+    PathBuf::from("src/main.fth")
+}
+
+fn default_include() -> Vec<PathBuf> {
+    vec![PathBuf::from("src")]
+}
+
+fn default_optimize() -> u8 {
+    2
+}
+
+fn default_target() -> String {
+    "native".to_string()
+}
+
+impl Manifest {
+    /// Manifest for a fresh project
+    pub fn new(name: &str, entry: &str) -> Self {
+        Manifest {
+            package: PackageConfig {
+                name: name.to_string(),
+                version: default_version(),
+                entry: PathBuf::from(entry),
+            },
+            build: BuildConfig::default(),
+            dependencies: BTreeMap::new(),
+        }
+    }
+
+    /// Parse and validate manifest text
+    pub fn parse(text: &str) -> Result<Self> {
+        let manifest: Manifest = toml::from_str(text)?;
+
+        validate_name(&manifest.package.name)?;
+        if manifest.build.optimize > 3 {
+            bail!("optimize must be between 0 and 3, got {}", manifest.build.optimize);
+        }
+        if CompileTarget::from_name(&manifest.build.target).is_none() {
+            bail!(
+                "unknown target '{}' (expected one of: native, wasm, js, llvm-ir, asm)",
+                manifest.build.target
+            );
+        }
+
+        Ok(manifest)
+    }
+
+    /// Serialize back to TOML
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// Optimization level for the compilation pipeline
+    pub fn optimization_level(&self) -> OptimizationLevel {
+        match self.build.optimize {
+            0 => OptimizationLevel::None,
+            1 => OptimizationLevel::Basic,
+            2 => OptimizationLevel::Standard,
+            _ => OptimizationLevel::Aggressive,
+        }
+    }
+}
+
+/// Check that a project name is usable as a directory and file name
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        bail!("project name must not be empty");
+    }
+    if name.starts_with('.') || name.contains(|c: char| c.is_whitespace() || c == '/' || c == '\\') {
+        bail!("invalid project name '{}'", name);
+    }
+    Ok(())
+}
+
+/// A project on disk: its root directory and manifest
+#[derive(Debug, Clone)]
+pub struct Project {
+    pub root: PathBuf,
+    pub manifest: Manifest,
+}
+
+impl Project {
+    /// Load the project whose `Forth.toml` is in `root`
+    pub fn load(root: &Path) -> Result<Self> {
+        let manifest_path = root.join(MANIFEST_FILE);
+        let text = std::fs::read_to_string(&manifest_path)
+            .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+        let manifest = Manifest::parse(&text)
+            .with_context(|| format!("Invalid manifest {}", manifest_path.display()))?;
+
+        Ok(Project {
+            root: root.to_path_buf(),
+            manifest,
+        })
+    }
+
+    /// Find the project containing `start`, searching parent directories
+    pub fn discover(start: &Path) -> Result<Option<Self>> {
+        for dir in start.ancestors() {
+            if dir.join(MANIFEST_FILE).is_file() {
+                return Self::load(dir).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Like [`Project::discover`] from the current directory, but a missing
+    /// manifest is an error
+    pub fn current() -> Result<Self> {
+        let cwd = std::env::current_dir()?;
+        Self::discover(&cwd)?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "could not find {} in {} or any parent directory",
+                MANIFEST_FILE,
+                cwd.display()
+            )
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.manifest.package.name
+    }
+
+    pub fn entry_path(&self) -> PathBuf {
+        self.root.join(&self.manifest.package.entry)
+    }
+
+    /// Where `build` writes the executable
+    pub fn output_path(&self) -> PathBuf {
+        self.root.join("target").join(self.name())
+    }
+
+    /// Full program source: dependencies first, then the entry file, with
+    /// `include` and `require` lines expanded
+    pub fn source(&self) -> Result<String> {
+        let mut loader = SourceLoader::default();
+        let mut out = String::new();
+        loader.load_project(self, &mut out)?;
+        Ok(out)
+    }
+
+    /// Test files under `tests/`, optionally filtered by a substring of
+    /// their path relative to the project root
+    pub fn test_files(&self, pattern: Option<&str>) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        let tests_dir = self.root.join("tests");
+        if tests_dir.is_dir() {
+            collect_sources(&tests_dir, &mut files)?;
+        }
+
+        if let Some(pattern) = pattern {
+            files.retain(|file| self.relative(file).to_string_lossy().contains(pattern));
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// The entry file and every source file under the include directories
+    pub fn source_files(&self) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for dir in &self.manifest.build.include {
+            let dir = self.root.join(dir);
+            if dir.is_dir() {
+                collect_sources(&dir, &mut files)?;
+            }
+        }
+        files.sort();
+        let entry = self.entry_path();
+        if entry.is_file() && !files.contains(&entry) {
+            files.insert(0, entry);
+        }
+        Ok(files)
+    }
+
+    /// Path relative to the project root, for display
+    pub fn relative<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    fn search_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self
+            .manifest
+            .build
+            .include
+            .iter()
+            .map(|dir| self.root.join(dir))
+            .collect();
+        paths.push(self.root.clone());
+        paths
+    }
+}
+
+/// Forth source files under `dir`, recursively
+pub fn collect_sources(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_sources(&path, files)?;
+        } else if matches!(path.extension().and_then(|e| e.to_str()), Some("fth" | "fs" | "forth")) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Concatenates project sources, expanding `include FILE` and `require FILE`
+///
+/// Directives must be alone on their line. `require` skips files that were
+/// already loaded; `include` always inserts the file.
+#[derive(Default)]
+struct SourceLoader {
+    loaded_files: HashSet<PathBuf>,
+    loaded_projects: HashSet<PathBuf>,
+    file_stack: Vec<PathBuf>,
+    project_stack: Vec<PathBuf>,
+}
+
+impl SourceLoader {
+    fn load_project(&mut self, project: &Project, out: &mut String) -> Result<()> {
+        let root = canonical(&project.root)?;
+        if self.project_stack.contains(&root) {
+            bail!("dependency cycle through '{}'", project.name());
+        }
+        if !self.loaded_projects.insert(root.clone()) {
+            return Ok(());
+        }
+
+        self.project_stack.push(root);
+        for (name, dependency) in &project.manifest.dependencies {
+            let dependency_root = project.root.join(&dependency.path);
+            let dependency_project = Project::load(&dependency_root)
+                .with_context(|| format!("Failed to load dependency '{}'", name))?;
+            self.load_project(&dependency_project, out)?;
+        }
+
+        let search_paths = project.search_paths();
+        self.load_file(&project.entry_path(), &search_paths, out)?;
+        self.project_stack.pop();
+        Ok(())
+    }
+
+    fn load_file(&mut self, path: &Path, search_paths: &[PathBuf], out: &mut String) -> Result<()> {
+        let file = canonical(path)?;
+        if self.file_stack.contains(&file) {
+            bail!("include cycle through {}", path.display());
+        }
+
+        let text = std::fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        self.loaded_files.insert(file.clone());
+        self.file_stack.push(file.clone());
+
+        for line in text.lines() {
+            let mut tokens = line.split_whitespace();
+            let directive = tokens.next().map(str::to_lowercase);
+            let target = tokens.next();
+
+            match (directive.as_deref(), target, tokens.next()) {
+                (Some(kind @ ("include" | "require")), Some(target), None) => {
+                    let resolved = resolve_include(target, &file, search_paths).with_context(|| {
+                        format!("{}: cannot find '{}'", path.display(), target)
+                    })?;
+                    if kind == "require" && self.loaded_files.contains(&canonical(&resolved)?) {
+                        continue;
+                    }
+                    self.load_file(&resolved, search_paths, out)?;
+                }
+                _ => {
+                    out.push_str(line);
+                    out.push('\n');
+                }
+            }
+        }
+
+        self.file_stack.pop();
+        Ok(())
+    }
+}
+
+fn resolve_include(target: &str, including_file: &Path, search_paths: &[PathBuf]) -> Option<PathBuf> {
+    let local = including_file.parent().map(|dir| dir.join(target));
+    local
+        .into_iter()
+        .chain(search_paths.iter().map(|dir| dir.join(target)))
+        .find(|candidate| candidate.is_file())
+}
+
+fn canonical(path: &Path) -> Result<PathBuf> {
+    path.canonicalize()
+        .with_context(|| format!("Failed to resolve {}", path.display()))
+}
+
+/// Project layout created by `new` and `init`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Template {
+    /// Program with an entry point in `src/main.fth`
+    Binary,
+    /// Word library in `src/lib.fth`
+    Library,
+}
+
+impl Template {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "default" | "bin" => Ok(Template::Binary),
+            "lib" | "library" => Ok(Template::Library),
+            _ => bail!("unknown template '{}' (expected: default, bin, lib)", name),
+        }
+    }
+
+    fn files(self, name: &str) -> Vec<(&'static str, String)> {
+        let (entry, entry_source, test_source) = match self {
+            Template::Binary => (
+                "src/main.fth",
+                format!(
+                    "\\ {} - program entry point\n\n: answer ( -- n ) 42 ;\n\nanswer\n",
+                    name
+                ),
+                "T{ answer -> 42 }T\n".to_string(),
+            ),
+            Template::Library => (
+                "src/lib.fth",
+                format!("\\ {} - word library\n\n: square ( n -- n*n ) dup * ;\n", name),
+                "T{ 3 square -> 9 }T\nT{ -4 square -> 16 }T\n".to_string(),
+            ),
+        };
+
+        let manifest = Manifest::new(name, entry)
+            .to_toml()
+            .expect("default manifest serializes");
+        let test_file = match self {
+            Template::Binary => "tests/answer.fth",
+            Template::Library => "tests/square.fth",
+        };
+
+        vec![
+            (MANIFEST_FILE, manifest),
+            (entry, entry_source),
+            (test_file, format!("{}{}", TEST_FILE_HEADER, test_source)),
+            (".gitignore", "/target\n".to_string()),
+        ]
+    }
+}
+
+const TEST_FILE_HEADER: &str = "\\ Tests run with the project's definitions loaded.\n\
+\\ T{ code -> expected }T passes when both sides leave the same stack.\n\n";
+
+/// Write the template's files into `root`, leaving existing files alone
+///
+/// Returns the files that were created.
+pub fn scaffold(root: &Path, name: &str, template: Template) -> Result<Vec<PathBuf>> {
+    validate_name(name)?;
+    if root.join(MANIFEST_FILE).exists() {
+        bail!("{} already exists in {}", MANIFEST_FILE, root.display());
+    }
+
+    let mut created = Vec::new();
+    for (file, contents) in template.files(name) {
+        let path = root.join(file);
+        if path.exists() {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        std::fs::write(&path, contents)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        created.push(PathBuf::from(file));
+    }
+
+    Ok(created)
+}
+
+/// Result of one test
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", content = "message", rename_all = "lowercase")]
+pub enum TestOutcome {
+    Passed,
+    Failed(String),
+}
+
+/// One test within a file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CaseReport {
+    /// Line of the `T{`, or 1 for a file of flag checks
+    pub line: usize,
+    #[serde(flatten)]
+    pub outcome: TestOutcome,
+}
+
+/// Results for one test file
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    pub file: String,
+    pub passed: usize,
+    pub failed: usize,
+    pub cases: Vec<CaseReport>,
+}
+
+impl FileReport {
+    fn new(file: &str, cases: Vec<CaseReport>) -> Self {
+        let passed = cases.iter().filter(|c| c.outcome == TestOutcome::Passed).count();
+        FileReport {
+            file: file.to_string(),
+            passed,
+            failed: cases.len() - passed,
+            cases,
+        }
+    }
+
+    /// Failed cases with their messages
+    pub fn failures(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.cases.iter().filter_map(|case| match &case.outcome {
+            TestOutcome::Failed(message) => Some((case.line, message.as_str())),
+            TestOutcome::Passed => None,
+        })
+    }
+}
+
+/// Results for a whole test run, as printed by `fastforth test --json`
+#[derive(Debug, Clone, Serialize)]
+pub struct TestSummary {
+    pub passed: usize,
+    pub failed: usize,
+    pub files: Vec<FileReport>,
+}
+
+impl TestSummary {
+    pub fn new(files: Vec<FileReport>) -> Self {
+        TestSummary {
+            passed: files.iter().map(|f| f.passed).sum(),
+            failed: files.iter().map(|f| f.failed).sum(),
+            files,
+        }
+    }
+}
+
+/// Runs test files against a project's definitions with the JIT
+///
+/// Each `T{ code -> expected }T` case runs `code` and `expected` on empty
+/// stacks and compares the results. Top-level code outside test cases is
+/// ignored in such files. A file without test cases is run as a single
+/// test that passes when it leaves only true (non-zero) flags.
+pub struct TestRunner {
+    prelude: Program,
+    optimization_level: OptimizationLevel,
+}
+
+impl TestRunner {
+    /// Load the project's definitions; its top-level code is not run
+    pub fn new(project: &Project) -> Result<Self> {
+        let source = project.source()?;
+        let prelude = parse_program(&source)
+            .map_err(|e| anyhow::anyhow!("Failed to parse project sources: {}", e))?;
+
+        Ok(TestRunner {
+            prelude,
+            optimization_level: project.manifest.optimization_level(),
+        })
+    }
+
+    /// Compile and run a test file, reporting it under `name`
+    pub fn run_file(&self, path: &Path, name: &str) -> Result<FileReport> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(self.run_source(&source, name))
+    }
+
+    /// Compile and run test source
+    pub fn run_source(&self, source: &str, name: &str) -> FileReport {
+        let test = match parse_program(source) {
+            Ok(test) => test,
+            Err(e) => {
+                let line = e.location().map_or(1, |location| location.line);
+                let outcome = TestOutcome::Failed(format!("parse error: {}", e));
+                return FileReport::new(name, vec![CaseReport { line, outcome }]);
+            }
+        };
+
+        let mut definitions: Vec<Definition> = self
+            .prelude
+            .definitions
+            .iter()
+            .filter(|def| !test.definitions.iter().any(|own| own.name == def.name))
+            .cloned()
+            .collect();
+        definitions.extend(test.definitions);
+
+        let cases = if test.tests.is_empty() {
+            let outcome = self.check_flags(&definitions, test.top_level_code);
+            vec![CaseReport { line: 1, outcome }]
+        } else {
+            test.tests
+                .into_iter()
+                .map(|case| CaseReport {
+                    line: case.location.line,
+                    outcome: self.check_case(&definitions, case),
+                })
+                .collect()
+        };
+
+        FileReport::new(name, cases)
+    }
+
+    fn check_case(&self, definitions: &[Definition], case: TestCase) -> TestOutcome {
+        let actual = match self.execute(definitions, case.body) {
+            Ok(stack) => stack,
+            Err(e) => return TestOutcome::Failed(e),
+        };
+        let expected = match self.execute(definitions, case.expected) {
+            Ok(stack) => stack,
+            Err(e) => return TestOutcome::Failed(format!("in expected results: {}", e)),
+        };
+
+        if actual == expected {
+            TestOutcome::Passed
+        } else {
+            TestOutcome::Failed(format!(
+                "expected {}, got {}",
+                format_stack(&expected),
+                format_stack(&actual)
+            ))
+        }
+    }
+
+    fn check_flags(&self, definitions: &[Definition], code: Vec<Word>) -> TestOutcome {
+        let stack = match self.execute(definitions, code) {
+            Ok(stack) => stack,
+            Err(e) => return TestOutcome::Failed(e),
+        };
+
+        if stack.is_empty() {
+            return TestOutcome::Failed("no result left on the stack".to_string());
+        }
+        let failed: Vec<String> = stack
+            .iter()
+            .enumerate()
+            .filter(|(_, &flag)| flag == 0)
+            .map(|(i, _)| (i + 1).to_string())
+            .collect();
+        if failed.is_empty() {
+            TestOutcome::Passed
+        } else {
+            TestOutcome::Failed(format!(
+                "false flag at stack position {} (stack: {})",
+                failed.join(", "),
+                format_stack(&stack)
+            ))
+        }
+    }
+
+    /// Run code on an empty stack and return the stack it leaves
+    fn execute(&self, definitions: &[Definition], code: Vec<Word>) -> std::result::Result<Vec<i64>, String> {
+        let program = Program {
+            definitions: definitions.to_vec(),
+            top_level_code: code,
+            tests: Vec::new(),
+            ..Program::new()
+        };
+
+        let mut pipeline = CompilationPipeline::new(self.optimization_level);
+        pipeline
+            .compile_program(&program, CompilationMode::JIT)
+            .map(|result| result.stack)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Render a stack as `<depth> bottom ... top`
+fn format_stack(stack: &[i64]) -> String {
+    let mut out = format!("<{}>", stack.len());
+    for value in stack {
+        out.push(' ');
+        out.push_str(&value.to_string());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fastforth-project-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_manifest_defaults() {
+        let manifest = Manifest::parse("[package]\nname = \"demo\"\n").unwrap();
+        assert_eq!(manifest.package.entry, PathBuf::from("src/main.fth"));
+        assert_eq!(manifest.build.include, vec![PathBuf::from("src")]);
+        assert_eq!(manifest.build.optimize, 2);
+        assert_eq!(manifest.build.target, "native");
+        assert!(manifest.dependencies.is_empty());
+    }
+
+    #[test]
+    fn test_manifest_validation() {
+        assert!(Manifest::parse("[package]\nname = \"a b\"\n").is_err());
+        assert!(Manifest::parse("[package]\nname = \"demo\"\n[build]\noptimize = 7\n").is_err());
+        assert!(Manifest::parse("[package]\nname = \"demo\"\n[build]\ntarget = \"z80\"\n").is_err());
+
+        let manifest = Manifest::new("demo", "src/main.fth");
+        assert_eq!(Manifest::parse(&manifest.to_toml().unwrap()).unwrap(), manifest);
+    }
+
+    #[test]
+    fn test_source_expands_includes_and_dependencies() {
+        let dir = temp_dir("sources");
+        let lib = dir.join("mathlib");
+        let app = dir.join("app");
+
+        scaffold(&lib, "mathlib", Template::Library).unwrap();
+        scaffold(&app, "app", Template::Binary).unwrap();
+        std::fs::write(
+            app.join(MANIFEST_FILE),
+            "[package]\nname = \"app\"\n\n[dependencies]\nmathlib = { path = \"../mathlib\" }\n",
+        )
+        .unwrap();
+        std::fs::write(app.join("src/util.fth"), ": twice ( n -- n ) 2 * ;\n").unwrap();
+        std::fs::write(
+            app.join("src/main.fth"),
+            "require util.fth\nrequire util.fth\n4 square twice\n",
+        )
+        .unwrap();
+
+        let project = Project::load(&app).unwrap();
+        let source = project.source().unwrap();
+        assert!(source.find(": square").unwrap() < source.find(": twice").unwrap());
+        assert_eq!(source.matches(": twice").count(), 1);
+        assert_eq!(crate::execute::execute_program(&source, false).unwrap(), 32);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_scaffolded_tests_pass() {
+        let dir = temp_dir("scaffold");
+        let created = scaffold(&dir, "demo", Template::Binary).unwrap();
+        assert!(created.contains(&PathBuf::from(MANIFEST_FILE)));
+        assert!(scaffold(&dir, "demo", Template::Binary).is_err());
+
+        let project = Project::discover(&dir.join("src")).unwrap().unwrap();
+        assert_eq!(project.name(), "demo");
+
+        let runner = TestRunner::new(&project).unwrap();
+        let tests = project.test_files(None).unwrap();
+        assert_eq!(tests.len(), 1);
+        let report = runner.run_file(&tests[0], "answer.fth").unwrap();
+        assert_eq!((report.passed, report.failed), (1, 0));
+        assert!(project.test_files(Some("nomatch")).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_case_failures_report_lines() {
+        let dir = temp_dir("cases");
+        scaffold(&dir, "demo", Template::Library).unwrap();
+        let runner = TestRunner::new(&Project::load(&dir).unwrap()).unwrap();
+
+        let report = runner.run_source(
+            "T{ 3 square -> 9 }T\n\nT{ 3 square -> 10 }T\nT{ 1 2 -> 1 2 }T\nT{ nope -> }T\n",
+            "t.fth",
+        );
+        assert_eq!((report.passed, report.failed), (2, 2));
+        let failures: Vec<_> = report.failures().collect();
+        assert_eq!(failures[0], (3, "expected <1> 10, got <1> 9"));
+        assert_eq!(failures[1].0, 5);
+
+        let flags = runner.run_source("2 square 4 = 0", "flags.fth");
+        assert_eq!(flags.failed, 1);
+
+        let json = serde_json::to_value(TestSummary::new(vec![report])).unwrap();
+        assert_eq!(json["failed"], 2);
+        assert_eq!(json["files"][0]["cases"][1]["status"], "failed");
+        assert_eq!(json["files"][0]["cases"][1]["line"], 3);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
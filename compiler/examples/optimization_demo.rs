@@ -113,15 +113,15 @@ fn demo_inlining() {
     let quad = WordDef::new(
         "quad".to_string(),
         vec![
-            Instruction::Call("square".to_string()),
-            Instruction::Call("square".to_string()),
+            Instruction::Call("square".into()),
+            Instruction::Call("square".into()),
         ],
     );
     ir.add_word(quad);
 
     ir.main = vec![
         Instruction::Literal(5),
-        Instruction::Call("quad".to_string()),
+        Instruction::Call("quad".into()),
     ];
 
     println!("Example: Nested inlining (5 quad)");
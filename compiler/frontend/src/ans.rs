@@ -0,0 +1,431 @@
+//! ANS Forth Word Sets
+//!
+//! The standard's word sets as name lists, used to tell standard words
+//! from extensions (`--ans-strict`), and the answers `environment?` gives.
+//! Extension words are folded into their word set except for CORE-EXT,
+//! which is listed on its own as the standard does for compliance claims.
+//!
+//! [`claim`] records how much of each word set the compiler implements;
+//! the compliance suite (`fastforth test --ans`) checks every claimed word.
+//!
+//! `environment?` is resolved while parsing: `"NAME" environment?` becomes
+//! the attribute's values followed by `true`, or `false` for an unknown
+//! query. The query string must be a literal, as the number of results
+//! depends on it.
+
+use crate::ast::{Program, SourceLocation, Word};
+use crate::error::{ForthError, Result};
+use std::collections::HashSet;
+
+/// Bytes in the pictured numeric output buffer of the runtime
+pub const HOLD_SIZE: i64 = 136;
+
+/// An ANS Forth word set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum WordSet {
+    Core,
+    CoreExt,
+    Block,
+    Double,
+    Exception,
+    Facility,
+    File,
+    Float,
+    Locals,
+    Memory,
+    SearchOrder,
+    String,
+    Tools,
+}
+
+impl WordSet {
+    pub const ALL: [WordSet; 13] = [
+        WordSet::Core,
+        WordSet::CoreExt,
+        WordSet::Block,
+        WordSet::Double,
+        WordSet::Exception,
+        WordSet::Facility,
+        WordSet::File,
+        WordSet::Float,
+        WordSet::Locals,
+        WordSet::Memory,
+        WordSet::SearchOrder,
+        WordSet::String,
+        WordSet::Tools,
+    ];
+
+    /// Name as used by `environment?` and in compliance reports
+    pub fn name(self) -> &'static str {
+        match self {
+            WordSet::Core => "CORE",
+            WordSet::CoreExt => "CORE-EXT",
+            WordSet::Block => "BLOCK",
+            WordSet::Double => "DOUBLE",
+            WordSet::Exception => "EXCEPTION",
+            WordSet::Facility => "FACILITY",
+            WordSet::File => "FILE",
+            WordSet::Float => "FLOATING",
+            WordSet::Locals => "LOCALS",
+            WordSet::Memory => "MEMORY-ALLOC",
+            WordSet::SearchOrder => "SEARCH-ORDER",
+            WordSet::String => "STRING",
+            WordSet::Tools => "TOOLS",
+        }
+    }
+
+    /// Look up a word set by name, ignoring case
+    pub fn from_name(name: &str) -> Option<WordSet> {
+        Self::ALL.into_iter().find(|set| set.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The standard words of the set, lowercase
+    pub fn words(self) -> &'static [&'static str] {
+        match self {
+            WordSet::Core => CORE,
+            WordSet::CoreExt => CORE_EXT,
+            WordSet::Block => BLOCK,
+            WordSet::Double => DOUBLE,
+            WordSet::Exception => EXCEPTION,
+            WordSet::Facility => FACILITY,
+            WordSet::File => FILE,
+            WordSet::Float => FLOAT,
+            WordSet::Locals => LOCALS,
+            WordSet::Memory => MEMORY,
+            WordSet::SearchOrder => SEARCH_ORDER,
+            WordSet::String => STRING,
+            WordSet::Tools => TOOLS,
+        }
+    }
+
+    /// Whether the set contains `word` (any case)
+    pub fn contains(self, word: &str) -> bool {
+        let word = word.to_lowercase();
+        self.words().contains(&word.as_str())
+    }
+}
+
+/// How much of a word set the compiler claims to implement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Claim {
+    /// Every word of the set
+    Complete,
+    /// These words of the set
+    Partial(&'static [&'static str]),
+    /// None of it
+    NotClaimed,
+}
+
+impl Claim {
+    /// Whether the claim covers `word` of its set
+    pub fn covers(self, set: WordSet, word: &str) -> bool {
+        match self {
+            Claim::Complete => set.contains(word),
+            Claim::Partial(words) => words.contains(&word.to_lowercase().as_str()),
+            Claim::NotClaimed => false,
+        }
+    }
+
+    /// The claimed words of `set`
+    pub fn words(self, set: WordSet) -> &'static [&'static str] {
+        match self {
+            Claim::Complete => set.words(),
+            Claim::Partial(words) => words,
+            Claim::NotClaimed => &[],
+        }
+    }
+}
+
+/// The compliance claim for a word set
+pub fn claim(set: WordSet) -> Claim {
+    match set {
+        WordSet::Core => Claim::Partial(CLAIMED_CORE),
+        WordSet::CoreExt => Claim::Partial(CLAIMED_CORE_EXT),
+        WordSet::Block => Claim::Partial(CLAIMED_BLOCK),
+        WordSet::File => Claim::Partial(CLAIMED_FILE),
+        WordSet::Memory => Claim::Complete,
+        _ => Claim::NotClaimed,
+    }
+}
+
+/// The word set that defines `word`, if it is standard
+pub fn standard_word_set(word: &str) -> Option<WordSet> {
+    WordSet::ALL.into_iter().find(|set| set.contains(word))
+}
+
+/// Whether `word` is defined by one of the standard word sets
+pub fn is_standard_word(word: &str) -> bool {
+    standard_word_set(word).is_some()
+}
+
+/// The values `environment?` leaves for a query, or `None` if unknown
+///
+/// Word set queries answer whether the complete set is present.
+pub fn environment_query(name: &str) -> Option<Vec<i64>> {
+    let value = match name.to_uppercase().as_str() {
+        "/COUNTED-STRING" => 255,
+        "/HOLD" => HOLD_SIZE,
+        "ADDRESS-UNIT-BITS" => 8,
+        "FLOORED" => 0,
+        "MAX-CHAR" => 255,
+        "MAX-N" => i64::MAX,
+        "MAX-U" => -1,
+        "MAX-D" => return Some(vec![-1, i64::MAX]),
+        "MAX-UD" => return Some(vec![-1, -1]),
+        "RETURN-STACK-CELLS" | "STACK-CELLS" => 1024,
+        other => {
+            let set = WordSet::from_name(other)?;
+            if claim(set) == Claim::Complete { -1 } else { 0 }
+        }
+    };
+    Some(vec![value])
+}
+
+/// Uses of words that neither a standard word set nor the program
+/// itself defines, in source order (what `--ans-strict` warns about)
+pub fn nonstandard_words(program: &Program) -> Vec<(String, SourceLocation)> {
+    let mut defined: HashSet<String> = program.definitions.iter().map(|def| def.name.to_lowercase()).collect();
+    defined.extend(program.code_words.iter().map(|word| word.name.to_lowercase()));
+    for word in &program.top_level_code {
+        if let Word::Variable { name, .. } | Word::Constant { name, .. } = word {
+            defined.insert(name.to_lowercase());
+        }
+    }
+
+    let mut uses = Vec::new();
+    for def in &program.definitions {
+        collect_nonstandard(&def.body, &defined, &mut uses);
+    }
+    collect_nonstandard(&program.top_level_code, &defined, &mut uses);
+    for test in &program.tests {
+        collect_nonstandard(&test.body, &defined, &mut uses);
+        collect_nonstandard(&test.expected, &defined, &mut uses);
+    }
+    uses.sort_by_key(|(_, location)| (location.line, location.column));
+    uses
+}
+
+fn collect_nonstandard(words: &[Word], defined: &HashSet<String>, uses: &mut Vec<(String, SourceLocation)>) {
+    for word in words {
+        match word {
+            Word::WordRef { name, location } if !is_standard_word(name) && !defined.contains(&name.to_lowercase()) => {
+                uses.push((name.to_string(), location.clone()));
+            }
+            Word::TaskSpawn { location, .. } => uses.push(("task:".to_string(), location.clone())),
+            Word::If { then_branch, else_branch } => {
+                collect_nonstandard(then_branch, defined, uses);
+                if let Some(else_branch) = else_branch {
+                    collect_nonstandard(else_branch, defined, uses);
+                }
+            }
+            Word::BeginUntil { body } | Word::DoLoop { body, .. } => collect_nonstandard(body, defined, uses),
+            Word::BeginWhileRepeat { condition, body } => {
+                collect_nonstandard(condition, defined, uses);
+                collect_nonstandard(body, defined, uses);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Replace every `"NAME" environment?` in a program with its answer
+pub fn resolve_environment_queries(program: &mut Program) -> Result<()> {
+    for def in &mut program.definitions {
+        resolve_words(&mut def.body)?;
+    }
+    resolve_words(&mut program.top_level_code)?;
+    for test in &mut program.tests {
+        resolve_words(&mut test.body)?;
+        resolve_words(&mut test.expected)?;
+    }
+    Ok(())
+}
+
+fn resolve_words(words: &mut Vec<Word>) -> Result<()> {
+    let mut resolved = Vec::with_capacity(words.len());
+    for mut word in words.drain(..) {
+        match &mut word {
+            Word::WordRef { name, location } if name.eq_ignore_ascii_case("environment?") => {
+                let Some(Word::StringLiteral(query)) = resolved.pop() else {
+                    return Err(ForthError::ParseError {
+                        line: location.line,
+                        column: location.column,
+                        message: "environment? needs a literal query string, e.g. \"MAX-N\" environment?"
+                            .to_string(),
+                    });
+                };
+                match environment_query(&query) {
+                    Some(values) => {
+                        resolved.extend(values.into_iter().map(Word::IntLiteral));
+                        resolved.push(Word::IntLiteral(-1));
+                    }
+                    None => resolved.push(Word::IntLiteral(0)),
+                }
+                continue;
+            }
+            Word::If { then_branch, else_branch } => {
+                resolve_words(then_branch)?;
+                if let Some(else_branch) = else_branch {
+                    resolve_words(else_branch)?;
+                }
+            }
+            Word::BeginUntil { body } | Word::DoLoop { body, .. } => resolve_words(body)?,
+            Word::BeginWhileRepeat { condition, body } => {
+                resolve_words(condition)?;
+                resolve_words(body)?;
+            }
+            _ => {}
+        }
+        resolved.push(word);
+    }
+    *words = resolved;
+    Ok(())
+}
+
+// Claimed words, each exercised by the compliance suite
+
+const CLAIMED_CORE: &[&str] = &[
+    "!", "#", "#>", "#s", "*", "+", "-", "/", "0<", "0=", ":", ";", "<", "<#", "=", ">", ">r", "@",
+    "abs", "and", "begin", "cells", "do", "drop", "dup", "else", "hold", "i", "if", "invert", "j",
+    "loop", "lshift", "mod", "negate", "or", "over", "r>", "r@", "repeat", "rot", "rshift", "sign",
+    "swap", "then", "until", "variable", "while", "xor",
+];
+
+const CLAIMED_CORE_EXT: &[&str] = &["0>", "<>", "nip", "tuck"];
+
+const CLAIMED_BLOCK: &[&str] = &["block", "buffer", "empty-buffers", "flush", "save-buffers", "update"];
+
+const CLAIMED_FILE: &[&str] = &[
+    "close-file", "create-file", "delete-file", "open-file", "r/o", "r/w", "read-file", "w/o",
+    "write-file",
+];
+
+// The standard's word sets
+
+const CORE: &[&str] = &[
+    "!", "#", "#>", "#s", "'", "(", "*", "*/", "*/mod", "+", "+!", "+loop", ",", "-", ".", ".\"",
+    "/", "/mod", "0<", "0=", "1+", "1-", "2!", "2*", "2/", "2@", "2drop", "2dup", "2over", "2swap",
+    ":", ";", "<", "<#", "=", ">", ">body", ">in", ">number", ">r", "?dup", "@", "abort", "abort\"",
+    "abs", "accept", "align", "aligned", "allot", "and", "base", "begin", "bl", "c!", "c,", "c@",
+    "cell+", "cells", "char", "char+", "chars", "constant", "count", "cr", "create", "decimal",
+    "depth", "do", "does>", "drop", "dup", "else", "emit", "environment?", "evaluate", "execute",
+    "exit", "fill", "find", "fm/mod", "here", "hold", "i", "if", "immediate", "invert", "j", "key",
+    "leave", "literal", "loop", "lshift", "m*", "max", "min", "mod", "move", "negate", "or", "over",
+    "postpone", "quit", "r>", "r@", "recurse", "repeat", "rot", "rshift", "s\"", "s>d", "sign",
+    "sm/rem", "source", "space", "spaces", "state", "swap", "then", "type", "u.", "u<", "um*",
+    "um/mod", "unloop", "until", "variable", "while", "word", "xor", "[", "[']", "[char]", "]",
+];
+
+const CORE_EXT: &[&str] = &[
+    "#tib", ".(", ".r", "0<>", "0>", "2>r", "2r>", "2r@", ":noname", "<>", "?do", "action-of",
+    "again", "buffer:", "c\"", "case", "compile,", "convert", "defer", "defer!", "defer@",
+    "endcase", "endof", "erase", "expect", "false", "hex", "holds", "is", "marker", "nip", "of",
+    "pad", "parse", "parse-name", "pick", "query", "refill", "restore-input", "roll", "s\\\"",
+    "save-input", "source-id", "span", "tib", "to", "true", "tuck", "u.r", "u>", "unused", "value",
+    "within", "[compile]", "\\",
+];
+
+const BLOCK: &[&str] = &[
+    "blk", "block", "buffer", "empty-buffers", "evaluate", "flush", "list", "load", "refill",
+    "save-buffers", "scr", "thru", "update", "\\",
+];
+
+const DOUBLE: &[&str] = &[
+    "2constant", "2literal", "2rot", "2value", "2variable", "d+", "d-", "d.", "d.r", "d0<", "d0=",
+    "d2*", "d2/", "d<", "d=", "d>s", "dabs", "dmax", "dmin", "dnegate", "du<", "m*/", "m+",
+];
+
+const EXCEPTION: &[&str] = &["abort", "abort\"", "catch", "throw"];
+
+const FACILITY: &[&str] = &[
+    "+field", "at-xy", "begin-structure", "cfield:", "ekey", "ekey>char", "ekey?", "emit?",
+    "end-structure", "field:", "key?", "ms", "page", "time&date",
+];
+
+const FILE: &[&str] = &[
+    "(", "bin", "close-file", "create-file", "delete-file", "file-position", "file-size",
+    "file-status", "flush-file", "include", "include-file", "included", "open-file", "r/o", "r/w",
+    "read-file", "read-line", "refill", "rename-file", "reposition-file", "require", "required",
+    "resize-file", "s\"", "s\\\"", "source-id", "w/o", "write-file", "write-line",
+];
+
+const FLOAT: &[&str] = &[
+    ">float", "d>f", "df!", "df@", "dfalign", "dfaligned", "dfloat+", "dfloats", "f!", "f*", "f**",
+    "f+", "f-", "f.", "f/", "f0<", "f0=", "f<", "f>d", "f@", "fabs", "facos", "facosh", "falign",
+    "faligned", "falog", "fasin", "fasinh", "fatan", "fatan2", "fatanh", "fconstant", "fcos",
+    "fcosh", "fdepth", "fdrop", "fdup", "fe.", "fexp", "fexpm1", "fliteral", "float+", "floats",
+    "floor", "fln", "flnp1", "flog", "fmax", "fmin", "fnegate", "fover", "frot", "fround", "fs.",
+    "fsin", "fsincos", "fsinh", "fsqrt", "fswap", "ftan", "ftanh", "fvariable", "f~", "precision",
+    "represent", "set-precision", "sf!", "sf@", "sfalign", "sfaligned", "sfloat+", "sfloats",
+];
+
+const LOCALS: &[&str] = &["(local)", "locals|", "{:"];
+
+const MEMORY: &[&str] = &["allocate", "free", "resize"];
+
+const SEARCH_ORDER: &[&str] = &[
+    "also", "definitions", "find", "forth", "forth-wordlist", "get-current", "get-order", "only",
+    "order", "previous", "search-wordlist", "set-current", "set-order", "wordlist",
+];
+
+const STRING: &[&str] = &[
+    "-trailing", "/string", "blank", "cmove", "cmove>", "compare", "replaces", "search",
+    "sliteral", "substitute", "unescape",
+];
+
+const TOOLS: &[&str] = &[
+    ".s", "?", "[defined]", "[else]", "[if]", "[then]", "[undefined]", ";code", "ahead",
+    "assembler", "bye", "code", "cs-pick", "cs-roll", "dump", "editor", "forget", "n>r",
+    "name>compile", "name>interpret", "name>string", "nr>", "see", "state", "synonym",
+    "traverse-wordlist", "words",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    #[test]
+    fn test_word_sets() {
+        assert_eq!(standard_word_set("DUP"), Some(WordSet::Core));
+        assert_eq!(standard_word_set("nip"), Some(WordSet::CoreExt));
+        assert_eq!(standard_word_set("catch"), Some(WordSet::Exception));
+        assert!(!is_standard_word("arg@"));
+        assert_eq!(WordSet::from_name("search-order"), Some(WordSet::SearchOrder));
+    }
+
+    #[test]
+    fn test_claims_name_words_of_their_set() {
+        for set in WordSet::ALL {
+            for word in claim(set).words(set) {
+                assert!(set.contains(word), "{} is not a {} word", word, set.name());
+            }
+        }
+        assert_eq!(claim(WordSet::Float), Claim::NotClaimed);
+        assert_eq!(claim(WordSet::Exception), Claim::NotClaimed);
+    }
+
+    #[test]
+    fn test_nonstandard_words() {
+        let program = parse_program(": sq dup * ;\n3 sq arg-count\nvariable v v @ getenv").unwrap();
+        let uses: Vec<(String, usize)> =
+            nonstandard_words(&program).into_iter().map(|(name, location)| (name, location.line)).collect();
+        assert_eq!(uses, vec![("arg-count".to_string(), 2), ("getenv".to_string(), 3)]);
+    }
+
+    #[test]
+    fn test_environment_queries_are_resolved() {
+        assert!(parse_program(": q dup environment? ;").is_err(), "the query string must be a literal");
+
+        let program = parse_program(r#": q "MAX-N" environment? ; "FLOATING" environment? "NOPE" environment?"#).unwrap();
+
+        let literals = |words: &[Word]| -> Vec<i64> {
+            words.iter().filter_map(|word| match word {
+                Word::IntLiteral(value) => Some(*value),
+                _ => None,
+            }).collect()
+        };
+        assert_eq!(literals(&program.definitions[0].body), vec![i64::MAX, -1]);
+        assert_eq!(literals(&program.top_level_code), vec![0, -1, 0]);
+    }
+}
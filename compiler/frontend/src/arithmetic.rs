@@ -0,0 +1,211 @@
+//! Arithmetic Semantics
+//!
+//! What `+`, `-`, `*`, `/` and `mod` do at the edges of a cell. Forth-2012
+//! leaves two choices to the system:
+//! - Division: symmetric division truncates the quotient toward zero, as
+//!   the hardware does, while floored division rounds it toward negative
+//!   infinity, so a nonzero remainder takes the sign of the divisor
+//! - Overflow: a result that does not fit in a cell, including the most
+//!   negative cell divided by -1, either wraps around, traps, or throws
+//!   [`RESULT_OUT_OF_RANGE`] as checked arithmetic does
+//!
+//! A compilation picks one [`Arithmetic`], and everything that evaluates
+//! arithmetic follows it: constant folding, the symbolic executor and the
+//! code both backends generate. Division by zero traps whatever the
+//! semantics, so nothing folds it away.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// How `/` and `mod` round
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Division {
+    /// Quotient truncated toward zero; the remainder has the sign of the
+    /// dividend
+    #[default]
+    Symmetric,
+    /// Quotient rounded toward negative infinity; the remainder has the
+    /// sign of the divisor
+    Floored,
+}
+
+/// What a result that does not fit in a cell does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Overflow {
+    /// Two's complement wraparound
+    #[default]
+    Wrapping,
+    /// A trap, as division by zero is
+    Trapping,
+    /// THROW [`RESULT_OUT_OF_RANGE`], which a guarded run reports as an
+    /// exception rather than a fault
+    Checked,
+}
+
+/// THROW code of a result out of range, which checked arithmetic raises
+pub const RESULT_OUT_OF_RANGE: i64 = -11;
+
+/// Semantics of integer arithmetic for a compilation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Arithmetic {
+    pub division: Division,
+    pub overflow: Overflow,
+}
+
+/// Why an operation has no result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticFault {
+    DivisionByZero,
+    Overflow,
+}
+
+impl fmt::Display for ArithmeticFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArithmeticFault::DivisionByZero => write!(f, "division by zero"),
+            ArithmeticFault::Overflow => write!(f, "integer overflow"),
+        }
+    }
+}
+
+impl Arithmetic {
+    /// `a + b`
+    pub fn add(self, a: i64, b: i64) -> Result<i64, ArithmeticFault> {
+        self.wrap(a.overflowing_add(b))
+    }
+
+    /// `a - b`
+    pub fn sub(self, a: i64, b: i64) -> Result<i64, ArithmeticFault> {
+        self.wrap(a.overflowing_sub(b))
+    }
+
+    /// `a * b`
+    pub fn mul(self, a: i64, b: i64) -> Result<i64, ArithmeticFault> {
+        self.wrap(a.overflowing_mul(b))
+    }
+
+    /// `a / b`
+    pub fn div(self, a: i64, b: i64) -> Result<i64, ArithmeticFault> {
+        self.div_mod(a, b).map(|(quotient, _)| quotient)
+    }
+
+    /// `a mod b`, which never overflows: the remainder of the most negative
+    /// cell by -1 is 0
+    pub fn modulo(self, a: i64, b: i64) -> Result<i64, ArithmeticFault> {
+        if b == 0 {
+            return Err(ArithmeticFault::DivisionByZero);
+        }
+        let remainder = a.wrapping_rem(b);
+        Ok(if self.rounds_down(remainder, b) { remainder + b } else { remainder })
+    }
+
+    /// Quotient and remainder of `a` by `b`, as `/mod` leaves them
+    pub fn div_mod(self, a: i64, b: i64) -> Result<(i64, i64), ArithmeticFault> {
+        if b == 0 {
+            return Err(ArithmeticFault::DivisionByZero);
+        }
+        let quotient = self.wrap(a.overflowing_div(b))?;
+        let remainder = a.wrapping_rem(b);
+        if self.rounds_down(remainder, b) {
+            return Ok((quotient - 1, remainder + b));
+        }
+        Ok((quotient, remainder))
+    }
+
+    /// Whether floored division takes one from a truncated quotient with
+    /// this remainder, and adds the divisor to the remainder
+    fn rounds_down(self, remainder: i64, divisor: i64) -> bool {
+        self.division == Division::Floored && remainder != 0 && (remainder < 0) != (divisor < 0)
+    }
+
+    fn wrap(self, (result, overflowed): (i64, bool)) -> Result<i64, ArithmeticFault> {
+        if overflowed && self.overflow != Overflow::Wrapping {
+            return Err(ArithmeticFault::Overflow);
+        }
+        Ok(result)
+    }
+}
+
+/// High cell of the unsigned double-cell product of two cells, which `um*`
+/// leaves above the low cell
+pub fn umul_high(a: i64, b: i64) -> i64 {
+    ((a as u64 as u128 * b as u64 as u128) >> 64) as i64
+}
+
+impl fmt::Display for Division {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Division::Symmetric => write!(f, "symmetric"),
+            Division::Floored => write!(f, "floored"),
+        }
+    }
+}
+
+impl FromStr for Division {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "symmetric" | "truncated" => Ok(Division::Symmetric),
+            "floored" => Ok(Division::Floored),
+            _ => Err(format!("unknown division '{}' (expected symmetric or floored)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Overflow::Wrapping => write!(f, "wrapping"),
+            Overflow::Trapping => write!(f, "trapping"),
+            Overflow::Checked => write!(f, "checked"),
+        }
+    }
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "wrapping" | "wrap" => Ok(Overflow::Wrapping),
+            "trapping" | "trap" => Ok(Overflow::Trapping),
+            "checked" => Ok(Overflow::Checked),
+            _ => Err(format!("unknown overflow '{}' (expected wrapping, trapping or checked)", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_division_rounding_and_overflow() {
+        let symmetric = Arithmetic::default();
+        let floored = Arithmetic { division: Division::Floored, ..Arithmetic::default() };
+        assert_eq!(symmetric.div_mod(-7, 2), Ok((-3, -1)));
+        assert_eq!(floored.div_mod(-7, 2), Ok((-4, 1)));
+        assert_eq!(floored.div_mod(7, -2), Ok((-4, -1)));
+        assert_eq!(floored.div_mod(-8, 2), Ok((-4, 0)));
+        assert_eq!(floored.div_mod(7, 0), Err(ArithmeticFault::DivisionByZero));
+
+        let trapping = Arithmetic { overflow: Overflow::Trapping, ..Arithmetic::default() };
+        assert_eq!(symmetric.div_mod(i64::MIN, -1), Ok((i64::MIN, 0)));
+        assert_eq!(trapping.div(i64::MIN, -1), Err(ArithmeticFault::Overflow));
+        assert_eq!(trapping.modulo(i64::MIN, -1), Ok(0));
+        assert_eq!(symmetric.add(i64::MAX, 1), Ok(i64::MIN));
+        assert_eq!(trapping.add(i64::MAX, 1), Err(ArithmeticFault::Overflow));
+        assert_eq!(trapping.mul(1 << 32, 1 << 32), Err(ArithmeticFault::Overflow));
+        assert_eq!(trapping.sub(-5, 7), Ok(-12));
+
+        let checked = Arithmetic { overflow: Overflow::Checked, ..Arithmetic::default() };
+        assert_eq!(checked.sub(i64::MIN, 1), Err(ArithmeticFault::Overflow));
+        assert_eq!(checked.div(i64::MIN, -1), Err(ArithmeticFault::Overflow));
+        assert_eq!("checked".parse(), Ok(Overflow::Checked));
+
+        assert_eq!(umul_high(-1, -1), -2);
+        assert_eq!(umul_high(1 << 32, 1 << 32), 1);
+        assert_eq!(umul_high(-1, 2), 1);
+    }
+}
@@ -1,5 +1,6 @@
 //! Abstract Syntax Tree definitions for Forth
 
+pub use crate::intern::{InternStats, Symbol};
 use std::fmt;
 
 /// A complete Forth program
@@ -7,6 +8,14 @@ use std::fmt;
 pub struct Program {
     pub definitions: Vec<Definition>,
     pub top_level_code: Vec<Word>,
+    /// `T{ ... -> ... }T` test cases, in source order
+    pub tests: Vec<TestCase>,
+    /// Words written in assembly, in source order
+    pub code_words: Vec<CodeWord>,
+    /// Record layouts declared with `STRUCT`, in source order
+    pub records: Vec<Record>,
+    /// How many word names and string literals the parser interned
+    pub names: InternStats,
 }
 
 impl Program {
@@ -14,8 +23,20 @@ impl Program {
         Self {
             definitions: Vec::new(),
             top_level_code: Vec::new(),
+            tests: Vec::new(),
+            code_words: Vec::new(),
+            records: Vec::new(),
+            names: InternStats::default(),
         }
     }
+
+    /// Definitions compiled into the program
+    ///
+    /// Immediate words are left out: every use of one runs while the
+    /// program is parsed, so nothing calls them at run time.
+    pub fn compiled_definitions(&self) -> impl Iterator<Item = &Definition> {
+        self.definitions.iter().filter(|def| !def.immediate)
+    }
 }
 
 impl Default for Program {
@@ -31,16 +52,122 @@ pub struct Definition {
     pub body: Vec<Word>,
     pub immediate: bool,
     pub stack_effect: Option<StackEffect>,
+    pub attributes: Attributes,
+    pub location: SourceLocation,
+}
+
+/// Optimization attributes of a definition, written as `\ @name` comments
+/// on the lines before its `:`
+///
+/// ```forth
+/// \ @inline(never) @hot
+/// : step ( n -- n' ) dup 1 and if 3 * 1 + else 2/ then ;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Attributes {
+    /// `@inline(always)` or `@inline(never)`; unset leaves it to the optimizer
+    pub inline: Option<InlineHint>,
+    /// `@optimize(none)`: no pass changes the body, and it is never inlined
+    pub optimize_none: bool,
+    /// `@hot`: the word runs often, so code size matters less than speed
+    pub hot: bool,
+    /// `@constant-time`: the word handles secrets, so its running time must
+    /// not depend on its data (see [`crate::constant_time`])
+    pub constant_time: bool,
+    /// `@recursion(N)`: the word recurses at most `N` activations deep,
+    /// which bounds its stack use (see [`crate::stack_bounds`])
+    pub recursion: Option<usize>,
+    /// `@export` or `@export(symbol)`: the C symbol the word is exported
+    /// as from a shared library; a bare `@export` uses the Forth name with
+    /// `-` as `_`
+    pub export: Option<String>,
+}
+
+/// Argument of `@inline`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InlineHint {
+    Always,
+    Never,
+}
+
+/// A word written in target assembly: `CODE name ( effect ) ... END-CODE`
+///
+/// The assembly is the body of a function with the platform C calling
+/// convention: the inputs arrive as integer arguments, deepest first, and
+/// the output, if the effect declares one, is the return value. The text
+/// goes to the assembler unchanged and is never optimized; the declared
+/// effect is all the rest of the compiler knows about the word.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeWord {
+    pub name: String,
+    pub stack_effect: StackEffect,
+    pub assembly: String,
+    pub location: SourceLocation,
+}
+
+/// Layout of a record: `STRUCT name ... [size] FIELD name ... END-STRUCT`
+///
+/// ```forth
+/// struct point field .x field .y end-struct
+/// create origin point allot
+/// : x@ ( point -- n ) .x @ ;
+/// ```
+///
+/// The parser defines a word for the record's size in bytes and an
+/// accessor for each field, which adds the field's offset to a record
+/// address. Type inference knows the address a field accessor takes is of
+/// this record, so applying `.x` to anything else is a type error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub name: String,
+    /// Size in bytes
+    pub size: usize,
+    pub fields: Vec<Field>,
+    pub location: SourceLocation,
+}
+
+/// A field of a [`Record`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    /// The accessor word, such as `.x`
+    pub name: String,
+    /// Offset in bytes from the start of the record
+    pub offset: usize,
+    /// Size in bytes
+    pub size: usize,
+    /// The record the field holds, when it was sized by a record name
+    pub record: Option<String>,
+}
+
+/// A test case: `T{ body -> expected }T`
+///
+/// The test passes when running `body` leaves the same stack as running
+/// `expected`. Test cases are only run by the test runner, never as part
+/// of the program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestCase {
+    pub body: Vec<Word>,
+    pub expected: Vec<Word>,
+    /// Location of the `T{`
     pub location: SourceLocation,
 }
 
 /// Source code location for error reporting
+///
+/// Lines and columns count from 1; the default (0, 0) means unknown.
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct SourceLocation {
     pub line: usize,
     pub column: usize,
 }
 
+impl SourceLocation {
+    /// Whether this points at real source
+    pub fn is_known(&self) -> bool {
+        self.line > 0
+    }
+}
+
 /// Stack effect declaration ( in1 in2 -- out1 )
 #[derive(Debug, Clone, PartialEq)]
 pub struct StackEffect {
@@ -93,6 +220,8 @@ pub enum StackType {
     Char,
     /// String
     String,
+    /// Address of a record of the named type
+    Record(String),
     /// Polymorphic type variable (for type inference)
     Var(TypeVar),
     /// Unknown type (to be inferred)
@@ -108,6 +237,7 @@ impl fmt::Display for StackType {
             StackType::Bool => write!(f, "bool"),
             StackType::Char => write!(f, "char"),
             StackType::String => write!(f, "string"),
+            StackType::Record(name) => write!(f, "{}", name),
             StackType::Var(v) => write!(f, "{}", v),
             StackType::Unknown => write!(f, "?"),
         }
@@ -141,11 +271,46 @@ pub enum Word {
     FloatLiteral(f64),
 
     /// A string literal
-    StringLiteral(String),
+    StringLiteral(Symbol),
 
     /// A word reference (calling another word)
     WordRef {
-        name: String,
+        name: Symbol,
+        location: SourceLocation,
+    },
+
+    /// `TASK: name`: run the word on a new thread, leaving the task
+    TaskSpawn {
+        word: Symbol,
+        location: SourceLocation,
+    },
+
+    /// Locals declaration, `{: a b | c -- :}` or `LOCALS| b a |`: pops the
+    /// top of the stack into the last argument, the next item into the one
+    /// before, and so on
+    Locals {
+        args: Vec<Symbol>,
+        /// Locals after `|`, which start at 0
+        uninitialized: Vec<Symbol>,
+        location: SourceLocation,
+    },
+
+    /// Push the value of a local
+    LocalRef {
+        name: Symbol,
+        location: SourceLocation,
+    },
+
+    /// `TO name` on a local: pop into it
+    LocalStore {
+        name: Symbol,
+        location: SourceLocation,
+    },
+
+    /// `[: ... ;]`, `' name` or `['] name`: push the execution token of
+    /// the word `name`, for a quotation the hidden word it was parsed into
+    Quotation {
+        name: Symbol,
         location: SourceLocation,
     },
 
@@ -172,9 +337,13 @@ pub enum Word {
         increment: i64, // 1 for LOOP, variable for +LOOP
     },
 
-    /// Variable definition
+    /// Variable definition: `VARIABLE name`, or `CREATE name size ALLOT`
     Variable {
         name: String,
+        /// Size in bytes
+        size: usize,
+        /// The record it holds, when it was sized by a record name
+        record: Option<String>,
     },
 
     /// Constant definition
@@ -243,6 +412,18 @@ pub enum Token {
     Constant,
     /// IMMEDIATE keyword
     Immediate,
+    /// T{ (start test case)
+    TestStart,
+    /// -> (separates test input from expected results)
+    TestArrow,
+    /// }T (end test case)
+    TestEnd,
+    /// CODE keyword
+    Code,
+    /// Assembly text of a CODE word, up to END-CODE
+    Assembly(String),
+    /// Attributes of a line comment starting with `@`, such as `@inline(never) @hot`
+    Attribute(String),
     /// End of file
     Eof,
 }
@@ -272,6 +453,12 @@ impl fmt::Display for Token {
             Token::Variable => write!(f, "VARIABLE"),
             Token::Constant => write!(f, "CONSTANT"),
             Token::Immediate => write!(f, "IMMEDIATE"),
+            Token::TestStart => write!(f, "T{{"),
+            Token::TestArrow => write!(f, "->"),
+            Token::TestEnd => write!(f, "}}T"),
+            Token::Code => write!(f, "CODE"),
+            Token::Assembly(text) => write!(f, "{} END-CODE", text),
+            Token::Attribute(text) => write!(f, "\\ {}", text),
             Token::Eof => write!(f, "<EOF>"),
         }
     }
@@ -0,0 +1,174 @@
+//! CASE Statements
+//!
+//! The parser expands `CASE k1 OF ... ENDOF ... default ENDCASE` into the
+//! chain of tests ANS Forth defines it as: `k1 OVER = IF DROP ... ELSE ...
+//! default DROP THEN`. Every analysis sees ordinary IFs, and code generation
+//! recovers the chain with [`CaseChain::find`]: a dense set of integer keys
+//! becomes one jump table instead of a test per key, and a chain that only
+//! picks a constant becomes a lookup in an array of them.
+
+use crate::ast::Word;
+
+/// Fewest keys worth a jump table
+pub const MIN_CASES: usize = 3;
+
+/// One `k OF ... ENDOF` arm
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseArm<'a> {
+    pub key: i64,
+    /// Runs with the selector dropped
+    pub body: &'a [Word],
+}
+
+/// Arms with literal keys at the start of a word sequence
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseChain<'a> {
+    /// Arms in source order, each key at most once
+    pub arms: Vec<CaseArm<'a>>,
+    /// Runs with the selector still on the stack, when no arm matches;
+    /// ends with ENDCASE's DROP
+    pub default: &'a [Word],
+}
+
+/// Constants a chain selects, indexed by key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseTable {
+    /// Key of the first entry
+    pub base: i64,
+    pub values: Vec<i64>,
+    /// Value for keys outside the table, or in a gap of it
+    pub default: i64,
+}
+
+impl<'a> CaseChain<'a> {
+    /// Words the chain starting `words` takes up
+    pub const LEN: usize = 4;
+
+    /// The chain of `k OVER = IF DROP ... ELSE ... THEN` tests starting
+    /// `words`, taking up its first [`Self::LEN`] words
+    ///
+    /// A key that is not a literal ends the chain: the tests from it on
+    /// belong to the default.
+    pub fn find(words: &'a [Word]) -> Option<Self> {
+        let (mut arm, mut rest) = arm(words)?;
+        let mut arms = Vec::new();
+        loop {
+            // A repeated key can never match
+            if arms.iter().all(|other: &CaseArm| other.key != arm.key) {
+                arms.push(arm);
+            }
+            match arm_exactly(rest) {
+                Some((next, next_rest)) => (arm, rest) = (next, next_rest),
+                None => return Some(CaseChain { arms, default: rest }),
+            }
+        }
+    }
+
+    /// Whether the keys are close enough together for a jump table
+    ///
+    /// The table may be at most half empty.
+    pub fn is_dense(&self) -> bool {
+        self.arms.len() >= MIN_CASES
+            && self.key_range().is_some_and(|(min, max)| max.abs_diff(min) < 2 * self.arms.len() as u64)
+    }
+
+    /// Smallest and largest key
+    fn key_range(&self) -> Option<(i64, i64)> {
+        let keys = self.arms.iter().map(|arm| arm.key);
+        Some((keys.clone().min()?, keys.max()?))
+    }
+
+    /// The constants the chain selects, when it is dense and every arm,
+    /// and the default, only leaves a literal
+    pub fn table(&self) -> Option<CaseTable> {
+        if !self.is_dense() {
+            return None;
+        }
+        let default = constant_default(self.default)?;
+        let (base, max) = self.key_range()?;
+        let mut values = vec![default; max.abs_diff(base) as usize + 1];
+        for arm in &self.arms {
+            let [Word::IntLiteral(value)] = arm.body else { return None };
+            values[arm.key.abs_diff(base) as usize] = *value;
+        }
+        Some(CaseTable { base, values, default })
+    }
+}
+
+impl CaseTable {
+    /// Value the table gives `key`
+    pub fn get(&self, key: i64) -> i64 {
+        let index = (key as u64).wrapping_sub(self.base as u64);
+        usize::try_from(index).ok().and_then(|index| self.values.get(index)).copied().unwrap_or(self.default)
+    }
+}
+
+/// The arm `k OVER = IF DROP ... ELSE rest THEN` starting `words`, and `rest`
+fn arm(words: &[Word]) -> Option<(CaseArm<'_>, &[Word])> {
+    let [Word::IntLiteral(key), over, equals, Word::If { then_branch, else_branch: Some(rest) }, ..] = words else {
+        return None;
+    };
+    let [drop, body @ ..] = then_branch.as_slice() else { return None };
+    if !is_word(over, "over") || !is_word(equals, "=") || !is_word(drop, "drop") {
+        return None;
+    }
+    Some((CaseArm { key: *key, body }, rest))
+}
+
+/// [`arm`], when the arm is all of `words`
+fn arm_exactly(words: &[Word]) -> Option<(CaseArm<'_>, &[Word])> {
+    if words.len() != CaseChain::LEN {
+        return None;
+    }
+    arm(words)
+}
+
+/// The value a default leaves in place of the selector: `k SWAP DROP` or
+/// `DROP k`
+fn constant_default(words: &[Word]) -> Option<i64> {
+    match words {
+        [Word::IntLiteral(value), swap, drop] if is_word(swap, "swap") && is_word(drop, "drop") => Some(*value),
+        [drop, Word::IntLiteral(value)] if is_word(drop, "drop") => Some(*value),
+        _ => None,
+    }
+}
+
+fn is_word(word: &Word, name: &str) -> bool {
+    matches!(word, Word::WordRef { name: word, .. } if word.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    fn body(source: &str) -> Vec<Word> {
+        parse_program(source).unwrap().definitions.remove(0).body
+    }
+
+    #[test]
+    fn test_find_case_chain() {
+        let words = body(": f ( n -- n ) case 1 of 10 endof 2 of 20 endof 1 of 30 endof dup endcase ;");
+        let chain = CaseChain::find(&words).unwrap();
+        assert_eq!(chain.arms.iter().map(|arm| arm.key).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(chain.arms[0].body, &[Word::IntLiteral(10)]);
+        assert_eq!(chain.default.len(), 2);
+        assert!(!chain.is_dense());
+    }
+
+    #[test]
+    fn test_case_table() {
+        let words = body(": f ( n -- n ) case 3 of 30 endof 5 of 50 endof 6 of 60 endof -1 swap endcase ;");
+        let table = CaseChain::find(&words).unwrap().table().unwrap();
+        assert_eq!(table, CaseTable { base: 3, values: vec![30, -1, 50, 60], default: -1 });
+        assert_eq!(table.get(5), 50);
+        assert_eq!(table.get(4), -1);
+        assert_eq!(table.get(i64::MIN), -1);
+
+        // An arm doing more than leaving a constant needs a jump table
+        let words = body(": f ( n -- n ) case 1 of 10 endof 2 of 20 endof 3 of 1 2 + endof 0 swap endcase ;");
+        let chain = CaseChain::find(&words).unwrap();
+        assert!(chain.is_dense());
+        assert_eq!(chain.table(), None);
+    }
+}
@@ -0,0 +1,298 @@
+//! Compile-time evaluation
+//!
+//! A small interpreter over the AST that runs words while a program is
+//! parsed: the bodies of immediate words, `[ ... ]` blocks, and the flags
+//! read by `[IF]`. It works on integers only; anything with run-time
+//! effects (memory, I/O, strings) is rejected.
+//!
+//! Words can compile code into the definition being parsed:
+//!
+//! - `literal` pops a value and compiles it as a literal
+//! - `postpone name` compiles a call to `name`, or runs `name` if it is
+//!   itself immediate (as `literal` is)
+
+use crate::ast::{Definition, Word};
+use crate::error::{ForthError, Result};
+use std::collections::HashMap;
+
+/// Upper bound on words executed by one evaluation, so a runaway loop
+/// fails compilation instead of hanging it
+const MAX_STEPS: usize = 1_000_000;
+
+/// Deepest call nesting allowed at compile time
+const MAX_CALL_DEPTH: usize = 256;
+
+/// Interpreter for compile-time code
+pub struct Interpreter<'a> {
+    /// Definitions parsed so far, keyed by lowercase name
+    definitions: &'a HashMap<String, Definition>,
+    /// Constants defined so far, keyed by lowercase name
+    constants: &'a HashMap<String, i64>,
+    /// Index and limit of each enclosing `DO` loop
+    loops: Vec<(i64, i64)>,
+    steps: usize,
+    depth: usize,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(definitions: &'a HashMap<String, Definition>, constants: &'a HashMap<String, i64>) -> Self {
+        Self {
+            definitions,
+            constants,
+            loops: Vec::new(),
+            steps: 0,
+            depth: 0,
+        }
+    }
+
+    /// Run `words` on `stack`, appending anything they compile to `out`
+    pub fn run(&mut self, words: &[Word], stack: &mut Vec<i64>, out: &mut Vec<Word>) -> Result<()> {
+        let mut index = 0;
+
+        while index < words.len() {
+            self.steps += 1;
+            if self.steps > MAX_STEPS {
+                return Err(error(format!("gave up after {} steps", MAX_STEPS)));
+            }
+
+            match &words[index] {
+                Word::IntLiteral(value) => stack.push(*value),
+                Word::WordRef { name, .. } if name.eq_ignore_ascii_case("postpone") => {
+                    index += 1;
+                    let Some(target @ Word::WordRef { name: target_name, .. }) = words.get(index) else {
+                        return Err(error("POSTPONE needs a word to postpone"));
+                    };
+                    // Immediate words, LITERAL included, run instead of compiling
+                    let immediate = target_name.eq_ignore_ascii_case("literal")
+                        || self
+                            .definitions
+                            .get(&target_name.to_lowercase())
+                            .is_some_and(|def| def.immediate);
+                    if immediate {
+                        self.execute(target_name, stack, out)?;
+                    } else {
+                        out.push(target.clone());
+                    }
+                }
+                Word::WordRef { name, .. } => self.execute(name, stack, out)?,
+                Word::If { then_branch, else_branch } => {
+                    if pop(stack)? != 0 {
+                        self.run(then_branch, stack, out)?;
+                    } else if let Some(else_branch) = else_branch {
+                        self.run(else_branch, stack, out)?;
+                    }
+                }
+                Word::BeginUntil { body } => loop {
+                    self.run(body, stack, out)?;
+                    if pop(stack)? != 0 {
+                        break;
+                    }
+                },
+                Word::BeginWhileRepeat { condition, body } => loop {
+                    self.run(condition, stack, out)?;
+                    if pop(stack)? == 0 {
+                        break;
+                    }
+                    self.run(body, stack, out)?;
+                },
+                Word::DoLoop { body, increment } => {
+                    let start = pop(stack)?;
+                    let limit = pop(stack)?;
+                    self.loops.push((start, limit));
+                    loop {
+                        self.run(body, stack, out)?;
+                        let (index, limit) = self.loops.last_mut().expect("loop pushed above");
+                        *index += increment;
+                        if *index >= *limit {
+                            break;
+                        }
+                    }
+                    self.loops.pop();
+                }
+                Word::Comment(_) => {}
+                Word::TaskSpawn { .. } => return Err(error("TASK: cannot run at compile time")),
+                Word::Locals { .. } | Word::LocalRef { .. } | Word::LocalStore { .. } => {
+                    return Err(error("locals are not supported at compile time"))
+                }
+                Word::Quotation { .. } => return Err(error("quotations are not supported at compile time")),
+                Word::FloatLiteral(_) => return Err(error("floats are not supported at compile time")),
+                Word::StringLiteral(_) => return Err(error("strings are not supported at compile time")),
+                Word::Variable { name, .. } | Word::Constant { name, .. } => {
+                    return Err(error(format!("cannot define '{}' at compile time", name)))
+                }
+            }
+            index += 1;
+        }
+
+        Ok(())
+    }
+
+    fn call(&mut self, def: &Definition, stack: &mut Vec<i64>, out: &mut Vec<Word>) -> Result<()> {
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(error(format!("calls nested deeper than {} in '{}'", MAX_CALL_DEPTH, def.name)));
+        }
+        self.depth += 1;
+        let result = self.run(&def.body, stack, out);
+        self.depth -= 1;
+        result
+    }
+
+    /// Execute a single word
+    fn execute(&mut self, name: &str, stack: &mut Vec<i64>, out: &mut Vec<Word>) -> Result<()> {
+        let lower = name.to_lowercase();
+
+        let flag = |condition: bool| if condition { -1 } else { 0 };
+        match lower.as_str() {
+            // Stack
+            "dup" => {
+                let a = peek(stack, 0)?;
+                stack.push(a);
+            }
+            "drop" => {
+                pop(stack)?;
+            }
+            "swap" => {
+                let b = pop(stack)?;
+                let a = pop(stack)?;
+                stack.extend([b, a]);
+            }
+            "over" => {
+                let a = peek(stack, 1)?;
+                stack.push(a);
+            }
+            "rot" => {
+                let c = pop(stack)?;
+                let b = pop(stack)?;
+                let a = pop(stack)?;
+                stack.extend([b, c, a]);
+            }
+            "nip" => {
+                let b = pop(stack)?;
+                pop(stack)?;
+                stack.push(b);
+            }
+            "tuck" => {
+                let b = pop(stack)?;
+                let a = pop(stack)?;
+                stack.extend([b, a, b]);
+            }
+
+            // Arithmetic and logic
+            "+" => binary(stack, |a, b| Ok(a.wrapping_add(b)))?,
+            "-" => binary(stack, |a, b| Ok(a.wrapping_sub(b)))?,
+            "*" => binary(stack, |a, b| Ok(a.wrapping_mul(b)))?,
+            "/" => binary(stack, |a, b| a.checked_div(b).ok_or_else(|| error("division by zero")))?,
+            "mod" => binary(stack, |a, b| a.checked_rem(b).ok_or_else(|| error("division by zero")))?,
+            "min" => binary(stack, |a, b| Ok(a.min(b)))?,
+            "max" => binary(stack, |a, b| Ok(a.max(b)))?,
+            "and" => binary(stack, |a, b| Ok(a & b))?,
+            "or" => binary(stack, |a, b| Ok(a | b))?,
+            "xor" => binary(stack, |a, b| Ok(a ^ b))?,
+            "lshift" => binary(stack, |a, b| Ok(a.wrapping_shl(b as u32)))?,
+            "rshift" => binary(stack, |a, b| Ok(((a as u64).wrapping_shr(b as u32)) as i64))?,
+            "rol" => binary(stack, |a, b| Ok(a.rotate_left(b as u32)))?,
+            "ror" => binary(stack, |a, b| Ok(a.rotate_right(b as u32)))?,
+            "=" => binary(stack, |a, b| Ok(flag(a == b)))?,
+            "<>" => binary(stack, |a, b| Ok(flag(a != b)))?,
+            "<" => binary(stack, |a, b| Ok(flag(a < b)))?,
+            ">" => binary(stack, |a, b| Ok(flag(a > b)))?,
+            "<=" => binary(stack, |a, b| Ok(flag(a <= b)))?,
+            ">=" => binary(stack, |a, b| Ok(flag(a >= b)))?,
+            "u<" => binary(stack, |a, b| Ok(flag((a as u64) < (b as u64))))?,
+            "u>" => binary(stack, |a, b| Ok(flag((a as u64) > (b as u64))))?,
+            "um*" => {
+                let b = pop(stack)? as u64 as u128;
+                let a = pop(stack)? as u64 as u128;
+                let product = a * b;
+                stack.extend([product as i64, (product >> 64) as i64]);
+            }
+            "u/mod" => {
+                let b = pop(stack)? as u64;
+                let a = pop(stack)? as u64;
+                if b == 0 {
+                    return Err(error("division by zero"));
+                }
+                stack.extend([(a % b) as i64, (a / b) as i64]);
+            }
+            "negate" => unary(stack, |a| a.wrapping_neg())?,
+            "abs" => unary(stack, |a| a.wrapping_abs())?,
+            "invert" => unary(stack, |a| !a)?,
+            "popcount" => unary(stack, |a| a.count_ones() as i64)?,
+            "ctz" => unary(stack, |a| a.trailing_zeros() as i64)?,
+            "clz" => unary(stack, |a| a.leading_zeros() as i64)?,
+            "1+" => unary(stack, |a| a.wrapping_add(1))?,
+            "1-" => unary(stack, |a| a.wrapping_sub(1))?,
+            "2*" => unary(stack, |a| a.wrapping_mul(2))?,
+            "2/" => unary(stack, |a| a >> 1)?,
+            "cells" => unary(stack, |a| a.wrapping_mul(8))?,
+            "cell+" => unary(stack, |a| a.wrapping_add(8))?,
+            "0=" => unary(stack, |a| flag(a == 0))?,
+            "0<" => unary(stack, |a| flag(a < 0))?,
+            "0>" => unary(stack, |a| flag(a > 0))?,
+            "true" => stack.push(-1),
+            "false" => stack.push(0),
+
+            // Loop indices
+            "i" | "j" => {
+                let depth = if lower == "i" { 1 } else { 2 };
+                let (index, _) = self
+                    .loops
+                    .len()
+                    .checked_sub(depth)
+                    .map(|n| self.loops[n])
+                    .ok_or_else(|| error(format!("{} outside of a DO loop", name)))?;
+                stack.push(index);
+            }
+
+            // Compilation
+            "literal" => {
+                let value = pop(stack)?;
+                out.push(Word::IntLiteral(value));
+            }
+
+            _ => {
+                if let Some(value) = self.constants.get(&lower) {
+                    stack.push(*value);
+                } else if let Some(def) = self.definitions.get(&lower) {
+                    self.call(def, stack, out)?;
+                } else {
+                    return Err(error(format!("'{}' cannot run at compile time", name)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn error(message: impl Into<String>) -> ForthError {
+    ForthError::CompileTimeError {
+        message: message.into(),
+        location: None,
+    }
+}
+
+fn pop(stack: &mut Vec<i64>) -> Result<i64> {
+    stack.pop().ok_or_else(|| error("stack underflow"))
+}
+
+fn peek(stack: &[i64], depth: usize) -> Result<i64> {
+    stack
+        .len()
+        .checked_sub(depth + 1)
+        .map(|n| stack[n])
+        .ok_or_else(|| error("stack underflow"))
+}
+
+fn unary(stack: &mut Vec<i64>, op: impl Fn(i64) -> i64) -> Result<()> {
+    let a = pop(stack)?;
+    stack.push(op(a));
+    Ok(())
+}
+
+fn binary(stack: &mut Vec<i64>, op: impl Fn(i64, i64) -> Result<i64>) -> Result<()> {
+    let b = pop(stack)?;
+    let a = pop(stack)?;
+    stack.push(op(a, b)?);
+    Ok(())
+}
@@ -0,0 +1,437 @@
+//! Constant-Time Code
+//!
+//! Words marked `\ @constant-time`, or every word with `--constant-time`,
+//! handle secrets: how long they run must not depend on their data. Two
+//! passes over their SSA serve this:
+//!
+//! - [`lower_branchless`] turns each `IF ... ELSE ... THEN` whose arms
+//!   only compute values into mask arithmetic, and `abs` into a sign
+//!   mask, so neither backend is left a branch or a select to lower.
+//!   Both arms then always run, so arms that divide, touch memory or call
+//!   keep their branch.
+//! - [`secret_dependences`] reports the branches left whose condition
+//!   derives from the word's inputs or from memory, which the compiler
+//!   cannot tell apart from secrets, CASE tables indexed by such data,
+//!   and calls passing it to words that are not constant-time.
+//!
+//! Comparisons need no rewriting: both backends already compute them with
+//! flag-setting instructions rather than branches, and the flag they leave,
+//! all ones when true, is itself a mask.
+
+use crate::ast::SourceLocation;
+use crate::ssa::{
+    BasicBlock, BinaryOperator, BlockId, Register, SSAFunction, SSAInstruction, UnaryOperator,
+};
+use crate::ssa_validator::SSAValidator;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// A branch or call in constant-time code that depends on secret data
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecretDependence {
+    /// The constant-time word
+    pub word: String,
+    /// The word called with secret data, or `None` for a branch
+    pub callee: Option<String>,
+    pub location: Option<SourceLocation>,
+}
+
+impl fmt::Display for SecretDependence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.callee {
+            None => write!(f, "secret-dependent branch in '{}'", self.word)?,
+            Some(callee) => write!(
+                f,
+                "'{}' passes secret data to '{}', which is not constant-time",
+                self.word, callee
+            )?,
+        }
+        if let Some(location) = &self.location {
+            write!(f, " at line {}", location.line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Rewrite branches over pure arms and `abs` as mask arithmetic, returning
+/// how many branches were removed
+///
+/// Nested `IF`s are converted innermost first, so an outer `IF` whose arms
+/// only held pure `IF`s becomes branchless too.
+pub fn lower_branchless(function: &mut SSAFunction) -> usize {
+    let mut lowering = Lowering { next_register: function.next_register() };
+    for block in &mut function.blocks {
+        lowering.lower_abs(block);
+    }
+
+    let mut removed = 0;
+    while let Some(diamond) = find_diamond(function) {
+        lowering.convert(function, diamond);
+        removed += 1;
+    }
+    removed
+}
+
+/// Branches and calls through which secret data can affect timing
+///
+/// Parameters and loaded values count as secret, as does everything
+/// computed from them. A phi also counts as secret once any branch does,
+/// since which value it takes reveals the branch taken.
+pub fn secret_dependences(
+    function: &SSAFunction,
+    is_constant_time: &dyn Fn(&str) -> bool,
+) -> Vec<SecretDependence> {
+    let mut secret: HashSet<Register> = function.parameters.iter().copied().collect();
+    loop {
+        let before = secret.len();
+        let secret_branch = function.blocks.iter().flat_map(|block| &block.instructions).any(
+            |inst| matches!(inst, SSAInstruction::Branch { condition, .. } | SSAInstruction::Switch { value: condition, .. } if secret.contains(condition)),
+        );
+        for inst in function.blocks.iter().flat_map(|block| &block.instructions) {
+            let tainted = match inst {
+                SSAInstruction::Load { .. } => true,
+                SSAInstruction::Phi { .. } if secret_branch => true,
+                _ => SSAValidator::get_used_registers(inst).iter().any(|reg| secret.contains(reg)),
+            };
+            if tainted {
+                secret.extend(SSAValidator::get_destination_registers(inst));
+            }
+        }
+        if secret.len() == before {
+            break;
+        }
+    }
+
+    let mut dependences = Vec::new();
+    for block in &function.blocks {
+        for (index, inst) in block.instructions.iter().enumerate() {
+            let callee = match inst {
+                // A table is indexed, or jumped through, by its selector
+                SSAInstruction::Branch { condition, .. }
+                | SSAInstruction::Switch { value: condition, .. }
+                | SSAInstruction::Lookup { index: condition, .. }
+                | SSAInstruction::CallIndirect { target: condition, .. }
+                    if secret.contains(condition) =>
+                {
+                    None
+                }
+                SSAInstruction::Call { name, args, .. }
+                    if !is_constant_time(name) && args.iter().any(|arg| secret.contains(arg)) =>
+                {
+                    Some(name.to_string())
+                }
+                // Which word runs is not known, let alone how long it takes
+                SSAInstruction::CallIndirect { args, .. } if args.iter().any(|arg| secret.contains(arg)) => {
+                    Some("execute".to_string())
+                }
+                _ => continue,
+            };
+            dependences.push(SecretDependence {
+                word: function.name.clone(),
+                callee,
+                location: block.location(index).cloned(),
+            });
+        }
+    }
+    dependences
+}
+
+/// An `IF` whose arms can both run: blocks are indices into the function
+struct Diamond {
+    head: usize,
+    condition: Register,
+    /// Arm blocks taken when the condition is true and false; `None` when
+    /// that side goes straight to the join
+    arms: [Option<usize>; 2],
+    join: usize,
+    /// The join's predecessor on the true and false side
+    edges: [BlockId; 2],
+}
+
+fn find_diamond(function: &SSAFunction) -> Option<Diamond> {
+    let index: HashMap<BlockId, usize> =
+        function.blocks.iter().enumerate().map(|(i, block)| (block.id, i)).collect();
+    let mut predecessors: HashMap<BlockId, Vec<BlockId>> = HashMap::new();
+    for block in &function.blocks {
+        for target in successors(block) {
+            predecessors.entry(target).or_default().push(block.id);
+        }
+    }
+
+    function.blocks.iter().enumerate().find_map(|(head, block)| {
+        let Some(SSAInstruction::Branch { condition, true_block, false_block }) =
+            block.instructions.last()
+        else {
+            return None;
+        };
+        // A side is an arm if it only computes and jumps on, and only the
+        // head reaches it; otherwise the head jumps straight to the join
+        let side = |target: BlockId| -> Option<(Option<usize>, BlockId, BlockId)> {
+            let arm = &function.blocks[*index.get(&target)?];
+            match arm.instructions.split_last() {
+                Some((SSAInstruction::Jump { target: join }, body))
+                    if body.iter().all(is_speculatable)
+                        && predecessors.get(&target).map(Vec::as_slice) == Some(&[block.id][..]) =>
+                {
+                    Some((Some(index[&target]), *join, target))
+                }
+                _ => Some((None, target, block.id)),
+            }
+        };
+        let (true_arm, true_join, true_edge) = side(*true_block)?;
+        let (false_arm, false_join, false_edge) = side(*false_block)?;
+
+        let join = *index.get(&true_join)?;
+        let join_block = &function.blocks[join];
+        let mut join_predecessors = predecessors.get(&true_join)?.clone();
+        join_predecessors.sort_by_key(|id| id.0);
+        let mut edges = vec![true_edge, false_edge];
+        edges.sort_by_key(|id| id.0);
+
+        let joins = true_join == false_join
+            && true_edge != false_edge
+            && true_join != block.id
+            && true_join != function.entry_block
+            && join_predecessors == edges
+            && join_block.instructions.iter().all(|inst| match inst {
+                SSAInstruction::Phi { incoming, .. } => incoming.len() == 2,
+                _ => true,
+            });
+        joins.then_some(Diamond {
+            head,
+            condition: *condition,
+            arms: [true_arm, false_arm],
+            join,
+            edges: [true_edge, false_edge],
+        })
+    })
+}
+
+fn successors(block: &BasicBlock) -> Vec<BlockId> {
+    match block.instructions.last() {
+        Some(SSAInstruction::Branch { true_block, false_block, .. }) => vec![*true_block, *false_block],
+        Some(SSAInstruction::Jump { target }) => vec![*target],
+        Some(SSAInstruction::Switch { cases, default, .. }) => {
+            cases.iter().map(|(_, target)| *target).chain([*default]).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Whether an instruction can run on the path not taken: it has no effect
+/// and cannot trap
+fn is_speculatable(inst: &SSAInstruction) -> bool {
+    match inst {
+        SSAInstruction::LoadInt { .. } | SSAInstruction::UnaryOp { .. } => true,
+        SSAInstruction::BinaryOp { op, .. } => !matches!(
+            op,
+            BinaryOperator::Div | BinaryOperator::Mod | BinaryOperator::UDiv | BinaryOperator::UMod
+        ),
+        _ => false,
+    }
+}
+
+struct Lowering {
+    next_register: usize,
+}
+
+impl Lowering {
+    fn fresh(&mut self) -> Register {
+        let reg = Register(self.next_register);
+        self.next_register += 1;
+        reg
+    }
+
+    fn binary(&mut self, out: &mut Vec<SSAInstruction>, op: BinaryOperator, left: Register, right: Register) -> Register {
+        let dest = self.fresh();
+        out.push(SSAInstruction::BinaryOp { dest, op, left, right });
+        dest
+    }
+
+    fn unary(&mut self, out: &mut Vec<SSAInstruction>, op: UnaryOperator, operand: Register) -> Register {
+        let dest = self.fresh();
+        out.push(SSAInstruction::UnaryOp { dest, op, operand });
+        dest
+    }
+
+    fn constant(&mut self, out: &mut Vec<SSAInstruction>, value: i64) -> Register {
+        let dest = self.fresh();
+        out.push(SSAInstruction::LoadInt { dest, value });
+        dest
+    }
+
+    /// `a xor b` as `(a or b) and not (a and b)`
+    fn xor(&mut self, out: &mut Vec<SSAInstruction>, a: Register, b: Register) -> Register {
+        let either = self.binary(out, BinaryOperator::Or, a, b);
+        let both = self.binary(out, BinaryOperator::And, a, b);
+        let not_both = self.unary(out, UnaryOperator::Not, both);
+        self.binary(out, BinaryOperator::And, either, not_both)
+    }
+
+    /// Replace `abs x` with `(x xor m) - m`, where `m` is all ones for a
+    /// negative `x`
+    fn lower_abs(&mut self, block: &mut BasicBlock) {
+        let instructions = std::mem::take(&mut block.instructions);
+        let locations = std::mem::take(&mut block.locations);
+        let mut locations = locations.into_iter();
+        for inst in instructions {
+            let location = locations.next().unwrap_or_default();
+            let lowered = match inst {
+                SSAInstruction::UnaryOp { dest, op: UnaryOperator::Abs, operand } => {
+                    let mut out = Vec::new();
+                    let zero = self.constant(&mut out, 0);
+                    let mask = self.binary(&mut out, BinaryOperator::Lt, operand, zero);
+                    let flipped = self.xor(&mut out, operand, mask);
+                    out.push(SSAInstruction::BinaryOp { dest, op: BinaryOperator::Sub, left: flipped, right: mask });
+                    out
+                }
+                inst => vec![inst],
+            };
+            block.locations.extend(std::iter::repeat_n(location, lowered.len()));
+            block.instructions.extend(lowered);
+        }
+    }
+
+    /// Run both arms in the head and pick each phi's value with a mask,
+    /// then fold the join into the head
+    fn convert(&mut self, function: &mut SSAFunction, diamond: Diamond) {
+        let head_id = function.blocks[diamond.head].id;
+        let join_id = function.blocks[diamond.join].id;
+        let head = &mut function.blocks[diamond.head];
+        head.instructions.pop();
+        let location = head.locations.pop().unwrap_or_default();
+
+        let mut out = Vec::new();
+        let mut out_locations = Vec::new();
+        for arm in diamond.arms.iter().flatten() {
+            let arm = &function.blocks[*arm];
+            let body = arm.instructions.len() - 1;
+            out.extend_from_slice(&arm.instructions[..body]);
+            out_locations.extend(
+                (0..body).map(|i| arm.locations.get(i).cloned().unwrap_or_else(|| location.clone())),
+            );
+        }
+
+        let join = &function.blocks[diamond.join];
+        let phis: Vec<_> = join
+            .instructions
+            .iter()
+            .filter_map(|inst| match inst {
+                SSAInstruction::Phi { dest, incoming } => Some((*dest, incoming.clone())),
+                _ => None,
+            })
+            .collect();
+        let rest: Vec<_> = join
+            .instructions
+            .iter()
+            .zip(join.locations.iter().cloned().chain(std::iter::repeat(SourceLocation::default())))
+            .filter(|(inst, _)| !matches!(inst, SSAInstruction::Phi { .. }))
+            .map(|(inst, location)| (inst.clone(), location))
+            .collect();
+
+        // mask is all ones when the condition holds, zero otherwise
+        let start = out.len();
+        let zero = self.constant(&mut out, 0);
+        let mask = self.binary(&mut out, BinaryOperator::Ne, diamond.condition, zero);
+        let inverse = self.unary(&mut out, UnaryOperator::Not, mask);
+        for (dest, incoming) in phis {
+            let value = |edge: BlockId| incoming.iter().find(|(from, _)| *from == edge).map(|(_, reg)| *reg);
+            let (Some(if_true), Some(if_false)) = (value(diamond.edges[0]), value(diamond.edges[1])) else {
+                continue;
+            };
+            let taken = self.binary(&mut out, BinaryOperator::And, if_true, mask);
+            let other = self.binary(&mut out, BinaryOperator::And, if_false, inverse);
+            out.push(SSAInstruction::BinaryOp { dest, op: BinaryOperator::Or, left: taken, right: other });
+        }
+        out_locations.extend(std::iter::repeat_n(location, out.len() - start));
+
+        let head = &mut function.blocks[diamond.head];
+        head.instructions.extend(out);
+        head.locations.extend(out_locations);
+        for (inst, location) in rest {
+            head.instructions.push(inst);
+            head.locations.push(location);
+        }
+
+        let removed: HashSet<BlockId> = diamond
+            .arms
+            .iter()
+            .flatten()
+            .map(|arm| function.blocks[*arm].id)
+            .chain([join_id])
+            .collect();
+        function.blocks.retain(|block| !removed.contains(&block.id));
+
+        // The join's successors now come from the head
+        for block in &mut function.blocks {
+            for pred in &mut block.predecessors {
+                if *pred == join_id {
+                    *pred = head_id;
+                }
+            }
+            for inst in &mut block.instructions {
+                if let SSAInstruction::Phi { incoming, .. } = inst {
+                    for (from, _) in incoming.iter_mut() {
+                        if *from == join_id {
+                            *from = head_id;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{convert_to_ssa, parse_program};
+
+    fn function(source: &str, name: &str) -> SSAFunction {
+        let program = parse_program(source).unwrap();
+        convert_to_ssa(&program).unwrap().into_iter().find(|f| f.name == name).unwrap()
+    }
+
+    fn branches(function: &SSAFunction) -> usize {
+        function
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .filter(|inst| matches!(inst, SSAInstruction::Branch { .. }))
+            .count()
+    }
+
+    #[test]
+    fn test_pure_conditionals_become_branchless() {
+        let source = ": pick ( a b f -- x ) if drop else swap drop then ;
+                      : clamp ( n -- n' ) dup 0 < if drop 0 else dup 9 > if drop 9 then then abs ;";
+        for name in ["pick", "clamp"] {
+            let mut f = function(source, name);
+            assert!(lower_branchless(&mut f) > 0, "{}", name);
+            assert_eq!(branches(&f), 0, "{}", name);
+            assert_eq!(f.blocks.len(), 1, "{}", name);
+            f.validate().unwrap();
+            assert!(secret_dependences(&f, &|_| false).is_empty(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_residual_secret_branches_are_reported() {
+        let source = ": slow ( a b -- q ) dup 0 = if drop 1 then / ;
+                      : guarded ( a b -- q ) dup if / else drop then ;
+                      : leak ( a b -- q ) slow ;";
+        let mut guarded = function(source, "guarded");
+        assert_eq!(lower_branchless(&mut guarded), 0, "division cannot run on the path not taken");
+        let found = secret_dependences(&guarded, &|_| false);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].callee, None);
+        assert!(found[0].to_string().starts_with("secret-dependent branch in 'guarded' at line 2"), "{}", found[0]);
+
+        let leak = function(source, "leak");
+        assert_eq!(secret_dependences(&leak, &|_| false)[0].callee.as_deref(), Some("slow"));
+        assert!(secret_dependences(&leak, &|name| name == "slow").is_empty());
+
+        let table = function(": sbox ( n -- n ) case 0 of 7 endof 1 of 3 endof 2 of 5 endof 0 swap endcase ;", "sbox");
+        assert_eq!(secret_dependences(&table, &|_| false).len(), 1);
+    }
+}
@@ -0,0 +1,139 @@
+//! Devirtualizing EXECUTE
+//!
+//! An `EXECUTE` becomes a `call_indirect` through the token on the stack.
+//! Often the word it reaches is known before the program runs:
+//!
+//! - the token was pushed in the same word, as in `['] sq swap execute`;
+//! - the program keeps the token of one word only, so every token is it.
+//!
+//! Those calls become direct calls, which the backends can inline. The
+//! rest keep their indirect call, and when a profile names the word they
+//! reach most, such as the tiered interpreter's counts, they are marked
+//! `likely` it: code generation then compares the token with that word's
+//! address and calls it directly when they match.
+
+use crate::intern::Symbol;
+use crate::ssa::{Register, SSAFunction, SSAInstruction};
+use std::collections::HashMap;
+
+/// How many `EXECUTE`s were devirtualized
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Devirtualized {
+    /// Replaced by a direct call
+    pub direct: usize,
+    /// Guarded by a test for the likely word
+    pub guarded: usize,
+}
+
+/// Devirtualize the indirect calls of `functions`
+///
+/// `targets` are the words whose tokens the program keeps, from
+/// [`crate::quotation::execution_targets`]; `likely` is the one a profile
+/// saw EXECUTE reach most, if any. A likely word the program keeps no
+/// token of is ignored.
+pub fn devirtualize(functions: &mut [SSAFunction], targets: &[Symbol], likely: Option<&str>) -> Devirtualized {
+    let only = match targets {
+        [only] => Some(*only),
+        _ => None,
+    };
+    let likely = likely.and_then(|name| targets.iter().copied().find(|target| *target == name));
+
+    let mut count = Devirtualized::default();
+    for function in functions {
+        let addresses: HashMap<Register, Symbol> = function
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .filter_map(|inst| match inst {
+                SSAInstruction::FunctionAddress { dest, name } => Some((*dest, *name)),
+                _ => None,
+            })
+            .collect();
+
+        for inst in function.blocks.iter_mut().flat_map(|block| &mut block.instructions) {
+            let SSAInstruction::CallIndirect { dest, target, args, likely: hint } = inst else {
+                continue;
+            };
+            if let Some(name) = addresses.get(target).copied().or(only) {
+                *inst = SSAInstruction::Call { dest: dest.clone(), name, args: args.clone() };
+                count.direct += 1;
+            } else if likely.is_some() {
+                *hint = likely;
+                count.guarded += 1;
+            }
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quotation::execution_targets;
+    use crate::{convert_to_ssa, parse_program, analyze};
+
+    fn compile(source: &str) -> (Vec<SSAFunction>, Vec<Symbol>) {
+        let program = parse_program(source).unwrap();
+        analyze(&program).unwrap();
+        let functions = convert_to_ssa(&program).unwrap();
+        let targets = execution_targets(&program).into_iter().map(|(name, _)| name).collect();
+        (functions, targets)
+    }
+
+    fn calls(function: &SSAFunction) -> Vec<String> {
+        function
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .filter_map(|inst| match inst {
+                SSAInstruction::Call { name, .. } => Some(format!("call {}", name)),
+                SSAInstruction::CallIndirect { likely, .. } => {
+                    Some(format!("call_indirect {}", likely.map_or("", |name| name.as_str())))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn function<'a>(functions: &'a [SSAFunction], name: &str) -> &'a SSAFunction {
+        functions.iter().find(|function| function.name == name).unwrap()
+    }
+
+    #[test]
+    fn test_one_target_is_called_directly() {
+        let (mut functions, targets) = compile(": sq ( n -- n ) dup * ; : apply ( n xt -- n ) execute ; 3 ' sq apply");
+        assert_eq!(calls(function(&functions, "apply")), ["call_indirect "]);
+
+        let count = devirtualize(&mut functions, &targets, None);
+        assert_eq!(count, Devirtualized { direct: 1, guarded: 0 });
+        assert_eq!(calls(function(&functions, "apply")), ["call sq"]);
+    }
+
+    #[test]
+    fn test_token_pushed_in_the_word_is_called_directly() {
+        let (mut functions, targets) = compile(
+            ": sq ( n -- n ) dup * ; : neg ( n -- n ) negate ; \
+             : f ( n -- n ) ['] sq swap ['] neg drop swap execute ; 3 f",
+        );
+        devirtualize(&mut functions, &targets, Some("neg"));
+        assert_eq!(calls(function(&functions, "f")), ["call sq"]);
+    }
+
+    #[test]
+    fn test_likely_target_guards_the_call() {
+        let source = ": sq ( n -- n ) dup * ; : neg ( n -- n ) negate ; \
+                      : apply ( n xt -- n ) execute ; 3 ' sq apply ' neg apply";
+        let (mut functions, targets) = compile(source);
+        let count = devirtualize(&mut functions, &targets, Some("neg"));
+        assert_eq!(count, Devirtualized { direct: 0, guarded: 1 });
+        assert_eq!(calls(function(&functions, "apply")), ["call_indirect neg"]);
+
+        // Without a profile, or with one naming a word no token is kept
+        // of, the call stays as it was
+        for likely in [None, Some("dup")] {
+            let (mut functions, targets) = compile(source);
+            assert_eq!(devirtualize(&mut functions, &targets, likely), Devirtualized::default());
+            assert_eq!(calls(function(&functions, "apply")), ["call_indirect "]);
+        }
+    }
+}
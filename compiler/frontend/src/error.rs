@@ -1,5 +1,11 @@
 //! Error types for the Fast Forth compiler
+//!
+//! Errors carry the [`SourceLocation`] of the token or word they concern
+//! when it is known. Errors raised where no location is at hand (inside a
+//! word's stack effect checks, for instance) are given one on the way out
+//! with [`ForthError::at`].
 
+use crate::ast::SourceLocation;
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, ForthError>;
@@ -13,16 +19,25 @@ pub enum ForthError {
         message: String,
     },
 
-    #[error("Lexical error at position {position}: {message}")]
+    #[error("Lexical error at line {line}, column {column}: {message}")]
     LexError {
         position: usize,
+        line: usize,
+        column: usize,
         message: String,
     },
 
     #[error("Undefined word: {word}")]
     UndefinedWord {
         word: String,
-        line: Option<usize>,
+        location: Option<SourceLocation>,
+    },
+
+    #[error("Word '{word}' is not available in {target} mode")]
+    UnavailableWord {
+        word: String,
+        target: String,
+        location: Option<SourceLocation>,
     },
 
     #[error("Stack underflow in word '{word}': expected {expected} items, found {found}")]
@@ -30,6 +45,7 @@ pub enum ForthError {
         word: String,
         expected: usize,
         found: usize,
+        location: Option<SourceLocation>,
     },
 
     #[error("Stack depth mismatch in {word}: {message}")]
@@ -38,6 +54,7 @@ pub enum ForthError {
         then_depth: usize,
         else_depth: usize,
         message: String,
+        location: Option<SourceLocation>,
     },
 
     #[error("Stack overflow: maximum depth {max} exceeded")]
@@ -49,17 +66,21 @@ pub enum ForthError {
     TypeError {
         expected: String,
         found: String,
-        location: Option<String>,
+        /// Construct the types were compared in, such as "IF branches"
+        context: Option<String>,
+        location: Option<SourceLocation>,
     },
 
     #[error("Invalid stack effect declaration: {declaration}")]
     InvalidStackEffect {
         declaration: String,
+        location: Option<SourceLocation>,
     },
 
     #[error("Redefinition of word: {word}")]
     RedefinitionError {
         word: String,
+        location: Option<SourceLocation>,
     },
 
     #[error("Control structure mismatch: expected {expected}, found {found}")]
@@ -76,6 +97,13 @@ pub enum ForthError {
     #[error("SSA conversion error: {message}")]
     SSAConversionError {
         message: String,
+        location: Option<SourceLocation>,
+    },
+
+    #[error("Compile-time evaluation failed: {message}")]
+    CompileTimeError {
+        message: String,
+        location: Option<SourceLocation>,
     },
 
     #[error("Internal compiler error: {message}")]
@@ -96,7 +124,7 @@ impl ForthError {
     pub fn undefined_word(word: impl Into<String>) -> Self {
         ForthError::UndefinedWord {
             word: word.into(),
-            line: None,
+            location: None,
         }
     }
 
@@ -104,7 +132,57 @@ impl ForthError {
         ForthError::TypeError {
             expected: expected.into(),
             found: found.into(),
+            context: None,
             location: None,
         }
     }
+
+    /// Source location the error points at, if known
+    pub fn location(&self) -> Option<SourceLocation> {
+        match self {
+            ForthError::ParseError { line, column, .. } | ForthError::LexError { line, column, .. } => {
+                Some(SourceLocation { line: *line, column: *column }).filter(SourceLocation::is_known)
+            }
+            ForthError::UndefinedWord { location, .. }
+            | ForthError::UnavailableWord { location, .. }
+            | ForthError::StackUnderflow { location, .. }
+            | ForthError::StackMismatch { location, .. }
+            | ForthError::TypeError { location, .. }
+            | ForthError::InvalidStackEffect { location, .. }
+            | ForthError::RedefinitionError { location, .. }
+            | ForthError::SSAConversionError { location, .. }
+            | ForthError::CompileTimeError { location, .. } => location.clone(),
+            ForthError::StackOverflow { .. }
+            | ForthError::ControlStructureMismatch { .. }
+            | ForthError::InvalidImmediateWord { .. }
+            | ForthError::InternalError { .. } => None,
+        }
+    }
+
+    /// Point the error at `at`, unless it already has a location
+    pub fn at(mut self, at: &SourceLocation) -> Self {
+        if !at.is_known() || self.location().is_some() {
+            return self;
+        }
+        match &mut self {
+            ForthError::ParseError { line, column, .. } | ForthError::LexError { line, column, .. } => {
+                *line = at.line;
+                *column = at.column;
+            }
+            ForthError::UndefinedWord { location, .. }
+            | ForthError::UnavailableWord { location, .. }
+            | ForthError::StackUnderflow { location, .. }
+            | ForthError::StackMismatch { location, .. }
+            | ForthError::TypeError { location, .. }
+            | ForthError::InvalidStackEffect { location, .. }
+            | ForthError::RedefinitionError { location, .. }
+            | ForthError::SSAConversionError { location, .. }
+            | ForthError::CompileTimeError { location, .. } => *location = Some(at.clone()),
+            ForthError::StackOverflow { .. }
+            | ForthError::ControlStructureMismatch { .. }
+            | ForthError::InvalidImmediateWord { .. }
+            | ForthError::InternalError { .. } => {}
+        }
+        self
+    }
 }
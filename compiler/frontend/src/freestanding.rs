@@ -0,0 +1,133 @@
+//! Freestanding Targets
+//!
+//! A freestanding program runs without libc: on a microcontroller, in a
+//! kernel, or anywhere the host only offers what the minimal runtime's
+//! hooks provide (`runtime/forth_freestanding.c`). Character I/O and
+//! pictured numeric output work through those hooks; words that need a
+//! file system, a process environment, a shell or a heap do not, and using one
+//! is a compile-time error. A program that defines a word of the same
+//! name uses its own definition and is not affected.
+
+use crate::ast::{Program, SourceLocation, Word};
+use crate::error::{ForthError, Result};
+use std::collections::HashSet;
+
+/// Words that need a hosted (libc) runtime
+pub const HOSTED_WORDS: &[&str] = &[
+    // Files
+    "r/o", "w/o", "r/w", "create-file", "open-file", "read-file", "write-file", "close-file", "delete-file",
+    // Blocks, kept in a memory-mapped file
+    "open-blocks", "block", "buffer", "update", "save-buffers", "empty-buffers", "flush",
+    // Process environment
+    "arg-count", "arg@", "getenv", "system",
+    // Heap, whose blocks the minimal runtime's bump allocator cannot free
+    "allocate", "free", "resize",
+    // Tasks and channels, on POSIX threads
+    "task:", "join", "pause", "channel", "send", "recv", "close-channel", "destroy-channel",
+];
+
+/// Whether `word` needs a hosted runtime
+pub fn is_hosted_word(word: &str) -> bool {
+    HOSTED_WORDS.contains(&word.to_lowercase().as_str())
+}
+
+/// Uses of hosted words the program does not define itself, in source order
+pub fn hosted_words(program: &Program) -> Vec<(String, SourceLocation)> {
+    restricted_words(program, is_hosted_word)
+}
+
+/// Uses of the words `restricted` accepts that the program does not define
+/// itself, in source order; `task:` is checked as a word
+pub fn restricted_words(program: &Program, restricted: impl Fn(&str) -> bool) -> Vec<(String, SourceLocation)> {
+    let defined: HashSet<String> = program
+        .definitions
+        .iter()
+        .map(|def| def.name.to_lowercase())
+        .chain(program.code_words.iter().map(|word| word.name.to_lowercase()))
+        .collect();
+
+    let restricted = &restricted as &dyn Fn(&str) -> bool;
+    let mut uses = Vec::new();
+    for def in &program.definitions {
+        collect_restricted(&def.body, restricted, &defined, &mut uses);
+    }
+    collect_restricted(&program.top_level_code, restricted, &defined, &mut uses);
+    for test in &program.tests {
+        collect_restricted(&test.body, restricted, &defined, &mut uses);
+        collect_restricted(&test.expected, restricted, &defined, &mut uses);
+    }
+    uses.sort_by_key(|(_, location)| (location.line, location.column));
+    uses
+}
+
+/// Reject the first use of a word a freestanding runtime cannot provide
+pub fn check_freestanding(program: &Program) -> Result<()> {
+    match hosted_words(program).into_iter().next() {
+        Some((word, location)) => Err(ForthError::UnavailableWord {
+            word,
+            target: "freestanding".to_string(),
+            location: Some(location),
+        }),
+        None => Ok(()),
+    }
+}
+
+fn collect_restricted(
+    words: &[Word],
+    restricted: &dyn Fn(&str) -> bool,
+    defined: &HashSet<String>,
+    uses: &mut Vec<(String, SourceLocation)>,
+) {
+    for word in words {
+        match word {
+            Word::WordRef { name, location } if restricted(name) && !defined.contains(&name.to_lowercase()) => {
+                uses.push((name.to_string(), location.clone()));
+            }
+            Word::TaskSpawn { location, .. } if restricted("task:") => uses.push(("task:".to_string(), location.clone())),
+            Word::If { then_branch, else_branch } => {
+                collect_restricted(then_branch, restricted, defined, uses);
+                if let Some(else_branch) = else_branch {
+                    collect_restricted(else_branch, restricted, defined, uses);
+                }
+            }
+            Word::BeginUntil { body } | Word::DoLoop { body, .. } => collect_restricted(body, restricted, defined, uses),
+            Word::BeginWhileRepeat { condition, body } => {
+                collect_restricted(condition, restricted, defined, uses);
+                collect_restricted(body, restricted, defined, uses);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    #[test]
+    fn test_hosted_words_are_found_in_control_flow() {
+        let program = parse_program(": greet 72 emit cr ;\n: args 0 if arg-count drop then ;\ngreet 1 block drop").unwrap();
+        let uses: Vec<(String, usize)> =
+            hosted_words(&program).into_iter().map(|(name, location)| (name, location.line)).collect();
+        assert_eq!(uses, vec![("arg-count".to_string(), 2), ("block".to_string(), 3)]);
+    }
+
+    #[test]
+    fn test_check_freestanding() {
+        let program = parse_program(": main 42 . cr ;").unwrap();
+        assert!(check_freestanding(&program).is_ok());
+
+        let program = parse_program(": flush 0 ;\n: main flush drop ;").unwrap();
+        assert!(check_freestanding(&program).is_ok(), "a program's own definition wins");
+
+        let program = parse_program(": main\n  s\" x\" r/o open-file ;").unwrap();
+        match check_freestanding(&program) {
+            Err(ForthError::UnavailableWord { word, location, .. }) => {
+                assert_eq!(word, "r/o");
+                assert_eq!(location.map(|location| location.line), Some(2));
+            }
+            other => panic!("expected an unavailable word, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! Interned Names
+//!
+//! Word names and string literals recur throughout a program: every call
+//! to `dup` names it again, and the AST, the SSA form, the optimizer IR and
+//! the code generators all hold on to those names. A [`Symbol`] is a `u32`
+//! index into a process-wide table that stores each distinct name once, so
+//! symbols are `Copy`, compare and hash as integers, and cost nothing to
+//! pass between stages.
+//!
+//! A symbol reads like a `&str`. Names stay in the table for the life of
+//! the process; the parser's [`Interner`] additionally counts what sharing
+//! saved in one program.
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{OnceLock, RwLock};
+
+/// An interned name
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// Names by index, and indices by name
+#[derive(Default)]
+struct Table {
+    names: Vec<&'static str>,
+    indices: FxHashMap<&'static str, u32>,
+}
+
+fn table() -> &'static RwLock<Table> {
+    static TABLE: OnceLock<RwLock<Table>> = OnceLock::new();
+    TABLE.get_or_init(Default::default)
+}
+
+impl Symbol {
+    /// The symbol for `name`, adding it to the table if it is new
+    pub fn intern(name: &str) -> Self {
+        if let Some(&index) = table().read().unwrap_or_else(|e| e.into_inner()).indices.get(name) {
+            return Symbol(index);
+        }
+        let mut table = table().write().unwrap_or_else(|e| e.into_inner());
+        if let Some(&index) = table.indices.get(name) {
+            return Symbol(index);
+        }
+        let index = u32::try_from(table.names.len()).expect("more than u32::MAX distinct names");
+        let name: &'static str = Box::leak(name.into());
+        table.names.push(name);
+        table.indices.insert(name, index);
+        Symbol(index)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        table().read().unwrap_or_else(|e| e.into_inner()).names[self.0 as usize]
+    }
+
+    /// Position in the table
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+/// Symbols sort by name, so output ordered by symbol does not depend on
+/// the order names were first seen in
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self.0 == other.0 {
+            Ordering::Equal
+        } else {
+            self.as_str().cmp(other.as_str())
+        }
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Symbol::intern(&name)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl From<Symbol> for String {
+    fn from(symbol: Symbol) -> Self {
+        symbol.as_str().to_string()
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<Symbol> for str {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Symbol> for &str {
+    fn eq(&self, other: &Symbol) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl PartialEq<Symbol> for String {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.as_str()
+    }
+}
+
+/// What interning saved in one program
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InternStats {
+    /// Distinct names stored
+    pub symbols: usize,
+    /// Bytes of the distinct names
+    pub bytes: usize,
+    /// Names looked up, including repeats
+    pub lookups: usize,
+    /// Bytes repeats would have allocated without interning
+    pub bytes_saved: usize,
+}
+
+/// Interns the names of one program, counting what sharing saved
+#[derive(Debug, Default)]
+pub struct Interner {
+    symbols: FxHashSet<Symbol>,
+    stats: InternStats,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The symbol for `name`, shared with every earlier lookup of it
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        self.stats.lookups += 1;
+        let symbol = Symbol::intern(name);
+        if self.symbols.insert(symbol) {
+            self.stats.symbols += 1;
+            self.stats.bytes += name.len();
+        } else {
+            self.stats.bytes_saved += name.len();
+        }
+        symbol
+    }
+
+    pub fn stats(&self) -> InternStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeated_names_share_one_allocation() {
+        let mut interner = Interner::new();
+        let first = interner.intern("dup");
+        let second = interner.intern("dup");
+        let other = interner.intern("swap");
+
+        assert_eq!(first.index(), second.index());
+        assert_eq!(first.as_str().as_ptr(), second.as_str().as_ptr());
+        assert_eq!(first, "dup");
+        assert_eq!(first, Symbol::from("dup"));
+        assert_ne!(first, other);
+        assert!(other > first, "symbols order by name");
+        assert_eq!(
+            interner.stats(),
+            InternStats { symbols: 2, bytes: 7, lookups: 3, bytes_saved: 3 }
+        );
+    }
+}
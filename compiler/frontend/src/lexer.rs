@@ -3,12 +3,27 @@
 use crate::ast::{SourceLocation, Token};
 use crate::error::{ForthError, Result};
 
+/// How far into a `CODE name ( effect ) ... END-CODE` block the lexer is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CodeBlock {
+    Outside,
+    /// After CODE, before the name
+    Name,
+    /// In the stack effect after the name
+    Effect,
+    /// After the stack effect: the rest up to END-CODE is assembly
+    Body,
+}
+
 /// Lexer state
 pub struct Lexer<'a> {
     input: &'a str,
     position: usize,
     line: usize,
     column: usize,
+    /// Where the most recently returned token starts
+    token_start: SourceLocation,
+    code_block: CodeBlock,
 }
 
 impl<'a> Lexer<'a> {
@@ -18,6 +33,8 @@ impl<'a> Lexer<'a> {
             position: 0,
             line: 1,
             column: 1,
+            token_start: SourceLocation { line: 1, column: 1 },
+            code_block: CodeBlock::Outside,
         }
     }
 
@@ -28,6 +45,16 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Lexical error pointing at the start of the current token
+    fn error(&self, message: impl Into<String>) -> ForthError {
+        ForthError::LexError {
+            position: self.position,
+            line: self.token_start.line,
+            column: self.token_start.column,
+            message: message.into(),
+        }
+    }
+
     /// Peek at the next character without consuming it
     fn peek(&self) -> Option<char> {
         self.input[self.position..].chars().next()
@@ -57,13 +84,18 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    /// Skip a line comment (starting with \)
-    fn skip_line_comment(&mut self) {
+    /// Skip a line comment (starting with \), returning its text when it
+    /// holds attributes (starts with @)
+    fn line_comment(&mut self) -> Option<String> {
+        let mut text = String::new();
         while let Some(ch) = self.advance() {
             if ch == '\n' {
                 break;
             }
+            text.push(ch);
         }
+        let text = text.strip_prefix('\\').unwrap_or(&text).trim();
+        text.starts_with('@').then(|| text.to_string())
     }
 
     /// Parse a parenthesized comment or stack effect
@@ -99,10 +131,7 @@ impl<'a> Lexer<'a> {
                     self.advance();
                 }
                 None => {
-                    return Err(ForthError::LexError {
-                        position: self.position,
-                        message: "Unclosed parenthesized comment".to_string(),
-                    });
+                    return Err(self.error("Unclosed parenthesized comment"));
                 }
             }
         }
@@ -120,7 +149,7 @@ impl<'a> Lexer<'a> {
             // It's a regular comment, consume the closing paren and skip it
             self.advance(); // consume ')'
             // Skip the comment entirely
-            self.next_token()
+            self.lex_token()
         }
     }
 
@@ -145,10 +174,7 @@ impl<'a> Lexer<'a> {
                         Some('"') => value.push('"'),
                         Some(ch) => value.push(ch),
                         None => {
-                            return Err(ForthError::LexError {
-                                position: self.position,
-                                message: "Unexpected end of string".to_string(),
-                            })
+                            return Err(self.error("Unexpected end of string"))
                         }
                     }
                 }
@@ -157,10 +183,7 @@ impl<'a> Lexer<'a> {
                     self.advance();
                 }
                 None => {
-                    return Err(ForthError::LexError {
-                        position: self.position,
-                        message: "Unterminated string literal".to_string(),
-                    })
+                    return Err(self.error("Unterminated string literal"))
                 }
             }
         }
@@ -196,17 +219,11 @@ impl<'a> Lexer<'a> {
         if is_float {
             num_str.parse::<f64>()
                 .map(Token::Float)
-                .map_err(|_| ForthError::LexError {
-                    position: self.position,
-                    message: format!("Invalid float literal: {}", num_str),
-                })
+                .map_err(|_| self.error(format!("Invalid float literal: {}", num_str)))
         } else {
             num_str.parse::<i64>()
                 .map(Token::Integer)
-                .map_err(|_| ForthError::LexError {
-                    position: self.position,
-                    message: format!("Invalid integer literal: {}", num_str),
-                })
+                .map_err(|_| self.error(format!("Invalid integer literal: {}", num_str)))
         }
     }
 
@@ -238,22 +255,84 @@ impl<'a> Lexer<'a> {
             "VARIABLE" => Token::Variable,
             "CONSTANT" => Token::Constant,
             "IMMEDIATE" => Token::Immediate,
+            "T{" => Token::TestStart,
+            "->" => Token::TestArrow,
+            "}T" => Token::TestEnd,
+            "CODE" => Token::Code,
             _ => Token::Word(word),
         }
     }
 
     /// Get the next token
     pub fn next_token(&mut self) -> Result<Token> {
+        if self.code_block == CodeBlock::Body {
+            self.code_block = CodeBlock::Outside;
+            return self.lex_assembly();
+        }
+
+        let token = self.lex_token()?;
+        self.code_block = match (self.code_block, &token) {
+            (_, Token::Code) => CodeBlock::Name,
+            (CodeBlock::Name, Token::Word(_)) => CodeBlock::Effect,
+            (CodeBlock::Effect, Token::RightParen) => CodeBlock::Body,
+            (CodeBlock::Effect, Token::LeftParen | Token::Word(_) | Token::Integer(_) | Token::StackEffectSep) => {
+                CodeBlock::Effect
+            }
+            _ => CodeBlock::Outside,
+        };
+        Ok(token)
+    }
+
+    /// Read the assembly of a CODE word, up to and including END-CODE
+    ///
+    /// END-CODE ends the block wherever it appears as a whole word.
+    fn lex_assembly(&mut self) -> Result<Token> {
+        self.token_start = self.location();
+        let mut text = String::new();
+        loop {
+            while let Some(ch) = self.peek().filter(|ch| ch.is_whitespace()) {
+                text.push(ch);
+                self.advance();
+            }
+            let rest = &self.input[self.position..];
+            let word = &rest[..rest.find(char::is_whitespace).unwrap_or(rest.len())];
+            if word.is_empty() {
+                return Err(self.error("Unterminated CODE word: missing END-CODE"));
+            }
+            let is_end = word.eq_ignore_ascii_case("end-code");
+            if !is_end {
+                text.push_str(word);
+            }
+            for _ in 0..word.chars().count() {
+                self.advance();
+            }
+            if is_end {
+                return Ok(Token::Assembly(text));
+            }
+        }
+    }
+
+    /// Lex one token, ignoring CODE blocks
+    fn lex_token(&mut self) -> Result<Token> {
         self.skip_whitespace();
+        self.token_start = self.location();
 
         match self.peek() {
             None => Ok(Token::Eof),
             Some(':') => {
                 self.advance();
+                // `:}` closes a locals declaration
+                if self.peek() == Some('}') {
+                    return Ok(self.parse_word(':'));
+                }
                 Ok(Token::Colon)
             }
             Some(';') => {
                 self.advance();
+                // `;]` closes a quotation
+                if self.peek() == Some(']') {
+                    return Ok(self.parse_word(';'));
+                }
                 Ok(Token::Semicolon)
             }
             Some('(') => self.parse_paren_comment(),
@@ -262,10 +341,10 @@ impl<'a> Lexer<'a> {
                 Ok(Token::RightParen)
             }
             Some('"') => self.parse_string(),
-            Some('\\') => {
-                self.skip_line_comment();
-                self.next_token()
-            }
+            Some('\\') => match self.line_comment() {
+                Some(attributes) => Ok(Token::Attribute(attributes)),
+                None => self.lex_token(),
+            },
             Some('-') => {
                 self.advance();
                 if self.peek() == Some('-') {
@@ -275,7 +354,7 @@ impl<'a> Lexer<'a> {
                     if ch.is_ascii_digit() {
                         self.parse_number('-')
                     } else {
-                        Ok(Token::Word("-".to_string()))
+                        Ok(self.parse_word('-'))
                     }
                 } else {
                     Ok(Token::Word("-".to_string()))
@@ -324,12 +403,36 @@ impl<'a> Lexer<'a> {
         }
         Ok(tokens)
     }
+
+    /// Tokenize the entire input, also returning where each token starts
+    pub fn tokenize_with_locations(&mut self) -> Result<(Vec<Token>, Vec<SourceLocation>)> {
+        let mut tokens = Vec::new();
+        let mut locations = Vec::new();
+        loop {
+            let token = self.next_token()?;
+            let done = token == Token::Eof;
+            tokens.push(token);
+            locations.push(self.token_start.clone());
+            if done {
+                break;
+            }
+        }
+        Ok((tokens, locations))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_tokenize_attributes() {
+        let mut lexer = Lexer::new("\\ @inline(never) @hot\n\\ plain comment\n: f ;");
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens[0], Token::Attribute("@inline(never) @hot".to_string()));
+        assert_eq!(tokens[1], Token::Colon);
+    }
+
     #[test]
     fn test_tokenize_simple() {
         let mut lexer = Lexer::new(": double 2 * ;");
@@ -360,6 +463,62 @@ mod tests {
         assert_eq!(tokens[4], Token::Then);
     }
 
+    #[test]
+    fn test_tokenize_test_case() {
+        let mut lexer = Lexer::new("T{ 1 -rot -> 3 }T");
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens[0], Token::TestStart);
+        assert_eq!(tokens[2], Token::Word("-rot".to_string()));
+        assert_eq!(tokens[3], Token::TestArrow);
+        assert_eq!(tokens[5], Token::TestEnd);
+    }
+
+    #[test]
+    fn test_token_locations() {
+        let mut lexer = Lexer::new("1 \\ comment\n  ( note ) dup");
+        let (tokens, locations) = lexer.tokenize_with_locations().unwrap();
+        assert_eq!(tokens[1], Token::Word("dup".to_string()));
+        assert_eq!(locations[0], SourceLocation { line: 1, column: 1 });
+        assert_eq!(locations[1], SourceLocation { line: 2, column: 12 });
+    }
+
+    #[test]
+    fn test_error_location() {
+        let mut lexer = Lexer::new("1 2\n  \" oops");
+        let error = lexer.tokenize().unwrap_err();
+        assert_eq!(error.location(), Some(SourceLocation { line: 2, column: 3 }));
+    }
+
+    #[test]
+    fn test_tokenize_code_word() {
+        let mut lexer = Lexer::new("code add ( a b -- n )\n  lea (%rdi,%rsi), %rax ; ret\nEND-CODE 1");
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens[0], Token::Code);
+        assert_eq!(tokens[1], Token::Word("add".to_string()));
+        assert_eq!(tokens[8], Token::Assembly("\n  lea (%rdi,%rsi), %rax ; ret\n".to_string()));
+        assert_eq!(tokens[9], Token::Integer(1));
+
+        let error = Lexer::new("code nop ( -- ) ret").tokenize().unwrap_err();
+        assert!(error.to_string().contains("END-CODE"));
+    }
+
+    #[test]
+    fn test_tokenize_locals() {
+        let tokens = Lexer::new(": f {: a b -- c :} ;").tokenize().unwrap();
+        assert_eq!(tokens[2], Token::Word("{:".to_string()));
+        assert_eq!(tokens[5], Token::StackEffectSep);
+        assert_eq!(tokens[7], Token::Word(":}".to_string()));
+        assert_eq!(tokens[8], Token::Semicolon);
+    }
+
+    #[test]
+    fn test_tokenize_quotation() {
+        let tokens = Lexer::new(": f [: 1 + ;] execute ;").tokenize().unwrap();
+        assert_eq!(tokens[2], Token::Word("[:".to_string()));
+        assert_eq!(tokens[5], Token::Word(";]".to_string()));
+        assert_eq!(tokens[7], Token::Semicolon);
+    }
+
     #[test]
     fn test_tokenize_float() {
         let mut lexer = Lexer::new("3.14159 1.0e-10");
@@ -9,20 +9,33 @@
 
 pub mod error;
 pub mod ast;
+pub mod intern;
 pub mod lexer;
 pub mod parser;
+pub mod comptime;
 pub mod stack_effects;
 pub mod type_inference;
 pub mod ssa;
 pub mod ssa_validator;
 pub mod semantic;
+pub mod ans;
+pub mod constant_time;
+pub mod stack_bounds;
+pub mod freestanding;
+pub mod sandbox;
+pub mod arithmetic;
+pub mod case;
+pub mod quotation;
+pub mod devirtualize;
 
 pub use error::{ForthError, Result};
-pub use ast::{Program, Definition, Word, StackEffect};
-pub use parser::parse_program;
+pub use ast::{Program, Definition, Attributes, InlineHint, CodeWord, Word, StackEffect};
+pub use parser::{parse_program, parse_program_recovering};
 pub use semantic::analyze;
-pub use ssa::{convert_to_ssa, SSAFunction};
+pub use ssa::{convert_to_ssa, convert_to_ssa_with_stack_buffer, SSAFunction};
 pub use ssa_validator::SSAValidator;
+pub use arithmetic::{umul_high, Arithmetic, ArithmeticFault, Division, Overflow, RESULT_OUT_OF_RANGE};
+pub use intern::{InternStats, Interner, Symbol};
 
 #[cfg(test)]
 mod tests {
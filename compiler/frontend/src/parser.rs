@@ -1,21 +1,176 @@
 //! Parser for Forth source code
 
 use crate::ast::*;
+use crate::comptime::Interpreter;
 use crate::error::{ForthError, Result};
+use crate::intern::Interner;
 use crate::lexer::Lexer;
+use std::collections::HashMap;
+
+/// Bytes in a cell
+const CELL_SIZE: usize = 8;
 
 /// Parser state
 pub struct Parser {
     tokens: Vec<Token>,
+    locations: Vec<SourceLocation>,
     position: usize,
+    /// Definitions parsed so far, for running immediate words
+    definitions: HashMap<String, Definition>,
+    /// Constants parsed so far
+    constants: HashMap<String, i64>,
+    /// Record layouts parsed so far
+    records: HashMap<String, Record>,
+    /// Data stack of compile-time evaluation
+    comptime_stack: Vec<i64>,
+    /// Whether a definition is being compiled
+    compiling: bool,
+    /// Locals declared so far in the definition being compiled
+    locals: Vec<Symbol>,
+    /// Hidden definitions of the quotations parsed since the last one was
+    /// added to the program
+    quotations: Vec<Definition>,
+    /// Quotations parsed so far, to number their hidden definitions
+    quotation_count: usize,
+    /// Errors to collect before giving up; 0 stops at the first error
+    error_limit: usize,
+    /// Errors recovered from so far
+    errors: Vec<ForthError>,
+    /// Attribute comments taken out of the token stream, with the position
+    /// of the token they precede
+    attributes: Vec<(usize, String, SourceLocation)>,
+    /// Word names and string literals, stored once each
+    names: Interner,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
+        Self::with_locations(tokens, Vec::new())
+    }
+
+    /// Create a parser that knows where each token starts
+    pub fn with_locations(tokens: Vec<Token>, locations: Vec<SourceLocation>) -> Self {
+        let mut kept = Vec::with_capacity(tokens.len());
+        let mut kept_locations = Vec::with_capacity(locations.len());
+        let mut attributes = Vec::new();
+        for (index, token) in tokens.into_iter().enumerate() {
+            let location = locations.get(index).cloned();
+            match token {
+                Token::Attribute(text) => attributes.push((kept.len(), text, location.unwrap_or_default())),
+                token => {
+                    kept.push(token);
+                    kept_locations.extend(location);
+                }
+            }
+        }
+
         Self {
-            tokens,
+            tokens: kept,
+            locations: kept_locations,
             position: 0,
+            definitions: HashMap::new(),
+            constants: HashMap::new(),
+            records: HashMap::new(),
+            comptime_stack: Vec::new(),
+            compiling: false,
+            locals: Vec::new(),
+            quotations: Vec::new(),
+            quotation_count: 0,
+            error_limit: 0,
+            errors: Vec::new(),
+            attributes,
+            names: Interner::new(),
+        }
+    }
+
+    /// Recover from errors instead of stopping at the first, until `limit`
+    /// of them have been collected (0 stops at the first error)
+    pub fn set_error_limit(&mut self, limit: usize) {
+        self.error_limit = limit;
+    }
+
+    /// Errors recovered from while parsing
+    pub fn errors(&self) -> &[ForthError] {
+        &self.errors
+    }
+
+    /// Record an error and carry on when recovering, otherwise fail with it
+    ///
+    /// Once the limit is reached the rest of the input is skipped.
+    fn report(&mut self, error: ForthError) -> Result<()> {
+        if self.error_limit == 0 {
+            return Err(error);
+        }
+        if self.errors.len() < self.error_limit {
+            self.errors.push(error);
+            if self.errors.len() == self.error_limit {
+                self.position = self.tokens.len();
+            }
+        }
+        Ok(())
+    }
+
+    /// Skip to where top-level parsing can resume after an error that began
+    /// at `start`: the next definition, or past the `;` ending the broken one
+    fn synchronize(&mut self, start: usize) {
+        if self.position == start {
+            self.advance();
+            return;
+        }
+        loop {
+            match self.peek() {
+                Token::Eof | Token::Colon => break,
+                Token::Semicolon => {
+                    self.advance();
+                    break;
+                }
+                _ => {
+                    self.advance();
+                }
+            }
+        }
+    }
+
+    /// Parse the next words of a body into `body`, skipping the offending
+    /// token when recovering from an error
+    fn parse_into(&mut self, body: &mut Vec<Word>) -> Result<()> {
+        let start = self.position;
+        match self.parse_next() {
+            Ok(words) => body.extend(words),
+            Err(error) => {
+                self.report(error)?;
+                if self.position == start {
+                    self.advance();
+                }
+            }
         }
+        Ok(())
+    }
+
+    /// Whether an open control structure ends here without its closing
+    /// word: at the end of input or the `;` of the enclosing definition
+    fn at_block_end(&self) -> bool {
+        matches!(self.peek(), Token::Eof | Token::Semicolon)
+    }
+
+    /// Location of the current token (default when unknown)
+    fn location(&self) -> SourceLocation {
+        self.locations.get(self.position).cloned().unwrap_or_default()
+    }
+
+    /// Location of the token just consumed
+    fn previous_location(&self) -> SourceLocation {
+        self.locations.get(self.position.wrapping_sub(1)).cloned().unwrap_or_default()
+    }
+
+    /// Parse error at the current token
+    fn error(&self, message: impl Into<String>) -> ForthError {
+        error_at(&self.location(), message)
+    }
+
+    /// Parse error at the token just consumed
+    fn error_after(&self, message: impl Into<String>) -> ForthError {
+        error_at(&self.previous_location(), message)
     }
 
     /// Peek at current token
@@ -36,11 +191,7 @@ impl Parser {
         if std::mem::discriminant(&token) == std::mem::discriminant(&expected) {
             Ok(())
         } else {
-            Err(ForthError::ParseError {
-                line: 0,
-                column: 0,
-                message: format!("Expected {:?}, found {:?}", expected, token),
-            })
+            Err(self.error_after(format!("Expected {:?}, found {:?}", expected, token)))
         }
     }
 
@@ -49,97 +200,416 @@ impl Parser {
         let mut program = Program::new();
         let mut pending_value: Option<i64> = None;
 
+        let misplaced: Vec<ForthError> = self
+            .attributes
+            .iter()
+            .filter(|(position, ..)| self.tokens.get(*position) != Some(&Token::Colon))
+            .map(|(_, text, location)| error_at(location, format!("{} must come right before a colon definition", text)))
+            .collect();
+        for error in misplaced {
+            self.report(error)?;
+        }
+
         while !matches!(self.peek(), Token::Eof) {
-            match self.peek() {
-                Token::Colon => {
-                    // If we have a pending value, push it first
-                    if let Some(value) = pending_value.take() {
-                        program.top_level_code.push(Word::IntLiteral(value));
-                    }
-                    let def = self.parse_definition()?;
-                    program.definitions.push(def);
+            let start = self.position;
+            if let Err(error) = self.parse_top_level(&mut program, &mut pending_value) {
+                self.report(error)?;
+                self.synchronize(start);
+            }
+            program.definitions.append(&mut self.quotations);
+        }
+
+        // Push any remaining pending value
+        if let Some(value) = pending_value {
+            program.top_level_code.push(Word::IntLiteral(value));
+        }
+
+        crate::ans::resolve_environment_queries(&mut program)?;
+        crate::quotation::resolve_quotations(&mut program)?;
+        program.names = self.names.stats();
+        Ok(program)
+    }
+
+    /// Parse one top-level item: a definition, declaration, test or word
+    fn parse_top_level(&mut self, program: &mut Program, pending_value: &mut Option<i64>) -> Result<()> {
+        match self.peek() {
+            Token::Colon => {
+                // If we have a pending value, push it first
+                if let Some(value) = pending_value.take() {
+                    program.top_level_code.push(Word::IntLiteral(value));
                 }
-                Token::Variable => {
-                    // If we have a pending value, push it first
-                    if let Some(value) = pending_value.take() {
-                        program.top_level_code.push(Word::IntLiteral(value));
-                    }
-                    self.advance();
+                let def = self.parse_definition()?;
+                self.definitions.insert(def.name.to_lowercase(), def.clone());
+                // Quotations come before the definition that uses them
+                program.definitions.append(&mut self.quotations);
+                program.definitions.push(def);
+            }
+            Token::Variable => {
+                // If we have a pending value, push it first
+                if let Some(value) = pending_value.take() {
+                    program.top_level_code.push(Word::IntLiteral(value));
+                }
+                self.advance();
+                if let Token::Word(name) = self.advance() {
+                    program.top_level_code.push(Word::Variable { name, size: CELL_SIZE, record: None });
+                } else {
+                    return Err(self.error_after("Expected variable name"));
+                }
+            }
+            Token::Constant => {
+                self.advance();
+                // The value should have been parsed as the previous token
+                if let Some(value) = pending_value.take() {
                     if let Token::Word(name) = self.advance() {
-                        program.top_level_code.push(Word::Variable { name });
+                        self.constants.insert(name.to_lowercase(), value);
+                        program.top_level_code.push(Word::Constant { name, value });
                     } else {
-                        return Err(ForthError::ParseError {
-                            line: 0,
-                            column: 0,
-                            message: "Expected variable name".to_string(),
-                        });
+                        return Err(self.error_after("Expected constant name"));
                     }
+                } else {
+                    return Err(self.error("Expected constant value before CONSTANT"));
                 }
-                Token::Constant => {
-                    self.advance();
-                    // The value should have been parsed as the previous token
-                    if let Some(value) = pending_value.take() {
-                        if let Token::Word(name) = self.advance() {
-                            program.top_level_code.push(Word::Constant { name, value });
-                        } else {
-                            return Err(ForthError::ParseError {
-                                line: 0,
-                                column: 0,
-                                message: "Expected constant name".to_string(),
-                            });
-                        }
-                    } else {
-                        return Err(ForthError::ParseError {
-                            line: 0,
-                            column: 0,
-                            message: "Expected constant value before CONSTANT".to_string(),
-                        });
-                    }
+            }
+            Token::Word(word) if word.eq_ignore_ascii_case("struct") => {
+                // If we have a pending value, push it first
+                if let Some(value) = pending_value.take() {
+                    program.top_level_code.push(Word::IntLiteral(value));
                 }
-                Token::Integer(value) => {
-                    // If we have a pending value, push it first
-                    if let Some(prev_value) = pending_value.take() {
-                        program.top_level_code.push(Word::IntLiteral(prev_value));
-                    }
-                    // Save this value in case the next token is CONSTANT
-                    pending_value = Some(*value);
-                    self.advance();
+                self.parse_struct(program)?;
+            }
+            Token::Word(word) if word.eq_ignore_ascii_case("table:") => {
+                // If we have a pending value, push it first
+                if let Some(value) = pending_value.take() {
+                    program.top_level_code.push(Word::IntLiteral(value));
                 }
-                _ => {
-                    // If we have a pending value, push it first
-                    if let Some(value) = pending_value.take() {
-                        program.top_level_code.push(Word::IntLiteral(value));
+                let def = self.parse_table()?;
+                self.definitions.insert(def.name.to_lowercase(), def.clone());
+                program.definitions.push(def);
+            }
+            Token::Word(word) if word.eq_ignore_ascii_case("create") => {
+                // If we have a pending value, push it first
+                if let Some(value) = pending_value.take() {
+                    program.top_level_code.push(Word::IntLiteral(value));
+                }
+                self.advance();
+                let name = self.parse_name("CREATE")?;
+                let usage = "CREATE needs a size and ALLOT, e.g. CREATE buf 4 CELLS ALLOT";
+                let Some((size, record)) = self.parse_size()? else {
+                    return Err(self.error(usage));
+                };
+                if !self.at_word("allot") {
+                    return Err(self.error(usage));
+                }
+                self.advance();
+                program.top_level_code.push(Word::Variable { name, size, record });
+            }
+            Token::TestStart => {
+                // If we have a pending value, push it first
+                if let Some(value) = pending_value.take() {
+                    program.top_level_code.push(Word::IntLiteral(value));
+                }
+                let test = self.parse_test_case()?;
+                program.tests.push(test);
+            }
+            Token::Code => {
+                // If we have a pending value, push it first
+                if let Some(value) = pending_value.take() {
+                    program.top_level_code.push(Word::IntLiteral(value));
+                }
+                let word = self.parse_code_word()?;
+                program.code_words.push(word);
+            }
+            Token::Integer(value) => {
+                // If we have a pending value, push it first
+                if let Some(prev_value) = pending_value.take() {
+                    program.top_level_code.push(Word::IntLiteral(prev_value));
+                }
+                // Save this value in case the next token is CONSTANT
+                *pending_value = Some(*value);
+                self.advance();
+            }
+            _ => {
+                // Outside a definition [IF] reads the value just interpreted
+                if self.at_word("[if]") && self.comptime_stack.is_empty() {
+                    if let Some(flag) = pending_value
+                        .take()
+                        .or_else(|| self.take_top_level_value(&mut program.top_level_code))
+                    {
+                        self.comptime_stack.push(flag);
                     }
-                    let word = self.parse_word()?;
-                    program.top_level_code.push(word);
                 }
+                // If we have a pending value, push it first
+                if let Some(value) = pending_value.take() {
+                    program.top_level_code.push(Word::IntLiteral(value));
+                }
+                let words = self.parse_next()?;
+                program.top_level_code.extend(words);
             }
         }
+        Ok(())
+    }
 
-        // Push any remaining pending value
-        if let Some(value) = pending_value {
-            program.top_level_code.push(Word::IntLiteral(value));
+    /// Parse a record layout (STRUCT name ... [size] FIELD name ... END-STRUCT)
+    ///
+    /// Each field takes the size written before its FIELD, one cell if none
+    /// is. The record's size word and field accessors become definitions.
+    fn parse_struct(&mut self, program: &mut Program) -> Result<()> {
+        let location = self.location();
+        self.advance();
+        let name = self.parse_name("record")?;
+
+        let mut fields: Vec<Field> = Vec::new();
+        let mut offset: usize = 0;
+        let mut aligned = false;
+        while !self.at_word("end-struct") {
+            let (size, record) = self.parse_size()?.unwrap_or((CELL_SIZE, None));
+            if !self.at_word("field") {
+                return Err(self.error(format!("Expected FIELD or END-STRUCT in record {}, found {:?}", name, self.peek())));
+            }
+            self.advance();
+            let field = self.parse_name("field")?;
+            if fields.iter().any(|other| other.name.eq_ignore_ascii_case(&field)) {
+                return Err(self.error_after(format!("Record {} already has a field {}", name, field)));
+            }
+
+            // Fields a whole number of cells long start on a cell boundary
+            if size % CELL_SIZE == 0 {
+                offset = offset.next_multiple_of(CELL_SIZE);
+                aligned = true;
+            }
+            fields.push(Field { name: field, offset, size, record });
+            offset += size;
         }
+        self.advance();
 
-        Ok(program)
+        // Padded so the cell fields of an array of records stay aligned
+        let size = if aligned { offset.next_multiple_of(CELL_SIZE) } else { offset };
+        let record = Record { name, size, fields, location };
+
+        let inline = Attributes { inline: Some(InlineHint::Always), ..Attributes::default() };
+        let mut definitions = vec![Definition {
+            name: record.name.clone(),
+            body: vec![Word::IntLiteral(size as i64)],
+            immediate: false,
+            stack_effect: Some(StackEffect::new(Vec::new(), vec![StackType::Int])),
+            attributes: inline.clone(),
+            location: record.location.clone(),
+        }];
+        for field in &record.fields {
+            let body = vec![
+                Word::IntLiteral(field.offset as i64),
+                Word::WordRef { name: self.names.intern("+"), location: record.location.clone() },
+            ];
+            let output = field.record.clone().map_or(StackType::Addr, StackType::Record);
+            definitions.push(Definition {
+                name: field.name.clone(),
+                body,
+                immediate: false,
+                stack_effect: Some(StackEffect::new(vec![StackType::Record(record.name.clone())], vec![output])),
+                attributes: inline.clone(),
+                location: record.location.clone(),
+            });
+        }
+        for def in definitions {
+            self.definitions.insert(def.name.to_lowercase(), def.clone());
+            program.definitions.push(def);
+        }
+
+        self.records.insert(record.name.to_lowercase(), record.clone());
+        program.records.push(record);
+        Ok(())
+    }
+
+    /// Parse a constant table (TABLE: name v0 v1 ... ;)
+    ///
+    /// The table becomes a word ( index -- value ): a CASE on the index,
+    /// giving 0 for an index outside the table.
+    fn parse_table(&mut self) -> Result<Definition> {
+        let location = self.location();
+        self.advance();
+        let name = self.parse_name("table")?;
+
+        let mut arms = Vec::new();
+        loop {
+            let value = match self.advance() {
+                Token::Semicolon => break,
+                Token::Integer(value) => value,
+                Token::Word(word) => match self.constants.get(&word.to_lowercase()) {
+                    Some(&value) => value,
+                    None => return Err(self.error_after(format!("Table {} can only hold numbers and constants, found {}", name, word))),
+                },
+                token => return Err(self.error_after(format!("Expected a value or ; in table {}, found {:?}", name, token))),
+            };
+            // Values may be laid out as for `,`: `table: t 1 , 2 , ;`
+            if self.at_word(",") {
+                self.advance();
+            }
+            arms.push((vec![Word::IntLiteral(arms.len() as i64)], vec![Word::IntLiteral(value)]));
+        }
+
+        let swap = Word::WordRef { name: self.names.intern("swap"), location: location.clone() };
+        let body = self.case_words(arms, vec![Word::IntLiteral(0), swap], &location);
+        Ok(Definition {
+            name,
+            body,
+            immediate: false,
+            stack_effect: Some(StackEffect::new(vec![StackType::Int], vec![StackType::Int])),
+            attributes: Attributes::default(),
+            location,
+        })
+    }
+
+    /// Parse a size in bytes written before FIELD or ALLOT: `n`, `n CELLS`,
+    /// `n CHARS`, `CELL`, `CHAR` or the name of a record, which also gives
+    /// the record it holds
+    fn parse_size(&mut self) -> Result<Option<(usize, Option<String>)>> {
+        let size = match self.peek().clone() {
+            Token::Integer(count) => {
+                self.advance();
+                let count = usize::try_from(count).map_err(|_| self.error_after("A size cannot be negative"))?;
+                let unit = if self.at_word("cells") {
+                    self.advance();
+                    CELL_SIZE
+                } else {
+                    if self.at_word("chars") {
+                        self.advance();
+                    }
+                    1
+                };
+                let size = count.checked_mul(unit).ok_or_else(|| self.error_after("Size too large"))?;
+                (size, None)
+            }
+            Token::Word(word) if word.eq_ignore_ascii_case("cell") => {
+                self.advance();
+                (CELL_SIZE, None)
+            }
+            Token::Word(word) if word.eq_ignore_ascii_case("char") => {
+                self.advance();
+                (1, None)
+            }
+            Token::Word(word) => match self.records.get(&word.to_lowercase()) {
+                Some(record) => {
+                    let size = (record.size, Some(record.name.clone()));
+                    self.advance();
+                    size
+                }
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(size))
+    }
+
+    /// Parse the name a defining word gives
+    fn parse_name(&mut self, what: &str) -> Result<String> {
+        match self.advance() {
+            Token::Word(name) => Ok(name),
+            token => Err(self.error_after(format!("Expected {} name, found {:?}", what, token))),
+        }
+    }
+
+    /// Parse a word written in assembly (CODE name ( effect ) ... END-CODE)
+    fn parse_code_word(&mut self) -> Result<CodeWord> {
+        let location = self.location();
+        self.expect(Token::Code)?;
+
+        let name = match self.advance() {
+            Token::Word(name) => name,
+            token => {
+                return Err(self.error_after(format!("Expected word name, found {:?}", token)))
+            }
+        };
+
+        // Calls to the word are compiled from its declared effect alone
+        let Some(stack_effect) = self.parse_stack_effect()? else {
+            return Err(error_at(&location, format!("CODE word {} needs a stack effect, e.g. ( a b -- n )", name)));
+        };
+        if stack_effect.outputs.len() > 1 {
+            return Err(error_at(&location, format!("CODE word {} can return at most one cell", name)));
+        }
+        if stack_effect.inputs.iter().chain(&stack_effect.outputs).any(|ty| *ty == StackType::Float) {
+            return Err(error_at(&location, format!("CODE word {} can only take and return cells", name)));
+        }
+
+        let assembly = match self.advance() {
+            Token::Assembly(text) => text.trim().to_string(),
+            token => {
+                return Err(self.error_after(format!("Expected assembly, found {:?}", token)))
+            }
+        };
+        Ok(CodeWord { name, stack_effect, assembly, location })
+    }
+
+    /// Attributes written in the comments right before the token at
+    /// `position`, which starts the definition of `name`
+    fn attributes_at(&self, position: usize, name: &str) -> Result<Attributes> {
+        let mut attributes = Attributes::default();
+        for (_, text, location) in self.attributes.iter().filter(|(at, ..)| *at == position) {
+            // Text after the attributes is an ordinary comment
+            for item in text.split_whitespace().take_while(|item| item.starts_with('@')) {
+                let inline = match item.to_lowercase().as_str() {
+                    "@inline(always)" => Some(InlineHint::Always),
+                    "@inline(never)" => Some(InlineHint::Never),
+                    "@optimize(none)" => {
+                        attributes.optimize_none = true;
+                        None
+                    }
+                    "@hot" => {
+                        attributes.hot = true;
+                        None
+                    }
+                    "@constant-time" => {
+                        attributes.constant_time = true;
+                        None
+                    }
+                    lower if lower.starts_with("@recursion(") && lower.ends_with(')') => {
+                        let depth = lower["@recursion(".len()..lower.len() - 1].parse::<usize>().ok().filter(|&depth| depth > 0);
+                        let Some(depth) = depth else {
+                            return Err(error_at(location, format!("{} needs a positive depth", item)));
+                        };
+                        attributes.recursion = Some(depth);
+                        None
+                    }
+                    "@export" => {
+                        attributes.export = Some(name.replace('-', "_"));
+                        None
+                    }
+                    lower if lower.starts_with("@export(") && lower.ends_with(')') => {
+                        attributes.export = Some(item["@export(".len()..item.len() - 1].to_string());
+                        None
+                    }
+                    _ => return Err(error_at(location, format!("Unknown attribute {}", item))),
+                };
+                if let Some(inline) = inline {
+                    if attributes.inline.is_some_and(|previous| previous != inline) {
+                        return Err(error_at(location, "@inline(always) conflicts with @inline(never)"));
+                    }
+                    attributes.inline = Some(inline);
+                }
+            }
+            if attributes.optimize_none && attributes.inline == Some(InlineHint::Always) {
+                return Err(error_at(location, "@inline(always) conflicts with @optimize(none)"));
+            }
+            if let Some(symbol) = attributes.export.as_deref().filter(|symbol| !is_c_identifier(symbol)) {
+                return Err(error_at(location, format!("{} cannot be exported as '{}'; name a C symbol with @export(symbol)", name, symbol)));
+            }
+        }
+        Ok(attributes)
     }
 
     /// Parse a word definition (: name ... ;)
     fn parse_definition(&mut self) -> Result<Definition> {
+        let location = self.location();
+        let start = self.position;
         self.expect(Token::Colon)?;
 
         let name = match self.advance() {
             Token::Word(name) => name,
             token => {
-                return Err(ForthError::ParseError {
-                    line: 0,
-                    column: 0,
-                    message: format!("Expected word name, found {:?}", token),
-                })
+                return Err(self.error_after(format!("Expected word name, found {:?}", token)))
             }
         };
-
-        let location = SourceLocation::default();
+        let attributes = self.attributes_at(start, &name)?;
 
         // Parse optional stack effect comment
         let stack_effect = if matches!(self.peek(), Token::LeftParen) {
@@ -150,6 +620,8 @@ impl Parser {
 
         let mut body = Vec::new();
         let mut immediate = false;
+        self.compiling = true;
+        self.locals.clear();
 
         // Parse definition body
         loop {
@@ -159,18 +631,14 @@ impl Parser {
                     break;
                 }
                 Token::Eof => {
-                    return Err(ForthError::ParseError {
-                        line: 0,
-                        column: 0,
-                        message: format!("Unterminated definition: {}", name),
-                    })
-                }
-                _ => {
-                    let word = self.parse_word()?;
-                    body.push(word);
+                    self.report(error_at(&location, format!("Unterminated definition: {}", name)))?;
+                    break;
                 }
+                _ => self.parse_into(&mut body)?,
             }
         }
+        self.compiling = false;
+        self.locals.clear();
 
         // Check for IMMEDIATE after semicolon
         if matches!(self.peek(), Token::Immediate) {
@@ -178,11 +646,69 @@ impl Parser {
             immediate = true;
         }
 
+        // Only an immediate word can compile code when it runs
+        if !immediate {
+            if let Some(word) = compile_only_word(&body) {
+                let error = error_at(&location, format!("{} in {} needs the word to be IMMEDIATE", word.to_uppercase(), name));
+                self.report(error)?;
+            }
+        }
+
         Ok(Definition {
             name,
             body,
             immediate,
             stack_effect,
+            attributes,
+            location,
+        })
+    }
+
+    /// Parse a test case T{ ... -> ... }T
+    fn parse_test_case(&mut self) -> Result<TestCase> {
+        let location = self.location();
+        self.expect(Token::TestStart)?;
+
+        let mut body = Vec::new();
+        let mut expected = Vec::new();
+        let mut seen_arrow = false;
+
+        loop {
+            match self.peek() {
+                Token::TestArrow if !seen_arrow => {
+                    self.advance();
+                    seen_arrow = true;
+                }
+                Token::TestEnd if seen_arrow => {
+                    self.advance();
+                    break;
+                }
+                Token::TestArrow | Token::TestEnd | Token::TestStart | Token::Eof => {
+                    let message = if seen_arrow {
+                        "Unterminated T{ (expected }T)"
+                    } else {
+                        "Expected -> in T{ ... }T"
+                    };
+                    return Err(ForthError::ParseError {
+                        line: location.line,
+                        column: location.column,
+                        message: message.to_string(),
+                    });
+                }
+                _ => {
+                    let words = self.parse_next()?;
+                    if seen_arrow {
+                        expected.extend(words);
+                    } else {
+                        body.extend(words);
+                    }
+                }
+            }
+        }
+
+        Ok(TestCase {
+            body,
+            expected,
             location,
         })
     }
@@ -218,7 +744,10 @@ impl Parser {
                         "bool" | "flag" => StackType::Bool,
                         "c" | "char" => StackType::Char,
                         "s" | "string" => StackType::String,
-                        _ => StackType::Unknown,
+                        _ => match self.records.get(&name.to_lowercase()) {
+                            Some(record) => StackType::Record(record.name.clone()),
+                            None => StackType::Unknown,
+                        },
                     };
 
                     if before_separator {
@@ -227,12 +756,19 @@ impl Parser {
                         outputs.push(stack_type);
                     }
                 }
+                // A number names a cell holding that value, as in `( n -- 0 )`
+                Token::Integer(_) | Token::Float(_) => {
+                    let stack_type =
+                        if matches!(self.peek(), Token::Float(_)) { StackType::Float } else { StackType::Int };
+                    self.advance();
+                    if before_separator {
+                        inputs.push(stack_type);
+                    } else {
+                        outputs.push(stack_type);
+                    }
+                }
                 Token::Eof => {
-                    return Err(ForthError::ParseError {
-                        line: 0,
-                        column: 0,
-                        message: "Unterminated stack effect".to_string(),
-                    })
+                    return Err(self.error("Unterminated stack effect"))
                 }
                 _ => {
                     self.advance(); // Skip other tokens in comments
@@ -243,6 +779,345 @@ impl Parser {
         Ok(Some(StackEffect::new(inputs, outputs)))
     }
 
+    /// Parse the next word, running it first if it executes at compile time
+    ///
+    /// Returns the words to compile in its place: none for conditional
+    /// compilation, or whatever an immediate word compiled.
+    fn parse_next(&mut self) -> Result<Vec<Word>> {
+        let Token::Word(name) = self.peek() else {
+            return Ok(vec![self.parse_word()?]);
+        };
+        let name = name.to_lowercase();
+        let location = self.location();
+
+        // `TO` on a local stores to it, and on anything else is left alone
+        if name == "to" && self.compiling {
+            if let Some(local) = self.local_after_to() {
+                self.advance();
+                let location = self.location();
+                self.advance();
+                return Ok(vec![Word::LocalStore { name: local, location }]);
+            }
+        }
+
+        match name.as_str() {
+            "[if]" => {
+                self.advance();
+                if self.pop_comptime("[IF]")? == 0 {
+                    self.skip_conditional(true)?;
+                }
+                Ok(Vec::new())
+            }
+            "[else]" => {
+                self.advance();
+                self.skip_conditional(false)?;
+                Ok(Vec::new())
+            }
+            "[then]" => {
+                self.advance();
+                Ok(Vec::new())
+            }
+            "[" => {
+                self.advance();
+                self.interpret_brackets().map_err(|e| e.at(&location))
+            }
+            // Left for the immediate word being defined to run
+            "literal" if self.compiling && self.comptime_stack.is_empty() => Ok(vec![self.parse_word()?]),
+            "literal" if self.compiling => {
+                self.advance();
+                let value = self.pop_comptime("LITERAL")?;
+                Ok(vec![Word::IntLiteral(value)])
+            }
+            "postpone" if self.compiling => {
+                let postpone = self.parse_word()?;
+                match self.peek() {
+                    Token::Word(_) => Ok(vec![postpone, self.parse_word()?]),
+                    token if control_word(&token.to_string().to_lowercase()).is_some() => {
+                        let name = self.names.intern(&token.to_string().to_lowercase());
+                        let location = self.location();
+                        self.advance();
+                        Ok(vec![postpone, Word::WordRef { name, location }])
+                    }
+                    token => Err(self.error(format!("Expected word after POSTPONE, found {:?}", token))),
+                }
+            }
+            "case" => {
+                self.advance();
+                self.parse_case(location)
+            }
+            "{:" if self.compiling => {
+                self.advance();
+                self.parse_locals(location)
+            }
+            "locals|" if self.compiling => {
+                self.advance();
+                self.parse_ans_locals(location)
+            }
+            "[:" => {
+                self.advance();
+                self.parse_quotation(location)
+            }
+            // Locals shadow every word, immediate ones too
+            _ if self.local(&name).is_some() => Ok(vec![self.parse_word()?]),
+            "'" | "[']" => {
+                self.advance();
+                self.parse_tick(&name, location)
+            }
+            "task:" => {
+                self.advance();
+                match self.peek() {
+                    Token::Word(word) => {
+                        let word = word.clone();
+                        let word = self.names.intern(&word);
+                        self.advance();
+                        Ok(vec![Word::TaskSpawn { word, location }])
+                    }
+                    token => Err(self.error(format!("Expected word after TASK:, found {:?}", token))),
+                }
+            }
+            _ => match self.definitions.get(&name) {
+                Some(def) if def.immediate => {
+                    let def = def.clone();
+                    self.advance();
+                    let compiled = self.run_comptime(&def.body).map_err(|e| e.at(&location))?;
+                    if !compiled.iter().any(|word| matches!(word, Word::WordRef { name, .. } if control_word(name).is_some())) {
+                        return Ok(compiled);
+                    }
+                    // Postponed control words open or close structures
+                    // around the call, so they are parsed with its neighbours
+                    self.splice(compiled, &location)?;
+                    Ok(Vec::new())
+                }
+                _ => Ok(vec![self.parse_word()?]),
+            },
+        }
+    }
+
+    /// The local in scope named `name`, the latest declared if several are
+    fn local(&self, name: &str) -> Option<Symbol> {
+        self.locals.iter().rev().find(|local| local.eq_ignore_ascii_case(name)).copied()
+    }
+
+    /// The local the `TO` at the current token stores to, if it names one
+    fn local_after_to(&self) -> Option<Symbol> {
+        match self.tokens.get(self.position + 1) {
+            Some(Token::Word(name)) => self.local(name),
+            _ => None,
+        }
+    }
+
+    /// Parse the rest of `{: args | uninitialized -- outputs :}`
+    ///
+    /// The outputs are a comment, as in a stack effect.
+    fn parse_locals(&mut self, location: SourceLocation) -> Result<Vec<Word>> {
+        let mut args = Vec::new();
+        let mut uninitialized = Vec::new();
+        let mut after_bar = false;
+        let mut outputs = false;
+        loop {
+            match self.advance() {
+                Token::Word(word) if word == ":}" => break,
+                Token::Eof => return Err(error_at(&location, "Unterminated {: (expected :})")),
+                Token::StackEffectSep => outputs = true,
+                _ if outputs => {}
+                Token::Word(word) if word == "|" && !after_bar => after_bar = true,
+                Token::Word(name) if after_bar => uninitialized.push(self.names.intern(&name)),
+                Token::Word(name) => args.push(self.names.intern(&name)),
+                token => return Err(self.error_after(format!("Expected a local name in {{:, found {:?}", token))),
+            }
+        }
+        self.locals.extend(args.iter().chain(&uninitialized).copied());
+        Ok(vec![Word::Locals { args, uninitialized, location }])
+    }
+
+    /// Parse the rest of `LOCALS| a b |`, where `a` takes the top of the stack
+    fn parse_ans_locals(&mut self, location: SourceLocation) -> Result<Vec<Word>> {
+        let mut args = Vec::new();
+        loop {
+            match self.advance() {
+                Token::Word(word) if word == "|" => break,
+                Token::Eof => return Err(error_at(&location, "Unterminated LOCALS| (expected |)")),
+                Token::Word(name) => args.push(self.names.intern(&name)),
+                token => return Err(self.error_after(format!("Expected a local name in LOCALS|, found {:?}", token))),
+            }
+        }
+        args.reverse();
+        self.locals.extend(args.iter().copied());
+        Ok(vec![Word::Locals { args, uninitialized: Vec::new(), location }])
+    }
+
+    /// Parse the rest of `[: body ;]` into a hidden definition
+    ///
+    /// The body may start with a stack effect comment. It sees none of the
+    /// enclosing definition's locals: quotations capture nothing.
+    fn parse_quotation(&mut self, location: SourceLocation) -> Result<Vec<Word>> {
+        let name = self.quotation_name();
+        let stack_effect = if matches!(self.peek(), Token::LeftParen) {
+            self.parse_stack_effect()?
+        } else {
+            None
+        };
+
+        let outer_locals = std::mem::take(&mut self.locals);
+        let compiling = std::mem::replace(&mut self.compiling, true);
+        let mut body = Vec::new();
+        let result = loop {
+            match self.peek() {
+                Token::Word(word) if word == ";]" => {
+                    self.advance();
+                    break Ok(());
+                }
+                Token::Semicolon | Token::Eof => break Err(error_at(&location, "Unterminated [: (expected ;])")),
+                _ => {
+                    if let Err(error) = self.parse_into(&mut body) {
+                        break Err(error);
+                    }
+                }
+            }
+        };
+        self.compiling = compiling;
+        self.locals = outer_locals;
+        result?;
+
+        Ok(vec![self.hidden_quotation(name, body, stack_effect, location)])
+    }
+
+    /// Parse the word after `'` or `[']` into the execution token it pushes
+    ///
+    /// A word the program defines is its own token; anything else, such as
+    /// a builtin, gets a quotation calling it.
+    fn parse_tick(&mut self, tick: &str, location: SourceLocation) -> Result<Vec<Word>> {
+        let Token::Word(name) = self.peek() else {
+            return Err(self.error(format!("Expected a word after {}, found {:?}", tick, self.peek())));
+        };
+        if let Some(def) = self.definitions.get(&name.to_lowercase()) {
+            let name = self.names.intern(&def.name);
+            self.advance();
+            return Ok(vec![Word::Quotation { name, location }]);
+        }
+        let name = self.quotation_name();
+        let body = vec![self.parse_word()?];
+        Ok(vec![self.hidden_quotation(name, body, None, location)])
+    }
+
+    /// Name of the next hidden quotation definition
+    fn quotation_name(&mut self) -> String {
+        self.quotation_count += 1;
+        format!("[:{}]", self.quotation_count)
+    }
+
+    /// Make `body` the hidden quotation definition `name`, returning the
+    /// word that pushes its token
+    fn hidden_quotation(&mut self, name: String, body: Vec<Word>, stack_effect: Option<StackEffect>, location: SourceLocation) -> Word {
+        let def = Definition {
+            name: name.clone(),
+            body,
+            immediate: false,
+            stack_effect,
+            attributes: Attributes { inline: Some(InlineHint::Always), ..Attributes::default() },
+            location: location.clone(),
+        };
+        self.definitions.insert(name.clone(), def.clone());
+        self.quotations.push(def);
+        Word::Quotation { name: self.names.intern(&name), location }
+    }
+
+    /// Whether the current token is the word `name` (case-insensitive)
+    fn at_word(&self, name: &str) -> bool {
+        matches!(self.peek(), Token::Word(word) if word.eq_ignore_ascii_case(name))
+    }
+
+    fn pop_comptime(&mut self, word: &str) -> Result<i64> {
+        self.comptime_stack.pop().ok_or_else(|| ForthError::CompileTimeError {
+            message: format!("{} needs a value on the stack", word),
+            location: Some(self.previous_location()),
+        })
+    }
+
+    /// Run words at compile time, returning anything they compile
+    fn run_comptime(&mut self, words: &[Word]) -> Result<Vec<Word>> {
+        let mut compiled = Vec::new();
+        Interpreter::new(&self.definitions, &self.constants).run(words, &mut self.comptime_stack, &mut compiled)?;
+        Ok(compiled)
+    }
+
+    /// Put compiled `words` back in the input, to be parsed next
+    fn splice(&mut self, words: Vec<Word>, location: &SourceLocation) -> Result<()> {
+        let mut tokens = Vec::with_capacity(words.len());
+        let mut locations = Vec::with_capacity(words.len());
+        for word in words {
+            let (token, at) = match word {
+                Word::IntLiteral(value) => (Token::Integer(value), location.clone()),
+                Word::WordRef { name, location } => {
+                    (control_word(&name).unwrap_or_else(|| Token::Word(name.to_string())), location)
+                }
+                word => return Err(error_at(location, format!("cannot compile {:?} along with a postponed control word", word))),
+            };
+            tokens.push(token);
+            locations.push(at);
+        }
+        self.tokens.splice(self.position..self.position, tokens);
+        if !self.locations.is_empty() {
+            self.locations.splice(self.position..self.position, locations);
+        }
+        Ok(())
+    }
+
+    /// Parse and interpret the words up to the closing `]`
+    fn interpret_brackets(&mut self) -> Result<Vec<Word>> {
+        let compiling = std::mem::replace(&mut self.compiling, false);
+        let mut words = Vec::new();
+
+        while !self.at_word("]") {
+            if matches!(self.peek(), Token::Eof) {
+                return Err(self.error("Unterminated [ (expected ])"));
+            }
+            words.extend(self.parse_next()?);
+        }
+        self.advance();
+        self.compiling = compiling;
+
+        self.run_comptime(&words)
+    }
+
+    /// Take the value a top-level `[IF]` tests from the code before it
+    fn take_top_level_value(&self, code: &mut Vec<Word>) -> Option<i64> {
+        let value = match code.last()? {
+            Word::IntLiteral(value) => *value,
+            Word::WordRef { name, .. } => *self.constants.get(&name.to_lowercase())?,
+            _ => return None,
+        };
+        code.pop();
+        Some(value)
+    }
+
+    /// Skip tokens of a false `[IF]` or a taken `[ELSE]`
+    ///
+    /// Stops after the matching `[THEN]`, or after the matching `[ELSE]`
+    /// when `to_else` is set.
+    fn skip_conditional(&mut self, to_else: bool) -> Result<()> {
+        let mut depth = 0;
+
+        loop {
+            match self.advance() {
+                Token::Word(word) if word.eq_ignore_ascii_case("[if]") => depth += 1,
+                Token::Word(word) if word.eq_ignore_ascii_case("[else]") && depth == 0 && to_else => {
+                    return Ok(())
+                }
+                Token::Word(word) if word.eq_ignore_ascii_case("[then]") => {
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                    depth -= 1;
+                }
+                Token::Eof => {
+                    return Err(self.error("Unterminated [IF] (expected [THEN])"))
+                }
+                _ => {}
+            }
+        }
+    }
+
     /// Parse a single word
     fn parse_word(&mut self) -> Result<Word> {
         match self.peek().clone() {
@@ -256,7 +1131,7 @@ impl Parser {
             }
             Token::String(value) => {
                 self.advance();
-                Ok(Word::StringLiteral(value))
+                Ok(Word::StringLiteral(self.names.intern(&value)))
             }
             Token::If => {
                 self.advance();
@@ -271,17 +1146,14 @@ impl Parser {
                 self.parse_do_loop()
             }
             Token::Word(name) => {
+                let location = self.location();
                 self.advance();
-                Ok(Word::WordRef {
-                    name,
-                    location: SourceLocation::default(),
-                })
+                if let Some(local) = self.local(&name) {
+                    return Ok(Word::LocalRef { name: local, location });
+                }
+                Ok(Word::WordRef { name: self.names.intern(&name), location })
             }
-            token => Err(ForthError::ParseError {
-                line: 0,
-                column: 0,
-                message: format!("Unexpected token: {:?}", token),
-            }),
+            token => Err(self.error(format!("Unexpected token: {:?}", token))),
         }
     }
 
@@ -309,31 +1181,22 @@ impl Parser {
                                     else_branch,
                                 });
                             }
-                            Token::Eof => {
-                                return Err(ForthError::ParseError {
-                                    line: 0,
-                                    column: 0,
-                                    message: "Unterminated IF...ELSE".to_string(),
-                                })
-                            }
-                            _ => {
-                                let word = self.parse_word()?;
-                                else_body.push(word);
+                            _ if self.at_block_end() => {
+                                self.report(self.error("Unterminated IF...ELSE"))?;
+                                return Ok(Word::If {
+                                    then_branch,
+                                    else_branch: Some(else_body),
+                                });
                             }
+                            _ => self.parse_into(&mut else_body)?,
                         }
                     }
                 }
-                Token::Eof => {
-                    return Err(ForthError::ParseError {
-                        line: 0,
-                        column: 0,
-                        message: "Unterminated IF".to_string(),
-                    })
-                }
-                _ => {
-                    let word = self.parse_word()?;
-                    then_branch.push(word);
+                _ if self.at_block_end() => {
+                    self.report(self.error("Unterminated IF"))?;
+                    break;
                 }
+                _ => self.parse_into(&mut then_branch)?,
             }
         }
 
@@ -343,6 +1206,53 @@ impl Parser {
         })
     }
 
+    /// Parse CASE ... k OF ... ENDOF ... ENDCASE into the tests it stands
+    /// for: `k OVER = IF DROP ... ELSE ... THEN` for each arm, the default
+    /// in the innermost ELSE, then ENDCASE's DROP
+    fn parse_case(&mut self, location: SourceLocation) -> Result<Vec<Word>> {
+        let mut arms = Vec::new();
+        let mut words = Vec::new();
+        loop {
+            if self.at_word("of") {
+                self.advance();
+                let mut body = Vec::new();
+                while !self.at_word("endof") {
+                    if self.at_block_end() || self.at_word("endcase") {
+                        return Err(self.error("Unterminated OF (expected ENDOF)"));
+                    }
+                    self.parse_into(&mut body)?;
+                }
+                self.advance();
+                arms.push((std::mem::take(&mut words), body));
+            } else if self.at_word("endcase") {
+                self.advance();
+                break;
+            } else if self.at_block_end() {
+                self.report(self.error("Unterminated CASE"))?;
+                break;
+            } else {
+                self.parse_into(&mut words)?;
+            }
+        }
+        Ok(self.case_words(arms, words, &location))
+    }
+
+    /// The tests a CASE with `arms`, each a key and its body, and `default`
+    /// stands for
+    fn case_words(&mut self, arms: Vec<(Vec<Word>, Vec<Word>)>, default: Vec<Word>, location: &SourceLocation) -> Vec<Word> {
+        let mut word = |name: &str| Word::WordRef { name: self.names.intern(name), location: location.clone() };
+        let (over, equals, drop) = (word("over"), word("="), word("drop"));
+
+        let mut words = default;
+        words.push(drop.clone());
+        for (mut key, body) in arms.into_iter().rev() {
+            let then_branch = std::iter::once(drop.clone()).chain(body).collect();
+            key.extend([over.clone(), equals.clone(), Word::If { then_branch, else_branch: Some(words) }]);
+            words = key;
+        }
+        words
+    }
+
     /// Parse BEGIN...UNTIL or BEGIN...WHILE...REPEAT
     fn parse_begin(&mut self) -> Result<Word> {
         let mut body = Vec::new();
@@ -367,31 +1277,22 @@ impl Parser {
                                     body: repeat_body,
                                 });
                             }
-                            Token::Eof => {
-                                return Err(ForthError::ParseError {
-                                    line: 0,
-                                    column: 0,
-                                    message: "Unterminated BEGIN...WHILE".to_string(),
-                                })
-                            }
-                            _ => {
-                                let word = self.parse_word()?;
-                                repeat_body.push(word);
+                            _ if self.at_block_end() => {
+                                self.report(self.error("Unterminated BEGIN...WHILE"))?;
+                                return Ok(Word::BeginWhileRepeat {
+                                    condition,
+                                    body: repeat_body,
+                                });
                             }
+                            _ => self.parse_into(&mut repeat_body)?,
                         }
                     }
                 }
-                Token::Eof => {
-                    return Err(ForthError::ParseError {
-                        line: 0,
-                        column: 0,
-                        message: "Unterminated BEGIN".to_string(),
-                    })
-                }
-                _ => {
-                    let word = self.parse_word()?;
-                    body.push(word);
+                _ if self.at_block_end() => {
+                    self.report(self.error("Unterminated BEGIN"))?;
+                    return Ok(Word::BeginUntil { body });
                 }
+                _ => self.parse_into(&mut body)?,
             }
         }
     }
@@ -411,30 +1312,94 @@ impl Parser {
                     // TODO: Handle variable increment
                     return Ok(Word::DoLoop { body, increment: 1 });
                 }
-                Token::Eof => {
-                    return Err(ForthError::ParseError {
-                        line: 0,
-                        column: 0,
-                        message: "Unterminated DO loop".to_string(),
-                    })
-                }
-                _ => {
-                    let word = self.parse_word()?;
-                    body.push(word);
+                _ if self.at_block_end() => {
+                    self.report(self.error("Unterminated DO loop"))?;
+                    return Ok(Word::DoLoop { body, increment: 1 });
                 }
+                _ => self.parse_into(&mut body)?,
             }
         }
     }
 }
 
+fn error_at(location: &SourceLocation, message: impl Into<String>) -> ForthError {
+    ForthError::ParseError {
+        line: location.line,
+        column: location.column,
+        message: message.into(),
+    }
+}
+
+/// The token of the control structure word `name`, which POSTPONE compiles
+/// into the definition an immediate word runs in
+fn control_word(name: &str) -> Option<Token> {
+    match name {
+        "if" => Some(Token::If),
+        "else" => Some(Token::Else),
+        "then" => Some(Token::Then),
+        "begin" => Some(Token::Begin),
+        "until" => Some(Token::Until),
+        "while" => Some(Token::While),
+        "repeat" => Some(Token::Repeat),
+        "do" => Some(Token::Do),
+        "loop" => Some(Token::Loop),
+        "+loop" => Some(Token::PlusLoop),
+        _ => None,
+    }
+}
+
+/// Whether `symbol` can name a C function
+fn is_c_identifier(symbol: &str) -> bool {
+    let mut chars = symbol.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// First `POSTPONE` or deferred `LITERAL` in a body, which only an
+/// immediate word can run
+fn compile_only_word(words: &[Word]) -> Option<&str> {
+    words.iter().find_map(|word| match word {
+        Word::WordRef { name, .. }
+            if name.eq_ignore_ascii_case("postpone") || name.eq_ignore_ascii_case("literal") =>
+        {
+            Some(name.as_str())
+        }
+        Word::If { then_branch, else_branch } => compile_only_word(then_branch)
+            .or_else(|| else_branch.as_deref().and_then(compile_only_word)),
+        Word::BeginUntil { body } | Word::DoLoop { body, .. } => compile_only_word(body),
+        Word::BeginWhileRepeat { condition, body } => {
+            compile_only_word(condition).or_else(|| compile_only_word(body))
+        }
+        _ => None,
+    })
+}
+
 /// Parse a Forth program from source code
 pub fn parse_program(source: &str) -> Result<Program> {
     let mut lexer = Lexer::new(source);
-    let tokens = lexer.tokenize()?;
-    let mut parser = Parser::new(tokens);
+    let (tokens, locations) = lexer.tokenize_with_locations()?;
+    let mut parser = Parser::with_locations(tokens, locations);
     parser.parse_program()
 }
 
+/// Parse source, recovering from errors to report up to `limit` of them
+///
+/// Returns as much of the program as could be parsed, with every error
+/// found. A lexical error still ends parsing, as nothing after it can be
+/// tokenized reliably.
+pub fn parse_program_recovering(source: &str, limit: usize) -> (Program, Vec<ForthError>) {
+    let mut lexer = Lexer::new(source);
+    let (tokens, locations) = match lexer.tokenize_with_locations() {
+        Ok(tokens) => tokens,
+        Err(error) => return (Program::new(), vec![error]),
+    };
+
+    let mut parser = Parser::with_locations(tokens, locations);
+    parser.set_error_limit(limit.max(1));
+    let program = parser.parse_program().unwrap_or_else(|_| Program::new());
+    (program, parser.errors)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -455,12 +1420,37 @@ mod tests {
         assert!(def.stack_effect.is_some());
     }
 
+    #[test]
+    fn test_stack_effect_counts_numbers() {
+        let program = parse_program(": countdown ( n -- 0 ) begin 1 - dup 0= until ;").unwrap();
+        let effect = program.definitions[0].stack_effect.as_ref().unwrap();
+        assert_eq!(effect.inputs, vec![StackType::Int]);
+        assert_eq!(effect.outputs, vec![StackType::Int]);
+    }
+
     #[test]
     fn test_parse_if_then() {
         let program = parse_program(": abs ( n -- |n| ) dup 0 < IF negate THEN ;").unwrap();
         assert_eq!(program.definitions.len(), 1);
     }
 
+    #[test]
+    fn test_parse_test_cases() {
+        let program = parse_program(": sq dup * ;\nT{ 3 sq -> 9 }T\n  T{ -> }T").unwrap();
+        assert_eq!(program.tests.len(), 2);
+        assert!(program.top_level_code.is_empty());
+
+        let first = &program.tests[0];
+        assert_eq!(first.body.len(), 2);
+        assert_eq!(first.expected, vec![Word::IntLiteral(9)]);
+        assert_eq!(first.location, SourceLocation { line: 2, column: 1 });
+        assert_eq!(program.tests[1].location, SourceLocation { line: 3, column: 3 });
+
+        assert!(parse_program("T{ 1 2 }T").is_err());
+        assert!(parse_program("T{ 1 -> 1").is_err());
+        assert!(parse_program(": f T{ 1 -> 1 }T ;").is_err());
+    }
+
     #[test]
     fn test_parse_begin_until() {
         let program = parse_program(": countdown BEGIN dup . 1 - dup 0 = UNTIL drop ;").unwrap();
@@ -617,4 +1607,256 @@ mod tests {
         assert_eq!(program.definitions[1].name, "second");
         assert_eq!(program.definitions[2].name, "third");
     }
+
+    #[test]
+    fn test_errors_have_locations() {
+        let error = parse_program("1 2 +\n: f dup").unwrap_err();
+        assert_eq!(error.location(), Some(SourceLocation { line: 2, column: 1 }));
+
+        let error = parse_program("variable\n  42").unwrap_err();
+        assert_eq!(error.location(), Some(SourceLocation { line: 2, column: 3 }));
+    }
+
+    #[test]
+    fn test_recovery_reports_every_error() {
+        let source = ": a 1 IF 2 ;\n: b THEN 3 ;\n: c ( n -- n ) dup ;\n: d 1 2";
+        let (program, errors) = parse_program_recovering(source, 20);
+
+        let lines: Vec<_> = errors.iter().map(|e| e.location().unwrap().line).collect();
+        assert_eq!(lines, vec![1, 2, 4]);
+        assert!(errors[0].to_string().contains("Unterminated IF"));
+        let names: Vec<_> = program.definitions.iter().map(|def| def.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_recovery_stops_at_error_limit() {
+        let (_, errors) = parse_program_recovering("then then then then", 2);
+        assert_eq!(errors.len(), 2);
+        assert!(parse_program("then then").is_err());
+    }
+
+    #[test]
+    fn test_bracket_literal() {
+        let program = parse_program(": area [ 6 7 * ] literal ;").unwrap();
+        assert_eq!(program.definitions[0].body, vec![Word::IntLiteral(42)]);
+    }
+
+    #[test]
+    fn test_immediate_word_runs_at_compile_time() {
+        let source = r#"
+            : square-of ( n -- ) dup * postpone literal ; IMMEDIATE
+            : twice postpone dup postpone + ; IMMEDIATE
+            : f [ 9 ] square-of twice ;
+        "#;
+        let program = parse_program(source).unwrap();
+        let f = program.compiled_definitions().next().unwrap();
+
+        assert_eq!(f.name, "f");
+        assert_eq!(
+            f.body,
+            vec![
+                Word::IntLiteral(81),
+                Word::WordRef { name: "dup".into(), location: SourceLocation { line: 3, column: 30 } },
+                Word::WordRef { name: "+".into(), location: SourceLocation { line: 3, column: 43 } },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conditional_compilation() {
+        let source = r#"
+            1 CONSTANT debug
+            debug [IF] : log 1 ; [ELSE] : log 0 ; [THEN]
+            0 [IF] : skipped [IF] [ELSE] [THEN] ; [THEN]
+            : g [ debug 0= ] [IF] 2 [ELSE] 3 [THEN] ;
+        "#;
+        let program = parse_program(source).unwrap();
+        let bodies: Vec<_> = program.definitions.iter().map(|def| (def.name.as_str(), &def.body)).collect();
+
+        assert_eq!(
+            bodies,
+            vec![("log", &vec![Word::IntLiteral(1)]), ("g", &vec![Word::IntLiteral(3)])]
+        );
+        assert!(parse_program("0 [IF] : h ;").is_err());
+    }
+
+    #[test]
+    fn test_postpone_control_words() {
+        let source = r#"
+            : unless postpone 0= postpone if ; IMMEDIATE
+            : otherwise postpone else ; IMMEDIATE
+            : end postpone then ; IMMEDIATE
+            : f ( n -- n ) unless 1 otherwise 2 end ;
+        "#;
+        let program = parse_program(source).unwrap();
+        let f = program.compiled_definitions().next().unwrap();
+        assert!(matches!(&f.body[0], Word::WordRef { name, .. } if *name == "0="));
+        assert_eq!(
+            f.body[1],
+            Word::If { then_branch: vec![Word::IntLiteral(1)], else_branch: Some(vec![Word::IntLiteral(2)]) }
+        );
+
+        // The structure still has to be closed where the word is used
+        let result = parse_program(": unless postpone 0= postpone if ; IMMEDIATE : f unless 1 ;");
+        assert!(result.is_err());
+        assert!(parse_program(": f postpone ; ; IMMEDIATE").is_err());
+    }
+
+    #[test]
+    fn test_postpone_requires_immediate() {
+        let result = parse_program(": f postpone dup ;");
+        assert!(matches!(result, Err(ForthError::ParseError { message, .. }) if message.contains("IMMEDIATE")));
+
+        let result = parse_program(": f emit ; IMMEDIATE : g f ;");
+        assert!(matches!(result, Err(ForthError::CompileTimeError { message, .. }) if message.contains("emit")));
+    }
+
+    #[test]
+    fn test_code_word() {
+        let source = "code add3 ( a b c -- n )\n    lea (%rdi,%rsi), %rax\n    add %rdx, %rax\n    ret\nend-code\n1 2 3 add3";
+        let program = parse_program(source).unwrap();
+
+        let word = &program.code_words[0];
+        assert_eq!(word.name, "add3");
+        assert_eq!((word.stack_effect.inputs.len(), word.stack_effect.outputs.len()), (3, 1));
+        assert_eq!(word.assembly, "lea (%rdi,%rsi), %rax\n    add %rdx, %rax\n    ret");
+        assert_eq!(word.location.line, 1);
+        assert_eq!(program.top_level_code.len(), 4);
+
+        assert!(parse_program("code f ret end-code").is_err(), "the stack effect is required");
+        assert!(parse_program("code f ( -- a b ) ret end-code").is_err());
+        assert!(parse_program(": f code g ( -- ) ret end-code ;").is_err());
+    }
+
+    #[test]
+    fn test_struct() {
+        let source = "struct point field .x field .y end-struct\nstruct line point field .from point field .to char field .style end-struct\ncreate origin point allot";
+        let program = parse_program(source).unwrap();
+
+        let point = &program.records[0];
+        assert_eq!(point.size, 16);
+        assert_eq!(point.fields.iter().map(|field| (field.name.as_str(), field.offset)).collect::<Vec<_>>(), [(".x", 0), (".y", 8)]);
+
+        let line = &program.records[1];
+        assert_eq!(line.size, 40, "cell-aligned, so an array of lines stays aligned");
+        assert_eq!(line.fields[1].offset, 16);
+        assert_eq!(line.fields[1].record.as_deref(), Some("point"));
+        assert_eq!(line.fields[2].size, 1);
+
+        let y = program.definitions.iter().find(|def| def.name == ".y").unwrap();
+        assert_eq!(y.stack_effect.as_ref().unwrap().inputs, vec![StackType::Record("point".into())]);
+        assert_eq!(y.body[0], Word::IntLiteral(8));
+        assert!(program.definitions.iter().any(|def| def.name == "point" && def.body == [Word::IntLiteral(16)]));
+        assert_eq!(
+            program.top_level_code,
+            vec![Word::Variable { name: "origin".into(), size: 16, record: Some("point".into()) }]
+        );
+
+        let program = parse_program("struct point field .x field .y end-struct : x@ ( point -- n ) .x @ ;").unwrap();
+        assert_eq!(program.definitions[3].stack_effect.as_ref().unwrap().inputs, vec![StackType::Record("point".into())]);
+
+        let wide = &parse_program("struct wide 2 cells field .pair 3 chars field .tag field .n end-struct").unwrap().records[0];
+        assert_eq!(wide.fields.iter().map(|field| field.offset).collect::<Vec<_>>(), [0, 16, 24]);
+        assert_eq!(wide.size, 32);
+
+        assert!(parse_program("struct p field .a field .a end-struct").is_err());
+        assert!(parse_program("struct p field .a").is_err());
+        assert!(parse_program("create buf 4 cells").is_err(), "ALLOT is required");
+    }
+
+    #[test]
+    fn test_case() {
+        let program = parse_program(": f ( n -- n ) case 1 of 10 endof 2 + of 20 endof 0 swap endcase ;").unwrap();
+        let body = &program.definitions[0].body;
+        assert_eq!(body.len(), 4);
+        assert_eq!(body[0], Word::IntLiteral(1));
+        let Word::If { then_branch, else_branch: Some(rest) } = &body[3] else { panic!("{:?}", body) };
+        assert_eq!(then_branch[1], Word::IntLiteral(10));
+        assert_eq!(rest.len(), 5, "the second key is 2 +");
+        let Word::If { else_branch: Some(default), .. } = &rest[4] else { panic!("{:?}", rest) };
+        assert_eq!(default.len(), 3, "0 swap, then ENDCASE's drop");
+
+        assert!(parse_program(": f case 1 of 10 endcase ;").is_err());
+        assert!(parse_program(": f case 1 of 10 endof ;").is_err());
+    }
+
+    #[test]
+    fn test_table() {
+        let program = parse_program("7 constant seven\ntable: t 3 -1 seven ;\n1 t").unwrap();
+        let table = &program.definitions[0];
+        assert_eq!(table.name, "t");
+        assert_eq!(table.stack_effect.as_ref().unwrap().inputs, vec![StackType::Int]);
+        let chain = crate::case::CaseChain::find(&table.body).unwrap();
+        assert_eq!(chain.table().unwrap().values, vec![3, -1, 7]);
+        assert_eq!(program.top_level_code.len(), 3);
+
+        assert!(parse_program("table: t 1 dup ;").is_err());
+
+        let program = parse_program("table: sq 0 , 1 , 4 , 9 ;\ntable: cubes 0 , 1 , 8 , ;").unwrap();
+        let values = |index: usize| crate::case::CaseChain::find(&program.definitions[index].body).unwrap().table().unwrap().values;
+        assert_eq!(values(0), vec![0, 1, 4, 9]);
+        assert_eq!(values(1), vec![0, 1, 8]);
+        assert!(parse_program("table: t 1 , , 2 ;").is_err());
+        assert!(parse_program("table: t , 1 ;").is_err());
+    }
+
+    #[test]
+    fn test_definition_attributes() {
+        let source = "\\ @inline(never) @hot inner loop\n\\ @optimize(none) @constant-time\n: step 1+ ;\n: plain step ;";
+        let program = parse_program(source).unwrap();
+
+        let step = &program.definitions[0].attributes;
+        assert_eq!(step.inline, Some(InlineHint::Never));
+        assert!(step.optimize_none && step.hot && step.constant_time);
+        assert_eq!(parse_program("\\ @recursion(64)\n: f ;").unwrap().definitions[0].attributes.recursion, Some(64));
+        assert!(parse_program("\\ @recursion(0)\n: f ;").is_err());
+        assert_eq!(program.definitions[1].attributes, Attributes::default());
+
+        let program = parse_program("\\ @export\n: sum-of ;\n\\ @export(square)\n: sq ;").unwrap();
+        assert_eq!(program.definitions[0].attributes.export.as_deref(), Some("sum_of"));
+        assert_eq!(program.definitions[1].attributes.export.as_deref(), Some("square"));
+        assert!(parse_program("\\ @export\n: 2* ;").is_err());
+
+        assert!(parse_program("\\ @inline(sometimes)\n: f ;").is_err());
+        assert!(parse_program("\\ @hot\n1 2 +").is_err(), "attributes need a definition");
+        assert!(parse_program("\\ @inline(always) @optimize(none)\n: f ;").is_err());
+        assert!(parse_program("\\ @inline(always)\n\\ @inline(never)\n: f ;").is_err());
+    }
+
+    #[test]
+    fn test_names_are_interned() {
+        let program = parse_program(": sq dup * ; : f sq sq dup ; : g \"hi\" \"hi\" ;").unwrap();
+
+        let name = |def: usize, word: usize| match &program.definitions[def].body[word] {
+            Word::WordRef { name, .. } | Word::StringLiteral(name) => *name,
+            other => panic!("expected a name, got {:?}", other),
+        };
+        assert_eq!(name(1, 0), "sq");
+        assert_eq!(name(1, 0).as_ptr(), name(1, 1).as_ptr());
+        assert_eq!(name(0, 0).as_ptr(), name(1, 2).as_ptr());
+        assert_eq!(name(2, 0).as_ptr(), name(2, 1).as_ptr());
+        assert!(program.names.lookups > program.names.symbols);
+        assert_eq!(program.names.bytes_saved, "sqduphi".len());
+    }
+
+    #[test]
+    fn test_locals() {
+        let names = |names: &[Symbol]| names.iter().map(|name| name.as_str()).collect::<Vec<_>>();
+        let program = parse_program(": f {: a b | c -- d :} b a - to c c ;").unwrap();
+        let body = &program.definitions[0].body;
+        let Word::Locals { args, uninitialized, .. } = &body[0] else { panic!("{:?}", body) };
+        assert_eq!(names(args), ["a", "b"]);
+        assert_eq!(names(uninitialized), ["c"]);
+        assert!(matches!(&body[1], Word::LocalRef { name, .. } if *name == "b"));
+        assert!(matches!(&body[4], Word::LocalStore { name, .. } if *name == "c"));
+
+        let program = parse_program(": g locals| x y | y x ;").unwrap();
+        let Word::Locals { args, .. } = &program.definitions[0].body[0] else { panic!() };
+        assert_eq!(names(args), ["y", "x"], "LOCALS| names are popped in order");
+
+        let program = parse_program(": h {: a :} a ; : k a ;").unwrap();
+        assert!(matches!(&program.definitions[1].body[0], Word::WordRef { .. }), "locals end with their definition");
+        assert!(parse_program(": f {: a b ;").is_err());
+    }
 }
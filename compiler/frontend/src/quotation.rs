@@ -0,0 +1,300 @@
+//! Quotations
+//!
+//! `[: ... ;]` is parsed into a hidden definition, `[:1]`, `[:2]` and so on,
+//! and the quotation itself into a [`Word::Quotation`] that pushes its
+//! execution token. A quotation captures nothing from the definition it
+//! appears in, so its hidden definition is an ordinary word: it is
+//! analyzed, inferred and compiled like any other, and is marked to be
+//! inlined.
+//!
+//! `' name` and `['] name` push the token of a word the program defines,
+//! and of anything else, such as a builtin, through a quotation calling it.
+//!
+//! A token followed by `EXECUTE` becomes a call to its word, and a
+//! quotation followed by a combinator is expanded here into the loop the
+//! combinator stands for, calling the word for each cell of an
+//! `( addr len )` range:
+//!
+//! ```forth
+//! \ each ( addr len xt -- ), xt ( x -- )
+//! dup if 0 do dup i cells + @ xt loop else drop then drop
+//!
+//! \ map! ( addr len xt -- ), xt ( x -- y ), storing y over x
+//! dup if 0 do dup i cells + dup @ xt swap ! loop else drop then drop
+//!
+//! \ reduce ( addr len init xt -- acc ), xt ( acc x -- acc )
+//! swap dup if 0 do over i cells + @ xt loop else drop then swap drop
+//! ```
+//!
+//! A quotation without a stack comment of its own is declared with the
+//! effect its combinator calls it with, so a call to it leaves what the loop
+//! expects, and inference checks the quotation against it.
+//!
+//! Once the quotation is inlined, the result is the loop one would write by
+//! hand, so induction variable optimization and vectorization treat both
+//! alike. An empty range runs the quotation no times. A word the program
+//! defines itself under a combinator's name is called as written.
+//!
+//! Any other token is a value, the address of its word's code, which may be
+//! passed to words and stored like any cell. `EXECUTE` on such a token is
+//! an indirect call that may reach any of the program's [targets]: each
+//! must have the same stack effect, which is then the effect of `EXECUTE`
+//! after it takes the token.
+//!
+//! [targets]: execution_targets
+
+use crate::ast::{Program, SourceLocation, StackEffect, StackType, Word};
+use crate::error::{ForthError, Result};
+use crate::intern::Symbol;
+
+/// Words that take a quotation and are expanded at compile time
+pub const COMBINATORS: &[&str] = &["each", "map!", "reduce"];
+
+/// Replace each quotation followed by `EXECUTE` with a call to its word,
+/// and expand each combinator applied to a quotation
+pub fn resolve_quotations(program: &mut Program) -> Result<()> {
+    let combinators: Vec<&str> = COMBINATORS
+        .iter()
+        .copied()
+        .filter(|name| !program.definitions.iter().any(|def| def.name.eq_ignore_ascii_case(name)))
+        .collect();
+    let mut applied = Vec::new();
+    for def in &mut program.definitions {
+        resolve_words(&mut def.body, &combinators, &mut applied)?;
+    }
+    resolve_words(&mut program.top_level_code, &combinators, &mut applied)?;
+    for test in &mut program.tests {
+        resolve_words(&mut test.body, &combinators, &mut applied)?;
+        resolve_words(&mut test.expected, &combinators, &mut applied)?;
+    }
+
+    for (quotation, combinator) in applied {
+        if let Some(def) = program.definitions.iter_mut().find(|def| def.name == quotation.as_str()) {
+            def.stack_effect.get_or_insert_with(|| effect(&combinator));
+        }
+    }
+    Ok(())
+}
+
+/// Words whose execution tokens the program keeps as values, with where
+/// each is first pushed: the tokens not consumed by `EXECUTE` or a
+/// combinator right after them
+pub fn execution_targets(program: &Program) -> Vec<(Symbol, SourceLocation)> {
+    let mut targets = Vec::new();
+    let bodies = program.definitions.iter().map(|def| &def.body)
+        .chain([&program.top_level_code])
+        .chain(program.tests.iter().flat_map(|test| [&test.body, &test.expected]));
+    for body in bodies {
+        collect_targets(body, &mut targets);
+    }
+    targets
+}
+
+fn collect_targets(words: &[Word], targets: &mut Vec<(Symbol, SourceLocation)>) {
+    for word in words {
+        match word {
+            Word::Quotation { name, location } if !targets.iter().any(|(target, _)| target == name) => {
+                targets.push((*name, location.clone()));
+            }
+            Word::If { then_branch, else_branch } => {
+                collect_targets(then_branch, targets);
+                collect_targets(else_branch.as_deref().unwrap_or_default(), targets);
+            }
+            Word::BeginUntil { body } | Word::DoLoop { body, .. } => collect_targets(body, targets),
+            Word::BeginWhileRepeat { condition, body } => {
+                collect_targets(condition, targets);
+                collect_targets(body, targets);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The `( inputs -- outputs )` of the word `EXECUTE` calls on a token kept
+/// as a value, which every one of the program's [targets] must have;
+/// `None` if there are no targets
+///
+/// `effect_of` gives the effect of each target.
+///
+/// [targets]: execution_targets
+pub fn execute_effect(
+    program: &Program,
+    mut effect_of: impl FnMut(&str) -> (usize, usize),
+) -> Result<Option<(usize, usize)>> {
+    let targets = execution_targets(program);
+    let describe = |name: &Symbol, location: &SourceLocation| describe(program, name, location);
+    let mut first: Option<(&Symbol, &SourceLocation, (usize, usize))> = None;
+    for (name, location) in &targets {
+        let effect = effect_of(name.as_str());
+        match first {
+            None => first = Some((name, location, effect)),
+            Some((first_name, first_location, first_effect)) if first_effect != effect => {
+                return Err(ForthError::InvalidStackEffect {
+                    declaration: format!(
+                        "EXECUTE may call any word whose token is kept, so they need one stack effect, \
+                         but {} is ( {} -- {} ) and {} is ( {} -- {} )",
+                        describe(first_name, first_location), first_effect.0, first_effect.1,
+                        describe(name, location), effect.0, effect.1,
+                    ),
+                    location: Some(location.clone()),
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(first.map(|(_, _, effect)| effect))
+}
+
+/// A target as a reader knows it: a quotation by where it is, unless it
+/// only calls one word, as `' name` of a builtin does
+fn describe(program: &Program, name: &Symbol, location: &SourceLocation) -> String {
+    if !name.as_str().starts_with("[:") {
+        return format!("'{}'", name);
+    }
+    match program.definitions.iter().find(|def| def.name == name.as_str()).map(|def| &def.body[..]) {
+        Some([Word::WordRef { name, .. }]) => format!("'{}'", name),
+        _ => format!("the quotation at line {}", location.line),
+    }
+}
+
+/// The effect `combinator` calls its quotation with
+fn effect(combinator: &str) -> StackEffect {
+    let (inputs, outputs) = match combinator {
+        "each" => (1, 0),
+        "map!" => (1, 1),
+        _ => (2, 1),
+    };
+    StackEffect::new(vec![StackType::Unknown; inputs], vec![StackType::Unknown; outputs])
+}
+
+/// Resolve the quotations in `words`, noting each quotation applied to a
+/// combinator in `applied`
+fn resolve_words(words: &mut Vec<Word>, combinators: &[&str], applied: &mut Vec<(Symbol, String)>) -> Result<()> {
+    let mut resolved = Vec::with_capacity(words.len());
+    for mut word in words.drain(..) {
+        match &mut word {
+            Word::WordRef { name, location } if name.eq_ignore_ascii_case("execute") => {
+                if let Some(Word::Quotation { name, .. }) = resolved.last() {
+                    let name = *name;
+                    resolved.pop();
+                    resolved.push(Word::WordRef { name, location: location.clone() });
+                    continue;
+                }
+            }
+            Word::WordRef { name, location } if combinators.iter().any(|c| name.eq_ignore_ascii_case(c)) => {
+                let Some(Word::Quotation { name: quotation, .. }) = resolved.pop() else {
+                    return Err(ForthError::ParseError {
+                        line: location.line,
+                        column: location.column,
+                        message: format!("{} needs a quotation right before it, e.g. [: . ;] {}", name.to_uppercase(), name),
+                    });
+                };
+                let combinator = name.to_lowercase();
+                resolved.extend(expand(&combinator, quotation, location));
+                applied.push((quotation, combinator));
+                continue;
+            }
+            Word::If { then_branch, else_branch } => {
+                resolve_words(then_branch, combinators, applied)?;
+                if let Some(else_branch) = else_branch {
+                    resolve_words(else_branch, combinators, applied)?;
+                }
+            }
+            Word::BeginUntil { body } | Word::DoLoop { body, .. } => resolve_words(body, combinators, applied)?,
+            Word::BeginWhileRepeat { condition, body } => {
+                resolve_words(condition, combinators, applied)?;
+                resolve_words(body, combinators, applied)?;
+            }
+            _ => {}
+        }
+        resolved.push(word);
+    }
+    *words = resolved;
+    Ok(())
+}
+
+/// The loop `combinator` stands for, calling `quotation` on each cell
+fn expand(combinator: &str, quotation: Symbol, location: &SourceLocation) -> Vec<Word> {
+    let words = |names: &str| -> Vec<Word> {
+        names
+            .split_whitespace()
+            .map(|name| match name {
+                "xt" => Word::WordRef { name: quotation, location: location.clone() },
+                "0" => Word::IntLiteral(0),
+                name => Word::WordRef { name: Symbol::intern(name), location: location.clone() },
+            })
+            .collect()
+    };
+    let (setup, body, teardown) = match combinator {
+        "each" => ("", "dup i cells + @ xt", "drop"),
+        "map!" => ("", "dup i cells + dup @ xt swap !", "drop"),
+        _ => ("swap", "over i cells + @ xt", "swap drop"),
+    };
+
+    // DO runs at least once, so an empty range skips the loop
+    let mut counted = words("0");
+    counted.push(Word::DoLoop { body: words(body), increment: 1 });
+    let mut expansion = words(setup);
+    expansion.extend(words("dup"));
+    expansion.push(Word::If { then_branch: counted, else_branch: Some(words("drop")) });
+    expansion.extend(words(teardown));
+    expansion
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_program;
+    use crate::ast::Word;
+
+    #[test]
+    fn test_quotation_then_execute_is_a_call() {
+        let program = parse_program(": f ( n -- n ) [: 1 + ;] execute ;\n: g ( -- ) [: ;] drop ;").unwrap();
+        let names: Vec<&str> = program.definitions.iter().map(|def| def.name.as_str()).collect();
+        assert_eq!(names, ["[:1]", "f", "[:2]", "g"]);
+
+        assert!(matches!(&program.definitions[1].body[..], [Word::WordRef { name, .. }] if *name == "[:1]"));
+        assert!(matches!(&program.definitions[3].body[0], Word::Quotation { name, .. } if *name == "[:2]"));
+
+        // The enclosing definition's locals are out of scope
+        let program = parse_program(": h {: a :} [: a ;] execute ;").unwrap();
+        assert!(matches!(&program.definitions[0].body[0], Word::WordRef { name, .. } if *name == "a"));
+        assert!(parse_program(": f [: 1 + ;").is_err());
+    }
+
+    #[test]
+    fn test_ticks_push_tokens() {
+        // A defined word is its own token, a builtin gets a quotation
+        let program = parse_program(": sq dup * ; : f ( -- xt xt ) ['] sq ' + ; : g ['] sq execute ;").unwrap();
+        let f = program.definitions.iter().find(|def| def.name == "f").unwrap();
+        assert!(matches!(&f.body[..], [Word::Quotation { name: sq, .. }, Word::Quotation { name: plus, .. }]
+            if *sq == "sq" && *plus == "[:1]"));
+        let g = program.definitions.iter().find(|def| def.name == "g").unwrap();
+        assert!(matches!(&g.body[..], [Word::WordRef { name, .. }] if *name == "sq"));
+
+        // Only tokens kept as values are targets of EXECUTE
+        let targets: Vec<String> = super::execution_targets(&program).iter().map(|(name, _)| name.to_string()).collect();
+        assert_eq!(targets, ["sq", "[:1]"]);
+        let error = super::execute_effect(&program, |name| if name == "sq" { (1, 1) } else { (2, 1) }).unwrap_err();
+        assert!(error.to_string().contains("'sq' is ( 1 -- 1 ) and '+' is ( 2 -- 1 )"), "{}", error);
+        assert!(parse_program(": f ' ;").is_err());
+    }
+
+    #[test]
+    fn test_combinators_expand_to_loops() {
+        let program = parse_program(": total ( addr len -- n ) 0 [: + ;] reduce ;").unwrap();
+        let body = &program.definitions[1].body;
+        assert!(matches!(&body[..2], [Word::IntLiteral(0), Word::WordRef { name, .. }] if *name == "swap"));
+        let Word::If { then_branch, .. } = &body[3] else { panic!("{:?}", body) };
+        let Word::DoLoop { body: each, .. } = &then_branch[1] else { panic!("{:?}", then_branch) };
+        assert!(matches!(each.last(), Some(Word::WordRef { name, .. }) if *name == "[:1]"));
+        assert_eq!(program.definitions[0].stack_effect.as_ref().unwrap().to_string(), "( ? ? -- ? )");
+        crate::analyze(&program).unwrap();
+
+        let error = parse_program(": f ( addr len xt -- ) each ;").unwrap_err();
+        assert!(error.to_string().contains("EACH needs a quotation"), "{}", error);
+
+        // The program's own word of the same name is called as written
+        let program = parse_program(": each ( a b -- ) 2drop ; : f 1 2 each ;").unwrap();
+        assert!(matches!(&program.definitions[1].body[2], Word::WordRef { name, .. } if *name == "each"));
+    }
+}
@@ -0,0 +1,74 @@
+//! Sandboxed Programs
+//!
+//! Source from an untrusted client, such as an agent, is compiled in
+//! sandbox mode, where a program may not reach outside the memory it is
+//! given. Words that run a shell command or touch files or blocks are
+//! compile-time errors, and so are CODE words, whose machine code could do
+//! anything. A program that defines a word of the same name uses its own
+//! definition and is not affected.
+
+use crate::ast::Program;
+use crate::error::{ForthError, Result};
+use crate::freestanding::restricted_words;
+
+/// Words a sandboxed program may not use
+pub const SANDBOXED_WORDS: &[&str] = &[
+    // Files
+    "r/o", "w/o", "r/w", "create-file", "open-file", "read-file", "write-file", "close-file", "delete-file",
+    // Blocks, kept in a memory-mapped file
+    "open-blocks", "block", "buffer", "update", "save-buffers", "empty-buffers", "flush",
+    // Shell commands
+    "system",
+];
+
+/// Whether a sandboxed program may not use `word`
+pub fn is_sandboxed_word(word: &str) -> bool {
+    SANDBOXED_WORDS.contains(&word.to_lowercase().as_str())
+}
+
+/// Reject the first CODE word, or else the first use of a word the sandbox
+/// denies
+pub fn check_sandbox(program: &Program) -> Result<()> {
+    if let Some(word) = program.code_words.first() {
+        return Err(ForthError::UnavailableWord {
+            word: format!("code {}", word.name),
+            target: "sandbox".to_string(),
+            location: Some(word.location.clone()),
+        });
+    }
+    match restricted_words(program, is_sandboxed_word).into_iter().next() {
+        Some((word, location)) => Err(ForthError::UnavailableWord {
+            word,
+            target: "sandbox".to_string(),
+            location: Some(location),
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    #[test]
+    fn test_check_sandbox() {
+        let program = parse_program(": main 42 . cr 16 allocate drop free drop ;").unwrap();
+        assert!(check_sandbox(&program).is_ok());
+
+        let program = parse_program(": system drop drop ;\n: main s\" ls\" system ;").unwrap();
+        assert!(check_sandbox(&program).is_ok(), "a program's own definition wins");
+
+        let program = parse_program(": main\n  0 if s\" ls\" system then ;").unwrap();
+        match check_sandbox(&program) {
+            Err(ForthError::UnavailableWord { word, location, .. }) => {
+                assert_eq!(word, "system");
+                assert_eq!(location.map(|location| location.line), Some(2));
+            }
+            other => panic!("expected an unavailable word, got {:?}", other),
+        }
+
+        let program = parse_program("code nop ( -- )\n  ret\nend-code").unwrap();
+        assert!(matches!(check_sandbox(&program), Err(ForthError::UnavailableWord { .. })));
+    }
+}
@@ -35,6 +35,7 @@ impl SemanticAnalyzer {
             // Arithmetic
             "+", "-", "*", "/", "mod", "/mod", "negate", "abs", "min", "max",
             "1+", "1-", "2+", "2-", "2*", "2/", "*/", "*/mod",
+            "um*", "u/mod",
             // Stack manipulation
             "dup", "drop", "swap", "over", "rot", "2dup", "2drop", "2swap", "2over",
             "pick", "roll", "depth", "?dup",
@@ -43,17 +44,20 @@ impl SemanticAnalyzer {
             "u<", "u>", "u<=", "u>=",
             "d=", "d<", "d0=", "d0<",
             // Logical
-            "and", "or", "xor", "not", "invert", "true", "false",
+            "and", "or", "xor", "not", "invert", "lshift", "rshift", "true", "false",
+            "popcount", "ctz", "clz", "rol", "ror",
             // Memory
-            "@", "!", "c@", "c!", "+!", "?",
+            "@", "!", "c@", "c!", "w@", "w!", "l@", "l!", "+!", "?",
             "cell", "cells", "cell+", "char+", "chars", "align", "aligned",
             "move", "fill", "erase", "compare", "search", "count",
             // I/O
             ".", "emit", "cr", "space", "spaces", "type",
-            ".\"", ".(", ".r", ".s",
+            ".\"", ".(", ".r", ".s", "u.",
+            "key", "key?", "accept",
+            "<#", "#", "#s", "#>", "hold", "sign",
             // Control (these are special but should be recognized)
             "if", "then", "else", "begin", "until", "while", "repeat",
-            "do", "loop", "+loop", "leave", "exit", "recurse",
+            "do", "loop", "+loop", "i", "j", "leave", "exit", "recurse",
             // Return stack
             ">r", "r>", "r@",
             // File I/O (ANS Forth File Access word set)
@@ -65,6 +69,16 @@ impl SemanticAnalyzer {
             "bin", // Binary mode flag
             // System operations
             "system",
+            // Process environment
+            "arg-count", "arg@", "getenv",
+            // Heap (ANS Memory-Allocation word set)
+            "allocate", "free", "resize",
+            // Tasks, channels and atomic cells
+            "join", "pause", "channel", "send", "recv", "close-channel", "destroy-channel",
+            "@atomic", "!atomic",
+            // Blocks (ANS Block word set)
+            "block", "buffer", "update", "save-buffers", "empty-buffers", "flush",
+            "open-blocks",
             // Other
             "here", "allot", "execute", "char",
             "within", "sm/rem", "fm/mod",
@@ -96,36 +110,77 @@ impl SemanticAnalyzer {
 
     /// Analyze a complete program
     pub fn analyze(&mut self, program: &Program) -> Result<()> {
+        // Collect variables and constants from top-level code, which
+        // definitions may use
+        for word in &program.top_level_code {
+            match word {
+                Word::Variable { name, .. } => {
+                    self.variables.insert(name.clone());
+                    self.stack_inference.add_variable(name);
+                }
+                Word::Constant { name, value } => {
+                    self.constants.insert(name.clone(), *value);
+                }
+                _ => {}
+            }
+        }
+
         // First pass: collect all definitions
-        for def in &program.definitions {
+        for def in program.compiled_definitions() {
             if self.defined_words.contains(&def.name) && !self.is_builtin(&def.name) {
-                self.error(ForthError::RedefinitionError {
-                    word: def.name.clone(),
-                });
+                self.error(
+                    ForthError::RedefinitionError {
+                        word: def.name.clone(),
+                        location: None,
+                    }
+                    .at(&def.location),
+                );
             }
             self.defined_words.insert(def.name.clone());
 
             // Add to stack inference
             if let Err(e) = self.stack_inference.add_definition(def) {
-                self.error(e);
+                self.error(e.at(&def.location));
             }
         }
 
-        // Collect variables and constants from top-level code
-        for word in &program.top_level_code {
-            match word {
-                Word::Variable { name } => {
-                    self.variables.insert(name.clone());
-                }
-                Word::Constant { name, value } => {
-                    self.constants.insert(name.clone(), *value);
+        // EXECUTE on a token kept as a value calls a word with the effect of
+        // every word whose token is kept, so definitions inferred without
+        // it are inferred again
+        let effect = crate::quotation::execute_effect(program, |name| {
+            let effect = self.stack_inference.get_effect(name);
+            effect.map_or((0, 0), |effect| (effect.inputs.len(), effect.outputs.len()))
+        });
+        match effect {
+            Ok(Some(effect)) => {
+                self.stack_inference.set_execute_effect(effect);
+                for def in program.compiled_definitions().filter(|def| def.stack_effect.is_none()) {
+                    if let Err(e) = self.stack_inference.add_definition(def) {
+                        self.error(e.at(&def.location));
+                    }
                 }
-                _ => {}
             }
+            Ok(None) => {}
+            Err(e) => self.error(e),
+        }
+
+        // Code words are known only by their declared effects
+        for word in &program.code_words {
+            if self.defined_words.contains(&word.name) && !self.is_builtin(&word.name) {
+                self.error(
+                    ForthError::RedefinitionError {
+                        word: word.name.clone(),
+                        location: None,
+                    }
+                    .at(&word.location),
+                );
+            }
+            self.defined_words.insert(word.name.clone());
+            self.stack_inference.add_code_word(word);
         }
 
         // Second pass: validate definitions
-        for def in &program.definitions {
+        for def in program.compiled_definitions() {
             self.validate_definition(def)?;
         }
 
@@ -133,6 +188,12 @@ impl SemanticAnalyzer {
         for word in &program.top_level_code {
             self.validate_word(word)?;
         }
+        self.validate_local_uses(&program.top_level_code, &[], "outside a definition");
+
+        // Field accessors applied to something other than their record
+        for error in crate::type_inference::TypeInference::check_records(program) {
+            self.error(error);
+        }
 
         // Return error if any were collected
         if !self.errors.is_empty() {
@@ -148,15 +209,17 @@ impl SemanticAnalyzer {
             word,
             // Arithmetic
             "+" | "-" | "*" | "/" | "mod" | "/mod" | "negate" | "abs" | "min" | "max"
+            | "um*" | "u/mod"
             // Stack manipulation
             | "dup" | "drop" | "swap" | "over" | "rot" | "2dup" | "2drop" | "2swap" | "2over"
             | "pick" | "roll" | "depth"
             // Comparison
-            | "<" | ">" | "=" | "<=" | ">=" | "<>" | "0<" | "0>" | "0="
+            | "<" | ">" | "=" | "<=" | ">=" | "<>" | "0<" | "0>" | "0=" | "u<" | "u>"
             // Logical
-            | "and" | "or" | "xor" | "not" | "invert"
+            | "and" | "or" | "xor" | "not" | "invert" | "lshift" | "rshift"
+            | "popcount" | "ctz" | "clz" | "rol" | "ror"
             // Memory
-            | "@" | "!" | "c@" | "c!" | "+!" | "?"
+            | "@" | "!" | "c@" | "c!" | "w@" | "w!" | "l@" | "l!" | "+!" | "?"
             // I/O
             | "." | "emit" | "cr" | "space" | "spaces" | "type"
             // Control
@@ -173,6 +236,7 @@ impl SemanticAnalyzer {
     fn validate_definition(&mut self, def: &Definition) -> Result<()> {
         // Check for control structure balance
         self.validate_control_structures(&def.body)?;
+        self.validate_locals(&def.body);
 
         // Check for undefined words
         for word in &def.body {
@@ -193,18 +257,22 @@ impl SemanticAnalyzer {
                         if declared_effect.inputs.len() != inferred_effect.inputs.len()
                             || declared_effect.outputs.len() != inferred_effect.outputs.len()
                         {
-                            self.error(ForthError::InvalidStackEffect {
-                                declaration: format!(
-                                    "Declared {} but inferred ( {} -- {} )",
-                                    declared_effect,
-                                    inferred_effect.inputs.len(),
-                                    inferred_effect.outputs.len()
-                                ),
-                            });
+                            self.error(
+                                ForthError::InvalidStackEffect {
+                                    declaration: format!(
+                                        "Declared {} but inferred ( {} -- {} )",
+                                        declared_effect,
+                                        inferred_effect.inputs.len(),
+                                        inferred_effect.outputs.len()
+                                    ),
+                                    location: None,
+                                }
+                                .at(&def.location),
+                            );
                         }
                     }
                     Err(e) => {
-                        self.error(e);
+                        self.error(e.at(&def.location));
                     }
                 }
             }
@@ -216,14 +284,29 @@ impl SemanticAnalyzer {
     /// Validate a word
     fn validate_word(&mut self, word: &Word) -> Result<()> {
         match word {
-            Word::WordRef { name, .. } => {
+            Word::WordRef { name, location } => {
                 if !self.is_defined(name) {
-                    self.error(ForthError::UndefinedWord {
-                        word: name.clone(),
-                        line: None,
-                    });
+                    self.error(
+                        ForthError::UndefinedWord {
+                            word: name.to_string(),
+                            location: None,
+                        }
+                        .at(location),
+                    );
                 }
             }
+            // A task runs a compiled word
+            Word::TaskSpawn { word, location }
+                if !self.defined_words.contains(word.as_str()) || self.is_builtin(word) =>
+            {
+                self.error(
+                    ForthError::UndefinedWord {
+                        word: word.to_string(),
+                        location: None,
+                    }
+                    .at(location),
+                );
+            }
             Word::If {
                 then_branch,
                 else_branch,
@@ -261,6 +344,52 @@ impl SemanticAnalyzer {
         Ok(())
     }
 
+    /// Check the locals of a definition body: declared outside control
+    /// structures, each name once, and used after their declaration
+    fn validate_locals(&mut self, body: &[Word]) {
+        let mut scope: Vec<Symbol> = Vec::new();
+        for word in body {
+            match word {
+                Word::Locals { args, uninitialized, location } => {
+                    for name in args.iter().chain(uninitialized) {
+                        if scope.iter().any(|local| local.eq_ignore_ascii_case(name)) {
+                            self.error(ForthError::RedefinitionError { word: name.to_string(), location: None }.at(location));
+                        }
+                        scope.push(*name);
+                    }
+                }
+                word => self.validate_local_uses(std::slice::from_ref(word), &scope, "inside a control structure"),
+            }
+        }
+    }
+
+    /// Check that `words` only use the locals in `scope` and declare none,
+    /// since they are `place`
+    fn validate_local_uses(&mut self, words: &[Word], scope: &[Symbol], place: &str) {
+        for word in words {
+            match word {
+                Word::Locals { location, .. } => self.error(ForthError::ParseError {
+                    line: location.line,
+                    column: location.column,
+                    message: format!("Locals cannot be declared {}", place),
+                }),
+                Word::LocalRef { name, location } | Word::LocalStore { name, location } if !scope.contains(name) => {
+                    self.error(ForthError::UndefinedWord { word: name.to_string(), location: None }.at(location));
+                }
+                Word::If { then_branch, else_branch } => {
+                    self.validate_local_uses(then_branch, scope, place);
+                    self.validate_local_uses(else_branch.as_deref().unwrap_or_default(), scope, place);
+                }
+                Word::BeginUntil { body } | Word::DoLoop { body, .. } => self.validate_local_uses(body, scope, place),
+                Word::BeginWhileRepeat { condition, body } => {
+                    self.validate_local_uses(condition, scope, place);
+                    self.validate_local_uses(body, scope, place);
+                }
+                _ => {}
+            }
+        }
+    }
+
     /// Check if a word sequence contains complex control flow (loops, return stack ops)
     fn has_complex_control_flow(&self, words: &[Word]) -> bool {
         for word in words {
@@ -394,8 +523,9 @@ mod tests {
         let program = parse_program(": test undefined-word ;").unwrap();
         let result = analyze(&program);
         assert!(result.is_err());
-        if let Err(ForthError::UndefinedWord { word, .. }) = result {
+        if let Err(ForthError::UndefinedWord { word, location }) = result {
             assert_eq!(word, "undefined-word");
+            assert_eq!(location, Some(SourceLocation { line: 1, column: 8 }));
         } else {
             panic!("Expected UndefinedWord error");
         }
@@ -425,6 +555,22 @@ mod tests {
         assert!(analyze(&program).is_ok());
     }
 
+    #[test]
+    fn test_field_of_another_record() {
+        let records = "struct point field .x field .y end-struct\nstruct size field .w field .h end-struct\n";
+        let program = parse_program(&format!("{}: area ( size -- n ) dup .w @ swap .h @ * ;", records)).unwrap();
+        assert!(analyze(&program).is_ok());
+
+        let program = parse_program(&format!("{}: area ( size -- n ) dup .w @ swap .y @ * ;", records)).unwrap();
+        match analyze(&program) {
+            Err(ForthError::TypeError { expected, found, location, .. }) => {
+                assert_eq!((expected.as_str(), found.as_str()), ("point", "size"));
+                assert_eq!(location.map(|at| at.line), Some(3));
+            }
+            other => panic!("Expected a type error, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_validation_result() {
         let program = parse_program(": test 1 2 + ;").unwrap();
@@ -432,4 +578,16 @@ mod tests {
         assert!(result.passed);
         assert!(result.errors.is_empty());
     }
+
+    #[test]
+    fn test_locals_scope() {
+        let program = parse_program(": f {: a b :} b a - ;").unwrap();
+        assert!(analyze(&program).is_ok());
+
+        let program = parse_program(": f {: a a :} a ;").unwrap();
+        assert!(matches!(analyze(&program), Err(ForthError::RedefinitionError { .. })));
+
+        let program = parse_program(": f ( n -- n ) dup if {: a :} a then ;").unwrap();
+        assert!(matches!(analyze(&program), Err(ForthError::ParseError { .. })));
+    }
 }
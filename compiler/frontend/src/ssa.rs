@@ -4,10 +4,25 @@
 //! Each stack value gets a unique SSA variable, and all operations are explicit.
 
 use crate::ast::*;
+use crate::case::{CaseChain, CaseTable};
 use crate::error::{ForthError, Result};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use smallvec::SmallVec;
 use std::fmt;
 
+/// Below this many definitions, conversion runs on the calling thread
+pub const PARALLEL_CONVERSION_THRESHOLD: usize = 16;
+
+/// Bytes in a cell
+const CELL_SIZE: i64 = 8;
+
+/// Name the cells moved to the return stack are kept under among the locals
+const RETURN_STACK_CELL: &str = ">r";
+
+/// Most passes of a DO loop that changes the stack depth are unrolled
+const MAX_UNROLLED_PASSES: usize = 256;
+
 /// SSA register/variable
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Register(pub usize);
@@ -37,7 +52,15 @@ pub enum SSAInstruction {
     LoadString {
         dest_addr: Register,   // String address
         dest_len: Register,    // String length
-        value: String,
+        value: Symbol,
+    },
+
+    /// Address of a variable or CREATE buffer: `size` zeroed bytes, aligned
+    /// to a cell, that every function shares
+    DataAddress {
+        dest: Register,
+        name: String,
+        size: usize,
     },
 
     /// Binary arithmetic operation
@@ -58,8 +81,28 @@ pub enum SSAInstruction {
     /// Call a word
     Call {
         dest: SmallVec<[Register; 4]>,
-        name: String,
+        name: Symbol,
+        args: SmallVec<[Register; 4]>,
+    },
+
+    /// Execution token of a word: the address of its code
+    /// Stack effect: ( -- xt )
+    FunctionAddress {
+        dest: Register,
+        name: Symbol,
+    },
+
+    /// Call the word of an execution token (EXECUTE)
+    /// Stack effect: ( i*x xt -- j*x )
+    ///
+    /// `likely` is the word the token most often names, from a profile:
+    /// code generation tests for it and calls it directly, falling back to
+    /// the indirect call.
+    CallIndirect {
+        dest: SmallVec<[Register; 4]>,
+        target: Register,
         args: SmallVec<[Register; 4]>,
+        likely: Option<Symbol>,
     },
 
     /// Conditional branch
@@ -74,6 +117,14 @@ pub enum SSAInstruction {
         target: BlockId,
     },
 
+    /// Jump to the block of the case equal to a value, or to the default
+    /// block; each target has this block as its only predecessor
+    Switch {
+        value: Register,
+        cases: Vec<(i64, BlockId)>,
+        default: BlockId,
+    },
+
     /// Return from function
     Return {
         values: SmallVec<[Register; 4]>,
@@ -85,11 +136,20 @@ pub enum SSAInstruction {
         incoming: Vec<(BlockId, Register)>,
     },
 
+    /// Entry of a constant table: `values[index - base]`, or `default`
+    /// outside the table
+    Lookup {
+        dest: Register,
+        index: Register,
+        table: CaseTable,
+    },
+
     /// Load from memory
     Load {
         dest: Register,
         address: Register,
         ty: StackType,
+        width: MemoryWidth,
     },
 
     /// Store to memory
@@ -97,6 +157,7 @@ pub enum SSAInstruction {
         address: Register,
         value: Register,
         ty: StackType,
+        width: MemoryWidth,
     },
 
     // FFI and File I/O Operations
@@ -169,6 +230,150 @@ pub enum SSAInstruction {
         command_addr: Register, // Command string address
         command_len: Register,  // Command string length
     },
+
+    /// Number of command line arguments, program name included
+    /// Stack effect: ( -- n )
+    ArgCount {
+        dest: Register,
+    },
+
+    /// Command line argument n (0 0 if there is no such argument)
+    /// Stack effect: ( n -- c-addr u )
+    ArgFetch {
+        dest_addr: Register,    // Argument string address
+        dest_len: Register,     // Argument string length
+        index: Register,        // Argument index (0 = program name)
+    },
+
+    /// Value of an environment variable (0 0 if it is not set)
+    /// Stack effect: ( c-addr u -- c-addr u )
+    GetEnv {
+        dest_addr: Register,    // Value string address
+        dest_len: Register,     // Value string length
+        name_addr: Register,    // Variable name address
+        name_len: Register,     // Variable name length
+    },
+
+    /// Allocate u bytes of heap (ANS Memory word set)
+    /// Stack effect: ( u -- a-addr ior )
+    HeapAllocate {
+        dest_addr: Register,    // Block address (0 on failure)
+        dest_ior: Register,     // I/O result (0 = success)
+        size: Register,         // Bytes to allocate
+    },
+
+    /// Return a heap block
+    /// Stack effect: ( a-addr -- ior )
+    HeapFree {
+        dest_ior: Register,     // I/O result (0 = success)
+        addr: Register,         // Block address
+    },
+
+    /// Change the size of a heap block, moving it if need be
+    /// Stack effect: ( a-addr1 u -- a-addr2 ior )
+    HeapResize {
+        dest_addr: Register,    // New block address (a-addr1 on failure)
+        dest_ior: Register,     // I/O result (0 = success)
+        addr: Register,         // Block address
+        size: Register,         // New size in bytes
+    },
+
+    /// Run a compiled word on a new thread (TASK:)
+    /// Stack effect: ( x1 .. xn -- task )
+    TaskSpawn {
+        dest: Register,                // Task handle (0 if no thread started)
+        word: Symbol,                  // Word the task runs
+        args: SmallVec<[Register; 4]>, // The word's inputs, at most four
+    },
+
+    /// Load a cell shared with other threads, ordered with every other
+    /// atomic access
+    /// Stack effect: ( a-addr -- x )
+    AtomicLoad {
+        dest: Register,
+        address: Register,
+    },
+
+    /// Store a cell shared with other threads, ordered with every other
+    /// atomic access
+    /// Stack effect: ( x a-addr -- )
+    AtomicStore {
+        address: Register,
+        value: Register,
+    },
+
+    /// Buffer holding block u, read from the block file if not cached
+    /// Stack effect: ( u -- a-addr )
+    BlockFetch {
+        dest: Register,         // Buffer address (0 on failure)
+        block: Register,        // Block number (from 1)
+    },
+
+    /// Buffer assigned to block u, without reading it
+    /// Stack effect: ( u -- a-addr )
+    BlockAssign {
+        dest: Register,         // Buffer address (0 on failure)
+        block: Register,        // Block number (from 1)
+    },
+
+    /// Mark the current block buffer as modified
+    /// Stack effect: ( -- )
+    BlockUpdate,
+
+    /// Write all modified block buffers to the block file
+    /// Stack effect: ( -- )
+    BlockSave,
+
+    /// Unassign all block buffers without saving them
+    /// Stack effect: ( -- )
+    BlockEmpty,
+
+    /// Save, then unassign, all block buffers
+    /// Stack effect: ( -- )
+    BlockFlush,
+}
+
+/// Words the C runtime implements: the word, its function, and the cells
+/// it takes from and leaves on the stack
+const RUNTIME_IO_WORDS: &[(&str, &str, usize, usize)] = &[
+    ("emit", "forth_io_emit", 1, 0),
+    ("type", "forth_io_type", 2, 0),
+    ("cr", "forth_io_cr", 0, 0),
+    ("space", "forth_io_space", 0, 0),
+    (".", "forth_io_dot", 1, 0),
+    ("u.", "forth_io_udot", 1, 0),
+    (".r", "forth_io_dot_r", 2, 0),
+    ("key", "forth_io_key", 0, 1),
+    ("key?", "forth_io_key_ready", 0, 1),
+    ("accept", "forth_io_accept", 2, 1),
+    ("<#", "forth_pict_begin", 0, 0),
+    ("#", "forth_pict_digit", 2, 2),
+    ("#s", "forth_pict_digits", 2, 2),
+    ("hold", "forth_pict_hold", 1, 0),
+    ("sign", "forth_pict_sign", 1, 0),
+    ("#>", "forth_pict_end", 2, 2),
+    ("open-blocks", "forth_block_open", 2, 0),
+    ("join", "forth_task_join", 1, 1),
+    ("pause", "forth_task_pause", 0, 0),
+    ("channel", "forth_channel_create", 1, 1),
+    ("send", "forth_channel_send", 2, 0),
+    ("recv", "forth_channel_recv", 1, 1),
+    ("close-channel", "forth_channel_close", 1, 0),
+    ("destroy-channel", "forth_channel_destroy", 1, 0),
+];
+
+/// The C runtime function behind an I/O word, with the cells it takes
+/// from and leaves on the stack
+fn runtime_io_function(word: &str) -> Option<(&'static str, usize, usize)> {
+    RUNTIME_IO_WORDS
+        .iter()
+        .find(|(name, ..)| *name == word)
+        .map(|&(_, function, inputs, outputs)| (function, inputs, outputs))
+}
+
+/// The word a C runtime function implements
+pub fn runtime_io_word(function: &str) -> Option<&'static str> {
+    RUNTIME_IO_WORDS.iter().find(|(_, name, ..)| *name == function).map(|&(word, ..)| word)
 }
 
 /// Binary operators
@@ -187,6 +392,24 @@ pub enum BinaryOperator {
     Ne,
     And,
     Or,
+    Xor,
+    /// Shift left, by the low six bits of the right operand
+    Shl,
+    /// Logical shift right, by the low six bits of the right operand
+    Shr,
+    /// Unsigned comparisons
+    ULt,
+    UGt,
+    /// Unsigned division and remainder
+    UDiv,
+    UMod,
+    /// Low and high cell of the unsigned double-cell product; the low cell
+    /// wraps whatever the overflow semantics
+    UMul,
+    UMulHigh,
+    /// Rotations, by the right operand modulo the cell width
+    Rotl,
+    Rotr,
 }
 
 impl fmt::Display for BinaryOperator {
@@ -205,6 +428,17 @@ impl fmt::Display for BinaryOperator {
             BinaryOperator::Ne => write!(f, "ne"),
             BinaryOperator::And => write!(f, "and"),
             BinaryOperator::Or => write!(f, "or"),
+            BinaryOperator::Xor => write!(f, "xor"),
+            BinaryOperator::Shl => write!(f, "shl"),
+            BinaryOperator::Shr => write!(f, "shr"),
+            BinaryOperator::ULt => write!(f, "ult"),
+            BinaryOperator::UGt => write!(f, "ugt"),
+            BinaryOperator::UDiv => write!(f, "udiv"),
+            BinaryOperator::UMod => write!(f, "umod"),
+            BinaryOperator::UMul => write!(f, "umul"),
+            BinaryOperator::UMulHigh => write!(f, "umulhi"),
+            BinaryOperator::Rotl => write!(f, "rotl"),
+            BinaryOperator::Rotr => write!(f, "rotr"),
         }
     }
 }
@@ -215,6 +449,11 @@ pub enum UnaryOperator {
     Negate,
     Not,
     Abs,
+    /// Number of set bits
+    Popcount,
+    /// Trailing and leading zero bits; the cell width for zero
+    Ctz,
+    Clz,
 }
 
 impl fmt::Display for UnaryOperator {
@@ -223,6 +462,55 @@ impl fmt::Display for UnaryOperator {
             UnaryOperator::Negate => write!(f, "neg"),
             UnaryOperator::Not => write!(f, "not"),
             UnaryOperator::Abs => write!(f, "abs"),
+            UnaryOperator::Popcount => write!(f, "popcount"),
+            UnaryOperator::Ctz => write!(f, "ctz"),
+            UnaryOperator::Clz => write!(f, "clz"),
+        }
+    }
+}
+
+/// How much memory a load or store accesses
+///
+/// Memory is little-endian on every target, whatever the target's own byte
+/// order, so every backend and interpreter lays a buffer out the same way:
+/// a cell is 8 bytes, low byte first, and the narrower accesses (`c@ w@ l@`
+/// and their stores) read and write the low bytes of one. Loads zero-extend
+/// and stores write the low bytes of the value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MemoryWidth {
+    W8,
+    W16,
+    W32,
+    Cell,
+}
+
+impl MemoryWidth {
+    /// Width of the fetch or store word `name`
+    pub fn of_word(name: &str) -> Option<Self> {
+        match name {
+            "@" | "!" => Some(MemoryWidth::Cell),
+            "c@" | "c!" => Some(MemoryWidth::W8),
+            "w@" | "w!" => Some(MemoryWidth::W16),
+            "l@" | "l!" => Some(MemoryWidth::W32),
+            _ => None,
+        }
+    }
+
+    pub fn bytes(self) -> u32 {
+        match self {
+            MemoryWidth::W8 => 1,
+            MemoryWidth::W16 => 2,
+            MemoryWidth::W32 => 4,
+            MemoryWidth::Cell => 8,
+        }
+    }
+}
+
+impl fmt::Display for MemoryWidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemoryWidth::Cell => write!(f, "cell"),
+            width => write!(f, "{}", width.bytes() * 8),
         }
     }
 }
@@ -243,6 +531,8 @@ pub struct BasicBlock {
     pub id: BlockId,
     pub instructions: Vec<SSAInstruction>,
     pub predecessors: Vec<BlockId>,
+    /// Source location of each instruction, parallel to `instructions`
+    pub locations: Vec<SourceLocation>,
 }
 
 impl BasicBlock {
@@ -251,8 +541,14 @@ impl BasicBlock {
             id,
             instructions: Vec::new(),
             predecessors: Vec::new(),
+            locations: Vec::new(),
         }
     }
+
+    /// Source location of the instruction at `index`, if known
+    pub fn location(&self, index: usize) -> Option<&SourceLocation> {
+        self.locations.get(index).filter(|location| location.is_known())
+    }
 }
 
 /// SSA function representation
@@ -277,6 +573,18 @@ impl SSAFunction {
         }
     }
 
+    /// Number of values the function returns, the same at every return
+    pub fn result_count(&self) -> usize {
+        self.blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .find_map(|inst| match inst {
+                SSAInstruction::Return { values } => Some(values.len()),
+                _ => None,
+            })
+            .unwrap_or(0)
+    }
+
     /// Validate SSA form invariants
     ///
     /// This performs comprehensive validation including:
@@ -291,9 +599,31 @@ impl SSAFunction {
         let mut validator = SSAValidator::new(self);
         validator.validate()
     }
+
+    /// First register the function leaves unused, for passes that add
+    /// instructions to it
+    pub fn next_register(&self) -> usize {
+        use crate::ssa_validator::SSAValidator;
+        let defined = self
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .flat_map(SSAValidator::get_destination_registers);
+        self.parameters.iter().copied().chain(defined).map(|reg| reg.0 + 1).max().unwrap_or(0)
+    }
+}
+
+/// A loop being converted: its header block, the phis there carrying the
+/// stack, the locals and the loop's own values around it, and how many
+/// locals there were
+struct LoopHeader {
+    block: BlockId,
+    phis: Vec<Register>,
+    locals: usize,
 }
 
 /// SSA converter
+#[derive(Clone)]
 pub struct SSAConverter {
     next_register: usize,
     next_block: usize,
@@ -301,8 +631,31 @@ pub struct SSAConverter {
     blocks: Vec<BasicBlock>,
     /// Map from function name to parameter count
     function_params: std::collections::HashMap<String, usize>,
+    /// Map from word name to the number of results a call leaves, declared
+    /// or inferred for colon definitions and declared for CODE words
+    word_results: std::collections::HashMap<String, usize>,
     /// Current function name (for RECURSE support)
     current_function_name: Option<String>,
+    /// Source location of the word being converted
+    location: SourceLocation,
+    /// Registers holding the locals declared so far, latest last, and the
+    /// cells moved to the return stack, as locals named
+    /// [`RETURN_STACK_CELL`]: control structures merge and carry both
+    locals: Vec<(Symbol, Register)>,
+    /// Registers holding the index of each enclosing DO loop, innermost last
+    loop_indices: Vec<Register>,
+    /// Map from variable and CREATE buffer name to size in bytes
+    variables: std::collections::HashMap<String, usize>,
+    /// Parameters and results of the words EXECUTE may call on a token
+    /// kept as a value, if the program keeps any
+    execute_effect: Option<(usize, usize)>,
+    /// Bodies of the words whose DO loops change the stack depth, such as
+    /// `: squares 0 do i i * loop ;`: they leave no fixed number of
+    /// results, so they are expanded where they are called instead of
+    /// being compiled as functions
+    expanded: std::collections::HashMap<String, Vec<Word>>,
+    /// Words being expanded, outermost first
+    expanding: Vec<String>,
 }
 
 impl SSAConverter {
@@ -313,7 +666,15 @@ impl SSAConverter {
             current_block: BlockId(0),
             blocks: Vec::new(),
             function_params: std::collections::HashMap::new(),
+            word_results: std::collections::HashMap::new(),
             current_function_name: None,
+            location: SourceLocation::default(),
+            locals: Vec::new(),
+            loop_indices: Vec::new(),
+            variables: std::collections::HashMap::new(),
+            execute_effect: None,
+            expanded: std::collections::HashMap::new(),
+            expanding: Vec::new(),
         }
     }
 
@@ -332,6 +693,7 @@ impl SSAConverter {
     fn emit(&mut self, instruction: SSAInstruction) {
         if let Some(block) = self.blocks.iter_mut().find(|b| b.id == self.current_block) {
             block.instructions.push(instruction);
+            block.locations.push(self.location.clone());
         } else {
             debug_assert!(false, "Attempting to emit instruction to non-existent block {:?}", self.current_block);
         }
@@ -348,9 +710,22 @@ impl SSAConverter {
     }
 
     /// Convert a sequence of words to SSA
+    ///
+    /// A CASE with dense integer keys becomes a single switch, or a lookup
+    /// when it only picks a constant.
     pub fn convert_sequence(&mut self, words: &[Word], stack: &mut Vec<Register>) -> Result<()> {
-        for word in words {
-            self.convert_word(word, stack)?;
+        let mut rest = words;
+        while let Some(word) = rest.first() {
+            match CaseChain::find(rest).filter(|chain| chain.is_dense() && !stack.is_empty()) {
+                Some(chain) => {
+                    self.convert_case(&chain, stack)?;
+                    rest = &rest[CaseChain::LEN..];
+                }
+                None => {
+                    self.convert_word(word, stack)?;
+                    rest = &rest[1..];
+                }
+            }
         }
         Ok(())
     }
@@ -382,15 +757,63 @@ impl SSAConverter {
                 self.emit(SSAInstruction::LoadString {
                     dest_addr,
                     dest_len,
-                    value: value.clone(),
+                    value: *value,
                 });
                 // Push both address and length (ANS Forth convention)
                 stack.push(dest_addr);
                 stack.push(dest_len);
             }
 
-            Word::WordRef { name, .. } => {
-                self.convert_word_call(name, stack)?;
+            Word::WordRef { name, location } => {
+                self.location = location.clone();
+                self.convert_word_call(*name, stack).map_err(|e| e.at(location))?;
+            }
+
+            Word::TaskSpawn { word, location } => {
+                self.location = location.clone();
+                self.convert_task_spawn(*word, stack).map_err(|e| e.at(location))?;
+            }
+
+            Word::Locals { args, uninitialized, location } => {
+                if stack.len() < args.len() {
+                    return Err(ForthError::StackUnderflow {
+                        word: "{:".to_string(),
+                        expected: args.len(),
+                        found: stack.len(),
+                        location: Some(location.clone()),
+                    });
+                }
+                let values = stack.split_off(stack.len() - args.len());
+                self.locals.extend(args.iter().copied().zip(values));
+                for name in uninitialized {
+                    let dest = self.fresh_register();
+                    self.emit(SSAInstruction::LoadInt { dest, value: 0 });
+                    self.locals.push((*name, dest));
+                }
+            }
+
+            // A local is whichever register was last stored to it
+            Word::LocalRef { name, location } => {
+                let register = self.local(*name, location)?;
+                stack.push(self.locals[register].1);
+            }
+
+            Word::LocalStore { name, location } => {
+                let register = self.local(*name, location)?;
+                let value = stack.pop().ok_or_else(|| ForthError::StackUnderflow {
+                    word: format!("TO {}", name),
+                    expected: 1,
+                    found: 0,
+                    location: Some(location.clone()),
+                })?;
+                self.locals[register].1 = value;
+            }
+
+            Word::Quotation { name, location } => {
+                self.location = location.clone();
+                let dest = self.fresh_register();
+                self.emit(SSAInstruction::FunctionAddress { dest, name: *name });
+                stack.push(dest);
             }
 
             Word::If {
@@ -408,15 +831,13 @@ impl SSAConverter {
                 self.convert_begin_while_repeat(condition, body, stack)?;
             }
 
-            Word::DoLoop { body, .. } => {
-                self.convert_do_loop(body, stack)?;
+            Word::DoLoop { body, increment } => {
+                self.convert_do_loop(body, *increment, stack)?;
             }
 
-            Word::Variable { name: _ } => {
-                // Variables push their address
-                let dest = self.fresh_register();
-                // Would need to implement variable address allocation
-                stack.push(dest);
+            Word::Variable { .. } => {
+                // Defining a variable doesn't touch the stack; using it
+                // pushes its address
             }
 
             Word::Constant { name: _, value } => {
@@ -436,8 +857,58 @@ impl SSAConverter {
         Ok(())
     }
 
+    /// Size of the variable `name`, unless a definition of the same name
+    /// hides it
+    fn variable(&self, name: &str) -> Option<usize> {
+        self.variables.get(name).copied().filter(|_| !self.function_params.contains_key(name))
+    }
+
+    /// Index of the local `name` in scope
+    fn local(&self, name: Symbol, location: &SourceLocation) -> Result<usize> {
+        self.locals.iter().rposition(|(local, _)| *local == name).ok_or_else(|| ForthError::UndefinedWord {
+            word: name.to_string(),
+            location: Some(location.clone()),
+        })
+    }
+
+    /// Registers of the locals in scope before a control structure, as a
+    /// path through it left them
+    ///
+    /// Locals declared inside the structure go out of scope with it, and
+    /// so do cells moved to the return stack; a path may not take back
+    /// cells moved there before the structure.
+    fn path_locals(&mut self, before: &[(Symbol, Register)]) -> Result<Vec<Register>> {
+        let path = std::mem::replace(&mut self.locals, before.to_vec());
+        let kept = path.iter().zip(before).take_while(|((path, _), (before, _))| path == before).count();
+        if kept < before.len() {
+            return Err(ForthError::SSAConversionError {
+                message: "R> takes back a cell moved to the return stack before the control structure; \
+                          each path through it has to leave the return stack as deep as it found it"
+                    .to_string(),
+                location: Some(self.location.clone()),
+            });
+        }
+        Ok(path.iter().take(before.len()).map(|(_, register)| *register).collect())
+    }
+
+    /// Take the merged registers of the locals off the end of a merged stack
+    fn merge_locals(&mut self, before: &[(Symbol, Register)], merged: &mut Vec<Register>) {
+        let registers = merged.split_off(merged.len() - before.len());
+        self.locals = before.iter().map(|(name, _)| *name).zip(registers).collect();
+    }
+
     /// Convert a word call to SSA
-    fn convert_word_call(&mut self, name: &str, stack: &mut Vec<Register>) -> Result<()> {
+    fn convert_word_call(&mut self, symbol: Symbol, stack: &mut Vec<Register>) -> Result<()> {
+        let name = symbol.as_str();
+        if let Some(size) = self.variable(name) {
+            let dest = self.fresh_register();
+            self.emit(SSAInstruction::DataAddress { dest, name: name.to_string(), size });
+            stack.push(dest);
+            return Ok(());
+        }
+        if let Some(body) = self.expanded.get(name).cloned() {
+            return self.expand(name, &body, stack);
+        }
         match name {
             // Arithmetic operations
             "+" => self.convert_binary_op(BinaryOperator::Add, stack),
@@ -453,11 +924,37 @@ impl SSAConverter {
             ">=" => self.convert_binary_op(BinaryOperator::Ge, stack),
             "=" => self.convert_binary_op(BinaryOperator::Eq, stack),
             "<>" => self.convert_binary_op(BinaryOperator::Ne, stack),
+            "u<" => self.convert_binary_op(BinaryOperator::ULt, stack),
+            "u>" => self.convert_binary_op(BinaryOperator::UGt, stack),
+
+            // Unsigned arithmetic, leaving two cells
+            "um*" => self.convert_pair_op(BinaryOperator::UMul, BinaryOperator::UMulHigh, stack),
+            "u/mod" => self.convert_pair_op(BinaryOperator::UMod, BinaryOperator::UDiv, stack),
 
-            // Logical operations
+            // Bitwise operations
             "and" => self.convert_binary_op(BinaryOperator::And, stack),
             "or" => self.convert_binary_op(BinaryOperator::Or, stack),
-            "not" => self.convert_unary_op(UnaryOperator::Not, stack),
+            "xor" => self.convert_binary_op(BinaryOperator::Xor, stack),
+            "lshift" => self.convert_binary_op(BinaryOperator::Shl, stack),
+            "rshift" => self.convert_binary_op(BinaryOperator::Shr, stack),
+            "not" | "invert" => self.convert_unary_op(UnaryOperator::Not, stack),
+
+            // Bit manipulation
+            "popcount" => self.convert_unary_op(UnaryOperator::Popcount, stack),
+            "ctz" => self.convert_unary_op(UnaryOperator::Ctz, stack),
+            "clz" => self.convert_unary_op(UnaryOperator::Clz, stack),
+            "rol" => self.convert_binary_op(BinaryOperator::Rotl, stack),
+            "ror" => self.convert_binary_op(BinaryOperator::Rotr, stack),
+
+            // Operations with a constant
+            "1+" => self.convert_constant_op(BinaryOperator::Add, 1, stack),
+            "1-" => self.convert_constant_op(BinaryOperator::Sub, 1, stack),
+            "cells" => self.convert_constant_op(BinaryOperator::Mul, CELL_SIZE, stack),
+            "cell+" => self.convert_constant_op(BinaryOperator::Add, CELL_SIZE, stack),
+            "0=" => self.convert_constant_op(BinaryOperator::Eq, 0, stack),
+            "0<>" => self.convert_constant_op(BinaryOperator::Ne, 0, stack),
+            "0<" => self.convert_constant_op(BinaryOperator::Lt, 0, stack),
+            "0>" => self.convert_constant_op(BinaryOperator::Gt, 0, stack),
 
             // Unary operations
             "negate" => self.convert_unary_op(UnaryOperator::Negate, stack),
@@ -472,6 +969,7 @@ impl SSAConverter {
                         word: "dup".to_string(),
                         expected: 1,
                         found: 0,
+                        location: None,
                     });
                 }
                 Ok(())
@@ -483,6 +981,7 @@ impl SSAConverter {
                         word: "drop".to_string(),
                         expected: 1,
                         found: 0,
+                        location: None,
                     });
                 }
                 Ok(())
@@ -494,6 +993,7 @@ impl SSAConverter {
                         word: "swap".to_string(),
                         expected: 2,
                         found: stack.len(),
+                        location: None,
                     });
                 }
                 let len = stack.len();
@@ -507,6 +1007,7 @@ impl SSAConverter {
                         word: "over".to_string(),
                         expected: 2,
                         found: stack.len(),
+                        location: None,
                     });
                 }
                 let reg = stack[stack.len() - 2];
@@ -520,6 +1021,7 @@ impl SSAConverter {
                         word: "rot".to_string(),
                         expected: 3,
                         found: stack.len(),
+                        location: None,
                     });
                 }
                 let len = stack.len();
@@ -531,31 +1033,38 @@ impl SSAConverter {
             }
 
             // Memory operations
-            "@" => {
+            "@" | "c@" | "w@" | "l@" => {
+                let width = MemoryWidth::of_word(name).unwrap_or(MemoryWidth::Cell);
+                let ty = if width == MemoryWidth::W8 { StackType::Char } else { StackType::Int };
                 if let Some(addr) = stack.pop() {
                     let dest = self.fresh_register();
                     self.emit(SSAInstruction::Load {
                         dest,
                         address: addr,
-                        ty: StackType::Int,
+                        ty,
+                        width,
                     });
                     stack.push(dest);
                 } else {
                     return Err(ForthError::StackUnderflow {
-                        word: "@".to_string(),
+                        word: name.to_string(),
                         expected: 1,
                         found: 0,
+                        location: None,
                     });
                 }
                 Ok(())
             }
 
-            "!" => {
+            "!" | "c!" | "w!" | "l!" => {
+                let width = MemoryWidth::of_word(name).unwrap_or(MemoryWidth::Cell);
+                let ty = if width == MemoryWidth::W8 { StackType::Char } else { StackType::Int };
                 if stack.len() < 2 {
                     return Err(ForthError::StackUnderflow {
-                        word: "!".to_string(),
+                        word: name.to_string(),
                         expected: 2,
                         found: stack.len(),
+                        location: None,
                     });
                 }
                 let addr = stack.pop().unwrap();
@@ -563,62 +1072,36 @@ impl SSAConverter {
                 self.emit(SSAInstruction::Store {
                     address: addr,
                     value,
-                    ty: StackType::Int,
+                    ty,
+                    width,
                 });
                 Ok(())
             }
 
-            // Return stack operations (for now, treat as no-ops or simple calls)
-            // TODO: Implement proper return stack handling
-            ">r" | "r>" | "r@" => {
-                // For now, treat these as opaque operations
-                // A full implementation would need a separate return stack
-                let dest = self.fresh_register();
-                let mut args = SmallVec::new();
-                if name == ">r" {
-                    // Move value from data stack to return stack
-                    if let Some(val) = stack.pop() {
-                        args.push(val);
-                    } else {
-                        return Err(ForthError::StackUnderflow {
-                            word: name.to_string(),
-                            expected: 1,
-                            found: 0,
-                        });
-                    }
-                } else if name == "r>" {
-                    // Move value from return stack to data stack
-                    stack.push(dest);
-                } else if name == "r@" {
-                    // Copy top of return stack to data stack
-                    stack.push(dest);
+            ">r" | "r>" | "r@" => self.convert_return_stack(name, stack),
+
+            // I/O operations call into the C runtime, unless redefined
+            io if !self.function_params.contains_key(io) && runtime_io_function(io).is_some() => {
+                let (function, inputs, outputs) = runtime_io_function(io).unwrap();
+                if stack.len() < inputs {
+                    return Err(ForthError::StackUnderflow {
+                        word: name.to_string(),
+                        expected: inputs,
+                        found: stack.len(),
+                        location: None,
+                    });
                 }
+                let args: SmallVec<[Register; 4]> = stack.drain(stack.len() - inputs..).collect();
+                let dest: SmallVec<[Register; 4]> = (0..outputs).map(|_| self.fresh_register()).collect();
 
-                self.emit(SSAInstruction::Call {
-                    dest: smallvec::smallvec![dest],
-                    name: name.to_string(),
+                self.emit(SSAInstruction::FFICall {
+                    dest: dest.clone(),
+                    function: function.to_string(),
                     args,
                 });
-                Ok(())
-            }
 
-            // I/O operations
-            "." | "emit" | "cr" => {
-                // Print operations - consume from stack
-                if let Some(val) = stack.pop() {
-                    self.emit(SSAInstruction::Call {
-                        dest: SmallVec::new(),
-                        name: name.to_string(),
-                        args: smallvec::smallvec![val],
-                    });
-                    Ok(())
-                } else {
-                    Err(ForthError::StackUnderflow {
-                        word: name.to_string(),
-                        expected: 1,
-                        found: 0,
-                    })
-                }
+                stack.extend(dest);
+                Ok(())
             }
 
             // File mode constants (ANS Forth)
@@ -630,7 +1113,7 @@ impl SSAConverter {
                 self.emit(SSAInstruction::LoadString {
                     dest_addr,
                     dest_len,
-                    value: "r".to_string(),
+                    value: "r".into(),
                 });
                 stack.push(dest_addr);
                 stack.push(dest_len);
@@ -643,7 +1126,7 @@ impl SSAConverter {
                 self.emit(SSAInstruction::LoadString {
                     dest_addr,
                     dest_len,
-                    value: "w".to_string(),
+                    value: "w".into(),
                 });
                 stack.push(dest_addr);
                 stack.push(dest_len);
@@ -656,7 +1139,7 @@ impl SSAConverter {
                 self.emit(SSAInstruction::LoadString {
                     dest_addr,
                     dest_len,
-                    value: "r+".to_string(),
+                    value: "r+".into(),
                 });
                 stack.push(dest_addr);
                 stack.push(dest_len);
@@ -671,6 +1154,7 @@ impl SSAConverter {
                         word: "create-file".to_string(),
                         expected: 4,
                         found: stack.len(),
+                        location: None,
                     });
                 }
                 let _mode_len = stack.pop().unwrap();
@@ -701,6 +1185,7 @@ impl SSAConverter {
                         word: "open-file".to_string(),
                         expected: 4,
                         found: stack.len(),
+                        location: None,
                     });
                 }
                 let _mode_len = stack.pop().unwrap();
@@ -731,6 +1216,7 @@ impl SSAConverter {
                         word: "read-file".to_string(),
                         expected: 3,
                         found: stack.len(),
+                        location: None,
                     });
                 }
                 let fileid = stack.pop().unwrap();
@@ -760,6 +1246,7 @@ impl SSAConverter {
                         word: "write-file".to_string(),
                         expected: 3,
                         found: stack.len(),
+                        location: None,
                     });
                 }
                 let fileid = stack.pop().unwrap();
@@ -786,6 +1273,7 @@ impl SSAConverter {
                         word: "close-file".to_string(),
                         expected: 1,
                         found: 0,
+                        location: None,
                     });
                 }
                 let fileid = stack.pop().unwrap();
@@ -807,6 +1295,7 @@ impl SSAConverter {
                         word: "delete-file".to_string(),
                         expected: 2,
                         found: stack.len(),
+                        location: None,
                     });
                 }
                 let path_len = stack.pop().unwrap();
@@ -832,6 +1321,7 @@ impl SSAConverter {
                         word: "system".to_string(),
                         expected: 2,
                         found: stack.len(),
+                        location: None,
                     });
                 }
                 let command_len = stack.pop().unwrap();
@@ -849,119 +1339,489 @@ impl SSAConverter {
                 Ok(())
             }
 
-            // Loop index word
-            "i" | "j" => {
-                // Loop index - pushes current loop counter
+            // Process environment
+            "arg-count" => {
+                // Stack effect: ( -- n )
                 let dest = self.fresh_register();
-                self.emit(SSAInstruction::Call {
-                    dest: smallvec::smallvec![dest],
-                    name: name.to_string(),
-                    args: SmallVec::new(),
-                });
+                self.emit(SSAInstruction::ArgCount { dest });
                 stack.push(dest);
                 Ok(())
             }
 
-            // Other special words
-            "execute" | "char" => {
-                // For now, treat as generic calls
-                let dest = self.fresh_register();
-                let args = if name == "execute" && !stack.is_empty() {
-                    smallvec::smallvec![stack.pop().unwrap()]
-                } else {
-                    SmallVec::new()
-                };
-                self.emit(SSAInstruction::Call {
-                    dest: smallvec::smallvec![dest],
-                    name: name.to_string(),
-                    args,
+            "arg@" => {
+                // Stack effect: ( n -- c-addr u )
+                let index = stack.pop().ok_or_else(|| ForthError::StackUnderflow {
+                    word: "arg@".to_string(),
+                    expected: 1,
+                    found: 0,
+                    location: None,
+                })?;
+
+                let dest_addr = self.fresh_register();
+                let dest_len = self.fresh_register();
+
+                self.emit(SSAInstruction::ArgFetch {
+                    dest_addr,
+                    dest_len,
+                    index,
                 });
-                stack.push(dest);
+
+                stack.push(dest_addr);
+                stack.push(dest_len);
                 Ok(())
             }
 
-            // Recursion - call current function
-            "recurse" => {
-                let func_name = self.current_function_name.clone().ok_or_else(|| {
-                    ForthError::SSAConversionError {
-                        message: "RECURSE used outside of word definition".to_string(),
-                    }
-                })?;
-
-                // Get parameter count for current function
-                let param_count = self.function_params.get(&func_name).copied().unwrap_or(0);
-
-                // Pop arguments from stack
-                if stack.len() < param_count {
+            "getenv" => {
+                // Stack effect: ( c-addr u -- c-addr u )
+                if stack.len() < 2 {
                     return Err(ForthError::StackUnderflow {
-                        word: "recurse".to_string(),
-                        expected: param_count,
+                        word: "getenv".to_string(),
+                        expected: 2,
                         found: stack.len(),
+                        location: None,
                     });
                 }
+                let name_len = stack.pop().unwrap();
+                let name_addr = stack.pop().unwrap();
 
-                let mut args = SmallVec::new();
-                for _ in 0..param_count {
-                    if let Some(arg) = stack.pop() {
-                        args.push(arg);
-                    }
-                }
-                args.reverse();
+                let dest_addr = self.fresh_register();
+                let dest_len = self.fresh_register();
 
-                let dest = self.fresh_register();
-                self.emit(SSAInstruction::Call {
-                    dest: smallvec::smallvec![dest],
-                    name: func_name,
-                    args,
+                self.emit(SSAInstruction::GetEnv {
+                    dest_addr,
+                    dest_len,
+                    name_addr,
+                    name_len,
                 });
-                stack.push(dest);
+
+                stack.push(dest_addr);
+                stack.push(dest_len);
                 Ok(())
             }
 
-            // Generic word call
-            _ => {
-                // Look up the function to determine how many parameters it takes
-                let param_count = self.function_params.get(name).copied().unwrap_or(0);
+            // Memory-allocation word set, unless redefined
+            "allocate" if !self.function_params.contains_key(name) => {
+                // Stack effect: ( u -- a-addr ior )
+                let size = stack.pop().ok_or_else(|| ForthError::StackUnderflow {
+                    word: "allocate".to_string(),
+                    expected: 1,
+                    found: 0,
+                    location: None,
+                })?;
 
-                // Pop arguments from stack
-                if stack.len() < param_count {
-                    return Err(ForthError::StackUnderflow {
-                        word: name.to_string(),
-                        expected: param_count,
-                        found: stack.len(),
-                    });
-                }
+                let dest_addr = self.fresh_register();
+                let dest_ior = self.fresh_register();
 
-                let mut args = SmallVec::new();
-                for _ in 0..param_count {
-                    // Pop from end and reverse to maintain order
-                    if let Some(arg) = stack.pop() {
-                        args.push(arg);
-                    }
-                }
-                // Reverse to get correct argument order
-                args.reverse();
+                self.emit(SSAInstruction::HeapAllocate { dest_addr, dest_ior, size });
 
-                let dest = self.fresh_register();
-                self.emit(SSAInstruction::Call {
-                    dest: smallvec::smallvec![dest],
-                    name: name.to_string(),
-                    args,
-                });
-                stack.push(dest);
+                stack.push(dest_addr);
+                stack.push(dest_ior);
                 Ok(())
             }
-        }
-    }
 
-    fn convert_binary_op(&mut self, op: BinaryOperator, stack: &mut Vec<Register>) -> Result<()> {
-        if stack.len() < 2 {
-            return Err(ForthError::StackUnderflow {
-                word: format!("{}", op),
-                expected: 2,
-                found: stack.len(),
-            });
-        }
+            "free" if !self.function_params.contains_key(name) => {
+                // Stack effect: ( a-addr -- ior )
+                let addr = stack.pop().ok_or_else(|| ForthError::StackUnderflow {
+                    word: "free".to_string(),
+                    expected: 1,
+                    found: 0,
+                    location: None,
+                })?;
+
+                let dest_ior = self.fresh_register();
+
+                self.emit(SSAInstruction::HeapFree { dest_ior, addr });
+
+                stack.push(dest_ior);
+                Ok(())
+            }
+
+            "resize" if !self.function_params.contains_key(name) => {
+                // Stack effect: ( a-addr1 u -- a-addr2 ior )
+                if stack.len() < 2 {
+                    return Err(ForthError::StackUnderflow {
+                        word: "resize".to_string(),
+                        expected: 2,
+                        found: stack.len(),
+                        location: None,
+                    });
+                }
+                let size = stack.pop().unwrap();
+                let addr = stack.pop().unwrap();
+
+                let dest_addr = self.fresh_register();
+                let dest_ior = self.fresh_register();
+
+                self.emit(SSAInstruction::HeapResize { dest_addr, dest_ior, addr, size });
+
+                stack.push(dest_addr);
+                stack.push(dest_ior);
+                Ok(())
+            }
+
+            // Atomic cells shared between tasks, unless redefined
+            "@atomic" if !self.function_params.contains_key(name) => {
+                // Stack effect: ( a-addr -- x )
+                let address = stack.pop().ok_or_else(|| ForthError::StackUnderflow {
+                    word: "@atomic".to_string(),
+                    expected: 1,
+                    found: 0,
+                    location: None,
+                })?;
+
+                let dest = self.fresh_register();
+                self.emit(SSAInstruction::AtomicLoad { dest, address });
+                stack.push(dest);
+                Ok(())
+            }
+
+            "!atomic" if !self.function_params.contains_key(name) => {
+                // Stack effect: ( x a-addr -- )
+                if stack.len() < 2 {
+                    return Err(ForthError::StackUnderflow {
+                        word: "!atomic".to_string(),
+                        expected: 2,
+                        found: stack.len(),
+                        location: None,
+                    });
+                }
+                let address = stack.pop().unwrap();
+                let value = stack.pop().unwrap();
+                self.emit(SSAInstruction::AtomicStore { address, value });
+                Ok(())
+            }
+
+            // Block word set, unless redefined
+            "block" | "buffer" if !self.function_params.contains_key(name) => {
+                // Stack effect: ( u -- a-addr )
+                let block = stack.pop().ok_or_else(|| ForthError::StackUnderflow {
+                    word: name.to_string(),
+                    expected: 1,
+                    found: 0,
+                    location: None,
+                })?;
+                let dest = self.fresh_register();
+
+                if name == "block" {
+                    self.emit(SSAInstruction::BlockFetch { dest, block });
+                } else {
+                    self.emit(SSAInstruction::BlockAssign { dest, block });
+                }
+
+                stack.push(dest);
+                Ok(())
+            }
+
+            "update" if !self.function_params.contains_key(name) => {
+                self.emit(SSAInstruction::BlockUpdate);
+                Ok(())
+            }
+
+            "save-buffers" if !self.function_params.contains_key(name) => {
+                self.emit(SSAInstruction::BlockSave);
+                Ok(())
+            }
+
+            "empty-buffers" if !self.function_params.contains_key(name) => {
+                self.emit(SSAInstruction::BlockEmpty);
+                Ok(())
+            }
+
+            "flush" if !self.function_params.contains_key(name) => {
+                self.emit(SSAInstruction::BlockFlush);
+                Ok(())
+            }
+
+            // Loop index word
+            "i" | "j" if !self.function_params.contains_key(name) => {
+                let depth = if name == "i" { 1 } else { 2 };
+                let index = self.loop_indices.iter().rev().nth(depth - 1).copied().ok_or_else(|| {
+                    ForthError::SSAConversionError {
+                        message: format!("{} is only defined inside {} DO loop", name.to_uppercase(), if depth == 1 { "a" } else { "a nested" }),
+                        location: Some(self.location.clone()),
+                    }
+                })?;
+                stack.push(index);
+                Ok(())
+            }
+
+            // A token right before EXECUTE has become a call by now, so
+            // this one is a value
+            "execute" if !self.function_params.contains_key(name) => self.convert_execute(stack),
+
+            // Other special words
+            "char" => {
+                // For now, treat as a generic call
+                let dest = self.fresh_register();
+                self.emit(SSAInstruction::Call {
+                    dest: smallvec::smallvec![dest],
+                    name: symbol,
+                    args: SmallVec::new(),
+                });
+                stack.push(dest);
+                Ok(())
+            }
+
+            // Recursion - call current function
+            "recurse" => {
+                let func_name = self.current_function_name.clone().ok_or_else(|| {
+                    ForthError::SSAConversionError {
+                        message: "RECURSE used outside of word definition".to_string(),
+                        location: None,
+                    }
+                })?;
+
+                // Get parameter count for current function
+                let param_count = self.function_params.get(&func_name).copied().unwrap_or(0);
+
+                // Pop arguments from stack
+                if stack.len() < param_count {
+                    return Err(ForthError::StackUnderflow {
+                        word: "recurse".to_string(),
+                        expected: param_count,
+                        found: stack.len(),
+                        location: None,
+                    });
+                }
+
+                let mut args = SmallVec::new();
+                for _ in 0..param_count {
+                    if let Some(arg) = stack.pop() {
+                        args.push(arg);
+                    }
+                }
+                args.reverse();
+
+                let dest = self.fresh_register();
+                self.emit(SSAInstruction::Call {
+                    dest: smallvec::smallvec![dest],
+                    name: func_name.into(),
+                    args,
+                });
+                stack.push(dest);
+                Ok(())
+            }
+
+            // Generic word call
+            _ => {
+                // Look up the function to determine how many parameters it takes
+                let param_count = self.function_params.get(name).copied().unwrap_or(0);
+
+                // Pop arguments from stack
+                if stack.len() < param_count {
+                    return Err(ForthError::StackUnderflow {
+                        word: name.to_string(),
+                        expected: param_count,
+                        found: stack.len(),
+                        location: None,
+                    });
+                }
+
+                let mut args = SmallVec::new();
+                for _ in 0..param_count {
+                    // Pop from end and reverse to maintain order
+                    if let Some(arg) = stack.pop() {
+                        args.push(arg);
+                    }
+                }
+                // Reverse to get correct argument order
+                args.reverse();
+
+                let results = self.word_results.get(name).copied().unwrap_or(1);
+                let dest: SmallVec<[Register; 4]> = (0..results).map(|_| self.fresh_register()).collect();
+                stack.extend(dest.iter().copied());
+                self.emit(SSAInstruction::Call {
+                    dest,
+                    name: symbol,
+                    args,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// `>R`, `R>` and `R@`, on the cells moved to the return stack in this
+    /// word, which are kept in registers
+    fn convert_return_stack(&mut self, name: &str, stack: &mut Vec<Register>) -> Result<()> {
+        let cell = Symbol::intern(RETURN_STACK_CELL);
+        if name == ">r" {
+            let value = stack.pop().ok_or_else(|| ForthError::StackUnderflow {
+                word: name.to_string(),
+                expected: 1,
+                found: 0,
+                location: None,
+            })?;
+            self.locals.push((cell, value));
+            return Ok(());
+        }
+
+        let position = self.locals.iter().rposition(|(local, _)| *local == cell).ok_or_else(|| {
+            ForthError::SSAConversionError {
+                message: format!("{} needs a cell moved to the return stack with >R in the same word", name.to_uppercase()),
+                location: Some(self.location.clone()),
+            }
+        })?;
+        let value = if name == "r>" { self.locals.remove(position).1 } else { self.locals[position].1 };
+        stack.push(value);
+        Ok(())
+    }
+
+    /// Convert the body of an expanded word in place of a call to it; the
+    /// locals it declares end with it
+    fn expand(&mut self, name: &str, body: &[Word], stack: &mut Vec<Register>) -> Result<()> {
+        if self.expanding.iter().any(|word| word == name) {
+            return Err(ForthError::SSAConversionError {
+                message: format!(
+                    "'{}' changes the stack depth in a loop and calls itself, so it cannot be expanded",
+                    name
+                ),
+                location: Some(self.location.clone()),
+            });
+        }
+        let locals = self.locals.len();
+        self.expanding.push(name.to_string());
+        let converted = self.convert_sequence(body, stack);
+        self.expanding.pop();
+        self.locals.truncate(locals);
+        converted
+    }
+
+    /// Whether `body` has a DO loop that changes the stack depth, or calls
+    /// a word that is expanded
+    fn needs_expansion(&self, body: &[Word]) -> bool {
+        body.iter().any(|word| match word {
+            Word::WordRef { name, .. } => self.expanded.contains_key(name.as_str()),
+            Word::If { then_branch, else_branch } => {
+                self.needs_expansion(then_branch)
+                    || else_branch.as_deref().is_some_and(|words| self.needs_expansion(words))
+            }
+            Word::BeginUntil { body } => self.needs_expansion(body),
+            Word::BeginWhileRepeat { condition, body } => self.needs_expansion(condition) || self.needs_expansion(body),
+            Word::DoLoop { body, .. } => self.pass_changes_depth(body),
+            _ => false,
+        })
+    }
+
+    /// Whether a pass of a DO loop with this body changes the stack depth
+    fn pass_changes_depth(&self, body: &[Word]) -> bool {
+        let (mut depth, mut min_depth) = (0, 0);
+        self.simulate_depth(body, &mut depth, &mut min_depth);
+        depth != 0 || self.needs_expansion(body)
+    }
+
+    /// The value of `register` if it was loaded as an integer constant
+    fn constant(&self, register: Register) -> Option<i64> {
+        self.blocks.iter().flat_map(|block| &block.instructions).find_map(|inst| match inst {
+            SSAInstruction::LoadInt { dest, value } if *dest == register => Some(*value),
+            _ => None,
+        })
+    }
+
+    /// Unroll `limit start DO ... LOOP` when both bounds are constants and
+    /// it runs at most [`MAX_UNROLLED_PASSES`] times, converting the body
+    /// once per index. Returns whether it did.
+    fn unroll_do_loop(
+        &mut self,
+        body: &[Word],
+        increment: i64,
+        limit: Register,
+        start: Register,
+        stack: &mut Vec<Register>,
+    ) -> Result<bool> {
+        let (Some(limit), Some(start)) = (self.constant(limit), self.constant(start)) else {
+            return Ok(false);
+        };
+        let mut indices = vec![start];
+        loop {
+            let next = indices[indices.len() - 1].wrapping_add(increment);
+            let again = if increment < 0 { next >= limit } else { next < limit };
+            if !again {
+                break;
+            }
+            if indices.len() == MAX_UNROLLED_PASSES {
+                return Ok(false);
+            }
+            indices.push(next);
+        }
+
+        for value in indices {
+            let index = self.fresh_register();
+            self.emit(SSAInstruction::LoadInt { dest: index, value });
+            self.loop_indices.push(index);
+            let converted = self.convert_sequence(body, stack);
+            self.loop_indices.pop();
+            converted?;
+        }
+        Ok(true)
+    }
+
+    /// EXECUTE a token taken as a value, calling its word with the effect
+    /// every such word has
+    fn convert_execute(&mut self, stack: &mut Vec<Register>) -> Result<()> {
+        let Some((inputs, outputs)) = self.execute_effect else {
+            return Err(ForthError::SSAConversionError {
+                message: "EXECUTE needs an execution token, e.g. [: 2 * ;] execute or ' name execute, \
+                          and the program takes none"
+                    .to_string(),
+                location: None,
+            });
+        };
+        if stack.len() < inputs + 1 {
+            return Err(ForthError::StackUnderflow {
+                word: "execute".to_string(),
+                expected: inputs + 1,
+                found: stack.len(),
+                location: None,
+            });
+        }
+
+        let target = stack.pop().unwrap();
+        let args: SmallVec<[Register; 4]> = stack.drain(stack.len() - inputs..).collect();
+        let dest: SmallVec<[Register; 4]> = (0..outputs).map(|_| self.fresh_register()).collect();
+        stack.extend(dest.iter().copied());
+        self.emit(SSAInstruction::CallIndirect { dest, target, args, likely: None });
+        Ok(())
+    }
+
+    /// TASK: word, passing the word its inputs from the stack
+    fn convert_task_spawn(&mut self, word: Symbol, stack: &mut Vec<Register>) -> Result<()> {
+        let Some(&inputs) = self.function_params.get(word.as_str()) else {
+            return Err(ForthError::SSAConversionError {
+                message: format!("TASK: needs a compiled word, and '{}' is not one", word),
+                location: None,
+            });
+        };
+        if inputs > 4 || self.word_results.get(word.as_str()).is_some_and(|&results| results > 1) {
+            return Err(ForthError::SSAConversionError {
+                message: format!("TASK: runs words taking up to 4 cells and leaving at most 1, not '{}'", word),
+                location: None,
+            });
+        }
+        if stack.len() < inputs {
+            return Err(ForthError::StackUnderflow {
+                word: format!("task: {}", word),
+                expected: inputs,
+                found: stack.len(),
+                location: None,
+            });
+        }
+
+        let args: SmallVec<[Register; 4]> = stack.drain(stack.len() - inputs..).collect();
+        let dest = self.fresh_register();
+        self.emit(SSAInstruction::TaskSpawn { dest, word, args });
+        stack.push(dest);
+        Ok(())
+    }
+
+    fn convert_binary_op(&mut self, op: BinaryOperator, stack: &mut Vec<Register>) -> Result<()> {
+        if stack.len() < 2 {
+            return Err(ForthError::StackUnderflow {
+                word: format!("{}", op),
+                expected: 2,
+                found: stack.len(),
+                location: None,
+            });
+        }
 
         let right = stack.pop().unwrap();
         let left = stack.pop().unwrap();
@@ -978,6 +1838,36 @@ impl SSAConverter {
         Ok(())
     }
 
+    /// Apply `op` to the value on top of the stack and `constant`
+    fn convert_constant_op(&mut self, op: BinaryOperator, constant: i64, stack: &mut Vec<Register>) -> Result<()> {
+        let dest = self.fresh_register();
+        self.emit(SSAInstruction::LoadInt { dest, value: constant });
+        stack.push(dest);
+        self.convert_binary_op(op, stack)
+    }
+
+    /// Apply `first` and then `second` to the same two cells, leaving both
+    /// results with the second on top
+    fn convert_pair_op(&mut self, first: BinaryOperator, second: BinaryOperator, stack: &mut Vec<Register>) -> Result<()> {
+        if stack.len() < 2 {
+            return Err(ForthError::StackUnderflow {
+                word: format!("{}", second),
+                expected: 2,
+                found: stack.len(),
+                location: None,
+            });
+        }
+
+        let right = stack.pop().unwrap();
+        let left = stack.pop().unwrap();
+        for op in [first, second] {
+            let dest = self.fresh_register();
+            self.emit(SSAInstruction::BinaryOp { dest, op, left, right });
+            stack.push(dest);
+        }
+        Ok(())
+    }
+
     fn convert_unary_op(&mut self, op: UnaryOperator, stack: &mut Vec<Register>) -> Result<()> {
         if let Some(operand) = stack.pop() {
             let dest = self.fresh_register();
@@ -989,6 +1879,7 @@ impl SSAConverter {
                 word: format!("{}", op),
                 expected: 1,
                 found: 0,
+                location: None,
             })
         }
     }
@@ -1003,6 +1894,7 @@ impl SSAConverter {
             word: "IF".to_string(),
             expected: 1,
             found: 0,
+            location: None,
         })?;
 
         let then_block = self.create_block();
@@ -1026,12 +1918,14 @@ impl SSAConverter {
 
         //  Save original stack before branches
         let original_stack = stack.clone();
+        let original_locals = self.locals.clone();
 
         // Convert then branch
         self.set_current_block(then_block);
         let mut then_stack = original_stack.clone();
         self.convert_sequence(then_branch, &mut then_stack)?;
         let then_final = then_stack.clone();
+        let then_locals = self.path_locals(&original_locals)?;
         // Track which block we're actually in after conversion (may differ from then_block if nested control flow)
         let actual_then_block = self.current_block;
         self.emit(SSAInstruction::Jump {
@@ -1039,19 +1933,21 @@ impl SSAConverter {
         });
 
         // Convert else branch if present, otherwise use original stack
-        let (else_final, actual_else_block) = if let Some(else_words) = else_branch {
+        let (else_final, else_locals, actual_else_block) = if let Some(else_words) = else_branch {
             self.set_current_block(else_block);
             let mut else_stack = original_stack.clone();
             self.convert_sequence(else_words, &mut else_stack)?;
             let result = else_stack.clone();
+            let locals = self.path_locals(&original_locals)?;
             let actual_block = self.current_block;
             self.emit(SSAInstruction::Jump {
                 target: merge_block,
             });
-            (result, actual_block)
+            (result, locals, actual_block)
         } else {
             // No else branch: the false path comes directly from the branch_block
-            (original_stack.clone(), branch_block)
+            let locals = original_locals.iter().map(|(_, register)| *register).collect();
+            (original_stack.clone(), locals, branch_block)
         };
 
         // Verify same stack depth from both branches
@@ -1065,6 +1961,7 @@ impl SSAConverter {
                     then_final.len(),
                     else_final.len()
                 ),
+                location: None,
             });
         }
 
@@ -1074,6 +1971,12 @@ impl SSAConverter {
             "Branch stack depths must match for SSA Phi generation"
         );
 
+        // Locals merge like stack slots below the top
+        let mut then_final = then_final;
+        then_final.extend(then_locals);
+        let mut else_final = else_final;
+        else_final.extend(else_locals);
+
         // Continue from merge block
         self.set_current_block(merge_block);
 
@@ -1108,37 +2011,179 @@ impl SSAConverter {
             "Merged stack must have same size as input branches"
         );
 
+        self.merge_locals(&original_locals, &mut merged_stack);
         *stack = merged_stack;
         Ok(())
     }
 
-    fn convert_begin_until(&mut self, body: &[Word], stack: &mut Vec<Register>) -> Result<()> {
-        let loop_block = self.create_block();
-        let exit_block = self.create_block();
+    /// Convert a CASE on the value on top of the stack to a lookup, or a
+    /// switch to one block per arm
+    fn convert_case(&mut self, chain: &CaseChain, stack: &mut Vec<Register>) -> Result<()> {
+        let selector = stack.pop().ok_or_else(|| ForthError::StackUnderflow {
+            word: "CASE".to_string(),
+            expected: 1,
+            found: 0,
+            location: None,
+        })?;
+        if let Some(table) = chain.table() {
+            let dest = self.fresh_register();
+            self.emit(SSAInstruction::Lookup { dest, index: selector, table });
+            stack.push(dest);
+            return Ok(());
+        }
 
-        self.emit(SSAInstruction::Jump {
-            target: loop_block,
+        let arm_blocks: Vec<BlockId> = chain.arms.iter().map(|_| self.create_block()).collect();
+        let default_block = self.create_block();
+        let merge_block = self.create_block();
+        self.emit(SSAInstruction::Switch {
+            value: selector,
+            cases: chain.arms.iter().map(|arm| arm.key).zip(arm_blocks.iter().copied()).collect(),
+            default: default_block,
         });
 
-        self.set_current_block(loop_block);
-        let mut loop_stack = stack.clone();
-        self.convert_sequence(body, &mut loop_stack)?;
+        // Each arm starts without the selector, the default with it
+        let original_stack = stack.clone();
+        let original_locals = self.locals.clone();
+        let mut finals: Vec<(BlockId, Vec<Register>)> = Vec::new();
+        let mut final_locals = Vec::new();
+        let paths = chain.arms.iter().map(|arm| arm.body).zip(arm_blocks).chain([(chain.default, default_block)]);
+        for (body, block) in paths {
+            self.set_current_block(block);
+            let mut path_stack = original_stack.clone();
+            if block == default_block {
+                path_stack.push(selector);
+            }
+            self.convert_sequence(body, &mut path_stack)?;
+            finals.push((self.current_block, path_stack));
+            final_locals.push(self.path_locals(&original_locals)?);
+            self.emit(SSAInstruction::Jump { target: merge_block });
+        }
+
+        let depth = finals[0].1.len();
+        if let Some((_, other)) = finals.iter().find(|(_, path_stack)| path_stack.len() != depth) {
+            return Err(ForthError::StackMismatch {
+                word: "CASE".to_string(),
+                then_depth: depth,
+                else_depth: other.len(),
+                message: format!("One OF leaves {} items, another path through the CASE leaves {}", depth, other.len()),
+                location: None,
+            });
+        }
+
+        // Merge each stack slot the paths disagree on, and each local
+        for ((_, path_stack), locals) in finals.iter_mut().zip(final_locals) {
+            path_stack.extend(locals);
+        }
+        let depth = depth + original_locals.len();
+        self.set_current_block(merge_block);
+        let mut merged_stack = Vec::with_capacity(depth);
+        for slot in 0..depth {
+            let first = finals[0].1[slot];
+            if finals.iter().all(|(_, path_stack)| path_stack[slot] == first) {
+                merged_stack.push(first);
+            } else {
+                let dest = self.fresh_register();
+                let incoming = finals.iter().map(|(block, path_stack)| (*block, path_stack[slot])).collect();
+                self.emit(SSAInstruction::Phi { dest, incoming });
+                merged_stack.push(dest);
+            }
+        }
+
+        self.merge_locals(&original_locals, &mut merged_stack);
+        *stack = merged_stack;
+        Ok(())
+    }
+
+    /// Start a loop: jump to a new header block whose phis carry each stack
+    /// slot, each local and then `extra` around the loop, and continue in
+    /// it with those phis in place of the values
+    fn open_loop(&mut self, stack: &mut [Register], extra: &[Register]) -> LoopHeader {
+        let header = self.create_block();
+        let entry = self.current_block;
+        self.emit(SSAInstruction::Jump { target: header });
+        self.set_current_block(header);
+
+        let mut carried: Vec<Register> = stack.to_vec();
+        carried.extend(self.locals.iter().map(|(_, register)| *register));
+        carried.extend_from_slice(extra);
+        let phis: Vec<Register> = carried
+            .into_iter()
+            .map(|value| {
+                let dest = self.fresh_register();
+                self.emit(SSAInstruction::Phi { dest, incoming: vec![(entry, value)] });
+                dest
+            })
+            .collect();
+
+        let depth = stack.len();
+        stack.copy_from_slice(&phis[..depth]);
+        for (local, &phi) in self.locals.iter_mut().zip(&phis[depth..]) {
+            local.1 = phi;
+        }
+        LoopHeader { block: header, phis, locals: self.locals.len() }
+    }
+
+    /// Close a loop opened by [`Self::open_loop`] at the end of the current
+    /// block: the stack, the locals and `extra` flow back into its phis.
+    /// The loop has to leave the stack, and the return stack, as deep as it
+    /// found them.
+    fn close_loop(&mut self, header: &LoopHeader, stack: &[Register], extra: &[Register], word: &str) -> Result<()> {
+        if self.locals.len() != header.locals {
+            return Err(ForthError::SSAConversionError {
+                message: format!("Each pass of {} has to leave the return stack as deep as it found it", word),
+                location: Some(self.location.clone()),
+            });
+        }
+        let (phis, header) = (&header.phis[..], header.block);
+        let depth = phis.len() - self.locals.len() - extra.len();
+        if stack.len() != depth {
+            return Err(ForthError::StackMismatch {
+                word: word.to_string(),
+                then_depth: depth,
+                else_depth: stack.len(),
+                message: format!(
+                    "The loop is entered with {} items and repeats with {}; each pass has to leave the stack as deep as it found it",
+                    depth,
+                    stack.len()
+                ),
+                location: Some(self.location.clone()),
+            });
+        }
 
-        let condition = loop_stack.pop().ok_or_else(|| ForthError::StackUnderflow {
+        let latch = self.current_block;
+        let mut values = stack.iter().copied().chain(self.locals.iter().map(|(_, register)| *register)).chain(extra.iter().copied());
+        let block = self.blocks.iter_mut().find(|block| block.id == header).expect("loop header exists");
+        for instruction in &mut block.instructions {
+            if let SSAInstruction::Phi { dest, incoming } = instruction {
+                if phis.contains(dest) {
+                    incoming.push((latch, values.next().expect("one value per phi")));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn convert_begin_until(&mut self, body: &[Word], stack: &mut Vec<Register>) -> Result<()> {
+        let header = self.open_loop(stack, &[]);
+        self.convert_sequence(body, stack)?;
+
+        let condition = stack.pop().ok_or_else(|| ForthError::StackUnderflow {
             word: "UNTIL".to_string(),
             expected: 1,
             found: 0,
+            location: None,
         })?;
+        self.close_loop(&header, stack, &[], "BEGIN-UNTIL")?;
 
+        // The exit block comes after the body, whose values it uses
+        let exit_block = self.create_block();
         self.emit(SSAInstruction::Branch {
             condition,
             true_block: exit_block,
-            false_block: loop_block,
+            false_block: header.block,
         });
 
         self.set_current_block(exit_block);
-        *stack = loop_stack;
-
         Ok(())
     }
 
@@ -1148,85 +2193,146 @@ impl SSAConverter {
         body: &[Word],
         stack: &mut Vec<Register>,
     ) -> Result<()> {
-        let cond_block = self.create_block();
-        let body_block = self.create_block();
-        let exit_block = self.create_block();
-
-        self.emit(SSAInstruction::Jump {
-            target: cond_block,
-        });
+        let header = self.open_loop(stack, &[]);
+        self.convert_sequence(condition, stack)?;
 
-        self.set_current_block(cond_block);
-        let mut cond_stack = stack.clone();
-        self.convert_sequence(condition, &mut cond_stack)?;
-
-        let cond_val = cond_stack.pop().ok_or_else(|| ForthError::StackUnderflow {
+        let cond_val = stack.pop().ok_or_else(|| ForthError::StackUnderflow {
             word: "WHILE".to_string(),
             expected: 1,
             found: 0,
+            location: None,
         })?;
 
+        let body_block = self.create_block();
+        let exit_block = self.create_block();
         self.emit(SSAInstruction::Branch {
             condition: cond_val,
             true_block: body_block,
             false_block: exit_block,
         });
 
+        // The loop leaves by WHILE, with the stack and locals it had there
         self.set_current_block(body_block);
-        let mut body_stack = cond_stack.clone();
+        let mut body_stack = stack.clone();
+        let cond_locals = self.locals.clone();
         self.convert_sequence(body, &mut body_stack)?;
+        self.close_loop(&header, &body_stack, &[], "BEGIN-WHILE-REPEAT")?;
+        self.locals = cond_locals;
         self.emit(SSAInstruction::Jump {
-            target: cond_block,
+            target: header.block,
         });
 
         self.set_current_block(exit_block);
-        *stack = cond_stack;
-
         Ok(())
     }
 
-    fn convert_do_loop(&mut self, body: &[Word], stack: &mut Vec<Register>) -> Result<()> {
+    /// Convert `limit start DO ... LOOP`: the body runs for each index from
+    /// `start` up to `limit`, and at least once, as in Forth. The index is
+    /// carried around the loop with the stack, and is what `I` (and `J`, in
+    /// the loop around it) pushes.
+    fn convert_do_loop(&mut self, body: &[Word], increment: i64, stack: &mut Vec<Register>) -> Result<()> {
         // DO...LOOP requires two values: limit and start
         if stack.len() < 2 {
             return Err(ForthError::StackUnderflow {
                 word: "DO".to_string(),
                 expected: 2,
                 found: stack.len(),
+                location: None,
             });
         }
 
-        stack.pop(); // limit
-        stack.pop(); // start
-
-        let loop_block = self.create_block();
-        let exit_block = self.create_block();
+        let start = stack.pop().unwrap();
+        let limit = stack.pop().unwrap();
 
-        self.emit(SSAInstruction::Jump {
-            target: loop_block,
-        });
+        // A loop that changes the stack depth has no register for each cell
+        // it leaves, unless its passes are known and each is converted
+        if self.pass_changes_depth(body) && self.unroll_do_loop(body, increment, limit, start, stack)? {
+            return Ok(());
+        }
 
-        self.set_current_block(loop_block);
-        let mut loop_stack = stack.clone();
-        self.convert_sequence(body, &mut loop_stack)?;
+        let header = self.open_loop(stack, &[start]);
+        let index = *header.phis.last().unwrap();
+        self.loop_indices.push(index);
+        let converted = self.convert_sequence(body, stack);
+        self.loop_indices.pop();
+        converted?;
+
+        // Step the index, and go round again until it reaches the limit
+        let step = self.fresh_register();
+        self.emit(SSAInstruction::LoadInt { dest: step, value: increment });
+        let next = self.fresh_register();
+        self.emit(SSAInstruction::BinaryOp { dest: next, op: BinaryOperator::Add, left: index, right: step });
+        let again = self.fresh_register();
+        let op = if increment < 0 { BinaryOperator::Ge } else { BinaryOperator::Lt };
+        self.emit(SSAInstruction::BinaryOp { dest: again, op, left: next, right: limit });
+        self.close_loop(&header, stack, &[next], "DO-LOOP")?;
 
-        // TODO: Proper loop counter handling
-        self.emit(SSAInstruction::Jump {
-            target: exit_block,
+        let exit_block = self.create_block();
+        self.emit(SSAInstruction::Branch {
+            condition: again,
+            true_block: header.block,
+            false_block: exit_block,
         });
 
         self.set_current_block(exit_block);
-        *stack = loop_stack;
-
         Ok(())
     }
 
     /// Convert a definition to SSA function
+    ///
+    /// The function returns everything the definition leaves, deepest
+    /// first.
     pub fn convert_definition(&mut self, def: &Definition) -> Result<SSAFunction> {
+        let (mut function, stack) = self.convert_body(def)?;
+
+        // Return whatever the body leaves, which callers were told to expect
+        if let Some(&results) = self.word_results.get(&def.name) {
+            if stack.len() != results {
+                return Err(ForthError::SSAConversionError {
+                    message: format!("'{}' leaves {} items, but calls expect {}", def.name, stack.len(), results),
+                    location: Some(def.location.clone()),
+                });
+            }
+        }
+        self.emit(SSAInstruction::Return {
+            values: SmallVec::from_vec(stack),
+        });
+
+        // Move blocks to function
+        function.blocks = std::mem::take(&mut self.blocks);
+
+        Ok(function)
+    }
+
+    /// Convert top-level code to a `main` returning the top of its stack,
+    /// or 0 if it leaves nothing, as a C entry point returns one value
+    fn convert_main(&mut self, def: &Definition) -> Result<SSAFunction> {
+        let (mut function, mut stack) = self.convert_body(def)?;
+        let top = match stack.pop() {
+            Some(top) => top,
+            None => {
+                let zero = self.fresh_register();
+                self.emit(SSAInstruction::LoadInt { dest: zero, value: 0 });
+                zero
+            }
+        };
+        self.emit(SSAInstruction::Return {
+            values: smallvec::smallvec![top],
+        });
+        function.blocks = std::mem::take(&mut self.blocks);
+        Ok(function)
+    }
+
+    /// Convert the body of a definition, leaving its blocks open for the
+    /// return and giving the registers of the stack it leaves
+    fn convert_body(&mut self, def: &Definition) -> Result<(SSAFunction, Vec<Register>)> {
         // Reset converter state for new function
         self.next_block = 0;
         self.blocks.clear();
         self.current_block = BlockId(0);
         self.current_function_name = Some(def.name.clone());
+        self.location = def.location.clone();
+        self.locals.clear();
 
         // Determine number of parameters from stack effect, or infer from body
         let param_count = if let Some(ref effect) = def.stack_effect {
@@ -1236,7 +2342,7 @@ impl SSAConverter {
             self.infer_parameter_count(&def.body)?
         };
 
-        let mut function = SSAFunction::new(def.name.clone(), param_count);
+        let function = SSAFunction::new(def.name.clone(), param_count);
 
         // Register this function's parameter count for RECURSE support
         self.function_params.insert(def.name.clone(), param_count);
@@ -1252,93 +2358,197 @@ impl SSAConverter {
         let mut stack: Vec<Register> = function.parameters.clone();
 
         // Convert function body
-        self.convert_sequence(&def.body, &mut stack)?;
-
-        // Emit return - ensure we always return at least one value (0 if stack is empty)
-        // This matches Cranelift backend expectation that all Forth functions return i64
-        let return_values = if stack.is_empty() {
-            // Stack is empty - return 0 as default
-            let zero_reg = self.fresh_register();
-            self.emit(SSAInstruction::LoadInt {
-                dest: zero_reg,
-                value: 0,
+        self.convert_sequence(&def.body, &mut stack)
+            .map_err(|e| e.at(&def.location))?;
+
+        Ok((function, stack))
+    }
+
+    /// Convert top-level code to an entry point that spills its data stack
+    ///
+    /// The generated function takes the address of a buffer of at least
+    /// `capacity` cells. On return the buffer holds the final data stack
+    /// (bottom first) and the function returns the stack depth.
+    pub fn convert_entry_point(&mut self, name: &str, body: &[Word], capacity: usize) -> Result<SSAFunction> {
+        self.next_block = 0;
+        self.blocks.clear();
+        self.current_block = BlockId(0);
+        self.current_function_name = Some(name.to_string());
+        self.location = SourceLocation::default();
+        self.locals.clear();
+
+        let mut function = SSAFunction::new(name.to_string(), 1);
+        let buffer = function.parameters[0];
+        self.next_register = 1;
+
+        let entry = self.create_block();
+        self.set_current_block(entry);
+
+        let mut stack: Vec<Register> = Vec::new();
+        self.convert_sequence(body, &mut stack)?;
+
+        if stack.len() > capacity {
+            return Err(ForthError::SSAConversionError {
+                message: format!(
+                    "Top-level code leaves {} values on the stack (limit {})",
+                    stack.len(),
+                    capacity
+                ),
+                location: None,
             });
-            smallvec::smallvec![zero_reg]
-        } else {
-            // Return top of stack (or all values for multi-return functions)
-            SmallVec::from_vec(stack)
-        };
+        }
+
+        for (index, value) in stack.iter().enumerate() {
+            let address = if index == 0 {
+                buffer
+            } else {
+                let offset = self.fresh_register();
+                self.emit(SSAInstruction::LoadInt {
+                    dest: offset,
+                    value: (index * 8) as i64,
+                });
+                let address = self.fresh_register();
+                self.emit(SSAInstruction::BinaryOp {
+                    dest: address,
+                    op: BinaryOperator::Add,
+                    left: buffer,
+                    right: offset,
+                });
+                address
+            };
+            self.emit(SSAInstruction::Store {
+                address,
+                value: *value,
+                ty: StackType::Int,
+                width: MemoryWidth::Cell,
+            });
+        }
 
+        let depth = self.fresh_register();
+        self.emit(SSAInstruction::LoadInt {
+            dest: depth,
+            value: stack.len() as i64,
+        });
         self.emit(SSAInstruction::Return {
-            values: return_values,
+            values: smallvec::smallvec![depth],
         });
 
-        // Move blocks to function
         function.blocks = std::mem::take(&mut self.blocks);
 
         Ok(function)
     }
 
+    /// Record the parameters and results of each of `definitions`, declared
+    /// or inferred
+    fn infer_definitions(&mut self, definitions: &[&Definition]) -> Result<()> {
+        for def in definitions {
+            if self.needs_expansion(&def.body) {
+                self.expanded.insert(def.name.clone(), def.body.clone());
+                continue;
+            }
+            let (param_count, results) = if let Some(ref effect) = def.stack_effect {
+                (effect.inputs.len(), effect.outputs.len())
+            } else {
+                self.infer_effect(&def.body)?
+            };
+            self.function_params.insert(def.name.clone(), param_count);
+            self.word_results.insert(def.name.clone(), results);
+        }
+        Ok(())
+    }
+
+    /// Infer the parameters a body takes and the results it leaves by
+    /// simulating its stack depth
+    fn infer_effect(&self, body: &[Word]) -> Result<(usize, usize)> {
+        let (mut depth, mut min_depth) = (0, 0);
+        self.simulate_depth(body, &mut depth, &mut min_depth);
+        // The minimum depth below 0 tells us how many parameters we need
+        let params = (-min_depth).max(0);
+        Ok((params as usize, (depth + params).max(0) as usize))
+    }
+
     /// Infer the number of parameters needed by simulating stack depth
     fn infer_parameter_count(&self, body: &[Word]) -> Result<usize> {
-        let mut min_depth: i32 = 0;
-        let mut current_depth: i32 = 0;
+        Ok(self.infer_effect(body)?.0)
+    }
 
+    /// Track the stack depth through `body`, lowering `min_depth` to the
+    /// deepest point reached
+    ///
+    /// Both arms of an IF and the body of each loop must leave the stack as
+    /// deep as they found it, as conversion requires, so the depth after one
+    /// is the depth after its first arm.
+    fn simulate_depth(&self, body: &[Word], depth: &mut i32, min_depth: &mut i32) {
+        let pop = |depth: &mut i32, min_depth: &mut i32, count: i32| {
+            *depth -= count;
+            *min_depth = (*min_depth).min(*depth);
+        };
         for word in body {
             match word {
-                Word::IntLiteral(_) | Word::FloatLiteral(_) | Word::StringLiteral(_) => {
-                    current_depth += 1;
+                Word::IntLiteral(_) | Word::FloatLiteral(_) | Word::LocalRef { .. } | Word::Quotation { .. } => {
+                    *depth += 1;
                 }
+                // Address and length
+                Word::StringLiteral(_) => *depth += 2,
                 Word::WordRef { name, .. } => {
-                    // Get stack effect for this word
                     let (consumes, produces) = self.get_word_stack_effect(name);
-                    current_depth -= consumes;
-                    if current_depth < min_depth {
-                        min_depth = current_depth;
-                    }
-                    current_depth += produces;
+                    pop(depth, min_depth, consumes);
+                    *depth += produces;
                 }
-                Word::If { .. } | Word::DoLoop { .. } | Word::BeginUntil { .. } | Word::BeginWhileRepeat { .. } => {
-                    // Control flow consumes condition from stack
-                    // DoLoop consumes limit and index (2 items), others consume 1 (condition)
-                    let consumed = match word {
-                        Word::DoLoop { .. } => 2, // limit index
-                        _ => 1, // condition for IF, UNTIL, WHILE
-                    };
-                    current_depth -= consumed;
-                    if current_depth < min_depth {
-                        min_depth = current_depth;
+                Word::If { then_branch, else_branch } => {
+                    pop(depth, min_depth, 1);
+                    let mut after = *depth;
+                    self.simulate_depth(then_branch, &mut after, min_depth);
+                    if let Some(else_branch) = else_branch {
+                        self.simulate_depth(else_branch, &mut depth.clone(), min_depth);
                     }
+                    *depth = after;
+                }
+                Word::DoLoop { body, .. } => {
+                    // limit index
+                    pop(depth, min_depth, 2);
+                    self.simulate_depth(body, &mut depth.clone(), min_depth);
                 }
-                Word::Variable { .. } => {
-                    // Variable pushes its address
-                    current_depth += 1;
+                Word::BeginUntil { body } => {
+                    self.simulate_depth(body, depth, min_depth);
+                    pop(depth, min_depth, 1);
                 }
-                Word::Constant { .. } => {
-                    // Constant pushes its value
-                    current_depth += 1;
+                Word::BeginWhileRepeat { condition, body } => {
+                    self.simulate_depth(condition, depth, min_depth);
+                    pop(depth, min_depth, 1);
+                    self.simulate_depth(body, &mut depth.clone(), min_depth);
                 }
-                Word::Comment(_) => {
-                    // Comments don't affect stack
+                Word::TaskSpawn { word, .. } => {
+                    pop(depth, min_depth, self.function_params.get(word.as_str()).copied().unwrap_or(0) as i32);
+                    *depth += 1;
                 }
+                Word::Locals { args, .. } => pop(depth, min_depth, args.len() as i32),
+                Word::LocalStore { .. } => pop(depth, min_depth, 1),
+                // Defining a variable doesn't affect the stack
+                Word::Variable { .. } => {}
+                // Constant pushes its value
+                Word::Constant { .. } => *depth += 1,
+                Word::Comment(_) => {}
             }
         }
-
-        // The minimum depth below 0 tells us how many parameters we need
-        Ok((-min_depth).max(0) as usize)
     }
 
     /// Get stack effect for a word (consumes, produces)
     fn get_word_stack_effect(&self, name: &str) -> (i32, i32) {
         match name {
+            // Variables push their address
+            _ if self.variable(name).is_some() => (0, 1),
+
             // Arithmetic (2 in, 1 out)
             "+" | "-" | "*" | "/" | "mod" => (2, 1),
-            "<" | ">" | "<=" | ">=" | "=" | "<>" => (2, 1),
-            "and" | "or" => (2, 1),
+            "<" | ">" | "<=" | ">=" | "=" | "<>" | "u<" | "u>" => (2, 1),
+            "and" | "or" | "xor" | "lshift" | "rshift" | "rol" | "ror" => (2, 1),
+            "um*" | "u/mod" => (2, 2),
 
             // Unary (1 in, 1 out)
-            "negate" | "abs" | "not" => (1, 1),
-            "1+" | "1-" | "2*" | "2/" => (1, 1),
+            "negate" | "abs" | "not" | "invert" => (1, 1),
+            "popcount" | "ctz" | "clz" => (1, 1),
+            "1+" | "1-" | "2*" | "2/" | "cells" | "cell+" | "0=" | "0<>" | "0<" | "0>" => (1, 1),
 
             // Stack manipulation
             "dup" => (1, 2),
@@ -1347,12 +2557,47 @@ impl SSAConverter {
             "over" => (2, 3),
             "rot" => (3, 3),
 
+            // Return stack
+            ">r" => (1, 0),
+            "r>" | "r@" => (0, 1),
+            "i" | "j" if !self.function_params.contains_key(name) => (0, 1),
+
             // Memory
-            "@" => (1, 1),
-            "!" => (2, 0),
+            "@" | "c@" | "w@" | "l@" => (1, 1),
+            "!" | "c!" | "w!" | "l!" => (2, 0),
+
+            // Process environment
+            "arg-count" => (0, 1),
+            "arg@" => (1, 2),
+            "getenv" => (2, 2),
+
+            // Heap
+            "allocate" if !self.function_params.contains_key(name) => (1, 2),
+            "free" if !self.function_params.contains_key(name) => (1, 1),
+            "resize" if !self.function_params.contains_key(name) => (2, 2),
+
+            // Atomics
+            "@atomic" if !self.function_params.contains_key(name) => (1, 1),
+            "!atomic" if !self.function_params.contains_key(name) => (2, 0),
+
+            // Blocks
+            "block" | "buffer" if !self.function_params.contains_key(name) => (1, 1),
+
+            // The token, then what its word takes and leaves
+            "execute" if !self.function_params.contains_key(name) => self
+                .execute_effect
+                .map_or((1, 0), |(inputs, outputs)| (inputs as i32 + 1, outputs as i32)),
+
+            // CODE words declare their effect, as do definitions without outputs
+            _ if self.word_results.contains_key(name) => {
+                (self.function_params[name] as i32, self.word_results[name] as i32)
+            }
 
-            // Default: assume no stack effect for unknown words
-            _ => (0, 0),
+            // I/O words, otherwise assume no stack effect for unknown words
+            _ => runtime_io_function(name)
+                .filter(|_| !self.function_params.contains_key(name))
+                .map(|(_, inputs, outputs)| (inputs as i32, outputs as i32))
+                .unwrap_or((0, 0)),
         }
     }
 }
@@ -1365,25 +2610,7 @@ impl Default for SSAConverter {
 
 /// Convert a program to SSA form
 pub fn convert_to_ssa(program: &Program) -> Result<Vec<SSAFunction>> {
-    let mut converter = SSAConverter::new();
-    let mut functions = Vec::new();
-
-    // First pass: Build map of function names to parameter counts
-    for def in &program.definitions {
-        let param_count = if let Some(ref effect) = def.stack_effect {
-            effect.inputs.len()
-        } else {
-            // Infer parameter count
-            converter.infer_parameter_count(&def.body)?
-        };
-        converter.function_params.insert(def.name.clone(), param_count);
-    }
-
-    // Second pass: Convert all word definitions
-    for def in &program.definitions {
-        let function = converter.convert_definition(def)?;
-        functions.push(function);
-    }
+    let (mut converter, mut functions) = convert_definitions(program)?;
 
     // If there's top-level code, wrap it in an implicit :main function
     if !program.top_level_code.is_empty() {
@@ -1397,16 +2624,89 @@ pub fn convert_to_ssa(program: &Program) -> Result<Vec<SSAFunction>> {
                 inputs: vec![],  // Top-level has no parameters
                 outputs: vec![StackType::Int],  // Returns top of stack
             }),
+            attributes: Attributes::default(),
             location: SourceLocation::default(),
         };
 
-        let main_function = converter.convert_definition(&main_def)?;
+        let main_function = converter.convert_main(&main_def)?;
         functions.push(main_function);
     }
 
     Ok(functions)
 }
 
+/// Convert a program to SSA form with a stack-spilling entry point
+///
+/// Like [`convert_to_ssa`], except that top-level code becomes a `main`
+/// function built by [`SSAConverter::convert_entry_point`], so the caller
+/// can observe the whole data stack rather than just its top.
+pub fn convert_to_ssa_with_stack_buffer(program: &Program, capacity: usize) -> Result<Vec<SSAFunction>> {
+    let (mut converter, mut functions) = convert_definitions(program)?;
+
+    if !program.top_level_code.is_empty() {
+        let main_function = converter.convert_entry_point("main", &program.top_level_code, capacity)?;
+        functions.push(main_function);
+    }
+
+    Ok(functions)
+}
+
+/// Convert all colon definitions of a program
+fn convert_definitions(program: &Program) -> Result<(SSAConverter, Vec<SSAFunction>)> {
+    let mut converter = SSAConverter::new();
+    let definitions: Vec<&Definition> = program.compiled_definitions().collect();
+
+    for word in &program.top_level_code {
+        if let Word::Variable { name, size, .. } = word {
+            converter.variables.insert(name.clone(), *size);
+        }
+    }
+
+    // First pass: Build maps of function names to parameter and result
+    // counts, in order, so each inference sees the effects of the words called
+    for word in &program.code_words {
+        converter.function_params.insert(word.name.clone(), word.stack_effect.inputs.len());
+        converter.word_results.insert(word.name.clone(), word.stack_effect.outputs.len());
+    }
+    converter.infer_definitions(&definitions)?;
+    let definitions: Vec<&Definition> =
+        definitions.into_iter().filter(|def| !converter.expanded.contains_key(&def.name)).collect();
+
+    // EXECUTE on a token kept as a value calls a word with the effect all
+    // such words share, so definitions using it are inferred again
+    let effect_of = |converter: &SSAConverter, name: &str| {
+        (
+            converter.function_params.get(name).copied().unwrap_or(0),
+            converter.word_results.get(name).copied().unwrap_or(0),
+        )
+    };
+    converter.execute_effect = crate::quotation::execute_effect(program, |name| effect_of(&converter, name))?;
+    if converter.execute_effect.is_some() {
+        converter.infer_definitions(&definitions)?;
+        converter.execute_effect = crate::quotation::execute_effect(program, |name| effect_of(&converter, name))?;
+    }
+
+    // Second pass: Convert all word definitions. Definitions only share the
+    // parameter counts gathered above, so with the `parallel` feature large
+    // programs are converted in parallel, each worker with its own copy of
+    // the converter.
+    #[cfg(feature = "parallel")]
+    if definitions.len() >= PARALLEL_CONVERSION_THRESHOLD {
+        let functions = definitions
+            .par_iter()
+            .map_init(|| converter.clone(), |converter, def| converter.convert_definition(def))
+            .collect::<Result<Vec<_>>>()?;
+        return Ok((converter, functions));
+    }
+
+    let functions = definitions
+        .iter()
+        .map(|def| converter.convert_definition(def))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((converter, functions))
+}
+
 impl fmt::Display for SSAFunction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "define {} (", self.name)?;
@@ -1436,6 +2736,7 @@ fn format_instruction(inst: &SSAInstruction) -> String {
         SSAInstruction::LoadString { dest_addr, dest_len, value } => {
             format!("{}, {} = load_string \"{}\"", dest_addr, dest_len, value)
         }
+        SSAInstruction::DataAddress { dest, name, size } => format!("{} = data_address {} [{}]", dest, name, size),
         SSAInstruction::BinaryOp {
             dest,
             op,
@@ -1456,12 +2757,26 @@ fn format_instruction(inst: &SSAInstruction) -> String {
                 .join(", ");
             format!("{} = call {}({})", dest_str, name, args_str)
         }
+        SSAInstruction::FunctionAddress { dest, name } => format!("{} = function_address {}", dest, name),
+        SSAInstruction::CallIndirect { dest, target, args, likely } => {
+            let dest: Vec<String> = dest.iter().map(|r| r.to_string()).collect();
+            let args: Vec<String> = args.iter().map(|r| r.to_string()).collect();
+            let call = format!("{} = call_indirect {}({})", dest.join(", "), target, args.join(", "));
+            match likely {
+                Some(name) => format!("{} likely {}", call, name),
+                None => call,
+            }
+        }
         SSAInstruction::Branch {
             condition,
             true_block,
             false_block,
         } => format!("br {}, {}, {}", condition, true_block, false_block),
         SSAInstruction::Jump { target } => format!("jmp {}", target),
+        SSAInstruction::Switch { value, cases, default } => {
+            let cases: Vec<String> = cases.iter().map(|(key, block)| format!("{} => {}", key, block)).collect();
+            format!("switch {} [{}], {}", value, cases.join(", "), default)
+        }
         SSAInstruction::Return { values } => {
             let vals = values
                 .iter()
@@ -1478,8 +2793,14 @@ fn format_instruction(inst: &SSAInstruction) -> String {
                 .join(", ");
             format!("{} = phi {}", dest, incoming_str)
         }
-        SSAInstruction::Load { dest, address, .. } => format!("{} = load {}", dest, address),
-        SSAInstruction::Store { address, value, .. } => format!("store {}, {}", value, address),
+        SSAInstruction::Load { dest, address, width: MemoryWidth::Cell, .. } => format!("{} = load {}", dest, address),
+        SSAInstruction::Load { dest, address, width, .. } => format!("{} = load.{} {}", dest, width, address),
+        SSAInstruction::Lookup { dest, index, table } => {
+            let values: Vec<String> = table.values.iter().map(|value| value.to_string()).collect();
+            format!("{} = lookup {} - {} [{}], {}", dest, index, table.base, values.join(", "), table.default)
+        }
+        SSAInstruction::Store { address, value, width: MemoryWidth::Cell, .. } => format!("store {}, {}", value, address),
+        SSAInstruction::Store { address, value, width, .. } => format!("store.{} {}, {}", width, value, address),
 
         // FFI and File I/O formatting
         SSAInstruction::FFICall { dest, function, args } => {
@@ -1516,6 +2837,32 @@ fn format_instruction(inst: &SSAInstruction) -> String {
         SSAInstruction::SystemCall { dest, command_addr, command_len } => {
             format!("{} = system {}, {}", dest, command_addr, command_len)
         }
+        SSAInstruction::ArgCount { dest } => format!("{} = arg_count", dest),
+        SSAInstruction::ArgFetch { dest_addr, dest_len, index } => {
+            format!("{}, {} = arg_fetch {}", dest_addr, dest_len, index)
+        }
+        SSAInstruction::GetEnv { dest_addr, dest_len, name_addr, name_len } => {
+            format!("{}, {} = getenv {}, {}", dest_addr, dest_len, name_addr, name_len)
+        }
+        SSAInstruction::HeapAllocate { dest_addr, dest_ior, size } => {
+            format!("{}, {} = allocate {}", dest_addr, dest_ior, size)
+        }
+        SSAInstruction::HeapFree { dest_ior, addr } => format!("{} = free {}", dest_ior, addr),
+        SSAInstruction::HeapResize { dest_addr, dest_ior, addr, size } => {
+            format!("{}, {} = resize {}, {}", dest_addr, dest_ior, addr, size)
+        }
+        SSAInstruction::TaskSpawn { dest, word, args } => {
+            let args: Vec<String> = args.iter().map(|r| r.to_string()).collect();
+            format!("{} = task: {}({})", dest, word, args.join(", "))
+        }
+        SSAInstruction::AtomicLoad { dest, address } => format!("{} = @atomic {}", dest, address),
+        SSAInstruction::AtomicStore { address, value } => format!("!atomic {}, {}", address, value),
+        SSAInstruction::BlockFetch { dest, block } => format!("{} = block {}", dest, block),
+        SSAInstruction::BlockAssign { dest, block } => format!("{} = buffer {}", dest, block),
+        SSAInstruction::BlockUpdate => "update".to_string(),
+        SSAInstruction::BlockSave => "save_buffers".to_string(),
+        SSAInstruction::BlockEmpty => "empty_buffers".to_string(),
+        SSAInstruction::BlockFlush => "flush".to_string(),
     }
 }
 
@@ -1543,6 +2890,22 @@ mod tests {
         assert!(!func.blocks.is_empty());
     }
 
+    #[test]
+    fn test_parallel_conversion_matches_serial() {
+        let source: String = (0..PARALLEL_CONVERSION_THRESHOLD * 2)
+            .map(|i| format!(": w{} dup {} + swap drop ;\n", i, i))
+            .collect();
+        let program = parse_program(&source).unwrap();
+        let functions = convert_to_ssa(&program).unwrap();
+
+        let mut converter = SSAConverter::new();
+        for (def, function) in program.definitions.iter().zip(&functions) {
+            let serial = converter.convert_definition(def).unwrap();
+            assert_eq!(function.to_string(), serial.to_string());
+        }
+        assert_eq!(functions.len(), program.definitions.len());
+    }
+
     #[test]
     fn test_ssa_display() {
         let program = parse_program(": add-one ( n -- n+1 ) 1 + ;").unwrap();
@@ -1552,6 +2915,49 @@ mod tests {
         assert!(output.contains("define add-one"));
     }
 
+    #[test]
+    fn test_entry_point_spills_stack() {
+        let program = parse_program("1 2 3").unwrap();
+        let functions = convert_to_ssa_with_stack_buffer(&program, 16).unwrap();
+
+        assert_eq!(functions.len(), 1);
+        let main = &functions[0];
+        assert_eq!(main.name, "main");
+        assert_eq!(main.parameters.len(), 1);
+
+        let stores = main.blocks[0]
+            .instructions
+            .iter()
+            .filter(|inst| matches!(inst, SSAInstruction::Store { .. }))
+            .count();
+        assert_eq!(stores, 3);
+    }
+
+    #[test]
+    fn test_sized_memory_access() {
+        let program = parse_program(": copy ( src dst -- ) swap dup c@ swap w@ + swap l! ;").unwrap();
+        let functions = convert_to_ssa(&program).unwrap();
+        let widths: Vec<MemoryWidth> = functions[0].blocks[0]
+            .instructions
+            .iter()
+            .filter_map(|inst| match inst {
+                SSAInstruction::Load { width, .. } | SSAInstruction::Store { width, .. } => Some(*width),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(widths, [MemoryWidth::W8, MemoryWidth::W16, MemoryWidth::W32]);
+
+        let output = format!("{}", functions[0]);
+        assert!(output.contains("load.8"));
+        assert!(output.contains("store.32"));
+    }
+
+    #[test]
+    fn test_entry_point_capacity_exceeded() {
+        let program = parse_program("1 2 3").unwrap();
+        assert!(convert_to_ssa_with_stack_buffer(&program, 2).is_err());
+    }
+
     #[test]
     fn test_stack_underflow_detection() {
         // Test that stack underflow is detected during SSA conversion
@@ -1559,10 +2965,11 @@ mod tests {
         let program = parse_program(": underflow ( -- ) dup + ;").unwrap();
         let result = convert_to_ssa(&program);
         assert!(result.is_err(), "Expected stack underflow error");
-        if let Err(ForthError::StackUnderflow { word, expected, found }) = result {
+        if let Err(ForthError::StackUnderflow { word, expected, found, location }) = result {
             assert_eq!(word, "dup");
             assert_eq!(expected, 1);
             assert_eq!(found, 0);
+            assert_eq!(location, Some(SourceLocation { line: 1, column: 20 }));
         } else {
             panic!("Expected StackUnderflow error, got: {:?}", result);
         }
@@ -1617,7 +3024,7 @@ mod tests {
             ": nested-loops ( -- )
                 10 0 DO
                     5 0 DO
-                        i j +
+                        i j + drop
                     LOOP
                 LOOP
             ;"
@@ -1643,6 +3050,23 @@ mod tests {
         assert!(has_load_string, "Expected LoadString instruction for string literal");
     }
 
+    #[test]
+    fn test_variables_are_data_addresses() {
+        let program = parse_program("variable count create buf 3 cells allot : bump count @ 1+ count ! ; buf").unwrap();
+        let functions = convert_to_ssa(&program).unwrap();
+        let data: Vec<Vec<(&str, usize)>> = functions
+            .iter()
+            .map(|func| {
+                func.blocks[0].instructions.iter().filter_map(|inst| match inst {
+                    SSAInstruction::DataAddress { name, size, .. } => Some((name.as_str(), *size)),
+                    _ => None,
+                }).collect()
+            })
+            .collect();
+        assert_eq!(data, [vec![("count", 8), ("count", 8)], vec![("buf", 24)]]);
+        assert_eq!(functions[0].parameters.len(), 0);
+    }
+
     #[test]
     fn test_stack_manipulation_ssa() {
         // Test complex stack manipulation (dup, swap, over, rot)
@@ -1671,9 +3095,9 @@ mod tests {
         // Test file I/O operations generate correct SSA
         // This Forth uses double quotes, not S"
         let program = parse_program(
-            r#": test-file ( -- )
+            r#": test-file ( -- ior )
                 " test.txt " r/o open-file
-                close-file
+                drop close-file
             ;"#
         ).unwrap();
         let functions = convert_to_ssa(&program).unwrap();
@@ -1687,6 +3111,77 @@ mod tests {
         assert!(has_file_ops, "Expected file I/O instructions");
     }
 
+    #[test]
+    fn test_process_environment_ssa() {
+        let program = parse_program(
+            r#": env-test ( -- n a u a u )
+                arg-count 1 arg@ "HOME" getenv
+            ;"#
+        ).unwrap();
+        let functions = convert_to_ssa(&program).unwrap();
+
+        let insts = &functions[0].blocks[0].instructions;
+        assert!(insts.iter().any(|inst| matches!(inst, SSAInstruction::ArgCount { .. })));
+        assert!(insts.iter().any(|inst| matches!(inst, SSAInstruction::ArgFetch { .. })));
+        assert!(insts.iter().any(|inst| matches!(inst, SSAInstruction::GetEnv { .. })));
+        match insts.last() {
+            Some(SSAInstruction::Return { values }) => assert_eq!(values.len(), 5),
+            other => panic!("expected return, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_io_words_call_the_runtime() {
+        let program = parse_program(": show ( n -- ) 0 <# #s #> type cr ;").unwrap();
+        let functions = convert_to_ssa(&program).unwrap();
+
+        let calls: Vec<&str> = functions[0].blocks[0].instructions.iter().filter_map(|inst| match inst {
+            SSAInstruction::FFICall { function, .. } => Some(function.as_str()),
+            _ => None,
+        }).collect();
+        assert_eq!(calls, ["forth_pict_begin", "forth_pict_digits", "forth_pict_end", "forth_io_type", "forth_io_cr"]);
+
+        // A redefinition wins over the runtime word
+        let program = parse_program(": sign ( n -- n ) 1 + ; : f ( n -- n ) sign ;").unwrap();
+        let functions = convert_to_ssa(&program).unwrap();
+        let f = functions.iter().find(|func| func.name == "f").unwrap();
+        assert!(f.blocks[0].instructions.iter().any(|inst| matches!(inst, SSAInstruction::Call { name, .. } if name == "sign")));
+    }
+
+    #[test]
+    fn test_block_words_ssa() {
+        let program = parse_program(": touch ( u -- ) block drop update flush ;").unwrap();
+        let functions = convert_to_ssa(&program).unwrap();
+
+        let insts = &functions[0].blocks[0].instructions;
+        assert!(matches!(insts[0], SSAInstruction::BlockFetch { .. }));
+        assert!(matches!(insts[1], SSAInstruction::BlockUpdate));
+        assert!(matches!(insts[2], SSAInstruction::BlockFlush));
+
+        // User definitions named like block words are called as usual
+        let program = parse_program(": flush ( -- ) ; : f ( -- ) flush ;").unwrap();
+        let functions = convert_to_ssa(&program).unwrap();
+        let f = functions.iter().find(|func| func.name == "f").unwrap();
+        assert!(!f.blocks[0].instructions.iter().any(|inst| matches!(inst, SSAInstruction::BlockFlush)));
+    }
+
+    #[test]
+    fn test_task_spawn_ssa() {
+        let program = parse_program(": add ( a b -- n ) + ; : run ( -- n ) 1 2 task: add join ;").unwrap();
+        let functions = convert_to_ssa(&program).unwrap();
+
+        let run = functions.iter().find(|func| func.name == "run").unwrap();
+        let insts = &run.blocks[0].instructions;
+        assert!(matches!(&insts[2], SSAInstruction::TaskSpawn { word, args, .. } if word == "add" && args.len() == 2));
+        assert!(matches!(&insts[3], SSAInstruction::FFICall { function, .. } if function == "forth_task_join"));
+
+        // Only compiled words taking up to four cells run as tasks
+        let program = parse_program(": sum5 ( a b c d e -- n ) + + + + ; 1 2 3 4 5 task: sum5").unwrap();
+        let error = convert_to_ssa(&program).unwrap_err().to_string();
+        assert!(error.contains("up to 4 cells"), "{}", error);
+        assert!(convert_to_ssa(&parse_program("task: dup").unwrap()).is_err());
+    }
+
     #[test]
     fn test_begin_while_repeat_ssa() {
         // Test BEGIN-WHILE-REPEAT loop structure
@@ -1736,6 +3231,19 @@ mod tests {
         assert!(func.parameters.len() >= 2, "Should infer at least 2 parameters");
     }
 
+    #[test]
+    fn test_code_word_calls_use_declared_effect() {
+        let program = parse_program("code discard ( x -- ) ret end-code : f ( a b -- a ) discard ;").unwrap();
+        let functions = convert_to_ssa(&program).unwrap();
+        let func = functions.iter().find(|func| func.name == "f").unwrap();
+
+        let call = func.blocks.iter().flat_map(|block| &block.instructions).find_map(|inst| match inst {
+            SSAInstruction::Call { dest, name, args } if name == "discard" => Some((dest.len(), args.len())),
+            _ => None,
+        });
+        assert_eq!(call, Some((0, 1)));
+    }
+
     #[test]
     fn test_recurse_generates_self_call() {
         // Test that RECURSE generates a Call instruction to the current function
@@ -1760,4 +3268,91 @@ mod tests {
         });
         assert!(has_self_call, "RECURSE should generate a self-call to 'factorial'");
     }
+
+    #[test]
+    fn test_dense_case_becomes_switch_or_lookup() {
+        let program = parse_program(
+            ": op ( a n -- n ) case 0 of 1 + endof 1 of 2 * endof 2 of dup * endof endcase ;
+             : pick ( n -- n ) case 1 of 10 endof 2 of 20 endof 3 of 30 endof 0 swap endcase ;
+             : sparse ( n -- n ) case 1 of 10 endof 50 of 20 endof 99 of 30 endof 0 swap endcase ;",
+        )
+        .unwrap();
+        let functions = convert_to_ssa(&program).unwrap();
+        let instructions = |name: &str| -> Vec<SSAInstruction> {
+            let func = functions.iter().find(|func| func.name == name).unwrap();
+            func.validate().unwrap();
+            func.blocks.iter().flat_map(|block| block.instructions.clone()).collect()
+        };
+
+        let op = instructions("op");
+        let Some(SSAInstruction::Switch { cases, .. }) = op.iter().find(|inst| matches!(inst, SSAInstruction::Switch { .. }))
+        else {
+            panic!("no switch in {:?}", op)
+        };
+        assert_eq!(cases.iter().map(|(key, _)| *key).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert!(!op.iter().any(|inst| matches!(inst, SSAInstruction::Branch { .. })));
+        assert!(op.iter().any(|inst| matches!(inst, SSAInstruction::Phi { incoming, .. } if incoming.len() == 4)));
+
+        let pick = instructions("pick");
+        assert!(pick.iter().any(|inst| matches!(inst, SSAInstruction::Lookup { table, .. } if table.values == [10, 20, 30])));
+        assert_eq!(pick.len(), 2, "the lookup and the return: {:?}", pick);
+
+        let sparse = instructions("sparse");
+        assert!(sparse.iter().all(|inst| !matches!(inst, SSAInstruction::Switch { .. } | SSAInstruction::Lookup { .. })));
+
+        // The arms of a CASE must agree on the stack
+        let program = parse_program(": bad ( n -- n ) case 1 of 10 endof 2 of endof 3 of 30 endof 0 swap endcase ;").unwrap();
+        assert!(matches!(convert_to_ssa(&program), Err(ForthError::StackMismatch { .. })));
+    }
+
+    #[test]
+    fn test_locals_are_registers() {
+        let program = parse_program(": f ( a b -- n ) {: a b :} b a - ;").unwrap();
+        let function = &convert_to_ssa(&program).unwrap()[0];
+        let body = function.to_string();
+        assert!(!body.contains("load") && !body.contains("store"), "{}", body);
+
+        // A local set on one path of an IF merges like a stack value
+        let program = parse_program(": g ( n -- n ) {: n | r :} n 0< if 1 to r then r ;").unwrap();
+        let function = &convert_to_ssa(&program).unwrap()[0];
+        assert!(function.to_string().contains("phi"), "{}", function);
+    }
+
+    #[test]
+    fn test_return_stack_is_registers() {
+        let program = parse_program(": f ( a b -- b a ) >r >r r@ drop r> r> ;").unwrap();
+        let function = &convert_to_ssa(&program).unwrap()[0];
+        assert!(!function.to_string().contains("call"), "{}", function);
+
+        // A counter kept on the return stack is carried around a loop
+        let program = parse_program(": g ( n -- n ) 3 >r begin 2 * r> 1- dup >r 0= until r> drop ;").unwrap();
+        let function = &convert_to_ssa(&program).unwrap()[0];
+        assert!(!function.to_string().contains("call"), "{}", function);
+
+        for source in [
+            ": h ( n -- n ) r> ;",
+            ": h ( n -- ) begin >r 0 until ;",
+            ": h ( n f -- n ) swap >r if r> else r> then ;",
+        ] {
+            let program = parse_program(source).unwrap();
+            let error = convert_to_ssa(&program).unwrap_err().to_string();
+            assert!(error.contains("return stack"), "{}: {}", source, error);
+        }
+    }
+
+    #[test]
+    fn test_depth_changing_loops_are_expanded() {
+        let program = parse_program(": squares ( n -- ... ) 0 do i i * loop ; 3 squares").unwrap();
+        let functions = convert_to_ssa_with_stack_buffer(&program, 8).unwrap();
+        assert_eq!(functions.len(), 1, "squares is expanded into main");
+        let main = functions[0].to_string();
+        assert!(main.contains("ret %") && !main.contains("phi") && !main.contains("call"), "{}", main);
+
+        // Without constant bounds the passes are unknown
+        let program = parse_program(": squares ( n -- ... ) 0 do i i * loop ; : f ( n -- ... ) squares ; 3 f").unwrap();
+        assert!(convert_to_ssa_with_stack_buffer(&program, 8).is_ok());
+        let program = parse_program(": squares ( n -- ... ) 0 do i i * loop ; 3 1 + squares").unwrap();
+        let error = convert_to_ssa_with_stack_buffer(&program, 8).unwrap_err().to_string();
+        assert!(error.contains("as deep as it found it"), "{}", error);
+    }
 }
@@ -149,6 +149,12 @@ impl<'a> SSAValidator<'a> {
                         self.successors.entry(block.id).or_default().push(*target);
                         self.predecessors.entry(*target).or_default().push(block.id);
                     }
+                    SSAInstruction::Switch { cases, default, .. } => {
+                        for &target in cases.iter().map(|(_, target)| target).chain([default]) {
+                            self.successors.entry(block.id).or_default().push(target);
+                            self.predecessors.entry(target).or_default().push(block.id);
+                        }
+                    }
                     SSAInstruction::Return { .. } => {
                         // Return has no successors
                     }
@@ -166,9 +172,9 @@ impl<'a> SSAValidator<'a> {
         for block in &self.function.blocks {
             let mut block_defs = HashSet::new();
 
-            for inst in &block.instructions {
+            for (index, inst) in block.instructions.iter().enumerate() {
                 // Collect all destination registers from this instruction
-                let dests = self.get_destination_registers(inst);
+                let dests = Self::get_destination_registers(inst);
 
                 for dest in dests {
                     // Check if this register was already defined
@@ -178,6 +184,7 @@ impl<'a> SSAValidator<'a> {
                                 "Register {} assigned multiple times (violation of SSA form)",
                                 dest
                             ),
+                            location: block.location(index).cloned(),
                         });
                     }
 
@@ -206,7 +213,7 @@ impl<'a> SSAValidator<'a> {
 
             for inst in &block.instructions {
                 // First, check all uses in this instruction
-                let uses = self.get_used_registers(inst);
+                let uses = Self::get_used_registers(inst);
 
                 for used_reg in &uses {
                     // Skip Phi nodes - they're special (values come from predecessors)
@@ -223,7 +230,7 @@ impl<'a> SSAValidator<'a> {
                 }
 
                 // Then, add any definitions from this instruction
-                let defs = self.get_destination_registers(inst);
+                let defs = Self::get_destination_registers(inst);
                 for def in defs {
                     defined_in_block.insert(def);
                 }
@@ -264,6 +271,7 @@ impl<'a> SSAValidator<'a> {
                         "Unreachable block {} detected (not connected to entry block {})",
                         block.id, self.function.entry_block
                     ),
+                    location: None,
                 });
             }
         }
@@ -365,13 +373,13 @@ impl<'a> SSAValidator<'a> {
     /// Check 4: All uses are dominated by their definitions
     fn check_dominance(&self) -> Result<()> {
         for block in &self.function.blocks {
-            for inst in &block.instructions {
+            for (index, inst) in block.instructions.iter().enumerate() {
                 // Skip Phi nodes - they have special semantics
                 if matches!(inst, SSAInstruction::Phi { .. }) {
                     continue;
                 }
 
-                let uses = self.get_used_registers(inst);
+                let uses = Self::get_used_registers(inst);
 
                 for used_reg in uses {
                     // Find where this register is defined
@@ -385,6 +393,7 @@ impl<'a> SSAValidator<'a> {
                                     "Register {} used in block {} but defined in non-dominating block {}",
                                     used_reg, block.id, def_block_id
                                 ),
+                                location: block.location(index).cloned(),
                             });
                         }
                     } else if !self.function.parameters.contains(&used_reg) {
@@ -394,6 +403,7 @@ impl<'a> SSAValidator<'a> {
                                 "Register {} used in block {} but never defined",
                                 used_reg, block.id
                             ),
+                            location: block.location(index).cloned(),
                         });
                     }
                 }
@@ -408,7 +418,7 @@ impl<'a> SSAValidator<'a> {
         for block in &self.function.blocks {
             let mut seen_non_phi = false;
 
-            for inst in &block.instructions {
+            for (index, inst) in block.instructions.iter().enumerate() {
                 match inst {
                     SSAInstruction::Phi { dest, incoming } => {
                         // Phi nodes must be at the start of the block
@@ -418,6 +428,7 @@ impl<'a> SSAValidator<'a> {
                                     "Phi node for {} not at start of block {}",
                                     dest, block.id
                                 ),
+                                location: block.location(index).cloned(),
                             });
                         }
 
@@ -433,6 +444,7 @@ impl<'a> SSAValidator<'a> {
                                         "Phi node for {} in block {} missing incoming value from predecessor {}",
                                         dest, block.id, pred
                                     ),
+                                    location: block.location(index).cloned(),
                                 });
                             }
                         }
@@ -445,6 +457,7 @@ impl<'a> SSAValidator<'a> {
                                         "Phi node for {} in block {} has incoming value from non-predecessor {}",
                                         dest, block.id, phi_pred
                                     ),
+                                    location: block.location(index).cloned(),
                                 });
                             }
                         }
@@ -480,7 +493,7 @@ impl<'a> SSAValidator<'a> {
 
                 if phi_count > 0 {
                     // Verify that each Phi has the correct number of incoming edges
-                    for inst in &block.instructions {
+                    for (index, inst) in block.instructions.iter().enumerate() {
                         if let SSAInstruction::Phi { dest, incoming } = inst {
                             if incoming.len() != preds.len() {
                                 return Err(ForthError::SSAConversionError {
@@ -488,6 +501,7 @@ impl<'a> SSAValidator<'a> {
                                         "Type consistency error: Phi node for {} in block {} has {} incoming values but {} predecessors",
                                         dest, block.id, incoming.len(), preds.len()
                                     ),
+                                    location: block.location(index).cloned(),
                                 });
                             }
                         }
@@ -523,16 +537,20 @@ impl<'a> SSAValidator<'a> {
     }
 
     /// Helper: Extract all destination registers from an instruction
-    fn get_destination_registers(&self, inst: &SSAInstruction) -> Vec<Register> {
+    pub(crate) fn get_destination_registers(inst: &SSAInstruction) -> Vec<Register> {
         match inst {
             SSAInstruction::LoadInt { dest, .. } => vec![*dest],
             SSAInstruction::LoadFloat { dest, .. } => vec![*dest],
             SSAInstruction::LoadString { dest_addr, dest_len, .. } => vec![*dest_addr, *dest_len],
+            SSAInstruction::DataAddress { dest, .. } => vec![*dest],
             SSAInstruction::BinaryOp { dest, .. } => vec![*dest],
             SSAInstruction::UnaryOp { dest, .. } => vec![*dest],
             SSAInstruction::Call { dest, .. } => dest.to_vec(),
+            SSAInstruction::FunctionAddress { dest, .. } => vec![*dest],
+            SSAInstruction::CallIndirect { dest, .. } => dest.to_vec(),
             SSAInstruction::Phi { dest, .. } => vec![*dest],
             SSAInstruction::Load { dest, .. } => vec![*dest],
+            SSAInstruction::Lookup { dest, .. } => vec![*dest],
             SSAInstruction::FFICall { dest, .. } => dest.to_vec(),
             SSAInstruction::FileOpen { dest_fileid, dest_ior, .. } => vec![*dest_fileid, *dest_ior],
             SSAInstruction::FileRead { dest_bytes, dest_ior, .. } => vec![*dest_bytes, *dest_ior],
@@ -541,29 +559,50 @@ impl<'a> SSAValidator<'a> {
             SSAInstruction::FileDelete { dest_ior, .. } => vec![*dest_ior],
             SSAInstruction::FileCreate { dest_fileid, dest_ior, .. } => vec![*dest_fileid, *dest_ior],
             SSAInstruction::SystemCall { dest, .. } => vec![*dest],
+            SSAInstruction::ArgCount { dest } => vec![*dest],
+            SSAInstruction::ArgFetch { dest_addr, dest_len, .. } => vec![*dest_addr, *dest_len],
+            SSAInstruction::GetEnv { dest_addr, dest_len, .. } => vec![*dest_addr, *dest_len],
+            SSAInstruction::HeapAllocate { dest_addr, dest_ior, .. } => vec![*dest_addr, *dest_ior],
+            SSAInstruction::HeapFree { dest_ior, .. } => vec![*dest_ior],
+            SSAInstruction::HeapResize { dest_addr, dest_ior, .. } => vec![*dest_addr, *dest_ior],
+            SSAInstruction::TaskSpawn { dest, .. } => vec![*dest],
+            SSAInstruction::AtomicLoad { dest, .. } => vec![*dest],
+            SSAInstruction::AtomicStore { .. } => vec![],
+            SSAInstruction::BlockFetch { dest, .. } => vec![*dest],
+            SSAInstruction::BlockAssign { dest, .. } => vec![*dest],
+            SSAInstruction::BlockUpdate
+            | SSAInstruction::BlockSave
+            | SSAInstruction::BlockEmpty
+            | SSAInstruction::BlockFlush => vec![],
             SSAInstruction::Branch { .. } => vec![],
             SSAInstruction::Jump { .. } => vec![],
+            SSAInstruction::Switch { .. } => vec![],
             SSAInstruction::Return { .. } => vec![],
             SSAInstruction::Store { .. } => vec![],
         }
     }
 
     /// Helper: Extract all used registers from an instruction
-    fn get_used_registers(&self, inst: &SSAInstruction) -> Vec<Register> {
+    pub(crate) fn get_used_registers(inst: &SSAInstruction) -> Vec<Register> {
         match inst {
             SSAInstruction::LoadInt { .. } => vec![],
             SSAInstruction::LoadFloat { .. } => vec![],
             SSAInstruction::LoadString { .. } => vec![],
+            SSAInstruction::DataAddress { .. } => vec![],
             SSAInstruction::BinaryOp { left, right, .. } => vec![*left, *right],
             SSAInstruction::UnaryOp { operand, .. } => vec![*operand],
             SSAInstruction::Call { args, .. } => args.to_vec(),
+            SSAInstruction::FunctionAddress { .. } => vec![],
+            SSAInstruction::CallIndirect { target, args, .. } => args.iter().copied().chain([*target]).collect(),
             SSAInstruction::Branch { condition, .. } => vec![*condition],
             SSAInstruction::Jump { .. } => vec![],
+            SSAInstruction::Switch { value, .. } => vec![*value],
             SSAInstruction::Return { values } => values.to_vec(),
             SSAInstruction::Phi { incoming, .. } => {
                 incoming.iter().map(|(_, reg)| *reg).collect()
             }
             SSAInstruction::Load { address, .. } => vec![*address],
+            SSAInstruction::Lookup { index, .. } => vec![*index],
             SSAInstruction::Store { address, value, .. } => vec![*address, *value],
             SSAInstruction::FFICall { args, .. } => args.to_vec(),
             SSAInstruction::FileOpen { path_addr, path_len, mode, .. } => {
@@ -585,6 +624,21 @@ impl<'a> SSAValidator<'a> {
             SSAInstruction::SystemCall { command_addr, command_len, .. } => {
                 vec![*command_addr, *command_len]
             }
+            SSAInstruction::ArgCount { .. } => vec![],
+            SSAInstruction::ArgFetch { index, .. } => vec![*index],
+            SSAInstruction::GetEnv { name_addr, name_len, .. } => vec![*name_addr, *name_len],
+            SSAInstruction::HeapAllocate { size, .. } => vec![*size],
+            SSAInstruction::HeapFree { addr, .. } => vec![*addr],
+            SSAInstruction::HeapResize { addr, size, .. } => vec![*addr, *size],
+            SSAInstruction::TaskSpawn { args, .. } => args.to_vec(),
+            SSAInstruction::AtomicLoad { address, .. } => vec![*address],
+            SSAInstruction::AtomicStore { address, value } => vec![*address, *value],
+            SSAInstruction::BlockFetch { block, .. } => vec![*block],
+            SSAInstruction::BlockAssign { block, .. } => vec![*block],
+            SSAInstruction::BlockUpdate
+            | SSAInstruction::BlockSave
+            | SSAInstruction::BlockEmpty
+            | SSAInstruction::BlockFlush => vec![],
         }
     }
 }
@@ -0,0 +1,501 @@
+//! Stack Depth Bounds
+//!
+//! Worst-case data- and return-stack depth of each word and of the whole
+//! program, for sizing fixed stacks before deployment (`fastforth check
+//! --stack-bounds`). Depths are counted in cells. A word's data bound
+//! includes its inputs; its return bound includes its own return address,
+//! one cell for each word it calls while running, `>r` cells and two cells
+//! for each `DO` loop it is inside.
+//!
+//! A recursive word has no bound unless marked `\ @recursion(N)`, which
+//! promises at most `N` nested activations, and declares its stack effect:
+//! each activation past the first is counted at the deepest point any
+//! recursive call is made from. Mutual recursion is never bounded. Loops
+//! must leave both stacks as they found them, and words the analysis does
+//! not know make the bounds of their callers unknown.
+
+use crate::ast::{Definition, Program, SourceLocation, Word};
+use crate::stack_effects::StackEffectInference;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Worst-case depth of one stack
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bound {
+    /// At most this many cells
+    Cells(usize),
+    /// No bound could be found, and why
+    Unbounded(String),
+}
+
+impl Bound {
+    fn from_depth(depth: &Depth) -> Self {
+        match depth {
+            Ok(span) => Bound::Cells((span.peak - span.low.min(0)) as usize),
+            Err(reason) => Bound::Unbounded(reason.clone()),
+        }
+    }
+
+    /// The larger of two bounds; unbounded wins
+    pub fn max(self, other: Bound) -> Bound {
+        match (self, other) {
+            (Bound::Cells(a), Bound::Cells(b)) => Bound::Cells(a.max(b)),
+            (Bound::Unbounded(reason), _) | (_, Bound::Unbounded(reason)) => Bound::Unbounded(reason),
+        }
+    }
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bound::Cells(cells) => write!(f, "{}", cells),
+            Bound::Unbounded(reason) => write!(f, "unbounded ({})", reason),
+        }
+    }
+}
+
+/// Worst-case stack use of one word
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordBounds {
+    pub name: String,
+    /// Cells the word takes from the data stack, if known
+    pub inputs: Option<usize>,
+    pub data: Bound,
+    pub returns: Bound,
+    /// The word can call itself, directly or through other words
+    pub recursive: bool,
+    pub location: SourceLocation,
+}
+
+/// Worst-case stack use of a program
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackBounds {
+    /// Every definition, in source order
+    pub words: Vec<WordBounds>,
+    /// The program run from empty stacks: its top-level code, or for a
+    /// program without any, the deepest word
+    pub data: Bound,
+    pub returns: Bound,
+}
+
+/// Compute the stack bounds of every word and of the program
+pub fn stack_bounds(program: &Program) -> StackBounds {
+    let mut analysis = Analysis::new(program);
+    for index in 0..program.definitions.len() {
+        analysis.definition(index);
+    }
+
+    let words: Vec<WordBounds> = program
+        .definitions
+        .iter()
+        .enumerate()
+        .map(|(index, def)| {
+            let usage = &analysis.done[&index];
+            WordBounds {
+                name: def.name.clone(),
+                inputs: usage.data.as_ref().ok().map(|span| (-span.low).max(0) as usize),
+                data: Bound::from_depth(&usage.data),
+                returns: Bound::from_depth(&usage.returns),
+                recursive: analysis.recursive[index],
+                location: def.location.clone(),
+            }
+        })
+        .collect();
+
+    let (data, returns) = if program.top_level_code.is_empty() {
+        words.iter().fold((Bound::Cells(0), Bound::Cells(0)), |(data, returns), word| {
+            (data.max(word.data.clone()), returns.max(word.returns.clone()))
+        })
+    } else {
+        let usage = Walker::new(&mut analysis, None).sequence(&program.top_level_code, (0, 0));
+        (Bound::from_depth(&usage.data), Bound::from_depth(&usage.returns))
+    };
+    StackBounds { words, data, returns }
+}
+
+/// Depth use of some code on one stack, relative to the depth it starts at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Span {
+    /// Change in depth from start to end
+    net: i64,
+    /// Lowest depth reached, zero or below
+    low: i64,
+    /// Highest depth reached, zero or above
+    peak: i64,
+}
+
+impl Span {
+    const NONE: Span = Span { net: 0, low: 0, peak: 0 };
+
+    /// Take `inputs` cells and leave `outputs`
+    fn effect(inputs: usize, outputs: usize) -> Span {
+        let net = outputs as i64 - inputs as i64;
+        Span { net, low: -(inputs as i64), peak: net.max(0) }
+    }
+
+    fn then(self, next: Span) -> Span {
+        Span {
+            net: self.net + next.net,
+            low: self.low.min(self.net + next.low),
+            peak: self.peak.max(self.net + next.peak),
+        }
+    }
+
+    /// The span of a call: the return address is pushed around the body
+    fn framed(self) -> Span {
+        Span::effect(0, 1).then(self).then(Span::effect(1, 0))
+    }
+}
+
+type Depth = std::result::Result<Span, String>;
+
+fn then(first: &Depth, next: &Depth) -> Depth {
+    Ok(first.clone()?.then(next.clone()?))
+}
+
+/// Either branch of an `IF` may run; both must leave the same depth
+fn either(a: &Depth, b: &Depth, stack: &str) -> Depth {
+    let (a, b) = (a.clone()?, b.clone()?);
+    if a.net != b.net {
+        return Err(format!("IF branches leave different {} depths", stack));
+    }
+    Ok(Span { net: a.net, low: a.low.min(b.low), peak: a.peak.max(b.peak) })
+}
+
+/// A loop iteration runs any number of times, so it must not change the depth
+fn repeated(iteration: &Depth, stack: &str) -> Depth {
+    let iteration = iteration.clone()?;
+    if iteration.net != 0 {
+        return Err(format!("a loop changes the {} depth by {} each time", stack, iteration.net));
+    }
+    Ok(iteration)
+}
+
+/// Use of both stacks
+#[derive(Debug, Clone)]
+struct Usage {
+    data: Depth,
+    returns: Depth,
+}
+
+impl Usage {
+    fn new(data: Span, returns: Span) -> Self {
+        Self { data: Ok(data), returns: Ok(returns) }
+    }
+
+    fn data(data: Span) -> Self {
+        Self::new(data, Span::NONE)
+    }
+
+    fn unknown(reason: String) -> Self {
+        Self { data: Err(reason.clone()), returns: Err(reason) }
+    }
+
+    fn then(&self, next: &Usage) -> Usage {
+        Usage { data: then(&self.data, &next.data), returns: then(&self.returns, &next.returns) }
+    }
+}
+
+/// Cells a word takes and leaves on one stack
+type Effect = (usize, usize);
+
+/// Words the stack effect table lacks, with their effect on the data stack
+/// and on the return stack
+const EXTRA_WORDS: &[(&str, Effect, Effect)] = &[
+    (">r", (1, 0), (0, 1)),
+    ("r>", (0, 1), (1, 0)),
+    ("r@", (0, 1), (1, 1)),
+    ("2>r", (2, 0), (0, 2)),
+    ("2r>", (0, 2), (2, 0)),
+    ("2r@", (0, 2), (2, 2)),
+    ("i", (0, 1), (0, 0)),
+    ("j", (0, 1), (0, 0)),
+    ("exit", (0, 0), (0, 0)),
+    ("nip", (2, 1), (0, 0)),
+    ("tuck", (2, 3), (0, 0)),
+    ("-rot", (3, 3), (0, 0)),
+    ("?dup", (1, 2), (0, 0)),
+    ("2dup", (2, 4), (0, 0)),
+    ("2drop", (2, 0), (0, 0)),
+    ("2swap", (4, 4), (0, 0)),
+    ("2over", (4, 6), (0, 0)),
+    ("1+", (1, 1), (0, 0)),
+    ("1-", (1, 1), (0, 0)),
+    ("2*", (1, 1), (0, 0)),
+    ("2/", (1, 1), (0, 0)),
+    ("0=", (1, 1), (0, 0)),
+    ("0<", (1, 1), (0, 0)),
+    ("0>", (1, 1), (0, 0)),
+    ("cells", (1, 1), (0, 0)),
+    ("cell+", (1, 1), (0, 0)),
+    ("true", (0, 1), (0, 0)),
+    ("false", (0, 1), (0, 0)),
+];
+
+/// Results per definition, found depth-first through the call graph
+struct Analysis<'a> {
+    program: &'a Program,
+    effects: StackEffectInference,
+    /// Definitions by lowercase name; a later definition wins
+    definitions: HashMap<String, usize>,
+    /// CODE words, variables and constants by lowercase name
+    leaves: HashMap<String, Effect>,
+    done: HashMap<usize, Usage>,
+    /// Definitions being analyzed, outermost first
+    active: Vec<usize>,
+    recursive: Vec<bool>,
+}
+
+impl<'a> Analysis<'a> {
+    fn new(program: &'a Program) -> Self {
+        let definitions = program.definitions.iter().enumerate().map(|(i, def)| (def.name.to_lowercase(), i)).collect();
+        let mut leaves: HashMap<String, Effect> = program
+            .code_words
+            .iter()
+            .map(|word| (word.name.to_lowercase(), (word.stack_effect.inputs.len(), word.stack_effect.outputs.len())))
+            .collect();
+        for word in &program.top_level_code {
+            if let Word::Variable { name, .. } | Word::Constant { name, .. } = word {
+                leaves.insert(name.to_lowercase(), (0, 1));
+            }
+        }
+        Self {
+            program,
+            effects: StackEffectInference::new(),
+            definitions,
+            leaves,
+            done: HashMap::new(),
+            active: Vec::new(),
+            recursive: vec![false; program.definitions.len()],
+        }
+    }
+
+    /// Stack use of a call to a definition, return address included
+    fn definition(&mut self, index: usize) -> Usage {
+        if let Some(usage) = self.done.get(&index) {
+            return usage.clone();
+        }
+        if let Some(position) = self.active.iter().position(|&active| active == index) {
+            for &caller in &self.active[position..] {
+                self.recursive[caller] = true;
+            }
+            let name = &self.program.definitions[index].name;
+            return Usage::unknown(format!("mutually recursive through '{}'", name));
+        }
+
+        let program = self.program;
+        let def = &program.definitions[index];
+        self.active.push(index);
+        let mut walker = Walker::new(self, Some(def));
+        let body = walker.sequence(&def.body, (0, 0));
+        let sites = std::mem::take(&mut walker.sites);
+        self.active.pop();
+
+        let usage = if sites.is_empty() {
+            body
+        } else {
+            self.recursive[index] = true;
+            match (def.attributes.recursion, &def.stack_effect) {
+                (None, _) => Usage::unknown("recursive; bound it with \\ @recursion(N)".to_string()),
+                (Some(_), None) => Usage::unknown("recursive without a stack effect comment".to_string()),
+                (Some(levels), Some(_)) => {
+                    // Each nested activation starts where the deepest
+                    // recursive call is made from
+                    let deeper = levels.saturating_sub(1) as i64;
+                    let data_site = sites.iter().map(|site| site.0).max().unwrap_or(0);
+                    let return_site = sites.iter().map(|site| site.1).max().unwrap_or(0) + 1;
+                    Usage {
+                        data: body.data.map(|span| Span {
+                            net: span.net,
+                            low: span.low + deeper * data_site.min(0),
+                            peak: span.peak + deeper * data_site.max(0),
+                        }),
+                        returns: body.returns.map(|span| Span { peak: span.peak + deeper * return_site.max(0), ..span }),
+                    }
+                }
+            }
+        };
+        let usage = Usage { data: usage.data, returns: usage.returns.map(Span::framed) };
+        self.done.insert(index, usage.clone());
+        usage
+    }
+}
+
+/// Walks the body of one definition, or the top-level code
+struct Walker<'w, 'a> {
+    analysis: &'w mut Analysis<'a>,
+    /// The definition walked, whose calls to itself are recursive
+    word: Option<&'a Definition>,
+    /// Depths of both stacks at each recursive call, relative to the start
+    sites: Vec<(i64, i64)>,
+}
+
+impl<'w, 'a> Walker<'w, 'a> {
+    fn new(analysis: &'w mut Analysis<'a>, word: Option<&'a Definition>) -> Self {
+        Self { analysis, word, sites: Vec::new() }
+    }
+
+    /// Stack use of a sequence starting at the given depths
+    fn sequence(&mut self, words: &[Word], at: (i64, i64)) -> Usage {
+        let mut usage = Usage::new(Span::NONE, Span::NONE);
+        for word in words {
+            let here = (
+                at.0 + usage.data.as_ref().map_or(0, |span| span.net),
+                at.1 + usage.returns.as_ref().map_or(0, |span| span.net),
+            );
+            usage = usage.then(&self.word(word, here));
+        }
+        usage
+    }
+
+    fn word(&mut self, word: &Word, here: (i64, i64)) -> Usage {
+        let pop = Span::effect(1, 0);
+        match word {
+            Word::IntLiteral(_) | Word::FloatLiteral(_) => Usage::data(Span::effect(0, 1)),
+            Word::StringLiteral(_) => Usage::data(Span::effect(0, 2)),
+            Word::Variable { .. } | Word::Constant { .. } | Word::Comment(_) => Usage::data(Span::NONE),
+            // Locals live in registers, off both stacks
+            Word::Locals { args, .. } => Usage::data(Span::effect(args.len(), 0)),
+            Word::LocalRef { .. } => Usage::data(Span::effect(0, 1)),
+            Word::LocalStore { .. } => Usage::data(pop),
+            Word::Quotation { .. } => Usage::data(Span::effect(0, 1)),
+            Word::WordRef { name, .. } => self.call(name, here),
+            // The word runs on the task's own stacks, taking only its inputs from here
+            Word::TaskSpawn { word, .. } => match self.call(word, here).data {
+                Ok(span) => Usage::data(Span::effect(-span.low as usize, 1)),
+                Err(reason) => Usage::unknown(reason),
+            },
+            Word::If { then_branch, else_branch } => {
+                let start = (here.0 - 1, here.1);
+                let then_usage = self.sequence(then_branch, start);
+                let else_usage = match else_branch {
+                    Some(else_branch) => self.sequence(else_branch, start),
+                    None => Usage::new(Span::NONE, Span::NONE),
+                };
+                Usage {
+                    data: then(&Ok(pop), &either(&then_usage.data, &else_usage.data, "data stack")),
+                    returns: either(&then_usage.returns, &else_usage.returns, "return stack"),
+                }
+            }
+            Word::BeginUntil { body } => {
+                let body = self.sequence(body, here);
+                Usage {
+                    data: repeated(&then(&body.data, &Ok(pop)), "data stack"),
+                    returns: repeated(&body.returns, "return stack"),
+                }
+            }
+            Word::BeginWhileRepeat { condition, body } => {
+                let condition = self.sequence(condition, here);
+                let test = Usage { data: then(&condition.data, &Ok(pop)), returns: condition.returns };
+                let after = (
+                    here.0 + test.data.as_ref().map_or(0, |span| span.net),
+                    here.1 + test.returns.as_ref().map_or(0, |span| span.net),
+                );
+                let iteration = test.then(&self.sequence(body, after));
+                // The loop leaves through the test
+                let exit = |iteration: Depth, test: &Depth| -> Depth { Ok(Span { net: test.clone()?.net, ..iteration? }) };
+                Usage {
+                    data: exit(repeated(&iteration.data, "data stack"), &test.data),
+                    returns: exit(repeated(&iteration.returns, "return stack"), &test.returns),
+                }
+            }
+            Word::DoLoop { body, .. } => {
+                // The limit and index move to the return stack
+                let body = self.sequence(body, (here.0 - 2, here.1 + 2));
+                let parameters = Span::effect(0, 2);
+                Usage {
+                    data: then(&Ok(Span::effect(2, 0)), &repeated(&body.data, "data stack")),
+                    returns: then(&then(&Ok(parameters), &repeated(&body.returns, "return stack")), &Ok(Span::effect(2, 0))),
+                }
+            }
+        }
+    }
+
+    fn call(&mut self, name: &str, here: (i64, i64)) -> Usage {
+        let lower = name.to_lowercase();
+        if let Some(word) = self.word.filter(|word| lower == "recurse" || word.name.to_lowercase() == lower) {
+            self.sites.push(here);
+            return match &word.stack_effect {
+                Some(effect) => Usage::data(Span::effect(effect.inputs.len(), effect.outputs.len())),
+                None => Usage::unknown("recursive without a stack effect comment".to_string()),
+            };
+        }
+        if let Some(&index) = self.analysis.definitions.get(&lower) {
+            return self.analysis.definition(index);
+        }
+        if let Some(&(inputs, outputs)) = self.analysis.leaves.get(&lower) {
+            return Usage::new(Span::effect(inputs, outputs), Span::NONE.framed());
+        }
+        if let Some((_, data, returns)) = EXTRA_WORDS.iter().find(|(word, ..)| *word == lower) {
+            return Usage::new(Span::effect(data.0, data.1), Span::effect(returns.0, returns.1));
+        }
+        match self.analysis.effects.get_effect(&lower) {
+            Some(effect) => Usage::data(Span::effect(effect.inputs.len(), effect.outputs.len())),
+            None => Usage::unknown(format!("unknown word '{}'", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_program;
+
+    fn bounds(source: &str) -> StackBounds {
+        stack_bounds(&parse_program(source).unwrap())
+    }
+
+    fn word<'b>(bounds: &'b StackBounds, name: &str) -> &'b WordBounds {
+        bounds.words.iter().find(|word| word.name == name).unwrap()
+    }
+
+    #[test]
+    fn test_word_and_program_bounds() {
+        let b = bounds(
+            ": sq ( n -- n*n ) dup * ;
+             : sum-sq ( a b -- c ) sq swap sq + ;
+             : stash ( a b -- a ) >r r@ drop r> drop ;
+             : count ( n -- ) 0 do i sq drop loop ;
+             1 2 3 sum-sq 4 count",
+        );
+        let sq = word(&b, "sq");
+        assert_eq!((sq.inputs, &sq.data, &sq.returns), (Some(1), &Bound::Cells(2), &Bound::Cells(1)));
+        let sum = word(&b, "sum-sq");
+        assert_eq!((&sum.data, &sum.returns), (&Bound::Cells(3), &Bound::Cells(2)));
+        assert_eq!(word(&b, "stash").returns, Bound::Cells(2));
+        assert_eq!(word(&b, "count").returns, Bound::Cells(4), "frame, loop parameters and a call");
+
+        // 1 2 3 sum-sq: one cell below the three sum-sq needs
+        assert_eq!(b.data, Bound::Cells(4));
+        assert_eq!(b.returns, Bound::Cells(4));
+    }
+
+    #[test]
+    fn test_recursion_needs_an_annotation() {
+        let source = ": fact ( n -- n! ) dup 1 > if dup 1 - recurse * then ;";
+        let unannotated = bounds(source);
+        let fact = word(&unannotated, "fact");
+        assert!(fact.recursive);
+        assert!(matches!(&fact.data, Bound::Unbounded(reason) if reason.contains("@recursion")));
+
+        let annotated = bounds(&format!("\\ @recursion(10)\n{}", source));
+        let fact = word(&annotated, "fact");
+        // Each level keeps n below the next one's input: 9 levels of one
+        // cell under the three cells of the deepest
+        assert_eq!(fact.data, Bound::Cells(12));
+        assert_eq!(fact.returns, Bound::Cells(10));
+
+        let mutual = bounds(": a ( n -- ) dup if 1 - b else drop then ; : b ( n -- ) a ;");
+        assert!(word(&mutual, "a").recursive && word(&mutual, "b").recursive);
+        assert!(matches!(word(&mutual, "a").data, Bound::Unbounded(_)));
+    }
+
+    #[test]
+    fn test_unbalanced_loops_are_unbounded() {
+        let b = bounds(": fill ( n -- ) begin dup 1 - dup 0= until ; : ok ( n -- ) begin 1 - dup 0= until drop ;");
+        assert!(matches!(&word(&b, "fill").data, Bound::Unbounded(reason) if reason.contains("loop")));
+        assert_eq!(word(&b, "fill").returns, Bound::Cells(1));
+        assert_eq!(word(&b, "ok").data, Bound::Cells(2));
+        assert!(matches!(b.data, Bound::Unbounded(_)));
+    }
+}
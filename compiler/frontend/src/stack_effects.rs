@@ -48,6 +48,20 @@ impl StackEffectInference {
                 vec![StackType::Int, StackType::Int],
             ),
         );
+        builtins.insert(
+            "u/mod".to_string(),
+            StackEffect::new(
+                vec![StackType::Int, StackType::Int],
+                vec![StackType::Int, StackType::Int],
+            ),
+        );
+        builtins.insert(
+            "um*".to_string(),
+            StackEffect::new(
+                vec![StackType::Int, StackType::Int],
+                vec![StackType::Int, StackType::Int],
+            ),
+        );
 
         // Stack manipulation
         builtins.insert(
@@ -135,24 +149,32 @@ impl StackEffectInference {
             "<>".to_string(),
             StackEffect::new(vec![StackType::Int, StackType::Int], vec![StackType::Bool]),
         );
-
-        // Logical operations
         builtins.insert(
-            "and".to_string(),
-            StackEffect::new(vec![StackType::Bool, StackType::Bool], vec![StackType::Bool]),
+            "u<".to_string(),
+            StackEffect::new(vec![StackType::Int, StackType::Int], vec![StackType::Bool]),
         );
         builtins.insert(
-            "or".to_string(),
-            StackEffect::new(vec![StackType::Bool, StackType::Bool], vec![StackType::Bool]),
+            "u>".to_string(),
+            StackEffect::new(vec![StackType::Int, StackType::Int], vec![StackType::Bool]),
         );
+
+        // Bitwise operations
+        for word in ["and", "or", "xor", "lshift", "rshift", "rol", "ror"] {
+            builtins.insert(
+                word.to_string(),
+                StackEffect::new(vec![StackType::Int, StackType::Int], vec![StackType::Int]),
+            );
+        }
         builtins.insert(
             "not".to_string(),
             StackEffect::new(vec![StackType::Bool], vec![StackType::Bool]),
         );
-        builtins.insert(
-            "invert".to_string(),
-            StackEffect::new(vec![StackType::Int], vec![StackType::Int]),
-        );
+        for word in ["invert", "popcount", "ctz", "clz"] {
+            builtins.insert(
+                word.to_string(),
+                StackEffect::new(vec![StackType::Int], vec![StackType::Int]),
+            );
+        }
 
         // I/O operations
         builtins.insert(
@@ -167,6 +189,135 @@ impl StackEffectInference {
             "cr".to_string(),
             StackEffect::new(vec![], vec![]),
         );
+        builtins.insert(
+            "space".to_string(),
+            StackEffect::new(vec![], vec![]),
+        );
+        builtins.insert(
+            "u.".to_string(),
+            StackEffect::new(vec![StackType::Int], vec![]),
+        );
+        builtins.insert(
+            ".r".to_string(),
+            StackEffect::new(vec![StackType::Int, StackType::Int], vec![]),
+        );
+        builtins.insert(
+            "type".to_string(),
+            StackEffect::new(vec![StackType::Addr, StackType::Int], vec![]),
+        );
+        builtins.insert(
+            "key".to_string(),
+            StackEffect::new(vec![], vec![StackType::Char]),
+        );
+        builtins.insert(
+            "key?".to_string(),
+            StackEffect::new(vec![], vec![StackType::Bool]),
+        );
+        builtins.insert(
+            "accept".to_string(),
+            StackEffect::new(vec![StackType::Addr, StackType::Int], vec![StackType::Int]),
+        );
+
+        // Pictured numeric output
+        builtins.insert(
+            "<#".to_string(),
+            StackEffect::new(vec![], vec![]),
+        );
+        for digits in ["#", "#s"] {
+            builtins.insert(
+                digits.to_string(),
+                StackEffect::new(
+                    vec![StackType::Int, StackType::Int],
+                    vec![StackType::Int, StackType::Int],
+                ),
+            );
+        }
+        builtins.insert(
+            "hold".to_string(),
+            StackEffect::new(vec![StackType::Char], vec![]),
+        );
+        builtins.insert(
+            "sign".to_string(),
+            StackEffect::new(vec![StackType::Int], vec![]),
+        );
+        builtins.insert(
+            "#>".to_string(),
+            StackEffect::new(
+                vec![StackType::Int, StackType::Int],
+                vec![StackType::Addr, StackType::Int],
+            ),
+        );
+
+        // Process environment
+        builtins.insert(
+            "arg-count".to_string(),
+            StackEffect::new(vec![], vec![StackType::Int]),
+        );
+        builtins.insert(
+            "arg@".to_string(),
+            StackEffect::new(vec![StackType::Int], vec![StackType::Addr, StackType::Int]),
+        );
+        builtins.insert(
+            "getenv".to_string(),
+            StackEffect::new(
+                vec![StackType::Addr, StackType::Int],
+                vec![StackType::Addr, StackType::Int],
+            ),
+        );
+
+        // Heap
+        builtins.insert(
+            "allocate".to_string(),
+            StackEffect::new(vec![StackType::Int], vec![StackType::Addr, StackType::Int]),
+        );
+        builtins.insert(
+            "free".to_string(),
+            StackEffect::new(vec![StackType::Addr], vec![StackType::Int]),
+        );
+        builtins.insert(
+            "resize".to_string(),
+            StackEffect::new(
+                vec![StackType::Addr, StackType::Int],
+                vec![StackType::Addr, StackType::Int],
+            ),
+        );
+
+        // Blocks
+        for word in ["block", "buffer"] {
+            builtins.insert(
+                word.to_string(),
+                StackEffect::new(vec![StackType::Int], vec![StackType::Addr]),
+            );
+        }
+        for word in ["update", "save-buffers", "empty-buffers", "flush"] {
+            builtins.insert(word.to_string(), StackEffect::new(vec![], vec![]));
+        }
+        builtins.insert(
+            "open-blocks".to_string(),
+            StackEffect::new(vec![StackType::Addr, StackType::Int], vec![]),
+        );
+
+        // Tasks, channels and atomic cells
+        for word in ["join", "recv", "@atomic"] {
+            builtins.insert(
+                word.to_string(),
+                StackEffect::new(vec![StackType::Addr], vec![StackType::Int]),
+            );
+        }
+        builtins.insert("pause".to_string(), StackEffect::new(vec![], vec![]));
+        builtins.insert(
+            "channel".to_string(),
+            StackEffect::new(vec![StackType::Int], vec![StackType::Addr]),
+        );
+        for word in ["send", "!atomic"] {
+            builtins.insert(
+                word.to_string(),
+                StackEffect::new(vec![StackType::Int, StackType::Addr], vec![]),
+            );
+        }
+        for word in ["close-channel", "destroy-channel"] {
+            builtins.insert(word.to_string(), StackEffect::new(vec![StackType::Addr], vec![]));
+        }
 
         // Memory operations
         builtins.insert(
@@ -185,6 +336,18 @@ impl StackEffectInference {
             "c!".to_string(),
             StackEffect::new(vec![StackType::Char, StackType::Addr], vec![]),
         );
+        for fetch in ["w@", "l@"] {
+            builtins.insert(
+                fetch.to_string(),
+                StackEffect::new(vec![StackType::Addr], vec![StackType::Int]),
+            );
+        }
+        for store in ["w!", "l!"] {
+            builtins.insert(
+                store.to_string(),
+                StackEffect::new(vec![StackType::Int, StackType::Addr], vec![]),
+            );
+        }
 
         // Additional common words
         builtins.insert(
@@ -204,6 +367,12 @@ impl StackEffectInference {
             StackEffect::new(vec![StackType::Int, StackType::Int], vec![StackType::Int]),
         );
 
+        // The token, until the words it may call are known
+        builtins.insert(
+            "execute".to_string(),
+            StackEffect::new(vec![StackType::Int], vec![]),
+        );
+
         Self {
             builtins,
             user_words: FxHashMap::default(),
@@ -247,15 +416,28 @@ impl StackEffectInference {
             }
             Word::WordRef { name, .. } => {
                 // Look up word effect
-                if let Some(effect) = self.builtins.get(name) {
+                if let Some(effect) = self.builtins.get(name.as_str()) {
                     Ok(effect.clone())
-                } else if let Some(effect) = self.user_words.get(name) {
+                } else if let Some(effect) = self.user_words.get(name.as_str()) {
                     Ok(effect.clone())
                 } else {
                     // Unknown word - assume minimal effect
                     Ok(StackEffect::new(vec![], vec![]))
                 }
             }
+            Word::TaskSpawn { word, .. } => {
+                // The word takes its inputs here and leaves the task
+                let inputs = self
+                    .user_words
+                    .get(word.as_str())
+                    .map(|effect| effect.inputs.clone())
+                    .unwrap_or_default();
+                Ok(StackEffect::new(inputs, vec![StackType::Addr]))
+            }
+            Word::Locals { args, .. } => Ok(StackEffect::new(vec![StackType::Unknown; args.len()], vec![])),
+            Word::LocalRef { .. } => Ok(StackEffect::new(vec![], vec![StackType::Unknown])),
+            Word::LocalStore { .. } => Ok(StackEffect::new(vec![StackType::Unknown], vec![])),
+            Word::Quotation { .. } => Ok(StackEffect::new(vec![], vec![StackType::Int])),
             Word::If { then_branch, else_branch } => {
                 // IF consumes a boolean, branches should have same effect
                 let then_effect = self.infer_sequence(then_branch)?;
@@ -333,6 +515,28 @@ impl StackEffectInference {
         Ok(())
     }
 
+    /// Add a CODE word, whose declared effect is all there is to go on
+    pub fn add_code_word(&mut self, word: &CodeWord) {
+        self.user_words.insert(word.name.clone(), word.stack_effect.clone());
+    }
+
+    /// Add a variable or CREATE buffer, which pushes its address
+    pub fn add_variable(&mut self, name: &str) {
+        self.user_words.insert(name.to_string(), StackEffect::new(vec![], vec![StackType::Addr]));
+    }
+
+    /// Give `execute` the effect of the words it may call, after the token
+    ///
+    /// See [`crate::quotation::execute_effect`].
+    pub fn set_execute_effect(&mut self, (inputs, outputs): (usize, usize)) {
+        let mut taken = vec![StackType::Unknown; inputs];
+        taken.push(StackType::Int);
+        self.builtins.insert(
+            "execute".to_string(),
+            StackEffect::new(taken, vec![StackType::Unknown; outputs]),
+        );
+    }
+
     /// Get the stack effect for a word
     pub fn get_effect(&self, name: &str) -> Option<&StackEffect> {
         self.builtins.get(name).or_else(|| self.user_words.get(name))
@@ -371,7 +575,7 @@ mod tests {
             Word::IntLiteral(2),
             Word::IntLiteral(3),
             Word::WordRef {
-                name: "+".to_string(),
+                name: "+".into(),
                 location: SourceLocation::default(),
             },
         ];
@@ -385,7 +589,7 @@ mod tests {
     fn test_infer_dup() {
         let inference = StackEffectInference::new();
         let words = vec![Word::WordRef {
-            name: "dup".to_string(),
+            name: "dup".into(),
             location: SourceLocation::default(),
         }];
 
@@ -413,7 +617,7 @@ mod tests {
             Word::IntLiteral(1),
             Word::IntLiteral(2),
             Word::WordRef {
-                name: "swap".to_string(),
+                name: "swap".into(),
                 location: SourceLocation::default(),
             },
         ];
@@ -5,7 +5,7 @@
 
 use crate::ast::*;
 use crate::error::{ForthError, Result};
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use std::collections::HashMap;
 
 /// Type environment mapping variables to types
@@ -85,10 +85,59 @@ impl Substitution {
     }
 }
 
+/// Passes over a loop body before its types are taken as they stand
+const MAX_LOOP_PASSES: usize = 8;
+
+/// The abstract stack at one point in a sequence
+#[derive(Debug, Clone, Default)]
+struct FlowState {
+    /// Types on the stack, bottom first, including the inputs taken so far
+    stack: Vec<StackType>,
+    /// How many inputs this path has taken from the caller
+    consumed: usize,
+}
+
+/// Types on the stack after one word of a definition
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackState {
+    /// The word, or the word closing a control structure (`then`, `until`, `repeat`, `loop`)
+    pub word: String,
+    /// How many control structures the word is nested in
+    pub depth: usize,
+    /// Types on the stack, bottom first, including the inputs taken so far
+    pub stack: Vec<StackType>,
+}
+
+/// Inferred types of a definition, with the stack after each of its words
+#[derive(Debug, Clone)]
+pub struct TypedDefinition {
+    pub inputs: Vec<StackType>,
+    pub outputs: Vec<StackType>,
+    pub states: Vec<StackState>,
+}
+
 /// Type inference engine
+///
+/// Inference is flow-sensitive: both arms of an IF start from the stack
+/// before it and meet again at THEN, and loop bodies are iterated until the
+/// types at the loop head stop changing. Where paths meet, each slot gets the
+/// least upper bound of the types flowing in.
 pub struct TypeInference {
     env: TypeEnv,
     substitution: Substitution,
+    /// Inputs taken from the caller by the sequence being inferred, topmost first
+    inputs: Vec<StackType>,
+    /// Signatures of the definitions inferred so far
+    signatures: FxHashMap<String, (Vec<StackType>, Vec<StackType>)>,
+    /// Record size words and field accessors, typed by their layout
+    declared: FxHashSet<String>,
+    /// Field accessors, with the record each one takes
+    fields: FxHashMap<String, String>,
+    /// Stack states recorded by `trace_definition`
+    trace: Option<Vec<StackState>>,
+    nesting: usize,
+    /// Types of the locals declared so far in the definition being inferred
+    locals: Vec<(Symbol, StackType)>,
 }
 
 impl TypeInference {
@@ -104,6 +153,13 @@ impl TypeInference {
         Self {
             env,
             substitution: Substitution::new(),
+            inputs: Vec::new(),
+            signatures: FxHashMap::default(),
+            declared: FxHashSet::default(),
+            fields: FxHashMap::default(),
+            trace: None,
+            nesting: 0,
+            locals: Vec::new(),
         }
     }
 
@@ -124,6 +180,10 @@ impl TypeInference {
             // In Forth, Bool and Int are compatible (booleans are integers: -1 for true, 0 for false)
             (StackType::Bool, StackType::Int) | (StackType::Int, StackType::Bool) => Ok(()),
 
+            // A record address is an address, but not one of another record
+            (StackType::Record(r1), StackType::Record(r2)) if r1 == r2 => Ok(()),
+            (StackType::Record(_), StackType::Addr) | (StackType::Addr, StackType::Record(_)) => Ok(()),
+
             // Unknown can unify with anything
             (StackType::Unknown, _) | (_, StackType::Unknown) => Ok(()),
 
@@ -134,6 +194,7 @@ impl TypeInference {
                     Err(ForthError::TypeError {
                         expected: format!("{}", t1),
                         found: format!("{}", t2),
+                        context: None,
                         location: None,
                     })
                 } else {
@@ -146,6 +207,7 @@ impl TypeInference {
             _ => Err(ForthError::TypeError {
                 expected: format!("{}", t1),
                 found: format!("{}", t2),
+                context: None,
                 location: None,
             }),
         }
@@ -166,69 +228,36 @@ impl TypeInference {
             Word::FloatLiteral(_) => Ok((vec![], vec![StackType::Float])),
             Word::StringLiteral(_) => Ok((vec![], vec![StackType::String])),
 
-            Word::WordRef { name, .. } => {
-                // Look up word type from environment
-                self.infer_builtin_word(name)
-            }
-
-            Word::If { then_branch, else_branch } => {
-                // IF requires a boolean condition
-                let (then_inputs, then_outputs) = self.infer_sequence(then_branch)?;
-
-                let (_else_inputs, else_outputs) = if let Some(else_words) = else_branch {
-                    self.infer_sequence(else_words)?
-                } else {
-                    (vec![], vec![])
-                };
-
-                // Both branches should have compatible types
-                if then_outputs.len() != else_outputs.len() {
-                    return Err(ForthError::TypeError {
-                        expected: format!("{} outputs", then_outputs.len()),
-                        found: format!("{} outputs", else_outputs.len()),
-                        location: Some("IF branches".to_string()),
-                    });
+            Word::WordRef { name, location } => {
+                // Words defined earlier shadow the builtins
+                if let Some((inputs, outputs)) = self.signatures.get(name.as_str()).cloned() {
+                    return Ok(self.instantiate(&inputs, &outputs));
                 }
-
-                // Unify output types
-                for (t1, t2) in then_outputs.iter().zip(else_outputs.iter()) {
-                    self.unify(t1, t2)?;
-                }
-
-                let mut inputs = vec![StackType::Bool];
-                inputs.extend(then_inputs);
-
-                Ok((inputs, then_outputs))
+                self.infer_builtin_word(name).map_err(|e| e.at(location))
             }
 
-            Word::BeginUntil { body } => {
-                let (inputs, outputs) = self.infer_sequence(body)?;
-                let mut all_inputs = inputs.clone();
-                all_inputs.push(StackType::Bool);
-                Ok((all_inputs, outputs))
+            Word::TaskSpawn { word, location } => {
+                // The task handle is an address
+                let (inputs, _) = self.infer_builtin_word(word).map_err(|e| e.at(location))?;
+                Ok((inputs, vec![StackType::Addr]))
             }
 
-            Word::BeginWhileRepeat { condition, body } => {
-                let (cond_inputs, cond_outputs) = self.infer_sequence(condition)?;
-                let (body_inputs, body_outputs) = self.infer_sequence(body)?;
-
-                // Condition should produce a boolean
-                if let Some(last_output) = cond_outputs.last() {
-                    self.unify(last_output, &StackType::Bool)?;
-                }
-
-                let mut inputs = cond_inputs;
-                inputs.extend(body_inputs);
-
-                Ok((inputs, body_outputs))
+            // Each local has one type, unified with every value stored to it
+            Word::Locals { args, uninitialized, .. } => {
+                let types: Vec<StackType> = args.iter().map(|_| self.env.fresh_var()).collect();
+                self.locals.extend(args.iter().copied().zip(types.iter().cloned()));
+                self.locals.extend(uninitialized.iter().map(|name| (*name, StackType::Int)));
+                Ok((types, vec![]))
             }
+            Word::LocalRef { name, .. } => Ok((vec![], vec![self.local_type(*name)])),
+            Word::LocalStore { name, .. } => Ok((vec![self.local_type(*name)], vec![])),
+            // An execution token is a cell
+            Word::Quotation { .. } => Ok((vec![], vec![StackType::Int])),
 
-            Word::DoLoop { body, .. } => {
-                let (body_inputs, body_outputs) = self.infer_sequence(body)?;
-                let mut inputs = vec![StackType::Int, StackType::Int];
-                inputs.extend(body_inputs);
-                Ok((inputs, body_outputs))
-            }
+            Word::If { .. }
+            | Word::BeginUntil { .. }
+            | Word::BeginWhileRepeat { .. }
+            | Word::DoLoop { .. } => self.infer_sequence(std::slice::from_ref(word)),
 
             Word::Variable { .. } => Ok((vec![], vec![StackType::Addr])),
             Word::Constant { .. } => Ok((vec![], vec![StackType::Int])),
@@ -236,6 +265,11 @@ impl TypeInference {
         }
     }
 
+    fn local_type(&self, name: Symbol) -> StackType {
+        let local = self.locals.iter().rev().find(|(local, _)| *local == name);
+        local.map_or(StackType::Unknown, |(_, ty)| ty.clone())
+    }
+
     /// Infer types for builtin words
     fn infer_builtin_word(&mut self, name: &str) -> Result<(Vec<StackType>, Vec<StackType>)> {
         match name {
@@ -243,7 +277,7 @@ impl TypeInference {
             "+" | "-" | "*" | "/" | "mod" => {
                 Ok((vec![StackType::Int, StackType::Int], vec![StackType::Int]))
             }
-            "/mod" => Ok((
+            "/mod" | "u/mod" | "um*" => Ok((
                 vec![StackType::Int, StackType::Int],
                 vec![StackType::Int, StackType::Int],
             )),
@@ -281,27 +315,78 @@ impl TypeInference {
             }
 
             // Comparison
-            "<" | ">" | "=" | "<=" | ">=" | "<>" => {
+            "<" | ">" | "=" | "<=" | ">=" | "<>" | "u<" | "u>" => {
                 Ok((vec![StackType::Int, StackType::Int], vec![StackType::Bool]))
             }
 
-            // Logical
-            "and" | "or" => {
-                Ok((vec![StackType::Bool, StackType::Bool], vec![StackType::Bool]))
+            // Bitwise, so flags and other cells alike
+            "and" | "or" | "xor" | "lshift" | "rshift" | "rol" | "ror" => {
+                Ok((vec![StackType::Int, StackType::Int], vec![StackType::Int]))
             }
             "not" => Ok((vec![StackType::Bool], vec![StackType::Bool])),
-            "invert" => Ok((vec![StackType::Int], vec![StackType::Int])),
+            "invert" | "popcount" | "ctz" | "clz" => Ok((vec![StackType::Int], vec![StackType::Int])),
 
             // Memory
             "@" => Ok((vec![StackType::Addr], vec![StackType::Int])),
             "!" => Ok((vec![StackType::Int, StackType::Addr], vec![])),
             "c@" => Ok((vec![StackType::Addr], vec![StackType::Char])),
             "c!" => Ok((vec![StackType::Char, StackType::Addr], vec![])),
+            "w@" | "l@" => Ok((vec![StackType::Addr], vec![StackType::Int])),
+            "w!" | "l!" => Ok((vec![StackType::Int, StackType::Addr], vec![])),
 
             // I/O
             "." => Ok((vec![StackType::Int], vec![])),
             "emit" => Ok((vec![StackType::Char], vec![])),
-            "cr" => Ok((vec![], vec![])),
+            "cr" | "space" | "<#" => Ok((vec![], vec![])),
+            "u." | "sign" => Ok((vec![StackType::Int], vec![])),
+            ".r" => Ok((vec![StackType::Int, StackType::Int], vec![])),
+            "type" => Ok((vec![StackType::Addr, StackType::Int], vec![])),
+            "key" => Ok((vec![], vec![StackType::Char])),
+            "key?" => Ok((vec![], vec![StackType::Bool])),
+            "accept" => Ok((vec![StackType::Addr, StackType::Int], vec![StackType::Int])),
+            "hold" => Ok((vec![StackType::Char], vec![])),
+            "#" | "#s" => Ok((
+                vec![StackType::Int, StackType::Int],
+                vec![StackType::Int, StackType::Int],
+            )),
+            "#>" => Ok((
+                vec![StackType::Int, StackType::Int],
+                vec![StackType::Addr, StackType::Int],
+            )),
+
+            // Heap
+            "allocate" => Ok((vec![StackType::Int], vec![StackType::Addr, StackType::Int])),
+            "free" => Ok((vec![StackType::Addr], vec![StackType::Int])),
+            "resize" => Ok((
+                vec![StackType::Addr, StackType::Int],
+                vec![StackType::Addr, StackType::Int],
+            )),
+
+            // Tasks, channels and atomic cells
+            "join" => Ok((vec![StackType::Addr], vec![StackType::Int])),
+            "pause" => Ok((vec![], vec![])),
+            "channel" => Ok((vec![StackType::Int], vec![StackType::Addr])),
+            "send" => Ok((vec![StackType::Int, StackType::Addr], vec![])),
+            "recv" => Ok((vec![StackType::Addr], vec![StackType::Int])),
+            "close-channel" | "destroy-channel" => Ok((vec![StackType::Addr], vec![])),
+            "@atomic" => Ok((vec![StackType::Addr], vec![StackType::Int])),
+            "!atomic" => Ok((vec![StackType::Int, StackType::Addr], vec![])),
+
+            // Blocks
+            "block" | "buffer" => Ok((vec![StackType::Int], vec![StackType::Addr])),
+            "update" | "save-buffers" | "empty-buffers" | "flush" => Ok((vec![], vec![])),
+            "open-blocks" => Ok((vec![StackType::Addr, StackType::Int], vec![])),
+
+            // Process environment
+            "arg-count" => Ok((vec![], vec![StackType::Int])),
+            "arg@" => Ok((vec![StackType::Int], vec![StackType::Addr, StackType::Int])),
+            "getenv" => Ok((
+                vec![StackType::Addr, StackType::Int],
+                vec![StackType::Addr, StackType::Int],
+            )),
+
+            // Loop indices
+            "i" | "j" => Ok((vec![], vec![StackType::Int])),
 
             // Other
             "negate" | "abs" => Ok((vec![StackType::Int], vec![StackType::Int])),
@@ -319,44 +404,298 @@ impl TypeInference {
 
     /// Infer types for a sequence of words
     pub fn infer_sequence(&mut self, words: &[Word]) -> Result<(Vec<StackType>, Vec<StackType>)> {
-        let mut stack: Vec<StackType> = Vec::new();
-        let mut total_inputs = Vec::new();
+        let outer_inputs = std::mem::take(&mut self.inputs);
+        let mut state = FlowState::default();
+        let result = self.flow(words, &mut state);
+        let inputs = std::mem::replace(&mut self.inputs, outer_inputs);
+        result?;
+
+        // Apply substitutions to resolve type variables
+        let resolved_inputs = inputs.iter().rev().map(|t| self.substitution.apply(t)).collect();
+        let resolved_outputs = self.resolve(&state.stack);
+
+        Ok((resolved_inputs, resolved_outputs))
+    }
 
+    /// Run a sequence over the abstract stack, recording each word's state when tracing
+    fn flow(&mut self, words: &[Word], state: &mut FlowState) -> Result<()> {
         for word in words {
-            let (inputs, outputs) = self.infer_word(word)?;
-
-            // Pop inputs from stack
-            if stack.len() < inputs.len() {
-                // Need more inputs from outside
-                let needed = inputs.len() - stack.len();
-                for _ in 0..needed {
-                    let var = self.env.fresh_var();
-                    total_inputs.push(var.clone());
-                    stack.push(var);
+            let label = match word {
+                Word::If { then_branch, else_branch } => {
+                    self.apply(state, &[StackType::Bool], &[])?;
+                    self.nesting += 1;
+                    let mut then_state = state.clone();
+                    let mut else_state = state.clone();
+                    let result = self.flow(then_branch, &mut then_state).and_then(|()| match else_branch {
+                        Some(else_words) => self.flow(else_words, &mut else_state),
+                        None => Ok(()),
+                    });
+                    self.nesting -= 1;
+                    result?;
+                    *state = self.join(&then_state, &else_state)?;
+                    "then".to_string()
+                }
+                Word::BeginUntil { body } => {
+                    self.flow_loop(state, body, true, &[])?;
+                    "until".to_string()
+                }
+                Word::BeginWhileRepeat { condition, body } => {
+                    self.flow_loop(state, condition, true, body)?;
+                    "repeat".to_string()
                 }
+                Word::DoLoop { body, .. } => {
+                    self.apply(state, &[StackType::Int, StackType::Int], &[])?;
+                    self.flow_loop(state, body, false, &[])?;
+                    "loop".to_string()
+                }
+                Word::Comment(_) => continue,
+                _ => {
+                    let (inputs, outputs) = self.infer_word(word)?;
+                    self.apply(state, &inputs, &outputs).map_err(|error| self.field_error(word, error))?;
+                    Self::label(word)
+                }
+            };
+
+            if let Some(trace) = &mut self.trace {
+                trace.push(StackState {
+                    word: label,
+                    depth: self.nesting,
+                    stack: state.stack.clone(),
+                });
             }
+        }
+        Ok(())
+    }
+
+    /// Run a loop until the types at its head reach a fixpoint
+    ///
+    /// Each pass runs `head`, pops the exit flag if `flag` is set, then runs
+    /// `tail` back to the head. A body that changes the stack depth has no
+    /// fixpoint, so its first pass stands.
+    fn flow_loop(&mut self, state: &mut FlowState, head: &[Word], flag: bool, tail: &[Word]) -> Result<()> {
+        self.nesting += 1;
+        let result = self.loop_exit(state.clone(), head, flag, tail);
+        self.nesting -= 1;
+        *state = result?;
+        Ok(())
+    }
 
-            // Type check the inputs
-            for (expected, actual) in inputs.iter().zip(stack.iter().rev()).rev() {
-                self.unify(expected, actual)?;
+    fn loop_exit(&mut self, mut entry: FlowState, head: &[Word], flag: bool, tail: &[Word]) -> Result<FlowState> {
+        let mark = self.trace.as_ref().map_or(0, Vec::len);
+        let mut pass = 1;
+        loop {
+            if let Some(trace) = &mut self.trace {
+                trace.truncate(mark);
             }
 
-            // Remove consumed items
-            stack.truncate(stack.len().saturating_sub(inputs.len()));
+            let mut current = entry.clone();
+            self.flow(head, &mut current)?;
+            if flag {
+                self.apply(&mut current, &[StackType::Bool], &[])?;
+            }
+            let exit = current.clone();
+            self.flow(tail, &mut current)?;
 
-            // Add outputs
-            stack.extend(outputs);
+            match self.try_join(&entry, &current) {
+                Some(joined) if pass < MAX_LOOP_PASSES && !self.same_state(&joined, &entry) => {
+                    entry = joined;
+                    pass += 1;
+                }
+                _ => return Ok(exit),
+            }
         }
+    }
 
-        // Apply substitutions to resolve type variables
-        let resolved_inputs: Vec<_> = total_inputs.iter().map(|t| self.substitution.apply(t)).collect();
-        let resolved_outputs: Vec<_> = stack.iter().map(|t| self.substitution.apply(t)).collect();
+    /// Name the field accessor in a type error it caused
+    fn field_error(&self, word: &Word, error: ForthError) -> ForthError {
+        match (word, error) {
+            (Word::WordRef { name, location }, ForthError::TypeError { expected, found, .. })
+                if self.fields.contains_key(name.as_str()) =>
+            {
+                ForthError::TypeError { expected, found, context: Some(name.to_string()), location: Some(location.clone()) }
+            }
+            (_, error) => error,
+        }
+    }
 
-        Ok((resolved_inputs, resolved_outputs))
+    /// Take `inputs` off the stack, checking their types, and push `outputs`
+    fn apply(&mut self, state: &mut FlowState, inputs: &[StackType], outputs: &[StackType]) -> Result<()> {
+        let taken = self.take(state, inputs.len());
+        for (expected, actual) in inputs.iter().zip(taken.iter()) {
+            self.unify(expected, actual)?;
+        }
+        state.stack.extend_from_slice(outputs);
+        Ok(())
+    }
+
+    /// Pop the top `n` items, pulling inputs from the caller when the stack runs short
+    fn take(&mut self, state: &mut FlowState, n: usize) -> Vec<StackType> {
+        while state.stack.len() < n {
+            self.pull(state);
+        }
+        state.stack.split_off(state.stack.len() - n)
+    }
+
+    /// Pull the next caller input under the bottom of the stack
+    fn pull(&mut self, state: &mut FlowState) {
+        if state.consumed == self.inputs.len() {
+            let var = self.env.fresh_var();
+            self.inputs.push(var);
+        }
+        state.stack.insert(0, self.inputs[state.consumed].clone());
+        state.consumed += 1;
+    }
+
+    /// Merge two paths meeting at THEN
+    fn join(&mut self, a: &FlowState, b: &FlowState) -> Result<FlowState> {
+        let depth = |s: &FlowState| s.stack.len() as isize - s.consumed as isize;
+        self.try_join(a, b).ok_or_else(|| ForthError::TypeError {
+            expected: format!("net stack effect {:+}", depth(a)),
+            found: format!("net stack effect {:+}", depth(b)),
+            context: Some("IF branches".to_string()),
+            location: None,
+        })
+    }
+
+    /// Merge two paths slot by slot, or `None` if they leave different depths
+    fn try_join(&mut self, a: &FlowState, b: &FlowState) -> Option<FlowState> {
+        let (mut a, mut b) = (a.clone(), b.clone());
+        while a.consumed < b.consumed {
+            self.pull(&mut a);
+        }
+        while b.consumed < a.consumed {
+            self.pull(&mut b);
+        }
+        if a.stack.len() != b.stack.len() {
+            return None;
+        }
+
+        let stack = a.stack.iter().zip(b.stack.iter()).map(|(x, y)| self.lub(x, y)).collect();
+        Some(FlowState { stack, consumed: a.consumed })
+    }
+
+    /// Least upper bound of two types meeting at a join
+    fn lub(&mut self, a: &StackType, b: &StackType) -> StackType {
+        let a = self.substitution.apply(a);
+        let b = self.substitution.apply(b);
+        if a == b {
+            return a;
+        }
+
+        match (&a, &b) {
+            (StackType::Unknown, _) | (_, StackType::Unknown) => StackType::Unknown,
+            (StackType::Var(_), _) | (_, StackType::Var(_)) => match self.unify(&a, &b) {
+                Ok(()) => self.substitution.apply(&a),
+                Err(_) => StackType::Unknown,
+            },
+            // Addresses of different records are still addresses
+            (
+                StackType::Record(_) | StackType::Addr,
+                StackType::Record(_) | StackType::Addr,
+            ) => StackType::Addr,
+            // Flags and characters are cells
+            (
+                StackType::Int | StackType::Bool | StackType::Char,
+                StackType::Int | StackType::Bool | StackType::Char,
+            ) => StackType::Int,
+            // For example an address on one path and a zero on the other
+            _ => StackType::Unknown,
+        }
+    }
+
+    fn same_state(&self, a: &FlowState, b: &FlowState) -> bool {
+        a.consumed == b.consumed && self.resolve(&a.stack) == self.resolve(&b.stack)
+    }
+
+    fn resolve(&self, types: &[StackType]) -> Vec<StackType> {
+        types.iter().map(|t| self.substitution.apply(t)).collect()
+    }
+
+    /// Copy a recorded signature with fresh type variables
+    fn instantiate(
+        &mut self,
+        inputs: &[StackType],
+        outputs: &[StackType],
+    ) -> (Vec<StackType>, Vec<StackType>) {
+        let mut fresh = FxHashMap::default();
+        let mut rename = |ty: &StackType, env: &mut TypeEnv| match ty {
+            StackType::Var(TypeVar { id, .. }) => {
+                fresh.entry(*id).or_insert_with(|| env.fresh_var()).clone()
+            }
+            _ => ty.clone(),
+        };
+        let inputs = inputs.iter().map(|t| rename(t, &mut self.env)).collect();
+        let outputs = outputs.iter().map(|t| rename(t, &mut self.env)).collect();
+        (inputs, outputs)
+    }
+
+    fn label(word: &Word) -> String {
+        match word {
+            Word::IntLiteral(value) => value.to_string(),
+            Word::FloatLiteral(value) => value.to_string(),
+            Word::StringLiteral(text) => format!("s\" {}\"", text),
+            Word::WordRef { name, .. } => name.to_string(),
+            Word::TaskSpawn { word, .. } => format!("task: {}", word),
+            Word::Locals { args, uninitialized, .. } if uninitialized.is_empty() => format!("{{: {} :}}", names(args)),
+            Word::Locals { args, uninitialized, .. } => format!("{{: {} | {} :}}", names(args), names(uninitialized)),
+            Word::LocalRef { name, .. } => name.to_string(),
+            Word::LocalStore { name, .. } => format!("to {}", name),
+            Word::Quotation { name, .. } => name.to_string(),
+            Word::Variable { name, record: Some(record), .. } => format!("create {} {} allot", name, record),
+            Word::Variable { name, .. } => format!("variable {}", name),
+            Word::Constant { name, .. } => format!("constant {}", name),
+            _ => String::new(),
+        }
     }
 
     /// Infer types for a definition
+    ///
+    /// The signature is kept, so later definitions calling this one are
+    /// typed by it rather than as unknown words.
     pub fn infer_definition(&mut self, def: &Definition) -> Result<(Vec<StackType>, Vec<StackType>)> {
+        // Record words are typed by the layout, not by their address arithmetic
+        if self.declared.contains(&def.name) {
+            if let Some(signature) = self.signatures.get(&def.name) {
+                return Ok(signature.clone());
+            }
+        }
+
+        let (inputs, outputs) = self.check_definition(def)?;
+        self.signatures
+            .insert(def.name.clone(), (inputs.clone(), outputs.clone()));
+        Ok((inputs, outputs))
+    }
+
+    /// Infer types for a definition along with the stack after each word
+    pub fn trace_definition(&mut self, def: &Definition) -> Result<TypedDefinition> {
+        self.trace = Some(Vec::new());
+        let result = self.infer_definition(def);
+        self.finish_trace(result)
+    }
+
+    /// Infer types for a sequence along with the stack after each word
+    pub fn trace_sequence(&mut self, words: &[Word]) -> Result<TypedDefinition> {
+        self.trace = Some(Vec::new());
+        let result = self.infer_sequence(words);
+        self.finish_trace(result)
+    }
+
+    fn finish_trace(&mut self, result: Result<(Vec<StackType>, Vec<StackType>)>) -> Result<TypedDefinition> {
+        let states = self.trace.take().unwrap_or_default();
+        let (inputs, outputs) = result?;
+
+        let states = states
+            .into_iter()
+            .map(|state| StackState {
+                stack: self.resolve(&state.stack),
+                ..state
+            })
+            .collect();
+        Ok(TypedDefinition { inputs, outputs, states })
+    }
+
+    fn check_definition(&mut self, def: &Definition) -> Result<(Vec<StackType>, Vec<StackType>)> {
+        self.locals.clear();
         // If stack effect is declared, use it as a constraint
         if let Some(effect) = &def.stack_effect {
             // For empty bodies, trust the declaration (useful for identity functions, stubs, etc.)
@@ -385,7 +724,8 @@ impl TypeInference {
                             .collect::<Vec<_>>()
                             .join(" ")
                     ),
-                    location: Some(def.name.clone()),
+                    context: Some(def.name.clone()),
+                    location: None,
                 });
             }
 
@@ -406,15 +746,80 @@ impl TypeInference {
         }
     }
 
+    /// Learn the types of the words a program declares rather than defines
+    ///
+    /// Variables and constants are typed by what they push, and the size
+    /// words and field accessors of records by their layout.
+    pub fn declare(&mut self, program: &Program) {
+        for word in &program.top_level_code {
+            match word {
+                Word::Variable { name, record, .. } => {
+                    let ty = record.clone().map_or(StackType::Addr, StackType::Record);
+                    self.signatures.insert(name.clone(), (vec![], vec![ty]));
+                }
+                Word::Constant { name, .. } => {
+                    self.signatures.insert(name.clone(), (vec![], vec![StackType::Int]));
+                }
+                _ => {}
+            }
+        }
+
+        for record in &program.records {
+            self.signatures.insert(record.name.clone(), (vec![], vec![StackType::Int]));
+            self.declared.insert(record.name.clone());
+            for field in &record.fields {
+                let output = field.record.clone().map_or(StackType::Addr, StackType::Record);
+                self.signatures.insert(
+                    field.name.clone(),
+                    (vec![StackType::Record(record.name.clone())], vec![output]),
+                );
+                self.declared.insert(field.name.clone());
+                self.fields.insert(field.name.clone(), record.name.clone());
+            }
+        }
+    }
+
+    /// Uses of a field accessor on something other than its record, and
+    /// records passed where another record is declared
+    ///
+    /// Only these errors are reported: inference is stricter than Forth
+    /// about other cells, so a program's other type errors are left alone.
+    pub fn check_records(program: &Program) -> Vec<ForthError> {
+        if program.records.is_empty() {
+            return Vec::new();
+        }
+
+        let mut inference = Self::new();
+        inference.declare(program);
+        let mut results: Vec<_> = program
+            .compiled_definitions()
+            .map(|def| inference.infer_definition(def).map_err(|e| e.at(&def.location)))
+            .collect();
+        results.push(inference.infer_sequence(&program.top_level_code));
+
+        let is_record = |name: &str| program.records.iter().any(|record| record.name == name);
+        results
+            .into_iter()
+            .filter_map(|result| result.err())
+            .filter(|error| match error {
+                ForthError::TypeError { context: Some(word), .. } if inference.fields.contains_key(word) => true,
+                // One record where another was declared
+                ForthError::TypeError { expected, found, .. } => is_record(expected) && is_record(found),
+                _ => false,
+            })
+            .collect()
+    }
+
     /// Analyze an entire program
     pub fn analyze_program(
         &mut self,
         program: &Program,
     ) -> Result<HashMap<String, (Vec<StackType>, Vec<StackType>)>> {
         let mut types = HashMap::new();
+        self.declare(program);
 
-        for def in &program.definitions {
-            let (inputs, outputs) = self.infer_definition(def)?;
+        for def in program.compiled_definitions() {
+            let (inputs, outputs) = self.infer_definition(def).map_err(|e| e.at(&def.location))?;
             types.insert(def.name.clone(), (inputs, outputs));
         }
 
@@ -422,6 +827,11 @@ impl TypeInference {
     }
 }
 
+/// Local names separated by spaces
+fn names(locals: &[Symbol]) -> String {
+    locals.iter().map(|name| name.as_str()).collect::<Vec<_>>().join(" ")
+}
+
 impl Default for TypeInference {
     fn default() -> Self {
         Self::new()
@@ -439,7 +849,7 @@ mod tests {
             Word::IntLiteral(2),
             Word::IntLiteral(3),
             Word::WordRef {
-                name: "+".to_string(),
+                name: "+".into(),
                 location: SourceLocation::default(),
             },
         ];
@@ -454,7 +864,7 @@ mod tests {
     fn test_infer_polymorphic() {
         let mut inference = TypeInference::new();
         let words = vec![Word::WordRef {
-            name: "dup".to_string(),
+            name: "dup".into(),
             location: SourceLocation::default(),
         }];
 
@@ -471,12 +881,12 @@ mod tests {
             Word::IntLiteral(1),
             Word::IntLiteral(2),
             Word::WordRef {
-                name: "<".to_string(),
+                name: "<".into(),
                 location: SourceLocation::default(),
             },
             Word::IntLiteral(3),
             Word::WordRef {
-                name: "+".to_string(),
+                name: "+".into(),
                 location: SourceLocation::default(),
             },
         ];
@@ -486,4 +896,119 @@ mod tests {
         // In ANS Forth, booleans are just integers, so this is actually valid
         assert!(result.is_ok());
     }
+
+    fn definition(source: &str) -> Definition {
+        crate::parser::parse_program(source).unwrap().definitions.remove(0)
+    }
+
+    #[test]
+    fn test_branch_that_takes_no_inputs_of_its_own() {
+        let mut inference = TypeInference::new();
+        let (inputs, outputs) = inference
+            .infer_definition(&definition(": abs' dup 0 < if negate then ;"))
+            .unwrap();
+        assert_eq!(inputs, vec![StackType::Int]);
+        assert_eq!(outputs, vec![StackType::Int]);
+    }
+
+    #[test]
+    fn test_store_takes_value_then_address() {
+        let mut inference = TypeInference::new();
+        let (inputs, outputs) = inference.infer_definition(&definition(": put ! ;")).unwrap();
+        assert_eq!(inputs, vec![StackType::Int, StackType::Addr]);
+        assert!(outputs.is_empty());
+    }
+
+    #[test]
+    fn test_join_takes_least_upper_bound() {
+        let mut inference = TypeInference::new();
+        let (_, outputs) = inference
+            .infer_definition(&definition(": pick-flag if 1 2 < else 7 then ;"))
+            .unwrap();
+        assert_eq!(outputs, vec![StackType::Int], "a flag and a number meet as a cell");
+
+        let (_, outputs) = inference
+            .infer_definition(&definition(": maybe if 10 allocate drop else 0 then ;"))
+            .unwrap();
+        assert_eq!(outputs, vec![StackType::Unknown], "an address and a number have no common type");
+
+        let (_, outputs) = inference
+            .infer_definition(&definition(": same if 1 2 < else 3 4 > then ;"))
+            .unwrap();
+        assert_eq!(outputs, vec![StackType::Bool]);
+    }
+
+    #[test]
+    fn test_unbalanced_branches() {
+        let mut inference = TypeInference::new();
+        let error = inference.infer_definition(&definition(": bad if 1 then ;")).unwrap_err();
+        assert!(matches!(error, ForthError::TypeError { .. }), "{error}");
+    }
+
+    #[test]
+    fn test_loop_reaches_fixpoint() {
+        let mut inference = TypeInference::new();
+        let (inputs, outputs) = inference
+            .infer_definition(&definition(": count-down begin 1 - dup 0 = until ;"))
+            .unwrap();
+        assert_eq!(inputs, vec![StackType::Int]);
+        assert_eq!(outputs, vec![StackType::Int]);
+
+        let (inputs, outputs) = inference
+            .infer_definition(&definition(": sum 0 swap 0 do i + loop ;"))
+            .unwrap();
+        assert_eq!(inputs, vec![StackType::Int]);
+        assert_eq!(outputs, vec![StackType::Int]);
+    }
+
+    #[test]
+    fn test_calls_use_earlier_signatures() {
+        let mut inference = TypeInference::new();
+        inference.infer_definition(&definition(": get @ ;")).unwrap();
+        let (inputs, outputs) = inference
+            .infer_definition(&definition(": zero? get 0 = ;"))
+            .unwrap();
+        assert_eq!(inputs, vec![StackType::Addr]);
+        assert_eq!(outputs, vec![StackType::Bool]);
+    }
+
+    #[test]
+    fn test_trace_records_each_state() {
+        let mut inference = TypeInference::new();
+        let typed = inference
+            .trace_definition(&definition(": clamp dup 0 < if drop 0 then ;"))
+            .unwrap();
+
+        let words: Vec<_> = typed.states.iter().map(|state| state.word.as_str()).collect();
+        assert_eq!(words, ["dup", "0", "<", "drop", "0", "then"]);
+        assert_eq!(typed.states[2].stack, vec![StackType::Int, StackType::Bool]);
+        assert_eq!(typed.states[3].depth, 1);
+        assert_eq!(typed.states[5].stack, vec![StackType::Int]);
+        assert_eq!(typed.outputs, vec![StackType::Int]);
+    }
+
+    #[test]
+    fn test_field_access_checks_record() {
+        let source = "struct point field .x field .y end-struct\n\
+                      struct line point field .from point field .to end-struct\n\
+                      create origin point allot create diagonal line allot\n\
+                      : x@ ( point -- n ) .x @ ;\n\
+                      : end-x ( line -- n ) .to x@ ;\n\
+                      : heap-x ( -- n ) 16 allocate drop .x @ ;\n\
+                      origin x@ diagonal end-x";
+        let program = crate::parser::parse_program(source).unwrap();
+        assert!(TypeInference::check_records(&program).is_empty());
+
+        let mut inference = TypeInference::new();
+        let types = inference.analyze_program(&program).unwrap();
+        assert_eq!(types[".from"].1, vec![StackType::Record("point".into())]);
+        assert_eq!(types["end-x"].0, vec![StackType::Record("line".into())]);
+
+        for misuse in ["diagonal .y", "5 .x", ": f ( line -- n ) .x @ ;", ": g .from .from ;"] {
+            let program = crate::parser::parse_program(&format!("{} {}", source, misuse)).unwrap();
+            let errors = TypeInference::check_records(&program);
+            assert_eq!(errors.len(), 1, "{misuse}");
+            assert!(errors[0].location().is_some(), "{misuse}");
+        }
+    }
 }
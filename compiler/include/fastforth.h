@@ -0,0 +1,83 @@
+/**
+ * Fast Forth C API
+ *
+ * Embed the compiler in non-Rust hosts. Build the library with
+ *
+ *     cargo rustc --release --lib --crate-type cdylib     (libfastforth.so)
+ *     cargo rustc --release --lib --crate-type staticlib  (libfastforth.a)
+ *
+ * Every call returns a status code; on FASTFORTH_ERROR the compiler keeps a
+ * structured error as JSON, read with fastforth_last_error(). A compiler
+ * must not be used from two threads at once.
+ *
+ * The ABI only grows: functions are added, never changed or removed, and
+ * FASTFORTH_ABI_VERSION is bumped when they are.
+ */
+
+#ifndef FASTFORTH_H
+#define FASTFORTH_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define FASTFORTH_ABI_VERSION 1
+
+// ============================================================================
+// STATUS CODES
+// ============================================================================
+
+#define FASTFORTH_OK 0                // The call succeeded
+#define FASTFORTH_ERROR 1             // Compilation or execution failed
+#define FASTFORTH_INVALID_ARGUMENT 2  // Null pointer or non-UTF-8 source
+#define FASTFORTH_STACK_OVERFLOW 3    // Result stack larger than the buffer
+
+typedef struct FastForthCompiler fastforth_compiler;
+
+// ABI version of the loaded library; compare with FASTFORTH_ABI_VERSION
+uint32_t fastforth_abi_version(void);
+
+// ============================================================================
+// COMPILER LIFECYCLE
+// ============================================================================
+
+// Create a compiler at optimization level 0 (none) to 3 (aggressive)
+fastforth_compiler *fastforth_compiler_new(int opt_level);
+
+// Free a compiler; NULL is ignored
+void fastforth_compiler_free(fastforth_compiler *compiler);
+
+// ============================================================================
+// COMPILATION AND EXECUTION
+// ============================================================================
+
+// Compile NUL-terminated source to a relocatable object file. On success the
+// caller owns *object (*length bytes) and frees it with fastforth_buffer_free.
+int fastforth_compile_object(fastforth_compiler *compiler, const char *source,
+                             uint8_t **object, size_t *length);
+
+// Free a buffer returned by fastforth_compile_object; NULL is ignored
+void fastforth_buffer_free(uint8_t *data, size_t length);
+
+// JIT-compile and run source, copying the resulting data stack into stack,
+// bottom first. *depth is set to the number of cells left; if it exceeds
+// capacity nothing is copied and FASTFORTH_STACK_OVERFLOW is returned.
+int fastforth_run(fastforth_compiler *compiler, const char *source,
+                  int64_t *stack, size_t capacity, size_t *depth);
+
+// ============================================================================
+// ERRORS
+// ============================================================================
+
+// The last error as a JSON structured error, or NULL if the last call
+// succeeded. Owned by the compiler; valid until its next call.
+const char *fastforth_last_error(const fastforth_compiler *compiler);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif // FASTFORTH_H
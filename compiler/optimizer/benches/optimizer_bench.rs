@@ -127,7 +127,7 @@ fn bench_inlining(c: &mut Criterion) {
         "cube".to_string(),
         vec![
             Instruction::Dup,
-            Instruction::Call("square".to_string()),
+            Instruction::Call("square".into()),
             Instruction::Mul,
         ],
     );
@@ -135,7 +135,7 @@ fn bench_inlining(c: &mut Criterion) {
 
     ir.main = vec![
         Instruction::Literal(5),
-        Instruction::Call("cube".to_string()),
+        Instruction::Call("cube".into()),
     ];
 
     for level in [OptimizationLevel::Basic, OptimizationLevel::Standard, OptimizationLevel::Aggressive].iter() {
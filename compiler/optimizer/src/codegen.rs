@@ -6,7 +6,7 @@
 //! - C (for static compilation)
 //! - Assembly (for maximum control)
 
-use crate::ir::{ForthIR, Instruction, WordDef};
+use crate::ir::{ForthIR, Instruction, VectorOp, WordDef};
 use crate::Result;
 
 /// Code generation backend
@@ -59,7 +59,7 @@ impl CCodegen {
             Xor => "    NOS ^= TOS; DROP;".to_string(),
             Not => "    TOS = ~TOS;".to_string(),
             Shl => "    NOS <<= TOS; DROP;".to_string(),
-            Shr => "    NOS >>= TOS; DROP;".to_string(),
+            Shr => "    NOS = (ucell_t)NOS >> TOS; DROP;".to_string(),
 
             // Comparisons
             Eq => "    NOS = (NOS == TOS) ? -1 : 0; DROP;".to_string(),
@@ -72,6 +72,21 @@ impl CCodegen {
             ZeroLt => "    TOS = (TOS < 0) ? -1 : 0;".to_string(),
             ZeroGt => "    TOS = (TOS > 0) ? -1 : 0;".to_string(),
 
+            // Unsigned
+            ULt => "    NOS = ((ucell_t)NOS < (ucell_t)TOS) ? -1 : 0; DROP;".to_string(),
+            UGt => "    NOS = ((ucell_t)NOS > (ucell_t)TOS) ? -1 : 0; DROP;".to_string(),
+            UDiv => "    NOS = (ucell_t)NOS / (ucell_t)TOS; DROP;".to_string(),
+            UMod => "    NOS = (ucell_t)NOS % (ucell_t)TOS; DROP;".to_string(),
+            UMul => "    NOS = (ucell_t)NOS * (ucell_t)TOS; DROP;".to_string(),
+            UMulHigh => "    NOS = ((unsigned __int128)(ucell_t)NOS * (ucell_t)TOS) >> 64; DROP;".to_string(),
+
+            // Bit manipulation; the builtins are undefined for zero
+            Popcount => "    TOS = __builtin_popcountll(TOS);".to_string(),
+            Ctz => "    TOS = TOS ? __builtin_ctzll(TOS) : 64;".to_string(),
+            Clz => "    TOS = TOS ? __builtin_clzll(TOS) : 64;".to_string(),
+            Rotl => "    NOS = ((ucell_t)NOS << (TOS & 63)) | ((ucell_t)NOS >> (-TOS & 63)); DROP;".to_string(),
+            Rotr => "    NOS = ((ucell_t)NOS >> (TOS & 63)) | ((ucell_t)NOS << (-TOS & 63)); DROP;".to_string(),
+
             // Superinstructions (optimized)
             DupAdd => "    TOS = TOS + TOS;".to_string(),
             DupMul => "    TOS = TOS * TOS;".to_string(),
@@ -81,6 +96,14 @@ impl CCodegen {
             DivTwo => "    TOS >>= 1;".to_string(),
             LiteralAdd(n) => format!("    TOS += {};", n),
             LiteralMul(n) => format!("    TOS *= {};", n),
+            ExtractBits { shift, mask } => format!("    TOS = ((ucell_t)TOS >> {}) & {};", shift, mask),
+            Lookup(table) => {
+                let values: Vec<String> = table.values.iter().map(|value| value.to_string()).collect();
+                format!(
+                    "    {{ static const cell_t table[] = {{{}}}; ucell_t i = (ucell_t)TOS - {}ULL; TOS = i < {} ? table[i] : {}; }}",
+                    values.join(", "), table.base as u64, table.values.len(), table.default
+                )
+            }
 
             // Stack caching
             CachedDup { .. } => "    PUSH(TOS);".to_string(),
@@ -99,16 +122,54 @@ impl CCodegen {
             }
 
             // Memory operations
-            Load => "    TOS = *(cell_t*)TOS;".to_string(),
-            Store => "    *(cell_t*)TOS = NOS; sp -= 2;".to_string(),
+            Load => "    TOS = CELL_LE(*(cell_t*)TOS);".to_string(),
+            Store => "    *(cell_t*)TOS = CELL_LE(NOS); sp -= 2;".to_string(),
             Load8 => "    TOS = *(uint8_t*)TOS;".to_string(),
             Store8 => "    *(uint8_t*)TOS = (uint8_t)NOS; sp -= 2;".to_string(),
+            Load16 => "    TOS = forth_load_le((uint8_t*)TOS, 2);".to_string(),
+            Store16 => "    forth_store_le((uint8_t*)TOS, NOS, 2); sp -= 2;".to_string(),
+            Load32 => "    TOS = forth_load_le((uint8_t*)TOS, 4);".to_string(),
+            Store32 => "    forth_store_le((uint8_t*)TOS, NOS, 4); sp -= 2;".to_string(),
 
             // Return stack
             ToR => "    *rsp++ = TOS; DROP;".to_string(),
             FromR => "    PUSH(*--rsp);".to_string(),
             RFetch => "    PUSH(rsp[-1]);".to_string(),
 
+            // Vector kernels (bounds on top, arrays addressed below them)
+            VectorReduce { op, array } => {
+                let reduce = match op {
+                    VectorOp::Sub => "TOS - vec_reduce_add".to_string(),
+                    _ => format!("TOS {} vec_reduce_{}", c_operator(*op), kernel_name(*op)),
+                };
+                format!(
+                    "    {{ cell_t start = TOS, limit = NOS; sp -= 2; TOS = {}((const cell_t*)sp[-1 - {}], start, limit); }}",
+                    reduce, array
+                )
+            }
+            VectorMap { op, operand, array } => format!(
+                "    {{ cell_t start = TOS, limit = NOS; sp -= 2; vec_map_{}((cell_t*)sp[-1 - {}], {}, start, limit); }}",
+                kernel_name(*op), array, operand
+            ),
+            VectorZip { op, dest, src } => format!(
+                "    {{ cell_t start = TOS, limit = NOS; sp -= 2; vec_zip_{}((cell_t*)sp[-1 - {}], (const cell_t*)sp[-1 - {}], start, limit); }}",
+                kernel_name(*op), dest, src
+            ),
+
+            // Promoted variables
+            LocalFetch(slot) => format!("    PUSH(local{});", slot),
+            LocalStore(slot) => format!("    local{} = TOS; DROP;", slot),
+
+            // Learned superinstructions run their body inline
+            Fused(body) => {
+                let mut code = String::from("    /* superinstruction */");
+                for inst in body {
+                    code.push('\n');
+                    code.push_str(&self.generate_instruction(inst));
+                }
+                code
+            }
+
             // Metadata
             Label(name) => format!("{}:", sanitize_name(name)),
             Comment(text) => format!("    /* {} */", text),
@@ -136,6 +197,7 @@ impl CodegenBackend for CCodegen {
 #include <stdbool.h>
 
 typedef int64_t cell_t;
+typedef uint64_t ucell_t;
 
 // Stack macros
 #define STACK_SIZE 256
@@ -150,9 +212,28 @@ static cell_t* rsp = rstack;
 #define PUSH(x) (*sp++ = (x))
 #define DROP (sp--)
 
+// Memory is little-endian whatever the target's byte order, cells included
+#if defined(__BYTE_ORDER__) && __BYTE_ORDER__ == __ORDER_BIG_ENDIAN__
+#define CELL_LE(x) ((cell_t)__builtin_bswap64((ucell_t)(x)))
+#else
+#define CELL_LE(x) (x)
+#endif
+static inline cell_t forth_load_le(const uint8_t* p, int n) {
+    ucell_t x = 0;
+    for (int i = 0; i < n; i++) x |= (ucell_t)p[i] << (8 * i);
+    return (cell_t)x;
+}
+static inline void forth_store_le(uint8_t* p, cell_t x, int n) {
+    for (int i = 0; i < n; i++) p[i] = (uint8_t)((ucell_t)x >> (8 * i));
+}
+
 "#,
         );
 
+        if uses_vector_kernels(ir) {
+            code.push_str(VECTOR_KERNELS);
+        }
+
         // Generate word definitions
         for word in ir.words.values() {
             code.push_str(&self.generate_word(word)?);
@@ -179,6 +260,20 @@ static cell_t* rsp = rstack;
             word.stack_effect
         ));
 
+        let mut locals: Vec<u8> = word
+            .instructions
+            .iter()
+            .filter_map(|inst| match inst {
+                Instruction::LocalFetch(slot) | Instruction::LocalStore(slot) => Some(*slot),
+                _ => None,
+            })
+            .collect();
+        locals.sort_unstable();
+        locals.dedup();
+        for slot in locals {
+            code.push_str(&format!("    cell_t local{} = 0;\n", slot));
+        }
+
         for inst in &word.instructions {
             code.push_str(&self.generate_instruction(inst));
             code.push('\n');
@@ -190,6 +285,129 @@ static cell_t* rsp = rstack;
     }
 }
 
+/// SIMD kernels for vectorized loops
+///
+/// AVX2 handles four cells per instruction and NEON two; other targets, and
+/// multiplication (which neither has for 64-bit lanes), use scalar loops.
+const VECTOR_KERNELS: &str = r#"
+// Vector kernels
+#if defined(__AVX2__)
+#include <immintrin.h>
+#define VEC_LANES 4
+typedef __m256i vec_t;
+#define VEC_LOAD(p) _mm256_loadu_si256((const __m256i*)(p))
+#define VEC_STORE(p, v) _mm256_storeu_si256((__m256i*)(p), (v))
+#define VEC_SPLAT(x) _mm256_set1_epi64x(x)
+#define VEC_add _mm256_add_epi64
+#define VEC_sub _mm256_sub_epi64
+#define VEC_and _mm256_and_si256
+#define VEC_or _mm256_or_si256
+#define VEC_xor _mm256_xor_si256
+#elif defined(__ARM_NEON)
+#include <arm_neon.h>
+#define VEC_LANES 2
+typedef int64x2_t vec_t;
+#define VEC_LOAD(p) vld1q_s64(p)
+#define VEC_STORE(p, v) vst1q_s64((p), (v))
+#define VEC_SPLAT(x) vdupq_n_s64(x)
+#define VEC_add vaddq_s64
+#define VEC_sub vsubq_s64
+#define VEC_and vandq_s64
+#define VEC_or vorrq_s64
+#define VEC_xor veorq_s64
+#endif
+
+#ifdef VEC_LANES
+#define VEC_REDUCE(name, op, identity) \
+static inline cell_t vec_reduce_##name(const cell_t* a, cell_t start, cell_t limit) { \
+    cell_t i = start, r = identity, lanes[VEC_LANES]; \
+    vec_t acc = VEC_SPLAT(identity); \
+    for (; i + VEC_LANES <= limit; i += VEC_LANES) acc = VEC_##name(acc, VEC_LOAD(a + i)); \
+    VEC_STORE(lanes, acc); \
+    for (int l = 0; l < VEC_LANES; l++) r = r op lanes[l]; \
+    for (; i < limit; i++) r = r op a[i]; \
+    return r; \
+}
+#define VEC_MAP(name, op) \
+static inline void vec_map_##name(cell_t* a, cell_t x, cell_t start, cell_t limit) { \
+    cell_t i = start; \
+    vec_t v = VEC_SPLAT(x); \
+    for (; i + VEC_LANES <= limit; i += VEC_LANES) VEC_STORE(a + i, VEC_##name(VEC_LOAD(a + i), v)); \
+    for (; i < limit; i++) a[i] = a[i] op x; \
+}
+#define VEC_ZIP(name, op) \
+static inline void vec_zip_##name(cell_t* d, const cell_t* s, cell_t start, cell_t limit) { \
+    cell_t i = start; \
+    for (; i + VEC_LANES <= limit; i += VEC_LANES) VEC_STORE(d + i, VEC_##name(VEC_LOAD(d + i), VEC_LOAD(s + i))); \
+    for (; i < limit; i++) d[i] = d[i] op s[i]; \
+}
+#else
+#define VEC_REDUCE(name, op, identity) SCALAR_REDUCE(name, op, identity)
+#define VEC_MAP(name, op) SCALAR_MAP(name, op)
+#define VEC_ZIP(name, op) SCALAR_ZIP(name, op)
+#endif
+
+#define SCALAR_REDUCE(name, op, identity) \
+static inline cell_t vec_reduce_##name(const cell_t* a, cell_t start, cell_t limit) { \
+    cell_t r = identity; \
+    for (cell_t i = start; i < limit; i++) r = r op CELL_LE(a[i]); \
+    return r; \
+}
+#define SCALAR_MAP(name, op) \
+static inline void vec_map_##name(cell_t* a, cell_t x, cell_t start, cell_t limit) { \
+    for (cell_t i = start; i < limit; i++) a[i] = CELL_LE(CELL_LE(a[i]) op x); \
+}
+#define SCALAR_ZIP(name, op) \
+static inline void vec_zip_##name(cell_t* d, const cell_t* s, cell_t start, cell_t limit) { \
+    for (cell_t i = start; i < limit; i++) d[i] = CELL_LE(CELL_LE(d[i]) op CELL_LE(s[i])); \
+}
+
+VEC_REDUCE(add, +, 0) VEC_REDUCE(and, &, -1) VEC_REDUCE(or, |, 0) VEC_REDUCE(xor, ^, 0)
+SCALAR_REDUCE(mul, *, 1)
+VEC_MAP(add, +) VEC_MAP(sub, -) VEC_MAP(and, &) VEC_MAP(or, |) VEC_MAP(xor, ^)
+SCALAR_MAP(mul, *)
+VEC_ZIP(add, +) VEC_ZIP(sub, -) VEC_ZIP(and, &) VEC_ZIP(or, |) VEC_ZIP(xor, ^)
+SCALAR_ZIP(mul, *)
+
+"#;
+
+/// Whether any code in the IR calls a vector kernel
+fn uses_vector_kernels(ir: &ForthIR) -> bool {
+    ir.main
+        .iter()
+        .chain(ir.words.values().flat_map(|word| &word.instructions))
+        .any(|inst| {
+            matches!(
+                inst,
+                Instruction::VectorReduce { .. } | Instruction::VectorMap { .. } | Instruction::VectorZip { .. }
+            )
+        })
+}
+
+/// Suffix of the C kernels for an operation
+fn kernel_name(op: VectorOp) -> &'static str {
+    match op {
+        VectorOp::Add => "add",
+        VectorOp::Sub => "sub",
+        VectorOp::Mul => "mul",
+        VectorOp::And => "and",
+        VectorOp::Or => "or",
+        VectorOp::Xor => "xor",
+    }
+}
+
+/// C operator for an operation
+fn c_operator(op: VectorOp) -> &'static str {
+    match op {
+        VectorOp::Add => "+",
+        VectorOp::Sub => "-",
+        VectorOp::Mul => "*",
+        VectorOp::And => "&",
+        VectorOp::Or => "|",
+        VectorOp::Xor => "^",
+    }
+}
+
 /// Sanitize word name for C identifier
 fn sanitize_name(name: &str) -> String {
     let mut result = name.replace(|c: char| !c.is_alphanumeric() && c != '_', "_");
@@ -232,6 +450,38 @@ mod tests {
         assert!(code.contains("TOS"));
     }
 
+    #[test]
+    fn test_c_codegen_vector_kernels() {
+        let mut codegen = CCodegen::new();
+        let mut ir = ForthIR::new();
+        ir.add_word(WordDef::new(
+            "sum".to_string(),
+            vec![Instruction::VectorReduce { op: VectorOp::Add, array: 1 }],
+        ));
+
+        let code = codegen.generate(&ir).unwrap();
+        assert!(code.contains("_mm256_add_epi64"));
+        assert!(code.contains("vaddq_s64"));
+        assert!(code.contains("TOS = TOS + vec_reduce_add((const cell_t*)sp[-1 - 1], start, limit);"));
+
+        let plain = codegen.generate(&ForthIR::parse("1 2 +").unwrap()).unwrap();
+        assert!(!plain.contains("vec_reduce_add"));
+    }
+
+    #[test]
+    fn test_c_codegen_locals() {
+        let mut codegen = CCodegen::new();
+        let word = WordDef::new(
+            "f".to_string(),
+            vec![Instruction::LocalStore(0), Instruction::LocalFetch(0), Instruction::Add],
+        );
+
+        let code = codegen.generate_word(&word).unwrap();
+        assert_eq!(code.matches("cell_t local0 = 0;").count(), 1);
+        assert!(code.contains("local0 = TOS; DROP;"));
+        assert!(code.contains("PUSH(local0);"));
+    }
+
     #[test]
     fn test_sanitize_name() {
         assert_eq!(sanitize_name("foo+bar"), "foo_bar");
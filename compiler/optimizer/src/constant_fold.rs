@@ -21,10 +21,14 @@
 //! - Comparison constant folding
 //! - Constant propagation through stack
 //! - Algebraic simplifications (x*0=0, x*1=x, x+0=x, etc.)
+//!
+//! Arithmetic folds under the compilation's [`Arithmetic`]; an operation
+//! that would trap at run time, such as division by zero, is left to trap.
 
 use crate::ir::{ForthIR, Instruction, WordDef};
 use crate::Result;
-use smallvec::{SmallVec, smallvec};
+use fastforth_frontend::{umul_high, Arithmetic};
+use smallvec::SmallVec;
 
 /// Value that can be tracked through constant propagation
 #[derive(Debug, Clone, PartialEq)]
@@ -68,8 +72,16 @@ impl AbstractStack {
         }
     }
 
-    fn depth(&self) -> usize {
-        self.stack.len()
+    /// Emit the constants not yet on the real stack, which from then on
+    /// are unknown values there
+    fn materialize(&mut self) -> SmallVec<[Instruction; 4]> {
+        let pending = self.stack.iter().rev().take_while(|value| value.as_constant().is_some()).count();
+        let start = self.stack.len() - pending;
+        let literals = self.stack[start..].iter().filter_map(Value::as_constant).map(Instruction::Literal).collect();
+        for value in &mut self.stack[start..] {
+            *value = Value::Unknown;
+        }
+        literals
     }
 }
 
@@ -77,11 +89,17 @@ impl AbstractStack {
 pub struct ConstantFolder {
     /// Enable aggressive algebraic simplifications
     aggressive: bool,
+    arithmetic: Arithmetic,
 }
 
 impl ConstantFolder {
     pub fn new() -> Self {
-        Self { aggressive: true }
+        Self::with_arithmetic(Arithmetic::default())
+    }
+
+    /// Fold arithmetic as code compiled under `arithmetic` computes it
+    pub fn with_arithmetic(arithmetic: Arithmetic) -> Self {
+        Self { aggressive: true, arithmetic }
     }
 
     /// Fold constants in IR
@@ -108,7 +126,7 @@ impl ConstantFolder {
     }
 
     /// Fold constants in an instruction sequence
-    fn fold_sequence(&self, instructions: &[Instruction]) -> Result<Vec<Instruction>> {
+    pub(crate) fn fold_sequence(&self, instructions: &[Instruction]) -> Result<Vec<Instruction>> {
         let mut result = Vec::new();
         let mut stack = AbstractStack::new();
 
@@ -120,177 +138,168 @@ impl ConstantFolder {
         }
 
         // Materialize any remaining constants on the stack
-        for value in stack.stack.iter() {
-            if let Some(v) = value.as_constant() {
-                result.push(Instruction::Literal(v));
-            }
-        }
+        result.extend(stack.materialize());
 
         Ok(result)
     }
 
     /// Fold a single instruction with abstract stack
+    ///
+    /// Constants on the abstract stack have not been emitted yet. They only
+    /// ever sit above the values already on the real stack, and are
+    /// materialized before any instruction that is emitted, so that
+    /// instruction finds its operands where it expects them.
     fn fold_instruction(
         &self,
         inst: &Instruction,
         stack: &mut AbstractStack,
     ) -> FoldResult {
         use Instruction::*;
+        let arithmetic = self.arithmetic;
 
         match inst {
             // Literals: push constant value onto abstract stack, don't emit yet
             Literal(v) => {
                 stack.push(Value::Constant(*v));
-                FoldResult::None  // Don't emit yet, will materialize at end if needed
+                FoldResult::None  // Don't emit yet, will materialize when needed
             }
 
             // Binary arithmetic operations
-            Add => self.fold_binary_op(stack, |a, b| a.wrapping_add(b), Add),
-            Sub => self.fold_binary_op(stack, |a, b| a.wrapping_sub(b), Sub),
-            Mul => self.fold_binary_op(stack, |a, b| a.wrapping_mul(b), Mul),
-            Div => {
-                // Check for division by zero
-                if let Value::Constant(0) = stack.peek(0) {
-                    // Division by zero - keep instruction for runtime error
-                    stack.pop();
-                    stack.pop();
-                    stack.push(Value::Unknown);
-                    FoldResult::Instructions(smallvec![Div])
-                } else {
-                    self.fold_binary_op(stack, |a, b| a.wrapping_div(b), Div)
-                }
-            }
-            Mod => {
-                if let Value::Constant(0) = stack.peek(0) {
-                    stack.pop();
-                    stack.pop();
-                    stack.push(Value::Unknown);
-                    FoldResult::Instructions(smallvec![Mod])
-                } else {
-                    self.fold_binary_op(stack, |a, b| a.wrapping_rem(b), Mod)
-                }
-            }
+            Add => self.fold_binary_op(stack, |a, b| arithmetic.add(a, b).ok(), Add),
+            Sub => self.fold_binary_op(stack, |a, b| arithmetic.sub(a, b).ok(), Sub),
+            Mul => self.fold_binary_op(stack, |a, b| arithmetic.mul(a, b).ok(), Mul),
+            // Division by zero and trapping overflow are kept for the runtime error
+            Div => self.fold_binary_op(stack, |a, b| arithmetic.div(a, b).ok(), Div),
+            Mod => self.fold_binary_op(stack, |a, b| arithmetic.modulo(a, b).ok(), Mod),
 
             // Bitwise operations
-            And => self.fold_binary_op(stack, |a, b| a & b, And),
-            Or => self.fold_binary_op(stack, |a, b| a | b, Or),
-            Xor => self.fold_binary_op(stack, |a, b| a ^ b, Xor),
-            Shl => self.fold_binary_op(stack, |a, b| a.wrapping_shl(b as u32), Shl),
-            Shr => self.fold_binary_op(stack, |a, b| a.wrapping_shr(b as u32), Shr),
+            And => self.fold_binary_op(stack, |a, b| Some(a & b), And),
+            Or => self.fold_binary_op(stack, |a, b| Some(a | b), Or),
+            Xor => self.fold_binary_op(stack, |a, b| Some(a ^ b), Xor),
+            Shl => self.fold_binary_op(stack, |a, b| Some(a.wrapping_shl(b as u32)), Shl),
+            // `rshift` is a logical shift
+            Shr => self.fold_binary_op(stack, |a, b| Some((a as u64).wrapping_shr(b as u32) as i64), Shr),
 
             // Unary operations
-            Neg => self.fold_unary_op(stack, |a| a.wrapping_neg(), Neg),
-            Abs => self.fold_unary_op(stack, |a| a.abs(), Abs),
-            Not => self.fold_unary_op(stack, |a| !a, Not),
+            Neg => self.fold_unary_op(stack, |a| Some(a.wrapping_neg()), Neg),
+            Abs => self.fold_unary_op(stack, |a| Some(a.wrapping_abs()), Abs),
+            Not => self.fold_unary_op(stack, |a| Some(!a), Not),
 
             // Comparison operations
-            Eq => self.fold_binary_op(stack, |a, b| if a == b { -1 } else { 0 }, Eq),
-            Ne => self.fold_binary_op(stack, |a, b| if a != b { -1 } else { 0 }, Ne),
-            Lt => self.fold_binary_op(stack, |a, b| if a < b { -1 } else { 0 }, Lt),
-            Le => self.fold_binary_op(stack, |a, b| if a <= b { -1 } else { 0 }, Le),
-            Gt => self.fold_binary_op(stack, |a, b| if a > b { -1 } else { 0 }, Gt),
-            Ge => self.fold_binary_op(stack, |a, b| if a >= b { -1 } else { 0 }, Ge),
-            ZeroEq => self.fold_unary_op(stack, |a| if a == 0 { -1 } else { 0 }, ZeroEq),
-            ZeroLt => self.fold_unary_op(stack, |a| if a < 0 { -1 } else { 0 }, ZeroLt),
-            ZeroGt => self.fold_unary_op(stack, |a| if a > 0 { -1 } else { 0 }, ZeroGt),
-
-            // Stack operations that preserve constants
-            Dup => {
-                let top = stack.peek(0);
-                stack.push(top.clone());
-                // If duplicating a constant, no need to emit Dup
-                if top.as_constant().is_some() {
+            Eq => self.fold_binary_op(stack, |a, b| Some(if a == b { -1 } else { 0 }), Eq),
+            Ne => self.fold_binary_op(stack, |a, b| Some(if a != b { -1 } else { 0 }), Ne),
+            Lt => self.fold_binary_op(stack, |a, b| Some(if a < b { -1 } else { 0 }), Lt),
+            Le => self.fold_binary_op(stack, |a, b| Some(if a <= b { -1 } else { 0 }), Le),
+            Gt => self.fold_binary_op(stack, |a, b| Some(if a > b { -1 } else { 0 }), Gt),
+            Ge => self.fold_binary_op(stack, |a, b| Some(if a >= b { -1 } else { 0 }), Ge),
+            ZeroEq => self.fold_unary_op(stack, |a| Some(if a == 0 { -1 } else { 0 }), ZeroEq),
+            ZeroLt => self.fold_unary_op(stack, |a| Some(if a < 0 { -1 } else { 0 }), ZeroLt),
+            ZeroGt => self.fold_unary_op(stack, |a| Some(if a > 0 { -1 } else { 0 }), ZeroGt),
+
+            // Unsigned operations, which never overflow
+            ULt => self.fold_binary_op(stack, |a, b| Some(if (a as u64) < (b as u64) { -1 } else { 0 }), ULt),
+            UGt => self.fold_binary_op(stack, |a, b| Some(if (a as u64) > (b as u64) { -1 } else { 0 }), UGt),
+            UDiv => self.fold_binary_op(stack, |a, b| (a as u64).checked_div(b as u64).map(|q| q as i64), UDiv),
+            UMod => self.fold_binary_op(stack, |a, b| (a as u64).checked_rem(b as u64).map(|r| r as i64), UMod),
+            UMul => self.fold_binary_op(stack, |a, b| Some(a.wrapping_mul(b)), UMul),
+            UMulHigh => self.fold_binary_op(stack, |a, b| Some(umul_high(a, b)), UMulHigh),
+
+            // Bit manipulation
+            Popcount => self.fold_unary_op(stack, |a| Some(a.count_ones() as i64), Popcount),
+            Ctz => self.fold_unary_op(stack, |a| Some(a.trailing_zeros() as i64), Ctz),
+            Clz => self.fold_unary_op(stack, |a| Some(a.leading_zeros() as i64), Clz),
+            Rotl => self.fold_binary_op(stack, |a, n| Some(a.rotate_left(n as u32)), Rotl),
+            Rotr => self.fold_binary_op(stack, |a, n| Some(a.rotate_right(n as u32)), Rotr),
+
+            // Stack operations on constants need no code
+            Dup => match stack.peek(0) {
+                Value::Constant(v) => {
+                    stack.push(Value::Constant(v));
                     FoldResult::None
-                } else {
-                    FoldResult::Instructions(smallvec![Dup])
-                }
-            }
-
-            Drop => {
-                stack.pop();
-                FoldResult::None // Drop can be eliminated if value is unused
-            }
-
-            Swap => {
-                if stack.depth() >= 2 {
-                    let a = stack.pop();
-                    let b = stack.pop();
-                    // If both are constants, no need to emit Swap
-                    let both_const = a.as_constant().is_some() && b.as_constant().is_some();
-                    stack.push(a);
-                    stack.push(b);
-                    if both_const {
-                        FoldResult::None
-                    } else {
-                        FoldResult::Instructions(smallvec![Swap])
-                    }
-                } else {
-                    FoldResult::Instructions(smallvec![Swap])
                 }
-            }
+                Value::Unknown => self.emit(stack, inst),
+            },
 
-            Over => {
-                let second = stack.peek(1);
-                let is_const = second.as_constant().is_some();
-                stack.push(second);
-                // If copying a constant, no need to emit Over
-                if is_const {
+            Drop => match stack.peek(0) {
+                Value::Constant(_) => {
+                    stack.pop();
                     FoldResult::None
-                } else {
-                    FoldResult::Instructions(smallvec![Over])
                 }
-            }
+                Value::Unknown => self.emit(stack, inst),
+            },
 
-            // Superinstructions
-            DupAdd => {
-                if let Some(v) = stack.peek(0).as_constant() {
+            Swap => match (stack.peek(1), stack.peek(0)) {
+                (Value::Constant(a), Value::Constant(b)) => {
                     stack.pop();
-                    let result = v.wrapping_add(v);
-                    stack.push(Value::Constant(result));
-                    FoldResult::None  // Don't emit yet, will materialize at end
-                } else {
                     stack.pop();
-                    stack.push(Value::Unknown);
-                    FoldResult::Instructions(smallvec![DupAdd])
+                    stack.push(Value::Constant(b));
+                    stack.push(Value::Constant(a));
+                    FoldResult::None
                 }
-            }
+                _ => self.emit(stack, inst),
+            },
 
-            DupMul => {
-                if let Some(v) = stack.peek(0).as_constant() {
+            Over => match (stack.peek(1), stack.peek(0)) {
+                (Value::Constant(a), Value::Constant(_)) => {
+                    stack.push(Value::Constant(a));
+                    FoldResult::None
+                }
+                _ => self.emit(stack, inst),
+            },
+
+            Rot => match (stack.peek(2), stack.peek(1), stack.peek(0)) {
+                (Value::Constant(a), Value::Constant(b), Value::Constant(c)) => {
+                    stack.pop();
                     stack.pop();
-                    let result = v.wrapping_mul(v);
-                    stack.push(Value::Constant(result));
-                    FoldResult::None  // Don't emit yet, will materialize at end
-                } else {
                     stack.pop();
-                    stack.push(Value::Unknown);
-                    FoldResult::Instructions(smallvec![DupMul])
+                    stack.push(Value::Constant(b));
+                    stack.push(Value::Constant(c));
+                    stack.push(Value::Constant(a));
+                    FoldResult::None
                 }
-            }
+                _ => self.emit(stack, inst),
+            },
 
-            IncOne => self.fold_unary_op(stack, |a| a.wrapping_add(1), IncOne),
-            DecOne => self.fold_unary_op(stack, |a| a.wrapping_sub(1), DecOne),
-            MulTwo => self.fold_unary_op(stack, |a| a.wrapping_shl(1), MulTwo),
-            DivTwo => self.fold_unary_op(stack, |a| a.wrapping_shr(1), DivTwo),
+            // Superinstructions
+            DupAdd => self.fold_unary_op(stack, |a| arithmetic.add(a, a).ok(), DupAdd),
+            DupMul => self.fold_unary_op(stack, |a| arithmetic.mul(a, a).ok(), DupMul),
+            IncOne => self.fold_unary_op(stack, |a| arithmetic.add(a, 1).ok(), IncOne),
+            DecOne => self.fold_unary_op(stack, |a| arithmetic.sub(a, 1).ok(), DecOne),
+            MulTwo => self.fold_unary_op(stack, |a| Some(a.wrapping_shl(1)), MulTwo),
+            DivTwo => self.fold_unary_op(stack, |a| Some(a.wrapping_shr(1)), DivTwo),
+            ExtractBits { shift, mask } => {
+                self.fold_unary_op(stack, |a| Some(((a as u64) >> shift) as i64 & mask), inst.clone())
+            }
+            Lookup(table) => self.fold_unary_op(stack, |a| Some(table.get(a)), inst.clone()),
 
             // Non-foldable instructions
-            _ => {
-                // Conservatively mark stack as unknown after non-pure operations
-                if !inst.is_pure() {
-                    stack.stack.clear();
-                } else {
-                    // Handle stack effect
-                    let effect = inst.stack_effect();
-                    for _ in 0..effect.consumed {
-                        stack.pop();
-                    }
-                    for _ in 0..effect.produced {
-                        stack.push(Value::Unknown);
-                    }
-                }
-                FoldResult::Instructions(smallvec![inst.clone()])
+            _ => self.emit(stack, inst),
+        }
+    }
+
+    /// Materialize pending constants, then emit `inst` with its stack effect
+    /// applied to the abstract stack
+    fn emit(&self, stack: &mut AbstractStack, inst: &Instruction) -> FoldResult {
+        let mut insts = stack.materialize();
+
+        // Conservatively mark stack as unknown after non-pure operations and
+        // where control flow joins or leaves
+        let barrier = !inst.is_pure()
+            || matches!(inst, Instruction::Label(_) | Instruction::Branch(_) | Instruction::BranchIf(_) | Instruction::BranchIfNot(_) | Instruction::Return);
+        if barrier {
+            stack.stack.clear();
+        } else {
+            let effect = inst.stack_effect();
+            for _ in 0..effect.consumed {
+                stack.pop();
+            }
+            for _ in 0..effect.produced {
+                stack.push(Value::Unknown);
             }
         }
+
+        insts.push(inst.clone());
+        FoldResult::Instructions(insts)
     }
 
     /// Fold binary operation if both operands are constant
@@ -301,23 +310,21 @@ impl ConstantFolder {
         fallback: Instruction,
     ) -> FoldResult
     where
-        F: FnOnce(i64, i64) -> i64,
+        F: FnOnce(i64, i64) -> Option<i64>,
     {
-        let b = stack.pop();
-        let a = stack.pop();
-
-        match (a.as_constant(), b.as_constant()) {
-            (Some(av), Some(bv)) => {
+        match (stack.peek(1).as_constant(), stack.peek(0).as_constant()) {
+            (Some(av), Some(bv)) => match op(av, bv) {
                 // Both constants: fold!
-                let result = op(av, bv);
-                stack.push(Value::Constant(result));
-                FoldResult::None  // Don't emit yet, will materialize at end
-            }
-            _ => {
-                // Not both constants: keep original instruction
-                stack.push(Value::Unknown);
-                FoldResult::Instructions(smallvec![fallback])
-            }
+                Some(result) => {
+                    stack.pop();
+                    stack.pop();
+                    stack.push(Value::Constant(result));
+                    FoldResult::None  // Don't emit yet, will materialize when needed
+                }
+                None => self.emit(stack, &fallback),
+            },
+            // Not both constants: keep original instruction
+            _ => self.emit(stack, &fallback),
         }
     }
 
@@ -329,22 +336,17 @@ impl ConstantFolder {
         fallback: Instruction,
     ) -> FoldResult
     where
-        F: FnOnce(i64) -> i64,
+        F: FnOnce(i64) -> Option<i64>,
     {
-        let a = stack.pop();
-
-        match a.as_constant() {
-            Some(av) => {
+        match stack.peek(0).as_constant().and_then(op) {
+            Some(result) => {
                 // Constant: fold!
-                let result = op(av);
+                stack.pop();
                 stack.push(Value::Constant(result));
-                FoldResult::None  // Don't emit yet, will materialize at end
-            }
-            None => {
-                // Not constant: keep original instruction
-                stack.push(Value::Unknown);
-                FoldResult::Instructions(smallvec![fallback])
+                FoldResult::None  // Don't emit yet, will materialize when needed
             }
+            // Not constant, or would trap: keep original instruction
+            None => self.emit(stack, &fallback),
         }
     }
 }
@@ -386,6 +388,24 @@ mod tests {
         assert!(matches!(folded.main[0], Instruction::Literal(20)));
     }
 
+    #[test]
+    fn test_keep_literals_below_unknown_values() {
+        let folder = ConstantFolder::new();
+        let mut ir = ForthIR::new();
+        ir.main = vec![
+            Instruction::Literal(-20),
+            Instruction::Call("w1".into()),
+            Instruction::Literal(8),
+            Instruction::Literal(0),
+            Instruction::Div,
+        ];
+
+        let folded = folder.fold(&ir).unwrap();
+
+        // Nothing here can be folded: the call may use -20, and 8 0 / traps
+        assert_eq!(folded.main, ir.main);
+    }
+
     #[test]
     fn test_fold_dup_add() {
         let folder = ConstantFolder::new();
@@ -415,7 +435,7 @@ mod tests {
         let mut ir = ForthIR::new();
         // foo is a call (produces unknown value)
         ir.main = vec![
-            Instruction::Call("foo".to_string()),
+            Instruction::Call("foo".into()),
             Instruction::Literal(5),
             Instruction::Add,
         ];
@@ -437,4 +457,54 @@ mod tests {
         assert_eq!(folded.main.len(), 1);
         assert!(matches!(folded.main[0], Instruction::Literal(3)));
     }
+
+    #[test]
+    fn test_fold_follows_the_arithmetic_semantics() {
+        use fastforth_frontend::{Division, Overflow};
+
+        let ir = ForthIR::parse("-7 2 / -7 2 mod").unwrap();
+        let folded = ConstantFolder::new().fold(&ir).unwrap();
+        assert_eq!(folded.main, [Instruction::Literal(-3), Instruction::Literal(-1)]);
+        let floored = Arithmetic { division: Division::Floored, ..Arithmetic::default() };
+        let folded = ConstantFolder::with_arithmetic(floored).fold(&ir).unwrap();
+        assert_eq!(folded.main, [Instruction::Literal(-4), Instruction::Literal(1)]);
+
+        // An overflow that traps at run time is left to trap
+        let mut ir = ForthIR::new();
+        ir.main = vec![Instruction::Literal(i64::MAX), Instruction::Literal(1), Instruction::Add];
+        let folded = ConstantFolder::new().fold(&ir).unwrap();
+        assert_eq!(folded.main, [Instruction::Literal(i64::MIN)]);
+        let trapping = Arithmetic { overflow: Overflow::Trapping, ..Arithmetic::default() };
+        let folded = ConstantFolder::with_arithmetic(trapping).fold(&ir).unwrap();
+        assert_eq!(folded.main, ir.main);
+    }
+
+    #[test]
+    fn test_fold_unsigned() {
+        let ir = ForthIR::parse("-1 1 u< -8 2 u/mod -1 3 um* 1 0 u/mod").unwrap();
+        let folded = ConstantFolder::new().fold(&ir).unwrap();
+        let literals = [0, 0, i64::MAX - 3, -3, 2, 1, 0].map(Instruction::Literal);
+        assert_eq!(&folded.main[..7], literals);
+        // Unsigned division by zero is left for the runtime
+        assert!(folded.main.contains(&Instruction::UDiv));
+    }
+
+    #[test]
+    fn test_fold_lookup() {
+        use fastforth_frontend::case::CaseTable;
+
+        let table = CaseTable { base: 1, values: vec![10, 20, 30], default: -1 };
+        let mut ir = ForthIR::new();
+        ir.main = vec![
+            Instruction::Literal(2),
+            Instruction::Lookup(table.clone()),
+            Instruction::Literal(4),
+            Instruction::Lookup(table.clone()),
+            Instruction::Call("key".into()),
+            Instruction::Lookup(table.clone()),
+        ];
+        let folded = ConstantFolder::new().fold(&ir).unwrap();
+        assert_eq!(&folded.main[..2], [Instruction::Literal(20), Instruction::Literal(-1)]);
+        assert_eq!(folded.main.last(), Some(&Instruction::Lookup(table)));
+    }
 }
@@ -0,0 +1,246 @@
+//! Superinstruction Learning from a Corpus
+//!
+//! The builtin pattern table of [`SuperinstructionOptimizer`] covers
+//! sequences common to most Forth code. Domain-specific code has hot
+//! sequences of its own; mining a corpus of it finds them.
+//!
+//! [`CorpusMiner`] inlines each program it is given, the same way the
+//! optimizer does before recognizing superinstructions, then counts every
+//! run of 2 to [`MAX_SEQUENCE_LENGTH`] builtin instructions. The result is
+//! a [`SuperinstructionTable`] ranked by dispatches saved, which the
+//! superinstruction pass can load at compile time.
+//!
+//! # Table Format
+//!
+//! One sequence per line: its count in the corpus, then the sequence as
+//! Forth words. `#` starts a comment.
+//!
+//! ```text
+//! # fastforth superinstruction table
+//! 1520 over + swap
+//! 312 over over <
+//! ```
+//!
+//! [`SuperinstructionOptimizer`]: crate::SuperinstructionOptimizer
+
+use crate::inline::InlineOptimizer;
+use crate::ir::{ForthIR, Instruction};
+use crate::{OptimizationLevel, OptimizerError, Result};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Longest sequence considered for a superinstruction
+pub const MAX_SEQUENCE_LENGTH: usize = 4;
+
+/// Shortest sequence considered for a superinstruction
+const MIN_SEQUENCE_LENGTH: usize = 2;
+
+/// A mined instruction sequence
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableEntry {
+    pub sequence: Vec<Instruction>,
+    /// Occurrences in the corpus
+    pub count: u64,
+}
+
+impl TableEntry {
+    /// Instruction dispatches saved by fusing every occurrence
+    pub fn benefit(&self) -> u64 {
+        self.count * (self.sequence.len() as u64 - 1)
+    }
+}
+
+/// Ranked superinstruction table, most beneficial sequence first
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SuperinstructionTable {
+    pub entries: Vec<TableEntry>,
+}
+
+impl SuperinstructionTable {
+    /// Parse a table in the text format written by `Display`
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = |message: &str| OptimizerError::ParseError(format!("line {}: {}", number + 1, message));
+            let mut words = line.split_whitespace();
+            let count = words
+                .next()
+                .and_then(|count| count.parse().ok())
+                .ok_or_else(|| error("expected a count"))?;
+            let sequence = words
+                .map(|word| parse_word(word).ok_or_else(|| error(&format!("'{}' is not a builtin word", word))))
+                .collect::<Result<Vec<_>>>()?;
+            if !(MIN_SEQUENCE_LENGTH..=MAX_SEQUENCE_LENGTH).contains(&sequence.len()) {
+                return Err(error(&format!(
+                    "sequences have {} to {} words",
+                    MIN_SEQUENCE_LENGTH, MAX_SEQUENCE_LENGTH
+                )));
+            }
+
+            entries.push(TableEntry { sequence, count });
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl fmt::Display for SuperinstructionTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# fastforth superinstruction table")?;
+        writeln!(f, "# count sequence")?;
+        for entry in &self.entries {
+            let words: Vec<String> = entry.sequence.iter().filter_map(Instruction::to_word).collect();
+            writeln!(f, "{} {}", entry.count, words.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_word(word: &str) -> Option<Instruction> {
+    Instruction::from_word(&word.to_lowercase()).or_else(|| word.parse().ok().map(Instruction::Literal))
+}
+
+/// Counts instruction sequences across a corpus of programs
+pub struct CorpusMiner {
+    inline: InlineOptimizer,
+    /// Occurrences of each sequence, keyed by its Forth spelling
+    counts: HashMap<Vec<String>, u64>,
+    programs: usize,
+}
+
+impl CorpusMiner {
+    pub fn new() -> Self {
+        Self {
+            inline: InlineOptimizer::new(OptimizationLevel::Standard),
+            counts: HashMap::new(),
+            programs: 0,
+        }
+    }
+
+    /// Add a program to the corpus
+    pub fn add(&mut self, ir: &ForthIR) -> Result<()> {
+        let inlined = self.inline.inline(ir)?;
+
+        self.count_sequence(&inlined.main);
+        for word in inlined.words.values() {
+            self.count_sequence(&word.instructions);
+        }
+        self.programs += 1;
+
+        Ok(())
+    }
+
+    /// Number of programs added
+    pub fn programs(&self) -> usize {
+        self.programs
+    }
+
+    /// Count the sequences of each run of builtin instructions
+    fn count_sequence(&mut self, instructions: &[Instruction]) {
+        let words: Vec<Option<String>> = instructions.iter().map(Instruction::to_word).collect();
+
+        for run in words.split(|word| word.is_none()) {
+            let run: Vec<String> = run.iter().flatten().cloned().collect();
+            for length in MIN_SEQUENCE_LENGTH..=MAX_SEQUENCE_LENGTH.min(run.len()) {
+                for window in run.windows(length) {
+                    *self.counts.entry(window.to_vec()).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    /// The `limit` most beneficial sequences seen at least `min_count` times
+    pub fn table(&self, min_count: u64, limit: usize) -> SuperinstructionTable {
+        let mut entries: Vec<TableEntry> = self
+            .counts
+            .iter()
+            .filter(|(_, count)| **count >= min_count)
+            .filter_map(|(words, count)| {
+                let sequence = words.iter().map(|word| parse_word(word)).collect::<Option<Vec<_>>>()?;
+                Some(TableEntry { sequence, count: *count })
+            })
+            .collect();
+
+        // Ties are broken by spelling so the table is reproducible
+        entries.sort_by(|a, b| {
+            b.benefit()
+                .cmp(&a.benefit())
+                .then_with(|| b.count.cmp(&a.count))
+                .then_with(|| spelling(a).cmp(&spelling(b)))
+        });
+        entries.truncate(limit);
+
+        SuperinstructionTable { entries }
+    }
+}
+
+impl Default for CorpusMiner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn spelling(entry: &TableEntry) -> Vec<String> {
+    entry.sequence.iter().filter_map(Instruction::to_word).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::WordDef;
+
+    fn program(words: &[(&str, Vec<Instruction>)]) -> ForthIR {
+        let mut ir = ForthIR::new();
+        for (name, instructions) in words {
+            ir.add_word(WordDef::new(name.to_string(), instructions.clone()));
+        }
+        ir
+    }
+
+    #[test]
+    fn test_miner_ranks_frequent_sequences() {
+        use Instruction::*;
+        let body = vec![Over, Add, Swap, Call("emit".into()), Over, Add, Swap, Return];
+
+        let mut miner = CorpusMiner::new();
+        miner.add(&program(&[("a", body.clone()), ("b", body)])).unwrap();
+        let table = miner.table(2, 3);
+
+        assert_eq!(miner.programs(), 1);
+        assert_eq!(table.entries[0], TableEntry { sequence: vec![Over, Add, Swap], count: 4 });
+        assert!(table.entries.iter().all(|entry| !entry.sequence.contains(&Return)));
+    }
+
+    #[test]
+    fn test_table_round_trips_through_text() {
+        let table = SuperinstructionTable {
+            entries: vec![
+                TableEntry { sequence: vec![Instruction::Over, Instruction::Add, Instruction::Swap], count: 40 },
+                TableEntry { sequence: vec![Instruction::Literal(-8), Instruction::And], count: 7 },
+            ],
+        };
+
+        assert_eq!(SuperinstructionTable::parse(&table.to_string()).unwrap(), table);
+    }
+
+    #[test]
+    fn test_table_rejects_unknown_words() {
+        assert!(SuperinstructionTable::parse("3 over frobnicate").is_err());
+        assert!(SuperinstructionTable::parse("over +").is_err());
+        assert!(SuperinstructionTable::parse("3 dup").is_err());
+    }
+}
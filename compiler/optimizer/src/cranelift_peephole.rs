@@ -2,13 +2,20 @@
 //!
 //! These optimizations transform IR patterns into forms that Cranelift
 //! can optimize better, achieving 5-15% performance improvement.
+//!
+//! User-defined [`PeepholeRules`] loaded from a rule file run first, so
+//! they see the code before the builtin rewrites change it.
 
 use crate::ir::{ForthIR, Instruction, WordDef};
+use crate::peephole_rules::PeepholeRules;
 use crate::{OptimizerError, Result};
+use fastforth_frontend::Arithmetic;
 
 /// Peephole optimizer for Cranelift backend
 pub struct CraneliftPeephole {
     stats: PeepholeStats,
+    rules: PeepholeRules,
+    arithmetic: Arithmetic,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -17,16 +24,47 @@ pub struct PeepholeStats {
     pub constant_folds: usize,
     pub comparison_chains: usize,
     pub dead_stores: usize,
+    /// Mask-and-shift idioms fused into bit instructions
+    pub bit_idioms: usize,
+    /// Rewrites made by user-defined rules
+    pub user_rules: usize,
     pub total_passes: usize,
 }
 
+impl PeepholeStats {
+    /// Rewrites made since an earlier snapshot of the same statistics
+    pub fn since(&self, earlier: &PeepholeStats) -> PeepholeStats {
+        PeepholeStats {
+            strength_reductions: self.strength_reductions - earlier.strength_reductions,
+            constant_folds: self.constant_folds - earlier.constant_folds,
+            comparison_chains: self.comparison_chains - earlier.comparison_chains,
+            dead_stores: self.dead_stores - earlier.dead_stores,
+            bit_idioms: self.bit_idioms - earlier.bit_idioms,
+            user_rules: self.user_rules - earlier.user_rules,
+            total_passes: self.total_passes - earlier.total_passes,
+        }
+    }
+}
+
 impl CraneliftPeephole {
     pub fn new() -> Self {
         Self {
             stats: PeepholeStats::default(),
+            rules: PeepholeRules::default(),
+            arithmetic: Arithmetic::default(),
         }
     }
 
+    /// Fold arithmetic as code compiled under `arithmetic` computes it
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
+    }
+
+    /// Apply user-defined rules before the builtin rewrites
+    pub fn set_rules(&mut self, rules: PeepholeRules) {
+        self.rules = rules;
+    }
+
     /// Get optimization statistics
     pub fn stats(&self) -> &PeepholeStats {
         &self.stats
@@ -58,8 +96,12 @@ impl CraneliftPeephole {
         while changed && iterations < MAX_ITERATIONS {
             changed = false;
 
+            let rewrites = self.rules.rewrite(instructions);
+            self.stats.user_rules += rewrites;
+            changed |= rewrites > 0;
             changed |= self.strength_reduction(instructions)?;
             changed |= self.fold_constants(instructions)?;
+            changed |= self.fuse_bit_idioms(instructions)?;
             changed |= self.chain_comparisons(instructions)?;
             changed |= self.eliminate_dead_stores(instructions)?;
 
@@ -157,40 +199,17 @@ impl CraneliftPeephole {
         let mut i = 0;
 
         while i < instructions.len().saturating_sub(2) {
-            match (&instructions[i], &instructions[i + 1], &instructions[i + 2]) {
-                // Binary arithmetic operations
-                (Instruction::Literal(a), Instruction::Literal(b), Instruction::Add) => {
-                    let result = a.wrapping_add(*b);
-                    instructions.splice(i..=i+2, vec![Instruction::Literal(result)]);
-                    self.stats.constant_folds += 1;
-                    changed = true;
-                    continue;
-                }
-
-                (Instruction::Literal(a), Instruction::Literal(b), Instruction::Sub) => {
-                    let result = a.wrapping_sub(*b);
-                    instructions.splice(i..=i+2, vec![Instruction::Literal(result)]);
-                    self.stats.constant_folds += 1;
-                    changed = true;
-                    continue;
-                }
-
-                (Instruction::Literal(a), Instruction::Literal(b), Instruction::Mul) => {
-                    let result = a.wrapping_mul(*b);
-                    instructions.splice(i..=i+2, vec![Instruction::Literal(result)]);
-                    self.stats.constant_folds += 1;
-                    changed = true;
-                    continue;
-                }
-
-                (Instruction::Literal(a), Instruction::Literal(b), Instruction::Div) if *b != 0 => {
-                    let result = a.wrapping_div(*b);
+            // Binary arithmetic operations, unless they would trap
+            if let (Instruction::Literal(a), Instruction::Literal(b)) = (&instructions[i], &instructions[i + 1]) {
+                if let Some(result) = self.fold_arithmetic(&instructions[i + 2], *a, *b) {
                     instructions.splice(i..=i+2, vec![Instruction::Literal(result)]);
                     self.stats.constant_folds += 1;
                     changed = true;
                     continue;
                 }
+            }
 
+            match (&instructions[i], &instructions[i + 1], &instructions[i + 2]) {
                 // Bitwise operations
                 (Instruction::Literal(a), Instruction::Literal(b), Instruction::And) => {
                     instructions.splice(i..=i+2, vec![Instruction::Literal(a & b)]);
@@ -223,7 +242,7 @@ impl CraneliftPeephole {
                 }
 
                 (Instruction::Literal(a), Instruction::Literal(b), Instruction::Shr) if *b >= 0 && *b < 64 => {
-                    let result = a.wrapping_shr(*b as u32);
+                    let result = (*a as u64).wrapping_shr(*b as u32) as i64;
                     instructions.splice(i..=i+2, vec![Instruction::Literal(result)]);
                     self.stats.constant_folds += 1;
                     changed = true;
@@ -267,6 +286,60 @@ impl CraneliftPeephole {
         Ok(changed)
     }
 
+    /// `a op b` for an arithmetic `op`, if it has a result
+    fn fold_arithmetic(&self, op: &Instruction, a: i64, b: i64) -> Option<i64> {
+        match op {
+            Instruction::Add => self.arithmetic.add(a, b).ok(),
+            Instruction::Sub => self.arithmetic.sub(a, b).ok(),
+            Instruction::Mul => self.arithmetic.mul(a, b).ok(),
+            Instruction::Div => self.arithmetic.div(a, b).ok(),
+            Instruction::Mod => self.arithmetic.modulo(a, b).ok(),
+            _ => None,
+        }
+    }
+
+    /// Fuse mask-and-shift idioms into single bit instructions
+    ///
+    /// Examples:
+    /// - Literal(n), Shr, Literal(m), And → ExtractBits { shift: n, mask: m }
+    /// - Dup, Literal(k), Shl, Swap, Literal(64 - k), Shr, Or → Literal(k), Rotl
+    fn fuse_bit_idioms(&mut self, instructions: &mut Vec<Instruction>) -> Result<bool> {
+        use Instruction::*;
+        let mut changed = false;
+        let mut i = 0;
+
+        while i < instructions.len() {
+            let fused = match &instructions[i..] {
+                [Literal(shift), Shr, Literal(mask), And, ..] if (0..64).contains(shift) => {
+                    Some((4, vec![ExtractBits { shift: *shift as u8, mask: *mask }]))
+                }
+
+                // Either half of a rotate may come first; the zero-cost pass
+                // may already have cached the stack operations
+                [Dup | CachedDup { .. }, Literal(a), first, Swap | CachedSwap { .. }, Literal(b), second, Or, ..]
+                    if *a > 0 && *b > 0 && a + b == 64 =>
+                {
+                    match (first, second) {
+                        (Shl, Shr) => Some((7, vec![Literal(*a), Rotl])),
+                        (Shr, Shl) => Some((7, vec![Literal(*b), Rotl])),
+                        _ => None,
+                    }
+                }
+
+                _ => None,
+            };
+
+            if let Some((len, replacement)) = fused {
+                instructions.splice(i..i + len, replacement);
+                self.stats.bit_idioms += 1;
+                changed = true;
+            }
+            i += 1;
+        }
+
+        Ok(changed)
+    }
+
     /// Chain comparison operations for better codegen
     ///
     /// Example:
@@ -407,6 +480,46 @@ mod tests {
         assert_eq!(word.instructions[0], Instruction::Literal(42));
     }
 
+    #[test]
+    fn test_bit_idioms_fuse() {
+        let mut peephole = CraneliftPeephole::new();
+        let mut word = create_test_word(vec![
+            Instruction::Literal(8),
+            Instruction::Shr,
+            Instruction::Literal(255),
+            Instruction::And,
+            Instruction::Dup,
+            Instruction::Literal(56),
+            Instruction::Shr,
+            Instruction::Swap,
+            Instruction::Literal(8),
+            Instruction::Shl,
+            Instruction::Or,
+        ]);
+
+        peephole.optimize_word(&mut word).unwrap();
+
+        assert_eq!(
+            word.instructions,
+            [Instruction::ExtractBits { shift: 8, mask: 255 }, Instruction::Literal(8), Instruction::Rotl]
+        );
+        assert_eq!(peephole.stats.bit_idioms, 2);
+    }
+
+    #[test]
+    fn test_constant_folding_rshift_is_logical() {
+        let mut peephole = CraneliftPeephole::new();
+        let mut word = create_test_word(vec![
+            Instruction::Literal(-1),
+            Instruction::Literal(60),
+            Instruction::Shr,
+        ]);
+
+        peephole.optimize_word(&mut word).unwrap();
+
+        assert_eq!(word.instructions, [Instruction::Literal(15)]);
+    }
+
     #[test]
     fn test_dead_store_elimination_dup_drop() {
         let mut peephole = CraneliftPeephole::new();
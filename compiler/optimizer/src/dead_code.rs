@@ -28,13 +28,6 @@ use crate::ir::{ForthIR, Instruction, WordDef};
 use crate::Result;
 use std::collections::HashSet;
 
-/// Liveness analysis result
-#[derive(Debug, Clone)]
-struct LivenessInfo {
-    /// Instructions that are live (their results are used)
-    live_instructions: HashSet<usize>,
-}
-
 /// Dead code eliminator
 pub struct DeadCodeEliminator {
     /// Enable aggressive elimination
@@ -63,7 +56,7 @@ impl DeadCodeEliminator {
     }
 
     /// Eliminate dead code in a word definition
-    fn eliminate_word(&self, word: &WordDef) -> Result<WordDef> {
+    pub(crate) fn eliminate_word(&self, word: &WordDef) -> Result<WordDef> {
         let mut optimized = word.clone();
         optimized.instructions = self.eliminate_sequence(&word.instructions)?;
         optimized.update();
@@ -73,94 +66,83 @@ impl DeadCodeEliminator {
     /// Eliminate dead code in an instruction sequence
     fn eliminate_sequence(&self, instructions: &[Instruction]) -> Result<Vec<Instruction>> {
         // First pass: remove trivial operations
-        let mut result = self.remove_trivial_ops(instructions);
-
-        // Perform liveness analysis on simplified code
-        let liveness = self.analyze_liveness(&result);
+        let result = self.remove_trivial_ops(instructions);
 
         // Remove dead instructions
-        let mut filtered = Vec::new();
-        for (i, inst) in result.iter().enumerate() {
-            if self.should_keep(inst, i, &liveness) {
-                filtered.push(inst.clone());
-            }
-        }
-
-        Ok(filtered)
+        let dead = self.dead_instructions(&result);
+        Ok(result
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !dead.contains(i))
+            .map(|(_, inst)| inst)
+            .collect())
     }
 
-    /// Analyze which instructions are live (their results are used)
-    fn analyze_liveness(&self, instructions: &[Instruction]) -> LivenessInfo {
-        let mut live = HashSet::new();
-
-        // Simple backward analysis: mark instructions as live if they have side effects
-        // or their results are used by live instructions
+    /// Find the pure instructions whose values never leave them
+    ///
+    /// Each value on the stack is traced from the instruction producing it
+    /// to the one consuming it. Starting from every pure instruction, any
+    /// that consumes a value from outside the set, or produces one used
+    /// outside it, is dropped from the set until none is left to drop.
+    /// What remains can go as a whole: it consumes exactly what it
+    /// produces. Instructions with side effects, unknown stack effects and
+    /// control flow are barriers that use every value on the stack, as do
+    /// values left at the end.
+    fn dead_instructions(&self, instructions: &[Instruction]) -> HashSet<usize> {
+        // Producer and consumer of each value; `None` is outside the sequence
+        let mut producers: Vec<Option<usize>> = Vec::new();
+        let mut consumers: Vec<Option<usize>> = Vec::new();
+        let mut stack: Vec<usize> = Vec::new();
+
+        let mut candidates: HashSet<usize> = HashSet::new();
         for (i, inst) in instructions.iter().enumerate() {
-            if !inst.is_pure() {
-                // Instructions with side effects are always live
-                live.insert(i);
+            if Self::is_barrier(inst) {
+                for value in stack.drain(..) {
+                    consumers[value] = None;
+                }
+                continue;
             }
-        }
 
-        // Calculate final stack depth to mark final values as live
-        let mut final_stack_depth = 0i32;
-        for inst in instructions.iter() {
+            candidates.insert(i);
             let effect = inst.stack_effect();
-            final_stack_depth += effect.produced as i32 - effect.consumed as i32;
+            for _ in 0..effect.consumed {
+                let value = stack.pop().unwrap_or_else(|| {
+                    producers.push(None);
+                    consumers.push(None);
+                    producers.len() - 1
+                });
+                consumers[value] = Some(i);
+            }
+            for _ in 0..effect.produced {
+                producers.push(Some(i));
+                consumers.push(None);
+                stack.push(producers.len() - 1);
+            }
         }
 
-        // Backward pass to propagate liveness
         let mut changed = true;
         while changed {
             changed = false;
-            let mut stack_depth = final_stack_depth; // Start with final depth
-
-            for (i, inst) in instructions.iter().enumerate().rev() {
-                let effect = inst.stack_effect();
-
-                // If this instruction produces values and stack is needed, it's live
-                if effect.produced > 0 && stack_depth > 0 {
-                    if !live.contains(&i) {
-                        live.insert(i);
-                        changed = true;
+            for value in 0..producers.len() {
+                let (producer, consumer) = (producers[value], consumers[value]);
+                let inside = |end: Option<usize>| end.is_some_and(|i| candidates.contains(&i));
+                if inside(producer) != inside(consumer) || (producer.is_none() && consumer.is_none()) {
+                    for end in [producer, consumer].into_iter().flatten() {
+                        changed |= candidates.remove(&end);
                     }
                 }
-
-                // Update stack depth (going backwards)
-                stack_depth -= effect.produced as i32;
-                stack_depth += effect.consumed as i32;
-                stack_depth = stack_depth.max(0);
             }
         }
 
-        LivenessInfo {
-            live_instructions: live,
-        }
+        candidates
     }
 
-    /// Check if instruction should be kept
-    fn should_keep(&self, inst: &Instruction, index: usize, liveness: &LivenessInfo) -> bool {
+    /// Instructions the value trace does not see through
+    fn is_barrier(inst: &Instruction) -> bool {
         use Instruction::*;
 
-        // Always keep instructions with side effects
-        if !inst.is_pure() {
-            return true;
-        }
-
-        // Keep if live
-        if liveness.live_instructions.contains(&index) {
-            return true;
-        }
-
-        // Keep control flow and metadata
-        match inst {
-            Branch(_) | BranchIf(_) | BranchIfNot(_) | Return | Label(_) | Comment(_) => true,
-
-            // Remove dead pure instructions
-            _ if inst.is_pure() => false,
-
-            _ => true,
-        }
+        !inst.is_pure()
+            || matches!(inst, Label(_) | Pick(_) | Roll(_) | FromR | RFetch | InductionVar(_) | LocalFetch(_))
     }
 
     /// Remove trivial operations (second pass)
@@ -180,8 +162,9 @@ impl DeadCodeEliminator {
                     i += 2;
                 }
 
-                // over drop drop -> (remove all)
+                // over drop drop -> drop
                 [Instruction::Over, Instruction::Drop, Instruction::Drop, ..] => {
+                    result.push(Instruction::Drop);
                     i += 3;
                 }
 
@@ -283,6 +266,24 @@ mod tests {
         assert!(matches!(optimized.main[0], Instruction::Literal(5)));
     }
 
+    #[test]
+    fn test_keeps_word_results() {
+        let eliminator = DeadCodeEliminator::new();
+        let mut ir = ForthIR::new();
+        ir.add_word(WordDef::new(
+            "square".to_string(),
+            vec![Instruction::Dup, Instruction::Mul],
+        ));
+
+        let optimized = eliminator.eliminate(&ir).unwrap();
+
+        // The word's result is its output, so nothing is dead
+        assert_eq!(
+            optimized.get_word("square").unwrap().instructions,
+            vec![Instruction::Dup, Instruction::Mul]
+        );
+    }
+
     #[test]
     fn test_eliminate_swap_swap() {
         let eliminator = DeadCodeEliminator::new();
@@ -353,6 +354,36 @@ mod tests {
         assert!(optimized.main.len() <= 2);
     }
 
+    #[test]
+    fn test_keep_drop_across_label() {
+        let eliminator = DeadCodeEliminator::new();
+        let mut ir = ForthIR::new();
+        ir.main = vec![
+            Instruction::Literal(1),
+            Instruction::Label("L0".to_string()),
+            Instruction::Drop,
+            Instruction::Literal(1),
+            Instruction::Literal(2),
+            Instruction::Over,
+            Instruction::Drop,
+            Instruction::Drop,
+        ];
+
+        let optimized = eliminator.eliminate(&ir).unwrap();
+
+        // The drop after the label may see values from other paths, and
+        // over drop drop leaves 1 behind
+        assert_eq!(
+            optimized.main,
+            vec![
+                Instruction::Literal(1),
+                Instruction::Label("L0".to_string()),
+                Instruction::Drop,
+                Instruction::Literal(1),
+            ]
+        );
+    }
+
     #[test]
     fn test_elimination_stats() {
         let eliminator = DeadCodeEliminator::new();
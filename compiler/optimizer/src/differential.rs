@@ -0,0 +1,235 @@
+//! Differential Verification
+//!
+//! Checks an optimized program against the program it came from by running
+//! both on the [`IrInterpreter`]. Every word that survived optimization is
+//! run from random stacks as deep as its inferred stack effect consumes,
+//! and the main sequence once: the two programs must leave the same stack,
+//! print the same output, leave the same values in their variables and
+//! fail on the same inputs. Both run on the original program's memory
+//! layout, so a variable has the same address in each; a variable the
+//! optimizer removed is not compared.
+//!
+//! Inputs are drawn mostly from small values, so loops and comparisons take
+//! both ways, with the extremes of a cell mixed in. A trial on which the
+//! original program cannot be judged (it underflows, runs out of fuel,
+//! reads outside its variables or uses something the interpreter does not
+//! model) is skipped. The random stream is seeded by the word's name, so a
+//! run reports the same divergence every time.
+
+use crate::interpreter::{InterpretResult, IrInterpreter, Memory};
+use crate::ir::ForthIR;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Random stacks each word is run from
+pub const DEFAULT_TRIALS: usize = 32;
+
+/// Instructions each run may execute
+const TRIAL_FUEL: u64 = 100_000;
+
+/// Values inputs are drawn from besides small ones
+const EDGE_VALUES: [i64; 6] = [i64::MIN, i64::MIN + 1, -1, 0, i64::MAX - 1, i64::MAX];
+
+/// A word the optimized program computes differently
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The word, or `main` for the main sequence
+    pub word: String,
+    pub inputs: Vec<i64>,
+    pub original: Observation,
+    pub optimized: Observation,
+}
+
+/// What a run left: the data stack or the failure, the output and the
+/// cells of each variable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observation {
+    pub result: std::result::Result<Vec<i64>, String>,
+    pub output: String,
+    pub variables: BTreeMap<String, Vec<i64>>,
+}
+
+impl Observation {
+    /// Whether `other` shows the same, over the variables both have
+    fn agrees(&self, other: &Observation) -> bool {
+        self.result == other.result
+            && self.output == other.output
+            && self.variables.iter().all(|(name, cells)| other.variables.get(name).is_none_or(|other| other == cells))
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "optimized {} differs from the original on inputs {:?}", self.word, self.inputs)?;
+        writeln!(f, "  original  {}", self.original)?;
+        write!(f, "  optimized {}", self.optimized)
+    }
+}
+
+impl fmt::Display for Observation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.result {
+            Ok(stack) => write!(f, "{:?}", stack)?,
+            Err(e) => write!(f, "error: {}", e)?,
+        }
+        if !self.output.is_empty() {
+            write!(f, ", printing {:?}", self.output)?;
+        }
+        for (name, cells) in &self.variables {
+            write!(f, ", {} = {:?}", name, cells)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run `original` and `optimized` side by side on `trials` random stacks
+/// per word, returning the first difference
+pub fn verify(original: &ForthIR, optimized: &ForthIR, trials: usize) -> std::result::Result<(), Box<Divergence>> {
+    let layout = Memory::new(original);
+    let mut names: Vec<&String> = original.words.keys().filter(|name| optimized.words.contains_key(*name)).collect();
+    names.sort();
+
+    for name in names {
+        let depth = original.words[name].stack_effect.consumed as usize;
+        let mut random = SplitMix::seeded(name);
+        for _ in 0..trials {
+            let inputs: Vec<i64> = (0..depth).map(|_| random.input()).collect();
+            let run = |ir: &ForthIR| observe(&layout, |interpreter| {
+                interpreter.with_stack(inputs.clone()).with_fuel(TRIAL_FUEL).run_word(name)
+            }, ir);
+            compare(name, &inputs, run(original), || run(optimized))?;
+        }
+    }
+
+    let run = |ir: &ForthIR| observe(&layout, |interpreter| interpreter.with_fuel(TRIAL_FUEL).run(), ir);
+    compare("main", &[], run(original), || run(optimized))
+}
+
+/// Run `ir` with a fresh console and memory laid out as `layout`
+fn observe(
+    layout: &Memory,
+    run: impl FnOnce(IrInterpreter<'_>) -> InterpretResult<Vec<i64>>,
+    ir: &ForthIR,
+) -> (Observation, bool) {
+    use crate::interpreter::InterpretError::*;
+    let mut output = String::new();
+    let mut memory = layout.clone();
+    let result = run(IrInterpreter::new(ir).with_console(&mut output).with_memory(&mut memory));
+    let judged = !matches!(
+        result,
+        Err(StackUnderflow(_) | ReturnStackUnderflow(_) | InvalidAddress(_) | Unsupported(_) | OutOfFuel | CallDepthExceeded)
+    );
+    let variables = memory
+        .variables()
+        .into_iter()
+        .filter(|(name, _)| ir.variables.contains_key(*name))
+        .map(|(name, cells)| (name.to_string(), cells))
+        .collect();
+    (Observation { result: result.map_err(|e| e.to_string()), output, variables }, judged)
+}
+
+fn compare(
+    word: &str,
+    inputs: &[i64],
+    (original, judged): (Observation, bool),
+    optimized: impl FnOnce() -> (Observation, bool),
+) -> std::result::Result<(), Box<Divergence>> {
+    if !judged {
+        return Ok(());
+    }
+    let (optimized, _) = optimized();
+    if original.agrees(&optimized) {
+        return Ok(());
+    }
+    Err(Box::new(Divergence { word: word.to_string(), inputs: inputs.to_vec(), original, optimized }))
+}
+
+/// SplitMix64, enough to spread inputs without a dependency
+struct SplitMix(u64);
+
+impl SplitMix {
+    fn seeded(name: &str) -> Self {
+        Self(name.bytes().fold(0x9e37_79b9_7f4a_7c15, |state, byte| (state ^ byte as u64).wrapping_mul(0x0100_0000_01b3)))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Mostly `-8..=8`, sometimes an edge value or any cell
+    fn input(&mut self) -> i64 {
+        let value = self.next();
+        match value % 8 {
+            0 => EDGE_VALUES[(value >> 8) as usize % EDGE_VALUES.len()],
+            1 => (value >> 8) as i64,
+            _ => (value >> 8) as i64 % 9,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{Instruction, WordDef};
+    use crate::{OptimizationLevel, Optimizer};
+
+    fn program() -> ForthIR {
+        use Instruction::*;
+        let mut ir = ForthIR::new();
+        ir.add_word(WordDef::new("inc2".to_string(), vec![Literal(1), Add, Literal(1), Add]));
+        ir.add_word(WordDef::new("sq".to_string(), vec![Dup, Mul]));
+        ir.add_word(WordDef::new("f".to_string(), vec![Call("sq".into()), Call("inc2".into()), Literal(3), Mul]));
+        ir.main = vec![Literal(3), Call("f".into()), Call(".".into())];
+        ir
+    }
+
+    #[test]
+    fn test_optimized_program_agrees() {
+        let ir = program();
+        for level in [OptimizationLevel::Standard, OptimizationLevel::Aggressive] {
+            let optimized = Optimizer::new(level).optimize(ir.clone()).unwrap();
+            assert_eq!(verify(&ir, &optimized, DEFAULT_TRIALS), Ok(()), "{:?}", level);
+        }
+    }
+
+    #[test]
+    fn test_miscompile_is_reported() {
+        let ir = program();
+        let mut broken = ir.clone();
+        // 1 + 1 + folded to 1 +
+        broken.words.get_mut("inc2").unwrap().instructions = vec![Instruction::Literal(1), Instruction::Add];
+
+        let divergence = verify(&ir, &broken, DEFAULT_TRIALS).unwrap_err();
+        assert_eq!(divergence.word, "f");
+        assert_eq!(divergence.inputs.len(), 1);
+        assert!(divergence.to_string().contains("optimized f differs"), "{}", divergence);
+
+        // Output counts too
+        let mut silent = ir.clone();
+        silent.main.pop();
+        silent.main.push(Instruction::Drop);
+        assert_eq!(verify(&ir, &silent, 0).unwrap_err().word, "main");
+    }
+
+    #[test]
+    fn test_variables_are_observed() {
+        use Instruction::*;
+        // : bump ( n -- ) counter +! ;  2 bump 3 bump
+        let mut ir = ForthIR::new();
+        ir.add_buffer("counter", 8);
+        ir.add_word(WordDef::new("bump".to_string(), vec![Call("counter".into()), Call("+!".into())]));
+        ir.main = vec![Literal(2), Call("bump".into()), Literal(3), Call("bump".into())];
+        assert_eq!(verify(&ir, &ir, DEFAULT_TRIALS), Ok(()));
+
+        // Dropping the second increment leaves the stack alone but not memory
+        let mut lost = ir.clone();
+        lost.main.truncate(2);
+        let divergence = verify(&ir, &lost, DEFAULT_TRIALS).unwrap_err();
+        assert_eq!(divergence.word, "main");
+        assert_eq!(divergence.original.variables["counter"], [5]);
+    }
+}
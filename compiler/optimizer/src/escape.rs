@@ -0,0 +1,378 @@
+//! Escape Analysis for Variables
+//!
+//! Promotes variables used as scratch space by a single word to local
+//! registers, so reading and writing them no longer goes through memory.
+//!
+//! # Examples
+//!
+//! Before:
+//! ```forth
+//! variable tmp
+//! : f ( a b -- c ) tmp ! 2 * tmp @ + ;
+//! ```
+//!
+//! After (`tmp` lives in a register of `f`):
+//! ```forth
+//! : f ( a b -- c ) to-local 2 * local + ;
+//! ```
+//!
+//! A variable is promoted when:
+//!
+//! - every reference to it is in one word (not the main sequence), and
+//!   is immediately followed by `@` or `!`, so its address never escapes
+//!   onto the stack
+//! - the word cannot reach itself through calls, so no other activation
+//!   sees the value
+//! - the word stores to it before any branch, so no read can observe a
+//!   value left behind by an earlier call
+//!
+//! Once promoted, a literal stored to a local is forwarded to the reads
+//! that follow it in the same basic block, and stores to locals that are
+//! never read become drops for dead code elimination to remove.
+
+use crate::ir::{ForthIR, Instruction};
+use crate::Result;
+use std::collections::{HashMap, HashSet};
+
+/// Escape analysis and variable promotion
+pub struct EscapeAnalyzer {
+    /// Forward literals stored to promoted variables
+    forward_constants: bool,
+}
+
+impl EscapeAnalyzer {
+    pub fn new() -> Self {
+        Self { forward_constants: true }
+    }
+
+    /// Promote the variables that do not escape their word
+    pub fn promote(&self, ir: &ForthIR) -> Result<ForthIR> {
+        let mut optimized = ir.clone();
+
+        for (owner, variables) in self.candidates(ir) {
+            let Some(word) = optimized.words.get_mut(&owner) else {
+                continue;
+            };
+            // Promoted variables go above the word's own locals
+            let first = Instruction::free_local_slot(&word.instructions);
+            let slots: HashMap<&str, u8> = variables
+                .iter()
+                .zip(first..=u8::MAX)
+                .map(|(name, slot)| (name.as_str(), slot))
+                .collect();
+
+            let mut instructions = Self::rewrite(&word.instructions, &slots);
+            if self.forward_constants {
+                instructions = Self::forward_stores(&instructions);
+            }
+            word.instructions = Self::drop_dead_stores(&instructions);
+            word.update();
+        }
+
+        Ok(optimized)
+    }
+
+    /// Variables that can be promoted, grouped by the word using them
+    fn candidates(&self, ir: &ForthIR) -> HashMap<String, Vec<String>> {
+        let mut by_word: HashMap<String, Vec<String>> = HashMap::new();
+
+        for (variable, &size) in &ir.variables {
+            // A local holds one cell
+            if size != 8 || ir.words.contains_key(variable) || ir.main.iter().any(|inst| Self::references(inst, variable)) {
+                continue;
+            }
+
+            let mut users = ir
+                .words
+                .values()
+                .filter(|word| word.instructions.iter().any(|inst| Self::references(inst, variable)));
+            let (Some(word), None) = (users.next(), users.next()) else {
+                continue;
+            };
+
+            if Self::accesses_directly(&word.instructions, variable)
+                && Self::stored_on_entry(&word.instructions, variable)
+                && !Self::is_recursive(ir, &word.name)
+            {
+                by_word.entry(word.name.clone()).or_default().push(variable.clone());
+            }
+        }
+
+        for variables in by_word.values_mut() {
+            variables.sort();
+        }
+        by_word
+    }
+
+    fn references(inst: &Instruction, variable: &str) -> bool {
+        matches!(inst, Instruction::Call(name) if name == variable)
+    }
+
+    /// Whether every reference is consumed at once by `@` or `!`
+    fn accesses_directly(instructions: &[Instruction], variable: &str) -> bool {
+        instructions.iter().enumerate().all(|(index, inst)| {
+            !Self::references(inst, variable)
+                || matches!(instructions.get(index + 1), Some(Instruction::Load | Instruction::Store))
+        })
+    }
+
+    /// Whether the first access is a store that every path passes through
+    fn stored_on_entry(instructions: &[Instruction], variable: &str) -> bool {
+        for (index, inst) in instructions.iter().enumerate() {
+            match inst {
+                Instruction::Call(name) if name == variable => {
+                    return instructions.get(index + 1) == Some(&Instruction::Store);
+                }
+                Instruction::Branch(_)
+                | Instruction::BranchIf(_)
+                | Instruction::BranchIfNot(_)
+                | Instruction::Return => return false,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Whether a word can call itself, directly or through other words
+    fn is_recursive(ir: &ForthIR, name: &str) -> bool {
+        let mut visited = HashSet::new();
+        let mut pending = vec![name];
+
+        while let Some(current) = pending.pop() {
+            let Some(word) = ir.words.get(current) else {
+                continue;
+            };
+            for inst in &word.instructions {
+                if let Instruction::Call(callee) = inst {
+                    if callee == name {
+                        return true;
+                    }
+                    if visited.insert(callee.as_str()) {
+                        pending.push(callee);
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Replace `var @` and `var !` with local slot accesses
+    fn rewrite(instructions: &[Instruction], slots: &HashMap<&str, u8>) -> Vec<Instruction> {
+        let mut result = Vec::with_capacity(instructions.len());
+        let mut iter = instructions.iter().peekable();
+
+        while let Some(inst) = iter.next() {
+            let slot = match inst {
+                Instruction::Call(name) => slots.get(name.as_str()).copied(),
+                _ => None,
+            };
+            match (slot, iter.peek()) {
+                (Some(slot), Some(Instruction::Load)) => {
+                    iter.next();
+                    result.push(Instruction::LocalFetch(slot));
+                }
+                (Some(slot), Some(Instruction::Store)) => {
+                    iter.next();
+                    result.push(Instruction::LocalStore(slot));
+                }
+                _ => result.push(inst.clone()),
+            }
+        }
+
+        result
+    }
+
+    /// Replace reads of a local with the literal last stored to it in the
+    /// same basic block
+    fn forward_stores(instructions: &[Instruction]) -> Vec<Instruction> {
+        let mut result: Vec<Instruction> = Vec::with_capacity(instructions.len());
+        let mut known: HashMap<u8, i64> = HashMap::new();
+
+        for inst in instructions {
+            match inst {
+                Instruction::LocalStore(slot) => match result.last() {
+                    Some(Instruction::Literal(value)) => {
+                        known.insert(*slot, *value);
+                    }
+                    _ => {
+                        known.remove(slot);
+                    }
+                },
+                Instruction::LocalFetch(slot) => {
+                    if let Some(value) = known.get(slot) {
+                        result.push(Instruction::Literal(*value));
+                        continue;
+                    }
+                }
+                Instruction::Label(_)
+                | Instruction::Branch(_)
+                | Instruction::BranchIf(_)
+                | Instruction::BranchIfNot(_) => known.clear(),
+                _ => {}
+            }
+            result.push(inst.clone());
+        }
+
+        result
+    }
+
+    /// Turn stores to locals that are never read into drops
+    fn drop_dead_stores(instructions: &[Instruction]) -> Vec<Instruction> {
+        let read: HashSet<u8> = instructions
+            .iter()
+            .filter_map(|inst| match inst {
+                Instruction::LocalFetch(slot) => Some(*slot),
+                _ => None,
+            })
+            .collect();
+
+        instructions
+            .iter()
+            .map(|inst| match inst {
+                Instruction::LocalStore(slot) if !read.contains(slot) => Instruction::Drop,
+                _ => inst.clone(),
+            })
+            .collect()
+    }
+}
+
+impl Default for EscapeAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::WordDef;
+
+    fn call(name: &str) -> Instruction {
+        Instruction::Call(name.into())
+    }
+
+    fn program(words: Vec<(&str, Vec<Instruction>)>) -> ForthIR {
+        let mut ir = ForthIR::new();
+        ir.add_variable("tmp");
+        for (name, instructions) in words {
+            ir.add_word(WordDef::new(name.to_string(), instructions));
+        }
+        ir
+    }
+
+    #[test]
+    fn test_scratch_variable_is_promoted() {
+        let ir = program(vec![(
+            "f",
+            vec![
+                call("tmp"),
+                Instruction::Store,
+                Instruction::Literal(2),
+                Instruction::Mul,
+                call("tmp"),
+                Instruction::Load,
+                Instruction::Add,
+                Instruction::Return,
+            ],
+        )]);
+
+        let result = EscapeAnalyzer::new().promote(&ir).unwrap();
+        assert_eq!(
+            result.get_word("f").unwrap().instructions,
+            vec![
+                Instruction::LocalStore(0),
+                Instruction::Literal(2),
+                Instruction::Mul,
+                Instruction::LocalFetch(0),
+                Instruction::Add,
+                Instruction::Return,
+            ]
+        );
+        result.verify().unwrap();
+    }
+
+    #[test]
+    fn test_stored_literal_is_forwarded() {
+        let ir = program(vec![(
+            "f",
+            vec![
+                Instruction::Literal(10),
+                call("tmp"),
+                Instruction::Store,
+                call("tmp"),
+                Instruction::Load,
+                Instruction::Add,
+                Instruction::Return,
+            ],
+        )]);
+
+        let result = EscapeAnalyzer::new().promote(&ir).unwrap();
+        // The store is dead once its only read is forwarded
+        assert_eq!(
+            result.get_word("f").unwrap().instructions,
+            vec![
+                Instruction::Literal(10),
+                Instruction::Drop,
+                Instruction::Literal(10),
+                Instruction::Add,
+                Instruction::Return,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escaping_variables_stay_in_memory() {
+        let analyzer = EscapeAnalyzer::new();
+        let store_then = |rest: Vec<Instruction>| {
+            let mut instructions = vec![Instruction::Literal(0), call("tmp"), Instruction::Store];
+            instructions.extend(rest);
+            instructions.push(Instruction::Return);
+            instructions
+        };
+
+        // Address left on the stack
+        let escaped = program(vec![("f", store_then(vec![call("tmp")]))]);
+        // Used by two words
+        let shared = program(vec![
+            ("f", store_then(vec![])),
+            ("g", vec![call("tmp"), Instruction::Load, Instruction::Return]),
+        ]);
+        // Recursive word
+        let recursive = program(vec![("f", store_then(vec![call("f")]))]);
+        // Read before being stored
+        let stale = program(vec![(
+            "f",
+            vec![call("tmp"), Instruction::Load, Instruction::Literal(1), call("tmp"), Instruction::Store],
+        )]);
+        // Stored only on one branch
+        let conditional = program(vec![(
+            "f",
+            vec![
+                Instruction::BranchIfNot(0),
+                Instruction::Literal(1),
+                call("tmp"),
+                Instruction::Store,
+                Instruction::Label("L0".to_string()),
+                call("tmp"),
+                Instruction::Load,
+                Instruction::Return,
+            ],
+        )]);
+
+        for ir in [escaped, shared, recursive, stale, conditional] {
+            assert_eq!(analyzer.promote(&ir).unwrap(), ir);
+        }
+    }
+
+    #[test]
+    fn test_forwarding_stops_at_labels() {
+        let instructions = vec![
+            Instruction::Literal(3),
+            Instruction::LocalStore(0),
+            Instruction::Label("L0".to_string()),
+            Instruction::LocalFetch(0),
+        ];
+
+        assert_eq!(EscapeAnalyzer::forward_stores(&instructions), instructions);
+    }
+}
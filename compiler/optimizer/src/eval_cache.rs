@@ -0,0 +1,377 @@
+//! Compile-Time Evaluation Cache
+//!
+//! The zero-cost pass evaluates what it can of every word at compile time,
+//! and a rebuild evaluates the same unchanged words again. An [`EvalCache`]
+//! remembers each word's result under a hash of everything it depends on:
+//!
+//! - the word's instructions and attributes
+//! - those of every word it calls, directly or not, since they may be
+//!   inlined into it
+//! - the flags that change what evaluation computes, such as the
+//!   arithmetic mode, given when the cache is created
+//!
+//! A word whose hash is in the cache takes the stored result; the others go
+//! through the pass, along with the words they call, and their results are
+//! stored. Any change to a word therefore misses for it and every word
+//! calling it, and a cache written under other flags starts out empty.
+//!
+//! # Cache Format
+//!
+//! The flags hash, then one word per line: its hash and its result as
+//! Forth words, with the optimizer's own instructions spelled `%` and their
+//! name, and their operand after a `:`. Results with instructions that
+//! cannot be spelled, such as fused or vector instructions, are not stored.
+//! `#` starts a comment.
+//!
+//! ```text
+//! # fastforth compile-time evaluation cache
+//! flags 5a1f0c3e9b27d416
+//! 0e3b5c8f6a917d22 %label:bb0 25 exit
+//! 9c41d2b7e0a35f18 %DupMul %literal-add:3
+//! ```
+
+use crate::ir::{ForthIR, Instruction, WordDef};
+use crate::{OptimizerError, Result};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Entries kept past the ones the last build used
+const MAX_ENTRIES: usize = 4096;
+
+/// Results of compile-time evaluation, keyed by content hash
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalCache {
+    flags: u64,
+    entries: HashMap<u64, Vec<Instruction>>,
+    /// Hashes looked up or stored since the cache was loaded
+    used: HashSet<u64>,
+    hits: usize,
+    misses: usize,
+}
+
+impl EvalCache {
+    /// An empty cache for builds under `flags`
+    pub fn new(flags: &str) -> Self {
+        Self {
+            flags: hash(flags.as_bytes(), FNV_OFFSET),
+            entries: HashMap::new(),
+            used: HashSet::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Parse a cache in the text format written by `Display`
+    ///
+    /// A cache written under other flags is read as an empty one.
+    pub fn parse(text: &str, flags: &str) -> Result<Self> {
+        let mut cache = Self::new(flags);
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(number, line)| (number + 1, line.split('#').next().unwrap_or("").trim()))
+            .filter(|(_, line)| !line.is_empty());
+        let error = |number: usize, message: &str| OptimizerError::ParseError(format!("line {}: {}", number, message));
+
+        match lines.next() {
+            None => return Ok(cache),
+            Some((number, line)) => {
+                let flags = line
+                    .strip_prefix("flags ")
+                    .and_then(|flags| u64::from_str_radix(flags.trim(), 16).ok())
+                    .ok_or_else(|| error(number, "expected the flags hash"))?;
+                if flags != cache.flags {
+                    return Ok(cache);
+                }
+            }
+        }
+
+        for (number, line) in lines {
+            let mut words = line.split_whitespace();
+            let key = words
+                .next()
+                .and_then(|key| u64::from_str_radix(key, 16).ok())
+                .ok_or_else(|| error(number, "expected a hash"))?;
+            cache.entries.insert(key, words.map(parse_word).collect());
+        }
+        Ok(cache)
+    }
+
+    /// Words taken from the cache since it was loaded
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Words evaluated since the cache was loaded
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Run `pass` over the words of `ir` not in the cache, and take the
+    /// others' results from it
+    ///
+    /// `pass` must transform each word from its own instructions and those
+    /// of the words it calls alone. The main sequence always goes through
+    /// it.
+    pub fn run(&mut self, ir: &ForthIR, pass: impl FnOnce(&ForthIR) -> Result<ForthIR>) -> Result<ForthIR> {
+        let mut keys = HashMap::new();
+        for name in ir.words.keys() {
+            keys.insert(name.as_str(), self.key(ir, name));
+        }
+        let hit = |name: &str| self.entries.contains_key(&keys[name]);
+
+        // The words to evaluate, with the words they and main call
+        let mut missed: Vec<&str> = keys.keys().copied().filter(|name| !hit(name)).collect();
+        missed.sort_unstable();
+        let mut subset = ForthIR { words: HashMap::new(), main: ir.main.clone(), variables: ir.variables.clone() };
+        let mut pending: Vec<&str> = missed.clone();
+        pending.extend(callees(&ir.main));
+        while let Some(name) = pending.pop() {
+            if let Some(word) = ir.words.get(name) {
+                if !subset.words.contains_key(name) {
+                    subset.words.insert(name.to_string(), word.clone());
+                    pending.extend(callees(&word.instructions));
+                }
+            }
+        }
+
+        let evaluated = pass(&subset)?;
+        let mut optimized = ForthIR { words: HashMap::new(), main: evaluated.main, variables: evaluated.variables };
+        for (name, word) in &ir.words {
+            let key = keys[name.as_str()];
+            self.used.insert(key);
+            let result = match self.entries.get(&key) {
+                Some(instructions) => {
+                    self.hits += 1;
+                    let mut cached = WordDef { instructions: instructions.clone(), ..word.clone() };
+                    cached.update();
+                    cached
+                }
+                None => {
+                    self.misses += 1;
+                    let result = evaluated.words.get(name).cloned().unwrap_or_else(|| word.clone());
+                    if result.instructions.iter().all(|inst| spell(inst).is_some()) {
+                        self.entries.insert(key, result.instructions.clone());
+                    }
+                    result
+                }
+            };
+            optimized.words.insert(name.clone(), result);
+        }
+
+        if self.entries.len() > MAX_ENTRIES {
+            let used = &self.used;
+            self.entries.retain(|key, _| used.contains(key));
+        }
+        Ok(optimized)
+    }
+
+    /// Hash of a word, the words it calls and the flags
+    fn key(&self, ir: &ForthIR, name: &str) -> u64 {
+        let mut seen = HashSet::new();
+        let mut pending = vec![name];
+        let mut reachable = Vec::new();
+        while let Some(name) = pending.pop() {
+            if let Some(word) = ir.words.get(name) {
+                if seen.insert(name) {
+                    reachable.push(word);
+                    pending.extend(callees(&word.instructions));
+                }
+            }
+        }
+        // The word itself first, then its callees in name order
+        reachable[1..].sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut state = self.flags;
+        for word in reachable {
+            let content = format!(
+                "{}\0{:?}\0{:?}\0{:?}\0{}\0",
+                word.name, word.instructions, word.attributes, word.linkage, word.is_inline
+            );
+            state = hash(content.as_bytes(), state);
+        }
+        state
+    }
+}
+
+impl fmt::Display for EvalCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# fastforth compile-time evaluation cache")?;
+        writeln!(f, "flags {:016x}", self.flags)?;
+        let mut keys: Vec<&u64> = self.entries.keys().collect();
+        keys.sort_unstable();
+        for key in keys {
+            let words: Vec<String> = self.entries[key].iter().filter_map(spell).collect();
+            writeln!(f, "{:016x} {}", key, words.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Names of the words `instructions` call
+fn callees(instructions: &[Instruction]) -> impl Iterator<Item = &str> {
+    instructions.iter().filter_map(|inst| match inst {
+        Instruction::Call(name) => Some(name.as_str()),
+        _ => None,
+    })
+}
+
+/// Instructions with no operands and no Forth word, spelled `%` and their
+/// name
+const UNNAMED: &[Instruction] = &[
+    Instruction::DupAdd,
+    Instruction::DupMul,
+    Instruction::OverAdd,
+    Instruction::SwapSub,
+    Instruction::IncOne,
+    Instruction::DecOne,
+    Instruction::MulTwo,
+    Instruction::DivTwo,
+    Instruction::UDiv,
+    Instruction::UMod,
+    Instruction::UMul,
+    Instruction::UMulHigh,
+    Instruction::ToR,
+    Instruction::FromR,
+    Instruction::RFetch,
+    Instruction::Nop,
+];
+
+/// Spelling of an instruction the cache can store: its Forth word, or for
+/// the optimizer's own instructions `%` and its name, with its operand
+/// after a `:`
+fn spell(inst: &Instruction) -> Option<String> {
+    use Instruction::*;
+    let spelled = match inst {
+        Return => "exit".to_string(),
+        Call(name) => name.to_string(),
+        Label(name) => format!("%label:{}", name),
+        Branch(target) => format!("%branch:{}", target),
+        BranchIf(target) => format!("%branch-if:{}", target),
+        BranchIfNot(target) => format!("%branch-if-not:{}", target),
+        Pick(n) => format!("%pick:{}", n),
+        Roll(n) => format!("%roll:{}", n),
+        LiteralAdd(n) => format!("%literal-add:{}", n),
+        LiteralMul(n) => format!("%literal-mul:{}", n),
+        LocalFetch(slot) => format!("%local-fetch:{}", slot),
+        LocalStore(slot) => format!("%local-store:{}", slot),
+        inst => inst.to_word().unwrap_or_else(|| format!("%{:?}", inst)),
+    };
+    // Anything else, and names that would read back as something else,
+    // are not stored
+    (parse_word(&spelled) == *inst).then_some(spelled)
+}
+
+fn parse_word(word: &str) -> Instruction {
+    use Instruction::*;
+    if word == "exit" {
+        return Return;
+    }
+    if let Some(tagged) = word.strip_prefix('%') {
+        let parsed = match tagged.split_once(':') {
+            Some(("label", name)) => Some(Label(name.to_string())),
+            Some((tag, operand)) => match tag {
+                "branch" => operand.parse().ok().map(Branch),
+                "branch-if" => operand.parse().ok().map(BranchIf),
+                "branch-if-not" => operand.parse().ok().map(BranchIfNot),
+                "pick" => operand.parse().ok().map(Pick),
+                "roll" => operand.parse().ok().map(Roll),
+                "literal-add" => operand.parse().ok().map(LiteralAdd),
+                "literal-mul" => operand.parse().ok().map(LiteralMul),
+                "local-fetch" => operand.parse().ok().map(LocalFetch),
+                "local-store" => operand.parse().ok().map(LocalStore),
+                _ => None,
+            },
+            None => UNNAMED.iter().find(|inst| format!("{:?}", inst) == tagged).cloned(),
+        };
+        if let Some(inst) = parsed {
+            return inst;
+        }
+    }
+    Instruction::from_word(word)
+        .or_else(|| word.parse().ok().map(Literal))
+        .unwrap_or_else(|| Call(word.into()))
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// FNV-1a, continuing from `state`, so hashes are the same from one build
+/// to the next
+fn hash(bytes: &[u8], state: u64) -> u64 {
+    bytes.iter().fold(state, |state, &byte| (state ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ZeroCostOptimizer;
+
+    fn program() -> ForthIR {
+        let mut ir = ForthIR::new();
+        ir.add_word(WordDef::new("five".to_string(), vec![Instruction::Literal(2), Instruction::Literal(3), Instruction::Add]));
+        ir.add_word(WordDef::new("masked".to_string(), vec![Instruction::Call("five".into()), Instruction::Literal(6), Instruction::And]));
+        ir.add_word(WordDef::new("ten".to_string(), vec![Instruction::Call("five".into()), Instruction::Literal(2), Instruction::Mul]));
+        ir.add_word(WordDef::new("sq".to_string(), vec![Instruction::Dup, Instruction::Mul]));
+        ir.main = vec![Instruction::Call("ten".into()), Instruction::Call("masked".into())];
+        ir
+    }
+
+    #[test]
+    fn test_cached_results_match_evaluation() {
+        let zero_cost = ZeroCostOptimizer::default();
+        let ir = program();
+        let expected = zero_cost.optimize(&ir).unwrap();
+
+        let mut cache = EvalCache::new("-O3");
+        let first = cache.run(&ir, |ir| zero_cost.optimize(ir)).unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (0, 4));
+        assert_eq!(first, expected);
+
+        // A rebuild reads everything back, through the text format
+        let text = cache.to_string();
+        assert!(text.contains("%MulTwo"), "{}", text);
+        let mut cache = EvalCache::parse(&text, "-O3").unwrap();
+        let second = cache.run(&ir, |ir| zero_cost.optimize(ir)).unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (4, 0));
+        assert_eq!(second, expected);
+    }
+
+    #[test]
+    fn test_changes_invalidate_callers_and_flags_everything() {
+        let zero_cost = ZeroCostOptimizer::default();
+        let mut cache = EvalCache::new("-O3");
+        cache.run(&program(), |ir| zero_cost.optimize(ir)).unwrap();
+        let text = cache.to_string();
+
+        // Changing five misses for it and for the words calling it
+        let mut ir = program();
+        ir.words.get_mut("five").unwrap().instructions[0] = Instruction::Literal(4);
+        let mut cache = EvalCache::parse(&text, "-O3").unwrap();
+        let optimized = cache.run(&ir, |ir| zero_cost.optimize(ir)).unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (1, 3));
+        assert_eq!(optimized, zero_cost.optimize(&ir).unwrap());
+
+        let cache = EvalCache::parse(&text, "-O3 --overflow=trap").unwrap();
+        assert!(cache.is_empty());
+        assert!(EvalCache::parse("0123 dup", "-O3").is_err());
+    }
+
+    #[test]
+    fn test_spellings_read_back() {
+        use Instruction::*;
+        for inst in [Label("bb0".to_string()), BranchIfNot(4), LiteralAdd(-3), LocalStore(2), DupMul, Return, Literal(-7), Store] {
+            let spelled = spell(&inst).unwrap();
+            assert_eq!(parse_word(&spelled), inst, "{}", spelled);
+        }
+        assert_eq!(spell(&Call("%MulTwo".into())), None);
+        assert_eq!(spell(&Fused(vec![Dup, Add])), None);
+    }
+}
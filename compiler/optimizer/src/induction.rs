@@ -0,0 +1,479 @@
+//! Induction Variable Optimization for Counted Loops
+//!
+//! Replaces values computed from the loop index on every iteration with
+//! derived induction variables that are updated by addition.
+//!
+//! # Examples
+//!
+//! Before:
+//! ```forth
+//! 0 do over i cells + @ + loop
+//! ```
+//!
+//! After (`p` starts at `addr + start * 8` and steps by 8):
+//! ```forth
+//! 0 do p @ + loop
+//! ```
+//!
+//! Before:
+//! ```forth
+//! 0 do i 2 * . loop
+//! ```
+//!
+//! After (`k` starts at `start * 2` and steps by 2):
+//! ```forth
+//! 0 do k . loop
+//! ```
+//!
+//! Only innermost loops with a known step are rewritten, and only when
+//! every instruction of the body has a known stack effect. A base value
+//! may be a literal or a copy (`dup`, `over`) of a stack item that the
+//! body leaves in place.
+
+use crate::ir::{CountedLoop, ForthIR, Instruction, WordDef};
+use crate::Result;
+
+/// Bytes per cell, the scale of `cells`
+const CELL_SIZE: i64 = 8;
+
+/// Value of a stack slot in terms of the stack at the loop header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Symbolic {
+    /// Item `n` from the top of the stack at the header
+    Entry(usize),
+    /// Computed inside the body
+    Unknown,
+}
+
+/// Stack of symbolic values, reaching into the header stack on underflow
+#[derive(Debug, Default)]
+struct SymbolicStack {
+    items: Vec<Symbolic>,
+    /// Header stack items consumed so far
+    consumed: usize,
+}
+
+impl SymbolicStack {
+    fn pop(&mut self) -> Symbolic {
+        self.items.pop().unwrap_or_else(|| {
+            self.consumed += 1;
+            Symbolic::Entry(self.consumed - 1)
+        })
+    }
+
+    fn push(&mut self, value: Symbolic) {
+        self.items.push(value);
+    }
+
+    fn top(&self) -> Option<Symbolic> {
+        self.items.last().copied()
+    }
+
+    /// Simulate one instruction, or `None` if its effect is not known
+    fn apply(&mut self, inst: &Instruction) -> Option<()> {
+        use Instruction::*;
+
+        match inst {
+            Dup | CachedDup { .. } => {
+                let a = self.pop();
+                self.push(a);
+                self.push(a);
+            }
+            Swap | CachedSwap { .. } => {
+                let b = self.pop();
+                let a = self.pop();
+                self.push(b);
+                self.push(a);
+            }
+            Over => {
+                let b = self.pop();
+                let a = self.pop();
+                self.push(a);
+                self.push(b);
+                self.push(a);
+            }
+            Rot => {
+                let c = self.pop();
+                let b = self.pop();
+                let a = self.pop();
+                self.push(b);
+                self.push(c);
+                self.push(a);
+            }
+            Nip => {
+                let b = self.pop();
+                self.pop();
+                self.push(b);
+            }
+            Tuck => {
+                let b = self.pop();
+                let a = self.pop();
+                self.push(b);
+                self.push(a);
+                self.push(b);
+            }
+            OverAdd => {
+                self.pop();
+                let a = self.pop();
+                self.push(a);
+                self.push(Symbolic::Unknown);
+            }
+            Call(name) if known_call_effect(name).is_some() => {
+                let (consumed, produced) = known_call_effect(name)?;
+                for _ in 0..consumed {
+                    self.pop();
+                }
+                for _ in 0..produced {
+                    self.push(Symbolic::Unknown);
+                }
+            }
+
+            // Anything that moves control, touches the return stack or has
+            // an unknown effect stops the analysis
+            Call(_) | Execute | Return | Branch(_) | BranchIf(_) | BranchIfNot(_) | Label(_) | ToR | FromR
+            | RFetch | Pick(_) | Roll(_) | CachedOver { .. } | InductionInit { .. }
+            | InductionVar(_) | InductionStep { .. } => return None,
+
+            other => {
+                let effect = other.stack_effect();
+                for _ in 0..effect.consumed {
+                    self.pop();
+                }
+                for _ in 0..effect.produced {
+                    self.push(Symbolic::Unknown);
+                }
+            }
+        }
+
+        Some(())
+    }
+
+    /// Whether header item `n` is in its original position
+    fn preserves(&self, n: usize) -> bool {
+        if n >= self.consumed {
+            return true;
+        }
+        self.items.len() == self.consumed && self.items[self.items.len() - 1 - n] == Symbolic::Entry(n)
+    }
+}
+
+/// Stack effect of the called words a loop body may use
+fn known_call_effect(name: &str) -> Option<(usize, usize)> {
+    match name.to_ascii_lowercase().as_str() {
+        "i" => Some((0, 1)),
+        "cells" => Some((1, 1)),
+        "+!" => Some((2, 0)),
+        _ => None,
+    }
+}
+
+fn is_index(name: &str) -> bool {
+    name.eq_ignore_ascii_case("i")
+}
+
+fn is_cells(name: &str) -> bool {
+    name.eq_ignore_ascii_case("cells")
+}
+
+/// Starting value of a derived induction variable, before scaling the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Base {
+    Constant(i64),
+    /// Copy of a header stack item that the body leaves in place
+    Entry(usize),
+}
+
+impl Base {
+    /// Instruction pushing the base at the loop header
+    fn instruction(self) -> Instruction {
+        match self {
+            Base::Constant(value) => Instruction::Literal(value),
+            Base::Entry(0) => Instruction::Dup,
+            Base::Entry(_) => Instruction::Over,
+        }
+    }
+}
+
+/// A derived induction variable: `base + scale * index`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Derived {
+    base: Base,
+    scale: i64,
+}
+
+/// Replacement of `len` instructions at `at` with `with`
+#[derive(Debug)]
+struct Edit {
+    at: usize,
+    len: usize,
+    with: Vec<Instruction>,
+}
+
+/// Induction variable optimizer
+pub struct InductionVariableOptimizer {
+    /// Maximum derived variables per loop
+    max_slots: usize,
+}
+
+impl InductionVariableOptimizer {
+    pub fn new() -> Self {
+        Self { max_slots: u8::MAX as usize + 1 }
+    }
+
+    /// Introduce induction variables in IR
+    pub fn optimize(&self, ir: &ForthIR) -> Result<ForthIR> {
+        let mut optimized = ir.clone();
+
+        // Optimize main sequence
+        optimized.main = self.optimize_sequence(&ir.main);
+
+        // Optimize each word
+        for (name, word) in ir.words.iter() {
+            let optimized_word = self.optimize_word(word);
+            optimized.words.insert(name.clone(), optimized_word);
+        }
+
+        Ok(optimized)
+    }
+
+    /// Introduce induction variables in a word definition
+    pub(crate) fn optimize_word(&self, word: &WordDef) -> WordDef {
+        let mut optimized = word.clone();
+        optimized.instructions = self.optimize_sequence(&word.instructions);
+        optimized.update();
+        optimized
+    }
+
+    /// Introduce induction variables in an instruction sequence
+    fn optimize_sequence(&self, instructions: &[Instruction]) -> Vec<Instruction> {
+        let loops = ForthIR::counted_loops(instructions);
+
+        let mut edits: Vec<Edit> = loops
+            .iter()
+            .filter(|lp| !loops.iter().any(|inner| lp.contains(inner)))
+            .flat_map(|lp| self.rewrite_loop(instructions, lp))
+            .collect();
+
+        if edits.is_empty() {
+            return instructions.to_vec();
+        }
+
+        edits.sort_by_key(|edit| edit.at);
+        let mut result = Vec::with_capacity(instructions.len() + edits.len());
+        let mut pos = 0;
+        for edit in edits {
+            result.extend_from_slice(&instructions[pos..edit.at]);
+            result.extend(edit.with);
+            pos = edit.at + edit.len;
+        }
+        result.extend_from_slice(&instructions[pos..]);
+        result
+    }
+
+    /// Edits introducing induction variables in one innermost loop
+    fn rewrite_loop(&self, instructions: &[Instruction], lp: &CountedLoop) -> Vec<Edit> {
+        let Some(step) = lp.step else {
+            return Vec::new();
+        };
+
+        // Symbolic top of stack after each body instruction
+        let mut stack = SymbolicStack::default();
+        let mut tops = Vec::with_capacity(lp.body().len());
+        for inst in &instructions[lp.body()] {
+            if stack.apply(inst).is_none() {
+                return Vec::new();
+            }
+            tops.push(stack.top());
+        }
+        if stack.items.len() != stack.consumed {
+            return Vec::new();
+        }
+
+        let mut derived: Vec<Derived> = Vec::new();
+        let mut edits = Vec::new();
+        let mut index = lp.body().start;
+        let mut rewritten_to = index;
+
+        while index < lp.latch {
+            let Some((scale, scale_len)) = Self::index_scale(instructions, index, lp.latch) else {
+                index += 1;
+                continue;
+            };
+            let after = index + 1 + scale_len;
+
+            // `base i scale +` folds the base into the variable
+            let base = match (index > rewritten_to, instructions.get(after)) {
+                (true, Some(Instruction::Add)) if after < lp.latch => match &instructions[index - 1] {
+                    Instruction::Literal(value) => Some(Base::Constant(*value)),
+                    Instruction::Dup | Instruction::Over => match tops[index - 1 - lp.body().start] {
+                        Some(Symbolic::Entry(n)) if n <= 1 && stack.preserves(n) => Some(Base::Entry(n)),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                _ => None,
+            };
+            let (at, len, var) = match base {
+                Some(base) => (index - 1, scale_len + 3, Derived { base, scale }),
+                None => (index, scale_len + 1, Derived { base: Base::Constant(0), scale }),
+            };
+
+            let slot = match derived.iter().position(|d| *d == var) {
+                Some(slot) => slot,
+                None if derived.len() < self.max_slots => {
+                    derived.push(var);
+                    derived.len() - 1
+                }
+                None => break,
+            };
+
+            edits.push(Edit { at, len, with: vec![Instruction::InductionVar(slot as u8)] });
+            index = at + len;
+            rewritten_to = index;
+        }
+
+        let mut steps = Vec::with_capacity(derived.len());
+        let mut inits = Vec::with_capacity(derived.len() * 2);
+        for (slot, var) in derived.iter().enumerate() {
+            let Some(delta) = var.scale.checked_mul(step) else {
+                return Vec::new();
+            };
+            inits.push(var.base.instruction());
+            inits.push(Instruction::InductionInit { slot: slot as u8, scale: var.scale });
+            steps.push(Instruction::InductionStep { slot: slot as u8, delta });
+        }
+
+        if !inits.is_empty() {
+            edits.push(Edit { at: lp.header, len: 0, with: inits });
+            edits.push(Edit { at: lp.latch, len: 0, with: steps });
+        }
+        edits
+    }
+
+    /// Scale applied to the loop index at `index`, and how many
+    /// instructions after the index apply it
+    fn index_scale(instructions: &[Instruction], index: usize, latch: usize) -> Option<(i64, usize)> {
+        match &instructions[index] {
+            Instruction::Call(name) if is_index(name) => {}
+            _ => return None,
+        }
+
+        let next = instructions[index + 1..latch].first()?;
+        match (next, instructions[index + 1..latch].get(1)) {
+            (Instruction::Literal(scale), Some(Instruction::Mul)) => Some((*scale, 2)),
+            (Instruction::LiteralMul(scale), _) => Some((*scale, 1)),
+            (Instruction::MulTwo | Instruction::DupAdd, _) => Some((2, 1)),
+            (Instruction::Call(name), _) if is_cells(name) => Some((CELL_SIZE, 1)),
+            _ => None,
+        }
+    }
+}
+
+impl Default for InductionVariableOptimizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str) -> Instruction {
+        Instruction::Call(name.into())
+    }
+
+    /// `(do) L0: body (loop) BranchIfNot(0)`
+    fn counted_loop(body: Vec<Instruction>) -> Vec<Instruction> {
+        let mut instructions = vec![call("(do)"), Instruction::Label("L0".to_string())];
+        instructions.extend(body);
+        instructions.extend([call("(loop)"), Instruction::BranchIfNot(0)]);
+        instructions
+    }
+
+    #[test]
+    fn test_scaled_index_becomes_additive() {
+        let opt = InductionVariableOptimizer::new();
+        let result = opt.optimize_sequence(&counted_loop(vec![
+            call("i"),
+            Instruction::Literal(2),
+            Instruction::Mul,
+            Instruction::Drop,
+        ]));
+
+        assert_eq!(
+            result,
+            vec![
+                call("(do)"),
+                Instruction::Literal(0),
+                Instruction::InductionInit { slot: 0, scale: 2 },
+                Instruction::Label("L0".to_string()),
+                Instruction::InductionVar(0),
+                Instruction::Drop,
+                Instruction::InductionStep { slot: 0, delta: 2 },
+                call("(loop)"),
+                Instruction::BranchIfNot(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cell_address_becomes_pointer() {
+        // addr n 0 do over i cells + @ + loop
+        let opt = InductionVariableOptimizer::new();
+        let result = opt.optimize_sequence(&counted_loop(vec![
+            Instruction::Over,
+            call("i"),
+            call("cells"),
+            Instruction::Add,
+            Instruction::Load,
+            Instruction::Add,
+        ]));
+
+        assert_eq!(&result[1..3], &[Instruction::Over, Instruction::InductionInit { slot: 0, scale: 8 }]);
+        assert_eq!(&result[4..7], &[Instruction::InductionVar(0), Instruction::Load, Instruction::Add]);
+        assert_eq!(result[7], Instruction::InductionStep { slot: 0, delta: 8 });
+    }
+
+    #[test]
+    fn test_clobbered_base_is_not_folded() {
+        // The body replaces the item `over` copies, so it is not invariant
+        let opt = InductionVariableOptimizer::new();
+        let result = opt.optimize_sequence(&counted_loop(vec![
+            Instruction::Over,
+            call("i"),
+            call("cells"),
+            Instruction::Add,
+            Instruction::Rot,
+            Instruction::Drop,
+        ]));
+
+        assert_eq!(result[2], Instruction::InductionInit { slot: 0, scale: 8 });
+        assert_eq!(result[1], Instruction::Literal(0));
+        assert_eq!(&result[4..7], &[Instruction::Over, Instruction::InductionVar(0), Instruction::Add]);
+    }
+
+    #[test]
+    fn test_step_scales_delta() {
+        let opt = InductionVariableOptimizer::new();
+        let mut instructions = vec![call("(do)"), Instruction::Label("L0".to_string())];
+        instructions.extend([call("i"), call("cells"), Instruction::Drop]);
+        instructions.extend([Instruction::Literal(3), call("(+loop)"), Instruction::BranchIfNot(0)]);
+
+        let result = opt.optimize_sequence(&instructions);
+        assert!(result.contains(&Instruction::InductionStep { slot: 0, delta: 24 }));
+        assert_eq!(result[result.len() - 3], Instruction::Literal(3));
+    }
+
+    #[test]
+    fn test_unknown_calls_are_left_alone() {
+        let opt = InductionVariableOptimizer::new();
+        let instructions = counted_loop(vec![
+            call("i"),
+            call("cells"),
+            call("emit-cell"),
+        ]);
+
+        assert_eq!(opt.optimize_sequence(&instructions), instructions);
+    }
+}
@@ -25,8 +25,8 @@
 //! 5 dup * dup *
 //! ```
 
-use crate::ir::{ForthIR, Instruction, StackEffect, WordDef};
-use crate::{OptimizationLevel, Result};
+use crate::ir::{ForthIR, Instruction, StackEffect, Symbol, WordDef};
+use crate::{InlineDirective, OptimizationLevel, Result};
 use std::collections::{HashMap, HashSet};
 
 /// Inline cost threshold by optimization level
@@ -105,13 +105,13 @@ impl InlineOptimizer {
     }
 
     /// Count how many times each word is called
-    fn count_calls(&self, ir: &ForthIR) -> HashMap<String, usize> {
+    fn count_calls(&self, ir: &ForthIR) -> HashMap<Symbol, usize> {
         let mut counts = HashMap::new();
 
         // Count in main
         for inst in &ir.main {
             if let Instruction::Call(name) = inst {
-                *counts.entry(name.clone()).or_insert(0) += 1;
+                *counts.entry(*name).or_insert(0) += 1;
             }
         }
 
@@ -119,7 +119,7 @@ impl InlineOptimizer {
         for word in ir.words.values() {
             for inst in &word.instructions {
                 if let Instruction::Call(name) = inst {
-                    *counts.entry(name.clone()).or_insert(0) += 1;
+                    *counts.entry(*name).or_insert(0) += 1;
                 }
             }
         }
@@ -131,13 +131,14 @@ impl InlineOptimizer {
     fn make_inline_decisions(
         &self,
         ir: &ForthIR,
-        call_counts: &HashMap<String, usize>,
-    ) -> HashMap<String, InlineDecision> {
+        call_counts: &HashMap<Symbol, usize>,
+    ) -> HashMap<Symbol, InlineDecision> {
         let mut decisions = HashMap::new();
 
         for (name, word) in &ir.words {
-            let decision = self.should_inline(word, call_counts.get(name).copied().unwrap_or(0));
-            decisions.insert(name.clone(), decision);
+            let name = Symbol::from(name);
+            let decision = self.should_inline(word, call_counts.get(&name).copied().unwrap_or(0));
+            decisions.insert(name, decision);
         }
 
         decisions
@@ -145,9 +146,11 @@ impl InlineOptimizer {
 
     /// Determine if a word should be inlined
     fn should_inline(&self, word: &WordDef, call_count: usize) -> InlineDecision {
-        // Explicitly marked inline
-        if word.is_inline {
-            return InlineDecision::Inline;
+        // Explicitly marked inline or not
+        match word.inline_directive() {
+            InlineDirective::AlwaysInline => return InlineDecision::Inline,
+            InlineDirective::NeverInline => return InlineDecision::NoInline,
+            InlineDirective::Auto => {}
         }
 
         // Check for recursion
@@ -160,8 +163,8 @@ impl InlineOptimizer {
             return InlineDecision::TooLarge;
         }
 
-        // Too many call sites?
-        if call_count > self.max_inline_sites {
+        // Too many call sites? Hot words are worth the code size
+        if call_count > self.max_inline_sites && !word.attributes.hot {
             return InlineDecision::TooManyCalls;
         }
 
@@ -176,23 +179,52 @@ impl InlineOptimizer {
             .any(|inst| matches!(inst, Instruction::Call(name) if name == &word.name))
     }
 
+    /// The instructions a call to a word can be replaced with: its body
+    /// without the final return
+    ///
+    /// Words that return early or branch are not inlined, since their
+    /// returns and labels mean something else in the caller.
+    pub(crate) fn inline_body(word: &WordDef) -> Option<&[Instruction]> {
+        let body = match word.instructions.split_last() {
+            Some((Instruction::Return, body)) => body,
+            _ => &word.instructions[..],
+        };
+        let straight_line = body.iter().all(|inst| {
+            !matches!(
+                inst,
+                Instruction::Return
+                    | Instruction::Label(_)
+                    | Instruction::Branch(_)
+                    | Instruction::BranchIf(_)
+                    | Instruction::BranchIfNot(_)
+            )
+        });
+        straight_line.then_some(body)
+    }
+
     /// Inline calls in an instruction sequence
     fn inline_sequence(
         &self,
         instructions: &[Instruction],
         ir: &ForthIR,
-        decisions: &HashMap<String, InlineDecision>,
+        decisions: &HashMap<Symbol, InlineDecision>,
     ) -> Result<Vec<Instruction>> {
         let mut result = Vec::with_capacity(instructions.len());
+        // Inlined locals go above the caller's; bodies are straight-line, so
+        // each is done with them before the next one starts
+        let locals = Instruction::free_local_slot(instructions);
 
         for inst in instructions {
             match inst {
                 Instruction::Call(name) => {
                     // Check if we should inline this call
                     if let Some(InlineDecision::Inline) = decisions.get(name) {
-                        if let Some(word) = ir.get_word(name) {
+                        let body = ir.get_word(name).and_then(Self::inline_body);
+                        if let Some(body) = body.and_then(|body| Instruction::shift_locals(body, locals)) {
                             // Inline the word's instructions
-                            result.extend_from_slice(&word.instructions);
+                            for inst in body {
+                                push_devirtualized(&mut result, inst);
+                            }
                             continue;
                         }
                     }
@@ -201,7 +233,7 @@ impl InlineOptimizer {
                     result.push(inst.clone());
                 }
                 _ => {
-                    result.push(inst.clone());
+                    push_devirtualized(&mut result, inst.clone());
                 }
             }
         }
@@ -273,6 +305,23 @@ impl std::fmt::Display for InlineStats {
     }
 }
 
+/// Append `inst` to `result`, calling the word directly when `inst`
+/// EXECUTEs the token pushed just before it
+///
+/// Inlining a word that takes a token, such as `: apply execute ;`, often
+/// leaves the token next to its EXECUTE; the direct call can then be
+/// inlined like any other.
+pub(crate) fn push_devirtualized(result: &mut Vec<Instruction>, inst: Instruction) {
+    if matches!(inst, Instruction::Execute) {
+        if let Some(&Instruction::Tick(word)) = result.last() {
+            result.pop();
+            result.push(Instruction::Call(word));
+            return;
+        }
+    }
+    result.push(inst);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -288,7 +337,7 @@ mod tests {
         );
         ir.add_word(square);
 
-        ir.main = vec![Instruction::Literal(5), Instruction::Call("square".to_string())];
+        ir.main = vec![Instruction::Literal(5), Instruction::Call("square".into())];
 
         let optimized = optimizer.inline(&ir).unwrap();
 
@@ -304,6 +353,51 @@ mod tests {
         assert!(has_dup && has_mul);
     }
 
+    #[test]
+    fn test_inline_drops_return() {
+        let optimizer = InlineOptimizer::new(OptimizationLevel::Aggressive);
+
+        let mut ir = ForthIR::new();
+        ir.add_word(WordDef::new(
+            "square".to_string(),
+            vec![Instruction::Dup, Instruction::Mul, Instruction::Return],
+        ));
+        ir.add_word(WordDef::new(
+            "sign".to_string(),
+            vec![
+                Instruction::ZeroLt,
+                Instruction::BranchIfNot(0),
+                Instruction::Literal(-1),
+                Instruction::Return,
+                Instruction::Label("L0".to_string()),
+                Instruction::Literal(1),
+                Instruction::Return,
+            ],
+        ));
+
+        ir.main = vec![
+            Instruction::Literal(5),
+            Instruction::Call("square".into()),
+            Instruction::Call("sign".into()),
+            Instruction::Literal(1),
+        ];
+
+        let optimized = optimizer.inline(&ir).unwrap();
+
+        // The return of square would end main early; sign branches and
+        // returns early, so it stays a call
+        assert_eq!(
+            optimized.main,
+            vec![
+                Instruction::Literal(5),
+                Instruction::Dup,
+                Instruction::Mul,
+                Instruction::Call("sign".into()),
+                Instruction::Literal(1),
+            ]
+        );
+    }
+
     #[test]
     fn test_dont_inline_large_word() {
         let optimizer = InlineOptimizer::new(OptimizationLevel::Basic);
@@ -314,7 +408,7 @@ mod tests {
         let large = WordDef::new("large".to_string(), large_instructions);
         ir.add_word(large);
 
-        ir.main = vec![Instruction::Call("large".to_string())];
+        ir.main = vec![Instruction::Call("large".into())];
 
         let optimized = optimizer.inline(&ir).unwrap();
 
@@ -338,14 +432,14 @@ mod tests {
                 Instruction::Dup,
                 Instruction::Literal(1),
                 Instruction::Gt,
-                Instruction::Call("factorial".to_string()),
+                Instruction::Call("factorial".into()),
             ],
         );
         ir.add_word(recursive);
 
         ir.main = vec![
             Instruction::Literal(5),
-            Instruction::Call("factorial".to_string()),
+            Instruction::Call("factorial".into()),
         ];
 
         let optimized = optimizer.inline(&ir).unwrap();
@@ -369,7 +463,7 @@ mod tests {
 
         ir.main = vec![
             Instruction::Literal(5),
-            Instruction::Call("tiny".to_string()),
+            Instruction::Call("tiny".into()),
         ];
 
         let optimized = optimizer.inline(&ir).unwrap();
@@ -395,8 +489,8 @@ mod tests {
 
         ir.main = vec![
             Instruction::Literal(5),
-            Instruction::Call("square".to_string()),
-            Instruction::Call("square".to_string()),
+            Instruction::Call("square".into()),
+            Instruction::Call("square".into()),
         ];
 
         let optimized = optimizer.inline(&ir).unwrap();
@@ -406,4 +500,34 @@ mod tests {
         assert_eq!(stats.calls_after, 0);
         assert_eq!(stats.calls_inlined, 2);
     }
+
+    #[test]
+    fn test_inlined_locals_keep_their_own_slots() {
+        let optimizer = InlineOptimizer::new(OptimizationLevel::Aggressive);
+
+        let mut ir = ForthIR::new();
+        ir.add_word(WordDef::new(
+            "negated".to_string(),
+            vec![Instruction::LocalStore(0), Instruction::Literal(0), Instruction::LocalFetch(0), Instruction::Sub],
+        ));
+        ir.main = vec![
+            Instruction::Literal(5),
+            Instruction::LocalStore(0),
+            Instruction::LocalFetch(0),
+            Instruction::Call("negated".into()),
+            Instruction::LocalFetch(0),
+        ];
+
+        let optimized = optimizer.inline(&ir).unwrap();
+        assert_eq!(
+            &optimized.main[3..],
+            &[
+                Instruction::LocalStore(1),
+                Instruction::Literal(0),
+                Instruction::LocalFetch(1),
+                Instruction::Sub,
+                Instruction::LocalFetch(0),
+            ]
+        );
+    }
 }
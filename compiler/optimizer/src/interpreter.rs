@@ -0,0 +1,885 @@
+//! Reference Interpreter for Forth IR
+//!
+//! Executes [`ForthIR`] directly, before or after any pass, so the effect of
+//! an optimization can be observed: a program must leave the same data
+//! stack, output and variables whatever the optimizer did to it. It is the
+//! reference semantics of the IR, and is kept simple enough to be
+//! obviously right: tests check passes against it,
+//! [differential verification](crate::differential) checks whole programs,
+//! and the zero-cost pass uses it to evaluate calls to pure words on
+//! constant arguments.
+//!
+//! Semantics follow the code the passes assume:
+//!
+//! - arithmetic wraps, `/` and `mod` truncate toward zero
+//! - comparisons leave `-1` for true and `0` for false
+//! - `Branch(n)` and its conditional forms jump to `Label("Ln")`
+//! - counted loops use the `(do)`, `(loop)` and `(+loop)` calls lowering
+//!   produces, with the loop parameters on the return stack
+//! - a variable pushes the address of its buffer in a [`Memory`]; cells are
+//!   8 bytes, little-endian, and start out zero; an access outside every
+//!   buffer is an [`InterpretError::InvalidAddress`]
+//! - `c@`, `w@` and `l@` read 1, 2 and 4 bytes, little-endian, and
+//!   zero-extend them; `c!`, `w!` and `l!` write the low bytes of the value
+//! - the execution token of a word numbers it among the program's words
+//!   in name order, from [`TOKEN_BASE`]
+//!
+//! Floating point and concurrency instructions are not interpreted; running
+//! one is an [`InterpretError::Unsupported`]. The I/O words `.`, `emit`,
+//! `cr`, `space`, `spaces` and `key` go through a [`Console`] the host
+//! supplies, so the interpreter itself never touches a terminal or file,
+//! and runs where there is neither, such as the browser playground.
+
+use crate::ir::{ForthIR, Instruction};
+use fastforth_frontend::umul_high;
+use std::collections::{BTreeMap, HashMap};
+use thiserror::Error;
+
+/// Default number of instructions a run may execute
+pub const DEFAULT_FUEL: u64 = 10_000_000;
+
+/// Deepest call nesting a run may reach
+const MAX_CALL_DEPTH: usize = 1024;
+
+/// Bytes per cell, the scale of `cells`
+const CELL_SIZE: i64 = 8;
+
+/// Address of the first variable; low addresses stay invalid, so a small
+/// number used as an address is caught
+const DATA_BASE: i64 = 0x1_0000;
+
+/// Execution token of the first word, well above the data space
+pub const TOKEN_BASE: i64 = 0x1_0000_0000;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum InterpretError {
+    #[error("Stack underflow at {0}")]
+    StackUnderflow(String),
+
+    #[error("Return stack underflow at {0}")]
+    ReturnStackUnderflow(String),
+
+    #[error("Undefined word: {0}")]
+    UndefinedWord(String),
+
+    #[error("Branch to missing label L{0}")]
+    MissingLabel(usize),
+
+    #[error("Division by zero")]
+    DivisionByZero,
+
+    #[error("Invalid memory address {0:#x}")]
+    InvalidAddress(i64),
+
+    #[error("Not interpreted: {0}")]
+    Unsupported(String),
+
+    #[error("Ran out of fuel")]
+    OutOfFuel,
+
+    #[error("Call depth exceeded")]
+    CallDepthExceeded,
+}
+
+pub type InterpretResult<T> = std::result::Result<T, InterpretError>;
+
+/// Where the I/O words of a run write their output and read their input
+pub trait Console {
+    /// Write the text `.`, `emit`, `cr`, `space` or `spaces` prints
+    fn write(&mut self, text: &str);
+
+    /// The next input character for `key`, or `None` at the end of input
+    fn key(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+/// Collects the output, with no input
+impl Console for String {
+    fn write(&mut self, text: &str) {
+        self.push_str(text);
+    }
+}
+
+/// The data space of a run: one buffer per variable of a program, laid
+/// out in name order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Memory {
+    /// Start address and size in bytes of each buffer
+    buffers: BTreeMap<String, (i64, i64)>,
+    /// Bytes written so far
+    bytes: HashMap<i64, u8>,
+}
+
+impl Memory {
+    /// Buffers for the variables of `ir`, each a whole number of cells
+    pub fn new(ir: &ForthIR) -> Self {
+        let mut names: Vec<(&String, &usize)> = ir.variables.iter().collect();
+        names.sort();
+        let mut next = DATA_BASE;
+        let mut buffers = BTreeMap::new();
+        for (name, size) in names {
+            let size = (*size).max(1).div_ceil(CELL_SIZE as usize) as i64 * CELL_SIZE;
+            buffers.insert(name.clone(), (next, size));
+            next += size;
+        }
+        Self { buffers, bytes: HashMap::new() }
+    }
+
+    /// Address of the variable `name`
+    pub fn address(&self, name: &str) -> Option<i64> {
+        self.buffers.get(name).map(|(start, _)| *start)
+    }
+
+    /// The cell at `address`
+    pub fn fetch(&self, address: i64) -> InterpretResult<i64> {
+        self.fetch_sized(address, CELL_SIZE as u8)
+    }
+
+    /// Write the cell at `address`
+    pub fn store(&mut self, address: i64, value: i64) -> InterpretResult<()> {
+        self.store_sized(address, value, CELL_SIZE as u8)
+    }
+
+    /// The `width` bytes at `address`, little-endian and zero-extended
+    pub fn fetch_sized(&self, address: i64, width: u8) -> InterpretResult<i64> {
+        self.check(address, width as i64)?;
+        let mut bytes = [0; CELL_SIZE as usize];
+        for (offset, byte) in bytes.iter_mut().take(width as usize).enumerate() {
+            *byte = self.bytes.get(&(address + offset as i64)).copied().unwrap_or_default();
+        }
+        Ok(i64::from_le_bytes(bytes))
+    }
+
+    /// Write the low `width` bytes of `value` at `address`, little-endian
+    pub fn store_sized(&mut self, address: i64, value: i64, width: u8) -> InterpretResult<()> {
+        self.check(address, width as i64)?;
+        for (offset, byte) in value.to_le_bytes().into_iter().take(width as usize).enumerate() {
+            self.bytes.insert(address + offset as i64, byte);
+        }
+        Ok(())
+    }
+
+    /// The cells of each variable, by name
+    pub fn variables(&self) -> BTreeMap<&str, Vec<i64>> {
+        self.buffers
+            .iter()
+            .map(|(name, (start, size))| {
+                let cells = (0..size / CELL_SIZE).map(|i| self.fetch(start + i * CELL_SIZE).unwrap_or_default()).collect();
+                (name.as_str(), cells)
+            })
+            .collect()
+    }
+
+    /// Fail unless the `len` bytes at `address` lie inside one buffer
+    fn check(&self, address: i64, len: i64) -> InterpretResult<()> {
+        let inside = self.buffers.values().any(|(start, size)| {
+            address >= *start && address.checked_add(len).is_some_and(|end| end <= start + size)
+        });
+        if inside { Ok(()) } else { Err(InterpretError::InvalidAddress(address)) }
+    }
+}
+
+/// Interpreter state for one run of a program
+pub struct IrInterpreter<'a> {
+    ir: &'a ForthIR,
+    stack: Vec<i64>,
+    return_stack: Vec<i64>,
+    fuel: u64,
+    depth: usize,
+    console: Option<&'a mut dyn Console>,
+    memory: Option<&'a mut Memory>,
+    /// The memory of a run without one from the host
+    own_memory: Memory,
+    /// The words, in the order of their execution tokens
+    tokens: Vec<&'a str>,
+}
+
+/// Locals and induction variables of one activation
+#[derive(Default)]
+struct Frame {
+    locals: HashMap<u8, i64>,
+    induction: HashMap<u8, i64>,
+}
+
+/// A word being executed: where it is, and where its labels are
+struct Activation<'a> {
+    instructions: &'a [Instruction],
+    word: &'a str,
+    pc: usize,
+    labels: HashMap<&'a str, usize>,
+    frame: Frame,
+}
+
+impl<'a> Activation<'a> {
+    fn new(instructions: &'a [Instruction], word: &'a str) -> Self {
+        let labels = instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, inst)| match inst {
+                Instruction::Label(name) => Some((name.as_str(), index)),
+                _ => None,
+            })
+            .collect();
+        Self { instructions, word, pc: 0, labels, frame: Frame::default() }
+    }
+
+    /// Continue at `Label("Ln")`
+    fn jump(&mut self, label: usize) -> InterpretResult<()> {
+        self.pc = self.labels.get(format!("L{}", label).as_str()).copied().ok_or(InterpretError::MissingLabel(label))?;
+        Ok(())
+    }
+}
+
+impl<'a> IrInterpreter<'a> {
+    pub fn new(ir: &'a ForthIR) -> Self {
+        Self {
+            ir,
+            stack: Vec::new(),
+            return_stack: Vec::new(),
+            fuel: DEFAULT_FUEL,
+            depth: 0,
+            console: None,
+            memory: None,
+            own_memory: Memory::new(ir),
+            tokens: {
+                let mut names: Vec<&str> = ir.words.keys().map(String::as_str).collect();
+                names.sort_unstable();
+                names
+            },
+        }
+    }
+
+    /// Run the I/O words on `console`; without one they are not interpreted
+    pub fn with_console(mut self, console: &'a mut dyn Console) -> Self {
+        self.console = Some(console);
+        self
+    }
+
+    /// Run on `memory`, which keeps what the run stored once it is over
+    pub fn with_memory(mut self, memory: &'a mut Memory) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    /// Limit the number of instructions executed
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = fuel;
+        self
+    }
+
+    /// Start with `stack` on the data stack, bottom first
+    pub fn with_stack(mut self, stack: Vec<i64>) -> Self {
+        self.stack = stack;
+        self
+    }
+
+    /// Run the main sequence, returning the data stack it leaves
+    pub fn run(mut self) -> InterpretResult<Vec<i64>> {
+        let ir = self.ir;
+        self.execute(&ir.main, "main")?;
+        Ok(self.stack)
+    }
+
+    /// Run one word, returning the data stack it leaves
+    pub fn run_word(mut self, name: &str) -> InterpretResult<Vec<i64>> {
+        self.call(name)?;
+        Ok(self.stack)
+    }
+
+    /// Run `instructions` to their end, and every word they call
+    ///
+    /// Calls to defined words push an activation rather than recursing, so
+    /// deep Forth recursion does not exhaust the host's stack.
+    fn execute(&mut self, instructions: &'a [Instruction], word: &'a str) -> InterpretResult<()> {
+        let ir = self.ir;
+        let base = self.depth;
+        let mut calls = vec![Activation::new(instructions, word)];
+        let result = loop {
+            let depth = base + calls.len();
+            let Some(activation) = calls.last_mut() else {
+                break Ok(());
+            };
+            let Some(inst) = activation.instructions.get(activation.pc) else {
+                calls.pop();
+                continue;
+            };
+            activation.pc += 1;
+            let word = activation.word;
+            let outcome = match inst {
+                Instruction::Return => {
+                    calls.pop();
+                    Ok(())
+                }
+                Instruction::Branch(label) => activation.jump(*label),
+                Instruction::BranchIf(label) => match self.pop(word) {
+                    Ok(flag) if flag != 0 => activation.jump(*label),
+                    other => other.map(drop),
+                },
+                Instruction::BranchIfNot(label) => match self.pop(word) {
+                    Ok(0) => activation.jump(*label),
+                    other => other.map(drop),
+                },
+                Instruction::Call(name) if ir.words.contains_key(name.as_str()) => {
+                    match self.fuel.checked_sub(1) {
+                        _ if depth > MAX_CALL_DEPTH => Err(InterpretError::CallDepthExceeded),
+                        Some(fuel) => {
+                            self.fuel = fuel;
+                            let (name, def) = ir.words.get_key_value(name.as_str()).expect("checked above");
+                            calls.push(Activation::new(&def.instructions, name));
+                            Ok(())
+                        }
+                        None => Err(InterpretError::OutOfFuel),
+                    }
+                }
+                Instruction::Execute => match self.pop(word).and_then(|token| self.word_of(token)) {
+                    _ if depth > MAX_CALL_DEPTH => Err(InterpretError::CallDepthExceeded),
+                    Ok(name) => match self.fuel.checked_sub(1) {
+                        Some(fuel) => {
+                            self.fuel = fuel;
+                            calls.push(Activation::new(&ir.words[name].instructions, name));
+                            Ok(())
+                        }
+                        None => Err(InterpretError::OutOfFuel),
+                    },
+                    Err(e) => Err(e),
+                },
+                _ => {
+                    self.depth = depth;
+                    self.step(inst, word, &mut activation.frame)
+                }
+            };
+            if let Err(e) = outcome {
+                break Err(e);
+            }
+        };
+        self.depth = base;
+        result
+    }
+
+    /// Execute one straight-line instruction
+    fn step(&mut self, inst: &Instruction, word: &str, frame: &mut Frame) -> InterpretResult<()> {
+        use Instruction::*;
+
+        self.fuel = self.fuel.checked_sub(1).ok_or(InterpretError::OutOfFuel)?;
+
+        match inst {
+            Literal(value) => self.stack.push(*value),
+            Dup | CachedDup { .. } => {
+                let a = self.peek(0, word)?;
+                self.stack.push(a);
+            }
+            Drop => {
+                self.pop(word)?;
+            }
+            Swap | CachedSwap { .. } => {
+                let b = self.pop(word)?;
+                let a = self.pop(word)?;
+                self.stack.extend([b, a]);
+            }
+            Over | CachedOver { .. } => {
+                let a = self.peek(1, word)?;
+                self.stack.push(a);
+            }
+            Rot => {
+                let c = self.pop(word)?;
+                let b = self.pop(word)?;
+                let a = self.pop(word)?;
+                self.stack.extend([b, c, a]);
+            }
+            Nip => {
+                let b = self.pop(word)?;
+                self.pop(word)?;
+                self.stack.push(b);
+            }
+            Tuck => {
+                let b = self.pop(word)?;
+                let a = self.pop(word)?;
+                self.stack.extend([b, a, b]);
+            }
+            Pick(n) => {
+                let a = self.peek(*n as usize, word)?;
+                self.stack.push(a);
+            }
+            Roll(n) => {
+                let index = self.stack.len().checked_sub(*n as usize + 1).ok_or_else(|| underflow(word))?;
+                let a = self.stack.remove(index);
+                self.stack.push(a);
+            }
+
+            Add => self.binary(word, |a, b| Ok(a.wrapping_add(b)))?,
+            Sub => self.binary(word, |a, b| Ok(a.wrapping_sub(b)))?,
+            Mul => self.binary(word, |a, b| Ok(a.wrapping_mul(b)))?,
+            Div => self.binary(word, |a, b| nonzero(b).map(|b| a.wrapping_div(b)))?,
+            Mod => self.binary(word, |a, b| nonzero(b).map(|b| a.wrapping_rem(b)))?,
+            Neg => self.unary(word, i64::wrapping_neg)?,
+            Abs => self.unary(word, i64::wrapping_abs)?,
+            And => self.binary(word, |a, b| Ok(a & b))?,
+            Or => self.binary(word, |a, b| Ok(a | b))?,
+            Xor => self.binary(word, |a, b| Ok(a ^ b))?,
+            Not => self.unary(word, |a| !a)?,
+            Shl => self.binary(word, |a, n| Ok(a.wrapping_shl(n as u32)))?,
+            Shr => self.binary(word, |a, n| Ok((a as u64).wrapping_shr(n as u32) as i64))?,
+
+            Eq => self.binary(word, |a, b| Ok(flag(a == b)))?,
+            Ne => self.binary(word, |a, b| Ok(flag(a != b)))?,
+            Lt => self.binary(word, |a, b| Ok(flag(a < b)))?,
+            Le => self.binary(word, |a, b| Ok(flag(a <= b)))?,
+            Gt => self.binary(word, |a, b| Ok(flag(a > b)))?,
+            Ge => self.binary(word, |a, b| Ok(flag(a >= b)))?,
+            ZeroEq => self.unary(word, |a| flag(a == 0))?,
+            ZeroLt => self.unary(word, |a| flag(a < 0))?,
+            ZeroGt => self.unary(word, |a| flag(a > 0))?,
+
+            ULt => self.binary(word, |a, b| Ok(flag((a as u64) < (b as u64))))?,
+            UGt => self.binary(word, |a, b| Ok(flag((a as u64) > (b as u64))))?,
+            UDiv => self.binary(word, |a, b| nonzero(b).map(|b| ((a as u64) / (b as u64)) as i64))?,
+            UMod => self.binary(word, |a, b| nonzero(b).map(|b| ((a as u64) % (b as u64)) as i64))?,
+            UMul => self.binary(word, |a, b| Ok(a.wrapping_mul(b)))?,
+            UMulHigh => self.binary(word, |a, b| Ok(umul_high(a, b)))?,
+
+            Popcount => self.unary(word, |a| a.count_ones() as i64)?,
+            Ctz => self.unary(word, |a| a.trailing_zeros() as i64)?,
+            Clz => self.unary(word, |a| a.leading_zeros() as i64)?,
+            Rotl => self.binary(word, |a, n| Ok(a.rotate_left(n as u32)))?,
+            Rotr => self.binary(word, |a, n| Ok(a.rotate_right(n as u32)))?,
+
+            DupAdd => self.unary(word, |a| a.wrapping_add(a))?,
+            DupMul => self.unary(word, |a| a.wrapping_mul(a))?,
+            OverAdd => {
+                let b = self.pop(word)?;
+                let a = self.peek(0, word)?;
+                self.stack.push(a.wrapping_add(b));
+            }
+            SwapSub => self.binary(word, |a, b| Ok(b.wrapping_sub(a)))?,
+            LiteralAdd(n) => self.unary(word, |a| a.wrapping_add(*n))?,
+            LiteralMul(n) => self.unary(word, |a| a.wrapping_mul(*n))?,
+            IncOne => self.unary(word, |a| a.wrapping_add(1))?,
+            DecOne => self.unary(word, |a| a.wrapping_sub(1))?,
+            MulTwo => self.unary(word, |a| a.wrapping_shl(1))?,
+            DivTwo => self.unary(word, |a| a >> 1)?,
+            ExtractBits { shift, mask } => self.unary(word, |a| ((a as u64) >> shift) as i64 & mask)?,
+            Lookup(table) => self.unary(word, |a| table.get(a))?,
+
+            ToR => {
+                let a = self.pop(word)?;
+                self.return_stack.push(a);
+            }
+            FromR => {
+                let a = self.return_stack.pop().ok_or_else(|| return_underflow(word))?;
+                self.stack.push(a);
+            }
+            RFetch => {
+                let a = *self.return_stack.last().ok_or_else(|| return_underflow(word))?;
+                self.stack.push(a);
+            }
+
+            InductionInit { slot, scale } => {
+                let base = self.pop(word)?;
+                let index = self.loop_index(0, word)?;
+                frame.induction.insert(*slot, base.wrapping_add(scale.wrapping_mul(index)));
+            }
+            InductionVar(slot) => {
+                let value = frame.induction.get(slot).copied().unwrap_or_default();
+                self.stack.push(value);
+            }
+            InductionStep { slot, delta } => {
+                let value = frame.induction.entry(*slot).or_default();
+                *value = value.wrapping_add(*delta);
+            }
+            LocalFetch(slot) => {
+                let value = frame.locals.get(slot).copied().unwrap_or_default();
+                self.stack.push(value);
+            }
+            LocalStore(slot) => {
+                let value = self.pop(word)?;
+                frame.locals.insert(*slot, value);
+            }
+
+            Fused(body) => {
+                for inst in body {
+                    self.step(inst, word, frame)?;
+                }
+            }
+            Load | Load8 | Load16 | Load32 => {
+                let width = inst.access_width().unwrap_or(CELL_SIZE as u8);
+                let address = self.pop(word)?;
+                let value = self.memory().fetch_sized(address, width)?;
+                self.stack.push(value);
+            }
+            Store | Store8 | Store16 | Store32 => {
+                let width = inst.access_width().unwrap_or(CELL_SIZE as u8);
+                let address = self.pop(word)?;
+                let value = self.pop(word)?;
+                self.memory().store_sized(address, value, width)?;
+            }
+
+            Call(name) => self.call(name)?,
+            Tick(name) => {
+                let index = self.tokens.binary_search(&name.as_str()).map_err(|_| InterpretError::UndefinedWord(name.to_string()))?;
+                self.stack.push(TOKEN_BASE + index as i64);
+            }
+            Execute => {
+                let token = self.pop(word)?;
+                let name = self.word_of(token)?;
+                self.call(name)?;
+            }
+            Comment(_) | Label(_) | Nop | FlushCache => {}
+
+            Return | Branch(_) | BranchIf(_) | BranchIfNot(_) => {
+                return Err(InterpretError::Unsupported(format!("{:?} inside a fused instruction", inst)));
+            }
+            other => return Err(InterpretError::Unsupported(format!("{:?}", other))),
+        }
+        Ok(())
+    }
+
+    /// The word whose execution token is `token`
+    fn word_of(&self, token: i64) -> InterpretResult<&'a str> {
+        token
+            .checked_sub(TOKEN_BASE)
+            .and_then(|index| usize::try_from(index).ok())
+            .and_then(|index| self.tokens.get(index).copied())
+            .ok_or_else(|| InterpretError::UndefinedWord(format!("execution token {}", token)))
+    }
+
+    /// Call a defined word, or one of the words lowering leaves as calls
+    fn call(&mut self, name: &str) -> InterpretResult<()> {
+        let ir = self.ir;
+        if let Some((name, def)) = ir.words.get_key_value(name) {
+            if self.depth >= MAX_CALL_DEPTH {
+                return Err(InterpretError::CallDepthExceeded);
+            }
+            return self.execute(&def.instructions, name);
+        }
+        if let Some(address) = self.memory().address(name) {
+            self.stack.push(address);
+            return Ok(());
+        }
+
+        match name.to_ascii_lowercase().as_str() {
+            "(do)" => {
+                let start = self.pop(name)?;
+                let limit = self.pop(name)?;
+                self.return_stack.extend([limit, start]);
+            }
+            "(loop)" => self.advance_loop(1, name)?,
+            "(+loop)" => {
+                let step = self.pop(name)?;
+                self.advance_loop(step, name)?;
+            }
+            "i" => {
+                let index = self.loop_index(0, name)?;
+                self.stack.push(index);
+            }
+            "j" => {
+                let index = self.loop_index(1, name)?;
+                self.stack.push(index);
+            }
+            ">r" => {
+                let a = self.pop(name)?;
+                self.return_stack.push(a);
+            }
+            "r>" => {
+                let a = self.return_stack.pop().ok_or_else(|| return_underflow(name))?;
+                self.stack.push(a);
+            }
+            "r@" => {
+                let a = *self.return_stack.last().ok_or_else(|| return_underflow(name))?;
+                self.stack.push(a);
+            }
+            "cells" => self.unary(name, |a| a.wrapping_mul(CELL_SIZE))?,
+            "cell+" => self.unary(name, |a| a.wrapping_add(CELL_SIZE))?,
+            "+!" => {
+                let address = self.pop(name)?;
+                let n = self.pop(name)?;
+                let value = self.memory().fetch(address)?;
+                self.memory().store(address, value.wrapping_add(n))?;
+            }
+            "." | "emit" | "cr" | "space" | "spaces" | "key" => self.console_word(name)?,
+            _ => return Err(InterpretError::UndefinedWord(name.to_string())),
+        }
+        Ok(())
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        match self.memory.as_deref_mut() {
+            Some(memory) => memory,
+            None => &mut self.own_memory,
+        }
+    }
+
+    /// Run an I/O word on the console
+    fn console_word(&mut self, name: &str) -> InterpretResult<()> {
+        let word = name.to_ascii_lowercase();
+        let text = match word.as_str() {
+            "." => format!("{} ", self.pop(name)?),
+            "emit" => char::from_u32(self.pop(name)? as u32).unwrap_or(char::REPLACEMENT_CHARACTER).to_string(),
+            "cr" => "\n".to_string(),
+            "space" => " ".to_string(),
+            "spaces" => " ".repeat(self.pop(name)?.max(0) as usize),
+            _ => String::new(),
+        };
+        let console = self
+            .console
+            .as_deref_mut()
+            .ok_or_else(|| InterpretError::Unsupported(format!("{} without a console", name)))?;
+        if word == "key" {
+            let key = console.key().map_or(-1, i64::from);
+            self.stack.push(key);
+        } else {
+            console.write(&text);
+        }
+        Ok(())
+    }
+
+    /// Step the innermost loop index, leaving a flag that is true once the
+    /// loop is done (and its parameters dropped)
+    fn advance_loop(&mut self, step: i64, word: &str) -> InterpretResult<()> {
+        let len = self.return_stack.len();
+        if len < 2 {
+            return Err(return_underflow(word));
+        }
+        let limit = self.return_stack[len - 2];
+        let index = self.return_stack[len - 1];
+        let next = index.wrapping_add(step);
+
+        // Done when the index crosses the boundary between limit-1 and limit
+        let done = (index.wrapping_sub(limit) ^ next.wrapping_sub(limit)) < 0;
+        if done {
+            self.return_stack.truncate(len - 2);
+        } else {
+            self.return_stack[len - 1] = next;
+        }
+        self.stack.push(flag(done));
+        Ok(())
+    }
+
+    /// Index of the loop `outer` levels out from the innermost
+    fn loop_index(&self, outer: usize, word: &str) -> InterpretResult<i64> {
+        let position = self.return_stack.len().checked_sub(1 + 2 * outer).ok_or_else(|| return_underflow(word))?;
+        Ok(self.return_stack[position])
+    }
+
+    fn pop(&mut self, word: &str) -> InterpretResult<i64> {
+        self.stack.pop().ok_or_else(|| underflow(word))
+    }
+
+    fn peek(&self, depth: usize, word: &str) -> InterpretResult<i64> {
+        let index = self.stack.len().checked_sub(depth + 1).ok_or_else(|| underflow(word))?;
+        Ok(self.stack[index])
+    }
+
+    fn unary(&mut self, word: &str, f: impl FnOnce(i64) -> i64) -> InterpretResult<()> {
+        let a = self.pop(word)?;
+        self.stack.push(f(a));
+        Ok(())
+    }
+
+    fn binary(&mut self, word: &str, f: impl FnOnce(i64, i64) -> InterpretResult<i64>) -> InterpretResult<()> {
+        let b = self.pop(word)?;
+        let a = self.pop(word)?;
+        self.stack.push(f(a, b)?);
+        Ok(())
+    }
+}
+
+fn flag(condition: bool) -> i64 {
+    if condition { -1 } else { 0 }
+}
+
+fn nonzero(divisor: i64) -> InterpretResult<i64> {
+    if divisor == 0 { Err(InterpretError::DivisionByZero) } else { Ok(divisor) }
+}
+
+fn underflow(word: &str) -> InterpretError {
+    InterpretError::StackUnderflow(word.to_string())
+}
+
+fn return_underflow(word: &str) -> InterpretError {
+    InterpretError::ReturnStackUnderflow(word.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::WordDef;
+
+    fn label(n: usize) -> Instruction {
+        Instruction::Label(format!("L{}", n))
+    }
+
+    #[test]
+    fn test_branches_and_calls() {
+        use Instruction::*;
+
+        // : sign ( n -- s ) 0< if -1 else 1 then ;
+        let mut ir = ForthIR::new();
+        ir.add_word(WordDef::new(
+            "sign".to_string(),
+            vec![ZeroLt, BranchIfNot(0), Literal(-1), Branch(1), label(0), Literal(1), label(1), Return],
+        ));
+        ir.main = vec![Literal(-5), Call("sign".into()), Literal(7), Call("sign".into())];
+
+        assert_eq!(IrInterpreter::new(&ir).run(), Ok(vec![-1, 1]));
+    }
+
+    #[test]
+    fn test_counted_loop_and_induction() {
+        use Instruction::*;
+
+        // 0 5 0 do i + loop
+        let mut ir = ForthIR::new();
+        ir.main = vec![
+            Literal(0),
+            Literal(5),
+            Literal(0),
+            Call("(do)".into()),
+            label(0),
+            Call("i".into()),
+            Add,
+            Call("(loop)".into()),
+            BranchIfNot(0),
+        ];
+        assert_eq!(IrInterpreter::new(&ir).run(), Ok(vec![10]));
+
+        // Same sum through an induction variable that starts at 0 and steps by 1
+        ir.main = vec![
+            Literal(0),
+            Literal(5),
+            Literal(0),
+            Call("(do)".into()),
+            Literal(0),
+            InductionInit { slot: 0, scale: 1 },
+            label(0),
+            InductionVar(0),
+            Add,
+            InductionStep { slot: 0, delta: 1 },
+            Call("(loop)".into()),
+            BranchIfNot(0),
+        ];
+        assert_eq!(IrInterpreter::new(&ir).run(), Ok(vec![10]));
+    }
+
+    #[test]
+    fn test_errors() {
+        use Instruction::*;
+
+        let mut ir = ForthIR::new();
+        ir.main = vec![Literal(1), Literal(0), Div];
+        assert_eq!(IrInterpreter::new(&ir).run(), Err(InterpretError::DivisionByZero));
+
+        ir.main = vec![Add];
+        assert!(matches!(IrInterpreter::new(&ir).run(), Err(InterpretError::StackUnderflow(_))));
+
+        ir.main = vec![label(0), Branch(0)];
+        assert_eq!(IrInterpreter::new(&ir).with_fuel(100).run(), Err(InterpretError::OutOfFuel));
+
+        ir.main = vec![Literal(1), Call(".".into())];
+        assert!(matches!(IrInterpreter::new(&ir).run(), Err(InterpretError::Unsupported(_))));
+
+        ir.main = vec![Literal(8), Load];
+        assert_eq!(IrInterpreter::new(&ir).run(), Err(InterpretError::InvalidAddress(8)));
+    }
+
+    #[test]
+    fn test_variables() {
+        use Instruction::*;
+
+        // variable total  variable count  5 total !  3 total +!  total @  count @
+        let mut ir = ForthIR::new();
+        ir.add_buffer("total", 8);
+        ir.add_buffer("count", 8);
+        ir.main = vec![
+            Literal(5),
+            Call("total".into()),
+            Store,
+            Literal(3),
+            Call("total".into()),
+            Call("+!".into()),
+            Call("total".into()),
+            Load,
+            Call("count".into()),
+            Load,
+        ];
+        let mut memory = Memory::new(&ir);
+        assert_eq!(IrInterpreter::new(&ir).with_memory(&mut memory).run(), Ok(vec![8, 0]));
+        assert_eq!(memory.variables()["total"], [8]);
+
+        // One past the end of a buffer is outside it
+        ir.main = vec![Call("total".into()), Call("cell+".into()), Load];
+        let end = memory.address("total").unwrap() + 8;
+        assert_eq!(IrInterpreter::new(&ir).run(), Err(InterpretError::InvalidAddress(end)));
+    }
+
+    #[test]
+    fn test_sized_access() {
+        use Instruction::*;
+
+        // $11223344 total l!  $AABB total 1+ w!  total @  total 3 + c@  -1 total 4 + l!  total 4 + l@
+        let mut ir = ForthIR::new();
+        ir.add_buffer("total", 8);
+        ir.main = vec![
+            Literal(0x11223344),
+            Call("total".into()),
+            Store32,
+            Literal(0xAABB),
+            Call("total".into()),
+            Literal(1),
+            Add,
+            Store16,
+            Call("total".into()),
+            Load,
+            Call("total".into()),
+            Literal(3),
+            Add,
+            Load8,
+            Literal(-1),
+            Call("total".into()),
+            Literal(4),
+            Add,
+            Store32,
+            Call("total".into()),
+            Literal(4),
+            Add,
+            Load32,
+        ];
+        assert_eq!(IrInterpreter::new(&ir).run(), Ok(vec![0x11AABB44, 0x11, 0xFFFF_FFFF]));
+
+        // A wider access straddling the end of a buffer is outside it
+        ir.main = vec![Call("total".into()), Literal(6), Add, Load32];
+        let start = Memory::new(&ir).address("total").unwrap();
+        assert_eq!(IrInterpreter::new(&ir).run(), Err(InterpretError::InvalidAddress(start + 6)));
+    }
+
+    #[test]
+    fn test_console_words() {
+        use Instruction::*;
+
+        struct Keys(Vec<u8>);
+        impl Console for Keys {
+            fn write(&mut self, _: &str) {}
+            fn key(&mut self) -> Option<u8> {
+                self.0.pop()
+            }
+        }
+
+        let mut ir = ForthIR::new();
+        ir.main = vec![
+            Literal(-42),
+            Call(".".into()),
+            Literal(72),
+            Call("EMIT".into()),
+            Literal(3),
+            Call("spaces".into()),
+            Call("cr".into()),
+        ];
+        let mut output = String::new();
+        assert_eq!(IrInterpreter::new(&ir).with_console(&mut output).run(), Ok(vec![]));
+        assert_eq!(output, "-42 H   \n");
+
+        ir.main = vec![Call("key".into()), Call("key".into())];
+        let mut keys = Keys(vec![b'a']);
+        assert_eq!(IrInterpreter::new(&ir).with_console(&mut keys).run(), Ok(vec![97, -1]));
+    }
+}
@@ -2,11 +2,14 @@
 //!
 //! This module defines the IR used throughout the optimization pipeline.
 
-use crate::{OptimizerError, Result};
+use crate::{InlineDirective, OptimizerError, Result};
+use fastforth_frontend::case::CaseTable;
 use smallvec::SmallVec;
 use std::collections::HashMap;
 use std::fmt;
 
+pub use fastforth_frontend::Symbol;
+
 /// Stack effect notation: (before -- after)
 /// Example: (a b -- c) means: takes 2 items, produces 1 item
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -88,8 +91,25 @@ pub enum Instruction {
     ZeroLt,    // ( a -- a<0 )
     ZeroGt,    // ( a -- a>0 )
 
+    // Unsigned
+    ULt,       // ( u1 u2 -- u1<u2 )
+    UGt,       // ( u1 u2 -- u1>u2 )
+    UDiv,      // ( u1 u2 -- u1/u2 )
+    UMod,      // ( u1 u2 -- u1%u2 )
+    UMul,      // ( u1 u2 -- low cell of u1*u2 ), always wrapping
+    UMulHigh,  // ( u1 u2 -- high cell of u1*u2 )
+
+    // Bit manipulation, each a single instruction on most targets
+    Popcount,  // ( x -- number of set bits )
+    Ctz,       // ( x -- trailing zero bits ), 64 for 0
+    Clz,       // ( x -- leading zero bits ), 64 for 0
+    Rotl,      // ( x n -- x rotated left by n mod 64 )
+    Rotr,      // ( x n -- x rotated right by n mod 64 )
+
     // Control flow
-    Call(String),              // Call word by name
+    Call(Symbol),              // Call word by name
+    Tick(Symbol),              // ( -- xt ) Execution token of a word
+    Execute,                   // ( i*x xt -- j*x ) Call the word of an execution token
     Return,                    // Return from word
     Branch(usize),             // Unconditional branch to instruction
     BranchIf(usize),          // Branch if TOS is true
@@ -100,6 +120,10 @@ pub enum Instruction {
     Store,     // ( value addr -- )
     Load8,     // ( addr -- byte )
     Store8,    // ( byte addr -- )
+    Load16,    // ( addr -- u16 ), little-endian
+    Store16,   // ( x addr -- ), low 16 bits, little-endian
+    Load32,    // ( addr -- u32 ), little-endian
+    Store32,   // ( x addr -- ), low 32 bits, little-endian
 
     // Return stack
     ToR,       // ( a -- ) (R: -- a)
@@ -117,6 +141,8 @@ pub enum Instruction {
     DecOne,           // 1 - -> decrement
     MulTwo,           // 2 * -> shift left 1
     DivTwo,           // 2 / -> shift right 1
+    ExtractBits { shift: u8, mask: i64 },  // n rshift m and -> ( x -- (x>>n)&m )
+    Lookup(CaseTable),                     // CASE picking constants -> ( key -- value )
 
     // Stack caching hints (for codegen)
     CachedDup { depth: u8 },      // Dup with known stack depth
@@ -133,6 +159,26 @@ pub enum Instruction {
     CloseChannel,  // ( chan -- ) Close channel
     DestroyChannel, // ( chan -- ) Destroy channel
 
+    // Induction variables of the innermost counted loop
+    InductionInit { slot: u8, scale: i64 },  // ( base -- ) slot = base + scale * index
+    InductionVar(u8),                        // ( -- v ) Current value of slot
+    InductionStep { slot: u8, delta: i64 },  // ( -- ) slot += delta
+
+    // Vector kernels over cell arrays, each replacing a counted loop from
+    // `start` up to `limit`; arrays are stack items addressed by depth
+    // below the loop bounds
+    VectorReduce { op: VectorOp, array: u8 },            // ( acc limit start -- acc' )
+    VectorMap { op: VectorOp, operand: i64, array: u8 }, // ( limit start -- ) array[i] op= operand
+    VectorZip { op: VectorOp, dest: u8, src: u8 },       // ( limit start -- ) dest[i] op= src[i]
+
+    // Variables promoted to registers by escape analysis
+    LocalFetch(u8),  // ( -- v ) Value of local slot
+    LocalStore(u8),  // ( v -- ) Write local slot
+
+    // Superinstruction learned from a corpus: the sequence runs as one
+    // dispatch
+    Fused(Vec<Instruction>),
+
     // Metadata
     Comment(String),
     Label(String),
@@ -140,6 +186,141 @@ pub enum Instruction {
 }
 
 impl Instruction {
+    /// Instruction for a builtin word, if the word maps to one directly
+    pub fn from_word(word: &str) -> Option<Self> {
+        let inst = match word {
+            // Stack operations
+            "dup" => Instruction::Dup,
+            "drop" => Instruction::Drop,
+            "swap" => Instruction::Swap,
+            "over" => Instruction::Over,
+            "rot" => Instruction::Rot,
+            "nip" => Instruction::Nip,
+            "tuck" => Instruction::Tuck,
+
+            // Arithmetic
+            "+" => Instruction::Add,
+            "-" => Instruction::Sub,
+            "*" => Instruction::Mul,
+            "/" => Instruction::Div,
+            "mod" => Instruction::Mod,
+            "negate" => Instruction::Neg,
+            "abs" => Instruction::Abs,
+
+            // Bitwise
+            "&" | "and" => Instruction::And,
+            "|" | "or" => Instruction::Or,
+            "^" | "xor" => Instruction::Xor,
+            "~" | "not" | "invert" => Instruction::Not,
+            "<<" | "lshift" => Instruction::Shl,
+            ">>" | "rshift" => Instruction::Shr,
+
+            // Comparison
+            "=" => Instruction::Eq,
+            "<>" => Instruction::Ne,
+            "<" => Instruction::Lt,
+            "<=" => Instruction::Le,
+            ">" => Instruction::Gt,
+            ">=" => Instruction::Ge,
+            "0=" => Instruction::ZeroEq,
+            "0<" => Instruction::ZeroLt,
+            "0>" => Instruction::ZeroGt,
+            "u<" => Instruction::ULt,
+            "u>" => Instruction::UGt,
+
+            // Bit manipulation
+            "popcount" => Instruction::Popcount,
+            "ctz" => Instruction::Ctz,
+            "clz" => Instruction::Clz,
+            "rol" => Instruction::Rotl,
+            "ror" => Instruction::Rotr,
+
+            // Control flow
+            "return" => Instruction::Return,
+            "execute" => Instruction::Execute,
+
+            // Memory
+            "@" | "fetch" => Instruction::Load,
+            "!" | "store" => Instruction::Store,
+            "c@" => Instruction::Load8,
+            "c!" => Instruction::Store8,
+            "w@" => Instruction::Load16,
+            "w!" => Instruction::Store16,
+            "l@" => Instruction::Load32,
+            "l!" => Instruction::Store32,
+
+            _ => return None,
+        };
+        Some(inst)
+    }
+
+    /// Forth spelling of a builtin instruction, the inverse of
+    /// [`Instruction::from_word`] (literals are spelled as numbers)
+    pub fn to_word(&self) -> Option<String> {
+        use Instruction::*;
+        let word = match self {
+            Literal(value) => return Some(value.to_string()),
+            Dup => "dup",
+            Drop => "drop",
+            Swap => "swap",
+            Over => "over",
+            Rot => "rot",
+            Nip => "nip",
+            Tuck => "tuck",
+            Add => "+",
+            Sub => "-",
+            Mul => "*",
+            Div => "/",
+            Mod => "mod",
+            Neg => "negate",
+            Abs => "abs",
+            And => "and",
+            Or => "or",
+            Xor => "xor",
+            Not => "invert",
+            Shl => "lshift",
+            Shr => "rshift",
+            Eq => "=",
+            Ne => "<>",
+            Lt => "<",
+            Le => "<=",
+            Gt => ">",
+            Ge => ">=",
+            ZeroEq => "0=",
+            ZeroLt => "0<",
+            ZeroGt => "0>",
+            ULt => "u<",
+            UGt => "u>",
+            Popcount => "popcount",
+            Ctz => "ctz",
+            Clz => "clz",
+            Rotl => "rol",
+            Rotr => "ror",
+            Load => "@",
+            Store => "!",
+            Load8 => "c@",
+            Store8 => "c!",
+            Load16 => "w@",
+            Store16 => "w!",
+            Load32 => "l@",
+            Store32 => "l!",
+            Execute => "execute",
+            _ => return None,
+        };
+        Some(word.to_string())
+    }
+
+    /// Instructions for a word no single instruction implements, such as
+    /// those leaving two cells
+    pub fn expand_word(word: &str) -> Option<Vec<Self>> {
+        use Instruction::*;
+        match word {
+            "um*" => Some(vec![Over, Over, UMul, Rot, Rot, UMulHigh]),
+            "u/mod" => Some(vec![Over, Over, UMod, Rot, Rot, UDiv]),
+            _ => None,
+        }
+    }
+
     /// Get the stack effect of this instruction
     pub fn stack_effect(&self) -> StackEffect {
         use Instruction::*;
@@ -148,7 +329,10 @@ impl Instruction {
 
             Dup => StackEffect::new(1, 2),
             Drop => StackEffect::new(1, 0),
-            Swap | Rot | Nip | Tuck => StackEffect::new(2, 2),
+            Swap => StackEffect::new(2, 2),
+            Rot => StackEffect::new(3, 3),
+            Nip => StackEffect::new(2, 1),
+            Tuck => StackEffect::new(2, 3),
             Over => StackEffect::new(2, 3),
             Pick(_) => StackEffect::new(1, 1), // Simplified
             Roll(_) => StackEffect::new(1, 0),
@@ -156,15 +340,17 @@ impl Instruction {
             Add | Sub | Mul | Div | Mod => StackEffect::new(2, 1),
             And | Or | Xor => StackEffect::new(2, 1),
             Eq | Ne | Lt | Le | Gt | Ge => StackEffect::new(2, 1),
-            Shl | Shr => StackEffect::new(2, 1),
+            ULt | UGt | UDiv | UMod | UMul | UMulHigh => StackEffect::new(2, 1),
+            Shl | Shr | Rotl | Rotr => StackEffect::new(2, 1),
 
             Neg | Abs | Not => StackEffect::new(1, 1),
+            Popcount | Ctz | Clz => StackEffect::new(1, 1),
             ZeroEq | ZeroLt | ZeroGt => StackEffect::new(1, 1),
 
             Load => StackEffect::new(1, 1),
             Store => StackEffect::new(2, 0),
-            Load8 => StackEffect::new(1, 1),
-            Store8 => StackEffect::new(2, 0),
+            Load8 | Load16 | Load32 => StackEffect::new(1, 1),
+            Store8 | Store16 | Store32 => StackEffect::new(2, 0),
 
             ToR => StackEffect::new(1, 0),
             FromR => StackEffect::new(0, 1),
@@ -172,18 +358,22 @@ impl Instruction {
 
             // Superinstructions
             DupAdd | DupMul => StackEffect::new(1, 1),
-            OverAdd => StackEffect::new(2, 3),
+            OverAdd => StackEffect::new(2, 2),
             SwapSub => StackEffect::new(2, 1),
             LiteralAdd(_) | LiteralMul(_) => StackEffect::new(1, 1),
             IncOne | DecOne | MulTwo | DivTwo => StackEffect::new(1, 1),
+            ExtractBits { .. } | Lookup(_) => StackEffect::new(1, 1),
 
             // Stack caching
             CachedDup { .. } => StackEffect::new(1, 2),
-            CachedSwap { .. } | CachedOver { .. } => StackEffect::new(2, 2),
+            CachedSwap { .. } => StackEffect::new(2, 2),
+            CachedOver { .. } => StackEffect::new(2, 3),
             FlushCache => StackEffect::new(0, 0),
 
             Return | Branch(_) | BranchIf(_) | BranchIfNot(_) => StackEffect::new(0, 0),
             Call(_) => StackEffect::new(0, 0), // Depends on called word
+            Tick(_) => StackEffect::new(0, 1),
+            Execute => StackEffect::new(1, 0), // Plus the called word's
 
             // Concurrency primitives
             Spawn => StackEffect::new(1, 1),          // ( xt -- thread-id )
@@ -194,6 +384,24 @@ impl Instruction {
             CloseChannel => StackEffect::new(1, 0),   // ( chan -- )
             DestroyChannel => StackEffect::new(1, 0), // ( chan -- )
 
+            // Induction variables
+            InductionInit { .. } => StackEffect::new(1, 0),
+            InductionVar(_) => StackEffect::new(0, 1),
+            InductionStep { .. } => StackEffect::new(0, 0),
+
+            // Vector kernels
+            VectorReduce { .. } => StackEffect::new(3, 1),
+            VectorMap { .. } | VectorZip { .. } => StackEffect::new(2, 0),
+
+            // Promoted variables
+            LocalFetch(_) => StackEffect::new(0, 1),
+            LocalStore(_) => StackEffect::new(1, 0),
+
+            Fused(body) => body
+                .iter()
+                .map(|inst| inst.stack_effect())
+                .fold(StackEffect::new(0, 0), |acc, e| acc.compose(&e)),
+
             Comment(_) | Label(_) | Nop => StackEffect::new(0, 0),
         }
     }
@@ -201,15 +409,44 @@ impl Instruction {
     /// Check if this is a pure operation (no side effects)
     pub fn is_pure(&self) -> bool {
         use Instruction::*;
+        if let Fused(body) = self {
+            return body.iter().all(|inst| inst.is_pure());
+        }
         !matches!(
             self,
-            Store | Store8 | ToR | Call(_) | Return | Branch(_) |
+            Store | Store8 | Store16 | Store32 | ToR | Call(_) | Execute | Return | Branch(_) |
             BranchIf(_) | BranchIfNot(_) | FlushCache |
             // Concurrency primitives are NOT pure (side effects)
-            Spawn | Join | Channel(_) | Send | Recv | CloseChannel | DestroyChannel
+            Spawn | Join | Channel(_) | Send | Recv | CloseChannel | DestroyChannel |
+            // Induction variable updates carry state between iterations
+            InductionInit { .. } | InductionStep { .. } |
+            VectorMap { .. } | VectorZip { .. } |
+            LocalStore(_)
         )
     }
 
+    /// Bytes a load or store accesses: a cell is 8
+    pub fn access_width(&self) -> Option<u8> {
+        use Instruction::*;
+        match self {
+            Load | Store => Some(8),
+            Load8 | Store8 => Some(1),
+            Load16 | Store16 => Some(2),
+            Load32 | Store32 => Some(4),
+            _ => None,
+        }
+    }
+
+    /// Whether this reads memory, at any width
+    pub fn is_load(&self) -> bool {
+        matches!(self, Instruction::Load | Instruction::Load8 | Instruction::Load16 | Instruction::Load32)
+    }
+
+    /// Whether this writes memory, at any width
+    pub fn is_store(&self) -> bool {
+        matches!(self, Instruction::Store | Instruction::Store8 | Instruction::Store16 | Instruction::Store32)
+    }
+
     /// Check if this is a constant value
     pub fn as_constant(&self) -> Option<i64> {
         match self {
@@ -217,6 +454,64 @@ impl Instruction {
             _ => None,
         }
     }
+
+    /// The first local slot `instructions` leave unused
+    pub fn free_local_slot(instructions: &[Instruction]) -> u8 {
+        instructions
+            .iter()
+            .map(|inst| match inst {
+                Instruction::LocalFetch(slot) | Instruction::LocalStore(slot) => slot.saturating_add(1),
+                Instruction::Fused(body) => Self::free_local_slot(body),
+                _ => 0,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// `instructions` with each local slot moved up by `offset`, so a body
+    /// inlined into a word keeps its locals apart from the word's own
+    ///
+    /// None when the slots would run past the last one.
+    pub fn shift_locals(instructions: &[Instruction], offset: u8) -> Option<Vec<Instruction>> {
+        instructions
+            .iter()
+            .map(|inst| match inst {
+                Instruction::LocalFetch(slot) => slot.checked_add(offset).map(Instruction::LocalFetch),
+                Instruction::LocalStore(slot) => slot.checked_add(offset).map(Instruction::LocalStore),
+                Instruction::Fused(body) => Self::shift_locals(body, offset).map(Instruction::Fused),
+                inst => Some(inst.clone()),
+            })
+            .collect()
+    }
+}
+
+/// Programmer attributes of a word (`\ @inline(never)`, `\ @optimize(none)`, `\ @hot`,
+/// `\ @constant-time`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WordAttributes {
+    /// Inlining directive from `@inline(always)` or `@inline(never)`
+    pub inline: InlineDirective,
+    /// Passes leave the body as written, and calls to it are not inlined
+    pub optimize_none: bool,
+    /// Runs often: inlined at any number of call sites, compiled natively first
+    pub hot: bool,
+    /// Handles secrets: no pass may add branches or early exits that
+    /// depend on its data
+    pub constant_time: bool,
+    /// Called from outside the program, as a C export (`@export`)
+    pub export: bool,
+}
+
+/// Whether code outside the program can call a word
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Linkage {
+    /// Visible to the linker: an export, the entry point, or not yet
+    /// analyzed
+    #[default]
+    External,
+    /// Called only from within the program, so the code generator may
+    /// inline it everywhere and drop it
+    Internal,
 }
 
 /// Word definition (like a function)
@@ -227,6 +522,8 @@ pub struct WordDef {
     pub stack_effect: StackEffect,
     pub is_inline: bool,
     pub cost: usize, // Instruction count for inlining decisions
+    pub attributes: WordAttributes,
+    pub linkage: Linkage,
 }
 
 impl WordDef {
@@ -239,6 +536,18 @@ impl WordDef {
             stack_effect,
             is_inline: false,
             cost,
+            attributes: WordAttributes::default(),
+            linkage: Linkage::default(),
+        }
+    }
+
+    /// How inliners treat calls to the word: `is_inline` and the attributes
+    /// combined
+    pub fn inline_directive(&self) -> InlineDirective {
+        match self.attributes.inline {
+            InlineDirective::Auto if self.attributes.optimize_none => InlineDirective::NeverInline,
+            InlineDirective::Auto if self.is_inline => InlineDirective::AlwaysInline,
+            directive => directive,
         }
     }
 
@@ -256,11 +565,76 @@ impl WordDef {
     }
 }
 
+/// Element-wise operation of a vector kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VectorOp {
+    Add,
+    Sub,
+    Mul,
+    And,
+    Or,
+    Xor,
+}
+
+impl VectorOp {
+    /// Vector operation performing a binary instruction
+    pub fn from_instruction(inst: &Instruction) -> Option<Self> {
+        let op = match inst {
+            Instruction::Add => VectorOp::Add,
+            Instruction::Sub => VectorOp::Sub,
+            Instruction::Mul => VectorOp::Mul,
+            Instruction::And => VectorOp::And,
+            Instruction::Or => VectorOp::Or,
+            Instruction::Xor => VectorOp::Xor,
+            _ => return None,
+        };
+        Some(op)
+    }
+}
+
+/// A counted `DO ... LOOP` or `DO ... +LOOP` in an instruction sequence
+///
+/// Counted loops are lowered to flat instructions,
+/// `(do) L<n>: body (loop) BranchIfNot(n)`, with `(+loop)` taking the step
+/// from the stack instead. Induction variable setup may sit between `(do)`
+/// and the header. The indices locate each part of the loop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CountedLoop {
+    /// Index of the `(do)` call
+    pub start: usize,
+    /// Index of the header label
+    pub header: usize,
+    /// Index of the first increment instruction: the `(loop)` call, or the
+    /// step literal before `(+loop)`
+    pub latch: usize,
+    /// Index of the branch back to the header
+    pub end: usize,
+    /// Index increment per iteration, if known at compile time
+    pub step: Option<i64>,
+    /// Number of loops enclosing this one
+    pub depth: usize,
+}
+
+impl CountedLoop {
+    /// Index range of the loop body
+    pub fn body(&self) -> std::ops::Range<usize> {
+        self.header + 1..self.latch
+    }
+
+    /// Whether another loop is nested inside this one
+    pub fn contains(&self, other: &CountedLoop) -> bool {
+        self.start < other.start && other.end < self.end
+    }
+}
+
 /// Complete Forth IR with all word definitions
 #[derive(Debug, Clone, PartialEq)]
 pub struct ForthIR {
     pub words: HashMap<String, WordDef>,
     pub main: Vec<Instruction>,
+    /// Names defined with `VARIABLE` or `CREATE ... ALLOT`, with their sizes
+    /// in bytes; referencing one pushes its address
+    pub variables: HashMap<String, usize>,
 }
 
 impl ForthIR {
@@ -268,6 +642,7 @@ impl ForthIR {
         Self {
             words: HashMap::new(),
             main: Vec::new(),
+            variables: HashMap::new(),
         }
     }
 
@@ -280,58 +655,19 @@ impl ForthIR {
         let mut instructions = Vec::new();
 
         for token in tokens {
-            let inst = match token {
-                // Stack operations
-                "dup" => Instruction::Dup,
-                "drop" => Instruction::Drop,
-                "swap" => Instruction::Swap,
-                "over" => Instruction::Over,
-                "rot" => Instruction::Rot,
-                "nip" => Instruction::Nip,
-                "tuck" => Instruction::Tuck,
-
-                // Arithmetic
-                "+" => Instruction::Add,
-                "-" => Instruction::Sub,
-                "*" => Instruction::Mul,
-                "/" => Instruction::Div,
-                "mod" => Instruction::Mod,
-                "negate" => Instruction::Neg,
-                "abs" => Instruction::Abs,
-
-                // Bitwise
-                "&" | "and" => Instruction::And,
-                "|" | "or" => Instruction::Or,
-                "^" | "xor" => Instruction::Xor,
-                "~" | "not" | "invert" => Instruction::Not,
-                "<<" | "lshift" => Instruction::Shl,
-                ">>" | "rshift" => Instruction::Shr,
-
-                // Comparison
-                "=" => Instruction::Eq,
-                "<>" => Instruction::Ne,
-                "<" => Instruction::Lt,
-                "<=" => Instruction::Le,
-                ">" => Instruction::Gt,
-                ">=" => Instruction::Ge,
-                "0=" => Instruction::ZeroEq,
-                "0<" => Instruction::ZeroLt,
-                "0>" => Instruction::ZeroGt,
-
-                // Control flow
-                "return" => Instruction::Return,
-
-                // Memory
-                "@" | "fetch" => Instruction::Load,
-                "!" | "store" => Instruction::Store,
-
-                _ => {
+            if let Some(expansion) = Instruction::expand_word(token) {
+                instructions.extend(expansion);
+                continue;
+            }
+            let inst = match Instruction::from_word(token) {
+                Some(inst) => inst,
+                None => {
                     if let Ok(n) = token.parse::<i64>() {
                         Instruction::Literal(n)
                     } else if token.starts_with(':') || token.starts_with(';') {
                         continue; // Skip word definition markers
                     } else {
-                        Instruction::Call(token.to_string())
+                        Instruction::Call(token.into())
                     }
                 }
             };
@@ -347,6 +683,16 @@ impl ForthIR {
         self.words.insert(word.name.clone(), word);
     }
 
+    /// Declare a variable of one cell
+    pub fn add_variable(&mut self, name: impl Into<String>) {
+        self.add_buffer(name, 8);
+    }
+
+    /// Declare a variable of `size` bytes
+    pub fn add_buffer(&mut self, name: impl Into<String>, size: usize) {
+        self.variables.insert(name.into(), size);
+    }
+
     /// Get a word definition
     pub fn get_word(&self, name: &str) -> Option<&WordDef> {
         self.words.get(name)
@@ -363,8 +709,9 @@ impl ForthIR {
         self.verify_sequence(&self.main)?;
 
         // Check each word
+        // Words start with their inputs already on the stack
         for (name, word) in &self.words {
-            self.verify_sequence(&word.instructions).map_err(|e| {
+            self.verify_sequence_from(&word.instructions, word.stack_effect.consumed as i32).map_err(|e| {
                 OptimizerError::InvalidStackEffect(format!("In word '{}': {}", name, e))
             })?;
         }
@@ -373,10 +720,18 @@ impl ForthIR {
     }
 
     fn verify_sequence(&self, instructions: &[Instruction]) -> Result<()> {
-        let mut depth = 0i32;
+        self.verify_sequence_from(instructions, 0)
+    }
+
+    fn verify_sequence_from(&self, instructions: &[Instruction], initial_depth: i32) -> Result<()> {
+        let mut depth = initial_depth;
 
         for (i, inst) in instructions.iter().enumerate() {
-            let effect = inst.stack_effect();
+            let effect = match inst {
+                // A variable pushes its address
+                Instruction::Call(name) if self.variables.contains_key(name.as_str()) => StackEffect::new(0, 1),
+                _ => inst.stack_effect(),
+            };
             depth -= effect.consumed as i32;
 
             if depth < 0 {
@@ -393,6 +748,73 @@ impl ForthIR {
         Ok(())
     }
 
+    /// Find the counted loops of an instruction sequence, in order of their
+    /// `(do)` calls
+    ///
+    /// Loops whose parts are not in the shape produced by lowering (for
+    /// example after a pass rewrote the back branch) are not reported.
+    pub fn counted_loops(instructions: &[Instruction]) -> Vec<CountedLoop> {
+        let mut loops = Vec::new();
+        let mut open: Vec<(usize, usize)> = Vec::new();
+
+        for (index, inst) in instructions.iter().enumerate() {
+            match inst {
+                Instruction::Call(name) if name == "(do)" => {
+                    let header = Self::loop_header(instructions, index + 1);
+                    if let Some(Instruction::Label(_)) = instructions.get(header) {
+                        open.push((index, loops.len()));
+                        loops.push(None);
+                    }
+                }
+                Instruction::Call(name) if name == "(loop)" || name == "(+loop)" => {
+                    let Some((start, slot)) = open.pop() else {
+                        continue;
+                    };
+                    let header = Self::loop_header(instructions, start + 1);
+                    let closes = match (&instructions[header], instructions.get(index + 1)) {
+                        (Instruction::Label(label), Some(Instruction::BranchIfNot(target))) => {
+                            *label == format!("L{}", target)
+                        }
+                        _ => false,
+                    };
+                    if !closes {
+                        continue;
+                    }
+
+                    let (latch, step) = if name == "(loop)" {
+                        (index, Some(1))
+                    } else {
+                        match instructions[..index].last() {
+                            Some(Instruction::Literal(step)) if index - 1 > header => (index - 1, Some(*step)),
+                            _ => (index, None),
+                        }
+                    };
+
+                    loops[slot] = Some(CountedLoop {
+                        start,
+                        header,
+                        latch,
+                        end: index + 1,
+                        step,
+                        depth: open.len(),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        loops.into_iter().flatten().collect()
+    }
+
+    /// Index of the header label following `(do)`, past any induction
+    /// variable setup
+    fn loop_header(instructions: &[Instruction], mut index: usize) -> usize {
+        while let Some(Instruction::InductionInit { .. }) = instructions.get(index + 1) {
+            index += 2;
+        }
+        index
+    }
+
     /// Count total instructions
     pub fn instruction_count(&self) -> usize {
         self.main.len()
@@ -436,6 +858,43 @@ mod tests {
         assert!(matches!(ir.main[2], Instruction::Add));
     }
 
+    #[test]
+    fn test_word_spelling_round_trips() {
+        for word in ["dup", "over", "+", "invert", "0=", "@", "!", "c@", "w!", "l@"] {
+            let inst = Instruction::from_word(word).unwrap();
+            assert_eq!(inst.to_word().as_deref(), Some(word));
+        }
+        assert_eq!(Instruction::Literal(-3).to_word().as_deref(), Some("-3"));
+        assert_eq!(Instruction::Call("foo".into()).to_word(), None);
+    }
+
+    #[test]
+    fn test_counted_loops() {
+        let call = |name: &str| Instruction::Call(name.into());
+        let instructions = vec![
+            call("(do)"),
+            Instruction::Label("L0".to_string()),
+            call("(do)"),
+            Instruction::Label("L1".to_string()),
+            call("i"),
+            Instruction::Drop,
+            Instruction::Literal(2),
+            call("(+loop)"),
+            Instruction::BranchIfNot(1),
+            call("(loop)"),
+            Instruction::BranchIfNot(0),
+        ];
+
+        let loops = ForthIR::counted_loops(&instructions);
+        assert_eq!(loops.len(), 2);
+        assert_eq!((loops[0].start, loops[0].latch, loops[0].end), (0, 9, 10));
+        assert_eq!((loops[0].step, loops[0].depth), (Some(1), 0));
+        assert_eq!((loops[1].header, loops[1].latch, loops[1].end), (3, 6, 8));
+        assert_eq!((loops[1].step, loops[1].depth), (Some(2), 1));
+        assert_eq!(loops[1].body(), 4..6);
+        assert!(loops[0].contains(&loops[1]));
+    }
+
     #[test]
     fn test_word_def_stack_effect() {
         let word = WordDef::new(
@@ -458,4 +917,21 @@ mod tests {
         ir.main = vec![Instruction::Add]; // Requires 2 items but stack is empty
         assert!(matches!(ir.verify(), Err(OptimizerError::StackUnderflow(_))));
     }
+
+    #[test]
+    fn test_verify_word_with_inputs() {
+        let mut ir = ForthIR::new();
+        ir.add_word(WordDef::new(
+            "square".to_string(),
+            vec![Instruction::Dup, Instruction::Mul],
+        ));
+        assert!(ir.verify().is_ok());
+    }
+
+    #[test]
+    fn test_from_word() {
+        assert_eq!(Instruction::from_word("dup"), Some(Instruction::Dup));
+        assert_eq!(Instruction::from_word("invert"), Some(Instruction::Not));
+        assert_eq!(Instruction::from_word("square"), None);
+    }
 }
@@ -11,12 +11,24 @@
 //!   - Compile-time constant evaluation and algebraic simplification
 //!   - Conditional elimination based on constant conditions
 //!   - Loop unrolling with constant bounds
-//! - **Stack Caching**: Keep TOS/NOS/3OS in registers (2-3x speedup)
-//! - **Superinstructions**: Fuse common patterns (20-30% code size reduction)
+//! - **Stack Caching**: Keep the top stack items in registers, with the depth chosen per word (2-3x speedup)
+//! - **Superinstructions**: Fuse common patterns (20-30% code size reduction), optionally learned from a corpus
+//! - **Peephole**: Strength reduction and local rewrites, extensible with user-defined rules
 //! - **Constant Folding**: Compile-time evaluation of constants
 //! - **Dead Code Elimination**: Remove unused stack operations
 //! - **Inlining**: Expand small words with stack effect analysis
 //! - **Memory Optimization**: Alias analysis, load/store reordering, prefetching (5-15% speedup)
+//! - **Induction Variables**: Replace scaled loop indices with additive updates in counted loops
+//! - **Vectorization**: Turn stride-1 array loops (sum, scale, element-wise) into SIMD kernels
+//! - **Escape Analysis**: Keep variables used as scratch space by one word in registers
+//!
+//! The passes an optimizer runs, and their order, come from a
+//! [`PassPipeline`]: the preset of its optimization level unless
+//! [`Optimizer::set_pipeline`] gives it another.
+//!
+//! [`Optimizer::set_report`] makes the optimizer record what each pass did,
+//! per word, in an [`OptimizationReport`], and [`Optimizer::set_timing`]
+//! when each pass ran and for how long.
 //!
 //! # Example
 //!
@@ -48,9 +60,22 @@ pub mod memory_opt;
 pub mod whole_program;
 pub mod zero_cost;
 pub mod cranelift_peephole;
-
-pub use ir::{ForthIR, Instruction, StackEffect, WordDef};
-pub use stack_cache::StackCacheOptimizer;
+pub mod induction;
+pub mod vectorize;
+pub mod escape;
+pub mod corpus;
+pub mod report;
+pub mod interpreter;
+pub mod differential;
+pub mod lower;
+pub mod trace;
+pub mod timing;
+pub mod pass_manager;
+pub mod peephole_rules;
+pub mod eval_cache;
+
+pub use ir::{CountedLoop, ForthIR, Instruction, StackEffect, Symbol, VectorOp, WordDef};
+pub use stack_cache::{CacheDepth, CacheProfile, StackCacheOptimizer, StackCacheStats};
 pub use superinstructions::SuperinstructionOptimizer;
 pub use pgo_superinstructions::{PGOOptimizer, PatternDatabase, PGOStats, PGOConfig};
 pub use constant_fold::ConstantFolder;
@@ -62,9 +87,29 @@ pub use memory_opt::{MemoryOptimizer, OptimizationStats as MemoryOptimizationSta
 pub use whole_program::{WholeProgramOptimizer, WPOStats};
 pub use zero_cost::{ZeroCostOptimizer, ZeroCostConfig, ZeroCostStats};
 pub use cranelift_peephole::{CraneliftPeephole, PeepholeStats};
-
+pub use induction::InductionVariableOptimizer;
+pub use vectorize::Vectorizer;
+pub use escape::EscapeAnalyzer;
+pub use corpus::{CorpusMiner, SuperinstructionTable, TableEntry};
+pub use report::{OptimizationReport, PassReport, WordReport};
+pub use interpreter::{Console, IrInterpreter, InterpretError};
+pub use differential::Divergence;
+pub use trace::{DiffHunk, OptimizerTracer, PassSnapshot, WordDiff};
+pub use timing::PassTiming;
+pub use peephole_rules::{PeepholeRule, PeepholeRules};
+pub use eval_cache::EvalCache;
+pub use pass_manager::{Pass, PassInfo, PassManager, PassPipeline, PassScope, Stage};
+
+use fastforth_frontend::Arithmetic;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::time::Instant;
 use thiserror::Error;
 
+/// Below this many word definitions, [`Optimizer`] runs every pass on the
+/// calling thread
+pub const PARALLEL_OPTIMIZE_THRESHOLD: usize = 16;
+
 #[derive(Error, Debug)]
 pub enum OptimizerError {
     #[error("Stack underflow at instruction {0}")]
@@ -81,6 +126,9 @@ pub enum OptimizerError {
 
     #[error("Parse error: {0}")]
     ParseError(String),
+
+    #[error("Invalid pass pipeline: {0}")]
+    InvalidPipeline(String),
 }
 
 pub type Result<T> = std::result::Result<T, OptimizerError>;
@@ -110,8 +158,34 @@ pub struct Optimizer {
     type_specializer: TypeSpecializer,
     memory_opt: MemoryOptimizer,
     cranelift_peephole: CraneliftPeephole,
-    // whole_program: WholeProgramOptimizer, // Temporarily disabled
+    stack_cache_stats: StackCacheStats,
+    induction: InductionVariableOptimizer,
+    vectorizer: Vectorizer,
+    escape: EscapeAnalyzer,
+    passes: PassManager,
+    whole_program: WholeProgramOptimizer,
     pgo_enabled: bool,
+    vectorize: bool,
+    reporting: bool,
+    report: Option<OptimizationReport>,
+    tracing: bool,
+    trace: Option<OptimizerTracer>,
+    timing: bool,
+    timings: Vec<PassTiming>,
+    eval_cache: Option<EvalCache>,
+}
+
+/// A word after the word-local passes
+struct LocalWord {
+    word: WordDef,
+    /// Stack cache depth chosen, when stack caching ran
+    profile: Option<CacheProfile>,
+    /// Superinstructions and dead code, when reporting
+    report: WordReport,
+    /// The word after each local pass, when reporting or tracing
+    passes: Vec<WordDef>,
+    /// Each local pass over the word, when timing
+    timings: Vec<PassTiming>,
 }
 
 impl Optimizer {
@@ -119,7 +193,7 @@ impl Optimizer {
         Self {
             level,
             zero_cost: ZeroCostOptimizer::default(),
-            stack_cache: StackCacheOptimizer::new(3), // TOS, NOS, 3OS
+            stack_cache: StackCacheOptimizer::with_depth(CacheDepth::host()),
             superinstructions: SuperinstructionOptimizer::new(),
             pgo: PGOOptimizer::new(),
             constant_fold: ConstantFolder::new(),
@@ -128,11 +202,138 @@ impl Optimizer {
             type_specializer: TypeSpecializer::new(),
             memory_opt: MemoryOptimizer::new(),
             cranelift_peephole: CraneliftPeephole::new(),
-            // whole_program: WholeProgramOptimizer::new(level), // Temporarily disabled
+            stack_cache_stats: StackCacheStats::default(),
+            induction: InductionVariableOptimizer::new(),
+            vectorizer: Vectorizer::new(),
+            escape: EscapeAnalyzer::new(),
+            passes: PassManager::preset(level),
+            whole_program: WholeProgramOptimizer::new(level),
             pgo_enabled: false,
+            vectorize: true,
+            reporting: false,
+            report: None,
+            tracing: false,
+            trace: None,
+            timing: false,
+            timings: Vec::new(),
+            eval_cache: None,
+        }
+    }
+
+    /// Record an [`OptimizationReport`] on each run (off by default)
+    pub fn set_report(&mut self, reporting: bool) {
+        self.reporting = reporting;
+        if !reporting {
+            self.report = None;
+        }
+    }
+
+    /// Report of the last run, when reporting is enabled
+    pub fn report(&self) -> Option<&OptimizationReport> {
+        self.report.as_ref()
+    }
+
+    /// Take the report of the last run, leaving none until the next run
+    pub fn take_report(&mut self) -> Option<OptimizationReport> {
+        self.report.take()
+    }
+
+    /// Record an [`OptimizerTracer`] on each run (off by default)
+    pub fn set_trace(&mut self, tracing: bool) {
+        self.tracing = tracing;
+        if !tracing {
+            self.trace = None;
         }
     }
 
+    /// Trace of the last run, when tracing is enabled
+    pub fn trace(&self) -> Option<&OptimizerTracer> {
+        self.trace.as_ref()
+    }
+
+    /// Take the trace of the last run, leaving none until the next run
+    pub fn take_trace(&mut self) -> Option<OptimizerTracer> {
+        self.trace.take()
+    }
+
+    /// Record a [`PassTiming`] for each pass on each run (off by default)
+    pub fn set_timing(&mut self, timing: bool) {
+        self.timing = timing;
+        self.timings.clear();
+    }
+
+    /// Take the pass timings of the last run, leaving none until the next run
+    pub fn take_timings(&mut self) -> Vec<PassTiming> {
+        std::mem::take(&mut self.timings)
+    }
+
+    /// Take the zero-cost pass's results for unchanged words from `cache`,
+    /// and store the others' in it
+    pub fn set_eval_cache(&mut self, cache: Option<EvalCache>) {
+        self.eval_cache = cache;
+    }
+
+    pub fn eval_cache(&self) -> Option<&EvalCache> {
+        self.eval_cache.as_ref()
+    }
+
+    pub fn take_eval_cache(&mut self) -> Option<EvalCache> {
+        self.eval_cache.take()
+    }
+
+    /// Set how many stack items are cached in registers
+    pub fn set_cache_depth(&mut self, depth: CacheDepth) {
+        self.stack_cache = StackCacheOptimizer::with_depth(depth);
+    }
+
+    /// Fold arithmetic as code compiled under `arithmetic` computes it
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.constant_fold = ConstantFolder::with_arithmetic(arithmetic);
+        self.cranelift_peephole.set_arithmetic(arithmetic);
+        self.whole_program.set_arithmetic(arithmetic);
+    }
+
+    /// Fuse the sequences of a superinstruction table learned from a
+    /// corpus, in addition to the builtin patterns
+    pub fn set_superinstruction_table(&mut self, table: &SuperinstructionTable) {
+        self.superinstructions = SuperinstructionOptimizer::with_table(table);
+    }
+
+    /// Apply user-defined peephole rules in the peephole pass, before the
+    /// builtin rewrites
+    pub fn set_peephole_rules(&mut self, rules: &PeepholeRules) {
+        self.cranelift_peephole.set_rules(rules.clone());
+    }
+
+    /// Run `pipeline` instead of the preset of the optimization level
+    ///
+    /// Fails if a pass would run after one that must follow it. The
+    /// passes keep the thresholds of the optimization level.
+    pub fn set_pipeline(&mut self, pipeline: PassPipeline) -> Result<()> {
+        self.passes = PassManager::new(pipeline)?;
+        Ok(())
+    }
+
+    /// The passes each run goes through
+    pub fn pipeline(&self) -> &PassPipeline {
+        self.passes.pipeline()
+    }
+
+    /// Leave a pass out of every run
+    pub fn disable_pass(&mut self, pass: Pass) {
+        self.passes.disable(pass);
+    }
+
+    /// Enable or disable loop vectorization (on by default at Standard and above)
+    pub fn set_vectorize(&mut self, vectorize: bool) {
+        self.vectorize = vectorize;
+    }
+
+    /// Whether loop vectorization is enabled
+    pub fn vectorize(&self) -> bool {
+        self.vectorize
+    }
+
     /// Enable Profile-Guided Optimization
     pub fn enable_pgo(&mut self) {
         self.pgo_enabled = true;
@@ -173,107 +374,340 @@ impl Optimizer {
         Ok((ir, pgo_stats))
     }
 
-    /// Run all optimization passes in the optimal order
-    pub fn optimize(&mut self, mut ir: ForthIR) -> Result<ForthIR> {
-        if self.level == OptimizationLevel::None {
-            return Ok(ir);
-        }
-
-        // Pass 0: Zero-cost abstractions (aggressive inlining, constant folding, algebraic simplification)
-        // This early aggressive pass eliminates abstraction overhead
-        if self.level >= OptimizationLevel::Aggressive {
-            ir = self.zero_cost.optimize(&ir)?;
-        }
-
-        // Pass 1: Constant folding (enables other optimizations)
-        ir = self.constant_fold.fold(&ir)?;
-
-        // Pass 1.5: Cranelift-specific peephole optimizations (strength reduction, etc.)
-        // Run after constant folding for maximum effectiveness
-        if self.level >= OptimizationLevel::Basic {
-            ir = self.cranelift_peephole.optimize(&ir)?;
-        }
-
-        // Pass 2: Inlining (expands small definitions)
-        if self.level >= OptimizationLevel::Standard {
-            ir = self.inline.inline(&ir)?;
-        }
-
-        // Pass 3: Superinstruction recognition (after inlining)
-        if self.level >= OptimizationLevel::Basic {
-            ir = self.superinstructions.recognize(&ir)?;
-        }
+    /// Run the passes of the pipeline
+    pub fn optimize(&mut self, ir: ForthIR) -> Result<ForthIR> {
+        self.run_pipeline(ir, None)
+    }
 
-        // Pass 4: Dead code elimination
-        ir = self.dead_code.eliminate(&ir)?;
+    /// Run the passes of the pipeline, including type specialization
+    pub fn optimize_with_types(&mut self, ir: ForthIR, type_info: &TypeInferenceResults) -> Result<ForthIR> {
+        self.run_pipeline(ir, Some(type_info))
+    }
 
-        // Pass 5: Memory optimization (before stack caching)
-        if self.level >= OptimizationLevel::Standard {
-            ir = self.memory_opt.optimize(&ir)?;
+    fn run_pipeline(&mut self, mut ir: ForthIR, type_info: Option<&TypeInferenceResults>) -> Result<ForthIR> {
+        let mut report = self.reporting.then(|| OptimizationReport::new(self.level, &ir));
+        let mut trace = self.tracing.then(|| OptimizerTracer::new(&ir));
+        let mut timings = self.timing.then(Vec::new);
+        let peephole_before = self.cranelift_peephole.stats().clone();
+        let stages = self.passes.stages(|pass| match pass {
+            Pass::TypeSpecialization => type_info.is_some(),
+            Pass::Vectorize => self.vectorize,
+            _ => true,
+        });
+        if stages.is_empty() {
+            self.trace = trace;
+            self.timings = timings.unwrap_or_default();
+            self.finish_report(report, &ir, &peephole_before);
+            return Ok(ir);
         }
 
-        // Pass 6: Stack caching (final pass before codegen)
-        if self.level >= OptimizationLevel::Standard {
-            ir = self.stack_cache.optimize(&ir)?;
+        self.stack_cache_stats = StackCacheStats::default();
+        for stage in stages {
+            match stage {
+                Stage::Program(pass) => {
+                    let start = Instant::now();
+                    ir = self.run_program_pass(pass, ir, type_info, report.as_mut(), trace.as_mut())?;
+                    if let Some(timings) = timings.as_mut() {
+                        timings.push(PassTiming::since(pass.name(), None, start));
+                    }
+                }
+                Stage::Words(passes) => {
+                    let (local, cache_stats) =
+                        self.optimize_local(ir, &passes, report.as_mut(), trace.as_mut(), timings.as_mut())?;
+                    ir = local;
+                    if let Some(cache_stats) = cache_stats {
+                        self.stack_cache_stats = cache_stats;
+                    }
+                }
+            }
         }
 
         // Verify stack effects are still valid
         ir.verify()?;
 
+        self.finish_report(report, &ir, &peephole_before);
+        self.trace = trace;
+        self.timings = timings.unwrap_or_default();
         Ok(ir)
     }
 
-    /// Run optimization with type specialization
-    pub fn optimize_with_types(&mut self, mut ir: ForthIR, type_info: &TypeInferenceResults) -> Result<ForthIR> {
-        if self.level == OptimizationLevel::None {
-            return Ok(ir);
+    /// Run a whole-program pass
+    fn run_program_pass(
+        &mut self,
+        pass: Pass,
+        mut ir: ForthIR,
+        type_info: Option<&TypeInferenceResults>,
+        report: Option<&mut OptimizationReport>,
+        trace: Option<&mut OptimizerTracer>,
+    ) -> Result<ForthIR> {
+        match pass {
+            Pass::ZeroCost => self.run_zero_cost(ir, report, trace),
+            Pass::TypeSpecialization => {
+                let Some(type_info) = type_info else {
+                    return Ok(ir);
+                };
+                let before = report.is_some().then(|| ir.clone());
+                let kept = unoptimized_words(&ir);
+                let stats = self.type_specializer.specialize(&mut ir, type_info)?;
+                restore_words(&mut ir, kept);
+                if let (Some(report), Some(before)) = (report, before) {
+                    report.record_pass(pass.name(), &before, &ir);
+                    report.specialization = Some(stats);
+                }
+                if let Some(trace) = trace {
+                    trace.record(pass.name(), &ir);
+                }
+                Ok(ir)
+            }
+            Pass::WholeProgram => self.run_whole_program(ir, report, trace),
+            Pass::ConstantFold => run_pass(report, trace, pass.name(), ir, |ir| self.constant_fold.fold(ir)),
+            Pass::Peephole => run_pass(report, trace, pass.name(), ir, |ir| self.cranelift_peephole.optimize(ir)),
+            Pass::Inline => self.run_inline(ir, report, trace),
+            Pass::Escape => run_pass(report, trace, pass.name(), ir, |ir| self.escape.promote(ir)),
+            _ => unreachable!("{} is word-local", pass),
         }
+    }
 
-        // Pass 0: Zero-cost abstractions (aggressive early pass for Aggressive level)
-        if self.level >= OptimizationLevel::Aggressive {
-            ir = self.zero_cost.optimize(&ir)?;
+    /// Run the zero-cost pass, recording the calls it inlined
+    fn run_zero_cost(
+        &mut self,
+        ir: ForthIR,
+        report: Option<&mut OptimizationReport>,
+        trace: Option<&mut OptimizerTracer>,
+    ) -> Result<ForthIR> {
+        let kept = unoptimized_words(&ir);
+        let zero_cost = &self.zero_cost;
+        let mut optimized = match self.eval_cache.as_mut() {
+            Some(cache) => cache.run(&ir, |ir| zero_cost.optimize(ir))?,
+            None => zero_cost.optimize(&ir)?,
+        };
+        restore_words(&mut optimized, kept);
+        if let Some(trace) = trace {
+            trace.record("zero_cost", &optimized);
         }
-
-        // Pass 1: Type specialization (early, before other optimizations)
-        if self.level >= OptimizationLevel::Standard {
-            let _stats = self.type_specializer.specialize(&mut ir, type_info)?;
+        if let Some(report) = report {
+            report.record_pass("zero_cost", &ir, &optimized);
+            report.record_inlining(&ir, &optimized);
+            report.zero_cost = Some(self.zero_cost.get_stats(&ir, &optimized));
         }
+        Ok(optimized)
+    }
 
-        // Pass 2: Constant folding (enables other optimizations)
-        ir = self.constant_fold.fold(&ir)?;
-
-        // Pass 2.5: Cranelift-specific peephole optimizations
-        if self.level >= OptimizationLevel::Basic {
-            ir = self.cranelift_peephole.optimize(&ir)?;
+    /// Run the inliner, recording the calls it inlined
+    fn run_inline(
+        &self,
+        ir: ForthIR,
+        report: Option<&mut OptimizationReport>,
+        trace: Option<&mut OptimizerTracer>,
+    ) -> Result<ForthIR> {
+        let kept = unoptimized_words(&ir);
+        let mut optimized = self.inline.inline(&ir)?;
+        restore_words(&mut optimized, kept);
+        if let Some(trace) = trace {
+            trace.record("inline", &optimized);
         }
-
-        // Pass 3: Inlining (expands small definitions)
-        if self.level >= OptimizationLevel::Standard {
-            ir = self.inline.inline(&ir)?;
+        if let Some(report) = report {
+            report.record_pass("inline", &ir, &optimized);
+            report.record_inlining(&ir, &optimized);
+            report.inline = Some(self.inline.get_stats(&ir, &optimized));
         }
+        Ok(optimized)
+    }
 
-        // Pass 4: Superinstruction recognition (after inlining)
-        if self.level >= OptimizationLevel::Basic {
-            ir = self.superinstructions.recognize(&ir)?;
+    /// Run whole-program optimization, recording the single-call words it
+    /// inlined
+    fn run_whole_program(
+        &self,
+        ir: ForthIR,
+        report: Option<&mut OptimizationReport>,
+        trace: Option<&mut OptimizerTracer>,
+    ) -> Result<ForthIR> {
+        let kept = unoptimized_words(&ir);
+        let mut optimized = self.whole_program.optimize(&ir)?;
+        restore_words(&mut optimized, kept);
+        if let Some(trace) = trace {
+            trace.record("whole_program", &optimized);
         }
+        if let Some(report) = report {
+            report.record_pass("whole_program", &ir, &optimized);
+            report.record_inlining(&ir, &optimized);
+            report.whole_program = Some(self.whole_program.get_stats(&ir, &optimized));
+        }
+        Ok(optimized)
+    }
 
-        // Pass 5: Dead code elimination
-        ir = self.dead_code.eliminate(&ir)?;
+    /// Complete the report of a run and keep it
+    fn finish_report(&mut self, report: Option<OptimizationReport>, ir: &ForthIR, peephole_before: &PeepholeStats) {
+        self.report = report.map(|mut report| {
+            report.finish(ir);
+            if self.passes.pipeline().contains(Pass::Peephole) {
+                report.peephole = Some(self.cranelift_peephole.stats().since(peephole_before));
+            }
+            for (name, profile) in &self.stack_cache_stats.words {
+                report.word_mut(name).cache_depth = Some(profile.depth);
+            }
+            report
+        });
+    }
 
-        // Pass 6: Memory optimization (before stack caching)
-        if self.level >= OptimizationLevel::Standard {
-            ir = self.memory_opt.optimize(&ir)?;
+    /// Run consecutive word-local passes: induction variables,
+    /// vectorization, superinstruction recognition, dead code elimination,
+    /// memory optimization and stack caching
+    ///
+    /// None of these passes look beyond the word they rewrite, so the words
+    /// of large programs are optimized in parallel across the rayon thread
+    /// pool, one word per task. Returns the stack cache depth chosen for
+    /// each word when stack caching ran.
+    fn optimize_local(
+        &self,
+        mut ir: ForthIR,
+        passes: &[Pass],
+        mut report: Option<&mut OptimizationReport>,
+        trace: Option<&mut OptimizerTracer>,
+        mut timings: Option<&mut Vec<PassTiming>>,
+    ) -> Result<(ForthIR, Option<StackCacheStats>)> {
+        let recording = report.is_some() || trace.is_some();
+        let before = recording.then(|| ir.clone());
+        let words = std::mem::take(&mut ir.words);
+
+        // The main sequence
+        let mut main_passes: Vec<Vec<Instruction>> = Vec::new();
+        for &pass in passes {
+            let start = Instant::now();
+            match pass {
+                Pass::Induction => ir = self.induction.optimize(&ir)?,
+                Pass::Vectorize => ir = self.vectorizer.vectorize(&ir)?,
+                Pass::Superinstructions => {
+                    let mut fired = Vec::new();
+                    ir.main = self.superinstructions.recognize_sequence_into(&ir.main, &mut fired);
+                    if let Some(report) = report.as_deref_mut() {
+                        let main = report.word_mut(report::MAIN);
+                        main.superinstructions.extend(fired.iter().map(|&index| self.superinstructions.pattern_name(index)));
+                    }
+                }
+                Pass::DeadCode => {
+                    let len = ir.main.len();
+                    ir = self.dead_code.eliminate(&ir)?;
+                    if let Some(report) = report.as_deref_mut() {
+                        report.word_mut(report::MAIN).dead_code_eliminated += len.saturating_sub(ir.main.len());
+                    }
+                }
+                Pass::MemoryOpt => ir = self.memory_opt.optimize(&ir)?,
+                Pass::StackCache => ir = self.stack_cache.optimize(&ir)?,
+                _ => unreachable!("{} is a whole-program pass", pass),
+            }
+            if let Some(timings) = timings.as_deref_mut() {
+                timings.push(PassTiming::since(pass.name(), Some(report::MAIN), start));
+            }
+            if recording {
+                main_passes.push(ir.main.clone());
+            }
         }
 
-        // Pass 7: Stack caching (final pass before codegen)
-        if self.level >= OptimizationLevel::Standard {
-            ir = self.stack_cache.optimize(&ir)?;
+        // Word definitions
+        let original = recording.then(|| words.clone());
+        let optimize = |(name, word): (String, WordDef)| -> Result<(String, LocalWord)> {
+            Ok((name, self.optimize_local_word(&word, passes, recording)?))
+        };
+        #[cfg(feature = "parallel")]
+        let words: Vec<(String, LocalWord)> = if words.len() >= PARALLEL_OPTIMIZE_THRESHOLD {
+            words.into_par_iter().map(optimize).collect::<Result<_>>()?
+        } else {
+            words.into_iter().map(optimize).collect::<Result<_>>()?
+        };
+        #[cfg(not(feature = "parallel"))]
+        let words: Vec<(String, LocalWord)> = words.into_iter().map(optimize).collect::<Result<_>>()?;
+
+        // One snapshot per pass, as if it had run over the whole program
+        if let (Some(mut snapshot), Some(original)) = (before, original) {
+            snapshot.words = original;
+            let mut trace = trace;
+            for (index, &pass) in passes.iter().enumerate() {
+                let previous = report.is_some().then(|| snapshot.clone());
+                snapshot.main = main_passes[index].clone();
+                for (name, local) in &words {
+                    snapshot.words.insert(name.clone(), local.passes[index].clone());
+                }
+                if let (Some(report), Some(previous)) = (report.as_deref_mut(), previous) {
+                    report.record_pass(pass.name(), &previous, &snapshot);
+                }
+                if let Some(trace) = trace.as_deref_mut() {
+                    trace.record(pass.name(), &snapshot);
+                }
+            }
         }
 
-        // Verify stack effects are still valid
-        ir.verify()?;
+        let mut cache_stats = passes.contains(&Pass::StackCache).then(StackCacheStats::default);
+        for (name, local) in words {
+            if let Some(report) = report.as_deref_mut() {
+                let word = report.word_mut(&name);
+                word.superinstructions.extend(local.report.superinstructions);
+                word.dead_code_eliminated += local.report.dead_code_eliminated;
+            }
+            if let Some(timings) = timings.as_deref_mut() {
+                timings.extend(local.timings);
+            }
+            if let (Some(cache_stats), Some(profile)) = (cache_stats.as_mut(), local.profile) {
+                cache_stats.words.insert(name.clone(), profile);
+            }
+            ir.words.insert(name, local.word);
+        }
 
-        Ok(ir)
+        Ok((ir, cache_stats))
+    }
+
+    /// Run word-local passes over a single word definition
+    fn optimize_local_word(&self, word: &WordDef, passes: &[Pass], recording: bool) -> Result<LocalWord> {
+        let mut local = LocalWord {
+            word: word.clone(),
+            profile: None,
+            report: WordReport::default(),
+            passes: Vec::new(),
+            timings: Vec::new(),
+        };
+        if word.attributes.optimize_none {
+            if recording {
+                local.passes = vec![word.clone(); passes.len()];
+            }
+            return Ok(local);
+        }
+        for &pass in passes {
+            let start = self.timing.then(Instant::now);
+            local.word = match pass {
+                Pass::Induction => self.induction.optimize_word(&local.word),
+                // Remainder loops would branch on the data's length
+                Pass::Vectorize if word.attributes.constant_time => local.word.clone(),
+                Pass::Vectorize => self.vectorizer.vectorize_word(&local.word),
+                Pass::Superinstructions => {
+                    let (recognized, fired) = self.superinstructions.recognize_word_reported(&local.word);
+                    if self.reporting {
+                        let names = fired.iter().map(|&index| self.superinstructions.pattern_name(index));
+                        local.report.superinstructions.extend(names);
+                    }
+                    recognized
+                }
+                Pass::DeadCode => {
+                    let eliminated = self.dead_code.eliminate_word(&local.word)?;
+                    local.report.dead_code_eliminated +=
+                        local.word.instructions.len().saturating_sub(eliminated.instructions.len());
+                    eliminated
+                }
+                Pass::MemoryOpt => self.memory_opt.optimize_word(&local.word)?,
+                Pass::StackCache => {
+                    let (cached, profile) = self.stack_cache.optimize_word_profiled(&local.word)?;
+                    local.profile = Some(profile);
+                    cached
+                }
+                _ => unreachable!("{} is a whole-program pass", pass),
+            };
+            if let Some(start) = start {
+                local.timings.push(PassTiming::since(pass.name(), Some(&word.name), start));
+            }
+            if recording {
+                local.passes.push(local.word.clone());
+            }
+        }
+        Ok(local)
     }
 
     /// Get type specialization statistics
@@ -286,15 +720,20 @@ impl Optimizer {
         &self.memory_opt
     }
 
+    /// Get the stack cache depth chosen for each word in the last run
+    pub fn stack_cache_stats(&self) -> &StackCacheStats {
+        &self.stack_cache_stats
+    }
+
     /// Get peephole optimization statistics
     pub fn peephole_stats(&self) -> &PeepholeStats {
         self.cranelift_peephole.stats()
     }
 
-    // /// Get whole-program optimization reference
-    // pub fn whole_program_optimizer(&self) -> &WholeProgramOptimizer {
-    //     &self.whole_program
-    // }
+    /// Get whole-program optimization reference
+    pub fn whole_program_optimizer(&self) -> &WholeProgramOptimizer {
+        &self.whole_program
+    }
 
     /// Run optimization passes in a loop until fixpoint
     pub fn optimize_until_fixpoint(&mut self, ir: ForthIR) -> Result<ForthIR> {
@@ -315,6 +754,38 @@ impl Optimizer {
     }
 }
 
+/// Run a whole-program pass, recording it in the report and the trace when enabled
+fn run_pass(
+    report: Option<&mut OptimizationReport>,
+    trace: Option<&mut OptimizerTracer>,
+    name: &'static str,
+    ir: ForthIR,
+    pass: impl FnOnce(&ForthIR) -> Result<ForthIR>,
+) -> Result<ForthIR> {
+    let kept = unoptimized_words(&ir);
+    let mut optimized = pass(&ir)?;
+    restore_words(&mut optimized, kept);
+    if let Some(report) = report {
+        report.record_pass(name, &ir, &optimized);
+    }
+    if let Some(trace) = trace {
+        trace.record(name, &optimized);
+    }
+    Ok(optimized)
+}
+
+/// Words marked `@optimize(none)`, which every pass leaves as written
+fn unoptimized_words(ir: &ForthIR) -> Vec<WordDef> {
+    ir.words.values().filter(|word| word.attributes.optimize_none).cloned().collect()
+}
+
+/// Put back words as they were before a pass
+fn restore_words(ir: &mut ForthIR, words: Vec<WordDef>) {
+    for word in words {
+        ir.words.insert(word.name.clone(), word);
+    }
+}
+
 impl Default for Optimizer {
     fn default() -> Self {
         Self::new(OptimizationLevel::Standard)
@@ -331,6 +802,112 @@ mod tests {
         assert_eq!(opt.level, OptimizationLevel::Aggressive);
     }
 
+    #[test]
+    fn test_stack_cache_choice_is_reported() {
+        let mut opt = Optimizer::new(OptimizationLevel::Standard);
+        opt.set_cache_depth(CacheDepth::Auto { registers: 4 });
+
+        let mut ir = ForthIR::new();
+        let mut sq5 = WordDef::new("sq5".to_string(), ForthIR::parse("5 dup *").unwrap().main);
+        sq5.attributes.export = true;
+        ir.add_word(sq5);
+        opt.optimize(ir).unwrap();
+
+        let profile = opt.stack_cache_stats().words["sq5"];
+        assert!(profile.depth >= 1 && profile.depth <= 4);
+        assert_eq!(opt.stack_cache_stats().words.len(), 1);
+    }
+
+    #[test]
+    fn test_trace_follows_every_pass() {
+        let mut opt = Optimizer::new(OptimizationLevel::Standard);
+        opt.set_trace(true);
+
+        let mut ir = ForthIR::parse("2 3 + dup").unwrap();
+        ir.add_word(WordDef::new("sq".to_string(), ForthIR::parse("dup *").unwrap().main));
+        for _ in 0..PARALLEL_OPTIMIZE_THRESHOLD {
+            let name = format!("w{}", ir.words.len());
+            let mut word = WordDef::new(name, ForthIR::parse("1 drop dup +").unwrap().main);
+            // Exported, so whole-program optimization keeps them
+            word.attributes.export = true;
+            ir.add_word(word);
+        }
+        let optimized = opt.optimize(ir.clone()).unwrap();
+        assert_eq!(optimized.words.len(), PARALLEL_OPTIMIZE_THRESHOLD);
+
+        let trace = opt.take_trace().unwrap();
+        assert_eq!(trace.input(), &ir);
+        assert_eq!(trace.output().main, optimized.main);
+        for (name, word) in &optimized.words {
+            assert_eq!(trace.output().words[name].instructions, word.instructions);
+        }
+        let passes: Vec<_> = trace.snapshots().iter().map(|snapshot| snapshot.pass).collect();
+        assert_eq!(&passes[..3], ["whole_program", "constant_fold", "peephole"]);
+        assert!(passes.ends_with(&["dead_code", "memory_opt", "stack_cache"]));
+        assert!(trace.changed_passes().any(|pass| pass == "constant_fold"));
+        assert!(opt.trace().is_none());
+    }
+
+    #[test]
+    fn test_timings_cover_every_pass_and_word() {
+        let mut opt = Optimizer::new(OptimizationLevel::Standard);
+        opt.set_timing(true);
+
+        let mut ir = ForthIR::parse("2 3 + dup").unwrap();
+        let mut sq = WordDef::new("sq".to_string(), ForthIR::parse("dup *").unwrap().main);
+        sq.attributes.export = true;
+        ir.add_word(sq);
+        opt.optimize(ir).unwrap();
+
+        let timings = opt.take_timings();
+        assert_eq!(timings[0].pass, "whole_program");
+        assert!(timings[0].word.is_none());
+        for word in ["sq", report::MAIN] {
+            assert!(timings.iter().any(|timing| timing.pass == "stack_cache" && timing.word.as_deref() == Some(word)));
+        }
+        assert!(opt.take_timings().is_empty());
+    }
+
+    #[test]
+    fn test_word_attributes_are_respected() {
+        let mut opt = Optimizer::new(OptimizationLevel::Aggressive);
+        let mut ir = ForthIR::parse("5 raw 5 kept").unwrap();
+
+        let mut raw = WordDef::new("raw".to_string(), ForthIR::parse("2 3 + +").unwrap().main);
+        raw.attributes.optimize_none = true;
+        let mut kept = WordDef::new("kept".to_string(), ForthIR::parse("1 +").unwrap().main);
+        kept.attributes.inline = InlineDirective::NeverInline;
+        ir.add_word(raw.clone());
+        ir.add_word(kept);
+
+        let optimized = opt.optimize(ir).unwrap();
+        let calls: Vec<_> = optimized.main.iter().filter_map(|inst| match inst {
+            Instruction::Call(name) => Some(name.as_str()),
+            _ => None,
+        }).collect();
+        assert_eq!(calls, ["raw", "kept"]);
+        assert_eq!(optimized.words["raw"].instructions, raw.instructions);
+    }
+
+    #[test]
+    fn test_custom_pipeline() {
+        let mut opt = Optimizer::new(OptimizationLevel::Standard);
+        opt.set_pipeline(PassPipeline::parse("constant-fold,dce").unwrap()).unwrap();
+        opt.set_trace(true);
+        let optimized = opt.optimize(ForthIR::parse("2 3 + 7 drop").unwrap()).unwrap();
+
+        assert_eq!(optimized.main, vec![Instruction::Literal(5)]);
+        let passes: Vec<_> = opt.trace().unwrap().snapshots().iter().map(|snapshot| snapshot.pass).collect();
+        assert_eq!(passes, ["constant_fold", "dead_code"]);
+
+        assert!(opt.set_pipeline(PassPipeline::parse("escape,inline").unwrap()).is_err());
+        opt.set_pipeline(PassPipeline::preset(OptimizationLevel::Standard)).unwrap();
+        opt.disable_pass(Pass::StackCache);
+        opt.optimize(ForthIR::parse("1 2 +").unwrap()).unwrap();
+        let passes: Vec<_> = opt.trace().unwrap().snapshots().iter().map(|snapshot| snapshot.pass).collect();
+        assert_eq!(passes.last(), Some(&"memory_opt"));
+    }
+
     #[test]
     fn test_optimization_levels() {
         assert!(OptimizationLevel::None < OptimizationLevel::Basic);
@@ -0,0 +1,176 @@
+//! Lowering from the AST to IR
+//!
+//! Turns a parsed [`Program`] straight into [`ForthIR`], keeping stack
+//! manipulation words, so the result reads like the original definitions.
+//! Control flow becomes numbered labels and branches, and counted loops the
+//! `(do)`, `(loop)` and `(+loop)` calls the [interpreter](crate::interpreter)
+//! runs. Locals get a slot each, numbered from 0 in the order the
+//! definition declares them. Lowering needs nothing beyond the frontend, so the playground build
+//! uses it without the code generator.
+
+use crate::ir::{ForthIR, Instruction, WordAttributes, WordDef};
+use fastforth_frontend::ast::Attributes;
+use fastforth_frontend::case::CaseChain;
+use fastforth_frontend::{Program, Symbol, Word};
+
+/// Lower `program`, giving each definition the optimizer attributes
+/// `attributes` makes of its source attributes
+pub fn lower_program(program: &Program, attributes: impl Fn(&Attributes) -> WordAttributes) -> ForthIR {
+    let mut ir = ForthIR::new();
+    let mut next_label = 0;
+
+    for word in &program.top_level_code {
+        if let Word::Variable { name, size, .. } = word {
+            ir.add_buffer(name.clone(), *size);
+        }
+    }
+
+    for def in program.compiled_definitions() {
+        let mut instructions = Vec::new();
+        lower_words(&def.body, &mut instructions, &mut next_label, &mut Vec::new());
+        instructions.push(Instruction::Return);
+        let mut word = WordDef::new(def.name.clone(), instructions);
+        word.attributes = attributes(&def.attributes);
+        ir.add_word(word);
+    }
+    lower_words(&program.top_level_code, &mut ir.main, &mut next_label, &mut Vec::new());
+
+    ir
+}
+
+fn fresh_label(next_label: &mut usize) -> usize {
+    let label = *next_label;
+    *next_label += 1;
+    label
+}
+
+/// The slot of the local `name`, the latest declared if several are
+fn local_slot(locals: &[Symbol], name: Symbol) -> u8 {
+    locals.iter().rposition(|local| *local == name).unwrap_or_default() as u8
+}
+
+/// Lower a word sequence, using numbered labels for control flow and
+/// `locals` for the slots of the locals declared so far
+///
+/// A CASE that only picks constants becomes a single lookup.
+fn lower_words(words: &[Word], out: &mut Vec<Instruction>, next_label: &mut usize, locals: &mut Vec<Symbol>) {
+    let mut rest = words;
+    while let Some(word) = rest.first() {
+        if let Some(table) = CaseChain::find(rest).and_then(|chain| chain.table()) {
+            out.push(Instruction::Lookup(table));
+            rest = &rest[CaseChain::LEN..];
+            continue;
+        }
+        rest = &rest[1..];
+        match word {
+            Word::IntLiteral(value) => out.push(Instruction::Literal(*value)),
+            Word::FloatLiteral(value) => out.push(Instruction::FloatLiteral(*value)),
+            Word::StringLiteral(value) => out.push(Instruction::Comment(format!("{:?}", value))),
+            Word::WordRef { name, .. } => {
+                let lower = name.to_lowercase();
+                match Instruction::expand_word(&lower) {
+                    _ if lower == "exit" => out.push(Instruction::Return),
+                    Some(expansion) => out.extend(expansion),
+                    None => out.push(Instruction::from_word(&lower).unwrap_or(Instruction::Call(*name))),
+                }
+            }
+            Word::TaskSpawn { .. } => out.push(Instruction::Call("task:".into())),
+            // One followed by EXECUTE is already a call to its word
+            Word::Quotation { name, .. } => out.push(Instruction::Tick(*name)),
+            Word::Locals { args, uninitialized, .. } => {
+                locals.extend(args.iter().chain(uninitialized));
+                // The top of the stack goes to the last argument
+                for name in args.iter().rev() {
+                    out.push(Instruction::LocalStore(local_slot(locals, *name)));
+                }
+                for name in uninitialized {
+                    out.push(Instruction::Literal(0));
+                    out.push(Instruction::LocalStore(local_slot(locals, *name)));
+                }
+            }
+            Word::LocalRef { name, .. } => out.push(Instruction::LocalFetch(local_slot(locals, *name))),
+            Word::LocalStore { name, .. } => out.push(Instruction::LocalStore(local_slot(locals, *name))),
+            Word::If { then_branch, else_branch } => {
+                let else_label = fresh_label(next_label);
+                let end_label = fresh_label(next_label);
+                out.push(Instruction::BranchIfNot(else_label));
+                lower_words(then_branch, out, next_label, locals);
+                out.push(Instruction::Branch(end_label));
+                out.push(Instruction::Label(format!("L{}", else_label)));
+                if let Some(else_branch) = else_branch {
+                    lower_words(else_branch, out, next_label, locals);
+                }
+                out.push(Instruction::Label(format!("L{}", end_label)));
+            }
+            Word::BeginUntil { body } => {
+                let top = fresh_label(next_label);
+                out.push(Instruction::Label(format!("L{}", top)));
+                lower_words(body, out, next_label, locals);
+                out.push(Instruction::BranchIfNot(top));
+            }
+            Word::BeginWhileRepeat { condition, body } => {
+                let top = fresh_label(next_label);
+                let end = fresh_label(next_label);
+                out.push(Instruction::Label(format!("L{}", top)));
+                lower_words(condition, out, next_label, locals);
+                out.push(Instruction::BranchIfNot(end));
+                lower_words(body, out, next_label, locals);
+                out.push(Instruction::Branch(top));
+                out.push(Instruction::Label(format!("L{}", end)));
+            }
+            Word::DoLoop { body, increment } => {
+                let top = fresh_label(next_label);
+                out.push(Instruction::Call("(do)".into()));
+                out.push(Instruction::Label(format!("L{}", top)));
+                lower_words(body, out, next_label, locals);
+                let step = if *increment == 1 { "(loop)" } else { "(+loop)" };
+                out.push(Instruction::Call(step.into()));
+                out.push(Instruction::BranchIfNot(top));
+            }
+            Word::Variable { name, .. } => out.push(Instruction::Comment(format!("variable {}", name))),
+            Word::Constant { name, value } => {
+                out.push(Instruction::Comment(format!("constant {}", name)));
+                out.push(Instruction::Literal(*value));
+            }
+            Word::Comment(text) => out.push(Instruction::Comment(text.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::IrInterpreter;
+    use fastforth_frontend::parse_program;
+
+    #[test]
+    fn test_lowered_program_runs() {
+        let program = parse_program(
+            ": sq ( n -- n*n ) dup * ;\n: sign ( n -- s ) 0< if -1 else 1 then ;\n0 4 0 do i sq + loop dup . -3 sign",
+        )
+        .unwrap();
+        let ir = lower_program(&program, |_| WordAttributes::default());
+        assert_eq!(ir.words["sq"].instructions.last(), Some(&Instruction::Return));
+
+        let mut output = String::new();
+        assert_eq!(IrInterpreter::new(&ir).with_console(&mut output).run(), Ok(vec![14, -1]));
+        assert_eq!(output, "14 ");
+    }
+
+    #[test]
+    fn test_locals_lower_to_slots() {
+        let program = parse_program(
+            ": diff ( a b -- n ) {: a b | r :} b a - to r r ;
+: twice ( n -- n ) {: n :} n diff-with n + ;
+: diff-with ( n -- n ) 10 swap diff ;
+3 7 diff 4 twice",
+        )
+        .unwrap();
+        let ir = lower_program(&program, |_| WordAttributes::default());
+        assert_eq!(
+            &ir.words["diff"].instructions[..3],
+            &[Instruction::LocalStore(1), Instruction::LocalStore(0), Instruction::Literal(0)]
+        );
+        assert_eq!(IrInterpreter::new(&ir).run(), Ok(vec![4, -2]));
+    }
+}
@@ -14,7 +14,7 @@
 //! - 1-3% from cache line alignment
 //! - 1-2% from stack discipline optimization
 
-use crate::ir::{ForthIR, Instruction, WordDef};
+use crate::ir::{ForthIR, Instruction, Symbol, WordDef};
 use crate::analysis::StackDepthAnalysis;
 use crate::{Result, OptimizerError};
 use std::collections::{HashMap, HashSet, VecDeque};
@@ -29,6 +29,8 @@ pub enum MemoryAccessType {
     ReturnStack,
     /// Heap access with potential aliasing
     Heap,
+    /// Synchronization with other threads, which may access any memory
+    Shared,
     /// Thread-local storage (no aliasing across threads)
     ThreadLocal,
     /// Unknown or conservative classification
@@ -153,6 +155,11 @@ impl PointsToSet {
         }
     }
 
+    /// Whether nothing is known about the location
+    fn is_empty(&self) -> bool {
+        self.stack_locs.is_empty() && self.heap_locs.is_empty() && self.rstack_locs.is_empty()
+    }
+
     /// Check if two points-to sets may alias
     fn may_alias(&self, other: &PointsToSet) -> bool {
         // Two locations alias if their points-to sets intersect
@@ -162,6 +169,104 @@ impl PointsToSet {
     }
 }
 
+/// Whether `name` is a heap word (ANS Memory-Allocation word set)
+///
+/// Heap calls create, move and release blocks, so no memory access may be
+/// reordered across one: a load hoisted above `resize` could read a block
+/// that has since moved, and a store sunk below `free` writes freed memory.
+pub fn is_heap_word(name: &str) -> bool {
+    matches!(name, "allocate" | "free" | "resize")
+}
+
+fn is_heap_call(inst: &Instruction) -> bool {
+    matches!(inst, Instruction::Call(name) if is_heap_word(name.as_str()))
+}
+
+/// Whether `name` synchronizes with other threads: the atomic accesses,
+/// and the task and channel words
+///
+/// Memory another thread may read or write is only ordered by these, so
+/// like heap calls no memory access is reordered across one.
+pub fn is_sync_word(name: &str) -> bool {
+    matches!(
+        name,
+        "@atomic" | "!atomic" | "task:" | "join" | "pause" | "channel" | "send" | "recv" | "close-channel"
+            | "destroy-channel"
+    )
+}
+
+fn is_sync_call(inst: &Instruction) -> bool {
+    matches!(inst, Instruction::Call(name) if is_sync_word(name.as_str()))
+}
+
+/// Whether no memory access may be reordered across `inst`
+fn is_barrier(inst: &Instruction) -> bool {
+    is_heap_call(inst) || is_sync_call(inst)
+}
+
+/// Whether a load may be hoisted across `inst`: straight-line code whose
+/// stack effect is known
+fn is_hoistable_across(inst: &Instruction) -> bool {
+    !matches!(
+        inst,
+        Instruction::Call(_)
+            | Instruction::Execute
+            | Instruction::Label(_)
+            | Instruction::Branch(_)
+            | Instruction::BranchIf(_)
+            | Instruction::BranchIfNot(_)
+            | Instruction::Return
+            | Instruction::ToR
+            | Instruction::FromR
+            | Instruction::RFetch
+    )
+}
+
+/// Start of the code computing the address the load at `load` takes, if
+/// that code is straight-line, reads no memory and takes nothing from the
+/// stack below it
+fn address_start(code: &[Instruction], load: usize) -> Option<usize> {
+    let mut needed = 1i32;
+    for j in (0..load).rev() {
+        let inst = &code[j];
+        if !is_hoistable_across(inst) || inst.access_width().is_some() {
+            return None;
+        }
+        let effect = inst.stack_effect();
+        if effect.produced as i32 > needed {
+            return None;
+        }
+        needed += effect.consumed as i32 - effect.produced as i32;
+        if needed == 0 {
+            return Some(j);
+        }
+    }
+    None
+}
+
+/// Earliest position at most `window` instructions back from the end of
+/// `code` such that the instructions from there on leave the stack as they
+/// found it, never reach below it, and hold neither a barrier nor a store
+/// in `deps` (indices in `origin`)
+fn hoist_target(code: &[Instruction], origin: &[usize], deps: &[usize], window: usize) -> usize {
+    let mut target = code.len();
+    // Inputs the instructions from `j` on need, and what they leave
+    let (mut needed, mut net) = (0i32, 0i32);
+    for j in (code.len().saturating_sub(window)..code.len()).rev() {
+        let inst = &code[j];
+        if !is_hoistable_across(inst) || is_barrier(inst) || deps.contains(&origin[j]) {
+            break;
+        }
+        let effect = inst.stack_effect();
+        needed = effect.consumed as i32 + (needed - effect.produced as i32).max(0);
+        net += effect.produced as i32 - effect.consumed as i32;
+        if needed == 0 && net == 0 {
+            target = j;
+        }
+    }
+    target
+}
+
 /// Production-grade memory optimizer with formal analysis
 pub struct MemoryOptimizer {
     /// Enable aliasing analysis
@@ -239,15 +344,21 @@ impl MemoryOptimizer {
 
         // Optimize each word
         for (name, word) in ir.words.iter() {
-            if let Some(optimized_word) = optimized.get_word_mut(name) {
-                optimized_word.instructions = self.optimize_sequence(&word.instructions)?;
-                optimized_word.update();
-            }
+            let optimized_word = self.optimize_word(word)?;
+            optimized.words.insert(name.clone(), optimized_word);
         }
 
         Ok(optimized)
     }
 
+    /// Optimize a word definition
+    pub(crate) fn optimize_word(&self, word: &WordDef) -> Result<WordDef> {
+        let mut optimized = word.clone();
+        optimized.instructions = self.optimize_sequence(&word.instructions)?;
+        optimized.update();
+        Ok(optimized)
+    }
+
     /// Optimize a sequence of instructions
     fn optimize_sequence(&self, instructions: &[Instruction]) -> Result<Vec<Instruction>> {
         let mut optimized = instructions.to_vec();
@@ -297,8 +408,8 @@ impl MemoryOptimizer {
                 Instruction::Rot => {} // No depth change
                 Instruction::ToR => stack_depth -= 1,
                 Instruction::FromR => stack_depth += 1,
-                Instruction::Load | Instruction::Load8 => stack_depth -= 1, // Address on stack
-                Instruction::Store | Instruction::Store8 => stack_depth -= 2, // Addr + value
+                inst if inst.is_load() => stack_depth -= 1, // Address on stack
+                inst if inst.is_store() => stack_depth -= 2, // Addr + value
                 _ => {}
             }
             min_stack_depth = min_stack_depth.min(stack_depth);
@@ -324,16 +435,16 @@ impl MemoryOptimizer {
             let mut pts = PointsToSet::new();
 
             match inst {
-                Instruction::Load | Instruction::Load8 => {
+                inst if inst.access_width().is_some() => {
                     // Look back for address computation
                     self.analyze_address_sources(instructions, i, &mut pts);
                 }
-                Instruction::Store | Instruction::Store8 => {
-                    self.analyze_address_sources(instructions, i, &mut pts);
-                }
                 Instruction::ToR | Instruction::FromR | Instruction::RFetch => {
                     pts.rstack_locs.insert("rstack".to_string());
                 }
+                _ if is_barrier(inst) => {
+                    pts.heap_locs.insert("heap".to_string());
+                }
                 _ => continue,
             }
 
@@ -342,20 +453,30 @@ impl MemoryOptimizer {
 
         // Phase 2: Build MemoryOp structures
         for (i, inst) in instructions.iter().enumerate() {
-            if !matches!(inst, Instruction::Load | Instruction::Load8
-                       | Instruction::Store | Instruction::Store8
-                       | Instruction::ToR | Instruction::FromR | Instruction::RFetch) {
+            if !Self::is_memory_op(inst) {
                 continue;
             }
 
             let access_type = self.classify_memory_access_formal(instructions, i);
             let mut mem_op = MemoryOp::new(i, inst.clone(), access_type);
+            if is_barrier(inst) {
+                mem_op.barrier_before = true;
+                mem_op.barrier_after = true;
+            }
 
             // Compute aliases with other memory operations
             if let Some(pts_i) = points_to.get(&i) {
                 for mem_op_j in mem_ops.iter() {
                     if let Some(pts_j) = points_to.get(&mem_op_j.index) {
-                        let alias_result = if pts_i.may_alias(pts_j) {
+                        // Points-to sets do not see offsets, so a narrow
+                        // access may fall inside a wider one, and an access
+                        // to an unknown address may touch anything
+                        let widths = (inst.access_width(), mem_op_j.instruction.access_width());
+                        let overlapping = match widths {
+                            (Some(a), Some(b)) => a != b || pts_i.is_empty() || pts_j.is_empty(),
+                            _ => false,
+                        };
+                        let alias_result = if overlapping || pts_i.may_alias(pts_j) {
                             AliasResult::MayAlias
                         } else {
                             AliasResult::NoAlias
@@ -374,6 +495,14 @@ impl MemoryOptimizer {
         Ok(mem_ops)
     }
 
+    /// Whether an instruction takes part in alias analysis: memory and
+    /// return stack accesses, heap calls and synchronization
+    fn is_memory_op(inst: &Instruction) -> bool {
+        inst.access_width().is_some()
+            || matches!(inst, Instruction::ToR | Instruction::FromR | Instruction::RFetch)
+            || is_barrier(inst)
+    }
+
     /// Analyze address sources for a memory operation
     fn analyze_address_sources(
         &self,
@@ -387,11 +516,12 @@ impl MemoryOptimizer {
                 Instruction::Dup | Instruction::Over => {
                     pts.stack_locs.insert(format!("stack_{}", i));
                 }
-                Instruction::Call(name) if name.contains("alloc") || name.contains("malloc") => {
-                    pts.heap_locs.insert(name.clone());
+                // Every heap block is reached through the same allocator
+                Instruction::Call(name) if is_heap_word(name.as_str()) || name.contains("alloc") => {
+                    pts.heap_locs.insert("heap".to_string());
                 }
                 Instruction::Call(name) if name.contains("rstack") => {
-                    pts.rstack_locs.insert(name.clone());
+                    pts.rstack_locs.insert(name.to_string());
                 }
                 _ => {}
             }
@@ -409,6 +539,8 @@ impl MemoryOptimizer {
             Instruction::ToR | Instruction::FromR | Instruction::RFetch => {
                 return MemoryAccessType::ReturnStack;
             }
+            inst if is_heap_call(inst) => return MemoryAccessType::Heap,
+            inst if is_sync_call(inst) => return MemoryAccessType::Shared,
             _ => {}
         }
 
@@ -420,7 +552,9 @@ impl MemoryOptimizer {
                     return MemoryAccessType::Stack;
                 }
                 Instruction::ToR | Instruction::FromR => return MemoryAccessType::ReturnStack,
-                Instruction::Call(name) if name.contains("alloc") => return MemoryAccessType::Heap,
+                Instruction::Call(name) if is_heap_word(name.as_str()) || name.contains("alloc") => {
+                    return MemoryAccessType::Heap;
+                }
                 _ => {}
             }
         }
@@ -435,12 +569,18 @@ impl MemoryOptimizer {
         mem_op: &mut MemoryOp,
         prev_ops: &[MemoryOp],
     ) {
-        let is_load = matches!(mem_op.instruction, Instruction::Load | Instruction::Load8);
-        let is_store = matches!(mem_op.instruction, Instruction::Store | Instruction::Store8);
+        let is_load = mem_op.instruction.is_load();
+        let is_store = mem_op.instruction.is_store();
 
         for prev_op in prev_ops {
-            let prev_is_load = matches!(prev_op.instruction, Instruction::Load | Instruction::Load8);
-            let prev_is_store = matches!(prev_op.instruction, Instruction::Store | Instruction::Store8);
+            let prev_is_load = prev_op.instruction.is_load();
+            let prev_is_store = prev_op.instruction.is_store();
+
+            // Nothing moves across a heap or synchronizing call, in either direction
+            if prev_op.barrier_after || mem_op.barrier_before {
+                mem_op.add_true_dep(prev_op.index);
+                continue;
+            }
 
             // True dependency: load after store to same address
             if is_load && prev_is_store {
@@ -465,14 +605,16 @@ impl MemoryOptimizer {
         let mut mem_ops = Vec::new();
 
         for (i, inst) in instructions.iter().enumerate() {
-            if !matches!(inst, Instruction::Load | Instruction::Load8
-                       | Instruction::Store | Instruction::Store8
-                       | Instruction::ToR | Instruction::FromR | Instruction::RFetch) {
+            if !Self::is_memory_op(inst) {
                 continue;
             }
 
             let access_type = MemoryAccessType::Unknown;
-            let mem_op = MemoryOp::new(i, inst.clone(), access_type);
+            let mut mem_op = MemoryOp::new(i, inst.clone(), access_type);
+            if is_barrier(inst) {
+                mem_op.barrier_before = true;
+                mem_op.barrier_after = true;
+            }
             mem_ops.push(mem_op);
         }
 
@@ -486,8 +628,9 @@ impl MemoryOptimizer {
         memory_ops: &[MemoryOp],
     ) -> Result<Vec<Instruction>> {
         let mut reordered = instructions.to_vec();
-        let _mem_op_indices: HashSet<usize> =
-            memory_ops.iter().map(|op| op.index).collect();
+        // Original index of each instruction, moved along with it
+        let mut origin: Vec<usize> = (0..instructions.len()).collect();
+        let ops: HashMap<usize, &MemoryOp> = memory_ops.iter().map(|op| (op.index, op)).collect();
 
         // Build a dependency graph
         let mut can_move_before: HashMap<usize, Vec<usize>> = HashMap::new();
@@ -506,18 +649,22 @@ impl MemoryOptimizer {
             can_move_before.insert(op.index, moveable_before);
         }
 
-        // Reorder loads to reduce pipeline stalls
+        // Reorder loads to reduce pipeline stalls. A load moves together
+        // with the code computing its address, and only above code that
+        // leaves the stack as it found it and holds no store it depends on
+        let window = (self.max_reorder_window / 2).min(5);
         for i in 0..reordered.len() {
-            if let Some(moveable) = can_move_before.get(&i) {
-                if moveable.is_empty() {
-                    continue;
-                }
-                // Move loads forward
-                if matches!(reordered[i], Instruction::Load | Instruction::Load8) {
-                    let load = reordered.remove(i);
-                    let target = i.saturating_sub((self.max_reorder_window / 2).min(5));
-                    reordered.insert(target, load);
-                }
+            if !reordered[i].is_load() || can_move_before.get(&origin[i]).is_none_or(Vec::is_empty) {
+                continue;
+            }
+            let Some(start) = address_start(&reordered, i) else {
+                continue;
+            };
+            let deps = &ops[&origin[i]].true_deps;
+            let target = hoist_target(&reordered[..start], &origin[..start], deps, window);
+            if target < start {
+                reordered[target..=i].rotate_right(i + 1 - start);
+                origin[target..=i].rotate_right(i + 1 - start);
             }
         }
 
@@ -539,20 +686,16 @@ impl MemoryOptimizer {
                 }
 
                 match loop_info.pattern {
-                    AccessPattern::Sequential { stride } if stride > 0 => {
-                        if matches!(inst, Instruction::Load | Instruction::Load8) {
-                            // Emit prefetch hint
-                            prefetched.push(Instruction::Comment(
-                                format!("PREFETCH_HINT:{}", self.prefetch_distance)
-                            ));
-                        }
+                    AccessPattern::Sequential { stride } if stride > 0 && inst.is_load() => {
+                        // Emit prefetch hint
+                        prefetched.push(Instruction::Comment(
+                            format!("PREFETCH_HINT:{}", self.prefetch_distance)
+                        ));
                     }
-                    AccessPattern::Strided { stride } if stride > 0 => {
-                        if matches!(inst, Instruction::Load | Instruction::Load8) {
-                            prefetched.push(Instruction::Comment(
-                                format!("PREFETCH_STRIDE:{}", stride)
-                            ));
-                        }
+                    AccessPattern::Strided { stride } if stride > 0 && inst.is_load() => {
+                        prefetched.push(Instruction::Comment(
+                            format!("PREFETCH_STRIDE:{}", stride)
+                        ));
                     }
                     _ => {}
                 }
@@ -602,8 +745,8 @@ impl MemoryOptimizer {
 
         for inst in &instructions[start..=end.min(instructions.len() - 1)] {
             match inst {
-                Instruction::Load | Instruction::Load8 => load_count += 1,
-                Instruction::Store | Instruction::Store8 => store_count += 1,
+                inst if inst.is_load() => load_count += 1,
+                inst if inst.is_store() => store_count += 1,
                 Instruction::Add => add_count += 1,
                 Instruction::Sub => sub_count += 1,
                 _ => {}
@@ -646,7 +789,7 @@ impl MemoryOptimizer {
         // Analyze cache line utilization
         let mut accessed_locations: HashMap<usize, usize> = HashMap::new();
         for (i, inst) in instructions.iter().enumerate() {
-            if matches!(inst, Instruction::Load | Instruction::Load8 | Instruction::Store | Instruction::Store8) {
+            if inst.access_width().is_some() {
                 let cache_line = i / (self.cache_line_size / 8);
                 *accessed_locations.entry(cache_line).or_insert(0) += 1;
             }
@@ -669,18 +812,18 @@ impl MemoryOptimizer {
 
     /// Identify frequently accessed data
     fn identify_hot_data(&self, instructions: &[Instruction]) -> Vec<String> {
-        let mut access_counts: HashMap<String, usize> = HashMap::new();
+        let mut access_counts: HashMap<Symbol, usize> = HashMap::new();
 
         for inst in instructions {
             if let Instruction::Call(name) = inst {
-                *access_counts.entry(name.clone()).or_insert(0) += 1;
+                *access_counts.entry(*name).or_insert(0) += 1;
             }
         }
 
         access_counts
             .into_iter()
             .filter(|(_, count)| *count >= 3) // Lower threshold for tests
-            .map(|(name, _)| name)
+            .map(|(name, _)| name.to_string())
             .collect()
     }
 
@@ -688,10 +831,10 @@ impl MemoryOptimizer {
     pub fn compute_stats(&self, original: &[Instruction], optimized: &[Instruction]) -> OptimizationStats {
         let mut stats = OptimizationStats::default();
 
-        stats.original_loads = original.iter().filter(|i| matches!(i, Instruction::Load | Instruction::Load8)).count();
-        stats.optimized_loads = optimized.iter().filter(|i| matches!(i, Instruction::Load | Instruction::Load8)).count();
-        stats.original_stores = original.iter().filter(|i| matches!(i, Instruction::Store | Instruction::Store8)).count();
-        stats.optimized_stores = optimized.iter().filter(|i| matches!(i, Instruction::Store | Instruction::Store8)).count();
+        stats.original_loads = original.iter().filter(|i| i.is_load()).count();
+        stats.optimized_loads = optimized.iter().filter(|i| i.is_load()).count();
+        stats.original_stores = original.iter().filter(|i| i.is_store()).count();
+        stats.optimized_stores = optimized.iter().filter(|i| i.is_store()).count();
 
         // Count prefetches and cache hints
         stats.prefetches_inserted = optimized.iter().filter(|i| {
@@ -887,7 +1030,7 @@ mod tests {
     fn test_cache_optimization_usage() {
         let opt = MemoryOptimizer::new();
         let instructions = vec![
-            Instruction::Call("data_array".to_string()),
+            Instruction::Call("data_array".into()),
             Instruction::Load,
         ];
 
@@ -953,6 +1096,104 @@ mod tests {
         assert!(optimized.main.len() >= ir.main.len());
     }
 
+    #[test]
+    fn test_loads_stay_behind_heap_calls() {
+        let opt = MemoryOptimizer::new();
+        let instructions = vec![
+            Instruction::Literal(8),
+            Instruction::Call("allocate".into()),
+            Instruction::Drop,
+            Instruction::Dup,
+            Instruction::Literal(16),
+            Instruction::Call("resize".into()),
+            Instruction::Drop,
+            Instruction::Load,
+        ];
+
+        let mem_ops = opt.build_memory_ops(&instructions).unwrap();
+        assert_eq!(mem_ops.len(), 3);
+        assert!(mem_ops[..2].iter().all(|op| op.access_type == MemoryAccessType::Heap && op.barrier_after));
+        assert!(mem_ops[2].true_deps.contains(&5));
+
+        let reordered = opt.reorder_memory_ops_formal(&instructions, &mem_ops).unwrap();
+        let resize = reordered.iter().position(|inst| *inst == Instruction::Call("resize".into())).unwrap();
+        let load = reordered.iter().position(|inst| *inst == Instruction::Load).unwrap();
+        assert!(load > resize, "load hoisted above resize: {:?}", reordered);
+    }
+
+    #[test]
+    fn test_narrow_loads_stay_behind_wider_stores() {
+        let opt = MemoryOptimizer::new();
+        // -1 4096 !  4097 c@
+        let instructions = vec![
+            Instruction::Literal(-1),
+            Instruction::Literal(4096),
+            Instruction::Store,
+            Instruction::Literal(4097),
+            Instruction::Load8,
+        ];
+
+        let mem_ops = opt.build_memory_ops(&instructions).unwrap();
+        assert_eq!(mem_ops[1].aliases.get(&2), Some(&AliasResult::MayAlias));
+        assert!(mem_ops[1].true_deps.contains(&2));
+
+        let reordered = opt.reorder_memory_ops_formal(&instructions, &mem_ops).unwrap();
+        assert_eq!(reordered, instructions);
+    }
+
+    #[test]
+    fn test_loads_move_with_their_address() {
+        let opt = MemoryOptimizer::new();
+        // 8 @ drop  16 @
+        let instructions = vec![
+            Instruction::Literal(8),
+            Instruction::Load,
+            Instruction::Drop,
+            Instruction::Literal(16),
+            Instruction::Load,
+        ];
+
+        let mem_ops = opt.build_memory_ops(&instructions).unwrap();
+        let reordered = opt.reorder_memory_ops_formal(&instructions, &mem_ops).unwrap();
+        assert_eq!(
+            reordered,
+            vec![
+                Instruction::Literal(16),
+                Instruction::Load,
+                Instruction::Literal(8),
+                Instruction::Load,
+                Instruction::Drop,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_accesses_stay_between_sync_calls() {
+        let opt = MemoryOptimizer::new();
+        // A flag set with !atomic must not be read before the task is joined
+        let instructions = vec![
+            Instruction::Call("task:".into()),
+            Instruction::Call("join".into()),
+            Instruction::Drop,
+            Instruction::Literal(4096),
+            Instruction::Load,
+            Instruction::Literal(4104),
+            Instruction::Call("@atomic".into()),
+        ];
+
+        let mem_ops = opt.build_memory_ops(&instructions).unwrap();
+        assert_eq!(mem_ops.len(), 4);
+        assert!(mem_ops.iter().filter(|op| op.index != 4)
+            .all(|op| op.access_type == MemoryAccessType::Shared && op.barrier_before && op.barrier_after));
+        assert!(mem_ops[2].true_deps.contains(&1));
+        assert!(mem_ops[3].true_deps.contains(&4));
+
+        let reordered = opt.reorder_memory_ops_formal(&instructions, &mem_ops).unwrap();
+        let join = reordered.iter().position(|inst| *inst == Instruction::Call("join".into())).unwrap();
+        let load = reordered.iter().position(|inst| *inst == Instruction::Load).unwrap();
+        assert!(load > join, "load hoisted above join: {:?}", reordered);
+    }
+
     #[test]
     fn test_return_stack_classification() {
         let opt = MemoryOptimizer::new();
@@ -987,11 +1228,11 @@ mod tests {
     fn test_cache_line_optimization() {
         let opt = MemoryOptimizer::new();
         let instructions = vec![
-            Instruction::Call("data_array".to_string()),
+            Instruction::Call("data_array".into()),
             Instruction::Load,
-            Instruction::Call("data_array".to_string()),
+            Instruction::Call("data_array".into()),
             Instruction::Load,
-            Instruction::Call("data_array".to_string()),
+            Instruction::Call("data_array".into()),
             Instruction::Load,
         ];
 
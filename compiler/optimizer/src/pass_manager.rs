@@ -0,0 +1,482 @@
+//! Pass Manager
+//!
+//! Every optimization pass is registered in [`PASSES`] with its name, the
+//! scope it works on and the passes it depends on. A [`PassPipeline`] is
+//! an ordered list of passes, either one of the presets behind the
+//! optimization levels or a user-supplied list:
+//!
+//! ```text
+//! --passes=constant-fold,inline,dce
+//! --pipeline=pipeline.toml
+//! --disable-pass=stack-cache
+//! ```
+//!
+//! A pipeline file starts from a preset (the optimization level's, unless
+//! it names one) or lists its passes, and can disable some of them:
+//!
+//! ```toml
+//! preset = "aggressive"
+//! disable = ["stack-cache", "vectorize"]
+//! ```
+//!
+//! [`PassManager`] checks a pipeline against the dependencies and splits
+//! it into stages: whole-program passes run one at a time, and each run
+//! of consecutive word-local passes runs word by word.
+//!
+//! Pass names are the ones optimization reports and traces use; hyphens
+//! and underscores are interchangeable.
+
+use crate::{OptimizationLevel, OptimizerError, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// An optimization pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pass {
+    ZeroCost,
+    TypeSpecialization,
+    WholeProgram,
+    ConstantFold,
+    Peephole,
+    Inline,
+    Escape,
+    Induction,
+    Vectorize,
+    Superinstructions,
+    DeadCode,
+    MemoryOpt,
+    StackCache,
+}
+
+/// What a pass looks at when it rewrites code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassScope {
+    /// The whole program, across word boundaries
+    Program,
+    /// One word at a time, so words can be optimized in parallel
+    Word,
+}
+
+/// A registered pass
+#[derive(Debug, Clone, Copy)]
+pub struct PassInfo {
+    pub pass: Pass,
+    pub name: &'static str,
+    /// Other names the pass is known by
+    pub aliases: &'static [&'static str],
+    pub scope: PassScope,
+    /// Passes that may not run after this one, because it relies on
+    /// their output or they cannot handle its output
+    pub after: &'static [Pass],
+    pub description: &'static str,
+}
+
+/// Every pass, in the order of the aggressive preset
+pub const PASSES: [PassInfo; 13] = [
+    PassInfo {
+        pass: Pass::ZeroCost,
+        name: "zero_cost",
+        aliases: &[],
+        scope: PassScope::Program,
+        after: &[],
+        description: "inline tiny words, evaluate constants, unroll constant loops",
+    },
+    PassInfo {
+        pass: Pass::TypeSpecialization,
+        name: "type_specialization",
+        aliases: &["specialize"],
+        scope: PassScope::Program,
+        after: &[],
+        description: "specialize operations for inferred types (needs type information)",
+    },
+    PassInfo {
+        pass: Pass::WholeProgram,
+        name: "whole_program",
+        aliases: &["wpo"],
+        scope: PassScope::Program,
+        after: &[],
+        description: "drop unreachable words, inline single-call words, propagate constants across words",
+    },
+    PassInfo {
+        pass: Pass::ConstantFold,
+        name: "constant_fold",
+        aliases: &["fold"],
+        scope: PassScope::Program,
+        after: &[],
+        description: "evaluate operations on constants at compile time",
+    },
+    PassInfo {
+        pass: Pass::Peephole,
+        name: "peephole",
+        aliases: &[],
+        scope: PassScope::Program,
+        after: &[],
+        description: "strength reduction and local rewrites",
+    },
+    PassInfo {
+        pass: Pass::Inline,
+        name: "inline",
+        aliases: &[],
+        scope: PassScope::Program,
+        after: &[],
+        description: "expand small definitions into their callers",
+    },
+    PassInfo {
+        pass: Pass::Escape,
+        name: "escape",
+        aliases: &[],
+        scope: PassScope::Program,
+        // Inlining would copy promoted locals into other words
+        after: &[Pass::WholeProgram, Pass::Inline],
+        description: "keep variables used by one word in registers",
+    },
+    PassInfo {
+        pass: Pass::Induction,
+        name: "induction",
+        aliases: &[],
+        scope: PassScope::Word,
+        after: &[],
+        description: "replace scaled loop indices with additive updates",
+    },
+    PassInfo {
+        pass: Pass::Vectorize,
+        name: "vectorize",
+        aliases: &[],
+        scope: PassScope::Word,
+        // Recognizes the pointer updates induction variables leave
+        after: &[Pass::Induction],
+        description: "turn stride-1 array loops into SIMD kernels",
+    },
+    PassInfo {
+        pass: Pass::Superinstructions,
+        name: "superinstructions",
+        aliases: &[],
+        scope: PassScope::Word,
+        // Fused instructions hide the loops these passes look for
+        after: &[Pass::Induction, Pass::Vectorize],
+        description: "fuse common instruction sequences",
+    },
+    PassInfo {
+        pass: Pass::DeadCode,
+        name: "dead_code",
+        aliases: &["dce"],
+        scope: PassScope::Word,
+        after: &[],
+        description: "remove values that are never used",
+    },
+    PassInfo {
+        pass: Pass::MemoryOpt,
+        name: "memory_opt",
+        aliases: &["memory"],
+        scope: PassScope::Word,
+        after: &[],
+        description: "forward stores to loads and reorder memory accesses",
+    },
+    PassInfo {
+        pass: Pass::StackCache,
+        name: "stack_cache",
+        aliases: &[],
+        scope: PassScope::Word,
+        // The cached instructions are for code generation only
+        after: &[
+            Pass::ZeroCost,
+            Pass::TypeSpecialization,
+            Pass::WholeProgram,
+            Pass::ConstantFold,
+            Pass::Peephole,
+            Pass::Inline,
+            Pass::Escape,
+            Pass::Induction,
+            Pass::Vectorize,
+            Pass::Superinstructions,
+            Pass::DeadCode,
+            Pass::MemoryOpt,
+        ],
+        description: "keep the top of the stack in registers",
+    },
+];
+
+impl Pass {
+    /// The registry entry of this pass
+    pub fn info(self) -> &'static PassInfo {
+        PASSES.iter().find(|info| info.pass == self).expect("every pass is registered")
+    }
+
+    /// Name used in pipelines, reports and traces
+    pub fn name(self) -> &'static str {
+        self.info().name
+    }
+
+    /// Whether the pass works one word at a time
+    pub fn is_word_local(self) -> bool {
+        self.info().scope == PassScope::Word
+    }
+}
+
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Pass {
+    type Err = OptimizerError;
+
+    fn from_str(name: &str) -> Result<Self> {
+        let name = name.trim().to_ascii_lowercase().replace('-', "_");
+        PASSES
+            .iter()
+            .find(|info| info.name == name || info.aliases.contains(&name.as_str()))
+            .map(|info| info.pass)
+            .ok_or_else(|| {
+                let known: Vec<&str> = PASSES.iter().map(|info| info.name).collect();
+                OptimizerError::InvalidPipeline(format!("unknown pass `{}` (known: {})", name, known.join(", ")))
+            })
+    }
+}
+
+/// An ordered list of passes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PassPipeline {
+    passes: Vec<Pass>,
+}
+
+impl PassPipeline {
+    /// Names of the presets, one per optimization level
+    pub const PRESETS: [&'static str; 4] = ["none", "basic", "standard", "aggressive"];
+
+    pub fn new(passes: Vec<Pass>) -> Self {
+        Self { passes }
+    }
+
+    /// The passes an optimization level runs
+    ///
+    /// Type specialization only runs when the optimizer is given type
+    /// information, and vectorization only while it is enabled.
+    pub fn preset(level: OptimizationLevel) -> Self {
+        use Pass::*;
+
+        let passes = match level {
+            OptimizationLevel::None => vec![],
+            OptimizationLevel::Basic => vec![ConstantFold, Peephole, Superinstructions, DeadCode],
+            OptimizationLevel::Standard => vec![
+                TypeSpecialization,
+                WholeProgram,
+                ConstantFold,
+                Peephole,
+                Inline,
+                Escape,
+                Induction,
+                Vectorize,
+                Superinstructions,
+                DeadCode,
+                MemoryOpt,
+                StackCache,
+            ],
+            OptimizationLevel::Aggressive => PASSES.iter().map(|info| info.pass).collect(),
+        };
+        Self { passes }
+    }
+
+    /// A preset by name
+    pub fn named(name: &str) -> Option<Self> {
+        let level = match name.trim().to_ascii_lowercase().as_str() {
+            "none" => OptimizationLevel::None,
+            "basic" => OptimizationLevel::Basic,
+            "standard" => OptimizationLevel::Standard,
+            "aggressive" => OptimizationLevel::Aggressive,
+            _ => return None,
+        };
+        Some(Self::preset(level))
+    }
+
+    /// Parse a preset name or a comma-separated list of passes
+    pub fn parse(spec: &str) -> Result<Self> {
+        if let Some(preset) = Self::named(spec) {
+            return Ok(preset);
+        }
+        let passes = spec
+            .split(',')
+            .filter(|name| !name.trim().is_empty())
+            .map(str::parse)
+            .collect::<Result<_>>()?;
+        Ok(Self { passes })
+    }
+
+    /// Parse a pipeline file
+    ///
+    /// The file either names a `preset` or lists its `passes`; with
+    /// neither it starts from the preset of `level`. Passes listed under
+    /// `disable` are then removed.
+    pub fn from_toml(text: &str, level: OptimizationLevel) -> Result<Self> {
+        let invalid = |message: String| OptimizerError::InvalidPipeline(message);
+        let table: toml::Table = text.parse().map_err(|e: toml::de::Error| invalid(e.message().to_string()))?;
+        let names = |key: &str| -> Result<Option<Vec<&str>>> {
+            let Some(value) = table.get(key) else {
+                return Ok(None);
+            };
+            let list = value.as_array().ok_or_else(|| invalid(format!("`{}` must be a list of pass names", key)))?;
+            list.iter()
+                .map(|name| name.as_str().ok_or_else(|| invalid(format!("`{}` must be a list of pass names", key))))
+                .collect::<Result<_>>()
+                .map(Some)
+        };
+
+        if let Some(key) = table.keys().find(|key| !["preset", "passes", "disable"].contains(&key.as_str())) {
+            return Err(invalid(format!("unknown key `{}`", key)));
+        }
+        let mut pipeline = match (table.get("preset"), names("passes")?) {
+            (Some(_), Some(_)) => return Err(invalid("give either `preset` or `passes`, not both".to_string())),
+            (Some(preset), None) => {
+                let name = preset.as_str().ok_or_else(|| invalid("`preset` must be a string".to_string()))?;
+                Self::named(name).ok_or_else(|| {
+                    invalid(format!("unknown preset `{}` (known: {})", name, Self::PRESETS.join(", ")))
+                })?
+            }
+            (None, Some(passes)) => Self { passes: passes.into_iter().map(str::parse).collect::<Result<_>>()? },
+            (None, None) => Self::preset(level),
+        };
+        for name in names("disable")?.unwrap_or_default() {
+            pipeline.disable(name.parse()?);
+        }
+        Ok(pipeline)
+    }
+
+    /// Remove every run of a pass
+    pub fn disable(&mut self, pass: Pass) {
+        self.passes.retain(|&p| p != pass);
+    }
+
+    /// The pipeline without a pass
+    pub fn without(mut self, pass: Pass) -> Self {
+        self.disable(pass);
+        self
+    }
+
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    pub fn contains(&self, pass: Pass) -> bool {
+        self.passes.contains(&pass)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+}
+
+impl fmt::Display for PassPipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self.passes.iter().map(|pass| pass.name()).collect();
+        f.write_str(&names.join(","))
+    }
+}
+
+/// A step of a pipeline run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stage {
+    /// A whole-program pass
+    Program(Pass),
+    /// Consecutive word-local passes, run over each word in turn
+    Words(Vec<Pass>),
+}
+
+/// A validated pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassManager {
+    pipeline: PassPipeline,
+}
+
+impl PassManager {
+    /// Check that no pass runs after one that must come after it
+    pub fn new(pipeline: PassPipeline) -> Result<Self> {
+        for (index, pass) in pipeline.passes.iter().enumerate() {
+            if let Some(later) = pipeline.passes[index + 1..].iter().find(|later| pass.info().after.contains(later)) {
+                return Err(OptimizerError::InvalidPipeline(format!("{} cannot run after {}", later, pass)));
+            }
+        }
+        Ok(Self { pipeline })
+    }
+
+    /// The preset of an optimization level, which is always valid
+    pub fn preset(level: OptimizationLevel) -> Self {
+        Self { pipeline: PassPipeline::preset(level) }
+    }
+
+    pub fn pipeline(&self) -> &PassPipeline {
+        &self.pipeline
+    }
+
+    /// Remove a pass; a valid pipeline stays valid without it
+    pub fn disable(&mut self, pass: Pass) {
+        self.pipeline.disable(pass);
+    }
+
+    /// The stages running the passes `enabled` keeps, in order
+    pub fn stages(&self, enabled: impl Fn(Pass) -> bool) -> Vec<Stage> {
+        let mut stages = Vec::new();
+        for &pass in self.pipeline.passes.iter().filter(|&&pass| enabled(pass)) {
+            match stages.last_mut() {
+                Some(Stage::Words(passes)) if pass.is_word_local() => passes.push(pass),
+                _ if pass.is_word_local() => stages.push(Stage::Words(vec![pass])),
+                _ => stages.push(Stage::Program(pass)),
+            }
+        }
+        stages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pass_list() {
+        let pipeline = PassPipeline::parse("constant-fold,inline,dce").unwrap();
+        assert_eq!(pipeline.passes(), [Pass::ConstantFold, Pass::Inline, Pass::DeadCode]);
+        assert_eq!(pipeline.to_string(), "constant_fold,inline,dead_code");
+        assert_eq!(PassPipeline::parse("standard").unwrap(), PassPipeline::preset(OptimizationLevel::Standard));
+        assert!(PassPipeline::parse("constant-fold,unroll").is_err());
+    }
+
+    #[test]
+    fn test_dependencies_are_checked() {
+        assert!(PassManager::new(PassPipeline::parse("inline,escape").unwrap()).is_ok());
+        let err = PassManager::new(PassPipeline::parse("escape,inline").unwrap()).unwrap_err();
+        assert!(err.to_string().contains("inline cannot run after escape"));
+        assert!(PassManager::new(PassPipeline::parse("stack-cache,dce").unwrap()).is_err());
+
+        for name in PassPipeline::PRESETS {
+            assert!(PassManager::new(PassPipeline::named(name).unwrap()).is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_stages_group_word_local_passes() {
+        let manager = PassManager::new(PassPipeline::parse("dce,constant-fold,induction,vectorize,dce").unwrap()).unwrap();
+        assert_eq!(
+            manager.stages(|pass| pass != Pass::Vectorize),
+            vec![
+                Stage::Words(vec![Pass::DeadCode]),
+                Stage::Program(Pass::ConstantFold),
+                Stage::Words(vec![Pass::Induction, Pass::DeadCode]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pipeline_file() {
+        let pipeline = PassPipeline::from_toml("disable = [\"stack-cache\"]", OptimizationLevel::Standard).unwrap();
+        assert_eq!(pipeline, PassPipeline::preset(OptimizationLevel::Standard).without(Pass::StackCache));
+
+        let pipeline = PassPipeline::from_toml("passes = [\"fold\", \"dce\"]", OptimizationLevel::None).unwrap();
+        assert_eq!(pipeline.passes(), [Pass::ConstantFold, Pass::DeadCode]);
+
+        let pipeline = PassPipeline::from_toml("preset = \"basic\"", OptimizationLevel::Aggressive).unwrap();
+        assert_eq!(pipeline, PassPipeline::preset(OptimizationLevel::Basic));
+
+        assert!(PassPipeline::from_toml("preset = \"basic\"\npasses = []", OptimizationLevel::None).is_err());
+        assert!(PassPipeline::from_toml("pases = []", OptimizationLevel::None).is_err());
+    }
+}
@@ -0,0 +1,408 @@
+//! User-Defined Peephole Rules
+//!
+//! The builtin peephole rewrites know idioms common to most Forth code.
+//! Domain-specific code has hot idioms of its own; a rule file teaches the
+//! peephole pass to rewrite them, without rebuilding the compiler.
+//!
+//! # Rule Format
+//!
+//! One rule per line: a pattern of builtin words and literals, `=>`, the
+//! replacement, and optionally `where` followed by guards on the literals
+//! the pattern matched. `$name` in a pattern matches any literal; the same
+//! name twice matches the same value, and the replacement can use it. `#`
+//! starts a comment.
+//!
+//! ```text
+//! # fastforth peephole rules
+//! swap drop => nip
+//! 0 + =>
+//! $n and => drop 0 where $n = 0
+//! $n dup => $n $n
+//! 1 * =>
+//! ```
+//!
+//! Guards compare a literal with a number or another literal, using `=`,
+//! `<>`, `<`, `<=`, `>` or `>=`, joined by `and`.
+//!
+//! Every rule is checked when loaded: its replacement must leave the
+//! stack as deep as its pattern does, without reaching below the values
+//! the pattern consumes. Rules are tried in file order at each position,
+//! and the first one whose pattern and guards match is applied.
+
+use crate::ir::{Instruction, StackEffect};
+use crate::{OptimizerError, Result};
+use std::collections::HashMap;
+use std::fmt;
+
+/// An element of a rule pattern or replacement
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleTerm {
+    /// A builtin instruction or a literal with a fixed value
+    Instruction(Instruction),
+    /// Any literal, bound to a name
+    Constant(String),
+}
+
+/// The value a guard compares with
+#[derive(Debug, Clone, PartialEq)]
+pub enum GuardOperand {
+    Value(i64),
+    Constant(String),
+}
+
+/// A comparison a guard makes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuardOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl GuardOp {
+    fn parse(op: &str) -> Option<Self> {
+        Some(match op {
+            "=" | "==" => GuardOp::Eq,
+            "<>" | "!=" => GuardOp::Ne,
+            "<" => GuardOp::Lt,
+            "<=" => GuardOp::Le,
+            ">" => GuardOp::Gt,
+            ">=" => GuardOp::Ge,
+            _ => return None,
+        })
+    }
+
+    fn holds(self, left: i64, right: i64) -> bool {
+        match self {
+            GuardOp::Eq => left == right,
+            GuardOp::Ne => left != right,
+            GuardOp::Lt => left < right,
+            GuardOp::Le => left <= right,
+            GuardOp::Gt => left > right,
+            GuardOp::Ge => left >= right,
+        }
+    }
+
+    fn spelling(self) -> &'static str {
+        match self {
+            GuardOp::Eq => "=",
+            GuardOp::Ne => "<>",
+            GuardOp::Lt => "<",
+            GuardOp::Le => "<=",
+            GuardOp::Gt => ">",
+            GuardOp::Ge => ">=",
+        }
+    }
+}
+
+/// A condition on the literals a pattern matched
+#[derive(Debug, Clone, PartialEq)]
+pub struct Guard {
+    pub constant: String,
+    pub op: GuardOp,
+    pub operand: GuardOperand,
+}
+
+/// A rewrite from a pattern to a replacement
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeepholeRule {
+    pub pattern: Vec<RuleTerm>,
+    pub replacement: Vec<RuleTerm>,
+    pub guards: Vec<Guard>,
+}
+
+impl PeepholeRule {
+    /// Parse one rule, checking its stack effect
+    pub fn parse(text: &str) -> std::result::Result<Self, String> {
+        let (pattern, rest) = text.split_once("=>").ok_or("expected `pattern => replacement`")?;
+        let (replacement, guards) = match rest.split_once(" where ") {
+            Some((replacement, guards)) => (replacement, Some(guards)),
+            None => (rest, None),
+        };
+
+        let pattern = parse_terms(pattern)?;
+        let replacement = parse_terms(replacement)?;
+        let guards = match guards {
+            Some(guards) => guards.split(" and ").map(parse_guard).collect::<std::result::Result<_, _>>()?,
+            None => Vec::new(),
+        };
+        let rule = Self { pattern, replacement, guards };
+        rule.check()?;
+        Ok(rule)
+    }
+
+    /// Check that the rule is well formed and keeps the stack effect
+    fn check(&self) -> std::result::Result<(), String> {
+        if self.pattern.is_empty() {
+            return Err("the pattern is empty".to_string());
+        }
+        if self.pattern == self.replacement {
+            return Err("the replacement is the pattern".to_string());
+        }
+
+        let bound: Vec<&String> = self.pattern.iter().filter_map(RuleTerm::constant).collect();
+        let used = self
+            .replacement
+            .iter()
+            .filter_map(RuleTerm::constant)
+            .chain(self.guards.iter().flat_map(|guard| {
+                let operand = match &guard.operand {
+                    GuardOperand::Constant(name) => Some(name),
+                    GuardOperand::Value(_) => None,
+                };
+                std::iter::once(&guard.constant).chain(operand)
+            }));
+        for name in used {
+            if !bound.contains(&name) {
+                return Err(format!("${} is not matched by the pattern", name));
+            }
+        }
+
+        if self.pattern.iter().chain(&self.replacement).any(|term| *term == RuleTerm::Instruction(Instruction::Return)) {
+            return Err("rules cannot return".to_string());
+        }
+
+        let (before, after) = (effect(&self.pattern), effect(&self.replacement));
+        let depth_change = |effect: &StackEffect| effect.produced as i32 - effect.consumed as i32;
+        if depth_change(&before) != depth_change(&after) || after.consumed > before.consumed {
+            return Err(format!(
+                "the pattern is ( {} -- {} ) but the replacement is ( {} -- {} )",
+                before.consumed, before.produced, after.consumed, after.produced
+            ));
+        }
+        Ok(())
+    }
+
+    /// The replacement if the rule matches at the start of `instructions`
+    pub fn apply(&self, instructions: &[Instruction]) -> Option<Vec<Instruction>> {
+        if instructions.len() < self.pattern.len() {
+            return None;
+        }
+
+        let mut bindings: HashMap<&str, i64> = HashMap::new();
+        for (term, inst) in self.pattern.iter().zip(instructions) {
+            match (term, inst) {
+                (RuleTerm::Instruction(expected), inst) if expected == inst => {}
+                (RuleTerm::Constant(name), Instruction::Literal(value)) => {
+                    if *bindings.entry(name.as_str()).or_insert(*value) != *value {
+                        return None;
+                    }
+                }
+                _ => return None,
+            }
+        }
+
+        let holds = self.guards.iter().all(|guard| {
+            let right = match &guard.operand {
+                GuardOperand::Value(value) => *value,
+                GuardOperand::Constant(name) => bindings[name.as_str()],
+            };
+            guard.op.holds(bindings[guard.constant.as_str()], right)
+        });
+        holds.then(|| {
+            self.replacement
+                .iter()
+                .map(|term| match term {
+                    RuleTerm::Instruction(inst) => inst.clone(),
+                    RuleTerm::Constant(name) => Instruction::Literal(bindings[name.as_str()]),
+                })
+                .collect()
+        })
+    }
+}
+
+impl RuleTerm {
+    fn constant(&self) -> Option<&String> {
+        match self {
+            RuleTerm::Constant(name) => Some(name),
+            RuleTerm::Instruction(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for RuleTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleTerm::Instruction(inst) => match inst.to_word() {
+                Some(word) => f.write_str(&word),
+                None => write!(f, "{:?}", inst),
+            },
+            RuleTerm::Constant(name) => write!(f, "${}", name),
+        }
+    }
+}
+
+impl fmt::Display for PeepholeRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let spell = |terms: &[RuleTerm]| terms.iter().map(|term| term.to_string()).collect::<Vec<_>>().join(" ");
+        write!(f, "{} =>", spell(&self.pattern))?;
+        if !self.replacement.is_empty() {
+            write!(f, " {}", spell(&self.replacement))?;
+        }
+        for (index, guard) in self.guards.iter().enumerate() {
+            let operand = match &guard.operand {
+                GuardOperand::Value(value) => value.to_string(),
+                GuardOperand::Constant(name) => format!("${}", name),
+            };
+            let joiner = if index == 0 { "where" } else { "and" };
+            write!(f, " {} ${} {} {}", joiner, guard.constant, guard.op.spelling(), operand)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_terms(text: &str) -> std::result::Result<Vec<RuleTerm>, String> {
+    text.split_whitespace()
+        .map(|word| {
+            if let Some(name) = constant_name(word) {
+                return Ok(RuleTerm::Constant(name));
+            }
+            Instruction::from_word(&word.to_lowercase())
+                .or_else(|| word.parse().ok().map(Instruction::Literal))
+                .map(RuleTerm::Instruction)
+                .ok_or_else(|| format!("'{}' is not a builtin word", word))
+        })
+        .collect()
+}
+
+fn parse_guard(text: &str) -> std::result::Result<Guard, String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let [constant, op, operand] = words[..] else {
+        return Err(format!("expected `$name op value` in guard '{}'", text.trim()));
+    };
+    let constant = constant_name(constant).ok_or_else(|| format!("guards compare a $name, not '{}'", constant))?;
+    let op = GuardOp::parse(op).ok_or_else(|| format!("'{}' is not a comparison", op))?;
+    let operand = match constant_name(operand) {
+        Some(name) => GuardOperand::Constant(name),
+        None => GuardOperand::Value(operand.parse().map_err(|_| format!("'{}' is not a number", operand))?),
+    };
+    Ok(Guard { constant, op, operand })
+}
+
+fn constant_name(word: &str) -> Option<String> {
+    word.strip_prefix('$').filter(|name| !name.is_empty()).map(str::to_string)
+}
+
+fn effect(terms: &[RuleTerm]) -> StackEffect {
+    terms.iter().fold(StackEffect::new(0, 0), |effect, term| {
+        let step = match term {
+            RuleTerm::Instruction(inst) => inst.stack_effect(),
+            RuleTerm::Constant(_) => StackEffect::new(0, 1),
+        };
+        effect.compose(&step)
+    })
+}
+
+/// The rules of a rule file, in the order they are tried
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PeepholeRules {
+    pub rules: Vec<PeepholeRule>,
+}
+
+impl PeepholeRules {
+    /// Parse a rule file, checking every rule
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut rules = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let rule = PeepholeRule::parse(line)
+                .map_err(|message| OptimizerError::ParseError(format!("line {}: {}", number + 1, message)))?;
+            rules.push(rule);
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// Rewrite every match in a sequence, returning how many were rewritten
+    pub fn rewrite(&self, instructions: &mut Vec<Instruction>) -> usize {
+        let mut rewrites = 0;
+        let mut i = 0;
+        while i < instructions.len() {
+            let matched = self
+                .rules
+                .iter()
+                .find_map(|rule| rule.apply(&instructions[i..]).map(|replacement| (rule.pattern.len(), replacement)));
+            match matched {
+                Some((len, replacement)) => {
+                    let added = replacement.len();
+                    instructions.splice(i..i + len, replacement);
+                    i += added;
+                    rewrites += 1;
+                }
+                None => i += 1,
+            }
+        }
+        rewrites
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+impl fmt::Display for PeepholeRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# fastforth peephole rules")?;
+        for rule in &self.rules {
+            writeln!(f, "{}", rule)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Instruction::*;
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = PeepholeRules::parse(
+            "# fastforth peephole rules\n\
+             swap drop => nip\n\
+             0 + =>        # adding zero\n\
+             $n and => drop 0 where $n = 0\n",
+        )
+        .unwrap();
+
+        assert_eq!(rules.len(), 3);
+        assert_eq!(rules.rules[0].replacement, vec![RuleTerm::Instruction(Nip)]);
+        assert_eq!(rules.rules[2].to_string(), "$n and => drop 0 where $n = 0");
+        assert_eq!(PeepholeRules::parse(&rules.to_string()).unwrap(), rules);
+    }
+
+    #[test]
+    fn test_reject_unbalanced_rules() {
+        let err = PeepholeRules::parse("\ndup + => dup").unwrap_err().to_string();
+        assert!(err.contains("line 2"), "{}", err);
+        assert!(err.contains("( 1 -- 1 ) but the replacement is ( 1 -- 2 )"), "{}", err);
+
+        // The replacement may not read below what the pattern consumed
+        assert!(PeepholeRule::parse("dup => over").is_err());
+        assert!(PeepholeRule::parse("$n drop => $m drop").is_err());
+        assert!(PeepholeRule::parse("dup => dup").is_err());
+        assert!(PeepholeRule::parse("return => ").is_err());
+        assert!(PeepholeRule::parse("$n + => where $n < zero").is_err());
+    }
+
+    #[test]
+    fn test_rewrite_with_guards() {
+        let rules = PeepholeRules::parse("$n and => drop 0 where $n = 0\n$a $a + => $a 2 *").unwrap();
+        let mut instructions = vec![Dup, Literal(0), And, Literal(3), Literal(3), Add, Literal(1), And];
+        assert_eq!(rules.rewrite(&mut instructions), 2);
+        assert_eq!(
+            instructions,
+            vec![Dup, Drop, Literal(0), Literal(3), Literal(2), Mul, Literal(1), And]
+        );
+    }
+}
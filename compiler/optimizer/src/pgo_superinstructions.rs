@@ -188,9 +188,9 @@ impl PatternProfile {
         }
     }
 
-    fn record_execution(&mut self, cycles: u64) {
-        self.count += 1;
-        self.total_cycles += cycles;
+    fn record_executions(&mut self, executions: u64, cycles: u64) {
+        self.count += executions;
+        self.total_cycles += executions * cycles;
         self.avg_cycles_per_exec = self.total_cycles as f64 / self.count as f64;
     }
 
@@ -278,7 +278,16 @@ impl PatternDatabase {
 
     /// Record execution of an instruction sequence
     pub fn record_pattern(&mut self, instructions: &[Instruction], cycles: u64) {
-        self.total_instructions_executed += instructions.len() as u64;
+        self.record_executions(instructions, 1, cycles);
+    }
+
+    /// Record an instruction sequence that ran `executions` times, as
+    /// counted by an interpreter
+    pub fn record_executions(&mut self, instructions: &[Instruction], executions: u64, cycles: u64) {
+        if executions == 0 {
+            return;
+        }
+        self.total_instructions_executed += instructions.len() as u64 * executions;
 
         // Try all pattern lengths from MIN to MAX
         for length in MIN_PATTERN_LENGTH..=MAX_PATTERN_LENGTH.min(instructions.len()) {
@@ -292,7 +301,7 @@ impl PatternDatabase {
                     p
                 });
 
-                profile.record_execution(cycles);
+                profile.record_executions(executions, cycles);
             }
         }
     }
@@ -596,6 +605,8 @@ pub struct PGOOptimizer {
     optimized_execution_time: Option<Duration>,
     /// Track fusions applied per iteration
     fusions_per_iteration: Vec<usize>,
+    /// How often EXECUTE reached each word
+    execute_targets: HashMap<String, u64>,
 }
 
 impl PGOOptimizer {
@@ -615,6 +626,7 @@ impl PGOOptimizer {
             baseline_execution_time: None,
             optimized_execution_time: None,
             fusions_per_iteration: Vec::new(),
+            execute_targets: HashMap::new(),
         }
     }
 
@@ -643,6 +655,40 @@ impl PGOOptimizer {
         }
     }
 
+    /// Profile words by how often they ran, such as the call counts of the
+    /// tiered interpreter; words without a count are skipped
+    pub fn profile_calls<'a>(&mut self, ir: &ForthIR, calls: impl IntoIterator<Item = (&'a str, u64)>) {
+        if !self.profiling_enabled {
+            return;
+        }
+
+        for (name, count) in calls {
+            if let Some(word) = ir.words.get(name) {
+                self.database.record_executions(&word.instructions, count, 1);
+            }
+        }
+    }
+
+    /// Profile the words EXECUTE reached, with how often it reached each
+    pub fn profile_execute_targets<'a>(&mut self, targets: impl IntoIterator<Item = (&'a str, u64)>) {
+        if !self.profiling_enabled {
+            return;
+        }
+
+        for (name, count) in targets.into_iter().filter(|&(_, count)| count > 0) {
+            *self.execute_targets.entry(name.to_string()).or_insert(0) += count;
+        }
+    }
+
+    /// The word EXECUTE reached most often, which compiled code tests for
+    /// before calling through the token; ties go to the first name
+    pub fn likely_execute_target(&self) -> Option<&str> {
+        self.execute_targets
+            .iter()
+            .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then_with(|| b.cmp(a)))
+            .map(|(name, _)| name.as_str())
+    }
+
     /// Identify hot patterns with minimum count threshold
     pub fn identify_hot_patterns(&mut self, min_count: u64) -> Vec<PatternProfile> {
         self.database.identify_hot_patterns(min_count)
@@ -902,6 +948,18 @@ mod tests {
         assert!(hot.len() > 0);
     }
 
+    #[test]
+    fn test_weighted_recording() {
+        let mut db = PatternDatabase::new();
+        db.record_executions(&[Instruction::Dup, Instruction::Add], 15_000, 1);
+        db.record_executions(&[Instruction::Dup, Instruction::Mul], 0, 1);
+
+        let hot = db.identify_hot_patterns(10_000);
+        assert_eq!(hot.len(), 1);
+        assert_eq!(hot[0].count, 15_000);
+        assert!(db.identify_hot_patterns(1).iter().all(|p| p.key == hot[0].key));
+    }
+
     #[test]
     fn test_fusion_generation() {
         let mut gen = FusionGenerator::new();
@@ -934,6 +992,19 @@ mod tests {
         assert!(stats.fusions_generated > 0);
     }
 
+    #[test]
+    fn test_likely_execute_target() {
+        let mut pgo = PGOOptimizer::new();
+        pgo.profile_execute_targets([("sq", 10)]);
+        assert_eq!(pgo.likely_execute_target(), None);
+
+        pgo.enable_profiling();
+        pgo.profile_execute_targets([("sq", 10), ("neg", 7), ("dup", 0)]);
+        assert_eq!(pgo.likely_execute_target(), Some("sq"));
+        pgo.profile_execute_targets([("neg", 3)]);
+        assert_eq!(pgo.likely_execute_target(), Some("neg"));
+    }
+
     #[test]
     fn test_database_export_import() {
         let mut db = PatternDatabase::new();
@@ -0,0 +1,242 @@
+//! Optimization Report
+//!
+//! Collects what the optimizer did to a program in one place: the
+//! instruction count after each pass, the statistics the passes already
+//! keep, and per-word detail (calls inlined, superinstructions fired,
+//! instructions removed as dead code, stack cache depth, and the size of the
+//! word after each pass that changed it).
+//!
+//! Reporting is off by default because the per-word detail compares each
+//! word before and after the passes that change it. Enable it with
+//! [`Optimizer::set_report`], then read [`Optimizer::report`] after
+//! optimizing.
+//!
+//! [`Optimizer::set_report`]: crate::Optimizer::set_report
+//! [`Optimizer::report`]: crate::Optimizer::report
+
+use crate::cranelift_peephole::PeepholeStats;
+use crate::inline::InlineStats;
+use crate::ir::{ForthIR, Instruction};
+use crate::type_specialization::SpecializationStats;
+use crate::whole_program::WPOStats;
+use crate::zero_cost::ZeroCostStats;
+use crate::OptimizationLevel;
+use std::collections::{BTreeMap, HashMap};
+
+/// Name under which the main sequence is reported
+pub const MAIN: &str = "<main>";
+
+/// Program size after one pass
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassReport {
+    pub pass: &'static str,
+    pub instructions_before: usize,
+    pub instructions_after: usize,
+}
+
+/// What the optimizer did to one word
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WordReport {
+    pub instructions_before: usize,
+    pub instructions_after: usize,
+    /// Callees inlined into the word, once per call site
+    pub inlined: Vec<String>,
+    /// Superinstruction patterns fused, once per occurrence
+    pub superinstructions: Vec<String>,
+    /// Instructions removed by dead code elimination
+    pub dead_code_eliminated: usize,
+    /// Items kept in registers by stack caching, when it ran
+    pub cache_depth: Option<u8>,
+    /// Passes that changed the word, in the order they ran; a word a pass
+    /// removed ends with zero instructions
+    pub passes: Vec<PassReport>,
+}
+
+/// Report of one optimizer run
+#[derive(Debug, Clone)]
+pub struct OptimizationReport {
+    pub level: OptimizationLevel,
+    /// Passes in the order they ran
+    pub passes: Vec<PassReport>,
+    /// Per-word detail, keyed by word name ([`MAIN`] for the main sequence)
+    pub words: BTreeMap<String, WordReport>,
+    pub zero_cost: Option<ZeroCostStats>,
+    pub peephole: Option<PeepholeStats>,
+    pub inline: Option<InlineStats>,
+    pub specialization: Option<SpecializationStats>,
+    pub whole_program: Option<WPOStats>,
+}
+
+impl OptimizationReport {
+    /// Start a report for `ir` as it enters the optimizer
+    pub fn new(level: OptimizationLevel, ir: &ForthIR) -> Self {
+        let mut words = BTreeMap::new();
+        words.insert(
+            MAIN.to_string(),
+            WordReport {
+                instructions_before: ir.main.len(),
+                ..Default::default()
+            },
+        );
+        for (name, word) in &ir.words {
+            words.insert(
+                name.clone(),
+                WordReport {
+                    instructions_before: word.instructions.len(),
+                    ..Default::default()
+                },
+            );
+        }
+
+        Self {
+            level,
+            passes: Vec::new(),
+            words,
+            zero_cost: None,
+            peephole: None,
+            inline: None,
+            specialization: None,
+            whole_program: None,
+        }
+    }
+
+    /// Record a pass that turned `before` into `after`, in total and for
+    /// each word it changed
+    pub fn record_pass(&mut self, pass: &'static str, before: &ForthIR, after: &ForthIR) {
+        self.passes.push(PassReport {
+            pass,
+            instructions_before: before.instruction_count(),
+            instructions_after: after.instruction_count(),
+        });
+
+        let mut changed = Vec::new();
+        if before.main != after.main {
+            changed.push((MAIN, before.main.len(), after.main.len()));
+        }
+        for (name, word) in &before.words {
+            let instructions = after.words.get(name).map(|word| word.instructions.as_slice());
+            if instructions != Some(word.instructions.as_slice()) {
+                changed.push((name.as_str(), word.instructions.len(), instructions.map_or(0, <[_]>::len)));
+            }
+        }
+        for (name, word) in &after.words {
+            if !before.words.contains_key(name) {
+                changed.push((name.as_str(), 0, word.instructions.len()));
+            }
+        }
+        for (name, instructions_before, instructions_after) in changed {
+            self.word_mut(name).passes.push(PassReport { pass, instructions_before, instructions_after });
+        }
+    }
+
+    /// Record the calls a pass inlined, by comparing each word before and after it
+    pub fn record_inlining(&mut self, before: &ForthIR, after: &ForthIR) {
+        self.word_mut(MAIN).inlined.extend(removed_calls(&before.main, &after.main));
+        for (name, word) in &after.words {
+            if let Some(original) = before.words.get(name) {
+                let inlined = removed_calls(&original.instructions, &word.instructions);
+                self.word_mut(name).inlined.extend(inlined);
+            }
+        }
+    }
+
+    /// Record the final size of every word
+    pub fn finish(&mut self, ir: &ForthIR) {
+        self.word_mut(MAIN).instructions_after = ir.main.len();
+        for (name, word) in &ir.words {
+            self.word_mut(name).instructions_after = word.instructions.len();
+        }
+    }
+
+    /// Report of a word, created if a pass introduced it
+    pub fn word_mut(&mut self, name: &str) -> &mut WordReport {
+        self.words.entry(name.to_string()).or_default()
+    }
+
+    /// Total superinstructions fused across all words
+    pub fn superinstructions_fired(&self) -> usize {
+        self.words.values().map(|word| word.superinstructions.len()).sum()
+    }
+
+    /// Total instructions removed by dead code elimination
+    pub fn dead_code_eliminated(&self) -> usize {
+        self.words.values().map(|word| word.dead_code_eliminated).sum()
+    }
+}
+
+/// Callees called fewer times in `after` than in `before`, once per call
+/// that disappeared, in order of first appearance
+fn removed_calls(before: &[Instruction], after: &[Instruction]) -> Vec<String> {
+    let mut remaining: HashMap<&str, usize> = HashMap::new();
+    for inst in after {
+        if let Instruction::Call(name) = inst {
+            *remaining.entry(name.as_str()).or_insert(0) += 1;
+        }
+    }
+
+    let mut removed = Vec::new();
+    for inst in before {
+        if let Instruction::Call(name) = inst {
+            match remaining.get_mut(name.as_str()) {
+                Some(count) if *count > 0 => *count -= 1,
+                _ => removed.push(name.to_string()),
+            }
+        }
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::WordDef;
+    use crate::Optimizer;
+
+    #[test]
+    fn test_removed_calls_are_a_multiset_difference() {
+        let call = |name: &str| Instruction::Call(name.into());
+        let before = vec![call("sq"), call("emit"), call("sq"), call("sq")];
+        let after = vec![call("emit"), call("sq"), Instruction::Dup, Instruction::Mul];
+
+        assert_eq!(removed_calls(&before, &after), vec!["sq".to_string(), "sq".to_string()]);
+    }
+
+    #[test]
+    fn test_report_has_per_word_detail() {
+        let mut ir = ForthIR::new();
+        ir.add_word(WordDef::new(
+            "square".to_string(),
+            vec![Instruction::Dup, Instruction::Mul, Instruction::Return],
+        ));
+        let mut f = WordDef::new(
+            "f".to_string(),
+            vec![
+                Instruction::Call("square".into()),
+                Instruction::Literal(1),
+                Instruction::Add,
+                Instruction::Nop,
+                Instruction::Nop,
+                Instruction::Return,
+            ],
+        );
+        f.attributes.export = true;
+        ir.add_word(f);
+
+        let mut optimizer = Optimizer::new(OptimizationLevel::Standard);
+        optimizer.set_report(true);
+        let optimized = optimizer.optimize(ir).unwrap();
+        let report = optimizer.report().unwrap();
+        let f = &report.words["f"];
+
+        assert_eq!(f.inlined, vec!["square".to_string()]);
+        assert_eq!(f.superinstructions, vec!["dup_mul".to_string()]);
+        assert_eq!(f.dead_code_eliminated, 2);
+        assert_eq!(f.instructions_after, optimized.get_word("f").unwrap().instructions.len());
+        assert!(f.cache_depth.is_some());
+        assert!(f.passes.iter().any(|pass| pass.pass == "dead_code" && pass.instructions_after < pass.instructions_before));
+        assert_eq!(report.words["square"].passes.last().map(|pass| pass.instructions_after), Some(0));
+        assert_eq!(report.passes.first().map(|pass| pass.pass), Some("whole_program"));
+        assert!(report.inline.is_some());
+        assert_eq!(report.whole_program.as_ref().map(|stats| stats.words_eliminated), Some(1));
+    }
+}
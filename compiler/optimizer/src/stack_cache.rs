@@ -11,6 +11,14 @@
 //! 3. Transform instructions to use cached registers
 //! 4. Insert flush/reload instructions at call boundaries
 //!
+//! # Cache Depth
+//!
+//! The depth is either fixed or chosen per word ([`CacheDepth::Auto`]). In
+//! automatic mode every depth the target has registers for is tried, and
+//! the one serving the most stack operations from registers, net of items
+//! spilled at calls, branches and cache overflow, wins. Ties go to the
+//! shallower cache, which leaves more registers to the code generator.
+//!
 //! # Register Allocation
 //!
 //! ```text
@@ -81,16 +89,118 @@ impl CacheState {
     }
 }
 
+/// Largest supported cache depth
+pub const MAX_CACHE_SIZE: u8 = 8;
+
+/// How many stack items to keep in registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheDepth {
+    /// Cache this many items in every word
+    Fixed(u8),
+    /// Pick a depth per word, using at most this many registers
+    Auto { registers: u8 },
+}
+
+impl CacheDepth {
+    /// Automatic depth for a target architecture (as in a target triple)
+    ///
+    /// Registers are what is left after the stack pointers, frame pointer
+    /// and scratch registers for operations; unknown targets get the
+    /// classic TOS/NOS/3OS.
+    pub fn for_arch(arch: &str) -> Self {
+        let registers = match arch {
+            "x86_64" => 6,
+            "aarch64" | "riscv64" => MAX_CACHE_SIZE,
+            _ => 3,
+        };
+        CacheDepth::Auto { registers }
+    }
+
+    /// Automatic depth for the host architecture
+    pub fn host() -> Self {
+        Self::for_arch(std::env::consts::ARCH)
+    }
+
+    /// Depths to try for a word
+    fn candidates(self) -> std::ops::RangeInclusive<u8> {
+        match self {
+            CacheDepth::Fixed(size) => size..=size,
+            CacheDepth::Auto { registers } => 1..=registers,
+        }
+    }
+}
+
+/// Cache depth chosen for a word and what it bought
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheProfile {
+    /// Items kept in registers
+    pub depth: u8,
+    /// Deepest stack the word reaches, relative to its lowest point
+    pub peak_depth: u8,
+    /// Stack operations served from registers
+    pub cached_ops: usize,
+    /// Items written back to memory at flushes and on cache overflow
+    pub spilled_items: usize,
+}
+
+impl CacheProfile {
+    /// Benefit of the depth: register hits net of spills
+    fn score(&self) -> i64 {
+        self.cached_ops as i64 - self.spilled_items as i64
+    }
+}
+
+/// Stack caching statistics
+#[derive(Debug, Clone, Default)]
+pub struct StackCacheStats {
+    /// Chosen depth and its profile per word
+    pub words: HashMap<String, CacheProfile>,
+}
+
+impl StackCacheStats {
+    /// Stack operations served from registers across all words
+    pub fn cached_ops(&self) -> usize {
+        self.words.values().map(|p| p.cached_ops).sum()
+    }
+
+    /// Items spilled to memory across all words
+    pub fn spilled_items(&self) -> usize {
+        self.words.values().map(|p| p.spilled_items).sum()
+    }
+
+    /// Mean chosen depth
+    pub fn average_depth(&self) -> f64 {
+        if self.words.is_empty() {
+            return 0.0;
+        }
+        self.words.values().map(|p| p.depth as f64).sum::<f64>() / self.words.len() as f64
+    }
+}
+
 /// Stack caching optimizer
 pub struct StackCacheOptimizer {
     /// Number of stack items to cache in registers
-    cache_size: u8,
+    depth: CacheDepth,
 }
 
 impl StackCacheOptimizer {
     pub fn new(cache_size: u8) -> Self {
-        assert!(cache_size > 0 && cache_size <= 8, "Cache size must be 1-8");
-        Self { cache_size }
+        Self::with_depth(CacheDepth::Fixed(cache_size))
+    }
+
+    /// Create an optimizer with a fixed or automatic cache depth
+    pub fn with_depth(depth: CacheDepth) -> Self {
+        let max = match depth {
+            CacheDepth::Fixed(size) => size,
+            CacheDepth::Auto { registers } => registers,
+        };
+        assert!(max > 0 && max <= MAX_CACHE_SIZE, "Cache size must be 1-8");
+        Self { depth }
+    }
+
+    /// Configured cache depth
+    pub fn depth(&self) -> CacheDepth {
+        self.depth
     }
 
     /// Optimize IR with stack caching
@@ -110,30 +220,103 @@ impl StackCacheOptimizer {
     }
 
     /// Optimize a word definition
-    fn optimize_word(&self, word: &WordDef) -> Result<WordDef> {
+    pub(crate) fn optimize_word(&self, word: &WordDef) -> Result<WordDef> {
+        Ok(self.optimize_word_profiled(word)?.0)
+    }
+
+    /// Optimize a word definition, reporting the depth chosen for it
+    pub(crate) fn optimize_word_profiled(&self, word: &WordDef) -> Result<(WordDef, CacheProfile)> {
         let mut optimized = word.clone();
-        optimized.instructions = self.optimize_sequence(&word.instructions)?;
+        let (instructions, profile) = self.cache_sequence(&word.instructions)?;
+        optimized.instructions = instructions;
         optimized.update();
-        Ok(optimized)
+        Ok((optimized, profile))
     }
 
     /// Optimize a sequence of instructions
     fn optimize_sequence(&self, instructions: &[Instruction]) -> Result<Vec<Instruction>> {
+        Ok(self.cache_sequence(instructions)?.0)
+    }
+
+    /// Cache a sequence at each candidate depth and keep the best
+    fn cache_sequence(&self, instructions: &[Instruction]) -> Result<(Vec<Instruction>, CacheProfile)> {
+        let mut best: Option<(Vec<Instruction>, CacheProfile)> = None;
+
+        for size in self.depth.candidates() {
+            let candidate = self.cache_sequence_at(instructions, size)?;
+            if best.as_ref().map_or(true, |(_, profile)| candidate.1.score() > profile.score()) {
+                best = Some(candidate);
+            }
+        }
+
+        Ok(best.expect("at least one cache depth"))
+    }
+
+    /// Cache a sequence with a given number of registers
+    fn cache_sequence_at(&self, instructions: &[Instruction], cache_size: u8) -> Result<(Vec<Instruction>, CacheProfile)> {
         let mut result = Vec::with_capacity(instructions.len());
-        let mut state = CacheState::new(self.cache_size);
+        let mut state = CacheState::new(cache_size);
+        let mut profile = CacheProfile { depth: cache_size, ..CacheProfile::default() };
+        let (mut lowest, mut highest) = (0, 0);
 
         for inst in instructions {
             // Apply stack caching transformation
-            let transformed = self.transform_instruction(inst, &mut state)?;
+            let cached_before = state.cached_depth;
+            let transformed = self.transform_instruction(inst, &mut state, cache_size)?;
+
+            for out in &transformed {
+                match out {
+                    Instruction::FlushCache => profile.spilled_items += cached_before as usize,
+                    Instruction::CachedDup { .. } | Instruction::CachedSwap { .. } | Instruction::CachedOver { .. } => {
+                        profile.cached_ops += 1
+                    }
+                    _ => {}
+                }
+            }
+            if !transformed.contains(&Instruction::FlushCache) && cached_before > 0 && Self::uses_cache(inst) {
+                profile.cached_ops += 1;
+            }
+            if Self::overflows(inst) && cached_before == cache_size && state.cached_depth == cache_size {
+                profile.spilled_items += 1;
+            }
+
+            lowest = lowest.min(state.total_depth);
+            highest = highest.max(state.total_depth);
             result.extend(transformed);
         }
 
         // Flush cache at end if needed
         if state.cached_depth > 0 {
+            profile.spilled_items += state.cached_depth as usize;
             result.push(Instruction::FlushCache);
         }
 
-        Ok(result)
+        profile.peak_depth = (highest - lowest).clamp(0, u8::MAX as i32) as u8;
+        Ok((result, profile))
+    }
+
+    /// Whether an instruction works on cached items in place
+    fn uses_cache(inst: &Instruction) -> bool {
+        use Instruction::*;
+        matches!(
+            inst,
+            Drop | Add | Sub | Mul | Div | Mod | And | Or | Xor | Eq | Ne | Lt | Le | Gt | Ge | Shl | Shr
+                | ULt | UGt | UDiv | UMod | UMul | UMulHigh | Rotl | Rotr
+                | Neg | Abs | Not | ZeroEq | ZeroLt | ZeroGt | Popcount | Ctz | Clz
+                | DupAdd | DupMul | ExtractBits { .. } | Lookup(_) | LocalStore(_)
+        )
+    }
+
+    /// Whether an instruction pushes a new item into the cache
+    fn overflows(inst: &Instruction) -> bool {
+        matches!(
+            inst,
+            Instruction::Literal(_)
+                | Instruction::FloatLiteral(_)
+                | Instruction::LocalFetch(_)
+                | Instruction::Dup
+                | Instruction::Over
+        )
     }
 
     /// Transform a single instruction with stack cache awareness
@@ -141,6 +324,7 @@ impl StackCacheOptimizer {
         &self,
         inst: &Instruction,
         state: &mut CacheState,
+        cache_size: u8,
     ) -> Result<SmallVec<[Instruction; 4]>> {
         use Instruction::*;
 
@@ -150,12 +334,23 @@ impl StackCacheOptimizer {
             // Literals: push to cache
             Literal(v) => {
                 result.push(Literal(*v));
-                self.push_cache(state);
+                self.push_cache(state, cache_size);
             }
 
             FloatLiteral(v) => {
                 result.push(FloatLiteral(*v));
-                self.push_cache(state);
+                self.push_cache(state, cache_size);
+            }
+
+            // Promoted variables live in registers of their own
+            LocalFetch(_) => {
+                result.push(inst.clone());
+                self.push_cache(state, cache_size);
+            }
+
+            LocalStore(_) => {
+                result.push(inst.clone());
+                self.pop_cache(state);
             }
 
             // Stack operations with caching
@@ -164,7 +359,7 @@ impl StackCacheOptimizer {
                     result.push(CachedDup {
                         depth: state.cached_depth,
                     });
-                    self.push_cache(state);
+                    self.push_cache(state, cache_size);
                 } else {
                     result.push(Dup);
                     state.total_depth += 1;
@@ -172,10 +367,10 @@ impl StackCacheOptimizer {
             }
 
             Drop => {
+                result.push(Drop);
                 if state.cached_depth >= 1 {
                     self.pop_cache(state);
                 } else {
-                    result.push(Drop);
                     state.total_depth -= 1;
                 }
             }
@@ -198,7 +393,7 @@ impl StackCacheOptimizer {
                     result.push(CachedOver {
                         depth: state.cached_depth,
                     });
-                    self.push_cache(state);
+                    self.push_cache(state, cache_size);
                 } else {
                     self.flush_cache(&mut result, state);
                     result.push(Over);
@@ -208,7 +403,7 @@ impl StackCacheOptimizer {
 
             // Arithmetic: operate on cached values
             Add | Sub | Mul | Div | Mod | And | Or | Xor | Eq | Ne | Lt | Le | Gt | Ge | Shl
-            | Shr => {
+            | Shr | ULt | UGt | UDiv | UMod | UMul | UMulHigh | Rotl | Rotr => {
                 if state.cached_depth >= 2 {
                     result.push(inst.clone());
                     self.pop_cache(state); // Binary op: consume 2, produce 1
@@ -220,7 +415,7 @@ impl StackCacheOptimizer {
             }
 
             // Unary operations
-            Neg | Abs | Not | ZeroEq | ZeroLt | ZeroGt => {
+            Neg | Abs | Not | ZeroEq | ZeroLt | ZeroGt | Popcount | Ctz | Clz => {
                 if state.cached_depth >= 1 {
                     result.push(inst.clone());
                     // Depth unchanged
@@ -231,7 +426,7 @@ impl StackCacheOptimizer {
             }
 
             // Superinstructions
-            DupAdd | DupMul => {
+            DupAdd | DupMul | ExtractBits { .. } | Lookup(_) => {
                 if state.cached_depth >= 1 {
                     result.push(inst.clone());
                     // Net effect: consume 1, produce 1 (depth unchanged)
@@ -242,13 +437,13 @@ impl StackCacheOptimizer {
             }
 
             // Control flow: flush cache
-            Call(_) | Return | Branch(_) | BranchIf(_) | BranchIfNot(_) => {
+            Call(_) | Execute | Return | Branch(_) | BranchIf(_) | BranchIfNot(_) => {
                 self.flush_cache(&mut result, state);
                 result.push(inst.clone());
             }
 
             // Memory operations: flush cache for safety
-            Store | Store8 | Load | Load8 => {
+            Store | Store8 | Store16 | Store32 | Load | Load8 | Load16 | Load32 => {
                 self.flush_cache(&mut result, state);
                 result.push(inst.clone());
                 // Update depth
@@ -287,8 +482,8 @@ impl StackCacheOptimizer {
     }
 
     /// Push an item to the cache
-    fn push_cache(&self, state: &mut CacheState) {
-        if state.cached_depth < self.cache_size {
+    fn push_cache(&self, state: &mut CacheState, cache_size: u8) {
+        if state.cached_depth < cache_size {
             state.cached_depth += 1;
         }
         state.total_depth += 1;
@@ -322,7 +517,7 @@ mod tests {
         assert_eq!(state.total_depth, 0);
 
         let optimizer = StackCacheOptimizer::new(3);
-        optimizer.push_cache(&mut state);
+        optimizer.push_cache(&mut state, 3);
         assert_eq!(state.cached_depth, 1);
         assert_eq!(state.total_depth, 1);
 
@@ -355,6 +550,40 @@ mod tests {
         assert!(has_cached_dup);
     }
 
+    #[test]
+    fn test_cache_depth_for_arch() {
+        assert_eq!(CacheDepth::for_arch("x86_64"), CacheDepth::Auto { registers: 6 });
+        assert_eq!(CacheDepth::for_arch("aarch64"), CacheDepth::Auto { registers: MAX_CACHE_SIZE });
+        assert_eq!(CacheDepth::for_arch("wasm32"), CacheDepth::Auto { registers: 3 });
+    }
+
+    #[test]
+    fn test_auto_depth_follows_stack_profile() {
+        let optimizer = StackCacheOptimizer::with_depth(CacheDepth::Auto { registers: 8 });
+
+        // Two live items: a third register would never be used
+        let word = WordDef::new("sq5".to_string(), ForthIR::parse("5 dup *").unwrap().main);
+        let (_, profile) = optimizer.optimize_word_profiled(&word).unwrap();
+        assert_eq!(profile.depth, 2);
+        assert_eq!(profile.spilled_items, 1);
+
+        // Five live items before the adds consume them
+        let word = WordDef::new("sum5".to_string(), ForthIR::parse("1 2 3 4 5 + + + +").unwrap().main);
+        let (_, profile) = optimizer.optimize_word_profiled(&word).unwrap();
+        assert_eq!(profile.depth, 5);
+        assert_eq!(profile.peak_depth, 5);
+        assert_eq!(profile.cached_ops, 4);
+    }
+
+    #[test]
+    fn test_fixed_depth_is_kept() {
+        let optimizer = StackCacheOptimizer::new(3);
+        let word = WordDef::new("sum5".to_string(), ForthIR::parse("1 2 3 4 5 + + + +").unwrap().main);
+        let (_, profile) = optimizer.optimize_word_profiled(&word).unwrap();
+        assert_eq!(profile.depth, 3);
+        assert_eq!(profile.spilled_items, 3);
+    }
+
     #[test]
     fn test_flush_on_call() {
         let optimizer = StackCacheOptimizer::new(3);
@@ -37,6 +37,7 @@
 //! : square dup_mul ;  # Single superinstruction
 //! ```
 
+use crate::corpus::SuperinstructionTable;
 use crate::ir::{ForthIR, Instruction, WordDef};
 use crate::Result;
 
@@ -99,7 +100,8 @@ impl Pattern {
             // Literal matches
             (Literal(a), Literal(b)) => a == b,
 
-            _ => false,
+            // Learned patterns use any builtin instruction
+            _ => pattern == inst,
         }
     }
 }
@@ -115,6 +117,29 @@ impl SuperinstructionOptimizer {
         Self { patterns }
     }
 
+    /// Create with superinstructions learned from a corpus
+    ///
+    /// Table sequences are tried in rank order before the builtin library,
+    /// so a learned sequence wins over the builtin patterns inside it.
+    /// Sequences the library already fuses keep their builtin replacement.
+    pub fn with_table(table: &SuperinstructionTable) -> Self {
+        let builtin = Self::build_pattern_library();
+        let mut patterns: Vec<Pattern> = table
+            .entries
+            .iter()
+            .filter(|entry| !builtin.iter().any(|pattern| pattern.sequence == entry.sequence))
+            .map(|entry| {
+                Pattern::new(
+                    "learned",
+                    entry.sequence.clone(),
+                    vec![Instruction::Fused(entry.sequence.clone())],
+                )
+            })
+            .collect();
+        patterns.extend(builtin);
+        Self { patterns }
+    }
+
     /// Build comprehensive pattern library (50+ patterns)
     fn build_pattern_library() -> Vec<Pattern> {
         use Instruction::*;
@@ -282,15 +307,41 @@ impl SuperinstructionOptimizer {
     }
 
     /// Recognize patterns in a word definition
-    fn recognize_word(&self, word: &WordDef) -> WordDef {
+    pub(crate) fn recognize_word(&self, word: &WordDef) -> WordDef {
+        self.recognize_word_reported(word).0
+    }
+
+    /// Recognize patterns in a word definition, also returning the index of
+    /// each pattern fused
+    pub(crate) fn recognize_word_reported(&self, word: &WordDef) -> (WordDef, Vec<usize>) {
+        let mut fired = Vec::new();
         let mut optimized = word.clone();
-        optimized.instructions = self.recognize_sequence(&word.instructions);
+        optimized.instructions = self.recognize_sequence_into(&word.instructions, &mut fired);
         optimized.update();
-        optimized
+        (optimized, fired)
+    }
+
+    /// Name of a pattern for reports: its builtin name, or the fused
+    /// sequence for a learned one
+    pub(crate) fn pattern_name(&self, index: usize) -> String {
+        let pattern = &self.patterns[index];
+        match pattern.replacement.as_slice() {
+            [Instruction::Fused(sequence)] => {
+                let words: Vec<String> = sequence.iter().filter_map(Instruction::to_word).collect();
+                words.join(" ")
+            }
+            _ => pattern.name.to_string(),
+        }
     }
 
     /// Recognize patterns in an instruction sequence
     fn recognize_sequence(&self, instructions: &[Instruction]) -> Vec<Instruction> {
+        self.recognize_sequence_into(instructions, &mut Vec::new())
+    }
+
+    /// Recognize patterns in an instruction sequence, appending the index
+    /// of each pattern fused to `fired`
+    pub(crate) fn recognize_sequence_into(&self, instructions: &[Instruction], fired: &mut Vec<usize>) -> Vec<Instruction> {
         let mut result = Vec::with_capacity(instructions.len());
         let mut pos = 0;
 
@@ -298,9 +349,10 @@ impl SuperinstructionOptimizer {
             let mut matched = false;
 
             // Try each pattern
-            for pattern in &self.patterns {
+            for (index, pattern) in self.patterns.iter().enumerate() {
                 if pattern.matches(instructions, pos) {
                     // Pattern matched! Apply replacement
+                    fired.push(index);
                     result.extend_from_slice(&pattern.replacement);
                     pos += pattern.sequence.len();
                     matched = true;
@@ -474,4 +526,26 @@ mod tests {
 
         assert!(superinst_count >= 2); // Should find at least 2 patterns
     }
+
+    #[test]
+    fn test_learned_table() {
+        use crate::corpus::TableEntry;
+        use Instruction::*;
+
+        let table = SuperinstructionTable {
+            entries: vec![
+                TableEntry { sequence: vec![Over, Add, Swap], count: 50 },
+                TableEntry { sequence: vec![Dup, Add], count: 10 },
+            ],
+        };
+        let optimizer = SuperinstructionOptimizer::with_table(&table);
+        let ir = ForthIR::parse("over + swap dup + over +").unwrap();
+        let optimized = optimizer.recognize(&ir).unwrap();
+
+        // `dup +` keeps its builtin fusion; `over +` alone is builtin too
+        assert_eq!(
+            optimized.main,
+            vec![Fused(vec![Over, Add, Swap]), DupAdd, OverAdd]
+        );
+    }
 }
@@ -0,0 +1,49 @@
+//! Pass Timing
+//!
+//! Records when each optimizer pass ran and for how long: once per
+//! whole-program pass, and once per word for the word-local passes, which
+//! may run on several threads at once.
+//!
+//! Timing is off by default. Enable it with [`Optimizer::set_timing`], then
+//! take the timings with [`Optimizer::take_timings`] after optimizing.
+//!
+//! [`Optimizer::set_timing`]: crate::Optimizer::set_timing
+//! [`Optimizer::take_timings`]: crate::Optimizer::take_timings
+
+use std::time::{Duration, Instant};
+
+/// One run of a pass
+#[derive(Debug, Clone)]
+pub struct PassTiming {
+    pub pass: &'static str,
+    /// Word the pass rewrote, for word-local passes ([`MAIN`](crate::report::MAIN)
+    /// for the main sequence)
+    pub word: Option<String>,
+    pub start: Instant,
+    pub duration: Duration,
+    /// 0 for the calling thread, 1 + the rayon worker index otherwise
+    pub thread: usize,
+}
+
+impl PassTiming {
+    /// A run of `pass` over `word` that started at `start` and ends now
+    pub(crate) fn since(pass: &'static str, word: Option<&str>, start: Instant) -> Self {
+        PassTiming {
+            pass,
+            word: word.map(str::to_string),
+            start,
+            duration: start.elapsed(),
+            thread: worker_thread(),
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+fn worker_thread() -> usize {
+    rayon::current_thread_index().map_or(0, |index| index + 1)
+}
+
+#[cfg(not(feature = "parallel"))]
+fn worker_thread() -> usize {
+    0
+}
@@ -0,0 +1,263 @@
+//! Optimizer Tracing
+//!
+//! Captures the IR after every optimizer pass, so a change in the output
+//! can be pinned on the pass that made it. Each snapshot keeps the whole
+//! program and the per-word differences from the snapshot before it.
+//!
+//! The word-local passes run word by word (in parallel for large
+//! programs). The optimizer records each word after each of them and
+//! assembles one snapshot per pass, as if the pass had run over the whole
+//! program at once.
+//!
+//! Tracing is off by default because it clones the IR after every pass.
+//! Enable it with [`Optimizer::set_trace`], then read
+//! [`Optimizer::trace`] after optimizing.
+//!
+//! [`Optimizer::set_trace`]: crate::Optimizer::set_trace
+//! [`Optimizer::trace`]: crate::Optimizer::trace
+
+use crate::ir::{ForthIR, Instruction};
+use crate::report::MAIN;
+use std::collections::BTreeSet;
+
+/// Above this many instruction pairs, a changed word is diffed as one
+/// replacement instead of aligning its instructions
+const MAX_ALIGNMENT: usize = 1 << 20;
+
+/// A run of instructions a pass replaced in one word
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffHunk {
+    /// Index of the first removed instruction in the word before the pass
+    pub before_start: usize,
+    /// Index of the first added instruction in the word after the pass
+    pub after_start: usize,
+    pub removed: Vec<Instruction>,
+    pub added: Vec<Instruction>,
+}
+
+/// What a pass changed in one word
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordDiff {
+    /// Word name, or [`MAIN`] for the main sequence
+    pub word: String,
+    pub hunks: Vec<DiffHunk>,
+}
+
+/// The IR after one pass
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassSnapshot {
+    pub pass: &'static str,
+    pub ir: ForthIR,
+    /// Words the pass changed, in name order with the main sequence first
+    pub changes: Vec<WordDiff>,
+}
+
+impl PassSnapshot {
+    /// Whether the pass changed any instruction
+    pub fn changed(&self) -> bool {
+        !self.changes.is_empty()
+    }
+}
+
+/// The IR before optimization and after each pass of one run
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptimizerTracer {
+    input: ForthIR,
+    snapshots: Vec<PassSnapshot>,
+}
+
+impl OptimizerTracer {
+    /// Start a trace of a run on `input`
+    pub fn new(input: &ForthIR) -> Self {
+        Self { input: input.clone(), snapshots: Vec::new() }
+    }
+
+    /// Record the IR after a pass
+    pub fn record(&mut self, pass: &'static str, ir: &ForthIR) {
+        let changes = diff_ir(self.output(), ir);
+        self.snapshots.push(PassSnapshot { pass, ir: ir.clone(), changes });
+    }
+
+    /// The IR the run started from
+    pub fn input(&self) -> &ForthIR {
+        &self.input
+    }
+
+    /// The IR after the last recorded pass
+    pub fn output(&self) -> &ForthIR {
+        self.snapshots.last().map_or(&self.input, |snapshot| &snapshot.ir)
+    }
+
+    /// Snapshots in the order the passes ran
+    pub fn snapshots(&self) -> &[PassSnapshot] {
+        &self.snapshots
+    }
+
+    /// Passes that changed the IR, in order
+    pub fn changed_passes(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.snapshots.iter().filter(|snapshot| snapshot.changed()).map(|snapshot| snapshot.pass)
+    }
+
+    /// The first pass after which this trace and `other` disagree
+    ///
+    /// Returns the pass name from this trace, or `None` when both traces ran
+    /// the same passes to the same IR. A trace that stops early disagrees at
+    /// the first pass it lacks.
+    pub fn first_divergence(&self, other: &OptimizerTracer) -> Option<&'static str> {
+        if self.input != other.input {
+            return Some("input");
+        }
+        let mut theirs = other.snapshots.iter();
+        for snapshot in &self.snapshots {
+            match theirs.next() {
+                Some(their) if their.pass == snapshot.pass && their.ir == snapshot.ir => {}
+                _ => return Some(snapshot.pass),
+            }
+        }
+        theirs.next().map(|their| their.pass)
+    }
+}
+
+/// Differences between two versions of a program, word by word
+///
+/// A word only one side has is diffed against an empty word.
+pub fn diff_ir(before: &ForthIR, after: &ForthIR) -> Vec<WordDiff> {
+    let mut changes = Vec::new();
+    push_diff(&mut changes, MAIN, &before.main, &after.main);
+
+    let names: BTreeSet<&String> = before.words.keys().chain(after.words.keys()).collect();
+    for name in names {
+        push_diff(&mut changes, name, word_instructions(before, name), word_instructions(after, name));
+    }
+    changes
+}
+
+fn word_instructions<'a>(ir: &'a ForthIR, name: &str) -> &'a [Instruction] {
+    ir.words.get(name).map_or(&[], |word| &word.instructions)
+}
+
+fn push_diff(changes: &mut Vec<WordDiff>, word: &str, before: &[Instruction], after: &[Instruction]) {
+    let hunks = diff_instructions(before, after);
+    if !hunks.is_empty() {
+        changes.push(WordDiff { word: word.to_string(), hunks });
+    }
+}
+
+/// Hunks turning `before` into `after`, along a longest common subsequence
+pub fn diff_instructions(before: &[Instruction], after: &[Instruction]) -> Vec<DiffHunk> {
+    let prefix = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &before[prefix..before.len() - suffix];
+    let new = &after[prefix..after.len() - suffix];
+    if old.is_empty() && new.is_empty() {
+        return Vec::new();
+    }
+    if old.len().saturating_mul(new.len()) > MAX_ALIGNMENT {
+        return vec![DiffHunk {
+            before_start: prefix,
+            after_start: prefix,
+            removed: old.to_vec(),
+            added: new.to_vec(),
+        }];
+    }
+
+    // lengths[i][j]: longest common subsequence of old[i..] and new[j..]
+    let width = new.len() + 1;
+    let mut lengths = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i * width + j] = if old[i] == new[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut hunks = Vec::new();
+    let mut open: Option<DiffHunk> = None;
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            hunks.extend(open.take());
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let hunk = open.get_or_insert_with(|| DiffHunk {
+            before_start: prefix + i,
+            after_start: prefix + j,
+            removed: Vec::new(),
+            added: Vec::new(),
+        });
+        if j == new.len() || (i < old.len() && lengths[(i + 1) * width + j] >= lengths[i * width + j + 1]) {
+            hunk.removed.push(old[i].clone());
+            i += 1;
+        } else {
+            hunk.added.push(new[j].clone());
+            j += 1;
+        }
+    }
+    hunks.extend(open);
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::WordDef;
+    use Instruction::*;
+
+    #[test]
+    fn test_diff_instructions() {
+        let before = vec![Literal(2), Literal(3), Add, Dup, Mul];
+        let after = vec![Literal(5), Dup, Mul];
+
+        assert_eq!(
+            diff_instructions(&before, &after),
+            vec![DiffHunk {
+                before_start: 0,
+                after_start: 0,
+                removed: vec![Literal(2), Literal(3), Add],
+                added: vec![Literal(5)],
+            }]
+        );
+        assert!(diff_instructions(&before, &before).is_empty());
+
+        let hunks = diff_instructions(&[Dup, Drop, Literal(1), Swap], &[Literal(1), Swap, Over]);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!((hunks[0].before_start, hunks[0].removed.len(), hunks[0].added.len()), (0, 2, 0));
+        assert_eq!((hunks[1].before_start, hunks[1].after_start, hunks[1].added.clone()), (4, 2, vec![Over]));
+    }
+
+    #[test]
+    fn test_trace_records_changes() {
+        let mut input = ForthIR::new();
+        input.main = vec![Literal(2), Literal(3), Add];
+        input.add_word(WordDef::new("sq".to_string(), vec![Dup, Mul, Return]));
+
+        let mut tracer = OptimizerTracer::new(&input);
+        let mut folded = input.clone();
+        folded.main = vec![Literal(5)];
+        tracer.record("constant_fold", &folded);
+        tracer.record("peephole", &folded);
+
+        assert_eq!(tracer.output(), &folded);
+        assert_eq!(tracer.changed_passes().collect::<Vec<_>>(), vec!["constant_fold"]);
+        assert_eq!(tracer.snapshots()[0].changes[0].word, MAIN);
+
+        // A trace whose folding went wrong diverges at that pass
+        let mut other = OptimizerTracer::new(&input);
+        let mut wrong = input.clone();
+        wrong.main = vec![Literal(6)];
+        other.record("constant_fold", &wrong);
+        other.record("peephole", &wrong);
+        assert_eq!(tracer.first_divergence(&other), Some("constant_fold"));
+        assert_eq!(tracer.first_divergence(&tracer.clone()), None);
+    }
+}
@@ -50,7 +50,7 @@
 //! 3.14 SQUARE     → SQUARE-FLOAT
 //! ```
 
-use crate::ir::{ForthIR, Instruction, WordDef, StackEffect};
+use crate::ir::{ForthIR, Instruction, Linkage, Symbol, WordAttributes, WordDef, StackEffect};
 use crate::{OptimizerError, Result};
 use rustc_hash::{FxHashMap, FxHashSet};
 use std::collections::HashMap;
@@ -154,14 +154,14 @@ impl std::fmt::Display for TypeSignature {
 /// Usage profile for a word
 #[derive(Debug, Clone)]
 struct UsageProfile {
-    word_name: String,
+    word_name: Symbol,
     signatures: FxHashSet<TypeSignature>,
     call_count: usize,
     is_polymorphic: bool,
 }
 
 impl UsageProfile {
-    fn new(word_name: String) -> Self {
+    fn new(word_name: Symbol) -> Self {
         Self {
             word_name,
             signatures: FxHashSet::default(),
@@ -276,10 +276,10 @@ impl std::fmt::Display for SpecializationStats {
 /// Main type specialization engine
 pub struct TypeSpecializer {
     /// Usage profiles for all words
-    profiles: FxHashMap<String, UsageProfile>,
+    profiles: FxHashMap<Symbol, UsageProfile>,
 
     /// Generated specialized versions
-    specializations: FxHashMap<String, Vec<(TypeSignature, WordDef)>>,
+    specializations: FxHashMap<Symbol, Vec<(TypeSignature, WordDef)>>,
 
     /// Type information at call sites
     call_site_types: FxHashMap<usize, TypeSignature>,
@@ -308,9 +308,10 @@ impl TypeSpecializer {
             self.stats.words_analyzed += 1;
 
             if let Some(sig) = type_info.word_signatures.get(name) {
+                let name = Symbol::from(name);
                 let profile = self.profiles
-                    .entry(name.clone())
-                    .or_insert_with(|| UsageProfile::new(name.clone()));
+                    .entry(name)
+                    .or_insert_with(|| UsageProfile::new(name));
 
                 profile.add_signature(sig.clone());
             }
@@ -332,8 +333,8 @@ impl TypeSpecializer {
             if let Instruction::Call(name) = inst {
                 if let Some(sig) = type_info.call_site_signatures.get(&idx) {
                     let profile = self.profiles
-                        .entry(name.clone())
-                        .or_insert_with(|| UsageProfile::new(name.clone()));
+                        .entry(*name)
+                        .or_insert_with(|| UsageProfile::new(*name));
 
                     profile.add_signature(sig.clone());
                     self.call_site_types.insert(idx, sig.clone());
@@ -362,7 +363,7 @@ impl TypeSpecializer {
                 let specialized = self.specialize_word(original_word, signature)?;
 
                 self.specializations
-                    .entry(name.clone())
+                    .entry(*name)
                     .or_insert_with(Vec::new)
                     .push((signature.clone(), specialized.clone()));
 
@@ -413,6 +414,9 @@ impl TypeSpecializer {
             stack_effect: word.stack_effect.clone(),
             is_inline: word.is_inline,
             cost: word.cost,
+            // Only the optimizer knows the copy's name
+            attributes: WordAttributes { export: false, ..word.attributes },
+            linkage: Linkage::Internal,
         })
     }
 
@@ -473,8 +477,11 @@ impl TypeSpecializer {
             }
 
             // === Bitwise Operations ===
-            // These are integer-only and don't need specialization
-            Instruction::And | Instruction::Or | Instruction::Xor | Instruction::Not | Instruction::Shl | Instruction::Shr => {
+            // These, like the unsigned operations, are integer-only and don't need specialization
+            Instruction::And | Instruction::Or | Instruction::Xor | Instruction::Not | Instruction::Shl | Instruction::Shr
+            | Instruction::ULt | Instruction::UGt | Instruction::UDiv | Instruction::UMod
+            | Instruction::UMul | Instruction::UMulHigh
+            | Instruction::Popcount | Instruction::Ctz | Instruction::Clz | Instruction::Rotl | Instruction::Rotr => {
                 Ok(inst.clone())
             }
 
@@ -500,7 +507,9 @@ impl TypeSpecializer {
                 }
             }
 
-            Instruction::Load8 | Instruction::Store8 => {
+            Instruction::Load8 | Instruction::Store8
+            | Instruction::Load16 | Instruction::Store16
+            | Instruction::Load32 | Instruction::Store32 => {
                 Ok(inst.clone())
             }
 
@@ -691,8 +700,7 @@ impl TypeSpecializer {
                         // Find matching specialization
                         for (sig, specialized) in specializations {
                             if sig == signature {
-                                let new_name = specialized.name.clone();
-                                *inst = Instruction::Call(new_name);
+                                *inst = Instruction::Call(Symbol::from(&specialized.name));
                                 self.stats.call_sites_rewritten += 1;
                                 break;
                             }
@@ -823,7 +831,7 @@ mod tests {
 
     #[test]
     fn test_usage_profile() {
-        let mut profile = UsageProfile::new("test".to_string());
+        let mut profile = UsageProfile::new("test".into());
 
         profile.add_signature(TypeSignature::new(
             vec![ConcreteType::Int],
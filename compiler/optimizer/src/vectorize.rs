@@ -0,0 +1,213 @@
+//! Vectorization of Simple Array Loops
+//!
+//! Replaces counted loops that walk cell arrays one element at a time with
+//! vector kernels. The C code generator lowers them to SIMD instructions
+//! (AVX2 on x86-64, NEON on AArch64, scalar code elsewhere), and the IR
+//! interpreter and the threaded tier run each in a single step.
+//!
+//! Runs after induction variable optimization, which turns `i cells`
+//! address computations into pointers stepping one cell per iteration.
+//! Three loop shapes are recognized over such pointers:
+//!
+//! ```forth
+//! \ Reduction: acc op= a[i]
+//! 0 do over i cells + @ + loop
+//!
+//! \ Map with a constant: a[i] = a[i] op c
+//! 0 do dup i cells + dup @ 3 * swap ! loop
+//! 0 do 5 over i cells + +! loop
+//!
+//! \ Element-wise: b[i] += a[i]
+//! 0 do over i cells + @ over i cells + +! loop
+//! ```
+//!
+//! Like `?DO`, the kernels do nothing when `start` is not below `limit`.
+
+use crate::ir::{CountedLoop, ForthIR, Instruction, VectorOp, WordDef};
+use crate::Result;
+
+/// Bytes per cell, the pointer step of a stride-1 loop
+const CELL_SIZE: i64 = 8;
+
+/// A pointer induction variable: array at a stack depth, stepping one cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pointer {
+    slot: u8,
+    array: u8,
+}
+
+/// Loop vectorizer
+pub struct Vectorizer;
+
+impl Vectorizer {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Vectorize loops in IR
+    pub fn vectorize(&self, ir: &ForthIR) -> Result<ForthIR> {
+        let mut optimized = ir.clone();
+
+        // Vectorize main sequence
+        optimized.main = self.vectorize_sequence(&ir.main);
+
+        // Vectorize each word
+        for (name, word) in ir.words.iter() {
+            let optimized_word = self.vectorize_word(word);
+            optimized.words.insert(name.clone(), optimized_word);
+        }
+
+        Ok(optimized)
+    }
+
+    /// Vectorize loops in a word definition
+    pub(crate) fn vectorize_word(&self, word: &WordDef) -> WordDef {
+        let mut optimized = word.clone();
+        optimized.instructions = self.vectorize_sequence(&word.instructions);
+        optimized.update();
+        optimized
+    }
+
+    /// Vectorize loops in an instruction sequence
+    fn vectorize_sequence(&self, instructions: &[Instruction]) -> Vec<Instruction> {
+        let mut result = Vec::with_capacity(instructions.len());
+        let mut pos = 0;
+
+        for lp in ForthIR::counted_loops(instructions) {
+            if lp.start < pos {
+                continue;
+            }
+            if let Some(kernel) = Self::kernel(instructions, &lp) {
+                result.extend_from_slice(&instructions[pos..lp.start]);
+                result.push(kernel);
+                pos = lp.end + 1;
+            }
+        }
+
+        result.extend_from_slice(&instructions[pos..]);
+        result
+    }
+
+    /// Vector kernel performing a whole loop, if it has a supported shape
+    fn kernel(instructions: &[Instruction], lp: &CountedLoop) -> Option<Instruction> {
+        if lp.step != Some(1) {
+            return None;
+        }
+
+        let pointers = Self::pointers(&instructions[lp.start + 1..lp.header])?;
+        let pointer = |inst: &Instruction| match inst {
+            Instruction::InductionVar(slot) => pointers.iter().find(|p| p.slot == *slot).map(|p| p.array),
+            _ => None,
+        };
+
+        // Each pointer must advance exactly one cell per iteration
+        let body = &instructions[lp.body()];
+        let steps = body.iter().rev().take_while(|inst| matches!(inst, Instruction::InductionStep { .. })).count();
+        let (body, steps) = body.split_at(body.len() - steps);
+        if steps.len() != pointers.len()
+            || !steps.iter().all(|step| matches!(step, Instruction::InductionStep { delta: CELL_SIZE, .. }))
+        {
+            return None;
+        }
+
+        match body {
+            // acc op= a[i]
+            [ptr, Instruction::Load, op] => Some(Instruction::VectorReduce {
+                op: VectorOp::from_instruction(op)?,
+                array: pointer(ptr)?,
+            }),
+
+            // a[i] = a[i] op c
+            [ptr, Instruction::Dup, Instruction::Load, Instruction::Literal(operand), op, Instruction::Swap, Instruction::Store] => {
+                Some(Instruction::VectorMap {
+                    op: VectorOp::from_instruction(op)?,
+                    operand: *operand,
+                    array: pointer(ptr)?,
+                })
+            }
+
+            // a[i] += c
+            [Instruction::Literal(operand), ptr, Instruction::Call(store)] if store == "+!" => {
+                Some(Instruction::VectorMap { op: VectorOp::Add, operand: *operand, array: pointer(ptr)? })
+            }
+
+            // b[i] += a[i]
+            [src, Instruction::Load, dest, Instruction::Call(store)] if store == "+!" => {
+                Some(Instruction::VectorZip { op: VectorOp::Add, dest: pointer(dest)?, src: pointer(src)? })
+            }
+
+            _ => None,
+        }
+    }
+
+    /// Pointer induction variables set up between `(do)` and the header
+    fn pointers(setup: &[Instruction]) -> Option<Vec<Pointer>> {
+        setup
+            .chunks(2)
+            .map(|pair| match pair {
+                [base, Instruction::InductionInit { slot, scale: CELL_SIZE }] => {
+                    let array = match base {
+                        Instruction::Dup => 0,
+                        Instruction::Over => 1,
+                        _ => return None,
+                    };
+                    Some(Pointer { slot: *slot, array })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Default for Vectorizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::induction::InductionVariableOptimizer;
+
+    /// Lower `body` inside `(do) ... (loop)` and introduce induction variables
+    fn induced_loop(body: &str) -> Vec<Instruction> {
+        let mut instructions = vec![Instruction::Call("(do)".into()), Instruction::Label("L0".to_string())];
+        instructions.extend(ForthIR::parse(body).unwrap().main);
+        instructions.extend([Instruction::Call("(loop)".into()), Instruction::BranchIfNot(0)]);
+
+        let word = WordDef::new("test".to_string(), instructions);
+        InductionVariableOptimizer::new().optimize_word(&word).instructions
+    }
+
+    #[test]
+    fn test_sum_becomes_reduction() {
+        let result = Vectorizer::new().vectorize_sequence(&induced_loop("over i cells + @ +"));
+        assert_eq!(result, vec![Instruction::VectorReduce { op: VectorOp::Add, array: 1 }]);
+    }
+
+    #[test]
+    fn test_scale_becomes_map() {
+        let result = Vectorizer::new().vectorize_sequence(&induced_loop("dup i cells + dup @ 3 * swap !"));
+        assert_eq!(result, vec![Instruction::VectorMap { op: VectorOp::Mul, operand: 3, array: 0 }]);
+
+        let result = Vectorizer::new().vectorize_sequence(&induced_loop("5 over i cells + +!"));
+        assert_eq!(result, vec![Instruction::VectorMap { op: VectorOp::Add, operand: 5, array: 0 }]);
+    }
+
+    #[test]
+    fn test_elementwise_add_becomes_zip() {
+        let result = Vectorizer::new().vectorize_sequence(&induced_loop("over i cells + @ over i cells + +!"));
+        assert_eq!(result, vec![Instruction::VectorZip { op: VectorOp::Add, dest: 0, src: 1 }]);
+    }
+
+    #[test]
+    fn test_other_loops_are_kept() {
+        // Strided access is not a stride-1 loop
+        let instructions = induced_loop("over i 2 * cells + @ +");
+        assert_eq!(Vectorizer::new().vectorize_sequence(&instructions), instructions);
+
+        let instructions = induced_loop("over i cells + @ . ");
+        assert_eq!(Vectorizer::new().vectorize_sequence(&instructions), instructions);
+    }
+}
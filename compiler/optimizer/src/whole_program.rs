@@ -6,7 +6,10 @@
 //! - **Interprocedural constant propagation**: Propagate constants across word boundaries
 //! - **Word specialization**: Create specialized versions of words for constant arguments
 //! - **Global dead code elimination**: Remove unreachable words and code paths
+//! - **Single-call inlining**: Expand words called from one place into that place
 //! - **Call graph analysis**: Build complete call graph for optimization decisions
+//! - **Linkage**: Mark words nothing outside the program calls as internal, so
+//!   LLVM can inline them across codegen units and drop them
 //!
 //! # Performance Impact
 //!
@@ -26,9 +29,15 @@
 //! : MAIN  15 . ;  \ Inlined and constant-folded
 //! \ HELPER removed (unused after inlining)
 //! ```
-
-use crate::ir::{ForthIR, Instruction, WordDef};
-use crate::{OptimizationLevel, Result};
+//!
+//! The program is closed: only the main sequence, exported words and the
+//! `main` word are called from outside. A program with none of them is a
+//! library, and keeps every word.
+
+use crate::ir::{ForthIR, Instruction, Linkage, WordAttributes, WordDef};
+use crate::inline::push_devirtualized;
+use crate::{ConstantFolder, InlineDirective, InlineOptimizer, OptimizationLevel, Result};
+use fastforth_frontend::Arithmetic;
 use petgraph::graph::{DiGraph, NodeIndex};
 use petgraph::visit::EdgeRef;
 use petgraph::Direction;
@@ -63,7 +72,8 @@ pub struct CallGraph {
     pub graph: DiGraph<CallGraphNode, CallEdge>,
     /// Map word names to node indices
     pub name_to_node: HashMap<String, NodeIndex>,
-    /// Entry points (main sequence or exported words)
+    /// Entry points (main sequence or exported words), and words left as
+    /// written, which keep calling what they call
     pub entry_points: Vec<NodeIndex>,
 }
 
@@ -83,20 +93,29 @@ impl CallGraph {
         name_to_node.insert("__main__".to_string(), main_node);
         entry_points.push(main_node);
 
-        // Create nodes for all words
+        // Create nodes for all words; the outside world calls the exported
+        // ones and the `main` word. A program with neither those nor
+        // top-level code is a library, all of whose words can be called
+        let library = ir.main.iter().all(|inst| matches!(inst, Instruction::Comment(_)))
+            && !ir.words.values().any(is_entry_word);
         for (name, word) in &ir.words {
+            let is_entry_point = library || is_entry_word(word);
             let node = graph.add_node(CallGraphNode {
                 name: name.clone(),
                 call_count: 0,
-                is_entry_point: false,
+                is_entry_point,
             });
             name_to_node.insert(name.clone(), node);
+            if is_entry_point || word.attributes.optimize_none {
+                entry_points.push(node);
+            }
         }
 
-        // Add edges for calls in main sequence
+        // Add edges for calls in main sequence, and for the execution
+        // tokens it takes, whose words may be called
         for inst in &ir.main {
-            if let Instruction::Call(callee) = inst {
-                if let Some(&callee_node) = name_to_node.get(callee) {
+            if let Instruction::Call(callee) | Instruction::Tick(callee) = inst {
+                if let Some(&callee_node) = name_to_node.get(callee.as_str()) {
                     graph.add_edge(main_node, callee_node, CallEdge::Direct);
                 }
             }
@@ -108,10 +127,12 @@ impl CallGraph {
             let instructions = &word.instructions;
 
             for (i, inst) in instructions.iter().enumerate() {
-                if let Instruction::Call(callee_name) = inst {
-                    if let Some(&callee_node) = name_to_node.get(callee_name) {
+                if let Instruction::Call(callee_name) | Instruction::Tick(callee_name) = inst {
+                    if let Some(&callee_node) = name_to_node.get(callee_name.as_str()) {
                         // Determine edge type
-                        let edge_type = if callee_name == caller_name {
+                        let edge_type = if matches!(inst, Instruction::Tick(_)) {
+                            CallEdge::Direct
+                        } else if callee_name == caller_name {
                             CallEdge::Recursive
                         } else if i == instructions.len() - 1
                             || matches!(instructions.get(i + 1), Some(Instruction::Return))
@@ -147,6 +168,11 @@ impl CallGraph {
         }
     }
 
+    /// Whether the outside world may call `word`
+    pub fn is_entry_point(&self, word: &str) -> bool {
+        self.name_to_node.get(word).is_some_and(|&node| self.graph[node].is_entry_point)
+    }
+
     /// Find all reachable words from entry points
     pub fn find_reachable(&self) -> HashSet<NodeIndex> {
         let mut reachable = HashSet::new();
@@ -213,6 +239,32 @@ impl CallGraph {
         false
     }
 
+    /// Words `word` calls, sorted and without repeats; `__main__` stands
+    /// for the top-level code
+    pub fn callees(&self, word: &str) -> Vec<String> {
+        self.neighbors(word, Direction::Outgoing)
+    }
+
+    /// Words that call `word`, sorted and without repeats, with `__main__`
+    /// for a call from the top-level code
+    pub fn callers(&self, word: &str) -> Vec<String> {
+        self.neighbors(word, Direction::Incoming)
+    }
+
+    fn neighbors(&self, word: &str, direction: Direction) -> Vec<String> {
+        let Some(&node) = self.name_to_node.get(word) else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = self
+            .graph
+            .neighbors_directed(node, direction)
+            .map(|neighbor| self.graph[neighbor].name.clone())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
     /// Get topological order for interprocedural analysis (bottom-up)
     pub fn topological_order(&self) -> Vec<String> {
         let mut order = Vec::new();
@@ -235,8 +287,9 @@ impl CallGraph {
         for (name, word) in &ir.words {
             let effects = word.instructions.iter().any(|inst| {
                 match inst {
-                    Instruction::Store | Instruction::Store8 | Instruction::ToR => true,
-                    Instruction::Call(c) => has_side_effects.get(c).map_or(true, |&e| e),
+                    inst if inst.is_store() => true,
+                    Instruction::ToR | Instruction::Execute => true,
+                    Instruction::Call(c) => has_side_effects.get(c.as_str()).map_or(true, |&e| e),
                     _ => false,
                 }
             });
@@ -332,24 +385,6 @@ impl CallGraph {
     }
 }
 
-/// Constant value propagated across word boundaries
-#[derive(Debug, Clone, PartialEq)]
-pub enum ConstantValue {
-    /// Known integer constant
-    Integer(i64),
-    /// Known float constant
-    Float(f64),
-    /// Unknown or non-constant value
-    Unknown,
-}
-
-/// Information about constant arguments to a word
-#[derive(Debug, Clone)]
-pub struct ConstantArguments {
-    /// Constant values for each argument position (stack depth)
-    pub args: Vec<ConstantValue>,
-}
-
 /// Whole-program optimizer
 pub struct WholeProgramOptimizer {
     level: OptimizationLevel,
@@ -359,6 +394,8 @@ pub struct WholeProgramOptimizer {
     max_inline_cost: usize,
     /// Whether to inline single-call functions
     inline_single_calls: bool,
+    /// Folds the bodies of words checked for constant results
+    constant_fold: ConstantFolder,
 }
 
 impl WholeProgramOptimizer {
@@ -375,6 +412,7 @@ impl WholeProgramOptimizer {
             aggressive_specialization,
             max_inline_cost,
             inline_single_calls,
+            constant_fold: ConstantFolder::new(),
         }
     }
 
@@ -388,13 +426,20 @@ impl WholeProgramOptimizer {
         self.aggressive_specialization = enabled;
     }
 
+    /// Fold constant words' bodies as code compiled under `arithmetic`
+    /// computes them
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.constant_fold = ConstantFolder::with_arithmetic(arithmetic);
+    }
+
     /// Run complete whole-program optimization
     pub fn optimize(&self, ir: &ForthIR) -> Result<ForthIR> {
         if self.level == OptimizationLevel::None {
             return Ok(ir.clone());
         }
 
-        let mut optimized = ir.clone();
+        // Phase 0: EXECUTE of the one token the program keeps calls its word
+        let mut optimized = self.devirtualize_execute(ir);
 
         // Phase 1: Build call graph
         let call_graph = CallGraph::build(&optimized);
@@ -409,20 +454,43 @@ impl WholeProgramOptimizer {
 
         // Phase 4: Interprocedural constant propagation
         if self.level >= OptimizationLevel::Standard {
-            optimized = self.propagate_constants(&optimized, &call_graph)?;
+            optimized = self.propagate_constants(&optimized)?;
         }
 
         // Phase 5: Specialize words with constant arguments
         if self.aggressive_specialization {
+            let call_graph = CallGraph::build(&optimized);
             optimized = self.specialize_words(&optimized, &call_graph)?;
         }
 
+        // Words the earlier phases left without callers
+        let call_graph = CallGraph::build(&optimized);
+        optimized = self.eliminate_dead_words(&optimized, &call_graph)?;
+
+        // Phase 6: Internal linkage for everything the outside cannot call
+        self.assign_linkage(&mut optimized);
+
         // Verify the optimized IR
         optimized.verify()?;
 
         Ok(optimized)
     }
 
+    /// Give exported words and the `main` entry point external linkage and
+    /// every other word internal linkage, returning how many are internal
+    pub fn assign_linkage(&self, ir: &mut ForthIR) -> usize {
+        let mut internal = 0;
+        for word in ir.words.values_mut() {
+            word.linkage = if is_entry_word(word) {
+                Linkage::External
+            } else {
+                internal += 1;
+                Linkage::Internal
+            };
+        }
+        internal
+    }
+
     /// Eliminate unreachable words (global dead code)
     fn eliminate_dead_words(&self, ir: &ForthIR, call_graph: &CallGraph) -> Result<ForthIR> {
         let unreachable = call_graph.find_unreachable();
@@ -441,316 +509,170 @@ impl WholeProgramOptimizer {
         Ok(optimized)
     }
 
+    /// Call the word of every EXECUTE directly when the program takes the
+    /// execution token of one word only, so every token is that word's
+    ///
+    /// Inlining later removes the call where it can.
+    fn devirtualize_execute(&self, ir: &ForthIR) -> ForthIR {
+        let mut targets = ir.main.iter()
+            .chain(ir.words.values().flat_map(|word| &word.instructions))
+            .filter_map(|inst| match inst {
+                Instruction::Tick(word) => Some(*word),
+                _ => None,
+            });
+        let Some(only) = targets.next() else {
+            return ir.clone();
+        };
+        if targets.any(|word| word != only) {
+            return ir.clone();
+        }
+
+        let direct = |instructions: &[Instruction]| -> Vec<Instruction> {
+            instructions
+                .iter()
+                .flat_map(|inst| match inst {
+                    Instruction::Execute => vec![Instruction::Drop, Instruction::Call(only)],
+                    inst => vec![inst.clone()],
+                })
+                .collect()
+        };
+        let mut optimized = ir.clone();
+        optimized.main = direct(&ir.main);
+        for word in optimized.words.values_mut() {
+            if !word.attributes.optimize_none && word.instructions.iter().any(|inst| matches!(inst, Instruction::Execute)) {
+                word.instructions = direct(&word.instructions);
+                word.update();
+            }
+        }
+        optimized
+    }
+
     /// Inline words that are called only once
+    ///
+    /// Entry points stay, since the outside calls them too, and so do
+    /// recursive words, words that may not be inlined and words whose one
+    /// call is in a word left as written. Bodies that return early or
+    /// branch are kept out of line, as the inliner keeps them.
     fn inline_single_call_words(&self, ir: &ForthIR, call_graph: &CallGraph) -> Result<ForthIR> {
-        let single_call_words = call_graph.find_single_call_words();
+        let mut single_call_words = call_graph.find_single_call_words();
+        single_call_words.sort();
         let mut optimized = ir.clone();
 
         for word_name in single_call_words {
-            if let Some(word) = ir.get_word(&word_name) {
-                // Only inline if small enough
-                if word.cost <= self.max_inline_cost {
-                    // Inline in main
-                    optimized.main = self.inline_in_sequence(&optimized.main, &word_name, word);
-
-                    // Inline in other words - collect names first to avoid borrow issues
-                    let other_word_names: Vec<String> =
-                        optimized.words.keys().filter(|n| *n != &word_name).cloned().collect();
-
-                    for other_name in other_word_names {
-                        if let Some(other_word) = optimized.words.get(&other_name).cloned() {
-                            let mut updated = other_word;
-                            updated.instructions =
-                                self.inline_in_sequence(&updated.instructions, &word_name, word);
-                            updated.update();
-                            optimized.words.insert(other_name, updated);
-                        }
-                    }
+            // The word as earlier inlining left it
+            let Some(word) = optimized.get_word(&word_name).cloned() else {
+                continue;
+            };
+            if call_graph.is_entry_point(&word_name)
+                || word.cost > self.max_inline_cost
+                || word.inline_directive() == InlineDirective::NeverInline
+                || call_graph.is_recursive(&word_name)
+            {
+                continue;
+            }
+            let Some(body) = InlineOptimizer::inline_body(&word) else {
+                continue;
+            };
 
-                    // Remove the inlined word
-                    optimized.words.remove(&word_name);
+            // The caller, as earlier inlining may have moved the call
+            let caller = if optimized.main.iter().any(|inst| calls(inst, &word_name)) {
+                None
+            } else {
+                match optimized.words.values().find(|other| other.instructions.iter().any(|inst| calls(inst, &word_name))) {
+                    Some(caller) if !caller.attributes.optimize_none => Some(caller.name.clone()),
+                    _ => continue,
                 }
+            };
+            let sequence = match &caller {
+                Some(name) => &optimized.words[name].instructions,
+                None => &optimized.main,
+            };
+            let Some(inlined) = self.inline_in_sequence(sequence, &word_name, body) else {
+                continue;
+            };
+
+            match caller {
+                Some(name) => {
+                    let caller = optimized.words.get_mut(&name).expect("caller was found above");
+                    caller.instructions = inlined;
+                    caller.update();
+                }
+                None => optimized.main = inlined,
             }
+            optimized.words.remove(&word_name);
         }
 
         Ok(optimized)
     }
 
-    /// Inline a word in an instruction sequence
-    fn inline_in_sequence(
-        &self,
-        instructions: &[Instruction],
-        word_name: &str,
-        word_def: &WordDef,
-    ) -> Vec<Instruction> {
-        let mut result = Vec::with_capacity(instructions.len());
+    /// Replace calls to `word_name` with `body`, the word's locals above
+    /// the caller's; `None` if they do not fit
+    fn inline_in_sequence(&self, instructions: &[Instruction], word_name: &str, body: &[Instruction]) -> Option<Vec<Instruction>> {
+        let body = Instruction::shift_locals(body, Instruction::free_local_slot(instructions))?;
+        let mut result = Vec::with_capacity(instructions.len() + body.len());
 
         for inst in instructions {
-            if let Instruction::Call(name) = inst {
-                if name == word_name {
-                    // Replace call with word body
-                    result.extend_from_slice(&word_def.instructions);
-                    continue;
+            if calls(inst, word_name) {
+                for inst in &body {
+                    push_devirtualized(&mut result, inst.clone());
                 }
+            } else {
+                push_devirtualized(&mut result, inst.clone());
             }
-            result.push(inst.clone());
         }
 
-        result
+        Some(result)
     }
 
     /// Propagate constants across word boundaries
-    fn propagate_constants(&self, ir: &ForthIR, call_graph: &CallGraph) -> Result<ForthIR> {
-        let mut optimized = ir.clone();
-        let topo_order = call_graph.topological_order();
-
-        // Analyze each word in topological order (bottom-up)
-        let mut constant_info: HashMap<String, ConstantArguments> = HashMap::new();
-
-        for word_name in &topo_order {
-            if let Some(word) = ir.get_word(word_name) {
-                // Analyze constant arguments this word expects
-                let const_args = self.analyze_constant_args(word, &constant_info);
-                constant_info.insert(word_name.clone(), const_args);
-            }
-        }
-
-        // Apply constant propagation
-        optimized = self.apply_constant_propagation(&optimized, &constant_info)?;
-
-        Ok(optimized)
-    }
-
-    /// Analyze which arguments to a word could be constants
-    fn analyze_constant_args(
-        &self,
-        word: &WordDef,
-        constant_info: &HashMap<String, ConstantArguments>,
-    ) -> ConstantArguments {
-        // Improved analysis: dataflow-based constant argument detection
-        let mut args = Vec::new();
-        let mut stack = Vec::new();
-
-        // Forward dataflow analysis through word instructions
-        for inst in &word.instructions {
-            match inst {
-                Instruction::Literal(n) => {
-                    stack.push(ConstantValue::Integer(*n));
-                }
-                Instruction::FloatLiteral(f) => {
-                    stack.push(ConstantValue::Float(*f));
-                }
-                Instruction::Dup => {
-                    if let Some(val) = stack.last() {
-                        stack.push(val.clone());
-                    } else {
-                        stack.push(ConstantValue::Unknown);
-                    }
-                }
-                Instruction::Drop => {
-                    stack.pop();
-                }
-                Instruction::Swap => {
-                    if stack.len() >= 2 {
-                        let len = stack.len();
-                        stack.swap(len - 1, len - 2);
-                    }
-                }
-                Instruction::Call(name) => {
-                    // Check if the called word's output is constant
-                    if let Some(const_args) = constant_info.get(name) {
-                        // Invalidate stack for now (conservative)
-                        stack.clear();
-                    } else {
-                        stack.clear();
-                    }
+    ///
+    /// A word whose body folds to literals alone, such as `: CELLS-PER-ROW
+    /// 8 ;` or one computed from other such words, pushes the same values
+    /// wherever it is called, so its calls are replaced with the literals.
+    /// Words that may not be inlined are still called.
+    fn propagate_constants(&self, ir: &ForthIR) -> Result<ForthIR> {
+        let mut constants: HashMap<String, Vec<Instruction>> = HashMap::new();
+        let mut names: Vec<&String> = ir.words.keys().collect();
+        names.sort();
+
+        // Words found constant make their callers' bodies foldable, so
+        // look again until nothing changes
+        loop {
+            let mut found = false;
+            for name in &names {
+                let word = &ir.words[*name];
+                if constants.contains_key(*name) || word.inline_directive() == InlineDirective::NeverInline {
+                    continue;
                 }
-                _ => {
-                    let effect = inst.stack_effect();
-                    for _ in 0..effect.consumed {
-                        stack.pop();
-                    }
-                    for _ in 0..effect.produced {
-                        stack.push(ConstantValue::Unknown);
-                    }
+                let Some(body) = InlineOptimizer::inline_body(word) else {
+                    continue;
+                };
+                let body = replace_constant_calls(body, &constants);
+                let folded = self.constant_fold.fold_sequence(&body)?;
+                if folded.iter().all(|inst| matches!(inst, Instruction::Literal(_))) {
+                    constants.insert((*name).clone(), folded);
+                    found = true;
                 }
             }
+            if !found {
+                break;
+            }
         }
 
-        ConstantArguments { args }
-    }
-
-    /// Apply constant propagation transformations
-    fn apply_constant_propagation(
-        &self,
-        ir: &ForthIR,
-        constant_info: &HashMap<String, ConstantArguments>,
-    ) -> Result<ForthIR> {
-        let mut optimized = ir.clone();
-
-        // Propagate constants in main sequence
-        optimized.main = self.propagate_in_sequence(&ir.main, constant_info)?;
-
-        // Propagate in each word
-        for (name, word) in &ir.words {
-            let mut opt_word = word.clone();
-            opt_word.instructions = self.propagate_in_sequence(&word.instructions, constant_info)?;
-            opt_word.update();
-            optimized.words.insert(name.clone(), opt_word);
+        if constants.is_empty() {
+            return Ok(ir.clone());
         }
 
-        Ok(optimized)
-    }
-
-    /// Propagate constants in an instruction sequence
-    fn propagate_in_sequence(
-        &self,
-        instructions: &[Instruction],
-        constant_info: &HashMap<String, ConstantArguments>,
-    ) -> Result<Vec<Instruction>> {
-        let mut result = Vec::with_capacity(instructions.len());
-        let mut constant_stack: Vec<ConstantValue> = Vec::new();
-
-        for inst in instructions {
-            match inst {
-                Instruction::Literal(n) => {
-                    constant_stack.push(ConstantValue::Integer(*n));
-                    result.push(inst.clone());
-                }
-
-                Instruction::FloatLiteral(f) => {
-                    constant_stack.push(ConstantValue::Float(*f));
-                    result.push(inst.clone());
-                }
-
-                Instruction::Add => {
-                    if let (Some(ConstantValue::Integer(b)), Some(ConstantValue::Integer(a))) =
-                        (constant_stack.pop(), constant_stack.pop())
-                    {
-                        let value = a + b;
-                        constant_stack.push(ConstantValue::Integer(value));
-                        result.push(Instruction::Literal(value));
-                    } else {
-                        constant_stack.push(ConstantValue::Unknown);
-                        result.push(inst.clone());
-                    }
-                }
-
-                Instruction::Sub => {
-                    if let (Some(ConstantValue::Integer(b)), Some(ConstantValue::Integer(a))) =
-                        (constant_stack.pop(), constant_stack.pop())
-                    {
-                        let value = a - b;
-                        constant_stack.push(ConstantValue::Integer(value));
-                        result.push(Instruction::Literal(value));
-                    } else {
-                        constant_stack.push(ConstantValue::Unknown);
-                        result.push(inst.clone());
-                    }
-                }
-
-                Instruction::Mul => {
-                    if let (Some(ConstantValue::Integer(b)), Some(ConstantValue::Integer(a))) =
-                        (constant_stack.pop(), constant_stack.pop())
-                    {
-                        let value = a * b;
-                        constant_stack.push(ConstantValue::Integer(value));
-                        result.push(Instruction::Literal(value));
-                    } else {
-                        constant_stack.push(ConstantValue::Unknown);
-                        result.push(inst.clone());
-                    }
-                }
-
-                Instruction::Div => {
-                    if let (Some(ConstantValue::Integer(b)), Some(ConstantValue::Integer(a))) =
-                        (constant_stack.pop(), constant_stack.pop())
-                    {
-                        if b != 0 {
-                            let value = a / b;
-                            constant_stack.push(ConstantValue::Integer(value));
-                            result.push(Instruction::Literal(value));
-                        } else {
-                            constant_stack.push(ConstantValue::Unknown);
-                            result.push(inst.clone());
-                        }
-                    } else {
-                        constant_stack.push(ConstantValue::Unknown);
-                        result.push(inst.clone());
-                    }
-                }
-
-                Instruction::Mod => {
-                    if let (Some(ConstantValue::Integer(b)), Some(ConstantValue::Integer(a))) =
-                        (constant_stack.pop(), constant_stack.pop())
-                    {
-                        if b != 0 {
-                            let value = a % b;
-                            constant_stack.push(ConstantValue::Integer(value));
-                            result.push(Instruction::Literal(value));
-                        } else {
-                            constant_stack.push(ConstantValue::Unknown);
-                            result.push(inst.clone());
-                        }
-                    } else {
-                        constant_stack.push(ConstantValue::Unknown);
-                        result.push(inst.clone());
-                    }
-                }
-
-                Instruction::Neg => {
-                    if let Some(ConstantValue::Integer(a)) = constant_stack.pop() {
-                        constant_stack.push(ConstantValue::Integer(-a));
-                        result.push(Instruction::Literal(-a));
-                    } else {
-                        constant_stack.push(ConstantValue::Unknown);
-                        result.push(inst.clone());
-                    }
-                }
-
-                Instruction::Dup => {
-                    if let Some(val) = constant_stack.last() {
-                        constant_stack.push(val.clone());
-                    } else {
-                        constant_stack.push(ConstantValue::Unknown);
-                    }
-                    result.push(inst.clone());
-                }
-
-                Instruction::Drop => {
-                    constant_stack.pop();
-                    result.push(inst.clone());
-                }
-
-                Instruction::Swap => {
-                    if constant_stack.len() >= 2 {
-                        let len = constant_stack.len();
-                        constant_stack.swap(len - 1, len - 2);
-                    }
-                    result.push(inst.clone());
-                }
-
-                Instruction::Call(name) => {
-                    // Invalidate constant tracking across calls (conservative approach)
-                    constant_stack.clear();
-                    result.push(inst.clone());
-                }
-
-                _ => {
-                    // Update stack based on effect
-                    let effect = inst.stack_effect();
-                    for _ in 0..effect.consumed {
-                        constant_stack.pop();
-                    }
-                    for _ in 0..effect.produced {
-                        constant_stack.push(ConstantValue::Unknown);
-                    }
-                    result.push(inst.clone());
-                }
+        let mut optimized = ir.clone();
+        optimized.main = replace_constant_calls(&ir.main, &constants);
+        for word in optimized.words.values_mut() {
+            if !word.attributes.optimize_none {
+                word.instructions = replace_constant_calls(&word.instructions, &constants);
+                word.update();
             }
         }
 
-        Ok(result)
+        Ok(optimized)
     }
 
     /// Specialize words that are always called with constant arguments
@@ -762,9 +684,12 @@ impl WholeProgramOptimizer {
 
         // Create specialized versions
         for (word_name, constant_arg) in specializations {
+            let specialized_name = format!("{}__specialized_{}", word_name, constant_arg);
+            if optimized.words.contains_key(&specialized_name) {
+                continue;
+            }
             if let Some(word) = optimized.get_word(&word_name) {
                 let specialized = self.create_specialized_word(word, constant_arg)?;
-                let specialized_name = format!("{}__specialized_{}", word_name, constant_arg);
 
                 // Add specialized version
                 optimized.words.insert(specialized_name.clone(), specialized);
@@ -791,9 +716,12 @@ impl WholeProgramOptimizer {
         let mut opportunities = Vec::new();
 
         // Look for patterns: N WORD where N is always the same constant
-        for (name, word) in &ir.words {
-            if call_graph.is_recursive(name) {
-                continue; // Skip recursive words
+        let mut words: Vec<&WordDef> = ir.words.values().collect();
+        words.sort_by(|a, b| a.name.cmp(&b.name));
+        for word in words {
+            let name = &word.name;
+            if call_graph.is_recursive(name) || word.inline_directive() == InlineDirective::NeverInline {
+                continue; // Skip recursive words and words to be called as written
             }
 
             // Check if this word is small enough to specialize
@@ -838,6 +766,8 @@ impl WholeProgramOptimizer {
         let specialized_name = format!("{}__specialized_{}", word.name, constant);
         let mut specialized = WordDef::new(specialized_name, specialized_instructions);
         specialized.is_inline = word.cost < 10; // Inline small specialized words
+        // Only the original is called from outside
+        specialized.attributes = WordAttributes { export: false, ..word.attributes };
 
         Ok(specialized)
     }
@@ -860,8 +790,8 @@ impl WholeProgramOptimizer {
             constant,
         );
 
-        // Replace in each word
-        for (name, word) in &ir.words {
+        // Replace in each word but those left as written
+        for (name, word) in ir.words.iter().filter(|(_, word)| !word.attributes.optimize_none) {
             let mut opt_word = word.clone();
             opt_word.instructions = self.replace_in_sequence(
                 &word.instructions,
@@ -895,7 +825,7 @@ impl WholeProgramOptimizer {
                 {
                     if *n == constant && name == original_name {
                         // Replace with specialized call (without literal)
-                        result.push(Instruction::Call(specialized_name.to_string()));
+                        result.push(Instruction::Call(specialized_name.into()));
                         i += 2;
                         continue;
                     }
@@ -925,6 +855,7 @@ impl WholeProgramOptimizer {
             words_after,
             words_eliminated,
             unreachable_words: unreachable_words.len(),
+            internal_words: after.words.values().filter(|word| word.linkage == Linkage::Internal).count(),
             instructions_before: before.instruction_count(),
             instructions_after: after.instruction_count(),
             code_size_reduction: calculate_reduction(
@@ -935,6 +866,31 @@ impl WholeProgramOptimizer {
     }
 }
 
+/// Whether `inst` calls `word`
+fn calls(inst: &Instruction, word: &str) -> bool {
+    matches!(inst, Instruction::Call(name) if name == word)
+}
+
+/// `instructions` with each call to a constant word replaced by the
+/// literals it pushes
+fn replace_constant_calls(instructions: &[Instruction], constants: &HashMap<String, Vec<Instruction>>) -> Vec<Instruction> {
+    let mut result = Vec::with_capacity(instructions.len());
+    for inst in instructions {
+        match inst {
+            Instruction::Call(name) if constants.contains_key(name.as_str()) => {
+                result.extend_from_slice(&constants[name.as_str()]);
+            }
+            _ => result.push(inst.clone()),
+        }
+    }
+    result
+}
+
+/// Words called from outside the program
+fn is_entry_word(word: &WordDef) -> bool {
+    word.attributes.export || word.name == "main"
+}
+
 fn calculate_reduction(before: usize, after: usize) -> f64 {
     if before == 0 {
         0.0
@@ -950,6 +906,7 @@ pub struct WPOStats {
     pub words_after: usize,
     pub words_eliminated: usize,
     pub unreachable_words: usize,
+    pub internal_words: usize,
     pub instructions_before: usize,
     pub instructions_after: usize,
     pub code_size_reduction: f64,
@@ -962,12 +919,14 @@ impl std::fmt::Display for WPOStats {
             "Whole-Program Optimization Stats:\n\
              Words: {} -> {} (eliminated {})\n\
              Unreachable words found: {}\n\
+             Internal words: {}\n\
              Instructions: {} -> {}\n\
              Code size reduction: {:.1}%",
             self.words_before,
             self.words_after,
             self.words_eliminated,
             self.unreachable_words,
+            self.internal_words,
             self.instructions_before,
             self.instructions_after,
             self.code_size_reduction
@@ -999,7 +958,7 @@ mod tests {
         // Main uses only helper
         ir.main = vec![
             Instruction::Literal(10),
-            Instruction::Call("helper".to_string()),
+            Instruction::Call("helper".into()),
         ];
 
         ir
@@ -1017,6 +976,22 @@ mod tests {
         assert!(!call_graph.entry_points.is_empty());
     }
 
+    #[test]
+    fn test_callers_and_callees() {
+        let mut ir = create_test_ir_with_dead_code();
+        ir.add_word(WordDef::new(
+            "twice".to_string(),
+            vec![Instruction::Call("helper".into()), Instruction::Call("helper".into())],
+        ));
+        let call_graph = CallGraph::build(&ir);
+
+        assert_eq!(call_graph.callees("twice"), vec!["helper"]);
+        assert_eq!(call_graph.callers("helper"), vec!["__main__", "twice"]);
+        assert_eq!(call_graph.callees("__main__"), vec!["helper"]);
+        assert!(call_graph.callers("unused").is_empty());
+        assert!(call_graph.callees("missing").is_empty());
+    }
+
     #[test]
     fn test_find_unreachable_words() {
         let ir = create_test_ir_with_dead_code();
@@ -1041,6 +1016,95 @@ mod tests {
         assert!(!unreachable.contains(&"helper".to_string()));
     }
 
+    #[test]
+    fn test_exports_are_external_and_kept() {
+        let mut ir = create_test_ir_with_dead_code();
+        let mut api = WordDef::new("api".to_string(), vec![Instruction::Call("helper".into())]);
+        api.attributes.export = true;
+        ir.add_word(api);
+
+        let optimized = WholeProgramOptimizer::new(OptimizationLevel::Basic).optimize(&ir).unwrap();
+
+        // Nothing in the program calls `api`, but C can
+        assert_eq!(optimized.words["api"].linkage, Linkage::External);
+        assert_eq!(optimized.words["helper"].linkage, Linkage::Internal);
+        assert!(!optimized.words.contains_key("unused"));
+
+        let mut ir = create_test_ir_with_dead_code();
+        ir.add_word(WordDef::new("main".to_string(), vec![Instruction::Call("helper".into())]));
+        let optimizer = WholeProgramOptimizer::new(OptimizationLevel::Basic);
+        assert_eq!(optimizer.assign_linkage(&mut ir), 2);
+        assert_eq!(ir.words["main"].linkage, Linkage::External);
+        assert_eq!(optimizer.get_stats(&ir, &ir).internal_words, 2);
+    }
+
+    #[test]
+    fn test_single_call_words_are_inlined() {
+        use Instruction::*;
+        let mut ir = ForthIR::new();
+        ir.add_word(WordDef::new("add5".to_string(), vec![Literal(5), Add, Return]));
+        ir.add_word(WordDef::new("f".to_string(), vec![Call("add5".into()), Literal(2), Mul, Return]));
+        ir.main = vec![Literal(1), Call("f".into()), Literal(3), Call("f".into())];
+
+        let optimized = WholeProgramOptimizer::new(OptimizationLevel::Standard).optimize(&ir).unwrap();
+
+        // Without the callee's return, which would leave the caller early
+        assert!(!optimized.words.contains_key("add5"));
+        assert_eq!(optimized.words["f"].instructions, vec![Literal(5), Add, Literal(2), Mul, Return]);
+        assert_eq!(optimized.words["f"].linkage, Linkage::Internal);
+    }
+
+    #[test]
+    fn test_execute_of_the_only_token_is_a_call() {
+        use Instruction::*;
+        let mut ir = ForthIR::new();
+        ir.add_word(WordDef::new("sq".to_string(), vec![Dup, Mul, Return]));
+        ir.add_word(WordDef::new("apply".to_string(), vec![Execute, Return]));
+        ir.main = vec![Literal(3), Tick("sq".into()), Call("apply".into()), Literal(4), Tick("sq".into()), Call("apply".into())];
+
+        let optimized = WholeProgramOptimizer::new(OptimizationLevel::Standard).optimize(&ir).unwrap();
+        assert_eq!(optimized.words["apply"].instructions, vec![Drop, Call("sq".into()), Return]);
+
+        // With a second word's token, EXECUTE may reach either
+        ir.add_word(WordDef::new("neg".to_string(), vec![Neg, Return]));
+        ir.main.extend([Tick("neg".into()), Call("apply".into())]);
+        let optimized = WholeProgramOptimizer::new(OptimizationLevel::Standard).optimize(&ir).unwrap();
+        assert_eq!(optimized.words["apply"].instructions, vec![Execute, Return]);
+    }
+
+    #[test]
+    fn test_inlining_a_token_into_its_execute_calls_the_word() {
+        use Instruction::*;
+        let mut ir = ForthIR::new();
+        ir.add_word(WordDef::new("sq".to_string(), vec![Dup, Mul, Return]));
+        ir.add_word(WordDef::new("neg".to_string(), vec![Neg, Return]));
+        ir.add_word(WordDef::new("apply".to_string(), vec![Execute, Return]));
+        ir.add_word(WordDef::new("f".to_string(), vec![Tick("sq".into()), Call("apply".into()), Return]));
+        ir.main = vec![Literal(3), Call("f".into()), Call("f".into()), Tick("neg".into()), Drop];
+
+        let optimized = WholeProgramOptimizer::new(OptimizationLevel::Standard).optimize(&ir).unwrap();
+        // apply is inlined into f, leaving ['] sq execute, a call to sq,
+        // which is inlined in turn
+        assert_eq!(optimized.words["f"].instructions, vec![Dup, Mul, Return]);
+        assert!(!optimized.words.contains_key("sq"));
+    }
+
+    #[test]
+    fn test_constant_words_become_literals() {
+        use Instruction::*;
+        let mut ir = ForthIR::new();
+        ir.add_word(WordDef::new("size".to_string(), vec![Literal(8), Return]));
+        ir.add_word(WordDef::new("bytes".to_string(), vec![Call("size".into()), Literal(8), Mul, Return]));
+        let mut kept = WordDef::new("kept".to_string(), vec![Literal(1), Return]);
+        kept.attributes.inline = InlineDirective::NeverInline;
+        ir.add_word(kept);
+        ir.main = vec![Call("bytes".into()), Call("bytes".into()), Call("size".into()), Call("kept".into()), Call("kept".into())];
+
+        let optimized = WholeProgramOptimizer::new(OptimizationLevel::Standard).optimize(&ir).unwrap();
+        assert_eq!(optimized.main, vec![Literal(64), Literal(64), Literal(8), Call("kept".into()), Call("kept".into())]);
+        assert_eq!(optimized.words.keys().collect::<Vec<_>>(), ["kept"]);
+    }
+
     #[test]
     fn test_recursive_detection() {
         let mut ir = ForthIR::new();
@@ -1052,7 +1116,7 @@ mod tests {
                 Instruction::Dup,
                 Instruction::Literal(1),
                 Instruction::Le,
-                Instruction::Call("factorial".to_string()),
+                Instruction::Call("factorial".into()),
             ],
         );
         ir.add_word(factorial);
@@ -1093,6 +1157,8 @@ mod tests {
             vec![Instruction::Literal(2), Instruction::Mul],
         );
         ir.add_word(unused);
+        // A program, not a library
+        ir.main = vec![Instruction::Literal(1)];
 
         let optimizer = WholeProgramOptimizer::new(OptimizationLevel::Basic);
         let stats = optimizer.get_stats(&ir, &ir);
@@ -1108,11 +1174,11 @@ mod tests {
 
         let a = WordDef::new(
             "a".to_string(),
-            vec![Instruction::Call("b".to_string())],
+            vec![Instruction::Call("b".into())],
         );
         let b = WordDef::new(
             "b".to_string(),
-            vec![Instruction::Call("c".to_string())],
+            vec![Instruction::Call("c".into())],
         );
         let c = WordDef::new("c".to_string(), vec![Instruction::Literal(1)]);
 
@@ -1151,7 +1217,7 @@ mod tests {
         // Call helper with a value on the stack
         ir.main = vec![
             Instruction::Literal(10),
-            Instruction::Call("helper".to_string()),
+            Instruction::Call("helper".into()),
         ];
 
         let optimized = optimizer.optimize(&ir).unwrap();
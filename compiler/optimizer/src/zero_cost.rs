@@ -6,8 +6,10 @@
 //! 1. **Stack word macro expansion** - Inline all stack operations to direct register operations
 //! 2. **Compile-time constant evaluation** - Fold all constant expressions at compile time
 //! 3. **Unconditional inlining** - Inline all words <3 operations with no cost/benefit analysis
-//! 4. **Conditional elimination** - Remove dead branches based on constant conditions
-//! 5. **Loop unrolling** - Unroll loops with constant bounds completely
+//! 4. **Pure call evaluation** - Run calls to pure words on constant arguments on the
+//!    [reference interpreter](crate::interpreter), keeping the literals they leave
+//! 5. **Conditional elimination** - Remove dead branches based on constant conditions
+//! 6. **Loop unrolling** - Unroll loops with constant bounds completely
 //!
 //! # Performance Targets
 //!
@@ -38,10 +40,20 @@
 //! ;
 //! ```
 
-use crate::ir::{ForthIR, Instruction, StackEffect, WordDef};
-use crate::{ConstantFolder, InlineOptimizer, OptimizationLevel, Result, OptimizerError};
+use crate::interpreter::IrInterpreter;
+use crate::ir::{ForthIR, Instruction, StackEffect, Symbol, WordDef};
+use crate::{ConstantFolder, InlineDirective, InlineOptimizer, OptimizationLevel, Result, OptimizerError};
 use smallvec::{SmallVec, smallvec};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+/// Instructions a call evaluated at compile time may execute
+const EVALUATION_FUEL: u64 = 100_000;
+
+/// Most literals an evaluated call may be replaced with
+const MAX_EVALUATED_RESULTS: usize = 8;
+
+/// Words lowering leaves as calls that only touch the stacks
+const PURE_BUILTINS: &[&str] = &["(do)", "(loop)", "(+loop)", "i", "j", ">r", "r>", "r@", "cells", "cell+"];
 
 /// Configuration for zero-cost optimizations
 #[derive(Debug, Clone)]
@@ -58,6 +70,8 @@ pub struct ZeroCostConfig {
     pub conditional_elimination: bool,
     /// Enable algebraic simplifications
     pub algebraic_simplification: bool,
+    /// Evaluate calls to pure words whose arguments are all literals
+    pub pure_call_evaluation: bool,
 }
 
 impl Default for ZeroCostConfig {
@@ -69,6 +83,7 @@ impl Default for ZeroCostConfig {
             constant_folding: true,
             conditional_elimination: true,
             algebraic_simplification: true,
+            pure_call_evaluation: true,
         }
     }
 }
@@ -101,6 +116,11 @@ impl ZeroCostOptimizer {
             optimized = self.enhanced_constant_fold(&optimized)?;
         }
 
+        // Pass 2b: Evaluate pure calls on the constants folding left
+        if self.config.pure_call_evaluation {
+            optimized = self.evaluate_pure_calls(&optimized)?;
+        }
+
         // Pass 3: Macro expand stack operations
         if self.config.macro_expand_stack_ops {
             optimized = self.macro_expand(&optimized)?;
@@ -129,7 +149,8 @@ impl ZeroCostOptimizer {
         let mut optimized = ir.clone();
         let threshold = self.config.unconditional_inline_threshold;
 
-        // Identify tiny words that should ALWAYS be inlined
+        // Identify tiny words that should ALWAYS be inlined, and words
+        // whose attributes decide
         let mut inline_candidates: HashMap<String, bool> = HashMap::new();
         for (name, word) in &ir.words {
             // Inline if: instruction count <= threshold AND not recursive
@@ -137,7 +158,12 @@ impl ZeroCostOptimizer {
                 matches!(inst, Instruction::Call(called_name) if called_name == name)
             });
 
-            if word.instructions.len() <= threshold && !is_recursive {
+            let inline = match word.inline_directive() {
+                InlineDirective::AlwaysInline => true,
+                InlineDirective::NeverInline => false,
+                InlineDirective::Auto => word.instructions.len() <= threshold,
+            };
+            if inline && !is_recursive {
                 inline_candidates.insert(name.clone(), true);
             }
         }
@@ -162,17 +188,34 @@ impl ZeroCostOptimizer {
         instructions: &[Instruction],
         ir: &ForthIR,
         candidates: &HashMap<String, bool>,
+    ) -> Result<Vec<Instruction>> {
+        self.inline_nested(instructions, ir, candidates, &mut Vec::new())
+    }
+
+    /// Inline calls, recursively, skipping words already being expanded
+    /// (mutual recursion)
+    fn inline_nested(
+        &self,
+        instructions: &[Instruction],
+        ir: &ForthIR,
+        candidates: &HashMap<String, bool>,
+        expanding: &mut Vec<Symbol>,
     ) -> Result<Vec<Instruction>> {
         let mut result = Vec::new();
+        let locals = Instruction::free_local_slot(instructions);
 
         for inst in instructions {
             if let Instruction::Call(name) = inst {
-                if candidates.get(name).copied().unwrap_or(false) {
-                    if let Some(word) = ir.get_word(name) {
+                if candidates.get(name.as_str()).copied().unwrap_or(false) && !expanding.contains(name) {
+                    if let Some(body) = ir.get_word(name).and_then(InlineOptimizer::inline_body) {
                         // Recursively inline
-                        let inlined = self.inline_sequence(&word.instructions, ir, candidates)?;
-                        result.extend(inlined);
-                        continue;
+                        expanding.push(*name);
+                        let inlined = self.inline_nested(body, ir, candidates, expanding)?;
+                        expanding.pop();
+                        if let Some(inlined) = Instruction::shift_locals(&inlined, locals) {
+                            result.extend(inlined);
+                            continue;
+                        }
                     }
                 }
             }
@@ -182,6 +225,27 @@ impl ZeroCostOptimizer {
         Ok(result)
     }
 
+    /// Replace each call to a pure word whose arguments are all literals
+    /// with the literals the word leaves
+    ///
+    /// The word is run on the interpreter from exactly those literals, so
+    /// the result is right even where its inferred stack effect is not: a
+    /// word reaching deeper fails to run and keeps its call. So do calls
+    /// that fail, such as a division by zero, which must fail at run time,
+    /// and calls that run too long or leave too many results.
+    fn evaluate_pure_calls(&self, ir: &ForthIR) -> Result<ForthIR> {
+        let pure = pure_words(ir);
+        let mut optimized = ir.clone();
+        optimized.main = evaluate_calls(&ir.main, ir, &pure);
+        for (name, word) in &ir.words {
+            let mut evaluated = word.clone();
+            evaluated.instructions = evaluate_calls(&word.instructions, ir, &pure);
+            evaluated.update();
+            optimized.words.insert(name.clone(), evaluated);
+        }
+        Ok(optimized)
+    }
+
     /// Enhanced constant folding with algebraic simplification
     fn enhanced_constant_fold(&self, ir: &ForthIR) -> Result<ForthIR> {
         let mut optimized = self.constant_folder.fold(ir)?;
@@ -246,16 +310,19 @@ impl ZeroCostOptimizer {
                 match (&instructions[i], &instructions[i + 1], &instructions[i + 2]) {
                     // DUP followed by comparison to zero
                     (Instruction::Dup, Instruction::Literal(0), Instruction::Eq) => {
+                        result.push(Instruction::Dup);
                         result.push(Instruction::ZeroEq);
                         i += 3;
                         continue;
                     }
                     (Instruction::Dup, Instruction::Literal(0), Instruction::Lt) => {
+                        result.push(Instruction::Dup);
                         result.push(Instruction::ZeroLt);
                         i += 3;
                         continue;
                     }
                     (Instruction::Dup, Instruction::Literal(0), Instruction::Gt) => {
+                        result.push(Instruction::Dup);
                         result.push(Instruction::ZeroGt);
                         i += 3;
                         continue;
@@ -341,7 +408,8 @@ impl ZeroCostOptimizer {
 
         optimized.main = self.eliminate_conditional_sequence(&ir.main)?;
 
-        for (name, word) in ir.words.iter() {
+        // Constant-time words keep their branches as the frontend left them
+        for (name, word) in ir.words.iter().filter(|(_, word)| !word.attributes.constant_time) {
             let mut optimized_word = word.clone();
             optimized_word.instructions =
                 self.eliminate_conditional_sequence(&word.instructions)?;
@@ -415,58 +483,47 @@ impl ZeroCostOptimizer {
     }
 
     fn unroll_loop_sequence(&self, instructions: &[Instruction]) -> Result<Vec<Instruction>> {
-        // Simple pattern: end_val start_val DO ... LOOP
-        // In IR this would be represented with branches
-        // For now, we'll look for the pattern: Literal Literal ... Branch
-        // This is a simplified version; full implementation would need proper loop detection
-
+        // Pattern: Literal(limit) Literal(index) (do) L: body (loop) BranchIfNot(L)
+        // The body must be straight-line code whose only word call is `i`,
+        // which becomes the literal index of each copy
         let mut result = Vec::new();
-        let mut i = 0;
+        let mut copied = 0;
 
-        while i < instructions.len() {
-            // Look for constant loop pattern
-            // Pattern: Literal(end) Literal(start) ... BranchIfNot(back_to_loop)
-            // This is a simplified heuristic
-            if i + 5 < instructions.len() {
-                if let (Instruction::Literal(end_val), Instruction::Literal(start_val)) =
-                    (&instructions[i], &instructions[i + 1])
-                {
-                    // Check if this looks like a loop (has backward branch)
-                    let loop_body_end = instructions[i + 2..]
-                        .iter()
-                        .position(|inst| matches!(inst, Instruction::BranchIfNot(_) | Instruction::Branch(_)));
-
-                    if let Some(body_len) = loop_body_end {
-                        let iterations = (end_val - start_val).abs();
-
-                        if iterations > 0
-                            && iterations <= self.config.max_loop_unroll as i64
-                            && body_len > 0
-                        {
-                            // Unroll the loop!
-                            let body_start = i + 2;
-                            let body_end = body_start + body_len;
-                            let loop_body = &instructions[body_start..body_end];
-
-                            for iter in *start_val..*end_val {
-                                // Push iteration counter before body
-                                result.push(Instruction::Literal(iter));
-                                // Execute body
-                                result.extend_from_slice(loop_body);
-                            }
-
-                            // Skip past the entire loop structure
-                            i = body_end + 1;
-                            continue;
-                        }
-                    }
-                }
+        for lp in ForthIR::counted_loops(instructions) {
+            if lp.start < 2 || lp.start < copied + 2 || lp.header != lp.start + 1 || lp.step != Some(1) {
+                continue;
+            }
+            let (Instruction::Literal(limit), Instruction::Literal(first)) =
+                (&instructions[lp.start - 2], &instructions[lp.start - 1])
+            else {
+                continue;
+            };
+            let body = &instructions[lp.header + 1..lp.latch];
+            let straight_line = body.iter().all(|inst| match inst {
+                Instruction::Call(name) => name == "i",
+                Instruction::Label(_)
+                | Instruction::Branch(_)
+                | Instruction::BranchIf(_)
+                | Instruction::BranchIfNot(_)
+                | Instruction::Return => false,
+                _ => true,
+            });
+            let iterations = limit.checked_sub(*first).unwrap_or(0);
+            if !straight_line || iterations <= 0 || iterations > self.config.max_loop_unroll as i64 {
+                continue;
             }
 
-            result.push(instructions[i].clone());
-            i += 1;
+            result.extend_from_slice(&instructions[copied..lp.start - 2]);
+            for index in *first..*limit {
+                result.extend(body.iter().map(|inst| match inst {
+                    Instruction::Call(name) if name == "i" => Instruction::Literal(index),
+                    other => other.clone(),
+                }));
+            }
+            copied = lp.end + 1;
         }
 
+        result.extend_from_slice(&instructions[copied..]);
         Ok(result)
     }
 
@@ -574,10 +631,112 @@ impl std::fmt::Display for ZeroCostStats {
     }
 }
 
+/// Words whose result depends only on their arguments: no memory, I/O or
+/// concurrency, reached directly or through the words they call, and not
+/// kept out of optimization
+fn pure_words(ir: &ForthIR) -> HashSet<&str> {
+    let mut pure: HashSet<&str> = ir
+        .words
+        .iter()
+        .filter(|(_, word)| !word.attributes.optimize_none && word.inline_directive() != InlineDirective::NeverInline)
+        .map(|(name, _)| name.as_str())
+        .collect();
+
+    // Drop impure words until none is left calling one
+    loop {
+        let impure: Vec<&str> = pure
+            .iter()
+            .copied()
+            .filter(|name| {
+                !ir.words[*name].instructions.iter().all(|inst| match inst {
+                    Instruction::Call(callee) => pure.contains(callee.as_str()) || PURE_BUILTINS.contains(&callee.as_str()),
+                    inst => is_pure_instruction(inst),
+                })
+            })
+            .collect();
+        if impure.is_empty() {
+            return pure;
+        }
+        for name in impure {
+            pure.remove(name);
+        }
+    }
+}
+
+fn is_pure_instruction(inst: &Instruction) -> bool {
+    use Instruction::*;
+    match inst {
+        Load | Store | FloatLiteral(_) | Spawn | Join | Channel(_) | Send | Recv | CloseChannel | DestroyChannel => false,
+        VectorReduce { .. } | VectorMap { .. } | VectorZip { .. } => false,
+        Fused(body) => body.iter().all(is_pure_instruction),
+        _ => true,
+    }
+}
+
+/// `instructions` with each call to a `pure` word on literal arguments
+/// replaced by its result
+fn evaluate_calls(instructions: &[Instruction], ir: &ForthIR, pure: &HashSet<&str>) -> Vec<Instruction> {
+    let mut result: Vec<Instruction> = Vec::with_capacity(instructions.len());
+    for inst in instructions {
+        if let Instruction::Call(name) = inst {
+            if let Some(evaluated) = pure.contains(name.as_str()).then(|| evaluate_call(name, ir, &result)).flatten() {
+                let consumed = ir.words[name.as_str()].stack_effect.consumed as usize;
+                result.truncate(result.len() - consumed);
+                result.extend(evaluated.into_iter().map(Instruction::Literal));
+                continue;
+            }
+        }
+        result.push(inst.clone());
+    }
+    result
+}
+
+/// Run `name` on the literals ending `preceding`, if there are enough
+fn evaluate_call(name: &str, ir: &ForthIR, preceding: &[Instruction]) -> Option<Vec<i64>> {
+    let consumed = ir.words[name].stack_effect.consumed as usize;
+    let arguments = preceding.get(preceding.len().checked_sub(consumed)?..)?;
+    let arguments: Vec<i64> = arguments
+        .iter()
+        .map(|inst| match inst {
+            Instruction::Literal(value) => Some(*value),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+    let results = IrInterpreter::new(ir).with_stack(arguments).with_fuel(EVALUATION_FUEL).run_word(name).ok()?;
+    (results.len() <= MAX_EVALUATED_RESULTS).then_some(results)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_pure_calls_are_evaluated() {
+        use Instruction::*;
+        let optimizer = ZeroCostOptimizer::default();
+
+        // : fact ( n -- n! ) dup 1 > if dup 1 - fact * then ;
+        let mut ir = ForthIR::new();
+        ir.add_word(WordDef::new(
+            "fact".to_string(),
+            vec![
+                Dup, Literal(1), Gt, BranchIfNot(0),
+                Dup, Literal(1), Sub, Call("fact".into()), Mul,
+                Label("L0".to_string()), Return,
+            ],
+        ));
+        ir.add_buffer("seed", 8);
+        ir.add_word(WordDef::new("seeded".to_string(), vec![Call("seed".into()), Load, Add, Return]));
+        ir.main = vec![Literal(5), Call("fact".into()), Literal(1), Literal(0), Div, Literal(2), Call("seeded".into())];
+
+        let optimized = optimizer.optimize(&ir).unwrap();
+        assert_eq!(optimized.main[0], Literal(120));
+        // Reading memory is not pure, and a division by zero stays to fail
+        // at run time
+        assert!(optimized.main.contains(&Call("seeded".into())));
+        assert!(optimized.main.contains(&Div));
+    }
+
     #[test]
     fn test_unconditional_inline_tiny_words() {
         let optimizer = ZeroCostOptimizer::default();
@@ -592,8 +751,8 @@ mod tests {
         ir.add_word(three);
 
         ir.main = vec![
-            Instruction::Call("three".to_string()),
-            Instruction::Call("three".to_string()),
+            Instruction::Call("three".into()),
+            Instruction::Call("three".into()),
             Instruction::Add,
         ];
 
@@ -728,6 +887,20 @@ mod tests {
         assert_eq!(optimized.len(), 0);
     }
 
+    #[test]
+    fn test_constant_time_words_keep_conditionals() {
+        let optimizer = ZeroCostOptimizer::default();
+        let body = vec![Instruction::Literal(0), Instruction::BranchIf(1), Instruction::Literal(7)];
+
+        let mut ir = ForthIR::new();
+        let mut word = WordDef::new("select".to_string(), body.clone());
+        word.attributes.constant_time = true;
+        ir.add_word(word);
+
+        let optimized = optimizer.eliminate_conditionals(&ir).unwrap();
+        assert_eq!(optimized.words["select"].instructions, body);
+    }
+
     #[test]
     fn test_macro_expansion_stack_depth() {
         let optimizer = ZeroCostOptimizer::default();
@@ -772,6 +945,44 @@ mod tests {
         assert!(optimized.main.len() > 0, "Should produce valid output");
     }
 
+    #[test]
+    fn test_loop_unrolling_counted_loop() {
+        let optimizer = ZeroCostOptimizer::default();
+
+        // 0 3 0 do i + loop
+        let mut ir = ForthIR::new();
+        ir.main = vec![
+            Instruction::Literal(0),
+            Instruction::Literal(3),
+            Instruction::Literal(0),
+            Instruction::Call("(do)".into()),
+            Instruction::Label("L0".to_string()),
+            Instruction::Call("i".into()),
+            Instruction::Add,
+            Instruction::Call("(loop)".into()),
+            Instruction::BranchIfNot(0),
+        ];
+
+        let optimized = optimizer.unroll_loops(&ir).unwrap();
+        assert_eq!(
+            optimized.main,
+            vec![
+                Instruction::Literal(0),
+                Instruction::Literal(0),
+                Instruction::Add,
+                Instruction::Literal(1),
+                Instruction::Add,
+                Instruction::Literal(2),
+                Instruction::Add,
+            ]
+        );
+
+        // Bodies with control flow are left alone
+        ir.main.insert(6, Instruction::Label("L1".to_string()));
+        let optimized = optimizer.unroll_loops(&ir).unwrap();
+        assert_eq!(optimized.main, ir.main);
+    }
+
     #[test]
     fn test_zero_cost_stats() {
         let optimizer = ZeroCostOptimizer::default();
@@ -816,8 +1027,8 @@ mod tests {
 
         // Main: sum5 sum5 +  -> should become 6
         ir.main = vec![
-            Instruction::Call("sum5".to_string()),
-            Instruction::Call("sum5".to_string()),
+            Instruction::Call("sum5".into()),
+            Instruction::Call("sum5".into()),
             Instruction::Add,
         ];
 
@@ -851,8 +1062,8 @@ mod tests {
         let four = WordDef::new(
             "four".to_string(),
             vec![
-                Instruction::Call("two".to_string()),
-                Instruction::Call("two".to_string()),
+                Instruction::Call("two".into()),
+                Instruction::Call("two".into()),
                 Instruction::Add,
             ],
         );
@@ -860,7 +1071,7 @@ mod tests {
 
         // Main: four -> should become 4
         ir.main = vec![
-            Instruction::Call("four".to_string()),
+            Instruction::Call("four".into()),
         ];
 
         let optimized = optimizer.optimize(&ir).unwrap();
@@ -0,0 +1,75 @@
+//! Reference Semantics Tests
+//!
+//! Every optimization level must leave programs computing what the
+//! reference interpreter says they compute unoptimized: the same stacks
+//! for every word on random inputs, the same output and the same
+//! variables.
+
+use fastforth_frontend::parse_program;
+use fastforth_optimizer::differential::{verify, DEFAULT_TRIALS};
+use fastforth_optimizer::lower::lower_program;
+use fastforth_optimizer::{ForthIR, IrInterpreter, OptimizationLevel, Optimizer};
+
+const LEVELS: [OptimizationLevel; 4] = [
+    OptimizationLevel::None,
+    OptimizationLevel::Basic,
+    OptimizationLevel::Standard,
+    OptimizationLevel::Aggressive,
+];
+
+fn lower(source: &str) -> ForthIR {
+    lower_program(&parse_program(source).unwrap(), |_| Default::default())
+}
+
+fn check(source: &str) {
+    let ir = lower(source);
+    for level in LEVELS {
+        let optimized = Optimizer::new(level).optimize(ir.clone()).unwrap();
+        if let Err(divergence) = verify(&ir, &optimized, DEFAULT_TRIALS) {
+            panic!("{:?}: {}\n{}", level, source, divergence);
+        }
+    }
+}
+
+#[test]
+fn test_arithmetic_and_stack_words() {
+    check(": sq ( n -- n ) dup * ; : poly ( x -- y ) dup sq swap 3 * + 7 - ; : f ( a b -- c ) over poly swap - ; 4 9 f");
+    check(": mix ( a b c -- x ) rot xor swap 1 + and ; 1 2 3 mix");
+}
+
+#[test]
+fn test_control_flow() {
+    check(": clamp ( n -- n ) dup 0 < if drop 0 else dup 100 > if drop 100 then then ; -5 clamp 50 clamp 500 clamp");
+    check(": sum ( n -- s ) 0 swap 0 do i + loop ; 10 sum");
+    check(": countdown ( n -- s ) 0 begin over + swap 1 - swap over 0= until nip ; 6 countdown");
+}
+
+#[test]
+fn test_recursion_and_evaluation() {
+    check(": fact ( n -- n! ) dup 1 > if dup 1 - fact * then ; 6 fact");
+    check(": fib ( n -- f ) dup 2 < if exit then dup 1 - fib swap 2 - fib + ; 10 fib");
+}
+
+#[test]
+fn test_variables() {
+    check("variable total : add ( n -- ) total +! ; : twice ( n -- ) dup add add ; 5 twice 3 add total @");
+}
+
+#[test]
+fn test_sized_memory_words() {
+    check("variable buf : put ( -- ) 287454020 buf l! 43707 buf 1 + w! ; : peek ( -- a b c ) buf @ buf 3 + c@ buf 2 + w@ ; put peek");
+    check("variable buf : f ( n -- m ) buf ! buf 1 + c@ buf l@ + ; 4660 f -1 f");
+
+    let ir = lower("variable buf 287454020 buf l! 43707 buf 1 + w! buf @ buf 3 + c@ -1 buf 4 + l! buf 4 + l@");
+    assert_eq!(IrInterpreter::new(&ir).run(), Ok(vec![0x11AABB44, 0x11, 0xFFFF_FFFF]));
+}
+
+#[test]
+fn test_interpreter_agrees_with_evaluation() {
+    // The constant the optimizer computes for a pure call is the one the
+    // interpreter computes for the call itself
+    let ir = lower(": fact ( n -- n! ) dup 1 > if dup 1 - fact * then ; 10 fact");
+    let optimized = Optimizer::new(OptimizationLevel::Aggressive).optimize(ir.clone()).unwrap();
+    assert_eq!(IrInterpreter::new(&ir).run(), Ok(vec![3_628_800]));
+    assert!(optimized.main.contains(&fastforth_optimizer::Instruction::Literal(3_628_800)), "{:?}", optimized.main);
+}
@@ -0,0 +1,110 @@
+//! Whole-Program Optimization Tests
+//!
+//! Programs are optimized with and without the whole-program pass and run
+//! on the reference interpreter: the program with it must compute what
+//! the unoptimized program computes, and what the same pipeline computes
+//! without it, for every word that survives and for the main sequence.
+//! Some programs the pipeline only accepts with the pass, which turns calls
+//! to constant words into the literals `verify` counts.
+
+use fastforth_frontend::parse_program;
+use fastforth_optimizer::differential::{verify, DEFAULT_TRIALS};
+use fastforth_optimizer::lower::lower_program;
+use fastforth_optimizer::{ForthIR, Instruction, IrInterpreter, OptimizationLevel, Optimizer, Pass, PassPipeline};
+use std::path::Path;
+
+const LEVELS: [OptimizationLevel; 2] = [OptimizationLevel::Standard, OptimizationLevel::Aggressive];
+
+fn lower(source: &str) -> ForthIR {
+    lower_program(&parse_program(source).unwrap(), |_| Default::default())
+}
+
+/// Check `source` at each level, returning the programs optimized with
+/// whole-program optimization
+fn check(source: &str) -> Vec<ForthIR> {
+    let ir = lower(source);
+    LEVELS
+        .iter()
+        .map(|&level| {
+            let with = Optimizer::new(level).optimize(ir.clone()).unwrap();
+            let mut optimizer = Optimizer::new(level);
+            optimizer.set_pipeline(PassPipeline::preset(level).without(Pass::WholeProgram)).unwrap();
+            let without = optimizer.optimize(ir.clone()).ok();
+
+            let originals = [("unoptimized", Some(&ir)), ("without whole-program", without.as_ref())];
+            for (name, original) in originals.into_iter().filter_map(|(name, ir)| Some((name, ir?))) {
+                if let Err(divergence) = verify(original, &with, DEFAULT_TRIALS) {
+                    panic!("{:?}, against the program {}: {}\n{}", level, name, source, divergence);
+                }
+            }
+            with
+        })
+        .collect()
+}
+
+#[test]
+fn test_dead_and_single_call_words() {
+    let source = ": unused ( n -- n ) 3 * ; : helper ( n -- n ) 5 + ; : twice ( n -- n ) dup + ; 10 helper twice 4 twice +";
+    for optimized in check(source) {
+        assert!(!optimized.words.contains_key("unused"));
+        assert!(!optimized.words.contains_key("helper"), "{:?}", optimized.words.keys());
+        assert_eq!(IrInterpreter::new(&optimized).run(), Ok(vec![38]));
+    }
+
+    // A program of definitions alone is a library: every word stays
+    for optimized in check(": unused ( n -- n ) 3 * ; : helper ( n -- n ) 5 + ;") {
+        assert_eq!(optimized.words.len(), 2);
+    }
+}
+
+#[test]
+fn test_constants_cross_word_boundaries() {
+    let source = ": width 8 ; : height width 2 * ; : area ( -- n ) width height * ; : scale ( n -- n ) area * ; 3 scale 2 scale";
+    for optimized in check(source) {
+        let mut code = optimized.words.values().flat_map(|word| &word.instructions).chain(&optimized.main);
+        assert!(!code.any(|inst| matches!(inst, Instruction::Call(_))), "{:?}", optimized);
+        assert_eq!(IrInterpreter::new(&optimized).run(), Ok(vec![384, 256]));
+    }
+}
+
+#[test]
+fn test_recursive_words() {
+    check(": fact ( n -- n! ) dup 1 > if dup 1 - fact * then ; 10 fact");
+    check(": fib ( n -- f ) dup 2 < if exit then dup 1 - fib swap 2 - fib + ; 15 fib");
+    check(": gcd ( a b -- g ) ?dup if tuck mod gcd then ; : lcm ( a b -- l ) 2dup gcd */ ; 12 18 lcm 7 gcd");
+
+    // Called once, but calling itself: never inlined into its caller
+    for optimized in check(": count-down ( n -- ) dup 0 > if 1 - count-down else drop then ; 5 count-down") {
+        assert!(optimized.words.contains_key("count-down"));
+    }
+}
+
+#[test]
+fn test_mutually_recursive_words() {
+    let source = ": odd? ( n -- f ) dup 0= if drop 0 else 1 - even? then ;\n\
+                  : even? ( n -- f ) dup 0= if drop -1 else 1 - odd? then ;\n\
+                  : parity ( n -- n ) even? if 0 else 1 then ;\n\
+                  7 parity 10 parity";
+    for optimized in check(source) {
+        assert!(optimized.words.contains_key("odd?") && optimized.words.contains_key("even?"));
+        assert_eq!(IrInterpreter::new(&optimized).run(), Ok(vec![1, 0]));
+    }
+
+    // A cycle nothing reaches is dead as a whole
+    let source = ": ping ( n -- n ) dup 0 > if 1 - pong then ; : pong ( n -- n ) dup 0 > if 1 - ping then ; 3 4 +";
+    for optimized in check(source) {
+        assert!(optimized.words.is_empty(), "{:?}", optimized.words.keys());
+    }
+}
+
+#[test]
+fn test_benchmark_corpus() {
+    // bubble_sort and coremark use ELSE forms the parser does not accept
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("../benchmarks/forth");
+    for (name, entry) in [("fibonacci", "20 FIB-REC 20 FIB-ITER 20 FIB-ITERATIVE"), ("sieve", "100 SIEVE-SIMPLE"), ("matrix", "TEST-MATRIX")] {
+        let source = std::fs::read_to_string(corpus.join(format!("{}.fth", name))).unwrap();
+        // As a library, and as a program with an entry point
+        check(&source);
+        check(&format!("{}\n{}", source, entry));
+    }
+}
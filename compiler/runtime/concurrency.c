@@ -19,6 +19,7 @@
 #include <string.h>
 #include <stdio.h>
 #include <errno.h>
+#include <sched.h>
 
 // ============================================================================
 // THREAD MANAGEMENT
@@ -146,6 +147,11 @@ void forth_join(forth_vm_t* vm, cell_t thread_id) {
  * Creates bounded message queue with ring buffer
  */
 cell_t forth_channel_create(size_t capacity) {
+    // An unbuffered channel still needs a slot to hand the message over
+    if (capacity == 0) {
+        capacity = 1;
+    }
+
     forth_channel_t* chan = malloc(sizeof(forth_channel_t));
     if (!chan) {
         fprintf(stderr, "channel: failed to allocate channel\n");
@@ -323,6 +329,101 @@ void forth_channel_destroy(cell_t chan_ptr) {
     free(chan);
 }
 
+// ============================================================================
+// COMPILED TASKS
+// ============================================================================
+
+/**
+ * Thread entry point for compiled words: calls the word with its cells
+ * in C argument registers, the way compiled code calls it
+ */
+static void* forth_task_entry(void* arg) {
+    forth_task_t* task = (forth_task_t*)arg;
+    cell_t* a = task->args;
+
+    if (task->outputs == 0) {
+        switch (task->inputs) {
+            case 0: ((void (*)(void))task->entry)(); break;
+            case 1: ((void (*)(cell_t))task->entry)(a[0]); break;
+            case 2: ((void (*)(cell_t, cell_t))task->entry)(a[0], a[1]); break;
+            case 3: ((void (*)(cell_t, cell_t, cell_t))task->entry)(a[0], a[1], a[2]); break;
+            default: ((void (*)(cell_t, cell_t, cell_t, cell_t))task->entry)(a[0], a[1], a[2], a[3]); break;
+        }
+        return NULL;
+    }
+
+    switch (task->inputs) {
+        case 0: task->result = ((cell_t (*)(void))task->entry)(); break;
+        case 1: task->result = ((cell_t (*)(cell_t))task->entry)(a[0]); break;
+        case 2: task->result = ((cell_t (*)(cell_t, cell_t))task->entry)(a[0], a[1]); break;
+        case 3: task->result = ((cell_t (*)(cell_t, cell_t, cell_t))task->entry)(a[0], a[1], a[2]); break;
+        default: task->result = ((cell_t (*)(cell_t, cell_t, cell_t, cell_t))task->entry)(a[0], a[1], a[2], a[3]); break;
+    }
+    return NULL;
+}
+
+/**
+ * task: word ( x1 .. xn -- task )
+ */
+cell_t forth_task_spawn(cell_t entry, cell_t inputs, cell_t outputs,
+                        cell_t a0, cell_t a1, cell_t a2, cell_t a3) {
+    forth_task_t* task = malloc(sizeof(forth_task_t));
+    if (!task) {
+        fprintf(stderr, "task: failed to allocate task\n");
+        return 0;
+    }
+
+    task->entry = entry;
+    task->inputs = inputs;
+    task->outputs = outputs;
+    task->args[0] = a0;
+    task->args[1] = a1;
+    task->args[2] = a2;
+    task->args[3] = a3;
+    task->result = 0;
+
+    pthread_attr_t attr;
+    pthread_attr_init(&attr);
+    pthread_attr_setstacksize(&attr, FORTH_TASK_STACK_SIZE);
+    int result = pthread_create(&task->thread, &attr, forth_task_entry, task);
+    pthread_attr_destroy(&attr);
+    if (result != 0) {
+        fprintf(stderr, "task: pthread_create failed: %s\n", strerror(result));
+        free(task);
+        return 0;
+    }
+
+    return (cell_t)task;
+}
+
+/**
+ * join ( task -- x )
+ */
+cell_t forth_task_join(cell_t task_ptr) {
+    forth_task_t* task = (forth_task_t*)task_ptr;
+
+    if (!task) {
+        fprintf(stderr, "join: invalid task\n");
+        return 0;
+    }
+
+    int result = pthread_join(task->thread, NULL);
+    if (result != 0) {
+        fprintf(stderr, "join: pthread_join failed: %s\n", strerror(result));
+    }
+
+    cell_t value = task->result;
+    free(task);
+    return value;
+}
+
+/**
+ * pause ( -- )
+ */
+void forth_task_pause(void) {
+    sched_yield();
+}
+
 // ============================================================================
 // FORTH VM PRIMITIVES (STACK-BASED WRAPPERS)
 // ============================================================================
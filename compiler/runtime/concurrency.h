@@ -93,7 +93,7 @@ void forth_join(forth_vm_t* vm, cell_t thread_id);
  * send blocks when full, recv blocks when empty.
  *
  * Stack effect: ( size -- chan )
- * size: Maximum buffered messages (0 = unbuffered)
+ * size: Maximum buffered messages (0 holds one, like 1)
  * chan: Opaque channel handle
  *
  * Example:
@@ -153,6 +153,47 @@ void forth_channel_close(cell_t chan_ptr);
  */
 void forth_channel_destroy(cell_t chan_ptr);
 
+// ============================================================================
+// COMPILED TASKS (TASK: AND JOIN IN COMPILED CODE)
+// ============================================================================
+
+// Stack of a task's thread; compiled words keep their stacks there
+#define FORTH_TASK_STACK_SIZE (8 * 1024 * 1024)
+
+// A compiled word running on its own thread
+typedef struct {
+    pthread_t thread;
+    cell_t entry;                // Address of the compiled word
+    cell_t inputs;               // Cells it takes, up to 4
+    cell_t outputs;              // Cells it returns, 0 or 1
+    cell_t args[4];
+    cell_t result;
+} forth_task_t;
+
+/**
+ * task: word ( x1 .. xn -- task )
+ *
+ * Runs the compiled word at `entry` on a new OS thread, passing it the
+ * first `inputs` of a0..a3. Returns 0 if the thread cannot be started.
+ */
+cell_t forth_task_spawn(cell_t entry, cell_t inputs, cell_t outputs,
+                        cell_t a0, cell_t a1, cell_t a2, cell_t a3);
+
+/**
+ * join ( task -- x )
+ *
+ * Waits for the task to finish and frees it. x is the cell the word
+ * returned, or 0 if it returns none.
+ */
+cell_t forth_task_join(cell_t task);
+
+/**
+ * pause ( -- )
+ *
+ * Gives the processor to another thread that is ready to run.
+ */
+void forth_task_pause(void);
+
 // ============================================================================
 // VM PRIMITIVES (CALLED FROM FORTH CODE)
 // ============================================================================
@@ -0,0 +1,278 @@
+/**
+ * Fast Forth Runtime: Files component
+ *
+ * The ANS Block word set over a memory-mapped block file, and the file
+ * words, which go through an installable filesystem: libc, checked
+ * against the permissions, unless an embedder supplies its own. Linked
+ * only into programs that use blocks or files.
+ */
+
+#define _DEFAULT_SOURCE  // PATH_MAX under -std=c11
+#include "forth_runtime.h"
+#include <limits.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <fcntl.h>
+#include <sys/mman.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+// ============================================================================
+// BLOCKS
+// ============================================================================
+
+// Buffers are copies of blocks: reading copies out of the mapped file and
+// saving an updated buffer copies it back, so only UPDATEd blocks are ever
+// written. The least recently used buffer is reassigned when all are taken.
+
+typedef struct {
+    cell_t block;        // assigned block, 0 if free
+    int dirty;           // UPDATEd since it was last saved
+    unsigned long used;  // tick of the last access, for LRU
+    char data[BLOCK_SIZE];
+} block_buffer_t;
+
+static block_buffer_t block_buffers[BLOCK_BUFFERS];
+static block_buffer_t *current_buffer = NULL;
+static unsigned long block_tick = 0;
+
+static char block_path[4096] = "";
+static int block_fd = -1;
+static char *block_map = NULL;
+static size_t block_map_size = 0;
+
+static void block_unmap(void) {
+    if (block_map != NULL) munmap(block_map, block_map_size);
+    block_map = NULL;
+    block_map_size = 0;
+}
+
+// Map the block file at its current size
+static int block_remap(void) {
+    struct stat st;
+    block_unmap();
+    if (fstat(block_fd, &st) != 0) return -1;
+    if (st.st_size == 0) return 0;
+    void *map = mmap(NULL, (size_t)st.st_size, PROT_READ | PROT_WRITE, MAP_SHARED, block_fd, 0);
+    if (map == MAP_FAILED) return -1;
+    block_map = map;
+    block_map_size = (size_t)st.st_size;
+    return 0;
+}
+
+static int block_file_ready(void) {
+    if (block_fd >= 0) return 0;
+    if (block_path[0] == '\0') {
+        const char *path = getenv("FORTH_BLOCK_FILE");
+        snprintf(block_path, sizeof(block_path), "%s", path != NULL ? path : DEFAULT_BLOCK_FILE);
+    }
+    if (forth_permit_path(block_path, 1, 1) != 0) return -1;
+    block_fd = open(block_path, O_RDWR | O_CREAT, 0644);
+    if (block_fd < 0) return -1;
+    return block_remap();
+}
+
+static int block_write_back(block_buffer_t *buf) {
+    size_t end = (size_t)buf->block * BLOCK_SIZE;
+    if (end > block_map_size) {
+        if (ftruncate(block_fd, (off_t)end) != 0 || block_remap() != 0) return -1;
+    }
+    memcpy(block_map + end - BLOCK_SIZE, buf->data, BLOCK_SIZE);
+    buf->dirty = 0;
+    return 0;
+}
+
+// The buffer holding block u, assigning one (and saving its old block) if
+// needed; the flag says whether it was newly assigned
+static block_buffer_t *block_assign(cell_t u, int *assigned) {
+    block_buffer_t *victim = &block_buffers[0];
+    if (u <= 0 || block_file_ready() != 0) return NULL;
+
+    for (int i = 0; i < BLOCK_BUFFERS; i++) {
+        block_buffer_t *buf = &block_buffers[i];
+        if (buf->block == u) {
+            buf->used = ++block_tick;
+            *assigned = 0;
+            return buf;
+        }
+        if (victim->block != 0 && (buf->block == 0 || buf->used < victim->used)) victim = buf;
+    }
+
+    if (victim->dirty && block_write_back(victim) != 0) return NULL;
+    victim->block = u;
+    victim->dirty = 0;
+    victim->used = ++block_tick;
+    *assigned = 1;
+    return victim;
+}
+
+void forth_block_open(cell_t path_addr, cell_t path_len) {
+    forth_flush();
+    block_unmap();
+    if (block_fd >= 0) close(block_fd);
+    block_fd = -1;
+
+    size_t len = path_len > 0 ? (size_t)path_len : 0;
+    if (len >= sizeof(block_path)) len = sizeof(block_path) - 1;
+    memcpy(block_path, (const char *)path_addr, len);
+    block_path[len] = '\0';
+}
+
+cell_t forth_block(cell_t u) {
+    int assigned;
+    block_buffer_t *buf = block_assign(u, &assigned);
+    if (buf == NULL) return 0;
+    if (assigned) {
+        size_t start = (size_t)(u - 1) * BLOCK_SIZE;
+        if (start + BLOCK_SIZE <= block_map_size) {
+            memcpy(buf->data, block_map + start, BLOCK_SIZE);
+        } else {
+            memset(buf->data, ' ', BLOCK_SIZE);
+        }
+    }
+    current_buffer = buf;
+    return (cell_t)buf->data;
+}
+
+cell_t forth_buffer(cell_t u) {
+    int assigned;
+    block_buffer_t *buf = block_assign(u, &assigned);
+    if (buf == NULL) return 0;
+    current_buffer = buf;
+    return (cell_t)buf->data;
+}
+
+void forth_update(void) {
+    if (current_buffer != NULL && current_buffer->block != 0) current_buffer->dirty = 1;
+}
+
+void forth_save_buffers(void) {
+    int saved = 0;
+    for (int i = 0; i < BLOCK_BUFFERS; i++) {
+        if (block_buffers[i].dirty && block_write_back(&block_buffers[i]) == 0) saved = 1;
+    }
+    if (saved && block_map != NULL) msync(block_map, block_map_size, MS_SYNC);
+}
+
+void forth_empty_buffers(void) {
+    for (int i = 0; i < BLOCK_BUFFERS; i++) {
+        block_buffers[i].block = 0;
+        block_buffers[i].dirty = 0;
+    }
+    current_buffer = NULL;
+}
+
+void forth_flush(void) {
+    forth_save_buffers();
+    forth_empty_buffers();
+}
+
+// ============================================================================
+// FILE ACCESS
+// ============================================================================
+
+// The host filesystem through libc; handles are FILE pointers
+
+static cell_t libc_open(void *context, const char *path, const char *mode, cell_t *handle) {
+    (void)context;
+    FILE *file = fopen(path, mode);
+    *handle = (cell_t)file;
+    return file == NULL ? -1 : 0;
+}
+
+static cell_t libc_read(void *context, cell_t handle, char *buffer, cell_t count) {
+    (void)context;
+    FILE *file = (FILE *)handle;
+    size_t read = fread(buffer, 1, (size_t)count, file);
+    return read < (size_t)count && ferror(file) ? -1 : (cell_t)read;
+}
+
+static cell_t libc_write(void *context, cell_t handle, const char *buffer, cell_t count) {
+    (void)context;
+    return fwrite(buffer, 1, (size_t)count, (FILE *)handle) == (size_t)count ? 0 : -1;
+}
+
+static cell_t libc_close(void *context, cell_t handle) {
+    (void)context;
+    return fclose((FILE *)handle) == 0 ? 0 : -1;
+}
+
+static cell_t libc_remove(void *context, const char *path) {
+    (void)context;
+    return remove(path) == 0 ? 0 : -1;
+}
+
+static const forth_vfs_t libc_vfs = {NULL, libc_open, libc_read, libc_write, libc_close, libc_remove};
+
+static forth_vfs_t vfs = {NULL, libc_open, libc_read, libc_write, libc_close, libc_remove};
+static int vfs_on_host = 1;  // check permissions, which name host paths
+
+void forth_vfs_install(const forth_vfs_t *installed) {
+    vfs = installed != NULL ? *installed : libc_vfs;
+    vfs_on_host = installed == NULL;
+}
+
+static cell_t permit_path(const char *path, cell_t read, cell_t write) {
+    return vfs_on_host ? forth_permit_path(path, read, write) : 0;
+}
+
+// Copies a counted path into out as a C string; -1 if it does not fit
+static int file_path(cell_t path_addr, cell_t path_len, char *out) {
+    if (path_addr == 0 || path_len <= 0 || path_len >= PATH_MAX) return -1;
+    memcpy(out, (const char *)path_addr, (size_t)path_len);
+    out[path_len] = '\0';
+    return 0;
+}
+
+forth_pair_t forth_file_open(cell_t path_addr, cell_t path_len, cell_t mode) {
+    char path[PATH_MAX];
+    if (file_path(path_addr, path_len, path) != 0) return (forth_pair_t){0, -1};
+
+    const char *fam = (const char *)mode;
+    cell_t denied = permit_path(path, fam[0] == 'r', fam[0] != 'r' || strchr(fam, '+') != NULL);
+    if (denied != 0) return (forth_pair_t){0, denied};
+    cell_t handle = 0;
+    cell_t ior = vfs.open(vfs.context, path, fam, &handle);
+    return (forth_pair_t){ior == 0 ? handle : 0, ior};
+}
+
+forth_pair_t forth_file_create(cell_t path_addr, cell_t path_len, cell_t mode) {
+    char path[PATH_MAX];
+    if (file_path(path_addr, path_len, path) != 0) return (forth_pair_t){0, -1};
+
+    // Creating truncates, so R/W becomes "w+" rather than "r+"
+    int read = strchr((const char *)mode, '+') != NULL;
+    cell_t denied = permit_path(path, read, 1);
+    if (denied != 0) return (forth_pair_t){0, denied};
+    cell_t handle = 0;
+    cell_t ior = vfs.open(vfs.context, path, read ? "w+" : "w", &handle);
+    return (forth_pair_t){ior == 0 ? handle : 0, ior};
+}
+
+cell_t forth_file_delete(cell_t path_addr, cell_t path_len) {
+    char path[PATH_MAX];
+    if (file_path(path_addr, path_len, path) != 0) return -1;
+
+    cell_t denied = permit_path(path, 0, 1);
+    if (denied != 0) return denied;
+    return vfs.remove(vfs.context, path);
+}
+
+forth_pair_t forth_file_read(cell_t addr, cell_t u, cell_t fileid) {
+    if (fileid == 0) return (forth_pair_t){0, -1};
+    if (u <= 0) return (forth_pair_t){0, 0};
+    cell_t read = vfs.read(vfs.context, fileid, (char *)addr, u);
+    return read < 0 ? (forth_pair_t){0, read} : (forth_pair_t){read, 0};
+}
+
+cell_t forth_file_write(cell_t addr, cell_t u, cell_t fileid) {
+    if (fileid == 0) return -1;
+    if (u <= 0) return 0;
+    return vfs.write(vfs.context, fileid, (const char *)addr, u);
+}
+
+cell_t forth_file_close(cell_t fileid) {
+    if (fileid == 0) return -1;
+    return vfs.close(vfs.context, fileid);
+}
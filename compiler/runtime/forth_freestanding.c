@@ -0,0 +1,228 @@
+/**
+ * Fast Forth Freestanding Runtime Implementation
+ *
+ * Character I/O, pictured numeric output and the little memory support
+ * compiled code needs, with no C library underneath: microcontrollers,
+ * kernels and boot code. Build with -ffreestanding -fno-builtin.
+ */
+
+#include "forth_freestanding.h"
+#include "forth_runtime.h"
+
+// ============================================================================
+// HOOKS
+// ============================================================================
+
+static void discard_char(int c) {
+    (void)c;
+}
+
+static int no_input(void) {
+    return -1;
+}
+
+static unsigned char arena[FORTH_FREESTANDING_HEAP] __attribute__((aligned(16)));
+static size_t arena_used = 0;
+
+static void *arena_sbrk(intptr_t increment) {
+    if (increment < 0 || (size_t)increment > sizeof(arena) - arena_used) return (void *)-1;
+    void *old = &arena[arena_used];
+    arena_used += (size_t)increment;
+    return old;
+}
+
+static forth_hooks_t hooks = { discard_char, no_input, NULL, arena_sbrk };
+
+void forth_set_hooks(const forth_hooks_t *new_hooks) {
+    if (new_hooks->putchar) hooks.putchar = new_hooks->putchar;
+    if (new_hooks->getchar) hooks.getchar = new_hooks->getchar;
+    if (new_hooks->char_ready) hooks.char_ready = new_hooks->char_ready;
+    if (new_hooks->sbrk) hooks.sbrk = new_hooks->sbrk;
+}
+
+// ============================================================================
+// MEMORY (what compilers may call even without a C library)
+// ============================================================================
+
+// Byte loops go through volatile pointers so the compiler cannot turn
+// them back into calls to the functions being defined
+
+void *memcpy(void *dest, const void *src, size_t n) {
+    volatile unsigned char *d = dest;
+    const unsigned char *s = src;
+    while (n--) *d++ = *s++;
+    return dest;
+}
+
+void *memmove(void *dest, const void *src, size_t n) {
+    volatile unsigned char *d = dest;
+    const unsigned char *s = src;
+    if (d < s) {
+        while (n--) *d++ = *s++;
+    } else {
+        while (n--) d[n] = s[n];
+    }
+    return dest;
+}
+
+void *memset(void *dest, int c, size_t n) {
+    volatile unsigned char *d = dest;
+    while (n--) *d++ = (unsigned char)c;
+    return dest;
+}
+
+int memcmp(const void *a, const void *b, size_t n) {
+    const unsigned char *x = a;
+    const unsigned char *y = b;
+    for (; n > 0; n--, x++, y++) {
+        if (*x != *y) return *x - *y;
+    }
+    return 0;
+}
+
+// String literals are allocated once and never freed, so a bump
+// allocator over the sbrk hook is enough
+void *malloc(size_t size) {
+    size_t aligned = (size + 15) & ~(size_t)15;
+    void *block = hooks.sbrk((intptr_t)aligned);
+    return block == (void *)-1 ? NULL : block;
+}
+
+void free(void *ptr) {
+    (void)ptr;
+}
+
+// ============================================================================
+// I/O FOR COMPILED CODE
+// ============================================================================
+
+// Digits of u, most significant first; returns how many were written
+static int format_unsigned(char *buf, ucell_t u) {
+    char digits[20];
+    int count = 0;
+    do {
+        digits[count++] = (char)('0' + u % 10);
+        u /= 10;
+    } while (u != 0);
+    for (int i = 0; i < count; i++) buf[i] = digits[count - 1 - i];
+    return count;
+}
+
+static int format_signed(char *buf, cell_t n) {
+    if (n >= 0) return format_unsigned(buf, (ucell_t)n);
+    buf[0] = '-';
+    return 1 + format_unsigned(buf + 1, -(ucell_t)n);
+}
+
+static void put_chars(const char *s, cell_t len) {
+    for (cell_t i = 0; i < len; i++) hooks.putchar((unsigned char)s[i]);
+}
+
+void forth_io_emit(cell_t c) {
+    hooks.putchar((int)(unsigned char)c);
+}
+
+void forth_io_type(cell_t addr, cell_t len) {
+    if (addr != 0 && len > 0) put_chars((const char *)addr, len);
+}
+
+void forth_io_cr(void) {
+    hooks.putchar('\n');
+}
+
+void forth_io_space(void) {
+    hooks.putchar(' ');
+}
+
+void forth_io_dot(cell_t n) {
+    char buf[21];
+    put_chars(buf, format_signed(buf, n));
+    hooks.putchar(' ');
+}
+
+void forth_io_udot(cell_t u) {
+    char buf[20];
+    put_chars(buf, format_unsigned(buf, (ucell_t)u));
+    hooks.putchar(' ');
+}
+
+void forth_io_dot_r(cell_t n, cell_t width) {
+    char buf[21];
+    int len = format_signed(buf, n);
+    for (cell_t pad = width - len; pad > 0; pad--) hooks.putchar(' ');
+    put_chars(buf, len);
+}
+
+cell_t forth_io_key(void) {
+    return hooks.getchar();
+}
+
+cell_t forth_io_key_ready(void) {
+    return hooks.char_ready == NULL || hooks.char_ready() ? -1 : 0;
+}
+
+// Read a line of at most max characters; the newline is not stored
+cell_t forth_io_accept(cell_t addr, cell_t max) {
+    char *buf = (char *)addr;
+    cell_t count = 0;
+    int c;
+    while (count < max && (c = hooks.getchar()) != -1) {
+        if (c == '\n') break;
+        if (c == '\r') continue;
+        buf[count++] = (char)c;
+    }
+    return count;
+}
+
+#define PICTURED_SIZE 136  // the 39 digits of any double number, a sign and holds
+
+static char pictured[PICTURED_SIZE];
+static size_t pictured_start = PICTURED_SIZE;
+
+void forth_pict_begin(void) {
+    pictured_start = PICTURED_SIZE;
+}
+
+void forth_pict_hold(cell_t c) {
+    if (pictured_start > 0) pictured[--pictured_start] = (char)c;
+}
+
+// Double-cell division by 10 in 32-bit limbs, so no libgcc helper for
+// 128-bit division is needed
+forth_pair_t forth_pict_digit(cell_t lo, cell_t hi) {
+    uint64_t limbs[4] = {
+        (uint64_t)(ucell_t)hi >> 32, (uint32_t)(ucell_t)hi,
+        (uint64_t)(ucell_t)lo >> 32, (uint32_t)(ucell_t)lo,
+    };
+    uint64_t rem = 0;
+    for (int i = 0; i < 4; i++) {
+        uint64_t part = (rem << 32) | limbs[i];
+        limbs[i] = part / 10;
+        rem = part % 10;
+    }
+    forth_pict_hold('0' + (cell_t)rem);
+    forth_pair_t ud = {
+        (cell_t)((limbs[2] << 32) | limbs[3]),
+        (cell_t)((limbs[0] << 32) | limbs[1]),
+    };
+    return ud;
+}
+
+forth_pair_t forth_pict_digits(cell_t lo, cell_t hi) {
+    forth_pair_t ud = forth_pict_digit(lo, hi);
+    while (ud.lo != 0 || ud.hi != 0) {
+        ud = forth_pict_digit(ud.lo, ud.hi);
+    }
+    return ud;
+}
+
+void forth_pict_sign(cell_t n) {
+    if (n < 0) forth_pict_hold('-');
+}
+
+forth_pair_t forth_pict_end(cell_t lo, cell_t hi) {
+    (void)lo;
+    (void)hi;
+    forth_pair_t string = { (cell_t)&pictured[pictured_start], (cell_t)(PICTURED_SIZE - pictured_start) };
+    return string;
+}
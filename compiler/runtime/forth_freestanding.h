@@ -0,0 +1,40 @@
+/**
+ * Fast Forth Freestanding Runtime
+ *
+ * The libc-free layer linked by `--freestanding` builds in place of
+ * forth_runtime.c. Everything compiled code needs from the outside world
+ * goes through the hooks below, which the host installs before calling
+ * the Forth entry point.
+ */
+
+#ifndef FORTH_FREESTANDING_H
+#define FORTH_FREESTANDING_H
+
+#include <stdint.h>
+#include <stddef.h>
+
+typedef struct {
+    // Write one character (EMIT, TYPE, ., CR and friends)
+    void (*putchar)(int c);
+
+    // Read one character, or -1 when there is no more input (KEY, ACCEPT)
+    int (*getchar)(void);
+
+    // Nonzero when getchar would not block (KEY?); NULL means always ready
+    int (*char_ready)(void);
+
+    // Grow the heap by increment bytes and return the old break, or
+    // (void *)-1 when memory is exhausted (string literals)
+    void *(*sbrk)(intptr_t increment);
+} forth_hooks_t;
+
+// Install hooks; NULL members keep the defaults
+void forth_set_hooks(const forth_hooks_t *hooks);
+
+// The defaults discard output, have no input and allocate from a static
+// arena of this many bytes
+#ifndef FORTH_FREESTANDING_HEAP
+#define FORTH_FREESTANDING_HEAP 16384
+#endif
+
+#endif // FORTH_FREESTANDING_H
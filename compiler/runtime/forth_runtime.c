@@ -3,13 +3,26 @@
  * Stream 6: Core primitives and VM implementation
  *
  * Performance-critical primitives in C for maximum speed
+ *
+ * This is the core component: the VM, console I/O and pictured numerics.
+ * Blocks are in forth_files.c and the process environment accessors in
+ * forth_system.c, so executables link only the components they use.
  */
 
+#define _GNU_SOURCE  // realpath and the ucontext register names under -std=c11
 #include "forth_runtime.h"
 #include <stdlib.h>
 #include <string.h>
 #include <stdio.h>
 #include <ctype.h>
+#include <setjmp.h>
+#include <signal.h>
+#include <stdatomic.h>
+#include <stdint.h>
+#include <ucontext.h>
+#include <limits.h>
+#include <poll.h>
+#include <unistd.h>
 
 // ============================================================================
 // VM LIFECYCLE
@@ -545,6 +558,724 @@ void forth_dump_memory(forth_vm_t *vm, cell_t addr, size_t count) {
     }
 }
 
+// ============================================================================
+// PROCESS ENVIRONMENT (command line arguments, environment variables)
+// ============================================================================
+
+// The accessors are in forth_system.c
+int forth_argc = 0;
+char **forth_argv = NULL;
+
+void forth_runtime_init(int argc, char **argv) {
+    forth_argc = argc;
+    forth_argv = argv;
+}
+
+// ============================================================================
+// PERMISSIONS
+// ============================================================================
+
+// Directories are kept canonical, so a path is under one exactly when its
+// canonical form starts with it; ".." and symlinks cannot step outside.
+
+typedef struct {
+    char dir[PATH_MAX];
+    int write;  // writes allowed, otherwise reads
+} permitted_dir_t;
+
+static int permissions_restricted = 0;
+static int permissions_run = 0;
+static permitted_dir_t permitted_dirs[FORTH_MAX_PERMITTED_DIRS];
+static int permitted_count = 0;
+
+void forth_permissions_restrict(cell_t allow_run) {
+    permissions_restricted = 1;
+    permissions_run = allow_run != 0;
+}
+
+cell_t forth_permissions_allow(const char *dir, cell_t write) {
+    if (permitted_count == FORTH_MAX_PERMITTED_DIRS) return -1;
+    permitted_dir_t *permitted = &permitted_dirs[permitted_count];
+    if (realpath(dir, permitted->dir) == NULL) return -1;
+    permitted->write = write != 0;
+    permitted_count++;
+    return 0;
+}
+
+void forth_permissions_reset(void) {
+    permissions_restricted = 0;
+    permissions_run = 0;
+    permitted_count = 0;
+}
+
+// Canonical form of path, which need not exist yet if its directory does
+static int canonical_path(const char *path, char *out) {
+    if (realpath(path, out) != NULL) return 0;
+
+    char dir[PATH_MAX];
+    const char *slash = strrchr(path, '/');
+    const char *name = slash ? slash + 1 : path;
+    size_t dir_len = slash ? (size_t)(slash - path) : 0;
+    if (dir_len >= sizeof(dir) || *name == '\0' || strcmp(name, ".") == 0 || strcmp(name, "..") == 0) return -1;
+    if (slash == NULL) {
+        strcpy(dir, ".");
+    } else if (dir_len == 0) {
+        strcpy(dir, "/");
+    } else {
+        memcpy(dir, path, dir_len);
+        dir[dir_len] = '\0';
+    }
+    if (realpath(dir, out) == NULL) return -1;
+    size_t len = strlen(out);
+    if (len + 1 + strlen(name) >= PATH_MAX) return -1;
+    if (out[len - 1] != '/') out[len++] = '/';
+    strcpy(out + len, name);
+    return 0;
+}
+
+static int under(const char *path, const char *dir) {
+    size_t len = strlen(dir);
+    if (strncmp(path, dir, len) != 0) return 0;
+    return path[len] == '\0' || path[len] == '/' || dir[len - 1] == '/';
+}
+
+cell_t forth_permit_path(const char *path, cell_t read, cell_t write) {
+    if (!permissions_restricted) return 0;
+
+    char canonical[PATH_MAX];
+    int readable = 0, writable = 0;
+    if (canonical_path(path, canonical) == 0) {
+        for (int i = 0; i < permitted_count; i++) {
+            if (!under(canonical, permitted_dirs[i].dir)) continue;
+            if (permitted_dirs[i].write) writable = 1;
+            else readable = 1;
+        }
+    }
+    if (read && !readable) {
+        fprintf(stderr, "permission denied: cannot read '%s' (allow it with --allow-read)\n", path);
+        return FORTH_PERMISSION_DENIED;
+    }
+    if (write && !writable) {
+        fprintf(stderr, "permission denied: cannot write '%s' (allow it with --allow-write)\n", path);
+        return FORTH_PERMISSION_DENIED;
+    }
+    return 0;
+}
+
+cell_t forth_permit_run(const char *command) {
+    if (!permissions_restricted || permissions_run) return 0;
+    fprintf(stderr, "permission denied: cannot run '%s' (allow it with --allow-run)\n", command);
+    return FORTH_PERMISSION_DENIED;
+}
+
+cell_t forth_cstring_length(cell_t addr) {
+    return addr == 0 ? 0 : (cell_t)strlen((const char *)addr);
+}
+
+// ============================================================================
+// I/O FOR COMPILED CODE
+// ============================================================================
+
+// Input bypasses stdio buffering so KEY? sees exactly what KEY would read
+
+void forth_io_emit(cell_t c) {
+    putchar((char)c);
+    fflush(stdout);
+}
+
+void forth_io_type(cell_t addr, cell_t len) {
+    if (addr != 0 && len > 0) fwrite((const char *)addr, 1, (size_t)len, stdout);
+    fflush(stdout);
+}
+
+void forth_io_cr(void) {
+    putchar('\n');
+    fflush(stdout);
+}
+
+void forth_io_space(void) {
+    putchar(' ');
+    fflush(stdout);
+}
+
+void forth_io_dot(cell_t n) {
+    printf("%ld ", (long)n);
+    fflush(stdout);
+}
+
+void forth_io_udot(cell_t u) {
+    printf("%lu ", (unsigned long)(ucell_t)u);
+    fflush(stdout);
+}
+
+void forth_io_dot_r(cell_t n, cell_t width) {
+    printf("%*ld", (int)(width > 0 ? width : 0), (long)n);
+    fflush(stdout);
+}
+
+cell_t forth_io_key(void) {
+    unsigned char c;
+    return read(STDIN_FILENO, &c, 1) == 1 ? (cell_t)c : -1;
+}
+
+cell_t forth_io_key_ready(void) {
+    struct pollfd fd = { .fd = STDIN_FILENO, .events = POLLIN, .revents = 0 };
+    return poll(&fd, 1, 0) > 0 ? -1 : 0;
+}
+
+// Read a line of at most max characters; the newline is not stored
+cell_t forth_io_accept(cell_t addr, cell_t max) {
+    char *buf = (char *)addr;
+    cell_t count = 0;
+    unsigned char c;
+    while (count < max && read(STDIN_FILENO, &c, 1) == 1) {
+        if (c == '\n') break;
+        if (c == '\r') continue;
+        buf[count++] = (char)c;
+    }
+    return count;
+}
+
+#define PICTURED_SIZE 136  // the 39 digits of any double number, a sign and holds
+
+static char pictured[PICTURED_SIZE];
+static size_t pictured_start = PICTURED_SIZE;
+
+typedef unsigned __int128 forth_udouble_t;
+
+static forth_udouble_t make_udouble(cell_t lo, cell_t hi) {
+    return ((forth_udouble_t)(ucell_t)hi << 64) | (ucell_t)lo;
+}
+
+static forth_pair_t split_udouble(forth_udouble_t ud) {
+    forth_pair_t pair = { (cell_t)(ucell_t)ud, (cell_t)(ucell_t)(ud >> 64) };
+    return pair;
+}
+
+void forth_pict_begin(void) {
+    pictured_start = PICTURED_SIZE;
+}
+
+void forth_pict_hold(cell_t c) {
+    if (pictured_start > 0) pictured[--pictured_start] = (char)c;
+}
+
+forth_pair_t forth_pict_digit(cell_t lo, cell_t hi) {
+    forth_udouble_t ud = make_udouble(lo, hi);
+    forth_pict_hold('0' + (cell_t)(ud % 10));
+    return split_udouble(ud / 10);
+}
+
+forth_pair_t forth_pict_digits(cell_t lo, cell_t hi) {
+    forth_pair_t ud = forth_pict_digit(lo, hi);
+    while (ud.lo != 0 || ud.hi != 0) {
+        ud = forth_pict_digit(ud.lo, ud.hi);
+    }
+    return ud;
+}
+
+void forth_pict_sign(cell_t n) {
+    if (n < 0) forth_pict_hold('-');
+}
+
+forth_pair_t forth_pict_end(cell_t lo, cell_t hi) {
+    (void)lo;
+    (void)hi;
+    forth_pair_t string = { (cell_t)&pictured[pictured_start], (cell_t)(PICTURED_SIZE - pictured_start) };
+    return string;
+}
+
+// ============================================================================
+// HEAP (ANS Memory word set)
+// ============================================================================
+
+// Each block starts with a header; the address handed out follows it
+typedef struct heap_block {
+    struct heap_block *prev;   // live blocks, when tracked
+    struct heap_block *next;
+    size_t size;               // bytes asked for
+    uint32_t serial;           // allocation number, when tracked
+    uint32_t flags;
+} heap_block_t;
+
+#define HEAP_TRACKED 1
+#define HEAP_REGION 2
+
+static _Thread_local heap_block_t heap_live;   // sentinel of the tracked list
+static _Thread_local int heap_debug = 0;
+static _Thread_local uint32_t heap_serial = 0;
+static _Thread_local unsigned char *heap_region = NULL;
+static _Thread_local size_t heap_region_size = 0;
+static _Thread_local size_t heap_region_used = 0;
+static _Thread_local heap_block_t *heap_region_last = NULL;
+
+static heap_block_t *heap_live_list(void) {
+    if (heap_live.next == NULL) heap_live.next = heap_live.prev = &heap_live;
+    return &heap_live;
+}
+
+static void heap_track(heap_block_t *block) {
+    heap_block_t *live = heap_live_list();
+    block->serial = ++heap_serial;
+    block->flags |= HEAP_TRACKED;
+    block->prev = live->prev;
+    block->next = live;
+    live->prev->next = block;
+    live->prev = block;
+}
+
+static void heap_untrack(heap_block_t *block) {
+    block->prev->next = block->next;
+    block->next->prev = block->prev;
+    block->flags &= ~(uint32_t)HEAP_TRACKED;
+}
+
+static bool heap_is_live(heap_block_t *block) {
+    heap_block_t *live = heap_live_list();
+    for (heap_block_t *b = live->next; b != live; b = b->next) {
+        if (b == block) return true;
+    }
+    return false;
+}
+
+static size_t heap_region_need(size_t size) {
+    return (sizeof(heap_block_t) + size + 15) & ~(size_t)15;
+}
+
+static heap_block_t *heap_block_new(cell_t u) {
+    if (u < 0 || (size_t)u > SIZE_MAX / 2) return NULL;
+    size_t size = (size_t)u;
+    heap_block_t *block;
+    if (heap_region != NULL) {
+        size_t need = heap_region_need(size);
+        if (need > heap_region_size - heap_region_used) return NULL;
+        block = (heap_block_t *)(heap_region + heap_region_used);
+        heap_region_used += need;
+        heap_region_last = block;
+        block->flags = HEAP_REGION;
+    } else {
+        block = malloc(sizeof(heap_block_t) + size);
+        if (block == NULL) return NULL;
+        block->flags = 0;
+    }
+    block->size = size;
+    block->serial = 0;
+    block->prev = block->next = NULL;
+    if (heap_debug) heap_track(block);
+    return block;
+}
+
+static void heap_block_delete(heap_block_t *block) {
+    if (block->flags & HEAP_TRACKED) heap_untrack(block);
+    if (!(block->flags & HEAP_REGION)) {
+        free(block);
+    } else if (block == heap_region_last) {
+        heap_region_used = (size_t)((unsigned char *)block - heap_region);
+        heap_region_last = NULL;
+    }
+}
+
+// Whether addr is a block FREE and RESIZE may take; in debug mode only
+// live tracked blocks are
+static heap_block_t *heap_block_of(cell_t addr) {
+    if (addr == 0) return NULL;
+    heap_block_t *block = (heap_block_t *)addr - 1;
+    if (heap_debug && !heap_is_live(block)) return NULL;
+    return block;
+}
+
+forth_pair_t forth_allocate(cell_t u) {
+    heap_block_t *block = heap_block_new(u);
+    forth_pair_t result = { block ? (cell_t)(block + 1) : 0, block ? 0 : HEAP_IOR_ALLOCATE };
+    return result;
+}
+
+cell_t forth_free(cell_t addr) {
+    heap_block_t *block = heap_block_of(addr);
+    if (block == NULL) return HEAP_IOR_FREE;
+    heap_block_delete(block);
+    return 0;
+}
+
+// On failure the original block is untouched and its address returned
+forth_pair_t forth_resize(cell_t addr, cell_t u) {
+    forth_pair_t result = { addr, HEAP_IOR_RESIZE };
+    if (addr == 0) return forth_allocate(u);
+    heap_block_t *block = heap_block_of(addr);
+    if (block == NULL || u < 0) return result;
+
+    // The last block of a region grows or shrinks in place
+    if (block == heap_region_last) {
+        size_t start = (size_t)((unsigned char *)block - heap_region);
+        if ((size_t)u <= SIZE_MAX / 2 && heap_region_need((size_t)u) <= heap_region_size - start) {
+            heap_region_used = start + heap_region_need((size_t)u);
+            block->size = (size_t)u;
+            result.hi = 0;
+            return result;
+        }
+    }
+
+    heap_block_t *moved = heap_block_new(u);
+    if (moved == NULL) return result;
+    memcpy(moved + 1, block + 1, block->size < moved->size ? block->size : moved->size);
+    heap_block_delete(block);
+    result.lo = (cell_t)(moved + 1);
+    result.hi = 0;
+    return result;
+}
+
+cell_t forth_heap_configure(cell_t region_size) {
+    heap_block_t *live = heap_live_list();
+    for (heap_block_t *b = live->next; b != live;) {
+        heap_block_t *next = b->next;
+        if (b->flags & HEAP_REGION) heap_untrack(b);
+        b = next;
+    }
+    free(heap_region);
+    heap_region = NULL;
+    heap_region_size = heap_region_used = 0;
+    heap_region_last = NULL;
+
+    if (region_size <= 0) return 0;
+    heap_region = malloc((size_t)region_size);
+    if (heap_region == NULL) return HEAP_IOR_ALLOCATE;
+    heap_region_size = (size_t)region_size;
+    return 0;
+}
+
+void forth_heap_set_debug(cell_t on) {
+    heap_debug = on != 0;
+}
+
+cell_t forth_heap_leaks(cell_t *addrs, cell_t *sizes, cell_t *serials, cell_t max) {
+    heap_block_t *live = heap_live_list();
+    cell_t count = 0;
+    for (heap_block_t *b = live->next; b != live; b = b->next, count++) {
+        if (count < max) {
+            addrs[count] = (cell_t)(b + 1);
+            sizes[count] = (cell_t)b->size;
+            serials[count] = (cell_t)b->serial;
+        }
+    }
+    return count;
+}
+
+void forth_heap_reset(void) {
+    forth_heap_configure(0);
+    heap_block_t *live = heap_live_list();
+    while (live->next != live) {
+        heap_block_delete(live->next);
+    }
+    heap_debug = 0;
+    heap_serial = 0;
+}
+
+// ============================================================================
+// CANCELLATION (stopping compiled code from the host)
+// ============================================================================
+
+typedef struct forth_guard {
+    jmp_buf env;
+    const volatile cell_t *cancel;
+    cell_t fuel;               // polls left; negative for no limit
+    cell_t thrown;             // code passed to forth_throw
+    const char *stack_top;     // the guarded code's frames lie below
+} forth_guard_t;
+
+// Innermost guarded call on this thread, NULL while host code runs
+static _Thread_local forth_guard_t *guard_current = NULL;
+
+static void faults_install(void);
+
+cell_t forth_guarded_call(const volatile cell_t *cancel, cell_t fuel, const void *entry,
+                          cell_t argc, const cell_t *args, cell_t *result) {
+    if (argc < 0 || argc > 6) return -1;
+    faults_install();
+
+    forth_guard_t guard;
+    forth_guard_t *outer = guard_current;
+    guard.cancel = cancel;
+    guard.fuel = fuel;
+    guard.stack_top = (const char *)&guard;
+    switch (setjmp(guard.env)) {
+        case 0: break;
+        case 1: guard_current = outer; return FORTH_CANCELLED;
+        case 2: guard_current = outer; return FORTH_OUT_OF_FUEL;
+        case 3: guard_current = outer; return FORTH_TRAPPED;
+        default: guard_current = outer; return guard.thrown;
+    }
+    guard_current = &guard;
+
+    const cell_t *a = args;
+    cell_t value;
+    switch (argc) {
+        case 0: value = ((cell_t (*)(void))entry)(); break;
+        case 1: value = ((cell_t (*)(cell_t))entry)(a[0]); break;
+        case 2: value = ((cell_t (*)(cell_t, cell_t))entry)(a[0], a[1]); break;
+        case 3: value = ((cell_t (*)(cell_t, cell_t, cell_t))entry)(a[0], a[1], a[2]); break;
+        case 4: value = ((cell_t (*)(cell_t, cell_t, cell_t, cell_t))entry)(a[0], a[1], a[2], a[3]); break;
+        case 5: value = ((cell_t (*)(cell_t, cell_t, cell_t, cell_t, cell_t))entry)(a[0], a[1], a[2], a[3], a[4]); break;
+        default: value = ((cell_t (*)(cell_t, cell_t, cell_t, cell_t, cell_t, cell_t))entry)(a[0], a[1], a[2], a[3], a[4], a[5]); break;
+    }
+
+    guard_current = outer;
+    *result = value;
+    return 0;
+}
+
+void forth_poll(void) {
+    forth_guard_t *guard = guard_current;
+    if (guard == NULL) return;
+    if (*guard->cancel != 0) longjmp(guard->env, 1);
+    if (guard->fuel >= 0 && guard->fuel-- == 0) longjmp(guard->env, 2);
+}
+
+void forth_throw(cell_t code) {
+    forth_guard_t *guard = guard_current;
+    if (guard == NULL) {
+        fprintf(stderr, "uncaught exception %ld\n", (long)code);
+        abort();
+    }
+    guard->thrown = code;
+    longjmp(guard->env, 4);
+}
+
+void *forth_guard_suspend(void) {
+    forth_guard_t *guard = guard_current;
+    guard_current = NULL;
+    return guard;
+}
+
+void forth_guard_resume(void *guard) {
+    guard_current = (forth_guard_t *)guard;
+}
+
+// ============================================================================
+// RECORDING
+// ============================================================================
+
+// Writers claim slots with one atomic counter, so threads never wait on
+// each other; an event may be torn only if the ring wraps onto a slot
+// still being written, which a post-mortem can live with.
+
+static forth_record_t *record_ring = NULL;
+static cell_t record_capacity = 0;
+static atomic_long record_next = 0;   // events recorded since start
+static atomic_int record_on = 0;
+static _Thread_local cell_t record_depth = 0;
+
+typedef struct {
+    char *name;
+    cell_t line;  // where its body starts, 0 if unknown
+} record_label_t;
+
+static record_label_t *record_labels = NULL;
+static cell_t record_label_count = 0;
+
+void forth_record_label(cell_t word, const char *name, cell_t line) {
+    if (word < 0) return;
+    if (word >= record_label_count) {
+        cell_t count = word + 64;
+        record_label_t *labels = realloc(record_labels, (size_t)count * sizeof(record_label_t));
+        if (labels == NULL) return;
+        memset(labels + record_label_count, 0, (size_t)(count - record_label_count) * sizeof(record_label_t));
+        record_labels = labels;
+        record_label_count = count;
+    }
+    free(record_labels[word].name);
+    record_labels[word].name = strdup(name);
+    record_labels[word].line = line;
+}
+
+// Only async-signal-safe calls from here on: the dump runs in a handler
+static void record_write(const char *text) {
+    ssize_t ignored = write(STDERR_FILENO, text, strlen(text));
+    (void)ignored;
+}
+
+static void record_write_cell(cell_t value) {
+    char digits[24];
+    int at = sizeof(digits);
+    digits[--at] = '\0';
+    unsigned long magnitude = value < 0 ? -(unsigned long)value : (unsigned long)value;
+    do {
+        digits[--at] = (char)('0' + magnitude % 10);
+        magnitude /= 10;
+    } while (magnitude != 0);
+    if (value < 0) digits[--at] = '-';
+    record_write(digits + at);
+}
+
+static void record_dump(int sig) {
+    record_write("\nfatal signal ");
+    record_write_cell(sig);
+    record_write(", last words entered and exited:\n");
+
+    cell_t total = atomic_load(&record_next);
+    cell_t count = total < record_capacity ? total : record_capacity;
+    for (cell_t i = total - count; i < total; i++) {
+        const forth_record_t *event = &record_ring[i % record_capacity];
+        for (cell_t indent = 0; indent <= event->depth && indent < 32; indent++) record_write("  ");
+        record_write(event->exit ? "<- " : "-> ");
+        const record_label_t *label = event->word < record_label_count ? &record_labels[event->word] : NULL;
+        if (label != NULL && label->name != NULL) {
+            record_write(label->name);
+        } else {
+            record_write("word ");
+            record_write_cell(event->word);
+        }
+        record_write(" (");
+        if (event->count > FORTH_RECORD_CELLS) record_write(" ...");
+        cell_t shown = event->count < FORTH_RECORD_CELLS ? event->count : FORTH_RECORD_CELLS;
+        for (cell_t j = 0; j < shown; j++) {
+            record_write(" ");
+            record_write_cell(event->cells[j]);
+        }
+        record_write(" )");
+        if (label != NULL && label->line > 0) {
+            record_write("  line ");
+            record_write_cell(label->line);
+        }
+        record_write("\n");
+    }
+}
+
+cell_t forth_record_start(cell_t capacity) {
+    forth_record_stop();
+    if (capacity <= 0) return -1;
+    if (capacity != record_capacity) {
+        forth_record_t *ring = realloc(record_ring, (size_t)capacity * sizeof(forth_record_t));
+        if (ring == NULL) return -1;
+        record_ring = ring;
+        record_capacity = capacity;
+    }
+    atomic_store(&record_next, 0);
+    record_depth = 0;
+    faults_install();
+    atomic_store(&record_on, 1);
+    return 0;
+}
+
+void forth_record_stop(void) {
+    atomic_store(&record_on, 0);
+}
+
+static void record_event(cell_t word, cell_t exit, cell_t count, cell_t a, cell_t b, cell_t c, cell_t d) {
+    forth_record_t *event = &record_ring[atomic_fetch_add(&record_next, 1) % record_capacity];
+    event->word = word;
+    event->exit = exit;
+    event->depth = record_depth;
+    event->count = count;
+    event->cells[0] = a;
+    event->cells[1] = b;
+    event->cells[2] = c;
+    event->cells[3] = d;
+}
+
+void forth_record_enter(cell_t word, cell_t count, cell_t a, cell_t b, cell_t c, cell_t d) {
+    if (!atomic_load_explicit(&record_on, memory_order_relaxed)) return;
+    record_event(word, 0, count, a, b, c, d);
+    record_depth++;
+}
+
+void forth_record_exit(cell_t word, cell_t count, cell_t a, cell_t b, cell_t c, cell_t d) {
+    if (!atomic_load_explicit(&record_on, memory_order_relaxed)) return;
+    if (record_depth > 0) record_depth--;
+    record_event(word, 1, count, a, b, c, d);
+}
+
+cell_t forth_record_events(forth_record_t *events, cell_t max) {
+    cell_t total = atomic_load(&record_next);
+    cell_t count = total < record_capacity ? total : record_capacity;
+    if (events == NULL) return count;
+    if (count > max) count = max;
+    for (cell_t i = 0; i < count; i++) {
+        events[i] = record_ring[(total - count + i) % record_capacity];
+    }
+    return count;
+}
+
+// ============================================================================
+// FAULTS
+// ============================================================================
+
+// One handler serves the fault signals for the rest of the process. A
+// fault in guarded code unwinds to its guarded call, as a cancellation
+// does, after noting where it happened. Any other fault dumps the running
+// recording, if there is one, and goes on to the handler installed before.
+
+static const int fault_signals[] = { SIGSEGV, SIGBUS, SIGFPE, SIGILL };
+#define FAULT_SIGNALS (sizeof(fault_signals) / sizeof(fault_signals[0]))
+static struct sigaction fault_saved[FAULT_SIGNALS];
+static atomic_int faults_installed = 0;
+
+static _Thread_local forth_trap_t trap_last;
+
+#if defined(__linux__) && defined(__x86_64__)
+#define FAULT_PC(uc) ((uc)->uc_mcontext.gregs[REG_RIP])
+#define FAULT_SP(uc) ((uc)->uc_mcontext.gregs[REG_RSP])
+#define FAULT_FP(uc) ((uc)->uc_mcontext.gregs[REG_RBP])
+#elif defined(__linux__) && defined(__aarch64__)
+#define FAULT_PC(uc) ((uc)->uc_mcontext.pc)
+#define FAULT_SP(uc) ((uc)->uc_mcontext.sp)
+#define FAULT_FP(uc) ((uc)->uc_mcontext.regs[29])
+#endif
+
+// Note the faulting pc and walk the frame pointers up to the guarded call.
+// Every frame starts with the caller's frame pointer and the return
+// address; only stack between the fault and stack_top is ever read.
+static void fault_frames(forth_trap_t *trap, const void *context, const char *stack_top) {
+#ifdef FAULT_PC
+    const ucontext_t *uc = context;
+    trap->pc = (cell_t)FAULT_PC(uc);
+    uintptr_t sp = (uintptr_t)FAULT_SP(uc);
+    uintptr_t fp = (uintptr_t)FAULT_FP(uc);
+    while (trap->depth < FORTH_TRAP_FRAMES && fp >= sp && fp % sizeof(cell_t) == 0 &&
+           fp + 2 * sizeof(cell_t) <= (uintptr_t)stack_top) {
+        const cell_t *frame = (const cell_t *)fp;
+        trap->returns[trap->depth++] = frame[1];
+        sp = fp + 2 * sizeof(cell_t);
+        fp = (uintptr_t)frame[0];
+    }
+#else
+    (void)trap;
+    (void)context;
+    (void)stack_top;
+#endif
+}
+
+static void fault_handler(int sig, siginfo_t *info, void *context) {
+    forth_guard_t *guard = guard_current;
+    if (guard != NULL) {
+        memset(&trap_last, 0, sizeof(trap_last));
+        trap_last.signal = sig;
+        trap_last.address = (cell_t)info->si_addr;
+        fault_frames(&trap_last, context, guard->stack_top);
+        // SA_NODEFER left the signal unblocked, so jumping out is enough
+        longjmp(guard->env, 3);
+    }
+
+    if (atomic_exchange(&record_on, 0)) record_dump(sig);
+    for (size_t i = 0; i < FAULT_SIGNALS; i++) {
+        if (fault_signals[i] == sig) sigaction(sig, &fault_saved[i], NULL);
+    }
+    // The faulting instruction meets that handler on return
+}
+
+static void faults_install(void) {
+    if (atomic_exchange(&faults_installed, 1)) return;
+    struct sigaction action;
+    memset(&action, 0, sizeof(action));
+    action.sa_sigaction = fault_handler;
+    action.sa_flags = SA_SIGINFO | SA_NODEFER;
+    sigemptyset(&action.sa_mask);
+    for (size_t i = 0; i < FAULT_SIGNALS; i++) sigaction(fault_signals[i], &action, &fault_saved[i]);
+}
+
+void forth_last_trap(forth_trap_t *trap) {
+    *trap = trap_last;
+}
+
 // ============================================================================
 // FFI SUPPORT (C function calling)
 // ============================================================================
@@ -265,6 +265,214 @@ typedef cell_t (*ffi_func_t)(cell_t*, int);
 int forth_ffi_call(forth_vm_t *vm, void *func_ptr, int arg_count);
 void forth_ffi_register(forth_vm_t *vm, const char *name, void *func_ptr);
 
+// ============================================================================
+// PROCESS ENVIRONMENT
+// ============================================================================
+
+// Called by the generated C main of an AOT executable before Forth main
+void forth_runtime_init(int argc, char **argv);
+extern int forth_argc;     // as passed to forth_runtime_init
+extern char **forth_argv;
+cell_t forth_arg_count(void);
+cell_t forth_arg(cell_t n);
+cell_t forth_getenv(cell_t name_addr, cell_t name_len);
+cell_t forth_cstring_length(cell_t addr);
+
+// ============================================================================
+// PERMISSIONS (file and command access of the running program)
+// ============================================================================
+
+#define FORTH_PERMISSION_DENIED -257  // ior of a refused file or command
+#define FORTH_MAX_PERMITTED_DIRS 32
+
+// Unrestricted until forth_permissions_restrict; from then on files are
+// accessible only under directories passed to forth_permissions_allow,
+// and SYSTEM runs commands only if allow_run is nonzero. A refused access
+// is reported on stderr, naming the path or command, and fails with
+// FORTH_PERMISSION_DENIED. Set before running code, not during.
+void forth_permissions_restrict(cell_t allow_run);
+cell_t forth_permissions_allow(const char *dir, cell_t write);  // reads, or writes; -1 if dir is unusable
+void forth_permissions_reset(void);                           // unrestricted again
+cell_t forth_permit_path(const char *path, cell_t read, cell_t write);  // 0 or FORTH_PERMISSION_DENIED
+cell_t forth_permit_run(const char *command);                 // 0 or FORTH_PERMISSION_DENIED
+
+// ============================================================================
+// I/O FOR COMPILED CODE (cells in, cells out; decimal only)
+// ============================================================================
+
+// Two cells returned in registers: a double number or a c-addr u pair
+typedef struct {
+    cell_t lo;
+    cell_t hi;
+} forth_pair_t;
+
+void forth_io_emit(cell_t c);                           // EMIT
+void forth_io_type(cell_t addr, cell_t len);            // TYPE
+void forth_io_cr(void);                                 // CR
+void forth_io_space(void);                              // SPACE
+void forth_io_dot(cell_t n);                            // .
+void forth_io_udot(cell_t u);                           // U.
+void forth_io_dot_r(cell_t n, cell_t width);            // .R
+cell_t forth_io_key(void);                              // KEY (-1 at end of input)
+cell_t forth_io_key_ready(void);                        // KEY?
+cell_t forth_io_accept(cell_t addr, cell_t max);        // ACCEPT
+
+// Pictured numeric output
+void forth_pict_begin(void);                            // <#
+forth_pair_t forth_pict_digit(cell_t lo, cell_t hi);    // #
+forth_pair_t forth_pict_digits(cell_t lo, cell_t hi);   // #S
+void forth_pict_hold(cell_t c);                         // HOLD
+void forth_pict_sign(cell_t n);                         // SIGN
+forth_pair_t forth_pict_end(cell_t lo, cell_t hi);      // #>
+
+// ============================================================================
+// HEAP (ANS Memory word set)
+// ============================================================================
+
+#define HEAP_IOR_ALLOCATE -59  // THROW codes of the standard
+#define HEAP_IOR_FREE -60
+#define HEAP_IOR_RESIZE -61
+
+// Blocks come from malloc, or from one fixed region once configured.
+// Freeing a region block gives its space back only if it is the last one
+// allocated; the rest is reclaimed with the region. In debug mode every
+// live block is tracked, FREE and RESIZE of an address that is not live
+// fail instead of corrupting the heap, and the blocks never freed can be
+// listed. The settings, the region and the tracked blocks are per thread.
+forth_pair_t forth_allocate(cell_t u);                  // ALLOCATE ( -- a-addr ior )
+cell_t forth_free(cell_t addr);                         // FREE ( -- ior )
+forth_pair_t forth_resize(cell_t addr, cell_t u);       // RESIZE ( -- a-addr ior )
+cell_t forth_heap_configure(cell_t region_size);        // 0 = malloc; ior
+void forth_heap_set_debug(cell_t on);
+// Live tracked blocks, oldest first: the count, with the first max written out
+cell_t forth_heap_leaks(cell_t *addrs, cell_t *sizes, cell_t *serials, cell_t max);
+void forth_heap_reset(void);                            // free tracked blocks, back to malloc
+
+// ============================================================================
+// CANCELLATION (stopping compiled code from the host)
+// ============================================================================
+
+#define FORTH_CANCELLED -28     // THROW code of a user interrupt
+#define FORTH_OUT_OF_FUEL -256  // first system-defined THROW code
+#define FORTH_TRAPPED -258      // a fault in guarded code
+#define FORTH_TRAP_FRAMES 32
+
+typedef struct {
+    cell_t signal;
+    cell_t address;   // the address a memory fault touched
+    cell_t pc;        // where the fault happened, 0 if unknown
+    cell_t depth;     // return addresses found
+    cell_t returns[FORTH_TRAP_FRAMES];  // innermost first
+} forth_trap_t;
+
+// Runs a compiled word taking argc cells (at most 6) and stores the cell it
+// returns; 0 on return, FORTH_CANCELLED once cancelled, FORTH_OUT_OF_FUEL
+// after fuel polls (negative for no limit). Code compiled with polls calls
+// forth_poll at word entry and at each loop head, which unwinds to the
+// innermost guarded call on its thread once that call's flag is nonzero or
+// its fuel is spent. Code blocked in the runtime, e.g. on a channel, stops
+// at its next poll, and tasks it spawned run unguarded.
+cell_t forth_guarded_call(const volatile cell_t *cancel, cell_t fuel, const void *entry,
+                          cell_t argc, const cell_t *args, cell_t *result);
+void forth_poll(void);
+// THROW: unwinds to the innermost guarded call on this thread, which
+// returns code (nonzero, and not one of the statuses above). With no
+// guarded call in progress it aborts the process.
+void forth_throw(cell_t code);
+// Host code called from a guarded word suspends the guard, so a poll never
+// unwinds through its frames
+void *forth_guard_suspend(void);
+void forth_guard_resume(void *guard);
+// A fault in guarded code, such as a trap on division by zero or a bad
+// memory access, unwinds to the guarded call, which returns FORTH_TRAPPED.
+// forth_last_trap then tells what happened on this thread: the signal, the
+// faulting pc and the return addresses on the frame pointer chain up to
+// the guarded call (Linux on x86-64 and AArch64; elsewhere only the
+// signal). A fault anywhere else goes to the handler in place before.
+void forth_last_trap(forth_trap_t *trap);
+
+// ============================================================================
+// RECORDING (word entries and exits of compiled code, for post-mortems)
+// ============================================================================
+
+#define FORTH_RECORD_CELLS 4  // top cells kept of each event's stack
+
+typedef struct {
+    cell_t word;    // index given to forth_record_label
+    cell_t exit;    // nonzero for an exit, zero for an entry
+    cell_t depth;   // words entered and not yet exited on its thread
+    cell_t count;   // cells taken (entry) or left (exit)
+    cell_t cells[FORTH_RECORD_CELLS];  // the top min(count, 4), deepest first
+} forth_record_t;
+
+// Code compiled with recording calls forth_record_enter on entry to every
+// word and forth_record_exit before it returns, passing its top cells
+// deepest first, then zeros. Between forth_record_start and forth_record_stop the last
+// capacity events are kept, from every thread; other calls are ignored.
+// While recording, a fatal fault (one outside guarded code) writes the
+// events to stderr, with the names and lines given to forth_record_label,
+// before the process dies. Events stay readable after forth_record_stop.
+void forth_record_label(cell_t word, const char *name, cell_t line);
+cell_t forth_record_start(cell_t capacity);
+void forth_record_stop(void);
+void forth_record_enter(cell_t word, cell_t count, cell_t a, cell_t b, cell_t c, cell_t d);
+void forth_record_exit(cell_t word, cell_t count, cell_t a, cell_t b, cell_t c, cell_t d);
+// Copies the last events, oldest first, returning how many; with events
+// NULL, returns how many there are
+cell_t forth_record_events(forth_record_t *events, cell_t max);
+
+// ============================================================================
+// BLOCKS (ANS Block word set over a memory-mapped block file)
+// ============================================================================
+
+#define BLOCK_SIZE 1024
+#define BLOCK_BUFFERS 8
+#define DEFAULT_BLOCK_FILE "blocks.fb"  // unless FORTH_BLOCK_FILE is set
+
+// Block u is bytes (u-1)*1024 .. u*1024-1 of the block file. Blocks past
+// the end of the file read as spaces; writing one grows the file. Buffer
+// addresses stay valid until the next BLOCK or BUFFER, as ANS requires.
+// Failures (block 0, an unusable file) give address 0.
+void forth_block_open(cell_t path_addr, cell_t path_len);  // OPEN-BLOCKS
+cell_t forth_block(cell_t u);                               // BLOCK
+cell_t forth_buffer(cell_t u);                              // BUFFER
+void forth_update(void);                                    // UPDATE
+void forth_save_buffers(void);                              // SAVE-BUFFERS
+void forth_empty_buffers(void);                             // EMPTY-BUFFERS
+void forth_flush(void);                                     // FLUSH
+
+// ============================================================================
+// FILE ACCESS (ANS File word set; subject to PERMISSIONS)
+// ============================================================================
+
+// Paths are counted strings and modes the C strings R/O, W/O and R/W push
+// ("r", "w", "r+"). File ids are handles of the installed filesystem.
+forth_pair_t forth_file_open(cell_t path_addr, cell_t path_len, cell_t mode);    // OPEN-FILE ( -- fileid ior )
+forth_pair_t forth_file_create(cell_t path_addr, cell_t path_len, cell_t mode);  // CREATE-FILE ( -- fileid ior )
+cell_t forth_file_delete(cell_t path_addr, cell_t path_len);                     // DELETE-FILE ( -- ior )
+forth_pair_t forth_file_read(cell_t addr, cell_t u, cell_t fileid);              // READ-FILE ( -- u ior )
+cell_t forth_file_write(cell_t addr, cell_t u, cell_t fileid);                   // WRITE-FILE ( -- ior )
+cell_t forth_file_close(cell_t fileid);                                          // CLOSE-FILE ( -- ior )
+
+// A filesystem the file words go through instead of libc, so an embedder
+// (or the browser) can supply one of its own, such as an in-memory one.
+// Paths and modes are C strings; handles are any nonzero cell the
+// filesystem chooses; failures are negative iors. Permissions apply only
+// to the host filesystem: an installed one decides its own access. The
+// block file stays on the host. Set before running code, not during.
+typedef struct {
+    void *context;                                                                      // passed back to every call
+    cell_t (*open)(void *context, const char *path, const char *mode, cell_t *handle);  // ior
+    cell_t (*read)(void *context, cell_t handle, char *buffer, cell_t count);           // bytes read, or an ior
+    cell_t (*write)(void *context, cell_t handle, const char *buffer, cell_t count);    // ior
+    cell_t (*close)(void *context, cell_t handle);                                      // ior
+    cell_t (*remove)(void *context, const char *path);                                  // ior
+} forth_vfs_t;
+
+void forth_vfs_install(const forth_vfs_t *vfs);  // copied; NULL restores libc
+
+cell_t forth_system(cell_t command_addr, cell_t command_len);                    // SYSTEM ( -- status )
+
 // ============================================================================
 // DEBUGGING & INTROSPECTION
 // ============================================================================
@@ -0,0 +1,50 @@
+/**
+ * Fast Forth Runtime: System component
+ *
+ * Command line arguments, environment variables and SYSTEM. Linked only
+ * into programs that use them.
+ */
+
+#include "forth_runtime.h"
+#include <stdlib.h>
+#include <string.h>
+
+// ============================================================================
+// PROCESS ENVIRONMENT (command line arguments, environment variables)
+// ============================================================================
+
+cell_t forth_arg_count(void) {
+    return forth_argc;
+}
+
+// Address of argument n as a C string, or 0 if there is no such argument
+cell_t forth_arg(cell_t n) {
+    if (forth_argv == NULL || n < 0 || n >= forth_argc) return 0;
+    return (cell_t)forth_argv[n];
+}
+
+// Address of the value of the named variable, or 0 if it is not set
+cell_t forth_getenv(cell_t name_addr, cell_t name_len) {
+    char name[256];
+    if (name_addr == 0 || name_len < 0 || name_len >= (cell_t)sizeof(name)) return 0;
+    memcpy(name, (const char *)name_addr, (size_t)name_len);
+    name[name_len] = '\0';
+    return (cell_t)getenv(name);
+}
+
+// ============================================================================
+// COMMANDS
+// ============================================================================
+
+cell_t forth_system(cell_t command_addr, cell_t command_len) {
+    if (command_addr == 0 || command_len < 0) return -1;
+    char *command = malloc((size_t)command_len + 1);
+    if (command == NULL) return -1;
+    memcpy(command, (const char *)command_addr, (size_t)command_len);
+    command[command_len] = '\0';
+
+    cell_t status = forth_permit_run(command);
+    if (status == 0) status = system(command);
+    free(command);
+    return status;
+}
@@ -1,5 +1,9 @@
 -- Fast Forth Pattern Library Database Schema
 -- SQLite database for storing canonical Forth patterns
+--
+-- This is migration 1 (src/patterns/migrations.rs), which also records
+-- it in the schema_version table. Later schema changes are new
+-- migrations; do not edit this file in place.
 
 CREATE TABLE IF NOT EXISTS patterns (
     id TEXT PRIMARY KEY,
@@ -47,11 +51,3 @@ CREATE INDEX IF NOT EXISTS idx_patterns_stack_effect ON patterns(stack_effect);
 CREATE INDEX IF NOT EXISTS idx_patterns_performance ON patterns(performance_class);
 CREATE INDEX IF NOT EXISTS idx_pattern_tags_tag ON pattern_tags(tag);
 CREATE INDEX IF NOT EXISTS idx_pattern_tags_pattern ON pattern_tags(pattern_id);
-
--- Metadata table
-CREATE TABLE IF NOT EXISTS schema_version (
-    version INTEGER PRIMARY KEY,
-    applied_at TEXT NOT NULL
-);
-
-INSERT INTO schema_version (version, applied_at) VALUES (1, datetime('now'));
@@ -0,0 +1,236 @@
+//! Access control for the HTTP APIs
+//!
+//! API keys, per-client rate limits and request size limits shared by
+//! `VerificationServer` and `PatternServer`. Nothing here depends on the
+//! HTTP stack: servers pass in the credential and client they saw.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Buckets kept before idle ones are dropped
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Requests allowed per client over a period
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub per: Duration,
+}
+
+impl RateLimit {
+    /// `requests` per minute
+    pub fn per_minute(requests: u32) -> Self {
+        Self {
+            requests,
+            per: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Who may call an API and how much
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessPolicy {
+    /// Accepted API keys; empty leaves the API open
+    pub api_keys: Vec<String>,
+    /// Limit per API key, or per client address when no keys are set
+    pub rate_limit: Option<RateLimit>,
+    /// Largest accepted request body
+    pub max_request_bytes: usize,
+}
+
+impl Default for AccessPolicy {
+    fn default() -> Self {
+        Self {
+            api_keys: Vec::new(),
+            rate_limit: None,
+            max_request_bytes: 1024 * 1024,
+        }
+    }
+}
+
+/// Why a request was refused
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AccessDenied {
+    #[error("Missing or invalid API key")]
+    Unauthorized,
+
+    #[error("Rate limit exceeded, retry in {}ms", retry_after.as_millis())]
+    RateLimited { retry_after: Duration },
+
+    #[error("Request body of {size} bytes exceeds the limit of {limit} bytes")]
+    TooLarge { size: usize, limit: usize },
+}
+
+/// Token bucket for one client
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Enforces an `AccessPolicy`
+pub struct AccessControl {
+    policy: AccessPolicy,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl AccessControl {
+    pub fn new(policy: AccessPolicy) -> Self {
+        Self {
+            policy,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn policy(&self) -> &AccessPolicy {
+        &self.policy
+    }
+
+    /// Whether callers must present an API key
+    pub fn requires_key(&self) -> bool {
+        !self.policy.api_keys.is_empty()
+    }
+
+    /// Admit or refuse a request
+    ///
+    /// `credential` is the API key presented, if any; `client` identifies
+    /// the caller (typically its address) for rate limiting when the API is
+    /// open.
+    pub fn check(&self, credential: Option<&str>, client: &str, body_bytes: usize) -> Result<(), AccessDenied> {
+        self.check_at(credential, client, body_bytes, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        credential: Option<&str>,
+        client: &str,
+        body_bytes: usize,
+        now: Instant,
+    ) -> Result<(), AccessDenied> {
+        if body_bytes > self.policy.max_request_bytes {
+            return Err(AccessDenied::TooLarge {
+                size: body_bytes,
+                limit: self.policy.max_request_bytes,
+            });
+        }
+
+        let identity = if self.requires_key() {
+            let key = credential.ok_or(AccessDenied::Unauthorized)?;
+            if !self.policy.api_keys.iter().any(|valid| keys_match(valid, key)) {
+                return Err(AccessDenied::Unauthorized);
+            }
+            key
+        } else {
+            client
+        };
+
+        match self.policy.rate_limit {
+            Some(limit) => self.take_token(identity, limit, now),
+            None => Ok(()),
+        }
+    }
+
+    fn take_token(&self, identity: &str, limit: RateLimit, now: Instant) -> Result<(), AccessDenied> {
+        let capacity = f64::from(limit.requests);
+        let refill_per_sec = capacity / limit.per.as_secs_f64();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(identity) {
+            // A bucket idle for a whole period is full again, so it is
+            // equivalent to a fresh one
+            buckets.retain(|_, bucket| now.duration_since(bucket.updated) < limit.per);
+        }
+
+        let bucket = buckets.entry(identity.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * refill_per_sec).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / refill_per_sec;
+            Err(AccessDenied::RateLimited {
+                retry_after: Duration::from_secs_f64(wait),
+            })
+        }
+    }
+}
+
+/// API key from an `Authorization: Bearer` header, else an `X-API-Key` header
+pub fn credential<'a>(authorization: Option<&'a str>, api_key: Option<&'a str>) -> Option<&'a str> {
+    authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .or(api_key.map(str::trim))
+}
+
+/// Compare keys without exiting early on the first differing byte
+fn keys_match(expected: &str, presented: &str) -> bool {
+    expected.len() == presented.len()
+        && expected
+            .bytes()
+            .zip(presented.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(keys: &[&str], rate_limit: Option<RateLimit>) -> AccessPolicy {
+        AccessPolicy {
+            api_keys: keys.iter().map(|key| key.to_string()).collect(),
+            rate_limit,
+            ..AccessPolicy::default()
+        }
+    }
+
+    #[test]
+    fn test_api_keys() {
+        let open = AccessControl::new(policy(&[], None));
+        assert_eq!(open.check(None, "10.0.0.1", 0), Ok(()));
+
+        let closed = AccessControl::new(policy(&["secret"], None));
+        assert_eq!(closed.check(None, "10.0.0.1", 0), Err(AccessDenied::Unauthorized));
+        assert_eq!(closed.check(Some("secrex"), "10.0.0.1", 0), Err(AccessDenied::Unauthorized));
+        assert_eq!(closed.check(Some("secret"), "10.0.0.1", 0), Ok(()));
+    }
+
+    #[test]
+    fn test_rate_limit_is_per_key_and_refills() {
+        let limit = RateLimit { requests: 2, per: Duration::from_secs(1) };
+        let control = AccessControl::new(policy(&["a", "b"], Some(limit)));
+        let start = Instant::now();
+
+        assert!(control.check_at(Some("a"), "x", 0, start).is_ok());
+        assert!(control.check_at(Some("a"), "x", 0, start).is_ok());
+        let denied = control.check_at(Some("a"), "x", 0, start).unwrap_err();
+        assert!(matches!(denied, AccessDenied::RateLimited { retry_after } if retry_after <= Duration::from_millis(500)));
+
+        // Other keys have their own budget
+        assert!(control.check_at(Some("b"), "x", 0, start).is_ok());
+
+        let later = start + Duration::from_millis(500);
+        assert!(control.check_at(Some("a"), "x", 0, later).is_ok());
+    }
+
+    #[test]
+    fn test_request_size_and_credentials() {
+        let control = AccessControl::new(AccessPolicy { max_request_bytes: 10, ..AccessPolicy::default() });
+        assert_eq!(
+            control.check(None, "x", 11),
+            Err(AccessDenied::TooLarge { size: 11, limit: 10 })
+        );
+
+        assert_eq!(credential(Some("Bearer abc"), Some("def")), Some("abc"));
+        assert_eq!(credential(Some("Basic abc"), Some("def")), Some("def"));
+        assert_eq!(credential(None, None), None);
+    }
+}
@@ -6,6 +6,7 @@
 //! - LLVM: Slow compilation (2-5min), excellent runtime (85-110% of C) - Default for -O2/-O3
 
 use crate::error::{CompileError, Result};
+use crate::pipeline::CompilationMode;
 use fastforth_frontend::ssa::SSAFunction;
 use fastforth_optimizer::{ForthIR, OptimizationLevel};
 
@@ -21,6 +22,99 @@ pub enum BackendType {
     LLVM,
 }
 
+/// Backend requested by the user
+///
+/// `Auto` picks a backend from the optimization level. An explicit choice
+/// that is not available falls back to the other backend with a diagnostic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Choose from the optimization level
+    #[default]
+    Auto,
+    /// Cranelift code generator
+    Cranelift,
+    /// LLVM code generator
+    LLVM,
+}
+
+impl Backend {
+    /// Resolve the request to a backend that can compile in `mode`
+    pub fn resolve(self, opt_level: OptimizationLevel, mode: CompilationMode) -> Result<ResolvedBackend> {
+        let requested = match self {
+            Backend::Auto => BackendSelector::select_backend(opt_level),
+            Backend::Cranelift => BackendType::Cranelift,
+            Backend::LLVM => BackendType::LLVM,
+        };
+
+        let reason = match BackendSelector::unavailable_reason(requested, mode) {
+            None => {
+                return Ok(ResolvedBackend {
+                    backend_type: requested,
+                    diagnostic: None,
+                })
+            }
+            Some(reason) => reason,
+        };
+
+        let fallback = match requested {
+            BackendType::Cranelift => BackendType::LLVM,
+            BackendType::LLVM => BackendType::Cranelift,
+        };
+        if BackendSelector::unavailable_reason(fallback, mode).is_some() {
+            return Err(CompileError::BackendError(format!(
+                "No backend available for {:?} compilation: {}",
+                mode, reason
+            )));
+        }
+
+        // Auto only expresses a preference, so falling back is not worth reporting
+        let diagnostic = match self {
+            Backend::Auto => None,
+            _ => Some(format!(
+                "{}; using {} instead",
+                reason,
+                BackendSelector::backend_name(fallback)
+            )),
+        };
+
+        Ok(ResolvedBackend {
+            backend_type: fallback,
+            diagnostic,
+        })
+    }
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Auto => write!(f, "auto"),
+            Backend::Cranelift => write!(f, "cranelift"),
+            Backend::LLVM => write!(f, "llvm"),
+        }
+    }
+}
+
+impl std::str::FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(Backend::Auto),
+            "cranelift" => Ok(Backend::Cranelift),
+            "llvm" => Ok(Backend::LLVM),
+            _ => Err(format!("unknown backend '{}' (expected auto, cranelift or llvm)", s)),
+        }
+    }
+}
+
+/// Backend chosen for a compilation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedBackend {
+    pub backend_type: BackendType,
+    /// Why the requested backend was replaced, if it was
+    pub diagnostic: Option<String>,
+}
+
 /// Backend selection strategy
 pub struct BackendSelector;
 
@@ -33,11 +127,11 @@ impl BackendSelector {
         match opt_level {
             OptimizationLevel::None | OptimizationLevel::Basic | OptimizationLevel::Standard => {
                 // For development and standard builds, prioritize fast compilation
-                #[cfg(feature = "cranelift")]
-                return BackendType::Cranelift;
-
-                #[cfg(not(feature = "cranelift"))]
-                BackendType::LLVM
+                if Self::is_available(BackendType::Cranelift) {
+                    BackendType::Cranelift
+                } else {
+                    BackendType::LLVM
+                }
             }
             OptimizationLevel::Aggressive => {
                 // For maximum optimization, use LLVM
@@ -46,14 +140,44 @@ impl BackendSelector {
         }
     }
 
-    /// Check if backend is available
+    /// Check if backend is compiled into this build
     pub fn is_available(backend: BackendType) -> bool {
         match backend {
-            BackendType::Cranelift => cfg!(feature = "cranelift"),
-            BackendType::LLVM => cfg!(feature = "llvm"),
+            BackendType::Cranelift => ::backend::CRANELIFT_ENABLED,
+            BackendType::LLVM => ::backend::LLVM_ENABLED,
+        }
+    }
+
+    /// Explain why a backend cannot be used in `mode`, or `None` if it can
+    pub fn unavailable_reason(backend: BackendType, mode: CompilationMode) -> Option<String> {
+        if !Self::is_available(backend) {
+            let feature = match backend {
+                BackendType::Cranelift => "cranelift",
+                BackendType::LLVM => "llvm",
+            };
+            return Some(format!(
+                "{} backend not available (built without the `{}` feature)",
+                Self::backend_name(backend),
+                feature
+            ));
+        }
+
+        match (backend, mode) {
+            (BackendType::LLVM, CompilationMode::JIT) => {
+                Some("LLVM backend does not support JIT execution".to_string())
+            }
+            _ => None,
         }
     }
 
+    /// Backends compiled into this build
+    pub fn available_backends() -> Vec<BackendType> {
+        [BackendType::Cranelift, BackendType::LLVM]
+            .into_iter()
+            .filter(|&backend| Self::is_available(backend))
+            .collect()
+    }
+
     /// Get backend name
     pub fn backend_name(backend: BackendType) -> &'static str {
         match backend {
@@ -80,13 +204,13 @@ impl BackendSelector {
 }
 
 /// Unified backend interface
-pub struct Backend {
+pub struct UnifiedBackend {
     backend_type: BackendType,
     #[cfg(feature = "cranelift")]
     cranelift: Option<CraneliftCompiler>,
 }
 
-impl Backend {
+impl UnifiedBackend {
     /// Create a new backend with automatic selection based on optimization level
     pub fn new(opt_level: OptimizationLevel) -> Result<Self> {
         let backend_type = BackendSelector::select_backend(opt_level);
@@ -256,17 +380,13 @@ mod tests {
     #[test]
     fn test_backend_selection() {
         // O0/O1/O2 should select Cranelift (if available)
-        let backend = BackendSelector::select_backend(OptimizationLevel::None);
-        #[cfg(feature = "cranelift")]
-        assert_eq!(backend, BackendType::Cranelift);
-        #[cfg(not(feature = "cranelift"))]
-        assert_eq!(backend, BackendType::LLVM);
-
-        let backend = BackendSelector::select_backend(OptimizationLevel::Standard);
-        #[cfg(feature = "cranelift")]
-        assert_eq!(backend, BackendType::Cranelift);
-        #[cfg(not(feature = "cranelift"))]
-        assert_eq!(backend, BackendType::LLVM);
+        let expected = if ::backend::CRANELIFT_ENABLED {
+            BackendType::Cranelift
+        } else {
+            BackendType::LLVM
+        };
+        assert_eq!(BackendSelector::select_backend(OptimizationLevel::None), expected);
+        assert_eq!(BackendSelector::select_backend(OptimizationLevel::Standard), expected);
 
         // O3 should always select LLVM
         let backend = BackendSelector::select_backend(OptimizationLevel::Aggressive);
@@ -275,7 +395,46 @@ mod tests {
 
     #[test]
     fn test_backend_availability() {
-        #[cfg(feature = "cranelift")]
-        assert!(BackendSelector::is_available(BackendType::Cranelift));
+        assert_eq!(
+            BackendSelector::is_available(BackendType::Cranelift),
+            ::backend::CRANELIFT_ENABLED
+        );
+        assert!(BackendSelector::unavailable_reason(BackendType::LLVM, CompilationMode::JIT).is_some());
+    }
+
+    #[test]
+    fn test_backend_parse() {
+        assert_eq!("auto".parse::<Backend>(), Ok(Backend::Auto));
+        assert_eq!("LLVM".parse::<Backend>(), Ok(Backend::LLVM));
+        assert!("gcc".parse::<Backend>().is_err());
+        assert_eq!(Backend::Cranelift.to_string(), "cranelift");
+    }
+
+    #[test]
+    fn test_explicit_backend_falls_back_with_diagnostic() {
+        if !::backend::CRANELIFT_ENABLED {
+            return;
+        }
+
+        // LLVM cannot JIT, so an explicit request falls back and says why
+        let resolved = Backend::LLVM
+            .resolve(OptimizationLevel::Standard, CompilationMode::JIT)
+            .unwrap();
+        assert_eq!(resolved.backend_type, BackendType::Cranelift);
+        let diagnostic = resolved.diagnostic.unwrap();
+        assert!(diagnostic.starts_with("LLVM backend"));
+        assert!(diagnostic.ends_with("using Cranelift instead"));
+
+        // Auto falls back quietly
+        let resolved = Backend::Auto
+            .resolve(OptimizationLevel::Aggressive, CompilationMode::JIT)
+            .unwrap();
+        assert_eq!(resolved.backend_type, BackendType::Cranelift);
+        assert!(resolved.diagnostic.is_none());
+
+        let resolved = Backend::Cranelift
+            .resolve(OptimizationLevel::Standard, CompilationMode::JIT)
+            .unwrap();
+        assert_eq!(resolved, ResolvedBackend { backend_type: BackendType::Cranelift, diagnostic: None });
     }
 }
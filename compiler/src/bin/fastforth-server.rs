@@ -9,12 +9,23 @@
 //!   POST /verify  - Verify code against expected stack effect
 //!   POST /infer   - Infer stack effect from code
 //!   POST /compose - Verify composition of words
+//!   POST /infer/batch  - Infer stack effects of many snippets
+//!   POST /compile      - Compile source, returning diagnostics
+//!   POST /jobs/compile - Queue a compilation, returning a job id
+//!   GET  /jobs/:id     - Poll a queued compilation
+//!   GET  /live         - WebSocket: push diagnostics as a document is edited
 //!   GET  /health  - Health check
 
 use clap::Parser;
 
 #[cfg(feature = "server")]
 use fastforth::server::{VerificationServer, ServerConfig};
+#[cfg(feature = "server")]
+use fastforth::Sandbox;
+#[cfg(feature = "server")]
+use fastforth::access::{AccessPolicy, RateLimit};
+#[cfg(feature = "server")]
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(name = "fastforth-server")]
@@ -32,6 +43,50 @@ struct Cli {
     /// Number of worker threads (0 = auto)
     #[arg(short, long, default_value = "0")]
     workers: usize,
+
+    /// Timeout for synchronous /compile requests, in seconds
+    #[arg(long, default_value = "10")]
+    request_timeout: u64,
+
+    /// Timeout for queued compile jobs, in seconds
+    #[arg(long, default_value = "300")]
+    job_timeout: u64,
+
+    /// Compilations allowed to run at once (0 = one per worker)
+    #[arg(long, default_value = "0")]
+    max_concurrent_compiles: usize,
+
+    /// Accepted API key (repeatable); keys in FASTFORTH_API_KEYS,
+    /// comma separated, are accepted too. Without keys the API is open
+    #[arg(long = "api-key", value_name = "KEY")]
+    api_keys: Vec<String>,
+
+    /// Requests per minute allowed per API key, or per client address
+    #[arg(long, value_name = "N")]
+    rate_limit: Option<u32>,
+
+    /// Largest accepted request body, in bytes
+    #[arg(long, default_value = "1048576")]
+    max_request_bytes: usize,
+
+    /// Compile every request as untrusted source, in a sandbox
+    #[arg(long)]
+    sandbox: bool,
+
+    /// Word calls and loop iterations a sandboxed JIT run may make
+    /// (implies --sandbox)
+    #[arg(long, value_name = "N")]
+    fuel: Option<u64>,
+
+    /// Most heap a sandboxed JIT run may allocate, in bytes (implies
+    /// --sandbox)
+    #[arg(long, value_name = "BYTES")]
+    sandbox_memory: Option<u64>,
+
+    /// Start inference from the project's stack-effect database, so calls
+    /// to words recorded from any of its files are typed
+    #[arg(long, value_name = "FILE")]
+    effect_db: Option<std::path::PathBuf>,
 }
 
 #[cfg(feature = "server")]
@@ -39,14 +94,35 @@ struct Cli {
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
+    let mut api_keys = cli.api_keys;
+    if let Ok(keys) = std::env::var("FASTFORTH_API_KEYS") {
+        api_keys.extend(keys.split(',').map(str::trim).filter(|key| !key.is_empty()).map(String::from));
+    }
+
+    let workers = if cli.workers == 0 {
+        num_cpus::get()
+    } else {
+        cli.workers
+    };
     let config = ServerConfig {
         host: cli.host,
         port: cli.port,
-        workers: if cli.workers == 0 {
-            num_cpus::get()
+        workers,
+        request_timeout: Duration::from_secs(cli.request_timeout),
+        job_timeout: Duration::from_secs(cli.job_timeout),
+        max_concurrent_compiles: if cli.max_concurrent_compiles == 0 {
+            workers
         } else {
-            cli.workers
+            cli.max_concurrent_compiles
+        },
+        access: AccessPolicy {
+            api_keys,
+            rate_limit: cli.rate_limit.map(RateLimit::per_minute),
+            max_request_bytes: cli.max_request_bytes,
         },
+        sandbox: Sandbox { fuel: cli.fuel, timeout: None, memory: cli.sandbox_memory }.for_request(None, cli.sandbox),
+        effect_database: cli.effect_db,
+        ..ServerConfig::default()
     };
 
     let server = VerificationServer::new(config);
@@ -0,0 +1,143 @@
+//! Cancellation
+//!
+//! A [`CancelToken`] stops compiled code from another thread. Code compiled
+//! interruptible ([`Compiler::set_interruptible`](crate::Compiler::set_interruptible))
+//! polls its token at the entry of every word and the head of every loop,
+//! and a run under a cancelled token unwinds at its next poll and fails
+//! with [`CompileError::Cancelled`].
+//!
+//! A guarded run also unwinds from a fault in its compiled code, such as a
+//! division by zero, and fails with [`CompileError::Trapped`] (see
+//! [`crate::snapshot`]), and from a THROW, such as checked arithmetic on an
+//! overflow, failing with [`CompileError::Thrown`].
+//!
+//! Unwinding skips whatever the run had not finished: blocks it allocated
+//! stay allocated and tasks it spawned keep running. Host closures called
+//! from Forth are never unwound; the run stops at the first poll after the
+//! closure returns. A run blocked in the runtime, such as on a channel,
+//! stops once it gets past the block.
+
+use crate::error::{CompileError, Result};
+use crate::snapshot::Snapshot;
+use crate::runtime_ffi::{forth_guard_resume, forth_guard_suspend, forth_guarded_call, CellT};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Status of a guarded call that was cancelled (`FORTH_CANCELLED`, the
+/// THROW code of a user interrupt)
+const CANCELLED: CellT = -28;
+
+/// Status of a guarded call that spent its fuel (`FORTH_OUT_OF_FUEL`)
+const OUT_OF_FUEL: CellT = -256;
+
+/// Status of a guarded call whose code faulted (`FORTH_TRAPPED`)
+const TRAPPED: CellT = -258;
+
+/// Status of a call the runtime refused, for taking too many cells
+const REFUSED: CellT = -1;
+
+/// Shared flag that cancels the runs it is passed to
+///
+/// Clones share the flag. Once cancelled a token stays cancelled, so a new
+/// run needs a new token.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    flag: Arc<AtomicI64>,
+}
+
+impl CancelToken {
+    /// Create a token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop the runs under this token at their next poll
+    pub fn cancel(&self) {
+        self.flag.store(1, Ordering::SeqCst);
+    }
+
+    /// Whether [`cancel`](Self::cancel) was called
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::SeqCst) != 0
+    }
+
+    /// Cancel the token once `timeout` passes, unless the returned
+    /// deadline is dropped first
+    pub fn cancel_after(&self, timeout: Duration) -> Deadline {
+        let (disarm, expired) = mpsc::channel::<()>();
+        let token = self.clone();
+        std::thread::spawn(move || {
+            if let Err(mpsc::RecvTimeoutError::Timeout) = expired.recv_timeout(timeout) {
+                token.cancel();
+            }
+        });
+        Deadline { _disarm: disarm }
+    }
+
+    /// Call a compiled word under this token, returning the cell it leaves
+    /// on top
+    ///
+    /// # Safety
+    ///
+    /// `address` must be a compiled word taking exactly `args.len()` cells,
+    /// at most [`MAX_ARGUMENTS`](crate::embed::MAX_ARGUMENTS).
+    pub(crate) unsafe fn call(&self, address: *const u8, args: &[i64]) -> Result<i64> {
+        self.call_with_fuel(address, args, None)
+    }
+
+    /// [`call`](Self::call) that also stops with
+    /// [`CompileError::OutOfFuel`] once the word has entered words and
+    /// started loop iterations `fuel` times in all
+    ///
+    /// # Safety
+    ///
+    /// As for [`call`](Self::call).
+    pub(crate) unsafe fn call_with_fuel(&self, address: *const u8, args: &[i64], fuel: Option<u64>) -> Result<i64> {
+        let budget = fuel.map_or(-1, |fuel| fuel.min(CellT::MAX as u64) as CellT);
+        let mut result = 0;
+        let status = forth_guarded_call(self.flag.as_ptr(), budget, address, args.len() as CellT, args.as_ptr(), &mut result);
+        match status {
+            0 => Ok(result),
+            CANCELLED => Err(CompileError::Cancelled),
+            OUT_OF_FUEL => Err(CompileError::OutOfFuel { fuel: fuel.unwrap_or_default() }),
+            TRAPPED => Err(CompileError::Trapped(Box::new(Snapshot::from_last_trap()))),
+            REFUSED => Err(CompileError::RuntimeError(format!("cannot call a word taking {} cells", args.len()))),
+            code => Err(CompileError::Thrown { code: code as i64 }),
+        }
+    }
+}
+
+/// A pending [`CancelToken::cancel_after`]; dropping it disarms the timer
+#[derive(Debug)]
+pub struct Deadline {
+    _disarm: mpsc::Sender<()>,
+}
+
+/// Run host code called from compiled code with the run's guard suspended,
+/// so a poll never unwinds through its frames
+pub(crate) fn unguarded<T>(host: impl FnOnce() -> T) -> T {
+    // The guard is restored before control returns to compiled code
+    let guard = unsafe { forth_guard_suspend() };
+    let value = host();
+    unsafe { forth_guard_resume(guard) };
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadlines_cancel_unless_dropped() {
+        let token = CancelToken::new();
+        drop(token.cancel_after(Duration::from_millis(1)));
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!token.is_cancelled());
+
+        let _deadline = token.cancel_after(Duration::from_millis(1));
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(token.is_cancelled());
+    }
+}
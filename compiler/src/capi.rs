@@ -0,0 +1,252 @@
+//! C API
+//!
+//! A stable `extern "C"` layer over [`Compiler`] for hosts that are not
+//! written in Rust: editors, game engines, scripting runtimes. The matching
+//! declarations are in `include/fastforth.h`; build the library with
+//!
+//! ```text
+//! cargo rustc --release --lib --crate-type cdylib     # libfastforth.so
+//! cargo rustc --release --lib --crate-type staticlib  # libfastforth.a
+//! ```
+//!
+//! Every function returns a status code, and on [`FASTFORTH_ERROR`] the
+//! compiler keeps the failure as a structured error in JSON, read with
+//! [`fastforth_last_error`]. Panics are caught at the boundary and reported
+//! the same way. A compiler handle must not be used from two threads at
+//! once.
+//!
+//! The ABI only grows: functions are added, never changed or removed, and
+//! [`FASTFORTH_ABI_VERSION`] is bumped when they are.
+
+use crate::errors::{format_error, to_structured_error, OutputFormat};
+use crate::{CompilationMode, CompileError, Compiler, OptimizationLevel};
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Version of the C ABI, `FASTFORTH_ABI_VERSION` in the header
+pub const FASTFORTH_ABI_VERSION: u32 = 1;
+
+/// The call succeeded
+pub const FASTFORTH_OK: c_int = 0;
+/// Compilation or execution failed; see [`fastforth_last_error`]
+pub const FASTFORTH_ERROR: c_int = 1;
+/// A required pointer was null or the source was not UTF-8
+pub const FASTFORTH_INVALID_ARGUMENT: c_int = 2;
+/// The result stack did not fit the caller's buffer
+pub const FASTFORTH_STACK_OVERFLOW: c_int = 3;
+
+/// A compiler handle, opaque to C
+pub struct FastForthCompiler {
+    compiler: Compiler,
+    last_error: Option<CString>,
+}
+
+impl FastForthCompiler {
+    /// Run `body` on the source, recording its error as JSON
+    fn run<T>(&mut self, source: &str, body: impl FnOnce(&Compiler) -> crate::Result<T>) -> Option<T> {
+        self.last_error = None;
+        let result = catch_unwind(AssertUnwindSafe(|| body(&self.compiler)))
+            .unwrap_or_else(|panic| {
+                let message = panic.downcast_ref::<&str>().map(|s| s.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "compiler panicked".to_string());
+                Err(CompileError::InternalError(message))
+            });
+        result.map_err(|e| self.set_error(&e, source)).ok()
+    }
+
+    fn set_error(&mut self, error: &CompileError, source: &str) {
+        let diagnostic = to_structured_error(error, false).with_source("<input>", source);
+        let json = format_error(&diagnostic, OutputFormat::Json);
+        self.last_error = CString::new(json.replace('\0', "")).ok();
+    }
+}
+
+/// The source argument as UTF-8, or `None` if it is null or invalid
+unsafe fn source_str<'a>(source: *const c_char) -> Option<&'a str> {
+    if source.is_null() {
+        return None;
+    }
+    CStr::from_ptr(source).to_str().ok()
+}
+
+/// Version of the ABI this library implements
+#[no_mangle]
+pub extern "C" fn fastforth_abi_version() -> u32 {
+    FASTFORTH_ABI_VERSION
+}
+
+/// Create a compiler at an optimization level from 0 (none) to 3
+/// (aggressive); free it with [`fastforth_compiler_free`]
+#[no_mangle]
+pub extern "C" fn fastforth_compiler_new(opt_level: c_int) -> *mut FastForthCompiler {
+    let level = match opt_level {
+        i32::MIN..=0 => OptimizationLevel::None,
+        1 => OptimizationLevel::Basic,
+        2 => OptimizationLevel::Standard,
+        _ => OptimizationLevel::Aggressive,
+    };
+    Box::into_raw(Box::new(FastForthCompiler { compiler: Compiler::new(level), last_error: None }))
+}
+
+/// Free a compiler; null is ignored
+///
+/// # Safety
+///
+/// `compiler` must come from [`fastforth_compiler_new`] and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn fastforth_compiler_free(compiler: *mut FastForthCompiler) {
+    if !compiler.is_null() {
+        drop(Box::from_raw(compiler));
+    }
+}
+
+/// Compile source code to a relocatable object file
+///
+/// On success `*object` and `*length` describe a buffer owned by the
+/// caller, freed with [`fastforth_buffer_free`].
+///
+/// # Safety
+///
+/// `compiler` must be a live handle, `source` a NUL-terminated string, and
+/// `object` and `length` writable.
+#[no_mangle]
+pub unsafe extern "C" fn fastforth_compile_object(
+    compiler: *mut FastForthCompiler,
+    source: *const c_char,
+    object: *mut *mut u8,
+    length: *mut usize,
+) -> c_int {
+    let (Some(compiler), Some(source)) = (compiler.as_mut(), source_str(source)) else {
+        return FASTFORTH_INVALID_ARGUMENT;
+    };
+    if object.is_null() || length.is_null() {
+        return FASTFORTH_INVALID_ARGUMENT;
+    }
+    match compiler.run(source, |compiler| compiler.compile_object(source)) {
+        Some(bytes) => {
+            let bytes = Box::into_raw(bytes.into_boxed_slice());
+            *length = bytes.len();
+            *object = bytes.cast();
+            FASTFORTH_OK
+        }
+        None => FASTFORTH_ERROR,
+    }
+}
+
+/// Free a buffer returned by [`fastforth_compile_object`]; null is ignored
+///
+/// # Safety
+///
+/// `data` and `length` must be exactly as returned, and the buffer not
+/// freed before.
+#[no_mangle]
+pub unsafe extern "C" fn fastforth_buffer_free(data: *mut u8, length: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(data, length)));
+    }
+}
+
+/// JIT-compile and run source code, copying the data stack it leaves into
+/// `stack`, bottom first
+///
+/// `*depth` is set to the number of cells left. If they do not fit in
+/// `capacity` cells nothing is copied and [`FASTFORTH_STACK_OVERFLOW`] is
+/// returned, so the caller can retry with a larger buffer.
+///
+/// # Safety
+///
+/// `compiler` must be a live handle, `source` a NUL-terminated string,
+/// `stack` writable for `capacity` cells (it may be null if `capacity` is
+/// 0), and `depth` writable.
+#[no_mangle]
+pub unsafe extern "C" fn fastforth_run(
+    compiler: *mut FastForthCompiler,
+    source: *const c_char,
+    stack: *mut i64,
+    capacity: usize,
+    depth: *mut usize,
+) -> c_int {
+    let (Some(compiler), Some(source)) = (compiler.as_mut(), source_str(source)) else {
+        return FASTFORTH_INVALID_ARGUMENT;
+    };
+    if depth.is_null() || (stack.is_null() && capacity > 0) {
+        return FASTFORTH_INVALID_ARGUMENT;
+    }
+    let Some(result) = compiler.run(source, |compiler| compiler.compile_string(source, CompilationMode::JIT)) else {
+        return FASTFORTH_ERROR;
+    };
+    *depth = result.stack.len();
+    if result.stack.len() > capacity {
+        return FASTFORTH_STACK_OVERFLOW;
+    }
+    if !result.stack.is_empty() {
+        std::ptr::copy_nonoverlapping(result.stack.as_ptr(), stack, result.stack.len());
+    }
+    FASTFORTH_OK
+}
+
+/// The last error as a JSON structured error, or null if the last call
+/// succeeded; the string is owned by the compiler and valid until its next
+/// call
+///
+/// # Safety
+///
+/// `compiler` must be a live handle or null.
+#[no_mangle]
+pub unsafe extern "C" fn fastforth_last_error(compiler: *const FastForthCompiler) -> *const c_char {
+    compiler.as_ref()
+        .and_then(|compiler| compiler.last_error.as_ref())
+        .map_or(std::ptr::null(), |error| error.as_ptr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_copies_the_stack_and_reports_overflow() {
+        let compiler = fastforth_compiler_new(2);
+        let source = c": sq dup * ; 3 4 sq";
+        let mut stack = [0i64; 4];
+        let mut depth = 0;
+        unsafe {
+            assert_eq!(fastforth_run(compiler, source.as_ptr(), stack.as_mut_ptr(), stack.len(), &mut depth), FASTFORTH_OK);
+            assert_eq!(&stack[..depth], &[3, 16]);
+            assert!(fastforth_last_error(compiler).is_null());
+
+            assert_eq!(fastforth_run(compiler, source.as_ptr(), stack.as_mut_ptr(), 1, &mut depth), FASTFORTH_STACK_OVERFLOW);
+            assert_eq!(depth, 2);
+            assert_eq!(fastforth_run(compiler, std::ptr::null(), stack.as_mut_ptr(), 4, &mut depth), FASTFORTH_INVALID_ARGUMENT);
+            fastforth_compiler_free(compiler);
+        }
+    }
+
+    #[test]
+    fn test_errors_are_reported_as_json() {
+        let compiler = fastforth_compiler_new(0);
+        let mut depth = 0;
+        unsafe {
+            let status = fastforth_run(compiler, c": broken undefined-word ;".as_ptr(), std::ptr::null_mut(), 0, &mut depth);
+            assert_eq!(status, FASTFORTH_ERROR);
+            let error = CStr::from_ptr(fastforth_last_error(compiler)).to_str().unwrap();
+            let json: serde_json::Value = serde_json::from_str(error).unwrap();
+            assert!(json["error"].as_str().unwrap().contains("undefined-word"));
+            fastforth_compiler_free(compiler);
+        }
+    }
+
+    #[test]
+    fn test_compile_object_returns_an_object_file() {
+        let compiler = fastforth_compiler_new(1);
+        let (mut object, mut length) = (std::ptr::null_mut(), 0);
+        unsafe {
+            let status = fastforth_compile_object(compiler, c": sq ( n -- n ) dup * ;".as_ptr(), &mut object, &mut length);
+            assert_eq!(status, FASTFORTH_OK, "{:?}", CStr::from_ptr(fastforth_last_error(compiler)));
+            let bytes = std::slice::from_raw_parts(object, length);
+            assert!(bytes.starts_with(b"\x7fELF") || bytes.starts_with(&[0xcf, 0xfa, 0xed, 0xfe]));
+            fastforth_buffer_free(object, length);
+            fastforth_compiler_free(compiler);
+        }
+    }
+}
@@ -26,7 +26,9 @@ pub fn generate_word_definition_fast(spec: &Specification) -> Result<String, std
 
     // Generate body based on pattern or heuristics
     let body = if let Some(implementation) = &spec.implementation {
-        if let Some(pattern) = &implementation.pattern {
+        if let Some(body) = &implementation.body {
+            body.as_str()
+        } else if let Some(pattern) = &implementation.pattern {
             generate_from_pattern_fast(pattern)
         } else {
             generate_heuristic_fast(&spec.word, spec.properties.as_deref())
@@ -2,7 +2,7 @@
 //!
 //! Generates Forth code from machine-readable specifications
 
-use crate::spec::{SpecError, SpecResult, Specification};
+use crate::spec::{ProgramSpec, SpecDocument, SpecError, SpecResult, SpecValidator, Specification, TestValue};
 
 /// Code generator for specifications
 pub struct SpecCodeGenerator {
@@ -55,9 +55,11 @@ impl SpecCodeGenerator {
 
         output.push('\n');
 
+        let definition = self.generate_word_definition(spec)?;
+
         // Add provenance metadata if requested
         if self.include_provenance {
-            output.push_str(&self.generate_provenance(spec));
+            output.push_str(&self.generate_provenance(spec, &definition));
         }
 
         // Add properties as comments
@@ -70,7 +72,7 @@ impl SpecCodeGenerator {
         }
 
         // Generate word definition
-        output.push_str(&self.generate_word_definition(spec)?);
+        output.push_str(&definition);
 
         // Add test harness if requested
         if self.include_tests {
@@ -85,8 +87,105 @@ impl SpecCodeGenerator {
         Ok(output)
     }
 
+    /// Generate code for either kind of specification
+    pub fn generate_document(&self, document: &SpecDocument) -> SpecResult<String> {
+        match document {
+            SpecDocument::Word(spec) => self.generate(spec),
+            SpecDocument::Program(program) => self.generate_program(program),
+        }
+    }
+
+    /// Generate a whole Forth file from a program specification
+    ///
+    /// Constants come first, then each word after the words it depends
+    /// on, each followed by its own tests, then the cross-word tests.
+    pub fn generate_program(&self, program: &ProgramSpec) -> SpecResult<String> {
+        program.validate()?;
+        let words = program.ordered_words()?;
+
+        let mut output = String::with_capacity(512 * words.len());
+        output.push_str(&format!("\\ Generated from program specification: {}\n", program.program));
+        if let Some(desc) = &program.description {
+            output.push_str(&format!("\\ {}\n", desc));
+        }
+        output.push_str(&format!("\\ Entry point: {}\n\n", program.entry_point));
+
+        if self.include_provenance {
+            if let Some(metadata) = &program.metadata {
+                output.push_str("\\ GENERATED METADATA\n");
+                if let Some(author) = &metadata.author {
+                    output.push_str(&format!("\\   AUTHOR: {}\n", author));
+                }
+                if let Some(version) = &metadata.version {
+                    output.push_str(&format!("\\   VERSION: {}\n", version));
+                }
+                if let Some(created) = &metadata.created {
+                    output.push_str(&format!("\\   CREATED: {}\n", created));
+                }
+                output.push('\n');
+            }
+        }
+
+        if !program.constants.is_empty() {
+            output.push_str("\\ Constants\n");
+            for constant in &program.constants {
+                output.push_str(&format!("{} constant {}", constant.value, constant.name));
+                if let Some(desc) = &constant.description {
+                    output.push_str(&format!("  \\ {}", desc));
+                }
+                output.push('\n');
+            }
+            output.push('\n');
+        }
+
+        // Definitions so far, so properties of a word can be checked
+        // through the words it calls
+        let mut definitions = String::new();
+        for word in words {
+            let spec = &word.spec;
+            let definition = self.generate_word_definition(spec)?;
+            definitions.push_str(&definition);
+            if self.include_provenance {
+                output.push_str(&self.generate_provenance(spec, &definitions));
+            }
+            if let Some(properties) = &spec.properties {
+                output.push_str("\\ Properties:\n");
+                for prop in properties {
+                    output.push_str(&format!("\\   {}\n", prop));
+                }
+            }
+            output.push_str(&definition);
+            if self.include_tests && spec.test_count() > 0 {
+                output.push('\n');
+                output.push_str(&self.generate_test_harness(spec));
+            }
+            output.push('\n');
+        }
+
+        if self.include_tests && !program.tests.is_empty() {
+            output.push_str(&format!("\\ Cross-word tests for {}\n\n", program.program));
+            for (i, test) in program.tests.iter().enumerate() {
+                match &test.description {
+                    Some(desc) => output.push_str(&format!("\\ Test {}: {}\n", i + 1, desc)),
+                    None => output.push_str(&format!("\\ Test {}\n", i + 1)),
+                }
+                let mut phrase = join_values(&test.input);
+                if !phrase.is_empty() {
+                    phrase.push(' ');
+                }
+                phrase.push_str(test.run.as_deref().unwrap_or(&program.entry_point).trim());
+                output.push_str(&format!("T{{ {} -> {} }}T\n", phrase, join_values(&test.output)));
+            }
+        }
+
+        Ok(output)
+    }
+
     /// Generate provenance metadata
-    fn generate_provenance(&self, spec: &Specification) -> String {
+    ///
+    /// `source` holds the generated definition, which declared properties
+    /// are checked against.
+    fn generate_provenance(&self, spec: &Specification, source: &str) -> String {
         // Phase 1 optimization: Pre-allocate buffer for metadata (typically ~200 chars)
         let mut output = String::with_capacity(256);
         use std::fmt::Write;
@@ -120,6 +219,12 @@ impl SpecCodeGenerator {
             }
         }
 
+        if let Ok(checks) = SpecValidator::new().check_properties(spec, source) {
+            for check in checks {
+                let _ = writeln!(&mut output, "\\   PROPERTY: {}", check);
+            }
+        }
+
         output.push('\n');
         output
     }
@@ -141,7 +246,9 @@ impl SpecCodeGenerator {
 
         // Generate body based on pattern or heuristics
         let body = if let Some(implementation) = &spec.implementation {
-            if let Some(pattern) = &implementation.pattern {
+            if let Some(body) = &implementation.body {
+                body.clone()
+            } else if let Some(pattern) = &implementation.pattern {
                 self.generate_from_pattern(spec, pattern)?
             } else {
                 self.generate_heuristic(spec)?
@@ -304,6 +411,10 @@ impl SpecCodeGenerator {
     }
 }
 
+fn join_values(values: &[TestValue]) -> String {
+    values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ")
+}
+
 impl Default for SpecCodeGenerator {
     fn default() -> Self {
         Self::new()
@@ -344,6 +455,7 @@ mod tests {
             complexity: None,
             implementation: Some(Implementation {
                 pattern: Some("DUP_TRANSFORM_001".to_string()),
+                body: None,
                 hints: None,
             }),
             metadata: None,
@@ -354,6 +466,52 @@ mod tests {
 
         assert!(code.contains(": square"));
         assert!(code.contains("dup *"));
+        assert!(code.contains("\\   PROPERTY: square(n) = n * n [verified]\n"));
         assert!(code.contains("T{ 5 square -> 25 }T"));
     }
+
+    #[test]
+    fn test_generate_program() {
+        let program = crate::spec::ProgramSpec::from_json(r#"{
+            "program": "hypot",
+            "constants": [{ "name": "SCALE", "value": 10 }],
+            "entry_point": "hypot-sq",
+            "words": [
+                {
+                    "word": "hypot-sq",
+                    "stack_effect": {
+                        "inputs": [{ "name": "a", "type": "int" }, { "name": "b", "type": "int" }],
+                        "outputs": [{ "name": "c", "type": "int" }]
+                    },
+                    "implementation": { "body": "square swap square +" },
+                    "depends_on": ["square"]
+                },
+                {
+                    "word": "square",
+                    "stack_effect": {
+                        "inputs": [{ "name": "n", "type": "int" }],
+                        "outputs": [{ "name": "n2", "type": "int" }]
+                    },
+                    "test_cases": [{ "input": [3], "output": [9] }]
+                }
+            ],
+            "tests": [
+                { "description": "3-4-5 triangle", "input": [3, 4], "output": [25] },
+                { "input": [2], "run": "SCALE * square", "output": [400] }
+            ]
+        }"#).unwrap();
+
+        let code = SpecCodeGenerator::new().generate_program(&program).unwrap();
+        let constant = code.find("10 constant SCALE").unwrap();
+        let square = code.find("\n: square").unwrap();
+        let hypot = code.find("\n: hypot-sq").unwrap();
+        assert!(constant < square && square < hypot);
+        assert!(code.contains("  square swap square +\n"));
+        assert!(code.contains("T{ 3 square -> 9 }T"));
+        assert!(code.contains("T{ 3 4 hypot-sq -> 25 }T"));
+        assert!(code.contains("T{ 2 SCALE * square -> 400 }T"));
+
+        let untested = SpecCodeGenerator::new().with_tests(false).generate_program(&program).unwrap();
+        assert!(!untested.contains("T{"));
+    }
 }
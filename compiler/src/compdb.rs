@@ -0,0 +1,220 @@
+//! Compilation Databases
+//!
+//! `--emit-compdb` indexes a build the way `compile_commands.json` does for
+//! C: one [`CompdbEntry`] per compiled definition, with the file and span
+//! it came from, its declared and inferred stack effects, what the
+//! optimizer did to it and the symbol its code is emitted as. Code search,
+//! review bots and the pattern miner read it instead of parsing Forth.
+//!
+//! The database is updated incrementally: compiling a file replaces that
+//! file's entries and keeps every other file's, so one database can cover
+//! a project built a file at a time. Entries are sorted by file and line,
+//! and the JSON is stable from one build of the same source to the next.
+
+use crate::error::{CompileError, Result};
+use crate::mangle;
+use fastforth_frontend::ast::Token;
+use fastforth_frontend::lexer::Lexer;
+use fastforth_frontend::type_inference::TypeInference;
+use fastforth_frontend::parse_program;
+use fastforth_optimizer::{OptimizationReport, WordReport};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Version of the database layout this compiler writes and reads
+pub const COMPDB_FORMAT: u32 = 1;
+
+/// Every definition of a build
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompilationDatabase {
+    pub format: u32,
+    pub entries: Vec<CompdbEntry>,
+}
+
+/// One compiled definition
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompdbEntry {
+    pub word: String,
+    /// Source file, as given to the compiler
+    pub file: String,
+    pub span: Span,
+    /// The stack effect comment, if it has one
+    pub declared_effect: Option<String>,
+    /// The effect type inference gives its body, if it can be typed
+    pub inferred_effect: Option<String>,
+    /// What the optimizer did, when it ran
+    pub optimization: Option<Optimization>,
+    /// Symbol its code is emitted as
+    pub symbol: String,
+}
+
+/// From the `:` to the `;` of a definition, inclusive, counting from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+/// The optimizer's decisions for one word, from its [`WordReport`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Optimization {
+    pub instructions_before: usize,
+    pub instructions_after: usize,
+    pub inlined: Vec<String>,
+    pub superinstructions: Vec<String>,
+    pub dead_code_eliminated: usize,
+    pub cache_depth: Option<u8>,
+}
+
+impl From<&WordReport> for Optimization {
+    fn from(report: &WordReport) -> Self {
+        Self {
+            instructions_before: report.instructions_before,
+            instructions_after: report.instructions_after,
+            inlined: report.inlined.clone(),
+            superinstructions: report.superinstructions.clone(),
+            dead_code_eliminated: report.dead_code_eliminated,
+            cache_depth: report.cache_depth,
+        }
+    }
+}
+
+impl Default for CompilationDatabase {
+    fn default() -> Self {
+        Self { format: COMPDB_FORMAT, entries: Vec::new() }
+    }
+}
+
+impl CompilationDatabase {
+    /// Load the database at `path`, or start an empty one if there is none
+    pub fn read(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json = std::fs::read_to_string(path).map_err(|e| CompileError::IoError(path.to_path_buf(), e))?;
+        let db: Self = serde_json::from_str(&json)
+            .map_err(|e| CompileError::RuntimeError(format!("{} is not a compilation database: {}", path.display(), e)))?;
+        if db.format > COMPDB_FORMAT {
+            return Err(CompileError::RuntimeError(format!(
+                "compilation database format {} is newer than this compiler reads ({})",
+                db.format, COMPDB_FORMAT
+            )));
+        }
+        Ok(db)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| CompileError::InternalError(format!("cannot serialize the compilation database: {}", e)))?;
+        std::fs::write(path, json + "\n").map_err(|e| CompileError::IoError(path.to_path_buf(), e))
+    }
+
+    /// Replace the entries of `file` with `entries`
+    pub fn update(&mut self, file: &str, entries: Vec<CompdbEntry>) {
+        self.entries.retain(|entry| entry.file != file);
+        self.entries.extend(entries);
+        self.entries
+            .sort_by(|a, b| (&a.file, a.span.line, a.span.column).cmp(&(&b.file, b.span.line, b.span.column)));
+    }
+
+    /// Entries of the definitions named `word`, in any file
+    pub fn lookup<'a>(&'a self, word: &'a str) -> impl Iterator<Item = &'a CompdbEntry> + 'a {
+        self.entries.iter().filter(move |entry| entry.word.eq_ignore_ascii_case(word))
+    }
+}
+
+/// Entries for the definitions of `source`, read from `file`
+///
+/// `report` is the optimization report of compiling it, if the optimizer
+/// ran with reporting on.
+pub fn entries(file: &str, source: &str, report: Option<&OptimizationReport>) -> Result<Vec<CompdbEntry>> {
+    let program = parse_program(source)?;
+    // The semicolons, to end each span at the one after its colon
+    let semicolons: Vec<(usize, usize)> = match Lexer::new(source).tokenize_with_locations() {
+        Ok((tokens, locations)) => tokens
+            .iter()
+            .zip(&locations)
+            .filter(|(token, _)| matches!(token, Token::Semicolon))
+            .map(|(_, location)| (location.line, location.column))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    let mut inference = TypeInference::new();
+    let mut entries = Vec::new();
+    for def in program.compiled_definitions() {
+        let start = (def.location.line, def.location.column);
+        let end = semicolons.iter().copied().find(|&semicolon| semicolon > start).unwrap_or(start);
+        let inferred_effect = inference.infer_definition(def).ok().map(|(inputs, outputs)| {
+            let side = |types: &[_]| types.iter().map(|t| format!("{} ", t)).collect::<String>();
+            format!("( {}-- {})", side(&inputs), side(&outputs))
+        });
+        entries.push(CompdbEntry {
+            word: def.name.clone(),
+            file: file.to_string(),
+            span: Span { line: start.0, column: start.1, end_line: end.0, end_column: end.1 },
+            declared_effect: def.stack_effect.as_ref().map(|effect| effect.to_string()),
+            inferred_effect,
+            optimization: report.and_then(|report| report.words.get(&def.name)).map(Optimization::from),
+            symbol: mangle(&def.name),
+        });
+    }
+    Ok(entries)
+}
+
+/// Index `file` into the database at `db_path`, keeping other files' entries
+pub fn update(db_path: &Path, file: &Path, report: Option<&OptimizationReport>) -> Result<()> {
+    let source = std::fs::read_to_string(file).map_err(|e| CompileError::IoError(file.to_path_buf(), e))?;
+    let name = file.display().to_string();
+    let entries = entries(&name, &source, report)?;
+    let mut db = CompilationDatabase::read(db_path)?;
+    db.update(&name, entries);
+    db.write(db_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompilationMode, CompilationPipeline, OptimizationLevel};
+
+    const SOURCE: &str = ": square ( n -- n ) dup * ;\n\n: quad ( n -- n )\n  square square\n;\n";
+
+    #[test]
+    fn test_entries_cover_each_definition() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Standard);
+        pipeline.set_opt_report(true);
+        pipeline.set_retain_ir(true);
+        let result = pipeline.compile(SOURCE, CompilationMode::JIT).unwrap();
+        let entries = entries("lib.fs", SOURCE, result.opt_report.as_ref()).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        let square = &entries[0];
+        assert_eq!(square.word, "square");
+        assert_eq!(square.span, Span { line: 1, column: 1, end_line: 1, end_column: 27 });
+        assert_eq!(square.declared_effect.as_deref(), Some("( int -- int )"));
+        assert!(square.inferred_effect.is_some());
+        assert_eq!(square.symbol, mangle("square"));
+        assert!(square.optimization.is_some());
+
+        let quad = &entries[1];
+        assert_eq!(quad.span, Span { line: 3, column: 1, end_line: 5, end_column: 1 });
+    }
+
+    #[test]
+    fn test_updates_replace_only_the_compiled_file() {
+        let path = std::env::temp_dir().join(format!("fastforth-compdb-{}.json", std::process::id()));
+        let mut db = CompilationDatabase::default();
+        db.update("b.fs", entries("b.fs", ": b1 1 ; : b2 2 ;", None).unwrap());
+        db.update("a.fs", entries("a.fs", ": a1 1 ;", None).unwrap());
+        db.write(&path).unwrap();
+
+        let mut db = CompilationDatabase::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        db.update("b.fs", entries("b.fs", ": b3 3 ;", None).unwrap());
+        let words: Vec<&str> = db.entries.iter().map(|entry| entry.word.as_str()).collect();
+        assert_eq!(words, ["a1", "b3"]);
+        assert_eq!(db.lookup("A1").count(), 1);
+    }
+}
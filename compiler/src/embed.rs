@@ -0,0 +1,284 @@
+//! Host Embedding
+//!
+//! Typed marshalling between Rust values and Forth cells, used by
+//! [`ForthEngine::call`](crate::ForthEngine::call) to call compiled words
+//! and by [`ForthEngine::register`](crate::ForthEngine::register) to call
+//! Rust closures from Forth.
+//!
+//! Every value occupies a fixed number of cells, deepest first:
+//! - Integers take one cell, truncated or sign-extended like `as` casts
+//! - `bool` takes one cell, a Forth flag: true is -1, and any nonzero cell
+//!   reads back as true
+//! - `f64` takes one cell holding its bit pattern
+//! - `&str` takes two, `c-addr u`; a [`String`] is read back from the same
+//!   pair
+//! - Tuples take the cells of their members in order, so `(a, b)` is
+//!   `( a b )` with `b` on top
+//!
+//! Compiled code reaches a closure through a dispatcher: a C ABI function
+//! for each argument count that takes the closure's id as its last argument
+//! and looks the closure up in a process-wide table.
+
+use std::sync::{Arc, Mutex};
+
+/// Most cells a word called from Rust, or a closure called from Forth, may take
+pub const MAX_ARGUMENTS: usize = 6;
+
+/// Rust values passed to Forth as cells
+pub trait IntoCells {
+    /// Number of cells the value occupies
+    const CELLS: usize;
+
+    /// Append the value's cells, deepest first
+    fn push_cells(self, cells: &mut Vec<i64>);
+}
+
+/// Rust values read back from Forth cells
+pub trait FromCells: Sized {
+    /// Number of cells the value occupies
+    const CELLS: usize;
+
+    /// Read the value from exactly [`CELLS`](Self::CELLS) cells, deepest first
+    ///
+    /// # Safety
+    ///
+    /// Cells read as a string must be the address and length of readable
+    /// memory.
+    unsafe fn from_cells(cells: &[i64]) -> Self;
+}
+
+macro_rules! integer_cells {
+    ($($ty:ty),*) => {$(
+        impl IntoCells for $ty {
+            const CELLS: usize = 1;
+
+            fn push_cells(self, cells: &mut Vec<i64>) {
+                cells.push(self as i64);
+            }
+        }
+
+        impl FromCells for $ty {
+            const CELLS: usize = 1;
+
+            unsafe fn from_cells(cells: &[i64]) -> Self {
+                cells[0] as $ty
+            }
+        }
+    )*};
+}
+
+integer_cells!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl IntoCells for bool {
+    const CELLS: usize = 1;
+
+    fn push_cells(self, cells: &mut Vec<i64>) {
+        cells.push(if self { -1 } else { 0 });
+    }
+}
+
+impl FromCells for bool {
+    const CELLS: usize = 1;
+
+    unsafe fn from_cells(cells: &[i64]) -> Self {
+        cells[0] != 0
+    }
+}
+
+impl IntoCells for f64 {
+    const CELLS: usize = 1;
+
+    fn push_cells(self, cells: &mut Vec<i64>) {
+        cells.push(self.to_bits() as i64);
+    }
+}
+
+impl FromCells for f64 {
+    const CELLS: usize = 1;
+
+    unsafe fn from_cells(cells: &[i64]) -> Self {
+        f64::from_bits(cells[0] as u64)
+    }
+}
+
+impl IntoCells for &str {
+    const CELLS: usize = 2;
+
+    fn push_cells(self, cells: &mut Vec<i64>) {
+        cells.push(self.as_ptr() as i64);
+        cells.push(self.len() as i64);
+    }
+}
+
+impl FromCells for String {
+    const CELLS: usize = 2;
+
+    unsafe fn from_cells(cells: &[i64]) -> Self {
+        let (addr, len) = (cells[0], cells[1]);
+        if addr == 0 || len <= 0 {
+            return String::new();
+        }
+        let bytes = std::slice::from_raw_parts(addr as *const u8, len as usize);
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+}
+
+macro_rules! tuple_cells {
+    ($($member:ident),*) => {
+        impl<$($member: IntoCells),*> IntoCells for ($($member,)*) {
+            const CELLS: usize = 0 $(+ $member::CELLS)*;
+
+            #[allow(non_snake_case, unused_variables)]
+            fn push_cells(self, cells: &mut Vec<i64>) {
+                let ($($member,)*) = self;
+                $($member.push_cells(cells);)*
+            }
+        }
+
+        impl<$($member: FromCells),*> FromCells for ($($member,)*) {
+            const CELLS: usize = 0 $(+ $member::CELLS)*;
+
+            #[allow(unused_variables, unused_mut, unused_assignments, clippy::unused_unit)]
+            unsafe fn from_cells(cells: &[i64]) -> Self {
+                let mut offset = 0;
+                ($({
+                    let value = $member::from_cells(&cells[offset..offset + $member::CELLS]);
+                    offset += $member::CELLS;
+                    value
+                },)*)
+            }
+        }
+    };
+}
+
+tuple_cells!();
+tuple_cells!(A);
+tuple_cells!(A, B);
+tuple_cells!(A, B, C);
+tuple_cells!(A, B, C, D);
+tuple_cells!(A, B, C, D, E);
+tuple_cells!(A, B, C, D, E, F);
+
+/// A closure called from Forth with its argument cells, returning the cell
+/// it leaves (0 if it leaves none)
+pub(crate) type HostClosure = Arc<dyn Fn(&[i64]) -> i64 + Send + Sync>;
+
+/// Registered closures, indexed by id; released slots are reused
+static CLOSURES: Mutex<Vec<Option<HostClosure>>> = Mutex::new(Vec::new());
+
+/// Register a closure for the dispatchers, returning its id
+pub(crate) fn register_closure(closure: HostClosure) -> i64 {
+    let mut closures = CLOSURES.lock().unwrap_or_else(|e| e.into_inner());
+    match closures.iter().position(Option::is_none) {
+        Some(id) => {
+            closures[id] = Some(closure);
+            id as i64
+        }
+        None => {
+            closures.push(Some(closure));
+            closures.len() as i64 - 1
+        }
+    }
+}
+
+/// Release a closure's id for reuse
+pub(crate) fn release_closure(id: i64) {
+    let mut closures = CLOSURES.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(slot) = closures.get_mut(id as usize) {
+        *slot = None;
+    }
+}
+
+/// Run a registered closure; the table is unlocked while it runs, so the
+/// closure may itself call into Forth
+fn run_closure(id: i64, args: &[i64]) -> i64 {
+    let closure = {
+        let closures = CLOSURES.lock().unwrap_or_else(|e| e.into_inner());
+        closures.get(id as usize).cloned().flatten()
+    };
+    closure.map_or(0, |closure| crate::cancel::unguarded(|| closure(args)))
+}
+
+macro_rules! cell_type {
+    ($arg:ident) => {
+        i64
+    };
+}
+
+macro_rules! arities {
+    ($($count:literal => $dispatch:ident($($arg:ident),*);)*) => {
+        $(
+            extern "C" fn $dispatch($($arg: i64,)* id: i64) -> i64 {
+                run_closure(id, &[$($arg),*])
+            }
+        )*
+
+        /// Address of the dispatcher for closures taking `arguments` cells
+        pub(crate) fn dispatcher(arguments: usize) -> Option<*const u8> {
+            match arguments {
+                $($count => Some($dispatch as *const u8),)*
+                _ => None,
+            }
+        }
+
+        /// Call a compiled word with the JIT calling convention, returning
+        /// the cell it leaves, which is meaningless if it leaves nothing
+        ///
+        /// # Safety
+        ///
+        /// `address` must be a compiled word taking exactly `args.len()` cells.
+        pub(crate) unsafe fn call_word(address: *const u8, args: &[i64]) -> Option<i64> {
+            match *args {
+                $([$($arg),*] => {
+                    let word: unsafe extern "C" fn($(cell_type!($arg)),*) -> i64 = std::mem::transmute(address);
+                    Some(word($($arg),*))
+                })*
+                _ => None,
+            }
+        }
+    };
+}
+
+arities! {
+    0 => dispatch_0();
+    1 => dispatch_1(a);
+    2 => dispatch_2(a, b);
+    3 => dispatch_3(a, b, c);
+    4 => dispatch_4(a, b, c, d);
+    5 => dispatch_5(a, b, c, d, e);
+    6 => dispatch_6(a, b, c, d, e, f);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(value: impl IntoCells) -> Vec<i64> {
+        let mut cells = Vec::new();
+        value.push_cells(&mut cells);
+        cells
+    }
+
+    #[test]
+    fn test_values_round_trip_through_cells() {
+        assert_eq!(cells((1i64, true, -2i32)), vec![1, -1, -2]);
+        assert_eq!(<(i64, bool, i32) as FromCells>::CELLS, 3);
+        let (a, flag, b) = unsafe { <(i64, bool, i32)>::from_cells(&[7, -1, -2]) };
+        assert_eq!((a, flag, b), (7, true, -2));
+        assert_eq!(unsafe { f64::from_cells(&cells(2.5f64)) }, 2.5);
+
+        let text = "hello";
+        let pair = cells((text,));
+        assert_eq!(pair[1], 5);
+        assert_eq!(unsafe { String::from_cells(&pair) }, "hello");
+    }
+
+    #[test]
+    fn test_dispatchers_run_registered_closures() {
+        let id = register_closure(Arc::new(|args: &[i64]| args.iter().sum()));
+        let dispatch: extern "C" fn(i64, i64, i64) -> i64 = unsafe { std::mem::transmute(dispatcher(2).unwrap()) };
+        assert_eq!(dispatch(40, 2, id), 42);
+        release_closure(id);
+        assert!(dispatcher(MAX_ARGUMENTS + 1).is_none());
+    }
+}
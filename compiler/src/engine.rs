@@ -1,8 +1,34 @@
 //! Simple Forth engine for testing and REPL
 //!
 //! Provides a simple interface for executing Forth code and inspecting the stack
+//!
+//! It is also the way to embed Forth in a Rust program: [`ForthEngine::define`]
+//! JIT-compiles definitions, [`ForthEngine::call`] calls them with typed
+//! arguments and results (see [`crate::embed`]), and
+//! [`ForthEngine::register`] makes Rust closures callable from Forth.
+//! [`ForthEngine::save_image`] captures the dictionary and data space as an
+//! [`Image`] that [`ForthEngine::restore_image`] starts from later.
+//!
+//! ```rust,no_run
+//! use fastforth::ForthEngine;
+//!
+//! let mut engine = ForthEngine::new();
+//! engine.register("scale", |(n,): (i64,)| (n * 10,))?;
+//! engine.define(": gcd ( a b -- n ) dup if swap over mod gcd else drop then ;")?;
+//! engine.define(": scaled-gcd ( a b -- n ) gcd scale ;")?;
+//! let (n,): (i64,) = engine.call("scaled-gcd", (48, 18))?;
+//! assert_eq!(n, 60);
+//! # Ok::<(), fastforth::CompileError>(())
+//! ```
 
+use crate::cancel::CancelToken;
+use crate::embed::{self, FromCells, HostClosure, IntoCells, MAX_ARGUMENTS};
+use crate::error::CompileError;
+use crate::image::{DataSpace, Image, ImageWord, WordKind};
+use crate::pipeline::{HostFunction, JitProgram};
 use crate::{Compiler, CompilationMode, OptimizationLevel, Result};
+use fastforth_frontend::ast::{StackEffect, StackType};
+use fastforth_frontend::{parse_program, CodeWord, Definition, Program, Word};
 use std::collections::HashMap;
 use std::fmt;
 
@@ -18,6 +44,70 @@ pub struct ForthEngine {
     next_addr: i64,
     base: i64,
     output: String,
+    definitions: Vec<Definition>,
+    code_words: Vec<CodeWord>,
+    host_words: Vec<HostWord>,
+    /// Every source [`define`](Self::define) took, in order, for images
+    sources: Vec<String>,
+    jit: Option<JitProgram>,
+}
+
+/// A Rust closure registered as a Forth word
+struct HostWord {
+    name: String,
+    inputs: usize,
+    returns: bool,
+    /// Closure id passed to the dispatcher
+    id: i64,
+}
+
+impl HostWord {
+    /// The word as a definition passing its inputs and id to the dispatcher
+    fn definition(&self) -> Definition {
+        let mut body = vec![Word::IntLiteral(self.id), Word::WordRef {
+            name: dispatcher_name(self.inputs).into(),
+            location: Default::default(),
+        }];
+        if !self.returns {
+            body.push(Word::WordRef { name: "drop".into(), location: Default::default() });
+        }
+        Definition {
+            name: self.name.clone(),
+            body,
+            immediate: false,
+            stack_effect: Some(cell_effect(self.inputs, usize::from(self.returns))),
+            attributes: Default::default(),
+            location: Default::default(),
+        }
+    }
+}
+
+/// Word the dispatcher for closures taking `inputs` cells is called by;
+/// the space keeps it from clashing with any word written in source
+fn dispatcher_name(inputs: usize) -> String {
+    format!("(host-call {})", inputs)
+}
+
+fn cell_effect(inputs: usize, outputs: usize) -> StackEffect {
+    StackEffect::new(vec![StackType::Int; inputs], vec![StackType::Int; outputs])
+}
+
+/// The words of `program` added to `definitions` and `code_words`, each
+/// replacing any word of the same name
+fn merge(definitions: &[Definition], code_words: &[CodeWord], program: Program) -> (Vec<Definition>, Vec<CodeWord>) {
+    let mut merged: Vec<Definition> = definitions
+        .iter()
+        .filter(|def| !program.definitions.iter().any(|new| new.name == def.name))
+        .cloned()
+        .collect();
+    merged.extend(program.definitions);
+    let mut merged_code: Vec<CodeWord> = code_words
+        .iter()
+        .filter(|word| !program.code_words.iter().any(|new| new.name == word.name))
+        .cloned()
+        .collect();
+    merged_code.extend(program.code_words);
+    (merged, merged_code)
 }
 
 impl ForthEngine {
@@ -34,7 +124,323 @@ impl ForthEngine {
             next_addr: 0x1000, // Start memory addresses at 0x1000
             base: 10,
             output: String::new(),
+            definitions: Vec::new(),
+            code_words: Vec::new(),
+            host_words: Vec::new(),
+            sources: Vec::new(),
+            jit: None,
+        }
+    }
+
+    /// JIT-compile the definitions in `source` so [`call`](Self::call) can
+    /// run them
+    ///
+    /// Words stay defined, so later sources can use them; defining an
+    /// existing name replaces it. Top-level code is rejected: this defines
+    /// words, and [`eval`](Self::eval) runs code. On error the engine is
+    /// unchanged.
+    pub fn define(&mut self, source: &str) -> Result<()> {
+        let program = parse_program(source)?;
+        if !program.top_level_code.is_empty() {
+            return Err(CompileError::SemanticError(
+                "define takes definitions only; top-level code is not run".to_string(),
+            ));
+        }
+
+        let (definitions, code_words) = merge(&self.definitions, &self.code_words, program);
+        self.jit = Some(self.compile(&definitions, &code_words, &self.host_words)?);
+        self.definitions = definitions;
+        self.code_words = code_words;
+        self.sources.push(source.to_string());
+        Ok(())
+    }
+
+    /// The dictionary and data space, to [restore](Self::restore_image)
+    /// in another engine or process
+    ///
+    /// Definitions are kept as the sources that defined them and compiled
+    /// again on restore; host words are kept by name and stack effect only,
+    /// since closures cannot be saved.
+    pub fn save_image(&self) -> Image {
+        let colon = self.definitions.iter().map(|def| ImageWord {
+            name: def.name.clone(),
+            kind: WordKind::Colon,
+            stack_effect: def.stack_effect.as_ref().map(ToString::to_string),
+            line: def.location.line,
+        });
+        let code = self.code_words.iter().map(|word| ImageWord {
+            name: word.name.clone(),
+            kind: WordKind::Code,
+            stack_effect: Some(word.stack_effect.to_string()),
+            line: word.location.line,
+        });
+        let host = self.host_words.iter().map(|word| ImageWord {
+            name: word.name.clone(),
+            kind: WordKind::Host,
+            stack_effect: Some(cell_effect(word.inputs, usize::from(word.returns)).to_string()),
+            line: 0,
+        });
+
+        Image::new(
+            self.sources.clone(),
+            colon.chain(code).chain(host).collect(),
+            DataSpace {
+                stack: self.stack.clone(),
+                memory: self.memory.iter().map(|(&addr, &value)| (addr, value)).collect(),
+                variables: self.variables.clone().into_iter().collect(),
+                constants: self.constants.clone().into_iter().collect(),
+                values: self.values.clone().into_iter().collect(),
+                next_addr: self.next_addr,
+                base: self.base,
+            },
+        )
+    }
+
+    /// Replace the dictionary and data space with those of `image`,
+    /// compiling its definitions in one go
+    ///
+    /// The host words the image was saved with must be
+    /// [registered](Self::register) first, with the same stack effects. On
+    /// error the engine is unchanged.
+    pub fn restore_image(&mut self, image: &Image) -> Result<()> {
+        image.check_format()?;
+        let missing: Vec<&str> = image
+            .words
+            .iter()
+            .filter(|word| word.kind == WordKind::Host)
+            .filter(|word| {
+                !self.host_words.iter().any(|host| {
+                    host.name == word.name
+                        && word.stack_effect.as_deref()
+                            == Some(cell_effect(host.inputs, usize::from(host.returns)).to_string().as_str())
+                })
+            })
+            .map(|word| word.name.as_str())
+            .collect();
+        if !missing.is_empty() {
+            return Err(CompileError::RuntimeError(format!(
+                "the image needs these host words registered first: {}",
+                missing.join(", ")
+            )));
+        }
+
+        let (mut definitions, mut code_words) = (Vec::new(), Vec::new());
+        for source in &image.sources {
+            (definitions, code_words) = merge(&definitions, &code_words, parse_program(source)?);
+        }
+        let jit = if definitions.is_empty() && code_words.is_empty() && self.host_words.is_empty() {
+            None
+        } else {
+            Some(self.compile(&definitions, &code_words, &self.host_words)?)
+        };
+
+        let data = &image.data;
+        self.jit = jit;
+        self.definitions = definitions;
+        self.code_words = code_words;
+        self.sources = image.sources.clone();
+        self.stack = data.stack.clone();
+        self.memory = data.memory.iter().map(|(&addr, &value)| (addr, value)).collect();
+        self.variables = data.variables.clone().into_iter().collect();
+        self.constants = data.constants.clone().into_iter().collect();
+        self.values = data.values.clone().into_iter().collect();
+        self.next_addr = data.next_addr;
+        self.base = data.base;
+        Ok(())
+    }
+
+    /// Make a Rust closure callable from Forth as the word `name`
+    ///
+    /// The closure takes its argument tuple from the stack, deepest first,
+    /// and leaves its result, which is `()` or a single cell. Registering
+    /// a name again replaces the closure; words already defined keep
+    /// calling it by name, so they call the new one.
+    ///
+    /// A panic in the closure aborts the process, since it would unwind
+    /// through compiled code.
+    pub fn register<A, R, F>(&mut self, name: &str, closure: F) -> Result<()>
+    where
+        A: FromCells,
+        R: IntoCells,
+        F: Fn(A) -> R + Send + Sync + 'static,
+    {
+        if A::CELLS > MAX_ARGUMENTS || R::CELLS > 1 {
+            return Err(CompileError::TypeError(format!(
+                "host word '{}' takes {} cells and leaves {}; at most {} in and 1 out are supported",
+                name, A::CELLS, R::CELLS, MAX_ARGUMENTS
+            )));
+        }
+
+        self.register_cells(name, A::CELLS, R::CELLS == 1, std::sync::Arc::new(move |cells: &[i64]| {
+            // The dispatcher passes exactly A::CELLS cells, each an integer
+            // or the address and length of a string the program owns
+            let args = unsafe { A::from_cells(cells) };
+            let mut results = Vec::with_capacity(1);
+            closure(args).push_cells(&mut results);
+            results.first().copied().unwrap_or(0)
+        }))
+    }
+
+    /// Register a closure over cells as the word `name`, taking `inputs`
+    /// cells and leaving one if `returns`
+    pub(crate) fn register_cells(&mut self, name: &str, inputs: usize, returns: bool, closure: HostClosure) -> Result<()> {
+        let id = embed::register_closure(closure);
+        let word = HostWord { name: name.to_string(), inputs, returns, id };
+
+        let mut host_words: Vec<HostWord> = Vec::with_capacity(self.host_words.len() + 1);
+        let mut replaced = None;
+        for existing in self.host_words.drain(..) {
+            if existing.name == name {
+                replaced = Some(existing);
+            } else {
+                host_words.push(existing);
+            }
+        }
+        host_words.push(word);
+
+        match self.compile(&self.definitions, &self.code_words, &host_words) {
+            Ok(jit) => {
+                self.jit = Some(jit);
+                if let Some(replaced) = replaced {
+                    embed::release_closure(replaced.id);
+                }
+                self.host_words = host_words;
+                Ok(())
+            }
+            Err(e) => {
+                let word = host_words.pop().expect("just pushed");
+                embed::release_closure(word.id);
+                host_words.extend(replaced);
+                self.host_words = host_words;
+                Err(e)
+            }
+        }
+    }
+
+    /// Call a word compiled by [`define`](Self::define)
+    ///
+    /// The argument tuple is pushed deepest first and the result read back
+    /// from what the word leaves. Both are checked against the word's
+    /// declared stack effect, which the word must have; a word called from
+    /// Rust leaves at most one cell.
+    ///
+    /// ```rust,no_run
+    /// # use fastforth::ForthEngine;
+    /// # let mut engine = ForthEngine::new();
+    /// engine.define(": gcd ( a b -- n ) dup if swap over mod gcd else drop then ;")?;
+    /// let (n,): (i64,) = engine.call("gcd", (48, 18))?;
+    /// # Ok::<(), fastforth::CompileError>(())
+    /// ```
+    pub fn call<A: IntoCells, R: FromCells>(&self, name: &str, args: A) -> Result<R> {
+        let mut cells = Vec::with_capacity(A::CELLS);
+        args.push_cells(&mut cells);
+        let results = self.call_cells(name, &cells, R::CELLS)?;
+        // The cells of any string argument stay borrowed until the call
+        // returns, and a string result is memory the program owns
+        Ok(unsafe { R::from_cells(&results) })
+    }
+
+    /// Call a word with arguments already marshalled into cells, returning
+    /// the `outputs` cells it leaves
+    ///
+    /// This is [`call`](Self::call) for callers that only know the types at
+    /// run time, such as language bindings.
+    pub fn call_cells(&self, name: &str, cells: &[i64], outputs: usize) -> Result<Vec<i64>> {
+        let address = self.callable(name, cells, outputs)?;
+        // The word was compiled taking its declared inputs
+        let top = unsafe { embed::call_word(address, cells) }.expect("argument count was checked");
+        Ok([top][..outputs].to_vec())
+    }
+
+    /// [`call`](Self::call) under `cancel`, failing with
+    /// [`CompileError::Cancelled`] once it is cancelled, and with
+    /// [`CompileError::Trapped`] if the word faults
+    ///
+    /// Only words compiled after [`set_interruptible`](Self::set_interruptible)
+    /// stop before they return.
+    pub fn call_cancellable<A: IntoCells, R: FromCells>(&self, name: &str, args: A, cancel: &CancelToken) -> Result<R> {
+        let mut cells = Vec::with_capacity(A::CELLS);
+        args.push_cells(&mut cells);
+        let address = self.callable(name, &cells, R::CELLS)?;
+        // The word was compiled taking its declared inputs, and the cells
+        // of any string argument stay borrowed until it returns
+        let top = unsafe { cancel.call(address, &cells) }.map_err(|error| match &self.jit {
+            Some(jit) => jit.trapped(error),
+            None => error,
+        })?;
+        Ok(unsafe { R::from_cells(&[top][..R::CELLS]) })
+    }
+
+    /// Compile words so [`call_cancellable`](Self::call_cancellable) can
+    /// stop them, recompiling the words already defined
+    pub fn set_interruptible(&mut self, interruptible: bool) -> Result<()> {
+        self.compiler.set_interruptible(interruptible);
+        if self.jit.is_some() {
+            self.jit = Some(self.compile(&self.definitions, &self.code_words, &self.host_words)?);
+        }
+        Ok(())
+    }
+
+    /// Address of a word, checking the cells it is called with and read
+    /// back as against its declared stack effect
+    fn callable(&self, name: &str, cells: &[i64], outputs: usize) -> Result<*const u8> {
+        let effect = self.stack_effect(name)?;
+        if effect.inputs.len() != cells.len() || effect.outputs.len() != outputs {
+            return Err(CompileError::TypeError(format!(
+                "'{}' {} is called with {} cells and read as {}",
+                name, effect, cells.len(), outputs
+            )));
+        }
+        if cells.len() > MAX_ARGUMENTS || outputs > 1 {
+            return Err(CompileError::TypeError(format!(
+                "'{}' {} cannot be called from Rust: at most {} cells in and 1 out are supported",
+                name, effect, MAX_ARGUMENTS
+            )));
         }
+
+        self.jit
+            .as_ref()
+            .and_then(|jit| jit.word(name))
+            .ok_or_else(|| CompileError::RuntimeError(format!("Word '{}' is not compiled", name)))
+    }
+
+    /// Declared stack effect of a word [`call`](Self::call) can run
+    pub fn stack_effect(&self, name: &str) -> Result<StackEffect> {
+        if let Some(def) = self.definitions.iter().find(|def| def.name == name) {
+            def.stack_effect.clone().ok_or_else(|| {
+                CompileError::TypeError(format!("'{}' must declare its stack effect to be called from Rust", name))
+            })
+        } else if let Some(word) = self.host_words.iter().find(|word| word.name == name) {
+            Ok(cell_effect(word.inputs, usize::from(word.returns)))
+        } else {
+            Err(CompileError::RuntimeError(format!("Word '{}' is not defined", name)))
+        }
+    }
+
+    /// Compile user words and host words together, each host word a
+    /// definition passing its inputs and id to a dispatcher
+    fn compile(&self, definitions: &[Definition], code_words: &[CodeWord], host_words: &[HostWord]) -> Result<JitProgram> {
+        let mut arities: Vec<usize> = host_words.iter().map(|word| word.inputs).collect();
+        arities.sort_unstable();
+        arities.dedup();
+        let host_functions: Vec<HostFunction> = arities
+            .into_iter()
+            .map(|inputs| HostFunction {
+                name: dispatcher_name(inputs),
+                stack_effect: cell_effect(inputs + 1, 1),
+                address: embed::dispatcher(inputs).expect("arity was checked on registration"),
+            })
+            .collect();
+
+        let program = Program {
+            definitions: host_words.iter().map(HostWord::definition).chain(definitions.iter().cloned()).collect(),
+            top_level_code: Vec::new(),
+            tests: Vec::new(),
+            code_words: code_words.to_vec(),
+            records: Vec::new(),
+            names: Default::default(),
+        };
+        self.compiler.pipeline()?.prepare_jit_program(&program, &host_functions)
     }
 
     /// Evaluate Forth code
@@ -453,6 +859,14 @@ impl Default for ForthEngine {
     }
 }
 
+impl Drop for ForthEngine {
+    fn drop(&mut self) {
+        for word in &self.host_words {
+            embed::release_closure(word.id);
+        }
+    }
+}
+
 impl fmt::Debug for ForthEngine {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ForthEngine")
@@ -485,4 +899,66 @@ mod tests {
         engine.eval("5 DUP").unwrap();
         assert_eq!(engine.stack(), &[5, 5]);
     }
+
+    #[test]
+    fn test_define_and_call() {
+        let mut engine = ForthEngine::new();
+        engine.define(": gcd ( a b -- n ) dup if swap over mod gcd else drop then ;").unwrap();
+        engine.define(": even? ( n -- flag ) 2 mod 0 = ;").unwrap();
+        let (n,): (i64,) = engine.call("gcd", (48, 18)).unwrap();
+        assert_eq!(n, 6);
+        assert!(engine.call::<_, bool>("even?", (gcd_of(&engine, 48, 18),)).unwrap());
+
+        engine.define(": gcd ( a b -- n ) + ;").unwrap();
+        assert_eq!(gcd_of(&engine, 48, 18), 66, "redefining replaces the word");
+    }
+
+    fn gcd_of(engine: &ForthEngine, a: i64, b: i64) -> i64 {
+        engine.call::<_, (i64,)>("gcd", (a, b)).unwrap().0
+    }
+
+    #[test]
+    fn test_host_closures() {
+        use std::sync::{Arc, Mutex};
+
+        let mut engine = ForthEngine::new();
+        engine.register("scale", |(n,): (i64,)| (n * 10,)).unwrap();
+        let logged = Arc::new(Mutex::new(Vec::new()));
+        let log = logged.clone();
+        engine.register("log", move |(text,): (String,)| log.lock().unwrap().push(text)).unwrap();
+
+        engine.define(": scaled ( a b -- n ) + scale ;\n: greet ( c-addr u -- ) log ;").unwrap();
+        assert_eq!(engine.call::<_, i64>("scaled", (2, 3)).unwrap(), 50);
+        engine.call::<_, ()>("greet", ("hello",)).unwrap();
+        assert_eq!(*logged.lock().unwrap(), vec!["hello".to_string()]);
+
+        engine.register("scale", |(n,): (i64,)| (n * 100,)).unwrap();
+        assert_eq!(engine.call::<_, i64>("scaled", (2, 3)).unwrap(), 500, "words call the new closure");
+    }
+
+    #[test]
+    fn test_call_checks_the_stack_effect() {
+        let mut engine = ForthEngine::new();
+        engine.define(": add ( a b -- n ) + ;\n: sq dup * ;").unwrap();
+        assert!(engine.call::<_, i64>("add", (1,)).is_err());
+        assert!(engine.call::<_, ()>("add", (1, 2)).is_err());
+        assert!(engine.call::<_, i64>("sq", (3,)).is_err(), "the stack effect must be declared");
+        assert!(engine.call::<_, i64>("missing", ()).is_err());
+        assert!(engine.define("1 2 add").is_err());
+        assert!(engine.define(": bad ( -- n ) undefined-word ;").is_err());
+        assert_eq!(engine.call::<_, i64>("add", (1, 2)).unwrap(), 3, "a failed define changes nothing");
+    }
+
+    #[test]
+    fn test_cancelled_calls_stop() {
+        let mut engine = ForthEngine::new();
+        engine.set_interruptible(true).unwrap();
+        engine.define(": spin ( n -- n ) begin 1 + dup 0 < until ;\n: add ( a b -- n ) + ;").unwrap();
+
+        let cancel = CancelToken::new();
+        let _deadline = cancel.cancel_after(std::time::Duration::from_millis(20));
+        let result = engine.call_cancellable::<_, i64>("spin", (0,), &cancel);
+        assert!(matches!(result, Err(CompileError::Cancelled)), "{:?}", result);
+        assert_eq!(engine.call_cancellable::<_, i64>("add", (1, 2), &CancelToken::new()).unwrap(), 3);
+    }
 }
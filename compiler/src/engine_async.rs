@@ -0,0 +1,237 @@
+//! Async Embedding
+//!
+//! [`ForthEngineAsync`] embeds Forth in a tokio program. Compiled words run
+//! on the blocking pool, so a long computation never stalls the event loop,
+//! and every call is limited by the engine's timeout or stopped through a
+//! [`CancelToken`]. Words are compiled interruptible (see [`crate::cancel`]),
+//! so a call that runs out of time stops at its next loop iteration instead
+//! of holding a thread.
+//!
+//! [`ForthEngineAsync::register_async`] makes an async Rust function
+//! callable from Forth: the word that calls it waits for the future on its
+//! blocking thread, and stops waiting once its call is cancelled.
+//!
+//! ```rust,no_run
+//! use fastforth::ForthEngineAsync;
+//! use std::time::Duration;
+//!
+//! # async fn run() -> fastforth::Result<()> {
+//! let mut engine = ForthEngineAsync::new();
+//! engine.set_timeout(Some(Duration::from_secs(1)));
+//! engine.register_async("fetch", |(key,): (i64,)| async move { (key * 2,) }).await?;
+//! engine.define(": lookup ( key -- n ) fetch 1 + ;").await?;
+//! let n: i64 = engine.call("lookup", (20,)).await?;
+//! assert_eq!(n, 41);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::cancel::CancelToken;
+use crate::embed::{FromCells, IntoCells, MAX_ARGUMENTS};
+use crate::error::CompileError;
+use crate::{ForthEngine, Result};
+use std::cell::RefCell;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// How often a waiting host callback checks whether its call was cancelled
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(5);
+
+thread_local! {
+    /// Token of the call running on this blocking thread
+    static CURRENT_CALL: RefCell<Option<CancelToken>> = const { RefCell::new(None) };
+}
+
+/// A [`ForthEngine`] whose words run on tokio's blocking pool
+///
+/// Clones share the engine and its words, and calls through them run
+/// concurrently. Defining or registering a word waits for running calls
+/// to finish.
+#[derive(Clone)]
+pub struct ForthEngineAsync {
+    engine: Arc<RwLock<ForthEngine>>,
+    timeout: Option<Duration>,
+}
+
+impl ForthEngineAsync {
+    /// Create an engine with no timeout
+    pub fn new() -> Self {
+        let mut engine = ForthEngine::new();
+        engine.set_interruptible(true).expect("nothing is compiled yet");
+        Self { engine: Arc::new(RwLock::new(engine)), timeout: None }
+    }
+
+    /// Limit each [`call`](Self::call) to `timeout`; `None` lets calls run
+    /// until they return
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// JIT-compile the definitions in `source`, like
+    /// [`ForthEngine::define`]
+    pub async fn define(&self, source: &str) -> Result<()> {
+        let source = source.to_string();
+        self.write(move |engine| engine.define(&source)).await
+    }
+
+    /// Make a Rust closure callable from Forth, like
+    /// [`ForthEngine::register`]
+    pub async fn register<A, R, F>(&self, name: &str, closure: F) -> Result<()>
+    where
+        A: FromCells,
+        R: IntoCells,
+        F: Fn(A) -> R + Send + Sync + 'static,
+    {
+        let name = name.to_string();
+        self.write(move |engine| engine.register(&name, closure)).await
+    }
+
+    /// Make an async Rust function callable from Forth as the word `name`
+    ///
+    /// Arguments and results are marshalled as for
+    /// [`ForthEngine::register`]. The word waits for the future on the
+    /// calling thread, driven by the runtime the engine is used from. If
+    /// the call is cancelled while it waits, the future is dropped, the
+    /// word leaves 0 and the call stops at its next poll.
+    pub async fn register_async<A, R, F, Fut>(&self, name: &str, callback: F) -> Result<()>
+    where
+        A: FromCells,
+        R: IntoCells,
+        F: Fn(A) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = R> + Send + 'static,
+    {
+        if A::CELLS > MAX_ARGUMENTS || R::CELLS > 1 {
+            return Err(CompileError::TypeError(format!(
+                "host word '{}' takes {} cells and leaves {}; at most {} in and 1 out are supported",
+                name, A::CELLS, R::CELLS, MAX_ARGUMENTS
+            )));
+        }
+
+        let runtime = tokio::runtime::Handle::current();
+        let closure = Arc::new(move |cells: &[i64]| {
+            // As for `register`, the dispatcher passes exactly A::CELLS cells
+            let future = callback(unsafe { A::from_cells(cells) });
+            let cancel = CURRENT_CALL.with(|current| current.borrow().clone());
+            let result = runtime.block_on(async move {
+                match cancel {
+                    Some(cancel) => tokio::select! {
+                        result = future => Some(result),
+                        _ = cancelled(&cancel) => None,
+                    },
+                    None => Some(future.await),
+                }
+            });
+            let mut results = Vec::with_capacity(1);
+            if let Some(result) = result {
+                result.push_cells(&mut results);
+            }
+            results.first().copied().unwrap_or(0)
+        });
+        let name = name.to_string();
+        self.write(move |engine| engine.register_cells(&name, A::CELLS, R::CELLS == 1, closure)).await
+    }
+
+    /// Call a word, like [`ForthEngine::call`], failing with
+    /// [`CompileError::Cancelled`] if it runs past the engine's timeout
+    pub async fn call<A, R>(&self, name: &str, args: A) -> Result<R>
+    where
+        A: IntoCells + Send + 'static,
+        R: FromCells + Send + 'static,
+    {
+        self.call_cancellable(name, args, CancelToken::new()).await
+    }
+
+    /// [`call`](Self::call) that also stops once `cancel` is cancelled
+    ///
+    /// A call that runs out of time cancels `cancel` and returns at once;
+    /// the word stops on its blocking thread at its next poll.
+    pub async fn call_cancellable<A, R>(&self, name: &str, args: A, cancel: CancelToken) -> Result<R>
+    where
+        A: IntoCells + Send + 'static,
+        R: FromCells + Send + 'static,
+    {
+        let engine = self.engine.clone();
+        let word = name.to_string();
+        let token = cancel.clone();
+        let mut call = tokio::task::spawn_blocking(move || {
+            let engine = engine.read().unwrap_or_else(|e| e.into_inner());
+            CURRENT_CALL.with(|current| *current.borrow_mut() = Some(token.clone()));
+            let result = engine.call_cancellable(&word, args, &token);
+            CURRENT_CALL.with(|current| current.borrow_mut().take());
+            result
+        });
+
+        let finished = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, &mut call).await.ok(),
+            None => Some((&mut call).await),
+        };
+        match finished {
+            Some(joined) => joined.map_err(|e| CompileError::RuntimeError(format!("call of '{}' failed: {}", name, e)))?,
+            None => {
+                cancel.cancel();
+                Err(CompileError::Cancelled)
+            }
+        }
+    }
+
+    /// Run `change` on the engine on the blocking pool, once no call is
+    /// running
+    async fn write<T, F>(&self, change: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut ForthEngine) -> Result<T> + Send + 'static,
+    {
+        let engine = self.engine.clone();
+        tokio::task::spawn_blocking(move || change(&mut engine.write().unwrap_or_else(|e| e.into_inner())))
+            .await
+            .map_err(|e| CompileError::RuntimeError(format!("engine update failed: {}", e)))?
+    }
+}
+
+impl Default for ForthEngineAsync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resolves once `cancel` is cancelled
+async fn cancelled(cancel: &CancelToken) {
+    while !cancel.is_cancelled() {
+        tokio::time::sleep(CANCEL_CHECK_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_calls_time_out_and_await_callbacks() {
+        let mut engine = ForthEngineAsync::new();
+        engine
+            .register_async("fetch", |(key,): (i64,)| async move {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                (key * 2,)
+            })
+            .await
+            .unwrap();
+        engine
+            .register_async("stall", |(): ()| async { std::future::pending::<()>().await })
+            .await
+            .unwrap();
+        engine
+            .define(": lookup ( key -- n ) fetch 1 + ;\n: spin ( n -- n ) begin 1 + dup 0 < until ;\n: wait ( -- ) stall ;")
+            .await
+            .unwrap();
+        assert_eq!(engine.call::<_, i64>("lookup", (20,)).await.unwrap(), 41);
+
+        engine.set_timeout(Some(Duration::from_millis(50)));
+        assert!(matches!(engine.call::<_, i64>("spin", (0,)).await, Err(CompileError::Cancelled)));
+        assert!(matches!(engine.call::<_, ()>("wait", ()).await, Err(CompileError::Cancelled)));
+
+        // Both calls unwind, so the engine can be changed again
+        engine.define(": add ( a b -- n ) + ;").await.unwrap();
+        assert_eq!(engine.call::<_, i64>("add", (1, 2)).await.unwrap(), 3);
+    }
+}
@@ -1,5 +1,8 @@
 //! Error types for the Fast Forth compiler
 
+use crate::snapshot::Snapshot;
+use fastforth_frontend::ForthError;
+use std::fmt;
 use std::path::PathBuf;
 use thiserror::Error;
 
@@ -9,6 +12,13 @@ pub type Result<T> = std::result::Result<T, CompileError>;
 /// Compilation error types
 #[derive(Error, Debug)]
 pub enum CompileError {
+    /// Frontend error, kept whole so its source location survives
+    #[error("{stage} error: {error}")]
+    Frontend {
+        stage: FrontendStage,
+        error: ForthError,
+    },
+
     /// Frontend parsing error
     #[error("Parse error: {0}")]
     ParseError(String),
@@ -29,6 +39,11 @@ pub enum CompileError {
     #[error("Optimization error: {0}")]
     OptimizationError(String),
 
+    /// The optimized program computed something the unoptimized one does
+    /// not (`--verify-optimizations`)
+    #[error("Miscompile: {0}")]
+    Miscompile(String),
+
     /// Code generation error
     #[error("Code generation error: {0}")]
     CodeGenError(String),
@@ -52,11 +67,74 @@ pub enum CompileError {
     /// Internal compiler error
     #[error("Internal compiler error: {0}")]
     InternalError(String),
+
+    /// The process grew past the memory limit during a compilation stage
+    #[error("Memory limit exceeded during {stage}: {used} bytes in use, limit is {limit} bytes")]
+    MemoryLimitExceeded {
+        stage: &'static str,
+        used: u64,
+        limit: u64,
+    },
+
+    /// A run was stopped through its [`CancelToken`](crate::CancelToken)
+    #[error("Execution cancelled")]
+    Cancelled,
+
+    /// A sandboxed run spent its fuel
+    #[error("Execution ran out of fuel after {fuel} word calls and loop iterations")]
+    OutOfFuel {
+        fuel: u64,
+    },
+
+    /// Compiled code faulted, such as on a division by zero
+    #[error("Execution trapped: {0}")]
+    Trapped(Box<Snapshot>),
+
+    /// Compiled code threw an exception that nothing caught, such as
+    /// checked arithmetic on an overflow
+    #[error("Execution threw {code}: {}", throw_description(*code))]
+    Thrown {
+        code: i64,
+    },
+}
+
+/// What a THROW code means, for the codes compiled code throws
+fn throw_description(code: i64) -> &'static str {
+    match code {
+        -10 => "division by zero",
+        -11 => "result out of range",
+        _ => "exception",
+    }
+}
+
+/// Frontend stage that raised a [`CompileError::Frontend`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontendStage {
+    Parse,
+    Semantic,
+    SSA,
+}
+
+impl fmt::Display for FrontendStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrontendStage::Parse => write!(f, "Parse"),
+            FrontendStage::Semantic => write!(f, "Semantic"),
+            FrontendStage::SSA => write!(f, "SSA conversion"),
+        }
+    }
+}
+
+impl CompileError {
+    /// Wrap an error from a frontend stage
+    pub fn frontend(stage: FrontendStage, error: ForthError) -> Self {
+        CompileError::Frontend { stage, error }
+    }
 }
 
-impl From<fastforth_frontend::ForthError> for CompileError {
-    fn from(err: fastforth_frontend::ForthError) -> Self {
-        CompileError::ParseError(err.to_string())
+impl From<ForthError> for CompileError {
+    fn from(err: ForthError) -> Self {
+        CompileError::frontend(FrontendStage::Parse, err)
     }
 }
 
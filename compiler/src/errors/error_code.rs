@@ -7,6 +7,7 @@
 //! - E3000-E3999: Control flow errors
 //! - E4000-E4999: Optimization errors
 //! - E5000-E5999: Code generation errors
+//! - E6000-E6999: Lint warnings
 //! - E9000-E9999: Internal compiler errors
 
 use serde::{Serialize, Deserialize};
@@ -29,6 +30,7 @@ pub enum ErrorCode {
     InvalidStackEffect = 1002,
     InvalidImmediate = 1003,
     RecursionWithoutBaseCase = 1004,
+    UnavailableWord = 1005,
 
     // Stack Effect Errors (E2000-E2999)
     StackUnderflow = 2000,
@@ -55,16 +57,30 @@ pub enum ErrorCode {
     InliningError = 4001,
     ConstantFoldingError = 4002,
     DeadCodeEliminationError = 4003,
+    Miscompile = 4004,
 
     // Code Generation Errors (E5000-E5999)
     CodeGenFailed = 5000,
     LLVMError = 5001,
     LinkingError = 5002,
 
+    // Lint Warnings (E6000-E6999)
+    UnusedDefinition = 6000,
+    ShadowedCoreWord = 6001,
+    MissingStackEffect = 6002,
+    LoopStackImbalance = 6003,
+    OversizedWord = 6004,
+    ConstantCondition = 6005,
+
     // Internal Errors (E9000-E9999)
     InternalCompilerError = 9000,
     SSAConversionError = 9001,
     UnexpectedState = 9002,
+    MemoryLimitExceeded = 9003,
+    ExecutionCancelled = 9004,
+    ExecutionOutOfFuel = 9005,
+    ExecutionTrapped = 9006,
+    ExecutionThrew = 9007,
 }
 
 impl ErrorCode {
@@ -83,6 +99,7 @@ impl ErrorCode {
             3000..=3999 => "Control Flow",
             4000..=4999 => "Optimization",
             5000..=5999 => "Code Generation",
+            6000..=6999 => "Lint",
             9000..=9999 => "Internal",
             _ => "Unknown",
         }
@@ -103,6 +120,7 @@ impl ErrorCode {
             ErrorCode::InvalidStackEffect => "Invalid stack effect declaration",
             ErrorCode::InvalidImmediate => "Invalid use of immediate word",
             ErrorCode::RecursionWithoutBaseCase => "Recursive definition without base case",
+            ErrorCode::UnavailableWord => "Word not available on the compilation target",
 
             ErrorCode::StackUnderflow => "Stack underflow - insufficient items on stack",
             ErrorCode::StackOverflow => "Stack overflow - too many items on stack",
@@ -126,14 +144,27 @@ impl ErrorCode {
             ErrorCode::InliningError => "Error during function inlining",
             ErrorCode::ConstantFoldingError => "Error during constant folding",
             ErrorCode::DeadCodeEliminationError => "Error during dead code elimination",
+            ErrorCode::Miscompile => "Optimized program behaves differently from the original",
 
             ErrorCode::CodeGenFailed => "Code generation failed",
             ErrorCode::LLVMError => "LLVM backend error",
             ErrorCode::LinkingError => "Linking error",
 
+            ErrorCode::UnusedDefinition => "Definition is never used",
+            ErrorCode::ShadowedCoreWord => "Definition shadows a core word",
+            ErrorCode::MissingStackEffect => "Definition has no stack-effect comment",
+            ErrorCode::LoopStackImbalance => "Loop body changes the stack depth on each iteration",
+            ErrorCode::OversizedWord => "Definition is longer than the configured limit",
+            ErrorCode::ConstantCondition => "Condition is constant, so one branch is dead",
+
             ErrorCode::InternalCompilerError => "Internal compiler error",
             ErrorCode::SSAConversionError => "SSA conversion error",
             ErrorCode::UnexpectedState => "Unexpected compiler state",
+            ErrorCode::MemoryLimitExceeded => "Compilation used more memory than the configured limit",
+            ErrorCode::ExecutionCancelled => "Execution was cancelled or ran out of time",
+            ErrorCode::ExecutionOutOfFuel => "Execution used more fuel than the sandbox allows",
+            ErrorCode::ExecutionTrapped => "Compiled code faulted, on a division by zero or a bad memory access",
+            ErrorCode::ExecutionThrew => "Compiled code threw an exception, such as checked arithmetic on an overflow",
         }
     }
 
@@ -176,6 +207,7 @@ impl ErrorCodeRegistry {
             ErrorCode::InvalidStackEffect,
             ErrorCode::InvalidImmediate,
             ErrorCode::RecursionWithoutBaseCase,
+            ErrorCode::UnavailableWord,
 
             // Stack Effects
             ErrorCode::StackUnderflow,
@@ -202,16 +234,30 @@ impl ErrorCodeRegistry {
             ErrorCode::InliningError,
             ErrorCode::ConstantFoldingError,
             ErrorCode::DeadCodeEliminationError,
+            ErrorCode::Miscompile,
 
             // Code Generation
             ErrorCode::CodeGenFailed,
             ErrorCode::LLVMError,
             ErrorCode::LinkingError,
 
+            // Lint
+            ErrorCode::UnusedDefinition,
+            ErrorCode::ShadowedCoreWord,
+            ErrorCode::MissingStackEffect,
+            ErrorCode::LoopStackImbalance,
+            ErrorCode::OversizedWord,
+            ErrorCode::ConstantCondition,
+
             // Internal
             ErrorCode::InternalCompilerError,
             ErrorCode::SSAConversionError,
             ErrorCode::UnexpectedState,
+            ErrorCode::MemoryLimitExceeded,
+            ErrorCode::ExecutionCancelled,
+            ErrorCode::ExecutionOutOfFuel,
+            ErrorCode::ExecutionTrapped,
+            ErrorCode::ExecutionThrew,
         ]
     }
 }
@@ -237,7 +283,7 @@ mod tests {
 
     #[test]
     fn test_all_codes() {
-        let codes = ERROR_CODE_REGISTRY.all_codes();
+        let codes = ErrorCodeRegistry::all_codes();
         assert!(!codes.is_empty());
     }
 }
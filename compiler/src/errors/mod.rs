@@ -15,8 +15,6 @@ pub use error_code::{ErrorCode, ERROR_CODE_REGISTRY};
 pub use structured::{StructuredError, Location, Suggestion, FixDiff, ErrorSeverity};
 pub use formatter::{ErrorFormatter, OutputFormat};
 
-use serde::{Serialize, Deserialize};
-
 /// Convert a ForthError to a StructuredError with auto-fix suggestions
 pub fn to_structured_error(
     error: &crate::error::CompileError,
@@ -40,7 +38,7 @@ mod tests {
     #[test]
     fn test_error_code_generation() {
         let code = ErrorCode::StackDepthMismatch;
-        assert_eq!(code.as_str(), "E0234");
+        assert_eq!(code.as_str(), "E2234");
     }
 
     #[test]
@@ -50,6 +48,6 @@ mod tests {
             "Stack depth mismatch".to_string(),
         );
         let json = serde_json::to_string(&error).unwrap();
-        assert!(json.contains("E0234"));
+        assert!(json.contains("E2234"));
     }
 }
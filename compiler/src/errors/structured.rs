@@ -1,6 +1,7 @@
 //! Structured error representation for machine consumption
 
 use super::error_code::ErrorCode;
+use fastforth_frontend::ForthError;
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 
@@ -174,6 +175,16 @@ impl StructuredError {
         self
     }
 
+    /// Name the file the error is in and quote the offending line, which
+    /// the human formatter underlines with a caret
+    pub fn with_source(mut self, file: impl Into<String>, source: &str) -> Self {
+        self.location.file = Some(file.into());
+        if self.location.context.is_none() && self.location.line > 0 {
+            self.location.context = source.lines().nth(self.location.line - 1).map(str::to_string);
+        }
+        self
+    }
+
     /// Convert to JSON string
     pub fn to_json(&self) -> Result<String, serde_json::Error> {
         serde_json::to_string_pretty(self)
@@ -193,6 +204,8 @@ pub fn convert_to_structured(
     use crate::error::CompileError;
 
     match error {
+        CompileError::Frontend { error, .. } => convert_frontend(error),
+
         CompileError::ParseError(msg) => {
             StructuredError::new(ErrorCode::UnexpectedToken, msg)
                 .with_location(Location::new(0, 0))
@@ -238,10 +251,18 @@ pub fn convert_to_structured(
             StructuredError::new(ErrorCode::OptimizationFailed, msg)
         }
 
+        CompileError::Miscompile(msg) => {
+            StructuredError::new(ErrorCode::Miscompile, msg)
+        }
+
         CompileError::CodeGenError(msg) => {
             StructuredError::new(ErrorCode::CodeGenFailed, msg)
         }
 
+        CompileError::BackendError(msg) => {
+            StructuredError::new(ErrorCode::CodeGenFailed, msg)
+        }
+
         CompileError::LLVMError(msg) => {
             StructuredError::new(ErrorCode::LLVMError, msg)
         }
@@ -260,6 +281,104 @@ pub fn convert_to_structured(
         CompileError::InternalError(msg) => {
             StructuredError::new(ErrorCode::InternalCompilerError, msg)
         }
+
+        CompileError::MemoryLimitExceeded { .. } => {
+            StructuredError::new(ErrorCode::MemoryLimitExceeded, error.to_string())
+        }
+
+        CompileError::Cancelled => {
+            StructuredError::new(ErrorCode::ExecutionCancelled, error.to_string())
+        }
+
+        CompileError::OutOfFuel { .. } => {
+            StructuredError::new(ErrorCode::ExecutionOutOfFuel, error.to_string())
+        }
+
+        CompileError::Trapped(snapshot) => {
+            let mut structured = StructuredError::new(ErrorCode::ExecutionTrapped, error.to_string());
+            if let Some(word) = &snapshot.word {
+                structured = structured.with_location(Location::new(snapshot.line.unwrap_or(0), 0).with_word(word.clone()));
+            }
+            if let Some(path) = &snapshot.path {
+                structured.metadata.insert("snapshot".to_string(), path.display().to_string());
+            }
+            structured
+        }
+
+        CompileError::Thrown { code } => {
+            StructuredError::new(ErrorCode::ExecutionThrew, error.to_string())
+                .add_metadata("throw_code", code.to_string())
+        }
+    }
+}
+
+/// Structured form of a frontend error, located where the frontend found it
+fn convert_frontend(error: &ForthError) -> StructuredError {
+    let (code, word) = match error {
+        ForthError::LexError { message, .. } => (lex_error_code(message), None),
+        ForthError::ParseError { message, .. } => (parse_error_code(message), None),
+        ForthError::UndefinedWord { word, .. } => (ErrorCode::UndefinedWord, Some(word)),
+        ForthError::UnavailableWord { word, .. } => (ErrorCode::UnavailableWord, Some(word)),
+        ForthError::StackUnderflow { word, .. } => (ErrorCode::StackUnderflow, Some(word)),
+        ForthError::StackMismatch { word, .. } => (ErrorCode::StackDepthMismatch, Some(word)),
+        ForthError::StackOverflow { .. } => (ErrorCode::StackOverflow, None),
+        ForthError::TypeError { context, .. } => (ErrorCode::TypeMismatch, context.as_ref()),
+        ForthError::InvalidStackEffect { .. } => (ErrorCode::InvalidStackEffect, None),
+        ForthError::RedefinitionError { word, .. } => (ErrorCode::RedefinedWord, Some(word)),
+        ForthError::ControlStructureMismatch { .. } => (ErrorCode::InvalidControlStructure, None),
+        ForthError::InvalidImmediateWord { word } => (ErrorCode::InvalidImmediate, Some(word)),
+        ForthError::CompileTimeError { .. } => (ErrorCode::InvalidImmediate, None),
+        ForthError::SSAConversionError { .. } => (ErrorCode::SSAConversionError, None),
+        ForthError::InternalError { .. } => (ErrorCode::InternalCompilerError, None),
+    };
+
+    // The location is reported separately, so leave it out of the message
+    let message = match error {
+        ForthError::LexError { message, .. } | ForthError::ParseError { message, .. } => message.clone(),
+        _ => error.to_string(),
+    };
+
+    let source = error.location().unwrap_or_default();
+    let mut location = Location::new(source.line, source.column);
+    if let Some(word) = word {
+        location = location.with_word(word.as_str());
+    }
+
+    let err = StructuredError::new(code, message).with_location(location);
+    match error {
+        ForthError::StackUnderflow { expected, found, .. } => {
+            err.with_stack_effect(format!("{} items", expected), format!("{} items", found))
+        }
+        ForthError::TypeError { expected, found, .. } => err.with_stack_effect(expected, found),
+        _ => err,
+    }
+}
+
+fn lex_error_code(message: &str) -> ErrorCode {
+    if message.contains("comment") {
+        ErrorCode::UnterminatedComment
+    } else if message.contains("string") {
+        ErrorCode::InvalidStringLiteral
+    } else if message.contains("literal") {
+        ErrorCode::InvalidNumber
+    } else {
+        ErrorCode::UnexpectedToken
+    }
+}
+
+fn parse_error_code(message: &str) -> ErrorCode {
+    if message.starts_with("Unterminated IF") {
+        ErrorCode::UnmatchedIf
+    } else if message.starts_with("Unterminated BEGIN") {
+        ErrorCode::UnmatchedBegin
+    } else if message.starts_with("Unterminated DO") {
+        ErrorCode::UnmatchedDo
+    } else if message.starts_with("Unterminated") {
+        ErrorCode::UnexpectedEof
+    } else if message.contains("IMMEDIATE") {
+        ErrorCode::InvalidImmediate
+    } else {
+        ErrorCode::UnexpectedToken
     }
 }
 
@@ -292,6 +411,23 @@ mod tests {
         assert!(json.contains("0.95"));
     }
 
+    #[test]
+    fn test_frontend_error_is_located_in_source() {
+        use crate::error::{CompileError, FrontendStage};
+
+        let source = ": f ( -- n )\n  1 frobnicate ;\n";
+        let error = fastforth_frontend::parse_program(source)
+            .and_then(|program| fastforth_frontend::analyze(&program))
+            .unwrap_err();
+        let error = CompileError::frontend(FrontendStage::Semantic, error);
+
+        let structured = convert_to_structured(&error, false).with_source("f.fs", source);
+        assert_eq!(structured.code, ErrorCode::UndefinedWord.as_str());
+        assert_eq!(structured.location.file.as_deref(), Some("f.fs"));
+        assert_eq!((structured.location.line, structured.location.column), (2, 5));
+        assert_eq!(structured.location.context.as_deref(), Some("  1 frobnicate ;"));
+    }
+
     #[test]
     fn test_suggestion_builder() {
         let suggestion = Suggestion::new("Add drop", "old code", "new code")
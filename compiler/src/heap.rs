@@ -0,0 +1,182 @@
+//! Heap
+//!
+//! `allocate`, `free` and `resize` are served by the C runtime, from
+//! malloc by default. A region allocator (`--heap region:SIZE`) serves them
+//! from one block reserved before the run instead: the program cannot grow
+//! past it, and whatever it holds is released with it when the run ends.
+//!
+//! In debug mode (`--heap-debug`) the runtime tracks every live block.
+//! `free` or `resize` of an address that is not live fails with an ior
+//! instead of corrupting the heap, and the blocks a JIT run leaves
+//! allocated are reported as leaks.
+//!
+//! The settings belong to the thread that runs the compiled code, so JIT
+//! runs on different threads do not see each other's blocks.
+
+use crate::error::{CompileError, Result};
+use crate::runtime_ffi::{forth_heap_configure, forth_heap_leaks, forth_heap_reset, forth_heap_set_debug, CellT};
+
+/// Where heap blocks come from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HeapAllocator {
+    /// The C library's malloc
+    #[default]
+    Malloc,
+    /// A fixed region of this many bytes, released after the run
+    Region(u64),
+}
+
+impl std::fmt::Display for HeapAllocator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeapAllocator::Malloc => write!(f, "malloc"),
+            HeapAllocator::Region(size) => write!(f, "region:{}", size),
+        }
+    }
+}
+
+impl std::str::FromStr for HeapAllocator {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("malloc") {
+            return Ok(HeapAllocator::Malloc);
+        }
+        let size = s
+            .strip_prefix("region:")
+            .ok_or_else(|| format!("unknown heap '{}' (expected malloc or region:SIZE)", s))?;
+        match crate::memory::parse_size(size) {
+            Some(bytes) if bytes > 0 && bytes <= i64::MAX as u64 => Ok(HeapAllocator::Region(bytes)),
+            _ => Err(format!("invalid region size '{}', expected bytes or a K, M or G suffix", size)),
+        }
+    }
+}
+
+/// Heap settings for JIT runs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeapConfig {
+    pub allocator: HeapAllocator,
+    /// Track live blocks, check frees and report leaks
+    pub debug: bool,
+}
+
+/// A block a run allocated and never freed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeapLeak {
+    pub address: i64,
+    pub size: usize,
+    /// Which allocation of the run it was, from 1
+    pub allocation: u64,
+}
+
+impl std::fmt::Display for HeapLeak {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "heap leak: {} bytes at {:#x} (allocation {}) never freed",
+            self.size, self.address, self.allocation
+        )
+    }
+}
+
+/// The heap settings of one run on the current thread, undone when
+/// dropped: tracked blocks are freed and the region is released
+pub(crate) struct HeapSession {
+    config: HeapConfig,
+}
+
+impl HeapSession {
+    pub(crate) fn begin(config: HeapConfig) -> Result<Self> {
+        if let HeapAllocator::Region(size) = config.allocator {
+            if unsafe { forth_heap_configure(size as CellT) } != 0 {
+                return Err(CompileError::RuntimeError(format!("cannot reserve a {}-byte heap region", size)));
+            }
+        }
+        unsafe { forth_heap_set_debug(config.debug as CellT) };
+        Ok(Self { config })
+    }
+
+    /// Blocks still allocated, oldest first (only tracked in debug mode)
+    pub(crate) fn leaks(&self) -> Vec<HeapLeak> {
+        if !self.config.debug {
+            return Vec::new();
+        }
+        let count = unsafe { forth_heap_leaks(std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::null_mut(), 0) };
+        let mut addrs = vec![0; count as usize];
+        let mut sizes = vec![0; count as usize];
+        let mut serials = vec![0; count as usize];
+        unsafe { forth_heap_leaks(addrs.as_mut_ptr(), sizes.as_mut_ptr(), serials.as_mut_ptr(), count) };
+        addrs
+            .into_iter()
+            .zip(sizes)
+            .zip(serials)
+            .map(|((address, size), serial)| HeapLeak {
+                address: address as i64,
+                size: size as usize,
+                allocation: serial as u64,
+            })
+            .collect()
+    }
+}
+
+impl Drop for HeapSession {
+    fn drop(&mut self) {
+        if self.config != HeapConfig::default() {
+            unsafe { forth_heap_reset() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime_ffi::{forth_allocate, forth_free, forth_resize};
+
+    #[test]
+    fn test_parse_allocator() {
+        assert_eq!("malloc".parse::<HeapAllocator>(), Ok(HeapAllocator::Malloc));
+        assert_eq!("region:64K".parse::<HeapAllocator>(), Ok(HeapAllocator::Region(64 << 10)));
+        assert!("region:".parse::<HeapAllocator>().is_err());
+        assert!("arena".parse::<HeapAllocator>().is_err());
+    }
+
+    #[test]
+    fn test_debug_mode_tracks_blocks_and_rejects_bad_frees() {
+        let session = HeapSession::begin(HeapConfig { allocator: HeapAllocator::Malloc, debug: true }).unwrap();
+        unsafe {
+            let kept = forth_allocate(24);
+            let freed = forth_allocate(8);
+            assert_eq!((kept.hi, freed.hi), (0, 0));
+            assert_eq!(forth_free(freed.lo), 0);
+            assert_eq!(forth_free(freed.lo), -60);
+
+            let moved = forth_resize(kept.lo, 4096);
+            assert_eq!(moved.hi, 0);
+            let leaks = session.leaks();
+            assert_eq!(leaks.len(), 1);
+            assert_eq!((leaks[0].address, leaks[0].size, leaks[0].allocation), (moved.lo as i64, 4096, 3));
+        }
+        drop(session);
+        assert!(HeapSession::begin(HeapConfig::default()).unwrap().leaks().is_empty());
+    }
+
+    #[test]
+    fn test_region_is_a_fixed_budget() {
+        let _session = HeapSession::begin(HeapConfig { allocator: HeapAllocator::Region(1024), debug: false }).unwrap();
+        unsafe {
+            let first = forth_allocate(512);
+            assert_eq!(first.hi, 0);
+            assert_eq!(forth_allocate(1024).hi, -59);
+
+            // The last block grows in place while the region has room
+            let grown = forth_resize(first.lo, 900);
+            assert_eq!((grown.lo, grown.hi), (first.lo, 0));
+            let failed = forth_resize(first.lo, 4096);
+            assert_eq!((failed.lo, failed.hi), (first.lo, -61));
+
+            // Freeing the last block gives its space back
+            assert_eq!(forth_free(first.lo), 0);
+            assert_eq!(forth_allocate(900).hi, 0);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Dictionary Images
+//!
+//! Classic Forths save their dictionary as an image and start from it
+//! again in an instant. An [`Image`] is the state of a [`ForthEngine`]:
+//! - The sources its definitions came from, in order, compiled again in
+//!   one pass on restore (a redefined word is the last one, as it was)
+//! - Each word's name, kind, declared stack effect and line
+//! - The host words the definitions call, by name and stack effect: the
+//!   closures themselves are registered again by whoever restores
+//! - The data space: the stack, memory, variables, constants and values
+//!
+//! Images are JSON, written by [`Image::write`] and read back by
+//! [`Image::read`], which refuses a format newer than it understands.
+//! `fastforth image save` and `fastforth image restore` do the same from
+//! the command line.
+//!
+//! [`ForthEngine`]: crate::ForthEngine
+
+use crate::error::{CompileError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Version of the image layout this compiler writes and reads
+pub const IMAGE_FORMAT: u32 = 1;
+
+/// A saved dictionary and data space
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Image {
+    pub format: u32,
+    /// Version of the compiler that saved it
+    pub compiler: String,
+    /// Sources of the definitions, in the order they were defined
+    pub sources: Vec<String>,
+    pub words: Vec<ImageWord>,
+    pub data: DataSpace,
+}
+
+/// What kind of word an [`ImageWord`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WordKind {
+    /// A colon definition
+    Colon,
+    /// A CODE word, in assembly
+    Code,
+    /// A Rust closure, which must be registered again on restore
+    Host,
+}
+
+/// One word of the dictionary
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageWord {
+    pub name: String,
+    pub kind: WordKind,
+    pub stack_effect: Option<String>,
+    /// Line of its source it is defined on, 0 for host words
+    pub line: usize,
+}
+
+/// Contents of the engine's data space
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DataSpace {
+    pub stack: Vec<i64>,
+    /// Cells written, by address
+    pub memory: BTreeMap<i64, i64>,
+    pub variables: BTreeMap<String, i64>,
+    pub constants: BTreeMap<String, i64>,
+    pub values: BTreeMap<String, i64>,
+    /// Address the next variable is given
+    pub next_addr: i64,
+    pub base: i64,
+}
+
+impl Image {
+    pub fn new(sources: Vec<String>, words: Vec<ImageWord>, data: DataSpace) -> Self {
+        Self { format: IMAGE_FORMAT, compiler: env!("CARGO_PKG_VERSION").to_string(), sources, words, data }
+    }
+
+    /// Fail unless this compiler understands the image's layout
+    pub fn check_format(&self) -> Result<()> {
+        if self.format > IMAGE_FORMAT {
+            return Err(CompileError::RuntimeError(format!(
+                "image format {} is newer than this compiler reads ({}); it was saved by fastforth {}",
+                self.format, IMAGE_FORMAT, self.compiler
+            )));
+        }
+        Ok(())
+    }
+
+    /// Save the image as JSON at `path`
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| CompileError::InternalError(format!("cannot serialize the image: {}", e)))?;
+        std::fs::write(path, json).map_err(|e| CompileError::IoError(path.to_path_buf(), e))
+    }
+
+    /// Load the image saved at `path`
+    pub fn read(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path).map_err(|e| CompileError::IoError(path.to_path_buf(), e))?;
+        let image: Image = serde_json::from_str(&json)
+            .map_err(|e| CompileError::RuntimeError(format!("{} is not an image: {}", path.display(), e)))?;
+        image.check_format()?;
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ForthEngine;
+
+    #[test]
+    fn test_restored_engine_has_the_saved_dictionary_and_data() {
+        let mut engine = ForthEngine::new();
+        engine.register("scale", |(n,): (i64,)| (n * 10,)).unwrap();
+        engine.define(": sq ( n -- n ) dup * ;\n: twice ( n -- n ) 2 * ;").unwrap();
+        engine.define(": twice ( n -- n ) dup + scale ;").unwrap();
+        let counter = engine.define_variable("counter");
+        engine.set_memory(counter, 7);
+        engine.define_constant("limit", 100);
+        engine.eval("1 2").unwrap();
+
+        let path = std::env::temp_dir().join(format!("fastforth-image-{}.json", std::process::id()));
+        engine.save_image().write(&path).unwrap();
+        let image = Image::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(image.words.iter().filter(|word| word.kind == WordKind::Host).count(), 1);
+
+        let mut restored = ForthEngine::new();
+        let error = restored.restore_image(&image).unwrap_err().to_string();
+        assert!(error.contains("scale"), "{}", error);
+        restored.register("scale", |(n,): (i64,)| (n * 10,)).unwrap();
+        restored.restore_image(&image).unwrap();
+
+        assert_eq!(restored.call::<_, i64>("sq", (9,)).unwrap(), 81);
+        assert_eq!(restored.call::<_, i64>("twice", (4,)).unwrap(), 80, "the redefinition wins");
+        assert_eq!(restored.get_memory(counter), 7);
+        assert_eq!(restored.constants()["LIMIT"], 100);
+        assert_eq!(restored.stack(), &[1, 2]);
+        assert_eq!(restored.define_variable("next"), counter + 8);
+    }
+
+    #[test]
+    fn test_newer_formats_are_refused() {
+        let mut image = ForthEngine::new().save_image();
+        assert!(image.check_format().is_ok());
+        image.format = IMAGE_FORMAT + 1;
+        assert!(image.check_format().unwrap_err().to_string().contains("newer"));
+    }
+}
@@ -0,0 +1,268 @@
+//! Stack-effect database
+//!
+//! Inferring the effect of a word means inferring the effects of the words
+//! it calls first, and a large dictionary was re-inferred from scratch on
+//! every request. An [`EffectDatabase`] remembers each definition's effect
+//! under a hash of what the effect depends on:
+//!
+//! - the definition's body, for a straight-line body, along with the effect
+//!   of every user word it calls
+//! - the declared `( -- )` effect, for a body with control flow, which the
+//!   engine does not follow
+//!
+//! A definition whose hash is in the database takes the stored effect
+//! without inference. Renaming a word or editing an unrelated one misses
+//! for nothing else; changing the effect of a word misses for the words
+//! calling it when they are recorded again.
+//!
+//! Words are also indexed by name, so code can call a word recorded from
+//! another file: one database per project, shared by the inference API,
+//! `verify-effect`, composition, live documents and hover, gives every one
+//! of them the whole dictionary. It is persisted as JSON, by default in
+//! `.fifth/effects`.
+
+use super::engine::InferenceEngine;
+use super::types::StackEffect;
+use fastforth_frontend::{parse_program_recovering, Word};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Version of the persisted format; a database of another version is
+/// read as an empty one
+const FORMAT_VERSION: u32 = 1;
+
+/// Entries kept past the ones some word refers to
+const MAX_ENTRIES: usize = 4096;
+
+/// Effect of one definition
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EffectEntry {
+    pub effect: StackEffect,
+    /// Taken from the definition's stack comment rather than inferred
+    pub declared: bool,
+}
+
+/// Effects of a project's definitions, keyed by definition hash
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EffectDatabase {
+    version: u32,
+    entries: HashMap<u64, EffectEntry>,
+    /// Hash of each word's current definition
+    words: HashMap<String, u64>,
+    #[serde(skip)]
+    hits: usize,
+    #[serde(skip)]
+    misses: usize,
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl EffectDatabase {
+    /// An empty database
+    pub fn new() -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            entries: HashMap::new(),
+            words: HashMap::new(),
+            hits: 0,
+            misses: 0,
+            changed: false,
+        }
+    }
+
+    /// Read a database written by [`to_json`](Self::to_json)
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let database: Self = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        if database.version != FORMAT_VERSION {
+            return Ok(Self::new());
+        }
+        Ok(database)
+    }
+
+    /// The database at `path`; one that is missing or cannot be read
+    /// starts out empty
+    pub fn load(path: &Path) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|text| Self::parse(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// Write the database to `path` if anything was recorded since it was
+    /// loaded, creating its directory
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.to_json())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("effect database serializes")
+    }
+
+    /// Effect of the word `name`, if it has been recorded
+    pub fn effect(&self, name: &str) -> Option<&StackEffect> {
+        self.entry(name).map(|entry| &entry.effect)
+    }
+
+    /// Entry for the current definition of `name`
+    pub fn entry(&self, name: &str) -> Option<&EffectEntry> {
+        self.words.get(name).and_then(|hash| self.entries.get(hash))
+    }
+
+    /// Definitions whose effect was taken from the database
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Definitions whose effect was inferred
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    /// Words with a recorded effect
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Record the definitions in `source`, in order, returning how many
+    /// got an effect
+    ///
+    /// Definitions the parser rejects are skipped. A word redefined with a
+    /// body that has neither a straight-line form nor a declared effect is
+    /// forgotten.
+    pub fn record(&mut self, engine: &InferenceEngine, source: &str) -> usize {
+        let (program, _) = parse_program_recovering(source, usize::MAX);
+        let mut recorded = 0;
+        for definition in &program.definitions {
+            let body = body_source(&definition.body);
+            let declared = definition.stack_effect.as_ref().map(|effect| effect.to_string());
+            let content = match (&body, &declared) {
+                (Some(body), _) => {
+                    let mut content = format!("body {}", body);
+                    for word in body.split_whitespace() {
+                        if let Some(effect) = self.effect(word) {
+                            content.push_str(&format!("\0{} {}", word, effect));
+                        }
+                    }
+                    content
+                }
+                (None, Some(declared)) => format!("declared {}", declared),
+                (None, None) => {
+                    self.changed |= self.words.remove(&definition.name).is_some();
+                    continue;
+                }
+            };
+            let hash = hash(content.as_bytes());
+
+            if self.entries.contains_key(&hash) {
+                self.hits += 1;
+            } else {
+                let entry = match (&body, &declared) {
+                    (Some(body), _) => engine
+                        .infer_with(body, |word| self.effect(word).cloned())
+                        .map(|result| EffectEntry { effect: result.effect, declared: false }),
+                    (None, declared) => engine
+                        .parse_effect(declared.as_deref().unwrap_or_default())
+                        .map(|effect| EffectEntry { effect, declared: true }),
+                };
+                let Ok(entry) = entry else {
+                    self.changed |= self.words.remove(&definition.name).is_some();
+                    continue;
+                };
+                self.misses += 1;
+                self.entries.insert(hash, entry);
+                self.changed = true;
+            }
+            if self.words.insert(definition.name.clone(), hash) != Some(hash) {
+                self.changed = true;
+            }
+            recorded += 1;
+        }
+
+        if self.entries.len() > MAX_ENTRIES {
+            let current: HashSet<u64> = self.words.values().copied().collect();
+            self.entries.retain(|hash, _| current.contains(hash));
+        }
+        recorded
+    }
+}
+
+impl Default for EffectDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Straight-line body as inference input, or `None` if it has control flow
+fn body_source(body: &[Word]) -> Option<String> {
+    let words = body
+        .iter()
+        .map(|word| match word {
+            Word::WordRef { name, .. } => Some(name.to_string()),
+            Word::IntLiteral(n) => Some(n.to_string()),
+            Word::FloatLiteral(f) => Some(f.to_string()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(words.join(" "))
+}
+
+/// FNV-1a, which unlike the standard hasher is the same in every build
+fn hash(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325, |state, &byte| (state ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effects_are_reused_by_definition() {
+        let engine = InferenceEngine::new();
+        let mut database = EffectDatabase::new();
+        assert_eq!(database.record(&engine, ": square dup * ; : cube dup square * ;"), 2);
+        assert_eq!((database.hits(), database.misses()), (0, 2));
+        assert_eq!(database.effect("cube").unwrap().to_string(), "( x -- n )");
+
+        // The same bodies under other names, in another file
+        database.record(&engine, ": sq dup * ; : cube dup square * ;");
+        assert_eq!((database.hits(), database.misses()), (2, 2));
+
+        // A new effect for square misses for cube as well
+        database.record(&engine, ": square drop ; : cube dup square * ;");
+        assert_eq!(database.misses(), 4);
+        assert_eq!(database.effect("cube").unwrap().to_string(), "( x n -- n )");
+    }
+
+    #[test]
+    fn test_control_flow_takes_the_declared_effect() {
+        let engine = InferenceEngine::new();
+        let mut database = EffectDatabase::new();
+        database.record(&engine, ": clamp ( n -- n ) dup 0 < if drop 0 then ; : abs dup 0 < if negate then ;");
+        assert_eq!(database.entry("clamp").unwrap().effect.inputs.len(), 1);
+        assert!(database.entry("clamp").unwrap().declared);
+        assert!(database.effect("abs").is_none());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let engine = InferenceEngine::new();
+        let mut database = EffectDatabase::new();
+        database.record(&engine, ": square dup * ;");
+        let loaded = EffectDatabase::parse(&database.to_json()).unwrap();
+        assert_eq!(loaded.effect("square"), database.effect("square"));
+        assert!(EffectDatabase::parse("{\"version\":0,\"entries\":{},\"words\":{}}").unwrap().is_empty());
+    }
+}
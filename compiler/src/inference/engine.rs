@@ -13,6 +13,29 @@ pub struct InferenceResult {
     pub operations: Vec<String>,
     pub latency_ms: f64,
     pub error: Option<String>,
+    /// Flow-sensitive types of each definition, with top-level code as `main`
+    #[serde(default)]
+    pub words: Vec<WordTypes>,
+}
+
+/// Inferred types of one definition
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WordTypes {
+    pub name: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    /// The stack after each word, bottom first
+    pub states: Vec<StackState>,
+    pub error: Option<String>,
+}
+
+/// Types on the stack after one word
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StackState {
+    pub word: String,
+    /// How many control structures the word is nested in
+    pub depth: usize,
+    pub stack: Vec<String>,
 }
 
 /// Internal inference result
@@ -190,18 +213,46 @@ impl InferenceEngine {
             "c!".to_string(),
             StackEffect::new(vec![StackType::Char, StackType::Addr], vec![]),
         );
+        for fetch in ["w@", "l@"] {
+            builtins.insert(
+                fetch.to_string(),
+                StackEffect::new(vec![StackType::Addr], vec![StackType::Int]),
+            );
+        }
+        for store in ["w!", "l!"] {
+            builtins.insert(
+                store.to_string(),
+                StackEffect::new(vec![StackType::Int, StackType::Addr], vec![]),
+            );
+        }
 
         Self { builtins }
     }
 
     /// Infer stack effect from code string
     pub fn infer(&self, code: &str) -> Result<InferResult, String> {
-        let words = self.tokenize(code);
+        self.infer_with(code, |_| None)
+    }
+
+    /// Infer stack effect from code string, taking the effects of words
+    /// other than builtins from `words`
+    ///
+    /// Definitions in the code leave the stack alone and are skipped; they
+    /// are recorded in an [`EffectDatabase`](super::EffectDatabase).
+    pub fn infer_with(&self, code: &str, words: impl Fn(&str) -> Option<StackEffect>) -> Result<InferResult, String> {
+        let mut tokens = self.tokenize(code).into_iter();
         let mut operations = Vec::new();
         let mut total_effect = StackEffect::identity();
 
-        for word in words {
-            let effect = self.infer_word(&word)?;
+        while let Some(word) = tokens.next() {
+            if word == ":" {
+                tokens.by_ref().find(|token| token == ";");
+                continue;
+            }
+            let effect = match words(&word).filter(|_| !self.builtins.contains_key(&word)) {
+                Some(effect) => effect,
+                None => self.infer_word(&word)?,
+            };
             total_effect = total_effect.compose(&effect)?;
             operations.push(word);
         }
@@ -3,27 +3,120 @@
 //! Pure type checker that infers stack effects without compilation.
 //! Designed for sub-millisecond latency (<1ms typical).
 
+pub mod database;
 pub mod engine;
 pub mod types;
 
-pub use engine::{InferenceEngine, InferenceResult};
+pub use database::{EffectDatabase, EffectEntry};
+pub use engine::{InferenceEngine, InferenceResult, StackState, WordTypes};
 pub use types::{StackEffect, StackType, OperationInfo};
 
+use engine::InferResult;
 use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
 use std::time::Instant;
 
+/// Type each definition of `code`, and its top-level code as `main`
+///
+/// Code the parser rejects has no per-word types; a definition that fails to
+/// type reports its error without stopping the rest.
+fn word_types(code: &str) -> Vec<WordTypes> {
+    use fastforth_frontend::type_inference::TypeInference;
+
+    let Ok(program) = fastforth_frontend::parse_program(code) else {
+        return Vec::new();
+    };
+
+    let names = |types: &[fastforth_frontend::ast::StackType]| types.iter().map(|t| t.to_string()).collect();
+    let report = |name: &str, typed: fastforth_frontend::Result<fastforth_frontend::type_inference::TypedDefinition>| match typed {
+        Ok(typed) => WordTypes {
+            name: name.to_string(),
+            inputs: names(&typed.inputs),
+            outputs: names(&typed.outputs),
+            states: typed
+                .states
+                .iter()
+                .map(|state| StackState {
+                    word: state.word.clone(),
+                    depth: state.depth,
+                    stack: names(&state.stack),
+                })
+                .collect(),
+            error: None,
+        },
+        Err(error) => WordTypes {
+            name: name.to_string(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            states: Vec::new(),
+            error: Some(error.to_string()),
+        },
+    };
+
+    let mut inference = TypeInference::new();
+    let mut words: Vec<_> = program
+        .compiled_definitions()
+        .map(|def| report(&def.name, inference.trace_definition(def)))
+        .collect();
+    if !program.top_level_code.is_empty() {
+        words.push(report("main", inference.trace_sequence(&program.top_level_code)));
+    }
+    words
+}
+
 /// Main API for stack effect inference
+///
+/// Definitions in the code given to any method are recorded in the API's
+/// [`EffectDatabase`], and calls to recorded words take their effects from
+/// it. Clones share the database.
 #[derive(Clone)]
 pub struct InferenceAPI {
     engine: InferenceEngine,
+    database: Arc<RwLock<EffectDatabase>>,
 }
 
 impl InferenceAPI {
     /// Create a new inference API instance
     pub fn new() -> Self {
+        Self::with_database(Arc::new(RwLock::new(EffectDatabase::new())))
+    }
+
+    /// An inference API recording definitions in `database`
+    pub fn with_database(database: Arc<RwLock<EffectDatabase>>) -> Self {
         Self {
             engine: InferenceEngine::new(),
+            database,
+        }
+    }
+
+    /// The database definitions are recorded in
+    pub fn database(&self) -> &Arc<RwLock<EffectDatabase>> {
+        &self.database
+    }
+
+    /// Record the definitions in `source`, returning how many got an effect
+    pub fn record(&self, source: &str) -> usize {
+        if !source.split_whitespace().any(|token| token == ":") {
+            return 0;
         }
+        let mut database = self.database.write().unwrap_or_else(|e| e.into_inner());
+        database.record(&self.engine, source)
+    }
+
+    /// Recorded effect of the word `name`, as hover shows it
+    pub fn word_effect(&self, name: &str) -> Option<EffectEntry> {
+        self.read_database().entry(name).cloned()
+    }
+
+    fn read_database(&self) -> RwLockReadGuard<'_, EffectDatabase> {
+        self.database.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record the definitions in `code`, then infer the effect of the rest
+    fn infer_code(&self, code: &str) -> Result<InferResult, String> {
+        self.record(code);
+        let database = self.read_database();
+        self.engine.infer_with(code, |word| database.effect(word).cloned())
     }
 
     /// Infer stack effect from Forth code
@@ -39,7 +132,7 @@ impl InferenceAPI {
     /// ```
     pub fn infer(&self, code: &str) -> Result<InferenceResult, String> {
         let start = Instant::now();
-        let result = self.engine.infer(code)?;
+        let result = self.infer_code(code)?;
         let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
 
         Ok(InferenceResult {
@@ -49,13 +142,14 @@ impl InferenceAPI {
             operations: result.operations,
             latency_ms,
             error: None,
+            words: word_types(code),
         })
     }
 
     /// Verify that code matches expected stack effect
     pub fn verify_effect(&self, code: &str, expected_effect: &str) -> Result<VerifyResult, String> {
         let start = Instant::now();
-        let result = self.engine.infer(code)?;
+        let result = self.infer_code(code)?;
         let expected = self.engine.parse_effect(expected_effect)?;
 
         let matches = result.effect.compatible_with(&expected);
@@ -83,7 +177,7 @@ impl InferenceAPI {
         let mut total_effect = StackEffect::identity();
 
         for word in words {
-            let result = self.engine.infer(word)?;
+            let result = self.infer_code(word)?;
             total_effect = total_effect.compose(&result.effect)?;
         }
 
@@ -135,6 +229,26 @@ mod tests {
         assert!(result.latency_ms < 10.0);
     }
 
+    #[test]
+    fn test_infer_reports_word_types() {
+        let api = InferenceAPI::new();
+        let result = api
+            .infer(": clamp dup 0 < if drop 0 then ; : bad if 1 then ; 5 clamp")
+            .unwrap();
+
+        let names: Vec<_> = result.words.iter().map(|word| word.name.as_str()).collect();
+        assert_eq!(names, ["clamp", "bad", "main"]);
+
+        let clamp = &result.words[0];
+        assert_eq!(clamp.inputs, ["int"]);
+        assert_eq!(clamp.outputs, ["int"]);
+        assert_eq!(clamp.states.last().unwrap().word, "then");
+        assert_eq!(clamp.states[2].stack, ["int", "bool"]);
+
+        assert!(result.words[1].error.is_some(), "unbalanced branches fail on their own");
+        assert_eq!(result.words[2].outputs, ["int"]);
+    }
+
     #[test]
     fn test_verify_effect() {
         let api = InferenceAPI::new();
@@ -151,6 +265,24 @@ mod tests {
         assert!(result.latency_ms < 10.0);
     }
 
+    #[test]
+    fn test_recorded_words_are_shared() {
+        let api = InferenceAPI::new();
+        // One file defines the words, another uses them
+        api.infer(": square dup * ; : sum3 + + ;").unwrap();
+        let clone = api.clone();
+
+        let result = clone.infer("3 square").unwrap();
+        assert_eq!(result.stack_depth_delta, 1);
+        assert!(clone.verify_effect("square sum3", "( n n n -- n )").unwrap().valid);
+        assert_eq!(clone.compose(&["square", "square"]).unwrap().effect, "( x -- n )");
+        assert_eq!(api.word_effect("sum3").unwrap().effect.to_string(), "( n n n -- n )");
+
+        // Unchanged definitions are not inferred again
+        api.infer(": square dup * ;").unwrap();
+        assert_eq!(api.database().read().unwrap().hits(), 1);
+    }
+
     #[test]
     fn test_subsecond_performance() {
         let api = InferenceAPI::new();
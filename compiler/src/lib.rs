@@ -17,12 +17,33 @@
 //! ```
 
 pub mod error;
+pub mod errors;
 pub mod compiler;
 pub mod pipeline;
+pub mod trace;
+pub mod memory;
+pub mod heap;
+pub mod repl;
+pub mod tiered;
+pub mod lint;
 pub mod backend;
 pub mod patterns;
+pub mod access;
 pub mod engine;
+pub mod embed;
+pub mod cancel;
+pub mod sandbox;
+pub mod permissions;
+pub mod vfs;
+pub mod recording;
+pub mod snapshot;
+pub mod image;
+pub mod compdb;
+#[cfg(feature = "async")]
+pub mod engine_async;
+pub mod capi;
 pub mod runtime_ffi;
+pub mod runtime_profile;
 
 // Machine-readable specifications
 pub mod spec;
@@ -47,12 +68,31 @@ pub mod provenance;
 #[cfg(feature = "inference")]
 pub mod inference;
 
-#[cfg(feature = "server")]
+#[cfg(feature = "inference")]
 pub mod server;
 
+#[cfg(feature = "python")]
+pub mod python;
+
 pub use error::{CompileError, Result};
-pub use pipeline::{CompilationPipeline, CompilationMode, CompilationResult};
+pub use pipeline::{CompilationPipeline, CompilationMode, CompilationResult, HostFunction, JitProgram, SharedLibrary, VerificationResult};
+pub use backend::{Backend, BackendSelector, BackendType};
+pub use ::backend::linker::Lto;
+pub use ::backend::mangle::{demangle, demangle_text, demangle_text_with, mangle};
+pub use ::backend::source_map::{source_map_path, SourceMap, SourceRange};
+pub use runtime_profile::RuntimeProfile;
+pub use heap::{HeapAllocator, HeapConfig};
+pub use repl::ReplSession;
 pub use engine::ForthEngine;
+pub use image::Image;
+#[cfg(feature = "async")]
+pub use engine_async::ForthEngineAsync;
+pub use cancel::{CancelToken, Deadline};
+pub use sandbox::Sandbox;
+pub use permissions::Permissions;
+pub use vfs::{MemoryVfs, Vfs};
+pub use trace::CompilationTrace;
+pub use embed::{FromCells, IntoCells};
 
 // Re-export pattern system
 pub use patterns::{
@@ -62,18 +102,19 @@ pub use patterns::{
 };
 
 // Re-export specification types
-pub use spec::{Specification, SpecValidator, SpecError, SpecResult};
+pub use spec::{Specification, SpecValidator, SpecError, SpecResult, ProgramSpec, SpecDocument, extract_specs};
 
 // Re-export code generation types
 pub use codegen::SpecCodeGenerator;
 
 // Re-export testing types
-pub use testing::TestGenerator;
+pub use testing::{TestGenerator, DifferentialRunner, run_compliance_suite};
 
 // Re-export performance types (Stream 6)
 pub use performance::{
     PerformanceOptimizer, PerformanceModel, PerformancePrediction, PerformanceTarget,
-    PerformanceMetrics, ExecutionProfile, BenchmarkSuite, BenchmarkResult,
+    PerformanceMetrics, ExecutionProfile, BenchmarkSuite, BenchmarkResult, FileBenchmark, FileBenchmarkReport,
+    CalibratedCosts, Calibrator,
 };
 
 // Re-export provenance types (Stream 6)
@@ -85,13 +126,15 @@ pub use provenance::{
 // Re-export commonly used types from components
 pub use fastforth_frontend::{
     Program, Definition, Word, StackEffect as FrontendStackEffect,
-    parse_program, analyze, convert_to_ssa,
+    parse_program, analyze, convert_to_ssa, Arithmetic, Division, Overflow,
 };
 pub use fastforth_optimizer::{
-    ForthIR, Instruction, StackEffect, Optimizer, OptimizationLevel,
+    EvalCache, ForthIR, Instruction, StackEffect, Optimizer, OptimizationLevel, OptimizationReport, Pass, PassReport,
+    PassPipeline, PeepholeRules, SuperinstructionTable,
 };
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 /// Main Fast Forth compiler instance
 ///
@@ -99,6 +142,28 @@ use std::path::Path;
 pub struct Compiler {
     optimization_level: OptimizationLevel,
     optimizer: Optimizer,
+    backend: Backend,
+    vectorize: bool,
+    superinstructions: Option<SuperinstructionTable>,
+    peephole_rules: Option<PeepholeRules>,
+    passes: Option<PassPipeline>,
+    opt_report: bool,
+    verify_optimizations: bool,
+    ans_strict: bool,
+    constant_time: bool,
+    freestanding: bool,
+    interruptible: bool,
+    sandbox: Option<Sandbox>,
+    recording: Option<usize>,
+    trap_snapshot: Option<PathBuf>,
+    arithmetic: Arithmetic,
+    trace: Option<Arc<CompilationTrace>>,
+    memory_limit: Option<u64>,
+    codegen_units: Option<usize>,
+    lto: Lto,
+    runtime_profile: RuntimeProfile,
+    heap: HeapConfig,
+    eval_cache: Option<PathBuf>,
 }
 
 impl Compiler {
@@ -107,13 +172,146 @@ impl Compiler {
         Self {
             optimization_level,
             optimizer: Optimizer::new(optimization_level),
+            backend: Backend::Auto,
+            vectorize: true,
+            superinstructions: None,
+            peephole_rules: None,
+            passes: None,
+            opt_report: false,
+            verify_optimizations: false,
+            ans_strict: false,
+            constant_time: false,
+            freestanding: false,
+            interruptible: false,
+            sandbox: None,
+            recording: None,
+            trap_snapshot: None,
+            arithmetic: Arithmetic::default(),
+            trace: None,
+            memory_limit: None,
+            codegen_units: None,
+            lto: Lto::Off,
+            runtime_profile: RuntimeProfile::Full,
+            heap: HeapConfig::default(),
+            eval_cache: None,
         }
     }
 
     /// Compile Forth source code from a string
     pub fn compile_string(&self, source: &str, mode: CompilationMode) -> Result<CompilationResult> {
+        self.with_pipeline(|pipeline| pipeline.compile(source, mode))
+    }
+
+    /// [`compile_string`](Self::compile_string), running JIT top-level code
+    /// under `cancel`; a cancelled run fails with [`CompileError::Cancelled`]
+    pub fn compile_string_cancellable(&self, source: &str, mode: CompilationMode, cancel: &CancelToken) -> Result<CompilationResult> {
+        self.with_pipeline(|pipeline| {
+            pipeline.set_cancel(Some(cancel.clone()));
+            pipeline.compile(source, mode)
+        })
+    }
+
+    /// [`compile_string_cancellable`](Self::compile_string_cancellable) in
+    /// `sandbox`, whatever sandbox the compiler is set to
+    pub fn compile_string_sandboxed(
+        &self,
+        source: &str,
+        mode: CompilationMode,
+        sandbox: Sandbox,
+        cancel: &CancelToken,
+    ) -> Result<CompilationResult> {
+        self.with_pipeline(|pipeline| {
+            pipeline.set_sandbox(Some(sandbox));
+            pipeline.set_cancel(Some(cancel.clone()));
+            pipeline.compile(source, mode)
+        })
+    }
+
+    /// Compile Forth source code to a relocatable object file
+    pub fn compile_object(&self, source: &str) -> Result<Vec<u8>> {
+        self.with_pipeline(|pipeline| pipeline.compile_object(source))
+    }
+
+    /// Build a shared library exporting a source file's `@export` words,
+    /// with a C header next to `output`
+    pub fn build_shared_library(&self, path: &Path, output: &Path) -> Result<SharedLibrary> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| CompileError::IoError(path.to_path_buf(), e))?;
+        self.with_pipeline(|pipeline| pipeline.build_shared_library(&source, output))
+    }
+
+    /// Build a standalone executable from a source file, returning its path
+    pub fn build_executable(&self, path: &Path, output: &Path) -> Result<PathBuf> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| CompileError::IoError(path.to_path_buf(), e))?;
+        self.with_pipeline(|pipeline| pipeline.build_executable(&source, output))
+    }
+
+    /// Report what the optimizer does to a source file, whatever it is
+    /// built into (see [`CompilationPipeline::optimization_report`])
+    pub fn optimization_report(&self, path: &Path) -> Result<Option<OptimizationReport>> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| CompileError::IoError(path.to_path_buf(), e))?;
+        self.with_pipeline(|pipeline| pipeline.optimization_report(&source))
+    }
+
+    /// A pipeline configured like this compiler
+    fn pipeline(&self) -> Result<CompilationPipeline> {
         let mut pipeline = CompilationPipeline::new(self.optimization_level);
-        pipeline.compile(source, mode)
+        pipeline.set_backend(self.backend);
+        pipeline.set_vectorize(self.vectorize);
+        if let Some(table) = &self.superinstructions {
+            pipeline.set_superinstruction_table(table);
+        }
+        if let Some(rules) = &self.peephole_rules {
+            pipeline.set_peephole_rules(rules);
+        }
+        if let Some(passes) = &self.passes {
+            pipeline.set_pass_pipeline(passes.clone())?;
+        }
+        pipeline.set_opt_report(self.opt_report);
+        pipeline.set_verify_optimizations(self.verify_optimizations);
+        pipeline.set_ans_strict(self.ans_strict);
+        pipeline.set_constant_time(self.constant_time);
+        pipeline.set_freestanding(self.freestanding);
+        pipeline.set_interruptible(self.interruptible);
+        pipeline.set_sandbox(self.sandbox);
+        pipeline.set_recording(self.recording);
+        pipeline.set_trap_snapshot(self.trap_snapshot.clone());
+        pipeline.set_arithmetic(self.arithmetic);
+        pipeline.set_trace(self.trace.clone());
+        pipeline.set_memory_limit(self.memory_limit);
+        pipeline.set_codegen_units(self.codegen_units);
+        pipeline.set_lto(self.lto);
+        pipeline.set_runtime_profile(self.runtime_profile);
+        pipeline.set_heap(self.heap);
+        Ok(pipeline)
+    }
+
+    /// Run `compile` on a pipeline configured like this compiler, with the
+    /// evaluation cache loaded, and write the cache back if it changed
+    fn with_pipeline<T>(&self, compile: impl FnOnce(&mut CompilationPipeline) -> Result<T>) -> Result<T> {
+        let mut pipeline = self.pipeline()?;
+        let Some(path) = &self.eval_cache else {
+            return compile(&mut pipeline);
+        };
+
+        // A cache that cannot be read is rebuilt
+        let flags = pipeline.eval_cache_flags();
+        let cache = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|text| EvalCache::parse(&text, &flags).ok())
+            .unwrap_or_else(|| EvalCache::new(&flags));
+        pipeline.set_eval_cache(Some(cache));
+        let result = compile(&mut pipeline)?;
+
+        if let Some(cache) = pipeline.take_eval_cache().filter(|cache| cache.misses() > 0) {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir).map_err(|e| CompileError::IoError(dir.to_path_buf(), e))?;
+            }
+            std::fs::write(path, cache.to_string()).map_err(|e| CompileError::IoError(path.clone(), e))?;
+        }
+        Ok(result)
     }
 
     /// Compile Forth source code from a file
@@ -123,6 +321,18 @@ impl Compiler {
         self.compile_string(&source, mode)
     }
 
+    /// Type check Forth source code without generating code
+    pub fn verify_string(&self, source: &str) -> Result<VerificationResult> {
+        CompilationPipeline::new(self.optimization_level).verify(source)
+    }
+
+    /// Type check a Forth source file without generating code
+    pub fn verify_file(&self, path: &Path) -> Result<VerificationResult> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| CompileError::IoError(path.to_path_buf(), e))?;
+        self.verify_string(&source)
+    }
+
     /// Get the optimization level
     pub fn optimization_level(&self) -> OptimizationLevel {
         self.optimization_level
@@ -132,6 +342,168 @@ impl Compiler {
     pub fn set_optimization_level(&mut self, level: OptimizationLevel) {
         self.optimization_level = level;
         self.optimizer = Optimizer::new(level);
+        self.optimizer.set_vectorize(self.vectorize);
+        if let Some(table) = &self.superinstructions {
+            self.optimizer.set_superinstruction_table(table);
+        }
+        if let Some(rules) = &self.peephole_rules {
+            self.optimizer.set_peephole_rules(rules);
+        }
+        if let Some(passes) = &self.passes {
+            self.optimizer.set_pipeline(passes.clone()).expect("pipeline was validated");
+        }
+    }
+
+    /// Get the requested backend
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Set the code generation backend
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    /// Whether array loops are vectorized
+    pub fn vectorize(&self) -> bool {
+        self.vectorize
+    }
+
+    /// Enable or disable loop vectorization
+    pub fn set_vectorize(&mut self, vectorize: bool) {
+        self.vectorize = vectorize;
+        self.optimizer.set_vectorize(vectorize);
+    }
+
+    /// Fuse the sequences of a superinstruction table learned from a corpus
+    pub fn set_superinstruction_table(&mut self, table: SuperinstructionTable) {
+        self.optimizer.set_superinstruction_table(&table);
+        self.superinstructions = Some(table);
+    }
+
+    /// Apply user-defined peephole rules in the peephole pass
+    pub fn set_peephole_rules(&mut self, rules: PeepholeRules) {
+        self.optimizer.set_peephole_rules(&rules);
+        self.peephole_rules = Some(rules);
+    }
+
+    /// Run a pass pipeline instead of the preset of the optimization level
+    ///
+    /// Fails if a pass would run after one that must follow it.
+    pub fn set_pass_pipeline(&mut self, pipeline: PassPipeline) -> Result<()> {
+        self.optimizer.set_pipeline(pipeline.clone())?;
+        self.passes = Some(pipeline);
+        Ok(())
+    }
+
+    /// The passes the optimizer runs
+    pub fn pass_pipeline(&self) -> &PassPipeline {
+        self.optimizer.pipeline()
+    }
+
+    /// Attach an optimization report to each compilation result
+    pub fn set_opt_report(&mut self, enabled: bool) {
+        self.opt_report = enabled;
+    }
+
+    /// Fail compilation if the optimized program behaves differently from
+    /// the unoptimized one on the reference interpreter
+    /// (`--verify-optimizations`)
+    pub fn set_verify_optimizations(&mut self, verify: bool) {
+        self.verify_optimizations = verify;
+    }
+
+    /// Warn about words outside the standard word sets (`--ans-strict`)
+    pub fn set_ans_strict(&mut self, strict: bool) {
+        self.ans_strict = strict;
+    }
+
+    /// Compile every definition constant-time (`--constant-time`)
+    pub fn set_constant_time(&mut self, constant_time: bool) {
+        self.constant_time = constant_time;
+    }
+
+    /// Reject words a libc-free runtime cannot provide (`--freestanding`)
+    pub fn set_freestanding(&mut self, freestanding: bool) {
+        self.freestanding = freestanding;
+    }
+
+    /// Compile JIT code that polls for cancellation at word entries and
+    /// loop heads, so it can be run under a [`CancelToken`]
+    pub fn set_interruptible(&mut self, interruptible: bool) {
+        self.interruptible = interruptible;
+    }
+
+    /// Compile untrusted source in a [`Sandbox`], rejecting words that
+    /// reach outside the process's memory and limiting JIT runs
+    pub fn set_sandbox(&mut self, sandbox: Option<Sandbox>) {
+        self.sandbox = sandbox;
+    }
+
+    /// The sandbox source is compiled in, if any
+    pub fn sandbox(&self) -> Option<Sandbox> {
+        self.sandbox
+    }
+
+    /// Record every word entry and exit of JIT runs, keeping the last
+    /// `events` for [`recording::last_events`]
+    pub fn set_recording(&mut self, events: Option<usize>) {
+        self.recording = events;
+    }
+
+    /// Write the [`snapshot`] of a JIT run that traps to `path`, as JSON,
+    /// and name it in the error
+    pub fn set_trap_snapshot(&mut self, path: Option<PathBuf>) {
+        self.trap_snapshot = path;
+    }
+
+    /// Divide floored or symmetric, and wrap or trap on overflow
+    /// (`--division`, `--overflow`)
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
+    }
+
+    /// Record the time spent in each stage, pass and word of every
+    /// compilation in a trace
+    pub fn set_trace(&mut self, trace: Option<Arc<CompilationTrace>>) {
+        self.trace = trace;
+    }
+
+    /// Stop compiling once the process holds more than `limit` bytes
+    /// (`--memory-limit`)
+    pub fn set_memory_limit(&mut self, limit: Option<u64>) {
+        self.memory_limit = limit;
+    }
+
+    /// Split LLVM code generation into at most `units` parallel modules
+    /// (`--codegen-units`); `None` uses one per core
+    pub fn set_codegen_units(&mut self, units: Option<usize>) {
+        self.codegen_units = units;
+    }
+
+    /// Emit LLVM bitcode and optimize it with the runtime at link time
+    /// (`--lto`)
+    pub fn set_lto(&mut self, lto: Lto) {
+        self.lto = lto;
+    }
+
+    /// Cap the runtime components a linked program may use
+    /// (`--runtime-profile`)
+    pub fn set_runtime_profile(&mut self, profile: RuntimeProfile) {
+        self.runtime_profile = profile;
+    }
+
+    /// Choose the allocator behind `allocate` in JIT runs, and whether
+    /// to check frees and report leaks (`--heap`, `--heap-debug`)
+    pub fn set_heap(&mut self, heap: HeapConfig) {
+        self.heap = heap;
+    }
+
+    /// Keep the zero-cost pass's compile-time evaluation of each word in
+    /// `path` across builds, reusing it while the word, its callees and the
+    /// flags are unchanged (`--eval-cache`)
+    pub fn set_eval_cache(&mut self, path: Option<PathBuf>) {
+        self.eval_cache = path;
     }
 }
 
@@ -156,4 +528,16 @@ mod tests {
         let compiler = Compiler::default();
         assert_eq!(compiler.optimization_level(), OptimizationLevel::Standard);
     }
+
+    #[test]
+    fn test_pass_pipeline_survives_level_change() {
+        let mut compiler = Compiler::new(OptimizationLevel::Standard);
+        let pipeline = PassPipeline::parse("constant-fold,inline,dce").unwrap();
+        compiler.set_pass_pipeline(pipeline.clone()).unwrap();
+        compiler.set_optimization_level(OptimizationLevel::Aggressive);
+        assert_eq!(compiler.pass_pipeline(), &pipeline);
+
+        assert!(compiler.set_pass_pipeline(PassPipeline::parse("stack-cache,dce").unwrap()).is_err());
+        assert_eq!(compiler.pass_pipeline(), &pipeline);
+    }
 }
@@ -0,0 +1,266 @@
+//! Lint rule engine
+//!
+//! Runs a set of rules over a parsed program and reports findings as
+//! [`StructuredError`]s with warning severity, so lint output shares the
+//! JSON format used for compiler errors.
+//!
+//! Findings about a whole definition are located at its `:`. Control
+//! structures carry no position in the AST, so findings about one are
+//! located at its token in the source: the rules count the structures of
+//! a word in order, and [`LintContext::find_token`] picks the token of the
+//! same rank.
+
+pub mod rules;
+
+pub use rules::{
+    ConstantConditionRule, LoopStackDepthRule, MissingStackEffectRule, OversizedWordRule,
+    ShadowedCoreWordRule, UnusedDefinitionRule,
+};
+
+use crate::error::Result;
+use crate::errors::{ErrorCode, ErrorSeverity, Location, StructuredError};
+use fastforth_frontend::ast::{SourceLocation, Token};
+use fastforth_frontend::lexer::Lexer;
+use fastforth_frontend::{parse_program, Program};
+use std::collections::HashSet;
+
+/// Name used in findings about top-level code
+pub const TOP_LEVEL: &str = "<top-level>";
+
+/// Lint configuration
+#[derive(Debug, Clone)]
+pub struct LintConfig {
+    /// Maximum number of words in a definition body
+    pub max_word_size: usize,
+    /// Names of rules to skip
+    pub disabled_rules: HashSet<String>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            max_word_size: 32,
+            disabled_rules: HashSet::new(),
+        }
+    }
+}
+
+/// Everything a rule can look at
+pub struct LintContext<'a> {
+    pub program: &'a Program,
+    pub config: &'a LintConfig,
+    file: Option<&'a str>,
+    /// Each token of the source and where it starts
+    tokens: Vec<(Token, SourceLocation)>,
+}
+
+impl<'a> LintContext<'a> {
+    /// Build a warning located at the definition of `word`
+    pub fn warning(&self, code: ErrorCode, word: &str, message: impl Into<String>) -> StructuredError {
+        self.warning_at(code, word, None, message)
+    }
+
+    /// Build a warning located at `at`, or at the definition of `word` when
+    /// the construct has no token of its own, as when an immediate word
+    /// compiled it
+    pub fn warning_at(
+        &self,
+        code: ErrorCode,
+        word: &str,
+        at: Option<SourceLocation>,
+        message: impl Into<String>,
+    ) -> StructuredError {
+        let at = at.or_else(|| {
+            let def = self.program.definitions.iter().find(|def| def.name.eq_ignore_ascii_case(word))?;
+            Some(def.location.clone())
+        });
+        let (line, column) = at.map_or((0, 0), |at| (at.line, at.column));
+        let mut location = Location::new(line, column).with_word(word);
+        if let Some(file) = self.file {
+            location = location.with_file(file);
+        }
+
+        StructuredError::new(code, message)
+            .with_location(location)
+            .with_severity(ErrorSeverity::Warning)
+    }
+
+    /// Where the `nth` token of `word`'s body (or of the top-level code,
+    /// for [`TOP_LEVEL`]) for which `is_match(previous, token)` holds
+    /// starts, counting from 0
+    pub fn find_token(
+        &self,
+        word: &str,
+        nth: usize,
+        is_match: impl Fn(Option<&Token>, &Token) -> bool,
+    ) -> Option<SourceLocation> {
+        let mut owner: Option<&str> = None;
+        let mut previous = None;
+        let mut tokens = self.tokens.iter().peekable();
+        let mut found = 0;
+        while let Some((token, location)) = tokens.next() {
+            match (token, tokens.peek()) {
+                (Token::Colon, Some((Token::Word(name), _))) => {
+                    owner = Some(name);
+                    tokens.next();
+                    previous = None;
+                    continue;
+                }
+                (Token::Semicolon, _) if owner.is_some() => {
+                    owner = None;
+                    previous = None;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let owned = match owner {
+                Some(name) => name.eq_ignore_ascii_case(word),
+                None => word == TOP_LEVEL,
+            };
+            if owned {
+                if is_match(previous, token) {
+                    if found == nth {
+                        return Some(location.clone());
+                    }
+                    found += 1;
+                }
+                previous = Some(token);
+            }
+        }
+        None
+    }
+}
+
+/// A single lint check
+pub trait LintRule {
+    /// Rule name, used to disable it from configuration
+    fn name(&self) -> &'static str;
+
+    /// Append findings for the program
+    fn check(&self, ctx: &LintContext, findings: &mut Vec<StructuredError>);
+}
+
+/// Runs lint rules over Forth programs
+pub struct Linter {
+    config: LintConfig,
+    rules: Vec<Box<dyn LintRule>>,
+}
+
+impl Linter {
+    /// Create a linter with all built-in rules
+    pub fn new(config: LintConfig) -> Self {
+        Self {
+            config,
+            rules: vec![
+                Box::new(UnusedDefinitionRule),
+                Box::new(ShadowedCoreWordRule),
+                Box::new(MissingStackEffectRule),
+                Box::new(LoopStackDepthRule),
+                Box::new(OversizedWordRule),
+                Box::new(ConstantConditionRule),
+            ],
+        }
+    }
+
+    /// Add a custom rule
+    pub fn with_rule(mut self, rule: Box<dyn LintRule>) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Names of all registered rules
+    pub fn rule_names(&self) -> Vec<&'static str> {
+        self.rules.iter().map(|rule| rule.name()).collect()
+    }
+
+    /// Parse and lint source code
+    pub fn lint_source(&self, source: &str, file: Option<&str>) -> Result<Vec<StructuredError>> {
+        let program = parse_program(source)?;
+        let (tokens, locations) = Lexer::new(source).tokenize_with_locations()?;
+
+        let ctx = LintContext {
+            program: &program,
+            config: &self.config,
+            file,
+            tokens: tokens.into_iter().zip(locations).collect(),
+        };
+
+        let mut findings = Vec::new();
+        for rule in &self.rules {
+            if !self.config.disabled_rules.contains(rule.name()) {
+                rule.check(&ctx, &mut findings);
+            }
+        }
+
+        findings.sort_by_key(|f| (f.location.line, f.location.column));
+        Ok(findings)
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::new(LintConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_program_has_no_findings() {
+        let linter = Linter::default();
+        let findings = linter
+            .lint_source(": square ( n -- n ) dup * ;\n5 square", None)
+            .unwrap();
+        assert!(findings.is_empty(), "{:?}", findings);
+    }
+
+    #[test]
+    fn test_findings_are_located_warnings() {
+        let linter = Linter::default();
+        let findings = linter
+            .lint_source("\n: square dup * ;\n5 square", Some("sq.fth"))
+            .unwrap();
+
+        assert_eq!(findings.len(), 1);
+        let finding = &findings[0];
+        assert_eq!(finding.code, "E6002");
+        assert_eq!(finding.severity, Some(ErrorSeverity::Warning));
+        assert_eq!(finding.location.line, 2);
+        assert_eq!(finding.location.file.as_deref(), Some("sq.fth"));
+        assert_eq!(finding.location.word.as_deref(), Some("square"));
+    }
+
+    #[test]
+    fn test_structures_are_located_at_their_tokens() {
+        let source = ": f ( n -- n )\n  dup if 1 + then\n  0 if 2 else 3 then ;\n\
+                      : g ( n -- ) 0 do 1 loop ;\n\
+                      5 f g  begin 0 while 1 drop repeat";
+        let findings = Linter::default().lint_source(source, None).unwrap();
+        let located: Vec<_> = findings
+            .iter()
+            .map(|f| (f.code.as_str(), f.location.line, f.location.column, f.location.word.as_deref().unwrap()))
+            .collect();
+
+        assert_eq!(
+            located,
+            [
+                ("E6005", 3, 5, "f"),
+                ("E6003", 4, 16, "g"),
+                ("E6005", 5, 16, TOP_LEVEL),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disabled_rules_are_skipped() {
+        let mut config = LintConfig::default();
+        config.disabled_rules.insert("missing-stack-effect".to_string());
+        let linter = Linter::new(config);
+
+        let findings = linter.lint_source(": square dup * ;\n5 square", None).unwrap();
+        assert!(findings.is_empty());
+    }
+}
@@ -0,0 +1,460 @@
+//! Built-in lint rules
+
+use super::{LintContext, LintRule, TOP_LEVEL};
+use crate::errors::{ErrorCode, StructuredError};
+use fastforth_frontend::ast::Token;
+use fastforth_frontend::{Definition, Word};
+use fastforth_optimizer::Instruction;
+use std::collections::{HashMap, HashSet};
+
+/// Standard words that user definitions should not redefine
+const CORE_WORDS: &[&str] = &[
+    "+", "-", "*", "/", "mod", "/mod", "negate", "abs", "min", "max",
+    "1+", "1-", "2*", "2/", "dup", "drop", "swap", "over", "rot", "nip", "tuck",
+    "2dup", "2drop", "2swap", "2over", "?dup", "pick", "roll", "depth",
+    "=", "<>", "<", ">", "<=", ">=", "0=", "0<", "0>",
+    "and", "or", "xor", "invert", "lshift", "rshift", "true", "false",
+    "@", "!", "c@", "c!", "w@", "w!", "l@", "l!", "+!", "cells", "cell+", "allot", "here",
+    ".", "emit", "cr", "space", "spaces", "type",
+    ">r", "r>", "r@", "i", "j", "execute", "exit", "recurse",
+];
+
+/// Definitions never referenced from other definitions, top-level code or
+/// test cases
+///
+/// Only applies to programs with top-level code or tests; a file of
+/// definitions alone is treated as a library whose words are used elsewhere.
+pub struct UnusedDefinitionRule;
+
+impl LintRule for UnusedDefinitionRule {
+    fn name(&self) -> &'static str {
+        "unused-definition"
+    }
+
+    fn check(&self, ctx: &LintContext, findings: &mut Vec<StructuredError>) {
+        let program = ctx.program;
+        if program.top_level_code.is_empty() && program.tests.is_empty() {
+            return;
+        }
+
+        let mut used = HashSet::new();
+        collect_references(&program.top_level_code, &mut used);
+        for test in &program.tests {
+            collect_references(&test.body, &mut used);
+            collect_references(&test.expected, &mut used);
+        }
+        for def in &program.definitions {
+            let mut refs = HashSet::new();
+            collect_references(&def.body, &mut refs);
+            refs.remove(def.name.as_str());
+            used.extend(refs);
+        }
+
+        for def in &program.definitions {
+            if !used.contains(def.name.as_str()) {
+                findings.push(ctx.warning(
+                    ErrorCode::UnusedDefinition,
+                    &def.name,
+                    format!("Word '{}' is defined but never used", def.name),
+                ));
+            }
+        }
+    }
+}
+
+/// Definitions that replace a standard word
+pub struct ShadowedCoreWordRule;
+
+impl LintRule for ShadowedCoreWordRule {
+    fn name(&self) -> &'static str {
+        "shadowed-core-word"
+    }
+
+    fn check(&self, ctx: &LintContext, findings: &mut Vec<StructuredError>) {
+        for def in &ctx.program.definitions {
+            if CORE_WORDS.contains(&def.name.to_lowercase().as_str()) {
+                findings.push(ctx.warning(
+                    ErrorCode::ShadowedCoreWord,
+                    &def.name,
+                    format!("Definition of '{}' shadows the core word", def.name),
+                ));
+            }
+        }
+    }
+}
+
+/// Definitions without a `( ... -- ... )` comment
+pub struct MissingStackEffectRule;
+
+impl LintRule for MissingStackEffectRule {
+    fn name(&self) -> &'static str {
+        "missing-stack-effect"
+    }
+
+    fn check(&self, ctx: &LintContext, findings: &mut Vec<StructuredError>) {
+        for def in &ctx.program.definitions {
+            if def.stack_effect.is_none() {
+                findings.push(ctx.warning(
+                    ErrorCode::MissingStackEffect,
+                    &def.name,
+                    format!("Word '{}' has no stack-effect comment", def.name),
+                ));
+            }
+        }
+    }
+}
+
+/// Loop bodies whose stack depth drifts on every iteration
+///
+/// A DO body should leave the depth unchanged, and a BEGIN body should
+/// leave exactly the flag consumed by UNTIL or WHILE. Loops containing
+/// words with unknown effects are skipped.
+pub struct LoopStackDepthRule;
+
+impl LoopStackDepthRule {
+    /// Check the loops in `words`; `loops` counts those of `owner` seen so
+    /// far, which is the rank of the next one's DO or BEGIN
+    fn check_words(
+        &self,
+        ctx: &LintContext,
+        owner: &str,
+        words: &[Word],
+        effects: &HashMap<&str, i32>,
+        loops: &mut usize,
+        findings: &mut Vec<StructuredError>,
+    ) {
+        for word in words {
+            let (kind, drift) = match word {
+                Word::DoLoop { body, .. } => ("DO ... LOOP", net_effect(body, effects)),
+                Word::BeginUntil { body } => ("BEGIN ... UNTIL", net_effect(body, effects).map(|n| n - 1)),
+                Word::BeginWhileRepeat { condition, body } => (
+                    "BEGIN ... WHILE ... REPEAT",
+                    net_effect(condition, effects)
+                        .zip(net_effect(body, effects))
+                        .map(|(c, b)| c + b - 1),
+                ),
+                _ => ("", None),
+            };
+
+            if !kind.is_empty() {
+                *loops += 1;
+            }
+            if let Some(drift) = drift.filter(|&d| d != 0) {
+                let at = ctx.find_token(owner, *loops - 1, |_, token| matches!(token, Token::Do | Token::Begin));
+                findings.push(ctx.warning_at(
+                    ErrorCode::LoopStackImbalance,
+                    owner,
+                    at,
+                    format!(
+                        "{} body in '{}' changes the stack depth by {:+} each iteration",
+                        kind, owner, drift
+                    ),
+                ));
+            }
+
+            for nested in children(word) {
+                self.check_words(ctx, owner, nested, effects, loops, findings);
+            }
+        }
+    }
+}
+
+impl LintRule for LoopStackDepthRule {
+    fn name(&self) -> &'static str {
+        "loop-stack-depth"
+    }
+
+    fn check(&self, ctx: &LintContext, findings: &mut Vec<StructuredError>) {
+        let effects = declared_effects(&ctx.program.definitions);
+        for def in &ctx.program.definitions {
+            self.check_words(ctx, &def.name, &def.body, &effects, &mut 0, findings);
+        }
+        self.check_words(ctx, TOP_LEVEL, &ctx.program.top_level_code, &effects, &mut 0, findings);
+    }
+}
+
+/// Definitions longer than `LintConfig::max_word_size` words
+pub struct OversizedWordRule;
+
+impl LintRule for OversizedWordRule {
+    fn name(&self) -> &'static str {
+        "oversized-word"
+    }
+
+    fn check(&self, ctx: &LintContext, findings: &mut Vec<StructuredError>) {
+        let limit = ctx.config.max_word_size;
+        for def in &ctx.program.definitions {
+            let size = word_count(&def.body);
+            if size > limit {
+                findings.push(
+                    ctx.warning(
+                        ErrorCode::OversizedWord,
+                        &def.name,
+                        format!("Word '{}' is {} words long (limit {}); consider factoring it", def.name, size, limit),
+                    )
+                    .add_metadata("size", size.to_string())
+                    .add_metadata("limit", limit.to_string()),
+                );
+            }
+        }
+    }
+}
+
+/// IF and WHILE conditions that are literal constants
+pub struct ConstantConditionRule;
+
+/// Constant conditions of one word seen so far, which is the rank of the
+/// next one's `literal IF` or `literal WHILE` among the word's tokens
+#[derive(Default)]
+struct ConstantConditions {
+    ifs: usize,
+    whiles: usize,
+}
+
+impl ConstantConditionRule {
+    fn check_words(
+        &self,
+        ctx: &LintContext,
+        owner: &str,
+        words: &[Word],
+        seen: &mut ConstantConditions,
+        findings: &mut Vec<StructuredError>,
+    ) {
+        for (index, word) in words.iter().enumerate() {
+            if let Word::If { else_branch, .. } = word {
+                if let Some(value) = index.checked_sub(1).and_then(|i| constant_value(&words[i])) {
+                    let dead = match (value != 0, else_branch.is_some()) {
+                        (true, true) => "the ELSE branch is dead",
+                        (true, false) => "the IF is redundant",
+                        (false, _) => "the IF branch is dead",
+                    };
+                    let at = ctx.find_token(owner, seen.ifs, |previous, token| {
+                        *token == Token::If && previous.is_some_and(is_constant_token)
+                    });
+                    seen.ifs += 1;
+                    findings.push(ctx.warning_at(
+                        ErrorCode::ConstantCondition,
+                        owner,
+                        at,
+                        format!("IF condition in '{}' is always {}; {}", owner, value != 0, dead),
+                    ));
+                }
+            }
+
+            if let Word::BeginWhileRepeat { condition, .. } = word {
+                if let Some(value) = condition.last().and_then(constant_value) {
+                    let at = ctx.find_token(owner, seen.whiles, |previous, token| {
+                        *token == Token::While && previous.is_some_and(is_constant_token)
+                    });
+                    seen.whiles += 1;
+                    if value == 0 {
+                        findings.push(ctx.warning_at(
+                            ErrorCode::ConstantCondition,
+                            owner,
+                            at,
+                            format!("WHILE condition in '{}' is always false; the loop body is dead", owner),
+                        ));
+                    }
+                }
+            }
+
+            for nested in children(word) {
+                self.check_words(ctx, owner, nested, seen, findings);
+            }
+        }
+    }
+}
+
+impl LintRule for ConstantConditionRule {
+    fn name(&self) -> &'static str {
+        "constant-condition"
+    }
+
+    fn check(&self, ctx: &LintContext, findings: &mut Vec<StructuredError>) {
+        for def in &ctx.program.definitions {
+            self.check_words(ctx, &def.name, &def.body, &mut ConstantConditions::default(), findings);
+        }
+        self.check_words(ctx, TOP_LEVEL, &ctx.program.top_level_code, &mut ConstantConditions::default(), findings);
+    }
+}
+
+/// Word sequences nested inside a control structure
+fn children(word: &Word) -> Vec<&[Word]> {
+    match word {
+        Word::If { then_branch, else_branch } => {
+            let mut nested = vec![then_branch.as_slice()];
+            if let Some(else_branch) = else_branch {
+                nested.push(else_branch.as_slice());
+            }
+            nested
+        }
+        Word::BeginUntil { body } | Word::DoLoop { body, .. } => vec![body.as_slice()],
+        Word::BeginWhileRepeat { condition, body } => vec![condition.as_slice(), body.as_slice()],
+        _ => Vec::new(),
+    }
+}
+
+fn collect_references<'a>(words: &'a [Word], refs: &mut HashSet<&'a str>) {
+    for word in words {
+        if let Word::WordRef { name, .. } = word {
+            refs.insert(name.as_str());
+        }
+        for nested in children(word) {
+            collect_references(nested, refs);
+        }
+    }
+}
+
+fn word_count(words: &[Word]) -> usize {
+    words
+        .iter()
+        .filter(|w| !matches!(w, Word::Comment(_)))
+        .map(|w| 1 + children(w).iter().map(|nested| word_count(nested)).sum::<usize>())
+        .sum()
+}
+
+fn constant_value(word: &Word) -> Option<i64> {
+    match word {
+        Word::IntLiteral(value) => Some(*value),
+        Word::WordRef { name, .. } if name.eq_ignore_ascii_case("true") => Some(-1),
+        Word::WordRef { name, .. } if name.eq_ignore_ascii_case("false") => Some(0),
+        _ => None,
+    }
+}
+
+/// Whether a token is a literal [`constant_value`] reads as a condition
+fn is_constant_token(token: &Token) -> bool {
+    match token {
+        Token::Integer(_) => true,
+        Token::Word(name) => name.eq_ignore_ascii_case("true") || name.eq_ignore_ascii_case("false"),
+        _ => false,
+    }
+}
+
+/// Net effects of user definitions that declare a stack effect
+fn declared_effects(definitions: &[Definition]) -> HashMap<&str, i32> {
+    definitions
+        .iter()
+        .filter_map(|def| {
+            let effect = def.stack_effect.as_ref()?;
+            Some((def.name.as_str(), effect.outputs.len() as i32 - effect.inputs.len() as i32))
+        })
+        .collect()
+}
+
+/// Net stack effect of a builtin word
+fn builtin_effect(name: &str) -> Option<i32> {
+    let lower = name.to_lowercase();
+    if let Some(inst) = Instruction::from_word(&lower) {
+        return Some(inst.stack_effect().net_change());
+    }
+    match lower.as_str() {
+        "1+" | "1-" | "2*" | "2/" | "cr" | "cells" | "cell+" => Some(0),
+        "i" | "j" | "r@" | "r>" | "depth" | "true" | "false" | "here" => Some(1),
+        "." | "emit" | "min" | "max" | ">r" | "allot" | "spaces" => Some(-1),
+        "2dup" | "2over" => Some(2),
+        "2drop" | "type" | "+!" => Some(-2),
+        _ => None,
+    }
+}
+
+/// Net stack effect of a word sequence, if every word's effect is known
+fn net_effect(words: &[Word], effects: &HashMap<&str, i32>) -> Option<i32> {
+    let mut net = 0;
+    for word in words {
+        net += match word {
+            Word::IntLiteral(_) | Word::FloatLiteral(_) => 1,
+            Word::StringLiteral(_) => 2,
+            Word::Variable { .. } | Word::Constant { .. } => 1,
+            Word::Comment(_) => 0,
+            Word::TaskSpawn { .. } => return None,
+            Word::Locals { args, .. } => -(args.len() as i32),
+            Word::LocalRef { .. } | Word::Quotation { .. } => 1,
+            Word::LocalStore { .. } => -1,
+            Word::WordRef { name, .. } => {
+                effects.get(name.as_str()).copied().or_else(|| builtin_effect(name))?
+            }
+            Word::If { then_branch, else_branch } => {
+                let then_net = net_effect(then_branch, effects)?;
+                let else_net = match else_branch {
+                    Some(else_branch) => net_effect(else_branch, effects)?,
+                    None => 0,
+                };
+                if then_net != else_net {
+                    return None;
+                }
+                then_net - 1
+            }
+            Word::DoLoop { body, .. } => {
+                if net_effect(body, effects)? != 0 {
+                    return None;
+                }
+                -2
+            }
+            Word::BeginUntil { body } => {
+                if net_effect(body, effects)? != 1 {
+                    return None;
+                }
+                0
+            }
+            Word::BeginWhileRepeat { condition, body } => {
+                net_effect(condition, effects)? - 1 + net_effect(body, effects)?
+            }
+        };
+    }
+    Some(net)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lint::{LintConfig, Linter};
+
+    fn codes(source: &str) -> Vec<String> {
+        Linter::default()
+            .lint_source(source, None)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.code)
+            .collect()
+    }
+
+    #[test]
+    fn test_unused_definition() {
+        assert_eq!(codes(": a ( -- n ) 1 ;\n: b ( -- n ) 2 ;\na"), vec!["E6000"]);
+        // Library files have no entry point, so nothing is reported
+        assert!(codes(": a ( -- n ) 1 ;").is_empty());
+    }
+
+    #[test]
+    fn test_shadowed_core_word() {
+        assert_eq!(codes(": dup ( n -- n n ) 0 + 0 + ;"), vec!["E6001"]);
+    }
+
+    #[test]
+    fn test_loop_stack_drift() {
+        assert_eq!(codes(": f ( n -- ) 0 do 1 loop ;"), vec!["E6003"]);
+        assert!(codes(": f ( n -- ) 0 do i drop loop ;").is_empty());
+        assert_eq!(codes(": g ( n -- n ) begin 1 - dup dup 0= until ;"), vec!["E6003"]);
+    }
+
+    #[test]
+    fn test_oversized_word() {
+        let config = LintConfig {
+            max_word_size: 3,
+            ..LintConfig::default()
+        };
+        let findings = Linter::new(config)
+            .lint_source(": f ( -- n ) 1 1 + 1 + ;", None)
+            .unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].code, "E6004");
+        assert_eq!(findings[0].metadata.get("size").map(String::as_str), Some("5"));
+    }
+
+    #[test]
+    fn test_constant_condition() {
+        assert_eq!(codes(": f ( -- n ) 1 if 2 else 3 then ;"), vec!["E6005"]);
+        assert_eq!(codes(": g ( n -- n ) begin 0 while 1 - repeat ;"), vec!["E6005"]);
+        assert!(codes(": h ( n -- n ) dup if 1 + then ;").is_empty());
+    }
+}
@@ -2,16 +2,26 @@
 //!
 //! A high-performance Forth compiler with LLVM backend
 
-use fastforth::{Compiler, CompilationMode, OptimizationLevel};
+use fastforth::{demangle, demangle_text_with, source_map_path, Arithmetic, Backend, BackendSelector, BackendType, CompilationTrace, CompileError, Compiler, ForthEngine, Image, CompilationMode, CompilationResult, Division, HeapAllocator, HeapConfig, Lto, OptimizationLevel, Overflow, RuntimeProfile, OptimizationReport, Pass, PassReport, Permissions, PassPipeline, PeepholeRules, ReplSession, Sandbox, SourceMap, SuperinstructionTable};
+use fastforth::errors::{format_error, to_structured_error, OutputFormat, StructuredError};
+use fastforth::patterns::{run_pattern_command, Outcome, PatternCommand, PatternDatabase, PatternValidator};
+use fastforth::repl::is_incomplete;
+use fastforth::tiered::debugger::Debugger;
+use fastforth_frontend::stack_bounds::{Bound, StackBounds};
 #[cfg(feature = "inference")]
 use fastforth::inference::InferenceAPI;
 #[cfg(feature = "server")]
 use fastforth::server::{VerificationServer, ServerConfig};
+#[cfg(feature = "inference")]
+use fastforth::server::StdioServer;
 use clap::{Parser, Subcommand};
 use colored::Colorize;
 use rustyline::DefaultEditor;
-use std::path::PathBuf;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(name = "fastforth")]
@@ -25,6 +35,171 @@ struct Cli {
     #[arg(short = 'O', long, default_value = "2", global = true)]
     opt_level: u8,
 
+    /// Code generation backend (auto, cranelift, llvm)
+    #[arg(long, default_value = "auto", global = true)]
+    backend: Backend,
+
+    /// Keep array loops scalar instead of using SIMD kernels
+    #[arg(long, global = true)]
+    no_vectorize: bool,
+
+    /// Superinstruction table learned with `analyze-corpus`
+    #[arg(long, global = true, value_name = "FILE")]
+    superinstructions: Option<PathBuf>,
+
+    /// Peephole rule file applied before the builtin peephole rewrites
+    #[arg(long, global = true, value_name = "FILE")]
+    peephole_rules: Option<PathBuf>,
+
+    /// Optimizer passes to run, in order: a comma-separated list such as
+    /// `constant-fold,inline,dce`, or a preset (none, basic, standard, aggressive)
+    #[arg(long, global = true, value_name = "PASSES", conflicts_with = "pipeline")]
+    passes: Option<String>,
+
+    /// TOML file describing the optimizer passes to run
+    #[arg(long, global = true, value_name = "FILE.toml")]
+    pipeline: Option<PathBuf>,
+
+    /// Leave an optimizer pass out (repeatable)
+    #[arg(long, global = true, value_name = "PASS", value_delimiter = ',')]
+    disable_pass: Vec<String>,
+
+    /// Write a JSON report of what each optimization pass did, per word
+    #[arg(long, global = true, value_name = "FILE.json")]
+    opt_report: Option<PathBuf>,
+
+    /// Run each program unoptimized and optimized on the reference
+    /// interpreter, with random inputs for every word, and fail if they
+    /// differ
+    #[arg(long, global = true)]
+    verify_optimizations: bool,
+
+    /// Index every compiled definition into a JSON compilation database
+    /// (file, span, stack effects, optimizations, symbol), updating the
+    /// entries of the compiled file only
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE.json",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "compdb.json"
+    )]
+    emit_compdb: Option<PathBuf>,
+
+    /// Keep the compile-time evaluation of unchanged words across builds,
+    /// in FILE
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ".fifth/eval-cache"
+    )]
+    eval_cache: Option<PathBuf>,
+
+    /// Keep the stack effects of the definitions `infer`, `verify-effect`
+    /// and the servers see in FILE, and type calls to them from any file
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ".fifth/effects"
+    )]
+    effect_db: Option<PathBuf>,
+
+    /// Write a Chrome trace event file (chrome://tracing, Perfetto) of the
+    /// time spent in each compilation stage, optimizer pass and word
+    #[arg(long, global = true, value_name = "FILE.json")]
+    trace_json: Option<PathBuf>,
+
+    /// Print the time spent in each compilation stage and optimizer pass
+    #[arg(long, global = true)]
+    time_passes: bool,
+
+    /// Split LLVM code generation into at most N modules compiled in
+    /// parallel (default: one per core)
+    #[arg(long, global = true, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    codegen_units: Option<u64>,
+
+    /// Link-time optimization of the LLVM output with the runtime: off,
+    /// thin or full (needs clang and lld)
+    #[arg(long, global = true, value_name = "MODE", default_value = "off")]
+    lto: Lto,
+
+    /// Runtime components a linked program may use: minimal (core only),
+    /// standard (adds files, blocks and the process environment) or full
+    /// (adds floating point)
+    #[arg(long, global = true, value_name = "PROFILE", default_value = "full")]
+    runtime_profile: RuntimeProfile,
+
+    /// Allocator behind ALLOCATE in JIT runs: malloc, or region:SIZE for
+    /// one fixed region released after the run (e.g. region:64M)
+    #[arg(long, global = true, value_name = "HEAP", default_value = "malloc")]
+    heap: HeapAllocator,
+
+    /// Track heap blocks in JIT runs: reject bad FREE and RESIZE calls and
+    /// warn about blocks never freed
+    #[arg(long, global = true)]
+    heap_debug: bool,
+
+    /// Stop compiling once the process holds more than SIZE of memory
+    /// (e.g. 512M, 2G)
+    #[arg(long, global = true, value_name = "SIZE", value_parser = parse_memory_limit)]
+    memory_limit: Option<u64>,
+
+    /// Warn about words outside the ANS standard word sets
+    #[arg(long, global = true)]
+    ans_strict: bool,
+
+    /// How / and MOD round: symmetric (toward zero) or floored (toward
+    /// negative infinity)
+    #[arg(long, global = true, value_name = "MODE", default_value = "symmetric")]
+    division: Division,
+
+    /// What a +, -, * or / whose result does not fit in a cell does:
+    /// wrapping, trapping or checked (throw -11)
+    #[arg(long, global = true, value_name = "MODE", default_value = "wrapping")]
+    overflow: Overflow,
+
+    /// Throw -11 (result out of range) from a +, -, * or / that overflows,
+    /// as --overflow checked
+    #[arg(long, global = true, conflicts_with = "overflow")]
+    checked_arithmetic: bool,
+
+    /// Compile every word for secret data: no data-dependent branches
+    /// where they can be removed, and a warning for each one left
+    #[arg(long, global = true)]
+    constant_time: bool,
+
+    /// Target the libc-free runtime (runtime/forth_freestanding.c): words
+    /// that need files, blocks or the process environment are errors
+    #[arg(long, global = true)]
+    freestanding: bool,
+
+    /// Treat the source as untrusted: shell commands, files, blocks and
+    /// CODE words are errors, and JIT runs are held to the limits below
+    #[arg(long, global = true)]
+    sandbox: bool,
+
+    /// Word calls and loop iterations a sandboxed JIT run may make
+    /// (implies --sandbox)
+    #[arg(long, global = true, value_name = "N")]
+    fuel: Option<u64>,
+
+    /// Longest a sandboxed JIT run may take, in milliseconds (implies
+    /// --sandbox)
+    #[arg(long, global = true, value_name = "MS")]
+    sandbox_timeout: Option<u64>,
+
+    /// Most heap a sandboxed JIT run may allocate, e.g. 16M (implies
+    /// --sandbox)
+    #[arg(long, global = true, value_name = "SIZE", value_parser = parse_memory_limit)]
+    sandbox_memory: Option<u64>,
+
     /// Enable verbose output
     #[arg(short, long, global = true)]
     verbose: bool,
@@ -37,7 +212,8 @@ enum Commands {
         /// Input Forth source file
         input: PathBuf,
 
-        /// Output file (default: based on input name)
+        /// Output file (default: based on input name); an AOT `bin` build
+        /// with an output is linked into a standalone executable
         #[arg(short, long)]
         output: Option<PathBuf>,
 
@@ -45,6 +221,11 @@ enum Commands {
         #[arg(short, long, default_value = "aot")]
         mode: String,
 
+        /// What to build: bin, or cdylib for a shared library of the
+        /// words marked `@export`, with a C header next to it
+        #[arg(long, default_value = "bin", value_name = "TYPE")]
+        crate_type: String,
+
         /// Output format for errors (human, json, json-pretty, plain)
         #[arg(long, default_value = "human")]
         error_format: String,
@@ -60,12 +241,78 @@ enum Commands {
         /// Include auto-fix suggestions in errors
         #[arg(long)]
         suggest_fixes: bool,
+
+        /// Record the outcome in this pattern database against every
+        /// pattern the source names with `\ PATTERN: ID`
+        #[arg(long)]
+        pattern_feedback: Option<PathBuf>,
     },
 
     /// Run Forth code in JIT mode
     Run {
         /// Forth source file to run
         input: PathBuf,
+
+        /// Let the program read files under DIR (repeatable); alone, any
+        /// file. The current directory is always allowed
+        #[arg(long, value_name = "DIR", num_args = 0..=1, require_equals = true, default_missing_value = "/")]
+        allow_read: Vec<PathBuf>,
+
+        /// Let the program create, write and delete files under DIR
+        /// (repeatable); alone, any file. The current directory is always
+        /// allowed
+        #[arg(long, value_name = "DIR", num_args = 0..=1, require_equals = true, default_missing_value = "/")]
+        allow_write: Vec<PathBuf>,
+
+        /// Let the program run commands with SYSTEM
+        #[arg(long)]
+        allow_run: bool,
+
+        /// Record every word entry and exit, and show the last N (default
+        /// 64) with their stacks if the run fails or crashes
+        #[arg(long, value_name = "N", num_args = 0..=1, require_equals = true,
+              default_missing_value = "64")]
+        record: Option<usize>,
+
+        /// Arguments for the program (`arg@`); `0 arg@` is the source file
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Step through a Forth source file in the interpreter, with
+    /// breakpoints, watchpoints and a backtrace (type `help` at a stop)
+    Debug {
+        /// Forth source file to debug
+        input: PathBuf,
+
+        /// Stop whenever WORD is entered (repeatable); the run then starts
+        /// without stopping
+        #[arg(long = "break", value_name = "WORD")]
+        breakpoints: Vec<String>,
+
+        /// Stop when a variable's cell, or the cell at an address, changes
+        /// (repeatable)
+        #[arg(long, value_name = "VAR")]
+        watch: Vec<String>,
+
+        /// Print every word as it runs, with both stacks, without stopping
+        /// unless a breakpoint or watchpoint is hit
+        #[arg(long)]
+        trace: bool,
+    },
+
+    /// Replace the mangled symbols of compiled words (`forth$NAME$HASH`)
+    /// with their Forth names, in SYMBOLS or in standard input, e.g.
+    /// `perf report | fastforth demangle`
+    Demangle {
+        /// Symbols to demangle (default: filter standard input); with
+        /// --source-map, also code addresses (0x...) to name
+        symbols: Vec<String>,
+
+        /// Source map of the program (`.srcmap`, written next to AOT
+        /// builds), to name addresses and add source lines to words
+        #[arg(long, value_name = "FILE")]
+        source_map: Option<PathBuf>,
     },
 
     /// Execute Forth code from command line
@@ -77,6 +324,12 @@ enum Commands {
     /// Start interactive REPL
     Repl,
 
+    /// Save definitions as a dictionary image, or start from one
+    Image {
+        #[command(subcommand)]
+        command: ImageCommands,
+    },
+
     /// Display compiler information
     Info,
 
@@ -115,6 +368,14 @@ enum Commands {
         host: String,
     },
 
+    /// Serve compiler requests to an agent as JSON-RPC
+    #[cfg(feature = "inference")]
+    Serve {
+        /// Read newline-delimited requests from stdin and answer on stdout
+        #[arg(long)]
+        stdio: bool,
+    },
+
     /// Specification commands
     Spec {
         #[command(subcommand)]
@@ -140,6 +401,16 @@ enum Commands {
         no_provenance: bool,
     },
 
+    /// Manage the pattern library
+    Pattern {
+        /// Pattern database
+        #[arg(long, default_value = "patterns.db")]
+        db: PathBuf,
+
+        #[command(subcommand)]
+        command: PatternCommand,
+    },
+
     /// Generate tests for a word
     GenerateTests {
         /// Specification file (JSON)
@@ -155,9 +426,14 @@ enum Commands {
     },
 
     /// Extract provenance metadata from source or binary
+    #[command(args_conflicts_with_subcommands = true)]
     Provenance {
+        #[command(subcommand)]
+        command: Option<ProvenanceCommands>,
+
         /// Source file or binary to extract from
-        input: PathBuf,
+        #[arg(required = true)]
+        input: Option<PathBuf>,
 
         /// Output format (text or json)
         #[arg(long, default_value = "text")]
@@ -176,17 +452,53 @@ enum Commands {
         verified_only: bool,
     },
 
-    /// Run benchmark suite
+    /// Benchmark a source file in JIT and AOT at each optimization level
+    /// (or run the standard suite if no file is given)
     Benchmark {
-        /// Run specific benchmark (or all if not specified)
-        #[arg(long)]
+        /// Forth source file to benchmark
+        input: Option<PathBuf>,
+
+        /// Run specific suite benchmark (or all if not specified)
+        #[arg(long, conflicts_with = "input")]
         name: Option<String>,
 
+        /// Timed iterations per case
+        #[arg(short = 'n', long, default_value_t = fastforth::performance::file_benchmark::DEFAULT_ITERATIONS)]
+        iterations: usize,
+
+        /// Untimed iterations run before timing
+        #[arg(long, default_value_t = fastforth::performance::file_benchmark::DEFAULT_WARMUP)]
+        warmup: usize,
+
+        /// JSON report of a previous run to compare against
+        #[arg(long, requires = "input")]
+        baseline: Option<PathBuf>,
+
+        /// Slowdown in percent flagged as a regression
+        #[arg(long, default_value_t = fastforth::performance::file_benchmark::DEFAULT_REGRESSION_THRESHOLD)]
+        threshold: f64,
+
+        /// Also write the JSON report here, for use as a later baseline
+        #[arg(short, long, requires = "input")]
+        output: Option<PathBuf>,
+
         /// Output format (text or json)
         #[arg(long, default_value = "text")]
         format: String,
     },
 
+    /// Calibrate the performance model on this machine
+    Calibrate {
+        /// Where to save the calibrated model (default: $FIFTH_PERFORMANCE_MODEL
+        /// or ~/.fifth/performance-model.json)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Timed runs per microbenchmark
+        #[arg(short = 'n', long, default_value_t = fastforth::performance::calibration::DEFAULT_SAMPLES)]
+        samples: usize,
+    },
+
     /// Compose two stack effects (type algebra)
     Compose {
         /// First word or effect
@@ -202,20 +514,185 @@ enum Commands {
 
     /// Semantic diff between two implementations
     Diff {
-        /// Old implementation file
+        /// Old implementation file, or directory of Forth sources
         old: PathBuf,
 
-        /// New implementation file
+        /// New implementation file, or directory of Forth sources
         new: PathBuf,
 
         /// Use semantic comparison
         #[arg(long)]
         semantic: bool,
 
+        /// Policies that fail the diff, comma separated: stack-effect-change,
+        /// perf-regression:N%, semantic-change. Each sets its own exit code
+        /// bit (2, 4 and 8) and the report gains a verdict block
+        #[arg(long, value_delimiter = ',')]
+        fail_on: Vec<fastforth::semantic_diff::FailOn>,
+
+        /// Loop iterations to unroll before equivalence is reported unknown
+        #[arg(long, default_value_t = fastforth::symbolic::DEFAULT_LOOP_UNROLL)]
+        unroll: usize,
+
         /// Output format (human or json)
         #[arg(long, default_value = "human")]
         format: String,
     },
+
+    /// Learn a superinstruction table from a directory of Forth sources
+    AnalyzeCorpus {
+        /// Directory searched recursively for .fs, .fth, .4th and .forth files
+        dir: PathBuf,
+
+        /// Write the table to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Number of sequences to keep
+        #[arg(long, default_value = "32")]
+        top: usize,
+
+        /// Ignore sequences seen fewer times than this
+        #[arg(long, default_value = "2")]
+        min_count: u64,
+    },
+
+    /// Run programs under Fast Forth and gforth and report where they diverge
+    Fuzz {
+        /// Forth source files to compare (random programs are generated if none are given)
+        inputs: Vec<PathBuf>,
+
+        /// Random programs to generate
+        #[arg(short = 'n', long, default_value = "1000")]
+        cases: usize,
+
+        /// Seed for random programs (default: from the clock)
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Most words in a random program
+        #[arg(long, default_value = "32")]
+        max_steps: usize,
+
+        /// gforth executable (default: $FIFTH_GFORTH or gforth on the path)
+        #[arg(long)]
+        gforth: Option<PathBuf>,
+
+        /// Report programs as given instead of minimizing them
+        #[arg(long)]
+        no_minimize: bool,
+
+        /// Stop after this many divergences
+        #[arg(long, default_value = "10")]
+        max_divergences: usize,
+
+        /// Output format (text or json)
+        #[arg(long, default_value = "text")]
+        format: String,
+    },
+
+    /// Check a source file without compiling it
+    Check {
+        /// Forth source file to check
+        input: PathBuf,
+
+        /// Report the worst-case data- and return-stack depth of each word
+        /// and of the program
+        #[arg(long)]
+        stack_bounds: bool,
+
+        /// Output the report as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Stop after reporting this many errors
+        #[arg(long, default_value_t = 20)]
+        max_errors: usize,
+    },
+
+    /// Run the compiler's own conformance tests
+    Test {
+        /// Run the ANS Forth compliance suite against the claimed word sets
+        #[arg(long)]
+        ans: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProvenanceCommands {
+    /// Generate a signing key
+    Keygen {
+        /// Write the secret key here instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Sign every provenance record in a source file
+    Sign {
+        /// Source file to sign
+        input: PathBuf,
+
+        /// Secret key file (default: FIFTH_PROVENANCE_KEY)
+        #[arg(long)]
+        key: Option<PathBuf>,
+
+        /// Write the signed source here instead of in place
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Check provenance signatures and report tampering
+    Verify {
+        /// Source file to check
+        input: PathBuf,
+
+        /// Public key (hex) to trust (default: the key of FIFTH_PROVENANCE_KEY);
+        /// with no trusted key, signatures are only reported as self-signed
+        #[arg(long = "trusted-key")]
+        trusted_keys: Vec<String>,
+
+        /// Output format (text or json)
+        #[arg(long, default_value = "text")]
+        format: String,
+    },
+
+    /// Aggregate a project's provenance into a CycloneDX JSON document
+    Report {
+        /// Project directory, source file or binary
+        input: PathBuf,
+
+        /// Write the document here instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ImageCommands {
+    /// Compile the definitions of source files into an image
+    Save {
+        /// Source files, defined in order; definitions only
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Image file to write
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+
+    /// Load an image and list its words, or call one of them
+    Restore {
+        /// Image file
+        image: PathBuf,
+
+        /// Word to call once loaded, which must declare its stack effect
+        #[arg(long, value_name = "WORD")]
+        call: Option<String>,
+
+        /// Arguments for --call, deepest first
+        #[arg(allow_negative_numbers = true)]
+        args: Vec<i64>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -228,6 +705,10 @@ enum SpecCommands {
         /// Use strict validation
         #[arg(long)]
         strict: bool,
+
+        /// Forth source implementing the word; its properties are checked
+        #[arg(long)]
+        implementation: Option<PathBuf>,
     },
 
     /// Show specification details
@@ -235,6 +716,20 @@ enum SpecCommands {
         /// Specification file (JSON)
         spec: PathBuf,
     },
+
+    /// Extract a specification for each word defined in Forth source
+    Extract {
+        /// Forth source file
+        source: PathBuf,
+
+        /// Directory to write one <word>.json per word (default: JSON array on stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Use strict validation
+        #[arg(long)]
+        strict: bool,
+    },
 }
 
 fn main() {
@@ -256,17 +751,62 @@ fn main() {
         _ => OptimizationLevel::Aggressive,
     };
 
-    let compiler = Compiler::new(opt_level);
+    // Only compiling a program runs the optimizer
+    let compiles = matches!(&cli.command, Some(Commands::Compile { verify_only: false, .. } | Commands::Run { .. }));
+    if cli.opt_report.is_some() && !compiles {
+        eprintln!("{}: --opt-report needs a program to be compiled, with `compile` (without --verify-only) or `run`", "Error".red());
+        process::exit(1);
+    }
+
+    let mut compiler = Compiler::new(opt_level);
+    compiler.set_backend(cli.backend);
+    compiler.set_vectorize(!cli.no_vectorize);
+    if let Some(path) = &cli.superinstructions {
+        compiler.set_superinstruction_table(load_superinstruction_table(path));
+    }
+    if let Some(path) = &cli.peephole_rules {
+        compiler.set_peephole_rules(load_peephole_rules(path));
+    }
+    if let Some(pipeline) = pass_pipeline(&cli, opt_level) {
+        if let Err(e) = compiler.set_pass_pipeline(pipeline) {
+            eprintln!("{}: {}", "Error".red().bold(), e);
+            process::exit(1);
+        }
+    }
+    compiler.set_opt_report(cli.opt_report.is_some() || cli.emit_compdb.is_some());
+    compiler.set_verify_optimizations(cli.verify_optimizations);
+    compiler.set_ans_strict(cli.ans_strict);
+    let overflow = if cli.checked_arithmetic { Overflow::Checked } else { cli.overflow };
+    compiler.set_arithmetic(Arithmetic { division: cli.division, overflow });
+    compiler.set_constant_time(cli.constant_time);
+    compiler.set_freestanding(cli.freestanding);
+    let sandbox = Sandbox {
+        fuel: cli.fuel,
+        timeout: cli.sandbox_timeout.map(Duration::from_millis),
+        memory: cli.sandbox_memory,
+    };
+    compiler.set_sandbox(sandbox.for_request(None, cli.sandbox));
+    compiler.set_memory_limit(cli.memory_limit);
+    compiler.set_codegen_units(cli.codegen_units.map(|units| units as usize));
+    compiler.set_lto(cli.lto);
+    compiler.set_runtime_profile(cli.runtime_profile);
+    compiler.set_heap(HeapConfig { allocator: cli.heap, debug: cli.heap_debug });
+    compiler.set_eval_cache(cli.eval_cache.clone());
+    let trace = (cli.trace_json.is_some() || cli.time_passes).then(|| Arc::new(CompilationTrace::new()));
+    compiler.set_trace(trace.clone());
+    let trace = trace.as_deref();
 
     match &cli.command {
         Some(Commands::Compile {
             input,
             output,
             mode,
+            crate_type,
             error_format,
             agent_mode,
             verify_only,
             suggest_fixes,
+            pattern_feedback,
         }) => {
             let compilation_mode = match mode.as_str() {
                 "aot" => CompilationMode::AOT,
@@ -277,19 +817,105 @@ fn main() {
                 }
             };
 
+            let output_format = match error_format.as_str() {
+                "human" => OutputFormat::Human,
+                "json" => OutputFormat::Json,
+                "json-pretty" => OutputFormat::JsonPretty,
+                "plain" => OutputFormat::Plain,
+                _ => {
+                    eprintln!(
+                        "{}: Invalid error format '{}', use 'human', 'json', 'json-pretty' or 'plain'",
+                        "Error".red(),
+                        error_format
+                    );
+                    process::exit(1);
+                }
+            };
+
+            match crate_type.as_str() {
+                "bin" if !*verify_only && compilation_mode == CompilationMode::AOT && output.is_some() => {
+                    let built = build_executable(&compiler, input, output.as_ref().unwrap(), *agent_mode, output_format, *suggest_fixes);
+                    report_trace(&cli, trace);
+                    if built {
+                        write_build_opt_report(&compiler, cli.opt_report.as_ref(), input);
+                    }
+                    if !built {
+                        process::exit(1);
+                    }
+                    return;
+                }
+                "bin" => {}
+                "cdylib" if !*verify_only => {
+                    let built = build_shared_library(&compiler, input, output.as_ref(), *agent_mode, output_format, *suggest_fixes);
+                    report_trace(&cli, trace);
+                    if built {
+                        write_build_opt_report(&compiler, cli.opt_report.as_ref(), input);
+                    }
+                    if !built {
+                        process::exit(1);
+                    }
+                    return;
+                }
+                "cdylib" => {}
+                _ => {
+                    eprintln!("{}: Invalid crate type '{}', use 'bin' or 'cdylib'", "Error".red(), crate_type);
+                    process::exit(1);
+                }
+            }
+
             // For verify-only mode, we only type-check
             if *verify_only {
-                // TODO: Implement type-check only mode
-                println!("{}", "Verify-only mode not yet implemented".yellow());
+                let verified = compiler.verify_file(input);
+                report_trace(&cli, trace);
+                record_pattern_feedback(pattern_feedback.as_ref(), input, &verified);
+                match verified {
+                    Ok(result) => {
+                        if *agent_mode {
+                            let json_output = serde_json::json!({
+                                "status": "success",
+                                "definitions_count": result.definitions_count,
+                                "verify_time_ms": result.verify_time_ms,
+                            });
+                            println!("{}", serde_json::to_string(&json_output).unwrap());
+                        } else {
+                            println!("{}", "✓ Verification passed".green().bold());
+                            println!("  Definitions: {}", result.definitions_count);
+                            println!("  Time: {}ms", result.verify_time_ms);
+                        }
+                    }
+                    Err(e) => {
+                        let diagnostic = structured_diagnostic(&e, input, *suggest_fixes);
+                        if *agent_mode {
+                            let json_output = serde_json::json!({
+                                "status": "error",
+                                "error": format!("{}", e),
+                                "diagnostic": diagnostic,
+                            });
+                            println!("{}", serde_json::to_string(&json_output).unwrap());
+                        } else {
+                            eprint!("{}", format_diagnostic(&diagnostic, output_format));
+                        }
+                        process::exit(1);
+                    }
+                }
+                return;
             }
 
-            match compiler.compile_file(input, compilation_mode) {
+            let compiled = compiler.compile_file(input, compilation_mode);
+            report_trace(&cli, trace);
+            record_pattern_feedback(pattern_feedback.as_ref(), input, &compiled);
+            match compiled {
                 Ok(result) => {
+                    write_opt_report(cli.opt_report.as_ref(), result.opt_report.as_ref());
+                    write_compdb(cli.emit_compdb.as_ref(), input, &result);
+
                     // Agent mode: JSON output only
                     if *agent_mode {
                         let json_output = serde_json::json!({
                             "status": "success",
                             "mode": format!("{:?}", result.mode),
+                            "backend": format!("{:?}", result.backend),
+                            "warnings": result.warnings,
                             "compile_time_ms": result.compile_time_ms,
                             "definitions_count": result.stats.definitions_count,
                             "optimization_savings": result.stats.optimization_savings(),
@@ -297,8 +923,10 @@ fn main() {
                         });
                         println!("{}", serde_json::to_string(&json_output).unwrap());
                     } else {
+                        print_warnings(&result);
                         println!("{}", "✓ Compilation successful".green().bold());
                         println!("  Mode: {:?}", result.mode);
+                        println!("  Backend: {:?}", result.backend);
                         println!("  Time: {}ms", result.compile_time_ms);
                         println!("  Definitions: {}", result.stats.definitions_count);
                         println!(
@@ -319,16 +947,40 @@ fn main() {
                         });
                         println!("{}", serde_json::to_string(&json_output).unwrap());
                     } else {
-                        eprintln!("{}: {}", "Compilation failed".red().bold(), e);
+                        let diagnostic = structured_diagnostic(&e, input, *suggest_fixes);
+                        eprint!("{}", format_diagnostic(&diagnostic, output_format));
                     }
                     process::exit(1);
                 }
             }
         }
 
-        Some(Commands::Run { input }) => {
-            match compiler.compile_file(input, CompilationMode::JIT) {
+        Some(Commands::Run { input, allow_read, allow_write, allow_run, record, args }) => {
+            let mut program_args = vec![input.display().to_string()];
+            program_args.extend(args.iter().cloned());
+            fastforth::runtime_ffi::set_program_args(&program_args);
+
+            let permissions = Permissions::current_dir().and_then(|mut permissions| {
+                permissions.read.extend(allow_read.iter().cloned());
+                permissions.write.extend(allow_write.iter().cloned());
+                permissions.run = *allow_run;
+                permissions.install()
+            });
+            if let Err(e) = permissions {
+                eprintln!("{}: {}", "Error".red(), e);
+                process::exit(1);
+            }
+
+            compiler.set_recording(*record);
+            let snapshot = input.file_stem().map(|stem| format!("{}.trap.json", stem.to_string_lossy()));
+            compiler.set_trap_snapshot(snapshot.map(PathBuf::from));
+            let compiled = compiler.compile_file(input, CompilationMode::JIT);
+            report_trace(&cli, trace);
+            match compiled {
                 Ok(result) => {
+                    print_warnings(&result);
+                    write_opt_report(cli.opt_report.as_ref(), result.opt_report.as_ref());
+                    write_compdb(cli.emit_compdb.as_ref(), input, &result);
                     println!("{}", "✓ Execution complete".green().bold());
                     println!("  Time: {}ms", result.compile_time_ms);
                     if let Some(jit_result) = result.jit_result {
@@ -337,14 +989,85 @@ fn main() {
                 }
                 Err(e) => {
                     eprintln!("{}: {}", "Execution failed".red().bold(), e);
+                    if record.is_some() {
+                        let events = fastforth::recording::last_events();
+                        eprintln!("Last {} words entered and exited in {}:", events.len(), input.display());
+                        for event in events {
+                            eprintln!("  {}", event);
+                        }
+                    }
                     process::exit(1);
                 }
             }
         }
 
+        Some(Commands::Debug { input, breakpoints, watch, trace }) => {
+            let source = match std::fs::read_to_string(input) {
+                Ok(source) => source,
+                Err(e) => {
+                    eprintln!("{}: cannot read {}: {}", "Error".red(), input.display(), e);
+                    process::exit(1);
+                }
+            };
+            let mut debugger = Debugger::new();
+            for word in breakpoints {
+                debugger.break_at(word);
+            }
+            for target in watch {
+                debugger.watch(target);
+            }
+            debugger.set_trace(*trace);
+            debugger.stop_at_start(breakpoints.is_empty() && !*trace);
+
+            let stdin = std::io::stdin();
+            match debugger.run(&source, &mut stdin.lock(), &mut std::io::stdout()) {
+                Ok(_) | Err(CompileError::Cancelled) => {}
+                Err(e) => {
+                    eprintln!("{}: {}", "Error".red(), e);
+                    process::exit(1);
+                }
+            }
+        }
+
+        Some(Commands::Demangle { symbols, source_map }) => {
+            let map = source_map.as_ref().map(|path| {
+                std::fs::read_to_string(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|text| SourceMap::parse(&text).map_err(|e| e.to_string()))
+                    .unwrap_or_else(|e| {
+                        eprintln!("{}: cannot read source map {}: {}", "Error".red(), path.display(), e);
+                        process::exit(1);
+                    })
+            });
+            // A word is shown with the line it is defined on, when the map has it
+            let show = |word: String| match map.as_ref().and_then(|map| map.word(&word)?.line) {
+                Some(line) => format!("{} (line {})", word, line),
+                None => word,
+            };
+            if !symbols.is_empty() {
+                for symbol in symbols {
+                    let address = symbol.strip_prefix("0x").and_then(|hex| u64::from_str_radix(hex, 16).ok());
+                    let named = address.and_then(|address| map.as_ref()?.symbolize(address));
+                    println!("{}", named.unwrap_or_else(|| demangle(symbol).map_or_else(|| symbol.clone(), &show)));
+                }
+                return;
+            }
+            let stdin = std::io::stdin();
+            let mut stdout = std::io::stdout().lock();
+            for line in stdin.lock().lines() {
+                let Ok(line) = line else { break };
+                if writeln!(stdout, "{}", demangle_text_with(&line, show)).is_err() {
+                    break;
+                }
+            }
+        }
+
         Some(Commands::Execute { code }) => {
-            match compiler.compile_string(code, CompilationMode::JIT) {
+            let compiled = compiler.compile_string(code, CompilationMode::JIT);
+            report_trace(&cli, trace);
+            match compiled {
                 Ok(result) => {
+                    print_warnings(&result);
                     if let Some(jit_result) = result.jit_result {
                         println!("{}", jit_result);
                     }
@@ -360,14 +1083,20 @@ fn main() {
             run_repl(compiler);
         }
 
+        Some(Commands::Image { command }) => {
+            handle_image_command(command);
+        }
+
         Some(Commands::Info) => {
             print_info(&compiler);
         }
 
         #[cfg(feature = "inference")]
         Some(Commands::Infer { code, json }) => {
-            let api = InferenceAPI::new();
-            match api.infer(code) {
+            let api = inference_api(&cli.effect_db);
+            let inferred = api.infer(code);
+            save_effect_database(&api, &cli.effect_db);
+            match inferred {
                 Ok(result) => {
                     if *json {
                         println!("{}", serde_json::to_string_pretty(&result).unwrap());
@@ -376,6 +1105,17 @@ fn main() {
                         println!("  Effect: {}", result.inferred_effect);
                         println!("  Depth Delta: {}", result.stack_depth_delta);
                         println!("  Operations: {}", result.operations.join(" "));
+                        for word in &result.words {
+                            match &word.error {
+                                Some(error) => println!("  {}: {}", word.name, error.red()),
+                                None => println!(
+                                    "  {}: ( {} -- {} )",
+                                    word.name,
+                                    word.inputs.join(" "),
+                                    word.outputs.join(" ")
+                                ),
+                            }
+                        }
                         println!("  Latency: {:.3}ms", result.latency_ms);
                     }
                 }
@@ -388,8 +1128,10 @@ fn main() {
 
         #[cfg(feature = "inference")]
         Some(Commands::VerifyEffect { code, effect, json }) => {
-            let api = InferenceAPI::new();
-            match api.verify_effect(code, effect) {
+            let api = inference_api(&cli.effect_db);
+            let verified = api.verify_effect(code, effect);
+            save_effect_database(&api, &cli.effect_db);
+            match verified {
                 Ok(result) => {
                     if *json {
                         println!("{}", serde_json::to_string_pretty(&result).unwrap());
@@ -422,6 +1164,9 @@ fn main() {
                 host: host.clone(),
                 port: *port,
                 workers: num_cpus::get(),
+                sandbox: compiler.sandbox(),
+                effect_database: cli.effect_db.clone(),
+                ..ServerConfig::default()
             };
 
             let server = VerificationServer::new(config);
@@ -434,6 +1179,25 @@ fn main() {
             });
         }
 
+        #[cfg(feature = "inference")]
+        Some(Commands::Serve { stdio }) => {
+            if !*stdio {
+                eprintln!(
+                    "{}: only --stdio is supported; use the `server` command for HTTP",
+                    "Error".red()
+                );
+                process::exit(1);
+            }
+
+            let server = StdioServer::new(compiler).with_inference(inference_api(&cli.effect_db));
+            let served = server.serve(io::stdin().lock(), io::stdout().lock());
+            save_effect_database(server.inference(), &cli.effect_db);
+            if let Err(e) = served {
+                eprintln!("{}: {}", "Server error".red().bold(), e);
+                process::exit(1);
+            }
+        }
+
         Some(Commands::Spec { command }) => {
             handle_spec_command(command);
         }
@@ -442,24 +1206,61 @@ fn main() {
             handle_generate_command(from_spec, output, *no_tests, *no_provenance);
         }
 
+        Some(Commands::Pattern { db, command }) => {
+            if let Err(e) = run_pattern_command(command.clone(), db) {
+                eprintln!("{}: {}", "Error".red().bold(), e);
+                process::exit(1);
+            }
+        }
+
         Some(Commands::GenerateTests { spec, output, random_count }) => {
             handle_generate_tests_command(spec, output, *random_count);
         }
 
-        Some(Commands::Provenance { input, format, agent, pattern, verified_only }) => {
-            handle_provenance_command(input, format, agent, pattern, *verified_only);
+        Some(Commands::Provenance { command: Some(command), .. }) => {
+            handle_provenance_subcommand(command);
         }
 
-        Some(Commands::Benchmark { name, format }) => {
+        Some(Commands::Provenance { command: None, input, format, agent, pattern, verified_only }) => {
+            if let Some(input) = input {
+                handle_provenance_command(input, format, agent, pattern, *verified_only);
+            }
+        }
+
+        Some(Commands::Benchmark { input: Some(input), iterations, warmup, baseline, threshold, output, format, .. }) => {
+            handle_file_benchmark_command(input, *iterations, *warmup, baseline.as_ref(), *threshold, output.as_ref(), format);
+        }
+
+        Some(Commands::Benchmark { input: None, name, format, .. }) => {
             handle_benchmark_command(name, format);
         }
 
+        Some(Commands::Calibrate { output, samples }) => {
+            handle_calibrate_command(output.as_ref(), *samples);
+        }
+
         Some(Commands::Compose { first, second, json }) => {
             handle_compose_command(first, second, *json);
         }
 
-        Some(Commands::Diff { old, new, semantic, format }) => {
-            handle_diff_command(old, new, *semantic, format);
+        Some(Commands::Diff { old, new, semantic, fail_on, unroll, format }) => {
+            handle_diff_command(old, new, *semantic, fail_on, *unroll, format);
+        }
+
+        Some(Commands::AnalyzeCorpus { dir, output, top, min_count }) => {
+            handle_analyze_corpus_command(dir, output.as_ref(), *top, *min_count);
+        }
+
+        Some(Commands::Fuzz { inputs, cases, seed, max_steps, gforth, no_minimize, max_divergences, format }) => {
+            handle_fuzz_command(inputs, *cases, *seed, *max_steps, gforth.as_ref(), *no_minimize, *max_divergences, format, opt_level);
+        }
+
+        Some(Commands::Check { input, stack_bounds, json, max_errors }) => {
+            handle_check_command(input, *stack_bounds, *json, *max_errors);
+        }
+
+        Some(Commands::Test { ans }) => {
+            handle_test_command(*ans);
         }
 
         None => {
@@ -470,18 +1271,32 @@ fn main() {
 }
 
 fn handle_spec_command(command: &SpecCommands) {
-    use fastforth::{Specification, SpecValidator};
+    use fastforth::{SpecDocument, Specification, SpecValidator};
 
     match command {
-        SpecCommands::Validate { spec, strict } => {
-            match Specification::from_file(spec) {
-                Ok(specification) => {
-                    let validator = if *strict {
-                        SpecValidator::strict()
-                    } else {
-                        SpecValidator::new()
-                    };
+        SpecCommands::Validate { spec, strict, implementation } => {
+            let validator = if *strict {
+                SpecValidator::strict()
+            } else {
+                SpecValidator::new()
+            };
 
+            match SpecDocument::from_file(spec) {
+                Ok(SpecDocument::Program(program)) => match program.validate_with(&validator) {
+                    Ok(()) => {
+                        println!("{}", "✓ Program specification is valid".green().bold());
+                        println!("  Program: {}", program.program);
+                        println!("  Entry Point: {}", program.entry_point);
+                        println!("  Constants: {}", program.constants.len());
+                        println!("  Words: {}", program.words.len());
+                        println!("  Cross-word Tests: {}", program.tests.len());
+                    }
+                    Err(e) => {
+                        eprintln!("{}: {}", "Validation failed".red().bold(), e);
+                        process::exit(1);
+                    }
+                },
+                Ok(SpecDocument::Word(specification)) => {
                     match validator.validate(&specification) {
                         Ok(()) => {
                             println!("{}", "✓ Specification is valid".green().bold());
@@ -497,6 +1312,24 @@ fn handle_spec_command(command: &SpecCommands) {
                             process::exit(1);
                         }
                     }
+
+                    if let Some(path) = implementation {
+                        let checks = std::fs::read_to_string(path)
+                            .map_err(fastforth::SpecError::from)
+                            .and_then(|source| validator.validate_implementation(&specification, &source));
+                        match checks {
+                            Ok(checks) => {
+                                println!("  Properties:");
+                                for check in checks {
+                                    println!("    {}", check);
+                                }
+                            }
+                            Err(e) => {
+                                eprintln!("{}: {}", "Implementation check failed".red().bold(), e);
+                                process::exit(1);
+                            }
+                        }
+                    }
                 }
                 Err(e) => {
                     eprintln!("{}: {}", "Failed to load specification".red().bold(), e);
@@ -505,6 +1338,15 @@ fn handle_spec_command(command: &SpecCommands) {
             }
         }
 
+        SpecCommands::Extract { source, output, strict } => {
+            let validator = if *strict {
+                SpecValidator::strict()
+            } else {
+                SpecValidator::new()
+            };
+            handle_spec_extract(source, output.as_deref(), &validator);
+        }
+
         SpecCommands::Show { spec } => {
             match Specification::from_file(spec) {
                 Ok(specification) => {
@@ -525,21 +1367,92 @@ fn handle_spec_command(command: &SpecCommands) {
     }
 }
 
-fn handle_generate_command(
-    spec_path: &PathBuf,
-    output: &Option<PathBuf>,
-    no_tests: bool,
-    no_provenance: bool,
-) {
-    use fastforth::{Specification, SpecCodeGenerator};
+fn handle_spec_extract(source: &Path, output: Option<&Path>, validator: &fastforth::SpecValidator) {
+    let extraction = match std::fs::read_to_string(source)
+        .map_err(fastforth::SpecError::from)
+        .and_then(|code| fastforth::extract_specs(&code, validator))
+    {
+        Ok(extraction) => extraction,
+        Err(e) => {
+            eprintln!("{}: {}", "Extraction failed".red().bold(), e);
+            process::exit(1);
+        }
+    };
 
-    match Specification::from_file(spec_path) {
-        Ok(specification) => {
+    for (word, reason) in &extraction.rejected {
+        eprintln!("{} Skipped {}: {}", "⚠".yellow().bold(), word, reason);
+    }
+    for line in &extraction.unassigned_tests {
+        eprintln!("{} Test at line {} does not apply one word to literals; not harvested", "⚠".yellow().bold(), line);
+    }
+    if extraction.specs.is_empty() {
+        eprintln!("{}: no valid specifications in {}", "Error".red().bold(), source.display());
+        process::exit(1);
+    }
+
+    let Some(dir) = output else {
+        match serde_json::to_string_pretty(&extraction.specs) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("{}: {}", "Failed to format specifications".red().bold(), e);
+                process::exit(1);
+            }
+        }
+        return;
+    };
+
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        eprintln!("{}: {}", "Failed to create output directory".red().bold(), e);
+        process::exit(1);
+    }
+    for spec in &extraction.specs {
+        let path = dir.join(format!("{}.json", spec_file_stem(&spec.word)));
+        let written = spec
+            .to_json_pretty()
+            .map_err(|e| e.to_string())
+            .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            eprintln!("{}: {}: {}", "Failed to write".red().bold(), path.display(), e);
+            process::exit(1);
+        }
+    }
+    println!(
+        "{} Extracted {} specification(s) to {}",
+        "✓".green().bold(),
+        extraction.specs.len(),
+        dir.display()
+    );
+}
+
+/// File name for a word; characters that are not safe in paths, such as
+/// the `/` in `*/`, are hex-escaped
+fn spec_file_stem(word: &str) -> String {
+    word.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c.to_string()
+            } else {
+                format!("_{:02x}", c as u32)
+            }
+        })
+        .collect()
+}
+
+fn handle_generate_command(
+    spec_path: &PathBuf,
+    output: &Option<PathBuf>,
+    no_tests: bool,
+    no_provenance: bool,
+) {
+    use fastforth::{SpecCodeGenerator, SpecDocument};
+
+    match SpecDocument::from_file(spec_path) {
+        Ok(document) => {
             let generator = SpecCodeGenerator::new()
                 .with_tests(!no_tests)
                 .with_provenance(!no_provenance);
 
-            match generator.generate(&specification) {
+            match generator.generate_document(&document) {
                 Ok(code) => {
                     if let Some(output_path) = output {
                         match std::fs::write(output_path, &code) {
@@ -632,11 +1545,29 @@ fn run_repl(compiler: Compiler) {
     println!("Type {} to exit\n", "'.quit'".yellow());
 
     let mut rl = DefaultEditor::new().unwrap();
+    let mut session = ReplSession::new(compiler.optimization_level());
     let mut line_number = 1;
+    // Lines of a definition or control structure still being entered
+    let mut block = String::new();
 
     loop {
-        let prompt = format!("{}> ", line_number.to_string().cyan());
+        let prompt = if block.is_empty() {
+            format!("{}> ", line_number.to_string().cyan())
+        } else {
+            format!("{}  ", "...".dimmed())
+        };
         match rl.readline(&prompt) {
+            Ok(line) if !block.is_empty() => {
+                block.push('\n');
+                block.push_str(&line);
+                if is_incomplete(&block) {
+                    continue;
+                }
+
+                let source = std::mem::take(&mut block);
+                eval_repl_input(&mut rl, &mut session, &source);
+                line_number += 1;
+            }
             Ok(line) => {
                 let trimmed = line.trim();
 
@@ -655,40 +1586,63 @@ fn run_repl(compiler: Compiler) {
 
                 if trimmed.starts_with(".load ") {
                     let path = trimmed.trim_start_matches(".load ").trim();
-                    match compiler.compile_file(&PathBuf::from(path), CompilationMode::JIT) {
-                        Ok(_) => println!("{}", "✓ File loaded".green()),
-                        Err(e) => eprintln!("{}: {}", "Error".red(), e),
+                    match std::fs::read_to_string(path) {
+                        Ok(source) => match session.eval(&source) {
+                            Ok(_) => println!("{}", "✓ File loaded".green()),
+                            Err(e) => eprintln!("{}: {}", "Error".red(), e),
+                        },
+                        Err(e) => eprintln!("{}: {}: {}", "Error".red(), path, e),
                     }
                     continue;
                 }
 
-                // Add to history
-                let _ = rl.add_history_entry(&line);
+                if trimmed == ".s" {
+                    println!("{}", session.format_stack());
+                    continue;
+                }
 
-                // Try to compile and execute
-                match compiler.compile_string(trimmed, CompilationMode::JIT) {
-                    Ok(result) => {
-                        if let Some(jit_result) = result.jit_result {
-                            println!("{} {}", "=>".green(), jit_result);
-                        } else {
-                            println!("{}", "ok".green());
-                        }
+                if trimmed.starts_with(".stack") {
+                    match trimmed.trim_start_matches(".stack").trim() {
+                        "on" => session.set_show_stack(true),
+                        "off" => session.set_show_stack(false),
+                        "" => println!("Stack display is {}", if session.show_stack() { "on" } else { "off" }),
+                        other => eprintln!("{}: expected 'on' or 'off', got '{}'", "Error".red(), other),
+                    }
+                    continue;
+                }
 
-                        if result.stats.definitions_count > 0 {
-                            println!(
-                                "{} {} definitions",
-                                "✓".green(),
-                                result.stats.definitions_count
-                            );
-                        }
+                if trimmed == ".tiers" {
+                    match session.format_tiers() {
+                        Some(tiers) if tiers.is_empty() => println!("No words defined"),
+                        Some(tiers) => println!("{}", tiers),
+                        None => eprintln!("{}: tiered execution is only used at -O0", "Error".red()),
                     }
-                    Err(e) => {
-                        eprintln!("{}: {}", "Error".red(), e);
+                    continue;
+                }
+
+                if trimmed.starts_with(".see ") {
+                    let name = trimmed.trim_start_matches(".see ").trim();
+                    match session.see(name) {
+                        Some(listing) => println!("{}", listing),
+                        None => eprintln!("{}: no user-defined word '{}'", "Error".red(), name),
                     }
+                    continue;
+                }
+
+                // Keep reading until the definition or control structure is closed
+                if is_incomplete(&line) {
+                    block = line;
+                    continue;
                 }
 
+                eval_repl_input(&mut rl, &mut session, &line);
                 line_number += 1;
             }
+            Err(rustyline::error::ReadlineError::Interrupted) if !block.is_empty() => {
+                // Abandon the unfinished block but stay in the REPL
+                println!("^C");
+                block.clear();
+            }
             Err(rustyline::error::ReadlineError::Interrupted) => {
                 println!("^C");
                 break;
@@ -707,11 +1661,59 @@ fn run_repl(compiler: Compiler) {
     println!("\n{}", "Goodbye!".cyan());
 }
 
+/// Record a complete input in history, then compile and run it
+///
+/// Multi-line blocks are stored as one history entry so they can be
+/// recalled and edited as a whole.
+fn eval_repl_input(rl: &mut DefaultEditor, session: &mut ReplSession, input: &str) {
+    let _ = rl.add_history_entry(input);
+
+    match session.eval(input.trim()) {
+        Ok(result) => {
+            let output = session.take_output();
+            if !output.is_empty() {
+                print!("{}", output);
+                if !output.ends_with('\n') {
+                    println!();
+                }
+            }
+            print_warnings(&result);
+
+            if let Some(jit_result) = result.jit_result {
+                println!("{} {}", "=>".green(), jit_result);
+            } else {
+                println!("{}", "ok".green());
+            }
+
+            if session.show_stack() {
+                println!("{}", session.format_stack().dimmed());
+            }
+
+            if result.stats.definitions_count > 0 {
+                println!(
+                    "{} {} definitions",
+                    "✓".green(),
+                    result.stats.definitions_count
+                );
+            }
+        }
+        Err(e) => {
+            eprintln!("{}: {}", "Error".red(), e);
+        }
+    }
+}
+
 fn print_repl_help() {
     println!("\n{}", "REPL Commands:".cyan().bold());
     println!("  {}        - Show this help", ".help".yellow());
     println!("  {}        - Quit the REPL", ".quit".yellow());
     println!("  {} <file> - Load and execute a Forth file", ".load".yellow());
+    println!("  {}           - Show the data stack without changing it", ".s".yellow());
+    println!("  {} on|off - Show the stack after every line", ".stack".yellow());
+    println!("  {} <word>  - Show the optimized IR of a word", ".see".yellow());
+    println!("  {}       - Show each word's execution tier (at -O0)", ".tiers".yellow());
+    println!("\nUnfinished definitions, control structures and strings continue on the");
+    println!("next line at the {} prompt; Ctrl-C abandons the block.", "...".dimmed());
     println!("\n{}", "Forth Basics:".cyan().bold());
     println!("  {}       - Push 42 on stack", "42".yellow());
     println!("  {}        - Duplicate top of stack", "dup".yellow());
@@ -722,6 +1724,293 @@ fn print_repl_help() {
     println!();
 }
 
+/// Report non-fatal diagnostics, such as a backend fallback
+fn print_warnings(result: &CompilationResult) {
+    for warning in &result.warnings {
+        eprintln!("{}: {}", "warning".yellow().bold(), warning);
+    }
+}
+
+/// `compile --crate-type cdylib`: build `lib<name>.so` and its header,
+/// returning whether it was built
+fn build_shared_library(
+    compiler: &Compiler,
+    input: &PathBuf,
+    output: Option<&PathBuf>,
+    agent_mode: bool,
+    output_format: OutputFormat,
+    suggest_fixes: bool,
+) -> bool {
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    let library = output.cloned().unwrap_or_else(|| input.with_file_name(format!("lib{}.so", stem)));
+
+    match compiler.build_shared_library(input, &library) {
+        Ok(built) => {
+            let symbols: Vec<&str> = built.exports.iter().map(|export| export.symbol.as_str()).collect();
+            if agent_mode {
+                let json_output = serde_json::json!({
+                    "status": "success",
+                    "library": built.library,
+                    "header": built.header,
+                    "source_map": built.source_map,
+                    "exports": symbols,
+                });
+                println!("{}", serde_json::to_string(&json_output).unwrap());
+            } else {
+                println!("{}", "✓ Shared library built".green().bold());
+                println!("  Library: {}", built.library.display());
+                println!("  Header: {}", built.header.display());
+                println!("  Source map: {}", built.source_map.display());
+                println!("  Exports: {}", symbols.join(", "));
+            }
+            true
+        }
+        Err(e) => {
+            if agent_mode {
+                let json_output = serde_json::json!({
+                    "status": "error",
+                    "error": format!("{}", e),
+                });
+                println!("{}", serde_json::to_string(&json_output).unwrap());
+            } else {
+                let diagnostic = structured_diagnostic(&e, input, suggest_fixes);
+                eprint!("{}", format_diagnostic(&diagnostic, output_format));
+            }
+            false
+        }
+    }
+}
+
+fn build_executable(
+    compiler: &Compiler,
+    input: &PathBuf,
+    output: &Path,
+    agent_mode: bool,
+    output_format: OutputFormat,
+    suggest_fixes: bool,
+) -> bool {
+    match compiler.build_executable(input, output) {
+        Ok(executable) => {
+            if agent_mode {
+                let json_output = serde_json::json!({
+                    "status": "success",
+                    "source_map": source_map_path(&executable),
+                    "executable": executable,
+                });
+                println!("{}", serde_json::to_string(&json_output).unwrap());
+            } else {
+                println!("{}", "✓ Executable built".green().bold());
+                println!("  Output: {}", executable.display());
+                println!("  Source map: {}", source_map_path(&executable).display());
+            }
+            true
+        }
+        Err(e) => {
+            if agent_mode {
+                let json_output = serde_json::json!({
+                    "status": "error",
+                    "error": format!("{}", e),
+                });
+                println!("{}", serde_json::to_string(&json_output).unwrap());
+            } else {
+                let diagnostic = structured_diagnostic(&e, input, suggest_fixes);
+                eprint!("{}", format_diagnostic(&diagnostic, output_format));
+            }
+            false
+        }
+    }
+}
+
+/// Turn a compilation error into a diagnostic pointing into the input file
+fn structured_diagnostic(error: &CompileError, input: &PathBuf, suggest_fixes: bool) -> StructuredError {
+    let diagnostic = to_structured_error(error, suggest_fixes);
+    match std::fs::read_to_string(input) {
+        Ok(source) => diagnostic.with_source(input.display().to_string(), &source),
+        Err(_) => diagnostic,
+    }
+}
+
+/// Render a diagnostic, ending with a newline
+fn format_diagnostic(diagnostic: &StructuredError, format: OutputFormat) -> String {
+    let mut output = format_error(diagnostic, format);
+    if !output.ends_with('\n') {
+        output.push('\n');
+    }
+    output
+}
+
+/// Record a compile outcome against the patterns named in the source
+///
+/// Feedback is best effort: failing to record it never fails the build.
+fn record_pattern_feedback<T>(db_path: Option<&PathBuf>, input: &PathBuf, result: &Result<T, CompileError>) {
+    let Some(db_path) = db_path else {
+        return;
+    };
+    let outcome = match result {
+        Ok(_) => Outcome::Verified,
+        Err(e) => Outcome::VerificationFailed { error: e.to_string() },
+    };
+
+    let recorded = std::fs::read_to_string(input)
+        .map_err(Into::into)
+        .and_then(|source| {
+            let mut db = PatternDatabase::open(db_path)?;
+            PatternValidator::new(false).record_outcome(&mut db, &source, &outcome)
+        });
+    if let Err(e) = recorded {
+        eprintln!("{}: no pattern feedback recorded: {}", "warning".yellow().bold(), e);
+    }
+}
+
+/// Parse a `--memory-limit` or `--sandbox-memory` size
+fn parse_memory_limit(size: &str) -> std::result::Result<u64, String> {
+    fastforth::memory::parse_size(size)
+        .ok_or_else(|| format!("invalid size '{}', expected bytes or a K, M or G suffix", size))
+}
+
+/// Write the trace requested with `--trace-json` and print the stage
+/// timings requested with `--time-passes`
+fn report_trace(cli: &Cli, trace: Option<&CompilationTrace>) {
+    let Some(trace) = trace else {
+        return;
+    };
+    if cli.time_passes {
+        eprintln!("{}", "Time passes:".bold());
+        for (stage, total) in trace.totals("phase") {
+            eprintln!("  {:<28} {:>10.3}ms", stage, total.as_secs_f64() * 1e3);
+        }
+        let passes = trace.totals("pass");
+        if !passes.is_empty() {
+            eprintln!("{}", "Optimizer passes:".bold());
+            for (pass, total) in passes {
+                eprintln!("  {:<28} {:>10.3}ms", pass, total.as_secs_f64() * 1e3);
+            }
+        }
+    }
+    if let Some(path) = &cli.trace_json {
+        if let Err(e) = trace.write(path) {
+            eprintln!("{}: {}", "Cannot write trace".red().bold(), e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Write the optimization report requested with `--opt-report` for an
+/// executable or shared library, which are built without the optimizer
+fn write_build_opt_report(compiler: &Compiler, path: Option<&PathBuf>, input: &Path) {
+    if path.is_none() {
+        return;
+    }
+    match compiler.optimization_report(input) {
+        Ok(report) => write_opt_report(path, report.as_ref()),
+        Err(e) => {
+            eprintln!("{}: {}", "Cannot report optimizations".red().bold(), e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Write the optimization report requested with `--opt-report`
+fn write_opt_report(path: Option<&PathBuf>, report: Option<&OptimizationReport>) {
+    let Some(path) = path else {
+        return;
+    };
+    let Some(report) = report else {
+        eprintln!("{}: {}: the optimizer produced no report", "Cannot write optimization report".red().bold(), path.display());
+        process::exit(1);
+    };
+
+    let json = serde_json::to_string_pretty(&opt_report_json(report)).unwrap();
+    if let Err(e) = std::fs::write(path, json + "\n") {
+        eprintln!("{}: {}: {}", "Cannot write optimization report".red().bold(), path.display(), e);
+        process::exit(1);
+    }
+}
+
+/// Index the compiled file into the database requested with `--emit-compdb`
+fn write_compdb(path: Option<&PathBuf>, input: &Path, result: &CompilationResult) {
+    let Some(path) = path else {
+        return;
+    };
+    if let Err(e) = fastforth::compdb::update(path, input, result.opt_report.as_ref()) {
+        eprintln!("{}: {}", "Cannot write compilation database".red().bold(), e);
+        process::exit(1);
+    }
+}
+
+fn opt_report_json(report: &OptimizationReport) -> serde_json::Value {
+    let passes_json = |passes: &[PassReport]| -> Vec<serde_json::Value> {
+        passes
+            .iter()
+            .map(|pass| {
+                serde_json::json!({
+                    "pass": pass.pass,
+                    "instructions_before": pass.instructions_before,
+                    "instructions_after": pass.instructions_after,
+                })
+            })
+            .collect()
+    };
+    let passes = passes_json(&report.passes);
+
+    let words: serde_json::Map<String, serde_json::Value> = report
+        .words
+        .iter()
+        .map(|(name, word)| {
+            let detail = serde_json::json!({
+                "instructions_before": word.instructions_before,
+                "instructions_after": word.instructions_after,
+                "inlined": word.inlined,
+                "superinstructions": word.superinstructions,
+                "dead_code_eliminated": word.dead_code_eliminated,
+                "cache_depth": word.cache_depth,
+                "passes": passes_json(&word.passes),
+            });
+            (name.clone(), detail)
+        })
+        .collect();
+
+    serde_json::json!({
+        "level": format!("{:?}", report.level),
+        "passes": passes,
+        "totals": {
+            "inlined": report.words.values().map(|word| word.inlined.len()).sum::<usize>(),
+            "superinstructions": report.superinstructions_fired(),
+            "dead_code_eliminated": report.dead_code_eliminated(),
+        },
+        "zero_cost": report.zero_cost.as_ref().map(|stats| serde_json::json!({
+            "calls_inlined": stats.calls_inlined,
+            "constants_folded": stats.constants_folded,
+            "branches_eliminated": stats.branches_eliminated,
+            "instructions_eliminated": stats.instructions_eliminated,
+        })),
+        "peephole": report.peephole.as_ref().map(|stats| serde_json::json!({
+            "strength_reductions": stats.strength_reductions,
+            "constant_folds": stats.constant_folds,
+            "comparison_chains": stats.comparison_chains,
+            "dead_stores": stats.dead_stores,
+            "bit_idioms": stats.bit_idioms,
+            "user_rules": stats.user_rules,
+        })),
+        "inline": report.inline.as_ref().map(|stats| serde_json::json!({
+            "calls_before": stats.calls_before,
+            "calls_after": stats.calls_after,
+            "calls_inlined": stats.calls_inlined,
+        })),
+        "specialization": report.specialization.as_ref().map(|stats| serde_json::json!({
+            "words_analyzed": stats.words_analyzed,
+            "specializations_created": stats.specializations_created,
+            "call_sites_rewritten": stats.call_sites_rewritten,
+        })),
+        "whole_program": report.whole_program.as_ref().map(|stats| serde_json::json!({
+            "words_eliminated": stats.words_eliminated,
+            "unreachable_words": stats.unreachable_words,
+            "internal_words": stats.internal_words,
+        })),
+        "words": words,
+    })
+}
+
 fn print_info(compiler: &Compiler) {
     println!("\n{}", "Fast Forth Compiler".cyan().bold());
     println!("{}", "=".repeat(50));
@@ -746,6 +2035,21 @@ fn print_info(compiler: &Compiler) {
 
     println!("{}", "Current Configuration:".green().bold());
     println!("  Optimization Level: {:?}", compiler.optimization_level());
+    println!("  Backend: {}", compiler.backend());
+    println!();
+
+    println!("{}", "Available Backends:".green().bold());
+    let available = BackendSelector::available_backends();
+    for backend in [BackendType::Cranelift, BackendType::LLVM] {
+        let marker = if available.contains(&backend) { "✓" } else { "✗" };
+        println!(
+            "  {} {} (compile: {}, runtime: {})",
+            marker,
+            BackendSelector::backend_name(backend),
+            BackendSelector::expected_compile_time(backend),
+            BackendSelector::expected_runtime_performance(backend)
+        );
+    }
     println!();
 
     println!("{}", "Supported Modes:".green().bold());
@@ -815,6 +2119,236 @@ fn handle_provenance_command(
     }
 }
 
+fn handle_image_command(command: &ImageCommands) {
+    let fail = |what: &str, e: &dyn std::fmt::Display| -> ! {
+        eprintln!("{}: {}", what.red().bold(), e);
+        process::exit(1);
+    };
+
+    match command {
+        ImageCommands::Save { inputs, output } => {
+            let mut engine = ForthEngine::new();
+            for input in inputs {
+                let source = std::fs::read_to_string(input).unwrap_or_else(|e| fail("Failed to read file", &e));
+                if let Err(e) = engine.define(&source) {
+                    fail(&format!("Failed to compile {}", input.display()), &e);
+                }
+            }
+            let image = engine.save_image();
+            if let Err(e) = image.write(output) {
+                fail("Failed to write image", &e);
+            }
+            println!("{} {} words saved to {}", "✓".green(), image.words.len(), output.display());
+        }
+
+        ImageCommands::Restore { image, call, args } => {
+            let start = std::time::Instant::now();
+            let mut engine = ForthEngine::new();
+            let loaded = Image::read(image).unwrap_or_else(|e| fail("Failed to read image", &e));
+            if let Err(e) = engine.restore_image(&loaded) {
+                fail("Failed to restore image", &e);
+            }
+            let elapsed = start.elapsed();
+
+            match call {
+                Some(word) => {
+                    let outputs = engine.stack_effect(word).map(|effect| effect.outputs.len());
+                    match outputs.and_then(|outputs| engine.call_cells(word, args, outputs)) {
+                        Ok(results) => {
+                            let results: Vec<String> = results.iter().map(ToString::to_string).collect();
+                            println!("{}", results.join(" "));
+                        }
+                        Err(e) => fail(&format!("Failed to call '{}'", word), &e),
+                    }
+                }
+                None => {
+                    for word in &loaded.words {
+                        println!("{:<24} {}", word.name, word.stack_effect.as_deref().unwrap_or(""));
+                    }
+                    println!(
+                        "{} {} words restored in {:.1}ms",
+                        "✓".green(),
+                        loaded.words.len(),
+                        elapsed.as_secs_f64() * 1000.0
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn handle_provenance_subcommand(command: &ProvenanceCommands) {
+    use fastforth::provenance::signing::{verify_source, ProvenanceSigner};
+    use fastforth::provenance::ProvenanceBom;
+
+    let read = |path: &PathBuf| match std::fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}: {}", "Failed to read file".red().bold(), e);
+            process::exit(1);
+        }
+    };
+    let fail = |what: &str, e: &dyn std::fmt::Display| -> ! {
+        eprintln!("{}: {}", what.red().bold(), e);
+        process::exit(1);
+    };
+
+    match command {
+        ProvenanceCommands::Keygen { output } => {
+            let signer = ProvenanceSigner::generate().unwrap_or_else(|e| fail("Failed to generate key", &e));
+            match output {
+                Some(path) => {
+                    if let Err(e) = std::fs::write(path, format!("{}\n", signer.secret_hex())) {
+                        fail("Failed to write key", &e);
+                    }
+                    println!("{} Secret key written to {}", "✓".green(), path.display());
+                }
+                None => println!("secret: {}", signer.secret_hex()),
+            }
+            println!("public: {}", signer.public_key_hex());
+        }
+
+        ProvenanceCommands::Sign { input, key, output } => {
+            let signer = match key {
+                Some(path) => ProvenanceSigner::from_file(path).unwrap_or_else(|e| fail("Failed to load key", &e)),
+                None => match ProvenanceSigner::from_env() {
+                    Ok(Some(signer)) => signer,
+                    Ok(None) => fail("No signing key", &"pass --key or set FIFTH_PROVENANCE_KEY"),
+                    Err(e) => fail("Failed to load key", &e),
+                },
+            };
+
+            let signed = signer.sign_source(&read(input)).unwrap_or_else(|e| fail("Failed to sign", &e));
+            let target = output.as_ref().unwrap_or(input);
+            if let Err(e) = std::fs::write(target, signed) {
+                fail("Failed to write file", &e);
+            }
+            println!("{} Signed {} with key {}", "✓".green(), target.display(), signer.public_key_hex());
+        }
+
+        ProvenanceCommands::Verify { input, trusted_keys, format } => {
+            let mut trusted = trusted_keys.clone();
+            if trusted.is_empty() {
+                match ProvenanceSigner::from_env() {
+                    Ok(Some(signer)) => trusted.push(signer.public_key_hex()),
+                    Ok(None) => {}
+                    Err(e) => fail("Failed to load key", &e),
+                }
+            }
+            let results = verify_source(&read(input), &trusted).unwrap_or_else(|e| fail("Failed to verify", &e));
+            let failed = results.iter().filter(|(_, status)| !status.is_valid()).count();
+
+            if format == "json" {
+                let report: Vec<_> = results
+                    .iter()
+                    .map(|(word, status)| serde_json::json!({ "word": word, "signature": status }))
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+            } else if results.is_empty() {
+                println!("{}", "No provenance metadata found".yellow());
+            } else {
+                for (word, status) in &results {
+                    let mark = if status.is_valid() { "✓".green() } else { "✗".red() };
+                    println!("{} {}: {}", mark, word, status);
+                }
+                println!();
+                println!("{} of {} record(s) verified", results.len() - failed, results.len());
+                if trusted.is_empty() && failed > 0 {
+                    println!("{}", "No trusted key: pass --trusted-key or set FIFTH_PROVENANCE_KEY".yellow());
+                }
+            }
+
+            if failed > 0 {
+                process::exit(1);
+            }
+        }
+
+        ProvenanceCommands::Report { input, output } => {
+            let bom = ProvenanceBom::from_path(input).unwrap_or_else(|e| fail("Failed to collect provenance", &e));
+            let json = serde_json::to_string_pretty(&bom.to_cyclonedx())
+                .unwrap_or_else(|e| fail("Failed to serialize report", &e));
+
+            match output {
+                Some(path) => {
+                    if let Err(e) = std::fs::write(path, format!("{}\n", json)) {
+                        fail("Failed to write report", &e);
+                    }
+                    println!(
+                        "{} {} word(s), {} verified, written to {}",
+                        "✓".green(),
+                        bom.entries.len(),
+                        bom.verified_count(),
+                        path.display()
+                    );
+                }
+                None => println!("{}", json),
+            }
+        }
+    }
+}
+
+fn handle_file_benchmark_command(
+    input: &PathBuf,
+    iterations: usize,
+    warmup: usize,
+    baseline: Option<&PathBuf>,
+    threshold: f64,
+    output: Option<&PathBuf>,
+    format: &str,
+) {
+    use fastforth::performance::{FileBenchmark, FileBenchmarkReport};
+
+    let source = match std::fs::read_to_string(input) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}: {}", "Failed to read file".red().bold(), e);
+            process::exit(1);
+        }
+    };
+
+    let mut report = FileBenchmark::new(input.display().to_string(), source)
+        .with_iterations(iterations)
+        .with_warmup(warmup)
+        .run();
+
+    if let Some(path) = baseline {
+        let baseline = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| FileBenchmarkReport::from_json(&json).map_err(|e| e.to_string()));
+        match baseline {
+            Ok(baseline) => report.compare_with(&baseline, threshold),
+            Err(e) => {
+                eprintln!("{}: {}: {}", "Failed to load baseline".red().bold(), path.display(), e);
+                process::exit(1);
+            }
+        }
+    }
+
+    let json = match report.to_json() {
+        Ok(json) => json,
+        Err(e) => {
+            eprintln!("{}: {}", "Failed to serialize results".red().bold(), e);
+            process::exit(1);
+        }
+    };
+    if let Some(path) = output {
+        if let Err(e) = std::fs::write(path, &json) {
+            eprintln!("{}: {}", "Failed to write report".red().bold(), e);
+            process::exit(1);
+        }
+    }
+
+    match format {
+        "json" => println!("{}", json),
+        _ => println!("{}", report.format()),
+    }
+
+    let compiled = report.cases.iter().any(|case| case.compile.is_some());
+    if !compiled || report.regressions().next().is_some() {
+        process::exit(1);
+    }
+}
+
 fn handle_benchmark_command(name: &Option<String>, format: &str) {
     use fastforth::performance::benchmarks::{StandardBenchmarks, BenchmarkReport};
 
@@ -852,6 +2386,59 @@ fn handle_benchmark_command(name: &Option<String>, format: &str) {
     }
 }
 
+fn handle_calibrate_command(output: Option<&PathBuf>, samples: usize) {
+    use fastforth::performance::{CalibratedCosts, Calibrator};
+
+    let Some(path) = output.cloned().or_else(CalibratedCosts::default_path) else {
+        eprintln!("{}: pass --output or set {}", "No place to save the model".red().bold(), fastforth::performance::calibration::MODEL_ENV);
+        process::exit(1);
+    };
+
+    println!("Running calibration microbenchmarks...");
+    let costs = match Calibrator::new().with_samples(samples).run() {
+        Ok(costs) => costs,
+        Err(e) => {
+            eprintln!("{}: {}", "Calibration failed".red().bold(), e);
+            process::exit(1);
+        }
+    };
+
+    println!("{} ({:.3}ns per cycle)", costs.host, costs.ns_per_cycle);
+    for (instruction, cycles) in &costs.costs {
+        println!("  {:<12} {:>8.2} cycles", instruction, cycles);
+    }
+
+    if let Err(e) = costs.save(&path) {
+        eprintln!("{}: {}", "Failed to save model".red().bold(), e);
+        process::exit(1);
+    }
+    println!("{} {}", "Saved calibrated model to".green(), path.display());
+}
+
+/// Inference API sharing the stack-effect database at `path`, if any
+#[cfg(feature = "inference")]
+fn inference_api(path: &Option<PathBuf>) -> InferenceAPI {
+    use fastforth::inference::EffectDatabase;
+    use std::sync::{Arc, RwLock};
+
+    match path {
+        Some(path) => InferenceAPI::with_database(Arc::new(RwLock::new(EffectDatabase::load(path)))),
+        None => InferenceAPI::new(),
+    }
+}
+
+/// Write back the stack-effect database of `api`; failing to is not fatal
+#[cfg(feature = "inference")]
+fn save_effect_database(api: &InferenceAPI, path: &Option<PathBuf>) {
+    let Some(path) = path else {
+        return;
+    };
+    let database = api.database().read().unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = database.save(path) {
+        eprintln!("{}: could not write {}: {}", "Warning".yellow(), path.display(), e);
+    }
+}
+
 fn handle_compose_command(first: &str, second: &str, json: bool) {
     use fastforth::type_algebra::{TypeComposer, AlgebraicStackEffect};
     use fastforth_frontend::parse_program;
@@ -950,12 +2537,24 @@ fn handle_compose_command(first: &str, second: &str, json: bool) {
     }
 }
 
-fn handle_diff_command(old_path: &PathBuf, new_path: &PathBuf, _semantic: bool, format: &str) {
+fn handle_diff_command(
+    old_path: &PathBuf,
+    new_path: &PathBuf,
+    _semantic: bool,
+    fail_on: &[fastforth::semantic_diff::FailOn],
+    unroll: usize,
+    format: &str,
+) {
     use fastforth::semantic_diff::{SemanticDiffer, DiffReporter, ReportFormat};
 
-    let differ = SemanticDiffer::new();
+    let differ = SemanticDiffer::new().with_loop_unroll(unroll);
 
-    let result = match differ.diff_files(old_path, new_path) {
+    let result = if old_path.is_dir() && new_path.is_dir() {
+        differ.diff_dirs(old_path, new_path)
+    } else {
+        differ.diff_files(old_path, new_path)
+    };
+    let mut result = match result {
         Ok(r) => r,
         Err(e) => {
             eprintln!("{}: {}", "Diff failed".red().bold(), e);
@@ -968,11 +2567,353 @@ fn handle_diff_command(old_path: &PathBuf, new_path: &PathBuf, _semantic: bool,
         _ => ReportFormat::Human,
     };
 
+    if !fail_on.is_empty() {
+        result.apply_policies(fail_on);
+    }
+
     let report = DiffReporter::report(&result, report_format);
     println!("{}", report);
 
-    // Exit with non-zero if changes detected
-    if result.changed_words > 0 {
+    // With policies, only broken policies fail the diff; otherwise any
+    // change does
+    match &result.verdict {
+        Some(verdict) if !verdict.passed => process::exit(verdict.exit_code),
+        Some(_) => {}
+        None if result.changed_words > 0 => process::exit(1),
+        None => {}
+    }
+}
+
+/// The pass pipeline given with `--passes`, `--pipeline` and
+/// `--disable-pass`, or `None` to run the preset of the optimization level
+fn pass_pipeline(cli: &Cli, level: OptimizationLevel) -> Option<PassPipeline> {
+    if cli.passes.is_none() && cli.pipeline.is_none() && cli.disable_pass.is_empty() {
+        return None;
+    }
+
+    let pipeline = match (&cli.passes, &cli.pipeline) {
+        (Some(passes), _) => PassPipeline::parse(passes).map_err(|e| e.to_string()),
+        (None, Some(path)) => std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| PassPipeline::from_toml(&text, level).map_err(|e| e.to_string()))
+            .map_err(|e| format!("{}: {}", path.display(), e)),
+        (None, None) => Ok(PassPipeline::preset(level)),
+    };
+    let pipeline = pipeline.and_then(|mut pipeline| {
+        for name in &cli.disable_pass {
+            pipeline.disable(name.parse::<Pass>().map_err(|e| e.to_string())?);
+        }
+        Ok(pipeline)
+    });
+
+    match pipeline {
+        Ok(pipeline) => Some(pipeline),
+        Err(e) => {
+            eprintln!("{}: {}", "Error".red().bold(), e);
+            process::exit(1);
+        }
+    }
+}
+
+fn load_superinstruction_table(path: &PathBuf) -> SuperinstructionTable {
+    let table = std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| SuperinstructionTable::parse(&text).map_err(|e| e.to_string()));
+
+    match table {
+        Ok(table) => table,
+        Err(e) => {
+            eprintln!("{}: {}: {}", "Invalid superinstruction table".red().bold(), path.display(), e);
+            process::exit(1);
+        }
+    }
+}
+
+fn load_peephole_rules(path: &PathBuf) -> PeepholeRules {
+    let rules = std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| PeepholeRules::parse(&text).map_err(|e| e.to_string()));
+
+    match rules {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("{}: {}: {}", "Invalid peephole rules".red().bold(), path.display(), e);
+            process::exit(1);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_check_command(input: &Path, stack_bounds: bool, json: bool, max_errors: usize) {
+    use fastforth_frontend::{parse_program_recovering, semantic::validate_program, stack_bounds::stack_bounds as compute_bounds, ForthError};
+
+    let source = match std::fs::read_to_string(input) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("{}: {}: {}", "Error".red().bold(), input.display(), e);
+            process::exit(1);
+        }
+    };
+
+    // The parser recovers from errors so one run reports all of them, and
+    // semantic checks only run once the file parses
+    let (program, mut errors) = parse_program_recovering(&source, max_errors);
+    if errors.is_empty() {
+        errors = validate_program(&program).errors;
+        errors.truncate(max_errors);
+    }
+    if !errors.is_empty() {
+        for error in &errors {
+            // Parse errors spell out their location, which leads the line here
+            let message = match error {
+                ForthError::ParseError { message, .. } => format!("Parse error: {}", message),
+                ForthError::LexError { message, .. } => format!("Lexical error: {}", message),
+                _ => error.to_string(),
+            };
+            match error.location() {
+                Some(location) => eprintln!(
+                    "{}: {}:{}:{}: {}",
+                    "Error".red().bold(),
+                    input.display(),
+                    location.line,
+                    location.column,
+                    message
+                ),
+                None => eprintln!("{}: {}: {}", "Error".red().bold(), input.display(), message),
+            }
+        }
+        process::exit(1);
+    }
+    if !stack_bounds {
+        println!("{} {} ({} definitions)", "✓".green().bold(), input.display(), program.definitions.len());
+        return;
+    }
+
+    let bounds = compute_bounds(&program);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stack_bounds_json(&bounds)).unwrap());
+        return;
+    }
+    println!("{:<24} {:>6} {:>6} {:>7}", "word", "inputs", "data", "return");
+    for word in &bounds.words {
+        let inputs = word.inputs.map_or("?".to_string(), |inputs| inputs.to_string());
+        println!("{:<24} {:>6} {:>6} {:>7}", word.name, inputs, cells(&word.data), cells(&word.returns));
+    }
+    println!("{:<24} {:>6} {:>6} {:>7}", "program".bold(), "", cells(&bounds.data), cells(&bounds.returns));
+
+    let mut reasons: Vec<(&str, &str)> = Vec::new();
+    for word in &bounds.words {
+        for bound in [&word.data, &word.returns] {
+            if let Bound::Unbounded(reason) = bound {
+                if !reasons.contains(&(word.name.as_str(), reason.as_str())) {
+                    reasons.push((word.name.as_str(), reason.as_str()));
+                }
+            }
+        }
+    }
+    for (word, reason) in reasons {
+        println!("  {}: {}", word.yellow(), reason);
+    }
+}
+
+fn cells(bound: &Bound) -> String {
+    match bound {
+        Bound::Cells(cells) => cells.to_string(),
+        Bound::Unbounded(_) => "∞".to_string(),
+    }
+}
+
+fn stack_bounds_json(bounds: &StackBounds) -> serde_json::Value {
+    fn bound(bound: &Bound) -> serde_json::Value {
+        match bound {
+            Bound::Cells(cells) => serde_json::json!(cells),
+            Bound::Unbounded(reason) => serde_json::json!({ "unbounded": reason }),
+        }
+    }
+
+    let words: Vec<_> = bounds
+        .words
+        .iter()
+        .map(|word| {
+            serde_json::json!({
+                "name": word.name,
+                "line": word.location.line,
+                "inputs": word.inputs,
+                "data_stack": bound(&word.data),
+                "return_stack": bound(&word.returns),
+                "recursive": word.recursive,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "program": { "data_stack": bound(&bounds.data), "return_stack": bound(&bounds.returns) },
+        "words": words,
+    })
+}
+
+fn handle_test_command(ans: bool) {
+    if !ans {
+        eprintln!("{}: nothing to test (use --ans for the ANS Forth compliance suite)", "Error".red().bold());
+        process::exit(2);
+    }
+
+    match fastforth::run_compliance_suite() {
+        Ok(report) => {
+            print!("{}", report);
+            if report.complies() {
+                println!("{}", "✓ All claimed word sets comply".green().bold());
+            } else {
+                eprintln!("{}", "✗ Compliance suite failed".red().bold());
+                process::exit(1);
+            }
+        }
+        Err(e) => {
+            eprintln!("{}: {}", "Error".red().bold(), e);
+            process::exit(1);
+        }
+    }
+}
+
+fn handle_fuzz_command(
+    inputs: &[PathBuf],
+    cases: usize,
+    seed: Option<u64>,
+    max_steps: usize,
+    gforth: Option<&PathBuf>,
+    no_minimize: bool,
+    max_divergences: usize,
+    format: &str,
+    opt_level: OptimizationLevel,
+) {
+    use fastforth::testing::differential::ProgramGenerator;
+    use fastforth::DifferentialRunner;
+
+    let mut runner = DifferentialRunner::new().with_optimization_level(opt_level).with_minimize(!no_minimize);
+    if let Some(gforth) = gforth {
+        runner = runner.with_gforth(gforth);
+    }
+    if !runner.gforth_available() {
+        eprintln!("{}: gforth not found (install it, or pass --gforth or set {})", "Error".red().bold(), fastforth::testing::differential::GFORTH_ENV);
+        process::exit(1);
+    }
+
+    // Named programs: the given files, or a batch of random ones
+    let programs: Vec<(String, String)> = if inputs.is_empty() {
+        let seed = seed.unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or_default()
+        });
+        if format != "json" {
+            println!("Comparing {} random programs (seed {})", cases, seed);
+        }
+        let mut generator = ProgramGenerator::new(seed);
+        (0..cases).map(|i| (format!("case {}", i), generator.generate(max_steps))).collect()
+    } else {
+        inputs
+            .iter()
+            .map(|path| match std::fs::read_to_string(path) {
+                Ok(source) => (path.display().to_string(), source),
+                Err(e) => {
+                    eprintln!("{}: Failed to read {}: {}", "Error".red().bold(), path.display(), e);
+                    process::exit(1);
+                }
+            })
+            .collect()
+    };
+
+    let mut divergences = Vec::new();
+    let mut compared = 0;
+    for (name, source) in &programs {
+        match runner.compare(source) {
+            Ok(None) => {}
+            Ok(Some(divergence)) => {
+                if format != "json" {
+                    print!("{} {}\n{}", "Divergence in".red().bold(), name, divergence);
+                }
+                divergences.push(divergence);
+            }
+            Err(e) => {
+                eprintln!("{}: {}", "Error".red().bold(), e);
+                process::exit(1);
+            }
+        }
+        compared += 1;
+        if divergences.len() >= max_divergences {
+            break;
+        }
+    }
+
+    if format == "json" {
+        let report = serde_json::json!({ "compared": compared, "divergences": divergences });
+        println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+    } else if divergences.is_empty() {
+        println!("{} {} programs agree", "✓".green().bold(), compared);
+    } else {
+        println!("{} of {} programs diverged", divergences.len(), compared);
+    }
+
+    if !divergences.is_empty() {
         process::exit(1);
     }
 }
+
+fn handle_analyze_corpus_command(dir: &PathBuf, output: Option<&PathBuf>, top: usize, min_count: u64) {
+    use fastforth::CompilationPipeline;
+    use fastforth_optimizer::CorpusMiner;
+
+    let mut sources = Vec::new();
+    if let Err(e) = collect_forth_sources(dir, &mut sources) {
+        eprintln!("{}: {}: {}", "Cannot read corpus".red().bold(), dir.display(), e);
+        process::exit(1);
+    }
+    sources.sort();
+
+    let pipeline = CompilationPipeline::new(OptimizationLevel::Standard);
+    let mut miner = CorpusMiner::new();
+    for path in &sources {
+        let ir = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| pipeline.lower_source(&source).map_err(|e| e.to_string()));
+        let added = ir.and_then(|ir| miner.add(&ir).map_err(|e| e.to_string()));
+        if let Err(e) = added {
+            eprintln!("{}: skipping {}: {}", "Warning".yellow(), path.display(), e);
+        }
+    }
+
+    let table = miner.table(min_count, top);
+    match output {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, table.to_string()) {
+                eprintln!("{}: {}: {}", "Cannot write table".red().bold(), path.display(), e);
+                process::exit(1);
+            }
+            println!(
+                "{} {} sequences from {} of {} files to {}",
+                "✓ Wrote".green().bold(),
+                table.len(),
+                miner.programs(),
+                sources.len(),
+                path.display()
+            );
+        }
+        None => print!("{}", table),
+    }
+}
+
+/// Forth source files under a directory, recursively
+fn collect_forth_sources(dir: &std::path::Path, sources: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_forth_sources(&path, sources)?;
+        } else if matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("fs" | "fth" | "4th" | "forth")
+        ) {
+            sources.push(path);
+        }
+    }
+    Ok(())
+}
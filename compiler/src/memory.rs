@@ -0,0 +1,69 @@
+//! Memory Budget
+//!
+//! Generated programs can be large enough that compiling them exhausts
+//! memory. The pipeline reads the resident set size of the process at the
+//! boundary of each stage and stops with
+//! [`CompileError::MemoryLimitExceeded`](crate::CompileError::MemoryLimitExceeded)
+//! once it is past the configured limit, rather than running on until the
+//! system kills it. The limit covers the whole process, so an embedding
+//! host's own memory counts towards it.
+//!
+//! Memory is read from `/proc/self/status`; on other platforms nothing is
+//! measured and no limit is enforced.
+
+/// Resident set size of the process in bytes
+pub fn resident_bytes() -> Option<u64> {
+    status_field("VmRSS:")
+}
+
+/// Largest resident set size the process has had, in bytes
+pub fn peak_resident_bytes() -> Option<u64> {
+    status_field("VmHWM:")
+}
+
+#[cfg(target_os = "linux")]
+fn status_field(field: &str) -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with(field))?;
+    let kib: u64 = line[field.len()..].trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn status_field(_field: &str) -> Option<u64> {
+    None
+}
+
+/// Parse a size such as `512M`, `2G`, `64k` or `1048576` into bytes
+///
+/// Suffixes are binary (`K` = 1024) and case-insensitive, with an optional
+/// trailing `B` or `iB`.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let upper = size.to_ascii_uppercase();
+    let upper = upper.strip_suffix("IB").or_else(|| upper.strip_suffix('B')).unwrap_or(&upper);
+    let (digits, shift) = match upper.chars().last()? {
+        'K' => (&upper[..upper.len() - 1], 10),
+        'M' => (&upper[..upper.len() - 1], 20),
+        'G' => (&upper[..upper.len() - 1], 30),
+        'T' => (&upper[..upper.len() - 1], 40),
+        _ => (upper, 0),
+    };
+    digits.trim().parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1048576"), Some(1 << 20));
+        assert_eq!(parse_size("64k"), Some(64 << 10));
+        assert_eq!(parse_size("512M"), Some(512 << 20));
+        assert_eq!(parse_size("2GiB"), Some(2 << 30));
+        assert_eq!(parse_size("1 GB"), Some(1 << 30));
+        assert_eq!(parse_size("lots"), None);
+        assert_eq!(parse_size(""), None);
+    }
+}
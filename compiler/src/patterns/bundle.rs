@@ -0,0 +1,444 @@
+//! Portable pattern bundles for sharing libraries between databases
+//!
+//! A bundle carries pattern metadata (templates, test cases, tags) but not
+//! local usage statistics. It is written either as a single JSON document
+//! or as a tar archive with a `manifest.json` and one
+//! `patterns/<ID>.json` per pattern, which diffs well under version control.
+//!
+//! Bundles can be signed with a shared key (HMAC-SHA256). The signature
+//! covers the patterns themselves, not the container, so a JSON bundle and
+//! a tar bundle of the same patterns carry the same signature.
+
+use super::validation::validate_pattern_metadata;
+use super::{Pattern, PatternDatabase, PatternError, PatternId, PatternMetadata, Result};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io::Read;
+use std::str::FromStr;
+
+/// Bundle layout version written by this release
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// Only signature scheme understood
+const SIGNATURE_ALGORITHM: &str = "hmac-sha256";
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Container a bundle is written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BundleFormat {
+    Json,
+    Tar,
+}
+
+impl FromStr for BundleFormat {
+    type Err = PatternError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(Self::Json),
+            "tar" => Ok(Self::Tar),
+            _ => Err(PatternError::ValidationError(format!("Unknown bundle format: {} (expected json or tar)", s))),
+        }
+    }
+}
+
+/// What to do when an imported pattern's ID is already taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep the local pattern
+    Skip,
+    /// Overwrite the local pattern, keeping its usage statistics
+    Replace,
+    /// Import under the next free number in the same category
+    Rename,
+    /// Abort the import before anything is written
+    Fail,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = PatternError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "replace" => Ok(Self::Replace),
+            "rename" => Ok(Self::Rename),
+            "fail" => Ok(Self::Fail),
+            _ => Err(PatternError::ValidationError(format!(
+                "Unknown conflict policy: {} (expected skip, replace, rename or fail)",
+                s
+            ))),
+        }
+    }
+}
+
+/// Keyed signature over a bundle's patterns
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleSignature {
+    pub algorithm: String,
+    /// Fingerprint of the signing key, so a mismatch can name the key
+    pub key_id: String,
+    /// Hex-encoded MAC
+    pub mac: String,
+}
+
+/// Patterns packaged for sharing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatternBundle {
+    pub format_version: u32,
+    /// Release that wrote the bundle
+    pub generator: String,
+    /// Sorted by ID
+    pub patterns: Vec<PatternMetadata>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<BundleSignature>,
+}
+
+/// Tar manifest: the bundle without its patterns, which are separate entries
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    format_version: u32,
+    generator: String,
+    patterns: Vec<PatternId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<BundleSignature>,
+}
+
+/// Exactly the bytes that get signed
+#[derive(Serialize)]
+struct SignedContent<'a> {
+    format_version: u32,
+    generator: &'a str,
+    patterns: &'a [PatternMetadata],
+}
+
+impl PatternBundle {
+    /// Bundle the given patterns, unsigned
+    pub fn new(patterns: impl IntoIterator<Item = PatternMetadata>) -> Self {
+        let mut patterns: Vec<_> = patterns.into_iter().collect();
+        patterns.sort_by(|a, b| a.id.0.cmp(&b.id.0));
+        Self {
+            format_version: BUNDLE_FORMAT_VERSION,
+            generator: format!("fastforth {}", env!("CARGO_PKG_VERSION")),
+            patterns,
+            signature: None,
+        }
+    }
+
+    /// Bundle every pattern in a database
+    pub fn from_database(db: &PatternDatabase) -> Result<Self> {
+        Ok(Self::new(db.list_all()?.into_iter().map(|pattern| pattern.metadata)))
+    }
+
+    /// Sign with a shared key, replacing any previous signature
+    pub fn sign(&mut self, key: &[u8]) -> Result<()> {
+        let mac = self.mac(key)?.finalize().into_bytes();
+        self.signature = Some(BundleSignature {
+            algorithm: SIGNATURE_ALGORITHM.to_string(),
+            key_id: key_id(key),
+            mac: hex::encode(mac),
+        });
+        Ok(())
+    }
+
+    /// Check the signature against a shared key
+    pub fn verify(&self, key: &[u8]) -> Result<()> {
+        let signature = self
+            .signature
+            .as_ref()
+            .ok_or_else(|| invalid("Bundle is not signed".to_string()))?;
+        if signature.algorithm != SIGNATURE_ALGORITHM {
+            return Err(invalid(format!("Unsupported signature algorithm: {}", signature.algorithm)));
+        }
+        if signature.key_id != key_id(key) {
+            return Err(invalid(format!(
+                "Bundle was signed with key {}, not {}",
+                signature.key_id,
+                key_id(key)
+            )));
+        }
+        let expected = hex::decode(&signature.mac)
+            .map_err(|_| invalid("Bundle signature is not valid hex".to_string()))?;
+        self.mac(key)?
+            .verify_slice(&expected)
+            .map_err(|_| invalid("Bundle signature does not match its contents".to_string()))
+    }
+
+    fn mac(&self, key: &[u8]) -> Result<HmacSha256> {
+        let content = serde_json::to_vec(&SignedContent {
+            format_version: self.format_version,
+            generator: &self.generator,
+            patterns: &self.patterns,
+        })?;
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(&content);
+        Ok(mac)
+    }
+
+    /// Serialize in the given container
+    pub fn write(&self, format: BundleFormat) -> Result<Vec<u8>> {
+        match format {
+            BundleFormat::Json => Ok(serde_json::to_vec_pretty(self)?),
+            BundleFormat::Tar => self.write_tar(),
+        }
+    }
+
+    fn write_tar(&self) -> Result<Vec<u8>> {
+        let manifest = Manifest {
+            format_version: self.format_version,
+            generator: self.generator.clone(),
+            patterns: self.patterns.iter().map(|pattern| pattern.id.clone()).collect(),
+            signature: self.signature.clone(),
+        };
+
+        let mut archive = tar::Builder::new(Vec::new());
+        append(&mut archive, MANIFEST_ENTRY, &serde_json::to_vec_pretty(&manifest)?)?;
+        for pattern in &self.patterns {
+            append(&mut archive, &pattern_entry(&pattern.id), &serde_json::to_vec_pretty(pattern)?)?;
+        }
+        Ok(archive.into_inner()?)
+    }
+
+    /// Read a bundle in either container
+    ///
+    /// The plain pattern array written by `PatternDatabase::export_json` is
+    /// also accepted, as an unsigned bundle.
+    pub fn read(bytes: &[u8]) -> Result<Self> {
+        let bundle = if is_tar(bytes) {
+            Self::read_tar(bytes)?
+        } else {
+            match serde_json::from_slice::<serde_json::Value>(bytes)? {
+                serde_json::Value::Array(_) => {
+                    let patterns: Vec<Pattern> = serde_json::from_slice(bytes)?;
+                    Self::new(patterns.into_iter().map(|pattern| pattern.metadata))
+                }
+                value => serde_json::from_value(value)?,
+            }
+        };
+
+        if bundle.format_version > BUNDLE_FORMAT_VERSION {
+            return Err(invalid(format!(
+                "Bundle format v{} is newer than v{} supported by this release",
+                bundle.format_version, BUNDLE_FORMAT_VERSION
+            )));
+        }
+        Ok(bundle)
+    }
+
+    fn read_tar(bytes: &[u8]) -> Result<Self> {
+        let mut manifest: Option<Manifest> = None;
+        let mut entries = std::collections::HashMap::new();
+        for entry in tar::Archive::new(bytes).entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.to_string_lossy().into_owned();
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents)?;
+            if path == MANIFEST_ENTRY {
+                manifest = Some(serde_json::from_slice(&contents)?);
+            } else {
+                entries.insert(path, contents);
+            }
+        }
+
+        let manifest = manifest.ok_or_else(|| invalid(format!("Bundle archive has no {}", MANIFEST_ENTRY)))?;
+        let mut patterns = Vec::with_capacity(manifest.patterns.len());
+        for id in &manifest.patterns {
+            let path = pattern_entry(id);
+            let contents = entries
+                .remove(&path)
+                .ok_or_else(|| invalid(format!("Bundle archive is missing {}", path)))?;
+            patterns.push(serde_json::from_slice(&contents)?);
+        }
+        if let Some(extra) = entries.keys().next() {
+            return Err(invalid(format!("Bundle archive has unlisted entry {}", extra)));
+        }
+
+        Ok(Self {
+            format_version: manifest.format_version,
+            generator: manifest.generator,
+            patterns,
+            signature: manifest.signature,
+        })
+    }
+}
+
+/// Outcome of importing a bundle
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub added: Vec<PatternId>,
+    pub replaced: Vec<PatternId>,
+    /// Bundle ID and the ID it was imported under
+    pub renamed: Vec<(PatternId, PatternId)>,
+    /// Conflicting patterns left as they were
+    pub skipped: Vec<PatternId>,
+    /// Already present with identical metadata
+    pub unchanged: Vec<PatternId>,
+}
+
+/// Import a bundle into a database
+///
+/// Every pattern is validated and every conflict resolved before the first
+/// write, so a rejected import leaves the database untouched. Patterns
+/// identical to the local copy are never treated as conflicts.
+pub fn import_bundle(db: &mut PatternDatabase, bundle: PatternBundle, on_conflict: ConflictPolicy) -> Result<ImportReport> {
+    let mut report = ImportReport::default();
+    let mut taken: HashSet<String> = db.list_all()?.into_iter().map(|pattern| pattern.metadata.id.0).collect();
+    let mut writes = Vec::new();
+
+    for mut metadata in bundle.patterns {
+        validate_pattern_metadata(&metadata)?;
+        let id = metadata.id.clone();
+        let Some(existing) = db.get(&id)? else {
+            taken.insert(id.0.clone());
+            writes.push(Pattern { metadata, usage_count: 0, success_rate: 1.0, quality_score: 1.0 });
+            report.added.push(id);
+            continue;
+        };
+
+        if serde_json::to_value(&existing.metadata)? == serde_json::to_value(&metadata)? {
+            report.unchanged.push(id);
+            continue;
+        }
+
+        match on_conflict {
+            ConflictPolicy::Skip => report.skipped.push(id),
+            ConflictPolicy::Replace => {
+                writes.push(Pattern { metadata, ..existing });
+                report.replaced.push(id);
+            }
+            ConflictPolicy::Rename => {
+                let renamed = next_free_id(&id, &taken)?;
+                taken.insert(renamed.0.clone());
+                metadata.id = renamed.clone();
+                writes.push(Pattern { metadata, usage_count: 0, success_rate: 1.0, quality_score: 1.0 });
+                report.renamed.push((id, renamed));
+            }
+            ConflictPolicy::Fail => {
+                return Err(PatternError::ValidationError(format!(
+                    "Pattern {} already exists with different contents",
+                    id
+                )))
+            }
+        }
+    }
+
+    for pattern in writes {
+        db.insert(pattern)?;
+    }
+    Ok(report)
+}
+
+/// Lowest unused ID sharing `id`'s category prefix
+fn next_free_id(id: &PatternId, taken: &HashSet<String>) -> Result<PatternId> {
+    let prefix = id
+        .as_str()
+        .rsplit_once('_')
+        .map(|(prefix, _)| prefix)
+        .ok_or_else(|| PatternError::InvalidId(id.to_string()))?;
+    (1..=999)
+        .map(|number| PatternId::new(prefix, number))
+        .find(|candidate| !taken.contains(candidate.as_str()))
+        .ok_or_else(|| PatternError::InvalidId(format!("No free ID left in category {}", prefix)))
+}
+
+/// Short fingerprint of a signing key
+fn key_id(key: &[u8]) -> String {
+    hex::encode(&Sha256::digest(key)[..8])
+}
+
+fn pattern_entry(id: &PatternId) -> String {
+    format!("patterns/{}.json", id)
+}
+
+fn append(archive: &mut tar::Builder<Vec<u8>>, path: &str, contents: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_ustar();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive.append_data(&mut header, path, contents)?;
+    Ok(())
+}
+
+/// Whether the bytes start with a ustar header
+fn is_tar(bytes: &[u8]) -> bool {
+    bytes.get(257..262) == Some(b"ustar".as_slice())
+}
+
+fn invalid(message: String) -> PatternError {
+    PatternError::ValidationError(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> PatternDatabase {
+        let mut db = PatternDatabase::open(":memory:").unwrap();
+        db.seed_defaults().unwrap();
+        db
+    }
+
+    fn sample(db: &PatternDatabase, id: &str) -> PatternMetadata {
+        db.get(&PatternId(id.to_string())).unwrap().unwrap().metadata
+    }
+
+    #[test]
+    fn test_round_trip_and_signature_in_both_formats() {
+        let db = database();
+        let mut bundle = PatternBundle::from_database(&db).unwrap();
+        bundle.sign(b"team key").unwrap();
+
+        for format in [BundleFormat::Json, BundleFormat::Tar] {
+            let read = PatternBundle::read(&bundle.write(format).unwrap()).unwrap();
+            assert_eq!(read.patterns.len(), db.count().unwrap());
+            assert!(read.verify(b"team key").is_ok());
+            assert!(read.verify(b"other key").is_err());
+        }
+
+        // Any change to the contents breaks the signature
+        let mut tampered = bundle.clone();
+        tampered.patterns[0].code_template.push_str(" drop");
+        assert!(tampered.verify(b"team key").is_err());
+
+        // Legacy exports read as unsigned bundles
+        let legacy = PatternBundle::read(db.export_json().unwrap().as_bytes()).unwrap();
+        assert_eq!(legacy.patterns.len(), bundle.patterns.len());
+        assert!(legacy.signature.is_none());
+    }
+
+    #[test]
+    fn test_conflict_policies() {
+        let mut changed = sample(&database(), "DUP_TRANSFORM_001");
+        changed.description = "Square, shared by another team".to_string();
+        let fresh = PatternMetadata { id: PatternId("SHARED_001".to_string()), ..changed.clone() };
+        let bundle = PatternBundle::new([changed.clone(), fresh]);
+
+        let mut db = database();
+        let before = db.count().unwrap();
+        assert!(import_bundle(&mut db, bundle.clone(), ConflictPolicy::Fail).is_err());
+        assert_eq!(db.count().unwrap(), before, "a failed import writes nothing");
+
+        let report = import_bundle(&mut db, bundle.clone(), ConflictPolicy::Skip).unwrap();
+        assert_eq!(report.added, vec![PatternId("SHARED_001".to_string())]);
+        assert_eq!(report.skipped, vec![changed.id.clone()]);
+
+        // Identical patterns are not conflicts
+        let same = PatternBundle::new([sample(&db, "SHARED_001")]);
+        let report = import_bundle(&mut db, same, ConflictPolicy::Fail).unwrap();
+        assert_eq!(report.unchanged.len(), 1);
+
+        let report = import_bundle(&mut db, bundle.clone(), ConflictPolicy::Rename).unwrap();
+        let (from, to) = &report.renamed[0];
+        assert_eq!(from, &changed.id);
+        assert!(to.as_str().starts_with("DUP_TRANSFORM_") && to != from);
+        assert_eq!(sample(&db, to.as_str()).description, changed.description);
+
+        import_bundle(&mut db, bundle, ConflictPolicy::Replace).unwrap();
+        assert_eq!(sample(&db, "DUP_TRANSFORM_001").description, changed.description);
+    }
+}
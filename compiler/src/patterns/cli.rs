@@ -1,8 +1,9 @@
 //! CLI commands for pattern management
 
-use super::{PatternDatabase, PatternQuery, PatternId, Result};
+use super::{import_bundle, migrations, AuditEntry, EffectMatch, Outcome, PatternBundle, PatternDatabase, PatternError, PatternQuery, PatternId, Result, SearchHit, SemanticQuery};
 use clap::{Parser, Subcommand};
 use serde_json;
+use std::path::Path;
 
 /// Pattern CLI commands
 #[derive(Debug, Parser)]
@@ -13,7 +14,7 @@ pub struct PatternCli {
     pub command: PatternCommand,
 }
 
-#[derive(Debug, Subcommand)]
+#[derive(Debug, Clone, Subcommand)]
 pub enum PatternCommand {
     /// List all patterns
     List {
@@ -44,10 +45,22 @@ pub enum PatternCommand {
         format: String,
     },
 
-    /// Search patterns
+    /// Search patterns by text, or by stack effect and properties
     Search {
         /// Search query (searches in description, tags, category)
-        query: String,
+        query: Option<String>,
+
+        /// Rank patterns by compatibility with this stack effect, e.g. "( n n -- n )"
+        #[arg(long)]
+        effect: Option<String>,
+
+        /// Property keyword to rank by, e.g. commutative or "O(n)" (repeatable)
+        #[arg(long = "property")]
+        properties: Vec<String>,
+
+        /// Limit results
+        #[arg(long)]
+        limit: Option<usize>,
 
         /// Output format
         #[arg(long, default_value = "table")]
@@ -92,18 +105,82 @@ pub enum PatternCommand {
         seed: bool,
     },
 
-    /// Export patterns to JSON
+    /// Upgrade a database to the current schema
+    Migrate {
+        /// Database path
+        #[arg(long, default_value = "patterns.db")]
+        db: String,
+
+        /// Skip the backup copy taken before migrating
+        #[arg(long)]
+        no_backup: bool,
+
+        /// Only report the current and required schema versions
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Export patterns as a portable bundle
     Export {
         /// Output file
         #[arg(long)]
         output: String,
+
+        /// Bundle format (json, tar)
+        #[arg(long, default_value = "json")]
+        format: String,
+
+        /// Sign the bundle with the shared key in this file
+        #[arg(long)]
+        key_file: Option<String>,
     },
 
-    /// Import patterns from JSON
+    /// Import patterns from a bundle
     Import {
-        /// Input file
+        /// Input file (json or tar bundle)
         #[arg(long)]
         input: String,
+
+        /// On an ID conflict: skip, replace, rename or fail
+        #[arg(long, default_value = "fail")]
+        on_conflict: String,
+
+        /// Verify the bundle's signature with the shared key in this file
+        #[arg(long)]
+        key_file: Option<String>,
+
+        /// Import without verifying a signature
+        #[arg(long, conflicts_with = "key_file")]
+        allow_unsigned: bool,
+    },
+
+    /// Record the outcome of code instantiated from a pattern
+    Feedback {
+        /// Pattern ID
+        id: String,
+
+        /// Verification passed
+        #[arg(long, conflicts_with_all = ["failed", "observed"])]
+        verified: bool,
+
+        /// Verification failed with this error
+        #[arg(long, conflicts_with = "observed")]
+        failed: Option<String>,
+
+        /// Measured performance class, e.g. "O(n²)"
+        #[arg(long)]
+        observed: Option<String>,
+    },
+
+    /// List patterns whose recorded results contradict their metadata
+    Audit {
+        /// Only list patterns scoring below this quality
+        #[arg(long)]
+        below: Option<f64>,
+
+        /// Output format
+        #[arg(long, default_value = "table")]
+        format: String,
     },
 
     /// Show pattern statistics
@@ -114,6 +191,18 @@ pub enum PatternCommand {
     },
 }
 
+/// Run a pattern command against the database at `db_path`
+///
+/// `init` and `migrate` name their own database and must work on one that
+/// cannot be opened yet, so `db_path` is only opened for other commands.
+pub fn run_pattern_command(cmd: PatternCommand, db_path: &Path) -> Result<()> {
+    match cmd {
+        PatternCommand::Init { db, seed } => init_database(&db, seed),
+        PatternCommand::Migrate { db, no_backup, dry_run } => migrate_database(&db, !no_backup, dry_run),
+        cmd => execute_pattern_command(cmd, &mut PatternDatabase::open(db_path)?),
+    }
+}
+
 /// Execute pattern CLI command
 pub fn execute_pattern_command(cmd: PatternCommand, db: &mut PatternDatabase) -> Result<()> {
     match cmd {
@@ -195,28 +284,91 @@ pub fn execute_pattern_command(cmd: PatternCommand, db: &mut PatternDatabase) ->
         }
 
         PatternCommand::Init { db: db_path, seed } => {
-            let mut new_db = PatternDatabase::open(&db_path)?;
-            new_db.init_schema()?;
-
-            if seed {
-                new_db.seed_defaults()?;
-                println!("Database initialized and seeded at: {}", db_path);
-                println!("Total patterns: {}", new_db.count()?);
-            } else {
-                println!("Database initialized at: {}", db_path);
+            init_database(&db_path, seed)?;
+        }
+
+        PatternCommand::Migrate { db: db_path, no_backup, dry_run } => {
+            migrate_database(&db_path, !no_backup, dry_run)?;
+        }
+
+        PatternCommand::Export { output, format, key_file } => {
+            let mut bundle = PatternBundle::from_database(db)?;
+            if let Some(key_file) = &key_file {
+                bundle.sign(&read_key(key_file)?)?;
             }
+            std::fs::write(&output, bundle.write(format.parse()?)?)?;
+            println!(
+                "Exported {} patterns to: {}{}",
+                bundle.patterns.len(),
+                output,
+                if bundle.signature.is_some() { " (signed)" } else { "" }
+            );
         }
 
-        PatternCommand::Export { output } => {
-            let json = db.export_json()?;
-            std::fs::write(&output, json)?;
-            println!("Patterns exported to: {}", output);
+        PatternCommand::Import { input, on_conflict, key_file, allow_unsigned } => {
+            let bundle = PatternBundle::read(&std::fs::read(&input)?)?;
+            match &key_file {
+                Some(key_file) => bundle.verify(&read_key(key_file)?)?,
+                None if allow_unsigned => {}
+                None => {
+                    return Err(PatternError::ValidationError(
+                        "Refusing to import an unverified bundle; pass --key-file or --allow-unsigned".to_string(),
+                    ))
+                }
+            }
+
+            let report = import_bundle(db, bundle, on_conflict.parse()?)?;
+            println!("Imported from: {}", input);
+            println!("  added:     {}", report.added.len());
+            println!("  replaced:  {}", report.replaced.len());
+            println!("  unchanged: {}", report.unchanged.len());
+            println!("  skipped:   {}", report.skipped.len());
+            for (from, to) in &report.renamed {
+                println!("  renamed:   {} -> {}", from, to);
+            }
         }
 
-        PatternCommand::Import { input } => {
-            let json = std::fs::read_to_string(&input)?;
-            let count = db.import_json(&json)?;
-            println!("Imported {} patterns from: {}", count, input);
+        PatternCommand::Feedback { id, verified, failed, observed } => {
+            let outcome = match (verified, failed, observed) {
+                (true, _, _) => Outcome::Verified,
+                (_, Some(error), _) => Outcome::VerificationFailed { error },
+                (_, _, Some(observed)) => Outcome::Measured { observed: observed.parse()? },
+                _ => {
+                    return Err(PatternError::ValidationError(
+                        "Give one of --verified, --failed or --observed".to_string(),
+                    ))
+                }
+            };
+            let pattern_id = PatternId(id);
+            let kind = db.record_feedback(&pattern_id, &outcome)?;
+            let counts = db.feedback_counts(&pattern_id)?;
+            println!(
+                "Recorded {} for {} (quality {:.2} over {} outcomes)",
+                kind.as_str(),
+                pattern_id,
+                counts.quality_score(),
+                counts.total()
+            );
+        }
+
+        PatternCommand::Audit { below, format } => {
+            let mut entries = db.audit()?;
+            if let Some(below) = below {
+                entries.retain(|entry| entry.quality_score < below);
+            }
+
+            match format.as_str() {
+                "json" => {
+                    let json = serde_json::to_string_pretty(&entries)?;
+                    println!("{}", json);
+                }
+                "table" => {
+                    print_audit_table(&entries);
+                }
+                _ => {
+                    eprintln!("Unknown format: {}", format);
+                }
+            }
         }
 
         PatternCommand::Stats { format } => {
@@ -236,15 +388,44 @@ pub fn execute_pattern_command(cmd: PatternCommand, db: &mut PatternDatabase) ->
             }
         }
 
-        PatternCommand::Search { query, format } => {
+        PatternCommand::Search { query, effect, properties, limit, format } if effect.is_some() || !properties.is_empty() => {
+            let mut hits = db.semantic_search(&SemanticQuery {
+                stack_effect: effect,
+                properties,
+                limit: None,
+            })?;
+            if let Some(query) = &query {
+                hits.retain(|hit| matches_text(&hit.pattern, query));
+            }
+            if let Some(limit) = limit {
+                hits.truncate(limit);
+            }
+
+            match format.as_str() {
+                "json" => {
+                    let json = serde_json::to_string_pretty(&hits)?;
+                    println!("{}", json);
+                }
+                "table" => {
+                    print_hits_table(&hits);
+                }
+                _ => {
+                    eprintln!("Unknown format: {}", format);
+                }
+            }
+        }
+
+        PatternCommand::Search { query, limit, format, .. } => {
+            let query = query.ok_or_else(|| {
+                PatternError::ValidationError("Give a search query, --effect or --property".to_string())
+            })?;
             let all_patterns = db.list_all()?;
-            let results: Vec<_> = all_patterns.into_iter()
-                .filter(|p| {
-                    p.metadata.description.to_lowercase().contains(&query.to_lowercase()) ||
-                    p.metadata.tags.iter().any(|t| t.to_lowercase().contains(&query.to_lowercase())) ||
-                    p.metadata.category.to_lowercase().contains(&query.to_lowercase())
-                })
+            let mut results: Vec<_> = all_patterns.into_iter()
+                .filter(|p| matches_text(p, &query))
                 .collect();
+            if let Some(limit) = limit {
+                results.truncate(limit);
+            }
 
             match format.as_str() {
                 "json" => {
@@ -264,23 +445,130 @@ pub fn execute_pattern_command(cmd: PatternCommand, db: &mut PatternDatabase) ->
     Ok(())
 }
 
+fn init_database(db_path: &str, seed: bool) -> Result<()> {
+    let mut new_db = PatternDatabase::open(db_path)?;
+    new_db.init_schema()?;
+
+    if seed {
+        new_db.seed_defaults()?;
+        println!("Database initialized and seeded at: {}", db_path);
+        println!("Total patterns: {}", new_db.count()?);
+    } else {
+        println!("Database initialized at: {}", db_path);
+    }
+    Ok(())
+}
+
+/// Shared signing key, with surrounding whitespace trimmed
+fn read_key(path: &str) -> Result<Vec<u8>> {
+    let key = std::fs::read(path)?;
+    let key = key.trim_ascii();
+    if key.is_empty() {
+        return Err(PatternError::ValidationError(format!("Signing key file is empty: {}", path)));
+    }
+    Ok(key.to_vec())
+}
+
+fn migrate_database(db_path: &str, backup: bool, dry_run: bool) -> Result<()> {
+    let path = Path::new(db_path);
+    let latest = migrations::latest_version();
+
+    if dry_run {
+        let conn = rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let current = migrations::schema_version(&conn)?;
+        println!("Schema version: v{} (this release: v{})", current, latest);
+        for migration in migrations::MIGRATIONS.iter().filter(|m| m.version > current) {
+            println!("  pending v{}: {}", migration.version, migration.description);
+        }
+        return Ok(());
+    }
+
+    let report = migrations::migrate(path, backup)?;
+    if let Some(backup) = &report.backup {
+        println!("Backed up to: {}", backup.display());
+    }
+    if report.from == report.to {
+        println!("{} is up to date (schema v{})", db_path, report.to);
+    } else {
+        println!("Migrated {} from schema v{} to v{}", db_path, report.from, report.to);
+    }
+    Ok(())
+}
+
 fn print_patterns_table(patterns: &[super::Pattern]) {
-    println!("{:<25} {:<20} {:<25} {:<10}", "ID", "Category", "Stack Effect", "Performance");
-    println!("{}", "-".repeat(80));
+    println!("{:<25} {:<20} {:<25} {:<12} {:<7}", "ID", "Category", "Stack Effect", "Performance", "Quality");
+    println!("{}", "-".repeat(92));
 
     for pattern in patterns {
         println!(
-            "{:<25} {:<20} {:<25} {:<10}",
-            pattern.metadata.id,
+            "{:<25} {:<20} {:<25} {:<12} {:<7.2}",
+            pattern.metadata.id.as_str(),
             pattern.metadata.category,
             pattern.metadata.stack_effect,
-            pattern.metadata.performance_class
+            pattern.metadata.performance_class.to_string(),
+            pattern.quality_score
         );
     }
 
     println!("\nTotal: {} patterns", patterns.len());
 }
 
+fn print_audit_table(entries: &[AuditEntry]) {
+    println!("{:<25} {:<8} {:<9} {:<10} {:<20} Last Error", "ID", "Quality", "Failures", "Declared", "Observed");
+    println!("{}", "-".repeat(100));
+
+    for entry in entries {
+        let observed = match &entry.worst_observed {
+            Some(class) => format!("{} ({}x)", class, entry.counts.performance_mismatch),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:<25} {:<8.2} {:<9} {:<10} {:<20} {}",
+            entry.id.as_str(),
+            entry.quality_score,
+            entry.counts.verification_failed,
+            entry.declared_class.to_string(),
+            observed,
+            entry.last_error.as_deref().unwrap_or("-")
+        );
+    }
+
+    println!("\nTotal: {} patterns contradicted by recorded results", entries.len());
+}
+
+fn print_hits_table(hits: &[SearchHit]) {
+    println!("{:<25} {:<25} {:<8} {:<14} Properties", "ID", "Stack Effect", "Score", "Match");
+    println!("{}", "-".repeat(90));
+
+    for hit in hits {
+        let effect_match = match hit.effect_match {
+            Some(EffectMatch::Exact) => "exact",
+            Some(EffectMatch::MoreGeneral) => "more general",
+            Some(EffectMatch::MoreSpecific) => "more specific",
+            Some(EffectMatch::Compatible) => "compatible",
+            None => "-",
+        };
+        println!(
+            "{:<25} {:<25} {:<8.2} {:<14} {}",
+            hit.pattern.metadata.id.as_str(),
+            hit.pattern.metadata.stack_effect,
+            hit.score,
+            effect_match,
+            hit.matched_properties.join(", ")
+        );
+    }
+
+    println!("\nTotal: {} patterns", hits.len());
+}
+
+/// Case-insensitive substring match on description, tags and category
+fn matches_text(pattern: &super::Pattern, query: &str) -> bool {
+    let query = query.to_lowercase();
+    pattern.metadata.description.to_lowercase().contains(&query)
+        || pattern.metadata.tags.iter().any(|t| t.to_lowercase().contains(&query))
+        || pattern.metadata.category.to_lowercase().contains(&query)
+}
+
 fn print_pattern_details(pattern: &super::Pattern) {
     println!("Pattern ID: {}", pattern.metadata.id);
     println!("Category: {}", pattern.metadata.category);
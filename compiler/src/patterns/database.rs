@@ -1,6 +1,9 @@
 //! SQLite database for persistent pattern storage
 
-use super::{PatternId, PatternMetadata, Pattern, Result, PatternError, PerformanceClass, TestCase};
+use super::search::{self, SearchHit, SemanticQuery};
+use super::feedback::{AuditEntry, FeedbackCounts, FeedbackKind, Outcome};
+use super::{migrations, PatternId, PatternMetadata, Pattern, Result, PatternError, PerformanceClass, TestCase};
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 use std::sync::RwLock;
@@ -26,8 +29,11 @@ pub struct PatternQuery {
 }
 
 /// SQLite-based pattern database
+///
+/// Every pattern is loaded into memory on open; writes go to both.
 pub struct PatternDatabase {
     db_path: std::path::PathBuf,
+    conn: Connection,
     // Phase 1 optimization: Use FxHashMap for faster hashing
     // FxHashMap is 2-3x faster than std HashMap for small keys
     patterns: FxHashMap<PatternId, Pattern>,
@@ -35,35 +41,276 @@ pub struct PatternDatabase {
 
 impl PatternDatabase {
     /// Create or open a pattern database
+    ///
+    /// A new database gets the current schema. An existing one must already
+    /// be at the current schema version; see [`migrations::migrate`].
+    /// `:memory:` opens a private in-memory database.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
         let db_path = path.as_ref().to_path_buf();
+        let mut conn = Connection::open(&db_path)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
 
-        // In a real implementation, we would:
-        // 1. Open SQLite connection
-        // 2. Run schema migrations
-        // 3. Create indexes
+        if migrations::schema_version(&conn)? == 0 {
+            migrations::apply_pending(&mut conn)?;
+        }
+        migrations::check_version(&conn, &db_path)?;
 
-        Ok(Self {
+        let mut db = Self {
             db_path,
+            conn,
             patterns: FxHashMap::default(),
-        })
+        };
+        db.load()?;
+        Ok(db)
     }
 
     /// Initialize database schema
-    pub fn init_schema(&self) -> Result<()> {
-        // In a real implementation, this would execute:
-        // CREATE TABLE patterns (...)
-        // See seed.sql for the schema
+    pub fn init_schema(&mut self) -> Result<()> {
+        migrations::apply_pending(&mut self.conn)?;
         Ok(())
     }
 
-    /// Insert a pattern
-    pub fn insert(&mut self, pattern: Pattern) -> Result<()> {
-        let id = pattern.metadata.id.clone();
+    /// Path the database was opened from
+    pub fn path(&self) -> &Path {
+        &self.db_path
+    }
+
+    /// Insert a pattern, replacing any pattern with the same ID
+    ///
+    /// Recorded feedback is kept and the quality score recomputed from it.
+    pub fn insert(&mut self, mut pattern: Pattern) -> Result<()> {
+        let meta = &pattern.metadata;
+        let tx = self.conn.transaction()?;
+        for table in ["pattern_tags", "pattern_test_cases", "template_variables"] {
+            tx.execute(&format!("DELETE FROM {} WHERE pattern_id = ?1", table), params![meta.id.0])?;
+        }
+        tx.execute(
+            "INSERT INTO patterns (id, category, stack_effect, code_template, performance_class,
+                description, created_at, updated_at, usage_count, success_rate)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+             ON CONFLICT (id) DO UPDATE SET
+                category = excluded.category, stack_effect = excluded.stack_effect,
+                code_template = excluded.code_template, performance_class = excluded.performance_class,
+                description = excluded.description, created_at = excluded.created_at,
+                updated_at = excluded.updated_at, usage_count = excluded.usage_count,
+                success_rate = excluded.success_rate",
+            params![
+                meta.id.0,
+                meta.category,
+                meta.stack_effect,
+                meta.code_template,
+                meta.performance_class.to_string(),
+                meta.description,
+                meta.created_at,
+                meta.updated_at,
+                pattern.usage_count as i64,
+                pattern.success_rate,
+            ],
+        )?;
+        for tag in &meta.tags {
+            tx.execute("INSERT OR IGNORE INTO pattern_tags (pattern_id, tag) VALUES (?1, ?2)", params![meta.id.0, tag])?;
+        }
+        for test in &meta.test_cases {
+            tx.execute(
+                "INSERT INTO pattern_test_cases (pattern_id, input_values, output_values, description)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    meta.id.0,
+                    serde_json::to_string(&test.input)?,
+                    serde_json::to_string(&test.output)?,
+                    test.description,
+                ],
+            )?;
+        }
+        for variable in &meta.template_variables {
+            tx.execute(
+                "INSERT OR IGNORE INTO template_variables (pattern_id, variable_name) VALUES (?1, ?2)",
+                params![meta.id.0, variable],
+            )?;
+        }
+        tx.commit()?;
+
+        let id = meta.id.clone();
+        pattern.quality_score = self.feedback_counts(&id)?.quality_score();
+        PATTERN_CACHE.write().unwrap().pop(&id.0);
         self.patterns.insert(id, pattern);
         Ok(())
     }
 
+    /// Record what happened to code instantiated from a pattern
+    ///
+    /// Measurements are compared with the declared performance class; the
+    /// returned kind says how the outcome was classified.
+    pub fn record_feedback(&mut self, id: &PatternId, outcome: &Outcome) -> Result<FeedbackKind> {
+        let declared = match self.patterns.get(id) {
+            Some(pattern) => pattern.metadata.performance_class.clone(),
+            None => return Err(PatternError::NotFound(id.to_string())),
+        };
+        let kind = FeedbackKind::of(outcome, &declared);
+        let (observed, detail) = match outcome {
+            Outcome::Verified => (None, None),
+            Outcome::VerificationFailed { error } => (None, Some(error.clone())),
+            Outcome::Measured { observed } => (Some(observed.to_string()), None),
+        };
+
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO pattern_feedback (pattern_id, kind, observed_class, detail, recorded_at)
+             VALUES (?1, ?2, ?3, ?4, datetime('now'))",
+            params![id.0, kind.as_str(), observed, detail],
+        )?;
+        let counts = feedback_counts(&tx, id)?;
+        tx.execute(
+            "UPDATE patterns SET usage_count = usage_count + 1, success_rate = ?2 WHERE id = ?1",
+            params![id.0, counts.success_rate()],
+        )?;
+        tx.commit()?;
+
+        PATTERN_CACHE.write().unwrap().pop(&id.0);
+        if let Some(pattern) = self.patterns.get_mut(id) {
+            pattern.usage_count += 1;
+            pattern.success_rate = counts.success_rate();
+            pattern.quality_score = counts.quality_score();
+        }
+        Ok(kind)
+    }
+
+    /// Outcomes recorded for a pattern
+    pub fn feedback_counts(&self, id: &PatternId) -> Result<FeedbackCounts> {
+        feedback_counts(&self.conn, id)
+    }
+
+    /// Patterns whose recorded results contradict their metadata, worst first
+    pub fn audit(&self) -> Result<Vec<AuditEntry>> {
+        let mut entries = Vec::new();
+        for pattern in self.patterns.values() {
+            let id = &pattern.metadata.id;
+            let counts = self.feedback_counts(id)?;
+            if counts.contradictions() == 0 {
+                continue;
+            }
+
+            let worst_observed = self
+                .conn
+                .prepare("SELECT observed_class FROM pattern_feedback WHERE pattern_id = ?1 AND kind = ?2")?
+                .query_map(params![id.0, FeedbackKind::PerformanceMismatch.as_str()], |row| row.get::<_, String>(0))?
+                .map(|class| class?.parse::<PerformanceClass>())
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .max();
+            let last_error = self
+                .conn
+                .query_row(
+                    "SELECT detail FROM pattern_feedback WHERE pattern_id = ?1 AND kind = ?2 ORDER BY id DESC LIMIT 1",
+                    params![id.0, FeedbackKind::VerificationFailed.as_str()],
+                    |row| row.get::<_, Option<String>>(0),
+                )
+                .optional()?
+                .flatten();
+
+            entries.push(AuditEntry {
+                id: id.clone(),
+                declared_class: pattern.metadata.performance_class.clone(),
+                counts,
+                quality_score: counts.quality_score(),
+                worst_observed,
+                last_error,
+            });
+        }
+
+        entries.sort_by(|a, b| a.quality_score.total_cmp(&b.quality_score).then_with(|| a.id.0.cmp(&b.id.0)));
+        Ok(entries)
+    }
+
+    /// Read every stored pattern into memory
+    fn load(&mut self) -> Result<()> {
+        let ids: Vec<String> = self
+            .conn
+            .prepare("SELECT id FROM patterns ORDER BY id")?
+            .query_map([], |row| row.get(0))?
+            .collect::<std::result::Result<_, _>>()?;
+        for id in ids {
+            if let Some(pattern) = self.read_pattern(&id)? {
+                self.patterns.insert(pattern.metadata.id.clone(), pattern);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_pattern(&self, id: &str) -> Result<Option<Pattern>> {
+        let row = self
+            .conn
+            .query_row(
+                "SELECT category, stack_effect, code_template, performance_class, description,
+                    created_at, updated_at, usage_count, success_rate
+                 FROM patterns WHERE id = ?1",
+                params![id],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, String>(4)?,
+                        row.get::<_, String>(5)?,
+                        row.get::<_, String>(6)?,
+                        row.get::<_, i64>(7)?,
+                        row.get::<_, f64>(8)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((category, stack_effect, code_template, performance_class, description, created_at, updated_at, usage_count, success_rate)) = row else {
+            return Ok(None);
+        };
+
+        let tags = self
+            .conn
+            .prepare("SELECT tag FROM pattern_tags WHERE pattern_id = ?1 ORDER BY rowid")?
+            .query_map(params![id], |row| row.get(0))?
+            .collect::<std::result::Result<_, _>>()?;
+        let template_variables = self
+            .conn
+            .prepare("SELECT variable_name FROM template_variables WHERE pattern_id = ?1 ORDER BY rowid")?
+            .query_map(params![id], |row| row.get(0))?
+            .collect::<std::result::Result<_, _>>()?;
+
+        let mut test_cases = Vec::new();
+        let mut statement = self.conn.prepare(
+            "SELECT input_values, output_values, description FROM pattern_test_cases WHERE pattern_id = ?1 ORDER BY id",
+        )?;
+        let rows = statement.query_map(params![id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?))
+        })?;
+        for row in rows {
+            let (input, output, description) = row?;
+            test_cases.push(TestCase {
+                input: serde_json::from_str(&input)?,
+                output: serde_json::from_str(&output)?,
+                description,
+            });
+        }
+
+        Ok(Some(Pattern {
+            metadata: PatternMetadata {
+                id: PatternId(id.to_string()),
+                category,
+                stack_effect,
+                code_template,
+                performance_class: performance_class.parse()?,
+                test_cases,
+                description,
+                tags,
+                template_variables,
+                created_at,
+                updated_at,
+            },
+            usage_count: usage_count as u64,
+            success_rate,
+            quality_score: self.feedback_counts(&PatternId(id.to_string()))?.quality_score(),
+        }))
+    }
+
     /// Get a pattern by ID (with LRU cache optimization)
     pub fn get(&self, id: &PatternId) -> Result<Option<Pattern>> {
         let cache_key = id.0.clone();
@@ -120,6 +367,11 @@ impl PatternDatabase {
         Ok(results)
     }
 
+    /// Rank patterns by stack effect compatibility and properties
+    pub fn semantic_search(&self, query: &SemanticQuery) -> Result<Vec<SearchHit>> {
+        search::semantic_search(self.patterns.values().cloned(), query)
+    }
+
     /// List all patterns
     pub fn list_all(&self) -> Result<Vec<Pattern>> {
         Ok(self.patterns.values().cloned().collect())
@@ -160,6 +412,17 @@ impl PatternDatabase {
     }
 }
 
+fn feedback_counts(conn: &Connection, id: &PatternId) -> Result<FeedbackCounts> {
+    let mut counts = FeedbackCounts::default();
+    let mut statement = conn.prepare("SELECT kind, COUNT(*) FROM pattern_feedback WHERE pattern_id = ?1 GROUP BY kind")?;
+    let rows = statement.query_map(params![id.0], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))?;
+    for row in rows {
+        let (kind, count) = row?;
+        counts.add(kind.parse()?, count as u64);
+    }
+    Ok(counts)
+}
+
 /// Create default pattern library (20+ patterns)
 fn create_default_patterns() -> Vec<Pattern> {
     vec![
@@ -469,6 +732,7 @@ fn create_pattern(
         },
         usage_count: 0,
         success_rate: 1.0,
+        quality_score: 1.0,
     }
 }
 
@@ -498,20 +762,75 @@ mod tests {
 
     #[test]
     fn test_database_creation() {
-        let db = PatternDatabase::open("test.db").unwrap();
+        let db = PatternDatabase::open(":memory:").unwrap();
         assert_eq!(db.count().unwrap(), 0);
     }
 
     #[test]
     fn test_seed_defaults() {
-        let mut db = PatternDatabase::open("test.db").unwrap();
+        let mut db = PatternDatabase::open(":memory:").unwrap();
         db.seed_defaults().unwrap();
         assert!(db.count().unwrap() >= 20);
     }
 
+    #[test]
+    fn test_patterns_persist_across_opens() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("patterns.db");
+
+        let mut db = PatternDatabase::open(&path).unwrap();
+        db.seed_defaults().unwrap();
+        let count = db.count().unwrap();
+        drop(db);
+
+        let db = PatternDatabase::open(&path).unwrap();
+        assert_eq!(db.count().unwrap(), count);
+        let pattern = db.get(&PatternId("CONDITIONAL_001".to_string())).unwrap().unwrap();
+        assert_eq!(pattern.metadata.performance_class, PerformanceClass::Constant);
+        assert_eq!(pattern.metadata.tags, vec!["arithmetic", "conditional", "abs"]);
+        assert_eq!(pattern.metadata.test_cases[1].input, vec![-5]);
+    }
+
+    #[test]
+    fn test_feedback_persists_and_survives_reinsert() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("patterns.db");
+        let id = PatternId("CONDITIONAL_001".to_string());
+
+        let mut db = PatternDatabase::open(&path).unwrap();
+        db.seed_defaults().unwrap();
+        let failed = Outcome::VerificationFailed { error: "wrong result".to_string() };
+        assert_eq!(db.record_feedback(&id, &failed).unwrap(), FeedbackKind::VerificationFailed);
+        assert!(db.record_feedback(&PatternId("MISSING_001".to_string()), &Outcome::Verified).is_err());
+
+        // Replacing the pattern keeps what was learned about it
+        let pattern = db.get(&id).unwrap().unwrap();
+        db.insert(pattern).unwrap();
+        drop(db);
+
+        let db = PatternDatabase::open(&path).unwrap();
+        let pattern = db.get(&id).unwrap().unwrap();
+        assert_eq!(pattern.quality_score, 0.5);
+        assert_eq!(db.feedback_counts(&id).unwrap().verification_failed, 1);
+        assert_eq!(db.audit().unwrap()[0].id, id);
+    }
+
+    #[test]
+    fn test_open_refuses_newer_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("patterns.db");
+        drop(PatternDatabase::open(&path).unwrap());
+
+        Connection::open(&path)
+            .unwrap()
+            .execute("INSERT INTO schema_version (version, applied_at) VALUES (999, 'later')", [])
+            .unwrap();
+        assert!(PatternDatabase::open(&path).is_err());
+    }
+
     #[test]
     fn test_query_by_category() {
-        let mut db = PatternDatabase::open("test.db").unwrap();
+        let mut db = PatternDatabase::open(":memory:").unwrap();
         db.seed_defaults().unwrap();
 
         let query = PatternQuery {
@@ -0,0 +1,199 @@
+//! Quality feedback from code instantiated from patterns
+//!
+//! Every time code built from a pattern is verified or benchmarked, the
+//! outcome is recorded against its `PatternId`. Patterns whose real-world
+//! results contradict their metadata (instances that fail verification, or
+//! run in a worse complexity class than declared) lose quality score and
+//! show up in `fastforth pattern audit`.
+
+use super::{PatternError, PatternId, PerformanceClass, Result};
+use serde::{Deserialize, Serialize};
+
+/// What happened to one instance of a pattern
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum Outcome {
+    /// The instance passed verification
+    Verified,
+    /// The instance failed verification
+    VerificationFailed { error: String },
+    /// The instance was measured running in this complexity class
+    Measured { observed: PerformanceClass },
+}
+
+/// How an outcome is stored, once compared with the pattern's metadata
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedbackKind {
+    Verified,
+    VerificationFailed,
+    /// Measured within the declared class
+    PerformanceConfirmed,
+    /// Measured in a worse class than declared
+    PerformanceMismatch,
+}
+
+impl FeedbackKind {
+    /// Classify an outcome against the declared performance class
+    pub fn of(outcome: &Outcome, declared: &PerformanceClass) -> Self {
+        match outcome {
+            Outcome::Verified => Self::Verified,
+            Outcome::VerificationFailed { .. } => Self::VerificationFailed,
+            Outcome::Measured { observed } if observed > declared => Self::PerformanceMismatch,
+            Outcome::Measured { .. } => Self::PerformanceConfirmed,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Verified => "verified",
+            Self::VerificationFailed => "verification_failed",
+            Self::PerformanceConfirmed => "performance_confirmed",
+            Self::PerformanceMismatch => "performance_mismatch",
+        }
+    }
+}
+
+impl std::str::FromStr for FeedbackKind {
+    type Err = PatternError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "verified" => Ok(Self::Verified),
+            "verification_failed" => Ok(Self::VerificationFailed),
+            "performance_confirmed" => Ok(Self::PerformanceConfirmed),
+            "performance_mismatch" => Ok(Self::PerformanceMismatch),
+            _ => Err(PatternError::DatabaseError(format!("Unknown feedback kind: {}", s))),
+        }
+    }
+}
+
+/// Recorded outcomes for one pattern
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeedbackCounts {
+    pub verified: u64,
+    pub verification_failed: u64,
+    pub performance_confirmed: u64,
+    pub performance_mismatch: u64,
+}
+
+impl FeedbackCounts {
+    pub fn add(&mut self, kind: FeedbackKind, count: u64) {
+        match kind {
+            FeedbackKind::Verified => self.verified += count,
+            FeedbackKind::VerificationFailed => self.verification_failed += count,
+            FeedbackKind::PerformanceConfirmed => self.performance_confirmed += count,
+            FeedbackKind::PerformanceMismatch => self.performance_mismatch += count,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.verified + self.verification_failed + self.performance_confirmed + self.performance_mismatch
+    }
+
+    /// Outcomes that contradict the pattern's metadata
+    pub fn contradictions(&self) -> u64 {
+        self.verification_failed + self.performance_mismatch
+    }
+
+    /// Share of outcomes that agree with the metadata, between 0 and 1
+    ///
+    /// Counted as if one good outcome had already been seen, so a pattern
+    /// without feedback scores 1.0 and a single failure does not sink it
+    /// to zero.
+    pub fn quality_score(&self) -> f64 {
+        (self.total() - self.contradictions() + 1) as f64 / (self.total() + 1) as f64
+    }
+
+    /// Share of verifications that passed; 1.0 if none were recorded
+    pub fn success_rate(&self) -> f64 {
+        let attempts = self.verified + self.verification_failed;
+        if attempts == 0 {
+            1.0
+        } else {
+            self.verified as f64 / attempts as f64
+        }
+    }
+}
+
+/// A pattern whose recorded results contradict its metadata
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: PatternId,
+    pub declared_class: PerformanceClass,
+    pub counts: FeedbackCounts,
+    pub quality_score: f64,
+    /// Worst complexity class measured, if worse than declared
+    pub worst_observed: Option<PerformanceClass>,
+    /// Most recent verification error
+    pub last_error: Option<String>,
+}
+
+/// Complexity class that best fits timings taken at several input sizes
+///
+/// `samples` are `(input size, seconds)` pairs. The class is read off the
+/// slope of a log-log fit, which is crude but enough to tell an O(n)
+/// pattern that actually runs in O(n²). Needs at least two distinct
+/// sizes with positive timings.
+pub fn estimate_class(samples: &[(f64, f64)]) -> Option<PerformanceClass> {
+    let points: Vec<(f64, f64)> = samples
+        .iter()
+        .filter(|(size, seconds)| *size > 1.0 && *seconds > 0.0)
+        .map(|(size, seconds)| (size.ln(), seconds.ln()))
+        .collect();
+    if points.len() < 2 {
+        return None;
+    }
+
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let spread: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    if spread == 0.0 {
+        return None;
+    }
+    let slope = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum::<f64>() / spread;
+
+    Some(match slope {
+        s if s < 0.15 => PerformanceClass::Constant,
+        s if s < 0.6 => PerformanceClass::Logarithmic,
+        s if s < 1.1 => PerformanceClass::Linear,
+        s if s < 1.5 => PerformanceClass::Linearithmic,
+        s if s < 2.5 => PerformanceClass::Quadratic,
+        _ => PerformanceClass::Exponential,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quality_score() {
+        let mut counts = FeedbackCounts::default();
+        assert_eq!(counts.quality_score(), 1.0);
+
+        counts.add(FeedbackKind::Verified, 3);
+        counts.add(FeedbackKind::VerificationFailed, 1);
+        assert_eq!(counts.quality_score(), 0.8);
+        assert_eq!(counts.success_rate(), 0.75);
+
+        counts.add(FeedbackKind::PerformanceMismatch, 5);
+        assert_eq!(counts.contradictions(), 6);
+        assert!(counts.quality_score() < 0.5);
+        assert_eq!(counts.success_rate(), 0.75);
+    }
+
+    #[test]
+    fn test_measurements_are_compared_with_declared_class() {
+        let quadratic = Outcome::Measured { observed: PerformanceClass::Quadratic };
+        assert_eq!(FeedbackKind::of(&quadratic, &PerformanceClass::Linear), FeedbackKind::PerformanceMismatch);
+        assert_eq!(FeedbackKind::of(&quadratic, &PerformanceClass::Exponential), FeedbackKind::PerformanceConfirmed);
+
+        let linear: Vec<_> = [100.0, 1000.0, 10000.0].iter().map(|&n| (n, n * 1e-6)).collect();
+        assert_eq!(estimate_class(&linear), Some(PerformanceClass::Linear));
+        let quadratic: Vec<_> = [100.0, 1000.0, 10000.0].iter().map(|&n| (n, n * n * 1e-9)).collect();
+        assert_eq!(estimate_class(&quadratic), Some(PerformanceClass::Quadratic));
+        assert_eq!(estimate_class(&[(100.0, 1.0)]), None);
+    }
+}
@@ -1,6 +1,7 @@
 //! HTTP API for pattern queries
 
-use super::{PatternDatabase, PatternQuery, PatternId, Result, PatternError};
+use super::{PatternDatabase, PatternQuery, PatternId, Result, PatternError, SemanticQuery};
+use crate::access::{AccessControl, AccessPolicy};
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
 
@@ -10,6 +11,8 @@ pub struct PatternApiConfig {
     pub host: String,
     pub port: u16,
     pub max_results: usize,
+    /// API keys, rate limit and request size limit
+    pub access: AccessPolicy,
 }
 
 impl Default for PatternApiConfig {
@@ -18,6 +21,7 @@ impl Default for PatternApiConfig {
             host: "127.0.0.1".to_string(),
             port: 8080,
             max_results: 100,
+            access: AccessPolicy::default(),
         }
     }
 }
@@ -26,17 +30,27 @@ impl Default for PatternApiConfig {
 pub struct PatternServer {
     config: PatternApiConfig,
     database: Arc<Mutex<PatternDatabase>>,
+    access: AccessControl,
 }
 
 impl PatternServer {
     /// Create a new pattern server
     pub fn new(config: PatternApiConfig, database: PatternDatabase) -> Self {
         Self {
+            access: AccessControl::new(config.access.clone()),
             config,
             database: Arc::new(Mutex::new(database)),
         }
     }
 
+    /// Admit or refuse a request before it reaches a handler
+    ///
+    /// `credential` is the presented API key (see [`crate::access::credential`])
+    /// and `client` the caller's address.
+    pub fn authorize(&self, credential: Option<&str>, client: &str, body_bytes: usize) -> Result<()> {
+        Ok(self.access.check(credential, client, body_bytes)?)
+    }
+
     /// Get server address
     pub fn address(&self) -> String {
         format!("{}:{}", self.config.host, self.config.port)
@@ -45,10 +59,14 @@ impl PatternServer {
     /// Start the server (mock implementation)
     pub async fn start(&self) -> Result<()> {
         println!("Pattern API server starting on {}", self.address());
+        if self.access.requires_key() {
+            println!("API keys required ({} configured)", self.config.access.api_keys.len());
+        }
         println!("Available endpoints:");
         println!("  GET  /patterns - List all patterns");
         println!("  GET  /patterns/:id - Get pattern by ID");
         println!("  POST /patterns/query - Query patterns");
+        println!("  POST /patterns/search - Rank patterns by stack effect and properties");
         println!("  GET  /patterns/categories - List categories");
         println!("  GET  /health - Health check");
 
@@ -149,6 +167,21 @@ pub mod handlers {
         })
     }
 
+    /// Rank patterns by stack effect and properties
+    pub async fn search_patterns(
+        db: Arc<Mutex<PatternDatabase>>,
+        query: SemanticQuery
+    ) -> Result<PatternResponse> {
+        let db = db.lock().unwrap();
+        let hits = db.semantic_search(&query)?;
+
+        Ok(PatternResponse {
+            success: true,
+            data: serde_json::to_value(hits)?,
+            error: None,
+        })
+    }
+
     /// Health check
     pub async fn health_check(db: Arc<Mutex<PatternDatabase>>) -> Result<HealthResponse> {
         let db = db.lock().unwrap();
@@ -170,12 +203,22 @@ mod tests {
     #[test]
     fn test_server_creation() {
         let config = PatternApiConfig::default();
-        let db = PatternDatabase::open("test.db").unwrap();
+        let db = PatternDatabase::open(":memory:").unwrap();
         let server = PatternServer::new(config, db);
 
         assert_eq!(server.address(), "127.0.0.1:8080");
     }
 
+    #[test]
+    fn test_authorize_requires_configured_key() {
+        let mut config = PatternApiConfig::default();
+        config.access.api_keys = vec!["agent-key".to_string()];
+        let server = PatternServer::new(config, PatternDatabase::open(":memory:").unwrap());
+
+        assert!(matches!(server.authorize(None, "10.0.0.1", 0), Err(PatternError::AccessDenied(_))));
+        assert!(server.authorize(Some("agent-key"), "10.0.0.1", 0).is_ok());
+    }
+
     #[test]
     fn test_query_request_conversion() {
         let req = QueryRequest {
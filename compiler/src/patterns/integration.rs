@@ -1,7 +1,7 @@
 //! Compiler integration for pattern validation
 
-use super::{PatternId, Result, PatternError};
-use super::validation::{extract_pattern_id_from_code, validate_pattern_in_code};
+use super::{Outcome, PatternDatabase, PatternId, Result, PatternError};
+use super::validation::{extract_pattern_id_from_code, extract_pattern_ids_from_code, validate_pattern_in_code};
 
 /// Pattern validation during compilation
 pub struct PatternValidator {
@@ -32,6 +32,22 @@ impl PatternValidator {
     pub fn validate_pattern_match(&self, code: &str, expected_id: &PatternId) -> Result<()> {
         validate_pattern_in_code(code, expected_id)
     }
+
+    /// Record an outcome for code against every pattern it names
+    ///
+    /// IDs not in the database are ignored; the ones recorded are returned.
+    /// Outcomes are per source, so a failure in code naming several
+    /// patterns counts against each of them.
+    pub fn record_outcome(&self, db: &mut PatternDatabase, code: &str, outcome: &Outcome) -> Result<Vec<PatternId>> {
+        let mut recorded = Vec::new();
+        for id in extract_pattern_ids_from_code(code) {
+            if db.get(&id)?.is_some() {
+                db.record_feedback(&id, outcome)?;
+                recorded.push(id);
+            }
+        }
+        Ok(recorded)
+    }
 }
 
 #[cfg(test)]
@@ -50,6 +66,37 @@ mod tests {
         assert_eq!(result, Some(PatternId("DUP_TRANSFORM_001".to_string())));
     }
 
+    #[test]
+    fn test_outcomes_are_recorded_against_named_patterns() {
+        let mut db = PatternDatabase::open(":memory:").unwrap();
+        db.seed_defaults().unwrap();
+        let validator = PatternValidator::new(false);
+        let code = r#"
+\ PATTERN: DUP_TRANSFORM_001
+: square ( n -- n² ) dup * ;
+\ PATTERN: UNKNOWN_001
+\ PATTERN: DUP_TRANSFORM_001
+"#;
+
+        let failed = Outcome::VerificationFailed { error: "stack underflow".to_string() };
+        let recorded = validator.record_outcome(&mut db, code, &failed).unwrap();
+        assert_eq!(recorded, vec![PatternId("DUP_TRANSFORM_001".to_string())]);
+
+        let slow = Outcome::Measured { observed: crate::patterns::PerformanceClass::Quadratic };
+        validator.record_outcome(&mut db, code, &slow).unwrap();
+
+        let audit = db.audit().unwrap();
+        assert_eq!(audit.len(), 1);
+        assert_eq!(audit[0].last_error.as_deref(), Some("stack underflow"));
+        assert_eq!(audit[0].worst_observed, Some(crate::patterns::PerformanceClass::Quadratic));
+        assert!((audit[0].quality_score - 1.0 / 3.0).abs() < 1e-9);
+
+        let pattern = db.get(&audit[0].id).unwrap().unwrap();
+        assert_eq!(pattern.usage_count, 2);
+        assert_eq!(pattern.success_rate, 0.0);
+        assert_eq!(pattern.quality_score, audit[0].quality_score);
+    }
+
     #[test]
     fn test_strict_mode_requires_pattern() {
         let validator = PatternValidator::new(true);
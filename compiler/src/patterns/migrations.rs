@@ -0,0 +1,201 @@
+//! Schema versioning for the pattern database
+//!
+//! Migrations are forward-only and numbered from 1. Each runs in its own
+//! transaction and is recorded in the `schema_version` table. A database
+//! written by a newer release is refused rather than guessed at.
+
+use super::{PatternError, Result};
+use rusqlite::{params, Connection};
+use std::path::{Path, PathBuf};
+
+/// One schema change
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+/// Every migration, in order
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Initial pattern schema",
+        sql: include_str!("../../runtime/schema.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "Record verification and performance feedback per pattern",
+        sql: "CREATE TABLE pattern_feedback (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                pattern_id TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                observed_class TEXT,
+                detail TEXT,
+                recorded_at TEXT NOT NULL,
+                FOREIGN KEY (pattern_id) REFERENCES patterns(id) ON DELETE CASCADE
+            );
+            CREATE INDEX idx_pattern_feedback_pattern ON pattern_feedback(pattern_id);",
+    },
+];
+
+/// Schema version this release reads and writes
+pub fn latest_version() -> u32 {
+    latest_in(MIGRATIONS)
+}
+
+fn latest_in(migrations: &[Migration]) -> u32 {
+    migrations.last().map_or(0, |migration| migration.version)
+}
+
+/// Outcome of bringing a database up to date
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub from: u32,
+    pub to: u32,
+    /// Copy of the database taken before migrating
+    pub backup: Option<PathBuf>,
+}
+
+/// Schema version of a database; 0 if it has never been migrated
+pub fn schema_version(conn: &Connection) -> Result<u32> {
+    let tracked: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !tracked {
+        return Ok(0);
+    }
+    Ok(conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))?)
+}
+
+/// Fail unless the database is at the version this release expects
+pub fn check_version(conn: &Connection, path: &Path) -> Result<()> {
+    let version = schema_version(conn)?;
+    let latest = latest_version();
+    if version > latest {
+        return Err(newer_than_supported(path, version, latest));
+    }
+    if version < latest {
+        return Err(PatternError::DatabaseError(format!(
+            "{} uses pattern schema v{}, this release needs v{}; run `fastforth pattern migrate --db {}`",
+            path.display(),
+            version,
+            latest,
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Apply every migration newer than the database, returning the new version
+pub fn apply_pending(conn: &mut Connection) -> Result<u32> {
+    apply(conn, MIGRATIONS)
+}
+
+fn apply(conn: &mut Connection, migrations: &[Migration]) -> Result<u32> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            applied_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    let current = schema_version(conn)?;
+    let mut version = current;
+    for migration in migrations.iter().filter(|migration| migration.version > current) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration.sql)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, applied_at) VALUES (?1, datetime('now'))",
+            params![migration.version],
+        )?;
+        tx.commit()?;
+        version = migration.version;
+    }
+    Ok(version)
+}
+
+/// Bring the database at `path` up to date
+///
+/// A database that already has a schema is copied to
+/// `<path>.v<version>.bak` first when `backup` is set.
+pub fn migrate(path: &Path, backup: bool) -> Result<MigrationReport> {
+    migrate_with(path, MIGRATIONS, backup)
+}
+
+fn migrate_with(path: &Path, migrations: &[Migration], backup: bool) -> Result<MigrationReport> {
+    let mut conn = Connection::open(path)?;
+    let from = schema_version(&conn)?;
+    let latest = latest_in(migrations);
+    if from > latest {
+        return Err(newer_than_supported(path, from, latest));
+    }
+    if from == latest {
+        return Ok(MigrationReport { from, to: from, backup: None });
+    }
+
+    let backup = if backup && from > 0 {
+        let backup_path = PathBuf::from(format!("{}.v{}.bak", path.display(), from));
+        std::fs::copy(path, &backup_path)?;
+        Some(backup_path)
+    } else {
+        None
+    };
+
+    let to = apply(&mut conn, migrations)?;
+    Ok(MigrationReport { from, to, backup })
+}
+
+fn newer_than_supported(path: &Path, version: u32, latest: u32) -> PatternError {
+    PatternError::DatabaseError(format!(
+        "{} uses pattern schema v{}, newer than v{} supported by this release",
+        path.display(),
+        version,
+        latest
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V2: Migration = Migration {
+        version: 2,
+        description: "Add notes",
+        sql: "ALTER TABLE patterns ADD COLUMN notes TEXT",
+    };
+
+    #[test]
+    fn test_fresh_database_gets_latest_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+        assert_eq!(apply_pending(&mut conn).unwrap(), latest_version());
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+
+        // Already current: nothing to do
+        assert_eq!(apply_pending(&mut conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn test_migrate_backs_up_before_upgrading() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("patterns.db");
+        let v1 = &MIGRATIONS[..1];
+
+        let report = migrate_with(&path, v1, true).unwrap();
+        assert_eq!(report, MigrationReport { from: 0, to: 1, backup: None });
+
+        let upgraded = [MIGRATIONS[0], V2];
+        let report = migrate_with(&path, &upgraded, true).unwrap();
+        assert_eq!((report.from, report.to), (1, 2));
+        let backup = report.backup.unwrap();
+        assert_eq!(schema_version(&Connection::open(&backup).unwrap()).unwrap(), 1);
+        assert_eq!(schema_version(&Connection::open(&path).unwrap()).unwrap(), 2);
+
+        // A release that only knows v1 must not touch it now
+        assert!(migrate_with(&path, v1, true).is_err());
+    }
+}
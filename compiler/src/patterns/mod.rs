@@ -5,7 +5,10 @@
 //! - Pattern metadata and validation
 //! - SQLite-based pattern database
 //! - CLI and HTTP API for pattern queries
+//! - Semantic search by stack effect and properties
+//! - Quality scores from compilation feedback
 //! - Pattern template instantiation
+//! - Signed bundles for sharing patterns between databases
 
 pub mod registry;
 pub mod database;
@@ -15,6 +18,10 @@ pub mod http;
 pub mod validation;
 pub mod cli;
 pub mod integration;
+pub mod migrations;
+pub mod bundle;
+pub mod search;
+pub mod feedback;
 
 pub use registry::{PatternRegistry, Pattern, PatternCategory};
 pub use database::{PatternDatabase, PatternQuery};
@@ -22,8 +29,11 @@ pub use templates::{PatternTemplate, TemplateVariable, instantiate_pattern};
 pub use template_jit::{instantiate_compiled, compile_and_cache};
 pub use http::{PatternServer, PatternApiConfig};
 pub use validation::{validate_pattern_metadata, PatternValidationError};
-pub use cli::{PatternCli, PatternCommand, execute_pattern_command};
+pub use cli::{PatternCli, PatternCommand, execute_pattern_command, run_pattern_command};
 pub use integration::PatternValidator;
+pub use bundle::{import_bundle, BundleFormat, ConflictPolicy, ImportReport, PatternBundle};
+pub use search::{semantic_search, EffectMatch, SearchHit, SemanticQuery};
+pub use feedback::{AuditEntry, FeedbackCounts, FeedbackKind, Outcome};
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -48,8 +58,8 @@ impl std::fmt::Display for PatternId {
     }
 }
 
-/// Performance class for patterns
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Performance class for patterns, cheapest first
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum PerformanceClass {
     Constant,      // O(1)
     Logarithmic,   // O(log n)
@@ -72,6 +82,23 @@ impl std::fmt::Display for PerformanceClass {
     }
 }
 
+impl std::str::FromStr for PerformanceClass {
+    type Err = PatternError;
+
+    /// Parse the notation written by `Display`
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "O(1)" => Ok(Self::Constant),
+            "O(log n)" => Ok(Self::Logarithmic),
+            "O(n)" => Ok(Self::Linear),
+            "O(n log n)" => Ok(Self::Linearithmic),
+            "O(n²)" => Ok(Self::Quadratic),
+            "O(2^n)" => Ok(Self::Exponential),
+            _ => Err(PatternError::ValidationError(format!("Unknown performance class: {}", s))),
+        }
+    }
+}
+
 /// Test case for pattern validation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TestCase {
@@ -120,6 +147,9 @@ pub enum PatternError {
     #[error("HTTP server error: {0}")]
     HttpError(String),
 
+    #[error("Access denied: {0}")]
+    AccessDenied(#[from] crate::access::AccessDenied),
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
@@ -127,6 +157,12 @@ pub enum PatternError {
     JsonError(#[from] serde_json::Error),
 }
 
+impl From<rusqlite::Error> for PatternError {
+    fn from(error: rusqlite::Error) -> Self {
+        PatternError::DatabaseError(error.to_string())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -142,5 +178,7 @@ mod tests {
         assert_eq!(PerformanceClass::Constant.to_string(), "O(1)");
         assert_eq!(PerformanceClass::Linear.to_string(), "O(n)");
         assert_eq!(PerformanceClass::Quadratic.to_string(), "O(n²)");
+        assert_eq!("O(n log n)".parse::<PerformanceClass>().unwrap(), PerformanceClass::Linearithmic);
+        assert!("O(n!)".parse::<PerformanceClass>().is_err());
     }
 }
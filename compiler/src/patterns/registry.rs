@@ -46,6 +46,13 @@ pub struct Pattern {
     pub metadata: PatternMetadata,
     pub usage_count: u64,
     pub success_rate: f64,
+    /// How well recorded results agree with the metadata; see `FeedbackCounts`
+    #[serde(default = "default_quality_score")]
+    pub quality_score: f64,
+}
+
+fn default_quality_score() -> f64 {
+    1.0
 }
 
 /// In-memory pattern registry
@@ -156,6 +163,7 @@ mod tests {
             },
             usage_count: 0,
             success_rate: 1.0,
+            quality_score: 1.0,
         }
     }
 
@@ -0,0 +1,285 @@
+//! Semantic pattern search
+//!
+//! Finds patterns by what they do rather than what they are called: a
+//! desired stack effect is unified against each pattern's effect, so
+//! `( x y -- y x )` finds a pattern written as `( a b -- b a )`. Property
+//! keywords ("commutative", "O(n)") then refine the ranking.
+//!
+//! Effects are read loosely, the way pattern authors write them. `n`,
+//! `int`, `flag`, `addr`, `float` and friends are concrete types; any
+//! other plain name is a type variable shared within the effect; computed
+//! results such as `n²` or `a+b` are fresh variables.
+
+use super::{Pattern, PatternError, Result};
+use crate::type_algebra::{AlgebraicStackEffect, AlgebraicType, ConcreteType, TypeVariable, Unifier};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Effect match scores; property matches scale these down, never up
+const EXACT_SCORE: f64 = 1.0;
+const MORE_GENERAL_SCORE: f64 = 0.9;
+const MORE_SPECIFIC_SCORE: f64 = 0.75;
+const COMPATIBLE_SCORE: f64 = 0.6;
+
+/// Search by stack effect and properties
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SemanticQuery {
+    /// Desired effect, e.g. `( n n -- n )`
+    pub stack_effect: Option<String>,
+    /// Keywords matched against tags, category, performance class and description
+    #[serde(default)]
+    pub properties: Vec<String>,
+    pub limit: Option<usize>,
+}
+
+/// How a pattern's effect relates to the one asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EffectMatch {
+    /// Same effect up to variable renaming
+    Exact,
+    /// The pattern accepts everything asked for, and more
+    MoreGeneral,
+    /// The pattern only covers some of what was asked for
+    MoreSpecific,
+    /// The effects unify, but neither subsumes the other
+    Compatible,
+}
+
+impl EffectMatch {
+    fn score(self) -> f64 {
+        match self {
+            Self::Exact => EXACT_SCORE,
+            Self::MoreGeneral => MORE_GENERAL_SCORE,
+            Self::MoreSpecific => MORE_SPECIFIC_SCORE,
+            Self::Compatible => COMPATIBLE_SCORE,
+        }
+    }
+}
+
+/// One ranked result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub pattern: Pattern,
+    /// Between 0 and 1, higher is better
+    pub score: f64,
+    /// Absent when the query has no effect
+    pub effect_match: Option<EffectMatch>,
+    pub matched_properties: Vec<String>,
+}
+
+/// Rank patterns against a query, best first
+///
+/// With an effect, only patterns whose effect unifies with it are returned;
+/// without one, only patterns matching at least one property.
+pub fn semantic_search(patterns: impl IntoIterator<Item = Pattern>, query: &SemanticQuery) -> Result<Vec<SearchHit>> {
+    let mut reader = EffectReader::default();
+    let wanted = query
+        .stack_effect
+        .as_deref()
+        .map(|effect| {
+            reader.read(effect).ok_or_else(|| {
+                PatternError::ValidationError(format!("Invalid stack effect: {}. Expected ( inputs -- outputs )", effect))
+            })
+        })
+        .transpose()?;
+    if wanted.is_none() && query.properties.is_empty() {
+        return Err(PatternError::ValidationError(
+            "Semantic search needs a stack effect or at least one property".to_string(),
+        ));
+    }
+
+    let mut hits: Vec<SearchHit> = patterns
+        .into_iter()
+        .filter_map(|pattern| {
+            let effect_match = match &wanted {
+                Some(wanted) => Some(match_effect(wanted, &reader.read(&pattern.metadata.stack_effect)?)?),
+                None => None,
+            };
+            let matched_properties: Vec<String> = query
+                .properties
+                .iter()
+                .filter(|property| has_property(&pattern, property))
+                .cloned()
+                .collect();
+
+            let property_fraction = if query.properties.is_empty() {
+                1.0
+            } else {
+                matched_properties.len() as f64 / query.properties.len() as f64
+            };
+            let score = match effect_match {
+                Some(effect_match) => effect_match.score() * (0.5 + 0.5 * property_fraction),
+                None if matched_properties.is_empty() => return None,
+                None => property_fraction,
+            };
+            Some(SearchHit { pattern, score, effect_match, matched_properties })
+        })
+        .collect();
+
+    hits.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.pattern.metadata.id.0.cmp(&b.pattern.metadata.id.0))
+    });
+    if let Some(limit) = query.limit {
+        hits.truncate(limit);
+    }
+    Ok(hits)
+}
+
+/// Compare a pattern's effect with the wanted one
+fn match_effect(wanted: &AlgebraicStackEffect, found: &AlgebraicStackEffect) -> Option<EffectMatch> {
+    if wanted.inputs.len() != found.inputs.len() || wanted.outputs.len() != found.outputs.len() {
+        return None;
+    }
+
+    let mut unifier = Unifier::new();
+    for (a, b) in slots(wanted).zip(slots(found)) {
+        unifier.unify(a, b).ok()?;
+    }
+
+    Some(match (instance_of(wanted, found), instance_of(found, wanted)) {
+        (true, true) => EffectMatch::Exact,
+        (true, false) => EffectMatch::MoreGeneral,
+        (false, true) => EffectMatch::MoreSpecific,
+        (false, false) => EffectMatch::Compatible,
+    })
+}
+
+/// Whether `specific` is `general` with some of its variables substituted
+fn instance_of(specific: &AlgebraicStackEffect, general: &AlgebraicStackEffect) -> bool {
+    let mut bindings: HashMap<&TypeVariable, &AlgebraicType> = HashMap::new();
+    slots(general).zip(slots(specific)).all(|(g, s)| match g {
+        AlgebraicType::Var(var) => *bindings.entry(var).or_insert(s) == s,
+        _ => g == s,
+    })
+}
+
+fn slots(effect: &AlgebraicStackEffect) -> impl Iterator<Item = &AlgebraicType> {
+    effect.inputs.iter().chain(&effect.outputs)
+}
+
+/// Case-insensitive match on tags, category, performance class or a
+/// description word
+fn has_property(pattern: &Pattern, property: &str) -> bool {
+    let meta = &pattern.metadata;
+    let property = property.to_lowercase();
+    meta.tags.iter().any(|tag| tag.to_lowercase() == property)
+        || meta.category.to_lowercase() == property
+        || meta.performance_class.to_string().to_lowercase() == property
+        || format!("{:?}", meta.performance_class).to_lowercase() == property
+        || meta
+            .description
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric() && c != '-')
+            .any(|word| word == property)
+}
+
+/// Reads effect strings, keeping variables from different effects apart
+#[derive(Default)]
+struct EffectReader {
+    next_var: usize,
+}
+
+impl EffectReader {
+    fn read(&mut self, effect: &str) -> Option<AlgebraicStackEffect> {
+        let inner = effect.trim().strip_prefix('(')?.strip_suffix(')')?;
+        let (inputs, outputs) = inner.split_once("--")?;
+
+        let mut names = HashMap::new();
+        let inputs = inputs.split_whitespace().map(|token| self.slot(token, &mut names)).collect();
+        let outputs = outputs.split_whitespace().map(|token| self.slot(token, &mut names)).collect();
+        Some(AlgebraicStackEffect::new(inputs, outputs))
+    }
+
+    fn slot(&mut self, token: &str, names: &mut HashMap<String, AlgebraicType>) -> AlgebraicType {
+        if let Some(concrete) = concrete_type(token) {
+            return AlgebraicType::Concrete(concrete);
+        }
+        let is_name = token.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') && !token.contains('²');
+        if is_name {
+            if let Some(var) = names.get(token) {
+                return var.clone();
+            }
+        }
+
+        let var = AlgebraicType::Var(TypeVariable { id: self.next_var, name: Some(token.to_string()) });
+        self.next_var += 1;
+        if is_name {
+            names.insert(token.to_string(), var.clone());
+        }
+        var
+    }
+}
+
+/// Type names pattern authors use; single letters other than `n` and `f`
+/// are left as variables since `a b c` usually means "any three values"
+fn concrete_type(token: &str) -> Option<ConcreteType> {
+    match token {
+        "n" | "n1" | "n2" | "n3" | "u" | "int" => Some(ConcreteType::Int),
+        "f" | "float" => Some(ConcreteType::Float),
+        "flag" | "bool" => Some(ConcreteType::Bool),
+        "addr" => Some(ConcreteType::Addr),
+        "char" => Some(ConcreteType::Char),
+        "string" | "str" => Some(ConcreteType::String),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patterns::PatternDatabase;
+
+    fn library() -> Vec<Pattern> {
+        let mut db = PatternDatabase::open(":memory:").unwrap();
+        db.seed_defaults().unwrap();
+        db.list_all().unwrap()
+    }
+
+    fn effect_query(effect: &str) -> SemanticQuery {
+        SemanticQuery { stack_effect: Some(effect.to_string()), ..SemanticQuery::default() }
+    }
+
+    #[test]
+    fn test_effects_match_modulo_renaming() {
+        let hits = semantic_search(library(), &effect_query("( x y z -- z y x )")).unwrap();
+        assert_eq!(hits[0].pattern.metadata.id.as_str(), "STACK_MANIP_001");
+        assert_eq!(hits[0].effect_match, Some(EffectMatch::Exact));
+
+        // ( a b -- b a b ) is tuck: the copy must be the second input
+        let hits = semantic_search(library(), &effect_query("( x y -- y x y )")).unwrap();
+        assert_eq!(hits[0].effect_match, Some(EffectMatch::Exact));
+        let hits = semantic_search(library(), &effect_query("( x y -- x y x )")).unwrap();
+        assert!(hits.iter().all(|hit| hit.effect_match != Some(EffectMatch::Exact)));
+    }
+
+    #[test]
+    fn test_binary_ops_ranked_by_properties() {
+        let mut query = effect_query("( n n -- n )");
+        let hits = semantic_search(library(), &query).unwrap();
+        assert!(hits.len() >= 3);
+        assert!(hits.iter().all(|hit| hit.effect_match == Some(EffectMatch::MoreGeneral)));
+
+        query.properties = vec!["average".to_string()];
+        let hits = semantic_search(library(), &query).unwrap();
+        assert_eq!(hits[0].pattern.metadata.tags, vec!["arithmetic", "average"]);
+        assert_eq!(hits[0].matched_properties, vec!["average".to_string()]);
+        assert!(hits[0].score > hits[1].score);
+
+        // Unary effects never match a binary query
+        assert!(hits.iter().all(|hit| hit.pattern.metadata.id.as_str() != "DUP_TRANSFORM_001"));
+    }
+
+    #[test]
+    fn test_properties_alone_and_invalid_queries() {
+        let query = SemanticQuery { properties: vec!["O(n)".to_string()], ..SemanticQuery::default() };
+        let hits = semantic_search(library(), &query).unwrap();
+        assert!(!hits.is_empty());
+        assert!(hits.iter().all(|hit| hit.pattern.metadata.performance_class.to_string() == "O(n)"));
+
+        assert!(semantic_search(library(), &SemanticQuery::default()).is_err());
+        assert!(semantic_search(library(), &effect_query("n -- n")).is_err());
+    }
+}
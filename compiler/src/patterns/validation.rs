@@ -94,6 +94,20 @@ pub fn extract_pattern_id_from_code(code: &str) -> Option<PatternId> {
         .map(|m| PatternId(m.as_str().to_string()))
 }
 
+/// Every distinct pattern ID named in code comments, in order of appearance
+pub fn extract_pattern_ids_from_code(code: &str) -> Vec<PatternId> {
+    let re = Regex::new(r"\\\ PATTERN:\s*([A-Z_]+_\d{3})").unwrap();
+
+    let mut ids = Vec::new();
+    for cap in re.captures_iter(code) {
+        let id = PatternId(cap[1].to_string());
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
 /// Validate pattern is present in code
 pub fn validate_pattern_in_code(code: &str, expected_id: &PatternId) -> Result<()> {
     match extract_pattern_id_from_code(code) {
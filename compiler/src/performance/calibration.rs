@@ -0,0 +1,436 @@
+//! Calibrating the performance model on the host
+//!
+//! The static costs in [`PerformanceModel`](super::PerformanceModel) are
+//! guesses. Calibration runs a suite of microbenchmarks under the JIT,
+//! fits a cost to every instruction and superinstruction they exercise by
+//! least squares, and saves the result so later predictions use costs
+//! measured on this machine.
+//!
+//! Each microbenchmark is a unit of Forth with stack effect `( a -- a )`
+//! and the IR instructions it stands for. A unit is repeated inside a
+//! word, the word is called from straight-line code, and the time per unit
+//! is what remains after subtracting a kernel without the unit. Calls keep
+//! the JIT from folding the unit away, since the kernel's input is not a
+//! constant inside the word.
+//!
+//! Costs are stored in cycles, where one cycle is the measured cost of
+//! `+`: the model's C baseline spends one cycle per operation. Instructions
+//! the JIT cannot run yet (memory access, return stack) keep their static
+//! costs.
+
+use crate::error::{CompileError, Result};
+use crate::pipeline::CompilationPipeline;
+use fastforth_optimizer::{Instruction, OptimizationLevel};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Environment variable naming the calibrated model file
+pub const MODEL_ENV: &str = "FIFTH_PERFORMANCE_MODEL";
+
+/// Default number of timed runs per kernel
+pub const DEFAULT_SAMPLES: usize = 200;
+
+/// Untimed runs before timing a kernel
+const WARMUP_RUNS: usize = 10;
+
+/// Copies of the unit in the kernel word
+const UNITS_PER_CALL: usize = 32;
+
+/// Calls of the kernel word per run
+const CALLS_PER_RUN: usize = 64;
+
+/// Value the kernel is first called with
+const KERNEL_INPUT: i64 = 1000;
+
+/// Word a unit is repeated in
+const KERNEL_WORD: &str = "calibrate-kernel";
+
+/// Weight of the constraint holding `Drop` at zero in the fit
+const PIN_WEIGHT: f64 = 1e6;
+
+/// Smallest cost of `+`, in nanoseconds, accepted as one cycle
+const MIN_NS_PER_CYCLE: f64 = 0.01;
+
+/// One kernel of the calibration suite
+#[derive(Debug, Clone)]
+pub struct Microbenchmark {
+    /// Forth spelling of one unit, with stack effect `( a -- a )`
+    pub source: String,
+    /// Instructions one unit executes
+    pub instructions: Vec<Instruction>,
+    /// Definitions the unit relies on
+    pub prelude: String,
+}
+
+impl Microbenchmark {
+    pub fn new(source: &str, instructions: Vec<Instruction>) -> Self {
+        Self { source: source.to_string(), instructions, prelude: String::new() }
+    }
+
+    /// Add definitions the unit relies on
+    pub fn with_prelude(mut self, prelude: &str) -> Self {
+        self.prelude = prelude.to_string();
+        self
+    }
+}
+
+/// The standard calibration suite
+///
+/// Superinstructions get their own kernels, spelled as the sequence the
+/// optimizer fuses, so their costs are fitted independently of the
+/// instructions they replace.
+pub fn microbenchmarks() -> Vec<Microbenchmark> {
+    use Instruction::*;
+    vec![
+        // Stack operations
+        Microbenchmark::new("dup drop", vec![Dup, Drop]),
+        Microbenchmark::new("dup swap drop", vec![Dup, Swap, Drop]),
+        Microbenchmark::new("dup over drop drop", vec![Dup, Over, Drop, Drop]),
+        Microbenchmark::new("dup dup rot drop drop", vec![Dup, Dup, Rot, Drop, Drop]),
+        // Arithmetic
+        Microbenchmark::new("dup +", vec![Dup, Add]),
+        Microbenchmark::new("7 +", vec![Literal(7), Add]),
+        Microbenchmark::new("3 -", vec![Literal(3), Sub]),
+        Microbenchmark::new("dup *", vec![Dup, Mul]),
+        Microbenchmark::new("3 *", vec![Literal(3), Mul]),
+        Microbenchmark::new("7 /", vec![Literal(7), Div]),
+        Microbenchmark::new("7 mod", vec![Literal(7), Mod]),
+        Microbenchmark::new("negate", vec![Neg]),
+        Microbenchmark::new("abs", vec![Abs]),
+        // Bitwise and comparison
+        Microbenchmark::new("85 and", vec![Literal(85), And]),
+        Microbenchmark::new("85 or", vec![Literal(85), Or]),
+        Microbenchmark::new("5 <", vec![Literal(5), Lt]),
+        Microbenchmark::new("5 =", vec![Literal(5), Eq]),
+        // Control flow; the input never takes the branch
+        Microbenchmark::new("dup 5 < if 1 + then", vec![Dup, Literal(5), Lt, BranchIfNot(0)]),
+        Microbenchmark::new("calibrate-id", vec![Call("calibrate-id".into()), Dup, Drop])
+            .with_prelude(": calibrate-id ( a -- a ) dup drop ;"),
+        // Superinstructions
+        Microbenchmark::new("dup +", vec![DupAdd]),
+        Microbenchmark::new("dup *", vec![DupMul]),
+        Microbenchmark::new("dup over + swap drop", vec![Dup, OverAdd, Swap, Drop]),
+        Microbenchmark::new("5 swap -", vec![Literal(5), SwapSub]),
+        Microbenchmark::new("7 +", vec![LiteralAdd(7)]),
+        Microbenchmark::new("3 *", vec![LiteralMul(3)]),
+        Microbenchmark::new("1 +", vec![IncOne]),
+        Microbenchmark::new("1 -", vec![DecOne]),
+        Microbenchmark::new("2 *", vec![MulTwo]),
+        Microbenchmark::new("2 /", vec![DivTwo]),
+    ]
+}
+
+/// Name an instruction's cost is stored under: its variant, without operands
+pub fn cost_key(inst: &Instruction) -> String {
+    let debug = format!("{:?}", inst);
+    debug.split(['(', ' ', '{']).next().unwrap_or_default().to_string()
+}
+
+/// Instruction costs measured on one machine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalibratedCosts {
+    /// Architecture and OS the costs were measured on
+    pub host: String,
+    /// Seconds since the Unix epoch
+    pub calibrated_at: u64,
+    /// Measured cost of `+`, which the costs are expressed in
+    pub ns_per_cycle: f64,
+    /// Cycles per instruction, by [`cost_key`]
+    pub costs: BTreeMap<String, f64>,
+}
+
+impl CalibratedCosts {
+    /// Costs in cycles from costs in nanoseconds
+    pub fn from_nanoseconds(ns: BTreeMap<String, f64>) -> Self {
+        let ns_per_cycle = ns.get("Add").copied().unwrap_or(1.0).max(MIN_NS_PER_CYCLE);
+        Self {
+            host: host(),
+            calibrated_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            ns_per_cycle,
+            costs: ns.into_iter().map(|(key, ns)| (key, ns / ns_per_cycle)).collect(),
+        }
+    }
+
+    /// Calibrated cost of `inst` in cycles, if it was measured
+    pub fn cost(&self, inst: &Instruction) -> Option<f64> {
+        self.costs.get(&cost_key(inst)).copied()
+    }
+
+    /// Whether the costs were measured on this kind of machine
+    pub fn matches_host(&self) -> bool {
+        self.host == host()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|e| CompileError::IoError(path.to_path_buf(), e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| CompileError::InternalError(format!("Invalid performance model {}: {}", path.display(), e)))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| CompileError::IoError(dir.to_path_buf(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| CompileError::InternalError(format!("Failed to serialize performance model: {}", e)))?;
+        std::fs::write(path, json).map_err(|e| CompileError::IoError(path.to_path_buf(), e))
+    }
+
+    /// Where the calibrated model is kept: `$FIFTH_PERFORMANCE_MODEL`, or
+    /// `~/.fifth/performance-model.json`
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os(MODEL_ENV).filter(|path| !path.is_empty()) {
+            return Some(PathBuf::from(path));
+        }
+        let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+        Some(PathBuf::from(home).join(".fifth").join("performance-model.json"))
+    }
+
+    /// The saved model, if there is one for this machine
+    pub fn load_default() -> Option<Self> {
+        Self::load(Self::default_path()?).ok().filter(Self::matches_host)
+    }
+}
+
+fn host() -> String {
+    format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS)
+}
+
+/// Runs the calibration suite
+pub struct Calibrator {
+    suite: Vec<Microbenchmark>,
+    samples: usize,
+}
+
+impl Calibrator {
+    pub fn new() -> Self {
+        Self { suite: microbenchmarks(), samples: DEFAULT_SAMPLES }
+    }
+
+    /// Set the number of timed runs per kernel
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    /// Replace the microbenchmark suite
+    pub fn with_suite(mut self, suite: Vec<Microbenchmark>) -> Self {
+        self.suite = suite;
+        self
+    }
+
+    /// Measure every kernel and fit instruction costs
+    pub fn run(&self) -> Result<CalibratedCosts> {
+        let pipeline = CompilationPipeline::new(OptimizationLevel::Aggressive);
+
+        // Kernels that only make the call, so the unit's time is what is left
+        let mut baselines: HashMap<&str, f64> = HashMap::new();
+        // Kernels spelled the same are measured once
+        let mut per_unit: HashMap<(&str, &str), f64> = HashMap::new();
+        let mut observations = Vec::with_capacity(self.suite.len());
+
+        for bench in &self.suite {
+            let baseline = match baselines.get(bench.prelude.as_str()) {
+                Some(ns) => *ns,
+                None => {
+                    let ns = self.measure(&pipeline, &kernel_source(&bench.prelude, ""))?;
+                    *baselines.entry(bench.prelude.as_str()).or_insert(ns)
+                }
+            };
+
+            let key = (bench.prelude.as_str(), bench.source.as_str());
+            let ns = match per_unit.get(&key) {
+                Some(ns) => *ns,
+                None => {
+                    let units = vec![bench.source.as_str(); UNITS_PER_CALL].join(" ");
+                    let total = self.measure(&pipeline, &kernel_source(&bench.prelude, &units))?;
+                    let ns = (total - baseline) / (UNITS_PER_CALL * CALLS_PER_RUN) as f64;
+                    *per_unit.entry(key).or_insert(ns)
+                }
+            };
+
+            observations.push((bench.instructions.clone(), ns));
+        }
+
+        Ok(CalibratedCosts::from_nanoseconds(fit_costs(&observations)))
+    }
+
+    /// Median time of one run of `source`, in nanoseconds
+    fn measure(&self, pipeline: &CompilationPipeline, source: &str) -> Result<f64> {
+        let program = pipeline.prepare_jit(source)?;
+
+        for _ in 0..WARMUP_RUNS {
+            std::hint::black_box(program.run());
+        }
+
+        let mut times = Vec::with_capacity(self.samples);
+        for _ in 0..self.samples {
+            let start = Instant::now();
+            let stack = std::hint::black_box(program.run());
+            times.push(start.elapsed().as_nanos() as f64);
+            if stack.is_none() {
+                return Err(CompileError::InternalError("Calibration kernel has no entry point".to_string()));
+            }
+        }
+
+        times.sort_by(|a, b| a.total_cmp(b));
+        Ok(times[times.len() / 2])
+    }
+}
+
+impl Default for Calibrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Program calling a kernel made of `units`
+///
+/// The kernel keeps a `dup drop` so it is never empty, and the same kernel
+/// without units is the baseline it is compared with.
+fn kernel_source(prelude: &str, units: &str) -> String {
+    let calls = vec![KERNEL_WORD; CALLS_PER_RUN].join(" ");
+    format!("{}\n: {} ( a -- a ) dup drop {} ;\n{} {}\n", prelude, KERNEL_WORD, units, KERNEL_INPUT, calls)
+}
+
+/// Fit a cost to every instruction so that each observation's time is the
+/// sum of its instructions' costs, in the least-squares sense
+///
+/// Units that leave the stack as they found it only fix costs up to a
+/// shift proportional to each instruction's net stack effect, so `Drop` is
+/// pinned at zero: discarding a value the JIT holds in a register is free.
+/// Costs are clamped at zero, since a negative cost is measurement noise on
+/// an instruction that was optimized away.
+pub fn fit_costs(observations: &[(Vec<Instruction>, f64)]) -> BTreeMap<String, f64> {
+    let keys: Vec<String> = observations
+        .iter()
+        .flat_map(|(instructions, _)| instructions.iter().map(cost_key))
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let index: HashMap<&str, usize> = keys.iter().enumerate().map(|(i, key)| (key.as_str(), i)).collect();
+    let n = keys.len();
+
+    // Normal equations AᵀA x = Aᵀb, where row i of A counts the
+    // instructions of observation i
+    let mut ata = vec![vec![0.0; n]; n];
+    let mut atb = vec![0.0; n];
+    for (instructions, time) in observations {
+        let mut row = vec![0.0; n];
+        for inst in instructions {
+            row[index[cost_key(inst).as_str()]] += 1.0;
+        }
+        for i in 0..n {
+            atb[i] += row[i] * time;
+            for j in 0..n {
+                ata[i][j] += row[i] * row[j];
+            }
+        }
+    }
+
+    // A small ridge keeps instructions that always appear together solvable
+    for (i, row) in ata.iter_mut().enumerate() {
+        row[i] += 1e-9;
+    }
+    if let Some(&drop) = index.get("Drop") {
+        ata[drop][drop] += PIN_WEIGHT;
+    }
+
+    let solution = solve(ata, atb);
+    keys.into_iter().zip(solution).map(|(key, cost)| (key, cost.max(0.0))).collect()
+}
+
+/// Solve `a x = b` by Gaussian elimination with partial pivoting
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Vec<f64> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs())).unwrap_or(col);
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        if a[col][col].abs() < f64::EPSILON {
+            continue;
+        }
+        let (upper, lower) = a.split_at_mut(col + 1);
+        let pivot_row = &upper[col];
+        for (offset, row) in lower.iter_mut().enumerate() {
+            let factor = row[col] / pivot_row[col];
+            for (value, pivot) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *value -= factor * pivot;
+            }
+            b[col + 1 + offset] -= factor * b[col];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        if a[row][row].abs() < f64::EPSILON {
+            continue;
+        }
+        let rest: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - rest) / a[row][row];
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Instruction::*;
+
+    #[test]
+    fn test_fit_recovers_costs() {
+        // Dup 0.25, Drop 0, Add 1, Literal 0.5, Mul 3, DupAdd 0.75
+        let observations = vec![
+            (vec![Dup, Drop], 0.25),
+            (vec![Dup, Add], 1.25),
+            (vec![Literal(7), Add], 1.5),
+            (vec![Literal(3), Mul], 3.5),
+            (vec![Dup, Mul], 3.25),
+            (vec![DupAdd], 0.75),
+        ];
+        let costs = fit_costs(&observations);
+
+        assert!(costs["Drop"].abs() < 1e-6);
+        assert!((costs["Dup"] - 0.25).abs() < 1e-6);
+        assert!((costs["Add"] - 1.0).abs() < 1e-6);
+        assert!((costs["Literal"] - 0.5).abs() < 1e-6);
+        assert!((costs["Mul"] - 3.0).abs() < 1e-6);
+        assert!((costs["DupAdd"] - 0.75).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_costs_in_cycles_of_add() {
+        let ns = BTreeMap::from([("Add".to_string(), 0.5), ("Div".to_string(), 10.0)]);
+        let costs = CalibratedCosts::from_nanoseconds(ns);
+
+        assert_eq!(costs.ns_per_cycle, 0.5);
+        assert_eq!(costs.cost(&Add), Some(1.0));
+        assert_eq!(costs.cost(&Div), Some(20.0));
+        assert_eq!(costs.cost(&LiteralAdd(3)), None);
+        assert!(costs.matches_host());
+
+        let path = std::env::temp_dir().join(format!("fifth-model-{}.json", std::process::id()));
+        costs.save(&path).unwrap();
+        assert_eq!(CalibratedCosts::load(&path).unwrap(), costs);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_calibration_runs_kernels() {
+        let suite = vec![
+            Microbenchmark::new("dup *", vec![Dup, Mul]),
+            Microbenchmark::new("calibrate-id", vec![Call("calibrate-id".into()), Dup, Drop])
+                .with_prelude(": calibrate-id ( a -- a ) dup drop ;"),
+        ];
+        let costs = Calibrator::new().with_samples(5).with_suite(suite).run().unwrap();
+
+        assert_eq!(cost_key(&Call("x".into())), "Call");
+        assert!(costs.costs.contains_key("Mul"));
+        assert!(costs.costs.contains_key("Call"));
+        assert!(costs.costs.values().all(|cost| *cost >= 0.0));
+    }
+}
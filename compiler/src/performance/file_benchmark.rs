@@ -0,0 +1,426 @@
+//! Benchmarking a user's program
+//!
+//! Compiles a source file in JIT and AOT mode at every optimization level,
+//! times compilation and execution over a number of iterations after a
+//! warmup, and compares the timings with a previous run's report.
+//!
+//! JIT cases time compilation and execution separately. The AOT backend
+//! does not produce runnable code yet, so AOT cases time compilation only.
+
+use crate::pipeline::{CompilationMode, CompilationPipeline};
+use fastforth_optimizer::OptimizationLevel;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Default number of timed iterations
+pub const DEFAULT_ITERATIONS: usize = 100;
+
+/// Default number of untimed iterations run first
+pub const DEFAULT_WARMUP: usize = 5;
+
+/// Default slowdown, in percent, reported as a regression
+pub const DEFAULT_REGRESSION_THRESHOLD: f64 = 10.0;
+
+/// Summary statistics of a set of timings, in nanoseconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimingStats {
+    pub samples: usize,
+    pub mean_ns: f64,
+    pub median_ns: f64,
+    pub stddev_ns: f64,
+    pub min_ns: f64,
+    pub max_ns: f64,
+}
+
+impl TimingStats {
+    /// Statistics of `samples`; `None` if there are none
+    pub fn from_samples(samples: &[Duration]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let mut ns: Vec<f64> = samples.iter().map(|d| d.as_nanos() as f64).collect();
+        ns.sort_by(|a, b| a.total_cmp(b));
+
+        let n = ns.len();
+        let mean = ns.iter().sum::<f64>() / n as f64;
+        let median = if n.is_multiple_of(2) { (ns[n / 2 - 1] + ns[n / 2]) / 2.0 } else { ns[n / 2] };
+        // Sample standard deviation
+        let variance = if n > 1 {
+            ns.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64
+        } else {
+            0.0
+        };
+
+        Some(Self {
+            samples: n,
+            mean_ns: mean,
+            median_ns: median,
+            stddev_ns: variance.sqrt(),
+            min_ns: ns[0],
+            max_ns: ns[n - 1],
+        })
+    }
+
+    /// One-line summary with readable units
+    pub fn summary(&self) -> String {
+        format!(
+            "mean {} | median {} | stddev {} | min {} | max {}",
+            format_ns(self.mean_ns),
+            format_ns(self.median_ns),
+            format_ns(self.stddev_ns),
+            format_ns(self.min_ns),
+            format_ns(self.max_ns)
+        )
+    }
+}
+
+/// Timings of one mode at one optimization level
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkCase {
+    /// `jit` or `aot`
+    pub mode: String,
+    /// `O0` to `O3`
+    pub opt_level: String,
+    pub compile: Option<TimingStats>,
+    /// Execution timings, when the case produced something to run
+    pub run: Option<TimingStats>,
+    /// Data stack left by the last run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub result: Vec<i64>,
+    /// Why the case was not run, or could not be compiled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl BenchmarkCase {
+    /// Key matching the case across reports, e.g. `jit-O2`
+    pub fn key(&self) -> String {
+        format!("{}-{}", self.mode, self.opt_level)
+    }
+}
+
+/// A case's mean timing against the same case in a baseline report
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaselineComparison {
+    pub case: String,
+    /// `run` or `compile`
+    pub metric: String,
+    pub baseline_mean_ns: f64,
+    pub mean_ns: f64,
+    /// Positive when slower than the baseline
+    pub change_percent: f64,
+    pub regression: bool,
+}
+
+/// Results of benchmarking one file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileBenchmarkReport {
+    pub file: String,
+    pub iterations: usize,
+    pub warmup: usize,
+    pub cases: Vec<BenchmarkCase>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comparisons: Vec<BaselineComparison>,
+}
+
+impl FileBenchmarkReport {
+    /// Compare each case with the same case in `baseline`
+    ///
+    /// A case whose mean is more than `threshold_percent` slower than the
+    /// baseline's is flagged as a regression. Execution time is compared
+    /// where both reports have it, compilation time otherwise.
+    pub fn compare_with(&mut self, baseline: &FileBenchmarkReport, threshold_percent: f64) {
+        self.comparisons = self
+            .cases
+            .iter()
+            .filter_map(|case| {
+                let old = baseline.cases.iter().find(|old| old.key() == case.key())?;
+                let (metric, new_stats, old_stats) = match (&case.run, &old.run) {
+                    (Some(new), Some(old)) => ("run", new, old),
+                    _ => ("compile", case.compile.as_ref()?, old.compile.as_ref()?),
+                };
+
+                let change_percent = if old_stats.mean_ns > 0.0 {
+                    (new_stats.mean_ns - old_stats.mean_ns) / old_stats.mean_ns * 100.0
+                } else {
+                    0.0
+                };
+                Some(BaselineComparison {
+                    case: case.key(),
+                    metric: metric.to_string(),
+                    baseline_mean_ns: old_stats.mean_ns,
+                    mean_ns: new_stats.mean_ns,
+                    change_percent,
+                    regression: change_percent > threshold_percent,
+                })
+            })
+            .collect();
+    }
+
+    /// Comparisons flagged as regressions
+    pub fn regressions(&self) -> impl Iterator<Item = &BaselineComparison> {
+        self.comparisons.iter().filter(|c| c.regression)
+    }
+
+    /// Generate formatted report
+    pub fn format(&self) -> String {
+        let mut report = String::new();
+        report.push_str(&format!("Benchmark: {}\n", self.file));
+        report.push_str(&format!("{} iterations after {} warmup\n\n", self.iterations, self.warmup));
+
+        for case in &self.cases {
+            report.push_str(&format!("{}\n", case.key()));
+            if let Some(compile) = &case.compile {
+                report.push_str(&format!("  compile: {}\n", compile.summary()));
+            }
+            if let Some(run) = &case.run {
+                report.push_str(&format!("  run:     {}\n", run.summary()));
+            }
+            if let Some(note) = &case.note {
+                report.push_str(&format!("  note:    {}\n", note));
+            }
+        }
+
+        if !self.comparisons.is_empty() {
+            report.push_str("\nAgainst baseline\n");
+            for c in &self.comparisons {
+                report.push_str(&format!(
+                    "  {} {} {}: {} -> {} ({:+.1}%)\n",
+                    if c.regression { "✗" } else { "✓" },
+                    c.case,
+                    c.metric,
+                    format_ns(c.baseline_mean_ns),
+                    format_ns(c.mean_ns),
+                    c.change_percent
+                ));
+            }
+            let regressions = self.regressions().count();
+            if regressions > 0 {
+                report.push_str(&format!("\n{} regression(s)\n", regressions));
+            }
+        }
+
+        report
+    }
+
+    /// Generate JSON report
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Read a report saved by a previous run
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+}
+
+/// Benchmark of one source file
+pub struct FileBenchmark {
+    name: String,
+    source: String,
+    iterations: usize,
+    warmup: usize,
+    levels: Vec<OptimizationLevel>,
+    modes: Vec<CompilationMode>,
+}
+
+impl FileBenchmark {
+    /// Benchmark `source`, reported under `name`
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            source: source.into(),
+            iterations: DEFAULT_ITERATIONS,
+            warmup: DEFAULT_WARMUP,
+            levels: vec![
+                OptimizationLevel::None,
+                OptimizationLevel::Basic,
+                OptimizationLevel::Standard,
+                OptimizationLevel::Aggressive,
+            ],
+            modes: vec![CompilationMode::JIT, CompilationMode::AOT],
+        }
+    }
+
+    /// Set the number of timed iterations (at least one)
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations.max(1);
+        self
+    }
+
+    /// Set the number of untimed iterations run first
+    pub fn with_warmup(mut self, warmup: usize) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// Only benchmark these optimization levels
+    pub fn with_levels(mut self, levels: Vec<OptimizationLevel>) -> Self {
+        self.levels = levels;
+        self
+    }
+
+    /// Only benchmark these modes
+    pub fn with_modes(mut self, modes: Vec<CompilationMode>) -> Self {
+        self.modes = modes;
+        self
+    }
+
+    /// Run every mode at every level
+    pub fn run(&self) -> FileBenchmarkReport {
+        let mut cases = Vec::new();
+        for &mode in &self.modes {
+            for &level in &self.levels {
+                cases.push(match mode {
+                    CompilationMode::JIT => self.bench_jit(level),
+                    CompilationMode::AOT => self.bench_aot(level),
+                });
+            }
+        }
+
+        FileBenchmarkReport {
+            file: self.name.clone(),
+            iterations: self.iterations,
+            warmup: self.warmup,
+            cases,
+            comparisons: Vec::new(),
+        }
+    }
+
+    fn bench_jit(&self, level: OptimizationLevel) -> BenchmarkCase {
+        let mut case = empty_case(CompilationMode::JIT, level);
+        let pipeline = CompilationPipeline::new(level);
+
+        let mut compile_times = Vec::with_capacity(self.iterations);
+        let mut program = None;
+        for i in 0..self.warmup + self.iterations {
+            let start = Instant::now();
+            match pipeline.prepare_jit(&self.source) {
+                Ok(compiled) => program = Some(compiled),
+                Err(e) => {
+                    case.note = Some(format!("compilation failed: {}", e));
+                    return case;
+                }
+            }
+            if i >= self.warmup {
+                compile_times.push(start.elapsed());
+            }
+        }
+        case.compile = TimingStats::from_samples(&compile_times);
+
+        let Some(program) = program.filter(|p| p.has_entry()) else {
+            case.note = Some("no top-level code to run".to_string());
+            return case;
+        };
+
+        let mut run_times = Vec::with_capacity(self.iterations);
+        for i in 0..self.warmup + self.iterations {
+            let start = Instant::now();
+            let stack = program.run();
+            if i >= self.warmup {
+                run_times.push(start.elapsed());
+            }
+            case.result = stack.unwrap_or_default();
+        }
+        case.run = TimingStats::from_samples(&run_times);
+        case
+    }
+
+    fn bench_aot(&self, level: OptimizationLevel) -> BenchmarkCase {
+        let mut case = empty_case(CompilationMode::AOT, level);
+        let mut pipeline = CompilationPipeline::new(level);
+
+        let mut compile_times = Vec::with_capacity(self.iterations);
+        for i in 0..self.warmup + self.iterations {
+            let start = Instant::now();
+            if let Err(e) = pipeline.compile(&self.source, CompilationMode::AOT) {
+                case.note = Some(format!("compilation failed: {}", e));
+                return case;
+            }
+            if i >= self.warmup {
+                compile_times.push(start.elapsed());
+            }
+        }
+        case.compile = TimingStats::from_samples(&compile_times);
+        case.note = Some("AOT output is not runnable yet; compilation timed only".to_string());
+        case
+    }
+}
+
+fn empty_case(mode: CompilationMode, level: OptimizationLevel) -> BenchmarkCase {
+    BenchmarkCase {
+        mode: match mode {
+            CompilationMode::JIT => "jit",
+            CompilationMode::AOT => "aot",
+        }
+        .to_string(),
+        opt_level: match level {
+            OptimizationLevel::None => "O0",
+            OptimizationLevel::Basic => "O1",
+            OptimizationLevel::Standard => "O2",
+            OptimizationLevel::Aggressive => "O3",
+        }
+        .to_string(),
+        compile: None,
+        run: None,
+        result: Vec::new(),
+        note: None,
+    }
+}
+
+fn format_ns(ns: f64) -> String {
+    if ns >= 1e9 {
+        format!("{:.2}s", ns / 1e9)
+    } else if ns >= 1e6 {
+        format!("{:.2}ms", ns / 1e6)
+    } else if ns >= 1e3 {
+        format!("{:.2}µs", ns / 1e3)
+    } else {
+        format!("{:.0}ns", ns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timing_stats() {
+        let samples: Vec<Duration> = [10, 20, 30, 40].iter().map(|&n| Duration::from_nanos(n)).collect();
+        let stats = TimingStats::from_samples(&samples).unwrap();
+        assert_eq!(stats.mean_ns, 25.0);
+        assert_eq!(stats.median_ns, 25.0);
+        assert_eq!(stats.min_ns, 10.0);
+        assert_eq!(stats.max_ns, 40.0);
+        assert!((stats.stddev_ns - 12.909944).abs() < 1e-5);
+        assert!(TimingStats::from_samples(&[]).is_none());
+    }
+
+    #[test]
+    fn test_baseline_regressions() {
+        let stats = |mean_ns| TimingStats { samples: 1, mean_ns, median_ns: mean_ns, stddev_ns: 0.0, min_ns: mean_ns, max_ns: mean_ns };
+        let report = |run_mean, compile_mean| FileBenchmarkReport {
+            file: "f.fs".to_string(),
+            iterations: 1,
+            warmup: 0,
+            cases: vec![
+                BenchmarkCase { run: Some(stats(run_mean)), compile: Some(stats(1.0)), ..empty_case(CompilationMode::JIT, OptimizationLevel::Standard) },
+                BenchmarkCase { compile: Some(stats(compile_mean)), ..empty_case(CompilationMode::AOT, OptimizationLevel::Standard) },
+            ],
+            comparisons: Vec::new(),
+        };
+
+        let baseline = report(100.0, 1000.0);
+        let mut current = report(150.0, 1050.0);
+        current.compare_with(&FileBenchmarkReport::from_json(&baseline.to_json().unwrap()).unwrap(), 10.0);
+
+        assert_eq!(current.comparisons.len(), 2);
+        let regressions: Vec<_> = current.regressions().collect();
+        assert_eq!(regressions.len(), 1);
+        assert_eq!(regressions[0].case, "jit-O2");
+        assert_eq!(regressions[0].metric, "run");
+        assert_eq!(regressions[0].change_percent, 50.0);
+        assert!(current.format().contains("1 regression(s)"));
+    }
+}
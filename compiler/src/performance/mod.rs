@@ -6,10 +6,14 @@
 pub mod modeling;
 pub mod metrics;
 pub mod benchmarks;
+pub mod file_benchmark;
+pub mod calibration;
 
 pub use modeling::{PerformanceModel, PerformancePrediction, PerformanceTarget};
 pub use metrics::{PerformanceMetrics, ExecutionProfile};
 pub use benchmarks::{BenchmarkSuite, BenchmarkResult};
+pub use file_benchmark::{FileBenchmark, FileBenchmarkReport};
+pub use calibration::{CalibratedCosts, Calibrator};
 
 use crate::error::{CompileError, Result};
 use fastforth_optimizer::ForthIR;
@@ -22,13 +26,28 @@ pub struct PerformanceOptimizer {
 
 impl PerformanceOptimizer {
     /// Create a new performance optimizer
+    ///
+    /// Uses the calibrated model saved for this machine, if there is one.
     pub fn new() -> Self {
+        let model = CalibratedCosts::load_default()
+            .map(PerformanceModel::calibrated)
+            .unwrap_or_default();
+        Self::with_model(model)
+    }
+
+    /// Create a performance optimizer with the given model
+    pub fn with_model(model: PerformanceModel) -> Self {
         Self {
-            model: PerformanceModel::new(),
+            model,
             target: None,
         }
     }
 
+    /// Whether predictions use costs calibrated on this machine
+    pub fn is_calibrated(&self) -> bool {
+        self.model.calibration().is_some()
+    }
+
     /// Set a performance target
     pub fn with_target(mut self, target: PerformanceTarget) -> Self {
         self.target = Some(target);
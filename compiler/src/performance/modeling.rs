@@ -6,6 +6,7 @@
 //! - Binary size
 //! - Memory usage
 
+use super::calibration::CalibratedCosts;
 use crate::error::{CompileError, Result};
 use fastforth_optimizer::{ForthIR, Instruction};
 use serde::{Deserialize, Serialize};
@@ -138,6 +139,9 @@ pub struct OperationBreakdown {
 pub struct PerformanceModel {
     /// Operation costs in CPU cycles
     operation_costs: OperationCosts,
+
+    /// Per-instruction costs measured on this machine, if calibrated
+    calibration: Option<CalibratedCosts>,
 }
 
 impl PerformanceModel {
@@ -145,13 +149,31 @@ impl PerformanceModel {
     pub fn new() -> Self {
         Self {
             operation_costs: OperationCosts::default(),
+            calibration: None,
         }
     }
 
+    /// Create a performance model that uses calibrated costs, falling back
+    /// to the default costs for instructions that were not measured
+    pub fn calibrated(calibration: CalibratedCosts) -> Self {
+        Self {
+            operation_costs: OperationCosts::default(),
+            calibration: Some(calibration),
+        }
+    }
+
+    /// Calibrated costs in use, if any
+    pub fn calibration(&self) -> Option<&CalibratedCosts> {
+        self.calibration.as_ref()
+    }
+
     /// Predict performance for the given IR
     pub fn predict(&self, ir: &ForthIR) -> Result<PerformancePrediction> {
         let breakdown = self.analyze_operations(ir);
-        let total_cycles = self.estimate_cycles(&breakdown);
+        let total_cycles = match &self.calibration {
+            Some(calibration) => self.calibrated_cycles(ir, calibration),
+            None => self.estimate_cycles(&breakdown),
+        };
 
         // Model execution speed
         // Assume baseline C implementation takes 1 cycle per operation
@@ -215,7 +237,7 @@ impl PerformanceModel {
                     }
 
                     // Call operations
-                    Instruction::Call(_) | Instruction::Return => {
+                    Instruction::Call(_) | Instruction::Execute | Instruction::Return => {
                         breakdown.call_ops += 1;
                     }
 
@@ -247,6 +269,15 @@ impl PerformanceModel {
         total_cycles
     }
 
+    /// Total CPU cycles from calibrated per-instruction costs
+    fn calibrated_cycles(&self, ir: &ForthIR, calibration: &CalibratedCosts) -> f64 {
+        ir.words
+            .values()
+            .flat_map(|word| &word.instructions)
+            .map(|inst| calibration.cost(inst).unwrap_or_else(|| self.operation_costs.cost_of(inst)))
+            .sum()
+    }
+
     /// Estimate maximum stack depth
     fn estimate_stack_depth(&self, ir: &ForthIR) -> usize {
         // Simple heuristic: count maximum stack operations in any word
@@ -287,6 +318,25 @@ struct OperationCosts {
     stack: f64,
 }
 
+impl OperationCosts {
+    /// Cost of one instruction, by the class `analyze_operations` puts it in
+    fn cost_of(&self, inst: &Instruction) -> f64 {
+        match inst {
+            Instruction::Add | Instruction::Sub |
+            Instruction::Mul | Instruction::Div |
+            Instruction::Mod | Instruction::Neg |
+            Instruction::Abs => self.arithmetic,
+            Instruction::Load | Instruction::Store => self.memory,
+            Instruction::Branch(_) | Instruction::BranchIfNot(_) => self.branch,
+            Instruction::Call(_) | Instruction::Execute | Instruction::Return => self.call,
+            Instruction::Dup | Instruction::Drop |
+            Instruction::Swap | Instruction::Over |
+            Instruction::Rot => self.stack,
+            _ => 0.0,
+        }
+    }
+}
+
 impl Default for OperationCosts {
     fn default() -> Self {
         Self {
@@ -328,4 +378,24 @@ mod tests {
         let model = PerformanceModel::new();
         assert_eq!(model.operation_costs.arithmetic, 1.0);
     }
+
+    #[test]
+    fn test_calibrated_costs_drive_prediction() {
+        use fastforth_optimizer::WordDef;
+        use std::collections::BTreeMap;
+
+        let mut ir = ForthIR::new();
+        ir.add_word(WordDef::new("square".to_string(), vec![Instruction::Dup, Instruction::Mul]));
+
+        // Static costs: 0.5 + 1.0 cycles for two operations
+        let uncalibrated = PerformanceModel::new().predict(&ir).unwrap();
+        assert!((uncalibrated.speed_ratio - 2.0 / 1.5).abs() < 1e-9);
+
+        // Multiplication measured at 3 cycles; dup falls back to 0.5
+        let ns = BTreeMap::from([("Add".to_string(), 0.3), ("Mul".to_string(), 0.9)]);
+        let model = PerformanceModel::calibrated(CalibratedCosts::from_nanoseconds(ns));
+        let calibrated = model.predict(&ir).unwrap();
+        assert!((calibrated.speed_ratio - 2.0 / 3.5).abs() < 1e-9);
+        assert!(!calibrated.meets_target(&PerformanceTarget::new(0.9)));
+    }
 }
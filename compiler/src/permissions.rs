@@ -0,0 +1,126 @@
+//! Permissions
+//!
+//! What a running program may reach outside its memory: the directories
+//! it may read and write files under, and whether SYSTEM may run commands.
+//! The runtime checks OPEN-FILE, CREATE-FILE, DELETE-FILE, the block file
+//! and SYSTEM against the installed permissions; a refused access reports
+//! the path or command on stderr and fails with
+//! [`PERMISSION_DENIED`] as its ior (SYSTEM's status).
+//!
+//! Permissions are per process, like the program arguments
+//! ([`set_program_args`](crate::runtime_ffi::set_program_args)): they apply
+//! to every JIT run from [`install`](Permissions::install) until
+//! [`uninstall`](Permissions::uninstall). Unlike a
+//! [`Sandbox`](crate::Sandbox), nothing is refused at compile time.
+
+use crate::error::{CompileError, Result};
+use crate::runtime_ffi::{forth_permissions_allow, forth_permissions_reset, forth_permissions_restrict, CellT};
+use std::ffi::CString;
+use std::path::PathBuf;
+
+/// The ior of a refused file access, and the status of a refused SYSTEM
+pub const PERMISSION_DENIED: i64 = -257;
+
+/// Most directories the runtime accepts in all (`FORTH_MAX_PERMITTED_DIRS`)
+const MAX_DIRS: usize = 32;
+
+/// Capabilities granted to a running program
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Permissions {
+    /// Directories whose files may be read, recursively
+    pub read: Vec<PathBuf>,
+    /// Directories whose files may be created, written and deleted
+    pub write: Vec<PathBuf>,
+    /// Whether SYSTEM may run commands
+    pub run: bool,
+}
+
+impl Permissions {
+    /// Read and write access to the current directory and nothing else,
+    /// what `fastforth run` grants before its `--allow-*` flags
+    pub fn current_dir() -> Result<Self> {
+        let dir = std::env::current_dir().map_err(|e| CompileError::IoError(PathBuf::from("."), e))?;
+        Ok(Self { read: vec![dir.clone()], write: vec![dir], run: false })
+    }
+
+    /// Enforce these permissions on the code this process runs
+    ///
+    /// Fails if a directory cannot be resolved, leaving the process
+    /// unrestricted.
+    pub fn install(&self) -> Result<()> {
+        if self.read.len() + self.write.len() > MAX_DIRS {
+            return Err(CompileError::RuntimeError(format!(
+                "at most {} directories can be allowed, {} were given",
+                MAX_DIRS,
+                self.read.len() + self.write.len()
+            )));
+        }
+        let dirs = self
+            .read
+            .iter()
+            .map(|dir| (dir, false))
+            .chain(self.write.iter().map(|dir| (dir, true)))
+            .map(|(dir, write)| {
+                let canonical = dir.canonicalize().map_err(|e| CompileError::IoError(dir.clone(), e))?;
+                let path = CString::new(canonical.as_os_str().as_encoded_bytes())
+                    .map_err(|_| CompileError::RuntimeError(format!("'{}' contains a NUL byte", dir.display())))?;
+                Ok((path, write))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        unsafe {
+            forth_permissions_reset();
+            forth_permissions_restrict(self.run as CellT);
+            for (dir, write) in &dirs {
+                // Resolved above and within MAX_DIRS, so the runtime takes it
+                forth_permissions_allow(dir.as_ptr(), *write as CellT);
+            }
+        }
+        Ok(())
+    }
+
+    /// Lift any installed permissions, leaving the process unrestricted
+    pub fn uninstall() {
+        unsafe { forth_permissions_reset() };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompilationMode, Compiler, OptimizationLevel};
+
+    #[test]
+    fn test_runs_reach_only_permitted_files_and_commands() {
+        let _files = crate::runtime_ffi::FILE_ACCESS.lock().unwrap_or_else(|e| e.into_inner());
+        let root = std::env::temp_dir().join(format!("fastforth-permissions-{}", std::process::id()));
+        let allowed = root.join("allowed");
+        let other = root.join("other");
+        std::fs::create_dir_all(&allowed).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+        std::fs::write(other.join("secret.txt"), "secret").unwrap();
+
+        let compiler = Compiler::new(OptimizationLevel::None);
+        let run = |source: String| compiler.compile_string(&source, CompilationMode::JIT).unwrap().jit_result;
+        let create = |path: PathBuf| format!("\"{}\" w/o create-file swap drop", path.display());
+        let open = |path: PathBuf| format!("\"{}\" r/o open-file swap drop", path.display());
+        let escape = allowed.join("../other/secret.txt");
+
+        Permissions { read: vec![allowed.clone()], write: vec![allowed.clone()], run: false }.install().unwrap();
+        let inside = run(create(allowed.join("out.txt")));
+        let outside = run(create(other.join("out.txt")));
+        let through_parent = run(open(escape.clone()));
+        let command = run("\"true\" system".to_string());
+        Permissions::uninstall();
+        let unrestricted = run(open(escape));
+
+        assert_eq!(inside, Some(0));
+        assert!(allowed.join("out.txt").exists());
+        assert_eq!(outside, Some(PERMISSION_DENIED));
+        assert!(!other.join("out.txt").exists());
+        assert_eq!(through_parent, Some(PERMISSION_DENIED));
+        assert_eq!(command, Some(PERMISSION_DENIED));
+        assert_eq!(unrestricted, Some(0));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -6,12 +6,41 @@
 //! 3. Backend: LLVM IR generation → Native code
 //! 4. Execution: JIT or AOT
 
-use crate::error::{CompileError, Result};
-use fastforth_frontend::{parse_program, analyze, convert_to_ssa, Program, SSAFunction};
-use fastforth_optimizer::{ForthIR, Optimizer, OptimizationLevel, Instruction};
+use crate::backend::{Backend, BackendType};
+use crate::error::{CompileError, FrontendStage, Result};
+use fastforth_frontend::{
+    ans, constant_time, devirtualize, freestanding, parse_program, quotation, sandbox, analyze, convert_to_ssa, convert_to_ssa_with_stack_buffer, Arithmetic, Attributes, CodeWord,
+    ForthError, InlineHint, InternStats, Program, SSAFunction, Word,
+};
+use fastforth_frontend::ssa::{runtime_io_word, MemoryWidth};
+use fastforth_optimizer::ir::WordAttributes;
+use fastforth_optimizer::differential;
+use fastforth_optimizer::lower::lower_program;
+use fastforth_optimizer::memory_opt::is_sync_word;
+use fastforth_optimizer::whole_program::CallGraph;
+use fastforth_optimizer::{
+    EvalCache, ForthIR, Optimizer, OptimizationLevel, OptimizationReport, InlineDirective, Instruction, PassPipeline,
+    PeepholeRules, SuperinstructionTable,
+};
+use backend::linker::{CExport, Lto};
+use backend::source_map::{source_map_path, SourceMap, SourceRange};
+use fastforth_frontend::ast::{SourceLocation, StackEffect, StackType};
 use tracing::{debug, info, warn};
+use crate::trace::{CompilationTrace, Span};
+use crate::heap::{HeapConfig, HeapSession};
+use crate::memory;
+use crate::cancel::CancelToken;
+use crate::sandbox::Sandbox;
+use crate::recording::{self, RecordingSession};
+use crate::runtime_profile::{RuntimeComponent, RuntimeProfile, RuntimeRequirements};
+use crate::tiered::TieredEngine;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Instant;
 
+/// Maximum number of data stack cells a JIT-executed program may leave behind
+pub const JIT_STACK_CAPACITY: usize = 256;
+
 /// Compilation mode
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CompilationMode {
@@ -34,8 +63,41 @@ pub struct CompilationResult {
     pub output_path: Option<String>,
     /// JIT execution result (for JIT mode)
     pub jit_result: Option<i64>,
+    /// Data stack left by JIT execution, bottom first
+    pub stack: Vec<i64>,
+    /// Optimized IR (only when IR retention is enabled)
+    pub ir: Option<ForthIR>,
+    /// Backend that generated the code
+    pub backend: BackendType,
+    /// Non-fatal diagnostics, such as a backend fallback
+    pub warnings: Vec<String>,
     /// Optimization statistics
     pub stats: CompilationStats,
+    /// What each optimizer pass did (only when reporting is enabled and the
+    /// optimizer ran)
+    pub opt_report: Option<OptimizationReport>,
+}
+
+/// Result of checking a program without generating code
+#[derive(Debug, Clone)]
+pub struct VerificationResult {
+    /// Number of definitions checked
+    pub definitions_count: usize,
+    /// Time taken in milliseconds
+    pub verify_time_ms: u64,
+}
+
+/// A shared library of a program's `@export` words
+#[derive(Debug, Clone)]
+pub struct SharedLibrary {
+    /// The library file
+    pub library: PathBuf,
+    /// C header declaring the exports, next to the library
+    pub header: PathBuf,
+    /// Source map of the library's words, next to it
+    pub source_map: PathBuf,
+    /// Exported words, in source order
+    pub exports: Vec<CExport>,
 }
 
 /// Compilation statistics
@@ -53,6 +115,10 @@ pub struct CompilationStats {
     pub optimization_time_ms: u64,
     /// Backend time in milliseconds
     pub backend_time_ms: u64,
+    /// Word names and string literals the parser shared
+    pub names: InternStats,
+    /// Peak resident memory of the process in bytes, where it can be read
+    pub peak_memory_bytes: Option<u64>,
 }
 
 impl CompilationStats {
@@ -66,10 +132,133 @@ impl CompilationStats {
     }
 }
 
+/// JIT-compiled program that can be run repeatedly
+pub struct JitProgram {
+    /// Owns the memory the compiled code lives in
+    backend: backend::cranelift::CraneliftBackend,
+    definitions: Vec<String>,
+    entry: Option<*const u8>,
+    /// Code of every compiled function, the entry point included
+    source_map: SourceMap,
+}
+
+// SAFETY: the compiled code is finalized and never written again, and the
+// backend only hands out its addresses, so the program can be run and its
+// words called from any thread
+unsafe impl Send for JitProgram {}
+unsafe impl Sync for JitProgram {}
+
+/// A function of the host program that compiled code calls like a CODE
+/// word: a C ABI function taking the word's inputs, deepest first, and
+/// returning its output, if the effect declares one
+#[derive(Debug, Clone)]
+pub struct HostFunction {
+    /// Name the program calls the function by
+    pub name: String,
+    pub stack_effect: StackEffect,
+    pub address: *const u8,
+}
+
+impl JitProgram {
+    /// Whether the program has top-level code to run
+    pub fn has_entry(&self) -> bool {
+        self.entry.is_some()
+    }
+
+    /// Address of a compiled definition
+    ///
+    /// The word takes its inputs as `i64` arguments, deepest first, and
+    /// returns the cell it leaves with the platform C calling convention.
+    /// A word leaving nothing returns nothing, and one leaving several
+    /// cells returns them as a struct of `i64`s, deepest first.
+    pub fn word(&self, name: &str) -> Option<*const u8> {
+        if !self.definitions.iter().any(|def| def == name) {
+            return None;
+        }
+        self.backend.get_function(name)
+    }
+
+    /// Where the code of every compiled function, the entry point
+    /// included, lies and which line it is defined on
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    /// Execute the entry point, returning the data stack it leaves
+    ///
+    /// Returns `None` if the program only defines words.
+    pub fn run(&self) -> Option<Vec<i64>> {
+        let entry = self.entry?;
+
+        // The entry point spills the data stack into the buffer and returns its depth
+        type EntryFn = unsafe extern "C" fn(*mut i64) -> i64;
+        let entry_fn: EntryFn = unsafe { std::mem::transmute(entry) };
+        let mut stack = vec![0i64; JIT_STACK_CAPACITY];
+        let depth = unsafe { entry_fn(stack.as_mut_ptr()) };
+        stack.truncate(depth.clamp(0, JIT_STACK_CAPACITY as i64) as usize);
+
+        Some(stack)
+    }
+
+    /// Execute the entry point under `cancel`, like [`run`](Self::run)
+    ///
+    /// The program must have been compiled interruptible to stop before it
+    /// returns; see [`crate::cancel`].
+    pub fn run_cancellable(&self, cancel: &CancelToken) -> Result<Option<Vec<i64>>> {
+        self.run_with_fuel(cancel, None)
+    }
+
+    /// Name the words of a trap snapshot
+    pub(crate) fn trapped(&self, error: CompileError) -> CompileError {
+        match error {
+            CompileError::Trapped(mut snapshot) => {
+                snapshot.resolve(self);
+                CompileError::Trapped(snapshot)
+            }
+            error => error,
+        }
+    }
+
+    /// [`run_cancellable`](Self::run_cancellable) that also stops with
+    /// [`CompileError::OutOfFuel`] after `fuel` word calls and loop
+    /// iterations
+    pub fn run_with_fuel(&self, cancel: &CancelToken, fuel: Option<u64>) -> Result<Option<Vec<i64>>> {
+        let Some(entry) = self.entry else {
+            return Ok(None);
+        };
+
+        let mut stack = vec![0i64; JIT_STACK_CAPACITY];
+        // The entry point takes the stack buffer and returns its depth
+        let depth = unsafe { cancel.call_with_fuel(entry, &[stack.as_mut_ptr() as i64], fuel) }
+            .map_err(|error| self.trapped(error))?;
+        stack.truncate(depth.clamp(0, JIT_STACK_CAPACITY as i64) as usize);
+        Ok(Some(stack))
+    }
+}
+
 /// The main compilation pipeline
 pub struct CompilationPipeline {
     optimization_level: OptimizationLevel,
     optimizer: Optimizer,
+    retain_ir: bool,
+    verify_optimizations: bool,
+    opt_report: bool,
+    ans_strict: bool,
+    constant_time: bool,
+    freestanding: bool,
+    interruptible: bool,
+    cancel: Option<CancelToken>,
+    sandbox: Option<Sandbox>,
+    recording: Option<usize>,
+    trap_snapshot: Option<PathBuf>,
+    arithmetic: Arithmetic,
+    backend: Backend,
+    trace: Option<Arc<CompilationTrace>>,
+    memory_limit: Option<u64>,
+    codegen_units: Option<usize>,
+    lto: Lto,
+    runtime_profile: RuntimeProfile,
+    heap: HeapConfig,
 }
 
 impl CompilationPipeline {
@@ -78,41 +267,364 @@ impl CompilationPipeline {
         Self {
             optimization_level,
             optimizer: Optimizer::new(optimization_level),
+            retain_ir: false,
+            verify_optimizations: false,
+            opt_report: false,
+            ans_strict: false,
+            constant_time: false,
+            freestanding: false,
+            interruptible: false,
+            cancel: None,
+            sandbox: None,
+            recording: None,
+            trap_snapshot: None,
+            arithmetic: Arithmetic::default(),
+            backend: Backend::Auto,
+            trace: None,
+            memory_limit: None,
+            codegen_units: None,
+            lto: Lto::Off,
+            runtime_profile: RuntimeProfile::Full,
+            heap: HeapConfig::default(),
+        }
+    }
+
+    /// Choose the code generation backend
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    /// Requested code generation backend
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Take the compile-time evaluation of unchanged words from `cache`
+    pub fn set_eval_cache(&mut self, cache: Option<EvalCache>) {
+        self.optimizer.set_eval_cache(cache);
+    }
+
+    /// The evaluation cache, with the results of the last compilation
+    pub fn take_eval_cache(&mut self) -> Option<EvalCache> {
+        self.optimizer.take_eval_cache()
+    }
+
+    /// Everything that changes what compile-time evaluation computes: an
+    /// evaluation cache written under other flags is discarded
+    pub fn eval_cache_flags(&self) -> String {
+        format!(
+            "fastforth {} {:?} {:?} {}",
+            env!("CARGO_PKG_VERSION"),
+            self.optimization_level,
+            self.arithmetic,
+            self.optimizer.pipeline()
+        )
+    }
+
+    /// Take the profile of a run on the tiered engine: among others, the
+    /// words EXECUTE reached, where compiled code tests for the one reached
+    /// most before calling through a token
+    pub fn profile_run(&mut self, engine: &TieredEngine) {
+        let pgo = self.optimizer.pgo_mut();
+        pgo.enable_profiling();
+        engine.profile(pgo);
+    }
+
+    /// Enable or disable loop vectorization in the optimizer
+    pub fn set_vectorize(&mut self, vectorize: bool) {
+        self.optimizer.set_vectorize(vectorize);
+    }
+
+    /// Run every compiled program unoptimized and optimized on the reference
+    /// interpreter, and fail with [`CompileError::Miscompile`] if they
+    /// differ
+    pub fn set_verify_optimizations(&mut self, verify: bool) {
+        self.verify_optimizations = verify;
+    }
+
+    /// Fuse the sequences of a learned superinstruction table
+    pub fn set_superinstruction_table(&mut self, table: &SuperinstructionTable) {
+        self.optimizer.set_superinstruction_table(table);
+    }
+
+    /// Apply user-defined peephole rules
+    pub fn set_peephole_rules(&mut self, rules: &PeepholeRules) {
+        self.optimizer.set_peephole_rules(rules);
+    }
+
+    /// Run a pass pipeline instead of the preset of the optimization level
+    pub fn set_pass_pipeline(&mut self, pipeline: PassPipeline) -> Result<()> {
+        Ok(self.optimizer.set_pipeline(pipeline)?)
+    }
+
+    /// Keep the optimized per-word IR in the compilation result
+    ///
+    /// JIT compilation normally skips the optimizer entirely; with retention
+    /// enabled the IR is still produced so tools can display it.
+    pub fn set_retain_ir(&mut self, retain: bool) {
+        self.retain_ir = retain;
+    }
+
+    /// Warn about every word a program uses outside the standard word
+    /// sets, unless the program defines it
+    pub fn set_ans_strict(&mut self, strict: bool) {
+        self.ans_strict = strict;
+    }
+
+    /// Compile every definition as if marked `@constant-time`
+    pub fn set_constant_time(&mut self, constant_time: bool) {
+        self.constant_time = constant_time;
+    }
+
+    /// Compile for the libc-free runtime: words that need files, blocks
+    /// or the process environment become compile-time errors
+    pub fn set_freestanding(&mut self, freestanding: bool) {
+        self.freestanding = freestanding;
+    }
+
+    /// Make JIT code poll for cancellation on entry to every word and at
+    /// the head of every loop
+    pub fn set_interruptible(&mut self, interruptible: bool) {
+        self.interruptible = interruptible;
+    }
+
+    /// Run JIT top-level code under `cancel`; the code is compiled
+    /// interruptible
+    pub fn set_cancel(&mut self, cancel: Option<CancelToken>) {
+        self.cancel = cancel;
+    }
+
+    /// Compile untrusted source: words that reach outside the process's
+    /// memory are rejected, and JIT runs are compiled interruptible and
+    /// held to the sandbox's limits
+    pub fn set_sandbox(&mut self, sandbox: Option<Sandbox>) {
+        self.sandbox = sandbox;
+    }
+
+    /// Make JIT code record every word entry and exit, keeping the last
+    /// `events` of a run for a post-mortem (see [`crate::recording`])
+    pub fn set_recording(&mut self, events: Option<usize>) {
+        self.recording = events;
+    }
+
+    /// Write the [`Snapshot`](crate::snapshot::Snapshot) of a JIT run that
+    /// traps to `path`, as JSON
+    pub fn set_trap_snapshot(&mut self, path: Option<PathBuf>) {
+        self.trap_snapshot = path;
+    }
+
+    /// Divide and overflow as `arithmetic` says, in constant folding and in
+    /// the code either backend generates
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
+        self.optimizer.set_arithmetic(arithmetic);
+    }
+
+    /// Record an optimization report for each compilation
+    ///
+    /// JIT compilation skips the optimizer, so with a report requested it
+    /// also runs the optimizer over the program, only to report on it.
+    pub fn set_opt_report(&mut self, enabled: bool) {
+        self.opt_report = enabled;
+        self.optimizer.set_report(enabled);
+    }
+
+    /// Record the time spent in each stage, pass and word in a trace
+    pub fn set_trace(&mut self, trace: Option<Arc<CompilationTrace>>) {
+        self.optimizer.set_timing(trace.is_some());
+        self.trace = trace;
+    }
+
+    /// Stop with [`CompileError::MemoryLimitExceeded`] once the process
+    /// holds more than `limit` bytes at the end of a stage
+    pub fn set_memory_limit(&mut self, limit: Option<u64>) {
+        self.memory_limit = limit;
+    }
+
+    /// Split LLVM code generation into at most `units` modules compiled
+    /// in parallel (`--codegen-units`); `None` uses one per core
+    ///
+    /// Cranelift already compiles words across the thread pool and ignores
+    /// this.
+    pub fn set_codegen_units(&mut self, units: Option<usize>) {
+        self.codegen_units = units;
+    }
+
+    /// The codegen unit limit, if one was set
+    pub fn codegen_units(&self) -> Option<usize> {
+        self.codegen_units
+    }
+
+    /// Emit LLVM bitcode and optimize it with the runtime at link time
+    /// (`--lto`)
+    pub fn set_lto(&mut self, lto: Lto) {
+        self.lto = lto;
+    }
+
+    /// Cap the runtime components a linked program may use
+    /// (`--runtime-profile`)
+    pub fn set_runtime_profile(&mut self, profile: RuntimeProfile) {
+        self.runtime_profile = profile;
+    }
+
+    /// Choose the allocator behind `allocate` in JIT runs, and whether
+    /// to check frees and report leaks (`--heap`, `--heap-debug`)
+    pub fn set_heap(&mut self, heap: HeapConfig) {
+        self.heap = heap;
+    }
+
+    /// The runtime components optimized code calls, checked against the
+    /// runtime profile
+    fn runtime_components(&self, ssa_functions: &[SSAFunction]) -> Result<Vec<RuntimeComponent>> {
+        let requirements = RuntimeRequirements::scan(ssa_functions);
+        requirements.check(self.runtime_profile)?;
+        debug!("Runtime components: {:?}", requirements.components());
+        Ok(requirements.components())
+    }
+
+    /// Check the memory limit at the end of a stage
+    fn check_memory(&self, stage: &'static str) -> Result<()> {
+        let Some(limit) = self.memory_limit else {
+            return Ok(());
+        };
+        match memory::resident_bytes() {
+            Some(used) if used > limit => Err(CompileError::MemoryLimitExceeded { stage, used, limit }),
+            _ => Ok(()),
         }
     }
 
+    /// Start a span of the trace, when tracing
+    fn span(&self, name: &str, category: &'static str) -> Option<Span> {
+        self.trace.as_ref().map(|trace| trace.span(name, category))
+    }
+
+    /// Parse source code, as the `parse` stage of the trace
+    fn parse(&self, source: &str) -> Result<Program> {
+        let span = self.span("parse", "phase");
+        let program = parse_program(source)?;
+        drop(span);
+        self.check_memory("parse")?;
+        Ok(program)
+    }
+
     /// Compile Forth source code
     pub fn compile(&mut self, source: &str, mode: CompilationMode) -> Result<CompilationResult> {
+        let frontend_start = Instant::now();
+
+        debug!("Parsing source code...");
+        let program = self.parse(source)?;
+
+        let mut result = self.compile_program(&program, mode).map_err(|error| self.trapped(error, source))?;
+        result.stats.frontend_time_ms = frontend_start.elapsed().as_millis() as u64;
+        Ok(result)
+    }
+
+    /// Complete the snapshot of a trapped run with what the source tells of
+    /// its words, and write it out if asked to
+    fn trapped(&self, error: CompileError, source: &str) -> CompileError {
+        let CompileError::Trapped(mut snapshot) = error else {
+            return error;
+        };
+        snapshot.add_provenance(source);
+        if let Some(path) = &self.trap_snapshot {
+            if let Err(e) = snapshot.write(path) {
+                warn!("cannot write the trap snapshot: {}", e);
+            }
+        }
+        CompileError::Trapped(snapshot)
+    }
+
+    /// Parse source code and lower it to IR without optimizing
+    pub fn lower_source(&self, source: &str) -> Result<ForthIR> {
+        let program = self.parse(source)?;
+        Ok(self.lower_to_ir(&program))
+    }
+
+    /// Check source code without generating code
+    ///
+    /// Runs the frontend as compilation does: semantic analysis, which
+    /// checks declared stack effects against inferred ones, then SSA
+    /// conversion and validation. Optimization and code generation are
+    /// skipped.
+    pub fn verify(&self, source: &str) -> Result<VerificationResult> {
+        let start_time = Instant::now();
+        let program = self.parse(source)?;
+        self.run_frontend(&program, CompilationMode::AOT)?;
+
+        Ok(VerificationResult {
+            definitions_count: program.compiled_definitions().count(),
+            verify_time_ms: start_time.elapsed().as_millis() as u64,
+        })
+    }
+
+    /// Compile an already parsed program
+    pub fn compile_program(&mut self, program: &Program, mode: CompilationMode) -> Result<CompilationResult> {
         let start_time = Instant::now();
         let mut stats = CompilationStats::default();
 
         info!("Starting compilation in {:?} mode", mode);
 
-        // Phase 1: Frontend (Parsing, Semantic Analysis, Type Inference, SSA)
+        let resolved = self.backend.resolve(self.optimization_level, mode)?;
+        let mut warnings = Vec::new();
+        if let Some(diagnostic) = resolved.diagnostic {
+            warn!("{}", diagnostic);
+            warnings.push(diagnostic);
+        }
+        if self.ans_strict {
+            for (name, location) in ans::nonstandard_words(program) {
+                let warning = format!("nonstandard word '{}' at line {}", name, location.line);
+                warn!("{}", warning);
+                warnings.push(warning);
+            }
+        }
+        debug!("Using {:?} backend", resolved.backend_type);
+
+        // Phase 1: Frontend (Semantic Analysis, Type Inference, SSA)
         let frontend_start = Instant::now();
-        let (program, ssa_functions) = self.run_frontend(source)?;
+        let ssa_functions = self.run_frontend(program, mode)?;
         stats.frontend_time_ms = frontend_start.elapsed().as_millis() as u64;
-        stats.definitions_count = program.definitions.len();
+        stats.definitions_count = program.compiled_definitions().count();
+        stats.names = program.names;
+        for dependence in self.secret_dependences(program, &ssa_functions) {
+            let warning = dependence.to_string();
+            warn!("{}", warning);
+            warnings.push(warning);
+        }
 
         debug!("Frontend complete: {} definitions", stats.definitions_count);
 
+        if self.verify_optimizations {
+            self.verify_optimizations(program)?;
+        }
+
         // Phase 2-4: Backend code generation
         // JIT mode: Skip optimization for faster compilation
         // AOT mode: Use full optimization pipeline
         let backend_start = Instant::now();
+        let mut retained_ir = None;
+        let mut stack = Vec::new();
         let result = match mode {
             CompilationMode::JIT => {
                 debug!("JIT mode: Skipping optimization for fast compilation");
-                self.compile_jit(&ssa_functions, &mut stats)?
+                if self.retain_ir || self.opt_report {
+                    let optimized = self.retained_ir(program);
+                    retained_ir = self.retain_ir.then_some(optimized);
+                }
+                let has_entry = !program.top_level_code.is_empty();
+                stack = self.compile_jit(&ssa_functions, &program.code_words, has_entry, &mut warnings)?;
+                (None, None, stack.last().copied())
             }
             CompilationMode::AOT => {
                 // Phase 2: Convert SSA to Optimizer IR
-                let ir = self.convert_to_ir(&ssa_functions)?;
+                let lowering = self.span("lower to IR", "phase");
+                let ir = self.convert_to_ir(program, &ssa_functions)?;
+                drop(lowering);
                 stats.instructions_before = self.count_instructions(&ir);
 
                 // Phase 3: Optimization
                 let optimization_start = Instant::now();
                 let optimized_ir = self.run_optimizer(ir)?;
+                self.check_memory("optimize")?;
                 stats.optimization_time_ms = optimization_start.elapsed().as_millis() as u64;
                 stats.instructions_after = self.count_instructions(&optimized_ir);
 
@@ -122,10 +634,20 @@ impl CompilationPipeline {
                 );
 
                 // Phase 4: AOT compilation
-                self.compile_aot(&optimized_ir, &mut stats)?
+                let result = match resolved.backend_type {
+                    #[cfg(feature = "llvm")]
+                    BackendType::LLVM => self.compile_llvm(&ssa_functions, &optimized_ir)?,
+                    _ => self.compile_aot(&optimized_ir, &mut stats)?,
+                };
+                self.check_memory("codegen")?;
+                if self.retain_ir {
+                    retained_ir = Some(optimized_ir);
+                }
+                result
             }
         };
         stats.backend_time_ms = backend_start.elapsed().as_millis() as u64;
+        stats.peak_memory_bytes = memory::peak_resident_bytes();
 
         let compile_time_ms = start_time.elapsed().as_millis() as u64;
 
@@ -143,46 +665,171 @@ impl CompilationPipeline {
             code_size: result.0,
             output_path: result.1,
             jit_result: result.2,
+            stack,
+            ir: retained_ir,
+            backend: resolved.backend_type,
+            warnings,
             stats,
+            opt_report: self.optimizer.take_report(),
         })
     }
 
-    /// Run the frontend pipeline
-    fn run_frontend(&self, source: &str) -> Result<(Program, Vec<SSAFunction>)> {
-        // Step 1: Parse
-        debug!("Parsing source code...");
-        let program = parse_program(source)
-            .map_err(|e| CompileError::ParseError(format!("{}", e)))?;
+    /// Run the frontend pipeline on a parsed program
+    fn run_frontend(&self, program: &Program, mode: CompilationMode) -> Result<Vec<SSAFunction>> {
+        let mut span = self.span("frontend", "phase");
+        if let Some(span) = span.as_mut() {
+            span.arg("definitions", program.compiled_definitions().count());
+        }
 
-        // Step 2: Semantic analysis
+        // Step 1: Semantic analysis
         debug!("Running semantic analysis...");
-        analyze(&program)
-            .map_err(|e| CompileError::SemanticError(format!("{}", e)))?;
+        let semantic = self.span("semantic analysis", "frontend");
+        // Untrusted source is refused before anything else looks at it
+        if self.sandbox.is_some() {
+            sandbox::check_sandbox(program)
+                .map_err(|e| CompileError::frontend(FrontendStage::Semantic, e))?;
+        }
+        analyze(program)
+            .map_err(|e| CompileError::frontend(FrontendStage::Semantic, e))?;
+        if self.freestanding {
+            freestanding::check_freestanding(program)
+                .map_err(|e| CompileError::frontend(FrontendStage::Semantic, e))?;
+        }
+        drop(semantic);
 
-        // Step 3: Type inference happens inside convert_to_ssa
+        // Step 2: Type inference happens inside convert_to_ssa
 
-        // Step 4: Convert to SSA
+        // Step 3: Convert to SSA
+        // JIT entry points spill the data stack so the caller can inspect it
         debug!("Converting to SSA...");
-        let ssa_functions = convert_to_ssa(&program)
-            .map_err(|e| CompileError::SSAError(format!("{}", e)))?;
+        let ssa_span = self.span("type inference and SSA", "frontend");
+        let ssa_functions = match mode {
+            CompilationMode::JIT => convert_to_ssa_with_stack_buffer(program, JIT_STACK_CAPACITY),
+            CompilationMode::AOT => convert_to_ssa(program),
+        }
+        .map_err(|e| CompileError::frontend(FrontendStage::SSA, e))?;
+
+        // Step 4: EXECUTE calls the word it can only reach directly, and
+        // tests first for the word a profile saw it reach most
+        let mut ssa_functions = ssa_functions;
+        if self.optimization_level != OptimizationLevel::None {
+            let targets: Vec<_> = quotation::execution_targets(program).into_iter().map(|(name, _)| name).collect();
+            let likely = self.optimizer.pgo().likely_execute_target();
+            for func in ssa_functions.iter_mut() {
+                // Testing a secret token would branch on it
+                let likely = likely.filter(|_| !self.is_constant_time(program, &func.name));
+                let count = devirtualize::devirtualize(std::slice::from_mut(func), &targets, likely);
+                if count != devirtualize::Devirtualized::default() {
+                    debug!("Devirtualized EXECUTE in '{}': {:?}", func.name, count);
+                }
+            }
+        }
+
+        // Step 5: Constant-time words lose the branches they can do without
+        for func in ssa_functions.iter_mut().filter(|func| self.is_constant_time(program, &func.name)) {
+            let removed = constant_time::lower_branchless(func);
+            debug!("Removed {} branches from constant-time '{}'", removed, func.name);
+        }
 
-        // Step 5: Validate SSA form
+        // Step 6: Validate SSA form
         debug!("Validating SSA invariants...");
         for func in &ssa_functions {
             func.validate()
-                .map_err(|e| CompileError::SSAError(format!("SSA validation failed for {}: {}", func.name, e)))?;
+                .map_err(|e| CompileError::frontend(FrontendStage::SSA, e))?;
         }
         debug!("SSA validation passed for {} functions", ssa_functions.len());
+        drop(ssa_span);
+        drop(span);
+        self.check_memory("frontend")?;
+
+        Ok(ssa_functions)
+    }
+
+    /// Whether a word is compiled constant-time: marked `@constant-time`,
+    /// or any definition with `--constant-time`
+    ///
+    /// CODE words count as constant-time, since they are assembled exactly
+    /// as written.
+    fn is_constant_time(&self, program: &Program, name: &str) -> bool {
+        program.code_words.iter().any(|word| word.name == name)
+            || program
+                .definitions
+                .iter()
+                .any(|def| def.name == name && (self.constant_time || def.attributes.constant_time))
+    }
+
+    /// Branches and calls left in constant-time words that depend on their data
+    fn secret_dependences(&self, program: &Program, ssa_functions: &[SSAFunction]) -> Vec<constant_time::SecretDependence> {
+        let is_constant_time = |name: &str| self.is_constant_time(program, name);
+        ssa_functions
+            .iter()
+            .filter(|func| self.is_constant_time(program, &func.name))
+            .flat_map(|func| constant_time::secret_dependences(func, &is_constant_time))
+            .collect()
+    }
+
+    /// Build optimized IR for display alongside a JIT compilation
+    ///
+    /// Optimizer failures are not fatal: the program still runs and the
+    /// unoptimized IR is retained instead.
+    fn retained_ir(&mut self, program: &Program) -> ForthIR {
+        let ir = self.lower_to_ir(program);
+
+        match self.run_optimizer(ir.clone()) {
+            Ok(optimized) => optimized,
+            Err(e) => {
+                warn!("Retaining unoptimized IR: {}", e);
+                ir
+            }
+        }
+    }
+
+    /// Check that the optimizer preserves what `program` computes, running
+    /// it before and after optimization on random inputs
+    ///
+    /// The check runs on IR lowered from the AST, which the interpreter
+    /// executes faithfully, with the same optimizer compilation uses. A
+    /// program the optimizer rejects is not checked; compilation reports
+    /// that where it optimizes.
+    fn verify_optimizations(&mut self, program: &Program) -> Result<()> {
+        let _span = self.span("verify optimizations", "phase");
+        let ir = self.lower_to_ir(program);
+        let optimized = match self.optimizer.optimize(ir.clone()) {
+            Ok(optimized) => optimized,
+            Err(e) => {
+                warn!("Not verifying optimizations: {}", e);
+                return Ok(());
+            }
+        };
+        differential::verify(&ir, &optimized, differential::DEFAULT_TRIALS)
+            .map_err(|divergence| CompileError::Miscompile(divergence.to_string()))
+    }
+
+    /// Which words call which in `program`, with the top-level code as
+    /// `__main__`
+    pub fn call_graph(&self, program: &Program) -> CallGraph {
+        CallGraph::build(&self.lower_to_ir(program))
+    }
 
-        Ok((program, ssa_functions))
+    /// Lower the AST directly to optimizer IR
+    ///
+    /// Unlike `convert_to_ir`, this keeps stack manipulation words, so the
+    /// result reads like the original definitions.
+    pub(crate) fn lower_to_ir(&self, program: &Program) -> ForthIR {
+        lower_program(program, |attributes| self.word_attributes(attributes))
     }
 
     /// Convert frontend SSA to optimizer IR
-    fn convert_to_ir(&self, ssa_functions: &[SSAFunction]) -> Result<ForthIR> {
+    fn convert_to_ir(&self, program: &Program, ssa_functions: &[SSAFunction]) -> Result<ForthIR> {
         debug!("Converting SSA to optimizer IR...");
 
         // Create a new ForthIR
         let mut ir = ForthIR::new();
+        for word in &program.top_level_code {
+            if let Word::Variable { name, size, .. } = word {
+                ir.add_buffer(name.clone(), *size);
+            }
+        }
 
         // Convert each SSA function to IR instructions
         for func in ssa_functions {
@@ -190,7 +837,10 @@ impl CompilationPipeline {
 
             // Create a word definition for this function
             use fastforth_optimizer::ir::WordDef;
-            let word_def = WordDef::new(func.name.clone(), instructions);
+            let mut word_def = WordDef::new(func.name.clone(), instructions);
+            if let Some(def) = program.definitions.iter().find(|def| def.name == func.name) {
+                word_def.attributes = self.word_attributes(&def.attributes);
+            }
             ir.add_word(word_def);
         }
 
@@ -232,6 +882,17 @@ impl CompilationPipeline {
                             BinaryOperator::Ne => Instruction::Ne,
                             BinaryOperator::And => Instruction::And,
                             BinaryOperator::Or => Instruction::Or,
+                            BinaryOperator::Xor => Instruction::Xor,
+                            BinaryOperator::Shl => Instruction::Shl,
+                            BinaryOperator::Shr => Instruction::Shr,
+                            BinaryOperator::ULt => Instruction::ULt,
+                            BinaryOperator::UGt => Instruction::UGt,
+                            BinaryOperator::UDiv => Instruction::UDiv,
+                            BinaryOperator::UMod => Instruction::UMod,
+                            BinaryOperator::UMul => Instruction::UMul,
+                            BinaryOperator::UMulHigh => Instruction::UMulHigh,
+                            BinaryOperator::Rotl => Instruction::Rotl,
+                            BinaryOperator::Rotr => Instruction::Rotr,
                         };
                         instructions.push(inst);
                     }
@@ -240,11 +901,24 @@ impl CompilationPipeline {
                             UnaryOperator::Negate => Instruction::Neg,
                             UnaryOperator::Not => Instruction::Not,
                             UnaryOperator::Abs => Instruction::Abs,
+                            UnaryOperator::Popcount => Instruction::Popcount,
+                            UnaryOperator::Ctz => Instruction::Ctz,
+                            UnaryOperator::Clz => Instruction::Clz,
                         };
                         instructions.push(inst);
                     }
                     SSAInstruction::Call { name, .. } => {
-                        instructions.push(Instruction::Call(name.clone()));
+                        instructions.push(Instruction::Call(*name));
+                    }
+                    SSAInstruction::FunctionAddress { name, .. } => {
+                        instructions.push(Instruction::Tick(*name));
+                    }
+                    SSAInstruction::CallIndirect { .. } => {
+                        instructions.push(Instruction::Execute);
+                    }
+                    // The interpreter finds a variable's buffer by its name
+                    SSAInstruction::DataAddress { name, .. } => {
+                        instructions.push(Instruction::Call(name.as_str().into()));
                     }
                     SSAInstruction::Return { .. } => {
                         instructions.push(Instruction::Return);
@@ -257,12 +931,58 @@ impl CompilationPipeline {
                     SSAInstruction::Jump { target } => {
                         instructions.push(Instruction::Branch(target.0));
                     }
-                    SSAInstruction::Load { .. } => {
-                        instructions.push(Instruction::Load);
+                    SSAInstruction::Switch { cases, default, .. } => {
+                        for (key, target) in cases {
+                            instructions.extend([Instruction::Dup, Instruction::Literal(*key), Instruction::Eq]);
+                            instructions.push(Instruction::BranchIf(target.0));
+                        }
+                        instructions.push(Instruction::Branch(default.0));
+                    }
+                    SSAInstruction::Lookup { table, .. } => {
+                        instructions.push(Instruction::Lookup(table.clone()));
+                    }
+                    SSAInstruction::Load { width, .. } => {
+                        instructions.push(match width {
+                            MemoryWidth::W8 => Instruction::Load8,
+                            MemoryWidth::W16 => Instruction::Load16,
+                            MemoryWidth::W32 => Instruction::Load32,
+                            MemoryWidth::Cell => Instruction::Load,
+                        });
+                    }
+                    SSAInstruction::Store { width, .. } => {
+                        instructions.push(match width {
+                            MemoryWidth::W8 => Instruction::Store8,
+                            MemoryWidth::W16 => Instruction::Store16,
+                            MemoryWidth::W32 => Instruction::Store32,
+                            MemoryWidth::Cell => Instruction::Store,
+                        });
+                    }
+                    SSAInstruction::HeapAllocate { .. } => {
+                        instructions.push(Instruction::Call("allocate".into()));
+                    }
+                    SSAInstruction::HeapFree { .. } => {
+                        instructions.push(Instruction::Call("free".into()));
                     }
-                    SSAInstruction::Store { .. } => {
-                        instructions.push(Instruction::Store);
+                    SSAInstruction::HeapResize { .. } => {
+                        instructions.push(Instruction::Call("resize".into()));
                     }
+                    // Calls the optimizer must not move memory accesses across
+                    SSAInstruction::TaskSpawn { .. } => {
+                        instructions.push(Instruction::Call("task:".into()));
+                    }
+                    SSAInstruction::AtomicLoad { .. } => {
+                        instructions.push(Instruction::Call("@atomic".into()));
+                    }
+                    SSAInstruction::AtomicStore { .. } => {
+                        instructions.push(Instruction::Call("!atomic".into()));
+                    }
+                    SSAInstruction::FFICall { function, .. } => match runtime_io_word(function) {
+                        Some(word) if is_sync_word(word) => instructions.push(Instruction::Call(word.into())),
+                        _ => {
+                            warn!("Unhandled SSA instruction: {:?}", ssa_inst);
+                            continue;
+                        }
+                    },
                     SSAInstruction::Phi { .. } => {
                         // Phi nodes are handled by SSA construction and don't need runtime code
                         // They're just for data flow analysis
@@ -283,15 +1003,36 @@ impl CompilationPipeline {
     fn run_optimizer(&mut self, ir: ForthIR) -> Result<ForthIR> {
         debug!("Running optimizer with level {:?}...", self.optimization_level);
 
+        let span = self.span("optimize", "phase");
         let optimized = self.optimizer.optimize(ir)
-            .map_err(|e| CompileError::OptimizationError(format!("{}", e)))?;
+            .map_err(|e| CompileError::OptimizationError(format!("{}", e)));
+        if let Some(trace) = &self.trace {
+            for timing in self.optimizer.take_timings() {
+                let mut args = serde_json::Map::new();
+                if let Some(word) = timing.word {
+                    args.insert("word".to_string(), word.into());
+                }
+                trace.record(timing.pass, "pass", timing.start, timing.duration, timing.thread, args);
+            }
+        }
+        drop(span);
+
+        optimized
+    }
 
-        Ok(optimized)
+    /// Record the time the backend spent on each word
+    fn record_codegen_timings(&self, timings: Vec<backend::cranelift::FunctionTiming>) {
+        if let Some(trace) = &self.trace {
+            for timing in timings {
+                trace.record(timing.name, "codegen", timing.start, timing.duration, timing.thread, serde_json::Map::new());
+            }
+        }
     }
 
     /// Compile to native executable (AOT)
     fn compile_aot(&self, ir: &ForthIR, stats: &mut CompilationStats) -> Result<(Option<usize>, Option<String>, Option<i64>)> {
         debug!("Generating native code (AOT)...");
+        let _span = self.span("codegen", "phase");
 
         // TODO: Implement LLVM backend integration
         // For now, return a placeholder
@@ -300,16 +1041,360 @@ impl CompilationPipeline {
         Ok((None, Some("output.o".to_string()), None))
     }
 
-    /// Compile and execute with JIT
-    fn compile_jit(&self, ssa_functions: &[SSAFunction], stats: &mut CompilationStats) -> Result<(Option<usize>, Option<String>, Option<i64>)> {
+    /// Compile to a native object with LLVM, in parallel codegen units
+    ///
+    /// Words the whole-program optimizer marks internal get internal
+    /// linkage; with LTO the runtime is merged into the object.
+    #[cfg(feature = "llvm")]
+    fn compile_llvm(
+        &self,
+        ssa_functions: &[SSAFunction],
+        ir: &ForthIR,
+    ) -> Result<(Option<usize>, Option<String>, Option<i64>)> {
+        use backend::codegen::{parallel, CodegenUnits};
+        use fastforth_optimizer::ir::Linkage;
+        use fastforth_optimizer::whole_program::WholeProgramOptimizer;
+
+        let _span = self.span("codegen", "phase");
+        let mut linked = ir.clone();
+        WholeProgramOptimizer::new(self.optimization_level).assign_linkage(&mut linked);
+        let internal: std::collections::HashSet<String> = linked
+            .words
+            .values()
+            .filter(|word| word.linkage == Linkage::Internal)
+            .map(|word| word.name.clone())
+            .collect();
+        let runtime: Vec<&str> = if self.lto.enabled() {
+            self.runtime_components(ssa_functions)?
+                .into_iter()
+                .filter_map(|component| component.source().map(|(_, text)| text))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let mut units = CodegenUnits { lto: self.lto, ..CodegenUnits::default() };
+        if let Some(limit) = self.codegen_units {
+            units.units = limit;
+        }
+        debug!("Generating native code (LLVM, {} codegen units)...", units.units);
+
+        let output = PathBuf::from("output.o");
+        parallel::compile_object(ssa_functions, self.optimization_level as u8, self.arithmetic, units, &internal, &runtime, &output)
+            .map_err(|e| CompileError::BackendError(e.to_string()))?;
+        let code_size = std::fs::metadata(&output).ok().map(|metadata| metadata.len() as usize);
+        Ok((code_size, Some(output.display().to_string()), None))
+    }
+
+    /// Compile and execute with JIT, returning the resulting data stack
+    fn compile_jit(
+        &self,
+        ssa_functions: &[SSAFunction],
+        code_words: &[CodeWord],
+        has_entry: bool,
+        warnings: &mut Vec<String>,
+    ) -> Result<Vec<i64>> {
         debug!("Compiling and executing (JIT)...");
+        let program = self.build_jit(ssa_functions, code_words, &[], has_entry)?;
+        let _span = self.span("execute", "phase");
+        let heap = HeapSession::begin(self.sandbox.map_or(self.heap, |sandbox| sandbox.heap(self.heap)))?;
+        let _recording = self.recording.map(RecordingSession::begin).transpose()?;
+        // Always guarded, so a trap fails the run instead of the process
+        let cancel = self.cancel.clone().unwrap_or_default();
+        let sandbox = self.sandbox.unwrap_or_default();
+        let _deadline = sandbox.timeout.map(|timeout| cancel.cancel_after(timeout));
+        let stack = match program.run_with_fuel(&cancel, sandbox.fuel) {
+            Err(CompileError::Trapped(mut snapshot)) if self.recording.is_some() => {
+                snapshot.add_recording(&recording::last_events());
+                return Err(CompileError::Trapped(snapshot));
+            }
+            run => run?.unwrap_or_default(),
+        };
+        for leak in heap.leaks() {
+            let warning = leak.to_string();
+            warn!("{}", warning);
+            warnings.push(warning);
+        }
+        Ok(stack)
+    }
 
-        // Use the backend crate's Cranelift compiler
+    /// JIT-compile source code without running it
+    ///
+    /// The returned program can be run any number of times, which lets
+    /// benchmarks time execution apart from compilation.
+    pub fn prepare_jit(&self, source: &str) -> Result<JitProgram> {
+        let program = self.parse(source)?;
+        self.backend.resolve(self.optimization_level, CompilationMode::JIT)?;
+        let ssa_functions = self.run_frontend(&program, CompilationMode::JIT)?;
+        self.build_jit(&ssa_functions, &program.code_words, &[], !program.top_level_code.is_empty())
+    }
+
+    /// JIT-compile a parsed program that calls functions of the host
+    ///
+    /// Each host function is known to the program by its name and stack
+    /// effect, like a CODE word, and called at its address.
+    pub fn prepare_jit_program(&self, program: &Program, host_functions: &[HostFunction]) -> Result<JitProgram> {
+        self.backend.resolve(self.optimization_level, CompilationMode::JIT)?;
+        let mut analyzed = program.clone();
+        analyzed.code_words.extend(host_functions.iter().map(|function| CodeWord {
+            name: function.name.clone(),
+            stack_effect: function.stack_effect.clone(),
+            assembly: String::new(),
+            location: SourceLocation::default(),
+        }));
+        let ssa_functions = self.run_frontend(&analyzed, CompilationMode::JIT)?;
+        self.build_jit(&ssa_functions, &program.code_words, host_functions, !program.top_level_code.is_empty())
+    }
+
+    /// Build a position-independent shared library exporting the words
+    /// marked `@export` as C functions, with a C header declaring them
+    ///
+    /// The words are compiled to assembler source and linked with the C
+    /// runtime and one reverse trampoline per export, the C entry point
+    /// that calls the word. The header is written next to `output`, with
+    /// the extension `.h`, and so is the source map of the words, with
+    /// `.srcmap`. Top-level code is compiled but never run.
+    pub fn build_shared_library(&self, source: &str, output: &Path) -> Result<SharedLibrary> {
+        use backend::linker::{export_header, export_trampolines_source, Linker, LinkerConfig};
+
+        let program = self.parse(source)?;
+        let exports = c_exports(&program)?;
+        if exports.is_empty() {
+            return Err(CompileError::CodeGenError("no words are marked @export".to_string()));
+        }
+        let ssa_functions = self.run_frontend(&program, CompilationMode::AOT)?;
+        for export in &exports {
+            let arity = ssa_functions.iter().find(|func| func.name == export.word).map(|func| func.parameters.len());
+            if arity != Some(export.inputs) {
+                return Err(CompileError::CodeGenError(format!(
+                    "'{}' does not take the {} cells its stack effect declares",
+                    export.word, export.inputs
+                )));
+            }
+        }
+
+        let components = self.runtime_components(&ssa_functions)?;
+        let (assembly, words) = self.words_assembly(&program, &ssa_functions)?;
+
+        let dir = scratch_dir("cdylib");
+        std::fs::create_dir_all(&dir).map_err(|e| CompileError::IoError(dir.clone(), e))?;
+        let link = self.span("link", "phase");
+        let linked = (|| {
+            let mut sources = vec![
+                (dir.join("words.s"), assembly),
+                (dir.join("exports.c"), export_trampolines_source(&exports)),
+            ];
+            sources.extend(components.iter().filter_map(|component| component.source())
+                .map(|(name, text)| (dir.join(name), text.to_string())));
+            for (name, text) in RUNTIME_HEADERS {
+                std::fs::write(dir.join(name), text).map_err(|e| CompileError::IoError(dir.join(name), e))?;
+            }
+
+            let linker = Linker::new(LinkerConfig { libs: runtime_libs(&components), ..LinkerConfig::default() });
+            let mut objects = Vec::new();
+            for (source, text) in &sources {
+                std::fs::write(source, text).map_err(|e| CompileError::IoError(source.clone(), e))?;
+                let object = source.with_extension("o");
+                let built = if source.extension().is_some_and(|ext| ext == "c") {
+                    linker.compile_c(source, &object)
+                } else {
+                    linker.assemble(source, &object)
+                };
+                built.map_err(|e| CompileError::BackendError(format!("{}", e)))?;
+                objects.push(object);
+            }
+            linker.create_shared_library(&objects, output)
+                .map_err(|e| CompileError::BackendError(format!("{}", e)))
+        })();
+        let _ = std::fs::remove_dir_all(&dir);
+        drop(link);
+        linked?;
+        let source_map = write_source_map(output, &words)?;
+
+        let header = output.with_extension("h");
+        let library_name = output.file_name().unwrap_or_default().to_string_lossy();
+        std::fs::write(&header, export_header(&library_name, &exports))
+            .map_err(|e| CompileError::IoError(header.clone(), e))?;
+
+        Ok(SharedLibrary { library: output.to_path_buf(), header, source_map, exports })
+    }
+
+    /// Build a standalone executable running the program's top-level code
+    /// (or its `main` word)
+    ///
+    /// The words are compiled to assembler source and linked with the
+    /// runtime archives built into the compiler, so nothing outside the
+    /// compiler has to be found at link time beyond a C toolchain and libc.
+    /// Only the runtime components the program calls are linked, and the
+    /// runtime profile must allow them. Words marked `@export` are also
+    /// defined under their C symbols. The source map of the words is
+    /// written next to the executable (see [`source_map_path`]). Returns
+    /// the executable's path, which gains `.exe` on Windows.
+    pub fn build_executable(&self, source: &str, output: &Path) -> Result<PathBuf> {
+        use backend::linker::{export_aliases_assembly, word_symbol, LinkMode, Linker, LinkerConfig};
+
+        let program = self.parse(source)?;
+        let exports = c_exports(&program)?;
+        let ssa_functions = self.run_frontend(&program, CompilationMode::AOT)?;
+        if !ssa_functions.iter().any(|func| func.name == "main") {
+            return Err(CompileError::CodeGenError(
+                "Nothing to run: the program has no top-level code and no 'main' word".to_string(),
+            ));
+        }
+        let components = self.runtime_components(&ssa_functions)?;
+        let (assembly, words) = self.words_assembly(&program, &ssa_functions)?;
+        let assembly = assembly + &export_aliases_assembly(&exports);
+
+        let dir = scratch_dir("exe");
+        std::fs::create_dir_all(&dir).map_err(|e| CompileError::IoError(dir.clone(), e))?;
+        let link = self.span("link", "phase");
+        let linked = (|| {
+            let linker = Linker::new(LinkerConfig::default());
+            let (text, object) = (dir.join("words.s"), dir.join("words.o"));
+            std::fs::write(&text, assembly).map_err(|e| CompileError::IoError(text.clone(), e))?;
+            linker.assemble(&text, &object)
+                .map_err(|e| CompileError::BackendError(format!("{}", e)))?;
+
+            // The core goes last: the other components call into it
+            let mut inputs = vec![object];
+            for (name, bytes) in components.iter().rev().filter_map(|component| component.archive()) {
+                let archive = dir.join(name);
+                std::fs::write(&archive, bytes).map_err(|e| CompileError::IoError(archive.clone(), e))?;
+                inputs.push(archive);
+            }
+            let runtime = inputs.pop().unwrap_or_default();
+            let config = LinkerConfig {
+                mode: LinkMode::Dynamic,
+                runtime_lib: runtime,
+                libs: runtime_libs(&components),
+                output: output.to_path_buf(),
+                entry: Some(word_symbol("main")),
+                ..LinkerConfig::default()
+            };
+            Linker::new(config).link(&inputs)
+                .map_err(|e| CompileError::BackendError(format!("{}", e)))
+        })();
+        let _ = std::fs::remove_dir_all(&dir);
+        drop(link);
+        let executable = linked?;
+        write_source_map(&executable, &words)?;
+        Ok(executable)
+    }
+
+    /// Report what the optimizer does to `source`
+    ///
+    /// Executables and shared libraries are built from SSA without running
+    /// the optimizer, so their report comes from running it here.
+    pub fn optimization_report(&mut self, source: &str) -> Result<Option<OptimizationReport>> {
+        let program = self.parse(source)?;
+        self.run_frontend(&program, CompilationMode::AOT)?;
+        self.run_optimizer(self.lower_to_ir(&program))?;
+        Ok(self.optimizer.take_report())
+    }
+
+    /// Compile source code to a relocatable object file, returned as bytes
+    ///
+    /// The object defines every definition under its mangled symbol (see
+    /// [`backend::mangle`]) with the calling convention of shared library
+    /// exports, plus the program's CODE words. Words marked `@export` are
+    /// also defined under their C symbols. It does not contain the runtime;
+    /// hosts link it against `forth_runtime.c` or provide the words it
+    /// calls themselves.
+    pub fn compile_object(&self, source: &str) -> Result<Vec<u8>> {
+        use backend::linker::{export_aliases_assembly, Linker, LinkerConfig};
+
+        let program = self.parse(source)?;
+        let exports = c_exports(&program)?;
+        let ssa_functions = self.run_frontend(&program, CompilationMode::AOT)?;
+        let (assembly, _) = self.words_assembly(&program, &ssa_functions)?;
+        let assembly = assembly + &export_aliases_assembly(&exports);
+
+        let dir = scratch_dir("object");
+        std::fs::create_dir_all(&dir).map_err(|e| CompileError::IoError(dir.clone(), e))?;
+        let (text, object) = (dir.join("words.s"), dir.join("words.o"));
+        let _span = self.span("assemble", "phase");
+        let assembled = std::fs::write(&text, assembly)
+            .map_err(|e| CompileError::IoError(text.clone(), e))
+            .and_then(|()| {
+                Linker::new(LinkerConfig::default()).assemble(&text, &object)
+                    .map_err(|e| CompileError::BackendError(format!("{}", e)))
+            })
+            .and_then(|()| std::fs::read(&object).map_err(|e| CompileError::IoError(object.clone(), e)));
+        let _ = std::fs::remove_dir_all(&dir);
+        assembled
+    }
+
+    /// Assembler source for a program's definitions and CODE words, and
+    /// the source lines of each definition's code (see [`word_lines`])
+    fn words_assembly(&self, program: &Program, ssa_functions: &[SSAFunction]) -> Result<(String, Vec<SourceRange>)> {
         use backend::cranelift::{CraneliftBackend, CraneliftSettings};
+        use backend::linker::{code_word_assembly, code_word_symbol};
 
-        if ssa_functions.is_empty() {
-            return Ok((None, None, Some(0)));
+        let settings = CraneliftSettings {
+            opt_level: 1,
+            debug_info: false,
+            target_triple: None,
+            enable_verification: cfg!(debug_assertions),
+            arithmetic: self.arithmetic,
+        };
+        let _span = self.span("codegen", "phase");
+        let mut backend = CraneliftBackend::assembly(settings)
+            .map_err(|e| CompileError::BackendError(format!("{}", e)))?;
+        backend.set_timing(self.trace.is_some());
+        for word in &program.code_words {
+            let effect = &word.stack_effect;
+            backend.declare_external_function(&word.name, &code_word_symbol(&word.name), effect.inputs.len(), effect.outputs.len())
+                .map_err(|e| CompileError::BackendError(format!("{}", e)))?;
         }
+        let functions: Vec<(String, &SSAFunction)> = ssa_functions.iter().map(|func| (func.name.clone(), func)).collect();
+        backend.declare_all_functions(&functions)
+            .map_err(|e| CompileError::BackendError(format!("{}", e)))?;
+        backend.compile_functions(&functions)
+            .map_err(|e| CompileError::BackendError(format!("{}", e)))?;
+        self.record_codegen_timings(backend.take_timings());
+        let lines = ssa_functions.iter().map(|func| word_lines(func, 0, 0, backend.line_table(&func.name))).collect();
+
+        let mut assembly = backend.finish_assembly();
+        for word in &program.code_words {
+            assembly.push_str(&code_word_assembly(&code_word_symbol(&word.name), &word.assembly));
+        }
+        Ok((assembly, lines))
+    }
+
+    fn build_jit(
+        &self,
+        ssa_functions: &[SSAFunction],
+        code_words: &[CodeWord],
+        host_functions: &[HostFunction],
+        has_entry: bool,
+    ) -> Result<JitProgram> {
+        // Use the backend crate's Cranelift compiler
+        use backend::cranelift::{CraneliftBackend, CraneliftSettings};
+
+        crate::runtime_ffi::register_jit_symbols();
+        let recorded;
+        let ssa_functions = if self.recording.is_some() {
+            recorded = with_recording(ssa_functions);
+            &recorded[..]
+        } else {
+            ssa_functions
+        };
+        let polled;
+        let ssa_functions = if self.interruptible || self.cancel.is_some() || self.sandbox.is_some() {
+            polled = with_polls(ssa_functions);
+            &polled[..]
+        } else {
+            ssa_functions
+        };
+        let code_symbols = load_code_words(code_words)?;
+        let host_symbols: Vec<String> = host_functions
+            .iter()
+            .map(|function| {
+                let symbol = format!("forth_host_{}", function.name);
+                backend::cranelift::register_runtime_symbol(symbol.as_str(), function.address);
+                symbol
+            })
+            .collect();
 
         // Create Cranelift backend
         let settings = CraneliftSettings {
@@ -317,10 +1402,32 @@ impl CompilationPipeline {
             debug_info: false,
             target_triple: None,
             enable_verification: cfg!(debug_assertions),
+            arithmetic: self.arithmetic,
         };
 
+        let _span = self.span("codegen", "phase");
         let mut backend = CraneliftBackend::new(settings)
             .map_err(|e| CompileError::BackendError(format!("{}", e)))?;
+        backend.set_timing(self.trace.is_some());
+
+        for (word, symbol) in code_words.iter().zip(&code_symbols) {
+            let effect = &word.stack_effect;
+            backend.declare_external_function(&word.name, symbol, effect.inputs.len(), effect.outputs.len())
+                .map_err(|e| CompileError::BackendError(format!("{}", e)))?;
+        }
+
+        for (function, symbol) in host_functions.iter().zip(&host_symbols) {
+            let effect = &function.stack_effect;
+            backend.declare_external_function(&function.name, symbol, effect.inputs.len(), effect.outputs.len())
+                .map_err(|e| CompileError::BackendError(format!("{}", e)))?;
+        }
+
+        // The entry point, when there is one, is not a definition
+        let defined = ssa_functions.len() - usize::from(has_entry && !ssa_functions.is_empty());
+        let definitions: Vec<String> = ssa_functions[..defined].iter().map(|func| func.name.clone()).collect();
+        if ssa_functions.is_empty() {
+            return Ok(JitProgram { backend, definitions, entry: None, source_map: SourceMap::default() });
+        }
 
         // Prepare (name, function) pairs
         let functions_with_names: Vec<(String, &SSAFunction)> = ssa_functions
@@ -332,36 +1439,335 @@ impl CompilationPipeline {
         backend.declare_all_functions(&functions_with_names)
             .map_err(|e| CompileError::BackendError(format!("{}", e)))?;
 
-        for (name, func) in &functions_with_names {
-            backend.compile_function(func, name)
-                .map_err(|e| CompileError::BackendError(format!("{}", e)))?;
-        }
+        // Large programs are compiled across the thread pool
+        backend.compile_functions(&functions_with_names)
+            .map_err(|e| CompileError::BackendError(format!("{}", e)))?;
+        self.record_codegen_timings(backend.take_timings());
 
         backend.finalize_all()
             .map_err(|e| CompileError::BackendError(format!("{}", e)))?;
+        self.check_memory("codegen")?;
+        let source_map = SourceMap::new(
+            ssa_functions
+                .iter()
+                .filter_map(|func| {
+                    let (address, size) = backend.code_range(&func.name)?;
+                    Some(word_lines(func, address as u64, size as u64, backend.line_table(&func.name)))
+                })
+                .collect(),
+        );
+
+        // Definitions alone compile but have nothing to run
+        if !has_entry {
+            return Ok(JitProgram { backend, definitions, entry: None, source_map });
+        }
 
-        // Execute the last function (usually :main)
+        // The entry point is always the last function, :main
         let func_name = &ssa_functions.last().unwrap().name;
-        let main_func_ptr = backend.get_function(func_name)
+        let entry = backend.get_function(func_name)
             .ok_or_else(|| CompileError::BackendError("Failed to get compiled function".to_string()))?;
 
-        // Call function (all Forth functions return i64)
-        type ForthFn = unsafe extern "C" fn() -> i64;
-        let forth_fn: ForthFn = unsafe { std::mem::transmute(main_func_ptr) };
-        let result = unsafe { forth_fn() };
-
-        Ok((None, None, Some(result)))
+        Ok(JitProgram { backend, definitions, entry: Some(entry), source_map })
     }
 
     /// Count total instructions in IR
     fn count_instructions(&self, ir: &ForthIR) -> usize {
         ir.instruction_count()
     }
+
+    /// Optimizer view of a definition's attributes
+    fn word_attributes(&self, attributes: &Attributes) -> WordAttributes {
+        WordAttributes {
+            inline: match attributes.inline {
+                Some(InlineHint::Always) => InlineDirective::AlwaysInline,
+                Some(InlineHint::Never) => InlineDirective::NeverInline,
+                None => InlineDirective::Auto,
+            },
+            optimize_none: attributes.optimize_none,
+            hot: attributes.hot,
+            constant_time: self.constant_time || attributes.constant_time,
+            export: attributes.export.is_some(),
+        }
+    }
+}
+
+/// Headers of the C runtime compiled into shared libraries, so they build
+/// from any directory
+const RUNTIME_HEADERS: [(&str, &str); 2] = [
+    ("forth_runtime.h", include_str!("../runtime/forth_runtime.h")),
+    ("concurrency.h", include_str!("../runtime/concurrency.h")),
+];
+
+/// System libraries the runtime components link against
+fn runtime_libs(components: &[RuntimeComponent]) -> Vec<String> {
+    std::iter::once("c")
+        .chain(components.iter().flat_map(|component| component.libs().iter().copied()))
+        .map(String::from)
+        .collect()
+}
+
+/// The words marked `@export`, checked for a C signature: a declared stack
+/// effect of cells with at most one output, and a symbol no other export
+/// uses
+fn c_exports(program: &Program) -> Result<Vec<CExport>> {
+    let mut exports: Vec<CExport> = Vec::new();
+    for def in &program.definitions {
+        let Some(symbol) = &def.attributes.export else { continue };
+        let invalid = |declaration: String| {
+            CompileError::frontend(
+                FrontendStage::Semantic,
+                ForthError::InvalidStackEffect { declaration, location: Some(def.location.clone()) },
+            )
+        };
+        let Some(effect) = &def.stack_effect else {
+            return Err(invalid(format!("exported word '{}' must declare its stack effect", def.name)));
+        };
+        if def.immediate || effect.outputs.len() > 1 {
+            return Err(invalid(format!("exported word '{}' must be a non-immediate word leaving at most one cell", def.name)));
+        }
+        if effect.inputs.iter().chain(&effect.outputs).any(|ty| *ty == StackType::Float) {
+            return Err(invalid(format!("exported word '{}' can only take and return cells", def.name)));
+        }
+        if exports.iter().any(|export| export.symbol == *symbol) {
+            return Err(CompileError::frontend(
+                FrontendStage::Semantic,
+                ForthError::RedefinitionError { word: symbol.clone(), location: Some(def.location.clone()) },
+            ));
+        }
+        exports.push(CExport {
+            word: def.name.clone(),
+            symbol: symbol.clone(),
+            inputs: effect.inputs.len(),
+            returns: effect.outputs.len() == 1,
+            stack_effect: effect.to_string(),
+        });
+    }
+    Ok(exports)
+}
+
+/// Copies of `functions` that call `forth_poll` on entry and at the head of
+/// every loop, so a run under a [`CancelToken`] stops within an iteration
+///
+/// A loop head is the target of a branch from a block at or after it.
+fn with_polls(functions: &[SSAFunction]) -> Vec<SSAFunction> {
+    use fastforth_frontend::ssa::{BlockId, SSAInstruction};
+    use std::collections::{HashMap, HashSet};
+
+    let mut functions = functions.to_vec();
+    for function in &mut functions {
+        let order: HashMap<BlockId, usize> =
+            function.blocks.iter().enumerate().map(|(index, block)| (block.id, index)).collect();
+        let mut heads = HashSet::from([function.entry_block]);
+        for (index, block) in function.blocks.iter().enumerate() {
+            for instruction in &block.instructions {
+                let targets = match instruction {
+                    SSAInstruction::Jump { target } => vec![*target],
+                    SSAInstruction::Branch { true_block, false_block, .. } => vec![*true_block, *false_block],
+                    SSAInstruction::Switch { cases, default, .. } => {
+                        cases.iter().map(|(_, target)| *target).chain([*default]).collect()
+                    }
+                    _ => continue,
+                };
+                heads.extend(targets.into_iter().filter(|target| order.get(target).is_some_and(|&at| at <= index)));
+            }
+        }
+
+        for block in function.blocks.iter_mut().filter(|block| heads.contains(&block.id)) {
+            let at = block.instructions.iter().take_while(|i| matches!(i, SSAInstruction::Phi { .. })).count();
+            block.instructions.insert(at, SSAInstruction::FFICall {
+                dest: Default::default(),
+                function: "forth_poll".to_string(),
+                args: Default::default(),
+            });
+            if at <= block.locations.len() {
+                block.locations.insert(at, SourceLocation::default());
+            }
+        }
+    }
+    functions
+}
+
+/// Where a function's word is defined: the location of its first
+/// instruction that has one
+fn definition_location(function: &SSAFunction) -> SourceLocation {
+    function
+        .blocks
+        .iter()
+        .flat_map(|block| &block.locations)
+        .find(|location| location.is_known())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Source range of a compiled function whose code is at `start`, with the
+/// line table the backend recorded for it
+fn word_lines(function: &SSAFunction, start: u64, size: u64, lines: &[(u32, usize)]) -> SourceRange {
+    let location = definition_location(function);
+    SourceRange {
+        start,
+        size,
+        word: function.name.clone(),
+        line: location.is_known().then_some(location.line),
+        lines: lines.iter().map(|&(offset, line)| (offset as u64, line)).collect(),
+    }
+}
+
+/// Write the source map of the linked ELF file `binary` next to it,
+/// returning its path (see [`source_map_path`])
+fn write_source_map(binary: &Path, words: &[SourceRange]) -> Result<PathBuf> {
+    let image = std::fs::read(binary).map_err(|e| CompileError::IoError(binary.to_path_buf(), e))?;
+    let map = SourceMap::from_elf(&image, words).map_err(|e| CompileError::BackendError(format!("{}", e)))?;
+    let path = source_map_path(binary);
+    std::fs::write(&path, map.to_text()).map_err(|e| CompileError::IoError(path.clone(), e))?;
+    Ok(path)
+}
+
+/// Copies of `functions` that report every entry, with the cells taken,
+/// and every return, with the cells left, to the runtime's recorder
+///
+/// The entry point is not a word, so it is left alone.
+fn with_recording(functions: &[SSAFunction]) -> Vec<SSAFunction> {
+    use fastforth_frontend::ssa::SSAInstruction;
+
+    let mut functions = functions.to_vec();
+    for function in functions.iter_mut().filter(|function| function.name != "main") {
+        let word = recording::word_id(&function.name, definition_location(function));
+        let mut next_register = function.next_register();
+        let parameters = function.parameters.clone();
+        let entry_block = function.entry_block;
+
+        for block in &mut function.blocks {
+            let mut at = 0;
+            if block.id == entry_block {
+                at = block.instructions.iter().take_while(|i| matches!(i, SSAInstruction::Phi { .. })).count();
+                at += insert_record(block, at, "forth_record_enter", word, &parameters, &mut next_register);
+            }
+            while at < block.instructions.len() {
+                if let SSAInstruction::Return { values } = &block.instructions[at] {
+                    let values = values.to_vec();
+                    at += insert_record(block, at, "forth_record_exit", word, &values, &mut next_register);
+                }
+                at += 1;
+            }
+        }
+    }
+    functions
+}
+
+/// Insert a call of the recorder `function` at `at`, passing the word, the
+/// number of `cells` and the top four of them, returning the instructions
+/// inserted
+fn insert_record(
+    block: &mut fastforth_frontend::ssa::BasicBlock,
+    at: usize,
+    function: &str,
+    word: i64,
+    cells: &[fastforth_frontend::ssa::Register],
+    next_register: &mut usize,
+) -> usize {
+    use fastforth_frontend::ssa::{Register, SSAInstruction};
+
+    let mut instructions = Vec::new();
+    let mut load = |value: i64| {
+        let dest = Register(*next_register);
+        *next_register += 1;
+        instructions.push(SSAInstruction::LoadInt { dest, value });
+        dest
+    };
+    let word = load(word);
+    let count = load(cells.len() as i64);
+    let top = &cells[cells.len().saturating_sub(4)..];
+    let zero = (top.len() < 4).then(|| load(0));
+    let mut args = vec![word, count];
+    args.extend(top);
+    args.resize(6, zero.unwrap_or(word));
+    instructions.push(SSAInstruction::FFICall {
+        dest: Default::default(),
+        function: function.to_string(),
+        args: args.into_iter().collect(),
+    });
+
+    let inserted = instructions.len();
+    block.instructions.splice(at..at, instructions);
+    if at <= block.locations.len() {
+        block.locations.splice(at..at, std::iter::repeat_n(SourceLocation::default(), inserted));
+    }
+    inserted
+}
+
+/// A fresh scratch directory for one build, named for `kind`
+///
+/// Builds on several threads of one process each get their own.
+fn scratch_dir(kind: &str) -> PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static BUILDS: AtomicUsize = AtomicUsize::new(0);
+
+    std::env::temp_dir().join(format!(
+        "fifth-{}-{}-{}",
+        kind,
+        std::process::id(),
+        BUILDS.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Assemble CODE words into a shared library, load it and register each
+/// word's address with the JIT, returning their symbols
+///
+/// The assembly is never seen by the optimizer or Cranelift; each word is
+/// assembled by the system assembler exactly as written.
+fn load_code_words(code_words: &[CodeWord]) -> Result<Vec<String>> {
+    use backend::linker::{code_word_assembly, code_word_symbol, Linker, LinkerConfig, Platform};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static LIBRARIES: AtomicUsize = AtomicUsize::new(0);
+
+    if code_words.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let dir = std::env::temp_dir().join(format!(
+        "fifth-code-{}-{}",
+        std::process::id(),
+        LIBRARIES.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir_all(&dir).map_err(|e| CompileError::IoError(dir.clone(), e))?;
+
+    let loaded = (|| {
+        let linker = Linker::new(LinkerConfig::default());
+        let symbols: Vec<String> = code_words.iter().map(|word| code_word_symbol(&word.name)).collect();
+        let mut objects = Vec::new();
+        for (word, symbol) in code_words.iter().zip(&symbols) {
+            let source = dir.join(format!("{}.s", symbol));
+            let object = dir.join(format!("{}.o", symbol));
+            std::fs::write(&source, code_word_assembly(symbol, &word.assembly))
+                .map_err(|e| CompileError::IoError(source.clone(), e))?;
+            linker.assemble(&source, &object).map_err(|e| {
+                CompileError::BackendError(format!("CODE word '{}' at line {}: {}", word.name, word.location.line, e))
+            })?;
+            objects.push(object);
+        }
+
+        let library = dir.join(format!("code_words.{}", Platform::host().shared_library_extension()));
+        linker.create_shared_library(&objects, &library)
+            .map_err(|e| CompileError::BackendError(format!("{}", e)))?;
+        let addresses = crate::runtime_ffi::load_shared_library(&library, &symbols)
+            .map_err(|e| CompileError::BackendError(format!("Failed to load CODE words: {}", e)))?;
+        for (symbol, address) in symbols.iter().zip(addresses) {
+            backend::cranelift::register_runtime_symbol(symbol, address);
+        }
+        Ok(symbols)
+    })();
+
+    // The loaded library stays mapped after its file is removed
+    let _ = std::fs::remove_dir_all(&dir);
+    loaded
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use fastforth_optimizer::VectorOp;
 
     #[test]
     fn test_pipeline_creation() {
@@ -379,4 +1785,944 @@ mod tests {
         // We expect this to fail for now, but it should be a compilation error, not a panic
         assert!(result.is_ok() || result.is_err());
     }
+
+    #[test]
+    fn test_jit_exposes_data_stack() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        let result = pipeline.compile("1 2 3 +", CompilationMode::JIT).unwrap();
+
+        assert_eq!(result.stack, vec![1, 5]);
+        assert_eq!(result.jit_result, Some(5));
+        assert!(result.ir.is_none());
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    fn test_jit_calls_code_words() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        let source = "code add3 ( a b c -- n )
+                lea (%rdi,%rsi), %rax
+                add %rdx, %rax
+                ret
+            end-code
+            code discard ( x -- ) ret end-code
+            : add3+10 ( a b c -- n ) add3 10 + ;
+            4 1 2 3 add3+10 99 discard";
+        let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+
+        assert_eq!(result.stack, vec![4, 16]);
+    }
+
+    #[test]
+    fn test_jit_reads_program_arguments() {
+        crate::runtime_ffi::set_program_args(&["prog".to_string(), "input.fs".to_string()]);
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        let result = pipeline.compile("arg-count 1 arg@ swap drop 5 arg@ +", CompilationMode::JIT).unwrap();
+
+        assert_eq!(result.stack, vec![2, 8, 0]);
+    }
+
+    #[test]
+    fn test_jit_calls_runtime_io() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        let source = ": digits ( n -- u ) dup abs 0 <# #s rot sign #> swap drop ; -1234 digits 0 digits";
+        let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+
+        assert_eq!(result.stack, vec![5, 1]);
+    }
+
+    #[test]
+    fn test_jit_runs_loops() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        let source = ": count-up ( -- n ) 0 begin 1+ dup 10 = until ;
+            : halve ( n -- steps ) 0 swap begin dup 1 > while 2 / swap 1+ swap repeat drop ;
+            : sum-to ( n -- sum ) 0 swap 0 do i + loop ;
+            : grid ( -- n ) 0 3 0 do 2 0 do j 10 * i + + loop loop ;
+            count-up 64 halve 10 sum-to grid";
+        let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+
+        assert_eq!(result.stack, vec![10, 6, 45, 63]);
+        // A loop that changes the stack depth runs when its passes are known
+        let result = pipeline.compile(": f ( -- ) 3 0 do i loop ; f", CompilationMode::JIT).unwrap();
+        assert_eq!(result.stack, vec![0, 1, 2]);
+        let error = pipeline.compile(": f ( n -- ) 0 do i loop ; 3 1+ f", CompilationMode::JIT).unwrap_err();
+        assert!(error.to_string().contains("DO-LOOP"), "{}", error);
+    }
+
+    #[test]
+    fn test_jit_runs_postponed_control_words() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        let source = ": unless postpone 0= postpone if ; IMMEDIATE
+            : times 0 postpone literal postpone do ; IMMEDIATE
+            : pick-one ( n -- n ) dup unless drop 10 else drop 20 then ;
+            : tally ( n -- n ) 0 swap times 3 + loop ;
+            0 pick-one 5 pick-one 4 tally";
+        let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+
+        assert_eq!(result.stack, vec![10, 20, 12]);
+    }
+
+    #[test]
+    fn test_jit_runs_quotation_combinators() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        let source = ": cells3 ( -- addr ) 3 cells allocate drop 1 over ! 2 over cell+ ! 3 over 2 cells + ! ;
+            : total ( addr len -- n ) 0 [: + ;] reduce ;
+            : double ( addr len -- ) [: 2 * ;] map! ;
+            cells3 dup 3 total over 3 double over 3 total rot 0 total";
+        let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+
+        assert_eq!(result.stack, vec![6, 12, 0]);
+    }
+
+    #[test]
+    fn test_jit_blocks_persist_updates() {
+        let _blocks = crate::runtime_ffi::BLOCK_FILE.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.fb");
+        let source = format!(
+            r#""{}" open-blocks 42 2 block ! update 7 3 buffer ! flush 2 block @ 3 block @"#,
+            path.display()
+        );
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        let result = pipeline.compile(&source, CompilationMode::JIT).unwrap();
+
+        // Block 3 was never updated, so it reads back as spaces
+        assert_eq!(result.stack, vec![42, i64::from_le_bytes([b' '; 8])]);
+        let file = std::fs::read(&path).unwrap();
+        assert_eq!(file.len(), 2048);
+        assert_eq!(file[1024..1032], 42i64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_retained_ir_contains_words() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        pipeline.set_retain_ir(true);
+        let result = pipeline.compile(": sq dup * ; 4 sq", CompilationMode::JIT).unwrap();
+
+        assert_eq!(result.stack, vec![16]);
+        let ir = result.ir.expect("IR should be retained");
+        assert!(ir.get_word("sq").is_some());
+    }
+
+    #[test]
+    fn test_attributes_reach_the_optimizer() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Aggressive);
+        pipeline.set_retain_ir(true);
+        let source = "\\ @inline(never)\n: inc 1 + ;\n\\ @optimize(none)\n: five 2 3 + ;\n\\ @export\n: six 5 inc ;\nsix five";
+        let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+
+        let ir = result.ir.expect("IR should be retained");
+        assert!(ir.get_word("six").unwrap().instructions.contains(&Instruction::Call("inc".into())));
+        assert!(ir.get_word("five").unwrap().instructions.contains(&Instruction::Add));
+        assert!(ir.main.contains(&Instruction::Call("five".into())));
+    }
+
+    #[test]
+    fn test_large_program_compiles_in_parallel() {
+        // Exported, so whole-program optimization keeps the chain of words
+        let mut source = String::from("\\ @export\n: w0 1 + ;\n");
+        for i in 1..40 {
+            source.push_str(&format!("\\ @export\n: w{} w{} 1 + ;\n", i, i - 1));
+        }
+        source.push_str("0 w39 w0");
+
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Standard);
+        pipeline.set_retain_ir(true);
+        let result = pipeline.compile(&source, CompilationMode::JIT).unwrap();
+
+        assert_eq!(result.stack, vec![41]);
+        assert_eq!(result.ir.expect("IR should be retained").words.len(), 40);
+    }
+
+    #[test]
+    fn test_counted_loops_get_induction_variables() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Standard);
+        pipeline.set_vectorize(false);
+        let program = parse_program(": sum-cells ( addr n -- sum ) 0 swap 0 do over i cells + @ + loop nip ;").unwrap();
+        let ir = pipeline.run_optimizer(pipeline.lower_to_ir(&program)).unwrap();
+
+        let word = ir.get_word("sum-cells").unwrap();
+        assert!(word.instructions.contains(&Instruction::InductionVar(0)));
+        assert!(!word.instructions.contains(&Instruction::Call("cells".into())));
+    }
+
+    #[test]
+    fn test_array_loops_are_vectorized_unless_disabled() {
+        let program = parse_program(": sum ( addr n -- sum ) 0 swap 0 do over i cells + @ + loop nip ;").unwrap();
+        let vector_sum = Instruction::VectorReduce { op: VectorOp::Add, array: 1 };
+
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Standard);
+        let ir = pipeline.run_optimizer(pipeline.lower_to_ir(&program)).unwrap();
+        assert!(ir.get_word("sum").unwrap().instructions.contains(&vector_sum));
+
+        pipeline.set_vectorize(false);
+        let ir = pipeline.run_optimizer(pipeline.lower_to_ir(&program)).unwrap();
+        assert!(!ir.get_word("sum").unwrap().instructions.contains(&vector_sum));
+    }
+
+    #[test]
+    fn test_scratch_variables_are_promoted() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Standard);
+        let program = parse_program("variable tmp variable count : f ( a b -- c ) tmp ! 2 * tmp @ + ; : bump 1 count +! ;").unwrap();
+        let ir = pipeline.run_optimizer(pipeline.lower_to_ir(&program)).unwrap();
+
+        let f = &ir.get_word("f").unwrap().instructions;
+        assert!(f.contains(&Instruction::LocalFetch(0)));
+        assert!(!f.contains(&Instruction::Load));
+        // `+!` takes the address, so `count` stays in memory
+        assert!(ir.get_word("bump").unwrap().instructions.contains(&Instruction::Call("count".into())));
+    }
+
+    #[test]
+    fn test_learned_superinstructions_are_fused() {
+        let table = SuperinstructionTable::parse("12 over + swap").unwrap();
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Standard);
+        pipeline.set_superinstruction_table(&table);
+        let ir = pipeline.lower_source(": f over + swap ; : g f f ;").unwrap();
+        let ir = pipeline.run_optimizer(ir).unwrap();
+
+        let fused = Instruction::Fused(vec![Instruction::Over, Instruction::Add, Instruction::Swap]);
+        assert!(ir.get_word("f").unwrap().instructions.contains(&fused));
+    }
+
+    #[test]
+    fn test_user_peephole_rules_are_applied() {
+        let rules = PeepholeRules::parse("0 + =>\n$n and => drop 0 where $n = 0").unwrap();
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        pipeline.set_peephole_rules(&rules);
+        let ir = pipeline.lower_source(": f 0 and ;").unwrap();
+        let ir = pipeline.run_optimizer(ir).unwrap();
+
+        assert_eq!(ir.get_word("f").unwrap().instructions.first(), Some(&Instruction::Drop));
+    }
+
+    #[test]
+    fn test_verify_optimizations_catches_a_wrong_rewrite() {
+        let source = ": inc ( n -- n ) 1 + ;\n\\ @export\n: f ( a b -- n ) swap inc * ;\n3 4 f";
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Aggressive);
+        pipeline.set_verify_optimizations(true);
+        assert!(pipeline.compile(source, CompilationMode::JIT).is_ok());
+
+        // A rule that changes what + computes
+        pipeline.set_peephole_rules(&PeepholeRules::parse("1 + => 2 +").unwrap());
+        let Err(CompileError::Miscompile(message)) = pipeline.compile(source, CompilationMode::JIT) else {
+            panic!("wrong rewrite not caught");
+        };
+        assert!(message.contains("optimized f differs"), "{}", message);
+    }
+
+    #[test]
+    fn test_immediate_words_are_not_lowered() {
+        let pipeline = CompilationPipeline::new(OptimizationLevel::None);
+        let ir = pipeline
+            .lower_source(": cube dup dup * * postpone literal ; IMMEDIATE : f [ 3 ] cube ;")
+            .unwrap();
+
+        assert!(ir.get_word("cube").is_none());
+        assert!(ir.get_word("f").unwrap().instructions.contains(&Instruction::Literal(27)));
+    }
+
+    #[test]
+    fn test_opt_report_is_attached_to_every_compilation() {
+        let source = ": sq dup * ; : f ( n -- n ) sq sq ; 3 f f";
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Standard);
+        pipeline.set_opt_report(true);
+
+        // JIT compilation runs the optimizer only to report on it
+        let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+        let report = result.opt_report.unwrap();
+        assert_eq!(report.words["f"].inlined, vec!["sq".to_string(), "sq".to_string()]);
+        assert!(!report.passes.is_empty());
+        assert!(result.ir.is_none());
+        assert!(report.words["f"].passes.iter().any(|pass| pass.pass == "whole_program" || pass.pass == "inline"));
+
+        // As do the builds of executables and shared libraries
+        let report = pipeline.optimization_report(source).unwrap().unwrap();
+        assert_eq!(report.words["f"].inlined, vec!["sq".to_string(), "sq".to_string()]);
+    }
+
+    #[test]
+    fn test_executed_quotations_are_inlined() {
+        let source = ": f ( n -- n ) [: ( n -- n ) dup * ;] execute 1 + ; 3 f f";
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Standard);
+        pipeline.set_opt_report(true);
+        pipeline.set_retain_ir(true);
+        let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+        assert_eq!(result.opt_report.unwrap().words["f"].inlined, vec!["[:1]".to_string()]);
+
+        // As is a word's own token
+        let source = ": sq ( n -- n ) dup * ; : g ( n -- n ) ['] sq execute ['] sq execute ; 3 g g";
+        let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+        assert_eq!(result.opt_report.unwrap().words["g"].inlined, vec!["sq".to_string(), "sq".to_string()]);
+        assert_eq!(result.stack, [43_046_721]);
+    }
+
+    #[test]
+    fn test_combinators_optimize_like_hand_written_loops() {
+        let pairs = [
+            (": f ( addr len -- ) [: 3 * ;] map! ;", ": f ( addr len -- ) dup if 0 do dup i cells + dup @ 3 * swap ! loop else drop then drop ;"),
+            (": f ( addr len -- n ) 0 [: + ;] reduce ;", ": f ( addr len -- n ) 0 swap dup if 0 do over i cells + @ + loop else drop then swap drop ;"),
+            (": f ( addr len -- ) [: . ;] each ;", ": f ( addr len -- ) dup if 0 do dup i cells + @ . loop else drop then drop ;"),
+        ];
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Aggressive);
+        for (combinator, by_hand) in pairs {
+            let mut optimized = |source| {
+                let ir = pipeline.run_optimizer(pipeline.lower_source(source).unwrap()).unwrap();
+                ir.get_word("f").unwrap().instructions.clone()
+            };
+            assert_eq!(optimized(combinator), optimized(by_hand), "{}", combinator);
+        }
+    }
+
+    #[test]
+    fn test_eval_cache_reuses_unchanged_words() {
+        let source = ": five 2 3 + ; : sq dup * ; : f ( n -- n ) sq five + ; 3 f";
+        let compile = |source: &str, cache: Option<EvalCache>| {
+            let mut pipeline = CompilationPipeline::new(OptimizationLevel::Aggressive);
+            pipeline.set_retain_ir(true);
+            pipeline.set_eval_cache(cache);
+            let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+            (result.ir.unwrap(), pipeline.take_eval_cache())
+        };
+        let flags = CompilationPipeline::new(OptimizationLevel::Aggressive).eval_cache_flags();
+
+        let (expected, _) = compile(source, None);
+        let (first, cache) = compile(source, Some(EvalCache::new(&flags)));
+        let text = cache.unwrap().to_string();
+        let (second, cache) = compile(source, EvalCache::parse(&text, &flags).ok());
+        let cache = cache.unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (3, 0));
+        assert_eq!((&first.words, &second.words), (&expected.words, &expected.words));
+
+        // Another optimization level starts over
+        assert!(EvalCache::parse(&text, &CompilationPipeline::new(OptimizationLevel::Standard).eval_cache_flags())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_trace_covers_stages_passes_and_words() {
+        let trace = Arc::new(CompilationTrace::new());
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Standard);
+        pipeline.set_trace(Some(trace.clone()));
+        pipeline.set_retain_ir(true);
+        pipeline.compile(": sq dup * ; : f ( n -- n ) sq 1 + ; 3 f f", CompilationMode::JIT).unwrap();
+
+        let stages: Vec<String> = trace.totals("phase").into_iter().map(|(name, _)| name).collect();
+        assert_eq!(stages, ["parse", "frontend", "optimize", "codegen", "execute"]);
+        let events = trace.events();
+        assert!(events.iter().any(|event| event.category == "pass" && event.args.get("word").is_some_and(|word| word == "f")));
+        let compiled: Vec<&str> = events.iter().filter(|event| event.category == "codegen").map(|event| event.name.as_str()).collect();
+        assert!(compiled.contains(&"sq") && compiled.contains(&"f"));
+    }
+
+    #[test]
+    fn test_memory_stats_and_limit() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Standard);
+        let result = pipeline.compile(": sq dup * ; : f sq dup * ; 3 f", CompilationMode::JIT).unwrap();
+        assert!(result.stats.names.lookups > result.stats.names.symbols);
+        assert_eq!(result.stats.peak_memory_bytes.is_some(), memory::resident_bytes().is_some());
+
+        pipeline.set_memory_limit(Some(1));
+        match pipeline.compile("3 4 +", CompilationMode::JIT) {
+            Err(CompileError::MemoryLimitExceeded { stage, used, limit }) => {
+                assert_eq!((stage, limit), ("parse", 1));
+                assert!(used > limit);
+                let error = crate::errors::to_structured_error(&CompileError::MemoryLimitExceeded { stage, used, limit }, false);
+                assert_eq!(error.code, "E9003");
+            }
+            Ok(_) => assert!(memory::resident_bytes().is_none(), "the limit is enforced where memory can be read"),
+            Err(other) => panic!("expected the memory limit, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_verify_checks_without_generating_code() {
+        let pipeline = CompilationPipeline::new(OptimizationLevel::Standard);
+        let result = pipeline.verify(": sq ( n -- n ) dup * ; : f ( n -- n ) sq 1 + ;").unwrap();
+        assert_eq!(result.definitions_count, 2);
+
+        let error = pipeline.verify(": f ( n -- n )\n  dup * frob ;").unwrap_err();
+        match error {
+            CompileError::Frontend { stage, error } => {
+                assert_eq!(stage, FrontendStage::Semantic);
+                assert_eq!(error.location().map(|location| location.line), Some(2));
+            }
+            other => panic!("expected a frontend error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unavailable_backend_falls_back() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        pipeline.set_backend(Backend::LLVM);
+        let result = pipeline.compile("2 3 *", CompilationMode::JIT).unwrap();
+
+        assert_eq!(result.backend, BackendType::Cranelift);
+        assert_eq!(result.stack, vec![6]);
+        assert_eq!(result.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_heap_debug_reports_leaks() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        pipeline.set_backend(Backend::Cranelift);
+        pipeline.set_heap(HeapConfig { debug: true, ..HeapConfig::default() });
+        let source = ": scratch 32 allocate drop ;\nscratch drop 16 allocate drop dup free swap free";
+        let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+
+        assert_eq!(result.stack, vec![0, -60]);
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].starts_with("heap leak: 32 bytes at 0x"), "{:?}", result.warnings);
+        assert!(result.warnings[0].ends_with("(allocation 1) never freed"));
+    }
+
+    #[test]
+    fn test_tasks_channels_and_atomics() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        pipeline.set_backend(Backend::Cranelift);
+        let source = ": square ( n -- n ) dup * ;
+: produce ( chan -- n ) dup 10 swap send dup 32 swap send close-channel 0 ;
+: drain ( chan -- n ) dup recv over recv + swap recv + ;
+: publish ( addr -- n ) 42 swap !atomic 0 ;
+7 task: square join
+4 channel dup task: produce swap drain swap join drop
+8 allocate drop dup task: publish join drop @atomic";
+        let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+
+        assert_eq!(result.stack, vec![49, 42, 42]);
+    }
+
+    #[test]
+    fn test_ans_strict_warns_about_nonstandard_words() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        pipeline.set_backend(Backend::Cranelift);
+        pipeline.set_ans_strict(true);
+        let result = pipeline.compile(": twice 2 * ;\n3 twice\narg-count drop", CompilationMode::JIT).unwrap();
+
+        assert_eq!(result.stack, vec![6]);
+        assert_eq!(result.warnings, vec!["nonstandard word 'arg-count' at line 3".to_string()]);
+    }
+
+    #[test]
+    fn test_freestanding_rejects_hosted_words() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        pipeline.set_backend(Backend::Cranelift);
+        pipeline.set_freestanding(true);
+        let result = pipeline.compile(": twice 2 * ;\n3 twice", CompilationMode::JIT).unwrap();
+        assert_eq!(result.stack, vec![6]);
+
+        match pipeline.compile(": twice 2 * ;\n3 twice\narg-count", CompilationMode::JIT) {
+            Err(CompileError::Frontend { stage, error }) => {
+                assert_eq!(stage, FrontendStage::Semantic);
+                assert_eq!(error.location().map(|location| location.line), Some(3));
+            }
+            other => panic!("expected a frontend error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_sandbox_limits_untrusted_runs() {
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        pipeline.set_backend(Backend::Cranelift);
+        pipeline.set_sandbox(Some(Sandbox { fuel: Some(10_000), timeout: None, memory: Some(4096) }));
+        let result = pipeline.compile(": down ( n -- n ) dup if 1 - down then ;\n100 down 7 +", CompilationMode::JIT).unwrap();
+        assert_eq!(result.stack, vec![7]);
+
+        let result = pipeline.compile("1024 allocate swap drop 8192 allocate swap drop", CompilationMode::JIT).unwrap();
+        assert_eq!(result.stack[0], 0, "allocations fit in the sandbox's memory");
+        assert_ne!(result.stack[1], 0, "allocations past it fail");
+
+        let spin = ": spin ( n -- n ) begin 1 + dup 0 < until ;\n0 spin";
+        assert!(matches!(pipeline.compile(spin, CompilationMode::JIT), Err(CompileError::OutOfFuel { fuel: 10_000 })));
+
+        pipeline.set_sandbox(Some(Sandbox { timeout: Some(std::time::Duration::from_millis(20)), ..Sandbox::default() }));
+        assert!(matches!(pipeline.compile(spin, CompilationMode::JIT), Err(CompileError::Cancelled)));
+
+        match pipeline.compile(": run system ;", CompilationMode::JIT) {
+            Err(CompileError::Frontend { error: ForthError::UnavailableWord { word, .. }, .. }) => assert_eq!(word, "system"),
+            other => panic!("expected an unavailable word, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_semantics_hold_in_jit_code_and_folding() {
+        use fastforth_frontend::{Division, Overflow};
+
+        let source = ": div ( a b -- n ) / ;\n: rem ( a b -- n ) mod ;\n-7 2 div -7 2 rem 7 -2 div 7 -2 rem 8 -1 div";
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        pipeline.set_backend(Backend::Cranelift);
+        assert_eq!(pipeline.compile(source, CompilationMode::JIT).unwrap().stack, [-3, -1, -3, 1, -8]);
+        pipeline.set_arithmetic(Arithmetic { division: Division::Floored, ..Arithmetic::default() });
+        assert_eq!(pipeline.compile(source, CompilationMode::JIT).unwrap().stack, [-4, 1, -4, -1, -8]);
+        let folded = pipeline.optimizer.optimize(pipeline.lower_source("-7 2 / -7 2 mod").unwrap()).unwrap();
+        assert_eq!(folded.main, [Instruction::Literal(-4), Instruction::Literal(1)]);
+
+        // The most negative cell by -1 wraps, or traps like any overflow
+        let overflow = ": div ( a b -- n ) / ;\n: inc ( n -- n ) 1 + ;\n-9223372036854775807 1 - -1 div 9223372036854775807 inc";
+        pipeline.set_arithmetic(Arithmetic::default());
+        assert_eq!(pipeline.compile(overflow, CompilationMode::JIT).unwrap().stack, [i64::MIN, i64::MIN]);
+        pipeline.set_arithmetic(Arithmetic { overflow: Overflow::Trapping, ..Arithmetic::default() });
+        let Err(CompileError::Trapped(snapshot)) = pipeline.compile(overflow, CompilationMode::JIT) else {
+            panic!("overflow should trap");
+        };
+        assert_eq!(snapshot.word.as_deref(), Some("div"));
+        let result = pipeline.compile(": inc ( n -- n ) 1 + ;\n9223372036854775806 inc", CompilationMode::JIT).unwrap();
+        assert_eq!(result.stack, [i64::MAX]);
+        assert!(matches!(pipeline.compile(": inc ( n -- n ) 1 + ;\n9223372036854775807 inc", CompilationMode::JIT), Err(CompileError::Trapped(_))));
+    }
+
+    #[test]
+    fn test_checked_arithmetic_throws_result_out_of_range() {
+        use fastforth_frontend::Overflow;
+
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        pipeline.set_backend(Backend::Cranelift);
+        pipeline.set_arithmetic(Arithmetic { overflow: Overflow::Checked, ..Arithmetic::default() });
+        let words = ": mul ( a b -- n ) * ;\n: div ( a b -- n ) / ;\n";
+        let result = pipeline.compile(&format!("{}3 -4 mul -9223372036854775807 -1 div", words), CompilationMode::JIT).unwrap();
+        assert_eq!(result.stack, [-12, i64::MAX]);
+        for overflow in ["4611686018427387904 2 mul", "-9223372036854775807 1 - -1 div"] {
+            let result = pipeline.compile(&format!("{}{}", words, overflow), CompilationMode::JIT);
+            assert!(matches!(result, Err(CompileError::Thrown { code: -11 })), "{}: {:?}", overflow, result);
+        }
+
+        // Folding leaves the overflow for the generated code to throw
+        let folded = pipeline.optimizer.optimize(pipeline.lower_source("9223372036854775807 1 +").unwrap()).unwrap();
+        assert_eq!(folded.main, [Instruction::Literal(i64::MAX), Instruction::IncOne]);
+    }
+
+    #[test]
+    fn test_unsigned_and_bitwise_words() {
+        let cases: [(&str, &[i64]); 6] = [
+            ("-1 1 u< 1 -1 u< -1 1 u>", &[0, -1, -1]),
+            ("-1 60 rshift 1 4 lshift", &[15, 16]),
+            ("3 4 um* -1 2 um*", &[12, 0, -2, 1]),
+            ("7 2 u/mod -1 2 u/mod", &[1, 3, 1, i64::MAX]),
+            ("6 3 xor 5 invert 12 10 and 12 10 or", &[5, -6, 8, 14]),
+            (": hi ( a b -- n ) um* swap drop ;\n-1 -1 hi", &[-2]),
+        ];
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        pipeline.set_backend(Backend::Cranelift);
+        for (source, expected) in cases {
+            let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+            assert_eq!(result.stack, expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_jit_flags_are_all_ones() {
+        let source = ": ge? ( a b -- f ) < invert ;
+            : uge? ( a b -- f ) u< invert ;
+            1 2 < 1 2 ge? if 111 else 222 then -1 1 uge? if 111 else 222 then 2 2 = 1 2 u<";
+        for level in [OptimizationLevel::None, OptimizationLevel::Basic, OptimizationLevel::Standard, OptimizationLevel::Aggressive] {
+            let mut pipeline = CompilationPipeline::new(level);
+            pipeline.set_backend(Backend::Cranelift);
+            let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+            assert_eq!(result.stack, [-1, 222, 111, -1, -1], "{:?}", level);
+        }
+    }
+
+    #[test]
+    fn test_jit_calls_leave_inferred_results() {
+        // Undeclared words leave exactly what their bodies leave: nothing,
+        // or several cells, never a padding 0
+        let cases: [(&str, &[i64]); 3] = [
+            (": w ; 3 w", &[3]),
+            (": w1 -6 14 drop drop ; 10 -7 + w1 13 -", &[-10]),
+            (": two 1 2 ; two + 4", &[3, 4]),
+        ];
+        for level in [OptimizationLevel::None, OptimizationLevel::Basic, OptimizationLevel::Standard, OptimizationLevel::Aggressive] {
+            for (source, expected) in cases {
+                let mut pipeline = CompilationPipeline::new(level);
+                pipeline.set_backend(Backend::Cranelift);
+                let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+                assert_eq!(result.stack, expected, "{} at {:?}", source, level);
+            }
+        }
+    }
+
+    #[test]
+    fn test_execution_tokens_are_values() {
+        // Tokens passed to a word, stored, or taken with ' of a builtin
+        let cases: [(&str, &[i64]); 4] = [
+            (": apply ( n xt -- m ) execute ; 5 [: 2 * ;] apply", &[10]),
+            (": double 2 * ; : apply ( n xt -- m ) execute ; 5 ' double apply 7 ['] negate apply", &[10, -7]),
+            ("variable op : run ( a b -- c ) op @ execute ; ' + op ! 3 4 run ' * op ! 3 4 run", &[7, 12]),
+            (": pick-op ( f -- xt ) if ['] 1+ else ['] 1- then ; 10 -1 pick-op execute 10 0 pick-op execute", &[11, 9]),
+        ];
+        for level in [OptimizationLevel::None, OptimizationLevel::Aggressive] {
+            for (source, expected) in cases {
+                let mut pipeline = CompilationPipeline::new(level);
+                pipeline.set_backend(Backend::Cranelift);
+                let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+                assert_eq!(result.stack, expected, "{} at {:?}", source, level);
+            }
+        }
+
+        // The reference interpreter agrees
+        let pipeline = CompilationPipeline::new(OptimizationLevel::None);
+        let ir = pipeline.lower_source(": apply ( n xt -- m ) execute ; 5 [: 2 * ;] apply").unwrap();
+        assert_eq!(fastforth_optimizer::IrInterpreter::new(&ir).run().unwrap(), [10]);
+
+        // Every word EXECUTE may reach needs the same effect
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::None);
+        pipeline.set_backend(Backend::Cranelift);
+        let error = pipeline.compile(": apply ( n xt -- m ) execute ; 5 [: 2 * ;] apply ' dup apply", CompilationMode::JIT).unwrap_err();
+        assert!(error.to_string().contains("need one stack effect"), "{}", error);
+        let error = pipeline.compile(": run ( xt -- ) execute ; ' . run 1 ' + drop", CompilationMode::JIT).unwrap_err();
+        assert!(error.to_string().contains("'.' is ( 1 -- 0 ) and '+' is ( 2 -- 1 )"), "{}", error);
+        let error = pipeline.compile(": run ( n -- ) execute ; 1 run", CompilationMode::JIT).unwrap_err();
+        assert!(error.to_string().contains("EXECUTE needs an execution token"), "{}", error);
+    }
+
+    #[test]
+    fn test_execute_is_devirtualized() {
+        use fastforth_frontend::ssa::SSAInstruction;
+
+        let source = ": sq ( n -- n ) dup * ; : neg ( n -- n ) negate ; : apply ( n xt -- n ) execute ; \
+                      3 ' sq apply ' neg apply ' neg apply";
+        let program = parse_program(source).unwrap();
+        let indirect_calls = |functions: &[SSAFunction]| -> Vec<Option<String>> {
+            functions
+                .iter()
+                .flat_map(|func| func.blocks.iter().flat_map(|block| &block.instructions))
+                .filter_map(|inst| match inst {
+                    SSAInstruction::CallIndirect { likely, .. } => Some(likely.map(|name| name.to_string())),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // The tiered engine's counts tell the compiler which word EXECUTE
+        // reaches most, which compiled code tests for first
+        let mut engine = TieredEngine::default();
+        engine.load(&program).unwrap();
+        engine.run().unwrap();
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Standard);
+        pipeline.set_backend(Backend::Cranelift);
+        pipeline.profile_run(&engine);
+        let functions = pipeline.run_frontend(&program, CompilationMode::JIT).unwrap();
+        assert_eq!(indirect_calls(&functions), [Some("neg".to_string())]);
+
+        // Both the likely word and the others give what the interpreter does
+        for source in [source, ": sq ( n -- n ) dup * ; : neg ( n -- n ) negate ; : apply ( n xt -- n ) execute ; 3 ' sq apply ' sq apply"] {
+            let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+            assert_eq!(result.stack, engine_stack(source), "{}", source);
+        }
+
+        // Unoptimized code keeps the indirect call
+        let pipeline = CompilationPipeline::new(OptimizationLevel::None);
+        assert_eq!(indirect_calls(&pipeline.run_frontend(&program, CompilationMode::JIT).unwrap()), [None]);
+
+        // With one word's token kept, every EXECUTE calls it
+        let program = parse_program(": sq ( n -- n ) dup * ; : apply ( n xt -- n ) execute ; 3 ' sq apply ' sq apply").unwrap();
+        let pipeline = CompilationPipeline::new(OptimizationLevel::Standard);
+        assert!(indirect_calls(&pipeline.run_frontend(&program, CompilationMode::JIT).unwrap()).is_empty());
+
+        fn engine_stack(source: &str) -> Vec<i64> {
+            let mut engine = TieredEngine::default();
+            engine.load(&parse_program(source).unwrap()).unwrap();
+            engine.run().unwrap();
+            engine.stack().to_vec()
+        }
+    }
+
+    #[test]
+    fn test_bit_manipulation_words() {
+        let source = "255 popcount -1 popcount 8 ctz 0 ctz 1 clz 0 clz 1 63 rol 1 65 rol 3 1 ror";
+        let expected = [8, 64, 3, 64, 63, 64, i64::MIN, 2, i64::MIN | 1];
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        pipeline.set_backend(Backend::Cranelift);
+        assert_eq!(pipeline.compile(source, CompilationMode::JIT).unwrap().stack, expected);
+
+        // Folding and the peephole pass agree with the generated code
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Aggressive);
+        let optimized = pipeline.optimizer.optimize(pipeline.lower_source(source).unwrap()).unwrap();
+        assert_eq!(fastforth_optimizer::IrInterpreter::new(&optimized).run().unwrap(), expected);
+
+        let idioms = ": byte1 ( x -- b ) 8 rshift 255 and ;\n: rot8 ( x -- x' ) dup 8 lshift swap 56 rshift or ;";
+        let optimized = pipeline.optimizer.optimize(pipeline.lower_source(idioms).unwrap()).unwrap();
+        assert!(optimized.words["byte1"].instructions.contains(&Instruction::ExtractBits { shift: 8, mask: 255 }));
+        assert!(optimized.words["rot8"].instructions.contains(&Instruction::Rotl));
+    }
+
+    #[test]
+    fn test_case_jump_tables_and_lookups() {
+        let source = "table: squares 0 1 4 9 16 ;\n\
+                      : op ( a n -- n ) case 0 of 1 + endof 1 of 2 * endof 2 of dup * endof -1 of negate endof endcase ;\n\
+                      : sparse ( n -- n ) case 1 of 10 endof 1000 of 20 endof 0 swap endcase ;\n\
+                      3 squares 5 squares -9 squares 5 0 op 5 1 op 5 2 op 5 -1 op 5 9 op 1000 sparse 7 sparse";
+        let expected = [9, 0, 0, 6, 10, 25, -5, 5, 20, 0];
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        pipeline.set_backend(Backend::Cranelift);
+        assert_eq!(pipeline.compile(source, CompilationMode::JIT).unwrap().stack, expected);
+
+        // A constant key folds through the table
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Aggressive);
+        let optimized = pipeline.optimizer.optimize(pipeline.lower_source(source).unwrap()).unwrap();
+        assert_eq!(fastforth_optimizer::IrInterpreter::new(&optimized).run().unwrap(), expected);
+        let literals = [9, 0, 0].map(Instruction::Literal);
+        assert!(optimized.main.windows(3).any(|window| window == literals), "{:?}", optimized.main);
+    }
+
+    #[test]
+    fn test_comma_separated_table_runs() {
+        let source = "table: sq 0 , 1 , 4 , 9 ;\n2 sq 3 sq 4 sq";
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        assert_eq!(pipeline.compile(source, CompilationMode::JIT).unwrap().stack, [4, 9, 0]);
+    }
+
+    #[test]
+    fn test_definitions_share_variables() {
+        let source = "variable total  struct point field .x field .y end-struct  create origin point allot
+            : add ( n -- ) total @ + total ! ;
+            : move-by ( dx dy -- ) origin .y @ + origin .y ! origin .x @ + origin .x ! ;
+            3 add 4 add 5 2 move-by 1 1 move-by
+            total @ origin .x @ origin .y @";
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        assert_eq!(pipeline.compile(source, CompilationMode::JIT).unwrap().stack, [7, 6, 3]);
+    }
+
+    #[test]
+    fn test_shared_library_exports_words() {
+        if std::process::Command::new("gcc").arg("--version").output().is_err() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("fifth-cdylib-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let source = ": sq ( n -- n ) dup * ;\n\\ @export\n: sum-sq ( a b -- n ) sq swap sq + ;\n\\ @export(difference)\n: diff ( a b -- n ) - ;\n\\ @export\n: shout ( -- ) 72 emit 73 emit cr ;";
+        let pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        let library = pipeline.build_shared_library(source, &dir.join("libwords.so")).unwrap();
+        let symbols: Vec<&str> = library.exports.iter().map(|export| export.symbol.as_str()).collect();
+        assert_eq!(symbols, vec!["sum_sq", "difference", "shout"]);
+        let header = std::fs::read_to_string(&library.header).unwrap();
+        assert!(header.contains("intptr_t difference(intptr_t x1, intptr_t x2);"));
+        assert!(header.contains("void shout(void);"));
+
+        let host = dir.join("host.c");
+        std::fs::write(&host, "#include <stdio.h>\n#include \"libwords.h\"\nint main(void) { printf(\"%ld %ld \", (long)sum_sq(3, 4), (long)difference(10, 3)); fflush(stdout); shout(); return 0; }\n").unwrap();
+        let exe = dir.join("host");
+        let built = std::process::Command::new("gcc")
+            .arg(&host).arg("-I").arg(&dir).arg(&library.library).arg("-o").arg(&exe)
+            .arg(format!("-Wl,-rpath,{}", dir.display()))
+            .output().unwrap();
+        assert!(built.status.success(), "{}", String::from_utf8_lossy(&built.stderr));
+        let output = std::process::Command::new(&exe).output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "25 7 HI\n");
+    }
+
+    #[test]
+    fn test_shared_libraries_build_concurrently() {
+        if std::process::Command::new("gcc").arg("--version").output().is_err() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("fifth-cdylibs-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let libraries: Vec<SharedLibrary> = std::thread::scope(|scope| {
+            let builds: Vec<_> = (0..4)
+                .map(|i| {
+                    let output = dir.join(format!("libword{}.so", i));
+                    scope.spawn(move || {
+                        let pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+                        pipeline.build_shared_library(&format!("\\ @export\n: word{} ( -- n ) {} ;", i, i), &output).unwrap()
+                    })
+                })
+                .collect();
+            builds.into_iter().map(|build| build.join().unwrap()).collect()
+        });
+        let symbols: Vec<String> = libraries.iter().map(|library| library.exports[0].symbol.clone()).collect();
+        // Each library holds its own word, not one built alongside it
+        let contents: Vec<Vec<u8>> = libraries.iter().map(|library| std::fs::read(&library.library).unwrap()).collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(symbols, ["word0", "word1", "word2", "word3"]);
+        for (i, library) in contents.iter().enumerate() {
+            for (j, symbol) in symbols.iter().enumerate() {
+                let found = library.windows(symbol.len()).any(|window| window == symbol.as_bytes());
+                assert_eq!(found, i == j, "library {} and {}", i, symbol);
+            }
+        }
+    }
+
+    #[test]
+    fn test_standalone_executable() {
+        if std::process::Command::new("gcc").arg("--version").output().is_err() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("fifth-exe-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // Linked from a directory with no runtime sources in sight
+        let pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        let exe = pipeline.build_executable(": sq ( n -- n ) dup * ;\n7 sq . 72 emit cr", &dir.join("sq")).unwrap();
+        let output = std::process::Command::new(&exe).current_dir(std::env::temp_dir()).output().unwrap();
+        let map = SourceMap::parse(&std::fs::read_to_string(source_map_path(&exe)).unwrap()).unwrap();
+        let nothing = pipeline.build_executable(": sq dup * ;", &dir.join("none"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "49 H\n");
+        assert!(nothing.is_err(), "definitions alone have nothing to run");
+        let words: Vec<(&str, Option<usize>)> = map.ranges().iter().map(|range| (range.word.as_str(), range.line)).collect();
+        assert_eq!(words, [("sq", Some(1)), ("main", Some(2))]);
+    }
+
+    #[test]
+    fn test_executables_build_concurrently() {
+        if std::process::Command::new("gcc").arg("--version").output().is_err() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("fifth-exes-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let outputs: Vec<String> = std::thread::scope(|scope| {
+            let builds: Vec<_> = (0..4)
+                .map(|i| {
+                    let output = dir.join(format!("exe{}", i));
+                    scope.spawn(move || {
+                        let pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+                        let exe = pipeline.build_executable(&format!("{} .", i), &output).unwrap();
+                        String::from_utf8_lossy(&std::process::Command::new(exe).output().unwrap().stdout).into_owned()
+                    })
+                })
+                .collect();
+            builds.into_iter().map(|build| build.join().unwrap()).collect()
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(outputs, ["0 ", "1 ", "2 ", "3 "]);
+    }
+
+    #[test]
+    fn test_executable_runs_quotation_combinators() {
+        if std::process::Command::new("gcc").arg("--version").output().is_err() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("fifth-combinator-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        let source = ": cells3 ( -- addr ) 3 cells allocate drop 1 over ! 2 over cell+ ! 3 over 2 cells + ! ;
+            : show ( addr len -- ) [: . ;] each ;
+            cells3 dup 3 [: 10 * ;] map! dup 3 show 0 5 [: + ;] reduce . cells3 1 [: . ;] each";
+        let exe = pipeline.build_executable(source, &dir.join("combinators")).unwrap();
+        let output = std::process::Command::new(&exe).output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "10 20 30 5 1 ");
+    }
+
+    #[test]
+    fn test_executable_keeps_variables() {
+        if std::process::Command::new("gcc").arg("--version").output().is_err() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("fifth-variable-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        let source = "variable count  create buf 4 cells allot
+            : bump ( -- ) count @ 1+ count ! ;
+            : squares ( -- ) 4 0 do i i * buf i cells + ! loop ;
+            bump bump squares count @ . buf 3 cells + @ .";
+        let exe = pipeline.build_executable(source, &dir.join("variables")).unwrap();
+        let output = std::process::Command::new(&exe).output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "2 9 ");
+    }
+
+    #[test]
+    fn test_jit_source_map() {
+        let source = ": sum-sq ( a b -- n )\n  dup *\n  swap dup *\n  + ;\n3 4 sum-sq";
+        let pipeline = CompilationPipeline::new(OptimizationLevel::None);
+        let program = pipeline.prepare_jit_program(&parse_program(source).unwrap(), &[]).unwrap();
+        let map = program.source_map();
+
+        let address = program.word("sum-sq").unwrap() as u64;
+        let range = map.find(address).unwrap();
+        assert_eq!((range.word.as_str(), range.line), ("sum-sq", Some(2)));
+        let lines: Vec<usize> = range.lines.iter().map(|&(_, line)| line).collect();
+        assert_eq!(lines, [2, 3, 4]);
+        let (offset, _) = range.lines[1];
+        assert_eq!(range.line_at(address + offset), Some(3));
+        assert_eq!(map.word("main").and_then(|main| main.line_at(main.start + main.size - 1)), Some(5));
+    }
+
+    #[test]
+    fn test_words_named_like_c_functions() {
+        // Mangled symbols keep words from colliding with libc or the runtime
+        let source = ": malloc ( -- n ) 41 ;\n: forth_io_dot ( n -- n ) 1 + ;\n\\ @export(next_heap)\n: next ( -- n ) malloc forth_io_dot ;\nnext";
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        pipeline.set_backend(Backend::Cranelift);
+        assert_eq!(pipeline.compile(source, CompilationMode::JIT).unwrap().stack, vec![42]);
+
+        if std::process::Command::new("gcc").arg("--version").output().is_err() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("fifth-mangle-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let object = dir.join("words.o");
+        std::fs::write(&object, pipeline.compile_object(source).unwrap()).unwrap();
+        let host = dir.join("host.c");
+        std::fs::write(&host, "#include <stdio.h>\n#include <stdint.h>\n#include <stdlib.h>\nintptr_t next_heap(void);\nint main(void) { free(malloc(8)); printf(\"%ld\\n\", (long)next_heap()); return 0; }\n").unwrap();
+        let exe = dir.join("host");
+        let built = std::process::Command::new("gcc").arg(&host).arg(&object).arg("-o").arg(&exe).output().unwrap();
+        let output = built.status.success().then(|| std::process::Command::new(&exe).output().unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(built.status.success(), "{}", String::from_utf8_lossy(&built.stderr));
+        assert_eq!(String::from_utf8_lossy(&output.unwrap().stdout), "42\n");
+    }
+
+    #[test]
+    fn test_objects_build_concurrently() {
+        if std::process::Command::new("as").arg("--version").output().is_err() {
+            return;
+        }
+        let objects: Vec<Vec<u8>> = std::thread::scope(|scope| {
+            let builds: Vec<_> = (0..4)
+                .map(|i| scope.spawn(move || {
+                    let pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+                    pipeline.compile_object(&format!(": word{} ( -- n ) {} ;", i, i)).unwrap()
+                }))
+                .collect();
+            builds.into_iter().map(|build| build.join().unwrap()).collect()
+        });
+        for (i, object) in objects.iter().enumerate() {
+            let symbol = format!("word{}", i);
+            assert!(object.windows(symbol.len()).any(|window| window == symbol.as_bytes()), "object {} lacks {}", i, symbol);
+        }
+    }
+
+    #[test]
+    fn test_shared_library_needs_c_signatures() {
+        let pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        let output = std::env::temp_dir().join("fifth-unbuilt.so");
+        assert!(pipeline.build_shared_library(": sq dup * ;", &output).is_err(), "nothing is exported");
+        assert!(pipeline.build_shared_library("\\ @export\n: sq dup * ;", &output).is_err(), "no stack effect");
+        assert!(pipeline.build_shared_library("\\ @export\n: two ( -- a b ) 1 2 ;", &output).is_err());
+        assert!(pipeline.build_shared_library("\\ @export(f)\n: a ( -- ) ;\n\\ @export(f)\n: b ( -- ) ;", &output).is_err());
+    }
+
+    #[test]
+    fn test_constant_time_words() {
+        let source = "\\ @constant-time\n: ct-max ( a b -- m ) over over < if swap then drop ;\n\\ @constant-time\n: ct-div ( a b -- q ) dup if / else drop then ;\n\\ @constant-time\n: ct-abs ( n -- |n| ) abs ;\n-5 3 ct-max -7 ct-abs 8 2 ct-div 0 ct-abs";
+        let mut pipeline = CompilationPipeline::new(OptimizationLevel::Basic);
+        pipeline.set_backend(Backend::Cranelift);
+        let result = pipeline.compile(source, CompilationMode::JIT).unwrap();
+
+        assert_eq!(result.stack, vec![3, 7, 4, 0]);
+        assert_eq!(result.warnings, vec!["secret-dependent branch in 'ct-div' at line 4".to_string()]);
+
+        pipeline.set_constant_time(true);
+        let program = parse_program(": plain ( n -- n' ) dup 0 < if negate then ;").unwrap();
+        let functions = pipeline.run_frontend(&program, CompilationMode::AOT).unwrap();
+        assert_eq!(functions[0].blocks.len(), 1, "--constant-time covers unmarked words");
+        assert!(pipeline.lower_to_ir(&program).words["plain"].attributes.constant_time);
+    }
 }
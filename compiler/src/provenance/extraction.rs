@@ -7,35 +7,67 @@ use crate::provenance::metadata::ProvenanceMetadata;
 use std::collections::HashMap;
 use std::path::Path;
 
+/// A word's provenance metadata together with the text it came from
+#[derive(Debug, Clone)]
+pub struct ProvenanceRecord {
+    pub word: String,
+    pub metadata: ProvenanceMetadata,
+    /// Comment lines from `GENERATED_BY` up to the definition, trimmed,
+    /// without the signature line
+    pub header: Vec<String>,
+    /// Source of the definition, from `: word` through `;`
+    pub code: String,
+    /// Index of the `: word` line in the source
+    pub line: usize,
+    /// Index of the `\ SIGNATURE:` line, if there is one
+    pub signature_line: Option<usize>,
+}
+
 /// Extract provenance metadata from Forth source code
 pub fn extract_provenance(source: &str) -> Result<HashMap<String, ProvenanceMetadata>> {
-    let mut metadata_map = HashMap::new();
-    let mut current_word = None;
-    let mut current_metadata = None;
+    Ok(extract_records(source)?
+        .into_iter()
+        .map(|record| (record.word, record.metadata))
+        .collect())
+}
 
-    for line in source.lines() {
+/// Extract each word's provenance record, in source order
+pub fn extract_records(source: &str) -> Result<Vec<ProvenanceRecord>> {
+    let mut records = Vec::new();
+    let mut current: Option<(String, usize)> = None;
+    let mut current_metadata: Option<ProvenanceMetadata> = None;
+    let mut text = RecordText::default();
+
+    for (index, line) in source.lines().enumerate() {
         let trimmed = line.trim();
 
         // Check for word definition start
         if trimmed.starts_with(": ") {
             // If we have a previous word without metadata, save it
-            if let Some(word) = current_word.take() {
+            if let Some((word, start)) = current.take() {
                 if let Some(metadata) = current_metadata.take() {
-                    metadata_map.insert(word, metadata);
+                    records.push(text.finish(word, start, metadata));
                 }
             }
+            text.code.clear();
 
             // Extract word name and keep any accumulated metadata
             if let Some(word_name) = trimmed.split_whitespace().nth(1) {
-                current_word = Some(word_name.to_string());
+                current = Some((word_name.to_string(), index));
                 // Don't reset current_metadata - keep any metadata from preceding comments
             }
         }
+        if current.is_some() {
+            text.code.push_str(line);
+            text.code.push('\n');
+        }
 
         // Parse metadata comments
         if trimmed.starts_with("\\ GENERATED_BY: ") {
             let generated_by = trimmed.trim_start_matches("\\ GENERATED_BY: ").to_string();
             current_metadata = Some(ProvenanceMetadata::new(generated_by));
+            text.header.clear();
+            text.signature_line = None;
         } else if let Some(ref mut metadata) = current_metadata {
             if trimmed.starts_with("\\ PATTERN_ID: ") {
                 let pattern_id = trimmed.trim_start_matches("\\ PATTERN_ID: ").to_string();
@@ -50,6 +82,13 @@ pub fn extract_provenance(source: &str) -> Result<HashMap<String, ProvenanceMeta
                 // Parse verification status
                 let status_str = trimmed.trim_start_matches("\\ VERIFIED: ");
                 metadata.verification = parse_verification_status(status_str);
+            } else if let Some(check) = trimmed.strip_prefix("\\ PROPERTY: ") {
+                if let Ok(check) = check.parse() {
+                    metadata.verification.properties.push(check);
+                }
+            } else if let Some(signature) = trimmed.strip_prefix("\\ SIGNATURE: ") {
+                metadata.signature = signature.parse().ok();
+                text.signature_line = Some(index);
             } else if trimmed.starts_with("\\ OPTIMIZATION_LEVEL: ") {
                 let level = trimmed.trim_start_matches("\\ OPTIMIZATION_LEVEL: ").to_string();
                 metadata.context.optimization_level = Some(level);
@@ -58,30 +97,71 @@ pub fn extract_provenance(source: &str) -> Result<HashMap<String, ProvenanceMeta
                 metadata.context.performance_target = Some(target);
             }
         }
+        if current.is_none() && current_metadata.is_some() && trimmed.starts_with('\\') && text.signature_line != Some(index) {
+            text.header.push(trimmed.to_string());
+        }
 
         // Check for word definition end
-        if trimmed.ends_with(";") && current_word.is_some() {
-            if let (Some(word), Some(metadata)) = (current_word.take(), current_metadata.take()) {
-                metadata_map.insert(word, metadata);
+        if trimmed.ends_with(";") && current.is_some() {
+            if let (Some((word, start)), Some(metadata)) = (current.take(), current_metadata.take()) {
+                records.push(text.finish(word, start, metadata));
             }
         }
     }
 
     // Handle any remaining metadata
-    if let (Some(word), Some(metadata)) = (current_word, current_metadata) {
-        metadata_map.insert(word, metadata);
+    if let (Some((word, start)), Some(metadata)) = (current, current_metadata) {
+        records.push(text.finish(word, start, metadata));
     }
 
-    Ok(metadata_map)
+    Ok(records)
+}
+
+/// Text gathered for the record being read
+#[derive(Default)]
+struct RecordText {
+    header: Vec<String>,
+    code: String,
+    signature_line: Option<usize>,
+}
+
+impl RecordText {
+    fn finish(&mut self, word: String, line: usize, metadata: ProvenanceMetadata) -> ProvenanceRecord {
+        ProvenanceRecord {
+            word,
+            metadata,
+            header: std::mem::take(&mut self.header),
+            code: std::mem::take(&mut self.code),
+            line,
+            signature_line: self.signature_line.take(),
+        }
+    }
 }
 
 /// Extract provenance metadata from a compiled binary
+///
+/// Finds the records whose source text was carried into the binary, such
+/// as a string table or an embedded copy of the program.
 pub fn extract_from_binary<P: AsRef<Path>>(binary_path: P) -> Result<HashMap<String, ProvenanceMetadata>> {
-    // TODO: Implement binary metadata extraction from debug symbols
-    // For now, return an error indicating this is not yet implemented
-    Err(CompileError::InternalError(
-        "Binary provenance extraction not yet implemented. Use extract_provenance() for source code.".to_string()
-    ))
+    let path = binary_path.as_ref();
+    let bytes = std::fs::read(path).map_err(|e| CompileError::IoError(path.to_path_buf(), e))?;
+    Ok(extract_from_binary_text(&bytes)?
+        .into_iter()
+        .map(|record| (record.word, record.metadata))
+        .collect())
+}
+
+/// Extract records from the printable text inside binary data
+///
+/// Runs of at least four printable characters are read as lines, the way
+/// `strings` would show them.
+pub fn extract_from_binary_text(bytes: &[u8]) -> Result<Vec<ProvenanceRecord>> {
+    let text: Vec<&str> = bytes
+        .split(|&b| !(b == b'\t' || b == b'\n' || (0x20..0x7f).contains(&b)))
+        .filter(|run| run.len() >= 4)
+        .filter_map(|run| std::str::from_utf8(run).ok())
+        .collect();
+    extract_records(&text.join("\n"))
 }
 
 /// Parse verification status from string
@@ -278,6 +358,24 @@ mod tests {
         assert_eq!(meta.pattern_id, Some("RECURSIVE_004".to_string()));
     }
 
+    #[test]
+    fn test_extract_property_checks() {
+        let source = r#"
+\ GENERATED_BY: spec-generator
+\ VERIFIED: stack_balanced=true, tests_passed=1/1, type_checked=true, compiled=true
+\ PROPERTY: commutative [verified]
+\ PROPERTY: add(a, 0) = a [unknown: not proved; held on 8 sampled inputs]
+: add ( a b -- c )
+  + ;
+"#;
+
+        let metadata = extract_provenance(source).unwrap();
+        let properties = &metadata["add"].verification.properties;
+        assert_eq!(properties.len(), 2);
+        assert_eq!(properties[0].to_string(), "commutative [verified]");
+        assert_eq!(properties[1].property, "add(a, 0) = a");
+    }
+
     #[test]
     fn test_extract_multiple_words() {
         let source = r#"
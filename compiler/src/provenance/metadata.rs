@@ -2,6 +2,8 @@
 //!
 //! Defines the metadata format for tracking code generation provenance
 
+use super::signing::ProvenanceSignature;
+use crate::symbolic::{PropertyCheck, PropertyStatus};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -28,6 +30,10 @@ pub struct ProvenanceMetadata {
 
     /// Additional custom metadata
     pub custom: HashMap<String, String>,
+
+    /// Signature over this record and its definition
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<ProvenanceSignature>,
 }
 
 impl ProvenanceMetadata {
@@ -41,6 +47,7 @@ impl ProvenanceMetadata {
             spec_hash: None,
             context: GenerationContext::default(),
             custom: HashMap::new(),
+            signature: None,
         }
     }
 
@@ -86,6 +93,10 @@ impl ProvenanceMetadata {
         comment.push_str(&format!("\\ TIMESTAMP: {}\n", self.timestamp));
         comment.push_str(&format!("\\ VERIFIED: {}\n", self.verification.summary()));
 
+        for check in &self.verification.properties {
+            comment.push_str(&format!("\\ PROPERTY: {}\n", check));
+        }
+
         if let Some(spec_hash) = &self.spec_hash {
             comment.push_str(&format!("\\ SPEC_HASH: {}\n", spec_hash));
         }
@@ -104,6 +115,10 @@ impl ProvenanceMetadata {
             comment.push_str(&format!("\\ {}: {}\n", key.to_uppercase(), value));
         }
 
+        if let Some(signature) = &self.signature {
+            comment.push_str(&format!("\\ SIGNATURE: {}\n", signature));
+        }
+
         comment
     }
 
@@ -141,6 +156,10 @@ pub struct VerificationStatus {
 
     /// Verification timestamp
     pub verified_at: Option<String>,
+
+    /// Declared properties checked by symbolic execution
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<PropertyCheck>,
 }
 
 impl VerificationStatus {
@@ -180,6 +199,12 @@ impl VerificationStatus {
         self
     }
 
+    /// Set property check results
+    pub fn with_properties(mut self, properties: Vec<PropertyCheck>) -> Self {
+        self.properties = properties;
+        self
+    }
+
     /// Mark as verified with current timestamp
     pub fn mark_verified(mut self) -> Self {
         self.verified_at = Some(chrono::Utc::now().to_rfc3339());
@@ -193,11 +218,20 @@ impl VerificationStatus {
             && self.compiled
             && self.tests_passed == self.tests_total
             && self.tests_total > 0
+            && self.properties_falsified() == 0
     }
 
-    /// Check if there are test failures
+    /// Check if there are test failures or falsified properties
     pub fn has_failures(&self) -> bool {
-        self.tests_total > 0 && self.tests_passed < self.tests_total
+        (self.tests_total > 0 && self.tests_passed < self.tests_total) || self.properties_falsified() > 0
+    }
+
+    /// Number of declared properties shown not to hold
+    pub fn properties_falsified(&self) -> usize {
+        self.properties
+            .iter()
+            .filter(|check| matches!(check.status, PropertyStatus::Falsified { .. }))
+            .count()
     }
 
     /// Get test pass rate (0.0-1.0)
@@ -297,7 +331,7 @@ impl GenerationContext {
 }
 
 // Chrono is needed for timestamps, add a simple implementation
-mod chrono {
+pub(super) mod chrono {
     pub struct Utc;
 
     impl Utc {
@@ -366,6 +400,22 @@ mod tests {
         assert_eq!(status.test_pass_rate(), 0.4);
     }
 
+    #[test]
+    fn test_falsified_property_is_a_failure() {
+        let status = VerificationStatus::new()
+            .with_stack_balanced(true)
+            .with_tests(3, 3)
+            .with_type_checked(true)
+            .with_compiled(true)
+            .with_properties(vec!["commutative [falsified: a = 0, b = 1 gives -1 <> 1]".parse().unwrap()]);
+
+        assert!(!status.is_verified());
+        assert!(status.has_failures());
+
+        let comment = ProvenanceMetadata::new("agent".to_string()).with_verification(status).to_forth_comment();
+        assert!(comment.contains("\\ PROPERTY: commutative [falsified: a = 0, b = 1 gives -1 <> 1]\n"));
+    }
+
     #[test]
     fn test_generation_context() {
         let context = GenerationContext::new()
@@ -5,10 +5,14 @@
 pub mod metadata;
 pub mod extraction;
 pub mod embedding;
+pub mod signing;
+pub mod sbom;
 
 pub use metadata::{ProvenanceMetadata, GenerationContext, VerificationStatus};
 pub use extraction::extract_provenance;
 pub use embedding::embed_provenance;
+pub use sbom::ProvenanceBom;
+pub use signing::{ProvenanceSignature, ProvenanceSigner, SignatureStatus};
 
 use crate::error::{CompileError, Result};
 use serde::{Deserialize, Serialize};
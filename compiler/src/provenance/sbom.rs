@@ -0,0 +1,289 @@
+//! Provenance bill of materials
+//!
+//! Gathers the provenance records of a whole project into one CycloneDX
+//! 1.5 JSON document, so compliance tooling can see which words were
+//! generated by which agent, from which spec and pattern, and how far
+//! they were verified. Each word is a component; Fifth-specific facts are
+//! carried as `fifth:` properties.
+
+use super::extraction::{extract_from_binary_text, extract_records, ProvenanceRecord};
+use super::metadata::chrono;
+use super::signing::{verify_record, SignatureStatus};
+use crate::error::{CompileError, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// Extensions of the source files scanned in a project directory
+const SOURCE_EXTENSIONS: [&str; 4] = ["fs", "fth", "4th", "forth"];
+
+/// One word's provenance and where it was found
+#[derive(Debug, Clone)]
+pub struct BomEntry {
+    pub file: PathBuf,
+    pub record: ProvenanceRecord,
+    pub signature: SignatureStatus,
+}
+
+/// Provenance records collected across a project
+#[derive(Debug, Clone)]
+pub struct ProvenanceBom {
+    pub name: String,
+    pub entries: Vec<BomEntry>,
+}
+
+impl ProvenanceBom {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into(), entries: Vec::new() }
+    }
+
+    /// Collect every record under `path`
+    ///
+    /// A directory is searched recursively for Forth sources; a file is
+    /// read as source if it is text and scanned as a binary otherwise.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let name = path
+            .file_stem()
+            .or_else(|| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        let mut bom = Self::new(name);
+
+        if path.is_dir() {
+            let mut files = Vec::new();
+            collect_sources(path, &mut files).map_err(|e| CompileError::IoError(path.to_path_buf(), e))?;
+            files.sort();
+            for file in files {
+                let source = std::fs::read_to_string(&file).map_err(|e| CompileError::IoError(file.clone(), e))?;
+                let relative = file.strip_prefix(path).unwrap_or(&file).to_path_buf();
+                bom.add_records(relative, extract_records(&source)?);
+            }
+        } else {
+            let bytes = std::fs::read(path).map_err(|e| CompileError::IoError(path.to_path_buf(), e))?;
+            let records = match String::from_utf8(bytes) {
+                Ok(source) => extract_records(&source)?,
+                Err(e) => extract_from_binary_text(e.as_bytes())?,
+            };
+            let file = PathBuf::from(path.file_name().unwrap_or(path.as_os_str()));
+            bom.add_records(file, records);
+        }
+
+        Ok(bom)
+    }
+
+    /// Add the records found in `file`
+    pub fn add_records(&mut self, file: PathBuf, records: Vec<ProvenanceRecord>) {
+        for record in records {
+            let signature = verify_record(&record, &[]);
+            self.entries.push(BomEntry { file: file.clone(), record, signature });
+        }
+    }
+
+    /// Number of records whose verification passed
+    pub fn verified_count(&self) -> usize {
+        self.entries.iter().filter(|e| e.record.metadata.verification.is_verified()).count()
+    }
+
+    /// The records as a CycloneDX document
+    pub fn to_cyclonedx(&self) -> CycloneDx {
+        let components: Vec<Component> = self.entries.iter().map(component).collect();
+
+        let mut agents: Vec<&str> = self.entries.iter().map(|e| e.record.metadata.generated_by.as_str()).collect();
+        agents.sort_unstable();
+        agents.dedup();
+
+        let mut digest = Sha256::new();
+        for component in &components {
+            digest.update(component.bom_ref.as_bytes());
+            digest.update(&component.hashes[0].content);
+        }
+
+        CycloneDx {
+            bom_format: "CycloneDX".to_string(),
+            spec_version: "1.5".to_string(),
+            serial_number: format!("urn:uuid:{}", uuid_from(&digest.finalize())),
+            version: 1,
+            metadata: BomMetadata {
+                timestamp: chrono::Utc::now().to_rfc3339(),
+                tools: Tools {
+                    components: vec![Tool {
+                        kind: "application".to_string(),
+                        name: "fastforth".to_string(),
+                        version: env!("CARGO_PKG_VERSION").to_string(),
+                    }],
+                },
+                component: Subject {
+                    kind: "application".to_string(),
+                    bom_ref: self.name.clone(),
+                    name: self.name.clone(),
+                },
+                properties: vec![
+                    property("fifth:words", self.entries.len().to_string()),
+                    property("fifth:verified", self.verified_count().to_string()),
+                    property("fifth:agents", agents.join(", ")),
+                ],
+            },
+            components,
+        }
+    }
+}
+
+fn component(entry: &BomEntry) -> Component {
+    let metadata = &entry.record.metadata;
+    let code = entry.record.code.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    let mut properties = vec![
+        property("fifth:file", entry.file.display().to_string()),
+        property("fifth:generated_by", metadata.generated_by.clone()),
+        property("fifth:timestamp", metadata.timestamp.clone()),
+    ];
+    if let Some(pattern) = &metadata.pattern_id {
+        properties.push(property("fifth:pattern_id", pattern.clone()));
+    }
+    if let Some(spec_hash) = &metadata.spec_hash {
+        properties.push(property("fifth:spec_hash", spec_hash.clone()));
+    }
+    properties.push(property("fifth:verified", metadata.verification.is_verified().to_string()));
+    properties.push(property("fifth:verification", metadata.verification.summary()));
+    for check in &metadata.verification.properties {
+        properties.push(property("fifth:property", check.to_string()));
+    }
+    properties.push(property("fifth:signature", entry.signature.to_string()));
+
+    Component {
+        kind: "library".to_string(),
+        bom_ref: format!("{}#{}", entry.file.display(), entry.record.word),
+        name: entry.record.word.clone(),
+        author: metadata.generated_by.clone(),
+        hashes: vec![Hash { alg: "SHA-256".to_string(), content: hex::encode(Sha256::digest(code.as_bytes())) }],
+        properties,
+    }
+}
+
+fn property(name: &str, value: String) -> Property {
+    Property { name: name.to_string(), value }
+}
+
+/// Version-4-shaped UUID from the first 16 bytes of a digest, so the same
+/// project contents always get the same serial number
+fn uuid_from(digest: &[u8]) -> String {
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex::encode(bytes);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+fn collect_sources(dir: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_sources(&path, paths)?;
+        } else if path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| SOURCE_EXTENSIONS.contains(&ext)) {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// CycloneDX 1.5 bill of materials
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CycloneDx {
+    pub bom_format: String,
+    pub spec_version: String,
+    pub serial_number: String,
+    pub version: u32,
+    pub metadata: BomMetadata,
+    pub components: Vec<Component>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BomMetadata {
+    pub timestamp: String,
+    pub tools: Tools,
+    pub component: Subject,
+    pub properties: Vec<Property>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tools {
+    pub components: Vec<Tool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tool {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub name: String,
+    pub version: String,
+}
+
+/// The project the document describes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subject {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(rename = "bom-ref")]
+    pub bom_ref: String,
+    pub name: String,
+}
+
+/// One generated word
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Component {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(rename = "bom-ref")]
+    pub bom_ref: String,
+    pub name: String,
+    pub author: String,
+    pub hashes: Vec<Hash>,
+    pub properties: Vec<Property>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hash {
+    pub alg: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Property {
+    pub name: String,
+    pub value: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cyclonedx_components() {
+        let source = "\\ GENERATED_BY: agent-1\n\\ PATTERN_ID: DUP_TRANSFORM_001\n\\ VERIFIED: stack_balanced=true, tests_passed=2/2, type_checked=true, compiled=true\n: square dup * ;\n: helper 1 + ;\n\\ GENERATED_BY: agent-2\n\\ SPEC_HASH: abc123\n: cube dup dup * * ;\n";
+        let mut bom = ProvenanceBom::new("demo");
+        bom.add_records(PathBuf::from("lib/math.fs"), extract_records(source).unwrap());
+
+        let doc = bom.to_cyclonedx();
+        assert_eq!(doc.bom_format, "CycloneDX");
+        assert_eq!(doc.components.len(), 2);
+        assert_eq!(bom.verified_count(), 1);
+
+        let square = &doc.components[0];
+        assert_eq!(square.bom_ref, "lib/math.fs#square");
+        assert_eq!(square.author, "agent-1");
+        let value = |c: &Component, name: &str| c.properties.iter().find(|p| p.name == name).map(|p| p.value.clone());
+        assert_eq!(value(square, "fifth:pattern_id").as_deref(), Some("DUP_TRANSFORM_001"));
+        assert_eq!(value(square, "fifth:verified").as_deref(), Some("true"));
+        assert_eq!(value(square, "fifth:signature").as_deref(), Some("unsigned"));
+        assert_eq!(value(&doc.components[1], "fifth:spec_hash").as_deref(), Some("abc123"));
+
+        // Same contents, same serial number
+        assert_eq!(doc.serial_number, bom.to_cyclonedx().serial_number);
+        let json = serde_json::to_value(&doc).unwrap();
+        assert_eq!(json["specVersion"], "1.5");
+        assert_eq!(json["components"][1]["bom-ref"], "lib/math.fs#cube");
+    }
+}
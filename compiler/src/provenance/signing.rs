@@ -0,0 +1,403 @@
+//! Provenance signing
+//!
+//! Signs provenance records with Ed25519 so that edits to either the
+//! metadata or the definition it describes can be detected. The signature
+//! covers the record's comment lines as written, from `GENERATED_BY` up
+//! to the definition, plus the definition with its whitespace collapsed
+//! outside string literals, and is embedded as one more comment line:
+//!
+//! ```text
+//! \ SIGNATURE: ed25519:<public key hex>:<signature hex>
+//! ```
+//!
+//! The signing key is a 32-byte secret in hex, given directly or through
+//! a key file named by `FIFTH_PROVENANCE_KEY`. A signature only proves
+//! something when checked against a trusted public key: anyone can edit a
+//! record and sign it again with a key of their own, so a signature no key
+//! vouches for is reported as self-signed rather than valid.
+
+use super::extraction::{extract_records, ProvenanceRecord};
+use super::metadata::ProvenanceMetadata;
+use crate::error::CompileError;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Only signature scheme understood
+pub const SIGNATURE_ALGORITHM: &str = "ed25519";
+
+/// Environment variable holding the signing key, or a path to it
+pub const KEY_ENV: &str = "FIFTH_PROVENANCE_KEY";
+
+/// First line of every signed record, versioning the canonical form
+const RECORD_HEADER: &str = "fifth-provenance-v1";
+
+#[derive(Debug, Error)]
+pub enum SigningError {
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+
+    #[error("Cannot read key file {0}: {1}")]
+    KeyFile(PathBuf, #[source] std::io::Error),
+
+    #[error("No randomness available: {0}")]
+    Random(String),
+
+    #[error(transparent)]
+    Extraction(#[from] CompileError),
+}
+
+pub type Result<T> = std::result::Result<T, SigningError>;
+
+/// Signature embedded alongside a provenance record
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvenanceSignature {
+    pub algorithm: String,
+    pub public_key: String,
+    pub signature: String,
+}
+
+impl fmt::Display for ProvenanceSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.algorithm, self.public_key, self.signature)
+    }
+}
+
+impl FromStr for ProvenanceSignature {
+    type Err = SigningError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.trim().splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(algorithm), Some(public_key), Some(signature)) => Ok(Self {
+                algorithm: algorithm.to_string(),
+                public_key: public_key.to_string(),
+                signature: signature.to_string(),
+            }),
+            _ => Err(SigningError::InvalidSignature(format!("expected algorithm:key:signature, got '{}'", s))),
+        }
+    }
+}
+
+/// Outcome of checking one record's signature
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SignatureStatus {
+    /// Signed by a trusted key and intact
+    Valid { public_key: String },
+    /// Intact, but signed by a key that is not trusted
+    Untrusted { public_key: String },
+    /// Intact, but no key was trusted to check the signer against
+    SelfSigned { public_key: String },
+    /// The record or definition changed after signing, or the signature
+    /// is malformed
+    Tampered { reason: String },
+    Unsigned,
+}
+
+impl SignatureStatus {
+    pub fn is_valid(&self) -> bool {
+        matches!(self, SignatureStatus::Valid { .. })
+    }
+}
+
+impl fmt::Display for SignatureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureStatus::Valid { public_key } => write!(f, "valid (key {})", short_key(public_key)),
+            SignatureStatus::Untrusted { public_key } => write!(f, "untrusted key {}", short_key(public_key)),
+            SignatureStatus::SelfSigned { public_key } => {
+                write!(f, "self-signed by key {} (no trusted key)", short_key(public_key))
+            }
+            SignatureStatus::Tampered { reason } => write!(f, "TAMPERED: {}", reason),
+            SignatureStatus::Unsigned => write!(f, "unsigned"),
+        }
+    }
+}
+
+fn short_key(key: &str) -> &str {
+    &key[..key.len().min(16)]
+}
+
+/// Signs provenance records with an Ed25519 key
+pub struct ProvenanceSigner {
+    key: SigningKey,
+}
+
+impl ProvenanceSigner {
+    /// Create a signer with a fresh random key
+    pub fn generate() -> Result<Self> {
+        let mut secret = [0u8; 32];
+        getrandom::getrandom(&mut secret).map_err(|e| SigningError::Random(e.to_string()))?;
+        Ok(Self { key: SigningKey::from_bytes(&secret) })
+    }
+
+    /// Signer for a secret key given in hex
+    pub fn from_hex(secret: &str) -> Result<Self> {
+        let bytes = hex::decode(secret.trim()).map_err(|e| SigningError::InvalidKey(e.to_string()))?;
+        let secret: [u8; 32] = bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| SigningError::InvalidKey(format!("expected 32 bytes, got {}", bytes.len())))?;
+        Ok(Self { key: SigningKey::from_bytes(&secret) })
+    }
+
+    /// Signer for a key file holding the secret key in hex
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let secret = std::fs::read_to_string(path).map_err(|e| SigningError::KeyFile(path.to_path_buf(), e))?;
+        Self::from_hex(&secret)
+    }
+
+    /// Signer configured by `FIFTH_PROVENANCE_KEY`, if it is set
+    ///
+    /// The variable holds either the secret key in hex or a key file path.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(KEY_ENV) {
+            Ok(value) if Path::new(value.trim()).is_file() => Self::from_file(value.trim()).map(Some),
+            Ok(value) if !value.trim().is_empty() => Self::from_hex(&value).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Secret key in hex, as stored in key files
+    pub fn secret_hex(&self) -> String {
+        hex::encode(self.key.to_bytes())
+    }
+
+    /// Public key in hex, as embedded in signatures
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.key.verifying_key().to_bytes())
+    }
+
+    /// Sign a record given its header lines and definition
+    pub fn sign(&self, word: &str, header: &[String], code: &str) -> ProvenanceSignature {
+        let signature = self.key.sign(canonical_record(word, header, code).as_bytes());
+        ProvenanceSignature {
+            algorithm: SIGNATURE_ALGORITHM.to_string(),
+            public_key: self.public_key_hex(),
+            signature: hex::encode(signature.to_bytes()),
+        }
+    }
+
+    /// Embed `metadata` above a definition, signed
+    pub fn embed(&self, word: &str, code: &str, metadata: &ProvenanceMetadata) -> String {
+        let mut unsigned = metadata.clone();
+        unsigned.signature = None;
+        let comment = unsigned.to_forth_comment();
+        let header: Vec<String> = comment.lines().map(|line| line.trim().to_string()).collect();
+
+        let mut output = comment;
+        output.push_str(&format!("\\ SIGNATURE: {}\n", self.sign(word, &header, code)));
+        output.push_str(code);
+        if !code.ends_with('\n') {
+            output.push('\n');
+        }
+        output
+    }
+
+    /// Sign every provenance record in `source`, replacing old signatures
+    pub fn sign_source(&self, source: &str) -> Result<String> {
+        let records = extract_records(source)?;
+        let mut lines: Vec<String> = source.lines().map(str::to_string).collect();
+
+        // Back to front, so earlier line numbers stay valid
+        for record in records.iter().rev() {
+            let signature = self.sign(&record.word, &record.header, &record.code);
+            let indent: String = lines[record.line].chars().take_while(|c| c.is_whitespace()).collect();
+            lines.insert(record.line, format!("{}\\ SIGNATURE: {}", indent, signature));
+            if let Some(old) = record.signature_line {
+                lines.remove(old);
+            }
+        }
+
+        let mut signed = lines.join("\n");
+        if source.ends_with('\n') {
+            signed.push('\n');
+        }
+        Ok(signed)
+    }
+}
+
+/// Text a record's signature covers
+pub fn canonical_record(word: &str, header: &[String], code: &str) -> String {
+    let mut record = format!("{}\nword {}\n", RECORD_HEADER, word);
+    for line in header {
+        if !line.starts_with("\\ SIGNATURE:") {
+            record.push_str(line.trim());
+            record.push('\n');
+        }
+    }
+    record.push_str("code ");
+    record.push_str(&canonical_code(code));
+    record
+}
+
+/// Words whose text up to a closing delimiter is a string literal
+const STRING_WORDS: [(&str, char); 6] =
+    [(".\"", '"'), ("s\"", '"'), ("c\"", '"'), ("abort\"", '"'), ("s\\\"", '"'), (".(", ')')];
+
+/// A definition with each run of whitespace collapsed to one space,
+/// except inside string literals, whose contents are what the program
+/// prints or pushes
+fn canonical_code(code: &str) -> String {
+    let mut words: Vec<&str> = Vec::new();
+    let mut rest = code.trim_start();
+    while !rest.is_empty() {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let word = &rest[..end];
+        let literal = if word.starts_with('"') {
+            Some((1, '"'))
+        } else {
+            STRING_WORDS
+                .iter()
+                .find(|(name, _)| word.eq_ignore_ascii_case(name))
+                // One space separates the word from the string
+                .map(|(_, close)| (end + rest[end..].chars().next().map_or(0, char::len_utf8), *close))
+        };
+        let end = match literal {
+            Some((start, close)) => literal_end(rest, start, close),
+            None => end,
+        };
+        words.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    words.join(" ")
+}
+
+/// Byte index just past the `close` ending a string that starts at `start`
+/// in `text`, skipping backslash escapes, or the end of `text`
+fn literal_end(text: &str, start: usize, close: char) -> usize {
+    let mut chars = text[start.min(text.len())..].char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' && close == '"' {
+            chars.next();
+        } else if c == close {
+            return start + i + c.len_utf8();
+        }
+    }
+    text.len()
+}
+
+/// Check one record's signature
+///
+/// The signing key must be one of the trusted public keys. With none
+/// trusted, an intact signature is only [`SignatureStatus::SelfSigned`]:
+/// whoever edits a record can sign it again.
+pub fn verify_record(record: &ProvenanceRecord, trusted: &[String]) -> SignatureStatus {
+    let Some(signature) = &record.metadata.signature else {
+        return if record.signature_line.is_some() {
+            SignatureStatus::Tampered { reason: "malformed signature line".to_string() }
+        } else {
+            SignatureStatus::Unsigned
+        };
+    };
+    if signature.algorithm != SIGNATURE_ALGORITHM {
+        return SignatureStatus::Tampered { reason: format!("unknown algorithm '{}'", signature.algorithm) };
+    }
+
+    let key = hex::decode(&signature.public_key)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok());
+    let sig = hex::decode(&signature.signature)
+        .ok()
+        .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+        .map(|bytes| Signature::from_bytes(&bytes));
+    let (Some(key), Some(sig)) = (key, sig) else {
+        return SignatureStatus::Tampered { reason: "malformed key or signature".to_string() };
+    };
+
+    let record_text = canonical_record(&record.word, &record.header, &record.code);
+    if key.verify(record_text.as_bytes(), &sig).is_err() {
+        return SignatureStatus::Tampered { reason: "signature does not match record".to_string() };
+    }
+
+    let public_key = signature.public_key.to_lowercase();
+    if trusted.is_empty() {
+        SignatureStatus::SelfSigned { public_key }
+    } else if trusted.iter().any(|t| t.trim().eq_ignore_ascii_case(&public_key)) {
+        SignatureStatus::Valid { public_key }
+    } else {
+        SignatureStatus::Untrusted { public_key }
+    }
+}
+
+/// Check the signature of every provenance record in `source`
+pub fn verify_source(source: &str, trusted: &[String]) -> Result<Vec<(String, SignatureStatus)>> {
+    Ok(extract_records(source)?
+        .iter()
+        .map(|record| (record.word.clone(), verify_record(record, trusted)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer() -> ProvenanceSigner {
+        ProvenanceSigner::from_hex(&"07".repeat(32)).unwrap()
+    }
+
+    fn status(source: &str) -> SignatureStatus {
+        verify_source(source, &[signer().public_key_hex()]).unwrap().remove(0).1
+    }
+
+    #[test]
+    fn test_signed_source_verifies_and_detects_tampering() {
+        let source = "\\ GENERATED_BY: agent-1\n\\ TIMESTAMP: 2025-01-01T00:00:00Z\n: square ( n -- n² )\n  dup * ;\n";
+        let signed = signer().sign_source(source).unwrap();
+        assert!(signed.contains("\\ SIGNATURE: ed25519:"));
+        assert!(status(&signed).is_valid());
+
+        // Re-signing replaces the old signature rather than adding one
+        let resigned = signer().sign_source(&signed).unwrap();
+        assert_eq!(resigned, signed);
+
+        // Reformatting is fine, changing code or metadata is not
+        assert!(status(&signed.replace("  dup * ;", "dup   *\n;")).is_valid());
+        assert!(matches!(status(&signed.replace("dup *", "dup +")), SignatureStatus::Tampered { .. }));
+        assert!(matches!(status(&signed.replace("agent-1", "agent-2")), SignatureStatus::Tampered { .. }));
+        assert_eq!(status(source), SignatureStatus::Unsigned);
+    }
+
+    #[test]
+    fn test_resigned_records_are_not_valid_without_a_trusted_key() {
+        let source = "\\ GENERATED_BY: agent-1\n: square ( n -- n² ) dup * ;\n";
+        let signed = signer().sign_source(source).unwrap();
+        assert!(matches!(verify_source(&signed, &[]).unwrap()[0].1, SignatureStatus::SelfSigned { .. }));
+
+        // An edited record signed again with another key
+        let forged = ProvenanceSigner::generate().unwrap().sign_source(&signed.replace("dup *", "dup +")).unwrap();
+        assert!(matches!(status(&forged), SignatureStatus::Untrusted { .. }));
+        assert!(!verify_source(&forged, &[]).unwrap()[0].1.is_valid());
+    }
+
+    #[test]
+    fn test_string_literals_are_signed_as_written() {
+        let source = "\\ GENERATED_BY: agent-1\n: greet ( -- ) .\" a  b\" s\" c  d\" type .( e  f) ;\n";
+        let signed = signer().sign_source(source).unwrap();
+        assert!(status(&signed).is_valid());
+        assert!(status(&signed.replace(" ;", "\n   ;")).is_valid());
+        for (from, to) in [("a  b", "a b"), ("c  d", "c d"), ("e  f", "e f")] {
+            assert!(matches!(status(&signed.replace(from, to)), SignatureStatus::Tampered { .. }), "{}", from);
+        }
+    }
+
+    #[test]
+    fn test_embedded_signature_and_trusted_keys() {
+        let metadata = ProvenanceMetadata::new("agent-1".to_string()).with_pattern("DUP_TRANSFORM_001".to_string());
+        let code = signer().embed("square", ": square dup * ;", &metadata);
+        assert!(status(&code).is_valid());
+
+        let trusted = vec![signer().public_key_hex()];
+        assert!(verify_source(&code, &trusted).unwrap()[0].1.is_valid());
+        let other = ProvenanceSigner::generate().unwrap().public_key_hex();
+        assert!(matches!(verify_source(&code, &[other]).unwrap()[0].1, SignatureStatus::Untrusted { .. }));
+    }
+}
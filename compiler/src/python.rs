@@ -0,0 +1,193 @@
+//! Python Bindings
+//!
+//! The `fastforth` Python module, built from this crate with the `python`
+//! feature (see `pyproject.toml`), so agent frameworks written in Python can
+//! drive the compiler without shelling out to the CLI:
+//!
+//! ```python
+//! import fastforth
+//!
+//! fastforth.infer("dup * swap +")["inferred_effect"]
+//! fastforth.run(": sq dup * ; 7 sq")      # [49]
+//!
+//! engine = fastforth.Engine()
+//! engine.define(": gcd ( a b -- n ) dup if swap over mod gcd else drop then ;")
+//! engine.call("gcd", 48, 18)              # 6
+//! ```
+//!
+//! Results are plain Python values: inference results and reports are
+//! dicts with the fields of their JSON form, and stacks are lists of ints.
+//! Invalid input raises `ValueError`.
+
+// The #[pyfunction] expansion of pyo3 0.22 converts results into themselves
+#![allow(clippy::useless_conversion)]
+
+use crate::inference::InferenceAPI;
+use crate::{CompilationMode, Compiler, ForthEngine, OptimizationLevel, SpecCodeGenerator, SpecDocument, SpecValidator};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyList, PyString, PyTuple, PyType};
+use serde::Serialize;
+
+fn value_error(error: impl ToString) -> PyErr {
+    PyValueError::new_err(error.to_string())
+}
+
+/// A serializable result as the Python value of its JSON form
+fn to_python<T: Serialize>(py: Python<'_>, value: &T) -> PyResult<PyObject> {
+    let json = serde_json::to_value(value).map_err(value_error)?;
+    json_to_python(py, &json)
+}
+
+fn json_to_python(py: Python<'_>, value: &serde_json::Value) -> PyResult<PyObject> {
+    use serde_json::Value;
+    Ok(match value {
+        Value::Null => py.None(),
+        Value::Bool(flag) => flag.into_py(py),
+        Value::Number(number) => match number.as_i64() {
+            Some(int) => int.into_py(py),
+            None => number.as_f64().unwrap_or(f64::NAN).into_py(py),
+        },
+        Value::String(text) => text.into_py(py),
+        Value::Array(items) => {
+            let items = items.iter().map(|item| json_to_python(py, item)).collect::<PyResult<Vec<_>>>()?;
+            PyList::new_bound(py, items).into_py(py)
+        }
+        Value::Object(fields) => {
+            let dict = PyDict::new_bound(py);
+            for (key, field) in fields {
+                dict.set_item(key, json_to_python(py, field)?)?;
+            }
+            dict.into_py(py)
+        }
+    })
+}
+
+/// Infer the stack effect of a code fragment
+#[pyfunction]
+fn infer(py: Python<'_>, code: &str) -> PyResult<PyObject> {
+    let result = InferenceAPI::new().infer(code).map_err(value_error)?;
+    to_python(py, &result)
+}
+
+/// Check a code fragment against an expected stack effect
+#[pyfunction]
+fn verify_effect(py: Python<'_>, code: &str, expected: &str) -> PyResult<PyObject> {
+    let result = InferenceAPI::new().verify_effect(code, expected).map_err(value_error)?;
+    to_python(py, &result)
+}
+
+/// Compose the stack effects of a sequence of words
+#[pyfunction]
+fn compose(py: Python<'_>, words: Vec<String>) -> PyResult<PyObject> {
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    let result = InferenceAPI::new().compose(&words).map_err(value_error)?;
+    to_python(py, &result)
+}
+
+/// Validate a word or program specification given as JSON
+#[pyfunction]
+#[pyo3(signature = (spec, strict = false))]
+fn validate_spec(spec: &str, strict: bool) -> PyResult<()> {
+    let validator = if strict { SpecValidator::strict() } else { SpecValidator::new() };
+    match SpecDocument::from_json(spec).map_err(value_error)? {
+        SpecDocument::Word(specification) => validator.validate(&specification),
+        SpecDocument::Program(program) => program.validate_with(&validator),
+    }
+    .map_err(value_error)
+}
+
+/// Generate Forth source from a word or program specification given as JSON
+#[pyfunction]
+#[pyo3(signature = (spec, tests = true, provenance = true))]
+fn generate(spec: &str, tests: bool, provenance: bool) -> PyResult<String> {
+    let generator = SpecCodeGenerator::new().with_tests(tests).with_provenance(provenance);
+    match SpecDocument::from_json(spec).map_err(value_error)? {
+        SpecDocument::Word(specification) => generator.generate(&specification),
+        SpecDocument::Program(program) => generator.generate_program(&program),
+    }
+    .map_err(value_error)
+}
+
+/// JIT-compile and run a snippet, returning the data stack it leaves,
+/// bottom first
+#[pyfunction]
+#[pyo3(signature = (source, opt_level = 2))]
+fn run(source: &str, opt_level: u8) -> PyResult<Vec<i64>> {
+    let level = match opt_level {
+        0 => OptimizationLevel::None,
+        1 => OptimizationLevel::Basic,
+        2 => OptimizationLevel::Standard,
+        _ => OptimizationLevel::Aggressive,
+    };
+    let result = Compiler::new(level).compile_string(source, CompilationMode::JIT).map_err(value_error)?;
+    Ok(result.stack)
+}
+
+/// A [`ForthEngine`]: definitions compiled once and called from Python
+#[pyclass(name = "Engine", unsendable)]
+struct Engine {
+    engine: ForthEngine,
+}
+
+#[pymethods]
+impl Engine {
+    #[new]
+    fn new() -> Self {
+        Self { engine: ForthEngine::new() }
+    }
+
+    /// Compile definitions; they stay defined for later sources
+    fn define(&mut self, source: &str) -> PyResult<()> {
+        self.engine.define(source).map_err(value_error)
+    }
+
+    /// Call a word with ints, bools, floats or strs as its arguments
+    ///
+    /// The result is read as `returns`: `int` (the default), `float` or
+    /// `bool`. A word that leaves nothing returns `None`.
+    #[pyo3(signature = (name, *args, returns = None))]
+    fn call(&self, py: Python<'_>, name: &str, args: &Bound<'_, PyTuple>, returns: Option<&Bound<'_, PyType>>) -> PyResult<PyObject> {
+        // Strings are copied and passed as c-addr u; the copies outlive the call
+        let mut strings: Vec<String> = Vec::new();
+        let mut cells = Vec::with_capacity(args.len());
+        for arg in args.iter() {
+            if let Ok(text) = arg.downcast::<PyString>() {
+                strings.push(text.to_str()?.to_string());
+                let text = strings.last().expect("just pushed");
+                cells.push(text.as_ptr() as i64);
+                cells.push(text.len() as i64);
+            } else if arg.is_instance_of::<PyBool>() {
+                cells.push(if arg.extract::<bool>()? { -1 } else { 0 });
+            } else if arg.is_instance_of::<PyFloat>() {
+                cells.push(arg.extract::<f64>()?.to_bits() as i64);
+            } else {
+                cells.push(arg.extract::<i64>()?);
+            }
+        }
+
+        let effect = self.engine.stack_effect(name).map_err(value_error)?;
+        let outputs = self.engine.call_cells(name, &cells, effect.outputs.len()).map_err(value_error)?;
+        let Some(&top) = outputs.first() else {
+            return Ok(py.None());
+        };
+        Ok(match returns {
+            Some(ty) if ty.is(&py.get_type_bound::<PyFloat>()) => f64::from_bits(top as u64).into_py(py),
+            Some(ty) if ty.is(&py.get_type_bound::<PyBool>()) => (top != 0).into_py(py),
+            _ => top.into_py(py),
+        })
+    }
+}
+
+/// The `fastforth` module
+#[pymodule]
+fn fastforth(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(infer, module)?)?;
+    module.add_function(wrap_pyfunction!(verify_effect, module)?)?;
+    module.add_function(wrap_pyfunction!(compose, module)?)?;
+    module.add_function(wrap_pyfunction!(validate_spec, module)?)?;
+    module.add_function(wrap_pyfunction!(generate, module)?)?;
+    module.add_function(wrap_pyfunction!(run, module)?)?;
+    module.add_class::<Engine>()?;
+    Ok(())
+}
@@ -0,0 +1,145 @@
+//! Recording
+//!
+//! A flight recorder for post-mortems of JIT runs. Code compiled with
+//! recording ([`Compiler::set_recording`](crate::Compiler::set_recording))
+//! logs every word entry with the cells it takes and every exit with the
+//! cells it leaves, and a run keeps the last events in a ring buffer. When
+//! the run fails, whether cancelled, out of fuel or trapped (see
+//! [`crate::snapshot`]), [`last_events`] tells how it got there. If the
+//! process dies of a fault outside a guarded run instead, such as in a
+//! task, the runtime writes the events to stderr first.
+//!
+//! Events name their word and the source line of its definition. Each
+//! keeps the top four cells of its stack and the number of cells in all.
+//! The ring is shared by the process: tasks a run spawns record into it
+//! too, and a new recorded run starts it afresh.
+
+use crate::error::{CompileError, Result};
+use crate::runtime_ffi::{forth_record_events, forth_record_label, forth_record_start, forth_record_stop, CellT, ForthRecord};
+use fastforth_frontend::ast::SourceLocation;
+use std::ffi::CString;
+use std::fmt;
+use std::sync::Mutex;
+
+/// Recorded words, indexed by the id their code passes the runtime
+static WORDS: Mutex<Vec<(String, SourceLocation)>> = Mutex::new(Vec::new());
+
+/// A word entry or exit in a recorded run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// The word entered or exited
+    pub word: String,
+    /// Where the word is defined
+    pub location: SourceLocation,
+    /// Whether the word returned, rather than was entered
+    pub exit: bool,
+    /// Words entered and not yet exited around it, on its thread
+    pub depth: usize,
+    /// Cells the word took (entry) or left (exit)
+    pub count: usize,
+    /// The top of those cells, at most four, deepest first
+    pub cells: Vec<i64>,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{} {} (", "  ".repeat(self.depth.min(32)), if self.exit { "<-" } else { "->" }, self.word)?;
+        if self.count > self.cells.len() {
+            write!(f, " ...")?;
+        }
+        for cell in &self.cells {
+            write!(f, " {}", cell)?;
+        }
+        write!(f, " )")?;
+        if self.location.is_known() {
+            write!(f, "  line {}", self.location.line)?;
+        }
+        Ok(())
+    }
+}
+
+/// The id recorded code passes the runtime for `word`
+pub(crate) fn word_id(word: &str, location: SourceLocation) -> i64 {
+    let mut words = WORDS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(id) = words.iter().position(|(name, at)| name == word && *at == location) {
+        return id as i64;
+    }
+    let line = location.line;
+    words.push((word.to_string(), location));
+    let id = words.len() - 1;
+
+    // What a crash dump shows of the word
+    if let Ok(name) = CString::new(word) {
+        unsafe { forth_record_label(id as CellT, name.as_ptr(), line as CellT) };
+    }
+    id as i64
+}
+
+/// Keeps the runtime recording until dropped
+pub(crate) struct RecordingSession;
+
+impl RecordingSession {
+    /// Start recording the last `capacity` events, forgetting earlier ones
+    pub(crate) fn begin(capacity: usize) -> Result<Self> {
+        let capacity = capacity.clamp(1, CellT::MAX as usize);
+        if unsafe { forth_record_start(capacity as CellT) } != 0 {
+            return Err(CompileError::RuntimeError(format!("cannot keep {} recorded events", capacity)));
+        }
+        Ok(Self)
+    }
+}
+
+impl Drop for RecordingSession {
+    fn drop(&mut self) {
+        unsafe { forth_record_stop() };
+    }
+}
+
+/// Events of the last recorded run, oldest first
+pub fn last_events() -> Vec<Event> {
+    let available = unsafe { forth_record_events(std::ptr::null_mut(), 0) };
+    let mut records = vec![ForthRecord::default(); available.max(0) as usize];
+    let count = unsafe { forth_record_events(records.as_mut_ptr(), records.len() as CellT) };
+    records.truncate(count.max(0) as usize);
+
+    let words = WORDS.lock().unwrap_or_else(|e| e.into_inner());
+    records
+        .into_iter()
+        .map(|record| {
+            let (word, location) = usize::try_from(record.word)
+                .ok()
+                .and_then(|id| words.get(id).cloned())
+                .unwrap_or_else(|| (format!("word {}", record.word), SourceLocation::default()));
+            let count = record.count.max(0) as usize;
+            Event {
+                word,
+                location,
+                exit: record.exit != 0,
+                depth: record.depth.max(0) as usize,
+                count,
+                cells: record.cells[..count.min(record.cells.len())].iter().map(|&cell| cell as i64).collect(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompilationMode, Compiler, OptimizationLevel, Sandbox};
+
+    #[test]
+    fn test_failed_runs_keep_their_last_word_entries_and_exits() {
+        let mut compiler = Compiler::new(OptimizationLevel::None);
+        compiler.set_recording(Some(3));
+        compiler.set_sandbox(Some(Sandbox { fuel: Some(50), ..Sandbox::default() }));
+        let source = ": sq ( n -- n ) dup * ;\n: spin ( n -- n )\n  begin 1 + dup 0 < until ;\n3 sq 4 sq + spin";
+        let result = compiler.compile_string(source, CompilationMode::JIT);
+        assert!(matches!(result, Err(CompileError::OutOfFuel { .. })));
+
+        let events = last_events();
+        let shown: Vec<String> = events.iter().map(ToString::to_string).collect();
+        assert_eq!(shown, ["-> sq ( 4 )  line 1", "<- sq ( 16 )  line 1", "-> spin ( 25 )  line 2"]);
+        assert_eq!(events[2].depth, 0);
+    }
+}
@@ -0,0 +1,344 @@
+//! Interactive REPL session state
+//!
+//! Each line entered at the REPL is compiled and run with the JIT. The
+//! session carries state between lines the way a Forth system would:
+//! - Colon definitions stay available to later lines (redefining replaces)
+//! - The data stack left by one line is the starting stack of the next
+//! - The optimized IR of every word is kept for `.see`
+//!
+//! At `-O0` lines run in the [`TieredEngine`] instead: a threaded
+//! interpreter that starts instantly and compiles hot words with Cranelift
+//! as they are called. Output from `.` and `emit` is collected and returned
+//! by [`ReplSession::take_output`].
+//!
+//! [`is_incomplete`] tells the line editor when to keep reading, so a
+//! definition can span several lines.
+
+use crate::backend::BackendType;
+use crate::error::Result;
+use crate::pipeline::{CompilationMode, CompilationPipeline, CompilationResult, CompilationStats};
+use crate::tiered::TieredEngine;
+use fastforth_frontend::ast::Token;
+use fastforth_frontend::lexer::Lexer;
+use fastforth_frontend::{parse_program, CodeWord, Definition, ForthError, Program, Word};
+use fastforth_optimizer::ir::WordDef;
+use fastforth_optimizer::{Instruction, OptimizationLevel};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Instant;
+
+/// State of an interactive session
+pub struct ReplSession {
+    pipeline: CompilationPipeline,
+    definitions: Vec<Definition>,
+    code_words: Vec<CodeWord>,
+    stack: Vec<i64>,
+    words: HashMap<String, WordDef>,
+    show_stack: bool,
+    engine: Option<TieredEngine>,
+}
+
+impl ReplSession {
+    /// Create a new session with an empty stack and no user definitions
+    ///
+    /// Without optimization the session runs in the tiered engine.
+    pub fn new(optimization_level: OptimizationLevel) -> Self {
+        let mut pipeline = CompilationPipeline::new(optimization_level);
+        pipeline.set_retain_ir(true);
+
+        Self {
+            pipeline,
+            definitions: Vec::new(),
+            code_words: Vec::new(),
+            stack: Vec::new(),
+            words: HashMap::new(),
+            show_stack: false,
+            engine: (optimization_level == OptimizationLevel::None).then(TieredEngine::default),
+        }
+    }
+
+    /// Compile and run one line of input against the session state
+    ///
+    /// The session is only updated when compilation and execution succeed,
+    /// so a line with an error leaves the stack and dictionary untouched.
+    pub fn eval(&mut self, source: &str) -> Result<CompilationResult> {
+        let line = parse_program(source)?;
+
+        let mut definitions: Vec<Definition> = self
+            .definitions
+            .iter()
+            .filter(|def| !line.definitions.iter().any(|new| new.name == def.name))
+            .cloned()
+            .collect();
+        definitions.extend(line.definitions.iter().cloned());
+
+        if self.engine.is_some() {
+            return self.eval_tiered(definitions, line);
+        }
+
+        let mut top_level_code: Vec<Word> = self.stack.iter().map(|&v| Word::IntLiteral(v)).collect();
+        let has_code = !line.top_level_code.is_empty();
+        top_level_code.extend(line.top_level_code);
+
+        let mut code_words: Vec<CodeWord> = self
+            .code_words
+            .iter()
+            .filter(|word| !line.code_words.iter().any(|new| new.name == word.name))
+            .cloned()
+            .collect();
+        code_words.extend(line.code_words);
+
+        let program = Program {
+            definitions,
+            top_level_code,
+            tests: Vec::new(),
+            code_words,
+            records: line.records,
+            names: Default::default(),
+        };
+
+        let mut result = self.pipeline.compile_program(&program, CompilationMode::JIT)?;
+
+        if let Some(ir) = result.ir.take() {
+            self.words = ir.words.into_iter().filter(|(name, _)| name != "main").collect();
+        }
+        self.definitions = program.definitions;
+        self.code_words = program.code_words;
+        if has_code {
+            self.stack = result.stack.clone();
+        }
+        result.stats.definitions_count = line.definitions.len();
+
+        Ok(result)
+    }
+
+    /// Run a line in the tiered engine, which keeps the stack itself
+    fn eval_tiered(&mut self, definitions: Vec<Definition>, line: Program) -> Result<CompilationResult> {
+        let engine = self.engine.as_mut().expect("session runs tiered");
+        let start = Instant::now();
+        let reported = engine.diagnostics().len();
+        let has_code = !line.top_level_code.is_empty();
+        let program = Program {
+            definitions,
+            top_level_code: line.top_level_code,
+            tests: Vec::new(),
+            code_words: line.code_words,
+            records: line.records,
+            names: Default::default(),
+        };
+
+        engine.load(&program)?;
+        if has_code {
+            engine.run()?;
+        }
+
+        self.words = engine.ir().words.clone();
+        self.definitions = program.definitions;
+        self.stack = engine.stack().to_vec();
+
+        Ok(CompilationResult {
+            mode: CompilationMode::JIT,
+            compile_time_ms: start.elapsed().as_millis() as u64,
+            code_size: None,
+            output_path: None,
+            jit_result: if has_code { self.stack.last().copied() } else { None },
+            stack: self.stack.clone(),
+            ir: None,
+            backend: BackendType::Cranelift,
+            warnings: engine.diagnostics()[reported..].to_vec(),
+            stats: CompilationStats { definitions_count: line.definitions.len(), ..Default::default() },
+            opt_report: None,
+        })
+    }
+
+    /// Take the output printed since the last call (tiered sessions only;
+    /// JIT-compiled lines print directly)
+    pub fn take_output(&mut self) -> String {
+        self.engine.as_mut().map(TieredEngine::take_output).unwrap_or_default()
+    }
+
+    /// The tier and call count of every word, when running tiered
+    pub fn format_tiers(&self) -> Option<String> {
+        let engine = self.engine.as_ref()?;
+        let mut out = String::new();
+        for (name, tier, calls) in engine.words() {
+            let _ = writeln!(out, "{:<16} {:<12} {} calls", name, tier, calls);
+        }
+        out.pop();
+        Some(out)
+    }
+
+    /// Current data stack, bottom first
+    pub fn stack(&self) -> &[i64] {
+        &self.stack
+    }
+
+    /// Render the stack the way `.s` does: `<depth> bottom ... top`
+    pub fn format_stack(&self) -> String {
+        let mut out = format!("<{}>", self.stack.len());
+        for value in &self.stack {
+            let _ = write!(out, " {}", value);
+        }
+        out
+    }
+
+    /// Whether the stack is printed after every line
+    pub fn show_stack(&self) -> bool {
+        self.show_stack
+    }
+
+    /// Toggle printing the stack after every line
+    pub fn set_show_stack(&mut self, show: bool) {
+        self.show_stack = show;
+    }
+
+    /// Names of the words defined in this session
+    pub fn word_names(&self) -> Vec<&str> {
+        self.definitions.iter().map(|def| def.name.as_str()).collect()
+    }
+
+    /// Decompile a user-defined word into its optimized IR listing
+    pub fn see(&self, name: &str) -> Option<String> {
+        let word = self.words.get(name)?;
+
+        let mut out = format!(": {}  {}\n", word.name, word.stack_effect);
+        for inst in &word.instructions {
+            match inst {
+                Instruction::Label(label) => {
+                    let _ = writeln!(out, "{}:", label);
+                }
+                other => {
+                    let _ = writeln!(out, "    {:?}", other);
+                }
+            }
+        }
+        out.push(';');
+        Some(out)
+    }
+}
+
+/// Check whether input stops in the middle of a construct
+///
+/// Input is incomplete when a `:` definition has no `;`, a control
+/// structure (IF, DO, BEGIN) or `T{` test case is not closed, or a string
+/// or parenthesized comment is unterminated. Surplus closers are not
+/// treated as incomplete; the parser reports them once the input is
+/// submitted.
+pub fn is_incomplete(source: &str) -> bool {
+    let tokens = match Lexer::new(source).tokenize() {
+        Ok(tokens) => tokens,
+        Err(ForthError::LexError { message, .. }) => {
+            return message.starts_with("Unterminated") || message.starts_with("Unclosed")
+                || message == "Unexpected end of string";
+        }
+        Err(_) => return false,
+    };
+
+    let mut in_definition = false;
+    let mut control_depth = 0i32;
+
+    for token in &tokens {
+        match token {
+            Token::Colon => in_definition = true,
+            Token::Semicolon => in_definition = false,
+            Token::If | Token::Do | Token::Begin | Token::TestStart => control_depth += 1,
+            Token::Then
+            | Token::Loop
+            | Token::PlusLoop
+            | Token::Until
+            | Token::Repeat
+            | Token::TestEnd => control_depth -= 1,
+            _ => {}
+        }
+    }
+
+    in_definition || control_depth > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stack_persists_between_lines() {
+        let mut session = ReplSession::new(OptimizationLevel::Basic);
+        session.eval("1 2").unwrap();
+        session.eval("3 +").unwrap();
+
+        assert_eq!(session.stack(), &[1, 5]);
+        assert_eq!(session.format_stack(), "<2> 1 5");
+    }
+
+    #[test]
+    fn test_definitions_persist_between_lines() {
+        let mut session = ReplSession::new(OptimizationLevel::Basic);
+        session.eval(": sq dup * ;").unwrap();
+        session.eval("7 sq").unwrap();
+
+        assert_eq!(session.stack(), &[49]);
+        assert_eq!(session.word_names(), vec!["sq"]);
+    }
+
+    #[test]
+    fn test_failed_line_leaves_state_untouched() {
+        let mut session = ReplSession::new(OptimizationLevel::Basic);
+        session.eval("1 2").unwrap();
+        assert!(session.eval("undefined-word").is_err());
+
+        assert_eq!(session.stack(), &[1, 2]);
+    }
+
+    #[test]
+    fn test_unoptimized_session_runs_tiered() {
+        let mut session = ReplSession::new(OptimizationLevel::None);
+        session.eval(": sq dup * ;").unwrap();
+        session.eval("3 sq dup .").unwrap();
+        for _ in 0..200 {
+            session.eval("sq drop 3").unwrap();
+        }
+        assert!(session.eval("undefined-word").is_err());
+
+        assert_eq!(session.stack(), &[3]);
+        assert_eq!(session.take_output(), "9 ");
+        assert!(session.see("sq").unwrap().contains("Mul"));
+        assert!(session.format_tiers().unwrap().contains("cranelift"));
+        assert!(ReplSession::new(OptimizationLevel::Basic).format_tiers().is_none());
+    }
+
+    #[test]
+    fn test_incomplete_input() {
+        assert!(is_incomplete(": sq"));
+        assert!(is_incomplete(": abs' dup 0 < if negate"));
+        assert!(is_incomplete("1 if 2"));
+        assert!(is_incomplete("begin 1"));
+        assert!(is_incomplete("\"unterminated"));
+        assert!(is_incomplete(": f ( n -- "));
+        assert!(is_incomplete("T{ 1 2 + ->"));
+
+        assert!(!is_incomplete(": sq dup * ;"));
+        assert!(!is_incomplete(": f dup 0 < if negate then ;"));
+        assert!(!is_incomplete("1 2 +"));
+        assert!(!is_incomplete("T{ 1 2 + -> 3 }T"));
+        assert!(!is_incomplete("then"));
+    }
+
+    #[test]
+    fn test_multiline_block_evaluates() {
+        let mut session = ReplSession::new(OptimizationLevel::Basic);
+        session.eval(": add3\n  + +\n;").unwrap();
+        session.eval("1 2 3 add3").unwrap();
+
+        assert_eq!(session.stack(), &[6]);
+    }
+
+    #[test]
+    fn test_see_shows_word_ir() {
+        let mut session = ReplSession::new(OptimizationLevel::Basic);
+        session.eval(": sq dup * ;").unwrap();
+
+        let listing = session.see("sq").unwrap();
+        assert!(listing.starts_with(": sq"));
+        assert!(listing.contains("Mul"));
+        assert!(session.see("nope").is_none());
+    }
+}
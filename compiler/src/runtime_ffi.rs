@@ -5,7 +5,10 @@
  * The C code is compiled by build.rs and linked as libforthruntime.a
  */
 
+use std::ffi::{CStr, CString};
+use std::path::Path;
 use std::os::raw::{c_char, c_int, c_void};
+use std::sync::{Mutex, Once};
 
 // Link to the static library compiled by build.rs
 #[link(name = "forthruntime", kind = "static")]
@@ -29,6 +32,47 @@ pub struct WordHeader {
     pub name_len: u8,
 }
 
+/// Two cells returned by value (`forth_pair_t`)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForthPair {
+    pub lo: CellT,
+    pub hi: CellT,
+}
+
+/// Where a fault in guarded code happened (`forth_trap_t`)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ForthTrap {
+    pub signal: CellT,
+    pub address: CellT,
+    pub pc: CellT,
+    pub depth: CellT,
+    pub returns: [CellT; 32],
+}
+
+/// A recorded word entry or exit (`forth_record_t`)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ForthRecord {
+    pub word: CellT,
+    pub exit: CellT,
+    pub depth: CellT,
+    pub count: CellT,
+    pub cells: [CellT; 4],
+}
+
+/// A filesystem for the file words (`forth_vfs_t`)
+#[repr(C)]
+pub struct ForthVfs {
+    pub context: *mut c_void,
+    pub open: extern "C" fn(*mut c_void, *const c_char, *const c_char, *mut CellT) -> CellT,
+    pub read: extern "C" fn(*mut c_void, CellT, *mut c_char, CellT) -> CellT,
+    pub write: extern "C" fn(*mut c_void, CellT, *const c_char, CellT) -> CellT,
+    pub close: extern "C" fn(*mut c_void, CellT) -> CellT,
+    pub remove: extern "C" fn(*mut c_void, *const c_char) -> CellT,
+}
+
 extern "C" {
     // VM lifecycle
     pub fn forth_create() -> *mut ForthVM;
@@ -90,11 +134,247 @@ extern "C" {
     pub fn forth_channel_recv(chan: CellT) -> CellT;
     pub fn forth_channel_close(chan: CellT);
     pub fn forth_channel_destroy(chan: CellT);
+    pub fn forth_task_spawn(entry: CellT, inputs: CellT, outputs: CellT, a0: CellT, a1: CellT, a2: CellT, a3: CellT) -> CellT;
+    pub fn forth_task_join(task: CellT) -> CellT;
+    pub fn forth_task_pause();
 
     // FFI support
     pub fn forth_ffi_call(vm: *mut ForthVM, func_ptr: *mut c_void, arg_count: c_int) -> c_int;
 
+    // Process environment
+    pub fn forth_runtime_init(argc: c_int, argv: *mut *mut c_char);
+    pub fn forth_arg_count() -> CellT;
+    pub fn forth_arg(n: CellT) -> CellT;
+    pub fn forth_getenv(name_addr: CellT, name_len: CellT) -> CellT;
+    pub fn forth_cstring_length(addr: CellT) -> CellT;
+
+    // Permissions
+    pub fn forth_permissions_restrict(allow_run: CellT);
+    pub fn forth_permissions_allow(dir: *const c_char, write: CellT) -> CellT;
+    pub fn forth_permissions_reset();
+
+    // I/O for compiled code
+    pub fn forth_io_emit(c: CellT);
+    pub fn forth_io_type(addr: CellT, len: CellT);
+    pub fn forth_io_cr();
+    pub fn forth_io_space();
+    pub fn forth_io_dot(n: CellT);
+    pub fn forth_io_udot(u: CellT);
+    pub fn forth_io_dot_r(n: CellT, width: CellT);
+    pub fn forth_io_key() -> CellT;
+    pub fn forth_io_key_ready() -> CellT;
+    pub fn forth_io_accept(addr: CellT, max: CellT) -> CellT;
+
+    // Pictured numeric output
+    pub fn forth_pict_begin();
+    pub fn forth_pict_digit(lo: CellT, hi: CellT) -> ForthPair;
+    pub fn forth_pict_digits(lo: CellT, hi: CellT) -> ForthPair;
+    pub fn forth_pict_hold(c: CellT);
+    pub fn forth_pict_sign(n: CellT);
+    pub fn forth_pict_end(lo: CellT, hi: CellT) -> ForthPair;
+
+    // Blocks
+    pub fn forth_block_open(path_addr: CellT, path_len: CellT);
+    pub fn forth_block(u: CellT) -> CellT;
+    pub fn forth_buffer(u: CellT) -> CellT;
+    pub fn forth_update();
+    pub fn forth_save_buffers();
+    pub fn forth_empty_buffers();
+    pub fn forth_flush();
+
+    // Files and commands
+    pub fn forth_file_open(path_addr: CellT, path_len: CellT, mode: CellT) -> ForthPair;
+    pub fn forth_file_create(path_addr: CellT, path_len: CellT, mode: CellT) -> ForthPair;
+    pub fn forth_file_delete(path_addr: CellT, path_len: CellT) -> CellT;
+    pub fn forth_file_read(addr: CellT, u: CellT, fileid: CellT) -> ForthPair;
+    pub fn forth_file_write(addr: CellT, u: CellT, fileid: CellT) -> CellT;
+    pub fn forth_file_close(fileid: CellT) -> CellT;
+    pub fn forth_vfs_install(vfs: *const ForthVfs);
+    pub fn forth_system(command_addr: CellT, command_len: CellT) -> CellT;
+
+    // Heap
+    pub fn forth_allocate(u: CellT) -> ForthPair;
+    pub fn forth_free(addr: CellT) -> CellT;
+    pub fn forth_resize(addr: CellT, u: CellT) -> ForthPair;
+    pub fn forth_heap_configure(region_size: CellT) -> CellT;
+    pub fn forth_heap_set_debug(on: CellT);
+    pub fn forth_heap_leaks(addrs: *mut CellT, sizes: *mut CellT, serials: *mut CellT, max: CellT) -> CellT;
+    pub fn forth_heap_reset();
+
+    // Cancellation
+    pub fn forth_guarded_call(cancel: *const i64, fuel: CellT, entry: *const u8, argc: CellT, args: *const i64, result: *mut i64) -> CellT;
+    pub fn forth_poll();
+    pub fn forth_throw(code: CellT);
+    pub fn forth_guard_suspend() -> *mut c_void;
+    pub fn forth_guard_resume(guard: *mut c_void);
+    pub fn forth_last_trap(trap: *mut ForthTrap);
+
+    // Recording
+    pub fn forth_record_label(word: CellT, name: *const c_char, line: CellT);
+    pub fn forth_record_start(capacity: CellT) -> CellT;
+    pub fn forth_record_stop();
+    pub fn forth_record_enter(word: CellT, count: CellT, a: CellT, b: CellT, c: CellT, d: CellT);
+    pub fn forth_record_exit(word: CellT, count: CellT, a: CellT, b: CellT, c: CellT, d: CellT);
+    pub fn forth_record_events(events: *mut ForthRecord, max: CellT) -> CellT;
+
     // Debugging
     pub fn forth_dump_stack(vm: *mut ForthVM);
     pub fn forth_dump_dictionary(vm: *mut ForthVM);
 }
+
+extern "C" {
+    // Dynamic loading, for CODE words assembled into shared libraries
+    fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlerror() -> *mut c_char;
+}
+
+/// `dlopen` flag resolving every symbol at load time
+const RTLD_NOW: c_int = 2;
+
+/// Arguments handed to the runtime, kept alive for as long as it may read them
+struct ProgramArgs {
+    _strings: Vec<CString>,
+    _argv: Vec<*mut c_char>,
+}
+
+// The pointers only refer to the strings owned alongside them
+unsafe impl Send for ProgramArgs {}
+
+static PROGRAM_ARGS: Mutex<Option<ProgramArgs>> = Mutex::new(None);
+
+/// Held by code that opens the block file, which the runtime keeps one
+/// of per process
+pub static BLOCK_FILE: Mutex<()> = Mutex::new(());
+
+/// Held by code that changes how the file words reach files (the
+/// permissions or the filesystem), which are per process, while it runs
+pub static FILE_ACCESS: Mutex<()> = Mutex::new(());
+
+/// Set the arguments `arg-count` and `arg@` see in JIT-compiled code,
+/// program name first (what a generated AOT `main` does with argv)
+pub fn set_program_args(args: &[String]) {
+    let strings: Vec<CString> = args
+        .iter()
+        .map(|arg| CString::new(arg.replace('\0', "")).expect("NUL bytes were removed"))
+        .collect();
+    let mut argv: Vec<*mut c_char> = strings.iter().map(|arg| arg.as_ptr() as *mut c_char).collect();
+    argv.push(std::ptr::null_mut());
+
+    let mut slot = PROGRAM_ARGS.lock().unwrap_or_else(|e| e.into_inner());
+    unsafe { forth_runtime_init(strings.len() as c_int, argv.as_mut_ptr()) };
+    *slot = Some(ProgramArgs { _strings: strings, _argv: argv });
+}
+
+/// Point the JIT at the runtime functions compiled code calls, which the
+/// statically linked runtime does not export to the dynamic linker
+pub fn register_jit_symbols() {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        use backend::cranelift::register_runtime_symbol;
+
+        register_runtime_symbol("forth_arg_count", forth_arg_count as *const u8);
+        register_runtime_symbol("forth_arg", forth_arg as *const u8);
+        register_runtime_symbol("forth_getenv", forth_getenv as *const u8);
+        register_runtime_symbol("forth_cstring_length", forth_cstring_length as *const u8);
+
+        register_runtime_symbol("forth_io_emit", forth_io_emit as *const u8);
+        register_runtime_symbol("forth_io_type", forth_io_type as *const u8);
+        register_runtime_symbol("forth_io_cr", forth_io_cr as *const u8);
+        register_runtime_symbol("forth_io_space", forth_io_space as *const u8);
+        register_runtime_symbol("forth_io_dot", forth_io_dot as *const u8);
+        register_runtime_symbol("forth_io_udot", forth_io_udot as *const u8);
+        register_runtime_symbol("forth_io_dot_r", forth_io_dot_r as *const u8);
+        register_runtime_symbol("forth_io_key", forth_io_key as *const u8);
+        register_runtime_symbol("forth_io_key_ready", forth_io_key_ready as *const u8);
+        register_runtime_symbol("forth_io_accept", forth_io_accept as *const u8);
+        register_runtime_symbol("forth_pict_begin", forth_pict_begin as *const u8);
+        register_runtime_symbol("forth_pict_digit", forth_pict_digit as *const u8);
+        register_runtime_symbol("forth_pict_digits", forth_pict_digits as *const u8);
+        register_runtime_symbol("forth_pict_hold", forth_pict_hold as *const u8);
+        register_runtime_symbol("forth_pict_sign", forth_pict_sign as *const u8);
+        register_runtime_symbol("forth_pict_end", forth_pict_end as *const u8);
+
+        register_runtime_symbol("forth_block_open", forth_block_open as *const u8);
+        register_runtime_symbol("forth_block", forth_block as *const u8);
+        register_runtime_symbol("forth_buffer", forth_buffer as *const u8);
+        register_runtime_symbol("forth_update", forth_update as *const u8);
+        register_runtime_symbol("forth_save_buffers", forth_save_buffers as *const u8);
+        register_runtime_symbol("forth_empty_buffers", forth_empty_buffers as *const u8);
+        register_runtime_symbol("forth_flush", forth_flush as *const u8);
+        register_runtime_symbol("forth_file_open", forth_file_open as *const u8);
+        register_runtime_symbol("forth_file_create", forth_file_create as *const u8);
+        register_runtime_symbol("forth_file_delete", forth_file_delete as *const u8);
+        register_runtime_symbol("forth_file_read", forth_file_read as *const u8);
+        register_runtime_symbol("forth_file_write", forth_file_write as *const u8);
+        register_runtime_symbol("forth_file_close", forth_file_close as *const u8);
+        register_runtime_symbol("forth_system", forth_system as *const u8);
+
+        register_runtime_symbol("forth_allocate", forth_allocate as *const u8);
+        register_runtime_symbol("forth_free", forth_free as *const u8);
+        register_runtime_symbol("forth_resize", forth_resize as *const u8);
+
+        register_runtime_symbol("forth_task_spawn", forth_task_spawn as *const u8);
+        register_runtime_symbol("forth_task_join", forth_task_join as *const u8);
+        register_runtime_symbol("forth_task_pause", forth_task_pause as *const u8);
+        register_runtime_symbol("forth_poll", forth_poll as *const u8);
+        register_runtime_symbol("forth_throw", forth_throw as *const u8);
+        register_runtime_symbol("forth_record_enter", forth_record_enter as *const u8);
+        register_runtime_symbol("forth_record_exit", forth_record_exit as *const u8);
+        register_runtime_symbol("forth_channel_create", forth_channel_create as *const u8);
+        register_runtime_symbol("forth_channel_send", forth_channel_send as *const u8);
+        register_runtime_symbol("forth_channel_recv", forth_channel_recv as *const u8);
+        register_runtime_symbol("forth_channel_close", forth_channel_close as *const u8);
+        register_runtime_symbol("forth_channel_destroy", forth_channel_destroy as *const u8);
+    });
+}
+
+/// Load a shared library and look up `symbols` in it, in order
+///
+/// The library stays loaded for the rest of the process, since code
+/// compiled against the addresses may run at any time.
+pub fn load_shared_library(path: &Path, symbols: &[String]) -> Result<Vec<*const u8>, String> {
+    fn last_error() -> String {
+        let error = unsafe { dlerror() };
+        if error.is_null() {
+            "unknown error".to_string()
+        } else {
+            unsafe { CStr::from_ptr(error) }.to_string_lossy().into_owned()
+        }
+    }
+
+    let path = CString::new(path.as_os_str().as_encoded_bytes()).map_err(|e| e.to_string())?;
+    let handle = unsafe { dlopen(path.as_ptr(), RTLD_NOW) };
+    if handle.is_null() {
+        return Err(last_error());
+    }
+    symbols
+        .iter()
+        .map(|symbol| {
+            let name = CString::new(symbol.as_str()).map_err(|e| e.to_string())?;
+            let address = unsafe { dlsym(handle, name.as_ptr()) };
+            if address.is_null() {
+                Err(format!("{}: {}", symbol, last_error()))
+            } else {
+                Ok(address as *const u8)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_getenv_takes_counted_names() {
+        let name = "PATH and more";
+        let expected = std::env::var("PATH").unwrap();
+        unsafe {
+            let value = forth_getenv(name.as_ptr() as CellT, 4);
+            assert_eq!(forth_cstring_length(value), expected.len() as CellT);
+            assert_eq!(forth_getenv(name.as_ptr() as CellT, 0), 0);
+            assert_eq!(forth_cstring_length(0), 0);
+        }
+    }
+}
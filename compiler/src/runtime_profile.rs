@@ -0,0 +1,266 @@
+//! Runtime Profiles
+//!
+//! The C runtime is split into components: the core (VM, console I/O,
+//! pictured numerics), files and blocks, the process environment, tasks
+//! and channels, and floating point, which is the C math library. A program links only the
+//! components its compiled code calls, found by scanning its SSA after
+//! optimization, so a small deployed program carries no file or shell
+//! access it never uses.
+//!
+//! A profile caps the components a program may use. A program that needs
+//! more than its profile allows is rejected, naming the word that needs
+//! the missing component.
+
+use crate::error::{CompileError, Result};
+use fastforth_frontend::ssa::{SSAFunction, SSAInstruction};
+use std::collections::BTreeMap;
+
+/// A separately linked part of the runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RuntimeComponent {
+    /// VM, stack and memory words, console I/O; always linked
+    Core,
+    /// Files and blocks
+    Files,
+    /// Command line arguments, environment variables and shell commands
+    System,
+    /// Tasks and channels, on POSIX threads
+    Threads,
+    /// Floating point, from the C math library
+    Float,
+}
+
+impl RuntimeComponent {
+    pub const ALL: [RuntimeComponent; 5] = [
+        RuntimeComponent::Core,
+        RuntimeComponent::Files,
+        RuntimeComponent::System,
+        RuntimeComponent::Threads,
+        RuntimeComponent::Float,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RuntimeComponent::Core => "core",
+            RuntimeComponent::Files => "files",
+            RuntimeComponent::System => "system",
+            RuntimeComponent::Threads => "threads",
+            RuntimeComponent::Float => "float",
+        }
+    }
+
+    /// C source of the component and its file name, if it has any
+    pub fn source(self) -> Option<(&'static str, &'static str)> {
+        match self {
+            RuntimeComponent::Core => Some(("forth_runtime.c", include_str!("../runtime/forth_runtime.c"))),
+            RuntimeComponent::Files => Some(("forth_files.c", include_str!("../runtime/forth_files.c"))),
+            RuntimeComponent::System => Some(("forth_system.c", include_str!("../runtime/forth_system.c"))),
+            RuntimeComponent::Threads => Some(("concurrency.c", include_str!("../runtime/concurrency.c"))),
+            RuntimeComponent::Float => None,
+        }
+    }
+
+    /// The component precompiled by the build script, with the archive's
+    /// file name, if it has any
+    pub fn archive(self) -> Option<(String, &'static [u8])> {
+        let bytes: &'static [u8] = match self {
+            RuntimeComponent::Core => include_bytes!(env!("FORTH_RUNTIME_CORE_ARCHIVE")),
+            RuntimeComponent::Files => include_bytes!(env!("FORTH_RUNTIME_FILES_ARCHIVE")),
+            RuntimeComponent::System => include_bytes!(env!("FORTH_RUNTIME_SYSTEM_ARCHIVE")),
+            RuntimeComponent::Threads => include_bytes!(env!("FORTH_RUNTIME_THREADS_ARCHIVE")),
+            RuntimeComponent::Float => return None,
+        };
+        let name = if cfg!(target_env = "msvc") {
+            format!("forthruntime_{}.lib", self.name())
+        } else {
+            format!("libforthruntime_{}.a", self.name())
+        };
+        Some((name, bytes))
+    }
+
+    /// System libraries the component needs besides libc
+    pub fn libs(self) -> &'static [&'static str] {
+        match self {
+            RuntimeComponent::Threads => &["pthread"],
+            RuntimeComponent::Float => &["m"],
+            _ => &[],
+        }
+    }
+}
+
+impl std::fmt::Display for RuntimeComponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Which runtime components a program may link
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RuntimeProfile {
+    /// The core alone
+    Minimal,
+    /// The core, files and blocks, the process environment, and tasks
+    Standard,
+    /// Every component, floating point included
+    #[default]
+    Full,
+}
+
+impl RuntimeProfile {
+    pub fn allows(self, component: RuntimeComponent) -> bool {
+        match self {
+            RuntimeProfile::Minimal => component == RuntimeComponent::Core,
+            RuntimeProfile::Standard => component != RuntimeComponent::Float,
+            RuntimeProfile::Full => true,
+        }
+    }
+
+    /// The smallest profile allowing `component`
+    fn smallest_allowing(component: RuntimeComponent) -> Self {
+        [RuntimeProfile::Minimal, RuntimeProfile::Standard]
+            .into_iter()
+            .find(|profile| profile.allows(component))
+            .unwrap_or(RuntimeProfile::Full)
+    }
+}
+
+impl std::fmt::Display for RuntimeProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeProfile::Minimal => write!(f, "minimal"),
+            RuntimeProfile::Standard => write!(f, "standard"),
+            RuntimeProfile::Full => write!(f, "full"),
+        }
+    }
+}
+
+impl std::str::FromStr for RuntimeProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "minimal" => Ok(RuntimeProfile::Minimal),
+            "standard" => Ok(RuntimeProfile::Standard),
+            "full" => Ok(RuntimeProfile::Full),
+            _ => Err(format!("unknown runtime profile '{}' (expected minimal, standard or full)", s)),
+        }
+    }
+}
+
+/// The runtime components a program's compiled code calls
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuntimeRequirements {
+    /// Each component needed beyond the core, with the first word that
+    /// needs it
+    pub needed_by: BTreeMap<RuntimeComponent, String>,
+}
+
+impl RuntimeRequirements {
+    /// Scan optimized SSA for the instructions each component implements
+    pub fn scan(functions: &[SSAFunction]) -> Self {
+        let mut needed_by = BTreeMap::new();
+        for func in functions {
+            for inst in func.blocks.iter().flat_map(|block| &block.instructions) {
+                if let Some(component) = component_of(inst) {
+                    needed_by.entry(component).or_insert_with(|| func.name.clone());
+                }
+            }
+        }
+        Self { needed_by }
+    }
+
+    /// Components to link, the core first
+    pub fn components(&self) -> Vec<RuntimeComponent> {
+        RuntimeComponent::ALL
+            .into_iter()
+            .filter(|&component| component == RuntimeComponent::Core || self.needed_by.contains_key(&component))
+            .collect()
+    }
+
+    /// Fail on the first component `profile` leaves out
+    pub fn check(&self, profile: RuntimeProfile) -> Result<()> {
+        match self.needed_by.iter().find(|(&component, _)| !profile.allows(component)) {
+            Some((&component, word)) => Err(CompileError::CodeGenError(format!(
+                "'{}' needs the {} runtime, which the {} runtime profile leaves out (use --runtime-profile={})",
+                word,
+                component,
+                profile,
+                RuntimeProfile::smallest_allowing(component)
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The component beyond the core an instruction calls into
+fn component_of(inst: &SSAInstruction) -> Option<RuntimeComponent> {
+    match inst {
+        SSAInstruction::FileOpen { .. }
+        | SSAInstruction::FileRead { .. }
+        | SSAInstruction::FileWrite { .. }
+        | SSAInstruction::FileClose { .. }
+        | SSAInstruction::FileDelete { .. }
+        | SSAInstruction::FileCreate { .. }
+        | SSAInstruction::BlockFetch { .. }
+        | SSAInstruction::BlockAssign { .. }
+        | SSAInstruction::BlockUpdate
+        | SSAInstruction::BlockSave
+        | SSAInstruction::BlockEmpty
+        | SSAInstruction::BlockFlush => Some(RuntimeComponent::Files),
+        SSAInstruction::FFICall { function, .. } if function.starts_with("forth_block") => {
+            Some(RuntimeComponent::Files)
+        }
+        SSAInstruction::SystemCall { .. }
+        | SSAInstruction::ArgCount { .. }
+        | SSAInstruction::ArgFetch { .. }
+        | SSAInstruction::GetEnv { .. } => Some(RuntimeComponent::System),
+        SSAInstruction::TaskSpawn { .. } => Some(RuntimeComponent::Threads),
+        SSAInstruction::FFICall { function, .. }
+            if function.starts_with("forth_task") || function.starts_with("forth_channel") =>
+        {
+            Some(RuntimeComponent::Threads)
+        }
+        SSAInstruction::LoadFloat { .. } => Some(RuntimeComponent::Float),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastforth_frontend::{convert_to_ssa, parse_program};
+
+    fn requirements(source: &str) -> RuntimeRequirements {
+        RuntimeRequirements::scan(&convert_to_ssa(&parse_program(source).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn test_components_follow_the_instructions_used() {
+        let core = requirements(": sq dup * ; 7 sq .");
+        assert_eq!(core.components(), [RuntimeComponent::Core]);
+        assert!(core.check(RuntimeProfile::Minimal).is_ok());
+
+        let blocks = requirements(": show 1 block 64 type ; show");
+        assert_eq!(blocks.components(), [RuntimeComponent::Core, RuntimeComponent::Files]);
+        assert_eq!(blocks.needed_by[&RuntimeComponent::Files], "show");
+        let error = blocks.check(RuntimeProfile::Minimal).unwrap_err().to_string();
+        assert!(error.contains("'show' needs the files runtime"), "{}", error);
+        assert!(error.contains("--runtime-profile=standard"), "{}", error);
+        assert!(blocks.check(RuntimeProfile::Standard).is_ok());
+
+        let args = requirements("arg-count .");
+        assert_eq!(args.components(), [RuntimeComponent::Core, RuntimeComponent::System]);
+
+        let tasks = requirements(": work 1 + ; 1 task: work join .");
+        assert_eq!(tasks.components(), [RuntimeComponent::Core, RuntimeComponent::Threads]);
+        assert_eq!(tasks.needed_by[&RuntimeComponent::Threads], "main");
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        assert_eq!("minimal".parse::<RuntimeProfile>(), Ok(RuntimeProfile::Minimal));
+        assert_eq!("Standard".parse::<RuntimeProfile>(), Ok(RuntimeProfile::Standard));
+        assert!("tiny".parse::<RuntimeProfile>().is_err());
+        assert_eq!(RuntimeProfile::default().to_string(), "full");
+    }
+}
@@ -0,0 +1,97 @@
+//! Sandbox
+//!
+//! Limits for running untrusted source, such as the programs agents submit
+//! to the server. A sandboxed compilation rejects the words that reach
+//! outside the process's memory, shell commands, files, blocks and CODE
+//! words (see [`fastforth_frontend::sandbox`]), with a structured error at
+//! the word's location. Its JIT run is compiled interruptible (see
+//! [`crate::cancel`]) and limited by:
+//! - Fuel: the word calls and loop iterations it may make before it stops
+//!   with [`CompileError::OutOfFuel`](crate::CompileError::OutOfFuel)
+//! - A wall-clock timeout, after which it stops with
+//!   [`CompileError::Cancelled`](crate::CompileError::Cancelled)
+//! - Memory: the heap is served from a region of this many bytes, so
+//!   `allocate` fails with an ior instead of growing past it. Variables
+//!   are laid out at compile time, so the heap is the only memory a run
+//!   can grow.
+
+use crate::heap::{HeapAllocator, HeapConfig};
+use std::time::Duration;
+
+/// Limits of a sandboxed run; `None` leaves a resource unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sandbox {
+    /// Word calls and loop iterations the run may make
+    pub fuel: Option<u64>,
+    /// Longest the run may take
+    pub timeout: Option<Duration>,
+    /// Bytes the heap may hold
+    pub memory: Option<u64>,
+}
+
+impl Sandbox {
+    /// The tighter of each limit of `self` and `other`, such as a request's
+    /// own limits under a server's
+    pub fn tighten(self, other: Sandbox) -> Sandbox {
+        fn min<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+        Sandbox {
+            fuel: min(self.fuel, other.fuel),
+            timeout: min(self.timeout, other.timeout),
+            memory: min(self.memory, other.memory),
+        }
+    }
+
+    /// Sandbox of a request with these limits, under a server's sandbox
+    ///
+    /// A request is sandboxed if the server is, if it asked to be
+    /// (`requested`) or if it set any limit of its own.
+    pub fn for_request(self, server: Option<Sandbox>, requested: bool) -> Option<Sandbox> {
+        match server {
+            Some(server) => Some(server.tighten(self)),
+            None if requested || self != Sandbox::default() => Some(self),
+            None => None,
+        }
+    }
+
+    /// Heap settings of a run otherwise configured with `heap`
+    pub fn heap(&self, heap: HeapConfig) -> HeapConfig {
+        let Some(memory) = self.memory else {
+            return heap;
+        };
+        let region = match heap.allocator {
+            HeapAllocator::Region(size) => size.min(memory),
+            HeapAllocator::Malloc => memory,
+        };
+        HeapConfig { allocator: HeapAllocator::Region(region), ..heap }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_tighten_and_cap_the_heap() {
+        let server = Sandbox { fuel: Some(1000), timeout: Some(Duration::from_secs(1)), memory: None };
+        let request = Sandbox { fuel: Some(10), timeout: None, memory: Some(4096) };
+        assert_eq!(
+            server.tighten(request),
+            Sandbox { fuel: Some(10), timeout: Some(Duration::from_secs(1)), memory: Some(4096) }
+        );
+
+        assert_eq!(request.for_request(None, false), Some(request));
+        assert_eq!(Sandbox::default().for_request(None, false), None);
+        assert_eq!(Sandbox::default().for_request(None, true), Some(Sandbox::default()));
+        assert_eq!(Sandbox::default().for_request(Some(server), false), Some(server));
+
+        assert_eq!(server.heap(HeapConfig::default()), HeapConfig::default());
+        assert_eq!(request.heap(HeapConfig::default()).allocator, HeapAllocator::Region(4096));
+        let region = HeapConfig { allocator: HeapAllocator::Region(1024), debug: true };
+        assert_eq!(request.heap(region), region, "a smaller region is kept");
+    }
+}
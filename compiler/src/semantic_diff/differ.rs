@@ -2,14 +2,22 @@
 //!
 //! Core logic for semantic comparison
 
-use super::{SemanticDiff, PerformanceMetrics};
+use super::{FailOn, GateVerdict, SemanticDiff, PerformanceMetrics};
 use super::analyzer::PerformanceAnalyzer;
-use crate::symbolic::EquivalenceChecker;
+use crate::symbolic::{EquivalenceChecker, EquivalenceVerdict};
 use fastforth_frontend::{Program, Definition, parse_program};
 use serde::{Serialize, Deserialize};
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
+/// Operation similarity at which a removed and an added word are taken
+/// to be one renamed word
+const RENAME_SIMILARITY: f64 = 0.8;
+
+/// Extensions of the Forth sources compared by directory diffs
+const SOURCE_EXTENSIONS: [&str; 4] = ["fs", "fth", "4th", "forth"];
+
 #[derive(Debug, Error)]
 pub enum DiffError {
     #[error("Parse error in old file: {0}")]
@@ -32,6 +40,30 @@ pub struct DiffResult {
     pub total_words: usize,
     pub changed_words: usize,
     pub unchanged_words: usize,
+    #[serde(default)]
+    pub renamed_words: usize,
+    /// Words defined in a different file than before
+    #[serde(default)]
+    pub moved_words: usize,
+    /// Per-file counts, in directory diffs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileSummary>,
+    /// Result of `apply_policies`, if any were given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verdict: Option<GateVerdict>,
+}
+
+/// Word counts for one file of a directory diff
+///
+/// Words are counted in the file that defines them now, or for removed
+/// words the file that defined them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileSummary {
+    pub path: String,
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub unchanged: usize,
 }
 
 impl DiffResult {
@@ -41,18 +73,60 @@ impl DiffResult {
             total_words: 0,
             changed_words: 0,
             unchanged_words: 0,
+            renamed_words: 0,
+            moved_words: 0,
+            files: Vec::new(),
+            verdict: None,
         }
     }
 
+    /// Hold the diff to CI policies and record the verdict
+    pub fn apply_policies(&mut self, policies: &[FailOn]) -> &GateVerdict {
+        self.verdict.insert(GateVerdict::evaluate(self, policies))
+    }
+
     pub fn add_diff(&mut self, diff: SemanticDiff) {
         self.total_words += 1;
-        if diff.stack_effect_changed || diff.operations_changed || diff.performance_changed {
+        if diff.is_changed() {
             self.changed_words += 1;
         } else {
             self.unchanged_words += 1;
         }
+        if diff.renamed_from.is_some() {
+            self.renamed_words += 1;
+        }
+        if diff.is_moved() {
+            self.moved_words += 1;
+        }
         self.diffs.push(diff);
     }
+
+    /// Fill in `files` from the diffs
+    fn summarize_files(&mut self) {
+        let mut files: BTreeMap<&str, FileSummary> = BTreeMap::new();
+        for diff in &self.diffs {
+            let Some(path) = diff.new_file.as_deref().or(diff.old_file.as_deref()) else {
+                continue;
+            };
+            let summary = files.entry(path).or_insert_with(|| FileSummary { path: path.to_string(), ..Default::default() });
+            if diff.is_added() {
+                summary.added += 1;
+            } else if diff.is_removed() {
+                summary.removed += 1;
+            } else if diff.is_changed() {
+                summary.changed += 1;
+            } else {
+                summary.unchanged += 1;
+            }
+        }
+        self.files = files.into_values().collect();
+    }
+}
+
+/// A definition and the file it came from
+struct Located<'a> {
+    file: Option<&'a str>,
+    def: &'a Definition,
 }
 
 impl Default for DiffResult {
@@ -75,6 +149,13 @@ impl SemanticDiffer {
         }
     }
 
+    /// Unroll loops with unknown trip counts at most `loop_unroll` times
+    /// when checking equivalence
+    pub fn with_loop_unroll(mut self, loop_unroll: usize) -> Self {
+        self.equivalence_checker = EquivalenceChecker::new().with_loop_unroll(loop_unroll);
+        self
+    }
+
     /// Compare two programs from file paths
     pub fn diff_files(&self, old_path: &Path, new_path: &Path) -> Result<DiffResult, DiffError> {
         let old_source = std::fs::read_to_string(old_path)?;
@@ -96,61 +177,130 @@ impl SemanticDiffer {
 
     /// Compare two programs
     pub fn diff_programs(&self, old: &Program, new: &Program) -> Result<DiffResult, DiffError> {
-        let mut result = DiffResult::new();
+        let old_words: Vec<Located> = old.definitions.iter().map(|def| Located { file: None, def }).collect();
+        let new_words: Vec<Located> = new.definitions.iter().map(|def| Located { file: None, def }).collect();
+        Ok(self.diff_words(&old_words, &new_words))
+    }
 
-        // Build maps of definitions
-        let old_defs: std::collections::HashMap<_, _> = old
-            .definitions
-            .iter()
-            .map(|d| (d.name.clone(), d))
-            .collect();
-
-        let new_defs: std::collections::HashMap<_, _> = new
-            .definitions
-            .iter()
-            .map(|d| (d.name.clone(), d))
-            .collect();
-
-        // Find all unique word names
-        let mut all_names: Vec<String> = old_defs
-            .keys()
-            .chain(new_defs.keys())
-            .cloned()
-            .collect();
-        all_names.sort();
-        all_names.dedup();
-
-        // Compare each word
-        for name in all_names {
-            let diff = match (old_defs.get(&name), new_defs.get(&name)) {
-                (Some(old_def), Some(new_def)) => {
-                    self.diff_definitions(old_def, new_def)
-                }
-                (Some(old_def), None) => {
-                    let mut diff = SemanticDiff::new(name.clone());
-                    diff.operations_changed = true;
-                    diff.stack_effect_old = format!("{:?}", old_def.stack_effect);
-                    diff.stack_effect_new = "removed".to_string();
-                    diff.semantically_equivalent = false;
-                    diff.recommendation = "⚠ Word removed - check for usages".to_string();
-                    diff
-                }
-                (None, Some(new_def)) => {
-                    let mut diff = SemanticDiff::new(name.clone());
-                    diff.operations_changed = true;
-                    diff.stack_effect_old = "added".to_string();
-                    diff.stack_effect_new = format!("{:?}", new_def.stack_effect);
-                    diff.semantically_equivalent = false;
-                    diff.recommendation = "✓ New word added".to_string();
-                    diff
-                }
-                (None, None) => unreachable!(),
-            };
+    /// Compare every Forth source under two directories
+    ///
+    /// Words are matched across the whole tree, so a word moved to
+    /// another file is reported as moved rather than removed and added.
+    pub fn diff_dirs(&self, old_dir: &Path, new_dir: &Path) -> Result<DiffResult, DiffError> {
+        let old_programs = parse_dir(old_dir, DiffError::OldParseError)?;
+        let new_programs = parse_dir(new_dir, DiffError::NewParseError)?;
+
+        let mut result = self.diff_words(&locate(&old_programs), &locate(&new_programs));
+        result.summarize_files();
+        Ok(result)
+    }
+
+    /// Match words by name, then pair up the leftovers as renames
+    fn diff_words(&self, old: &[Located], new: &[Located]) -> DiffResult {
+        // Later definitions replace earlier ones, as in Forth
+        let old_defs: HashMap<&str, &Located> = old.iter().map(|l| (l.def.name.as_str(), l)).collect();
+        let new_defs: HashMap<&str, &Located> = new.iter().map(|l| (l.def.name.as_str(), l)).collect();
+
+        let mut diffs = Vec::new();
+        let mut removed = Vec::new();
+        for (name, old_word) in &old_defs {
+            match new_defs.get(name) {
+                Some(new_word) => diffs.push(self.diff_located(old_word, new_word)),
+                None => removed.push(*old_word),
+            }
+        }
+        let mut added: Vec<&Located> =
+            new_defs.iter().filter(|(name, _)| !old_defs.contains_key(*name)).map(|(_, word)| *word).collect();
+        removed.sort_by(|a, b| a.def.name.cmp(&b.def.name));
+        added.sort_by(|a, b| a.def.name.cmp(&b.def.name));
+
+        let (renamed_old, renamed_new) = self.match_renames(&removed, &added);
+        for (&i, &j) in renamed_old.iter().zip(&renamed_new) {
+            let mut diff = self.diff_located(removed[i], added[j]);
+            diff.word_name = added[j].def.name.clone();
+            diff.renamed_from = Some(removed[i].def.name.clone());
+            diff.generate_recommendation();
+            diffs.push(diff);
+        }
 
+        for (i, old_word) in removed.iter().enumerate() {
+            if renamed_old.contains(&i) {
+                continue;
+            }
+            let mut diff = SemanticDiff::new(old_word.def.name.clone());
+            diff.operations_changed = true;
+            diff.stack_effect_old = format!("{:?}", old_word.def.stack_effect);
+            diff.stack_effect_new = "removed".to_string();
+            diff.semantically_equivalent = false;
+            diff.recommendation = "⚠ Word removed - check for usages".to_string();
+            diff.old_file = old_word.file.map(str::to_string);
+            diffs.push(diff);
+        }
+        for (j, new_word) in added.iter().enumerate() {
+            if renamed_new.contains(&j) {
+                continue;
+            }
+            let mut diff = SemanticDiff::new(new_word.def.name.clone());
+            diff.operations_changed = true;
+            diff.stack_effect_old = "added".to_string();
+            diff.stack_effect_new = format!("{:?}", new_word.def.stack_effect);
+            diff.semantically_equivalent = false;
+            diff.recommendation = "✓ New word added".to_string();
+            diff.new_file = new_word.file.map(str::to_string);
+            diffs.push(diff);
+        }
+
+        diffs.sort_by(|a, b| a.word_name.cmp(&b.word_name));
+        let mut result = DiffResult::new();
+        for diff in diffs {
             result.add_diff(diff);
         }
+        result
+    }
 
-        Ok(result)
+    fn diff_located(&self, old: &Located, new: &Located) -> SemanticDiff {
+        let mut diff = self.diff_definitions(old.def, new.def);
+        diff.old_file = old.file.map(str::to_string);
+        diff.new_file = new.file.map(str::to_string);
+        diff
+    }
+
+    /// Pair removed words with added words that look like them renamed
+    ///
+    /// A pair qualifies if the bodies are similar enough or the words are
+    /// symbolically equivalent; the most similar pairs are taken first.
+    /// Returns matching index lists into `removed` and `added`.
+    fn match_renames(&self, removed: &[&Located], added: &[&Located]) -> (Vec<usize>, Vec<usize>) {
+        let mut candidates = Vec::new();
+        for (i, old) in removed.iter().enumerate() {
+            let old_ops = self.extract_operations(&old.def.body);
+            for (j, new) in added.iter().enumerate() {
+                let new_ops = self.extract_operations(&new.def.body);
+                let mut score = similarity(&old_ops, &new_ops);
+                if score < RENAME_SIMILARITY
+                    && old.def.stack_effect == new.def.stack_effect
+                    && self.equivalence_checker.check_definitions(old.def, new.def).verdict == EquivalenceVerdict::Equivalent
+                {
+                    score = RENAME_SIMILARITY;
+                }
+                if score >= RENAME_SIMILARITY {
+                    candidates.push((score, i, j));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+        let (mut used_old, mut used_new) = (HashSet::new(), HashSet::new());
+        let (mut renamed_old, mut renamed_new) = (Vec::new(), Vec::new());
+        for (_, i, j) in candidates {
+            if !used_old.contains(&i) && !used_new.contains(&j) {
+                used_old.insert(i);
+                used_new.insert(j);
+                renamed_old.push(i);
+                renamed_new.push(j);
+            }
+        }
+        (renamed_old, renamed_new)
     }
 
     /// Compare two definitions
@@ -178,6 +328,9 @@ impl SemanticDiffer {
         // Check semantic equivalence
         let equiv_result = self.equivalence_checker.check_definitions(old, new);
         diff.semantically_equivalent = equiv_result.equivalent;
+        if equiv_result.verdict == EquivalenceVerdict::Unknown {
+            diff.equivalence_unknown = Some(equiv_result.reason);
+        }
 
         // Generate recommendation
         diff.generate_recommendation();
@@ -192,7 +345,7 @@ impl SemanticDiffer {
         let mut ops = Vec::new();
         for word in body {
             match word {
-                Word::WordRef { name, .. } => ops.push(name.clone()),
+                Word::WordRef { name, .. } => ops.push(name.to_string()),
                 Word::IntLiteral(n) => ops.push(n.to_string()),
                 Word::FloatLiteral(f) => ops.push(f.to_string()),
                 Word::StringLiteral(s) => ops.push(format!("\"{}\"", s)),
@@ -203,6 +356,62 @@ impl SemanticDiffer {
     }
 }
 
+/// Definitions of parsed files, in file order
+fn locate(programs: &[(String, Program)]) -> Vec<Located<'_>> {
+    programs
+        .iter()
+        .flat_map(|(file, program)| program.definitions.iter().map(move |def| Located { file: Some(file), def }))
+        .collect()
+}
+
+/// Parse every Forth source under `dir`, keyed by path relative to it
+fn parse_dir(dir: &Path, parse_error: fn(String) -> DiffError) -> Result<Vec<(String, Program)>, DiffError> {
+    let mut paths = Vec::new();
+    collect_sources(dir, &mut paths)?;
+    paths.sort();
+
+    let mut programs = Vec::with_capacity(paths.len());
+    for path in paths {
+        let relative = path.strip_prefix(dir).unwrap_or(&path).display().to_string();
+        let source = std::fs::read_to_string(&path)?;
+        let program = parse_program(&source)
+            .map_err(|e| parse_error(format!("{}: {:?}", relative, e)))?;
+        programs.push((relative, program));
+    }
+    Ok(programs)
+}
+
+fn collect_sources(dir: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_sources(&path, paths)?;
+        } else if path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| SOURCE_EXTENSIONS.contains(&ext)) {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Share of operations two bodies have in common, in order, from 0 to 1
+fn similarity(a: &[String], b: &[String]) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+
+    // Longest common subsequence, one row at a time
+    let mut row = vec![0usize; b.len() + 1];
+    for x in a {
+        let mut diagonal = 0;
+        for (j, y) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if x == y { diagonal + 1 } else { above.max(row[j]) };
+            diagonal = above;
+        }
+    }
+    2.0 * row[b.len()] as f64 / (a.len() + b.len()) as f64
+}
+
 impl Default for SemanticDiffer {
     fn default() -> Self {
         Self::new()
@@ -233,4 +442,59 @@ mod tests {
         assert_eq!(result.total_words, 1);
         assert!(result.diffs[0].operations_changed);
     }
+
+    #[test]
+    fn test_diff_detects_renames() {
+        let old = ": square dup * ; : cube dup dup * * ; : gone 1 + ;";
+        let new = ": sq dup * ; : cube dup dup * * ; : fresh 2 3 4 + + ;";
+        let differ = SemanticDiffer::new();
+
+        let result = differ.diff_sources(old, new).unwrap();
+        assert_eq!(result.total_words, 4);
+        assert_eq!(result.renamed_words, 1);
+
+        let sq = result.diffs.iter().find(|d| d.word_name == "sq").unwrap();
+        assert_eq!(sq.renamed_from.as_deref(), Some("square"));
+        assert!(!sq.is_changed());
+        assert!(result.diffs.iter().any(|d| d.word_name == "gone" && d.is_removed()));
+        assert!(result.diffs.iter().any(|d| d.word_name == "fresh" && d.is_added()));
+    }
+
+    #[test]
+    fn test_diff_dirs_tracks_moves() {
+        let old = tempfile::tempdir().unwrap();
+        let new = tempfile::tempdir().unwrap();
+        std::fs::write(old.path().join("math.fs"), ": square dup * ; : double 2 * ;").unwrap();
+        std::fs::create_dir(new.path().join("lib")).unwrap();
+        std::fs::write(new.path().join("math.fs"), ": double dup + ;").unwrap();
+        std::fs::write(new.path().join("lib/square.fth"), ": square dup * ;").unwrap();
+        std::fs::write(new.path().join("notes.txt"), "not forth").unwrap();
+
+        let result = SemanticDiffer::new().diff_dirs(old.path(), new.path()).unwrap();
+        assert_eq!(result.total_words, 2);
+        assert_eq!(result.moved_words, 1);
+        assert_eq!(result.changed_words, 1);
+
+        let square = result.diffs.iter().find(|d| d.word_name == "square").unwrap();
+        assert!(square.is_moved() && !square.is_changed());
+        assert_eq!(
+            result.files,
+            vec![
+                FileSummary { path: "lib/square.fth".to_string(), unchanged: 1, ..Default::default() },
+                FileSummary { path: "math.fs".to_string(), changed: 1, ..Default::default() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_loops_degrade_to_unknown() {
+        let old = ": countdown begin 1- dup 0= until ;";
+        let new = ": countdown begin 1 - dup 0 = until ;";
+        let differ = SemanticDiffer::new().with_loop_unroll(4);
+
+        let result = differ.diff_sources(old, new).unwrap();
+        let diff = &result.diffs[0];
+        assert!(diff.equivalence_unknown.as_deref().unwrap().contains("beyond depth 4"));
+        assert!(diff.recommendation.starts_with("? Equivalence unknown"));
+    }
 }
@@ -0,0 +1,197 @@
+//! Diff Gate
+//!
+//! Policies a CI pipeline can hold a diff to, such as "semantically
+//! equivalent and at most 5% more operations". Each policy that is broken
+//! sets its own bit of the exit code, so scripts can tell them apart
+//! without parsing the report.
+
+use super::{DiffResult, SemanticDiff};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A condition that fails the gate
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "rule", rename_all = "kebab-case")]
+pub enum FailOn {
+    /// A word's declared stack effect changed
+    StackEffectChange,
+    /// A word needs more than `percent`% more operations than before
+    PerfRegression { percent: f64 },
+    /// A word is no longer semantically equivalent to its old version
+    SemanticChange,
+}
+
+impl FailOn {
+    /// Exit code bit set when this policy is broken
+    pub fn exit_bit(&self) -> i32 {
+        match self {
+            FailOn::StackEffectChange => 2,
+            FailOn::PerfRegression { .. } => 4,
+            FailOn::SemanticChange => 8,
+        }
+    }
+
+    /// Why `diff` breaks this policy, if it does
+    ///
+    /// Only words present in both versions are judged; added and removed
+    /// words have nothing to compare with.
+    pub fn check(&self, diff: &SemanticDiff) -> Option<String> {
+        if diff.is_added() || diff.is_removed() {
+            return None;
+        }
+
+        match self {
+            FailOn::StackEffectChange if diff.stack_effect_changed => {
+                Some(format!("stack effect {} became {}", diff.stack_effect_old, diff.stack_effect_new))
+            }
+            FailOn::PerfRegression { percent } => {
+                let old = diff.performance_old.operation_count as f64;
+                let new = diff.performance_new.operation_count as f64;
+                let limit = old * (1.0 + percent / 100.0);
+                (new > limit).then(|| {
+                    format!("{} operations, up from {} (+{:.1}%)", new, old, (new - old) / old.max(1.0) * 100.0)
+                })
+            }
+            FailOn::SemanticChange if !diff.semantically_equivalent && diff.equivalence_unknown.is_none() => {
+                Some("not semantically equivalent to the old version".to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FailOn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailOn::StackEffectChange => write!(f, "stack-effect-change"),
+            FailOn::PerfRegression { percent } => write!(f, "perf-regression:{}%", percent),
+            FailOn::SemanticChange => write!(f, "semantic-change"),
+        }
+    }
+}
+
+impl FromStr for FailOn {
+    type Err = String;
+
+    /// Parse `stack-effect-change`, `semantic-change`, or
+    /// `perf-regression[:N[%]]`, where a missing N allows no regression
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (name, arg) = match s.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s, None),
+        };
+
+        match (name.trim(), arg) {
+            ("stack-effect-change", None) => Ok(FailOn::StackEffectChange),
+            ("semantic-change", None) => Ok(FailOn::SemanticChange),
+            ("perf-regression", None) => Ok(FailOn::PerfRegression { percent: 0.0 }),
+            ("perf-regression", Some(arg)) => {
+                let number = arg.trim().trim_end_matches('%');
+                match number.parse::<f64>() {
+                    Ok(percent) if percent >= 0.0 => Ok(FailOn::PerfRegression { percent }),
+                    _ => Err(format!("invalid regression threshold '{}'", arg)),
+                }
+            }
+            _ => Err(format!(
+                "unknown policy '{}' (expected stack-effect-change, perf-regression[:N%] or semantic-change)",
+                s
+            )),
+        }
+    }
+}
+
+/// One word breaking one policy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Violation {
+    pub policy: String,
+    pub word: String,
+    pub detail: String,
+}
+
+/// Outcome of holding a diff to a set of policies
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GateVerdict {
+    pub passed: bool,
+    /// 0 if passed, else the OR of the broken policies' exit bits
+    pub exit_code: i32,
+    pub policies: Vec<String>,
+    pub violations: Vec<Violation>,
+    /// Words whose equivalence could be neither proved nor refuted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unknown: Vec<String>,
+}
+
+impl GateVerdict {
+    /// Check every word of `result` against `policies`
+    pub fn evaluate(result: &DiffResult, policies: &[FailOn]) -> Self {
+        let mut violations = Vec::new();
+        let mut exit_code = 0;
+        for diff in &result.diffs {
+            for policy in policies {
+                if let Some(detail) = policy.check(diff) {
+                    exit_code |= policy.exit_bit();
+                    violations.push(Violation {
+                        policy: policy.to_string(),
+                        word: diff.word_name.clone(),
+                        detail,
+                    });
+                }
+            }
+        }
+
+        let unknown = if policies.contains(&FailOn::SemanticChange) {
+            result
+                .diffs
+                .iter()
+                .filter(|diff| diff.equivalence_unknown.is_some())
+                .map(|diff| diff.word_name.clone())
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            passed: violations.is_empty(),
+            exit_code,
+            policies: policies.iter().map(|p| p.to_string()).collect(),
+            violations,
+            unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::semantic_diff::SemanticDiffer;
+
+    #[test]
+    fn test_parse_policies() {
+        assert_eq!("semantic-change".parse::<FailOn>(), Ok(FailOn::SemanticChange));
+        assert_eq!("perf-regression:10%".parse::<FailOn>(), Ok(FailOn::PerfRegression { percent: 10.0 }));
+        assert_eq!("perf-regression:2.5".parse::<FailOn>(), Ok(FailOn::PerfRegression { percent: 2.5 }));
+        assert_eq!("perf-regression".parse::<FailOn>(), Ok(FailOn::PerfRegression { percent: 0.0 }));
+        assert!("perf-regression:-1%".parse::<FailOn>().is_err());
+        assert!("stack-effect-change:1".parse::<FailOn>().is_err());
+        assert!("anything".parse::<FailOn>().is_err());
+    }
+
+    #[test]
+    fn test_gate_sets_exit_bits() {
+        let old = ": double ( n -- n ) 2 * ; : inc 1 + ;";
+        let new = ": double ( n -- n ) dup + ; : inc 1 + 0 + 0 + ;";
+        let result = SemanticDiffer::new().diff_sources(old, new).unwrap();
+
+        let lenient = GateVerdict::evaluate(&result, &[FailOn::PerfRegression { percent: 200.0 }]);
+        assert!(lenient.passed);
+        assert_eq!(lenient.exit_code, 0);
+
+        let strict = GateVerdict::evaluate(&result, &[FailOn::StackEffectChange, FailOn::PerfRegression { percent: 5.0 }]);
+        assert!(!strict.passed);
+        assert_eq!(strict.exit_code, 4);
+        assert_eq!(strict.violations.len(), 1);
+        assert_eq!(strict.violations[0].word, "inc");
+        assert_eq!(strict.violations[0].policy, "perf-regression:5%");
+    }
+}
@@ -6,10 +6,12 @@
 pub mod differ;
 pub mod analyzer;
 pub mod reporter;
+pub mod gate;
 
-pub use differ::{SemanticDiffer, DiffResult};
+pub use differ::{SemanticDiffer, DiffResult, FileSummary};
 pub use analyzer::PerformanceAnalyzer;
 pub use reporter::{DiffReporter, ReportFormat};
+pub use gate::{FailOn, GateVerdict, Violation};
 
 use serde::{Serialize, Deserialize};
 
@@ -27,7 +29,18 @@ pub struct SemanticDiff {
     pub performance_old: PerformanceMetrics,
     pub performance_new: PerformanceMetrics,
     pub semantically_equivalent: bool,
+    /// Why equivalence could be neither proved nor refuted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equivalence_unknown: Option<String>,
     pub recommendation: String,
+    /// Name the word had before, if it was renamed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renamed_from: Option<String>,
+    /// Files defining the word, in directory diffs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_file: Option<String>,
 }
 
 impl SemanticDiff {
@@ -45,13 +58,41 @@ impl SemanticDiff {
             performance_old: PerformanceMetrics::default(),
             performance_new: PerformanceMetrics::default(),
             semantically_equivalent: true,
+            equivalence_unknown: None,
             recommendation: String::new(),
+            renamed_from: None,
+            old_file: None,
+            new_file: None,
         }
     }
 
+    /// Whether the body, stack effect or cost changed
+    pub fn is_changed(&self) -> bool {
+        self.stack_effect_changed || self.operations_changed || self.performance_changed
+    }
+
+    /// Whether the word only exists in the new version
+    pub fn is_added(&self) -> bool {
+        self.stack_effect_old == "added"
+    }
+
+    /// Whether the word only exists in the old version
+    pub fn is_removed(&self) -> bool {
+        self.stack_effect_new == "removed"
+    }
+
+    /// Whether the word is defined in a different file than before
+    pub fn is_moved(&self) -> bool {
+        matches!((&self.old_file, &self.new_file), (Some(old), Some(new)) if old != new)
+    }
+
     /// Generate a recommendation based on the diff
     pub fn generate_recommendation(&mut self) {
-        if !self.semantically_equivalent {
+        if self.renamed_from.is_some() && !self.is_changed() {
+            self.recommendation = "✓ Renamed without changes - update callers".to_string();
+        } else if let Some(reason) = &self.equivalence_unknown {
+            self.recommendation = format!("? Equivalence unknown ({}) - test before deploying", reason);
+        } else if !self.semantically_equivalent {
             self.recommendation = "⚠ Not semantically equivalent - verify correctness before deploying".to_string();
         } else if self.stack_effect_changed {
             self.recommendation = "⚠ Stack effect changed - update documentation and callers".to_string();
@@ -2,7 +2,7 @@
 //!
 //! Formats semantic diff results for different output formats
 
-use super::{SemanticDiff, DiffResult};
+use super::{SemanticDiff, DiffResult, GateVerdict};
 use serde_json;
 use colored::Colorize;
 
@@ -36,23 +36,101 @@ impl DiffReporter {
         output.push_str(&format!("Total words: {}\n", result.total_words));
         output.push_str(&format!("Changed: {}\n", result.changed_words.to_string().yellow()));
         output.push_str(&format!("Unchanged: {}\n", result.unchanged_words.to_string().green()));
+        if result.renamed_words > 0 {
+            output.push_str(&format!("Renamed: {}\n", result.renamed_words.to_string().cyan()));
+        }
+        if result.moved_words > 0 {
+            output.push_str(&format!("Moved: {}\n", result.moved_words.to_string().cyan()));
+        }
         output.push_str("\n");
 
-        for diff in &result.diffs {
+        if !result.files.is_empty() {
+            output.push_str(&format!("{}\n", "Files:".cyan().bold()));
+            for file in &result.files {
+                output.push_str(&format!(
+                    "  {}  +{} -{} ~{} ={}\n",
+                    file.path,
+                    file.added.to_string().green(),
+                    file.removed.to_string().red(),
+                    file.changed.to_string().yellow(),
+                    file.unchanged
+                ));
+            }
+            output.push_str("\n");
+        }
+
+        // Words that only changed name or file get one line each
+        let (relocated, detailed): (Vec<&SemanticDiff>, Vec<&SemanticDiff>) = result
+            .diffs
+            .iter()
+            .partition(|diff| !diff.is_changed() && (diff.renamed_from.is_some() || diff.is_moved()));
+
+        if !relocated.is_empty() {
+            output.push_str(&format!("{}\n", "Renamed or moved without changes:".cyan().bold()));
+            for diff in relocated {
+                output.push_str(&format!("  {}\n", Self::format_relocation(diff)));
+            }
+            output.push_str("\n");
+        }
+
+        for diff in detailed {
             output.push_str(&Self::format_diff_human(diff));
             output.push_str("\n");
         }
 
+        if let Some(verdict) = &result.verdict {
+            output.push_str(&Self::format_verdict_human(verdict));
+        }
+
         output
     }
 
+    /// Format the CI gate verdict
+    fn format_verdict_human(verdict: &GateVerdict) -> String {
+        let mut output = format!("{} ({})\n", "GATE".cyan().bold(), verdict.policies.join(", "));
+        if verdict.passed {
+            output.push_str(&format!("  {} passed\n", "✓".green().bold()));
+        } else {
+            output.push_str(&format!("  {} failed (exit code {})\n", "✗".red().bold(), verdict.exit_code));
+            for violation in &verdict.violations {
+                output.push_str(&format!("  - {} [{}]: {}\n", violation.word, violation.policy, violation.detail));
+            }
+        }
+        for word in &verdict.unknown {
+            output.push_str(&format!("  ? {}: equivalence unknown\n", word));
+        }
+        output
+    }
+
+    /// `old → new` for a renamed word, `word: a.fs → b.fs` for a moved one
+    fn format_relocation(diff: &SemanticDiff) -> String {
+        let mut line = match &diff.renamed_from {
+            Some(old) => format!("{} → {}", old, diff.word_name),
+            None => diff.word_name.clone(),
+        };
+        if let (true, Some(old), Some(new)) = (diff.is_moved(), &diff.old_file, &diff.new_file) {
+            line.push_str(&format!(": {} → {}", old, new));
+        }
+        line
+    }
+
     /// Format a single diff for human reading
     fn format_diff_human(diff: &SemanticDiff) -> String {
         let mut output = String::new();
 
-        output.push_str(&format!("\n{}: {}\n", "Word".cyan().bold(), diff.word_name.yellow()));
+        output.push_str(&format!("\n{}: {}", "Word".cyan().bold(), diff.word_name.yellow()));
+        if let Some(old) = &diff.renamed_from {
+            output.push_str(&format!(" (renamed from {})", old));
+        }
+        if let Some(file) = diff.new_file.as_ref().or(diff.old_file.as_ref()) {
+            output.push_str(&format!(" in {}", file));
+        }
+        output.push('\n');
         output.push_str(&"-".repeat(80));
         output.push_str("\n");
+        if diff.is_moved() {
+            output.push_str(&format!("{} {}\n\n", "Moved:".green().bold(), Self::format_relocation(diff)));
+        }
 
         // Stack effect
         output.push_str(&format!("{}\n", "Stack Effect:".green().bold()));
@@ -116,7 +194,9 @@ impl DiffReporter {
 
         // Equivalence
         output.push_str(&format!("{}\n", "Semantic Equivalence:".green().bold()));
-        if diff.semantically_equivalent {
+        if let Some(reason) = &diff.equivalence_unknown {
+            output.push_str(&format!("  {} Unknown: {}\n", "?".yellow().bold(), reason));
+        } else if diff.semantically_equivalent {
             output.push_str(&format!("  {} Semantically equivalent\n", "✓".green().bold()));
         } else {
             output.push_str(&format!("  {} NOT semantically equivalent\n", "✗".red().bold()));
@@ -0,0 +1,124 @@
+//! Background compile jobs for the verification server
+//!
+//! Slow builds (LLVM at high optimization levels) are submitted as jobs and
+//! polled instead of holding an HTTP request open.
+
+use super::routes::CompileResponse;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Job identifier, unique for the lifetime of the server
+pub type JobId = u64;
+
+/// Progress of a compile job
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Waiting for a compile slot
+    Queued,
+    /// Compiling
+    Running,
+    /// Finished; the result may still report compile errors
+    Done { result: CompileResponse },
+    /// The request could not be compiled at all
+    Failed { error: String },
+    /// Exceeded the job timeout
+    TimedOut,
+}
+
+impl JobStatus {
+    /// Whether the job will not change again
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+/// Bounded table of submitted jobs
+///
+/// When full, the oldest finished job is forgotten to make room. If every
+/// job is still pending, new submissions are refused.
+pub struct JobStore {
+    capacity: usize,
+    state: Mutex<JobTable>,
+}
+
+#[derive(Default)]
+struct JobTable {
+    next_id: JobId,
+    jobs: BTreeMap<JobId, JobStatus>,
+}
+
+impl JobStore {
+    /// Create a store holding at most `capacity` jobs
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(JobTable::default()),
+        }
+    }
+
+    /// Register a queued job, or `None` if the store is full of pending jobs
+    pub fn submit(&self) -> Option<JobId> {
+        let mut table = self.state.lock().unwrap();
+        if table.jobs.len() >= self.capacity {
+            let oldest_finished = table
+                .jobs
+                .iter()
+                .find(|(_, status)| status.is_finished())
+                .map(|(id, _)| *id)?;
+            table.jobs.remove(&oldest_finished);
+        }
+
+        let id = table.next_id;
+        table.next_id += 1;
+        table.jobs.insert(id, JobStatus::Queued);
+        Some(id)
+    }
+
+    /// Record the progress of a job
+    pub fn update(&self, id: JobId, status: JobStatus) {
+        let mut table = self.state.lock().unwrap();
+        if let Some(entry) = table.jobs.get_mut(&id) {
+            *entry = status;
+        }
+    }
+
+    /// Current status of a job, if it is known
+    pub fn get(&self, id: JobId) -> Option<JobStatus> {
+        self.state.lock().unwrap().jobs.get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_lifecycle() {
+        let store = JobStore::new(4);
+        let id = store.submit().unwrap();
+        assert!(matches!(store.get(id), Some(JobStatus::Queued)));
+
+        store.update(id, JobStatus::Running);
+        assert!(matches!(store.get(id), Some(JobStatus::Running)));
+
+        store.update(id, JobStatus::Failed { error: "bad".to_string() });
+        assert!(store.get(id).unwrap().is_finished());
+        assert!(store.get(id + 1).is_none());
+    }
+
+    #[test]
+    fn test_full_store_evicts_finished_jobs_only() {
+        let store = JobStore::new(2);
+        let first = store.submit().unwrap();
+        let second = store.submit().unwrap();
+        assert!(store.submit().is_none());
+
+        store.update(first, JobStatus::TimedOut);
+        let third = store.submit().unwrap();
+        assert!(store.get(first).is_none());
+        assert!(store.get(second).is_some());
+        assert!(store.get(third).is_some());
+    }
+}
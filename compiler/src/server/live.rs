@@ -0,0 +1,280 @@
+//! Live diagnostics for an edited document
+//!
+//! Backs the server's WebSocket endpoint: the client sends edits as the
+//! user types and gets back diagnostics plus the stack effect of every
+//! definition that changed since the previous update.
+//!
+//! Reparsing the whole document is cheap; inferring effects is what gets
+//! cached, in the inference API's [`EffectDatabase`](crate::inference::EffectDatabase).
+//! Effects are keyed by definition body, so editing one word only re-infers
+//! that word and its callers even when the edit shifts every line below it,
+//! and calls to words recorded from other documents take their effects.
+
+use crate::error::{CompileError, FrontendStage};
+use crate::errors::{to_structured_error, StructuredError};
+use crate::inference::InferenceAPI;
+use fastforth_frontend::semantic::validate_program;
+use fastforth_frontend::{parse_program_recovering, Definition};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Most diagnostics reported for one version of a document
+const MAX_DIAGNOSTICS: usize = 100;
+
+/// 1-based position in a document, columns counted in characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Span replaced by an edit, end exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// One change to a document; without a range the text replaces everything
+#[derive(Debug, Clone, Deserialize)]
+pub struct Edit {
+    #[serde(default)]
+    pub range: Option<Range>,
+    pub text: String,
+}
+
+/// What is known about one definition
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DefinitionReport {
+    pub name: String,
+    pub line: usize,
+    pub column: usize,
+    /// Effect written in the definition's `( -- )` comment
+    pub declared_effect: Option<String>,
+    /// Effect inferred from the body; absent for bodies with control flow
+    pub inferred_effect: Option<String>,
+}
+
+/// Diagnostics pushed after a batch of edits
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveUpdate {
+    pub version: u64,
+    /// All current diagnostics; replaces the previous list
+    pub diagnostics: Vec<StructuredError>,
+    /// Definitions that are new or whose report changed
+    pub changed: Vec<DefinitionReport>,
+    /// Names of definitions that no longer exist
+    pub removed: Vec<String>,
+}
+
+/// A document being edited, with the reports last sent for it
+pub struct LiveDocument {
+    text: String,
+    version: u64,
+    api: InferenceAPI,
+    /// Reports sent in the previous update, by definition name
+    reports: HashMap<String, DefinitionReport>,
+}
+
+impl LiveDocument {
+    /// Start with an empty document
+    pub fn new() -> Self {
+        Self::with_api(InferenceAPI::new())
+    }
+
+    /// Start with an empty document whose definitions are recorded in the
+    /// database of `api`
+    pub fn with_api(api: InferenceAPI) -> Self {
+        Self {
+            text: String::new(),
+            version: 0,
+            api,
+            reports: HashMap::new(),
+        }
+    }
+
+    /// Current document text
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Apply edits in order, then report what changed
+    ///
+    /// If any edit is invalid the document is left as it was.
+    pub fn apply(&mut self, edits: &[Edit]) -> Result<LiveUpdate, String> {
+        let mut text = self.text.clone();
+        for edit in edits {
+            match edit.range {
+                Some(range) => {
+                    let start = offset(&text, range.start)?;
+                    let end = offset(&text, range.end)?;
+                    if start > end {
+                        return Err("Edit range ends before it starts".to_string());
+                    }
+                    text.replace_range(start..end, &edit.text);
+                }
+                None => text = edit.text.clone(),
+            }
+        }
+        self.text = text;
+        self.version += 1;
+        Ok(self.update())
+    }
+
+    fn update(&mut self) -> LiveUpdate {
+        let (program, mut errors) = parse_program_recovering(&self.text, MAX_DIAGNOSTICS);
+        let stage = if errors.is_empty() {
+            errors = validate_program(&program).errors;
+            errors.truncate(MAX_DIAGNOSTICS);
+            FrontendStage::Semantic
+        } else {
+            FrontendStage::Parse
+        };
+        let diagnostics = errors
+            .into_iter()
+            .map(|error| to_structured_error(&CompileError::frontend(stage, error), false))
+            .collect();
+
+        self.api.record(&self.text);
+        let mut reports = HashMap::new();
+        for definition in &program.definitions {
+            let report = self.report(definition);
+            reports.insert(report.name.clone(), report);
+        }
+
+        let mut changed: Vec<DefinitionReport> = reports
+            .values()
+            .filter(|report| self.reports.get(&report.name) != Some(*report))
+            .cloned()
+            .collect();
+        changed.sort_by_key(|report| (report.line, report.column));
+        let mut removed: Vec<String> = self
+            .reports
+            .keys()
+            .filter(|name| !reports.contains_key(*name))
+            .cloned()
+            .collect();
+        removed.sort();
+
+        self.reports = reports;
+        LiveUpdate {
+            version: self.version,
+            diagnostics,
+            changed,
+            removed,
+        }
+    }
+
+    fn report(&self, definition: &Definition) -> DefinitionReport {
+        let inferred_effect = self
+            .api
+            .word_effect(&definition.name)
+            .filter(|entry| !entry.declared)
+            .map(|entry| entry.effect.to_string());
+
+        DefinitionReport {
+            name: definition.name.clone(),
+            line: definition.location.line,
+            column: definition.location.column,
+            declared_effect: definition.stack_effect.as_ref().map(|effect| effect.to_string()),
+            inferred_effect,
+        }
+    }
+}
+
+impl Default for LiveDocument {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Byte offset of a position, which may be just past the end of a line
+fn offset(text: &str, position: Position) -> Result<usize, String> {
+    let invalid = || format!("Position {}:{} is outside the document", position.line, position.column);
+    if position.line == 0 || position.column == 0 {
+        return Err(invalid());
+    }
+
+    let mut line_start = 0;
+    for _ in 1..position.line {
+        line_start += text[line_start..].find('\n').ok_or_else(invalid)? + 1;
+    }
+    let line = text[line_start..].split('\n').next().unwrap_or("");
+    let column = line
+        .char_indices()
+        .map(|(offset, _)| offset)
+        .chain(std::iter::once(line.len()))
+        .nth(position.column - 1)
+        .ok_or_else(invalid)?;
+    Ok(line_start + column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace_all(text: &str) -> Edit {
+        Edit { range: None, text: text.to_string() }
+    }
+
+    #[test]
+    fn test_only_changed_definitions_are_pushed() {
+        let mut document = LiveDocument::new();
+        let update = document
+            .apply(&[replace_all(": square dup * ;\n: drop2 drop drop ;")])
+            .unwrap();
+        assert!(update.diagnostics.is_empty());
+        assert_eq!(update.changed.len(), 2);
+        assert_eq!(update.changed[0].name, "square");
+        assert_eq!(update.changed[0].inferred_effect.as_deref(), Some("( x -- n )"));
+
+        // Rename drop2; square is untouched
+        let edit = Edit {
+            range: Some(Range {
+                start: Position { line: 2, column: 3 },
+                end: Position { line: 2, column: 8 },
+            }),
+            text: "two-drops".to_string(),
+        };
+        let update = document.apply(&[edit]).unwrap();
+        assert_eq!(document.text(), ": square dup * ;\n: two-drops drop drop ;");
+        assert_eq!(update.version, 2);
+        assert_eq!(update.changed.len(), 1);
+        assert_eq!(update.changed[0].name, "two-drops");
+        assert_eq!(update.removed, vec!["drop2".to_string()]);
+    }
+
+    #[test]
+    fn test_documents_sharing_an_api_see_each_others_words() {
+        let api = InferenceAPI::new();
+        let mut library = LiveDocument::with_api(api.clone());
+        library.apply(&[replace_all(": sum3 + + ;")]).unwrap();
+
+        let mut document = LiveDocument::with_api(api);
+        let update = document.apply(&[replace_all(": avg3 sum3 3 / ;")]).unwrap();
+        assert_eq!(update.changed[0].inferred_effect.as_deref(), Some("( n n n -- n )"));
+    }
+
+    #[test]
+    fn test_parse_errors_are_reported_with_locations() {
+        let mut document = LiveDocument::new();
+        let update = document.apply(&[replace_all(": ok 1 + ;\n: broken 1 +")]).unwrap();
+        assert_eq!(update.diagnostics.len(), 1);
+        assert_eq!(update.diagnostics[0].location.line, 2);
+    }
+
+    #[test]
+    fn test_edit_outside_document_is_rejected() {
+        let mut document = LiveDocument::new();
+        document.apply(&[replace_all("1 2 +")]).unwrap();
+        let edit = Edit {
+            range: Some(Range {
+                start: Position { line: 3, column: 1 },
+                end: Position { line: 3, column: 1 },
+            }),
+            text: "x".to_string(),
+        };
+        assert!(document.apply(&[edit]).is_err());
+        assert_eq!(document.text(), "1 2 +");
+    }
+}
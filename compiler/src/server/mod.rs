@@ -2,8 +2,20 @@
 //!
 //! High-performance async server for stack effect verification.
 //! Target: <1ms latency, 10,000+ requests/sec
+//!
+//! The same operations are available without the `server` feature as
+//! newline-delimited JSON-RPC over stdin/stdout (see [`stdio`]).
 
+#[cfg(feature = "server")]
+pub mod jobs;
+#[cfg(feature = "server")]
 pub mod routes;
+#[cfg(feature = "server")]
 pub mod server;
+pub mod live;
+pub mod stdio;
 
-pub use server::{VerificationServer, ServerConfig};
+#[cfg(feature = "server")]
+pub use server::{VerificationServer, ServerConfig, ServerState};
+pub use live::LiveDocument;
+pub use stdio::StdioServer;
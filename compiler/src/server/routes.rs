@@ -2,14 +2,26 @@
 
 #[cfg(feature = "server")]
 use axum::{
-    extract::State,
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    extract::{ConnectInfo, Path, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
     http::StatusCode,
     Json,
 };
 
-use crate::inference::InferenceAPI;
+use super::jobs::{JobId, JobStatus};
+use super::live::{Edit, LiveDocument};
+use super::server::ServerState;
+use crate::access::{credential, AccessDenied};
+use crate::errors::{to_structured_error, StructuredError};
+use crate::inference::{InferenceAPI, InferenceResult};
+use crate::{Backend, CancelToken, CompilationMode, Compiler, OptimizationLevel, Sandbox};
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "server")]
 use lazy_static::lazy_static;
@@ -54,12 +66,284 @@ pub struct ComposeRequest {
     pub words: Vec<String>,
 }
 
+/// Batched infer request
+#[derive(Deserialize)]
+pub struct BatchInferRequest {
+    pub codes: Vec<String>,
+}
+
+/// Batched infer response, one result per input in order
+#[derive(Serialize)]
+pub struct BatchInferResponse {
+    pub results: Vec<InferenceResult>,
+    pub latency_ms: f64,
+}
+
+/// Compile request
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompileRequest {
+    pub source: String,
+    /// `aot` or `jit`
+    #[serde(default = "default_mode")]
+    pub mode: String,
+    /// Optimization level 0-3
+    #[serde(default = "default_opt_level")]
+    pub opt_level: u8,
+    /// `auto`, `cranelift` or `llvm`
+    #[serde(default)]
+    pub backend: Option<String>,
+    /// Type check only, without generating code
+    #[serde(default)]
+    pub verify_only: bool,
+    /// Return the generated object file, base64 encoded
+    #[serde(default)]
+    pub emit_object: bool,
+    /// File name reported in diagnostics
+    #[serde(default)]
+    pub file: Option<String>,
+    /// Stop the request after this many milliseconds; the server's own
+    /// timeout still applies if it is shorter
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Compile the source as untrusted, in a sandbox
+    #[serde(default)]
+    pub sandbox: bool,
+    /// Word calls and loop iterations the JIT run may make (implies
+    /// `sandbox`)
+    #[serde(default)]
+    pub fuel: Option<u64>,
+    /// Most heap the JIT run may allocate, in bytes (implies `sandbox`)
+    #[serde(default)]
+    pub max_memory: Option<u64>,
+}
+
+impl CompileRequest {
+    /// Time the request may take under a server limit of `limit`
+    pub fn timeout(&self, limit: Duration) -> Duration {
+        self.timeout_ms.map_or(limit, |ms| limit.min(Duration::from_millis(ms)))
+    }
+
+    /// Sandbox the request is compiled in under the server's sandbox
+    pub fn sandbox(&self, server: Option<Sandbox>) -> Option<Sandbox> {
+        Sandbox { fuel: self.fuel, timeout: None, memory: self.max_memory }.for_request(server, self.sandbox)
+    }
+}
+
+fn default_mode() -> String {
+    "aot".to_string()
+}
+
+fn default_opt_level() -> u8 {
+    2
+}
+
+/// Compile response
+///
+/// Compile errors are reported here with `success: false`; an HTTP error
+/// status means the request itself was rejected.
+#[derive(Debug, Clone, Serialize)]
+pub struct CompileResponse {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    pub compile_time_ms: u64,
+    pub definitions_count: usize,
+    /// Data stack left by JIT execution, bottom first
+    pub stack: Vec<i64>,
+    pub warnings: Vec<String>,
+    pub diagnostics: Vec<StructuredError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub object_code: Option<String>,
+}
+
+/// Submitted job reference
+#[derive(Serialize)]
+pub struct JobResponse {
+    pub id: JobId,
+    #[serde(flatten)]
+    pub status: JobStatus,
+}
+
+/// Edits sent over the live diagnostics WebSocket
+#[derive(Deserialize)]
+pub struct LiveRequest {
+    pub edits: Vec<Edit>,
+}
+
 /// Error response
 #[derive(Serialize)]
 pub struct ErrorResponse {
     pub error: String,
 }
 
+/// Infer each snippet, reporting failures in place
+pub fn infer_batch(api: &InferenceAPI, codes: &[String]) -> Vec<InferenceResult> {
+    codes
+        .iter()
+        .map(|code| {
+            api.infer(code).unwrap_or_else(|error| InferenceResult {
+                valid: false,
+                inferred_effect: String::new(),
+                stack_depth_delta: 0,
+                operations: Vec::new(),
+                latency_ms: 0.0,
+                error: Some(error),
+                words: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+/// Run a compile request to completion in `sandbox`, if any, or until
+/// `cancel` stops its JIT run
+///
+/// Returns `Err` only for invalid options.
+pub fn compile_source(req: &CompileRequest, sandbox: Option<Sandbox>, cancel: &CancelToken) -> Result<CompileResponse, String> {
+    let mode = match req.mode.as_str() {
+        "aot" => CompilationMode::AOT,
+        "jit" => CompilationMode::JIT,
+        other => return Err(format!("Invalid mode '{}', use 'aot' or 'jit'", other)),
+    };
+    let opt_level = match req.opt_level {
+        0 => OptimizationLevel::None,
+        1 => OptimizationLevel::Basic,
+        2 => OptimizationLevel::Standard,
+        _ => OptimizationLevel::Aggressive,
+    };
+    let backend = match &req.backend {
+        Some(name) => name.parse::<Backend>()?,
+        None => Backend::Auto,
+    };
+
+    let mut compiler = Compiler::new(opt_level);
+    compiler.set_backend(backend);
+    compiler.set_sandbox(sandbox);
+
+    let mut response = CompileResponse {
+        success: true,
+        mode: None,
+        backend: None,
+        compile_time_ms: 0,
+        definitions_count: 0,
+        stack: Vec::new(),
+        warnings: Vec::new(),
+        diagnostics: Vec::new(),
+        object_code: None,
+    };
+
+    let outcome = if req.verify_only {
+        compiler.verify_string(&req.source).map(|result| {
+            response.compile_time_ms = result.verify_time_ms;
+            response.definitions_count = result.definitions_count;
+        })
+    } else {
+        compiler.compile_string_cancellable(&req.source, mode, cancel).map(|result| {
+            response.mode = Some(format!("{:?}", result.mode));
+            response.backend = Some(format!("{:?}", result.backend));
+            response.compile_time_ms = result.compile_time_ms;
+            response.definitions_count = result.stats.definitions_count;
+            response.stack = result.stack;
+            response.warnings = result.warnings;
+            if req.emit_object {
+                response.object_code = encode_object(compiler.compile_object(&req.source), &mut response.warnings);
+            }
+        })
+    };
+
+    if let Err(e) = outcome {
+        let file = req.file.clone().unwrap_or_else(|| "<input>".to_string());
+        response.success = false;
+        response.diagnostics.push(to_structured_error(&e, false).with_source(file, &req.source));
+    }
+    Ok(response)
+}
+
+/// Base64 encode the object file built from the request's source
+fn encode_object(object: crate::Result<Vec<u8>>, warnings: &mut Vec<String>) -> Option<String> {
+    use base64::Engine;
+
+    match object {
+        Ok(bytes) => Some(base64::engine::general_purpose::STANDARD.encode(bytes)),
+        Err(e) => {
+            warnings.push(format!("no object code: {}", e));
+            None
+        }
+    }
+}
+
+/// Run `job` on the blocking pool under the server's compile limit
+///
+/// On timeout the job's token is cancelled, which stops a JIT run at its
+/// next loop iteration. The compile slot is held until the work actually
+/// finishes, even after the caller stops waiting, so a build that is past
+/// its timeout still counts against the limit until it stops.
+#[cfg(feature = "server")]
+async fn run_limited<T, F>(state: &ServerState, timeout: Duration, job: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&CancelToken) -> T + Send + 'static,
+{
+    let slots = state.compile_slots.clone();
+    let cancel = CancelToken::new();
+    let token = cancel.clone();
+    let work = async move {
+        let permit = slots.acquire_owned().await.ok()?;
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            job(&token)
+        })
+        .await
+        .ok()
+    };
+    let finished = tokio::time::timeout(timeout, work).await.ok().flatten();
+    if finished.is_none() {
+        cancel.cancel();
+    }
+    finished
+}
+
+#[cfg(feature = "server")]
+fn bad_request(error: String) -> (StatusCode, Json<ErrorResponse>) {
+    (StatusCode::BAD_REQUEST, Json(ErrorResponse { error }))
+}
+
+/// Reject requests the server's access policy does not admit
+///
+/// Keys are read from `Authorization: Bearer` or `X-API-Key`.
+#[cfg(feature = "server")]
+pub async fn access_guard(
+    State(state): State<ServerState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let headers = request.headers();
+    let text = |name| headers.get(name).and_then(|value: &header::HeaderValue| value.to_str().ok());
+    let body_bytes = text(header::CONTENT_LENGTH)
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    let presented = credential(text(header::AUTHORIZATION), text(header::HeaderName::from_static("x-api-key")));
+
+    match state.access.check(presented, &client.ip().to_string(), body_bytes) {
+        Ok(()) => next.run(request).await,
+        Err(denied) => {
+            let error = Json(ErrorResponse { error: denied.to_string() });
+            match denied {
+                AccessDenied::Unauthorized => {
+                    (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer".to_string())], error).into_response()
+                }
+                AccessDenied::RateLimited { retry_after } => {
+                    let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+                    (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, seconds.to_string())], error).into_response()
+                }
+                AccessDenied::TooLarge { .. } => (StatusCode::PAYLOAD_TOO_LARGE, error).into_response(),
+            }
+        }
+    }
+}
+
 #[cfg(feature = "server")]
 pub async fn health() -> Json<HealthResponse> {
     Json(HealthResponse {
@@ -110,3 +394,223 @@ pub async fn compose(
         )),
     }
 }
+
+#[cfg(feature = "server")]
+pub async fn infer_batch_handler(
+    State(state): State<ServerState>,
+    Json(req): Json<BatchInferRequest>,
+) -> Result<Json<BatchInferResponse>, (StatusCode, Json<ErrorResponse>)> {
+    if req.codes.len() > state.config.max_batch_size {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Json(ErrorResponse {
+                error: format!("Batch of {} exceeds the limit of {}", req.codes.len(), state.config.max_batch_size),
+            }),
+        ));
+    }
+
+    let start = Instant::now();
+    let results = infer_batch(&state.api, &req.codes);
+    Ok(Json(BatchInferResponse {
+        results,
+        latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+    }))
+}
+
+#[cfg(feature = "server")]
+pub async fn compile(
+    State(state): State<ServerState>,
+    Json(req): Json<CompileRequest>,
+) -> Result<Json<CompileResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let timeout = req.timeout(state.config.request_timeout);
+    let sandbox = req.sandbox(state.config.sandbox);
+    match run_limited(&state, timeout, move |cancel| compile_source(&req, sandbox, cancel)).await {
+        Some(Ok(response)) => Ok(Json(response)),
+        Some(Err(e)) => Err(bad_request(e)),
+        None => Err((
+            StatusCode::GATEWAY_TIMEOUT,
+            Json(ErrorResponse {
+                error: format!("Compilation exceeded {}ms; submit it to /jobs/compile instead", timeout.as_millis()),
+            }),
+        )),
+    }
+}
+
+#[cfg(feature = "server")]
+pub async fn submit_job(
+    State(state): State<ServerState>,
+    Json(req): Json<CompileRequest>,
+) -> Result<(StatusCode, Json<JobResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let Some(id) = state.jobs.submit() else {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ErrorResponse { error: "Too many pending jobs".to_string() }),
+        ));
+    };
+
+    let task_state = state.clone();
+    tokio::spawn(async move {
+        let jobs = task_state.jobs.clone();
+        let timeout = req.timeout(task_state.config.job_timeout);
+        let sandbox = req.sandbox(task_state.config.sandbox);
+        let status = run_limited(&task_state, timeout, move |cancel| {
+            jobs.update(id, JobStatus::Running);
+            compile_source(&req, sandbox, cancel)
+        })
+        .await;
+        let status = match status {
+            Some(Ok(result)) => JobStatus::Done { result },
+            Some(Err(error)) => JobStatus::Failed { error },
+            None => JobStatus::TimedOut,
+        };
+        task_state.jobs.update(id, status);
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(JobResponse { id, status: JobStatus::Queued })))
+}
+
+#[cfg(feature = "server")]
+pub async fn job_status(
+    State(state): State<ServerState>,
+    Path(id): Path<JobId>,
+) -> Result<Json<JobResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match state.jobs.get(id) {
+        Some(status) => Ok(Json(JobResponse { id, status })),
+        None => Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse { error: format!("No job {}", id) }),
+        )),
+    }
+}
+
+/// Live diagnostics: each text message holds edits, each reply an update
+#[cfg(feature = "server")]
+pub async fn live(State(api): State<Arc<InferenceAPI>>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| live_session(socket, api))
+}
+
+#[cfg(feature = "server")]
+async fn live_session(mut socket: WebSocket, api: Arc<InferenceAPI>) {
+    let mut document = LiveDocument::with_api((*api).clone());
+    while let Some(Ok(message)) = socket.recv().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        let reply = serde_json::from_str::<LiveRequest>(&text)
+            .map_err(|e| e.to_string())
+            .and_then(|request| document.apply(&request.edits));
+        let reply = match reply {
+            Ok(update) => serde_json::to_string(&update),
+            Err(error) => serde_json::to_string(&ErrorResponse { error }),
+        };
+        let Ok(reply) = reply else {
+            break;
+        };
+        if socket.send(Message::Text(reply)).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(source: &str) -> CompileRequest {
+        serde_json::from_value(serde_json::json!({ "source": source })).unwrap()
+    }
+
+    #[test]
+    fn test_compile_source_reports_errors_as_diagnostics() {
+        let mut req = request(": broken 1 +");
+        req.verify_only = true;
+        let response = compile_source(&req, None, &CancelToken::new()).unwrap();
+        assert!(!response.success);
+        assert_eq!(response.diagnostics.len(), 1);
+
+        req.source = ": square dup * ;".to_string();
+        let response = compile_source(&req, None, &CancelToken::new()).unwrap();
+        assert!(response.success);
+        assert_eq!(response.definitions_count, 1);
+    }
+
+    #[test]
+    fn test_cancelled_jit_runs_report_a_diagnostic() {
+        let mut req = request(": spin ( n -- n ) begin 1 + dup 0 < until ; 0 spin");
+        req.mode = "jit".to_string();
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let response = compile_source(&req, None, &cancel).unwrap();
+        assert!(!response.success);
+        assert_eq!(response.diagnostics[0].code, "E9004");
+
+        req.timeout_ms = Some(50);
+        assert_eq!(req.timeout(Duration::from_secs(10)), Duration::from_millis(50));
+        assert_eq!(req.timeout(Duration::from_millis(10)), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn test_sandboxed_requests() {
+        let mut req = request(": spin ( n -- n ) begin 1 + dup 0 < until ; 0 spin");
+        req.mode = "jit".to_string();
+        req.fuel = Some(1000);
+        let sandbox = req.sandbox(None);
+        assert_eq!(sandbox, Some(Sandbox { fuel: Some(1000), ..Sandbox::default() }));
+        let response = compile_source(&req, sandbox, &CancelToken::new()).unwrap();
+        assert_eq!(response.diagnostics[0].code, "E9005");
+
+        let mut req = request(": run system ;");
+        req.mode = "jit".to_string();
+        assert_eq!(req.sandbox(None), None);
+        let server = Some(Sandbox { fuel: Some(10), memory: Some(4096), ..Sandbox::default() });
+        let response = compile_source(&req, req.sandbox(server), &CancelToken::new()).unwrap();
+        assert_eq!(response.diagnostics[0].code, "E1005");
+    }
+
+    #[test]
+    fn test_emit_object_returns_an_object_file() {
+        use base64::Engine;
+
+        if std::process::Command::new("as").arg("--version").output().is_err() {
+            return;
+        }
+        let mut req = request(": square ( n -- n ) dup * ;\n3 square");
+        req.emit_object = true;
+        for mode in ["aot", "jit"] {
+            req.mode = mode.to_string();
+            let response = compile_source(&req, None, &CancelToken::new()).unwrap();
+            assert!(response.success, "{:?}", response.diagnostics);
+            let object = base64::engine::general_purpose::STANDARD.decode(response.object_code.unwrap()).unwrap();
+            let magic = &object[..4];
+            assert!(
+                magic == b"\x7fELF" || magic == [0xcf, 0xfa, 0xed, 0xfe],
+                "{} object starts {:02x?}", mode, magic
+            );
+        }
+    }
+
+    #[test]
+    fn test_compile_source_rejects_bad_options() {
+        let mut req = request("1 2 +");
+        req.mode = "interpret".to_string();
+        assert!(compile_source(&req, None, &CancelToken::new()).is_err());
+
+        let mut req = request("1 2 +");
+        req.backend = Some("gcc".to_string());
+        assert!(compile_source(&req, None, &CancelToken::new()).is_err());
+    }
+
+    #[test]
+    fn test_infer_batch_keeps_order() {
+        let api = InferenceAPI::new();
+        let codes = vec!["dup *".to_string(), "drop".to_string(), "1 2".to_string()];
+        let deltas: Vec<i32> = infer_batch(&api, &codes)
+            .iter()
+            .map(|result| result.stack_depth_delta)
+            .collect();
+        assert_eq!(deltas, vec![0, -1, 2]);
+    }
+}
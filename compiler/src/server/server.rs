@@ -1,8 +1,16 @@
 //! Async verification server implementation
 
-use crate::inference::InferenceAPI;
+use super::jobs::JobStore;
+use crate::access::{AccessControl, AccessPolicy};
+use crate::inference::{EffectDatabase, InferenceAPI};
+use crate::Sandbox;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use axum::extract::FromRef;
+use tokio::sync::Semaphore;
 
 /// Server configuration
 #[derive(Debug, Clone)]
@@ -10,30 +18,89 @@ pub struct ServerConfig {
     pub host: String,
     pub port: u16,
     pub workers: usize,
+    /// Longest a synchronous `/compile` request may take
+    pub request_timeout: Duration,
+    /// Longest a background compile job may take
+    pub job_timeout: Duration,
+    /// Compilations allowed to run at once, across requests and jobs
+    pub max_concurrent_compiles: usize,
+    /// Most snippets accepted by one `/infer/batch` request
+    pub max_batch_size: usize,
+    /// Most jobs remembered for polling
+    pub max_jobs: usize,
+    /// API keys, rate limit and request size limit; `/health` is exempt
+    pub access: AccessPolicy,
+    /// Limits every compile request is held to; a request may ask for
+    /// tighter ones, or for a sandbox when the server has none
+    pub sandbox: Option<Sandbox>,
+    /// Stack-effect database inference requests start from; definitions
+    /// they record are kept in memory only
+    pub effect_database: Option<PathBuf>,
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
+        let workers = num_cpus::get();
         Self {
             host: "127.0.0.1".to_string(),
             port: 8080,
-            workers: num_cpus::get(),
+            workers,
+            request_timeout: Duration::from_secs(10),
+            job_timeout: Duration::from_secs(300),
+            max_concurrent_compiles: workers,
+            max_batch_size: 1000,
+            max_jobs: 1024,
+            access: AccessPolicy::default(),
+            sandbox: None,
+            effect_database: None,
+        }
+    }
+}
+
+/// State shared by the route handlers
+#[derive(Clone)]
+pub struct ServerState {
+    pub config: Arc<ServerConfig>,
+    pub api: Arc<InferenceAPI>,
+    pub jobs: Arc<JobStore>,
+    pub compile_slots: Arc<Semaphore>,
+    pub access: Arc<AccessControl>,
+}
+
+impl ServerState {
+    /// Fresh state for `config`
+    pub fn new(config: ServerConfig) -> Self {
+        Self {
+            api: Arc::new(match &config.effect_database {
+                Some(path) => InferenceAPI::with_database(Arc::new(RwLock::new(EffectDatabase::load(path)))),
+                None => InferenceAPI::new(),
+            }),
+            jobs: Arc::new(JobStore::new(config.max_jobs)),
+            compile_slots: Arc::new(Semaphore::new(config.max_concurrent_compiles.max(1))),
+            access: Arc::new(AccessControl::new(config.access.clone())),
+            config: Arc::new(config),
         }
     }
 }
 
+impl FromRef<ServerState> for Arc<InferenceAPI> {
+    fn from_ref(state: &ServerState) -> Self {
+        state.api.clone()
+    }
+}
+
 /// Real-time verification server
 pub struct VerificationServer {
     config: ServerConfig,
-    api: Arc<InferenceAPI>,
+    state: ServerState,
 }
 
 impl VerificationServer {
     /// Create a new verification server
     pub fn new(config: ServerConfig) -> Self {
         Self {
+            state: ServerState::new(config.clone()),
             config,
-            api: Arc::new(InferenceAPI::new()),
         }
     }
 
@@ -46,31 +113,55 @@ impl VerificationServer {
         println!("Fast Forth Verification Server starting...");
         println!("  Address: {}", addr);
         println!("  Workers: {}", self.config.workers);
+        println!("  Concurrent compiles: {}", self.config.max_concurrent_compiles);
+        if !self.config.access.api_keys.is_empty() {
+            println!("  API keys: {} configured", self.config.access.api_keys.len());
+        }
+        if let Some(limit) = self.config.access.rate_limit {
+            println!("  Rate limit: {} requests per {:?}", limit.requests, limit.per);
+        }
         println!("\nEndpoints:");
         println!("  POST /verify       - Verify code against stack effect");
         println!("  POST /infer        - Infer stack effect from code");
         println!("  POST /compose      - Verify composition of words");
+        println!("  POST /infer/batch  - Infer stack effects of many snippets");
+        println!("  POST /compile      - Compile source, returning diagnostics");
+        println!("  POST /jobs/compile - Queue a compilation, returning a job id");
+        println!("  GET  /jobs/:id     - Poll a queued compilation");
+        println!("  GET  /live         - WebSocket: push diagnostics as a document is edited");
         println!("  GET  /health       - Health check");
         println!();
 
         #[cfg(feature = "server")]
         {
             use axum::{
+                extract::DefaultBodyLimit,
+                middleware,
                 routing::{get, post},
                 Router,
             };
             use super::routes;
 
-            let app = Router::new()
-                .route("/health", get(routes::health))
+            let max_request_bytes = self.config.access.max_request_bytes;
+            let api = Router::new()
                 .route("/verify", post(routes::verify))
                 .route("/infer", post(routes::infer))
                 .route("/compose", post(routes::compose))
-                .with_state(self.api);
+                .route("/infer/batch", post(routes::infer_batch_handler))
+                .route("/compile", post(routes::compile))
+                .route("/jobs/compile", post(routes::submit_job))
+                .route("/jobs/:id", get(routes::job_status))
+                .route("/live", get(routes::live))
+                .route_layer(middleware::from_fn_with_state(self.state.clone(), routes::access_guard));
+            let app = Router::new()
+                .route("/health", get(routes::health))
+                .merge(api)
+                .layer(DefaultBodyLimit::max(max_request_bytes))
+                .with_state(self.state);
 
             let listener = tokio::net::TcpListener::bind(addr).await?;
             println!("✓ Server listening on {}", addr);
-            axum::serve(listener, app).await?;
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
         }
 
         #[cfg(not(feature = "server"))]
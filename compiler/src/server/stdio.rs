@@ -0,0 +1,425 @@
+//! Newline-delimited JSON-RPC over stdin/stdout
+//!
+//! Lets an agent keep one compiler process warm instead of spawning
+//! `fastforth` per query. Each input line is a JSON-RPC 2.0 request, and
+//! each request gets exactly one response line:
+//!
+//! ```text
+//! -> {"jsonrpc":"2.0","id":1,"method":"infer","params":{"code":"dup *"}}
+//! <- {"jsonrpc":"2.0","id":1,"result":{"valid":true,"inferred_effect":"( a -- b )",...}}
+//! ```
+//!
+//! Methods: `compile`, `infer`, `verify-effect`, `compose`, `hover`,
+//! `diff`, `generate`. Definitions sent to the inference methods are
+//! recorded, so `hover` on a word gives its effect, and later code may call
+//! it.
+
+use crate::codegen::SpecCodeGenerator;
+use crate::errors::to_structured_error;
+use crate::inference::InferenceAPI;
+use crate::semantic_diff::SemanticDiffer;
+use crate::spec::SpecDocument;
+use crate::{CancelToken, CompilationMode, Compiler, Sandbox};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+
+/// Invalid JSON was received
+const PARSE_ERROR: i64 = -32700;
+/// The JSON sent is not a valid request object
+const INVALID_REQUEST: i64 = -32600;
+/// The method does not exist
+const METHOD_NOT_FOUND: i64 = -32601;
+/// Invalid method parameters
+const INVALID_PARAMS: i64 = -32602;
+/// The method ran but could not produce a result
+const METHOD_FAILED: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct CompileParams {
+    source: String,
+    #[serde(default = "default_mode")]
+    mode: String,
+    #[serde(default)]
+    verify_only: bool,
+    /// File name reported in diagnostics
+    #[serde(default)]
+    file: Option<String>,
+    #[serde(default)]
+    suggest_fixes: bool,
+    /// Stop a JIT run after this many milliseconds
+    #[serde(default)]
+    timeout_ms: Option<u64>,
+    /// Compile the source as untrusted, in a sandbox
+    #[serde(default)]
+    sandbox: bool,
+    /// Word calls and loop iterations a sandboxed JIT run may make
+    #[serde(default)]
+    fuel: Option<u64>,
+    /// Most heap a sandboxed JIT run may allocate, in bytes
+    #[serde(default)]
+    max_memory: Option<u64>,
+}
+
+impl CompileParams {
+    /// Sandbox the source is compiled in under the server's sandbox
+    fn sandbox(&self, server: Option<Sandbox>) -> Option<Sandbox> {
+        Sandbox { fuel: self.fuel, timeout: None, memory: self.max_memory }.for_request(server, self.sandbox)
+    }
+}
+
+fn default_mode() -> String {
+    "jit".to_string()
+}
+
+#[derive(Deserialize)]
+struct InferParams {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct VerifyEffectParams {
+    code: String,
+    effect: String,
+}
+
+#[derive(Deserialize)]
+struct ComposeParams {
+    words: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct HoverParams {
+    word: String,
+}
+
+#[derive(Deserialize)]
+struct DiffParams {
+    old: String,
+    new: String,
+}
+
+#[derive(Deserialize)]
+struct GenerateParams {
+    /// Word or program specification as a JSON object, or as a string holding one
+    spec: Value,
+    #[serde(default = "default_true")]
+    tests: bool,
+    #[serde(default = "default_true")]
+    provenance: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Error half of a JSON-RPC response
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+type RpcResult = std::result::Result<Value, RpcError>;
+
+/// JSON-RPC server reading requests from a line-oriented stream
+pub struct StdioServer {
+    compiler: Compiler,
+    api: InferenceAPI,
+    differ: SemanticDiffer,
+}
+
+impl StdioServer {
+    /// Serve requests with an already configured compiler
+    pub fn new(compiler: Compiler) -> Self {
+        Self {
+            compiler,
+            api: InferenceAPI::new(),
+            differ: SemanticDiffer::new(),
+        }
+    }
+
+    /// Infer with `api`, and the stack-effect database it shares
+    pub fn with_inference(mut self, api: InferenceAPI) -> Self {
+        self.api = api;
+        self
+    }
+
+    pub fn inference(&self) -> &InferenceAPI {
+        &self.api
+    }
+
+    /// Answer requests from `input` until it is closed
+    ///
+    /// Blank lines are ignored. Each response is flushed as soon as it is
+    /// written so a client can pipeline requests.
+    pub fn serve<R: BufRead, W: Write>(&self, input: R, mut output: W) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            writeln!(output, "{}", self.handle_line(&line))?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Answer a single request line
+    pub fn handle_line(&self, line: &str) -> Value {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(value) => value,
+            Err(e) => return response(Value::Null, Err(RpcError::new(PARSE_ERROR, e.to_string()))),
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let request: Request = match serde_json::from_value(request) {
+            Ok(request) => request,
+            Err(e) => return response(id, Err(RpcError::new(INVALID_REQUEST, e.to_string()))),
+        };
+
+        let result = self.dispatch(&request.method, request.params);
+        response(request.id, result)
+    }
+
+    fn dispatch(&self, method: &str, params: Value) -> RpcResult {
+        match method {
+            "compile" => self.compile(parse_params(params)?),
+            "infer" => self.infer(parse_params(params)?),
+            "verify-effect" => self.verify_effect(parse_params(params)?),
+            "compose" => self.compose(parse_params(params)?),
+            "hover" => self.hover(parse_params(params)?),
+            "diff" => self.diff(parse_params(params)?),
+            "generate" => self.generate(parse_params(params)?),
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+        }
+    }
+
+    /// Compile or verify source; compile errors are a result, not an RPC error
+    fn compile(&self, params: CompileParams) -> RpcResult {
+        let mode = match params.mode.as_str() {
+            "aot" => CompilationMode::AOT,
+            "jit" => CompilationMode::JIT,
+            other => {
+                return Err(RpcError::new(
+                    INVALID_PARAMS,
+                    format!("Invalid mode '{}', use 'aot' or 'jit'", other),
+                ))
+            }
+        };
+
+        let outcome = if params.verify_only {
+            self.compiler.verify_string(&params.source).map(|result| {
+                json!({
+                    "status": "success",
+                    "definitions_count": result.definitions_count,
+                    "verify_time_ms": result.verify_time_ms,
+                })
+            })
+        } else {
+            let cancel = CancelToken::new();
+            let _deadline = params.timeout_ms.map(|ms| cancel.cancel_after(Duration::from_millis(ms)));
+            let compiled = match params.sandbox(self.compiler.sandbox()) {
+                Some(sandbox) => self.compiler.compile_string_sandboxed(&params.source, mode, sandbox, &cancel),
+                None => self.compiler.compile_string_cancellable(&params.source, mode, &cancel),
+            };
+            compiled.map(|result| {
+                json!({
+                    "status": "success",
+                    "mode": format!("{:?}", result.mode),
+                    "backend": format!("{:?}", result.backend),
+                    "warnings": result.warnings,
+                    "compile_time_ms": result.compile_time_ms,
+                    "definitions_count": result.stats.definitions_count,
+                    "stack": result.stack,
+                    "output_path": result.output_path,
+                })
+            })
+        };
+
+        Ok(outcome.unwrap_or_else(|e| {
+            let file = params.file.unwrap_or_else(|| "<input>".to_string());
+            let diagnostic = to_structured_error(&e, params.suggest_fixes).with_source(file, &params.source);
+            json!({
+                "status": "error",
+                "error": e.to_string(),
+                "diagnostic": diagnostic,
+            })
+        }))
+    }
+
+    fn infer(&self, params: InferParams) -> RpcResult {
+        let result = self.api.infer(&params.code).map_err(|e| RpcError::new(METHOD_FAILED, e))?;
+        to_value(result)
+    }
+
+    fn verify_effect(&self, params: VerifyEffectParams) -> RpcResult {
+        let result = self
+            .api
+            .verify_effect(&params.code, &params.effect)
+            .map_err(|e| RpcError::new(METHOD_FAILED, e))?;
+        to_value(result)
+    }
+
+    fn compose(&self, params: ComposeParams) -> RpcResult {
+        let words: Vec<&str> = params.words.iter().map(String::as_str).collect();
+        let result = self.api.compose(&words).map_err(|e| RpcError::new(METHOD_FAILED, e))?;
+        to_value(result)
+    }
+
+    /// Recorded effect of a word, or null for a word never recorded
+    fn hover(&self, params: HoverParams) -> RpcResult {
+        Ok(match self.api.word_effect(&params.word) {
+            Some(entry) => json!({
+                "word": params.word,
+                "effect": entry.effect.to_string(),
+                "declared": entry.declared,
+            }),
+            None => Value::Null,
+        })
+    }
+
+    fn diff(&self, params: DiffParams) -> RpcResult {
+        let result = self
+            .differ
+            .diff_sources(&params.old, &params.new)
+            .map_err(|e| RpcError::new(METHOD_FAILED, e.to_string()))?;
+        to_value(result)
+    }
+
+    fn generate(&self, params: GenerateParams) -> RpcResult {
+        let document = match params.spec {
+            Value::String(json) => SpecDocument::from_json(&json)
+                .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?,
+            value => SpecDocument::from_value(value)
+                .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid specification: {}", e)))?,
+        };
+        let code = SpecCodeGenerator::new()
+            .with_tests(params.tests)
+            .with_provenance(params.provenance)
+            .generate_document(&document)
+            .map_err(|e| RpcError::new(METHOD_FAILED, e.to_string()))?;
+        Ok(json!({ "code": code }))
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> std::result::Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn to_value<T: serde::Serialize>(value: T) -> RpcResult {
+    serde_json::to_value(value).map_err(|e| RpcError::new(METHOD_FAILED, e.to_string()))
+}
+
+fn response(id: Value, result: RpcResult) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": error.code, "message": error.message },
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OptimizationLevel;
+
+    fn server() -> StdioServer {
+        StdioServer::new(Compiler::new(OptimizationLevel::Basic))
+    }
+
+    #[test]
+    fn test_requests_get_one_response_line_each() {
+        let input = concat!(
+            r#"{"jsonrpc":"2.0","id":1,"method":"infer","params":{"code":"dup *"}}"#,
+            "\n\n",
+            r#"{"jsonrpc":"2.0","id":"two","method":"compile","params":{"source":": sq dup * ;","verify_only":true}}"#,
+            "\n",
+        );
+        let mut output = Vec::new();
+        server().serve(input.as_bytes(), &mut output).unwrap();
+
+        let lines: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["id"], 1);
+        assert_eq!(lines[0]["result"]["valid"], true);
+        assert_eq!(lines[1]["id"], "two");
+        assert_eq!(lines[1]["result"]["status"], "success");
+        assert_eq!(lines[1]["result"]["definitions_count"], 1);
+    }
+
+    #[test]
+    fn test_compile_error_carries_diagnostic() {
+        let reply = server().handle_line(
+            r#"{"id":3,"method":"compile","params":{"source":": broken 1 +","verify_only":true}}"#,
+        );
+        assert_eq!(reply["result"]["status"], "error");
+        assert!(reply["result"]["diagnostic"]["code"].is_string());
+    }
+
+    #[test]
+    fn test_jit_runs_stop_at_their_timeout() {
+        let reply = server().handle_line(
+            r#"{"id":6,"method":"compile","params":{"source":": spin ( n -- n ) begin 1 + dup 0 < until ; 0 spin","timeout_ms":20}}"#,
+        );
+        assert_eq!(reply["result"]["status"], "error");
+        assert_eq!(reply["result"]["diagnostic"]["code"], "E9004");
+    }
+
+    #[test]
+    fn test_sandboxed_runs_stop_when_out_of_fuel() {
+        let reply = server().handle_line(
+            r#"{"id":7,"method":"compile","params":{"source":": spin ( n -- n ) begin 1 + dup 0 < until ; 0 spin","fuel":500}}"#,
+        );
+        assert_eq!(reply["result"]["status"], "error");
+        assert_eq!(reply["result"]["diagnostic"]["code"], "E9005");
+    }
+
+    #[test]
+    fn test_hover_shows_recorded_effects() {
+        let server = server();
+        server.handle_line(r#"{"id":8,"method":"infer","params":{"code":": sum3 + + ;"}}"#);
+        let reply = server.handle_line(r#"{"id":9,"method":"hover","params":{"word":"sum3"}}"#);
+        assert_eq!(reply["result"]["effect"], "( n n n -- n )");
+        assert_eq!(server.handle_line(r#"{"id":10,"method":"hover","params":{"word":"nope"}}"#)["result"], Value::Null);
+
+        let reply = server.handle_line(r#"{"id":11,"method":"compose","params":{"words":["sum3","sum3"]}}"#);
+        assert_eq!(reply["result"]["effect"], "( n n n n n -- n )");
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        let server = server();
+        assert_eq!(server.handle_line("{not json")["error"]["code"], PARSE_ERROR);
+        assert_eq!(server.handle_line(r#"{"id":4,"method":"fly"}"#)["error"]["code"], METHOD_NOT_FOUND);
+
+        let reply = server.handle_line(r#"{"id":5,"method":"infer","params":{}}"#);
+        assert_eq!(reply["id"], 5);
+        assert_eq!(reply["error"]["code"], INVALID_PARAMS);
+    }
+}
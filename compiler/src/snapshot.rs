@@ -0,0 +1,241 @@
+//! Trap Snapshots
+//!
+//! What a JIT run was doing when its compiled code faulted. Guarded runs,
+//! which every run of top-level code is and every call under a
+//! [`CancelToken`](crate::CancelToken), survive a fault such as a trap on
+//! division by zero or a bad memory access, and fail with
+//! [`CompileError::Trapped`] carrying a [`Snapshot`]:
+//! - The signal and what it means
+//! - The word that faulted, the last one executed, with its location
+//! - The return stack: the words it was called through, recovered from the
+//!   frame pointer chain
+//! - The dictionary: every compiled word with its code address and size,
+//!   and its provenance when the source carries any
+//! - The data stack, if the run was recorded (see [`crate::recording`]):
+//!   JIT code keeps the stack in registers, so the cells the faulting word
+//!   took are all that is known of it
+//!
+//! [`Snapshot::write`] saves it as JSON, which `fastforth run` does next to
+//! the program and names in its error message.
+
+use crate::error::{CompileError, Result};
+use crate::pipeline::JitProgram;
+use crate::provenance::extraction::extract_records;
+use crate::provenance::ProvenanceMetadata;
+use crate::recording::Event;
+use crate::runtime_ffi::{forth_last_trap, ForthTrap};
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+const SIGILL: i64 = 4;
+const SIGFPE: i64 = 8;
+const SIGSEGV: i64 = 11;
+#[cfg(target_os = "macos")]
+const SIGBUS: i64 = 10;
+#[cfg(not(target_os = "macos"))]
+const SIGBUS: i64 = 7;
+
+/// State of a run when its compiled code faulted
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    /// Name of the signal the fault raised
+    pub signal: String,
+    /// What the fault most likely was
+    pub error: String,
+    /// The address a memory fault touched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fault_address: Option<String>,
+    /// The word that faulted, if it was compiled code
+    pub word: Option<String>,
+    /// Source line of the code that faulted, from the source map
+    pub line: Option<usize>,
+    /// Words the faulting word was called through, innermost first
+    pub return_stack: Vec<Frame>,
+    /// Top of the data stack on entry to the faulting word, deepest first,
+    /// when the run was recorded
+    pub data_stack: Option<Vec<i64>>,
+    /// Compiled words of the program
+    pub dictionary: Vec<DictionaryEntry>,
+    /// Where [`write`](Self::write) saved the snapshot
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    /// The faulting pc, then the return addresses found
+    #[serde(skip)]
+    addresses: Vec<usize>,
+}
+
+/// A word on the return stack
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Frame {
+    pub word: String,
+    /// Source line of the call, from the source map
+    pub line: Option<usize>,
+    /// Return address into the word
+    pub address: String,
+}
+
+/// A compiled word
+#[derive(Debug, Clone, Serialize)]
+pub struct DictionaryEntry {
+    pub name: String,
+    pub address: String,
+    pub size: usize,
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<ProvenanceMetadata>,
+}
+
+impl Snapshot {
+    /// Snapshot of the last fault a guarded call on this thread caught,
+    /// before it is resolved against the program
+    pub(crate) fn from_last_trap() -> Self {
+        let mut trap = ForthTrap::default();
+        unsafe { forth_last_trap(&mut trap) };
+
+        let signal = trap.signal as i64;
+        let (name, error) = match signal {
+            SIGILL => ("SIGILL", "trap: division by zero or an integer overflow"),
+            SIGFPE => ("SIGFPE", "arithmetic exception: division by zero or an integer overflow"),
+            SIGSEGV => ("SIGSEGV", "invalid memory access"),
+            SIGBUS => ("SIGBUS", "misaligned or unmapped memory access"),
+            _ => ("unknown", "fault"),
+        };
+        let depth = (trap.depth.max(0) as usize).min(trap.returns.len());
+        let addresses = std::iter::once(trap.pc as usize)
+            .filter(|&pc| pc != 0)
+            .chain(trap.returns[..depth].iter().map(|&address| address as usize))
+            .collect();
+        Snapshot {
+            signal: if name == "unknown" { format!("signal {}", signal) } else { name.to_string() },
+            error: error.to_string(),
+            fault_address: matches!(signal, SIGSEGV | SIGBUS).then(|| format!("{:#x}", trap.address as usize)),
+            word: None,
+            line: None,
+            return_stack: Vec::new(),
+            data_stack: None,
+            dictionary: Vec::new(),
+            path: None,
+            addresses,
+        }
+    }
+
+    /// Name the words of the addresses and list the dictionary of `program`
+    pub(crate) fn resolve(&mut self, program: &JitProgram) {
+        let map = program.source_map();
+
+        // The pc is in the faulting word; a return address may be one past
+        // its call, which can be the end of the caller
+        let mut frames = self.addresses.iter().enumerate().filter_map(|(index, &address)| {
+            let lookup = if index == 0 { address } else { address.saturating_sub(1) };
+            map.find(lookup as u64).map(|range| Frame {
+                word: range.word.clone(),
+                line: range.line_at(lookup as u64),
+                address: format!("{:#x}", address),
+            })
+        });
+        if let Some(innermost) = frames.next() {
+            self.word = Some(innermost.word);
+            self.line = innermost.line;
+        }
+        self.return_stack = frames.collect();
+        self.dictionary = map
+            .ranges()
+            .iter()
+            .map(|range| DictionaryEntry {
+                name: range.word.clone(),
+                address: format!("{:#x}", range.start),
+                size: range.size as usize,
+                line: range.line,
+                provenance: None,
+            })
+            .collect();
+    }
+
+    /// Take the data stack from the events of a recorded run: the cells
+    /// the faulting word took when last entered
+    pub(crate) fn add_recording(&mut self, events: &[Event]) {
+        let Some(word) = &self.word else {
+            return;
+        };
+        self.data_stack = events
+            .iter()
+            .rev()
+            .find(|event| !event.exit && event.word == *word)
+            .map(|event| event.cells.clone());
+    }
+
+    /// Attach the provenance the program's source gives its words
+    pub(crate) fn add_provenance(&mut self, source: &str) {
+        let Ok(records) = extract_records(source) else {
+            return;
+        };
+        for entry in &mut self.dictionary {
+            entry.provenance = records.iter().find(|record| record.word == entry.name).map(|record| record.metadata.clone());
+        }
+    }
+
+    /// Save the snapshot as JSON at `path`
+    pub fn write(&mut self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| CompileError::InternalError(format!("cannot serialize the trap snapshot: {}", e)))?;
+        std::fs::write(path, json).map_err(|e| CompileError::IoError(path.to_path_buf(), e))?;
+        self.path = Some(path.to_path_buf());
+        Ok(())
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.error, self.signal)?;
+        match (&self.word, self.line) {
+            (Some(word), Some(line)) => write!(f, " in '{}' (line {})", word, line)?,
+            (Some(word), None) => write!(f, " in '{}'", word)?,
+            (None, _) => {}
+        }
+        if let Some(address) = &self.fault_address {
+            write!(f, " at {}", address)?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, "; snapshot written to {}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompilationMode, Compiler, OptimizationLevel};
+
+    #[test]
+    fn test_traps_fail_the_run_with_a_snapshot_of_the_words() {
+        let source = "\\ GENERATED_BY: test-agent\n: divide ( a b -- n ) / ;\n: ratio ( a -- n )\n  0 divide 1 + ;\n7 ratio";
+        let compiler = Compiler::new(OptimizationLevel::None);
+        let Err(CompileError::Trapped(mut snapshot)) = compiler.compile_string(source, CompilationMode::JIT) else {
+            panic!("division by zero should trap");
+        };
+
+        assert_eq!(snapshot.word.as_deref(), Some("divide"));
+        assert_eq!(snapshot.line, Some(2));
+        let callers: Vec<&str> = snapshot.return_stack.iter().map(|frame| frame.word.as_str()).collect();
+        assert_eq!(callers, ["ratio", "main"]);
+        let lines: Vec<Option<usize>> = snapshot.return_stack.iter().map(|frame| frame.line).collect();
+        assert_eq!(lines, [Some(4), Some(5)]);
+        let divide = snapshot.dictionary.iter().find(|entry| entry.name == "divide").unwrap();
+        assert_eq!(divide.provenance.as_ref().map(|p| p.generated_by.as_str()), Some("test-agent"));
+        assert!(snapshot.data_stack.is_none(), "the run was not recorded");
+
+        let path = std::env::temp_dir().join(format!("fastforth-trap-test-{}.json", std::process::id()));
+        snapshot.write(&path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["word"], "divide");
+        assert_eq!(json["return_stack"][0]["word"], "ratio");
+        assert!(snapshot.to_string().ends_with(&format!("snapshot written to {}", path.display())));
+        std::fs::remove_file(&path).unwrap();
+
+        // The process survives, so the compiler can run again
+        let result = compiler.compile_string("6 7 *", CompilationMode::JIT).unwrap();
+        assert_eq!(result.jit_result, Some(42));
+    }
+}
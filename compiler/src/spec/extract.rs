@@ -0,0 +1,420 @@
+//! Specification extraction from existing Forth source
+//!
+//! The reverse of spec-driven generation: every colon definition in a file
+//! becomes a `Specification`. Stack effects come from the declared comment
+//! where there is one and from inference where there is not; inline
+//! `T{ ... -> ... }T` tests that apply a single word to literals become its
+//! test cases; the performance analyzer supplies a complexity estimate; the
+//! definition body is kept verbatim so regenerating the word reproduces it.
+
+use super::{
+    Complexity, Implementation, SpecError, SpecResult, SpecValidator, Specification, StackEffect, StackParameter,
+    StackResult, StackType, TestCase, TestValue,
+};
+use crate::semantic_diff::PerformanceAnalyzer;
+use fastforth_frontend::ast::{self, Definition, SourceLocation, Word};
+use fastforth_frontend::stack_effects::StackEffectInference;
+use fastforth_frontend::type_inference::TypeInference;
+use fastforth_frontend::parse_program;
+use std::collections::HashMap;
+
+/// Result of extracting specifications from a source file
+#[derive(Debug, Clone, Default)]
+pub struct Extraction {
+    /// One validated specification per definition, in source order
+    pub specs: Vec<Specification>,
+    /// Definitions that produced no valid specification, with the reason
+    pub rejected: Vec<(String, String)>,
+    /// Lines of tests that could not be attributed to a single word
+    pub unassigned_tests: Vec<usize>,
+}
+
+/// Extract a specification for every word defined in `source`
+///
+/// Specifications that fail `validator` are reported in
+/// `Extraction::rejected` rather than failing the whole extraction; only
+/// source that does not parse is an error.
+pub fn extract_specs(source: &str, validator: &SpecValidator) -> SpecResult<Extraction> {
+    let program = parse_program(source).map_err(|e| SpecError::SourceError(e.to_string()))?;
+
+    let arities = StackEffectInference::new()
+        .analyze_program(&program)
+        .map_err(|e| SpecError::SourceError(e.to_string()))?;
+    let analyzer = PerformanceAnalyzer::new();
+    let lines: Vec<&str> = source.lines().collect();
+
+    let mut extraction = Extraction::default();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for def in program.compiled_definitions() {
+        let text = DefinitionText::read(&lines, &def.location);
+        let spec = Specification {
+            word: def.name.clone(),
+            description: text.as_ref().and_then(|text| text.description.clone()),
+            stack_effect: stack_effect(def, arities.get(&def.name), text.as_ref()),
+            properties: None,
+            test_cases: None,
+            complexity: Some(complexity(&analyzer, def)),
+            implementation: text.map(|text| Implementation { pattern: None, body: Some(text.body), hints: None }),
+            metadata: None,
+        };
+
+        // A redefinition replaces the earlier word, as it does in Forth
+        match index.get(&def.name) {
+            Some(&i) => extraction.specs[i] = spec,
+            None => {
+                index.insert(def.name.clone(), extraction.specs.len());
+                extraction.specs.push(spec);
+            }
+        }
+    }
+
+    for test in &program.tests {
+        let assigned = harvest_test(test).and_then(|(word, input, output)| {
+            let spec = &mut extraction.specs[*index.get(&word)?];
+            let case = test_case(&spec.stack_effect, &input, &output)?;
+            spec.test_cases.get_or_insert_with(Vec::new).push(case);
+            Some(())
+        });
+        if assigned.is_none() {
+            extraction.unassigned_tests.push(test.location.line);
+        }
+    }
+
+    for spec in std::mem::take(&mut extraction.specs) {
+        match validator.validate(&spec) {
+            Ok(()) => extraction.specs.push(spec),
+            Err(e) => extraction.rejected.push((spec.word, e.to_string())),
+        }
+    }
+    Ok(extraction)
+}
+
+/// Declared effect where the source has one, inferred otherwise
+fn stack_effect(def: &Definition, inferred: Option<&ast::StackEffect>, text: Option<&DefinitionText>) -> StackEffect {
+    let arity = def.stack_effect.as_ref().or(inferred);
+    let (input_count, output_count) = arity.map(|e| (e.inputs.len(), e.outputs.len())).unwrap_or((0, 0));
+
+    // Types come from the declaration, with gaps filled by type inference
+    // when it agrees on the arity
+    let mut inputs: Vec<ast::StackType> = vec![ast::StackType::Unknown; input_count];
+    let mut outputs: Vec<ast::StackType> = vec![ast::StackType::Unknown; output_count];
+    if let Some(declared) = &def.stack_effect {
+        inputs.clone_from(&declared.inputs);
+        outputs.clone_from(&declared.outputs);
+    }
+    if let Ok((typed_inputs, typed_outputs)) = TypeInference::new().infer_sequence(&def.body) {
+        if typed_inputs.len() == input_count && typed_outputs.len() == output_count {
+            refine(&mut inputs, typed_inputs);
+            refine(&mut outputs, typed_outputs);
+        }
+    }
+
+    let names = text.and_then(|text| text.effect_names.as_ref());
+    let name = |names: Option<&Vec<String>>, i: usize, count: usize| {
+        names.filter(|names| names.len() == count).map(|names| names[i].clone())
+    };
+    StackEffect {
+        inputs: inputs
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| StackParameter {
+                name: name(names.map(|(inputs, _)| inputs), i, input_count),
+                param_type: spec_type(ty),
+                constraint: None,
+            })
+            .collect(),
+        outputs: outputs
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| StackResult {
+                name: name(names.map(|(_, outputs)| outputs), i, output_count),
+                result_type: spec_type(ty),
+                value: None,
+            })
+            .collect(),
+    }
+}
+
+fn refine(types: &mut [ast::StackType], inferred: Vec<ast::StackType>) {
+    for (ty, inferred) in types.iter_mut().zip(inferred) {
+        if *ty == ast::StackType::Unknown {
+            *ty = inferred;
+        }
+    }
+}
+
+/// Specification types are coarser; anything without a counterpart is `any`
+fn spec_type(ty: &ast::StackType) -> StackType {
+    match ty {
+        ast::StackType::Int => StackType::Int,
+        ast::StackType::Bool => StackType::Bool,
+        ast::StackType::Char => StackType::Char,
+        ast::StackType::Addr | ast::StackType::Record(_) => StackType::Addr,
+        _ => StackType::Any,
+    }
+}
+
+fn complexity(analyzer: &PerformanceAnalyzer, def: &Definition) -> Complexity {
+    let metrics = analyzer.analyze_definition(def);
+    let time = metrics.complexity_class.split_whitespace().next().map(str::to_string);
+    let recursive = metrics.complexity_class.contains("recursive");
+    Complexity { time, space: Some(if recursive { "O(n)" } else { "O(1)" }.to_string()) }
+}
+
+/// `T{ 3 4 word -> 7 }T` as `("word", [3, 4], [7])`
+///
+/// Tests that call more than one word, or leave anything but literals on
+/// the expected side, cannot be attributed to one word and are skipped.
+fn harvest_test(test: &ast::TestCase) -> Option<(String, Vec<i64>, Vec<i64>)> {
+    let (Word::WordRef { name, .. }, arguments) = test.body.split_last()? else {
+        return None;
+    };
+    let input = arguments.iter().map(literal).collect::<Option<Vec<_>>>()?;
+    let output = test.expected.iter().map(literal).collect::<Option<Vec<_>>>()?;
+    Some((name.to_string(), input, output))
+}
+
+fn literal(word: &Word) -> Option<i64> {
+    match word {
+        Word::IntLiteral(value) => Some(*value),
+        Word::WordRef { name, .. } if name.eq_ignore_ascii_case("true") => Some(-1),
+        Word::WordRef { name, .. } if name.eq_ignore_ascii_case("false") => Some(0),
+        _ => None,
+    }
+}
+
+/// Test case typed to the word's effect, if the values fit it
+fn test_case(effect: &StackEffect, input: &[i64], output: &[i64]) -> Option<TestCase> {
+    if input.len() != effect.inputs.len() || output.len() != effect.outputs.len() {
+        return None;
+    }
+    let input = input
+        .iter()
+        .zip(&effect.inputs)
+        .map(|(value, param)| test_value(*value, &param.param_type))
+        .collect::<Option<Vec<_>>>()?;
+    let output = output
+        .iter()
+        .zip(&effect.outputs)
+        .map(|(value, result)| test_value(*value, &result.result_type))
+        .collect::<Option<Vec<_>>>()?;
+    Some(TestCase { description: None, input, output, tags: None })
+}
+
+fn test_value(value: i64, ty: &StackType) -> Option<TestValue> {
+    match ty {
+        StackType::Bool => match value {
+            0 => Some(TestValue::Bool(false)),
+            -1 => Some(TestValue::Bool(true)),
+            _ => None,
+        },
+        StackType::Int | StackType::Any => Some(TestValue::Int(value)),
+        StackType::Uint if value >= 0 => Some(TestValue::Int(value)),
+        _ => None,
+    }
+}
+
+/// What the parser drops from a definition: parameter names, comments and
+/// the body as written
+struct DefinitionText {
+    /// Names in the stack comment, inputs then outputs
+    effect_names: Option<(Vec<String>, Vec<String>)>,
+    /// `\` comment on the definition line, or the comment lines just above it
+    description: Option<String>,
+    /// Body without comments, whitespace collapsed
+    body: String,
+}
+
+impl DefinitionText {
+    fn read(lines: &[&str], location: &SourceLocation) -> Option<Self> {
+        if !location.is_known() {
+            return None;
+        }
+        let first = lines.get(location.line - 1)?;
+        let start: String = first.chars().skip(location.column - 1).collect();
+        let mut tokens = Tokens::new(std::iter::once(start.as_str()).chain(lines[location.line..].iter().copied()));
+
+        tokens.next_raw().filter(|token| *token == ":")?;
+        tokens.next_raw()?;
+
+        let mut effect_names = None;
+        let mut body = Vec::new();
+        let mut description = None;
+        if tokens.peek_raw() == Some("(") {
+            tokens.next_raw();
+            let mut names = (Vec::new(), Vec::new());
+            let mut after_separator = false;
+            loop {
+                match tokens.next_raw()? {
+                    ")" => break,
+                    "--" => after_separator = true,
+                    name if after_separator => names.1.push(name.to_string()),
+                    name => names.0.push(name.to_string()),
+                }
+            }
+            effect_names = Some(names);
+        }
+        if tokens.line == 0 {
+            description = tokens.rest_of_line_comment();
+        }
+
+        while let Some(token) = tokens.next_code() {
+            if token == ";" {
+                break;
+            }
+            body.push(token);
+        }
+
+        let description = description.or_else(|| {
+            let above: Vec<&str> = lines[..location.line - 1]
+                .iter()
+                .rev()
+                .map(|line| line.trim())
+                .take_while(|line| line.starts_with("\\ "))
+                .map(|line| line[2..].trim())
+                .collect();
+            (!above.is_empty()).then(|| above.into_iter().rev().collect::<Vec<_>>().join(" "))
+        });
+
+        Some(Self { effect_names, description, body: body.join(" ") })
+    }
+}
+
+/// Whitespace-separated tokens over several lines
+struct Tokens<'a, I: Iterator<Item = &'a str>> {
+    lines: I,
+    current: &'a str,
+    /// Lines consumed after the first
+    line: usize,
+}
+
+impl<'a, I: Iterator<Item = &'a str>> Tokens<'a, I> {
+    fn new(mut lines: I) -> Self {
+        let current = lines.next().unwrap_or("");
+        Self { lines, current, line: 0 }
+    }
+
+    fn skip_space(&mut self) -> bool {
+        loop {
+            self.current = self.current.trim_start();
+            if !self.current.is_empty() {
+                return true;
+            }
+            match self.lines.next() {
+                Some(next) => {
+                    self.current = next;
+                    self.line += 1;
+                }
+                None => return false,
+            }
+        }
+    }
+
+    fn peek_raw(&mut self) -> Option<&'a str> {
+        if !self.skip_space() {
+            return None;
+        }
+        self.current.split_whitespace().next()
+    }
+
+    fn next_raw(&mut self) -> Option<&'a str> {
+        let token = self.peek_raw()?;
+        self.current = &self.current[token.len()..];
+        Some(token)
+    }
+
+    /// A `\` comment ending the current line, consumed
+    fn rest_of_line_comment(&mut self) -> Option<String> {
+        let rest = self.current.trim_start();
+        let comment = rest.strip_prefix("\\ ").or_else(|| (rest == "\\").then_some(""))?;
+        self.current = "";
+        let comment = comment.trim();
+        (!comment.is_empty()).then(|| comment.to_string())
+    }
+
+    /// Next token of code, skipping comments and keeping strings whole
+    fn next_code(&mut self) -> Option<String> {
+        loop {
+            let token = self.next_raw()?;
+            match token {
+                "\\" => self.current = "",
+                "(" => {
+                    let end = self.current.find(')')?;
+                    self.current = &self.current[end + 1..];
+                }
+                _ if token.ends_with('"') && token.len() > 1 => {
+                    let end = self.current.get(1..)?.find('"')? + 1;
+                    let text = &self.current[..=end];
+                    self.current = &self.current[end + 1..];
+                    return Some(format!("{}{}", token, text));
+                }
+                _ => return Some(token.to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::SpecCodeGenerator;
+
+    const SOURCE: &str = r#"
+\ Square a number
+: square ( n -- n2 )
+  dup * ;  \ cheap
+
+: sum-squares ( x y -- sum )  \ Sum of the squares
+  square swap square + ;
+
+: positive? 0 > ;
+
+: greet ( -- ) ." hello" cr ;
+
+T{ 3 square -> 9 }T
+T{ 3 4 sum-squares -> 25 }T
+T{ 5 positive? -> true }T
+T{ 2 square square -> 16 }T
+"#;
+
+    #[test]
+    fn test_extract_effects_tests_and_bodies() {
+        let extraction = extract_specs(SOURCE, &SpecValidator::new()).unwrap();
+        let words: Vec<&str> = extraction.specs.iter().map(|spec| spec.word.as_str()).collect();
+        assert_eq!(words, vec!["square", "sum-squares", "positive?"]);
+
+        // ( -- ) has no inputs or outputs, which a specification cannot express
+        assert_eq!(extraction.rejected.len(), 1);
+        assert_eq!(extraction.rejected[0].0, "greet");
+        assert_eq!(extraction.unassigned_tests, vec![16]);
+
+        let square = &extraction.specs[0];
+        assert_eq!(square.stack_comment(), "( n -- n2 )");
+        assert_eq!(square.description.as_deref(), Some("Square a number"));
+        assert_eq!(square.implementation.as_ref().unwrap().body.as_deref(), Some("dup *"));
+        assert_eq!(square.test_count(), 1);
+        assert_eq!(square.complexity.as_ref().unwrap().time.as_deref(), Some("O(1)"));
+
+        let sum = &extraction.specs[1];
+        assert_eq!(sum.description.as_deref(), Some("Sum of the squares"));
+        assert_eq!(sum.test_cases.as_ref().unwrap()[0].input, vec![TestValue::Int(3), TestValue::Int(4)]);
+
+        // No stack comment: the effect is inferred
+        let positive = &extraction.specs[2];
+        assert_eq!(positive.stack_effect.inputs.len(), 1);
+        assert_eq!(positive.stack_effect.outputs[0].result_type, StackType::Bool);
+        assert_eq!(positive.test_cases.as_ref().unwrap()[0].output, vec![TestValue::Bool(true)]);
+    }
+
+    #[test]
+    fn test_extracted_specs_regenerate_the_source() {
+        let extraction = extract_specs(SOURCE, &SpecValidator::new()).unwrap();
+        let generator = SpecCodeGenerator::new().with_provenance(false);
+        let code = generator.generate(&extraction.specs[1]).unwrap();
+        assert!(code.contains(": sum-squares ( x y -- sum )"));
+        assert!(code.contains("square swap square +"));
+        assert!(code.contains("T{ 3 4 sum-squares -> 25 }T"));
+
+        assert!(extract_specs(": broken ( n -- n", &SpecValidator::new()).is_err());
+    }
+}
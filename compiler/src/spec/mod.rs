@@ -1,7 +1,8 @@
 //! Machine-Readable Specification System
 //!
-//! This module provides machine-readable specifications for Forth words,
-//! enabling AI agents to generate correct code from JSON specifications.
+//! This module provides machine-readable specifications for Forth words and
+//! multi-word programs, enabling AI agents to generate correct code from JSON
+//! specifications.
 
 use serde::{Deserialize, Serialize};
 use std::path::Path;
@@ -9,8 +10,12 @@ use thiserror::Error;
 
 pub mod validator;
 pub mod zero_copy;
+pub mod program;
+pub mod extract;
 
 pub use validator::SpecValidator;
+pub use program::{ConstantSpec, ProgramSpec, ProgramTest, ProgramWord, SpecDocument};
+pub use extract::{extract_specs, Extraction};
 pub use zero_copy::{ArchivedSpecification, ArchivedStackEffect, serialize_spec, deserialize_spec};
 
 /// Errors that can occur during specification processing
@@ -30,6 +35,9 @@ pub enum SpecError {
 
     #[error("Invalid constraint: {0}")]
     ConstraintError(String),
+
+    #[error("Failed to parse Forth source: {0}")]
+    SourceError(String),
 }
 
 pub type SpecResult<T> = Result<T, SpecError>;
@@ -171,6 +179,10 @@ pub struct Implementation {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub pattern: Option<String>,
 
+    /// Forth body to use verbatim, e.g. for words built from other words
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub hints: Option<Vec<String>>,
 }
@@ -0,0 +1,285 @@
+//! Program-level specifications
+//!
+//! A program spec groups several word specs into one generated file. Words
+//! declare the words they depend on; generation emits shared constants
+//! first, then every word after its dependencies, then tests that exercise
+//! the words together through the program's entry point.
+
+use super::{Metadata, SpecError, SpecResult, SpecValidator, Specification, TestValue};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Named value shared by the words of a program, emitted as a `constant`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConstantSpec {
+    pub name: String,
+    pub value: i64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// One word of a program
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgramWord {
+    #[serde(flatten)]
+    pub spec: Specification,
+
+    /// Words of this program the definition calls
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+}
+
+/// Test of a phrase that combines several words
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgramTest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    pub input: Vec<TestValue>,
+
+    /// Forth code run on the input; defaults to the entry point
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run: Option<String>,
+
+    pub output: Vec<TestValue>,
+}
+
+/// Complete machine-readable specification for a multi-word program
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgramSpec {
+    /// Name of the program
+    pub program: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub constants: Vec<ConstantSpec>,
+
+    pub words: Vec<ProgramWord>,
+
+    /// Word that runs the program
+    pub entry_point: String,
+
+    /// Cross-word tests
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<ProgramTest>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
+}
+
+impl ProgramSpec {
+    /// Load program specification from JSON file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> SpecResult<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Self::from_json(&content)
+    }
+
+    /// Parse program specification from JSON string
+    pub fn from_json(json: &str) -> SpecResult<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Validate this specification
+    pub fn validate(&self) -> SpecResult<()> {
+        self.validate_with(&SpecValidator::new())
+    }
+
+    /// Validate every word with `validator`, then the program structure
+    pub fn validate_with(&self, validator: &SpecValidator) -> SpecResult<()> {
+        if self.words.is_empty() {
+            return Err(SpecError::ValidationError(format!("Program '{}' has no words", self.program)));
+        }
+
+        let mut names = HashSet::new();
+        for constant in &self.constants {
+            if !names.insert(constant.name.as_str()) {
+                return Err(SpecError::ValidationError(format!("Duplicate name '{}'", constant.name)));
+            }
+        }
+        for word in &self.words {
+            validator.validate(&word.spec)?;
+            if !names.insert(word.spec.word.as_str()) {
+                return Err(SpecError::ValidationError(format!("Duplicate name '{}'", word.spec.word)));
+            }
+        }
+
+        if self.word(&self.entry_point).is_none() {
+            return Err(SpecError::ValidationError(format!(
+                "Entry point '{}' is not one of the program's words",
+                self.entry_point
+            )));
+        }
+        for test in &self.tests {
+            if matches!(&test.run, Some(run) if run.trim().is_empty()) {
+                return Err(SpecError::ValidationError("Program test has an empty 'run' phrase".to_string()));
+            }
+        }
+
+        self.ordered_words().map(|_| ())
+    }
+
+    /// Word spec by name
+    pub fn word(&self, name: &str) -> Option<&ProgramWord> {
+        self.words.iter().find(|word| word.spec.word == name)
+    }
+
+    /// Words with every word after its dependencies
+    ///
+    /// Independent words keep the order they were declared in.
+    pub fn ordered_words(&self) -> SpecResult<Vec<&ProgramWord>> {
+        let index: HashMap<&str, usize> = self
+            .words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| (word.spec.word.as_str(), i))
+            .collect();
+
+        let mut dependencies = Vec::with_capacity(self.words.len());
+        for word in &self.words {
+            let mut deps = Vec::new();
+            for dep in &word.depends_on {
+                match index.get(dep.as_str()) {
+                    Some(&i) => deps.push(i),
+                    None => {
+                        return Err(SpecError::ValidationError(format!(
+                            "Word '{}' depends on '{}', which the program does not define",
+                            word.spec.word, dep
+                        )))
+                    }
+                }
+            }
+            dependencies.push(deps);
+        }
+
+        // 0 = unvisited, 1 = on the current path, 2 = emitted
+        let mut state = vec![0u8; self.words.len()];
+        let mut ordered = Vec::with_capacity(self.words.len());
+        let mut path = Vec::new();
+        for start in 0..self.words.len() {
+            self.visit(start, &dependencies, &mut state, &mut path, &mut ordered)?;
+        }
+        Ok(ordered)
+    }
+
+    fn visit<'a>(
+        &'a self,
+        i: usize,
+        dependencies: &[Vec<usize>],
+        state: &mut [u8],
+        path: &mut Vec<usize>,
+        ordered: &mut Vec<&'a ProgramWord>,
+    ) -> SpecResult<()> {
+        match state[i] {
+            2 => return Ok(()),
+            1 => {
+                let start = path.iter().position(|&p| p == i).unwrap_or(0);
+                let cycle: Vec<&str> = path[start..]
+                    .iter()
+                    .chain(std::iter::once(&i))
+                    .map(|&p| self.words[p].spec.word.as_str())
+                    .collect();
+                return Err(SpecError::ValidationError(format!("Dependency cycle: {}", cycle.join(" -> "))));
+            }
+            _ => {}
+        }
+
+        state[i] = 1;
+        path.push(i);
+        for &dep in &dependencies[i] {
+            self.visit(dep, dependencies, state, path, ordered)?;
+        }
+        path.pop();
+        state[i] = 2;
+        ordered.push(&self.words[i]);
+        Ok(())
+    }
+}
+
+/// A specification file, describing either one word or a whole program
+#[derive(Debug, Clone)]
+pub enum SpecDocument {
+    Word(Specification),
+    Program(ProgramSpec),
+}
+
+impl SpecDocument {
+    /// Load from a JSON file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> SpecResult<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Self::from_json(&content)
+    }
+
+    /// Parse JSON; documents with a `words` list are program specs
+    pub fn from_json(json: &str) -> SpecResult<Self> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        Self::from_value(value)
+    }
+
+    /// Interpret an already parsed JSON document
+    pub fn from_value(value: serde_json::Value) -> SpecResult<Self> {
+        if value.get("words").is_some() {
+            Ok(Self::Program(serde_json::from_value(value)?))
+        } else {
+            Ok(Self::Word(serde_json::from_value(value)?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(words: &str) -> ProgramSpec {
+        ProgramSpec::from_json(&format!(
+            r#"{{ "program": "p", "entry_point": "main", "words": [{}] }}"#,
+            words
+        ))
+        .unwrap()
+    }
+
+    fn word(name: &str, deps: &[&str]) -> String {
+        format!(
+            r#"{{ "word": "{}", "stack_effect": {{ "inputs": [], "outputs": [{{ "name": "n", "type": "int" }}] }}, "depends_on": {:?} }}"#,
+            name, deps
+        )
+    }
+
+    fn names(spec: &ProgramSpec) -> Vec<&str> {
+        spec.ordered_words().unwrap().iter().map(|w| w.spec.word.as_str()).collect()
+    }
+
+    #[test]
+    fn test_words_follow_their_dependencies() {
+        let spec = program(&[word("main", &["report", "sum"]), word("report", &["sum"]), word("sum", &[]), word("unused", &[])].join(","));
+        assert!(spec.validate().is_ok());
+        assert_eq!(names(&spec), vec!["sum", "report", "main", "unused"]);
+    }
+
+    #[test]
+    fn test_invalid_programs_are_rejected() {
+        let cycle = program(&[word("main", &["a"]), word("a", &["b"]), word("b", &["a"])].join(","));
+        let error = cycle.validate().unwrap_err().to_string();
+        assert!(error.contains("a -> b -> a"), "{}", error);
+
+        let missing = program(&word("main", &["helper"]));
+        assert!(missing.validate().is_err());
+
+        let no_entry = program(&word("start", &[]));
+        assert!(no_entry.validate().unwrap_err().to_string().contains("Entry point"));
+
+        let duplicate = program(&[word("main", &[]), word("main", &[])].join(","));
+        assert!(duplicate.validate().is_err());
+    }
+
+    #[test]
+    fn test_documents_are_told_apart() {
+        let single = r#"{ "word": "sq", "stack_effect": { "inputs": [], "outputs": [] } }"#;
+        assert!(matches!(SpecDocument::from_json(single).unwrap(), SpecDocument::Word(_)));
+        let whole = format!(r#"{{ "program": "p", "entry_point": "main", "words": [{}] }}"#, word("main", &[]));
+        assert!(matches!(SpecDocument::from_json(&whole).unwrap(), SpecDocument::Program(_)));
+    }
+}
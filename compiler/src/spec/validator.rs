@@ -3,6 +3,8 @@
 //! Validates specifications for completeness and correctness
 
 use super::{SpecError, SpecResult, Specification, TestValue};
+use crate::symbolic::{PropertyCheck, PropertyChecker, PropertyStatus};
+use fastforth_frontend::parse_program;
 use rayon::prelude::*;
 
 /// Validator for specifications
@@ -36,6 +38,50 @@ impl SpecValidator {
         Ok(())
     }
 
+    /// Check the specification's properties against an implementation
+    ///
+    /// `source` must define the word; words it calls may be defined there
+    /// too. Returns one check per declared property, in order.
+    pub fn check_properties(&self, spec: &Specification, source: &str) -> SpecResult<Vec<PropertyCheck>> {
+        let program = parse_program(source).map_err(|e| SpecError::SourceError(e.to_string()))?;
+        if !program.definitions.iter().any(|def| def.name == spec.word) {
+            return Err(SpecError::ValidationError(format!(
+                "Source does not define '{}'",
+                spec.word
+            )));
+        }
+
+        let checker = PropertyChecker::new(&program);
+        Ok(spec
+            .properties
+            .iter()
+            .flatten()
+            .map(|property| checker.check(&spec.word, spec.stack_effect.inputs.len(), property))
+            .collect())
+    }
+
+    /// Validate a specification together with an implementation of it
+    ///
+    /// Fails if a property is falsified or, in strict mode, if a property
+    /// could not be verified either way.
+    pub fn validate_implementation(&self, spec: &Specification, source: &str) -> SpecResult<Vec<PropertyCheck>> {
+        self.validate(spec)?;
+        let checks = self.check_properties(spec, source)?;
+
+        for check in &checks {
+            let failed = match check.status {
+                PropertyStatus::Verified => false,
+                PropertyStatus::Falsified { .. } => true,
+                PropertyStatus::Unknown { .. } => self.strict,
+            };
+            if failed {
+                return Err(SpecError::ValidationError(format!("Property {}", check)));
+            }
+        }
+
+        Ok(checks)
+    }
+
     /// Validate word name
     fn validate_word_name(&self, word: &str) -> SpecResult<()> {
         if word.is_empty() {
@@ -369,4 +415,36 @@ mod tests {
 
         assert!(validator.validate(&bad_spec).is_err());
     }
+
+    #[test]
+    fn test_validate_implementation_properties() {
+        let mut spec = crate::spec::Specification::from_json(r#"{
+            "word": "plus",
+            "stack_effect": {
+                "inputs": [{ "type": "int" }, { "type": "int" }],
+                "outputs": [{ "type": "int" }]
+            },
+            "properties": ["commutative", "plus(a, 0) = a", "plus(a, b) >= a"]
+        }"#).unwrap();
+
+        let validator = SpecValidator::new();
+        let checks = validator.validate_implementation(&spec, ": plus + ;").unwrap();
+        assert_eq!(checks[0].status, PropertyStatus::Verified);
+        assert_eq!(checks[1].status, PropertyStatus::Verified);
+        assert!(matches!(checks[2].status, PropertyStatus::Unknown { .. }));
+
+        // Unknown properties only fail strict validation
+        spec.description = Some("Addition".to_string());
+        spec.test_cases = Some(vec![TestCase {
+            description: None,
+            input: vec![TestValue::Int(1), TestValue::Int(2)],
+            output: vec![TestValue::Int(3)],
+            tags: Some(vec![crate::spec::TestTag::BaseCase]),
+        }]);
+        assert!(SpecValidator::strict().validate_implementation(&spec, ": plus + ;").is_err());
+
+        let error = validator.validate_implementation(&spec, ": plus - ;").unwrap_err().to_string();
+        assert!(error.contains("commutative [falsified"), "{}", error);
+        assert!(validator.check_properties(&spec, ": minus - ;").is_err());
+    }
 }
@@ -2,13 +2,27 @@
 //!
 //! Determines if two implementations are semantically equivalent
 
+use super::executor::DEFAULT_LOOP_UNROLL;
+#[cfg(feature = "smt")]
+use super::smt::{SmtOutcome, SmtSolver};
 use super::{SymbolicExecutor, SymbolicValue};
 use fastforth_frontend::{Program, Definition};
 use serde::{Serialize, Deserialize};
 
+/// Outcome of an equivalence check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EquivalenceVerdict {
+    Equivalent,
+    NotEquivalent,
+    /// Neither proved nor refuted, e.g. past the loop unroll bound
+    Unknown,
+}
+
 /// Result of equivalence checking
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EquivalenceResult {
+    pub verdict: EquivalenceVerdict,
     pub equivalent: bool,
     pub reason: String,
     pub left_output: Vec<String>,
@@ -19,11 +33,34 @@ pub struct EquivalenceResult {
 /// Equivalence checker using symbolic execution
 pub struct EquivalenceChecker {
     max_inputs: usize,
+    loop_unroll: usize,
+    /// Decides outputs that differ structurally
+    #[cfg(feature = "smt")]
+    solver: Option<SmtSolver>,
 }
 
 impl EquivalenceChecker {
     pub fn new() -> Self {
-        Self { max_inputs: 10 }
+        Self {
+            max_inputs: 10,
+            loop_unroll: DEFAULT_LOOP_UNROLL,
+            #[cfg(feature = "smt")]
+            solver: Some(SmtSolver::from_env()),
+        }
+    }
+
+    /// Use `solver` for outputs that differ structurally, or only the
+    /// structural comparison if `None`
+    #[cfg(feature = "smt")]
+    pub fn with_solver(mut self, solver: Option<SmtSolver>) -> Self {
+        self.solver = solver;
+        self
+    }
+
+    /// Unroll loops with unknown trip counts at most `loop_unroll` times
+    pub fn with_loop_unroll(mut self, loop_unroll: usize) -> Self {
+        self.loop_unroll = loop_unroll;
+        self
     }
 
     /// Check if two programs are equivalent
@@ -35,8 +72,8 @@ impl EquivalenceChecker {
         // For simplicity, assume both have same number of inputs
         let input_count = self.infer_input_count(left).max(self.infer_input_count(right));
 
-        let mut left_executor = SymbolicExecutor::new();
-        let mut right_executor = SymbolicExecutor::new();
+        let mut left_executor = SymbolicExecutor::new().with_loop_unroll(self.loop_unroll);
+        let mut right_executor = SymbolicExecutor::new().with_loop_unroll(self.loop_unroll);
 
         left_executor.initialize_inputs(input_count);
         right_executor.initialize_inputs(input_count);
@@ -46,18 +83,37 @@ impl EquivalenceChecker {
 
         match (left_result, right_result) {
             (Ok(left_res), Ok(right_res)) => {
-                let equivalent = self.compare_outputs(&left_res.final_stack, &right_res.final_stack);
+                let same = self.compare_outputs(&left_res.final_stack, &right_res.final_stack);
+                let bounded = left_res.loop_bound_reached || right_res.loop_bound_reached;
+
+                // Past the unroll bound the outputs describe only some
+                // paths, so agreement proves nothing and disagreement may
+                // come from where each side was cut off
+                let (verdict, reason) = match (same, bounded) {
+                    (true, false) => (EquivalenceVerdict::Equivalent, "Outputs are symbolically equivalent".to_string()),
+                    #[cfg(feature = "smt")]
+                    (false, false) => self.decide(left_executor.final_values(), right_executor.final_values()),
+                    #[cfg(not(feature = "smt"))]
+                    (false, false) => (EquivalenceVerdict::NotEquivalent, "Outputs differ".to_string()),
+                    (true, true) => (
+                        EquivalenceVerdict::Unknown,
+                        format!("Outputs agree for up to {} loop iterations; unknown beyond depth {}", self.loop_unroll, self.loop_unroll),
+                    ),
+                    (false, true) => (
+                        EquivalenceVerdict::Unknown,
+                        format!("Outputs differ within {} loop iterations; unknown beyond depth {}", self.loop_unroll, self.loop_unroll),
+                    ),
+                };
+                let equivalent = verdict == EquivalenceVerdict::Equivalent;
+                let same = same || equivalent;
 
                 EquivalenceResult {
+                    verdict,
                     equivalent,
-                    reason: if equivalent {
-                        "Outputs are symbolically equivalent".to_string()
-                    } else {
-                        "Outputs differ".to_string()
-                    },
+                    reason,
                     left_output: left_res.final_stack.clone(),
                     right_output: right_res.final_stack.clone(),
-                    differences: if equivalent {
+                    differences: if same {
                         vec![]
                     } else {
                         self.find_differences(&left_res.final_stack, &right_res.final_stack)
@@ -65,6 +121,7 @@ impl EquivalenceChecker {
                 }
             }
             (Err(e), _) => EquivalenceResult {
+                verdict: EquivalenceVerdict::Unknown,
                 equivalent: false,
                 reason: format!("Left execution failed: {}", e),
                 left_output: vec![],
@@ -72,6 +129,7 @@ impl EquivalenceChecker {
                 differences: vec![],
             },
             (_, Err(e)) => EquivalenceResult {
+                verdict: EquivalenceVerdict::Unknown,
                 equivalent: false,
                 reason: format!("Right execution failed: {}", e),
                 left_output: vec![],
@@ -90,16 +148,44 @@ impl EquivalenceChecker {
         let left_prog = Program {
             definitions: vec![],
             top_level_code: left.body.clone(),
+            tests: vec![],
+            code_words: vec![],
+            records: vec![],
+            names: Default::default(),
         };
 
         let right_prog = Program {
             definitions: vec![],
             top_level_code: right.body.clone(),
+            tests: vec![],
+            code_words: vec![],
+            records: vec![],
+            names: Default::default(),
         };
 
         self.check_programs(&left_prog, &right_prog)
     }
 
+    /// Ask the solver about outputs that differ structurally
+    #[cfg(feature = "smt")]
+    fn decide(&self, left: &[SymbolicValue], right: &[SymbolicValue]) -> (EquivalenceVerdict, String) {
+        let Some(solver) = &self.solver else {
+            return (EquivalenceVerdict::NotEquivalent, "Outputs differ".to_string());
+        };
+
+        match solver.prove_equal(left, right) {
+            SmtOutcome::Equal => (EquivalenceVerdict::Equivalent, "Outputs are equal as 64-bit arithmetic".to_string()),
+            SmtOutcome::Differ(model) if model.is_empty() => (EquivalenceVerdict::NotEquivalent, "Outputs differ".to_string()),
+            SmtOutcome::Differ(model) => {
+                let inputs: Vec<String> =
+                    model.iter().map(|((name, index), value)| format!("{}_{} = {}", name, index, value)).collect();
+                (EquivalenceVerdict::NotEquivalent, format!("Outputs differ for {}", inputs.join(", ")))
+            }
+            // No answer from the solver leaves the structural verdict
+            SmtOutcome::Unknown(reason) => (EquivalenceVerdict::NotEquivalent, format!("Outputs differ ({})", reason)),
+        }
+    }
+
     /// Compare output stacks
     fn compare_outputs(&self, left: &[String], right: &[String]) -> bool {
         if left.len() != right.len() {
@@ -172,5 +258,18 @@ mod tests {
         let result = checker.check_definitions(&left.definitions[0], &right.definitions[0]);
 
         assert!(!result.equivalent);
+        assert_eq!(result.verdict, EquivalenceVerdict::NotEquivalent);
+    }
+
+    #[test]
+    fn test_loops_past_the_bound_are_unknown() {
+        let left = parse_program(": sum 0 swap 0 do i + loop ;").unwrap();
+        let right = parse_program(": sum 0 swap 0 do i + loop ;").unwrap();
+
+        let checker = EquivalenceChecker::new().with_loop_unroll(2);
+        let result = checker.check_definitions(&left.definitions[0], &right.definitions[0]);
+
+        assert_eq!(result.verdict, EquivalenceVerdict::Unknown);
+        assert!(result.reason.contains("beyond depth 2"), "{}", result.reason);
     }
 }
@@ -4,7 +4,7 @@
 
 use super::{SymbolicValue, SymbolicStack, SymbolicError, Result};
 use super::symbolic_value::{BinaryOperator, UnaryOperator};
-use fastforth_frontend::{Word, Definition, Program};
+use fastforth_frontend::{Arithmetic, Word, Definition, Program};
 use serde::{Serialize, Deserialize};
 use rustc_hash::FxHashMap;
 
@@ -15,14 +15,28 @@ pub struct ExecutionResult {
     pub final_stack: Vec<String>,
     pub operations_count: usize,
     pub simplified: bool,
+    /// Some loop could run more iterations than were unrolled
+    ///
+    /// The final stack then only describes paths that leave every loop
+    /// within the unroll bound.
+    #[serde(default)]
+    pub loop_bound_reached: bool,
 }
 
+/// Default number of symbolic loop iterations unrolled per loop
+pub const DEFAULT_LOOP_UNROLL: usize = 8;
+
 /// Symbolic executor for Forth code
 pub struct SymbolicExecutor {
     stack: SymbolicStack,
     definitions: FxHashMap<String, Definition>,
     operations_count: usize,
     max_operations: usize,
+    /// Indices and limits of the enclosing DO loops, innermost last
+    loop_indices: Vec<(SymbolicValue, SymbolicValue)>,
+    loop_unroll: usize,
+    loop_bound_reached: bool,
+    arithmetic: Arithmetic,
 }
 
 impl SymbolicExecutor {
@@ -32,6 +46,10 @@ impl SymbolicExecutor {
             definitions: FxHashMap::default(),
             operations_count: 0,
             max_operations: 10000,
+            loop_indices: Vec::new(),
+            loop_unroll: DEFAULT_LOOP_UNROLL,
+            loop_bound_reached: false,
+            arithmetic: Arithmetic::default(),
         }
     }
 
@@ -71,6 +89,15 @@ impl SymbolicExecutor {
                 let condition = self.stack.pop()
                     .ok_or(SymbolicError::StackUnderflow { required: 1, available: 0 })?;
 
+                // A known condition takes one branch, like the real program
+                if let SymbolicValue::Concrete(flag) = condition {
+                    let branch = if flag != 0 { Some(then_branch) } else { else_branch.as_ref() };
+                    for word in branch.into_iter().flatten() {
+                        self.execute_word(word)?;
+                    }
+                    return Ok(());
+                }
+
                 // Execute both branches and create conditional values
                 let mut then_executor = self.clone();
                 for word in then_branch {
@@ -85,10 +112,25 @@ impl SymbolicExecutor {
                 }
 
                 // Merge stacks with conditional values
-                self.merge_conditional_stacks(condition, &then_executor.stack, &else_executor.stack);
-                Ok(())
+                self.operations_count = then_executor.operations_count.max(else_executor.operations_count);
+                self.merge_conditional_stacks(condition, &then_executor.stack, &else_executor.stack)
             }
 
+            Word::BeginUntil { body } => self.begin_until(body, 0),
+
+            Word::BeginWhileRepeat { condition, body } => self.begin_while_repeat(condition, body, 0),
+
+            Word::DoLoop { body, increment } => {
+                let (limit, start) = self.stack.pop2()
+                    .ok_or(SymbolicError::StackUnderflow { required: 2, available: self.stack.depth() })?;
+                self.loop_indices.push((start, limit));
+                let result = self.do_loop(body, *increment, 0);
+                self.loop_indices.pop();
+                result
+            }
+
+            Word::Comment(_) => Ok(()),
+
             _ => Err(SymbolicError::UnsupportedOperation(format!("{:?}", word))),
         }
     }
@@ -102,6 +144,8 @@ impl SymbolicExecutor {
             "*" => self.binary_op(BinaryOperator::Mul),
             "/" => self.binary_op(BinaryOperator::Div),
             "mod" => self.binary_op(BinaryOperator::Mod),
+            "um*" => self.pair_op(BinaryOperator::UMul, BinaryOperator::UMulHigh),
+            "u/mod" => self.pair_op(BinaryOperator::UMod, BinaryOperator::UDiv),
 
             // Comparison
             "<" => self.binary_op(BinaryOperator::Lt),
@@ -110,15 +154,41 @@ impl SymbolicExecutor {
             "<=" => self.binary_op(BinaryOperator::Lte),
             ">=" => self.binary_op(BinaryOperator::Gte),
             "<>" => self.binary_op(BinaryOperator::Neq),
+            "0=" => self.apply_literal(BinaryOperator::Eq, 0),
+            "0<" => self.apply_literal(BinaryOperator::Lt, 0),
+            "0>" => self.apply_literal(BinaryOperator::Gt, 0),
+            "u<" => self.binary_op(BinaryOperator::ULt),
+            "u>" => self.binary_op(BinaryOperator::UGt),
 
             // Logical
             "and" => self.binary_op(BinaryOperator::And),
             "or" => self.binary_op(BinaryOperator::Or),
             "not" => self.unary_op(UnaryOperator::Not),
 
+            // Bitwise
+            "xor" => self.binary_op(BinaryOperator::Xor),
+            "invert" => self.apply_literal(BinaryOperator::Xor, -1),
+            "lshift" => self.binary_op(BinaryOperator::Shl),
+            "rshift" => self.binary_op(BinaryOperator::Shr),
+            "rol" => self.binary_op(BinaryOperator::Rotl),
+            "ror" => self.binary_op(BinaryOperator::Rotr),
+            "popcount" => self.unary_op(UnaryOperator::Popcount),
+            "ctz" => self.unary_op(UnaryOperator::Ctz),
+            "clz" => self.unary_op(UnaryOperator::Clz),
+
             // Unary
             "negate" => self.unary_op(UnaryOperator::Negate),
             "abs" => self.unary_op(UnaryOperator::Abs),
+            "1+" => self.apply_literal(BinaryOperator::Add, 1),
+            "1-" => self.apply_literal(BinaryOperator::Sub, 1),
+            "2*" => self.apply_literal(BinaryOperator::Mul, 2),
+
+            "min" => self.select(BinaryOperator::Lt),
+            "max" => self.select(BinaryOperator::Gt),
+
+            // Loop indices
+            "i" => self.loop_index(0),
+            "j" => self.loop_index(1),
 
             // Stack manipulation
             "dup" => {
@@ -166,11 +236,21 @@ impl SymbolicExecutor {
         let (a, b) = self.stack.pop2()
             .ok_or(SymbolicError::StackUnderflow { required: 2, available: self.stack.depth() })?;
 
-        let result = SymbolicValue::binary_op(op, a, b).simplify();
+        let result = SymbolicValue::binary_op(op, a, b).simplify_with(self.arithmetic);
         self.stack.push(result);
         Ok(())
     }
 
+    /// Push `a first b` then `a second b`, for words like `um*` that leave two cells
+    fn pair_op(&mut self, first: BinaryOperator, second: BinaryOperator) -> Result<()> {
+        let (a, b) = self.stack.pop2()
+            .ok_or(SymbolicError::StackUnderflow { required: 2, available: self.stack.depth() })?;
+
+        self.stack.push(SymbolicValue::binary_op(first, a.clone(), b.clone()).simplify_with(self.arithmetic));
+        self.stack.push(SymbolicValue::binary_op(second, a, b).simplify_with(self.arithmetic));
+        Ok(())
+    }
+
     /// Execute a unary operation
     fn unary_op(&mut self, op: UnaryOperator) -> Result<()> {
         let val = self.stack.pop()
@@ -181,18 +261,167 @@ impl SymbolicExecutor {
         Ok(())
     }
 
-    /// Merge conditional stacks
+    /// Apply `op` with a literal right operand, as in `1+`
+    fn apply_literal(&mut self, op: BinaryOperator, literal: i64) -> Result<()> {
+        self.stack.push(SymbolicValue::concrete(literal));
+        self.binary_op(op)
+    }
+
+    /// Keep the first of two values if `a op b` holds, as in `min`
+    fn select(&mut self, op: BinaryOperator) -> Result<()> {
+        let (a, b) = self.stack.pop2()
+            .ok_or(SymbolicError::StackUnderflow { required: 2, available: self.stack.depth() })?;
+
+        let condition = SymbolicValue::binary_op(op, a.clone(), b.clone()).simplify();
+        self.stack.push(SymbolicValue::conditional(condition, a, b).simplify());
+        Ok(())
+    }
+
+    /// Push the index of the DO loop `depth` levels out
+    fn loop_index(&mut self, depth: usize) -> Result<()> {
+        let (index, _) = self.loop_indices.iter().rev().nth(depth)
+            .ok_or_else(|| SymbolicError::UnsupportedOperation("loop index outside a DO loop".to_string()))?;
+        self.stack.push(index.clone());
+        Ok(())
+    }
+
+    /// Run BEGIN ... UNTIL from its `unrolled`th symbolic iteration
+    ///
+    /// A known flag decides the loop like the real program does. An
+    /// unknown one forks: the path that leaves and the path that goes
+    /// round again are merged into conditional values, up to the unroll
+    /// bound.
+    fn begin_until(&mut self, body: &[Word], unrolled: usize) -> Result<()> {
+        loop {
+            for word in body {
+                self.execute_word(word)?;
+            }
+            let flag = self.stack.pop()
+                .ok_or(SymbolicError::StackUnderflow { required: 1, available: 0 })?;
+
+            match flag {
+                SymbolicValue::Concrete(0) => continue,
+                SymbolicValue::Concrete(_) => return Ok(()),
+                flag => {
+                    let Some(mut again) = self.fork_iteration(unrolled) else { return Ok(()) };
+                    again.begin_until(body, unrolled + 1)?;
+                    return self.merge_iteration(flag, again, true);
+                }
+            }
+        }
+    }
+
+    /// Run BEGIN ... WHILE ... REPEAT from its `unrolled`th symbolic iteration
+    fn begin_while_repeat(&mut self, condition: &[Word], body: &[Word], unrolled: usize) -> Result<()> {
+        loop {
+            for word in condition {
+                self.execute_word(word)?;
+            }
+            let flag = self.stack.pop()
+                .ok_or(SymbolicError::StackUnderflow { required: 1, available: 0 })?;
+
+            match flag {
+                SymbolicValue::Concrete(0) => return Ok(()),
+                SymbolicValue::Concrete(_) => {
+                    for word in body {
+                        self.execute_word(word)?;
+                    }
+                }
+                flag => {
+                    let Some(mut again) = self.fork_iteration(unrolled) else { return Ok(()) };
+                    for word in body {
+                        again.execute_word(word)?;
+                    }
+                    again.begin_while_repeat(condition, body, unrolled + 1)?;
+                    return self.merge_iteration(flag, again, false);
+                }
+            }
+        }
+    }
+
+    /// Run the innermost DO loop from its `unrolled`th symbolic iteration
+    fn do_loop(&mut self, body: &[Word], increment: i64, unrolled: usize) -> Result<()> {
+        loop {
+            for word in body {
+                self.execute_word(word)?;
+            }
+
+            let (index, limit) = self.loop_indices.last().cloned()
+                .ok_or_else(|| SymbolicError::UnsupportedOperation("DO loop without an index".to_string()))?;
+            let next = SymbolicValue::binary_op(BinaryOperator::Add, index, SymbolicValue::concrete(increment)).simplify();
+            let done = if increment < 0 { BinaryOperator::Lt } else { BinaryOperator::Gte };
+            let flag = SymbolicValue::binary_op(done, next.clone(), limit.clone()).simplify();
+            if let Some(slot) = self.loop_indices.last_mut() {
+                slot.0 = next;
+            }
+
+            match flag {
+                SymbolicValue::Concrete(0) => continue,
+                SymbolicValue::Concrete(_) => return Ok(()),
+                flag => {
+                    let Some(mut again) = self.fork_iteration(unrolled) else { return Ok(()) };
+                    again.do_loop(body, increment, unrolled + 1)?;
+                    return self.merge_iteration(flag, again, true);
+                }
+            }
+        }
+    }
+
+    /// Copy of this executor that goes round a loop once more
+    ///
+    /// Past the unroll bound the loop is cut off instead: there is no
+    /// copy, the current path leaves the loop, and the result is marked
+    /// as bounded.
+    fn fork_iteration(&mut self, unrolled: usize) -> Option<Self> {
+        if unrolled >= self.loop_unroll {
+            self.loop_bound_reached = true;
+            return None;
+        }
+        Some(self.clone())
+    }
+
+    /// Merge the path that left a loop on `flag` with the one that went on
+    ///
+    /// `exits_when_true` tells which way the flag points.
+    fn merge_iteration(&mut self, flag: SymbolicValue, again: Self, exits_when_true: bool) -> Result<()> {
+        self.operations_count = self.operations_count.max(again.operations_count);
+        self.loop_bound_reached |= again.loop_bound_reached;
+
+        let stay = self.stack.clone();
+        if exits_when_true {
+            self.merge_conditional_stacks(flag, &stay, &again.stack)
+        } else {
+            self.merge_conditional_stacks(flag, &again.stack, &stay)
+        }
+    }
+
+    /// Merge the stacks left by the two branches of an IF
+    ///
+    /// Slots where the branches disagree become conditional values. The
+    /// stack below the branches is shared, so only the depth must match.
     fn merge_conditional_stacks(
         &mut self,
         condition: SymbolicValue,
         then_stack: &SymbolicStack,
         else_stack: &SymbolicStack,
-    ) {
-        // For simplicity, use the then branch values
-        // In a full implementation, create conditional symbolic values
-        for val in then_stack.get_stack() {
-            self.stack.push(val.clone());
+    ) -> Result<()> {
+        if then_stack.depth() != else_stack.depth() {
+            return Err(SymbolicError::UnsupportedOperation(format!(
+                "IF branches leave different stack depths ({} and {})",
+                then_stack.depth(),
+                else_stack.depth()
+            )));
+        }
+
+        self.stack.clear();
+        for (then_val, else_val) in then_stack.get_stack().iter().zip(else_stack.get_stack()) {
+            if then_val == else_val {
+                self.stack.push(then_val.clone());
+            } else {
+                self.stack.push(SymbolicValue::conditional(condition.clone(), then_val.clone(), else_val.clone()));
+            }
         }
+        Ok(())
     }
 
     /// Get the execution result
@@ -202,9 +431,15 @@ impl SymbolicExecutor {
             final_stack: self.stack.get_stack().iter().map(|v| format!("{}", v)).collect(),
             operations_count: self.operations_count,
             simplified: true,
+            loop_bound_reached: self.loop_bound_reached,
         }
     }
 
+    /// Values left on the stack
+    pub fn final_values(&self) -> &[SymbolicValue] {
+        self.stack.get_stack()
+    }
+
     /// Initialize stack with symbolic inputs
     pub fn initialize_inputs(&mut self, count: usize) {
         for i in 0..count {
@@ -212,6 +447,53 @@ impl SymbolicExecutor {
             self.stack.push(var);
         }
     }
+
+    /// Limit the number of words executed, to bound recursion
+    pub fn with_max_operations(mut self, max_operations: usize) -> Self {
+        self.max_operations = max_operations;
+        self
+    }
+
+    /// Unroll each loop at most `loop_unroll` times along a path whose
+    /// exit condition is not known
+    pub fn with_loop_unroll(mut self, loop_unroll: usize) -> Self {
+        self.loop_unroll = loop_unroll;
+        self
+    }
+
+    /// Fold arithmetic on known values as code compiled under
+    /// `arithmetic` computes it
+    pub fn with_arithmetic(mut self, arithmetic: Arithmetic) -> Self {
+        self.arithmetic = arithmetic;
+        self
+    }
+
+    /// Number of symbolic iterations unrolled per loop
+    pub fn loop_unroll(&self) -> usize {
+        self.loop_unroll
+    }
+
+    /// Whether a loop was cut off at the unroll bound since the last call
+    pub fn loop_bound_reached(&self) -> bool {
+        self.loop_bound_reached
+    }
+
+    /// Make a definition callable by name
+    pub fn define(&mut self, def: &Definition) {
+        self.definitions.insert(def.name.clone(), def.clone());
+    }
+
+    /// Run one word on `args` and return the resulting stack
+    pub fn call(&mut self, name: &str, args: Vec<SymbolicValue>) -> Result<Vec<SymbolicValue>> {
+        self.stack.clear();
+        self.operations_count = 0;
+        self.loop_bound_reached = false;
+        for arg in args {
+            self.stack.push(arg);
+        }
+        self.execute_builtin(name)?;
+        Ok(self.stack.get_stack().to_vec())
+    }
 }
 
 impl Clone for SymbolicExecutor {
@@ -221,6 +503,10 @@ impl Clone for SymbolicExecutor {
             definitions: self.definitions.clone(),
             operations_count: self.operations_count,
             max_operations: self.max_operations,
+            loop_indices: self.loop_indices.clone(),
+            loop_unroll: self.loop_unroll,
+            loop_bound_reached: self.loop_bound_reached,
+            arithmetic: self.arithmetic,
         }
     }
 }
@@ -248,6 +534,28 @@ mod tests {
         assert_eq!(result, SymbolicValue::concrete(5));
     }
 
+    #[test]
+    fn test_arithmetic_semantics_decide_what_folds() {
+        use fastforth_frontend::{Division, Overflow};
+
+        let run = |arithmetic: Arithmetic, a: i64, b: i64, word: &str| {
+            let mut executor = SymbolicExecutor::new().with_arithmetic(arithmetic);
+            executor.stack.push(SymbolicValue::concrete(a));
+            executor.stack.push(SymbolicValue::concrete(b));
+            executor.execute_builtin(word).unwrap();
+            executor.stack.pop().unwrap()
+        };
+        let floored = Arithmetic { division: Division::Floored, ..Arithmetic::default() };
+        assert_eq!(run(Arithmetic::default(), -7, 2, "/"), SymbolicValue::concrete(-3));
+        assert_eq!(run(floored, -7, 2, "/"), SymbolicValue::concrete(-4));
+        assert_eq!(run(floored, -7, 2, "mod"), SymbolicValue::concrete(1));
+
+        // A trapping overflow has no value to fold to
+        let trapping = Arithmetic { overflow: Overflow::Trapping, ..Arithmetic::default() };
+        assert_eq!(run(Arithmetic::default(), i64::MAX, 1, "+"), SymbolicValue::concrete(i64::MIN));
+        assert!(matches!(run(trapping, i64::MAX, 1, "+"), SymbolicValue::BinaryOp { .. }));
+    }
+
     #[test]
     fn test_execute_dup() {
         let mut executor = SymbolicExecutor::new();
@@ -261,6 +569,51 @@ mod tests {
         assert_eq!(val1, val2);
     }
 
+    #[test]
+    fn test_if_merges_both_branches() {
+        let program = parse_program(": my-abs dup 0 < if negate then ;").unwrap();
+        let mut executor = SymbolicExecutor::new();
+        executor.define(&program.definitions[0]);
+
+        let x = SymbolicValue::variable("x".to_string(), 0);
+        let result = executor.call("my-abs", vec![x.clone()]).unwrap();
+        assert_eq!(result.len(), 1);
+        assert!(matches!(result[0], SymbolicValue::Conditional { .. }));
+
+        // Known conditions only run the branch taken
+        let result = executor.call("my-abs", vec![SymbolicValue::concrete(-4)]).unwrap();
+        assert_eq!(result, vec![SymbolicValue::concrete(4)]);
+    }
+
+    #[test]
+    fn test_loops_unroll_to_a_bound() {
+        let program = parse_program(
+            ": countdown begin 1- dup 0= until ; : sum 0 swap 0 do i + loop ;",
+        ).unwrap();
+        let mut executor = SymbolicExecutor::new().with_loop_unroll(3);
+        for def in &program.definitions {
+            executor.define(def);
+        }
+
+        // Known trip counts run to completion
+        let result = executor.call("sum", vec![SymbolicValue::concrete(4)]).unwrap();
+        assert_eq!(result, vec![SymbolicValue::concrete(6)]);
+        assert!(!executor.loop_bound_reached());
+
+        // Unknown ones are merged up to the bound, then cut off
+        let n = SymbolicValue::variable("n".to_string(), 0);
+        let result = executor.call("countdown", vec![n.clone()]).unwrap();
+        assert_eq!(result.len(), 1);
+        assert!(executor.loop_bound_reached());
+
+        let inputs = |_: &str, _: usize| Some(2);
+        assert_eq!(result[0].evaluate(&inputs), Some(0));
+
+        let result = executor.call("sum", vec![n]).unwrap();
+        assert!(executor.loop_bound_reached());
+        assert_eq!(result[0].evaluate(&inputs), Some(1));
+    }
+
     #[test]
     fn test_symbolic_square() {
         let program = parse_program(": square dup * ; square").unwrap();
@@ -1,14 +1,21 @@
 //! Symbolic Execution Engine
 //!
-//! Enables equivalence checking and semantic comparison via symbolic execution
+//! Enables equivalence checking, semantic comparison and property
+//! verification via symbolic execution
 
 pub mod executor;
 pub mod symbolic_value;
 pub mod equivalence;
+pub mod properties;
+#[cfg(feature = "smt")]
+pub mod smt;
 
-pub use executor::{SymbolicExecutor, ExecutionResult};
+pub use executor::{SymbolicExecutor, ExecutionResult, DEFAULT_LOOP_UNROLL};
 pub use symbolic_value::{SymbolicValue, SymbolicStack};
-pub use equivalence::{EquivalenceChecker, EquivalenceResult};
+pub use equivalence::{EquivalenceChecker, EquivalenceResult, EquivalenceVerdict};
+pub use properties::{PropertyCheck, PropertyChecker, PropertyStatus};
+#[cfg(feature = "smt")]
+pub use smt::{SmtOutcome, SmtSolver};
 
 use thiserror::Error;
 
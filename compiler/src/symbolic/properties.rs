@@ -0,0 +1,495 @@
+//! Property Verification
+//!
+//! Checks the properties a specification declares for a word against an
+//! implementation of it. A property is either a keyword (`commutative`,
+//! `associative`, `idempotent`, `involution`) or an equation such as
+//! `gcd(a, b) = gcd(b, a)` or `square(0) = 0`. Both sides are executed
+//! symbolically; equal normal forms prove the property, and otherwise a
+//! grid of concrete inputs is searched for a counterexample. With the
+//! `smt` feature an SMT solver is asked first, before sampling.
+
+use super::symbolic_value::{BinaryOperator, UnaryOperator};
+#[cfg(feature = "smt")]
+use super::smt::{SmtOutcome, SmtSolver};
+use super::{SymbolicExecutor, SymbolicValue};
+use fastforth_frontend::Program;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Values tried for each variable when a property cannot be proved
+const SAMPLE_VALUES: [i64; 8] = [-7, -2, -1, 0, 1, 2, 3, 10];
+
+/// Most variable assignments tried per property
+const MAX_SAMPLES: usize = 1000;
+
+/// Words executed per call before giving up, which bounds recursion
+const MAX_OPERATIONS: usize = 2000;
+
+/// Outcome of checking one property
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PropertyStatus {
+    /// Both sides have the same normal form for all inputs
+    Verified,
+    /// Some input makes the two sides differ
+    Falsified { counterexample: String },
+    /// Neither proved nor refuted
+    Unknown { reason: String },
+}
+
+impl fmt::Display for PropertyStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PropertyStatus::Verified => write!(f, "verified"),
+            PropertyStatus::Falsified { counterexample } => write!(f, "falsified: {}", counterexample),
+            PropertyStatus::Unknown { reason } => write!(f, "unknown: {}", reason),
+        }
+    }
+}
+
+/// A declared property and what checking it found
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PropertyCheck {
+    pub property: String,
+    #[serde(flatten)]
+    pub status: PropertyStatus,
+}
+
+/// Formats as `commutative [verified]`, the form used in Forth comments
+impl fmt::Display for PropertyCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]", self.property, self.status)
+    }
+}
+
+impl std::str::FromStr for PropertyCheck {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (property, status) = s
+            .trim()
+            .strip_suffix(']')
+            .and_then(|s| s.rsplit_once(" ["))
+            .ok_or_else(|| format!("Invalid property check: {}", s))?;
+        let status = match status.split_once(": ") {
+            None if status == "verified" => PropertyStatus::Verified,
+            Some(("falsified", detail)) => PropertyStatus::Falsified { counterexample: detail.to_string() },
+            Some(("unknown", detail)) => PropertyStatus::Unknown { reason: detail.to_string() },
+            _ => return Err(format!("Invalid property status: {}", status)),
+        };
+        Ok(PropertyCheck { property: property.to_string(), status })
+    }
+}
+
+/// Checks properties of the words defined in a program
+pub struct PropertyChecker {
+    executor: SymbolicExecutor,
+    #[cfg(feature = "smt")]
+    solver: Option<SmtSolver>,
+}
+
+impl PropertyChecker {
+    pub fn new(program: &Program) -> Self {
+        let mut executor = SymbolicExecutor::new().with_max_operations(MAX_OPERATIONS);
+        for def in &program.definitions {
+            executor.define(def);
+        }
+        Self {
+            executor,
+            #[cfg(feature = "smt")]
+            solver: Some(SmtSolver::from_env()),
+        }
+    }
+
+    /// Ask `solver` before sampling, or never if `None`
+    #[cfg(feature = "smt")]
+    pub fn with_solver(mut self, solver: Option<SmtSolver>) -> Self {
+        self.solver = solver;
+        self
+    }
+
+    /// Check `property` of `word`, which takes `inputs` stack items
+    pub fn check(&self, word: &str, inputs: usize, property: &str) -> PropertyCheck {
+        PropertyCheck { property: property.to_string(), status: self.status(word, inputs, property) }
+    }
+
+    fn status(&self, word: &str, inputs: usize, property: &str) -> PropertyStatus {
+        let sides = equation(word, inputs, property).and_then(|(lhs, rhs)| Ok((self.eval(&lhs)?, self.eval(&rhs)?)));
+        match sides {
+            Ok((lhs, rhs)) => {
+                #[cfg(feature = "smt")]
+                if let Some(status) = self.decide(&lhs, &rhs) {
+                    return status;
+                }
+                compare(&lhs, &rhs)
+            }
+            Err(reason) => PropertyStatus::Unknown { reason },
+        }
+    }
+
+    /// The solver's verdict, if it reaches one
+    #[cfg(feature = "smt")]
+    fn decide(&self, lhs: &SymbolicValue, rhs: &SymbolicValue) -> Option<PropertyStatus> {
+        let solver = self.solver.as_ref()?;
+        if lhs.canonical() == rhs.canonical() {
+            return None;
+        }
+
+        match solver.prove_equal(std::slice::from_ref(lhs), std::slice::from_ref(rhs)) {
+            SmtOutcome::Equal => Some(PropertyStatus::Verified),
+            SmtOutcome::Differ(model) => {
+                let value = |name: &str, _: usize| model.iter().find(|((n, _), _)| n == name).map(|(_, v)| *v);
+                let (left, right) = (lhs.evaluate(&value)?, rhs.evaluate(&value)?);
+                let assignment: Vec<String> =
+                    model.iter().map(|((name, _), value)| format!("{} = {}", name, value)).collect();
+                Some(PropertyStatus::Falsified { counterexample: counterexample(&assignment, left, right) })
+            }
+            SmtOutcome::Unknown(_) => None,
+        }
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<SymbolicValue, String> {
+        Ok(match expr {
+            Expr::Num(n) => SymbolicValue::concrete(*n),
+            Expr::Var(name) => SymbolicValue::variable(name.clone(), 0),
+            Expr::Neg(value) => SymbolicValue::unary_op(UnaryOperator::Negate, self.eval(value)?).simplify(),
+            Expr::Bin(op, left, right) => SymbolicValue::binary_op(*op, self.eval(left)?, self.eval(right)?).simplify(),
+            Expr::Call(name, args) => {
+                let args = args.iter().map(|arg| self.eval(arg)).collect::<Result<Vec<_>, _>>()?;
+                let mut executor = self.executor.clone();
+                let mut results = executor.call(name, args).map_err(|e| e.to_string())?;
+                if executor.loop_bound_reached() {
+                    return Err(format!("unknown beyond {} loop iterations in {}", executor.loop_unroll(), name));
+                }
+                if results.len() != 1 {
+                    return Err(format!("{} leaves {} values, not one", name, results.len()));
+                }
+                results.remove(0)
+            }
+        })
+    }
+}
+
+/// Prove equal, or search sampled inputs for a counterexample
+fn compare(lhs: &SymbolicValue, rhs: &SymbolicValue) -> PropertyStatus {
+    if lhs.canonical() == rhs.canonical() {
+        return PropertyStatus::Verified;
+    }
+
+    let mut names = BTreeSet::new();
+    variables(lhs, &mut names);
+    variables(rhs, &mut names);
+    let names: Vec<String> = names.into_iter().collect();
+
+    let mut digits = vec![0usize; names.len()];
+    let mut checked = 0;
+    for _ in 0..MAX_SAMPLES {
+        let values: Vec<i64> = digits.iter().map(|&d| SAMPLE_VALUES[d]).collect();
+        let inputs = |name: &str, _: usize| names.iter().position(|n| n == name).map(|i| values[i]);
+        if let (Some(left), Some(right)) = (lhs.evaluate(&inputs), rhs.evaluate(&inputs)) {
+            if left != right {
+                let assignment: Vec<String> =
+                    names.iter().zip(&values).map(|(name, value)| format!("{} = {}", name, value)).collect();
+                return PropertyStatus::Falsified { counterexample: counterexample(&assignment, left, right) };
+            }
+            checked += 1;
+        }
+
+        // Next assignment, odometer style
+        let Some(i) = digits.iter().rposition(|&d| d + 1 < SAMPLE_VALUES.len()) else {
+            break;
+        };
+        digits[i] += 1;
+        digits[i + 1..].iter_mut().for_each(|d| *d = 0);
+    }
+
+    PropertyStatus::Unknown {
+        reason: if checked == 0 {
+            "could not be evaluated on any sampled input".to_string()
+        } else {
+            format!("not proved; held on {} sampled inputs", checked)
+        },
+    }
+}
+
+fn counterexample(assignment: &[String], left: i64, right: i64) -> String {
+    if assignment.is_empty() {
+        format!("{} <> {}", left, right)
+    } else {
+        format!("{} gives {} <> {}", assignment.join(", "), left, right)
+    }
+}
+
+fn variables(value: &SymbolicValue, names: &mut BTreeSet<String>) {
+    match value {
+        SymbolicValue::Concrete(_) => {}
+        SymbolicValue::Variable { name, .. } => {
+            names.insert(name.clone());
+        }
+        SymbolicValue::BinaryOp { left, right, .. } => {
+            variables(left, names);
+            variables(right, names);
+        }
+        SymbolicValue::UnaryOp { value, .. } => variables(value, names),
+        SymbolicValue::Conditional { condition, then_val, else_val } => {
+            variables(condition, names);
+            variables(then_val, names);
+            variables(else_val, names);
+        }
+    }
+}
+
+/// Side of a property equation
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Num(i64),
+    Var(String),
+    Neg(Box<Expr>),
+    Bin(BinaryOperator, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+fn call(word: &str, args: Vec<Expr>) -> Expr {
+    Expr::Call(word.to_string(), args)
+}
+
+fn var(name: &str) -> Expr {
+    Expr::Var(name.to_string())
+}
+
+/// The two sides a property claims are equal
+fn equation(word: &str, inputs: usize, property: &str) -> Result<(Expr, Expr), String> {
+    let keyword = property.trim().to_lowercase();
+    let needs = |count: usize| {
+        if inputs == count {
+            Ok(())
+        } else {
+            Err(format!("{} needs a word with {} input(s), {} has {}", keyword, count, word, inputs))
+        }
+    };
+
+    match keyword.as_str() {
+        "commutative" => {
+            needs(2)?;
+            Ok((call(word, vec![var("a"), var("b")]), call(word, vec![var("b"), var("a")])))
+        }
+        "associative" => {
+            needs(2)?;
+            Ok((
+                call(word, vec![call(word, vec![var("a"), var("b")]), var("c")]),
+                call(word, vec![var("a"), call(word, vec![var("b"), var("c")])]),
+            ))
+        }
+        "idempotent" => {
+            needs(1)?;
+            Ok((call(word, vec![call(word, vec![var("x")])]), call(word, vec![var("x")])))
+        }
+        "involution" | "involutive" | "self-inverse" => {
+            needs(1)?;
+            Ok((call(word, vec![call(word, vec![var("x")])]), var("x")))
+        }
+        _ if property.contains('=') => Parser::new(property, word).equation(),
+        _ => Err("not an equation or a known property".to_string()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(i64),
+    Name(String),
+    Op(char),
+}
+
+/// Recursive descent parser for `lhs = rhs`
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(text: &str, word: &str) -> Self {
+        Self { tokens: tokenize(text, word), pos: 0 }
+    }
+
+    fn equation(mut self) -> Result<(Expr, Expr), String> {
+        let lhs = self.expr()?;
+        self.expect('=')?;
+        let rhs = self.expr()?;
+        match self.tokens.get(self.pos) {
+            None => Ok((lhs, rhs)),
+            Some(Token::Name(name)) if matches!(name.as_str(), "if" | "for" | "when" | "where" | "unless") => {
+                Err("conditional properties are not checked".to_string())
+            }
+            Some(token) => Err(format!("unexpected {:?} in equation", token)),
+        }
+    }
+
+    fn expect(&mut self, op: char) -> Result<(), String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(c)) if *c == op => {
+                self.pos += 1;
+                Ok(())
+            }
+            other => Err(format!("expected '{}', found {:?}", op, other)),
+        }
+    }
+
+    fn binary(&mut self, ops: &[(char, BinaryOperator)], next: fn(&mut Self) -> Result<Expr, String>) -> Result<Expr, String> {
+        let mut left = next(self)?;
+        loop {
+            let op = match self.tokens.get(self.pos) {
+                Some(Token::Op(c)) => ops.iter().find(|(symbol, _)| symbol == c).map(|(_, op)| *op),
+                Some(Token::Name(name)) if name == "mod" => ops.iter().find(|(symbol, _)| *symbol == '%').map(|(_, op)| *op),
+                _ => None,
+            };
+            let Some(op) = op else {
+                return Ok(left);
+            };
+            self.pos += 1;
+            left = Expr::Bin(op, Box::new(left), Box::new(next(self)?));
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        self.binary(&[('+', BinaryOperator::Add), ('-', BinaryOperator::Sub)], Self::term)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        self.binary(&[('*', BinaryOperator::Mul), ('/', BinaryOperator::Div), ('%', BinaryOperator::Mod)], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.tokens.get(self.pos) == Some(&Token::Op('-')) {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or("equation ends early")?;
+        self.pos += 1;
+        match token {
+            Token::Num(n) => Ok(Expr::Num(n)),
+            Token::Op('(') => {
+                let inner = self.expr()?;
+                self.expect(')')?;
+                Ok(inner)
+            }
+            Token::Name(name) if self.tokens.get(self.pos) == Some(&Token::Op('(')) => {
+                self.pos += 1;
+                let mut args = Vec::new();
+                if self.tokens.get(self.pos) != Some(&Token::Op(')')) {
+                    args.push(self.expr()?);
+                    while self.tokens.get(self.pos) == Some(&Token::Op(',')) {
+                        self.pos += 1;
+                        args.push(self.expr()?);
+                    }
+                }
+                self.expect(')')?;
+                Ok(Expr::Call(name, args))
+            }
+            Token::Name(name) => Ok(Expr::Var(name)),
+            Token::Op(c) => Err(format!("unexpected '{}'", c)),
+        }
+    }
+}
+
+/// Split an equation into tokens
+///
+/// `word` is matched whole wherever it is called, since Forth names such
+/// as `sum-squares` would otherwise read as a subtraction.
+fn tokenize(text: &str, word: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim();
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = rest.trim_start();
+        } else if rest.starts_with(word) && rest[word.len()..].trim_start().starts_with('(') {
+            tokens.push(Token::Name(word.to_string()));
+            rest = &rest[word.len()..];
+        } else if c.is_ascii_digit() {
+            let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            match rest[..end].parse() {
+                Ok(n) => tokens.push(Token::Num(n)),
+                Err(_) => tokens.push(Token::Op('?')),
+            }
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '?' | '!')))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            tokens.push(Token::Op(c));
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastforth_frontend::parse_program;
+
+    fn status(source: &str, word: &str, inputs: usize, property: &str) -> PropertyStatus {
+        let program = parse_program(source).unwrap();
+        PropertyChecker::new(&program).check(word, inputs, property).status
+    }
+
+    #[test]
+    fn test_keyword_properties() {
+        assert_eq!(status(": add + ;", "add", 2, "commutative"), PropertyStatus::Verified);
+        assert_eq!(status(": add + ;", "add", 2, "associative"), PropertyStatus::Verified);
+        assert_eq!(status(": hyp dup * swap dup * + ;", "hyp", 2, "commutative"), PropertyStatus::Verified);
+        assert_eq!(status(": my-abs abs ;", "my-abs", 1, "idempotent"), PropertyStatus::Verified);
+        assert_eq!(status(": neg negate ;", "neg", 1, "involution"), PropertyStatus::Verified);
+
+        let PropertyStatus::Falsified { counterexample } = status(": diff - ;", "diff", 2, "commutative") else {
+            panic!("subtraction is not commutative");
+        };
+        assert!(counterexample.contains("a = "), "{}", counterexample);
+
+        assert!(matches!(status(": sq dup * ;", "sq", 1, "commutative"), PropertyStatus::Unknown { .. }));
+    }
+
+    #[test]
+    fn test_equations() {
+        let square = ": square ( n -- n2 ) dup * ;";
+        assert_eq!(status(square, "square", 1, "square(n) = n * n"), PropertyStatus::Verified);
+        assert_eq!(status(square, "square", 1, "square(-n) = square(n)"), PropertyStatus::Verified);
+        assert_eq!(status(square, "square", 1, "square(0) = 0"), PropertyStatus::Verified);
+        assert!(matches!(status(square, "square", 1, "square(2) = 5"), PropertyStatus::Falsified { .. }));
+
+        // Both branches of the IF are followed
+        let abs = ": my-abs ( n -- n ) dup 0 < if negate then ;";
+        assert_eq!(status(abs, "my-abs", 1, "my-abs(3) = 3"), PropertyStatus::Verified);
+        assert_eq!(status(abs, "my-abs", 1, "my-abs(-3) = 3"), PropertyStatus::Verified);
+        assert!(matches!(status(abs, "my-abs", 1, "my-abs(n) = n"), PropertyStatus::Falsified { .. }));
+        assert!(matches!(status(abs, "my-abs", 1, "my-abs(n) = n if n >= 0"), PropertyStatus::Unknown { .. }));
+
+        let sum = ": sum-sq ( a b -- c ) dup * swap dup * + ;";
+        assert_eq!(status(sum, "sum-sq", 2, "sum-sq(a, b) = sum-sq(b, a)"), PropertyStatus::Verified);
+        assert_eq!(status(sum, "sum-sq", 2, "sum-sq(a, 0) = a * a"), PropertyStatus::Verified);
+    }
+
+    #[test]
+    fn test_check_round_trips_through_comment_form() {
+        for check in [
+            PropertyCheck { property: "commutative".to_string(), status: PropertyStatus::Verified },
+            PropertyCheck {
+                property: "f(a) = a".to_string(),
+                status: PropertyStatus::Falsified { counterexample: "a = 1 gives 2 <> 1".to_string() },
+            },
+            PropertyCheck {
+                property: "x".to_string(),
+                status: PropertyStatus::Unknown { reason: "not an equation".to_string() },
+            },
+        ] {
+            assert_eq!(check.to_string().parse::<PropertyCheck>().unwrap(), check);
+        }
+    }
+}
@@ -0,0 +1,313 @@
+//! SMT Backend
+//!
+//! Discharges equivalence queries with an external SMT-LIB2 solver, so
+//! values the structural comparison cannot match (`x 2 *` and `x x +`,
+//! say) are decided with real 64-bit semantics: wrapping arithmetic,
+//! truncating division and Forth's -1/0 flags.
+//!
+//! The solver runs as a child process that reads the query on stdin. Z3
+//! is used unless `FIFTH_SMT_SOLVER` names another command line, e.g.
+//! `boolector --smt2 -m`. Inputs that divide by zero, or divide the most
+//! negative number by -1, on the path they take are left out of every
+//! query, since Forth traps on them.
+
+use super::symbolic_value::{BinaryOperator, UnaryOperator};
+use super::SymbolicValue;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Answer to "are these stacks equal for all inputs?"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SmtOutcome {
+    /// Equal for every input
+    Equal,
+    /// Different for these inputs, keyed by variable name and index
+    Differ(Vec<((String, usize), i64)>),
+    /// The solver gave up or could not be run
+    Unknown(String),
+}
+
+/// External SMT-LIB2 solver
+#[derive(Debug, Clone)]
+pub struct SmtSolver {
+    program: String,
+    args: Vec<String>,
+}
+
+impl SmtSolver {
+    /// Solver run as `program args...`, reading the query on stdin
+    pub fn new(program: impl Into<String>, args: Vec<String>) -> Self {
+        Self { program: program.into(), args }
+    }
+
+    /// Z3 with a ten second limit per query
+    pub fn z3() -> Self {
+        Self::new("z3", vec!["-in".to_string(), "-smt2".to_string(), "-T:10".to_string()])
+    }
+
+    /// Solver named by `FIFTH_SMT_SOLVER`, or Z3
+    pub fn from_env() -> Self {
+        let command = std::env::var("FIFTH_SMT_SOLVER").unwrap_or_default();
+        if command.trim().is_empty() || command.trim() == "z3" {
+            return Self::z3();
+        }
+        let mut words = command.split_whitespace().map(str::to_string);
+        let program = words.next().unwrap_or_default();
+        Self::new(program, words.collect())
+    }
+
+    /// Decide whether two stacks are equal for all inputs
+    pub fn prove_equal(&self, lhs: &[SymbolicValue], rhs: &[SymbolicValue]) -> SmtOutcome {
+        if lhs.len() != rhs.len() {
+            return SmtOutcome::Differ(Vec::new());
+        }
+        if lhs.is_empty() {
+            return SmtOutcome::Equal;
+        }
+
+        match self.run(&script(lhs, rhs)) {
+            Ok(output) => interpret(&output),
+            Err(e) => SmtOutcome::Unknown(format!("cannot run {}: {}", self.program, e)),
+        }
+    }
+
+    fn run(&self, script: &str) -> std::io::Result<String> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(script.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+impl Default for SmtSolver {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
+
+/// SMT-LIB2 query that is satisfiable exactly when the stacks differ
+pub fn script(lhs: &[SymbolicValue], rhs: &[SymbolicValue]) -> String {
+    let mut query = Query::default();
+    let differs: Vec<String> = lhs
+        .iter()
+        .zip(rhs)
+        .map(|(l, r)| format!("(distinct {} {})", query.term(l, "true"), query.term(r, "true")))
+        .collect();
+
+    let mut out = String::from("(set-logic QF_BV)\n(set-option :produce-models true)\n");
+    for var in &query.vars {
+        out.push_str(&format!("(declare-const {} (_ BitVec 64))\n", var));
+    }
+    for guard in &query.guards {
+        out.push_str(&format!("(assert {})\n", guard));
+    }
+    out.push_str(&format!("(assert (or {}))\n(check-sat)\n", differs.join(" ")));
+    if !query.vars.is_empty() {
+        let vars: Vec<&str> = query.vars.iter().map(String::as_str).collect();
+        out.push_str(&format!("(get-value ({}))\n", vars.join(" ")));
+    }
+    out.push_str("(exit)\n");
+    out
+}
+
+/// Terms, declarations and division guards of a query being built
+#[derive(Default)]
+struct Query {
+    vars: BTreeSet<String>,
+    guards: Vec<String>,
+}
+
+impl Query {
+    /// Translate `value`, reached when `path` holds
+    fn term(&mut self, value: &SymbolicValue, path: &str) -> String {
+        match value {
+            SymbolicValue::Concrete(n) => bv(*n),
+            SymbolicValue::Variable { name, index } => {
+                let var = format!("|{}_{}|", name.replace('|', "_"), index);
+                self.vars.insert(var.clone());
+                var
+            }
+            SymbolicValue::BinaryOp { op, left, right } => {
+                let a = self.term(left, path);
+                let b = self.term(right, path);
+                match op {
+                    BinaryOperator::Add => format!("(bvadd {} {})", a, b),
+                    BinaryOperator::Sub => format!("(bvsub {} {})", a, b),
+                    BinaryOperator::Mul => format!("(bvmul {} {})", a, b),
+                    BinaryOperator::Div | BinaryOperator::Mod => {
+                        self.guards.push(format!(
+                            "(=> {} (and (distinct {} {}) (not (and (= {} {}) (= {} {})))))",
+                            path, b, bv(0), a, bv(i64::MIN), b, bv(-1)
+                        ));
+                        let op = if *op == BinaryOperator::Div { "bvsdiv" } else { "bvsrem" };
+                        format!("({} {} {})", op, a, b)
+                    }
+                    BinaryOperator::And => format!("(bvand {} {})", a, b),
+                    BinaryOperator::Or => format!("(bvor {} {})", a, b),
+                    BinaryOperator::Lt => flag(format!("(bvslt {} {})", a, b)),
+                    BinaryOperator::Gt => flag(format!("(bvsgt {} {})", a, b)),
+                    BinaryOperator::Lte => flag(format!("(bvsle {} {})", a, b)),
+                    BinaryOperator::Gte => flag(format!("(bvsge {} {})", a, b)),
+                    BinaryOperator::Eq => flag(format!("(= {} {})", a, b)),
+                    BinaryOperator::Neq => flag(format!("(distinct {} {})", a, b)),
+                    BinaryOperator::Xor => format!("(bvxor {} {})", a, b),
+                    BinaryOperator::Shl => format!("(bvshl {} (bvand {} {}))", a, b, bv(63)),
+                    BinaryOperator::Shr => format!("(bvlshr {} (bvand {} {}))", a, b, bv(63)),
+                    BinaryOperator::ULt => flag(format!("(bvult {} {})", a, b)),
+                    BinaryOperator::UGt => flag(format!("(bvugt {} {})", a, b)),
+                    BinaryOperator::UDiv | BinaryOperator::UMod => {
+                        self.guards.push(format!("(=> {} (distinct {} {}))", path, b, bv(0)));
+                        let op = if *op == BinaryOperator::UDiv { "bvudiv" } else { "bvurem" };
+                        format!("({} {} {})", op, a, b)
+                    }
+                    BinaryOperator::UMul => format!("(bvmul {} {})", a, b),
+                    BinaryOperator::Rotl | BinaryOperator::Rotr => {
+                        let (towards, back) = if *op == BinaryOperator::Rotl { ("bvshl", "bvlshr") } else { ("bvlshr", "bvshl") };
+                        format!(
+                            "(bvor ({} {} (bvand {} {})) ({} {} (bvand (bvneg {}) {})))",
+                            towards, a, b, bv(63), back, a, b, bv(63)
+                        )
+                    }
+                    BinaryOperator::UMulHigh => format!(
+                        "((_ extract 127 64) (bvmul ((_ zero_extend 64) {}) ((_ zero_extend 64) {})))",
+                        a, b
+                    ),
+                }
+            }
+            SymbolicValue::UnaryOp { op, value } => {
+                let v = self.term(value, path);
+                match op {
+                    UnaryOperator::Negate => format!("(bvneg {})", v),
+                    UnaryOperator::Abs => format!("(ite (bvslt {} {}) (bvneg {}) {})", v, bv(0), v, v),
+                    UnaryOperator::Not => flag(format!("(= {} {})", v, bv(0))),
+                    UnaryOperator::Popcount | UnaryOperator::Ctz | UnaryOperator::Clz => bit_count(*op, &v),
+                }
+            }
+            SymbolicValue::Conditional { condition, then_val, else_val } => {
+                let c = self.term(condition, path);
+                let taken = format!("(distinct {} {})", c, bv(0));
+                let then_path = format!("(and {} {})", path, taken);
+                let else_path = format!("(and {} (not {}))", path, taken);
+                let t = self.term(then_val, &then_path);
+                let e = self.term(else_val, &else_path);
+                format!("(ite {} {} {})", taken, t, e)
+            }
+        }
+    }
+}
+
+fn bv(n: i64) -> String {
+    format!("#x{:016x}", n as u64)
+}
+
+/// `popcount`, `ctz` or `clz` of a term, bit by bit, since SMT-LIB has
+/// no such operations on bit-vectors
+fn bit_count(op: UnaryOperator, v: &str) -> String {
+    let bit = |i: i64| format!("(= ((_ extract {} {}) ?bits) #b1)", i, i);
+    let body = match op {
+        UnaryOperator::Popcount => {
+            let bits: Vec<String> = (0..64).map(|i| format!("(ite {} {} {})", bit(i), bv(1), bv(0))).collect();
+            format!("(bvadd {})", bits.join(" "))
+        }
+        // The outermost test is of the lowest bit for `ctz` and the highest for `clz`
+        UnaryOperator::Ctz => (0..64).rev().fold(bv(64), |rest, i| format!("(ite {} {} {})", bit(i), bv(i), rest)),
+        _ => (0..64).fold(bv(64), |rest, i| format!("(ite {} {} {})", bit(i), bv(63 - i), rest)),
+    };
+    format!("(let ((?bits {})) {})", v, body)
+}
+
+/// Forth flag, -1 or 0, for a boolean term
+fn flag(condition: String) -> String {
+    format!("(ite {} {} {})", condition, bv(-1), bv(0))
+}
+
+/// Read the solver's answer and, if the stacks differ, its model
+fn interpret(output: &str) -> SmtOutcome {
+    let mut lines = output.lines().map(str::trim).filter(|line| !line.is_empty());
+    match lines.next() {
+        Some("unsat") => SmtOutcome::Equal,
+        Some("sat") => {
+            let model: String = lines.collect::<Vec<_>>().join(" ");
+            SmtOutcome::Differ(parse_model(&model))
+        }
+        Some(other) => SmtOutcome::Unknown(format!("solver answered {}", other)),
+        None => SmtOutcome::Unknown("solver gave no answer".to_string()),
+    }
+}
+
+/// `((|x_0| #x...) (|y_0| (_ bv3 64)))` as variable values
+fn parse_model(model: &str) -> Vec<((String, usize), i64)> {
+    let mut values = Vec::new();
+    let mut rest = model;
+    while let Some(start) = rest.find('|') {
+        let Some(len) = rest[start + 1..].find('|') else { break };
+        let symbol = &rest[start + 1..start + 1 + len];
+        rest = &rest[start + len + 2..];
+
+        let value = rest.trim_start();
+        let end = if value.starts_with("(_") {
+            value.find(')').map(|i| i + 1)
+        } else {
+            value.find(|c: char| c == ')' || c.is_whitespace())
+        };
+        let Some(end) = end else { break };
+        if let (Some((name, index)), Some(n)) = (symbol.rsplit_once('_'), parse_bv(&value[..end])) {
+            if let Ok(index) = index.parse() {
+                values.push(((name.to_string(), index), n));
+            }
+        }
+        rest = &value[end..];
+    }
+    values
+}
+
+fn parse_bv(literal: &str) -> Option<i64> {
+    let bits = if let Some(hex) = literal.strip_prefix("#x") {
+        u64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = literal.strip_prefix("#b") {
+        u64::from_str_radix(bin, 2).ok()?
+    } else {
+        let decimal = literal.strip_prefix("(_ bv")?.split_whitespace().next()?;
+        decimal.parse::<u64>().ok()?
+    };
+    Some(bits as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn x() -> SymbolicValue {
+        SymbolicValue::variable("x".to_string(), 0)
+    }
+
+    #[test]
+    fn test_script_guards_division() {
+        let halved = SymbolicValue::binary_op(BinaryOperator::Div, x(), SymbolicValue::concrete(2));
+        let script = script(&[halved], &[x()]);
+
+        assert!(script.contains("(declare-const |x_0| (_ BitVec 64))"));
+        assert!(script.contains("(bvsdiv |x_0| #x0000000000000002)"));
+        assert!(script.contains("(assert (=> true (and (distinct #x0000000000000002 #x0000000000000000)"));
+        assert!(script.contains("(get-value (|x_0|))"));
+    }
+
+    #[test]
+    fn test_interpret_solver_answers() {
+        assert_eq!(interpret("unsat\n(error \"model is not available\")\n"), SmtOutcome::Equal);
+        assert_eq!(
+            interpret("sat\n((|x_0| #xffffffffffffffff)\n (|in_1| (_ bv3 64)))\n"),
+            SmtOutcome::Differ(vec![(("x".to_string(), 0), -1), (("in".to_string(), 1), 3)])
+        );
+        assert!(matches!(interpret("unknown\n"), SmtOutcome::Unknown(_)));
+    }
+}
@@ -2,6 +2,7 @@
 //!
 //! Represents values symbolically for execution analysis
 
+use fastforth_frontend::{umul_high, Arithmetic};
 use std::fmt;
 
 /// Symbolic value for abstract execution
@@ -65,28 +66,35 @@ impl SymbolicValue {
         }
     }
 
+    /// Create a conditional value
+    pub fn conditional(condition: SymbolicValue, then_val: SymbolicValue, else_val: SymbolicValue) -> Self {
+        SymbolicValue::Conditional {
+            condition: Box::new(condition),
+            then_val: Box::new(then_val),
+            else_val: Box::new(else_val),
+        }
+    }
+
     /// Simplify the symbolic value
     pub fn simplify(&self) -> SymbolicValue {
+        self.simplify_with(Arithmetic::default())
+    }
+
+    /// Simplify the symbolic value, folding constants under `arithmetic`
+    pub fn simplify_with(&self, arithmetic: Arithmetic) -> SymbolicValue {
         match self {
             SymbolicValue::BinaryOp { op, left, right } => {
-                let left = left.simplify();
-                let right = right.simplify();
+                let left = left.simplify_with(arithmetic);
+                let right = right.simplify_with(arithmetic);
 
-                match (op, &left, &right) {
-                    // Constant folding
-                    (BinaryOperator::Add, SymbolicValue::Concrete(a), SymbolicValue::Concrete(b)) => {
-                        SymbolicValue::Concrete(a + b)
-                    }
-                    (BinaryOperator::Sub, SymbolicValue::Concrete(a), SymbolicValue::Concrete(b)) => {
-                        SymbolicValue::Concrete(a - b)
-                    }
-                    (BinaryOperator::Mul, SymbolicValue::Concrete(a), SymbolicValue::Concrete(b)) => {
-                        SymbolicValue::Concrete(a * b)
-                    }
-                    (BinaryOperator::Div, SymbolicValue::Concrete(a), SymbolicValue::Concrete(b)) if *b != 0 => {
-                        SymbolicValue::Concrete(a / b)
+                // Constant folding
+                if let (SymbolicValue::Concrete(a), SymbolicValue::Concrete(b)) = (&left, &right) {
+                    if let Some(value) = op.apply_with(arithmetic, *a, *b) {
+                        return SymbolicValue::Concrete(value);
                     }
+                }
 
+                match (op, &left, &right) {
                     // Algebraic identities
                     (BinaryOperator::Add, _, SymbolicValue::Concrete(0)) => left,
                     (BinaryOperator::Add, SymbolicValue::Concrete(0), _) => right,
@@ -103,10 +111,17 @@ impl SymbolicValue {
                 }
             }
             SymbolicValue::UnaryOp { op, value } => {
-                let value = value.simplify();
+                let value = value.simplify_with(arithmetic);
                 match (op, &value) {
-                    (UnaryOperator::Negate, SymbolicValue::Concrete(v)) => {
-                        SymbolicValue::Concrete(-v)
+                    (_, SymbolicValue::Concrete(v)) => SymbolicValue::Concrete(op.apply(*v)),
+
+                    // --x = x, abs abs x = abs x, abs -x = abs x
+                    (UnaryOperator::Negate, SymbolicValue::UnaryOp { op: UnaryOperator::Negate, value: inner }) => {
+                        (**inner).clone()
+                    }
+                    (UnaryOperator::Abs, SymbolicValue::UnaryOp { op: UnaryOperator::Abs, .. }) => value,
+                    (UnaryOperator::Abs, SymbolicValue::UnaryOp { op: UnaryOperator::Negate, value: inner }) => {
+                        SymbolicValue::unary_op(UnaryOperator::Abs, (**inner).clone())
                     }
                     _ => SymbolicValue::UnaryOp {
                         op: *op,
@@ -114,9 +129,91 @@ impl SymbolicValue {
                     },
                 }
             }
+            SymbolicValue::Conditional { condition, then_val, else_val } => {
+                let condition = condition.simplify_with(arithmetic);
+                let then_val = then_val.simplify_with(arithmetic);
+                let else_val = else_val.simplify_with(arithmetic);
+                match condition {
+                    SymbolicValue::Concrete(0) => else_val,
+                    SymbolicValue::Concrete(_) => then_val,
+                    _ if then_val == else_val => then_val,
+                    _ => SymbolicValue::conditional(condition, then_val, else_val),
+                }
+            }
             _ => self.clone(),
         }
     }
+
+    /// Value for concrete inputs, or `None` on division by zero
+    ///
+    /// `inputs` maps each variable (name and index) to its value.
+    pub fn evaluate(&self, inputs: &dyn Fn(&str, usize) -> Option<i64>) -> Option<i64> {
+        match self {
+            SymbolicValue::Concrete(v) => Some(*v),
+            SymbolicValue::Variable { name, index } => inputs(name, *index),
+            SymbolicValue::BinaryOp { op, left, right } => op.apply(left.evaluate(inputs)?, right.evaluate(inputs)?),
+            SymbolicValue::UnaryOp { op, value } => Some(op.apply(value.evaluate(inputs)?)),
+            SymbolicValue::Conditional { condition, then_val, else_val } => {
+                if condition.evaluate(inputs)? != 0 {
+                    then_val.evaluate(inputs)
+                } else {
+                    else_val.evaluate(inputs)
+                }
+            }
+        }
+    }
+
+    /// Normal form for comparing values
+    ///
+    /// Operands of commutative operators are put in a fixed order, chains
+    /// of associative operators are flattened, `>` and `>=` are rewritten
+    /// as `<` and `<=`, and `(-a) * (-b)` becomes `a * b`. Two values with
+    /// equal normal forms are equal for all inputs.
+    pub fn canonical(&self) -> SymbolicValue {
+        match self.simplify() {
+            SymbolicValue::BinaryOp { op, left, right } => {
+                let (op, left, right) = match (op, *left, *right) {
+                    (BinaryOperator::Gt, l, r) => (BinaryOperator::Lt, r, l),
+                    (BinaryOperator::Gte, l, r) => (BinaryOperator::Lte, r, l),
+                    (
+                        BinaryOperator::Mul,
+                        SymbolicValue::UnaryOp { op: UnaryOperator::Negate, value: l },
+                        SymbolicValue::UnaryOp { op: UnaryOperator::Negate, value: r },
+                    ) => (BinaryOperator::Mul, *l, *r),
+                    (op, l, r) => (op, l, r),
+                };
+
+                if !op.is_commutative() {
+                    return SymbolicValue::binary_op(op, left.canonical(), right.canonical());
+                }
+
+                let mut operands = Vec::new();
+                for side in [left, right] {
+                    side.canonical().collect_operands(op, &mut operands);
+                }
+                operands.sort_by_cached_key(|operand| operand.to_string());
+                let mut operands = operands.into_iter();
+                let first = operands.next().unwrap_or(SymbolicValue::Concrete(0));
+                operands.fold(first, |acc, operand| SymbolicValue::binary_op(op, acc, operand))
+            }
+            SymbolicValue::UnaryOp { op, value } => SymbolicValue::unary_op(op, value.canonical()),
+            SymbolicValue::Conditional { condition, then_val, else_val } => {
+                SymbolicValue::conditional(condition.canonical(), then_val.canonical(), else_val.canonical())
+            }
+            value => value,
+        }
+    }
+
+    /// Operands of a chain of `op`, which must be associative to flatten
+    fn collect_operands(self, op: BinaryOperator, operands: &mut Vec<SymbolicValue>) {
+        match self {
+            SymbolicValue::BinaryOp { op: inner, left, right } if inner == op && op.is_associative() => {
+                left.collect_operands(op, operands);
+                right.collect_operands(op, operands);
+            }
+            value => operands.push(value),
+        }
+    }
 }
 
 impl fmt::Display for SymbolicValue {
@@ -153,6 +250,80 @@ pub enum BinaryOperator {
     Lte,
     Gte,
     Neq,
+    Xor,
+    /// Shifts by the low six bits of the right operand; `Shr` is logical
+    Shl,
+    Shr,
+    /// Unsigned comparisons, division and remainder
+    ULt,
+    UGt,
+    UDiv,
+    UMod,
+    /// Low and high cell of the unsigned double-cell product; the low cell
+    /// wraps whatever the overflow semantics
+    UMul,
+    UMulHigh,
+    /// Rotations by the right operand modulo 64
+    Rotl,
+    Rotr,
+}
+
+impl BinaryOperator {
+    /// Result on concrete operands; flags are -1 (true) and 0 (false)
+    ///
+    /// `None` for division or remainder by zero.
+    pub fn apply(self, a: i64, b: i64) -> Option<i64> {
+        self.apply_with(Arithmetic::default(), a, b)
+    }
+
+    /// Result on concrete operands under `arithmetic`, or `None` where it
+    /// traps
+    pub fn apply_with(self, arithmetic: Arithmetic, a: i64, b: i64) -> Option<i64> {
+        let flag = |holds: bool| if holds { -1 } else { 0 };
+        Some(match self {
+            BinaryOperator::Add => arithmetic.add(a, b).ok()?,
+            BinaryOperator::Sub => arithmetic.sub(a, b).ok()?,
+            BinaryOperator::Mul => arithmetic.mul(a, b).ok()?,
+            BinaryOperator::Div => arithmetic.div(a, b).ok()?,
+            BinaryOperator::Mod => arithmetic.modulo(a, b).ok()?,
+            BinaryOperator::And => a & b,
+            BinaryOperator::Or => a | b,
+            BinaryOperator::Lt => flag(a < b),
+            BinaryOperator::Gt => flag(a > b),
+            BinaryOperator::Eq => flag(a == b),
+            BinaryOperator::Lte => flag(a <= b),
+            BinaryOperator::Gte => flag(a >= b),
+            BinaryOperator::Neq => flag(a != b),
+            BinaryOperator::Xor => a ^ b,
+            BinaryOperator::Shl => a.wrapping_shl(b as u32),
+            BinaryOperator::Shr => (a as u64).wrapping_shr(b as u32) as i64,
+            BinaryOperator::ULt => flag((a as u64) < (b as u64)),
+            BinaryOperator::UGt => flag((a as u64) > (b as u64)),
+            BinaryOperator::UDiv => (a as u64).checked_div(b as u64)? as i64,
+            BinaryOperator::UMod => (a as u64).checked_rem(b as u64)? as i64,
+            BinaryOperator::UMul => a.wrapping_mul(b),
+            BinaryOperator::UMulHigh => umul_high(a, b),
+            BinaryOperator::Rotl => a.rotate_left(b as u32),
+            BinaryOperator::Rotr => a.rotate_right(b as u32),
+        })
+    }
+
+    pub fn is_commutative(self) -> bool {
+        matches!(
+            self,
+            BinaryOperator::Add | BinaryOperator::Mul | BinaryOperator::And | BinaryOperator::Or
+                | BinaryOperator::Eq | BinaryOperator::Neq | BinaryOperator::Xor
+                | BinaryOperator::UMul | BinaryOperator::UMulHigh
+        )
+    }
+
+    pub fn is_associative(self) -> bool {
+        matches!(
+            self,
+            BinaryOperator::Add | BinaryOperator::Mul | BinaryOperator::And | BinaryOperator::Or
+                | BinaryOperator::Xor | BinaryOperator::UMul
+        )
+    }
 }
 
 impl fmt::Display for BinaryOperator {
@@ -171,6 +342,17 @@ impl fmt::Display for BinaryOperator {
             BinaryOperator::Lte => write!(f, "<="),
             BinaryOperator::Gte => write!(f, ">="),
             BinaryOperator::Neq => write!(f, "<>"),
+            BinaryOperator::Xor => write!(f, "xor"),
+            BinaryOperator::Shl => write!(f, "lshift"),
+            BinaryOperator::Shr => write!(f, "rshift"),
+            BinaryOperator::ULt => write!(f, "u<"),
+            BinaryOperator::UGt => write!(f, "u>"),
+            BinaryOperator::UDiv => write!(f, "u/"),
+            BinaryOperator::UMod => write!(f, "umod"),
+            BinaryOperator::UMul => write!(f, "um*"),
+            BinaryOperator::UMulHigh => write!(f, "um*hi"),
+            BinaryOperator::Rotl => write!(f, "rol"),
+            BinaryOperator::Rotr => write!(f, "ror"),
         }
     }
 }
@@ -181,6 +363,24 @@ pub enum UnaryOperator {
     Negate,
     Not,
     Abs,
+    Popcount,
+    /// Trailing and leading zero bits, 64 for zero
+    Ctz,
+    Clz,
+}
+
+impl UnaryOperator {
+    /// Result on a concrete operand; `not` is `0=`
+    pub fn apply(self, v: i64) -> i64 {
+        match self {
+            UnaryOperator::Negate => v.wrapping_neg(),
+            UnaryOperator::Not => if v == 0 { -1 } else { 0 },
+            UnaryOperator::Abs => v.wrapping_abs(),
+            UnaryOperator::Popcount => v.count_ones() as i64,
+            UnaryOperator::Ctz => v.trailing_zeros() as i64,
+            UnaryOperator::Clz => v.leading_zeros() as i64,
+        }
+    }
 }
 
 impl fmt::Display for UnaryOperator {
@@ -189,6 +389,9 @@ impl fmt::Display for UnaryOperator {
             UnaryOperator::Negate => write!(f, "-"),
             UnaryOperator::Not => write!(f, "not "),
             UnaryOperator::Abs => write!(f, "abs "),
+            UnaryOperator::Popcount => write!(f, "popcount "),
+            UnaryOperator::Ctz => write!(f, "ctz "),
+            UnaryOperator::Clz => write!(f, "clz "),
         }
     }
 }
@@ -313,6 +516,25 @@ mod tests {
         assert_eq!(simplified, SymbolicValue::concrete(5));
     }
 
+    #[test]
+    fn test_canonical_forms() {
+        let a = || SymbolicValue::variable("a".to_string(), 0);
+        let b = || SymbolicValue::variable("b".to_string(), 0);
+        let c = || SymbolicValue::variable("c".to_string(), 0);
+        let add = |l, r| SymbolicValue::binary_op(BinaryOperator::Add, l, r);
+
+        assert_eq!(add(a(), b()).canonical(), add(b(), a()).canonical());
+        assert_eq!(add(add(a(), b()), c()).canonical(), add(a(), add(c(), b())).canonical());
+
+        let sub = |l, r| SymbolicValue::binary_op(BinaryOperator::Sub, l, r);
+        assert_ne!(sub(a(), b()).canonical(), sub(b(), a()).canonical());
+
+        let inputs = |name: &str, _: usize| Some(if name == "a" { 7 } else { 2 });
+        assert_eq!(sub(a(), b()).evaluate(&inputs), Some(5));
+        let div = SymbolicValue::binary_op(BinaryOperator::Div, a(), SymbolicValue::concrete(0));
+        assert_eq!(div.evaluate(&inputs), None);
+    }
+
     #[test]
     fn test_symbolic_stack() {
         let mut stack = SymbolicStack::new();
@@ -0,0 +1,9 @@
+\ BLOCK: the block file and its buffers, run in the JIT.
+\ open-blocks is the Fast Forth word that names the block file.
+
+T{ "%TMP%/ans.fb" open-blocks -> }T
+T{ 42 1 block ! update 1 block @ -> 42 }T
+T{ save-buffers empty-buffers 1 block @ -> 42 }T
+T{ 7 2 buffer ! update flush 2 block @ -> 7 }T
+T{ 3 block @ -> "        " drop @ }T
+T{ 9 1 block ! empty-buffers 1 block @ -> 42 }T
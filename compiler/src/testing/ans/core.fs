@@ -0,0 +1,80 @@
+\ CORE: stack, arithmetic, logic, comparison, memory and control flow.
+\ Runs in the threaded interpreter, the engine behind -O0 and the REPL,
+\ and in the JIT.
+
+T{ -> }T
+T{ 1 2 3 -> 1 2 3 }T
+
+\ Stack
+T{ 1 dup -> 1 1 }T
+T{ 1 2 drop -> 1 }T
+T{ 1 2 swap -> 2 1 }T
+T{ 1 2 over -> 1 2 1 }T
+T{ 1 2 3 rot -> 2 3 1 }T
+
+\ Arithmetic
+T{ 1 2 + -> 3 }T
+T{ -1 1 + -> 0 }T
+T{ 5 7 - -> -2 }T
+T{ 6 7 * -> 42 }T
+T{ -3 4 * -> -12 }T
+T{ 42 6 / -> 7 }T
+T{ 7 2 mod -> 1 }T
+T{ 5 negate -> -5 }T
+T{ -5 abs -> 5 }T
+T{ 5 abs -> 5 }T
+
+\ Logic
+T{ 12 10 and -> 8 }T
+T{ 12 10 or -> 14 }T
+T{ 12 10 xor -> 6 }T
+T{ 0 invert -> -1 }T
+T{ 1 4 lshift -> 16 }T
+T{ 16 4 rshift -> 1 }T
+T{ -1 63 rshift -> 1 }T
+
+\ Comparison
+T{ 1 1 = -> -1 }T
+T{ 1 2 = -> 0 }T
+T{ 1 2 < -> -1 }T
+T{ 2 1 < -> 0 }T
+T{ 2 1 > -> -1 }T
+T{ 0 0= -> -1 }T
+T{ 5 0= -> 0 }T
+T{ -5 0< -> -1 }T
+T{ 5 0< -> 0 }T
+
+\ Memory
+variable v
+T{ 123 v ! v @ -> 123 }T
+T{ 3 cells -> 24 }T
+
+\ Return stack
+T{ 1 2 >r 3 r> -> 1 3 2 }T
+T{ 7 >r r@ r> -> 7 7 }T
+
+\ Colon definitions and control flow
+: gr1 ( n -- flag ) 0 > if 1 else 2 then ;
+T{ 5 gr1 -> 1 }T
+T{ -5 gr1 -> 2 }T
+
+: gi2 ( n -- n ) dup 0< if negate then ;
+T{ -4 gi2 -> 4 }T
+
+: countdown ( n -- 0 ) begin 1 - dup 0= until ;
+T{ 5 countdown -> 0 }T
+
+: sum-to ( n -- sum ) 0 swap begin dup 0 > while dup rot + swap 1 - repeat drop ;
+T{ 4 sum-to -> 10 }T
+
+: squares ( n -- ... ) 0 do i i * loop ;
+T{ 3 squares -> 0 1 4 }T
+
+: grid ( -- ... ) 2 0 do 2 0 do j 10 * i + loop loop ;
+T{ grid -> 0 1 10 11 }T
+
+\ Pictured numeric output: <# # #s hold sign #>
+T{ 123 0 <# #s #> swap drop -> 3 }T
+T{ 5 0 <# # # #> swap drop -> 2 }T
+T{ 5 0 <# #s 45 hold #> swap drop -> 2 }T
+T{ -7 dup abs 0 <# #s rot sign #> swap drop -> 2 }T
@@ -0,0 +1,9 @@
+\ CORE-EXT: the extension words the threaded interpreter provides.
+
+T{ 1 2 nip -> 2 }T
+T{ 1 2 tuck -> 2 1 2 }T
+T{ 1 2 <> -> -1 }T
+T{ 2 2 <> -> 0 }T
+T{ 5 0> -> -1 }T
+T{ -5 0> -> 0 }T
+T{ 0 0> -> 0 }T
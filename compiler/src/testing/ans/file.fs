@@ -0,0 +1,11 @@
+\ FILE: files through the C runtime, run in the JIT.
+\ Cases run in order: the file is created, written, read back and deleted.
+\ Strings are written inline, as the JIT cannot yet return them from a
+\ colon definition.
+
+T{ "%TMP%/ans.txt" w/o create-file swap drop -> 0 }T
+T{ "%TMP%/ans.txt" w/o create-file drop dup "hello" rot write-file swap close-file -> 0 0 }T
+T{ "xxxxxxxx" drop "%TMP%/ans.txt" r/o open-file drop over swap 8 swap read-file rot @ -> 5 0 "helloxxx" drop @ }T
+T{ "%TMP%/ans.txt" r/w open-file swap drop -> 0 }T
+T{ "%TMP%/ans.txt" delete-file -> 0 }T
+T{ "%TMP%/ans.txt" delete-file 0 = -> 0 }T
@@ -0,0 +1,10 @@
+\ MEMORY-ALLOC: the heap through the C runtime, run in the JIT.
+\ The iors are the THROW codes of the standard: -59 for ALLOCATE, -60 for
+\ FREE and -61 for RESIZE.
+
+T{ 64 allocate swap drop -> 0 }T
+T{ 64 allocate drop free -> 0 }T
+T{ 16 allocate drop dup 42 swap ! dup @ swap free -> 42 0 }T
+T{ 8 allocate drop dup 7 swap ! 4096 resize drop dup @ swap free -> 7 0 }T
+T{ -1 allocate swap drop -> -59 }T
+T{ 8 allocate drop dup -1 resize swap drop swap free -> -61 0 }T
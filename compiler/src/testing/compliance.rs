@@ -0,0 +1,322 @@
+//! ANS Forth Compliance Suite
+//!
+//! Hayes-style `T{ ... -> ... }T` tests for the word sets the compiler
+//! claims (see [`fastforth_frontend::ans::claim`]), run by
+//! `fastforth test --ans`. Each suite runs its definitions and cases in
+//! one engine: the threaded interpreter for words that need loops and
+//! variables, or the JIT for words backed by the C runtime (files,
+//! blocks, the heap), which the interpreter does not have. The core suite
+//! runs in both, since every compiled program depends on it. A case
+//! passes when its body leaves the stack its expected part leaves.
+//!
+//! Besides passing, a claim must be exercised: every claimed word has to
+//! appear in its set's suites, so a claim cannot grow without a test.
+//! Suites that touch the file system use `%TMP%` in their strings, which
+//! is replaced by a scratch directory removed after the run.
+
+use crate::error::{CompileError, Result};
+use crate::pipeline::{CompilationMode, CompilationPipeline};
+use crate::runtime_ffi;
+use crate::tiered::{TierPolicy, TieredEngine};
+use fastforth_frontend::ans::{self, Claim, WordSet};
+use fastforth_frontend::{parse_program, Program, Word};
+use fastforth_optimizer::OptimizationLevel;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
+
+/// Placeholder in suite sources for the scratch directory
+const SCRATCH_PLACEHOLDER: &str = "%TMP%";
+
+/// Engine a suite runs in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    /// The threaded interpreter behind `-O0` and the REPL
+    Interpreter,
+    /// The Cranelift JIT
+    Jit,
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Engine::Interpreter => "interpreter",
+            Engine::Jit => "jit",
+        })
+    }
+}
+
+/// One file of compliance tests
+#[derive(Debug, Clone, Copy)]
+pub struct Suite {
+    pub name: &'static str,
+    pub set: WordSet,
+    pub engine: Engine,
+    pub source: &'static str,
+}
+
+/// The compliance suites, grouped by word set
+pub const SUITES: &[Suite] = &[
+    Suite { name: "core", set: WordSet::Core, engine: Engine::Interpreter, source: include_str!("ans/core.fs") },
+    Suite { name: "core", set: WordSet::Core, engine: Engine::Jit, source: include_str!("ans/core.fs") },
+    Suite { name: "core-ext", set: WordSet::CoreExt, engine: Engine::Interpreter, source: include_str!("ans/core_ext.fs") },
+    Suite { name: "block", set: WordSet::Block, engine: Engine::Jit, source: include_str!("ans/block.fs") },
+    Suite { name: "file", set: WordSet::File, engine: Engine::Jit, source: include_str!("ans/file.fs") },
+    Suite { name: "memory", set: WordSet::Memory, engine: Engine::Jit, source: include_str!("ans/memory.fs") },
+];
+
+/// A test case whose body and expected part disagree
+#[derive(Debug, Clone)]
+pub struct CaseFailure {
+    pub suite: &'static str,
+    pub engine: Engine,
+    /// Line of the `T{`
+    pub line: usize,
+    /// Stack left by the body, or why it did not run
+    pub actual: std::result::Result<Vec<i64>, String>,
+    /// Stack left by the expected part, or why it did not run
+    pub expected: std::result::Result<Vec<i64>, String>,
+}
+
+impl fmt::Display for CaseFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn stack(result: &std::result::Result<Vec<i64>, String>) -> String {
+            match result {
+                Ok(stack) => format!("{:?}", stack),
+                Err(e) => format!("error: {}", e),
+            }
+        }
+        write!(
+            f,
+            "{}.fs:{} ({}): got {}, expected {}",
+            self.suite,
+            self.line,
+            self.engine,
+            stack(&self.actual),
+            stack(&self.expected)
+        )
+    }
+}
+
+/// Outcome for one word set
+#[derive(Debug, Clone)]
+pub struct SetReport {
+    pub set: WordSet,
+    pub claim: Claim,
+    pub passed: usize,
+    pub failures: Vec<CaseFailure>,
+    /// Claimed words no suite of the set uses
+    pub untested: Vec<&'static str>,
+}
+
+impl SetReport {
+    /// Whether every case passed and every claimed word was exercised
+    pub fn complies(&self) -> bool {
+        self.failures.is_empty() && self.untested.is_empty()
+    }
+}
+
+/// Outcome of the whole compliance suite
+#[derive(Debug, Clone)]
+pub struct ComplianceReport {
+    pub sets: Vec<SetReport>,
+}
+
+impl ComplianceReport {
+    /// Whether every claimed word set complies
+    pub fn complies(&self) -> bool {
+        self.sets.iter().all(SetReport::complies)
+    }
+}
+
+impl fmt::Display for ComplianceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for report in &self.sets {
+            let claim = match report.claim {
+                Claim::Complete => "complete".to_string(),
+                Claim::Partial(words) => format!("{} of {} words", words.len(), report.set.words().len()),
+                Claim::NotClaimed => "not claimed".to_string(),
+            };
+            writeln!(
+                f,
+                "{:<14} {:<20} {} passed, {} failed",
+                report.set.name(),
+                claim,
+                report.passed,
+                report.failures.len()
+            )?;
+            for failure in &report.failures {
+                writeln!(f, "  {}", failure)?;
+            }
+            if !report.untested.is_empty() {
+                writeln!(f, "  claimed but untested: {}", report.untested.join(" "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run every suite and check the claims against them
+pub fn run_compliance_suite() -> Result<ComplianceReport> {
+    let scratch = std::env::temp_dir().join(format!("fifth-ans-{}", std::process::id()));
+    std::fs::create_dir_all(&scratch).map_err(|e| CompileError::IoError(scratch.clone(), e))?;
+    let report = run_suites(SUITES, &scratch);
+    let _ = std::fs::remove_dir_all(&scratch);
+    report
+}
+
+fn run_suites(suites: &[Suite], scratch: &Path) -> Result<ComplianceReport> {
+    let _blocks = runtime_ffi::BLOCK_FILE.lock().unwrap_or_else(|e| e.into_inner());
+    let mut sets = Vec::new();
+    for set in WordSet::ALL {
+        let claim = ans::claim(set);
+        let mut report = SetReport { set, claim, passed: 0, failures: Vec::new(), untested: Vec::new() };
+        let mut used = BTreeSet::new();
+
+        for suite in suites.iter().filter(|suite| suite.set == set) {
+            let source = suite.source.replace(SCRATCH_PLACEHOLDER, &scratch.display().to_string());
+            let program = parse_program(&source)?;
+            collect_used_words(&program, &mut used);
+
+            let mut runner = Runner::new(suite.engine, &program);
+            for case in &program.tests {
+                let actual = runner.run(&case.body);
+                let expected = runner.run(&case.expected);
+                match (&actual, &expected) {
+                    (Ok(a), Ok(e)) if a == e => report.passed += 1,
+                    _ => report.failures.push(CaseFailure {
+                        suite: suite.name,
+                        engine: suite.engine,
+                        line: case.location.line,
+                        actual,
+                        expected,
+                    }),
+                }
+            }
+        }
+
+        report.untested = claim.words(set).iter().copied().filter(|word| !used.contains(*word)).collect();
+        if claim != Claim::NotClaimed || report.passed > 0 || !report.failures.is_empty() {
+            sets.push(report);
+        }
+    }
+    Ok(ComplianceReport { sets })
+}
+
+/// Runs test cases against a suite's definitions
+struct Runner<'a> {
+    program: &'a Program,
+    /// The interpreter for suites run in it; other suites compile each
+    /// case with the JIT
+    interpreter: Option<TieredEngine>,
+    pipeline: CompilationPipeline,
+}
+
+impl<'a> Runner<'a> {
+    fn new(engine: Engine, program: &'a Program) -> Self {
+        let interpreter = (engine == Engine::Interpreter).then(|| TieredEngine::new(TierPolicy::default()));
+        Self { program, interpreter, pipeline: CompilationPipeline::new(OptimizationLevel::Basic) }
+    }
+
+    /// The stack a word sequence leaves when run from an empty stack
+    fn run(&mut self, words: &[Word]) -> std::result::Result<Vec<i64>, String> {
+        // Variables are declared again each time; the VM keeps their cells,
+        // and a compiled program starts with them cleared
+        let mut case = Program::new();
+        case.definitions = self.program.definitions.clone();
+        case.top_level_code = self
+            .program
+            .top_level_code
+            .iter()
+            .filter(|word| matches!(word, Word::Variable { .. }))
+            .cloned()
+            .collect();
+        case.top_level_code.extend_from_slice(words);
+
+        let Some(engine) = &mut self.interpreter else {
+            let result = self.pipeline.compile_program(&case, CompilationMode::JIT).map_err(|e| e.to_string())?;
+            return Ok(result.stack);
+        };
+        engine.set_stack(Vec::new());
+        engine.load(&case).and_then(|()| engine.run()).map_err(|e| e.to_string())?;
+        Ok(engine.stack().to_vec())
+    }
+}
+
+/// Add the words a program uses, lowercased, including the ones the
+/// parser turns into structure
+fn collect_used_words(program: &Program, used: &mut BTreeSet<String>) {
+    if !program.definitions.is_empty() {
+        used.extend([":".to_string(), ";".to_string()]);
+    }
+    for def in &program.definitions {
+        collect_words(&def.body, used);
+    }
+    collect_words(&program.top_level_code, used);
+    for case in &program.tests {
+        collect_words(&case.body, used);
+        collect_words(&case.expected, used);
+    }
+}
+
+fn collect_words(words: &[Word], used: &mut BTreeSet<String>) {
+    for word in words {
+        let (names, nested): (&[&str], Vec<&[Word]>) = match word {
+            Word::WordRef { name, .. } => {
+                used.insert(name.to_lowercase());
+                continue;
+            }
+            Word::If { then_branch, else_branch: None } => (&["if", "then"], vec![then_branch]),
+            Word::If { then_branch, else_branch: Some(else_branch) } => {
+                (&["if", "else", "then"], vec![then_branch, else_branch])
+            }
+            Word::BeginUntil { body } => (&["begin", "until"], vec![body]),
+            Word::BeginWhileRepeat { condition, body } => (&["begin", "while", "repeat"], vec![condition, body]),
+            Word::DoLoop { body, increment: 1 } => (&["do", "loop"], vec![body]),
+            Word::DoLoop { body, .. } => (&["do", "+loop"], vec![body]),
+            Word::TaskSpawn { .. } => (&["task:"], Vec::new()),
+            Word::Locals { .. } => (&["{:"], Vec::new()),
+            Word::LocalStore { .. } => (&["to"], Vec::new()),
+            Word::Quotation { .. } => (&["[:", ";]"], Vec::new()),
+            Word::Variable { size: 8, record: None, .. } => (&["variable"], Vec::new()),
+            Word::Variable { .. } => (&["create", "allot"], Vec::new()),
+            Word::Constant { .. } => (&["constant"], Vec::new()),
+            Word::IntLiteral(_) | Word::FloatLiteral(_) | Word::StringLiteral(_) | Word::Comment(_) | Word::LocalRef { .. } => continue,
+        };
+        used.extend(names.iter().map(|name| name.to_string()));
+        for words in nested {
+            collect_words(words, used);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claimed_word_sets_comply() {
+        let report = run_compliance_suite().unwrap();
+        assert!(report.complies(), "\n{}", report);
+        assert!(report.sets.iter().any(|set| set.set == WordSet::Core && set.passed > 0));
+    }
+
+    #[test]
+    fn test_failures_and_untested_claims_are_reported() {
+        let suites = [Suite {
+            name: "broken",
+            set: WordSet::Core,
+            engine: Engine::Interpreter,
+            source: "T{ 1 2 + -> 3 }T\nT{ 1 2 + -> 4 }T",
+        }];
+        let report = run_suites(&suites, &std::env::temp_dir()).unwrap();
+        let core = report.sets.iter().find(|set| set.set == WordSet::Core).unwrap();
+
+        assert_eq!(core.passed, 1);
+        assert_eq!(core.failures.len(), 1);
+        assert_eq!(core.failures[0].line, 2);
+        assert_eq!(core.failures[0].actual, Ok(vec![3]));
+        assert!(core.untested.contains(&"swap"));
+        assert!(!report.complies());
+    }
+}
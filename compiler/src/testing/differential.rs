@@ -0,0 +1,576 @@
+//! Differential Execution Against gforth
+//!
+//! Runs the same Forth source under the Fast Forth JIT and under gforth,
+//! normalizes what each leaves behind (data stack and printed output), and
+//! reports any divergence together with a reproducer minimized by token
+//! deletion and literal shrinking.
+//!
+//! The JIT cannot print yet, so programs that use output words fail on the
+//! Fast Forth side; the output comparison is in place for when it can.
+//! The JIT runs in-process: a program that loops forever or faults takes
+//! the caller with it, while gforth runs under a timeout.
+
+use crate::error::{CompileError, Result};
+use super::structured::SplitMix64;
+use crate::pipeline::CompilationPipeline;
+use fastforth_optimizer::OptimizationLevel;
+use serde::Serialize;
+use std::fmt;
+use std::io::{Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Environment variable naming the gforth executable
+pub const GFORTH_ENV: &str = "FIFTH_GFORTH";
+
+/// Default wall-clock limit for one gforth run
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Word appended to the gforth script to print the stack bottom first
+const DUMP_WORD: &str = "fifth-differential-dump";
+
+/// Printed by gforth between the program's output and the stack dump
+const STACK_MARKER: &str = "FIFTH-DIFFERENTIAL-STACK";
+
+/// How often a running gforth is polled for exit
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// What one implementation left behind after running a program
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Execution {
+    /// Data stack, bottom first
+    pub stack: Vec<i64>,
+
+    /// Printed output, normalized with [`normalize_output`]
+    pub output: String,
+}
+
+/// How two runs of a program disagree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DivergenceKind {
+    /// Both ran, leaving different stacks
+    StackMismatch,
+
+    /// Both ran and left the same stack, but printed different output
+    OutputMismatch,
+
+    /// gforth ran the program, Fast Forth rejected or failed it
+    FastForthFailed,
+
+    /// Fast Forth ran the program, gforth rejected or failed it
+    GforthFailed,
+}
+
+impl fmt::Display for DivergenceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DivergenceKind::StackMismatch => "stack mismatch",
+            DivergenceKind::OutputMismatch => "output mismatch",
+            DivergenceKind::FastForthFailed => "Fast Forth failed",
+            DivergenceKind::GforthFailed => "gforth failed",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A program Fast Forth and gforth disagree on
+#[derive(Debug, Clone, Serialize)]
+pub struct Divergence {
+    pub kind: DivergenceKind,
+
+    /// Program as given
+    pub source: String,
+
+    /// Smallest program found that diverges the same way
+    pub reproducer: String,
+
+    /// Fast Forth's run of the reproducer
+    pub fast_forth: std::result::Result<Execution, String>,
+
+    /// gforth's run of the reproducer
+    pub gforth: std::result::Result<Execution, String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}: {}", self.kind, self.reproducer)?;
+        for (name, run) in [("fast forth", &self.fast_forth), ("gforth", &self.gforth)] {
+            match run {
+                Ok(execution) => write!(f, "  {:<10} {:?}", name, execution.stack)?,
+                Err(e) => write!(f, "  {:<10} error: {}", name, e)?,
+            }
+            match run {
+                Ok(execution) if !execution.output.is_empty() => writeln!(f, " output {:?}", execution.output)?,
+                _ => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs programs under the Fast Forth JIT and gforth and compares them
+pub struct DifferentialRunner {
+    optimization_level: OptimizationLevel,
+    gforth: PathBuf,
+    timeout: Duration,
+    minimize: bool,
+}
+
+impl DifferentialRunner {
+    /// Runner using `$FIFTH_GFORTH` (or `gforth` on the path), the standard
+    /// optimization level and minimization of reproducers
+    pub fn new() -> Self {
+        Self {
+            optimization_level: OptimizationLevel::Standard,
+            gforth: std::env::var_os(GFORTH_ENV).map(PathBuf::from).unwrap_or_else(|| PathBuf::from("gforth")),
+            timeout: DEFAULT_TIMEOUT,
+            minimize: true,
+        }
+    }
+
+    /// Set the optimization level Fast Forth compiles at
+    pub fn with_optimization_level(mut self, level: OptimizationLevel) -> Self {
+        self.optimization_level = level;
+        self
+    }
+
+    /// Set the gforth executable
+    pub fn with_gforth(mut self, gforth: impl Into<PathBuf>) -> Self {
+        self.gforth = gforth.into();
+        self
+    }
+
+    /// Set the wall-clock limit for one gforth run
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set whether divergences are minimized (otherwise the reproducer is
+    /// the program as given)
+    pub fn with_minimize(mut self, minimize: bool) -> Self {
+        self.minimize = minimize;
+        self
+    }
+
+    /// Whether the gforth executable can be started
+    pub fn gforth_available(&self) -> bool {
+        Command::new(&self.gforth)
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok()
+    }
+
+    /// Run `source` under the JIT
+    pub fn run_fast_forth(&self, source: &str) -> std::result::Result<Execution, String> {
+        let pipeline = CompilationPipeline::new(self.optimization_level);
+        let run = panic::catch_unwind(AssertUnwindSafe(|| {
+            let program = pipeline.prepare_jit(source)?;
+            Ok::<_, CompileError>(program.run().unwrap_or_default())
+        }));
+
+        match run {
+            Ok(Ok(stack)) => Ok(Execution { stack, output: String::new() }),
+            Ok(Err(e)) => Err(e.to_string()),
+            Err(payload) => Err(panic_message(payload.as_ref())),
+        }
+    }
+
+    /// Run `source` under gforth
+    ///
+    /// The outer error is for gforth not starting at all; the inner one is
+    /// for the program failing under it.
+    pub fn run_gforth(&self, source: &str) -> Result<std::result::Result<Execution, String>> {
+        let mut child = Command::new(&self.gforth)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| CompileError::RuntimeError(format!("failed to start {}: {}", self.gforth.display(), e)))?;
+
+        // Readers on their own threads, so a chatty program cannot block on a full pipe
+        let stdout = child.stdout.take().map(read_pipe);
+        let stderr = child.stderr.take().map(read_pipe);
+
+        if let Some(mut stdin) = child.stdin.take() {
+            // A program that exits early closes its end; what it did read is still judged
+            let _ = stdin.write_all(gforth_script(source).as_bytes());
+        }
+
+        let started = Instant::now();
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if started.elapsed() >= self.timeout => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Ok(Err(format!("timed out after {:.1}s", self.timeout.as_secs_f64())));
+                }
+                Ok(None) => thread::sleep(POLL_INTERVAL),
+                Err(e) => return Err(CompileError::RuntimeError(format!("failed to wait for gforth: {}", e))),
+            }
+        };
+
+        let stdout = stdout.and_then(|reader| reader.join().ok()).unwrap_or_default();
+        let stderr = stderr.and_then(|reader| reader.join().ok()).unwrap_or_default();
+
+        let errors: Vec<&str> = stderr
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.to_ascii_lowercase().contains("warning"))
+            .collect();
+        if !errors.is_empty() {
+            return Ok(Err(errors.join("\n")));
+        }
+        if !status.success() {
+            return Ok(Err(format!("gforth exited with {}", status)));
+        }
+
+        Ok(parse_gforth_output(&stdout))
+    }
+
+    /// Run `source` under both and report how they disagree, if they do
+    ///
+    /// A program both implementations reject is not a divergence. Nor is
+    /// one gforth rejects for dividing by zero: the JIT traps on it, so it
+    /// is not run under the JIT at all.
+    pub fn compare(&self, source: &str) -> Result<Option<Divergence>> {
+        let (fast_forth, gforth) = self.run_both(source)?;
+
+        let Some(kind) = divergence_kind(&fast_forth, &gforth) else {
+            return Ok(None);
+        };
+
+        let mut divergence = Divergence {
+            kind,
+            source: source.to_string(),
+            reproducer: source.to_string(),
+            fast_forth,
+            gforth,
+        };
+
+        if self.minimize {
+            let reproducer = minimize(source, |candidate| match self.run_both(candidate) {
+                Ok((fast_forth, gforth)) => divergence_kind(&fast_forth, &gforth) == Some(kind),
+                Err(_) => false,
+            });
+
+            if reproducer != divergence.source {
+                let (fast_forth, gforth) = self.run_both(&reproducer)?;
+                divergence.fast_forth = fast_forth;
+                divergence.gforth = gforth;
+                divergence.reproducer = reproducer;
+            }
+        }
+
+        Ok(Some(divergence))
+    }
+
+    /// gforth's run first, which decides whether the JIT's is safe
+    fn run_both(&self, source: &str) -> Result<(std::result::Result<Execution, String>, std::result::Result<Execution, String>)> {
+        let gforth = self.run_gforth(source)?;
+        let fast_forth = match &gforth {
+            Err(e) if e.to_ascii_lowercase().contains("division by zero") => Err("not run: divides by zero".to_string()),
+            _ => self.run_fast_forth(source),
+        };
+        Ok((fast_forth, gforth))
+    }
+}
+
+impl Default for DifferentialRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Classify a pair of runs, `None` when they agree
+pub fn divergence_kind(
+    fast_forth: &std::result::Result<Execution, String>,
+    gforth: &std::result::Result<Execution, String>,
+) -> Option<DivergenceKind> {
+    match (fast_forth, gforth) {
+        (Ok(ours), Ok(theirs)) if ours.stack != theirs.stack => Some(DivergenceKind::StackMismatch),
+        (Ok(ours), Ok(theirs)) if ours.output != theirs.output => Some(DivergenceKind::OutputMismatch),
+        (Ok(_), Ok(_)) | (Err(_), Err(_)) => None,
+        (Err(_), Ok(_)) => Some(DivergenceKind::FastForthFailed),
+        (Ok(_), Err(_)) => Some(DivergenceKind::GforthFailed),
+    }
+}
+
+/// Shrink `source` while `diverges` still holds
+///
+/// Deletes runs of whitespace-separated tokens, halving the run length
+/// down to single tokens, then replaces integer literals with smaller
+/// ones. The result has its tokens joined by single spaces.
+pub fn minimize(source: &str, mut diverges: impl FnMut(&str) -> bool) -> String {
+    let mut tokens: Vec<String> = source.split_whitespace().map(str::to_string).collect();
+
+    let mut chunk = (tokens.len() / 2).max(1);
+    loop {
+        let mut removed = false;
+        let mut start = 0;
+        while start < tokens.len() {
+            let end = (start + chunk).min(tokens.len());
+            let candidate: Vec<String> = tokens[..start].iter().chain(&tokens[end..]).cloned().collect();
+            if !candidate.is_empty() && diverges(&candidate.join(" ")) {
+                tokens = candidate;
+                removed = true;
+            } else {
+                start += chunk;
+            }
+        }
+
+        if !removed {
+            if chunk == 1 {
+                break;
+            }
+            chunk = (chunk / 2).max(1);
+        }
+    }
+
+    for i in 0..tokens.len() {
+        let Ok(value) = tokens[i].parse::<i64>() else { continue };
+        for smaller in [0, 1, -1, value / 2] {
+            if smaller.unsigned_abs() >= value.unsigned_abs() {
+                continue;
+            }
+            let original = std::mem::replace(&mut tokens[i], smaller.to_string());
+            if diverges(&tokens.join(" ")) {
+                break;
+            }
+            tokens[i] = original;
+        }
+    }
+
+    tokens.join(" ")
+}
+
+/// Trim each line, collapse runs of spaces, drop gforth's `ok` prompts and
+/// blank lines
+pub fn normalize_output(output: &str) -> String {
+    output
+        .lines()
+        .map(|line| {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let words = match words.split_last() {
+                Some((&"ok", rest)) => rest,
+                _ => &words[..],
+            };
+            words.join(" ")
+        })
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Program followed by a dump of whatever stack it leaves, so the whole
+/// stack is seen and not just the top few cells `.s` shows
+fn gforth_script(source: &str) -> String {
+    format!(
+        "{source}\n: {word} depth 0 ?do depth i - 1- pick . loop ;\ncr .( {marker} ) depth . {word} cr bye\n",
+        source = source,
+        word = DUMP_WORD,
+        marker = STACK_MARKER,
+    )
+}
+
+/// Split gforth's stdout into the program's output and the stack dump
+fn parse_gforth_output(stdout: &str) -> std::result::Result<Execution, String> {
+    let Some(marker) = stdout.find(STACK_MARKER) else {
+        return Err(format!("program did not finish: {}", normalize_output(stdout)));
+    };
+
+    let mut values = stdout[marker + STACK_MARKER.len()..]
+        .split_whitespace()
+        .take_while(|word| *word != "ok")
+        .map(str::parse::<i64>);
+    let depth = match values.next() {
+        Some(Ok(depth)) if depth >= 0 => depth as usize,
+        _ => return Err("unreadable stack dump".to_string()),
+    };
+    let stack = values
+        .take(depth)
+        .collect::<std::result::Result<Vec<i64>, _>>()
+        .map_err(|e| format!("unreadable stack dump: {}", e))?;
+    if stack.len() != depth {
+        return Err("truncated stack dump".to_string());
+    }
+
+    Ok(Execution { stack, output: normalize_output(&stdout[..marker]) })
+}
+
+fn read_pipe(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = pipe.read_to_end(&mut bytes);
+        String::from_utf8_lossy(&bytes).into_owned()
+    })
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    format!("compiler panicked: {}", message)
+}
+
+/// A word random programs are built from
+struct Op {
+    spelling: &'static str,
+    inputs: usize,
+
+    /// Takes a nonzero literal pushed just before it as its second input
+    divides: bool,
+}
+
+/// Words both the JIT and gforth run
+const VOCABULARY: &[Op] = &[
+    Op { spelling: "+", inputs: 2, divides: false },
+    Op { spelling: "-", inputs: 2, divides: false },
+    Op { spelling: "*", inputs: 2, divides: false },
+    Op { spelling: "/", inputs: 1, divides: true },
+    Op { spelling: "mod", inputs: 1, divides: true },
+    Op { spelling: "dup", inputs: 1, divides: false },
+    Op { spelling: "drop", inputs: 1, divides: false },
+    Op { spelling: "swap", inputs: 2, divides: false },
+    Op { spelling: "over", inputs: 2, divides: false },
+    Op { spelling: "rot", inputs: 3, divides: false },
+    Op { spelling: "negate", inputs: 1, divides: false },
+    Op { spelling: "abs", inputs: 1, divides: false },
+    Op { spelling: "=", inputs: 2, divides: false },
+    Op { spelling: "<", inputs: 2, divides: false },
+    Op { spelling: ">", inputs: 2, divides: false },
+    Op { spelling: "and", inputs: 2, divides: false },
+    Op { spelling: "or", inputs: 2, divides: false },
+];
+
+/// Spell out a straight-line program that never underflows
+///
+/// Each step picks a word from the vocabulary by index; a word needing more
+/// inputs than the stack holds becomes a push of the step's literal instead.
+/// Divisors are the literal itself, made nonzero.
+pub fn render_program(steps: &[(usize, i64)]) -> String {
+    let mut words = Vec::with_capacity(steps.len());
+    let mut depth = 0usize;
+
+    for &(choice, literal) in steps {
+        let op = &VOCABULARY[choice % VOCABULARY.len()];
+        if op.inputs > depth {
+            words.push(literal.to_string());
+            depth += 1;
+            continue;
+        }
+
+        if op.divides {
+            words.push(if literal == 0 { "1".to_string() } else { literal.to_string() });
+        }
+        words.push(op.spelling.to_string());
+        depth = match op.spelling {
+            "drop" => depth - 1,
+            "dup" | "over" => depth + 1,
+            "negate" | "abs" | "swap" | "rot" | "/" | "mod" => depth,
+            _ => depth - 1,
+        };
+    }
+
+    words.join(" ")
+}
+
+/// Deterministic random programs, for fuzzing without proptest
+pub struct ProgramGenerator {
+    rng: SplitMix64,
+}
+
+impl ProgramGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self { rng: SplitMix64::new(seed) }
+    }
+
+    /// Program of `1..=max_steps` steps with literals in `-100..=100`
+    pub fn generate(&mut self, max_steps: usize) -> String {
+        let len = 1 + self.rng.below(max_steps.max(1) as u64) as usize;
+        let steps: Vec<(usize, i64)> = (0..len)
+            .map(|_| (self.rng.below(VOCABULARY.len() as u64) as usize, self.rng.below(201) as i64 - 100))
+            .collect();
+        render_program(&steps)
+    }
+}
+
+/// proptest strategies over the same programs
+#[cfg(feature = "proptest")]
+pub mod strategies {
+    use super::{render_program, DifferentialRunner, VOCABULARY};
+    use proptest::prelude::*;
+    use proptest::test_runner::TestCaseError;
+
+    /// Straight-line programs of up to `max_steps` steps that never underflow
+    pub fn arb_program(max_steps: usize) -> impl Strategy<Value = String> {
+        prop::collection::vec((0..VOCABULARY.len(), -100i64..=100), 1..=max_steps.max(1))
+            .prop_map(|steps| render_program(&steps))
+    }
+
+    impl DifferentialRunner {
+        /// Fail the test case on a divergence, with the minimized reproducer
+        /// in the message; pass when gforth is unavailable
+        pub fn check(&self, source: &str) -> Result<(), TestCaseError> {
+            match self.compare(source) {
+                Ok(None) => Ok(()),
+                Ok(Some(divergence)) => Err(TestCaseError::fail(divergence.to_string())),
+                Err(_) if !self.gforth_available() => Ok(()),
+                Err(e) => Err(TestCaseError::fail(e.to_string())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_gforth_output() {
+        let stdout = format!("hello  42 ok\n\n{} 3 1 -2 30  ok\n", STACK_MARKER);
+        let execution = parse_gforth_output(&stdout).unwrap();
+        assert_eq!(execution.stack, vec![1, -2, 30]);
+        assert_eq!(execution.output, "hello 42");
+
+        assert!(parse_gforth_output("Undefined word").is_err());
+        assert!(parse_gforth_output(&format!("{} 2 1", STACK_MARKER)).is_err());
+    }
+
+    #[test]
+    fn test_minimize_keeps_divergence() {
+        // Pretend only programs dividing 7 by something negative diverge
+        let diverges = |source: &str| {
+            let tokens: Vec<&str> = source.split_whitespace().collect();
+            tokens.windows(3).any(|w| w[0] == "7" && w[1].parse::<i64>().is_ok_and(|d| d < 0) && w[2] == "/")
+        };
+        let reproducer = minimize("1 2 + 7 -40 / swap drop 5 *", diverges);
+        assert_eq!(reproducer, "7 -1 /");
+    }
+
+    #[test]
+    fn test_generated_programs_run_under_jit() {
+        let runner = DifferentialRunner::new();
+        let mut generator = ProgramGenerator::new(3825);
+        for _ in 0..20 {
+            let program = generator.generate(16);
+            assert!(runner.run_fast_forth(&program).is_ok(), "JIT rejected {}", program);
+        }
+    }
+
+    #[test]
+    fn test_compare_needs_gforth() {
+        let runner = DifferentialRunner::new().with_gforth("/nonexistent/gforth");
+        assert!(!runner.gforth_available());
+        assert!(runner.compare("1 2 +").is_err());
+    }
+}
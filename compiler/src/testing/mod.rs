@@ -3,4 +3,12 @@
 //! Automatic test generation and testing utilities
 
 pub mod auto_gen;
+pub mod compliance;
+pub mod differential;
+pub mod snapshot;
+pub mod structured;
 pub use auto_gen::TestGenerator;
+pub use compliance::{run_compliance_suite, ComplianceReport, SetReport};
+pub use differential::{DifferentialRunner, Divergence, DivergenceKind, Execution};
+pub use snapshot::{PassSnapshots, SnapshotMismatch};
+pub use structured::{check_optimization_levels, GeneratedProgram, LevelMismatch, Runner, StructuredGenerator};
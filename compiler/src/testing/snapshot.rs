@@ -0,0 +1,237 @@
+//! Pass Snapshots
+//!
+//! Golden snapshots of the IR between optimizer passes. A program is
+//! lowered, optimized with an [`OptimizerTracer`] attached, and the trace
+//! is written as JSON: the input IR, then for every pass its name and the
+//! instructions it removed and added in each word, then the output IR.
+//!
+//! [`PassSnapshots::check`] compares a fresh trace with the stored one and
+//! names the first pass whose changes differ, so a regression or a
+//! reordering of passes points at the pass responsible. Set
+//! `FIFTH_UPDATE_SNAPSHOTS=1` to write the snapshots instead of checking
+//! them, after reviewing the change.
+
+use crate::error::{CompileError, Result};
+use crate::pipeline::CompilationPipeline;
+use fastforth_optimizer::report::MAIN;
+use fastforth_optimizer::{CacheDepth, ForthIR, Instruction, OptimizationLevel, Optimizer, OptimizerTracer, WordDiff};
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Environment variable that makes [`PassSnapshots::check`] write
+/// snapshots instead of comparing them
+pub const UPDATE_SNAPSHOTS_ENV: &str = "FIFTH_UPDATE_SNAPSHOTS";
+
+/// Architecture whose stack cache depth traces use, so that snapshots
+/// do not depend on the host
+pub const SNAPSHOT_ARCH: &str = "x86_64";
+
+/// Lower a program and optimize it at `level`, tracing every pass
+pub fn trace_source(source: &str, level: OptimizationLevel) -> Result<OptimizerTracer> {
+    let ir = CompilationPipeline::new(OptimizationLevel::None).lower_source(source)?;
+    let mut optimizer = Optimizer::new(level);
+    optimizer.set_cache_depth(CacheDepth::for_arch(SNAPSHOT_ARCH));
+    optimizer.set_trace(true);
+    optimizer.optimize(ir)?;
+    Ok(optimizer.take_trace().expect("tracing is enabled"))
+}
+
+/// A trace as JSON, instructions written in their IR notation
+pub fn trace_json(trace: &OptimizerTracer, level: OptimizationLevel) -> Value {
+    let passes: Vec<Value> = trace
+        .snapshots()
+        .iter()
+        .map(|snapshot| json!({ "pass": snapshot.pass, "changes": changes_json(&snapshot.changes) }))
+        .collect();
+
+    json!({
+        "level": format!("{:?}", level),
+        "input": ir_json(trace.input()),
+        "passes": passes,
+        "output": ir_json(trace.output()),
+    })
+}
+
+fn ir_json(ir: &ForthIR) -> Value {
+    let mut words = Map::new();
+    words.insert(MAIN.to_string(), instructions_json(&ir.main));
+    let mut names: Vec<&String> = ir.words.keys().collect();
+    names.sort();
+    for name in names {
+        words.insert(name.clone(), instructions_json(&ir.words[name].instructions));
+    }
+    Value::Object(words)
+}
+
+fn changes_json(changes: &[WordDiff]) -> Value {
+    let words = changes
+        .iter()
+        .map(|diff| {
+            let hunks = diff
+                .hunks
+                .iter()
+                .map(|hunk| {
+                    json!({
+                        "before_start": hunk.before_start,
+                        "after_start": hunk.after_start,
+                        "removed": instructions_json(&hunk.removed),
+                        "added": instructions_json(&hunk.added),
+                    })
+                })
+                .collect();
+            (diff.word.clone(), Value::Array(hunks))
+        })
+        .collect();
+    Value::Object(words)
+}
+
+fn instructions_json(instructions: &[Instruction]) -> Value {
+    instructions.iter().map(|inst| Value::String(format!("{:?}", inst))).collect()
+}
+
+/// A trace that differs from its stored snapshot
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotMismatch {
+    /// Snapshot name
+    pub name: String,
+    /// First pass whose changes differ, or `input`/`output`
+    pub pass: String,
+    /// The stored entry for that pass (null if the pass is new)
+    pub expected: Value,
+    /// The entry the optimizer produced now (null if the pass is gone)
+    pub actual: Value,
+}
+
+impl fmt::Display for SnapshotMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "snapshot {} differs at {}", self.name, self.pass)?;
+        writeln!(f, "  expected {}", self.expected)?;
+        write!(f, "  actual   {}", self.actual)?;
+        write!(f, "\n(set {}=1 to accept the new trace)", UPDATE_SNAPSHOTS_ENV)
+    }
+}
+
+/// Where a trace first differs from a stored one
+pub fn first_difference(expected: &Value, actual: &Value) -> Option<(String, Value, Value)> {
+    for key in ["level", "input"] {
+        if expected[key] != actual[key] {
+            return Some((key.to_string(), expected[key].clone(), actual[key].clone()));
+        }
+    }
+
+    let passes = |trace: &Value| trace["passes"].as_array().cloned().unwrap_or_default();
+    let (expected_passes, actual_passes) = (passes(expected), passes(actual));
+    for index in 0..expected_passes.len().max(actual_passes.len()) {
+        let (old, new) = (expected_passes.get(index), actual_passes.get(index));
+        if old != new {
+            let name = new.or(old).and_then(|pass| pass["pass"].as_str()).unwrap_or("?").to_string();
+            return Some((name, old.cloned().unwrap_or(Value::Null), new.cloned().unwrap_or(Value::Null)));
+        }
+    }
+
+    (expected["output"] != actual["output"])
+        .then(|| ("output".to_string(), expected["output"].clone(), actual["output"].clone()))
+}
+
+/// A directory of pass snapshots, one `<name>.json` per program
+#[derive(Debug, Clone)]
+pub struct PassSnapshots {
+    dir: PathBuf,
+    level: OptimizationLevel,
+    update: bool,
+}
+
+impl PassSnapshots {
+    /// Snapshots in `dir` at the aggressive level, which runs every pass;
+    /// updating when `FIFTH_UPDATE_SNAPSHOTS` is set
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let update = std::env::var(UPDATE_SNAPSHOTS_ENV).is_ok_and(|value| !value.is_empty() && value != "0");
+        Self { dir: dir.into(), level: OptimizationLevel::Aggressive, update }
+    }
+
+    /// Trace at another optimization level
+    pub fn with_level(mut self, level: OptimizationLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Write snapshots instead of checking them
+    pub fn with_update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// Path of a snapshot
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", name))
+    }
+
+    /// Trace `source` and compare it with the snapshot `name`
+    ///
+    /// Returns the mismatch, if any. A missing snapshot is an error unless
+    /// updating, in which case it is written like any other.
+    pub fn check(&self, name: &str, source: &str) -> Result<Option<SnapshotMismatch>> {
+        let trace = trace_source(source, self.level)?;
+        let actual = trace_json(&trace, self.level);
+        let path = self.path(name);
+
+        if self.update {
+            write_json(&path, &actual)?;
+            return Ok(None);
+        }
+
+        let stored = std::fs::read_to_string(&path).map_err(|e| CompileError::IoError(path.clone(), e))?;
+        let expected: Value = serde_json::from_str(&stored)
+            .map_err(|e| CompileError::InternalError(format!("{}: {}", path.display(), e)))?;
+        Ok(first_difference(&expected, &actual).map(|(pass, expected, actual)| SnapshotMismatch {
+            name: name.to_string(),
+            pass,
+            expected,
+            actual,
+        }))
+    }
+}
+
+fn write_json(path: &Path, value: &Value) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| CompileError::IoError(dir.to_path_buf(), e))?;
+    }
+    let json = serde_json::to_string_pretty(value).expect("JSON values serialize");
+    std::fs::write(path, json + "\n").map_err(|e| CompileError::IoError(path.to_path_buf(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_json_lists_pass_changes() {
+        let trace = trace_source(": sq dup * ; 2 3 + sq", OptimizationLevel::Basic).unwrap();
+        let json = trace_json(&trace, OptimizationLevel::Basic);
+
+        assert_eq!(json["level"], "Basic");
+        assert_eq!(json["input"][MAIN][0], "Literal(2)");
+        let fold = &json["passes"][0];
+        assert_eq!(fold["pass"], "constant_fold");
+        assert_eq!(fold["changes"][MAIN][0]["added"][0], "Literal(5)");
+    }
+
+    #[test]
+    fn test_check_names_the_pass() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshots = PassSnapshots::new(dir.path()).with_level(OptimizationLevel::Basic);
+
+        assert!(snapshots.check("sum", "2 3 +").is_err(), "missing snapshot");
+        snapshots.clone().with_update(true).check("sum", "2 3 +").unwrap();
+        assert_eq!(snapshots.check("sum", "2 3 +").unwrap(), None);
+
+        // Pretend constant folding used to produce 6
+        let path = snapshots.path("sum");
+        let stored = std::fs::read_to_string(&path).unwrap().replace("Literal(5)", "Literal(6)");
+        std::fs::write(&path, stored).unwrap();
+        let mismatch = snapshots.check("sum", "2 3 +").unwrap().unwrap();
+        assert_eq!(mismatch.pass, "constant_fold");
+        assert!(mismatch.to_string().contains(UPDATE_SNAPSHOTS_ENV));
+    }
+}
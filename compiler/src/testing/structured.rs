@@ -0,0 +1,626 @@
+//! Structured Program Generation
+//!
+//! Generates well-typed Forth programs from a small grammar: definitions
+//! calling earlier definitions, nested `if`/`else`/`then`, counted
+//! `do`/`loop`s reading their indices, and `begin`/`until` loops with a
+//! counter on the return stack. Every construct has a fixed stack effect,
+//! so the program never underflows, and every loop runs a bounded number
+//! of times.
+//!
+//! The generator evaluates what it builds, so each program comes with the
+//! stack it must leave. [`check_optimization_levels`] runs the lowered IR
+//! through the optimizer at every level and then through the reference
+//! [`IrInterpreter`], and compiles the program with the Cranelift JIT at
+//! the same level, and reports the first level whose result differs: a
+//! miscompile in one of the passes or in native code generation. With the
+//! `proptest` feature,
+//! [`strategies::arb_structured_program`] feeds the generator to property
+//! tests.
+
+use crate::backend::Backend;
+use crate::pipeline::{CompilationMode, CompilationPipeline};
+use fastforth_optimizer::{IrInterpreter, OptimizationLevel, Optimizer};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Levels [`check_optimization_levels`] compares
+pub const OPTIMIZATION_LEVELS: [OptimizationLevel; 4] = [
+    OptimizationLevel::None,
+    OptimizationLevel::Basic,
+    OptimizationLevel::Standard,
+    OptimizationLevel::Aggressive,
+];
+
+/// Default nesting depth of generated expressions
+pub const DEFAULT_MAX_DEPTH: usize = 4;
+
+/// Default most definitions in a program
+pub const DEFAULT_MAX_WORDS: usize = 4;
+
+/// Most iterations of a generated loop
+const MAX_ITERATIONS: i64 = 5;
+
+/// Generated literals lie in `-LITERAL_RANGE..=LITERAL_RANGE`
+const LITERAL_RANGE: i64 = 20;
+
+/// A program and the data stack it must leave
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratedProgram {
+    pub source: String,
+    pub expected: Vec<i64>,
+}
+
+/// What ran a program [`check_optimization_levels`] compiled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runner {
+    /// The optimized IR, on the reference interpreter
+    Reference,
+    /// Native code from the Cranelift JIT
+    Jit,
+}
+
+impl fmt::Display for Runner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Runner::Reference => "reference interpreter",
+            Runner::Jit => "jit",
+        })
+    }
+}
+
+/// A level whose optimized program left the wrong stack
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelMismatch {
+    pub source: String,
+    pub level: OptimizationLevel,
+    pub runner: Runner,
+    pub expected: Vec<i64>,
+    pub actual: std::result::Result<Vec<i64>, String>,
+}
+
+impl fmt::Display for LevelMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "miscompile at {:?} on the {}: {}", self.level, self.runner, self.source)?;
+        writeln!(f, "  expected {:?}", self.expected)?;
+        match &self.actual {
+            Ok(stack) => writeln!(f, "  actual   {:?}", stack),
+            Err(e) => writeln!(f, "  actual   error: {}", e),
+        }
+    }
+}
+
+/// Run `program` at every optimization level, on the reference
+/// interpreter and through the JIT, and compare each result with the
+/// expected stack
+///
+/// A level whose optimizer rejects the program is judged on the
+/// unoptimized IR, which is what compilation falls back to.
+pub fn check_optimization_levels(program: &GeneratedProgram) -> std::result::Result<(), LevelMismatch> {
+    let mismatch = |level, runner, actual| LevelMismatch {
+        source: program.source.clone(),
+        level,
+        runner,
+        expected: program.expected.clone(),
+        actual,
+    };
+
+    let ir = CompilationPipeline::new(OptimizationLevel::None)
+        .lower_source(&program.source)
+        .map_err(|e| mismatch(OptimizationLevel::None, Runner::Reference, Err(e.to_string())))?;
+
+    for level in OPTIMIZATION_LEVELS {
+        // As in compilation, a program the optimizer rejects runs unoptimized
+        let run = panic::catch_unwind(AssertUnwindSafe(|| {
+            let optimized = Optimizer::new(level).optimize(ir.clone()).unwrap_or_else(|_| ir.clone());
+            IrInterpreter::new(&optimized).run().map_err(|e| e.to_string())
+        }))
+        .unwrap_or_else(|_| Err("optimizer panicked".to_string()));
+        if run.as_ref() != Ok(&program.expected) {
+            return Err(mismatch(level, Runner::Reference, run));
+        }
+
+        let run = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut pipeline = CompilationPipeline::new(level);
+            pipeline.set_backend(Backend::Cranelift);
+            pipeline
+                .compile(&program.source, CompilationMode::JIT)
+                .map(|result| result.stack)
+                .map_err(|e| e.to_string())
+        }))
+        .unwrap_or_else(|_| Err("compiler panicked".to_string()));
+        if run.as_ref() != Ok(&program.expected) {
+            return Err(mismatch(level, Runner::Jit, run));
+        }
+    }
+
+    Ok(())
+}
+
+/// Primitive words, with fixed stack effects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prim {
+    Add,
+    Sub,
+    Mul,
+    And,
+    Or,
+    Xor,
+    Negate,
+    Abs,
+    Dup,
+    Drop,
+    Swap,
+    Over,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    ZeroEq,
+    ZeroLt,
+}
+
+impl Prim {
+    const BINARY: [Prim; 6] = [Prim::Add, Prim::Sub, Prim::Mul, Prim::And, Prim::Or, Prim::Xor];
+    const UNARY: [Prim; 2] = [Prim::Negate, Prim::Abs];
+    const COMPARE: [Prim; 4] = [Prim::Eq, Prim::Ne, Prim::Lt, Prim::Gt];
+    const TEST: [Prim; 2] = [Prim::ZeroEq, Prim::ZeroLt];
+
+    fn spelling(self) -> &'static str {
+        match self {
+            Prim::Add => "+",
+            Prim::Sub => "-",
+            Prim::Mul => "*",
+            Prim::And => "and",
+            Prim::Or => "or",
+            Prim::Xor => "xor",
+            Prim::Negate => "negate",
+            Prim::Abs => "abs",
+            Prim::Dup => "dup",
+            Prim::Drop => "drop",
+            Prim::Swap => "swap",
+            Prim::Over => "over",
+            Prim::Eq => "=",
+            Prim::Ne => "<>",
+            Prim::Lt => "<",
+            Prim::Gt => ">",
+            Prim::ZeroEq => "0=",
+            Prim::ZeroLt => "0<",
+        }
+    }
+
+    fn apply(self, stack: &mut Vec<i64>) {
+        let flag = |condition: bool| if condition { -1 } else { 0 };
+        match self {
+            Prim::Negate | Prim::Abs | Prim::ZeroEq | Prim::ZeroLt => {
+                let a = stack.pop().expect("generated program underflowed");
+                stack.push(match self {
+                    Prim::Negate => a.wrapping_neg(),
+                    Prim::Abs => a.wrapping_abs(),
+                    Prim::ZeroEq => flag(a == 0),
+                    _ => flag(a < 0),
+                });
+            }
+            Prim::Dup => {
+                let a = *stack.last().expect("generated program underflowed");
+                stack.push(a);
+            }
+            Prim::Drop => {
+                stack.pop();
+            }
+            Prim::Over => {
+                let a = stack[stack.len() - 2];
+                stack.push(a);
+            }
+            Prim::Swap => {
+                let len = stack.len();
+                stack.swap(len - 1, len - 2);
+            }
+            _ => {
+                let b = stack.pop().expect("generated program underflowed");
+                let a = stack.pop().expect("generated program underflowed");
+                stack.push(match self {
+                    Prim::Add => a.wrapping_add(b),
+                    Prim::Sub => a.wrapping_sub(b),
+                    Prim::Mul => a.wrapping_mul(b),
+                    Prim::And => a & b,
+                    Prim::Or => a | b,
+                    Prim::Xor => a ^ b,
+                    Prim::Eq => flag(a == b),
+                    Prim::Ne => flag(a != b),
+                    Prim::Lt => flag(a < b),
+                    _ => flag(a > b),
+                });
+            }
+        }
+    }
+}
+
+/// One construct of a generated program
+#[derive(Debug, Clone)]
+enum Node {
+    Literal(i64),
+    Prim(Prim),
+    /// Call of the definition with this index
+    Call(usize),
+    /// `if ... else ... then`, consuming a flag
+    If { then: Vec<Node>, otherwise: Vec<Node> },
+    /// `count 0 do ... loop`, with a body of effect `( x -- x )`
+    DoLoop { count: i64, body: Vec<Node> },
+    /// `count begin >r ... r> 1 - dup 0= until drop`, with a body of effect
+    /// `( x -- x )`
+    Repeat { count: i64, body: Vec<Node> },
+    /// `i` (0) or `j` (1)
+    Index(usize),
+}
+
+impl Node {
+    fn render(nodes: &[Node], words: &[String], out: &mut Vec<String>) {
+        for node in nodes {
+            match node {
+                Node::Literal(value) => out.push(value.to_string()),
+                Node::Prim(prim) => out.push(prim.spelling().to_string()),
+                Node::Call(word) => out.push(words[*word].clone()),
+                Node::If { then, otherwise } => {
+                    out.push("if".to_string());
+                    Self::render(then, words, out);
+                    out.push("else".to_string());
+                    Self::render(otherwise, words, out);
+                    out.push("then".to_string());
+                }
+                Node::DoLoop { count, body } => {
+                    out.extend([count.to_string(), "0".to_string(), "do".to_string()]);
+                    Self::render(body, words, out);
+                    out.push("loop".to_string());
+                }
+                Node::Repeat { count, body } => {
+                    out.extend([count.to_string(), "begin".to_string(), ">r".to_string()]);
+                    Self::render(body, words, out);
+                    out.extend(["r>", "1", "-", "dup", "0=", "until", "drop"].map(String::from));
+                }
+                Node::Index(0) => out.push("i".to_string()),
+                Node::Index(_) => out.push("j".to_string()),
+            }
+        }
+    }
+}
+
+/// Evaluates generated nodes, the oracle for expected results
+struct Evaluator<'a> {
+    words: &'a [Vec<Node>],
+    stack: Vec<i64>,
+    /// Indices of the enclosing counted loops, innermost last
+    indices: Vec<i64>,
+}
+
+impl Evaluator<'_> {
+    fn eval(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            match node {
+                Node::Literal(value) => self.stack.push(*value),
+                Node::Prim(prim) => prim.apply(&mut self.stack),
+                Node::Call(word) => {
+                    // A definition sees no loop indices of its caller
+                    let words = self.words;
+                    let indices = std::mem::take(&mut self.indices);
+                    self.eval(&words[*word]);
+                    self.indices = indices;
+                }
+                Node::If { then, otherwise } => {
+                    let flag = self.stack.pop().expect("generated program underflowed");
+                    self.eval(if flag != 0 { then } else { otherwise });
+                }
+                Node::DoLoop { count, body } => {
+                    for index in 0..*count {
+                        self.indices.push(index);
+                        self.eval(body);
+                        self.indices.pop();
+                    }
+                }
+                Node::Repeat { count, body } => {
+                    let indices = std::mem::take(&mut self.indices);
+                    for _ in 0..*count {
+                        self.eval(body);
+                    }
+                    self.indices = indices;
+                }
+                Node::Index(outer) => {
+                    let index = self.indices[self.indices.len() - 1 - outer];
+                    self.stack.push(index);
+                }
+            }
+        }
+    }
+}
+
+/// A definition and whether it takes one argument or two
+struct WordShape {
+    binary: bool,
+}
+
+/// Seeded generator of structured programs
+pub struct StructuredGenerator {
+    rng: SplitMix64,
+    max_depth: usize,
+    max_words: usize,
+    shapes: Vec<WordShape>,
+}
+
+impl StructuredGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: SplitMix64::new(seed),
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_words: DEFAULT_MAX_WORDS,
+            shapes: Vec::new(),
+        }
+    }
+
+    /// Set how deeply expressions, conditionals and loops nest
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Set the most definitions in a program
+    pub fn with_max_words(mut self, words: usize) -> Self {
+        self.max_words = words;
+        self
+    }
+
+    /// Generate the next program
+    pub fn generate(&mut self) -> GeneratedProgram {
+        self.shapes.clear();
+
+        let mut bodies = Vec::new();
+        let mut names = Vec::new();
+        for index in 0..self.rng.below(self.max_words as u64 + 1) as usize {
+            let binary = self.rng.chance(3);
+            let depth = self.max_depth.saturating_sub(1);
+            let body = if binary {
+                // ( a b -- c ): transform b, swap, transform a, combine
+                let mut body = self.transform(depth, 0);
+                body.push(Node::Prim(Prim::Swap));
+                body.extend(self.transform(depth, 0));
+                body.push(Node::Prim(self.pick(&Prim::BINARY)));
+                body
+            } else {
+                self.transform(depth, 0)
+            };
+            bodies.push(body);
+            names.push(format!("w{}", index));
+            self.shapes.push(WordShape { binary });
+        }
+
+        let values = 1 + self.rng.below(3) as usize;
+        let mut main = Vec::new();
+        for _ in 0..values {
+            main.extend(self.value(self.max_depth, 0));
+        }
+
+        let mut evaluator = Evaluator { words: &bodies, stack: Vec::new(), indices: Vec::new() };
+        evaluator.eval(&main);
+
+        let mut lines = Vec::new();
+        for (name, body) in names.iter().zip(&bodies) {
+            let mut words = vec![":".to_string(), name.clone()];
+            Node::render(body, &names, &mut words);
+            words.push(";".to_string());
+            lines.push(words.join(" "));
+        }
+        let mut words = Vec::new();
+        Node::render(&main, &names, &mut words);
+        lines.push(words.join(" "));
+
+        GeneratedProgram { source: lines.join("\n"), expected: evaluator.stack }
+    }
+
+    /// Nodes of effect `( -- x )`, with `loops` counted loop indices in scope
+    fn value(&mut self, depth: usize, loops: usize) -> Vec<Node> {
+        let literal = Node::Literal(self.literal());
+        if depth == 0 {
+            return vec![literal];
+        }
+
+        match self.rng.below(5) {
+            0 if loops > 0 => vec![Node::Index(self.rng.below(loops.min(2) as u64) as usize)],
+            1 => {
+                let mut nodes = self.value(depth - 1, loops);
+                nodes.extend(self.value(depth - 1, loops));
+                nodes.push(self.binary_word());
+                nodes
+            }
+            2 | 3 => {
+                let mut nodes = self.value(depth - 1, loops);
+                nodes.extend(self.transform(depth - 1, loops));
+                nodes
+            }
+            _ => vec![literal],
+        }
+    }
+
+    /// Nodes of effect `( x -- x' )`
+    fn transform(&mut self, depth: usize, loops: usize) -> Vec<Node> {
+        if depth == 0 {
+            return vec![Node::Literal(self.literal()), Node::Prim(self.pick(&Prim::BINARY))];
+        }
+
+        match self.rng.below(11) {
+            0 => vec![Node::Prim(self.pick(&Prim::UNARY))],
+            1 => {
+                // dup T op
+                let mut nodes = vec![Node::Prim(Prim::Dup)];
+                nodes.extend(self.transform(depth - 1, loops));
+                nodes.push(Node::Prim(self.pick(&Prim::BINARY)));
+                nodes
+            }
+            2 if self.shapes.iter().any(|shape| !shape.binary) => {
+                let unary: Vec<usize> = (0..self.shapes.len()).filter(|i| !self.shapes[*i].binary).collect();
+                vec![Node::Call(unary[self.rng.below(unary.len() as u64) as usize])]
+            }
+            3 => {
+                // dup <condition> if T else T then
+                let mut nodes = vec![Node::Prim(Prim::Dup)];
+                if self.rng.chance(2) {
+                    nodes.push(Node::Prim(self.pick(&Prim::TEST)));
+                } else {
+                    nodes.push(Node::Literal(self.literal()));
+                    nodes.push(Node::Prim(self.pick(&Prim::COMPARE)));
+                }
+                nodes.push(Node::If {
+                    then: self.transform(depth - 1, loops),
+                    otherwise: self.transform(depth - 1, loops),
+                });
+                nodes
+            }
+            4 => vec![Node::DoLoop { count: self.count(), body: self.transform(depth - 1, loops + 1) }],
+            5 => vec![Node::Repeat { count: self.count(), body: self.transform(depth - 1, 0) }],
+            6 => {
+                // V op, combining with a fresh value
+                let mut nodes = self.value(depth - 1, loops);
+                nodes.push(self.binary_word());
+                nodes
+            }
+            7 => {
+                // V swap op, combining with the operands reversed
+                let mut nodes = self.value(depth - 1, loops);
+                nodes.push(Node::Prim(Prim::Swap));
+                nodes.push(self.binary_word());
+                nodes
+            }
+            8 => {
+                // V drop, computing a value nobody uses
+                let mut nodes = self.value(depth - 1, loops);
+                nodes.push(Node::Prim(Prim::Drop));
+                nodes
+            }
+            9 => {
+                // V over op swap drop, combining while keeping the original
+                // until the end
+                let mut nodes = self.value(depth - 1, loops);
+                nodes.push(Node::Prim(Prim::Over));
+                nodes.push(self.binary_word());
+                nodes.push(Node::Prim(Prim::Swap));
+                nodes.push(Node::Prim(Prim::Drop));
+                nodes
+            }
+            _ => vec![Node::Literal(self.literal()), Node::Prim(self.pick(&Prim::BINARY))],
+        }
+    }
+
+    /// A primitive or definition of effect `( a b -- c )`
+    fn binary_word(&mut self) -> Node {
+        let binary: Vec<usize> = (0..self.shapes.len()).filter(|i| self.shapes[*i].binary).collect();
+        if !binary.is_empty() && self.rng.chance(3) {
+            Node::Call(binary[self.rng.below(binary.len() as u64) as usize])
+        } else {
+            Node::Prim(self.pick(&Prim::BINARY))
+        }
+    }
+
+    fn literal(&mut self) -> i64 {
+        self.rng.below(2 * LITERAL_RANGE as u64 + 1) as i64 - LITERAL_RANGE
+    }
+
+    fn count(&mut self) -> i64 {
+        1 + self.rng.below(MAX_ITERATIONS as u64) as i64
+    }
+
+    fn pick(&mut self, prims: &[Prim]) -> Prim {
+        prims[self.rng.below(prims.len() as u64) as usize]
+    }
+}
+
+/// splitmix64, the generators' source of randomness
+pub(crate) struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..bound` (`bound` must be nonzero)
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    /// True one time in `n`
+    pub(crate) fn chance(&mut self, n: u64) -> bool {
+        self.below(n) == 0
+    }
+}
+
+#[cfg(feature = "proptest")]
+pub mod strategies {
+    use super::{GeneratedProgram, StructuredGenerator};
+    use proptest::prelude::*;
+
+    /// Structured programs, each generated from a random seed
+    pub fn arb_structured_program() -> impl Strategy<Value = GeneratedProgram> {
+        any::<u64>().prop_map(|seed| StructuredGenerator::new(seed).generate())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_programs_lower_and_evaluate() {
+        let mut generator = StructuredGenerator::new(3826);
+        for _ in 0..50 {
+            let program = generator.generate();
+            assert!(!program.expected.is_empty());
+
+            let ir = CompilationPipeline::new(OptimizationLevel::None).lower_source(&program.source).unwrap();
+            assert_eq!(IrInterpreter::new(&ir).run().as_ref(), Ok(&program.expected), "{}", program.source);
+        }
+    }
+
+    #[test]
+    fn test_optimization_levels_agree() {
+        let mut generator = StructuredGenerator::new(0x5eed);
+        for _ in 0..200 {
+            let program = generator.generate();
+            if let Err(mismatch) = check_optimization_levels(&program) {
+                panic!("{}", mismatch);
+            }
+        }
+    }
+
+    #[test]
+    fn test_jit_runs_generated_constructs() {
+        // Loops that read the index or keep a counter on the return stack,
+        // in words without a stack effect comment
+        let programs = [
+            (": w0 1 0 do dup 20 = if 16 drop else -14 - then loop ; 5 w0", vec![19]),
+            (": w0 0 swap 0 do i + loop ; 5 w0", vec![10]),
+            (": w0 3 >r begin 2 * r> 1 - dup >r 0= until r> drop ; 1 w0", vec![8]),
+            (": w0 2 begin >r 1 begin >r -6 swap * r> 1 - dup 0= until drop r> 1 - dup 0= until drop ; 1 w0", vec![36]),
+        ];
+        for (source, expected) in programs {
+            let program = GeneratedProgram { source: source.to_string(), expected };
+            if let Err(mismatch) = check_optimization_levels(&program) {
+                panic!("{}", mismatch);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mismatch_is_reported() {
+        let program = GeneratedProgram { source: "2 3 +".to_string(), expected: vec![6] };
+        let mismatch = check_optimization_levels(&program).unwrap_err();
+        assert_eq!(mismatch.level, OptimizationLevel::None);
+        assert_eq!(mismatch.runner, Runner::Reference);
+        assert_eq!(mismatch.actual, Ok(vec![5]));
+    }
+}
@@ -0,0 +1,379 @@
+//! Debugger
+//!
+//! Runs a program in the threaded interpreter under the user's control, one
+//! op at a time. Every word stays interpreted, so each one can be stepped
+//! through. A run stops at the start, on entry to a word with a breakpoint,
+//! after an op changes a watched cell, and after a step. At each stop the
+//! debugger prints where it is and both stacks, then reads commands:
+//!
+//! | Command | Does |
+//! |---------|------|
+//! | `step`, `s` | Run one op, into calls |
+//! | `next`, `n` | Run one op, over calls |
+//! | `finish`, `f` | Run until the current word returns |
+//! | `continue`, `c` | Run until the next breakpoint or watchpoint |
+//! | `break WORD`, `b` | Stop whenever WORD is entered |
+//! | `delete WORD`, `d` | Remove WORD's breakpoint |
+//! | `watch VAR-OR-ADDR`, `w` | Stop when the cell changes |
+//! | `backtrace`, `bt` | Show the Forth call stack |
+//! | `stack`, `.s` | Show the data and return stacks |
+//! | `trace on`, `trace off` | Print every op as it runs |
+//! | `quit`, `q` | Stop the program |
+//!
+//! An empty line steps. At the end of input the program runs to completion
+//! without stopping again.
+
+use super::threaded::{Interpret, ThreadedProgram, Tracer, Vm};
+use crate::error::{CompileError, Result};
+use crate::pipeline::CompilationPipeline;
+use fastforth_optimizer::OptimizationLevel;
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+/// Where the run goes before it next stops
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resume {
+    /// Stop at the next op
+    Step,
+    /// Stop at the next op no deeper than this many calls
+    Next(usize),
+    /// Stop at the next op shallower than this many calls
+    Finish(usize),
+    /// Stop only at breakpoints and watchpoints
+    Continue,
+}
+
+/// A watched cell and the value it had at the last op
+#[derive(Debug, Clone)]
+struct Watch {
+    label: String,
+    addr: i64,
+    value: Option<i64>,
+}
+
+/// Breakpoints, watchpoints and tracing for debugging a program
+#[derive(Debug, Clone)]
+pub struct Debugger {
+    breakpoints: BTreeSet<String>,
+    watches: Vec<String>,
+    trace: bool,
+    stop_at_start: bool,
+}
+
+impl Debugger {
+    /// A debugger that stops before the first op
+    pub fn new() -> Self {
+        Self { breakpoints: BTreeSet::new(), watches: Vec::new(), trace: false, stop_at_start: true }
+    }
+
+    /// Stop whenever `word` is entered
+    pub fn break_at(&mut self, word: &str) {
+        self.breakpoints.insert(word.to_string());
+    }
+
+    /// Stop after any op that changes the cell of a variable, or at an
+    /// address
+    pub fn watch(&mut self, target: &str) {
+        self.watches.push(target.to_string());
+    }
+
+    /// Print every op, with the stacks, as it runs
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    /// Whether to stop before the first op, or run to the first breakpoint
+    pub fn stop_at_start(&mut self, stop: bool) {
+        self.stop_at_start = stop;
+    }
+
+    /// Debug `source`, reading commands from `input` and writing stops and
+    /// the program's output to `output`; returns the final data stack
+    ///
+    /// `quit` fails the run with [`CompileError::Cancelled`].
+    pub fn run(&mut self, source: &str, input: &mut dyn BufRead, output: &mut dyn Write) -> Result<Vec<i64>> {
+        let ir = CompilationPipeline::new(OptimizationLevel::None).lower_source(source)?;
+        let mut vm = Vm::new();
+        let program = ThreadedProgram::compile(&ir, &mut vm)?;
+
+        if let Some(word) = self.breakpoints.iter().find(|word| program.word(word).is_none()) {
+            return Err(CompileError::SemanticError(format!("Undefined word: {}", word)));
+        }
+        let watches = self.watches.iter().map(|target| resolve(target, &vm)).collect::<Result<Vec<_>>>()?;
+
+        let mut session = Session {
+            breakpoints: &mut self.breakpoints,
+            watches,
+            trace: self.trace,
+            input,
+            output,
+            resume: if self.stop_at_start { Resume::Step } else { Resume::Continue },
+            depth: 0,
+            attached: true,
+            last: (program.main(), Vec::new()),
+        };
+        let result = vm.run_traced(&program, program.main(), &mut Interpret, &mut session);
+        self.trace = session.trace;
+
+        let text = vm.take_output();
+        write!(session.output, "{}", text).map_err(io)?;
+        match result {
+            Ok(()) => {
+                writeln!(session.output, "program finished {}", depth_and_items(vm.stack())).map_err(io)?;
+                Ok(vm.stack().to_vec())
+            }
+            Err(CompileError::Cancelled) => Err(CompileError::Cancelled),
+            Err(e) => {
+                let (pc, frames) = std::mem::take(&mut session.last);
+                writeln!(session.output, "error: {}", e).map_err(io)?;
+                session.backtrace(&program, pc, &frames)?;
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A watchpoint on a variable's cell or on an address
+fn resolve(target: &str, vm: &Vm) -> Result<Watch> {
+    let addr = match vm.variable(target) {
+        Some(addr) => addr,
+        None => target
+            .parse::<i64>()
+            .map_err(|_| CompileError::SemanticError(format!("No variable or address named {}", target)))?,
+    };
+    Ok(Watch { label: target.to_string(), addr, value: vm.cell(addr).ok() })
+}
+
+/// One debugging run, stopping the interpreter as the user asked
+struct Session<'a> {
+    breakpoints: &'a mut BTreeSet<String>,
+    watches: Vec<Watch>,
+    trace: bool,
+    input: &'a mut dyn BufRead,
+    output: &'a mut dyn Write,
+    resume: Resume,
+    /// Calls in progress at the previous op, to tell when a word is entered
+    depth: usize,
+    /// Whether commands are still read; cleared at the end of input
+    attached: bool,
+    /// The op about to run and its return addresses, to report an error
+    last: (usize, Vec<usize>),
+}
+
+impl Tracer for Session<'_> {
+    fn before(&mut self, program: &ThreadedProgram, pc: usize, frames: &[usize], vm: &mut Vm) -> Result<()> {
+        let text = vm.take_output();
+        write!(self.output, "{}", text).map_err(io)?;
+        self.last = (pc, frames.to_vec());
+
+        let entered = frames.len() > self.depth;
+        self.depth = frames.len();
+        let mut reasons = Vec::new();
+        for watch in &mut self.watches {
+            let value = vm.cell(watch.addr).ok();
+            if value != watch.value {
+                reasons.push(format!("watch {}: {} -> {}", watch.label, show(watch.value), show(value)));
+                watch.value = value;
+            }
+        }
+        if entered {
+            let word = program.word_at(pc).map(|word| &program.names()[word]);
+            if let Some(word) = word.filter(|word| self.breakpoints.contains(word.as_str())) {
+                reasons.push(format!("breakpoint {}", word));
+            }
+        }
+
+        let working = !program.is_control(pc);
+        let stepped = match self.resume {
+            Resume::Step => working,
+            Resume::Next(depth) => working && frames.len() <= depth,
+            Resume::Finish(depth) => working && frames.len() < depth,
+            Resume::Continue => false,
+        };
+        if self.trace && working {
+            self.location(program, pc, vm)?;
+        }
+        if !self.attached || (reasons.is_empty() && !stepped) {
+            return Ok(());
+        }
+
+        for reason in reasons {
+            writeln!(self.output, "{}", reason).map_err(io)?;
+        }
+        if !self.trace || !working {
+            self.location(program, pc, vm)?;
+        }
+        self.prompt(program, pc, frames, vm)
+    }
+}
+
+impl Session<'_> {
+    /// Read commands until one resumes the run
+    fn prompt(&mut self, program: &ThreadedProgram, pc: usize, frames: &[usize], vm: &mut Vm) -> Result<()> {
+        loop {
+            write!(self.output, "(fdb) ").map_err(io)?;
+            self.output.flush().map_err(io)?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line).map_err(io)? == 0 {
+                writeln!(self.output).map_err(io)?;
+                self.attached = false;
+                return Ok(());
+            }
+
+            let mut words = line.split_whitespace();
+            let command = words.next().unwrap_or("step");
+            let argument = words.next();
+            match (command, argument) {
+                ("step" | "s", _) => self.resume = Resume::Step,
+                ("next" | "n", _) => self.resume = Resume::Next(frames.len()),
+                ("finish" | "f", _) => self.resume = Resume::Finish(frames.len()),
+                ("continue" | "c", _) => self.resume = Resume::Continue,
+                ("quit" | "q", _) => return Err(CompileError::Cancelled),
+                ("break" | "b", Some(word)) => {
+                    if program.word(word).is_some() {
+                        self.breakpoints.insert(word.to_string());
+                        writeln!(self.output, "breakpoint at {}", word).map_err(io)?;
+                    } else {
+                        writeln!(self.output, "no word named {}", word).map_err(io)?;
+                    }
+                    continue;
+                }
+                ("delete" | "d", Some(word)) => {
+                    if !self.breakpoints.remove(word) {
+                        writeln!(self.output, "no breakpoint at {}", word).map_err(io)?;
+                    }
+                    continue;
+                }
+                ("watch" | "w", Some(target)) => {
+                    match resolve(target, vm) {
+                        Ok(watch) => {
+                            writeln!(self.output, "watching {} = {}", watch.label, show(watch.value)).map_err(io)?;
+                            self.watches.push(watch);
+                        }
+                        Err(e) => writeln!(self.output, "{}", e).map_err(io)?,
+                    }
+                    continue;
+                }
+                ("backtrace" | "bt", _) => {
+                    self.backtrace(program, pc, frames)?;
+                    continue;
+                }
+                ("stack" | ".s", _) => {
+                    self.location(program, pc, vm)?;
+                    continue;
+                }
+                ("trace", Some("on")) => {
+                    self.trace = true;
+                    continue;
+                }
+                ("trace", Some("off")) => {
+                    self.trace = false;
+                    continue;
+                }
+                _ => {
+                    writeln!(
+                        self.output,
+                        "commands: step, next, finish, continue, break WORD, delete WORD, watch VAR, backtrace, stack, trace on|off, quit"
+                    )
+                    .map_err(io)?;
+                    continue;
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    /// Print the op about to run and both stacks
+    fn location(&mut self, program: &ThreadedProgram, pc: usize, vm: &Vm) -> Result<()> {
+        write!(self.output, "[{}] {:<12} {}", word_name(program, pc), program.text(pc), depth_and_items(vm.stack()))
+            .map_err(io)?;
+        if !vm.return_stack().is_empty() {
+            write!(self.output, "  R: {}", depth_and_items(vm.return_stack())).map_err(io)?;
+        }
+        writeln!(self.output).map_err(io)
+    }
+
+    /// Print the calls in progress, innermost first
+    fn backtrace(&mut self, program: &ThreadedProgram, pc: usize, frames: &[usize]) -> Result<()> {
+        let calls = std::iter::once(pc).chain(frames.iter().rev().map(|&ret| ret - 1));
+        for (level, pc) in calls.enumerate() {
+            writeln!(self.output, "#{} [{}] {}", level, word_name(program, pc), program.text(pc)).map_err(io)?;
+        }
+        Ok(())
+    }
+}
+
+/// Name of the word the op at `pc` belongs to
+fn word_name(program: &ThreadedProgram, pc: usize) -> &str {
+    program.word_at(pc).map_or("top level", |word| program.names()[word].as_str())
+}
+
+/// A stack as `.s` shows it: depth, then items bottom first
+fn depth_and_items(stack: &[i64]) -> String {
+    let items: Vec<String> = stack.iter().map(i64::to_string).collect();
+    format!("<{}> {}", stack.len(), items.join(" ")).trim_end().to_string()
+}
+
+fn show(value: Option<i64>) -> String {
+    value.map_or("?".to_string(), |value| value.to_string())
+}
+
+fn io(e: std::io::Error) -> CompileError {
+    CompileError::RuntimeError(format!("debugger I/O failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debug(debugger: &mut Debugger, source: &str, commands: &str) -> (Result<Vec<i64>>, String) {
+        let mut output = Vec::new();
+        let result = debugger.run(source, &mut commands.as_bytes(), &mut output);
+        (result, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_breakpoints_steps_and_watchpoints() {
+        let source = "variable x\n: sq ( n -- n ) dup * ;\n: save ( n -- ) sq x ! ;\n3 save x @ 1 +";
+        let mut debugger = Debugger::new();
+        debugger.stop_at_start(false);
+        debugger.break_at("sq");
+        debugger.watch("x");
+        let (result, output) = debug(&mut debugger, source, "bt\nnext\nstep\nfinish\ncontinue\n");
+        assert_eq!(result.unwrap(), vec![10]);
+        assert!(output.contains("breakpoint sq\n[sq] dup          <1> 3\n"), "{}", output);
+        assert!(output.contains("#0 [sq] dup\n#1 [save] sq\n#2 [top level] save\n"), "{}", output);
+        assert!(output.contains("[sq] *            <2> 3 3\n"), "{}", output);
+        assert!(output.contains("watch x: 0 -> 9\n"), "{}", output);
+        assert!(output.ends_with("program finished <1> 10\n"), "{}", output);
+    }
+
+    #[test]
+    fn test_stepping_from_the_start() {
+        let mut debugger = Debugger::new();
+        let (result, output) = debug(&mut debugger, ": inc 1 + ;\n2 inc . 5", "next\nnext\n\nquit\n");
+        assert!(matches!(result, Err(CompileError::Cancelled)));
+        // `next` runs all of `inc` in one step
+        assert!(output.contains("[top level] 2            <0>\n"), "{}", output);
+        assert!(output.contains("[top level] inc          <1> 2\n"), "{}", output);
+        assert!(output.contains("[top level] .            <1> 3\n"), "{}", output);
+        assert!(!output.contains("[inc]"), "{}", output);
+    }
+
+    #[test]
+    fn test_tracing_and_errors() {
+        let mut debugger = Debugger::new();
+        debugger.set_trace(true);
+        debugger.stop_at_start(false);
+        let (result, output) = debug(&mut debugger, ": half 0 / ;\n4 half", "");
+        assert!(result.is_err());
+        assert!(output.contains("[half] /            <2> 4 0\n"), "{}", output);
+        assert!(output.contains("error: Runtime error: Division by zero\n#0 [half] /\n#1 [top level] half\n"), "{}", output);
+    }
+}
@@ -0,0 +1,649 @@
+//! Tiered Execution
+//!
+//! Programs start in the threaded interpreter ([`threaded`]), which loads
+//! unoptimized IR with no code generation at all, so an interactive line
+//! runs as soon as it is parsed. Every call to a colon definition passes
+//! through the [`TieredEngine`], which counts it. Once a word has been
+//! called [`TierPolicy::jit_threshold`] times it is compiled with Cranelift
+//! and later calls run the native code; a word still hot at
+//! [`TierPolicy::llvm_threshold`] is offered to LLVM.
+//!
+//! A word is promoted only when native code can reproduce it exactly: it
+//! takes at most six items, leaves exactly one, uses only stack and
+//! arithmetic instructions and forward branches (comparisons only as
+//! branch conditions), and calls only words that qualify in turn. Anything
+//! else (loops, memory, output, division, which could trap) stays
+//! interpreted, and the reason is kept in [`TieredEngine::diagnostics`].
+//!
+//! A word marked `\ @hot` is compiled on its first call, and one marked
+//! `\ @optimize(none)` is compiled without Cranelift's optimizations. One
+//! marked `\ @constant-time` is compiled branchless where it can be, but
+//! the interpreter makes no timing promise before then.
+//!
+//! The call counts are an execution profile: [`TieredEngine::profile`]
+//! feeds them to the PGO superinstruction pass.
+
+pub mod debugger;
+pub mod threaded;
+
+use crate::backend::{BackendSelector, BackendType};
+use crate::error::{CompileError, Result};
+use crate::pipeline::{CompilationMode, CompilationPipeline};
+use backend::cranelift::{CraneliftBackend, CraneliftSettings};
+use fastforth_frontend::{constant_time, convert_to_ssa, Arithmetic, Definition, Program};
+use fastforth_optimizer::{ForthIR, Instruction, OptimizationLevel, PGOOptimizer};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use threaded::{CallHook, ThreadedProgram, Vm};
+
+/// Most items a native word may take, one per register argument
+pub const MAX_NATIVE_ARITY: usize = 6;
+
+/// When words move to a faster tier
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TierPolicy {
+    /// Calls after which a word is compiled with Cranelift
+    pub jit_threshold: u64,
+    /// Calls after which a Cranelift word is offered to LLVM, if ever
+    pub llvm_threshold: Option<u64>,
+}
+
+impl Default for TierPolicy {
+    fn default() -> Self {
+        Self { jit_threshold: 100, llvm_threshold: Some(10_000) }
+    }
+}
+
+/// Where a word currently runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Tier {
+    Interpreted,
+    Cranelift,
+    Llvm,
+}
+
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tier::Interpreted => write!(f, "interpreted"),
+            Tier::Cranelift => write!(f, "cranelift"),
+            Tier::Llvm => write!(f, "llvm"),
+        }
+    }
+}
+
+/// Call count and tier of one word
+#[derive(Debug, Clone)]
+struct WordState {
+    name: String,
+    calls: u64,
+    /// Calls through EXECUTE, part of `calls`
+    executed: u64,
+    tier: Tier,
+    native: Option<NativeWord>,
+    /// Promotion to Cranelift failed; not retried until the word changes
+    stuck: bool,
+    /// LLVM promotion was considered
+    llvm_checked: bool,
+}
+
+impl WordState {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            calls: 0,
+            executed: 0,
+            tier: Tier::Interpreted,
+            native: None,
+            stuck: false,
+            llvm_checked: false,
+        }
+    }
+}
+
+/// Entry point of a compiled word
+#[derive(Debug, Clone, Copy)]
+struct NativeWord {
+    code: *const u8,
+    arity: usize,
+}
+
+/// Promotion state, the [`CallHook`] the interpreter runs calls through
+struct Tiers {
+    policy: TierPolicy,
+    definitions: Vec<Definition>,
+    ir: ForthIR,
+    words: Vec<WordState>,
+    /// Compiled code stays mapped for as long as its module lives
+    modules: Vec<CraneliftBackend>,
+    diagnostics: Vec<String>,
+}
+
+impl CallHook for Tiers {
+    fn enter(&mut self, word: usize, vm: &mut Vm) -> Result<bool> {
+        let state = &mut self.words[word];
+        state.calls += 1;
+        let calls = state.calls;
+
+        // Words marked @hot are compiled on their first call
+        let hot = self.ir.get_word(&state.name).is_some_and(|def| def.attributes.hot);
+        let threshold = if hot { 1 } else { self.policy.jit_threshold };
+        if state.tier == Tier::Interpreted && !state.stuck && calls >= threshold {
+            if let Err(reason) = self.promote(word) {
+                let state = &mut self.words[word];
+                state.stuck = true;
+                self.diagnostics.push(format!("{} stays interpreted: {}", state.name, reason));
+            }
+        }
+
+        let state = &mut self.words[word];
+        if state.tier == Tier::Cranelift && !state.llvm_checked && self.policy.llvm_threshold.is_some_and(|t| calls >= t) {
+            state.llvm_checked = true;
+            // There is no LLVM JIT to hand the word to yet
+            let reason = BackendSelector::unavailable_reason(BackendType::LLVM, CompilationMode::JIT)
+                .unwrap_or_else(|| "LLVM promotion is not implemented".to_string());
+            self.diagnostics.push(format!("{} stays on cranelift: {}", state.name, reason));
+        }
+
+        let Some(native) = self.words[word].native else {
+            return Ok(false);
+        };
+        let args = vm.pop_n(native.arity)?;
+        // SAFETY: the code was compiled from a word taking `arity` cells and
+        // returning one, and its module lives in `self.modules`
+        let result = unsafe { call_native(native.code, &args) };
+        vm.push(result);
+        Ok(true)
+    }
+
+    fn execute(&mut self, word: usize) {
+        self.words[word].executed += 1;
+    }
+}
+
+impl Tiers {
+    /// Compile a word and the words it calls with Cranelift
+    fn promote(&mut self, word: usize) -> std::result::Result<(), String> {
+        let name = self.words[word].name.clone();
+        let (arity, produced) = native_effect(&self.ir, &name, &mut Vec::new())?;
+        if produced != 1 {
+            return Err(format!("leaves {} items, native words return one", produced));
+        }
+        if arity > MAX_NATIVE_ARITY {
+            return Err(format!("takes {} items, native words take at most {}", arity, MAX_NATIVE_ARITY));
+        }
+
+        let callees = callees(&self.ir, &name);
+        let program = Program {
+            definitions: self.definitions.iter().filter(|def| callees.contains(&def.name)).cloned().collect(),
+            top_level_code: Vec::new(),
+            tests: Vec::new(),
+            code_words: Vec::new(),
+            records: Vec::new(),
+            names: Default::default(),
+        };
+        let mut functions = convert_to_ssa(&program).map_err(|e| e.to_string())?;
+        for func in &mut functions {
+            if self.ir.get_word(&func.name).is_some_and(|def| def.attributes.constant_time) {
+                constant_time::lower_branchless(func);
+            }
+        }
+        let parameters = functions.iter().find(|func| func.name == name).map(|func| func.parameters.len());
+        if parameters != Some(arity) {
+            return Err(format!("SSA form takes {:?} parameters, expected {}", parameters, arity));
+        }
+
+        // Cranelift does not optimize words marked @optimize(none)
+        let optimize_none = self.ir.get_word(&name).is_some_and(|def| def.attributes.optimize_none);
+        let settings = CraneliftSettings {
+            opt_level: if optimize_none { 0 } else { 1 },
+            debug_info: false,
+            target_triple: None,
+            enable_verification: cfg!(debug_assertions),
+            arithmetic: Arithmetic::default(),
+        };
+        crate::runtime_ffi::register_jit_symbols();
+        let mut module = CraneliftBackend::new(settings).map_err(|e| e.to_string())?;
+        let named: Vec<(String, &_)> = functions.iter().map(|func| (func.name.clone(), func)).collect();
+        module.declare_all_functions(&named).map_err(|e| e.to_string())?;
+        module.compile_functions(&named).map_err(|e| e.to_string())?;
+        module.finalize_all().map_err(|e| e.to_string())?;
+        let code = module.get_function(&name).ok_or_else(|| format!("{} was not compiled", name))?;
+
+        self.modules.push(module);
+        let state = &mut self.words[word];
+        state.native = Some(NativeWord { code, arity });
+        state.tier = Tier::Cranelift;
+        Ok(())
+    }
+}
+
+/// Call compiled code with `args` as its parameters
+///
+/// # Safety
+///
+/// `code` must be a live function taking `args.len()` cells and returning
+/// one, with `args.len()` at most [`MAX_NATIVE_ARITY`].
+unsafe fn call_native(code: *const u8, args: &[i64]) -> i64 {
+    use std::mem::transmute;
+    type F0 = unsafe extern "C" fn() -> i64;
+    type F1 = unsafe extern "C" fn(i64) -> i64;
+    type F2 = unsafe extern "C" fn(i64, i64) -> i64;
+    type F3 = unsafe extern "C" fn(i64, i64, i64) -> i64;
+    type F4 = unsafe extern "C" fn(i64, i64, i64, i64) -> i64;
+    type F5 = unsafe extern "C" fn(i64, i64, i64, i64, i64) -> i64;
+    type F6 = unsafe extern "C" fn(i64, i64, i64, i64, i64, i64) -> i64;
+
+    match *args {
+        [] => transmute::<*const u8, F0>(code)(),
+        [a] => transmute::<*const u8, F1>(code)(a),
+        [a, b] => transmute::<*const u8, F2>(code)(a, b),
+        [a, b, c] => transmute::<*const u8, F3>(code)(a, b, c),
+        [a, b, c, d] => transmute::<*const u8, F4>(code)(a, b, c, d),
+        [a, b, c, d, e] => transmute::<*const u8, F5>(code)(a, b, c, d, e),
+        [a, b, c, d, e, f] => transmute::<*const u8, F6>(code)(a, b, c, d, e, f),
+        _ => unreachable!("native words take at most {} cells", MAX_NATIVE_ARITY),
+    }
+}
+
+/// Whether native code computes an instruction exactly as the interpreter
+fn is_native(inst: &Instruction) -> bool {
+    use Instruction::*;
+    matches!(inst, Literal(_) | Dup | Drop | Swap | Over | Rot | Add | Sub | Mul | Neg | Abs | And | Or)
+}
+
+/// Comparisons compile to a flag of 1 rather than -1 for true, so their
+/// result may only decide a branch
+fn is_comparison(inst: &Instruction) -> bool {
+    use Instruction::*;
+    matches!(inst, Eq | Ne | Lt | Le | Gt | Ge)
+}
+
+/// Items a word consumes and produces, if it can be compiled natively
+///
+/// Follows forward branches, requiring every path to leave the same depth.
+/// Backward branches, recursion, a comparison whose flag is not branched
+/// on and instructions outside [`is_native`] disqualify the word.
+fn native_effect(ir: &ForthIR, name: &str, visiting: &mut Vec<String>) -> std::result::Result<(usize, usize), String> {
+    if visiting.iter().any(|word| word == name) {
+        return Err(format!("{} is recursive", name));
+    }
+    let word = ir.words.get(name).ok_or_else(|| format!("{} is not a colon definition", name))?;
+    visiting.push(name.to_string());
+
+    let mut depth = 0i64;
+    let mut lowest = 0i64;
+    let mut reachable = true;
+    let mut placed = HashSet::new();
+    let mut pending: HashMap<usize, i64> = HashMap::new();
+    let mut exit: Option<i64> = None;
+    let unbalanced = || format!("{} leaves different depths on different paths", name);
+    let join = |depths: &mut HashMap<usize, i64>, label: usize, depth: i64| match depths.insert(label, depth) {
+        Some(other) if other != depth => Err(unbalanced()),
+        _ => Ok(()),
+    };
+
+    for (position, inst) in word.instructions.iter().enumerate() {
+        let (consumed, produced) = match inst {
+            Instruction::Label(label) => {
+                let label = label.trim_start_matches('L').parse::<usize>().map_err(|_| format!("unknown label {}", label))?;
+                placed.insert(label);
+                match (reachable, pending.remove(&label)) {
+                    (true, Some(other)) if other != depth => return Err(unbalanced()),
+                    (false, Some(other)) => {
+                        depth = other;
+                        reachable = true;
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            _ if !reachable => continue,
+            Instruction::Branch(label) | Instruction::BranchIf(label) | Instruction::BranchIfNot(label) => {
+                if placed.contains(label) {
+                    return Err("loops are not compiled natively".to_string());
+                }
+                if !matches!(inst, Instruction::Branch(_)) {
+                    depth -= 1;
+                    lowest = lowest.min(depth);
+                }
+                join(&mut pending, *label, depth)?;
+                reachable = !matches!(inst, Instruction::Branch(_));
+                continue;
+            }
+            Instruction::Return => {
+                if exit.is_some_and(|other| other != depth) {
+                    return Err(unbalanced());
+                }
+                exit = Some(depth);
+                reachable = false;
+                continue;
+            }
+            Instruction::Comment(_) | Instruction::Nop => continue,
+            Instruction::Call(callee) => {
+                let (consumed, produced) = native_effect(ir, callee, visiting)?;
+                (consumed as i64, produced as i64)
+            }
+            inst if is_comparison(inst)
+                && matches!(
+                    word.instructions.get(position + 1),
+                    Some(Instruction::BranchIf(_) | Instruction::BranchIfNot(_))
+                ) =>
+            {
+                (2, 1)
+            }
+            inst if is_native(inst) => {
+                let effect = inst.stack_effect();
+                (effect.consumed as i64, effect.produced as i64)
+            }
+            other => return Err(format!("{:?} is not compiled natively", other)),
+        };
+        depth -= consumed;
+        lowest = lowest.min(depth);
+        depth += produced;
+    }
+    if reachable && exit.is_some_and(|other| other != depth) {
+        return Err(unbalanced());
+    }
+    let exit = if reachable { depth } else { exit.ok_or_else(|| format!("{} never returns", name))? };
+
+    visiting.pop();
+    Ok(((-lowest) as usize, (exit - lowest) as usize))
+}
+
+/// A word and every word it calls, directly or not
+fn callees(ir: &ForthIR, name: &str) -> HashSet<String> {
+    let mut seen = HashSet::new();
+    let mut work = vec![name.to_string()];
+    while let Some(word) = work.pop() {
+        if let Some(def) = ir.words.get(&word) {
+            if seen.insert(word) {
+                for inst in &def.instructions {
+                    if let Instruction::Call(callee) = inst {
+                        work.push(callee.to_string());
+                    }
+                }
+            }
+        }
+    }
+    seen
+}
+
+/// Runs programs in the threaded interpreter, promoting hot words
+pub struct TieredEngine {
+    pipeline: CompilationPipeline,
+    program: ThreadedProgram,
+    vm: Vm,
+    tiers: Tiers,
+}
+
+impl TieredEngine {
+    pub fn new(policy: TierPolicy) -> Self {
+        Self {
+            pipeline: CompilationPipeline::new(OptimizationLevel::None),
+            program: ThreadedProgram::default(),
+            vm: Vm::new(),
+            tiers: Tiers {
+                policy,
+                definitions: Vec::new(),
+                ir: ForthIR::new(),
+                words: Vec::new(),
+                modules: Vec::new(),
+                diagnostics: Vec::new(),
+            },
+        }
+    }
+
+    /// Load a program, replacing the previous one
+    ///
+    /// Words whose definition is unchanged keep their call counts and
+    /// compiled code. Redefining a word drops all compiled code, since
+    /// compiled callers have the old definition built in. A program that
+    /// fails to load leaves the engine as it was.
+    pub fn load(&mut self, program: &Program) -> Result<()> {
+        if let Some(word) = program.code_words.first() {
+            return Err(CompileError::SemanticError(format!(
+                "CODE word '{}' needs native compilation; use -O1 or higher",
+                word.name
+            )));
+        }
+        let ir = self.pipeline.lower_to_ir(program);
+        let mut vm = self.vm.clone();
+        let threaded = ThreadedProgram::compile(&ir, &mut vm)?;
+
+        let previous: HashMap<&str, &Definition> =
+            self.tiers.definitions.iter().map(|def| (def.name.as_str(), def)).collect();
+        let changed: HashSet<&str> = program
+            .definitions
+            .iter()
+            .filter(|def| previous.get(def.name.as_str()).is_some_and(|&old| old != *def))
+            .map(|def| def.name.as_str())
+            .collect();
+
+        let mut states: HashMap<String, WordState> =
+            self.tiers.words.drain(..).map(|state| (state.name.clone(), state)).collect();
+        let words = threaded
+            .names()
+            .iter()
+            .map(|name| {
+                let mut state = states.remove(name).unwrap_or_else(|| WordState::new(name));
+                if changed.contains(name.as_str()) {
+                    state = WordState::new(name);
+                } else if !changed.is_empty() {
+                    state = WordState { calls: state.calls, ..WordState::new(name) };
+                }
+                state
+            })
+            .collect();
+        if !changed.is_empty() {
+            self.tiers.modules.clear();
+        }
+
+        self.tiers.words = words;
+        self.tiers.definitions = program.definitions.clone();
+        self.tiers.ir = ir;
+        self.program = threaded;
+        self.vm = vm;
+        Ok(())
+    }
+
+    /// Run the loaded program's top-level code
+    ///
+    /// On an error the data stack is restored to what it was before the
+    /// run; memory writes are kept.
+    pub fn run(&mut self) -> Result<()> {
+        let stack = self.vm.stack().to_vec();
+        let result = self.vm.run(&self.program, self.program.main(), &mut self.tiers);
+        if result.is_err() {
+            self.vm.set_stack(stack);
+            self.vm.clear_return_stack();
+        }
+        result
+    }
+
+    /// Call one word of the loaded program
+    pub fn call(&mut self, name: &str) -> Result<()> {
+        let word = self.program.word(name).ok_or_else(|| CompileError::SemanticError(format!("Undefined word: {}", name)))?;
+        if !self.tiers.enter(word, &mut self.vm)? {
+            self.vm.run(&self.program, self.program.entry(word), &mut self.tiers)?;
+        }
+        Ok(())
+    }
+
+    /// Data stack, bottom first
+    pub fn stack(&self) -> &[i64] {
+        self.vm.stack()
+    }
+
+    /// Replace the data stack
+    pub fn set_stack(&mut self, stack: Vec<i64>) {
+        self.vm.set_stack(stack);
+    }
+
+    /// Take the output written by `.`, `emit` and friends so far
+    pub fn take_output(&mut self) -> String {
+        self.vm.take_output()
+    }
+
+    /// The loaded program's unoptimized IR
+    pub fn ir(&self) -> &ForthIR {
+        &self.tiers.ir
+    }
+
+    /// Tier a word runs in
+    pub fn tier(&self, name: &str) -> Option<Tier> {
+        self.state(name).map(|state| state.tier)
+    }
+
+    /// Times a word was called from interpreted code
+    ///
+    /// Calls made by compiled code to other compiled words are not seen.
+    pub fn call_count(&self, name: &str) -> u64 {
+        self.state(name).map_or(0, |state| state.calls)
+    }
+
+    /// Every word with its tier and call count, in name order
+    pub fn words(&self) -> impl Iterator<Item = (&str, Tier, u64)> {
+        self.tiers.words.iter().map(|state| (state.name.as_str(), state.tier, state.calls))
+    }
+
+    /// Why words were not promoted, in the order it happened
+    pub fn diagnostics(&self) -> &[String] {
+        &self.tiers.diagnostics
+    }
+
+    /// Record the words that ran, weighted by call count, and the words
+    /// EXECUTE reached in a PGO profile
+    pub fn profile(&self, pgo: &mut PGOOptimizer) {
+        pgo.profile_calls(&self.tiers.ir, self.tiers.words.iter().map(|state| (state.name.as_str(), state.calls)));
+        pgo.profile_execute_targets(self.tiers.words.iter().map(|state| (state.name.as_str(), state.executed)));
+    }
+
+    fn state(&self, name: &str) -> Option<&WordState> {
+        self.program.word(name).map(|word| &self.tiers.words[word])
+    }
+}
+
+impl Default for TieredEngine {
+    fn default() -> Self {
+        Self::new(TierPolicy::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastforth_frontend::parse_program;
+
+    fn engine(source: &str, jit_threshold: u64) -> TieredEngine {
+        let mut engine = TieredEngine::new(TierPolicy { jit_threshold, llvm_threshold: None });
+        engine.load(&parse_program(source).unwrap()).unwrap();
+        engine
+    }
+
+    /// Call `word` on `args` until it is promoted, then check that the
+    /// native result matches the interpreted one
+    fn check_promotion(source: &str, word: &str, args: &[i64]) {
+        let mut engine = engine(source, 3);
+        let mut results = Vec::new();
+        for _ in 0..4 {
+            engine.set_stack(args.to_vec());
+            engine.call(word).unwrap();
+            results.push(engine.stack().to_vec());
+        }
+        assert_eq!(engine.tier(word), Some(Tier::Cranelift), "{}: {:?}", source, engine.diagnostics());
+        assert!(results.windows(2).all(|pair| pair[0] == pair[1]), "{}: {:?}", source, results);
+    }
+
+    #[test]
+    fn test_hot_words_are_promoted() {
+        check_promotion(": sq dup * ;", "sq", &[7]);
+        check_promotion(": diff - ;", "diff", &[10, 3]);
+        check_promotion(": sign dup 0 < if drop -1 else 0 > if 1 else 0 then then ;", "sign", &[-5]);
+        check_promotion(": sq dup * ; : hyp sq swap sq + ;", "hyp", &[3, 4]);
+        check_promotion(": max2 over over < if swap then drop ;", "max2", &[3, 9]);
+        check_promotion(": clamp dup 0 < if drop 0 then ; : pos clamp 1 + ;", "pos", &[-4]);
+    }
+
+    #[test]
+    fn test_hot_attribute_promotes_on_first_call() {
+        let mut engine = engine("\\ @hot\n: sq dup * ;\n\\ @optimize(none)\n: cube dup dup * * ;", 100);
+        engine.set_stack(vec![7]);
+        engine.call("sq").unwrap();
+        engine.call("cube").unwrap();
+
+        assert_eq!(engine.stack(), &[117649]);
+        assert_eq!(engine.tier("sq"), Some(Tier::Cranelift));
+        assert_eq!(engine.tier("cube"), Some(Tier::Interpreted));
+    }
+
+    #[test]
+    fn test_ineligible_words_stay_interpreted() {
+        let mut engine = engine(": sum 0 swap 0 do i + loop ; : two 1 2 ; : half 2 / ; : less < ;", 2);
+        for _ in 0..3 {
+            engine.set_stack(vec![4]);
+            engine.call("sum").unwrap();
+            engine.call("two").unwrap();
+            engine.call("half").unwrap();
+            engine.call("less").unwrap();
+        }
+        assert_eq!(engine.stack(), &[6, 0]);
+        for word in ["sum", "two", "half", "less"] {
+            assert_eq!(engine.tier(word), Some(Tier::Interpreted), "{}", word);
+        }
+        assert_eq!(engine.diagnostics().len(), 4);
+        assert_eq!(engine.call_count("sum"), 3);
+    }
+
+    #[test]
+    fn test_redefinition_drops_compiled_code() {
+        let mut engine = engine(": f 1 + ; : g ( n -- n ) f ;", 1);
+        engine.set_stack(vec![1]);
+        engine.call("g").unwrap();
+        assert_eq!(engine.tier("g"), Some(Tier::Cranelift));
+
+        engine.load(&parse_program(": f 2 + ; : g ( n -- n ) f ;").unwrap()).unwrap();
+        assert_eq!(engine.tier("g"), Some(Tier::Interpreted));
+        engine.set_stack(vec![1]);
+        engine.call("g").unwrap();
+        assert_eq!(engine.stack(), &[3]);
+    }
+
+    #[test]
+    fn test_llvm_promotion_reports_why_not() {
+        let mut engine = TieredEngine::new(TierPolicy { jit_threshold: 1, llvm_threshold: Some(2) });
+        engine.load(&parse_program(": sq dup * ; 3 sq sq sq").unwrap()).unwrap();
+        engine.run().unwrap();
+
+        assert_eq!(engine.stack(), &[6561]);
+        assert_eq!(engine.tier("sq"), Some(Tier::Cranelift));
+        assert!(engine.diagnostics().iter().any(|d| d.starts_with("sq stays on cranelift")));
+    }
+
+    #[test]
+    fn test_call_counts_feed_pgo() {
+        let mut engine = engine(": sq dup * ; : quad sq sq ; 2 quad quad", 1_000);
+        engine.run().unwrap();
+        assert_eq!(engine.call_count("sq"), 4);
+        assert_eq!(engine.call_count("quad"), 2);
+
+        let mut pgo = PGOOptimizer::new();
+        pgo.enable_profiling();
+        engine.profile(&mut pgo);
+        assert!(pgo.identify_hot_patterns(4).iter().any(|p| p.count == 4));
+    }
+
+    #[test]
+    fn test_execute_targets_feed_pgo() {
+        let source = ": sq ( n -- n ) dup * ; : neg ( n -- n ) negate ; : apply ( n xt -- n ) execute ; \
+                      3 ' sq apply ' neg apply ' neg apply";
+        let mut engine = engine(source, 1_000);
+        engine.run().unwrap();
+        assert_eq!(engine.stack(), &[9]);
+
+        let mut pgo = PGOOptimizer::new();
+        pgo.enable_profiling();
+        engine.profile(&mut pgo);
+        assert_eq!(pgo.likely_execute_target(), Some("neg"));
+    }
+}
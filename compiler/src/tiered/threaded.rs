@@ -0,0 +1,912 @@
+//! Threaded Code Interpreter
+//!
+//! Compiles unoptimized [`ForthIR`] to a flat array of [`Op`]s, each either
+//! a literal, a primitive function pointer, a call, a jump or a return.
+//! Labels are resolved to offsets and builtin calls to primitives when the
+//! program is loaded, so running it is a single dispatch loop with no name
+//! lookups. Calls to colon definitions go through a [`CallHook`], which is
+//! where the tiered engine counts calls and switches to native code.
+//!
+//! Semantics match the reference interpreter in the optimizer: arithmetic
+//! wraps, comparisons leave `-1` or `0`, and counted loops keep their limit
+//! and index on the return stack. An execution token numbers its word in
+//! name order, as there. Variables are cells in the VM's own
+//! memory; `.`, `emit`, `type`, pictured numeric output and the other
+//! output words append to an output buffer, while `key`, `key?` and
+//! `accept` read standard input through the C runtime.
+
+use crate::error::{CompileError, Result};
+use crate::runtime_ffi::{self, CellT};
+use fastforth_frontend::case::CaseTable;
+use fastforth_frontend::umul_high;
+use fastforth_optimizer::interpreter::TOKEN_BASE;
+use fastforth_optimizer::{ForthIR, Instruction};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Deepest call nesting a run may reach
+const MAX_CALL_DEPTH: usize = 1024;
+
+/// Bytes per cell, the scale of `cells`
+const CELL_SIZE: i64 = 8;
+
+/// Address of the first variable, so that 0 is never a valid address
+const MEMORY_BASE: i64 = 0x1000;
+
+/// Bytes of pictured numeric output `#>` can hand out (as in the runtime)
+const PICTURED_SIZE: usize = 136;
+
+/// Scratch memory `#>` copies pictured output into
+const PICTURED_REGION: &str = "(pictured)";
+
+/// A primitive operating on the VM
+pub type Primitive = fn(&mut Vm) -> Result<()>;
+
+/// One threaded code cell
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    /// Push a value
+    Lit(i64),
+    /// Run a primitive
+    Prim(Primitive),
+    /// Push the item `n` below the top
+    Pick(usize),
+    /// Move the item `n` below the top to the top
+    Roll(usize),
+    /// Call a colon definition by index
+    Call(usize),
+    /// Pop an execution token and call its colon definition
+    Execute,
+    /// Jump to an offset
+    Jump(usize),
+    /// Pop a flag and jump if it is zero
+    JumpIfZero(usize),
+    /// Pop a flag and jump if it is not zero
+    JumpIfNonZero(usize),
+    /// Replace the top item with its entry in a constant table, by index
+    Lookup(usize),
+    /// Return to the caller, or stop at the end of the entry point
+    Return,
+}
+
+/// Observes a run op by op, for tracing and debugging
+pub trait Tracer {
+    /// Called before the op at `pc` runs, with the return addresses of the
+    /// calls in progress, outermost first; an error stops the run
+    fn before(&mut self, program: &ThreadedProgram, pc: usize, frames: &[usize], vm: &mut Vm) -> Result<()>;
+}
+
+/// Observes nothing, so an untraced run pays nothing for the hook
+pub struct NoTrace;
+
+impl Tracer for NoTrace {
+    #[inline(always)]
+    fn before(&mut self, _program: &ThreadedProgram, _pc: usize, _frames: &[usize], _vm: &mut Vm) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Decides how a call to a colon definition runs
+pub trait CallHook {
+    /// Called before the interpreter enters `word`; returns `true` if the
+    /// hook ran the word itself
+    fn enter(&mut self, word: usize, vm: &mut Vm) -> Result<bool>;
+
+    /// Called when EXECUTE reaches `word`, before it is entered
+    fn execute(&mut self, _word: usize) {}
+}
+
+/// Runs every call in the interpreter
+pub struct Interpret;
+
+impl CallHook for Interpret {
+    fn enter(&mut self, _word: usize, _vm: &mut Vm) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+/// A program compiled to threaded code
+#[derive(Debug, Clone, Default)]
+pub struct ThreadedProgram {
+    code: Vec<Op>,
+    /// Forth text of each op, for tracing
+    text: Vec<String>,
+    names: Vec<String>,
+    entries: Vec<usize>,
+    index: HashMap<String, usize>,
+    main: usize,
+    tables: Vec<CaseTable>,
+}
+
+impl ThreadedProgram {
+    /// Compile a program, allocating its variables in `vm`
+    ///
+    /// Words are numbered in name order. An instruction the interpreter
+    /// cannot run, or a call to a word that is not defined, fails the whole
+    /// program.
+    pub fn compile(ir: &ForthIR, vm: &mut Vm) -> Result<Self> {
+        let mut names: Vec<String> = ir.words.keys().cloned().collect();
+        names.sort();
+        let index: HashMap<String, usize> = names.iter().cloned().enumerate().map(|(i, name)| (name, i)).collect();
+
+        let mut variables: Vec<(&String, &usize)> = ir.variables.iter().collect();
+        variables.sort();
+        for (name, &size) in variables {
+            vm.allocate(name, size);
+        }
+
+        let mut program =
+            Self { code: Vec::new(), text: Vec::new(), names, entries: Vec::new(), index, main: 0, tables: Vec::new() };
+        for name in program.names.clone() {
+            let entry = program.code.len();
+            program.entries.push(entry);
+            program.compile_word(&ir.words[&name].instructions, ir, vm)?;
+        }
+        program.main = program.code.len();
+        program.compile_word(&ir.main, ir, vm)?;
+        Ok(program)
+    }
+
+    fn compile_word(&mut self, instructions: &[Instruction], ir: &ForthIR, vm: &Vm) -> Result<()> {
+        let start = self.code.len();
+        let mut labels = HashMap::new();
+        let mut jumps = Vec::new();
+
+        for inst in instructions {
+            match inst {
+                Instruction::Label(label) => {
+                    labels.insert(label.clone(), self.code.len());
+                }
+                Instruction::Branch(target) | Instruction::BranchIf(target) | Instruction::BranchIfNot(target) => {
+                    jumps.push((self.code.len(), *target));
+                    let op = match inst {
+                        Instruction::Branch(_) => Op::Jump(0),
+                        Instruction::BranchIf(_) => Op::JumpIfNonZero(0),
+                        _ => Op::JumpIfZero(0),
+                    };
+                    self.push(op, inst);
+                }
+                Instruction::Call(name) => {
+                    let op = if let Some(&word) = self.index.get(name.as_str()) {
+                        Op::Call(word)
+                    } else if ir.variables.contains_key(name.as_str()) {
+                        Op::Lit(vm.variables[name.as_str()])
+                    } else {
+                        runtime_word(name).ok_or_else(|| CompileError::SemanticError(format!("Undefined word: {}", name)))?
+                    };
+                    self.push(op, inst);
+                }
+                Instruction::Tick(name) => {
+                    let word = self.index.get(name.as_str()).copied()
+                        .ok_or_else(|| CompileError::SemanticError(format!("Undefined word: {}", name)))?;
+                    self.push(Op::Lit(TOKEN_BASE + word as i64), inst);
+                }
+                Instruction::Execute => self.push(Op::Execute, inst),
+                Instruction::Lookup(table) => {
+                    self.tables.push(table.clone());
+                    self.push(Op::Lookup(self.tables.len() - 1), inst);
+                }
+                Instruction::Fused(body) => {
+                    for inst in body {
+                        self.push(straight_line(inst)?, inst);
+                    }
+                }
+                Instruction::Comment(_) | Instruction::Nop | Instruction::FlushCache => {}
+                other => self.push(straight_line(other)?, other),
+            }
+        }
+        self.push(Op::Return, &Instruction::Return);
+
+        for (at, label) in jumps {
+            let target = *labels
+                .get(&format!("L{}", label))
+                .ok_or_else(|| CompileError::CodeGenError(format!("Branch to missing label L{}", label)))?;
+            debug_assert!(target >= start);
+            match &mut self.code[at] {
+                Op::Jump(offset) | Op::JumpIfZero(offset) | Op::JumpIfNonZero(offset) => *offset = target,
+                _ => unreachable!("jump recorded at a non-jump op"),
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, op: Op, inst: &Instruction) {
+        self.code.push(op);
+        self.text.push(forth_text(inst));
+    }
+
+    /// Names of the compiled words, in index order
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Index of a word
+    pub fn word(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    /// Offset of a word's first op
+    pub fn entry(&self, word: usize) -> usize {
+        self.entries[word]
+    }
+
+    /// Offset of the main sequence's first op
+    pub fn main(&self) -> usize {
+        self.main
+    }
+
+    /// Word the op at `pc` belongs to, or `None` for the main sequence
+    pub fn word_at(&self, pc: usize) -> Option<usize> {
+        if pc >= self.main {
+            return None;
+        }
+        Some(self.entries.partition_point(|&entry| entry <= pc) - 1)
+    }
+
+    /// Forth text of the op at `pc`
+    pub fn text(&self, pc: usize) -> &str {
+        &self.text[pc]
+    }
+
+    /// Whether the op at `pc` only moves control within a word (an
+    /// unconditional jump or a return), rather than doing the word's work
+    pub fn is_control(&self, pc: usize) -> bool {
+        matches!(self.code[pc], Op::Jump(_) | Op::Return)
+    }
+
+    /// Number of ops in the program
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    /// Whether the program has no code at all
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+}
+
+/// How an instruction reads in Forth, or in the IR where it has no word
+fn forth_text(inst: &Instruction) -> String {
+    match inst {
+        Instruction::Call(name) => name.to_string(),
+        Instruction::Tick(name) => format!("['] {}", name),
+        Instruction::Branch(_) => "branch".to_string(),
+        Instruction::BranchIf(_) | Instruction::BranchIfNot(_) => "?branch".to_string(),
+        Instruction::Return => "exit".to_string(),
+        Instruction::ToR => ">r".to_string(),
+        Instruction::FromR => "r>".to_string(),
+        Instruction::RFetch => "r@".to_string(),
+        Instruction::Lookup(_) => "case".to_string(),
+        other => other.to_word().unwrap_or_else(|| format!("{:?}", other)),
+    }
+}
+
+/// The op for an instruction that neither jumps nor calls
+fn straight_line(inst: &Instruction) -> Result<Op> {
+    use Instruction::*;
+
+    let prim: Primitive = match inst {
+        Literal(value) => return Ok(Op::Lit(*value)),
+        Pick(n) => return Ok(Op::Pick(*n as usize)),
+        Roll(n) => return Ok(Op::Roll(*n as usize)),
+        Return => return Ok(Op::Return),
+
+        Dup | CachedDup { .. } => |vm| vm.peek(0).map(|a| vm.push(a)),
+        Drop => |vm| vm.pop().map(|_| ()),
+        Swap | CachedSwap { .. } => |vm| {
+            let (a, b) = vm.pop2()?;
+            vm.stack.extend([b, a]);
+            Ok(())
+        },
+        Over | CachedOver { .. } => |vm| vm.peek(1).map(|a| vm.push(a)),
+        Rot => |vm| {
+            let c = vm.pop()?;
+            let (a, b) = vm.pop2()?;
+            vm.stack.extend([b, c, a]);
+            Ok(())
+        },
+        Nip => |vm| vm.binary(|_, b| Ok(b)),
+        Tuck => |vm| {
+            let (a, b) = vm.pop2()?;
+            vm.stack.extend([b, a, b]);
+            Ok(())
+        },
+
+        Add => |vm| vm.binary(|a, b| Ok(a.wrapping_add(b))),
+        Sub => |vm| vm.binary(|a, b| Ok(a.wrapping_sub(b))),
+        Mul => |vm| vm.binary(|a, b| Ok(a.wrapping_mul(b))),
+        Div => |vm| vm.binary(|a, b| nonzero(b).map(|b| a.wrapping_div(b))),
+        Mod => |vm| vm.binary(|a, b| nonzero(b).map(|b| a.wrapping_rem(b))),
+        Neg => |vm| vm.unary(i64::wrapping_neg),
+        Abs => |vm| vm.unary(i64::wrapping_abs),
+        And => |vm| vm.binary(|a, b| Ok(a & b)),
+        Or => |vm| vm.binary(|a, b| Ok(a | b)),
+        Xor => |vm| vm.binary(|a, b| Ok(a ^ b)),
+        Not => |vm| vm.unary(|a| !a),
+        Shl => |vm| vm.binary(|a, n| Ok(a.wrapping_shl(n as u32))),
+        Shr => |vm| vm.binary(|a, n| Ok((a as u64).wrapping_shr(n as u32) as i64)),
+
+        Eq => |vm| vm.binary(|a, b| Ok(flag(a == b))),
+        Ne => |vm| vm.binary(|a, b| Ok(flag(a != b))),
+        Lt => |vm| vm.binary(|a, b| Ok(flag(a < b))),
+        Le => |vm| vm.binary(|a, b| Ok(flag(a <= b))),
+        Gt => |vm| vm.binary(|a, b| Ok(flag(a > b))),
+        Ge => |vm| vm.binary(|a, b| Ok(flag(a >= b))),
+        ZeroEq => |vm| vm.unary(|a| flag(a == 0)),
+        ZeroLt => |vm| vm.unary(|a| flag(a < 0)),
+        ZeroGt => |vm| vm.unary(|a| flag(a > 0)),
+
+        ULt => |vm| vm.binary(|a, b| Ok(flag((a as u64) < (b as u64)))),
+        UGt => |vm| vm.binary(|a, b| Ok(flag((a as u64) > (b as u64)))),
+        UDiv => |vm| vm.binary(|a, b| nonzero(b).map(|b| ((a as u64) / (b as u64)) as i64)),
+        UMod => |vm| vm.binary(|a, b| nonzero(b).map(|b| ((a as u64) % (b as u64)) as i64)),
+        UMul => |vm| vm.binary(|a, b| Ok(a.wrapping_mul(b))),
+        UMulHigh => |vm| vm.binary(|a, b| Ok(umul_high(a, b))),
+
+        Popcount => |vm| vm.unary(|a| a.count_ones() as i64),
+        Ctz => |vm| vm.unary(|a| a.trailing_zeros() as i64),
+        Clz => |vm| vm.unary(|a| a.leading_zeros() as i64),
+        Rotl => |vm| vm.binary(|a, n| Ok(a.rotate_left(n as u32))),
+        Rotr => |vm| vm.binary(|a, n| Ok(a.rotate_right(n as u32))),
+
+        DupAdd => |vm| vm.unary(|a| a.wrapping_add(a)),
+        DupMul => |vm| vm.unary(|a| a.wrapping_mul(a)),
+        OverAdd => |vm| {
+            let b = vm.pop()?;
+            let a = vm.peek(0)?;
+            vm.push(a.wrapping_add(b));
+            Ok(())
+        },
+        SwapSub => |vm| vm.binary(|a, b| Ok(b.wrapping_sub(a))),
+        IncOne => |vm| vm.unary(|a| a.wrapping_add(1)),
+        DecOne => |vm| vm.unary(|a| a.wrapping_sub(1)),
+        MulTwo => |vm| vm.unary(|a| a.wrapping_shl(1)),
+        DivTwo => |vm| vm.unary(|a| a >> 1),
+
+        Load => |vm| {
+            let addr = vm.pop()?;
+            let value = vm.cell(addr)?;
+            vm.push(value);
+            Ok(())
+        },
+        Store => |vm| {
+            let (value, addr) = vm.pop2()?;
+            vm.bytes_mut(addr, CELL_SIZE as usize)?.copy_from_slice(&value.to_le_bytes());
+            Ok(())
+        },
+        Load8 => |vm| {
+            let addr = vm.pop()?;
+            let byte = vm.bytes(addr, 1)?[0];
+            vm.push(byte as i64);
+            Ok(())
+        },
+        Store8 => |vm| {
+            let (value, addr) = vm.pop2()?;
+            vm.bytes_mut(addr, 1)?[0] = value as u8;
+            Ok(())
+        },
+        Load16 => |vm| {
+            let addr = vm.pop()?;
+            let bytes = vm.bytes(addr, 2)?;
+            vm.push(u16::from_le_bytes([bytes[0], bytes[1]]) as i64);
+            Ok(())
+        },
+        Store16 => |vm| {
+            let (value, addr) = vm.pop2()?;
+            vm.bytes_mut(addr, 2)?.copy_from_slice(&(value as u16).to_le_bytes());
+            Ok(())
+        },
+        Load32 => |vm| {
+            let addr = vm.pop()?;
+            let bytes = vm.bytes(addr, 4)?;
+            vm.push(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64);
+            Ok(())
+        },
+        Store32 => |vm| {
+            let (value, addr) = vm.pop2()?;
+            vm.bytes_mut(addr, 4)?.copy_from_slice(&(value as u32).to_le_bytes());
+            Ok(())
+        },
+
+        ToR => to_r,
+        FromR => from_r,
+        RFetch => r_fetch,
+
+        other => {
+            return Err(CompileError::CodeGenError(format!("{:?} is not supported by the threaded interpreter", other)));
+        }
+    };
+    Ok(Op::Prim(prim))
+}
+
+/// The primitive for a word lowering leaves as a call
+fn runtime_word(name: &str) -> Option<Op> {
+    let prim: Primitive = match name.to_ascii_lowercase().as_str() {
+        "(do)" => |vm| {
+            let (limit, start) = vm.pop2()?;
+            vm.return_stack.extend([limit, start]);
+            Ok(())
+        },
+        "(loop)" => |vm| vm.advance_loop(1),
+        "(+loop)" => |vm| {
+            let step = vm.pop()?;
+            vm.advance_loop(step)
+        },
+        "i" => |vm| vm.loop_index(0).map(|index| vm.push(index)),
+        "j" => |vm| vm.loop_index(1).map(|index| vm.push(index)),
+        ">r" => to_r,
+        "r>" => from_r,
+        "r@" => r_fetch,
+        "cells" => |vm| vm.unary(|a| a.wrapping_mul(CELL_SIZE)),
+        "." => |vm| {
+            let value = vm.pop()?;
+            let _ = write!(vm.output, "{} ", value);
+            Ok(())
+        },
+        "emit" => |vm| {
+            let code = vm.pop()?;
+            vm.output.push(char::from_u32(code as u32).unwrap_or(char::REPLACEMENT_CHARACTER));
+            Ok(())
+        },
+        "cr" => |vm| {
+            vm.output.push('\n');
+            Ok(())
+        },
+        "space" => |vm| {
+            vm.output.push(' ');
+            Ok(())
+        },
+        "u." => |vm| {
+            let value = vm.pop()?;
+            let _ = write!(vm.output, "{} ", value as u64);
+            Ok(())
+        },
+        ".r" => |vm| {
+            let (value, width) = vm.pop2()?;
+            let _ = write!(vm.output, "{:>1$}", value, width.max(0) as usize);
+            Ok(())
+        },
+        "type" => |vm| {
+            let (addr, len) = vm.pop2()?;
+            let text = String::from_utf8_lossy(vm.bytes(addr, len.max(0) as usize)?).into_owned();
+            vm.output.push_str(&text);
+            Ok(())
+        },
+        "<#" => |vm| {
+            vm.pictured.clear();
+            Ok(())
+        },
+        "#" => |vm| {
+            let ud = vm.pop_double()?;
+            vm.hold_digit(ud);
+            vm.push_double(ud / 10);
+            Ok(())
+        },
+        "#s" => |vm| {
+            let mut ud = vm.pop_double()?;
+            loop {
+                vm.hold_digit(ud);
+                ud /= 10;
+                if ud == 0 {
+                    break;
+                }
+            }
+            vm.push_double(0);
+            Ok(())
+        },
+        "hold" => |vm| {
+            let c = vm.pop()?;
+            vm.hold(c as u8);
+            Ok(())
+        },
+        "sign" => |vm| {
+            if vm.pop()? < 0 {
+                vm.hold(b'-');
+            }
+            Ok(())
+        },
+        "#>" => |vm| {
+            vm.pop_double()?;
+            let addr = vm.reserve(PICTURED_REGION, PICTURED_SIZE);
+            let len = vm.pictured.len();
+            let text: Vec<u8> = vm.pictured.iter().rev().copied().collect();
+            vm.bytes_mut(addr, len)?.copy_from_slice(&text);
+            vm.push(addr);
+            vm.push(len as i64);
+            Ok(())
+        },
+        "key" => |vm| {
+            vm.push(unsafe { runtime_ffi::forth_io_key() } as i64);
+            Ok(())
+        },
+        "key?" => |vm| {
+            vm.push(unsafe { runtime_ffi::forth_io_key_ready() } as i64);
+            Ok(())
+        },
+        "accept" => |vm| {
+            let (addr, max) = vm.pop2()?;
+            let mut line = vec![0u8; max.max(0) as usize];
+            let len = unsafe { runtime_ffi::forth_io_accept(line.as_mut_ptr() as CellT, line.len() as CellT) } as usize;
+            vm.bytes_mut(addr, len)?.copy_from_slice(&line[..len]);
+            vm.push(len as i64);
+            Ok(())
+        },
+        _ => return None,
+    };
+    Some(Op::Prim(prim))
+}
+
+fn to_r(vm: &mut Vm) -> Result<()> {
+    let a = vm.pop()?;
+    vm.return_stack.push(a);
+    Ok(())
+}
+
+fn from_r(vm: &mut Vm) -> Result<()> {
+    let a = vm.return_stack.pop().ok_or_else(return_underflow)?;
+    vm.push(a);
+    Ok(())
+}
+
+fn r_fetch(vm: &mut Vm) -> Result<()> {
+    let a = *vm.return_stack.last().ok_or_else(return_underflow)?;
+    vm.push(a);
+    Ok(())
+}
+
+/// Data, return stack and memory of the threaded interpreter
+#[derive(Debug, Clone, Default)]
+pub struct Vm {
+    stack: Vec<i64>,
+    return_stack: Vec<i64>,
+    memory: Vec<u8>,
+    variables: HashMap<String, i64>,
+    output: String,
+    /// Pictured numeric output, last character first
+    pictured: Vec<u8>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run the code at `entry` until its final return
+    pub fn run(&mut self, program: &ThreadedProgram, entry: usize, hook: &mut dyn CallHook) -> Result<()> {
+        self.run_traced(program, entry, hook, &mut NoTrace)
+    }
+
+    /// [`run`](Self::run) that shows `tracer` every op before it runs
+    pub fn run_traced<T: Tracer>(
+        &mut self,
+        program: &ThreadedProgram,
+        entry: usize,
+        hook: &mut dyn CallHook,
+        tracer: &mut T,
+    ) -> Result<()> {
+        let mut frames: Vec<usize> = Vec::new();
+        let mut pc = entry;
+
+        loop {
+            tracer.before(program, pc, &frames, self)?;
+            match program.code[pc] {
+                Op::Lit(value) => self.push(value),
+                Op::Prim(prim) => prim(self)?,
+                Op::Pick(n) => {
+                    let a = self.peek(n)?;
+                    self.push(a);
+                }
+                Op::Roll(n) => {
+                    let index = self.stack.len().checked_sub(n + 1).ok_or_else(underflow)?;
+                    let a = self.stack.remove(index);
+                    self.push(a);
+                }
+                Op::Call(_) | Op::Execute => {
+                    let word = match program.code[pc] {
+                        Op::Call(word) => word,
+                        _ => self.pop()?
+                            .checked_sub(TOKEN_BASE)
+                            .and_then(|word| usize::try_from(word).ok())
+                            .filter(|&word| word < program.entries.len())
+                            .ok_or_else(|| CompileError::RuntimeError("EXECUTE of an invalid execution token".to_string()))
+                            .inspect(|&word| hook.execute(word))?,
+                    };
+                    if !hook.enter(word, self)? {
+                        if frames.len() >= MAX_CALL_DEPTH {
+                            return Err(CompileError::RuntimeError("Call depth exceeded".to_string()));
+                        }
+                        frames.push(pc + 1);
+                        pc = program.entries[word];
+                        continue;
+                    }
+                }
+                Op::Jump(target) => {
+                    pc = target;
+                    continue;
+                }
+                Op::JumpIfZero(target) => {
+                    if self.pop()? == 0 {
+                        pc = target;
+                        continue;
+                    }
+                }
+                Op::JumpIfNonZero(target) => {
+                    if self.pop()? != 0 {
+                        pc = target;
+                        continue;
+                    }
+                }
+                Op::Lookup(table) => {
+                    let key = self.pop()?;
+                    self.push(program.tables[table].get(key));
+                }
+                Op::Return => match frames.pop() {
+                    Some(caller) => {
+                        pc = caller;
+                        continue;
+                    }
+                    None => return Ok(()),
+                },
+            }
+            pc += 1;
+        }
+    }
+
+    /// Data stack, bottom first
+    pub fn stack(&self) -> &[i64] {
+        &self.stack
+    }
+
+    /// Replace the data stack
+    pub fn set_stack(&mut self, stack: Vec<i64>) {
+        self.stack = stack;
+    }
+
+    /// Return stack, bottom first
+    pub fn return_stack(&self) -> &[i64] {
+        &self.return_stack
+    }
+
+    /// Address of a variable, if the program has one of that name
+    pub fn variable(&self, name: &str) -> Option<i64> {
+        self.variables.get(name).copied()
+    }
+
+    /// Cell at `addr`
+    pub fn cell(&self, addr: i64) -> Result<i64> {
+        Ok(i64::from_le_bytes(self.bytes(addr, CELL_SIZE as usize)?.try_into().unwrap()))
+    }
+
+    /// Empty the return stack, as after an aborted run
+    pub fn clear_return_stack(&mut self) {
+        self.return_stack.clear();
+    }
+
+    /// Take the output written so far
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Address of a variable of `size` bytes, zeroed when it is first
+    /// allocated and cell-aligned
+    pub fn allocate(&mut self, name: &str, size: usize) -> i64 {
+        if !self.variables.contains_key(name) {
+            self.memory.resize(self.memory.len().next_multiple_of(CELL_SIZE as usize), 0);
+        }
+        self.reserve(name, size)
+    }
+
+    /// Address of a named region of `len` bytes, allocated the first time
+    fn reserve(&mut self, name: &str, len: usize) -> i64 {
+        if let Some(&addr) = self.variables.get(name) {
+            return addr;
+        }
+        let addr = MEMORY_BASE + self.memory.len() as i64;
+        self.memory.resize(self.memory.len() + len, 0);
+        self.variables.insert(name.to_string(), addr);
+        addr
+    }
+
+    pub fn push(&mut self, value: i64) {
+        self.stack.push(value);
+    }
+
+    pub fn pop(&mut self) -> Result<i64> {
+        self.stack.pop().ok_or_else(underflow)
+    }
+
+    /// Pop the top `n` items, bottom first
+    pub fn pop_n(&mut self, n: usize) -> Result<Vec<i64>> {
+        let start = self.stack.len().checked_sub(n).ok_or_else(underflow)?;
+        Ok(self.stack.split_off(start))
+    }
+
+    /// Pop the top two items as `(second, top)`
+    fn pop2(&mut self) -> Result<(i64, i64)> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        Ok((a, b))
+    }
+
+    /// Pop an unsigned double number (high cell on top)
+    fn pop_double(&mut self) -> Result<u128> {
+        let (lo, hi) = self.pop2()?;
+        Ok(((hi as u64 as u128) << 64) | lo as u64 as u128)
+    }
+
+    fn push_double(&mut self, ud: u128) {
+        self.push(ud as u64 as i64);
+        self.push((ud >> 64) as u64 as i64);
+    }
+
+    fn hold(&mut self, c: u8) {
+        if self.pictured.len() < PICTURED_SIZE {
+            self.pictured.push(c);
+        }
+    }
+
+    fn hold_digit(&mut self, ud: u128) {
+        self.hold(b'0' + (ud % 10) as u8);
+    }
+
+    fn peek(&self, depth: usize) -> Result<i64> {
+        let index = self.stack.len().checked_sub(depth + 1).ok_or_else(underflow)?;
+        Ok(self.stack[index])
+    }
+
+    fn unary(&mut self, f: impl FnOnce(i64) -> i64) -> Result<()> {
+        let a = self.pop()?;
+        self.push(f(a));
+        Ok(())
+    }
+
+    fn binary(&mut self, f: impl FnOnce(i64, i64) -> Result<i64>) -> Result<()> {
+        let (a, b) = self.pop2()?;
+        self.push(f(a, b)?);
+        Ok(())
+    }
+
+    /// Step the innermost loop index, leaving a flag that is true once the
+    /// loop is done (and its parameters dropped)
+    fn advance_loop(&mut self, step: i64) -> Result<()> {
+        let len = self.return_stack.len();
+        if len < 2 {
+            return Err(return_underflow());
+        }
+        let limit = self.return_stack[len - 2];
+        let index = self.return_stack[len - 1];
+        let next = index.wrapping_add(step);
+
+        // Done when the index crosses the boundary between limit-1 and limit
+        let done = (index.wrapping_sub(limit) ^ next.wrapping_sub(limit)) < 0;
+        if done {
+            self.return_stack.truncate(len - 2);
+        } else {
+            self.return_stack[len - 1] = next;
+        }
+        self.push(flag(done));
+        Ok(())
+    }
+
+    /// Index of the loop `outer` levels out from the innermost
+    fn loop_index(&self, outer: usize) -> Result<i64> {
+        let position = self.return_stack.len().checked_sub(1 + 2 * outer).ok_or_else(return_underflow)?;
+        Ok(self.return_stack[position])
+    }
+
+    fn range(&self, addr: i64, len: usize) -> Result<std::ops::Range<usize>> {
+        let start = addr.checked_sub(MEMORY_BASE).filter(|&offset| offset >= 0).map(|offset| offset as usize);
+        match start {
+            Some(start) if start + len <= self.memory.len() => Ok(start..start + len),
+            _ => Err(CompileError::RuntimeError(format!("Invalid memory address: {}", addr))),
+        }
+    }
+
+    fn bytes(&self, addr: i64, len: usize) -> Result<&[u8]> {
+        let range = self.range(addr, len)?;
+        Ok(&self.memory[range])
+    }
+
+    fn bytes_mut(&mut self, addr: i64, len: usize) -> Result<&mut [u8]> {
+        let range = self.range(addr, len)?;
+        Ok(&mut self.memory[range])
+    }
+}
+
+fn flag(condition: bool) -> i64 {
+    if condition { -1 } else { 0 }
+}
+
+fn nonzero(divisor: i64) -> Result<i64> {
+    if divisor == 0 { Err(CompileError::RuntimeError("Division by zero".to_string())) } else { Ok(divisor) }
+}
+
+fn underflow() -> CompileError {
+    CompileError::RuntimeError("Stack underflow".to_string())
+}
+
+fn return_underflow() -> CompileError {
+    CompileError::RuntimeError("Return stack underflow".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::CompilationPipeline;
+    use fastforth_optimizer::{IrInterpreter, OptimizationLevel};
+
+    fn run(source: &str) -> Result<(Vec<i64>, String)> {
+        let ir = CompilationPipeline::new(OptimizationLevel::None).lower_source(source)?;
+        let mut vm = Vm::new();
+        let program = ThreadedProgram::compile(&ir, &mut vm)?;
+        vm.run(&program, program.main(), &mut Interpret)?;
+        let output = vm.take_output();
+        Ok((vm.stack().to_vec(), output))
+    }
+
+    #[test]
+    fn test_matches_reference_interpreter() {
+        let sources = [
+            ": sq dup * ; 3 sq 4 sq +",
+            ": sign dup 0 < if drop -1 else 0 > if 1 else 0 then then ; -5 sign 0 sign 9 sign",
+            ": sum 0 swap 0 do i + loop ; 10 sum",
+            ": tri 0 swap begin dup while tuck + swap 1 - repeat drop ; 6 tri",
+            ": grid 0 3 0 do 2 0 do i j * + loop loop ; grid",
+            ": fact dup 1 > if dup 1 - fact * then ; 10 fact",
+            "1 2 3 rot >r swap r> 10 0 do i + 3 +loop",
+            "255 popcount 8 ctz 0 ctz 1 clz 0 clz 1 63 rol -2 1 ror 7 -1 u/mod",
+            "variable x 287454020 x l! 43707 x 1 + w! x @ x 3 + c@ -1 x 4 + l! x 4 + l@ x 6 + w@",
+            CASES,
+        ];
+        for source in sources {
+            let ir = CompilationPipeline::new(OptimizationLevel::None).lower_source(source).unwrap();
+            let expected = IrInterpreter::new(&ir).run().unwrap();
+            assert_eq!(run(source).unwrap().0, expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_variables_and_output() {
+        let (stack, output) = run("variable x 42 x ! x @ dup . 65 emit cr x @ 1 + x ! x @").unwrap();
+        assert_eq!(stack, vec![42, 43]);
+        assert_eq!(output, "42 A\n");
+    }
+
+    const CASES: &str = "table: squares 0 1 4 9 16 ;\n\
+                         : op ( a n -- n ) case 0 of 1 + endof 1 of 2 * endof 2 of dup * endof endcase ;\n\
+                         3 squares 9 squares -1 squares 5 0 op 5 1 op 5 2 op 5 7 op";
+
+    #[test]
+    fn test_cases() {
+        let ir = CompilationPipeline::new(OptimizationLevel::None).lower_source(CASES).unwrap();
+        assert!(matches!(ir.words["squares"].instructions[0], Instruction::Lookup(_)));
+        assert_eq!(run(CASES).unwrap().0, vec![9, 0, 0, 6, 10, 25, 5]);
+    }
+
+    #[test]
+    fn test_records() {
+        let source = "struct point cell field .x cell field .y end-struct\n\
+                      create a point allot create b point allot\n\
+                      : move ( point -- ) dup .x @ 1 + over .x ! .y dup @ 2 * swap ! ;\n\
+                      3 a .x ! 5 a .y ! 7 b .x ! a move a .x @ a .y @ b .x @ b a -";
+        let (stack, _) = run(source).unwrap();
+        assert_eq!(stack, vec![4, 10, 7, 16]);
+    }
+
+    #[test]
+    fn test_number_formatting() {
+        let source = ": show ( n -- ) dup abs 0 <# #s rot sign #> type ; -42 show 0 show 7 3 .r -1 u.";
+        assert_eq!(run(source).unwrap(), (vec![], "-420  718446744073709551615 ".to_string()));
+        let (stack, _) = run("123 0 <# # # 46 hold # #> swap drop").unwrap();
+        assert_eq!(stack, vec![4]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(run("nope").is_err(), "undefined words fail at load time");
+        assert!(run("1 0 /").is_err());
+        assert!(run("drop").is_err());
+        assert!(run(": loop-forever loop-forever ; loop-forever").is_err(), "call depth is bounded");
+        assert!(run("123 @").is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Compilation Trace
+//!
+//! Timed spans for each stage of the pipeline, written as a Chrome trace
+//! event file (`fifthc --trace-json`) that loads in `chrome://tracing` and
+//! Perfetto. Stages are spans in the `phase` category: parse, frontend,
+//! optimize, codegen, link and execute. Inside them are the frontend steps,
+//! each optimizer pass (once per word for word-local passes), and the code
+//! generation of each word, on the thread that did the work.
+//!
+//! Tracing is off unless a trace is given to the pipeline with
+//! [`CompilationPipeline::set_trace`](crate::CompilationPipeline::set_trace).
+
+use crate::error::{CompileError, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// One complete event (`"ph": "X"`) in the Chrome trace format
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceEvent {
+    pub name: String,
+    #[serde(rename = "cat")]
+    pub category: &'static str,
+    pub ph: &'static str,
+    /// Start, in microseconds since the trace began
+    pub ts: f64,
+    /// Duration in microseconds
+    pub dur: f64,
+    pub pid: u32,
+    /// 0 for the thread that drives the pipeline, 1 + the rayon worker
+    /// index for work done on the thread pool
+    pub tid: usize,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub args: Map<String, Value>,
+}
+
+/// Spans recorded during one or more compilations
+#[derive(Debug)]
+pub struct CompilationTrace {
+    origin: Instant,
+    events: Mutex<Vec<TraceEvent>>,
+}
+
+impl CompilationTrace {
+    pub fn new() -> Self {
+        Self { origin: Instant::now(), events: Mutex::new(Vec::new()) }
+    }
+
+    /// Start a span on the calling thread; it is recorded when dropped
+    pub fn span(self: &Arc<Self>, name: impl Into<String>, category: &'static str) -> Span {
+        Span { trace: Arc::clone(self), name: name.into(), category, start: Instant::now(), args: Map::new() }
+    }
+
+    /// Record work that was timed elsewhere
+    pub fn record(
+        &self,
+        name: impl Into<String>,
+        category: &'static str,
+        start: Instant,
+        duration: Duration,
+        thread: usize,
+        args: Map<String, Value>,
+    ) {
+        let event = TraceEvent {
+            name: name.into(),
+            category,
+            ph: "X",
+            ts: start.saturating_duration_since(self.origin).as_secs_f64() * 1e6,
+            dur: duration.as_secs_f64() * 1e6,
+            pid: std::process::id(),
+            tid: thread,
+            args,
+        };
+        self.events.lock().unwrap_or_else(|e| e.into_inner()).push(event);
+    }
+
+    /// Events recorded so far, in order of completion
+    pub fn events(&self) -> Vec<TraceEvent> {
+        self.events.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Total time in each event name of a category, in order of first
+    /// appearance
+    pub fn totals(&self, category: &str) -> Vec<(String, Duration)> {
+        let mut totals: Vec<(String, Duration)> = Vec::new();
+        for event in self.events().iter().filter(|event| event.category == category) {
+            let duration = Duration::from_secs_f64(event.dur / 1e6);
+            match totals.iter_mut().find(|(name, _)| *name == event.name) {
+                Some((_, total)) => *total += duration,
+                None => totals.push((event.name.clone(), duration)),
+            }
+        }
+        totals
+    }
+
+    /// The trace as a Chrome trace event file
+    pub fn to_json(&self) -> Value {
+        serde_json::json!({
+            "traceEvents": self.events(),
+            "displayTimeUnit": "ms",
+        })
+    }
+
+    /// Write the trace as a Chrome trace event file
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string(&self.to_json())
+            .map_err(|e| CompileError::InternalError(format!("Failed to serialize trace: {}", e)))?;
+        std::fs::write(path, json).map_err(|e| CompileError::IoError(path.to_path_buf(), e))
+    }
+}
+
+impl Default for CompilationTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A span in progress
+pub struct Span {
+    trace: Arc<CompilationTrace>,
+    name: String,
+    category: &'static str,
+    start: Instant,
+    args: Map<String, Value>,
+}
+
+impl Span {
+    /// Attach an argument, shown with the event
+    pub fn arg(&mut self, key: &str, value: impl Into<Value>) {
+        self.args.insert(key.to_string(), value.into());
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let args = std::mem::take(&mut self.args);
+        self.trace.record(std::mem::take(&mut self.name), self.category, self.start, self.start.elapsed(), 0, args);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spans_are_recorded_as_complete_events() {
+        let trace = Arc::new(CompilationTrace::new());
+        {
+            let _outer = trace.span("frontend", "phase");
+            let mut inner = trace.span("semantic", "frontend");
+            inner.arg("definitions", 3);
+        }
+        trace.record("dead_code", "pass", Instant::now(), Duration::from_micros(5), 2, Map::new());
+
+        let events = trace.events();
+        let names: Vec<_> = events.iter().map(|event| event.name.as_str()).collect();
+        assert_eq!(names, ["semantic", "frontend", "dead_code"]);
+        assert!(events[1].ts <= events[0].ts && events[1].dur >= events[0].dur);
+        assert_eq!(events[0].args["definitions"], 3);
+        assert_eq!(events[2].tid, 2);
+
+        let json = trace.to_json();
+        assert_eq!(json["traceEvents"][0]["ph"], "X");
+        assert_eq!(json["traceEvents"][0]["cat"], "frontend");
+        assert_eq!(trace.totals("phase")[0].0, "frontend");
+    }
+}
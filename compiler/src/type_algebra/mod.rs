@@ -78,7 +78,7 @@ impl AlgebraicType {
         match stack_type {
             StackType::Int => AlgebraicType::Concrete(ConcreteType::Int),
             StackType::Float => AlgebraicType::Concrete(ConcreteType::Float),
-            StackType::Addr => AlgebraicType::Concrete(ConcreteType::Addr),
+            StackType::Addr | StackType::Record(_) => AlgebraicType::Concrete(ConcreteType::Addr),
             StackType::Bool => AlgebraicType::Concrete(ConcreteType::Bool),
             StackType::Char => AlgebraicType::Concrete(ConcreteType::Char),
             StackType::String => AlgebraicType::Concrete(ConcreteType::String),
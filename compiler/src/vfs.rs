@@ -0,0 +1,302 @@
+//! Virtual Filesystems
+//!
+//! The file words of compiled code (OPEN-FILE, CREATE-FILE, READ-FILE,
+//! WRITE-FILE, CLOSE-FILE and DELETE-FILE) go through the runtime's
+//! filesystem, which is libc over the host's files until a [`Vfs`] is
+//! [installed](install). An embedder can then keep a program's files in
+//! memory ([`MemoryVfs`]) or anywhere else, and tests can run file words
+//! without touching the disk.
+//!
+//! The filesystem is per process, like [`Permissions`](crate::Permissions):
+//! it serves every JIT run from [`install`] until [`uninstall`]. The
+//! permissions only govern host paths, so an installed filesystem decides
+//! its own access; refusing with [`io::ErrorKind::PermissionDenied`] gives
+//! [`PERMISSION_DENIED`] as the ior. The block file stays on the host.
+
+use crate::permissions::PERMISSION_DENIED;
+use crate::runtime_ffi::{forth_vfs_install, CellT, ForthVfs};
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr};
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The ior of any other failed file operation
+pub const FILE_ERROR: i64 = -1;
+
+/// A filesystem for the file words
+///
+/// Paths are as the program wrote them and modes those of C `fopen`:
+/// `"r"`, `"w"`, `"r+"` or `"w+"`. Handles are the file ids the program
+/// sees, and must not be 0.
+pub trait Vfs: Send {
+    /// Open `path`, returning a handle
+    fn open(&mut self, path: &str, mode: &str) -> io::Result<i64>;
+
+    /// Read into `buffer` from the handle's position, returning how many
+    /// bytes were read, 0 at the end of the file
+    fn read(&mut self, handle: i64, buffer: &mut [u8]) -> io::Result<usize>;
+
+    /// Write all of `data` at the handle's position
+    fn write(&mut self, handle: i64, data: &[u8]) -> io::Result<()>;
+
+    fn close(&mut self, handle: i64) -> io::Result<()>;
+
+    /// Delete the file at `path`
+    fn remove(&mut self, path: &str) -> io::Result<()>;
+}
+
+/// The installed filesystem, which the runtime calls back into
+static INSTALLED: Mutex<Option<Box<dyn Vfs>>> = Mutex::new(None);
+
+fn installed() -> MutexGuard<'static, Option<Box<dyn Vfs>>> {
+    INSTALLED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Serve the file words of the code this process runs from `vfs`
+pub fn install(vfs: impl Vfs + 'static) {
+    *installed() = Some(Box::new(vfs));
+    let table = ForthVfs {
+        context: std::ptr::null_mut(),
+        open: vfs_open,
+        read: vfs_read,
+        write: vfs_write,
+        close: vfs_close,
+        remove: vfs_remove,
+    };
+    // The runtime copies the table; the callbacks find the filesystem above
+    unsafe { forth_vfs_install(&table) };
+}
+
+/// Go back to the host's files through libc
+pub fn uninstall() {
+    unsafe { forth_vfs_install(std::ptr::null()) };
+    *installed() = None;
+}
+
+fn ior(error: io::Error) -> CellT {
+    match error.kind() {
+        io::ErrorKind::PermissionDenied => PERMISSION_DENIED as CellT,
+        _ => FILE_ERROR as CellT,
+    }
+}
+
+/// Run `f` on the installed filesystem, failing if there is none
+fn with_installed(f: impl FnOnce(&mut dyn Vfs) -> io::Result<CellT>) -> CellT {
+    match installed().as_deref_mut() {
+        Some(vfs) => f(vfs).unwrap_or_else(ior),
+        None => FILE_ERROR as CellT,
+    }
+}
+
+/// # Safety
+/// The runtime passes C strings it built from the program's counted strings
+unsafe fn c_str<'a>(s: *const c_char) -> io::Result<&'a str> {
+    CStr::from_ptr(s).to_str().map_err(|_| io::ErrorKind::InvalidInput.into())
+}
+
+extern "C" fn vfs_open(_: *mut c_void, path: *const c_char, mode: *const c_char, handle: *mut CellT) -> CellT {
+    with_installed(|vfs| {
+        let (path, mode) = unsafe { (c_str(path)?, c_str(mode)?) };
+        let opened = vfs.open(path, mode)?;
+        unsafe { *handle = opened as CellT };
+        Ok(0)
+    })
+}
+
+extern "C" fn vfs_read(_: *mut c_void, handle: CellT, buffer: *mut c_char, count: CellT) -> CellT {
+    with_installed(|vfs| {
+        // The program's buffer, whose length the runtime checked is positive
+        let buffer = unsafe { std::slice::from_raw_parts_mut(buffer as *mut u8, count as usize) };
+        Ok(vfs.read(handle as i64, buffer)? as CellT)
+    })
+}
+
+extern "C" fn vfs_write(_: *mut c_void, handle: CellT, buffer: *const c_char, count: CellT) -> CellT {
+    with_installed(|vfs| {
+        let data = unsafe { std::slice::from_raw_parts(buffer as *const u8, count as usize) };
+        vfs.write(handle as i64, data)?;
+        Ok(0)
+    })
+}
+
+extern "C" fn vfs_close(_: *mut c_void, handle: CellT) -> CellT {
+    with_installed(|vfs| vfs.close(handle as i64).map(|()| 0))
+}
+
+extern "C" fn vfs_remove(_: *mut c_void, path: *const c_char) -> CellT {
+    with_installed(|vfs| vfs.remove(unsafe { c_str(path)? }).map(|()| 0))
+}
+
+/// Files kept in memory, keyed by path
+///
+/// Clones share their files, so a clone kept aside sees what a program
+/// run on the installed one wrote.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryVfs {
+    files: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+    open: HashMap<i64, OpenFile>,
+    next_handle: i64,
+}
+
+#[derive(Debug, Clone)]
+struct OpenFile {
+    path: String,
+    position: usize,
+    readable: bool,
+    writable: bool,
+}
+
+impl MemoryVfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add or replace the file at `path`
+    pub fn insert(&self, path: impl Into<String>, contents: impl Into<Vec<u8>>) {
+        self.files().insert(path.into(), contents.into());
+    }
+
+    /// Contents of the file at `path`
+    pub fn file(&self, path: &str) -> Option<Vec<u8>> {
+        self.files().get(path).cloned()
+    }
+
+    /// Paths of every file, sorted
+    pub fn paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = self.files().keys().cloned().collect();
+        paths.sort();
+        paths
+    }
+
+    fn files(&self) -> MutexGuard<'_, HashMap<String, Vec<u8>>> {
+        self.files.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn open_file(&mut self, handle: i64) -> io::Result<&mut OpenFile> {
+        self.open.get_mut(&handle).ok_or_else(|| io::ErrorKind::InvalidInput.into())
+    }
+}
+
+impl Vfs for MemoryVfs {
+    fn open(&mut self, path: &str, mode: &str) -> io::Result<i64> {
+        let truncate = mode.starts_with('w');
+        {
+            let mut files = self.files();
+            if truncate {
+                files.insert(path.to_string(), Vec::new());
+            } else if !files.contains_key(path) {
+                return Err(io::ErrorKind::NotFound.into());
+            }
+        }
+        self.next_handle += 1;
+        let file = OpenFile {
+            path: path.to_string(),
+            position: 0,
+            readable: !truncate || mode.contains('+'),
+            writable: truncate || mode.contains('+'),
+        };
+        self.open.insert(self.next_handle, file);
+        Ok(self.next_handle)
+    }
+
+    fn read(&mut self, handle: i64, buffer: &mut [u8]) -> io::Result<usize> {
+        let files = self.files.clone();
+        let file = self.open_file(handle)?;
+        if !file.readable {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+        let files = files.lock().unwrap_or_else(|e| e.into_inner());
+        let contents = files.get(&file.path).map_or(&[][..], Vec::as_slice);
+        let available = contents.get(file.position..).unwrap_or_default();
+        let count = available.len().min(buffer.len());
+        buffer[..count].copy_from_slice(&available[..count]);
+        file.position += count;
+        Ok(count)
+    }
+
+    fn write(&mut self, handle: i64, data: &[u8]) -> io::Result<()> {
+        let files = self.files.clone();
+        let file = self.open_file(handle)?;
+        if !file.writable {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+        let mut files = files.lock().unwrap_or_else(|e| e.into_inner());
+        let contents = files.entry(file.path.clone()).or_default();
+        let end = file.position + data.len();
+        if contents.len() < end {
+            contents.resize(end, 0);
+        }
+        contents[file.position..end].copy_from_slice(data);
+        file.position = end;
+        Ok(())
+    }
+
+    fn close(&mut self, handle: i64) -> io::Result<()> {
+        self.open.remove(&handle).map(|_| ()).ok_or_else(|| io::ErrorKind::InvalidInput.into())
+    }
+
+    fn remove(&mut self, path: &str) -> io::Result<()> {
+        self.files().remove(path).map(|_| ()).ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompilationMode, Compiler, OptimizationLevel};
+
+    #[test]
+    fn test_memory_vfs_modes() {
+        let mut vfs = MemoryVfs::new();
+        vfs.insert("in.txt", "hello");
+        assert_eq!(vfs.open("missing.txt", "r").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        let input = vfs.open("in.txt", "r").unwrap();
+        let mut buffer = [0u8; 3];
+        assert_eq!(vfs.read(input, &mut buffer).unwrap(), 3);
+        assert_eq!(vfs.read(input, &mut buffer).unwrap(), 2);
+        assert_eq!(&buffer[..2], b"lo");
+        assert_eq!(vfs.read(input, &mut buffer).unwrap(), 0);
+        assert_eq!(vfs.write(input, b"x").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        vfs.close(input).unwrap();
+        assert!(vfs.close(input).is_err());
+
+        let output = vfs.open("in.txt", "r+").unwrap();
+        vfs.write(output, b"J").unwrap();
+        assert_eq!(vfs.file("in.txt").unwrap(), b"Jello");
+        let output = vfs.open("in.txt", "w").unwrap();
+        vfs.write(output, b"new").unwrap();
+        assert_eq!(vfs.file("in.txt").unwrap(), b"new");
+
+        vfs.remove("in.txt").unwrap();
+        assert!(vfs.paths().is_empty());
+    }
+
+    #[test]
+    fn test_file_words_run_on_the_installed_vfs() {
+        let _files = crate::runtime_ffi::FILE_ACCESS.lock().unwrap_or_else(|e| e.into_inner());
+        let vfs = MemoryVfs::new();
+        vfs.insert("in.txt", "abc");
+
+        let compiler = Compiler::new(OptimizationLevel::None);
+        let run = |source: &str| compiler.compile_string(source, CompilationMode::JIT).unwrap().jit_result;
+        // ior of writing to a new file, then closing it; bytes read back
+        let write = "\"out.txt\" w/o create-file drop dup \"forth\" rot write-file swap close-file +";
+        let read = "16 allocate drop \"in.txt\" r/o open-file drop swap over 10 swap read-file drop swap close-file drop";
+
+        install(vfs.clone());
+        let written = run(write);
+        let bytes_read = run(read);
+        let missing = run("\"nowhere.txt\" r/o open-file swap drop");
+        let deleted = run("\"in.txt\" delete-file");
+        uninstall();
+
+        assert_eq!(written, Some(0));
+        assert_eq!(vfs.file("out.txt").unwrap(), b"forth");
+        assert_eq!(bytes_read, Some(3));
+        assert_eq!(missing, Some(FILE_ERROR));
+        assert_eq!(deleted, Some(0));
+        assert_eq!(vfs.paths(), ["out.txt"]);
+        assert!(!std::path::Path::new("out.txt").exists());
+    }
+}
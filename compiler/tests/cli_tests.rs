@@ -333,6 +333,65 @@ fn test_cli_batch_execution() {
     }
 }
 
+#[test]
+fn test_cli_check_fails_on_any_error() {
+    // Test 16: `check` reports every error it finds, and exits nonzero
+    let (_temp, bad) = create_temp_forth_file(": f foo ;\n: g ) ;\n: h ) ;");
+    let output = Command::new(env!("CARGO_BIN_EXE_fifthc")).arg("check").arg(&bad).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("test.fth:2:5: Parse error"), "{}", stderr);
+    assert!(stderr.contains("test.fth:3:5: Parse error"), "{}", stderr);
+
+    // Semantic errors once the file parses
+    fs::write(&bad, ": f foo ;\n: g bar ;").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_fifthc")).arg("check").arg(&bad).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("test.fth:1:5: Undefined word: foo"), "{}", stderr);
+    assert!(stderr.contains("test.fth:2:5: Undefined word: bar"), "{}", stderr);
+
+    fs::write(&bad, ": f 1 ;").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_fifthc")).arg("check").arg(&bad).output().unwrap();
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_cli_no_vectorize_keeps_loops_scalar() {
+    // Test 17: --no-vectorize leaves array loops to run one cell at a time
+    let (temp, source) = create_temp_forth_file(
+        "create data 5 cells allot\n\
+         : fill-data ( -- ) 5 0 do i 1+ data i cells + ! loop ;\n\
+         : sum ( addr n -- sum ) 0 swap 0 do over i cells + @ + loop swap drop ;\n\
+         fill-data data 5 sum .\n",
+    );
+    let vectorize_pass = |flags: &[&str]| {
+        let report = temp.path().join("report.json");
+        let output = Command::new(env!("CARGO_BIN_EXE_fifthc"))
+            .args(flags)
+            .arg("--opt-report")
+            .arg(&report)
+            .arg("run")
+            .arg(&source)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert!(String::from_utf8_lossy(&output.stdout).starts_with("15 "));
+
+        let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+        report["passes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|pass| pass["pass"] == "vectorize")
+            .map(|pass| (pass["instructions_before"].as_u64().unwrap(), pass["instructions_after"].as_u64().unwrap()))
+    };
+
+    let (before, after) = vectorize_pass(&[]).expect("the vectorize pass runs by default");
+    assert!(after < before, "the sum loop becomes a kernel: {} -> {}", before, after);
+    assert_eq!(vectorize_pass(&["--no-vectorize"]), None);
+}
+
 // ============================================================================
 // Server Tests (5 tests)
 // ============================================================================
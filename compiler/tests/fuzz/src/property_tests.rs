@@ -9,8 +9,8 @@
 /// 5. Automatic shrinking to minimal failing cases
 
 use proptest::prelude::*;
-use std::process::{Command, Stdio};
-use std::io::Write;
+use fastforth::testing::differential::strategies::arb_program;
+use fastforth::testing::structured::{check_optimization_levels, strategies::arb_structured_program};
 
 // Re-export the main library
 pub use fastforth::*;
@@ -162,79 +162,22 @@ fn arb_complex_expr() -> impl Strategy<Value = String> {
 
 /// Check if GForth is available
 pub fn gforth_available() -> bool {
-    Command::new("gforth")
-        .arg("--version")
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .is_ok()
+    DifferentialRunner::new().gforth_available()
 }
 
 /// Execute code in GForth and extract stack state
 pub fn run_gforth(code: &str) -> Result<Vec<i64>, String> {
-    let mut child = Command::new("gforth")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|e| format!("Failed to spawn gforth: {}", e))?;
-
-    if let Some(mut stdin) = child.stdin.take() {
-        // Execute code and print stack
-        stdin.write_all(code.as_bytes())
-            .map_err(|e| format!("Failed to write code: {}", e))?;
-        stdin.write_all(b"\n.s\nbye\n")
-            .map_err(|e| format!("Failed to write .s: {}", e))?;
-    }
-
-    let output = child.wait_with_output()
-        .map_err(|e| format!("Failed to wait: {}", e))?;
-
-    if !output.status.success() {
-        return Err(String::from_utf8_lossy(&output.stderr).to_string());
-    }
-
-    // Parse GForth stack output
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    parse_gforth_stack(&stdout)
-}
-
-/// Parse GForth .s output to extract stack values
-fn parse_gforth_stack(output: &str) -> Result<Vec<i64>, String> {
-    // Look for stack depth indicator and values
-    // GForth .s outputs: <depth> value1 value2 ...
-    for line in output.lines() {
-        if line.starts_with('<') {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 2 {
-                // Skip the <depth> part and parse the rest
-                let values: Result<Vec<i64>, _> = parts[1..]
-                    .iter()
-                    .map(|s| s.parse::<i64>())
-                    .collect();
-                return values.map_err(|e| format!("Parse error: {}", e));
-            }
-        }
-    }
-
-    // If no stack output found, assume empty stack
-    Ok(Vec::new())
+    DifferentialRunner::new()
+        .run_gforth(code)
+        .map_err(|e| e.to_string())?
+        .map(|execution| execution.stack)
 }
 
 /// Execute code in Fast Forth and extract stack state
 pub fn run_fast_forth(code: &str) -> Result<Vec<i64>, String> {
-    use fastforth_frontend::parse_program;
-    use fastforth_optimizer::{Optimizer, OptimizationLevel};
-
-    // Parse the code
-    let program = parse_program(code)
-        .map_err(|e| format!("Parse error: {}", e))?;
-
-    // For now, we'll use the frontend parser
-    // In a full implementation, we'd execute through the JIT
-    // This is a placeholder - actual implementation needs runtime
-
-    Err("Fast Forth execution not fully implemented yet".to_string())
+    DifferentialRunner::new()
+        .run_fast_forth(code)
+        .map(|execution| execution.stack)
 }
 
 // ============================================================================
@@ -439,21 +382,8 @@ mod differential_tests {
             b in 1i64..1000,  // Avoid division by zero
             op in arb_arithmetic_op()
         ) {
-            if !gforth_available() {
-                return Ok(());
-            }
-
             let code = format!("{} {} {}", a, b, op);
-
-            // Run in GForth
-            if let Ok(gforth_stack) = run_gforth(&code) {
-                // Run in Fast Forth (when implemented)
-                // let fast_forth_stack = run_fast_forth(&code)?;
-                // prop_assert_eq!(gforth_stack, fast_forth_stack);
-
-                // For now, just verify GForth execution succeeded
-                let _ = gforth_stack;
-            }
+            DifferentialRunner::new().check(&code)?;
         }
     }
 
@@ -466,14 +396,30 @@ mod differential_tests {
             a in arb_forth_int(),
             op in arb_stack_op()
         ) {
-            if !gforth_available() {
-                return Ok(());
-            }
-
             let code = format!("{} {}", a, op);
+            DifferentialRunner::new().check(&code)?;
+        }
+    }
+
+    /// Differential test: Compare random straight-line programs against GForth
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(100))]
 
-            if let Ok(_gforth_stack) = run_gforth(&code) {
-                // Comparison would go here when execution is implemented
+        #[test]
+        fn diff_programs_against_gforth(code in arb_program(32)) {
+            DifferentialRunner::new().check(&code)?;
+        }
+    }
+
+    /// Every optimization level must agree with the generator's expected
+    /// results on programs with definitions, conditionals and loops
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(500))]
+
+        #[test]
+        fn optimization_levels_agree(program in arb_structured_program()) {
+            if let Err(mismatch) = check_optimization_levels(&program) {
+                return Err(TestCaseError::fail(mismatch.to_string()));
             }
         }
     }
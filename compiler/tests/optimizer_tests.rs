@@ -274,7 +274,7 @@ fn test_dead_code_unused_definitions() {
     ir.add_word(used_word);
 
     ir.main = vec![
-        Instruction::Call("used".to_string()),
+        Instruction::Call("used".into()),
     ];
 
     let optimized = eliminator.eliminate(&ir).expect("Elimination failed");
@@ -349,7 +349,7 @@ fn test_inline_small_words() {
 
     ir.main = vec![
         Instruction::Literal(5),
-        Instruction::Call("double".to_string()),
+        Instruction::Call("double".into()),
     ];
 
     let optimized = inliner.inline(&ir).expect("Inlining failed");
@@ -382,7 +382,7 @@ fn test_no_inline_recursive() {
             Instruction::Dup,
             Instruction::Literal(1),
             Instruction::Sub,
-            Instruction::Call("factorial".to_string()),  // Recursive call
+            Instruction::Call("factorial".into()),  // Recursive call
             Instruction::Mul,
         ],
     );
@@ -415,7 +415,7 @@ fn test_inline_threshold() {
 
     ir.main = vec![
         Instruction::Literal(5),
-        Instruction::Call("large".to_string()),
+        Instruction::Call("large".into()),
     ];
 
     let optimized = inliner.inline(&ir).expect("Inlining failed");
@@ -587,9 +587,9 @@ fn test_loop_invariant_hoisting() {
 
     // Simulate calling it multiple times (as in a loop)
     ir.main = vec![
-        Instruction::Call("loop_body".to_string()),
-        Instruction::Call("loop_body".to_string()),
-        Instruction::Call("loop_body".to_string()),
+        Instruction::Call("loop_body".into()),
+        Instruction::Call("loop_body".into()),
+        Instruction::Call("loop_body".into()),
     ];
 
     let optimized = optimizer.optimize(ir).expect("Optimization failed");
@@ -630,8 +630,8 @@ fn test_nested_loop_optimization() {
 
     // Simulate nested calls and use results
     ir.main = vec![
-        Instruction::Call("inner_compute".to_string()),  // Produces 6
-        Instruction::Call("inner_compute".to_string()),  // Produces 6
+        Instruction::Call("inner_compute".into()),  // Produces 6
+        Instruction::Call("inner_compute".into()),  // Produces 6
         Instruction::Add,                                // 6 + 6 = 12
     ];
 
@@ -708,12 +708,12 @@ fn test_loop_fusion() {
 
     // Two sequential calls simulating loop bodies
     ir.main = vec![
-        Instruction::Call("loop1_body".to_string()),
-        Instruction::Call("loop1_body".to_string()),
-        Instruction::Call("loop1_body".to_string()),
-        Instruction::Call("loop2_body".to_string()),
-        Instruction::Call("loop2_body".to_string()),
-        Instruction::Call("loop2_body".to_string()),
+        Instruction::Call("loop1_body".into()),
+        Instruction::Call("loop1_body".into()),
+        Instruction::Call("loop1_body".into()),
+        Instruction::Call("loop2_body".into()),
+        Instruction::Call("loop2_body".into()),
+        Instruction::Call("loop2_body".into()),
     ];
 
     let optimized = optimizer.optimize(ir).expect("Optimization failed");
@@ -912,7 +912,7 @@ fn test_interprocedural_constant_prop() {
 
     // Main: just call helper (should inline and fold to 25)
     ir.main = vec![
-        Instruction::Call("helper".to_string()),
+        Instruction::Call("helper".into()),
     ];
 
     let optimized = optimizer.optimize(ir).expect("Optimization failed");
@@ -951,11 +951,11 @@ fn test_whole_program_dead_code() {
 
     // Only call word0, word5, and word9
     ir.main = vec![
-        Instruction::Call("word0".to_string()),
+        Instruction::Call("word0".into()),
         Instruction::Drop,
-        Instruction::Call("word5".to_string()),
+        Instruction::Call("word5".into()),
         Instruction::Drop,
-        Instruction::Call("word9".to_string()),
+        Instruction::Call("word9".into()),
     ];
 
     let optimized = dce.eliminate(&ir).expect("Optimization failed");
@@ -0,0 +1,39 @@
+//! Optimizer Pass Snapshot Tests
+//!
+//! Every program in `tests/snapshots/corpus` is traced through the
+//! optimizer and its IR after each pass is compared with the snapshot in
+//! `tests/snapshots/passes`. A failure names the first pass whose changes
+//! differ. After reviewing an intended change, accept the new traces with
+//!
+//! ```text
+//! FIFTH_UPDATE_SNAPSHOTS=1 cargo test --test pass_snapshots
+//! ```
+
+use fastforth::testing::PassSnapshots;
+use std::path::Path;
+
+#[test]
+fn test_pass_snapshots() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+    let snapshots = PassSnapshots::new(root.join("passes"));
+
+    let mut programs: Vec<_> = std::fs::read_dir(root.join("corpus"))
+        .expect("snapshot corpus")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "fs"))
+        .collect();
+    programs.sort();
+    assert!(!programs.is_empty(), "empty snapshot corpus");
+
+    let mut failures = Vec::new();
+    for path in &programs {
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let source = std::fs::read_to_string(path).unwrap();
+        match snapshots.check(&name, &source) {
+            Ok(None) => {}
+            Ok(Some(mismatch)) => failures.push(mismatch.to_string()),
+            Err(e) => failures.push(format!("snapshot {}: {}", name, e)),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
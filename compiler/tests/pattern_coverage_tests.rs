@@ -138,6 +138,7 @@ fn test_pattern_database_insert_and_get() {
         metadata: create_test_metadata("INSERT_TEST_001", ": test ;", true),
         usage_count: 0,
         success_rate: 1.0,
+        quality_score: 1.0,
     };
 
     let id = pattern.metadata.id.clone();
@@ -3,10 +3,14 @@
 /// Tests for Windows-specific behavior in C runtime and Rust FFI.
 /// These tests only compile on Windows.
 
+use backend::linker::{Linker, LinkerConfig, LinkMode, Platform};
+use std::path::PathBuf;
+
 #[test]
 #[cfg(target_os = "windows")]
 fn test_windows_platform_detected() {
     assert!(cfg!(target_os = "windows"));
+    assert_eq!(Platform::host(), Platform::Windows);
     println!("Running on Windows");
 }
 
@@ -17,9 +21,56 @@ fn test_not_on_windows() {
     // but doesn't run Windows-specific tests
 }
 
-// Add Windows-specific tests here as needed
-// Examples:
-// - VirtualAlloc memory tests
-// - Windows path conventions (backslashes)
-// - PE binary format tests
-// - Windows threading API tests (if not using pthread-win32)
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fastforth-windows-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Links a C `main` with whichever toolchain is installed (MSVC, clang,
+/// MinGW) into an `.exe`, with the Unix `c` and `m` libraries dropped
+#[test]
+fn test_links_exe_with_detected_toolchain() {
+    let dir = scratch_dir("exe");
+    let source = dir.join("hello.c");
+    std::fs::write(&source, "#include <math.h>\nint main(void) { return (int)sqrt(49.0) - 7; }\n").unwrap();
+
+    let config = LinkerConfig {
+        output: dir.join("hello"),
+        runtime_lib: source,
+        entry: None,
+        mode: LinkMode::Dynamic,
+        ..LinkerConfig::default()
+    };
+    let Ok(exe) = Linker::new(config).link(&[]) else {
+        // No C toolchain on this machine
+        let _ = std::fs::remove_dir_all(&dir);
+        return;
+    };
+    let status = std::process::Command::new(&exe).status().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(exe.extension().unwrap(), "exe");
+    assert!(status.success());
+}
+
+/// Builds a DLL whose exports are visible to `LoadLibrary`
+#[test]
+fn test_creates_dll() {
+    let dir = scratch_dir("dll");
+    let source = dir.join("square.c");
+    std::fs::write(&source, "__declspec(dllexport) long long square(long long n) { return n * n; }\n").unwrap();
+
+    let linker = Linker::new(LinkerConfig::default());
+    let object = dir.join("square.obj");
+    if linker.compile_c(&source, &object).is_err() {
+        let _ = std::fs::remove_dir_all(&dir);
+        return;
+    }
+    let dll = dir.join(format!("square.{}", Platform::Windows.shared_library_extension()));
+    linker.create_shared_library(&[object], &dll).unwrap();
+    let built = dll.exists();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(built);
+}
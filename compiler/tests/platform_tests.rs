@@ -7,19 +7,24 @@
 
 // Platform-specific modules
 #[cfg(target_os = "linux")]
+#[path = "platform/linux_tests.rs"]
 mod linux_tests;
 
 #[cfg(target_os = "macos")]
+#[path = "platform/macos_tests.rs"]
 mod macos_tests;
 
 #[cfg(target_os = "windows")]
+#[path = "platform/windows_tests.rs"]
 mod windows_tests;
 
 // Architecture-specific modules
 #[cfg(target_arch = "x86_64")]
+#[path = "platform/x86_64_tests.rs"]
 mod x86_64_tests;
 
 #[cfg(target_arch = "aarch64")]
+#[path = "platform/aarch64_tests.rs"]
 mod aarch64_tests;
 
 // Common platform tests that run on all platforms
@@ -0,0 +1,3 @@
+\ Conditionals inside a definition
+: clamp dup 10 > if drop 10 then ;
+15 clamp 3 clamp +
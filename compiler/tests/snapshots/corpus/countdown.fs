@@ -0,0 +1,2 @@
+\ A begin/until loop counting down
+5 begin 1 - dup 0= until drop
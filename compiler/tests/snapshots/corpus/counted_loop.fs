@@ -0,0 +1,2 @@
+\ A counted loop with constant bounds
+0 4 0 do i + loop
@@ -0,0 +1,2 @@
+\ Arithmetic on literals folds to one constant
+2 3 + 4 * 10 -
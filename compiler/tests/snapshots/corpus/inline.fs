@@ -0,0 +1,4 @@
+\ Small definitions are inlined into their callers
+: sq dup * ;
+: quad sq sq ;
+3 quad
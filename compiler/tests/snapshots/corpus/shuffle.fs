@@ -0,0 +1,3 @@
+\ Stack shuffles that superinstructions and dead code elimination see
+: mix over + swap drop dup drop ;
+1 2 mix 5 swap -
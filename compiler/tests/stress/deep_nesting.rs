@@ -14,7 +14,7 @@ use inkwell::{context::Context, OptimizationLevel};
 fn test_deeply_nested_if_statements() {
     // Test parsing and SSA conversion of deeply nested IF statements
     let code = r#"
-: deeply-nested ( n -- result )
+: deeply-nested ( n -- n result )
   dup 0 > if
     dup 1 > if
       dup 2 > if
@@ -284,7 +284,7 @@ fn test_massive_stack_depth() {
 : deep-stack ( -- result )
   1 2 3 4 5 6 7 8 9 10
   11 12 13 14 15 16 17 18 19 20
-  + + + + + + + + +
+  + + + + + + + + + +
   + + + + + + + + +
 ;
 "#;
@@ -458,7 +458,7 @@ fn test_phi_nodes_in_complex_cfg() {
 #[test]
 fn test_extreme_nesting_limit() {
     // Test behavior at extreme nesting levels
-    let mut code = String::from(": extreme-nesting ( n -- result )\n");
+    let mut code = String::from(": extreme-nesting ( n -- n result )\n");
 
     // Create 20 levels of nesting
     for i in 0..20 {
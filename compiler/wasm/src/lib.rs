@@ -0,0 +1,246 @@
+//! Fast Forth in the Browser
+//!
+//! The frontend, type inference and the IR interpreter, built for
+//! `wasm32-unknown-unknown` so a playground can run Forth without a server.
+//! [`parse`], [`infer`] and [`execute`] are exported to JavaScript through
+//! wasm-bindgen; each takes Forth source and returns a JSON string:
+//!
+//! ```js
+//! import init, { execute } from "./pkg/fastforth_wasm.js";
+//! await init();
+//! const run = JSON.parse(execute(": sq dup * ; 7 sq .", "", undefined));
+//! // { ok: true, stack: [], output: "49 ", error: null }
+//! ```
+//!
+//! Nothing here touches a process, file or thread: the frontend and
+//! optimizer are built without their `parallel` feature, programs run on
+//! the [`IrInterpreter`] rather than compiled code, and the I/O words write
+//! to a string through its [`Console`]. The interpreter's limits apply, so
+//! memory and floating point words are reported as not supported.
+
+use fastforth_frontend::type_inference::{TypeInference, TypedDefinition};
+use fastforth_frontend::{parse_program, ForthError, Program};
+use fastforth_optimizer::ir::WordAttributes;
+use fastforth_optimizer::lower::lower_program;
+use fastforth_optimizer::{Console, IrInterpreter};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+/// An error, with where in the source it is when known
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub message: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl From<&ForthError> for Diagnostic {
+    fn from(error: &ForthError) -> Self {
+        let location = error.location();
+        Diagnostic {
+            message: error.to_string(),
+            line: location.as_ref().map(|location| location.line),
+            column: location.as_ref().map(|location| location.column),
+        }
+    }
+}
+
+impl Diagnostic {
+    fn message(message: impl ToString) -> Self {
+        Diagnostic { message: message.to_string(), line: None, column: None }
+    }
+}
+
+/// A definition of the parsed program
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Definition {
+    pub name: String,
+    pub line: usize,
+    /// The declared stack effect
+    pub stack_effect: Option<String>,
+    pub immediate: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParseResult {
+    pub ok: bool,
+    pub definitions: Vec<Definition>,
+    pub error: Option<Diagnostic>,
+}
+
+/// Inferred types of one definition, or of the top-level code as `main`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WordTypes {
+    pub name: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub error: Option<Diagnostic>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InferResult {
+    pub ok: bool,
+    pub words: Vec<WordTypes>,
+    pub error: Option<Diagnostic>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExecuteResult {
+    pub ok: bool,
+    /// The data stack the program leaves, bottom first
+    pub stack: Vec<i64>,
+    pub output: String,
+    pub error: Option<Diagnostic>,
+}
+
+/// Parse `source`, listing its definitions
+#[wasm_bindgen]
+pub fn parse(source: &str) -> String {
+    to_json(&parse_source(source))
+}
+
+/// Infer the stack types of each definition of `source` and of its
+/// top-level code
+#[wasm_bindgen]
+pub fn infer(source: &str) -> String {
+    to_json(&infer_source(source))
+}
+
+/// Run `source`, with `input` as what `key` reads, for at most `fuel`
+/// instructions (ten million when not given)
+#[wasm_bindgen]
+pub fn execute(source: &str, input: &str, fuel: Option<u32>) -> String {
+    to_json(&execute_source(source, input, fuel.map(u64::from)))
+}
+
+/// Version of the compiler the playground was built from
+#[wasm_bindgen]
+pub fn version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
+
+pub fn parse_source(source: &str) -> ParseResult {
+    match parse_program(source) {
+        Ok(program) => ParseResult {
+            ok: true,
+            definitions: program
+                .definitions
+                .iter()
+                .map(|def| Definition {
+                    name: def.name.clone(),
+                    line: def.location.line,
+                    stack_effect: def.stack_effect.as_ref().map(ToString::to_string),
+                    immediate: def.immediate,
+                })
+                .collect(),
+            error: None,
+        },
+        Err(error) => ParseResult { ok: false, definitions: Vec::new(), error: Some(Diagnostic::from(&error)) },
+    }
+}
+
+pub fn infer_source(source: &str) -> InferResult {
+    let program = match parse_program(source) {
+        Ok(program) => program,
+        Err(error) => return InferResult { ok: false, words: Vec::new(), error: Some(Diagnostic::from(&error)) },
+    };
+
+    let mut inference = TypeInference::new();
+    let mut words: Vec<WordTypes> = program
+        .compiled_definitions()
+        .map(|def| word_types(&def.name, inference.trace_definition(def)))
+        .collect();
+    if !program.top_level_code.is_empty() {
+        words.push(word_types("main", inference.trace_sequence(&program.top_level_code)));
+    }
+    InferResult { ok: words.iter().all(|word| word.error.is_none()), words, error: None }
+}
+
+fn word_types(name: &str, typed: fastforth_frontend::Result<TypedDefinition>) -> WordTypes {
+    let names = |types: &[fastforth_frontend::ast::StackType]| types.iter().map(ToString::to_string).collect();
+    match typed {
+        Ok(typed) => WordTypes { name: name.to_string(), inputs: names(&typed.inputs), outputs: names(&typed.outputs), error: None },
+        Err(error) => WordTypes { name: name.to_string(), inputs: Vec::new(), outputs: Vec::new(), error: Some(Diagnostic::from(&error)) },
+    }
+}
+
+/// Output of a run, with `key` reading from a fixed input
+struct Playground<'a> {
+    output: String,
+    input: std::str::Bytes<'a>,
+}
+
+impl Console for Playground<'_> {
+    fn write(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+
+    fn key(&mut self) -> Option<u8> {
+        self.input.next()
+    }
+}
+
+pub fn execute_source(source: &str, input: &str, fuel: Option<u64>) -> ExecuteResult {
+    let program: Program = match parse_program(source) {
+        Ok(program) => program,
+        Err(error) => {
+            return ExecuteResult { ok: false, stack: Vec::new(), output: String::new(), error: Some(Diagnostic::from(&error)) }
+        }
+    };
+
+    let ir = lower_program(&program, |_| WordAttributes::default());
+    let mut console = Playground { output: String::new(), input: input.bytes() };
+    let mut interpreter = IrInterpreter::new(&ir).with_console(&mut console);
+    if let Some(fuel) = fuel {
+        interpreter = interpreter.with_fuel(fuel);
+    }
+    let result = interpreter.run();
+    let output = console.output;
+    match result {
+        Ok(stack) => ExecuteResult { ok: true, stack, output, error: None },
+        Err(error) => ExecuteResult { ok: false, stack: Vec::new(), output, error: Some(Diagnostic::message(error)) },
+    }
+}
+
+fn to_json(value: &impl Serialize) -> String {
+    serde_json::to_string(value).expect("results serialize to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let result = parse_source(": sq ( n -- n ) dup * ;\n5 sq");
+        assert!(result.ok);
+        assert_eq!(result.definitions[0].name, "sq");
+        assert_eq!(result.definitions[0].stack_effect.as_deref(), Some("( int -- int )"));
+
+        let result = parse_source(": broken dup");
+        assert!(!result.ok);
+        assert!(result.error.is_some());
+    }
+
+    #[test]
+    fn test_infer() {
+        let result = infer_source(": sq dup * ;\n5 sq");
+        assert!(result.ok);
+        assert_eq!(result.words[0].inputs, vec!["int"]);
+        assert_eq!(result.words[1].name, "main");
+        assert_eq!(result.words[1].outputs, vec!["int"]);
+    }
+
+    #[test]
+    fn test_execute() {
+        let json = execute(": sq dup * ;\n7 sq . 3 4 + 65 emit key", "x", None);
+        assert_eq!(json, r#"{"ok":true,"stack":[7,120],"output":"49 A","error":null}"#);
+
+        let result = execute_source("1 0 /", "", None);
+        assert!(!result.ok);
+        assert_eq!(result.error.unwrap().message, "Division by zero");
+
+        let result = execute_source("begin 0 until", "", Some(1000));
+        assert_eq!(result.error.unwrap().message, "Ran out of fuel");
+    }
+}